@@ -0,0 +1,793 @@
+use super::error::ApiError;
+use super::facts::{gather_facts_arg, is_local_host, FactSubset};
+use super::results::{CompileOutcome, CompiledRunner, DeployOutcome, GatheredFacts, RunOutcome};
+use super::{parse_plan, runner_output_path, write_runner};
+use crate::compilation::cache::CompilationCache;
+use crate::compilation::compiler::{BinaryCompiler, CompilerConfig};
+use crate::compilation::estimate::{CompileEstimate, CompileEstimator};
+use crate::compilation::scheduler::{CompileJob, CompileProgress, CompileScheduler};
+use crate::compilation::{is_wasi_target, TargetDetector};
+use crate::deploy::manifest::{plan_hash, MANIFEST_FILE};
+use crate::deploy::{
+    ArtifactEntry, ArtifactIndex, CompilerVersions, DeploymentManager, DeploymentManifest,
+    DeploymentMetrics, EventSink, ExecutionVerificationConfig, RollbackPolicy,
+};
+use crate::execution::rustle_plan::{BinaryDeploymentPlan, RustlePlanOutput};
+use crate::execution::{ExecutionStrategy, PlaybookLoader, VaultSecrets};
+use crate::inventory::InventoryProcessor;
+use crate::modules::{CustomModule, PythonModules};
+use crate::runtime::ProgressEvent;
+use crate::template::{
+    BinaryTemplateGenerator, PayloadPolicy, TargetInfo, TemplateConfig, TemplateError,
+};
+use crate::types::compilation::{
+    BinaryCompilation, DeploymentConfig, EmbeddedExecutionData, LegacyCompilationOptions,
+    OptimizationLevel, RunnerProfile, TargetSpecification,
+};
+use crate::types::deployment::{
+    DeploymentMetadata, DeploymentMethod, DeploymentPlan, DeploymentStatus, DeploymentStrategy,
+    DeploymentTarget, HostSchedule,
+};
+use chrono::Utc;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// A target to compile a runner for, and the deployment it serves
+#[derive(Debug, Clone)]
+pub struct SelectedTarget {
+    pub spec: TargetSpecification,
+    pub deployment: BinaryDeploymentPlan,
+}
+
+/// Compiles an execution plan into runners and deploys them to the plan's
+/// hosts
+pub struct Deployment {
+    plan: RustlePlanOutput,
+    output_dir: PathBuf,
+    targets: Vec<String>,
+    inventory: Option<PathBuf>,
+    optimization: OptimizationLevel,
+    compiler_config: CompilerConfig,
+    template_config: TemplateConfig,
+    vault: VaultSecrets,
+    template_paths: Vec<PathBuf>,
+    payload_policy: PayloadPolicy,
+    custom_modules: Vec<CustomModule>,
+    python_modules: Option<PythonModules>,
+    deployment_config: DeploymentConfig,
+    progress: Option<UnboundedSender<CompileProgress>>,
+    execute: bool,
+    events: Option<EventSink>,
+    metrics: Option<Arc<DeploymentMetrics>>,
+    rollback: RollbackPolicy,
+    resume: bool,
+    cancel: CancellationToken,
+}
+
+impl Deployment {
+    /// Deploy `plan`, writing its runners to the current directory
+    pub fn new(plan: RustlePlanOutput) -> Self {
+        let compiler_config = CompilerConfig::default();
+        let output_dir = PathBuf::from(".");
+        let deployment_config = DeploymentConfig {
+            cache_dir: compiler_config.cache_dir.clone(),
+            output_dir: output_dir.clone(),
+            parallel_jobs: compiler_config.max_parallel_compilations,
+            forks: 5,
+            default_timeout_secs: 0,
+            verify_deployments: true,
+            compression: false,
+            strip_symbols: true,
+            binary_size_limit_mb: 50,
+        };
+        Self {
+            plan,
+            output_dir,
+            targets: Vec::new(),
+            inventory: None,
+            optimization: OptimizationLevel::Release,
+            compiler_config,
+            template_config: TemplateConfig::default(),
+            vault: VaultSecrets::default(),
+            template_paths: Vec::new(),
+            payload_policy: PayloadPolicy::default(),
+            custom_modules: Vec::new(),
+            python_modules: None,
+            deployment_config,
+            progress: None,
+            execute: true,
+            events: None,
+            metrics: None,
+            rollback: RollbackPolicy::default(),
+            resume: false,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Deploy the plan in the rustle-plan JSON file at `path`, decrypting
+    /// its vaulted values with `vault` and inlining the files it includes,
+    /// which are relative to it
+    pub async fn load(path: impl AsRef<Path>, vault: &VaultSecrets) -> Result<Self, ApiError> {
+        let path = path.as_ref();
+        let content = tokio::fs::read_to_string(path).await?;
+        let mut plan = parse_plan(&content, vault)?;
+        PlaybookLoader::new()
+            .with_vault(vault.clone())
+            .resolve_includes(&mut plan, path.parent().unwrap_or(Path::new(".")))?;
+        Ok(Self::new(plan).with_vault(vault.clone()))
+    }
+
+    /// Write the runners and their manifest to `dir`
+    pub fn with_output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = dir.into();
+        self.deployment_config.output_dir = self.output_dir.clone();
+        self
+    }
+
+    /// Only compile for these targets, of those the plan's binary
+    /// deployments need, or for these when the plan names none
+    pub fn with_targets(mut self, targets: Vec<String>) -> Self {
+        self.targets = targets;
+        self
+    }
+
+    /// Connect to hosts as the inventory at `path` says
+    pub fn with_inventory(mut self, path: impl Into<PathBuf>) -> Self {
+        self.inventory = Some(path.into());
+        self
+    }
+
+    pub fn with_optimization(mut self, level: OptimizationLevel) -> Self {
+        self.optimization = level;
+        self
+    }
+
+    pub fn with_compiler_config(mut self, config: CompilerConfig) -> Self {
+        self.compiler_config = config;
+        self
+    }
+
+    /// Generate the runners' sources with `config`; the runner profile is
+    /// the compiler's for each target
+    pub fn with_template_config(mut self, config: TemplateConfig) -> Self {
+        self.template_config = config;
+        self
+    }
+
+    /// Decrypt vaulted files and values embedded into the runners
+    pub fn with_vault(mut self, vault: VaultSecrets) -> Self {
+        self.vault = vault;
+        self
+    }
+
+    /// Directories templates of tasks include other templates from
+    pub fn with_template_paths(mut self, paths: Vec<PathBuf>) -> Self {
+        self.template_paths = paths;
+        self
+    }
+
+    pub fn with_payload_policy(mut self, policy: PayloadPolicy) -> Self {
+        self.payload_policy = policy;
+        self
+    }
+
+    /// Compile these modules into the runners of the plan, found with a
+    /// [`crate::modules::ModuleSearchPath`]
+    pub fn with_custom_modules(mut self, modules: Vec<CustomModule>) -> Self {
+        self.custom_modules = modules;
+        self
+    }
+
+    /// Have runners run the modules they are not built with from these
+    /// Ansible Python modules; WASI runners do without them
+    pub fn with_python_modules(mut self, modules: PythonModules) -> Self {
+        self.python_modules = Some(modules);
+        self
+    }
+
+    pub fn with_deployment_config(mut self, config: DeploymentConfig) -> Self {
+        self.output_dir = config.output_dir.clone();
+        self.deployment_config = config;
+        self
+    }
+
+    /// Report the progress of each target's compilation to `sender`
+    pub fn with_compile_progress(mut self, sender: UnboundedSender<CompileProgress>) -> Self {
+        self.progress = Some(sender);
+        self
+    }
+
+    /// Whether deployed runners are run, on by default
+    pub fn with_execution(mut self, execute: bool) -> Self {
+        self.execute = execute;
+        self
+    }
+
+    /// Forward the events runners stream while they execute to `sink`
+    pub fn with_event_sink(mut self, sink: EventSink) -> Self {
+        self.events = Some(sink);
+        self
+    }
+
+    /// Count hosts, transferred bytes and task durations in `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<DeploymentMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// [`RollbackPolicy::disabled`] leaves hosts as they are when the
+    /// deployment fails
+    pub fn with_rollback_policy(mut self, policy: RollbackPolicy) -> Self {
+        self.rollback = policy;
+        self
+    }
+
+    /// Have runners continue from the first task a previous, failed run did
+    /// not complete
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Stop once `token` is cancelled: builds are killed, transfers abandoned
+    /// and runners stop after their current task, keeping their progress
+    /// for a resumed run
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Cancels the deployment when cancelled
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    pub fn plan(&self) -> &RustlePlanOutput {
+        &self.plan
+    }
+
+    /// The targets to compile for: those the plan's binary deployments
+    /// need, one runner each, or the one given or this host's when the plan
+    /// has none
+    pub fn select_targets(&self) -> Result<Vec<SelectedTarget>, ApiError> {
+        let detector = TargetDetector::new();
+        let selection_failed =
+            |e: crate::compilation::TargetDetectionError| ApiError::TargetSelection {
+                reason: e.to_string(),
+            };
+
+        let mut selected: Vec<SelectedTarget> = Vec::new();
+        for deployment in &self.plan.binary_deployments {
+            let spec = detector
+                .create_target_spec_from_requirements(
+                    &deployment.compilation_requirements,
+                    self.optimization.clone(),
+                )
+                .map_err(selection_failed)?;
+            if !self.targets.is_empty() && !self.targets.contains(&spec.target_triple) {
+                continue;
+            }
+            // Deployments of the same target share its runner
+            match selected
+                .iter_mut()
+                .find(|target| target.spec.target_triple == spec.target_triple)
+            {
+                Some(target) => target
+                    .deployment
+                    .target_hosts
+                    .extend(deployment.target_hosts.iter().cloned()),
+                None => selected.push(SelectedTarget {
+                    spec,
+                    deployment: deployment.clone(),
+                }),
+            }
+        }
+
+        if self.plan.binary_deployments.is_empty() {
+            let hosts = BinaryDeploymentPlan {
+                target_hosts: self.plan.hosts.clone(),
+                ..Default::default()
+            };
+            if self.targets.is_empty() {
+                selected.push(SelectedTarget {
+                    spec: detector
+                        .create_localhost_target_spec()
+                        .map_err(selection_failed)?,
+                    deployment: hosts.clone(),
+                });
+            }
+            for target in &self.targets {
+                selected.push(SelectedTarget {
+                    spec: detector
+                        .create_target_spec(target, self.optimization.clone())
+                        .map_err(selection_failed)?,
+                    deployment: hosts.clone(),
+                });
+            }
+        }
+
+        if selected.is_empty() {
+            return Err(ApiError::TargetSelection {
+                reason: format!("the plan deploys to none of {}", self.targets.join(", ")),
+            });
+        }
+        Ok(selected)
+    }
+
+    /// How long compiling the runners of the selected targets should take,
+    /// predicted from the builds recorded in the compilation cache
+    pub fn estimate_compilation(&self) -> Result<CompileEstimate, ApiError> {
+        let targets: Vec<String> = self
+            .select_targets()?
+            .into_iter()
+            .map(|target| target.spec.target_triple)
+            .collect();
+        let cache_dir = &self.compiler_config.cache_dir;
+        let cache = CompilationCache::new(
+            cache_dir.clone(),
+            self.compiler_config.enable_cache && cache_dir.exists(),
+        );
+        Ok(CompileEstimator::from_cache(&cache).estimate(&self.plan, &targets))
+    }
+
+    /// Compile a runner for each selected target, and write them and their
+    /// manifest to the output directory
+    pub async fn compile(&self) -> Result<CompileOutcome, ApiError> {
+        self.check_cancelled()?;
+        let (jobs, mut hosts) = self.compile_jobs().await?;
+
+        let template_id = jobs[0].template.template_id.clone();
+        let plan_hashes = plan_hashes(&jobs);
+        let single_target = jobs.len() == 1;
+        let total = jobs.len();
+
+        info!("Compiling runners for {} targets", total);
+        let mut scheduler = CompileScheduler::new(self.compiler_config.max_parallel_compilations);
+        if let Some(progress) = &self.progress {
+            scheduler = scheduler.with_progress(progress.clone());
+        }
+        // Builds in progress are killed once cancelled
+        let mut compiler = BinaryCompiler::new(self.compiler_config.clone())
+            .with_cancellation(self.cancel.clone());
+        let report = scheduler.run(&mut compiler, jobs).await;
+        self.check_cancelled()?;
+        if !report.is_success() {
+            return Err(ApiError::Compilation {
+                failures: report.failures,
+                total,
+            });
+        }
+
+        tokio::fs::create_dir_all(&self.output_dir).await?;
+        let mut manifest =
+            DeploymentManifest::new(&template_id, CompilerVersions::detect().clone());
+        manifest.reproducible = self.compiler_config.reproducible.clone();
+        let mut runners = Vec::new();
+        for (target, binary) in report.binaries {
+            let path = runner_output_path(&self.output_dir, &target, single_target);
+            write_runner(&path, &binary.binary_data).await?;
+            manifest.add_artifact(ArtifactEntry::from_file(
+                &self.output_dir,
+                &path,
+                &target,
+                &plan_hashes[&target],
+            )?);
+            runners.push(CompiledRunner {
+                hosts: hosts.remove(&target).unwrap_or_default(),
+                target_triple: target,
+                path,
+                size: binary.size,
+                checksum: binary.checksum,
+            });
+        }
+        let manifest_path = manifest.write(&self.output_dir)?;
+
+        Ok(CompileOutcome {
+            runners,
+            manifest,
+            manifest_path,
+        })
+    }
+
+    /// The runners compiled earlier for the plan, from the output directory:
+    /// those its manifest lists, or those found where [`Self::compile`]
+    /// writes them. A runner built from another plan, or whose plan is
+    /// unknown, is refused unless `force`d.
+    pub async fn prebuilt(&self, force: bool) -> Result<CompileOutcome, ApiError> {
+        self.check_cancelled()?;
+        let (jobs, mut hosts) = self.compile_jobs().await?;
+        let plan_hashes = plan_hashes(&jobs);
+
+        let index = ArtifactIndex::load(&self.output_dir)?;
+        let selected = index.select(&plan_hashes, force)?;
+        let mut runners = Vec::new();
+        let mut built_for = HashMap::new();
+        for (target, artifact) in selected {
+            let target_triple = artifact.target_triple.clone().unwrap_or(target.clone());
+            built_for.insert(target_triple.clone(), target.clone());
+            runners.push(CompiledRunner {
+                hosts: hosts.remove(&target).unwrap_or_default(),
+                target_triple,
+                path: artifact.path.clone(),
+                size: artifact.size,
+                checksum: artifact.sha256.clone(),
+            });
+        }
+
+        // Deploying checks the runners against the manifest, which runners
+        // deployed as forced without one are recorded in first
+        let manifest = match index.manifest {
+            Some(manifest) => manifest,
+            None => {
+                let mut manifest = DeploymentManifest::new(
+                    &jobs[0].template.template_id,
+                    CompilerVersions::default(),
+                );
+                for runner in &runners {
+                    manifest.add_artifact(ArtifactEntry::from_file(
+                        &self.output_dir,
+                        &runner.path,
+                        &runner.target_triple,
+                        &plan_hashes[&built_for[&runner.target_triple]],
+                    )?);
+                }
+                manifest.write(&self.output_dir)?;
+                manifest
+            }
+        };
+
+        Ok(CompileOutcome {
+            runners,
+            manifest,
+            manifest_path: self.output_dir.join(MANIFEST_FILE),
+        })
+    }
+
+    /// A compile job for each selected target, and the hosts of each
+    /// target's runner
+    async fn compile_jobs(
+        &self,
+    ) -> Result<(Vec<CompileJob>, HashMap<String, Vec<String>>), ApiError> {
+        let selected = self.select_targets()?;
+
+        let mut jobs = Vec::new();
+        let mut hosts = HashMap::new();
+        for SelectedTarget {
+            spec,
+            mut deployment,
+        } in selected
+        {
+            let runner_profile = self.compiler_config.runner_profile_for(&spec.target_triple);
+            let template_config = TemplateConfig {
+                runner_profile,
+                ..self.template_config.clone()
+            };
+            let mut generator = BinaryTemplateGenerator::new(template_config)
+                .map_err(TemplateError::from)?
+                .with_vault(self.vault.clone())
+                .with_template_search_path(self.template_paths.clone())
+                .with_payload_policy(self.payload_policy.clone())
+                .with_custom_modules(self.custom_modules.clone());
+            if let Some(python) = &self.python_modules {
+                if runner_profile != RunnerProfile::Wasi {
+                    generator = generator.with_python_modules(python.clone());
+                }
+            }
+            deployment.migrate_from_legacy();
+            let target_info = TargetInfo::for_target(&spec.target_triple)?;
+            let template = self
+                .cancellable(generator.generate_binary_template(
+                    &self.plan,
+                    &deployment,
+                    &target_info,
+                ))
+                .await??;
+            hosts.insert(spec.target_triple.clone(), deployment.target_hosts);
+            jobs.push(CompileJob {
+                template,
+                target: spec,
+            });
+        }
+        Ok((jobs, hosts))
+    }
+
+    /// The deployment of `compiled` to the hosts of each runner, connecting
+    /// as the inventory says when there is one
+    pub async fn deployment_plan(
+        &self,
+        compiled: &CompileOutcome,
+    ) -> Result<DeploymentPlan, ApiError> {
+        let mut inventory_targets = HashMap::new();
+        if let Some(path) = &self.inventory {
+            let processor = InventoryProcessor::new();
+            let inventory =
+                processor
+                    .process_from_source(path)
+                    .await
+                    .map_err(|e| ApiError::Inventory {
+                        reason: e.to_string(),
+                    })?;
+            for (name, host) in &inventory.hosts {
+                inventory_targets.insert(name.clone(), processor.to_deployment_target(name, host));
+            }
+        }
+
+        let deployment_id = uuid::Uuid::new_v4().to_string();
+        let source_tasks: Vec<String> = self
+            .plan
+            .plays
+            .iter()
+            .flat_map(|play| &play.batches)
+            .flat_map(|batch| &batch.tasks)
+            .map(|task| task.name.clone())
+            .collect();
+        let mut binary_compilations = Vec::new();
+        let mut deployment_targets = Vec::new();
+        let mut assigned = BTreeSet::new();
+        for runner in &compiled.runners {
+            let compilation_id = format!("{deployment_id}-{}", runner.target_triple);
+            binary_compilations.push(BinaryCompilation {
+                compilation_id: compilation_id.clone(),
+                binary_name: runner
+                    .path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                target_triple: runner.target_triple.clone(),
+                source_tasks: source_tasks.clone(),
+                embedded_data: EmbeddedExecutionData {
+                    execution_plan: String::new(),
+                    module_implementations: vec![],
+                    static_files: vec![],
+                    runtime_config: Default::default(),
+                    facts_template: vec![],
+                },
+                compilation_options: LegacyCompilationOptions {
+                    optimization_level: self.optimization.clone(),
+                    strip_symbols: self.deployment_config.strip_symbols,
+                    static_linking: runner.target_triple.contains("musl"),
+                    compression: self.deployment_config.compression,
+                    custom_features: vec![],
+                    target_cpu: None,
+                },
+                output_path: runner.path.clone(),
+                checksum: runner.checksum.clone(),
+                size: runner.size,
+            });
+
+            for host in &runner.hosts {
+                if !assigned.insert(host.clone()) {
+                    continue;
+                }
+                let mut target = inventory_targets
+                    .remove(host)
+                    .unwrap_or_else(|| default_target(host, &runner.target_triple));
+                target.binary_compilation_id = compilation_id.clone();
+                deployment_targets.push(target);
+            }
+        }
+
+        Ok(DeploymentPlan {
+            metadata: DeploymentMetadata {
+                deployment_id,
+                created_at: Utc::now(),
+                rustle_plan_version: self.plan.metadata.rustle_plan_version.clone(),
+                execution_plan_hash: self.plan.metadata.playbook_hash.clone(),
+                compiler_version: CompilerVersions::detect().rustc.clone().unwrap_or_default(),
+            },
+            binary_compilations,
+            deployment_targets,
+            deployment_strategy: DeploymentStrategy::Parallel,
+            rollback_info: None,
+            schedule: HostSchedule::default(),
+            delegated_tasks: vec![],
+            strategy: ExecutionStrategy::default(),
+        })
+    }
+
+    /// Deploy the runners of `compiled` to their hosts, checked against the
+    /// manifest, then run them on the hosts they reached
+    pub async fn deploy(&self, compiled: &CompileOutcome) -> Result<DeployOutcome, ApiError> {
+        self.deploy_with(compiled, &[], self.events.as_ref(), None)
+            .await
+    }
+
+    /// Compile runners with no tasks for the plan's hosts, then have them
+    /// report the minimal facts and those of `subsets` instead of running
+    /// anything. Hosts whose runner failed, or reported no facts, are
+    /// failed; the deployment fails only when none could be deployed to.
+    pub async fn gather_facts(&self, subsets: &[FactSubset]) -> Result<GatheredFacts, ApiError> {
+        let compiled = self.compile().await?;
+        let (sink, mut events) = tokio::sync::mpsc::unbounded_channel();
+        // Runners gathering facts report no execution result
+        let verification = ExecutionVerificationConfig {
+            require_result: false,
+            ..Default::default()
+        };
+        let outcome = self
+            .deploy_with(
+                &compiled,
+                &[gather_facts_arg(subsets)],
+                Some(&sink),
+                Some(verification),
+            )
+            .await?;
+        drop(sink);
+
+        let mut gathered = GatheredFacts::default();
+        while let Some(event) = events.recv().await {
+            if let ProgressEvent::FactsCollected { facts, .. } = &event.event {
+                gathered.hosts.insert(event.host.clone(), facts.clone());
+            }
+            if let Some(sink) = &self.events {
+                let _ = sink.send(event);
+            }
+        }
+        for (host, error) in outcome.failed_hosts() {
+            gathered.hosts.remove(host);
+            gathered
+                .failed
+                .entry(host.to_string())
+                .or_insert_with(|| error.to_string());
+        }
+        for host in &outcome.unassigned_hosts {
+            gathered
+                .failed
+                .insert(host.clone(), "no runner was built for it".to_string());
+        }
+        for host in &self.plan.hosts {
+            if !gathered.hosts.contains_key(host) && !gathered.failed.contains_key(host) {
+                gathered
+                    .failed
+                    .insert(host.clone(), "the runner reported no facts".to_string());
+            }
+        }
+        Ok(gathered)
+    }
+
+    /// Deploy `compiled` and run it with `args`, streaming its events to
+    /// `events` and checking its runs with `verification`
+    async fn deploy_with(
+        &self,
+        compiled: &CompileOutcome,
+        args: &[String],
+        events: Option<&EventSink>,
+        verification: Option<ExecutionVerificationConfig>,
+    ) -> Result<DeployOutcome, ApiError> {
+        self.check_cancelled()?;
+        let plan = self.deployment_plan(compiled).await?;
+        let unassigned_hosts: Vec<String> = self
+            .plan
+            .hosts
+            .iter()
+            .filter(|host| {
+                !plan
+                    .deployment_targets
+                    .iter()
+                    .any(|target| &target.host == *host)
+            })
+            .cloned()
+            .collect();
+        for host in &unassigned_hosts {
+            warn!("No runner was built for {}, it is left out", host);
+        }
+
+        // Once cancelled, the manager abandons transfers, has runners stop
+        // after their current task and reports the hosts it left alone
+        let mut manager = DeploymentManager::new(self.deployment_config.clone())
+            .with_manifest_verification()
+            .with_rollback_policy(self.rollback.clone())
+            .with_resume(self.resume)
+            .with_cancellation(self.cancel.clone());
+        if let Some(sink) = events {
+            manager = manager.with_event_sink(sink.clone());
+        }
+        if let Some(verification) = verification {
+            manager = manager.with_execution_verification(verification);
+        }
+        if let Some(metrics) = &self.metrics {
+            manager = manager.with_metrics(Arc::clone(metrics));
+        }
+        let deployment = manager.deploy_binaries(&plan).await?;
+
+        let execution = if self.execute && !self.cancel.is_cancelled() {
+            let deployed: BTreeSet<&str> = deployment
+                .deployment_results
+                .iter()
+                .filter(|result| {
+                    matches!(
+                        result.status,
+                        DeploymentStatus::Deployed | DeploymentStatus::Verified
+                    )
+                })
+                .map(|result| result.host.as_str())
+                .collect();
+            let plan = DeploymentPlan {
+                deployment_targets: plan
+                    .deployment_targets
+                    .iter()
+                    .filter(|target| deployed.contains(target.host.as_str()))
+                    .cloned()
+                    .collect(),
+                ..plan.clone()
+            };
+            Some(manager.execute_deployments(&plan, args).await?)
+        } else {
+            None
+        };
+
+        Ok(DeployOutcome {
+            deployment,
+            execution,
+            unassigned_hosts,
+        })
+    }
+
+    /// Compile, then deploy
+    pub async fn run(&self) -> Result<RunOutcome, ApiError> {
+        let compiled = self.compile().await?;
+        let deployed = self.deploy(&compiled).await?;
+        Ok(RunOutcome { compiled, deployed })
+    }
+
+    fn check_cancelled(&self) -> Result<(), ApiError> {
+        if self.cancel.is_cancelled() {
+            return Err(ApiError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// `work`, unless cancelled first
+    async fn cancellable<T>(&self, work: impl Future<Output = T>) -> Result<T, ApiError> {
+        tokio::select! {
+            biased;
+            _ = self.cancel.cancelled() => Err(ApiError::Cancelled),
+            output = work => Ok(output),
+        }
+    }
+}
+
+/// The hash of the plan each job's runner embeds, by target
+fn plan_hashes(jobs: &[CompileJob]) -> BTreeMap<String, String> {
+    jobs.iter()
+        .map(|job| {
+            (
+                job.target.target_triple.clone(),
+                plan_hash(&job.template.embedded_data.execution_plan),
+            )
+        })
+        .collect()
+}
+
+/// The target of a host no inventory describes, reached over SSH unless it
+/// is this one
+fn default_target(host: &str, target_triple: &str) -> DeploymentTarget {
+    let local = is_local_host(host);
+    let target_path = if target_triple.contains("windows") {
+        "C:\\temp\\rustle-runner.exe".to_string()
+    } else if is_wasi_target(target_triple) {
+        "/tmp/rustle-runner.wasm".to_string()
+    } else {
+        "/tmp/rustle-runner".to_string()
+    };
+    DeploymentTarget {
+        host: host.to_string(),
+        target_path,
+        binary_compilation_id: String::new(),
+        deployment_method: if local {
+            DeploymentMethod::Local
+        } else {
+            DeploymentMethod::Ssh
+        },
+        status: DeploymentStatus::Pending,
+        deployed_at: None,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        connection: Default::default(),
+    }
+}
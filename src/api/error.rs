@@ -0,0 +1,42 @@
+use crate::deploy::DeployError;
+use crate::execution::PlaybookError;
+use crate::template::TemplateError;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("Invalid execution plan: {reason}")]
+    InvalidPlan { reason: String },
+
+    #[error("Cannot select targets: {reason}")]
+    TargetSelection { reason: String },
+
+    #[error("Template generation failed: {0}")]
+    Template(#[from] TemplateError),
+
+    #[error("Compilation failed for {} of {total} targets", failures.len())]
+    Compilation {
+        /// The error of each target that failed to build
+        failures: BTreeMap<String, String>,
+        total: usize,
+    },
+
+    #[error("Deployment failed: {0}")]
+    Deploy(#[from] DeployError),
+
+    #[error("{0}")]
+    Playbook(#[from] PlaybookError),
+
+    #[error("Inventory error: {reason}")]
+    Inventory { reason: String },
+
+    #[error("Fact gathering failed: {reason}")]
+    Facts { reason: String },
+
+    #[error("Cancelled")]
+    Cancelled,
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
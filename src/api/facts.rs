@@ -0,0 +1,132 @@
+//! Gathering the facts of hosts without running a plan
+//!
+//! [`super::Deployment::gather_facts`] deploys runners built from a plan
+//! with no tasks, [`facts_plan`], and runs them with [`gather_facts_arg`]:
+//! each reports its host's facts instead of executing anything. This host's
+//! facts are gathered with the local collectors by [`local_facts`], without
+//! compiling a runner.
+
+use super::error::ApiError;
+use crate::execution::rustle_plan::RustlePlanOutput;
+use crate::execution::{PlanBuilder, PlayBuilder};
+use crate::modules::system::facts::collector::{FactCollector, SystemFactCollector};
+use crate::modules::system::facts::FactCategory;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Argument runners gather facts with instead of running their plan
+pub const GATHER_FACTS_ARG: &str = "--gather-facts";
+
+/// A subset of facts, as Ansible's `gather_subset` names them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FactSubset {
+    /// The system, distribution, kernel and user, always gathered
+    Min,
+    Hardware,
+    Network,
+    Virtual,
+    Env,
+    All,
+}
+
+impl FactSubset {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Min => "min",
+            Self::Hardware => "hardware",
+            Self::Network => "network",
+            Self::Virtual => "virtual",
+            Self::Env => "env",
+            Self::All => "all",
+        }
+    }
+
+    fn categories(&self) -> &'static [FactCategory] {
+        match self {
+            Self::Min => &[],
+            Self::Hardware => &[FactCategory::Hardware],
+            Self::Network => &[FactCategory::Network],
+            Self::Virtual => &[FactCategory::Virtual],
+            Self::Env => &[FactCategory::Env],
+            Self::All => &[FactCategory::All],
+        }
+    }
+}
+
+impl fmt::Display for FactSubset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for FactSubset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "min" | "minimal" => Ok(Self::Min),
+            "hardware" => Ok(Self::Hardware),
+            "network" => Ok(Self::Network),
+            "virtual" => Ok(Self::Virtual),
+            "env" => Ok(Self::Env),
+            "all" => Ok(Self::All),
+            other => Err(format!(
+                "unknown fact subset '{other}', expected min, hardware, network, virtual, env or all"
+            )),
+        }
+    }
+}
+
+/// The runner argument gathering `subsets`, besides the minimal facts
+pub fn gather_facts_arg(subsets: &[FactSubset]) -> String {
+    if subsets.is_empty() {
+        return GATHER_FACTS_ARG.to_string();
+    }
+    let names: Vec<&str> = subsets.iter().map(FactSubset::as_str).collect();
+    format!("{GATHER_FACTS_ARG}={}", names.join(","))
+}
+
+/// A plan with no tasks for `hosts`, whose runners only gather facts
+pub fn facts_plan(hosts: &[String]) -> Result<RustlePlanOutput, ApiError> {
+    PlanBuilder::new()
+        .with_play(PlayBuilder::new("Gather facts").with_hosts(hosts.iter().cloned()))
+        .build()
+        .map_err(|e| ApiError::Facts {
+            reason: e.to_string(),
+        })
+}
+
+/// Whether `host` names this one
+pub fn is_local_host(host: &str) -> bool {
+    matches!(host, "localhost" | "127.0.0.1" | "::1")
+}
+
+/// This host's minimal facts and those of `subsets`, from the local
+/// collectors
+pub async fn local_facts(subsets: &[FactSubset]) -> Result<HashMap<String, Value>, ApiError> {
+    let mut categories = vec![FactCategory::Platform];
+    for subset in subsets {
+        categories.extend(subset.categories().iter().cloned());
+    }
+    let facts = SystemFactCollector::new()
+        .collect_facts(&categories)
+        .await
+        .map_err(|e| ApiError::Facts {
+            reason: e.to_string(),
+        })?;
+    let facts = serde_json::to_value(facts).map_err(|e| ApiError::Facts {
+        reason: e.to_string(),
+    })?;
+    let Value::Object(mut facts) = facts else {
+        return Err(ApiError::Facts {
+            reason: "facts are not an object".to_string(),
+        });
+    };
+    // The collectors read the environment whatever the subsets
+    if !subsets.contains(&FactSubset::Env) && !subsets.contains(&FactSubset::All) {
+        facts.remove("ansible_env");
+    }
+    Ok(facts.into_iter().collect())
+}
@@ -0,0 +1,113 @@
+//! Library API for compiling execution plans into runners and deploying
+//! them.
+//!
+//! [`Deployment`] drives the whole pipeline the command line does: it loads
+//! a rustle-plan, selects the targets its binary deployments need, compiles
+//! a runner for each, deploys the runners to their hosts and runs them.
+//! Each stage can also be driven on its own, and a [`CancellationToken`]
+//! stops the pipeline between or during stages.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), rustle_deploy::api::ApiError> {
+//! use rustle_deploy::api::Deployment;
+//! use rustle_deploy::execution::VaultSecrets;
+//!
+//! let outcome = Deployment::load("plan.json", &VaultSecrets::default())
+//!     .await?
+//!     .with_output_dir("target/runners")
+//!     .with_inventory("inventory.yml")
+//!     .run()
+//!     .await?;
+//! for (host, error) in outcome.deployed.failed_hosts() {
+//!     eprintln!("{host}: {error}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod deployment;
+pub mod error;
+pub mod facts;
+pub mod results;
+#[cfg(feature = "server")]
+pub mod server;
+
+pub use deployment::{Deployment, SelectedTarget};
+pub use error::ApiError;
+pub use facts::{facts_plan, local_facts, FactSubset};
+pub use results::{CompileOutcome, CompiledRunner, DeployOutcome, GatheredFacts, RunOutcome};
+#[cfg(feature = "server")]
+pub use server::ControlServer;
+pub use tokio_util::sync::CancellationToken;
+
+use crate::compilation::is_wasi_target;
+use crate::execution::plan_schema::{ParseMode, PlanMigrations};
+use crate::execution::rustle_plan::RustlePlanOutput;
+use crate::execution::VaultSecrets;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Parse a rustle-plan JSON document, decrypting its vaulted values with
+/// `vault` and migrating it from older schemas
+pub fn parse_plan(content: &str, vault: &VaultSecrets) -> Result<RustlePlanOutput, ApiError> {
+    parse_plan_with_mode(content, vault, ParseMode::Lenient)
+}
+
+/// Parse a rustle-plan JSON document as [`parse_plan`] does, refusing in
+/// [`ParseMode::Strict`] plans that cannot be migrated to the current schema
+pub fn parse_plan_with_mode(
+    content: &str,
+    vault: &VaultSecrets,
+    mode: ParseMode,
+) -> Result<RustlePlanOutput, ApiError> {
+    let invalid = |reason: String| ApiError::InvalidPlan { reason };
+    let mut value: serde_json::Value =
+        serde_json::from_str(content).map_err(|e| invalid(e.to_string()))?;
+    let report = PlanMigrations::new()
+        .migrate(&mut value, mode)
+        .map_err(|e| invalid(e.to_string()))?;
+    if !report.is_current() {
+        info!(
+            "Migrated the plan from schema {} to {}",
+            report.from_version, report.to_version
+        );
+    }
+    for warning in &report.warnings {
+        warn!("Plan migration: {}", warning);
+    }
+
+    vault
+        .decrypt_json(&mut value)
+        .map_err(|e| invalid(format!("Failed to decrypt vaulted values of the plan: {e}")))?;
+    serde_json::from_value(value).map_err(|e| invalid(e.to_string()))
+}
+
+/// Where the runner for `target` goes in `output_dir`: at the top when it
+/// is the only one, in a directory per target otherwise
+pub fn runner_output_path(output_dir: &Path, target: &str, single_target: bool) -> PathBuf {
+    let binary_name = if is_wasi_target(target) {
+        "rustle-runner.wasm"
+    } else {
+        "rustle-runner"
+    };
+    if single_target {
+        output_dir.join(binary_name)
+    } else {
+        output_dir.join(target).join(binary_name)
+    }
+}
+
+/// Write the runner `binary` to `path`, executable
+pub async fn write_runner(path: &Path, binary: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, binary).await?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use crate::deploy::manager::DeploymentReport;
+use crate::deploy::DeploymentManifest;
+use crate::types::deployment::DeploymentStatus;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+/// A runner written to the output directory
+#[derive(Debug, Clone, Serialize)]
+pub struct CompiledRunner {
+    pub target_triple: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub checksum: String,
+    /// The plan's hosts the runner is for
+    pub hosts: Vec<String>,
+}
+
+/// What [`super::Deployment::compile`] built
+#[derive(Debug, Clone)]
+pub struct CompileOutcome {
+    pub runners: Vec<CompiledRunner>,
+    pub manifest: DeploymentManifest,
+    pub manifest_path: PathBuf,
+}
+
+impl CompileOutcome {
+    /// The runner built for `target_triple`
+    pub fn runner(&self, target_triple: &str) -> Option<&CompiledRunner> {
+        self.runners
+            .iter()
+            .find(|runner| runner.target_triple == target_triple)
+    }
+}
+
+/// What [`super::Deployment::deploy`] did on the hosts
+#[derive(Debug)]
+pub struct DeployOutcome {
+    /// Transfer of the runners
+    pub deployment: DeploymentReport,
+    /// Their runs, on the hosts they were deployed to, unless execution is
+    /// turned off
+    pub execution: Option<DeploymentReport>,
+    /// Hosts of the plan no runner was built for
+    pub unassigned_hosts: Vec<String>,
+}
+
+impl DeployOutcome {
+    /// Whether every host got its runner and, when run, ran it successfully
+    pub fn is_success(&self) -> bool {
+        let succeeded = |report: &DeploymentReport| {
+            report.deployment_results.iter().all(|result| {
+                matches!(
+                    result.status,
+                    DeploymentStatus::Deployed | DeploymentStatus::Verified
+                )
+            })
+        };
+        succeeded(&self.deployment) && self.execution.as_ref().is_none_or(succeeded)
+    }
+
+    /// The hosts that failed to deploy or to run, with their errors
+    pub fn failed_hosts(&self) -> Vec<(&str, &str)> {
+        self.deployment
+            .deployment_results
+            .iter()
+            .chain(
+                self.execution
+                    .iter()
+                    .flat_map(|report| &report.deployment_results),
+            )
+            .filter_map(|result| match &result.status {
+                DeploymentStatus::Failed { error } => Some((result.host.as_str(), error.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// What [`super::Deployment::run`] built and did
+#[derive(Debug)]
+pub struct RunOutcome {
+    pub compiled: CompileOutcome,
+    pub deployed: DeployOutcome,
+}
+
+impl RunOutcome {
+    pub fn is_success(&self) -> bool {
+        self.deployed.is_success()
+    }
+}
+
+/// What [`super::Deployment::gather_facts`] gathered
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GatheredFacts {
+    /// The facts of each host that reported them
+    pub hosts: BTreeMap<String, HashMap<String, Value>>,
+    /// Why each other host reported none
+    pub failed: BTreeMap<String, String>,
+}
+
+impl GatheredFacts {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
@@ -0,0 +1,740 @@
+//! HTTP control API over [`Deployment`], for `rustle-deploy serve`
+//!
+//! A [`ControlServer`] keeps the plans submitted to it and the runs it
+//! started, so other services and web UIs can drive deployments:
+//!
+//! | Method | Path | |
+//! |---|---|---|
+//! | `POST` | `/plans` | Submit a rustle-plan, answering its id |
+//! | `GET` | `/plans` | The plans submitted |
+//! | `GET` | `/plans/{plan}` | A plan, as submitted: its vaulted values stay encrypted |
+//! | `POST` | `/plans/{plan}/compile` | Compile the plan's runners |
+//! | `POST` | `/plans/{plan}/deploy` | Compile, deploy and run them; `?runners={run}` deploys those a compile run built |
+//! | `GET` | `/runs` | The runs started |
+//! | `GET` | `/runs/{run}` | A run |
+//! | `POST` | `/runs/{run}/cancel` | Cancel a run |
+//! | `GET` | `/runs/{run}/events` | The hosts' events as server-sent `host` events, then a `finished` event with the run |
+//! | `GET` | `/runs/{run}/ws` | The same over a WebSocket, as `{"event": ...}` and `{"finished": ...}` frames |
+//! | `GET` | `/history` | Runs recorded in the execution history, newest first; `?host=` and `?limit=` narrow them |
+//!
+//! Runs go on in the background: starting one answers `202 Accepted` with
+//! the run, whose runners are written to a directory of its own under the
+//! output directory. Event streams replay what a run streamed before they
+//! were opened. Only the most recent plans and finished runs are kept, as
+//! many of each as [`ControlServer::with_retention`] says.
+//!
+//! Given a token, every request must carry it as
+//! `Authorization: Bearer <token>`. Event streams, which browsers open
+//! without setting headers, also take it as a `token` query parameter.
+
+use super::error::ApiError;
+use super::parse_plan;
+use super::results::{CompileOutcome, CompiledRunner, DeployOutcome};
+use super::Deployment;
+use crate::compilation::compiler::CompilerConfig;
+use crate::deploy::history::RunRecord;
+use crate::deploy::{ExecutionHistory, HostEvent};
+use crate::execution::rustle_plan::RustlePlanOutput;
+use crate::execution::VaultSecrets;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path as UrlPath, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+/// Environment variable `rustle-deploy serve` reads its token from
+pub const TOKEN_ENV: &str = "RUSTLE_API_TOKEN";
+
+/// How many plans, and how many finished runs, a server keeps by default
+pub const DEFAULT_RETENTION: usize = 100;
+
+/// Why a request was refused
+#[derive(Error, Debug)]
+pub enum ServerError {
+    #[error("{what} not found")]
+    NotFound { what: String },
+
+    #[error("{reason}")]
+    BadRequest { reason: String },
+
+    #[error("{reason}")]
+    Conflict { reason: String },
+
+    #[error("Missing or wrong token")]
+    Unauthorized,
+
+    #[error("{reason}")]
+    Internal { reason: String },
+}
+
+impl IntoResponse for ServerError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            Self::NotFound { .. } => StatusCode::NOT_FOUND,
+            Self::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            Self::Conflict { .. } => StatusCode::CONFLICT,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let body = serde_json::json!({ "error": self.to_string() });
+        (status, Json(body)).into_response()
+    }
+}
+
+/// A plan submitted to the server
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanSummary {
+    pub plan_id: String,
+    pub submitted_at: DateTime<Utc>,
+    pub hosts: Vec<String>,
+    pub total_tasks: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunKind {
+    /// Compiles the plan's runners
+    Compile,
+    /// Deploys and runs them, compiled first unless a compile run built them
+    Deploy,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum RunState {
+    Running,
+    Succeeded,
+    Failed { error: String },
+    Cancelled,
+}
+
+/// A compilation or deployment the server started
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    pub run_id: String,
+    pub plan_id: String,
+    pub kind: RunKind,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub state: RunState,
+    /// Where the run's runners are written
+    pub output_dir: PathBuf,
+    pub runners: Vec<CompiledRunner>,
+    /// The hosts that failed to deploy or to run, with their errors
+    pub failed_hosts: BTreeMap<String, String>,
+    /// Events the hosts streamed so far
+    pub events: usize,
+}
+
+/// A run going on or done, and the events its hosts streamed
+struct Run {
+    summary: Mutex<RunSummary>,
+    events: Mutex<Vec<HostEvent>>,
+    /// Changes whenever an event arrives or the run finishes
+    changes: watch::Sender<()>,
+    cancel: CancellationToken,
+}
+
+impl Run {
+    fn new(summary: RunSummary) -> Self {
+        Self {
+            summary: Mutex::new(summary),
+            events: Mutex::new(Vec::new()),
+            changes: watch::Sender::new(()),
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    fn summary(&self) -> RunSummary {
+        self.summary.lock().unwrap().clone()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.summary.lock().unwrap().state != RunState::Running
+    }
+
+    fn push(&self, event: HostEvent) {
+        self.events.lock().unwrap().push(event);
+        self.summary.lock().unwrap().events += 1;
+        self.changes.send_modify(|_| {});
+    }
+
+    fn finish(&self, outcome: Result<(CompileOutcome, Option<DeployOutcome>), ApiError>) {
+        let mut summary = self.summary.lock().unwrap();
+        let state = match outcome {
+            Ok((compiled, deployed)) => {
+                summary.runners = compiled.runners;
+                let Some(deployed) = deployed else {
+                    return self.finish_with(summary, RunState::Succeeded);
+                };
+                for (host, error) in deployed.failed_hosts() {
+                    summary
+                        .failed_hosts
+                        .insert(host.to_string(), error.to_string());
+                }
+                for host in &deployed.unassigned_hosts {
+                    summary
+                        .failed_hosts
+                        .insert(host.clone(), "no runner was built for it".to_string());
+                }
+                if self.cancel.is_cancelled() {
+                    RunState::Cancelled
+                } else if deployed.is_success() && summary.failed_hosts.is_empty() {
+                    RunState::Succeeded
+                } else {
+                    RunState::Failed {
+                        error: format!("{} hosts failed", summary.failed_hosts.len()),
+                    }
+                }
+            }
+            Err(ApiError::Cancelled) => RunState::Cancelled,
+            Err(e) => RunState::Failed {
+                error: e.to_string(),
+            },
+        };
+        self.finish_with(summary, state);
+    }
+
+    fn finish_with(&self, mut summary: std::sync::MutexGuard<'_, RunSummary>, state: RunState) {
+        summary.state = state;
+        summary.finished_at = Some(Utc::now());
+        drop(summary);
+        self.changes.send_modify(|_| {});
+    }
+
+    /// The run's events, those streamed so far and then the others as they
+    /// arrive, until it finishes
+    fn events(self: Arc<Self>) -> impl Stream<Item = HostEvent> + Send + 'static {
+        let changes = self.changes.subscribe();
+        futures::stream::unfold((self, changes, 0), |(run, mut changes, next)| async move {
+            loop {
+                // Every event is in before the run finishes
+                let finished = run.is_finished();
+                let event = run.events.lock().unwrap().get(next).cloned();
+                if let Some(event) = event {
+                    return Some((event, (run, changes, next + 1)));
+                }
+                if finished {
+                    return None;
+                }
+                changes.changed().await.ok()?;
+            }
+        })
+    }
+}
+
+struct SubmittedPlan {
+    summary: PlanSummary,
+    /// The plan as submitted, its vaulted values still encrypted
+    document: serde_json::Value,
+    plan: RustlePlanOutput,
+}
+
+/// Serves the control API, deploying as its configuration says
+#[derive(Clone)]
+pub struct ControlServer {
+    output_dir: PathBuf,
+    inventory: Option<PathBuf>,
+    compiler_config: CompilerConfig,
+    vault: VaultSecrets,
+    history: ExecutionHistory,
+    token: Option<String>,
+    retention: usize,
+    plans: Arc<RwLock<BTreeMap<String, SubmittedPlan>>>,
+    runs: Arc<RwLock<BTreeMap<String, Arc<Run>>>>,
+}
+
+impl ControlServer {
+    /// Serve without a token, writing runs' runners under the current
+    /// directory
+    pub fn new() -> Self {
+        Self {
+            output_dir: PathBuf::from("."),
+            inventory: None,
+            compiler_config: CompilerConfig::default(),
+            vault: VaultSecrets::default(),
+            history: ExecutionHistory::new(ExecutionHistory::default_dir()),
+            token: None,
+            retention: DEFAULT_RETENTION,
+            plans: Arc::default(),
+            runs: Arc::default(),
+        }
+    }
+
+    /// Write the runners of each run to a directory of its own under `dir`
+    pub fn with_output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = dir.into();
+        self
+    }
+
+    /// Connect to hosts as the inventory at `path` says
+    pub fn with_inventory(mut self, path: impl Into<PathBuf>) -> Self {
+        self.inventory = Some(path.into());
+        self
+    }
+
+    pub fn with_compiler_config(mut self, config: CompilerConfig) -> Self {
+        self.compiler_config = config;
+        self
+    }
+
+    /// Decrypt the vaulted values of submitted plans
+    pub fn with_vault(mut self, vault: VaultSecrets) -> Self {
+        self.vault = vault;
+        self
+    }
+
+    /// Answer `/history` from `history` instead of the default one, which
+    /// deployments record their runs in
+    pub fn with_history(mut self, history: ExecutionHistory) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Refuse requests that do not carry `token`
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Keep the `limit` most recent plans and finished runs, forgetting
+    /// older ones as new ones arrive
+    pub fn with_retention(mut self, limit: usize) -> Self {
+        self.retention = limit;
+        self
+    }
+
+    pub fn router(&self) -> Router {
+        let streams = Router::new()
+            .route("/runs/{run_id}/events", get(run_events))
+            .route("/runs/{run_id}/ws", get(run_socket))
+            .route_layer(middleware::from_fn_with_state(
+                self.clone(),
+                authorize_stream,
+            ));
+        Router::new()
+            .route("/plans", post(submit_plan).get(list_plans))
+            .route("/plans/{plan_id}", get(get_plan))
+            .route("/plans/{plan_id}/compile", post(compile_plan))
+            .route("/plans/{plan_id}/deploy", post(deploy_plan))
+            .route("/runs", get(list_runs))
+            .route("/runs/{run_id}", get(get_run))
+            .route("/runs/{run_id}/cancel", post(cancel_run))
+            .route("/history", get(history))
+            .route_layer(middleware::from_fn_with_state(self.clone(), authorize))
+            .merge(streams)
+            .with_state(self.clone())
+    }
+
+    /// Answer requests on `listener` until the server fails
+    pub async fn serve(self, listener: TcpListener) -> std::io::Result<()> {
+        info!("Control API listening on {}", listener.local_addr()?);
+        axum::serve(listener, self.router()).await
+    }
+
+    fn plan(&self, plan_id: &str) -> Result<RustlePlanOutput, ServerError> {
+        self.plans
+            .read()
+            .unwrap()
+            .get(plan_id)
+            .map(|submitted| submitted.plan.clone())
+            .ok_or_else(|| ServerError::NotFound {
+                what: format!("Plan {plan_id}"),
+            })
+    }
+
+    /// Forget the oldest finished runs, and the oldest plans no running run
+    /// deploys, beyond those the server keeps
+    fn evict(&self) {
+        let mut runs = self.runs.write().unwrap();
+        let mut finished: Vec<(DateTime<Utc>, String)> = runs
+            .iter()
+            .filter(|(_, run)| run.is_finished())
+            .map(|(run_id, run)| (run.summary().started_at, run_id.clone()))
+            .collect();
+        finished.sort();
+        let excess = finished.len().saturating_sub(self.retention);
+        for (_, run_id) in finished.into_iter().take(excess) {
+            runs.remove(&run_id);
+        }
+
+        let mut plans = self.plans.write().unwrap();
+        let mut idle: Vec<(DateTime<Utc>, String)> = plans
+            .iter()
+            .filter(|(plan_id, _)| {
+                !runs.values().any(|run| {
+                    !run.is_finished() && run.summary.lock().unwrap().plan_id == **plan_id
+                })
+            })
+            .map(|(plan_id, submitted)| (submitted.summary.submitted_at, plan_id.clone()))
+            .collect();
+        idle.sort();
+        let excess = plans.len().saturating_sub(self.retention);
+        for (_, plan_id) in idle.into_iter().take(excess) {
+            plans.remove(&plan_id);
+        }
+    }
+
+    fn run(&self, run_id: &str) -> Result<Arc<Run>, ServerError> {
+        self.runs
+            .read()
+            .unwrap()
+            .get(run_id)
+            .cloned()
+            .ok_or_else(|| ServerError::NotFound {
+                what: format!("Run {run_id}"),
+            })
+    }
+
+    /// Start a run of `kind` of the plan, deploying the runners in
+    /// `runners` if given instead of compiling them
+    fn start(
+        &self,
+        plan_id: String,
+        plan: RustlePlanOutput,
+        kind: RunKind,
+        runners: Option<PathBuf>,
+    ) -> Arc<Run> {
+        let run_id = uuid::Uuid::new_v4().to_string();
+        let output_dir = runners
+            .clone()
+            .unwrap_or_else(|| self.output_dir.join(&run_id));
+        info!("Starting {:?} run {} of plan {}", kind, run_id, plan_id);
+        let run = Arc::new(Run::new(RunSummary {
+            run_id: run_id.clone(),
+            plan_id,
+            kind,
+            started_at: Utc::now(),
+            finished_at: None,
+            state: RunState::Running,
+            output_dir: output_dir.clone(),
+            runners: Vec::new(),
+            failed_hosts: BTreeMap::new(),
+            events: 0,
+        }));
+        self.runs.write().unwrap().insert(run_id, Arc::clone(&run));
+
+        let (sink, mut events) = tokio::sync::mpsc::unbounded_channel();
+        let mut deployment = Deployment::new(plan)
+            .with_output_dir(output_dir)
+            .with_compiler_config(self.compiler_config.clone())
+            .with_vault(self.vault.clone())
+            .with_event_sink(sink)
+            .with_cancellation(run.cancel.clone());
+        if let Some(inventory) = &self.inventory {
+            deployment = deployment.with_inventory(inventory.clone());
+        }
+
+        let forwarded = {
+            let run = Arc::clone(&run);
+            tokio::spawn(async move {
+                while let Some(event) = events.recv().await {
+                    run.push(event);
+                }
+            })
+        };
+        let finished = Arc::clone(&run);
+        let server = self.clone();
+        tokio::spawn(async move {
+            let outcome = match (kind, runners) {
+                (RunKind::Compile, _) => {
+                    deployment.compile().await.map(|compiled| (compiled, None))
+                }
+                (RunKind::Deploy, None) => deployment
+                    .run()
+                    .await
+                    .map(|outcome| (outcome.compiled, Some(outcome.deployed))),
+                (RunKind::Deploy, Some(_)) => match deployment.prebuilt(false).await {
+                    Ok(compiled) => deployment
+                        .deploy(&compiled)
+                        .await
+                        .map(|deployed| (compiled, Some(deployed))),
+                    Err(e) => Err(e),
+                },
+            };
+            // The sink closes with the deployment, once its events are in
+            drop(deployment);
+            let _ = forwarded.await;
+            finished.finish(outcome);
+            server.evict();
+        });
+        run
+    }
+}
+
+impl Default for ControlServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether `given` is `token`, taking as long whatever it is
+fn token_matches(given: &str, token: &str) -> bool {
+    let given = Sha256::digest(given.as_bytes());
+    let token = Sha256::digest(token.as_bytes());
+    given
+        .iter()
+        .zip(token.iter())
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+fn bearer_token(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+async fn authorize(
+    State(server): State<ControlServer>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ServerError> {
+    if let Some(token) = &server.token {
+        if !bearer_token(&request).is_some_and(|given| token_matches(given, token)) {
+            return Err(ServerError::Unauthorized);
+        }
+    }
+    Ok(next.run(request).await)
+}
+
+/// Authorize as [`authorize`] does, also taking the token as a `token`
+/// query parameter, since browsers open event streams without headers
+async fn authorize_stream(
+    State(server): State<ControlServer>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ServerError> {
+    if let Some(token) = &server.token {
+        let query = request.uri().query().and_then(|query| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .find(|(name, _)| name == "token")
+                .map(|(_, value)| value.into_owned())
+        });
+        let given = bearer_token(&request).map(str::to_string).or(query);
+        if !given.is_some_and(|given| token_matches(&given, token)) {
+            return Err(ServerError::Unauthorized);
+        }
+    }
+    Ok(next.run(request).await)
+}
+
+async fn submit_plan(
+    State(server): State<ControlServer>,
+    body: String,
+) -> Result<(StatusCode, Json<PlanSummary>), ServerError> {
+    let plan = parse_plan(&body, &server.vault).map_err(|e| ServerError::BadRequest {
+        reason: e.to_string(),
+    })?;
+    let document = serde_json::from_str(&body).map_err(|e| ServerError::BadRequest {
+        reason: e.to_string(),
+    })?;
+    let summary = PlanSummary {
+        plan_id: uuid::Uuid::new_v4().to_string(),
+        submitted_at: Utc::now(),
+        hosts: plan.hosts.clone(),
+        total_tasks: plan.total_tasks,
+    };
+    info!(
+        "Plan {} submitted for {} hosts",
+        summary.plan_id,
+        summary.hosts.len()
+    );
+    server.plans.write().unwrap().insert(
+        summary.plan_id.clone(),
+        SubmittedPlan {
+            summary: summary.clone(),
+            document,
+            plan,
+        },
+    );
+    server.evict();
+    Ok((StatusCode::CREATED, Json(summary)))
+}
+
+async fn list_plans(State(server): State<ControlServer>) -> Json<Vec<PlanSummary>> {
+    let mut plans: Vec<PlanSummary> = server
+        .plans
+        .read()
+        .unwrap()
+        .values()
+        .map(|submitted| submitted.summary.clone())
+        .collect();
+    plans.sort_by_key(|plan| plan.submitted_at);
+    Json(plans)
+}
+
+async fn get_plan(
+    State(server): State<ControlServer>,
+    UrlPath(plan_id): UrlPath<String>,
+) -> Result<Json<serde_json::Value>, ServerError> {
+    server
+        .plans
+        .read()
+        .unwrap()
+        .get(&plan_id)
+        .map(|submitted| Json(submitted.document.clone()))
+        .ok_or_else(|| ServerError::NotFound {
+            what: format!("Plan {plan_id}"),
+        })
+}
+
+async fn compile_plan(
+    State(server): State<ControlServer>,
+    UrlPath(plan_id): UrlPath<String>,
+) -> Result<(StatusCode, Json<RunSummary>), ServerError> {
+    let plan = server.plan(&plan_id)?;
+    let run = server.start(plan_id, plan, RunKind::Compile, None);
+    Ok((StatusCode::ACCEPTED, Json(run.summary())))
+}
+
+#[derive(Debug, Deserialize)]
+struct DeployQuery {
+    /// The compile run whose runners to deploy
+    runners: Option<String>,
+}
+
+async fn deploy_plan(
+    State(server): State<ControlServer>,
+    UrlPath(plan_id): UrlPath<String>,
+    Query(query): Query<DeployQuery>,
+) -> Result<(StatusCode, Json<RunSummary>), ServerError> {
+    let plan = server.plan(&plan_id)?;
+    let runners = match &query.runners {
+        Some(run_id) => {
+            let compiled = server.run(run_id)?.summary();
+            if compiled.kind != RunKind::Compile || compiled.plan_id != plan_id {
+                return Err(ServerError::Conflict {
+                    reason: format!("Run {run_id} did not compile plan {plan_id}"),
+                });
+            }
+            if compiled.state != RunState::Succeeded {
+                return Err(ServerError::Conflict {
+                    reason: format!("Run {run_id} has not compiled its runners"),
+                });
+            }
+            Some(compiled.output_dir)
+        }
+        None => None,
+    };
+    let run = server.start(plan_id, plan, RunKind::Deploy, runners);
+    Ok((StatusCode::ACCEPTED, Json(run.summary())))
+}
+
+async fn list_runs(State(server): State<ControlServer>) -> Json<Vec<RunSummary>> {
+    let mut runs: Vec<RunSummary> = server
+        .runs
+        .read()
+        .unwrap()
+        .values()
+        .map(|run| run.summary())
+        .collect();
+    runs.sort_by_key(|run| run.started_at);
+    Json(runs)
+}
+
+async fn get_run(
+    State(server): State<ControlServer>,
+    UrlPath(run_id): UrlPath<String>,
+) -> Result<Json<RunSummary>, ServerError> {
+    Ok(Json(server.run(&run_id)?.summary()))
+}
+
+async fn cancel_run(
+    State(server): State<ControlServer>,
+    UrlPath(run_id): UrlPath<String>,
+) -> Result<Json<RunSummary>, ServerError> {
+    let run = server.run(&run_id)?;
+    run.cancel.cancel();
+    Ok(Json(run.summary()))
+}
+
+async fn run_events(
+    State(server): State<ControlServer>,
+    UrlPath(run_id): UrlPath<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ServerError> {
+    let run = server.run(&run_id)?;
+    let events = Arc::clone(&run)
+        .events()
+        .map(|event| Event::default().event("host").json_data(event));
+    let finished = futures::stream::once(async move {
+        Event::default().event("finished").json_data(run.summary())
+    });
+    Ok(Sse::new(events.chain(finished)).keep_alive(KeepAlive::default()))
+}
+
+/// A frame of a run's WebSocket
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Frame {
+    Event(HostEvent),
+    Finished(RunSummary),
+}
+
+async fn run_socket(
+    State(server): State<ControlServer>,
+    UrlPath(run_id): UrlPath<String>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, ServerError> {
+    let run = server.run(&run_id)?;
+    Ok(upgrade.on_upgrade(move |socket| stream_run(run, socket)))
+}
+
+async fn stream_run(run: Arc<Run>, mut socket: WebSocket) {
+    let events = Arc::clone(&run).events().map(Frame::Event);
+    let finished = futures::stream::once(async move { Frame::Finished(run.summary()) });
+    let mut frames = std::pin::pin!(events.chain(finished));
+    while let Some(frame) = frames.next().await {
+        let Ok(text) = serde_json::to_string(&frame) else {
+            continue;
+        };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            return;
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    host: Option<String>,
+    limit: Option<usize>,
+}
+
+async fn history(
+    State(server): State<ControlServer>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<RunRecord>>, ServerError> {
+    let mut runs = server
+        .history
+        .load_runs()
+        .map_err(|e| ServerError::Internal {
+            reason: e.to_string(),
+        })?;
+    if let Some(host) = &query.host {
+        runs.retain(|run| run.host.as_ref() == Some(host));
+    }
+    runs.sort_by_key(|run| std::cmp::Reverse(run.recorded_at));
+    if let Some(limit) = query.limit {
+        runs.truncate(limit);
+    }
+    Ok(Json(runs))
+}
@@ -1,13 +1,52 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use rustle_deploy::api::facts::is_local_host;
+use rustle_deploy::api::{
+    facts_plan, local_facts, parse_plan_with_mode, runner_output_path, write_runner, ApiError,
+    CancellationToken, CompileOutcome, CompiledRunner, DeployOutcome, Deployment, FactSubset,
+    GatheredFacts,
+};
 use rustle_deploy::compilation::compiler::{BinaryCompiler, CompilerConfig};
-use rustle_deploy::compilation::TargetDetector;
-use rustle_deploy::execution::format_migration::FormatMigrator;
-use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
-use rustle_deploy::template::{BinaryTemplateGenerator, TargetInfo, TemplateConfig};
-use rustle_deploy::types::compilation::{OptimizationLevel, TargetSpecification};
-use rustle_deploy::types::platform::Platform;
-use std::path::PathBuf;
+use rustle_deploy::compilation::scheduler::{CompileJob, CompileProgress, CompileScheduler};
+use rustle_deploy::compilation::{
+    check_profile_compatibility, choose_linkage, plan_targets, rustc_release, CacheStoreConfig,
+    CompilationCache, CompilationDoctor, CompileEstimate, CompileEstimator, Linkage,
+    ReproducibleBuild, RustupTargets, SizeOptimizer, SmokeTest, SmokeTestStatus, StripMode,
+    TargetDetector, TargetInstallPolicy, UpxConfig, WASI_MODULES,
+};
+use rustle_deploy::deploy::manifest::{local_builder_id, plan_hash, MANIFEST_FILE};
+use rustle_deploy::deploy::{
+    ArtifactEntry, AuditFormat, AuditLog, CompilerVersions, DeployError, DeploymentManifest,
+    DeploymentMetrics, ExecutionHistory, HostEvent, OtlpExporter, ReportTarget, ResultCollector,
+    RollbackPolicy, RunReport,
+};
+use rustle_deploy::execution::rustle_plan::{BinaryDeploymentPlan, RustlePlanOutput};
+use rustle_deploy::execution::vault::DEFAULT_VAULT_ID;
+use rustle_deploy::execution::{
+    is_playbook, plan_json_schema, render_plan_variables, resolve_plan_lookups,
+    resolve_variable_lookups, ParseMode, PlanMigrations, PlaybookLoader, SopsKeys, VarsFileLoader,
+    VaultIdentity, VaultSecrets,
+};
+use rustle_deploy::inventory::{
+    inventory_root, ConnectionPreflight, DirectoryVars, HostPattern, InventoryExport,
+    InventoryProcessor, PrecedenceResolver, PreflightReport, VariablePrecedence,
+};
+use rustle_deploy::modules::files::template_engine::{LookupContext, LookupRegistry, LookupSide};
+use rustle_deploy::modules::{
+    CustomModule, ModuleSearchPath, PythonModules, ANSIBLE_LIBRARY_ENV, MODULE_PATH_ENV,
+};
+use rustle_deploy::runtime::{
+    generate_result_keypair, BinarySigner, LookupConfig, ObjectStoreConfig, SecretLookups,
+};
+use rustle_deploy::template::{
+    BinaryTemplateGenerator, DataLayout, PayloadPolicy, TargetInfo, TemplateConfig,
+};
+use rustle_deploy::types::compilation::{OptimizationLevel, RunnerProfile, TargetSpecification};
+use rustle_deploy::types::deployment::DeploymentStatus;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::{error, info, warn};
 
 #[derive(Parser)]
@@ -15,7 +54,11 @@ use tracing::{error, info, warn};
 #[command(about = "Ansible replacement with binary deployment optimization")]
 #[command(version = env!("CARGO_PKG_VERSION"))]
 struct RustleDeployCli {
-    /// Execution plan JSON file from rustle-plan (or stdin if -)
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Execution plan JSON file from rustle-plan (or stdin if -), or an
+    /// Ansible playbook (.yml) to plan against the inventory
     execution_plan: Option<PathBuf>,
 
     /// Inventory file with target host information
@@ -26,10 +69,15 @@ struct RustleDeployCli {
     #[arg(long)]
     check_capabilities: bool,
 
-    /// Install missing dependencies
+    /// Install missing dependencies; with an execution plan, install the
+    /// rust targets its builds need as they go
     #[arg(long)]
     setup: bool,
 
+    /// Pin the runners' builds to this Rust toolchain, e.g. 1.79.0
+    #[arg(long, value_name = "CHANNEL")]
+    toolchain: Option<String>,
+
     /// Directory for compiled binaries
     #[arg(short, long, default_value = ".")]
     output_dir: PathBuf,
@@ -42,10 +90,20 @@ struct RustleDeployCli {
     #[arg(long)]
     cache_dir: Option<PathBuf>,
 
-    /// Enable incremental compilation
+    /// Shared cache store configuration (YAML or JSON: local, s3 or http)
+    #[arg(long)]
+    cache_store: Option<PathBuf>,
+
+    /// Keep each target's generated project between builds and recompile
+    /// only what the plan changed
     #[arg(long)]
     incremental: bool,
 
+    /// Build byte-identical runners: pin the toolchain, set SOURCE_DATE_EPOCH,
+    /// remap build paths and check each target builds the same twice
+    #[arg(long, conflicts_with = "incremental")]
+    reproducible: bool,
+
     /// Force rebuild of all binaries
     #[arg(long)]
     rebuild: bool,
@@ -58,10 +116,76 @@ struct RustleDeployCli {
     #[arg(long)]
     compile_only: bool,
 
+    /// With --deploy-only, deploy runners built from another plan, or whose
+    /// plan no manifest records
+    #[arg(long, requires = "deploy_only")]
+    force: bool,
+
+    /// Targets to compile at once (default: one per CPU)
+    #[arg(long)]
+    compile_jobs: Option<usize>,
+
     /// Optimization mode
     #[arg(long, default_value = "auto")]
     optimization: String,
 
+    /// Strip binaries (none, debuginfo, symbols) whatever the optimization
+    #[arg(long)]
+    strip: Option<StripMode>,
+
+    /// Pack binaries with UPX at this level (1-9, 10 for --best)
+    #[arg(long, value_name = "LEVEL", num_args = 0..=1, default_missing_value = "7")]
+    upx: Option<u8>,
+
+    /// Pack with UPX's LZMA: smaller binaries that start slower
+    #[arg(long, requires = "upx")]
+    upx_lzma: bool,
+
+    /// Build static musl runners (--static=false to keep glibc); by default
+    /// only hosts without a compatible glibc get them
+    #[arg(long = "static", num_args = 0..=1, default_missing_value = "true")]
+    static_linking: Option<bool>,
+
+    /// Runner profile (standard, minimal); WASI targets always get the wasi one
+    #[arg(long, default_value = "standard")]
+    runner_profile: String,
+
+    /// Append the execution data to a runner built once per target and
+    /// module set, instead of compiling it in, so plan-only changes need no
+    /// rebuild
+    #[arg(long)]
+    appended_data: bool,
+
+    /// Sign the appended execution data with this key; runners refuse data
+    /// not signed by it
+    #[arg(long, value_name = "PATH", requires = "appended_data")]
+    data_signing_key: Option<PathBuf>,
+
+    /// Have runners run the modules they are not built with from
+    /// WebAssembly components on their hosts, `<module>.wasm` in
+    /// RUSTLE_WASM_MODULE_PATH or `modules` next to the runner
+    #[arg(long)]
+    wasm_modules: bool,
+
+    /// Have runners run the modules they are not built with by embedding
+    /// Ansible's own Python modules, run with the target's Python
+    #[arg(long)]
+    python_fallback: bool,
+
+    /// Directory of Ansible Python modules searched before the Ansible
+    /// installation and ANSIBLE_LIBRARY (repeatable)
+    #[arg(
+        long = "python-module-path",
+        value_name = "DIR",
+        requires = "python_fallback"
+    )]
+    python_module_paths: Vec<PathBuf>,
+
+    /// The `ansible` package whose modules and module_utils are embedded,
+    /// instead of the one the controller's python3 imports
+    #[arg(long, value_name = "DIR", requires = "python_fallback")]
+    ansible_path: Option<PathBuf>,
+
     /// Enable verbose output
     #[arg(short, long)]
     verbose: bool,
@@ -70,9 +194,315 @@ struct RustleDeployCli {
     #[arg(long)]
     dry_run: bool,
 
-    /// Test compilation and execution on localhost only
+    /// Compile, then smoke test each runner the host can execute in a
+    /// sandbox before recording it as deployable
     #[arg(long)]
     localhost_test: bool,
+
+    /// Leave hosts as they are when a deployment fails instead of restoring
+    /// the previous binary and backed-up files
+    #[arg(long)]
+    no_rollback: bool,
+
+    /// Re-deploy and continue from the first task the previous, failed run
+    /// did not complete instead of running the whole plan again
+    #[arg(long)]
+    resume: bool,
+
+    /// Refuse plans that cannot be fully migrated to the current schema, or
+    /// are newer than it, instead of warning about them
+    #[arg(long)]
+    strict_plan: bool,
+
+    /// Also write SLSA provenance for compiled binaries next to the manifest
+    #[arg(long)]
+    provenance: bool,
+
+    /// Vault id and where its password comes from, as ID@SOURCE where
+    /// SOURCE is `prompt`, a password file or a script (repeatable)
+    #[arg(long = "vault-id")]
+    vault_ids: Vec<VaultIdentity>,
+
+    /// File holding the vault password, or a script printing it (repeatable)
+    #[arg(long = "vault-password-file")]
+    vault_password_files: Vec<PathBuf>,
+
+    /// Ask for the vault password
+    #[arg(long)]
+    ask_vault_pass: bool,
+
+    /// Variables file, plain or encrypted with Ansible Vault or SOPS, whose
+    /// variables are rendered into the arguments of tasks (repeatable, later
+    /// files take precedence)
+    #[arg(long = "vars-file")]
+    vars_files: Vec<PathBuf>,
+
+    /// Also look for the roles of playbooks here, after the roles/ next to
+    /// the playbook and before ANSIBLE_ROLES_PATH (repeatable)
+    #[arg(long = "roles-path", value_name = "DIR")]
+    roles_paths: Vec<PathBuf>,
+
+    /// File of age identities to decrypt SOPS files with, besides those
+    /// SOPS itself would use (repeatable)
+    #[arg(long = "sops-age-key-file")]
+    sops_age_key_files: Vec<PathBuf>,
+
+    /// Directory the templates of tasks extend, include or import templates
+    /// from, when they are not next to them; those templates are embedded
+    /// with them (repeatable)
+    #[arg(long = "template-path", value_name = "DIR")]
+    template_paths: Vec<PathBuf>,
+
+    /// Directory of custom modules, `<name>.rs` files or `<name>/`
+    /// directories with a `mod.rs`, compiled into the runners of plans
+    /// using them; searched before RUSTLE_MODULE_PATH (repeatable)
+    #[arg(long = "module-path", value_name = "DIR")]
+    module_paths: Vec<PathBuf>,
+
+    /// Largest local file of a copy or template task embedded into runners
+    #[arg(long, value_name = "MB", default_value_t = 16)]
+    max_payload_size: u64,
+
+    /// Glob of local files never embedded into runners, which tasks then
+    /// read on the targets (repeatable)
+    #[arg(long = "payload-exclude", value_name = "GLOB")]
+    payload_excludes: Vec<String>,
+
+    /// Resolve `lookup()` expressions for HashiCorp Vault, Secrets Manager
+    /// and SSM here instead of on the targets; the other lookup plugins are
+    /// always evaluated here
+    #[arg(long)]
+    resolve_lookups: bool,
+
+    /// YAML or JSON file of how lookups reach the secret stores; the
+    /// VAULT_* and AWS_* environment variables are used otherwise
+    #[arg(long, value_name = "PATH")]
+    lookup_config: Option<PathBuf>,
+
+    /// Write a report of the run for CI, as FORMAT=PATH where FORMAT is
+    /// junit or sarif (repeatable)
+    #[arg(long = "report", global = true)]
+    reports: Vec<ReportTarget>,
+
+    /// Write Prometheus metrics of the run to PATH, for node_exporter's
+    /// textfile collector
+    #[arg(long, global = true, value_name = "PATH")]
+    metrics_textfile: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show statistics recorded in the execution history
+    Stats {
+        #[command(subcommand)]
+        report: StatsReport,
+    },
+    /// Manage encrypted result bundles uploaded to object storage
+    Results {
+        #[command(subcommand)]
+        action: ResultsAction,
+    },
+    /// Verify or export the audit log of changes made by deployments
+    Audit {
+        #[command(subcommand)]
+        action: AuditAction,
+    },
+    /// Show the merged inventory: its groups, hosts and their variables,
+    /// with encrypted values masked
+    Inventory {
+        /// Inventory file, script, plugin configuration or directory
+        #[arg(short, long)]
+        inventory: PathBuf,
+
+        /// Print the inventory as JSON, as `ansible-inventory --list` does
+        #[arg(long, conflicts_with = "graph")]
+        list: bool,
+
+        /// Draw the tree of the groups and hosts under GROUP
+        #[arg(long, value_name = "GROUP", num_args = 0..=1, default_missing_value = "all")]
+        graph: Option<String>,
+
+        /// Also draw the variables of groups and hosts in the graph
+        #[arg(long, requires = "graph")]
+        vars: bool,
+
+        /// Only show the hosts this pattern selects
+        #[arg(short, long, value_name = "PATTERN")]
+        limit: Option<HostPattern>,
+
+        /// Connect to the SSH and WinRM hosts and report whether they can
+        /// be reached and logged into, and their latency
+        #[arg(long, conflicts_with_all = ["list", "graph"])]
+        preflight: bool,
+
+        /// Give up on a host after this many seconds when preflighting
+        #[arg(long, default_value = "10", requires = "preflight")]
+        preflight_timeout: u64,
+    },
+    /// Gather the facts of hosts, as a play's fact gathering would, and
+    /// print them as JSON without running a plan
+    Facts {
+        /// Inventory of the hosts (defaults to --inventory, or this host)
+        #[arg(short, long)]
+        inventory: Option<PathBuf>,
+
+        /// Only gather from the hosts this pattern selects
+        #[arg(short, long, value_name = "PATTERN")]
+        limit: Option<HostPattern>,
+
+        /// Subsets to gather besides the minimal facts: hardware, network,
+        /// virtual, env or all
+        #[arg(long, value_delimiter = ',', value_name = "SUBSETS")]
+        subset: Vec<FactSubset>,
+
+        /// Store the facts of each host as DIR/<host>.json instead of
+        /// printing them
+        #[arg(long, value_name = "DIR")]
+        output: Option<PathBuf>,
+    },
+    /// Inspect or clean the compilation cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Rewrite a plan written in an older schema into the current one,
+    /// reporting what was changed
+    MigratePlan {
+        /// Execution plan JSON file
+        plan: PathBuf,
+
+        /// Write the migrated plan here instead of over the original, or to
+        /// stdout if -
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Fail instead of warning when the plan cannot be fully migrated
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Print the JSON Schema of execution plans of the current schema
+    /// version, for tools writing plans to check them against
+    PlanSchema {
+        /// Write the schema here instead of to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Diagnose what building each target needs on this machine, and how
+    /// to fix what is missing
+    Doctor {
+        /// Target triples (defaults to --target, or the host's)
+        #[arg(value_name = "TARGET")]
+        targets: Vec<String>,
+
+        /// Print the diagnoses as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Serve the HTTP control API: submit plans, compile and deploy them,
+    /// stream their hosts' progress and query past runs
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080", value_name = "ADDRESS")]
+        listen: String,
+
+        /// Refuse requests that do not carry the bearer token in this file,
+        /// or else in the RUSTLE_API_TOKEN environment variable
+        #[arg(long, value_name = "PATH")]
+        token_file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Show the size and contents of the cache
+    Stats {
+        /// Print the statistics as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Remove cached binaries, all of them unless limited
+    Clean {
+        /// Only remove binaries not used in this many days
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<u64>,
+
+        /// Evict the least recently used binaries down to this many MB
+        #[arg(long, value_name = "MB", conflicts_with = "older_than")]
+        max_size: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuditAction {
+    /// Check the hash chain of the audit log
+    Verify {
+        /// Audit log (defaults to ~/.rustle/audit.log)
+        #[arg(long)]
+        log: Option<PathBuf>,
+    },
+    /// Verify the audit log and write its records as json or csv
+    Export {
+        /// Audit log (defaults to ~/.rustle/audit.log)
+        #[arg(long)]
+        log: Option<PathBuf>,
+
+        #[arg(long, default_value = "json")]
+        format: AuditFormat,
+
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ResultsAction {
+    /// Generate a controller keypair for sealing result bundles
+    Keygen,
+    /// Poll object storage and decrypt the result bundles of a deployment
+    Collect {
+        /// Object store configuration (JSON)
+        #[arg(long)]
+        store: PathBuf,
+
+        /// File containing the base64-encoded controller secret key
+        #[arg(long)]
+        secret_key_file: PathBuf,
+
+        #[arg(long)]
+        deployment_id: String,
+
+        /// Hosts expected to upload a result
+        #[arg(long = "host", required = true)]
+        hosts: Vec<String>,
+
+        /// Give up waiting for missing hosts after this many seconds
+        #[arg(long, default_value = "600")]
+        timeout: u64,
+
+        /// Print the collected results as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Append the changes the hosts made to this audit log
+        #[arg(long)]
+        audit_log: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum StatsReport {
+    /// Per-module invocation counts and durations across recorded runs
+    Modules {
+        /// History directory (defaults to ~/.rustle/history)
+        #[arg(long)]
+        history_dir: Option<PathBuf>,
+
+        /// Print the aggregate as JSON
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 #[tokio::main]
@@ -90,10 +520,60 @@ async fn main() -> Result<()> {
 
     info!("Starting rustle-deploy v{}", env!("CARGO_PKG_VERSION"));
 
-    if cli.check_capabilities {
+    if let Some(ref command) = cli.command {
+        match command {
+            Command::Stats { report } => run_stats(report)?,
+            Command::Results { action } => {
+                run_results(action, &cli.reports, cli.metrics_textfile.as_deref()).await?
+            }
+            Command::Audit { action } => run_audit(action)?,
+            Command::Inventory {
+                inventory,
+                limit,
+                preflight: true,
+                preflight_timeout,
+                ..
+            } => run_preflight(&cli, inventory, limit.as_ref(), *preflight_timeout).await?,
+            Command::Inventory {
+                inventory,
+                graph,
+                vars,
+                limit,
+                ..
+            } => run_inventory(&cli, inventory, graph.as_deref(), *vars, limit.as_ref()).await?,
+            Command::Facts {
+                inventory,
+                limit,
+                subset,
+                output,
+            } => {
+                let inventory = inventory.as_deref().or(cli.inventory.as_deref());
+                run_facts(&cli, inventory, limit.as_ref(), subset, output.as_deref()).await?
+            }
+            Command::Cache { action } => run_cache(&cli, action)?,
+            Command::MigratePlan {
+                plan,
+                output,
+                strict,
+            } => run_migrate_plan(plan, output.as_deref(), *strict)?,
+            Command::PlanSchema { output } => {
+                let schema = serde_json::to_string_pretty(&plan_json_schema())? + "\n";
+                match output {
+                    Some(output) => std::fs::write(output, schema)
+                        .with_context(|| format!("Failed to write {}", output.display()))?,
+                    None => print!("{schema}"),
+                }
+            }
+            Command::Doctor { targets, json } => run_doctor(&cli, targets, *json).await?,
+            #[cfg(feature = "server")]
+            Command::Serve { listen, token_file } => {
+                run_serve(&cli, listen, token_file.as_deref()).await?
+            }
+        }
+    } else if cli.check_capabilities {
         check_capabilities().await?;
-    } else if cli.setup {
-        run_setup().await?;
+    } else if cli.setup && cli.execution_plan.is_none() {
+        run_setup(&cli).await?;
     } else if let Some(ref execution_plan) = cli.execution_plan {
         run_deployment(execution_plan.clone(), &cli).await?;
     } else {
@@ -103,6 +583,565 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Serve the control API, deploying as the global options say
+#[cfg(feature = "server")]
+async fn run_serve(cli: &RustleDeployCli, listen: &str, token_file: Option<&Path>) -> Result<()> {
+    let mut server = rustle_deploy::api::ControlServer::new()
+        .with_output_dir(cli.output_dir.clone())
+        .with_compiler_config(CompilerConfig {
+            cache_dir: compilation_cache_dir(cli),
+            ..Default::default()
+        })
+        .with_vault(load_vault_secrets(cli)?);
+    if let Some(inventory) = &cli.inventory {
+        server = server.with_inventory(inventory.clone());
+    }
+    let token = match token_file {
+        Some(path) => Some(
+            tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("Failed to read the token from {}", path.display()))?
+                .trim()
+                .to_string(),
+        ),
+        None => std::env::var(rustle_deploy::api::server::TOKEN_ENV).ok(),
+    };
+    if let Some(token) = token.filter(|token| !token.is_empty()) {
+        server = server.with_token(token);
+    }
+    let listener = tokio::net::TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("Failed to listen on {listen}"))?;
+    server.serve(listener).await?;
+    Ok(())
+}
+
+/// Where binaries are cached: `--cache-dir`, or the compiler's default
+fn compilation_cache_dir(cli: &RustleDeployCli) -> PathBuf {
+    cli.cache_dir
+        .clone()
+        .unwrap_or_else(|| CompilerConfig::default().cache_dir)
+}
+
+fn run_cache(cli: &RustleDeployCli, action: &CacheAction) -> Result<()> {
+    let cache_dir = compilation_cache_dir(cli);
+    let mut cache = CompilationCache::new(cache_dir.clone(), true);
+    match action {
+        CacheAction::Stats { json } => {
+            let stats = cache.stats();
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+                return Ok(());
+            }
+
+            println!("📦 Compilation Cache: {}", cache_dir.display());
+            println!("===================================================");
+            println!("Binaries:  {}", stats.entries);
+            println!(
+                "Size:      {:.1} MB{}",
+                megabytes(stats.total_size_bytes),
+                stats
+                    .max_size_bytes
+                    .map(|max| format!(" of {:.1} MB", megabytes(max)))
+                    .unwrap_or_default()
+            );
+            println!("Reuses:    {}", stats.hits);
+            if let (Some(oldest), Some(newest)) = (stats.oldest_used, stats.newest_used) {
+                let age = |time: std::time::SystemTime| {
+                    let elapsed = time.elapsed().unwrap_or_default();
+                    format!("{:.1} days ago", elapsed.as_secs_f64() / 86_400.0)
+                };
+                println!("Last used: {} to {}", age(oldest), age(newest));
+            }
+            for (target, count) in &stats.targets {
+                println!("   {target:<40} {count:>4}");
+            }
+        }
+        CacheAction::Clean {
+            older_than,
+            max_size,
+        } => {
+            let cleanup = match (older_than, max_size) {
+                (_, Some(max_size)) => cache.shrink_to(max_size * 1024 * 1024)?,
+                (Some(days), None) => cache.clean(Some(Duration::from_secs(days * 86_400)))?,
+                (None, None) => cache.clean(None)?,
+            };
+            println!(
+                "🧹 Removed {} cached binaries, freeing {:.1} MB",
+                cleanup.removed,
+                megabytes(cleanup.freed_bytes)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Migrate `plan` to the current schema, writing it to `output` or over
+/// itself
+fn run_migrate_plan(plan: &Path, output: Option<&Path>, strict: bool) -> Result<()> {
+    let content = std::fs::read_to_string(plan)
+        .with_context(|| format!("Failed to read {}", plan.display()))?;
+    let mut value: serde_json::Value = serde_json::from_str(&content)
+        .with_context(|| format!("{} is not a JSON plan", plan.display()))?;
+    let mode = if strict {
+        ParseMode::Strict
+    } else {
+        ParseMode::Lenient
+    };
+    let report = PlanMigrations::new().migrate(&mut value, mode)?;
+    let migrated = serde_json::to_string_pretty(&value)? + "\n";
+
+    let output = output.unwrap_or(plan);
+    if output == Path::new("-") {
+        print!("{migrated}");
+    } else if !report.is_current() {
+        std::fs::write(output, migrated)
+            .with_context(|| format!("Failed to write {}", output.display()))?;
+    }
+
+    // The report goes to stderr so that a plan written to stdout stays valid
+    if report.is_current() {
+        eprintln!(
+            "✅ {} needs no migration from schema {}",
+            plan.display(),
+            report.to_version
+        );
+    } else {
+        eprintln!(
+            "🔁 Migrated {} from schema {} to {}",
+            plan.display(),
+            report.from_version,
+            report.to_version
+        );
+        for step in &report.applied {
+            eprintln!("  • {step}");
+        }
+    }
+    for warning in &report.warnings {
+        eprintln!("  ⚠️  {warning}");
+    }
+    Ok(())
+}
+
+async fn run_doctor(cli: &RustleDeployCli, targets: &[String], json: bool) -> Result<()> {
+    let doctor = CompilationDoctor::detect().await?;
+    let targets = if !targets.is_empty() {
+        targets.to_vec()
+    } else if let Some(target) = &cli.target {
+        vec![target.clone()]
+    } else {
+        vec![doctor.toolchain().host_target.clone()]
+    };
+    let diagnoses = doctor.diagnose_all(&targets);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diagnoses)?);
+    } else {
+        println!("🩺 Compilation Doctor");
+        println!("===================================================");
+        for diagnosis in &diagnoses {
+            let status = match (&diagnosis.backend, diagnosis.fallbacks.first()) {
+                (Some(backend), _) => format!("✅ builds with {backend}"),
+                (None, Some(fallback)) => format!("⚠️  only builds with {fallback}"),
+                (None, None) => "❌ cannot be built".to_string(),
+            };
+            println!("{}: {}", diagnosis.target, status);
+            for check in &diagnosis.checks {
+                let mark = if check.ok { "✅" } else { "❌" };
+                println!("  {mark} {:<15} {}", check.name, check.detail);
+                if let Some(fix) = &check.fix {
+                    println!("     fix: {fix}");
+                }
+            }
+            if diagnosis.backend.is_some() && !diagnosis.fallbacks.is_empty() {
+                println!("  fallbacks: {}", diagnosis.fallbacks.join(", "));
+            }
+            println!();
+        }
+    }
+
+    let unbuildable: Vec<_> = diagnoses
+        .iter()
+        .filter(|diagnosis| !diagnosis.is_buildable())
+        .map(|diagnosis| diagnosis.target.as_str())
+        .collect();
+    if !unbuildable.is_empty() {
+        anyhow::bail!("Cannot build {}", unbuildable.join(", "));
+    }
+    Ok(())
+}
+
+fn megabytes(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+fn run_stats(report: &StatsReport) -> Result<()> {
+    match report {
+        StatsReport::Modules { history_dir, json } => {
+            let history = ExecutionHistory::new(
+                history_dir
+                    .clone()
+                    .unwrap_or_else(ExecutionHistory::default_dir),
+            );
+            let aggregates = history.aggregate_modules()?;
+
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&aggregates)?);
+                return Ok(());
+            }
+
+            if aggregates.is_empty() {
+                println!(
+                    "No module metrics recorded in {}",
+                    history.history_dir().display()
+                );
+                return Ok(());
+            }
+
+            println!("📊 Module Execution Statistics");
+            println!("===================================================");
+            println!(
+                "{:<24} {:>6} {:>8} {:>8} {:>12} {:>10} {:>10}",
+                "MODULE", "RUNS", "CALLS", "FAILED", "TOTAL", "AVG", "MAX"
+            );
+            for aggregate in &aggregates {
+                let stats = &aggregate.stats;
+                println!(
+                    "{:<24} {:>6} {:>8} {:>8} {:>12} {:>10} {:>10}",
+                    aggregate.module,
+                    aggregate.runs,
+                    stats.invocations,
+                    stats.failures,
+                    format!("{:.2?}", stats.total_duration),
+                    format!("{:.2?}", stats.average_duration()),
+                    format!("{:.2?}", stats.max_duration.unwrap_or_default()),
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_results(
+    action: &ResultsAction,
+    reports: &[ReportTarget],
+    metrics_textfile: Option<&Path>,
+) -> Result<()> {
+    match action {
+        ResultsAction::Keygen => {
+            let (secret_key, public_key) = generate_result_keypair();
+            println!("secret_key: {secret_key}");
+            println!("public_key: {public_key}");
+        }
+        ResultsAction::Collect {
+            store,
+            secret_key_file,
+            deployment_id,
+            hosts,
+            timeout,
+            json,
+            audit_log,
+        } => {
+            let store: ObjectStoreConfig =
+                serde_json::from_str(&tokio::fs::read_to_string(store).await?)?;
+            let secret_key = tokio::fs::read_to_string(secret_key_file).await?;
+
+            let collector = ResultCollector::new(store, secret_key.trim().to_string());
+            let collected = collector
+                .collect(
+                    deployment_id,
+                    hosts,
+                    std::time::Duration::from_secs(*timeout),
+                )
+                .await?;
+
+            let report = RunReport::from(&collected);
+            for target in reports {
+                report
+                    .write(target)
+                    .with_context(|| format!("Failed to write report {}", target.path.display()))?;
+            }
+            if let Some(path) = metrics_textfile {
+                let metrics = DeploymentMetrics::new();
+                metrics.record_collected(&collected);
+                metrics
+                    .write_textfile(path)
+                    .with_context(|| format!("Failed to write metrics to {}", path.display()))?;
+            }
+            if let Some(path) = audit_log {
+                let log = AuditLog::new(path);
+                for result in collected.results.values() {
+                    log.append(deployment_id, &result.audit)
+                        .with_context(|| format!("Failed to append to {}", path.display()))?;
+                }
+            }
+            // Runners given a trace context shipped their spans back
+            if let Some(exporter) = OtlpExporter::from_env() {
+                let spans: Vec<_> = collected
+                    .results
+                    .values()
+                    .flat_map(|result| result.spans.iter().cloned())
+                    .collect();
+                if let Err(e) = exporter.export(&spans).await {
+                    warn!("{}", e);
+                }
+            }
+
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&collected)?);
+                return Ok(());
+            }
+
+            println!("📬 Collected Results for {deployment_id}");
+            println!("===================================================");
+            for (host, result) in &collected.results {
+                println!(
+                    "{:<32} {:<8} {:>4} tasks {:>4} failed {:>10}",
+                    host,
+                    if result.success { "ok" } else { "FAILED" },
+                    result.summary.total_tasks,
+                    result.summary.failed_tasks,
+                    format!("{:.2?}", result.duration),
+                );
+            }
+            for host in &collected.missing_hosts {
+                println!("{host:<32} missing");
+            }
+
+            if !collected.is_complete() || !collected.failed_hosts().is_empty() {
+                std::process::exit(1);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn run_audit(action: &AuditAction) -> Result<()> {
+    let open =
+        |log: &Option<PathBuf>| AuditLog::new(log.clone().unwrap_or_else(AuditLog::default_path));
+    match action {
+        AuditAction::Verify { log } => {
+            let log = open(log);
+            let (records, head) = log.verify()?;
+            println!(
+                "✅ {} records in {} are intact",
+                records,
+                log.path().display()
+            );
+            println!("Head hash: {head}");
+        }
+        AuditAction::Export {
+            log,
+            format,
+            output,
+        } => {
+            let exported = open(log).export(*format)?;
+            match output {
+                Some(path) => std::fs::write(path, exported)
+                    .with_context(|| format!("Failed to write {}", path.display()))?,
+                None => print!("{exported}"),
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_inventory(
+    cli: &RustleDeployCli,
+    path: &Path,
+    graph: Option<&str>,
+    vars: bool,
+    limit: Option<&HostPattern>,
+) -> Result<()> {
+    let loader = inventory_vars_loader(cli)?;
+    let mut inventory = InventoryProcessor::new()
+        .with_vars_loader(loader.clone())
+        .process_from_source(path)
+        .await
+        .with_context(|| format!("Failed to load inventory {}", path.display()))?;
+    // The strings of the group_vars/ and host_vars/ that were encrypted
+    let sensitive = DirectoryVars::load(&inventory_root(path), &inventory, &loader)?.sensitive;
+    if let Some(pattern) = limit {
+        inventory = pattern.filter(&inventory);
+    }
+
+    let export = InventoryExport::new(&inventory).with_sensitive(sensitive);
+    match graph {
+        Some(group) => {
+            let graph = export
+                .graph(group, vars)
+                .with_context(|| format!("No group {group} in the inventory"))?;
+            print!("{graph}");
+        }
+        None => println!("{}", serde_json::to_string_pretty(&export.to_json())?),
+    }
+    Ok(())
+}
+
+/// Gather facts with the local collectors for this host, and with runners
+/// compiled to only gather facts for the others
+async fn run_facts(
+    cli: &RustleDeployCli,
+    inventory: Option<&Path>,
+    limit: Option<&HostPattern>,
+    subsets: &[FactSubset],
+    output: Option<&Path>,
+) -> Result<()> {
+    let hosts: Vec<String> = match inventory {
+        Some(path) => {
+            let mut inventory = InventoryProcessor::new()
+                .with_vars_loader(inventory_vars_loader(cli)?)
+                .process_from_source(path)
+                .await
+                .with_context(|| format!("Failed to load inventory {}", path.display()))?;
+            if let Some(pattern) = limit {
+                inventory = pattern.filter(&inventory);
+            }
+            let mut hosts: Vec<String> = inventory.hosts.keys().cloned().collect();
+            hosts.sort();
+            hosts
+        }
+        None => vec!["localhost".to_string()],
+    };
+    if hosts.is_empty() {
+        anyhow::bail!("No hosts to gather facts from");
+    }
+    let (local, remote): (Vec<String>, Vec<String>) =
+        hosts.into_iter().partition(|host| is_local_host(host));
+
+    let mut gathered = GatheredFacts::default();
+    if !local.is_empty() {
+        let facts = local_facts(subsets).await?;
+        for host in local {
+            gathered.hosts.insert(host, facts.clone());
+        }
+    }
+    if !remote.is_empty() {
+        // The runners are only needed for this run
+        let runners_dir = tempfile::tempdir()?;
+        let mut deployment = Deployment::new(facts_plan(&remote)?)
+            .with_output_dir(runners_dir.path())
+            .with_compiler_config(CompilerConfig {
+                cache_dir: compilation_cache_dir(cli),
+                ..Default::default()
+            });
+        if let Some(path) = inventory {
+            deployment = deployment.with_inventory(path);
+        }
+        if let Some(target) = &cli.target {
+            deployment = deployment.with_targets(vec![target.clone()]);
+        }
+        let facts = deployment.gather_facts(subsets).await?;
+        gathered.hosts.extend(facts.hosts);
+        gathered.failed.extend(facts.failed);
+    }
+
+    match output {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+            for (host, facts) in &gathered.hosts {
+                let path = dir.join(format!("{host}.json"));
+                std::fs::write(&path, serde_json::to_string_pretty(facts)? + "\n")
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+            }
+            println!(
+                "Stored the facts of {} hosts in {}",
+                gathered.hosts.len(),
+                dir.display()
+            );
+        }
+        None => println!("{}", serde_json::to_string_pretty(&gathered.hosts)?),
+    }
+    for (host, error) in &gathered.failed {
+        eprintln!("{host}: {error}");
+    }
+    if !gathered.is_success() {
+        std::process::exit(EXIT_HOSTS_FAILED);
+    }
+    Ok(())
+}
+
+async fn run_preflight(
+    cli: &RustleDeployCli,
+    path: &Path,
+    limit: Option<&HostPattern>,
+    timeout: u64,
+) -> Result<()> {
+    let processor = InventoryProcessor::new()
+        .with_vars_loader(inventory_vars_loader(cli)?)
+        .with_preflight(ConnectionPreflight::new().with_timeout(Duration::from_secs(timeout)));
+    let mut inventory = processor
+        .process_from_source(path)
+        .await
+        .with_context(|| format!("Failed to load inventory {}", path.display()))?;
+    if let Some(pattern) = limit {
+        inventory = pattern.filter(&inventory);
+    }
+
+    let report = processor.preflight(&inventory).await.unwrap_or_default();
+    println!("🔌 Connection Preflight");
+    println!("===================================================");
+    for (host, reachability) in &report.hosts {
+        let status = match (reachability.reachable, reachability.authenticated) {
+            (true, true) => "ok",
+            (true, false) => "AUTH FAILED",
+            _ => "UNREACHABLE",
+        };
+        let latency = reachability
+            .latency
+            .map(|latency| format!("{latency:.2?}"))
+            .unwrap_or_default();
+        let platform = reachability
+            .platform
+            .as_ref()
+            .map(|platform| platform.to_string())
+            .unwrap_or_default();
+        println!("{host:<32} {status:<12} {latency:>10}  {platform}");
+        if let Some(ref error) = reachability.error {
+            println!("  {error}");
+        }
+    }
+    let unchecked = inventory.hosts.len() - report.hosts.len();
+    if unchecked > 0 {
+        println!("{unchecked} hosts without SSH or WinRM connections were not checked");
+    }
+
+    if !report.unusable_hosts().is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// The preflight of the inventory at `path`, probing the hosts' platforms;
+/// empty when the inventory cannot be loaded
+async fn probe_platforms(cli: &RustleDeployCli, path: &Path) -> PreflightReport {
+    let processor = match inventory_vars_loader(cli) {
+        Ok(loader) => InventoryProcessor::new()
+            .with_vars_loader(loader)
+            .with_preflight(ConnectionPreflight::new()),
+        Err(e) => {
+            warn!("Not probing host platforms: {}", e);
+            return PreflightReport::default();
+        }
+    };
+    match processor.process_from_source(path).await {
+        Ok(inventory) => processor.preflight(&inventory).await.unwrap_or_default(),
+        Err(e) => {
+            warn!("Not probing host platforms: {}", e);
+            PreflightReport::default()
+        }
+    }
+}
+
+/// Loads the `group_vars/` and `host_vars/` of inventories with the vault
+/// and SOPS keys the options give
+fn inventory_vars_loader(cli: &RustleDeployCli) -> Result<VarsFileLoader> {
+    vars_file_loader(cli, &load_vault_secrets(cli)?)
+}
+
 async fn check_capabilities() -> Result<()> {
     println!("🔧 Cross-Compilation Capabilities");
     println!("===================================================");
@@ -198,7 +1237,7 @@ async fn check_capabilities() -> Result<()> {
     Ok(())
 }
 
-async fn run_setup() -> Result<()> {
+async fn run_setup(cli: &RustleDeployCli) -> Result<()> {
     println!("🚀 rustle-deploy Setup");
     println!("==========================================");
 
@@ -207,6 +1246,16 @@ async fn run_setup() -> Result<()> {
     let zig_available = check_zig().await;
     let zigbuild_available = check_zigbuild().await;
 
+    if let (Some(target), true) = (&cli.target, rust_available) {
+        println!("📦 Installing rust target {target}...");
+        let rustup =
+            RustupTargets::new(TargetInstallPolicy::Install).with_toolchain(cli.toolchain.clone());
+        match rustup.ensure(target).await {
+            Ok(()) => println!("✅ {target} installed"),
+            Err(e) => println!("❌ Failed to install {target}: {e}"),
+        }
+    }
+
     if rust_available && zig_available && zigbuild_available {
         println!("✅ Your setup is already fully optimized!");
         return Ok(());
@@ -246,15 +1295,40 @@ async fn run_deployment(execution_plan_path: PathBuf, cli: &RustleDeployCli) ->
     println!("🚀 rustle-deploy: Deployment");
     println!("==============================================");
 
+    let vault = load_vault_secrets(cli)?;
+    let mut vars = load_vars_files(cli, &vault)?;
+    resolve_variable_lookups(
+        &mut vars,
+        &lookup_registry(cli)?,
+        &LookupContext::current_dir(),
+    )
+    .await?;
+    // Extra variables may refer to each other, whatever file defines them
+    let vars = PrecedenceResolver::new()
+        .with_layer(VariablePrecedence::ExtraVars, vars)
+        .resolve_all()?;
+
     // Parse execution plan from rustle-plan JSON and cache the content for later use
+    let estimator = compile_estimator(cli);
     let (execution_plan, cached_rustle_plan) = if execution_plan_path.to_string_lossy() == "-" {
         println!("📖 Execution Plan: <stdin>");
-        let rustle_plan = parse_rustle_plan_from_stdin().await?;
-        let execution_plan = create_execution_plan_summary(&rustle_plan)?;
+        let mut rustle_plan = parse_rustle_plan_from_stdin(&vault, parse_mode(cli)).await?;
+        resolve_includes(cli, &mut rustle_plan, Path::new("."), &vault, &vars)?;
+        render_plan_variables(&mut rustle_plan, &vars);
+        resolve_lookups(cli, &mut rustle_plan, &vars).await?;
+        let execution_plan = create_execution_plan_summary(&rustle_plan, &estimator)?;
+        (execution_plan, Some(rustle_plan))
+    } else if is_playbook(&execution_plan_path) {
+        println!("📖 Playbook: {execution_plan_path:?}");
+        let mut rustle_plan = load_playbook(cli, &execution_plan_path, &vault, &vars).await?;
+        render_plan_variables(&mut rustle_plan, &vars);
+        resolve_lookups(cli, &mut rustle_plan, &vars).await?;
+        let execution_plan = create_execution_plan_summary(&rustle_plan, &estimator)?;
         (execution_plan, Some(rustle_plan))
     } else {
         println!("📖 Execution Plan: {execution_plan_path:?}");
-        let execution_plan = parse_execution_plan_from_file(&execution_plan_path).await?;
+        let execution_plan =
+            parse_execution_plan_from_file(&execution_plan_path, &estimator).await?;
         (execution_plan, None)
     };
 
@@ -267,6 +1341,14 @@ async fn run_deployment(execution_plan_path: PathBuf, cli: &RustleDeployCli) ->
     println!("⚙️  Optimization: {}", cli.optimization);
     println!("📁 Output Directory: {:?}", cli.output_dir);
 
+    if cli.no_rollback {
+        println!("⏪ Rollback: disabled");
+    }
+
+    if cli.resume {
+        println!("🔁 Resume: continuing from the first incomplete task");
+    }
+
     if cli.dry_run {
         println!("🔍 DRY RUN MODE - No actual deployment will occur");
     }
@@ -326,8 +1408,8 @@ async fn run_deployment(execution_plan_path: PathBuf, cli: &RustleDeployCli) ->
         execution_plan.estimated_speedup
     );
 
-    if let Some(compilation_time) = execution_plan.estimated_compilation_time {
-        println!("  • Estimated compilation time: {compilation_time:?}");
+    if let Some(estimate) = &execution_plan.compile_estimate {
+        print_compile_estimate(estimate);
     }
 
     if cli.dry_run {
@@ -363,8 +1445,18 @@ async fn run_deployment(execution_plan_path: PathBuf, cli: &RustleDeployCli) ->
             println!("🔨 Compilation-only mode");
         }
 
-        match run_compilation(&execution_plan, cli, cached_rustle_plan).await {
-            Ok(()) => {
+        let cancel = cancel_on_ctrl_c();
+        match run_compilation(
+            &execution_plan,
+            cli,
+            cached_rustle_plan,
+            &vault,
+            &vars,
+            &cancel,
+        )
+        .await
+        {
+            Ok(_) => {
                 println!("✅ Compilation completed successfully");
                 if cli.localhost_test {
                     println!("✅ Localhost test completed successfully");
@@ -378,37 +1470,243 @@ async fn run_deployment(execution_plan_path: PathBuf, cli: &RustleDeployCli) ->
     } else if cli.deploy_only {
         println!();
         println!("🚀 Deploy-only mode");
-        println!("   Deploying existing binaries from: {:?}", cli.output_dir);
-        println!("   ⚠️  Actual deployment not yet implemented");
+        println!("   Deploying runners built earlier in {:?}", cli.output_dir);
+
+        let cancel = cancel_on_ctrl_c();
+        let rustle_plan = load_rustle_plan(cli, cached_rustle_plan, &vault, &vars).await?;
+        deploy_and_recap(cli, rustle_plan, &vault, None, &cancel).await?;
     } else {
         println!();
-        println!("⚠️  Full compile and deploy not yet implemented");
-        println!("   Currently showing execution plan analysis only");
-        println!("   Use --dry-run to see deployment planning");
-        println!("   Use --compile-only to compile binaries only");
-        println!("   Use --deploy-only to deploy existing binaries");
+        println!("🚀 Compiling and deploying");
+
+        let cancel = cancel_on_ctrl_c();
+        let (rustle_plan, compiled) = match run_compilation(
+            &execution_plan,
+            cli,
+            cached_rustle_plan,
+            &vault,
+            &vars,
+            &cancel,
+        )
+        .await
+        {
+            Ok(compiled) => compiled,
+            Err(e) => {
+                error!("❌ Compilation failed: {}", e);
+                return Err(e);
+            }
+        };
+        println!("✅ Compiled {} runners", compiled.runners.len());
+        deploy_and_recap(cli, rustle_plan, &vault, Some(compiled), &cancel).await?;
     }
 
     Ok(())
 }
 
+/// Exit status when some hosts failed to deploy or run, as ansible-playbook's
+const EXIT_HOSTS_FAILED: i32 = 2;
+
+/// Exit status when the run was interrupted
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Deploy the runners of `compiled`, or those built earlier for the plan
+/// when there are none, and run them, printing the events they stream as
+/// they arrive and then the recap. Exits with [`EXIT_HOSTS_FAILED`] or
+/// [`EXIT_INTERRUPTED`] unless every host succeeded.
+async fn deploy_and_recap(
+    cli: &RustleDeployCli,
+    plan: RustlePlanOutput,
+    vault: &VaultSecrets,
+    compiled: Option<CompileOutcome>,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let (sink, mut events) = tokio::sync::mpsc::unbounded_channel::<HostEvent>();
+    let printer = tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            println!("   {}", event.progress_line());
+        }
+    });
+
+    let metrics = Arc::new(DeploymentMetrics::new());
+    // Runners built earlier are matched by the plan they embed, which
+    // embedding the tasks' local files changes
+    let mut deployment = Deployment::new(plan)
+        .with_output_dir(&cli.output_dir)
+        .with_vault(vault.clone())
+        .with_template_paths(cli.template_paths.clone())
+        .with_payload_policy(payload_policy(cli))
+        .with_custom_modules(custom_modules(cli)?)
+        .with_event_sink(sink)
+        .with_metrics(Arc::clone(&metrics))
+        .with_resume(cli.resume)
+        .with_cancellation(cancel.clone());
+    if let Some(python) = python_modules(cli) {
+        deployment = deployment.with_python_modules(python);
+    }
+    if let Some(inventory) = &cli.inventory {
+        deployment = deployment.with_inventory(inventory);
+    }
+    if let (Some(target), true) = (&cli.target, deployment.plan().binary_deployments.is_empty()) {
+        deployment = deployment.with_targets(vec![target.clone()]);
+    }
+    if cli.no_rollback {
+        deployment = deployment.with_rollback_policy(RollbackPolicy::disabled());
+    }
+
+    let outcome = async {
+        let compiled = match compiled {
+            Some(compiled) => compiled,
+            None => {
+                let compiled = deployment.prebuilt(cli.force).await?;
+                println!(
+                    "   ✅ {} runners of the plan match {}",
+                    compiled.runners.len(),
+                    MANIFEST_FILE
+                );
+                compiled
+            }
+        };
+        println!();
+        println!("📡 Deploying to {} hosts", deployment.plan().hosts.len());
+        deployment.deploy(&compiled).await
+    }
+    .await;
+    // The printer is done once the last sender is gone
+    drop(deployment);
+    let _ = printer.await;
+
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(ApiError::Cancelled) => {
+            println!("⏹️  Cancelled before anything was deployed");
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        Err(e @ ApiError::Deploy(DeployError::StaleArtifact { .. })) => {
+            error!("❌ {}", e);
+            return Err(anyhow::anyhow!(
+                "{e}; rebuild it, or deploy it anyway with --force"
+            ));
+        }
+        Err(e) => {
+            error!("❌ Deployment failed: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    print_recap(&outcome);
+    write_run_artifacts(cli, &outcome, &metrics)?;
+    if cancel.is_cancelled() {
+        println!("⏹️  Cancelled: rerun with --resume to continue where the runners stopped");
+        std::process::exit(EXIT_INTERRUPTED);
+    }
+    if !outcome.is_success() {
+        std::process::exit(EXIT_HOSTS_FAILED);
+    }
+    println!("✅ Deployment completed successfully");
+    Ok(())
+}
+
+/// Print how each host ended, its run if it got that far
+fn print_recap(outcome: &DeployOutcome) {
+    println!();
+    println!("📋 Recap");
+    println!("===================================================");
+    for deployed in &outcome.deployment.deployment_results {
+        let result = outcome
+            .execution
+            .iter()
+            .flat_map(|report| &report.deployment_results)
+            .find(|result| result.host == deployed.host)
+            .unwrap_or(deployed);
+        let (status, reason) = match &result.status {
+            DeploymentStatus::Deployed | DeploymentStatus::Verified => ("ok", None),
+            DeploymentStatus::Failed { error } => ("failed", Some(error)),
+            DeploymentStatus::Skipped { reason } => ("skipped", Some(reason)),
+            DeploymentStatus::Aborted { reason } => ("aborted", Some(reason)),
+            DeploymentStatus::RolledBack => ("rolled back", None),
+            _ => ("pending", None),
+        };
+        let tasks = result
+            .verification
+            .as_ref()
+            .and_then(|verification| verification.result.as_ref())
+            .map(|run| {
+                let summary = &run.summary;
+                format!(
+                    "tasks={} changed={} failed={} skipped={}",
+                    summary.total_tasks,
+                    summary.changed_tasks,
+                    summary.failed_tasks,
+                    summary.skipped_tasks
+                )
+            })
+            .unwrap_or_default();
+        println!("{:<32} {:<12} {}", result.host, status, tasks);
+        if let Some(reason) = reason {
+            println!("   {reason}");
+        }
+    }
+    for host in &outcome.unassigned_hosts {
+        println!("{:<32} {:<12}", host, "no runner");
+    }
+    if let Some(rollback) = outcome
+        .execution
+        .as_ref()
+        .and_then(|report| report.rollback.as_ref())
+        .or(outcome.deployment.rollback.as_ref())
+    {
+        println!("⏪ Rolled back: {}", rollback.reason);
+    }
+}
+
+/// Write the reports and metrics the options ask for
+fn write_run_artifacts(
+    cli: &RustleDeployCli,
+    outcome: &DeployOutcome,
+    metrics: &DeploymentMetrics,
+) -> Result<()> {
+    let report = RunReport::from(outcome.execution.as_ref().unwrap_or(&outcome.deployment));
+    for target in &cli.reports {
+        report
+            .write(target)
+            .with_context(|| format!("Failed to write report {}", target.path.display()))?;
+    }
+    if let Some(path) = &cli.metrics_textfile {
+        metrics
+            .write_textfile(path)
+            .with_context(|| format!("Failed to write metrics to {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// A token cancelled by the first Ctrl-C, which lets in-flight work wind
+/// down cleanly; a second one exits straight away
+fn cancel_on_ctrl_c() -> CancellationToken {
+    let cancel = CancellationToken::new();
+    let token = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        warn!("Interrupted, finishing in-flight work (press Ctrl-C again to exit now)");
+        token.cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+    cancel
+}
+
 async fn run_compilation(
     _execution_plan: &ExecutionPlanSummary,
     cli: &RustleDeployCli,
     cached_rustle_plan: Option<RustlePlanOutput>,
-) -> Result<()> {
+    vault: &VaultSecrets,
+    vars: &HashMap<String, serde_json::Value>,
+    cancel: &CancellationToken,
+) -> Result<(RustlePlanOutput, CompileOutcome)> {
     info!("Starting binary compilation pipeline");
-
-    // Use cached rustle plan if available (from stdin), otherwise parse from file
-    let rustle_plan = if let Some(cached_plan) = cached_rustle_plan {
-        cached_plan
-    } else if let Some(ref execution_plan_path) = cli.execution_plan {
-        parse_rustle_plan_from_file(execution_plan_path).await?
-    } else {
-        return Err(anyhow::anyhow!(
-            "Execution plan is required for compilation"
-        ));
-    };
+    let rustle_plan = load_rustle_plan(cli, cached_rustle_plan, vault, vars).await?;
 
     // Parse optimization level
     let optimization_level = match cli.optimization.as_str() {
@@ -425,145 +1723,774 @@ async fn run_compilation(
         }
     };
 
+    let default_runner_profile = match cli.runner_profile.as_str() {
+        "standard" => RunnerProfile::Standard,
+        "minimal" => RunnerProfile::Minimal,
+        _ => {
+            warn!(
+                "Unknown runner profile '{}', using 'standard'",
+                cli.runner_profile
+            );
+            RunnerProfile::Standard
+        }
+    };
+    let cache_store = match &cli.cache_store {
+        Some(path) => Some(CacheStoreConfig::load(path)?.into_store()),
+        None => None,
+    };
+    let mut size_optimizer = SizeOptimizer::default();
+    if let Some(strip) = cli.strip {
+        size_optimizer = size_optimizer.with_strip(strip);
+    }
+    if let Some(level) = cli.upx {
+        size_optimizer = size_optimizer.with_upx(UpxConfig {
+            level,
+            lzma: cli.upx_lzma,
+        });
+    }
+    let reproducible = if cli.reproducible {
+        let toolchain = match &cli.toolchain {
+            Some(toolchain) => toolchain.clone(),
+            None => CompilerVersions::detect()
+                .rustc
+                .as_deref()
+                .and_then(rustc_release)
+                .context("Reproducible builds need --toolchain without an installed rustc")?,
+        };
+        let epoch =
+            ReproducibleBuild::source_date_epoch_or(rustle_plan.metadata.created_at.timestamp());
+        info!(
+            "Building reproducibly with Rust {} and SOURCE_DATE_EPOCH={}",
+            toolchain, epoch
+        );
+        Some(ReproducibleBuild::new(toolchain, epoch))
+    } else {
+        None
+    };
+    let data_signer = cli
+        .data_signing_key
+        .as_deref()
+        .map(BinarySigner::load)
+        .transpose()
+        .context("Failed to load the data signing key")?;
+    let compiler_config = CompilerConfig {
+        default_runner_profile,
+        cache_dir: compilation_cache_dir(cli),
+        cache_store,
+        size_optimizer,
+        incremental_dir: cli
+            .incremental
+            .then(|| compilation_cache_dir(cli).join("projects")),
+        toolchain: cli.toolchain.clone(),
+        target_install: if cli.setup {
+            TargetInstallPolicy::Install
+        } else {
+            TargetInstallPolicy::Suggest
+        },
+        reproducible: reproducible.clone(),
+        data_signer: data_signer.clone(),
+        ..Default::default()
+    };
+
     // Set up target detection
     let target_detector = TargetDetector::new();
 
-    // Determine target specification - prefer execution plan's compilation requirements
-    let target_spec = if let Some(deployment) = rustle_plan.binary_deployments.first() {
+    // Determine the target specifications - prefer the execution plan's
+    // compilation requirements, one binary per distinct target
+    let mut targets: Vec<(TargetSpecification, BinaryDeploymentPlan)> = Vec::new();
+    if !rustle_plan.binary_deployments.is_empty() {
         // Use target information from the execution plan
         info!("Using target information from execution plan");
-        target_detector.create_target_spec_from_requirements(
-            &deployment.compilation_requirements,
-            optimization_level.clone(),
-        )?
+        for deployment in &rustle_plan.binary_deployments {
+            let target_spec = target_detector.create_target_spec_from_requirements(
+                &deployment.compilation_requirements,
+                optimization_level.clone(),
+            )?;
+            if !targets
+                .iter()
+                .any(|(spec, _)| spec.target_triple == target_spec.target_triple)
+            {
+                targets.push((target_spec, deployment.clone()));
+            }
+        }
     } else if cli.localhost_test {
         // Fallback to localhost for testing
         info!("No binary deployments in plan, using localhost target for testing");
-        target_detector.create_localhost_target_spec()?
+        targets.push((
+            target_detector.create_localhost_target_spec()?,
+            BinaryDeploymentPlan::default(),
+        ));
     } else if let Some(target) = &cli.target {
         // Allow manual override via CLI
         info!("Using manually specified target: {}", target);
-        target_detector.create_target_spec(target, optimization_level.clone())?
+        targets.push((
+            target_detector.create_target_spec(target, optimization_level.clone())?,
+            BinaryDeploymentPlan::default(),
+        ));
     } else {
         // Final fallback
         warn!("No target information available, defaulting to localhost");
-        target_detector.create_localhost_target_spec()?
-    };
-
-    info!("Compiling for target: {}", target_spec.target_triple);
+        targets.push((
+            target_detector.create_localhost_target_spec()?,
+            BinaryDeploymentPlan::default(),
+        ));
+    }
 
-    // Create binary template generator
-    let template_config = TemplateConfig::default();
-    let template_generator = BinaryTemplateGenerator::new(template_config)?;
+    // Static musl runners for hosts without a compatible glibc, as the
+    // preflight of the inventory finds them
+    let preflight = match (&cli.inventory, cli.static_linking) {
+        (Some(path), None) => probe_platforms(cli, path).await,
+        _ => PreflightReport::default(),
+    };
+    let mut linked: Vec<(TargetSpecification, BinaryDeploymentPlan)> = Vec::new();
+    for (mut target_spec, deployment) in targets {
+        let hosts = if deployment.target_hosts.is_empty() {
+            preflight.platforms(preflight.hosts.keys())
+        } else {
+            preflight.platforms(&deployment.target_hosts)
+        };
+        let decision = choose_linkage(&target_spec.target_triple, &hosts, cli.static_linking);
+        if let Some(reason) = &decision.reason {
+            info!(
+                "Building {} rather than {}: {}",
+                decision.target_triple, target_spec.target_triple, reason
+            );
+            target_spec = target_detector
+                .create_target_spec(&decision.target_triple, optimization_level.clone())?;
+            target_spec.compilation_options.static_linking = true;
+        }
+        if !linked
+            .iter()
+            .any(|(spec, _)| spec.target_triple == target_spec.target_triple)
+        {
+            linked.push((target_spec, deployment));
+        }
+    }
 
-    // Create target info
-    let target_info = create_target_info_from_spec(&target_spec)?;
+    let custom_modules = custom_modules(cli)?;
+    let python_modules = python_modules(cli);
+    let mut jobs = Vec::new();
+    let mut target_hosts = HashMap::new();
+    for (target_spec, mut binary_deployment) in linked {
+        info!("Compiling for target: {}", target_spec.target_triple);
+
+        let runner_profile = compiler_config.runner_profile_for(&target_spec.target_triple);
+        if runner_profile != RunnerProfile::Standard {
+            let report = check_profile_compatibility(
+                &rustle_plan,
+                &target_spec.target_triple,
+                runner_profile,
+            );
+            info!(
+                "Runner profile {:?}: {} supported modules",
+                runner_profile,
+                report.supported_modules.len()
+            );
+            for feature in &report.unsupported {
+                warn!(
+                    "Module '{}' requires the {:?} subsystem, which the {:?} profile does not include",
+                    feature.module, feature.subsystem, runner_profile
+                );
+            }
+            for note in &report.notes {
+                info!("   {}", note);
+            }
+            // A sandboxed runner cannot fall back to anything for them
+            if runner_profile == RunnerProfile::Wasi && !report.is_compatible() {
+                let modules: Vec<_> = report
+                    .unsupported
+                    .iter()
+                    .map(|feature| feature.module.as_str())
+                    .collect();
+                anyhow::bail!(
+                    "Target {} cannot run modules {}: WASI runners only run {}",
+                    target_spec.target_triple,
+                    modules.join(", "),
+                    WASI_MODULES.join(", ")
+                );
+            }
+        }
 
-    // Generate binary template from execution plan
-    info!("Generating binary template");
+        // WASI runners are WebAssembly themselves, and run no components
+        let wasm_modules = cli.wasm_modules && runner_profile != RunnerProfile::Wasi;
+        if cli.wasm_modules && !wasm_modules {
+            warn!(
+                "WASI runners for {} cannot load WebAssembly modules",
+                target_spec.target_triple
+            );
+        }
 
-    // Create or modify binary deployment plan to include verbose setting
-    let mut binary_deployment = rustle_plan
-        .binary_deployments
-        .first()
-        .cloned()
-        .unwrap_or_default();
-    binary_deployment.verbose = Some(cli.verbose);
+        // Nor Python modules, having no processes to run them with
+        let python_modules = python_modules
+            .as_ref()
+            .filter(|_| runner_profile != RunnerProfile::Wasi);
+        if python_modules.is_none() && cli.python_fallback {
+            warn!(
+                "WASI runners for {} cannot run Python modules",
+                target_spec.target_triple
+            );
+        }
 
-    // Ensure migration is applied to this specific deployment
-    binary_deployment.migrate_from_legacy();
+        // Create binary template generator
+        let template_config = TemplateConfig {
+            runner_profile,
+            data_layout: if cli.appended_data {
+                DataLayout::Appended
+            } else {
+                DataLayout::Compiled
+            },
+            data_key: data_signer.as_ref().map(BinarySigner::public_key),
+            wasm_modules,
+            ..Default::default()
+        };
+        let template_generator = BinaryTemplateGenerator::new(template_config)?
+            .with_vault(vault.clone())
+            .with_template_search_path(cli.template_paths.clone())
+            .with_payload_policy(payload_policy(cli))
+            .with_custom_modules(custom_modules.clone());
+        let template_generator = match python_modules {
+            Some(python) => template_generator.with_python_modules(python.clone()),
+            None => template_generator,
+        };
+
+        // Create target info
+        let target_info = TargetInfo::for_target(&target_spec.target_triple)?;
+
+        // Generate binary template from execution plan
+        info!("Generating binary template");
+
+        // Include the verbose setting in the deployment
+        binary_deployment.verbose = Some(cli.verbose);
+
+        // Ensure migration is applied to this specific deployment
+        binary_deployment.migrate_from_legacy();
+
+        let template = template_generator
+            .generate_binary_template(&rustle_plan, &binary_deployment, &target_info)
+            .await?;
 
-    let template = template_generator
-        .generate_binary_template(&rustle_plan, &binary_deployment, &target_info)
-        .await?;
+        info!(
+            "Template generated with {} source files",
+            template.source_files.len()
+        );
+        for task in template_generator.python_fallback_tasks(&rustle_plan) {
+            info!(
+                "   Task {} ({}) runs {} with Python, from {}",
+                task.task_id,
+                task.name,
+                task.module,
+                task.source.display()
+            );
+        }
+        info!("Template hash: {}", template.calculate_hash());
+        let sections = template.section_report();
+        info!(
+            "Embedded data of {}: {} bytes packed into {} bytes\n{}",
+            target_info.target_triple,
+            sections.original_size(),
+            sections.embedded_size(),
+            sections
+        );
 
-    info!(
-        "Template generated with {} source files",
-        template.source_files.len()
-    );
-    info!("Template hash: {}", template.calculate_hash());
+        // A runner built without a binary deployment serves every host
+        let hosts = if binary_deployment.target_hosts.is_empty() {
+            rustle_plan.hosts.clone()
+        } else {
+            binary_deployment.target_hosts.clone()
+        };
+        target_hosts.insert(target_spec.target_triple.clone(), hosts);
+        jobs.push(CompileJob {
+            template,
+            target: target_spec,
+        });
+    }
 
-    // Binary compilation is now enabled with unified types
-    if cli.compile_only {
-        info!("Starting binary compilation");
+    info!("Starting binary compilation of {} targets", jobs.len());
+
+    let max_jobs = cli
+        .compile_jobs
+        .unwrap_or(compiler_config.max_parallel_compilations);
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::unbounded_channel();
+    let progress = tokio::spawn(async move {
+        while let Some(progress) = progress_rx.recv().await {
+            match progress {
+                CompileProgress::Cached { target } => info!("   {}: cached", target),
+                CompileProgress::Started { target } => info!("   {}: compiling", target),
+                CompileProgress::Finished {
+                    target,
+                    elapsed,
+                    size,
+                } => info!("   {}: compiled in {:?} ({} bytes)", target, elapsed, size),
+                CompileProgress::Failed { target, error } => {
+                    error!("   {}: failed: {}", target, error)
+                }
+            }
+        }
+    });
+    let scheduler = CompileScheduler::new(max_jobs).with_progress(progress_tx);
 
-        let compiler_config = CompilerConfig::default();
-        let mut compiler = BinaryCompiler::new(compiler_config);
-        let compiled_binary = compiler.compile_binary(&template, &target_spec).await?;
+    let template_id = jobs[0].template.template_id.clone();
+    let plan_hashes: HashMap<String, String> = jobs
+        .iter()
+        .map(|job| {
+            (
+                job.target.target_triple.clone(),
+                plan_hash(&job.template.embedded_data.execution_plan),
+            )
+        })
+        .collect();
+    let single_target = jobs.len() == 1;
+
+    let mut compiler = BinaryCompiler::new(compiler_config).with_cancellation(cancel.clone());
+    let report = scheduler.run(&mut compiler, jobs).await;
+    drop(scheduler);
+    let _ = progress.await;
+    if cancel.is_cancelled() {
+        return Err(anyhow::anyhow!("Compilation cancelled"));
+    }
 
+    tokio::fs::create_dir_all(&cli.output_dir).await?;
+    let mut manifest = DeploymentManifest::new(&template_id, CompilerVersions::detect().clone());
+    manifest.reproducible = reproducible.clone();
+    let mut smoke_test_failures = Vec::new();
+    let mut runners = Vec::new();
+    for (target, compiled_binary) in &report.binaries {
         info!("✅ Binary compiled successfully:");
         info!("   Target: {}", compiled_binary.target_triple);
         info!("   Size: {} bytes", compiled_binary.size);
+        info!(
+            "   Linkage: {}",
+            Linkage::of(
+                &compiled_binary.target_triple,
+                Some(&compiled_binary.binary_data)
+            )
+        );
+        if let Some(size) = &compiled_binary.size_report {
+            let profile = &size.profile;
+            info!(
+                "   Profile: opt-level={} lto={:?} panic={} strip={:?}",
+                profile.opt_level,
+                profile.lto,
+                if profile.panic_abort {
+                    "abort"
+                } else {
+                    "unwind"
+                },
+                profile.strip
+            );
+            if let (Some(packed), Some(unpack)) = (size.packed_size, size.unpack_time) {
+                info!(
+                    "   UPX: {} -> {} bytes ({:.0}%), unpacks in {:?} on each start",
+                    size.built_size,
+                    packed,
+                    packed as f64 * 100.0 / size.built_size.max(1) as f64,
+                    unpack
+                );
+            }
+        }
         info!(
             "   Compilation time: {:?}",
             compiled_binary.compilation_time
         );
         info!("   Binary ID: {}", compiled_binary.binary_id);
 
-        // Binary output management - copy to output directory
-        tokio::fs::create_dir_all(&cli.output_dir).await?;
-        let output_path = cli.output_dir.join("rustle-runner");
-
-        // Write binary data to output directory
-        tokio::fs::write(&output_path, &compiled_binary.binary_data).await?;
-
-        // Make the binary executable
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = std::fs::metadata(&output_path)?.permissions();
-            perms.set_mode(0o755); // rwxr-xr-x
-            std::fs::set_permissions(&output_path, perms)?;
-        }
+        // Binary output management - copy to output directory, in a
+        // directory per target when there are several
+        let output_path = runner_output_path(&cli.output_dir, target, single_target);
+        write_runner(&output_path, &compiled_binary.binary_data).await?;
 
         info!(
             "✅ Binary copied to output directory: {}",
             output_path.display()
         );
+
+        let mut artifact = ArtifactEntry::from_file(
+            &cli.output_dir,
+            &output_path,
+            &compiled_binary.target_triple,
+            &plan_hashes[target],
+        )?;
+        if cli.localhost_test {
+            let smoke_test = SmokeTest::new()
+                .run(
+                    &output_path,
+                    &compiled_binary.target_triple,
+                    rustle_plan.total_tasks as usize,
+                )
+                .await?;
+            match &smoke_test.status {
+                SmokeTestStatus::Passed => info!(
+                    "✅ Smoke test passed in {:?}, {} events streamed",
+                    smoke_test.duration,
+                    smoke_test.events.len()
+                ),
+                SmokeTestStatus::Skipped { reason } => {
+                    warn!("⚠️  Smoke test of {} skipped: {}", target, reason)
+                }
+                SmokeTestStatus::Failed { failures } => {
+                    error!("❌ Smoke test of {} failed:", target);
+                    for failure in failures {
+                        error!("   {}", failure);
+                    }
+                    if !smoke_test.stderr.is_empty() {
+                        error!("   stderr:\n{}", smoke_test.stderr.trim_end());
+                    }
+                    smoke_test_failures.push(target.clone());
+                    continue;
+                }
+            }
+            artifact.smoke_test = Some(smoke_test.status);
+        }
+        manifest.add_artifact(artifact);
+        runners.push(CompiledRunner {
+            target_triple: target.clone(),
+            path: output_path,
+            size: compiled_binary.size,
+            checksum: compiled_binary.checksum.clone(),
+            hosts: target_hosts.remove(target).unwrap_or_default(),
+        });
+    }
+
+    let manifest_path = if manifest.artifacts.is_empty() {
+        None
     } else {
-        info!("✅ Template generated successfully:");
-        info!("   Target: {}", target_spec.target_triple);
-        info!("   Template files: {}", template.source_files.len());
+        let manifest_path = manifest.write(&cli.output_dir)?;
+        info!("✅ Manifest written to {}", manifest_path.display());
+        if cli.provenance {
+            let provenance_path =
+                manifest.write_provenance(&cli.output_dir, &local_builder_id())?;
+            info!("✅ Provenance written to {}", provenance_path.display());
+        }
+        Some(manifest_path)
+    };
+
+    if !report.is_success() {
+        for (target, error) in &report.failures {
+            match report.diagnostics.get(target) {
+                Some(diagnostics) => error!(
+                    "❌ Compilation for {} failed:\n{}",
+                    target,
+                    diagnostics.render()
+                ),
+                None => error!("❌ Compilation for {} failed: {}", target, error),
+            }
+        }
+        return Err(anyhow::anyhow!(
+            "Compilation failed for {} of {} targets",
+            report.failures.len(),
+            report.failures.len() + report.binaries.len()
+        ));
+    }
+    if !smoke_test_failures.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Smoke test failed for {}",
+            smoke_test_failures.join(", ")
+        ));
     }
-    // let binary_manager = BinaryOutputManager::new(...);
-    // let copy_result = binary_manager.copy_to_output(&compiled_binary, &output_path).await?;
 
-    if !cli.compile_only {
-        info!("Output would be written to: {}", cli.output_dir.display());
+    let compiled = CompileOutcome {
+        runners,
+        manifest,
+        manifest_path: manifest_path.context("No runner was compiled")?,
+    };
+    Ok((rustle_plan, compiled))
+}
+
+/// The plan read from stdin if it was, otherwise parsed from its file with
+/// the variables rendered into it
+async fn load_rustle_plan(
+    cli: &RustleDeployCli,
+    cached_rustle_plan: Option<RustlePlanOutput>,
+    vault: &VaultSecrets,
+    vars: &HashMap<String, serde_json::Value>,
+) -> Result<RustlePlanOutput> {
+    if let Some(cached_plan) = cached_rustle_plan {
+        return Ok(cached_plan);
     }
+    let Some(execution_plan_path) = &cli.execution_plan else {
+        return Err(anyhow::anyhow!("Execution plan is required"));
+    };
+    let mut rustle_plan =
+        parse_rustle_plan_from_file(execution_plan_path, vault, parse_mode(cli)).await?;
+    let base_dir = execution_plan_path.parent().unwrap_or(Path::new("."));
+    resolve_includes(cli, &mut rustle_plan, base_dir, vault, vars)?;
+    render_plan_variables(&mut rustle_plan, vars);
+    resolve_lookups(cli, &mut rustle_plan, vars).await?;
+    Ok(rustle_plan)
+}
 
-    // TODO: Make the binary executable and test execution
-    // Currently disabled until compilation is working
-    // #[cfg(unix)]
-    // {
-    //     use std::os::unix::fs::PermissionsExt;
-    //     let mut perms = std::fs::metadata(&copy_result.output_path)?.permissions();
-    //     perms.set_mode(0o755); // rwxr-xr-x
-    //     std::fs::set_permissions(&copy_result.output_path, perms)?;
-    // }
+/// Which local files of tasks are embedded, the defaults extended by the
+/// command line
+fn payload_policy(cli: &RustleDeployCli) -> PayloadPolicy {
+    let defaults = PayloadPolicy::default();
+    let max_file_size = cli.max_payload_size * 1024 * 1024;
+    PayloadPolicy {
+        max_file_size,
+        max_total_size: defaults.max_total_size.max(max_file_size),
+        exclude: defaults
+            .exclude
+            .into_iter()
+            .chain(cli.payload_excludes.iter().cloned())
+            .collect(),
+    }
+}
 
-    // if cli.localhost_test {
-    //     info!("Testing binary execution on localhost");
-    //     test_binary_execution(&copy_result.output_path).await?;
-    // }
+/// The custom modules on the `--module-path` directories and those
+/// RUSTLE_MODULE_PATH lists
+fn custom_modules(cli: &RustleDeployCli) -> Result<Vec<CustomModule>> {
+    let search_path = ModuleSearchPath::new()
+        .with_dirs(cli.module_paths.iter().cloned())
+        .with_env();
+    let modules = search_path.discover().with_context(|| {
+        format!("Failed to load the custom modules of --module-path and {MODULE_PATH_ENV}")
+    })?;
+    for module in &modules {
+        info!(
+            "Custom module {} from {}",
+            module.name,
+            module.path.display()
+        );
+    }
+    Ok(modules)
+}
 
-    Ok(())
+/// The Ansible Python modules runners fall back to, with `--python-fallback`
+fn python_modules(cli: &RustleDeployCli) -> Option<PythonModules> {
+    if !cli.python_fallback {
+        return None;
+    }
+    let modules = cli
+        .python_module_paths
+        .iter()
+        .fold(PythonModules::new(), |modules, dir| {
+            modules.with_library(dir)
+        })
+        .with_env();
+    let modules = match &cli.ansible_path {
+        Some(dir) => modules.with_ansible_dir(dir),
+        None => match modules.clone().with_installed_ansible() {
+            Ok(modules) => modules,
+            Err(e) => {
+                warn!(
+                    "Only the modules of --python-module-path and {ANSIBLE_LIBRARY_ENV} can run with Python: {e}"
+                );
+                modules
+            }
+        },
+    };
+    if let Some(dir) = modules.ansible_dir() {
+        info!(
+            "Python modules fall back to the Ansible of {}",
+            dir.display()
+        );
+    }
+    Some(modules)
 }
 
-async fn parse_rustle_plan_from_file(path: &PathBuf) -> Result<RustlePlanOutput> {
+async fn parse_rustle_plan_from_file(
+    path: &PathBuf,
+    vault: &VaultSecrets,
+    mode: ParseMode,
+) -> Result<RustlePlanOutput> {
     let content = tokio::fs::read_to_string(path).await?;
-    parse_rustle_plan_content(&content).await
+    Ok(parse_plan_with_mode(&content, vault, mode)?)
 }
 
-async fn parse_rustle_plan_from_stdin() -> Result<RustlePlanOutput> {
+async fn parse_rustle_plan_from_stdin(
+    vault: &VaultSecrets,
+    mode: ParseMode,
+) -> Result<RustlePlanOutput> {
     use tokio::io::{self, AsyncReadExt};
     let mut stdin = io::stdin();
     let mut content = String::new();
     stdin.read_to_string(&mut content).await?;
-    parse_rustle_plan_content(&content).await
+    Ok(parse_plan_with_mode(&content, vault, mode)?)
+}
+
+/// The plan of the playbook at `path` for the hosts of the inventory the
+/// options name, with the extra variables over those it defines
+async fn load_playbook(
+    cli: &RustleDeployCli,
+    path: &Path,
+    vault: &VaultSecrets,
+    vars: &HashMap<String, serde_json::Value>,
+) -> Result<RustlePlanOutput> {
+    let vars_loader = vars_file_loader(cli, vault)?;
+    let inventory = match &cli.inventory {
+        Some(inventory) => Some(
+            InventoryProcessor::new()
+                .with_vars_loader(vars_loader)
+                .with_architecture_from_facts()
+                .process_from_source(inventory)
+                .await
+                .with_context(|| format!("Failed to load inventory {}", inventory.display()))?,
+        ),
+        None => None,
+    };
+    playbook_loader(cli, vault, vars)?
+        .load(path, inventory.as_ref())
+        .with_context(|| format!("Failed to plan playbook {}", path.display()))
+}
+
+/// Inline the task and variables files `plan` includes, relative to
+/// `base_dir`, failing on those missing before anything is built
+fn resolve_includes(
+    cli: &RustleDeployCli,
+    plan: &mut RustlePlanOutput,
+    base_dir: &Path,
+    vault: &VaultSecrets,
+    vars: &HashMap<String, serde_json::Value>,
+) -> Result<()> {
+    playbook_loader(cli, vault, vars)?
+        .resolve_includes(plan, base_dir)
+        .context("Failed to resolve the files the plan includes")
+}
+
+/// Plans playbooks with the roles paths, keys and extra variables the
+/// options give
+fn playbook_loader(
+    cli: &RustleDeployCli,
+    vault: &VaultSecrets,
+    vars: &HashMap<String, serde_json::Value>,
+) -> Result<PlaybookLoader> {
+    let env_roles_paths = std::env::var_os("ANSIBLE_ROLES_PATH").unwrap_or_default();
+    let mut loader = PlaybookLoader::new()
+        .with_vault(vault.clone())
+        .with_vars_loader(vars_file_loader(cli, vault)?)
+        .with_extra_vars(vars.clone());
+    for roles_path in cli
+        .roles_paths
+        .iter()
+        .cloned()
+        .chain(std::env::split_paths(&env_roles_paths))
+        .filter(|roles_path| !roles_path.as_os_str().is_empty())
+    {
+        loader = loader.with_roles_path(roles_path);
+    }
+    Ok(loader)
+}
+
+/// How plans not of the current schema are parsed
+fn parse_mode(cli: &RustleDeployCli) -> ParseMode {
+    if cli.strict_plan {
+        ParseMode::Strict
+    } else {
+        ParseMode::Lenient
+    }
+}
+
+/// The vault passwords the options name, asking for them where needed
+fn load_vault_secrets(cli: &RustleDeployCli) -> Result<VaultSecrets> {
+    let mut identities = cli.vault_ids.clone();
+    identities.extend(
+        cli.vault_password_files
+            .iter()
+            .map(|path| VaultIdentity::file(DEFAULT_VAULT_ID, path)),
+    );
+    if cli.ask_vault_pass {
+        identities.push(VaultIdentity::prompt(DEFAULT_VAULT_ID));
+    }
+    Ok(VaultSecrets::load(&identities)?)
+}
+
+/// The variables of the variables files the options name, decrypted in
+/// memory
+fn load_vars_files(
+    cli: &RustleDeployCli,
+    vault: &VaultSecrets,
+) -> Result<HashMap<String, serde_json::Value>> {
+    if cli.vars_files.is_empty() {
+        return Ok(HashMap::new());
+    }
+    Ok(vars_file_loader(cli, vault)?.load_all(&cli.vars_files)?)
+}
+
+/// Loads variables files with `vault` and the SOPS keys the options give
+fn vars_file_loader(cli: &RustleDeployCli, vault: &VaultSecrets) -> Result<VarsFileLoader> {
+    let mut sops = SopsKeys::load()?;
+    for path in &cli.sops_age_key_files {
+        sops = sops.with_age_key_file(path)?;
+    }
+    Ok(VarsFileLoader::new()
+        .with_vault(vault.clone())
+        .with_sops(sops))
+}
+
+/// The lookup plugins, with the secret stores configured as the options say
+fn lookup_registry(cli: &RustleDeployCli) -> Result<LookupRegistry> {
+    let config = match &cli.lookup_config {
+        Some(path) => {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read lookup config {}", path.display()))?;
+            serde_yaml::from_str::<LookupConfig>(&content)
+                .with_context(|| format!("Invalid lookup config {}", path.display()))?
+        }
+        None => LookupConfig::default(),
+    };
+    Ok(LookupRegistry::builtin().with_secret_lookups(Arc::new(SecretLookups::new(config))))
+}
+
+/// Evaluate the lookups of `plan` the controller evaluates, and those of
+/// the secret stores when the options ask for it. Their arguments may refer
+/// to `vars`.
+async fn resolve_lookups(
+    cli: &RustleDeployCli,
+    plan: &mut RustlePlanOutput,
+    vars: &HashMap<String, serde_json::Value>,
+) -> Result<()> {
+    let sides: &[LookupSide] = if cli.resolve_lookups {
+        &[LookupSide::Controller, LookupSide::Target]
+    } else {
+        &[LookupSide::Controller]
+    };
+    resolve_plan_lookups(
+        plan,
+        &lookup_registry(cli)?,
+        &LookupContext::current_dir().with_variables(vars.clone()),
+        sides,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Predicts compile times from the builds in the compilation cache, if
+/// there is one
+fn compile_estimator(cli: &RustleDeployCli) -> CompileEstimator {
+    let cache_dir = compilation_cache_dir(cli);
+    let exists = cache_dir.exists();
+    CompileEstimator::from_cache(&CompilationCache::new(cache_dir, exists))
+}
+
+/// The compile time of the runners `plan` needs, if it deploys any
+fn estimate_compilation(
+    plan: &RustlePlanOutput,
+    estimator: &CompileEstimator,
+) -> Option<CompileEstimate> {
+    let targets = plan_targets(plan);
+    (!targets.is_empty()).then(|| estimator.estimate(plan, &targets))
 }
 
-fn create_execution_plan_summary(rustle_plan: &RustlePlanOutput) -> Result<ExecutionPlanSummary> {
+fn print_compile_estimate(estimate: &CompileEstimate) {
+    println!(
+        "  • Estimated compilation time: {:.0}s ({} tasks, {} modules)",
+        estimate.total.as_secs_f64(),
+        estimate.complexity.tasks,
+        estimate.complexity.modules
+    );
+    for target in &estimate.targets {
+        println!(
+            "     {:<40} {:>5.0}s  {}",
+            target.target_triple,
+            target.compile_time.as_secs_f64(),
+            target.basis
+        );
+    }
+}
+
+fn create_execution_plan_summary(
+    rustle_plan: &RustlePlanOutput,
+    estimator: &CompileEstimator,
+) -> Result<ExecutionPlanSummary> {
     let total_tasks = rustle_plan.total_tasks;
 
     // Count binary deployment opportunities
@@ -593,126 +2520,11 @@ fn create_execution_plan_summary(rustle_plan: &RustlePlanOutput) -> Result<Execu
         binary_deployment_hosts,
         ssh_fallback_hosts,
         estimated_speedup,
-        estimated_compilation_time: rustle_plan.estimated_compilation_time,
+        compile_estimate: estimate_compilation(rustle_plan, estimator),
         strategy,
     })
 }
 
-async fn parse_rustle_plan_content(content: &str) -> Result<RustlePlanOutput> {
-    let mut rustle_plan: RustlePlanOutput = serde_json::from_str(content)?;
-
-    // Apply format migration to ensure compatibility with both old and new formats
-    let migrator = FormatMigrator::new();
-    match migrator.migrate_rustle_plan_output(&mut rustle_plan) {
-        Ok(warnings) => {
-            if !warnings.is_empty() {
-                warn!(
-                    "Format migration completed with {} warnings",
-                    warnings.len()
-                );
-                for warning in warnings {
-                    warn!("Migration warning: {:?}", warning);
-                }
-            }
-        }
-        Err(e) => {
-            warn!(
-                "Format migration failed, continuing with original format: {}",
-                e
-            );
-        }
-    }
-
-    Ok(rustle_plan)
-}
-
-fn create_target_info_from_spec(target_spec: &TargetSpecification) -> Result<TargetInfo> {
-    let platform = if target_spec.target_triple.contains("apple-darwin") {
-        Platform::MacOS
-    } else if target_spec.target_triple.contains("linux") {
-        Platform::Linux
-    } else if target_spec.target_triple.contains("windows") {
-        Platform::Windows
-    } else {
-        return Err(anyhow::anyhow!(
-            "Unsupported target platform: {}",
-            target_spec.target_triple
-        ));
-    };
-
-    let architecture = if target_spec.target_triple.starts_with("aarch64") {
-        "aarch64"
-    } else if target_spec.target_triple.starts_with("x86_64") {
-        "x86_64"
-    } else {
-        "unknown"
-    };
-
-    let os_family = if target_spec.target_triple.contains("windows") {
-        "windows"
-    } else {
-        "unix"
-    };
-
-    let libc = if target_spec.target_triple.contains("musl") {
-        Some("musl".to_string())
-    } else if target_spec.target_triple.contains("gnu") {
-        Some("gnu".to_string())
-    } else {
-        None
-    };
-
-    Ok(TargetInfo {
-        target_triple: target_spec.target_triple.clone(),
-        platform,
-        architecture: architecture.to_string(),
-        os_family: os_family.to_string(),
-        libc,
-        features: vec![], // Default empty features
-    })
-}
-
-#[allow(dead_code)]
-async fn test_binary_execution(binary_path: &PathBuf) -> Result<()> {
-    info!("Executing binary for testing: {}", binary_path.display());
-
-    let output = tokio::process::Command::new(binary_path)
-        .arg("--help") // Try to get help output first
-        .output()
-        .await?;
-
-    if output.status.success() {
-        info!("✅ Binary executed successfully");
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        if !stdout.trim().is_empty() {
-            info!("Binary output:\n{}", stdout);
-        }
-    } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        warn!("⚠️  Binary execution had non-zero exit status");
-        if !stderr.trim().is_empty() {
-            warn!("Stderr: {}", stderr);
-        }
-
-        // Try running without --help flag
-        info!("Trying to run binary without arguments");
-        let output2 = tokio::process::Command::new(binary_path).output().await?;
-
-        if output2.status.success() {
-            info!("✅ Binary executed successfully without arguments");
-            let stdout = String::from_utf8_lossy(&output2.stdout);
-            if !stdout.trim().is_empty() {
-                info!("Binary output:\n{}", stdout);
-            }
-        } else {
-            let stderr = String::from_utf8_lossy(&output2.stderr);
-            return Err(anyhow::anyhow!("Binary execution failed: {}", stderr));
-        }
-    }
-
-    Ok(())
-}
-
 fn show_usage() {
     println!("rustle-deploy: Binary compiler and deployment manager");
     println!();
@@ -723,6 +2535,8 @@ fn show_usage() {
     println!("  rustle-deploy <execution-plan.json> --deploy-only  # Deploy existing binaries");
     println!("  rustle-deploy --check-capabilities                 # Check setup");
     println!("  rustle-deploy --setup                              # Install dependencies");
+    println!("  rustle-deploy stats modules                        # Module execution statistics");
+    println!("  rustle-deploy results collect --deployment-id ID   # Collect uploaded results");
     println!();
     println!("Input from rustle-plan:");
     println!("  rustle-plan playbook.yml -i inventory.yml | rustle-deploy -");
@@ -866,16 +2680,22 @@ struct ExecutionPlanSummary {
     binary_deployment_hosts: usize,
     ssh_fallback_hosts: usize,
     estimated_speedup: f32,
-    estimated_compilation_time: Option<std::time::Duration>,
+    compile_estimate: Option<CompileEstimate>,
     strategy: String,
 }
 
-async fn parse_execution_plan_from_file(path: &std::path::Path) -> Result<ExecutionPlanSummary> {
+async fn parse_execution_plan_from_file(
+    path: &std::path::Path,
+    estimator: &CompileEstimator,
+) -> Result<ExecutionPlanSummary> {
     let content = tokio::fs::read_to_string(path).await?;
-    parse_execution_plan_json(&content)
+    parse_execution_plan_json(&content, estimator)
 }
 
-fn parse_execution_plan_json(content: &str) -> Result<ExecutionPlanSummary> {
+fn parse_execution_plan_json(
+    content: &str,
+    estimator: &CompileEstimator,
+) -> Result<ExecutionPlanSummary> {
     // Parse the JSON to extract key information for analysis
     let json: serde_json::Value = serde_json::from_str(content)?;
 
@@ -911,21 +2731,17 @@ fn parse_execution_plan_json(content: &str) -> Result<ExecutionPlanSummary> {
         .unwrap_or("Unknown")
         .to_string();
 
-    // Estimate compilation time (simplified calculation)
-    let estimated_compilation_time = if binary_deployment_hosts > 0 {
-        let base_time = std::time::Duration::from_secs(30); // Base compilation time
-        let per_target_time = std::time::Duration::from_secs(10); // Per target overhead
-        Some(base_time + per_target_time * (binary_deployment_hosts.min(5) as u32))
-    } else {
-        None
-    };
+    // Plans of older schemas are only summarized
+    let compile_estimate = serde_json::from_value::<RustlePlanOutput>(json)
+        .ok()
+        .and_then(|plan| estimate_compilation(&plan, estimator));
 
     Ok(ExecutionPlanSummary {
         total_tasks,
         binary_deployment_hosts,
         ssh_fallback_hosts,
         estimated_speedup,
-        estimated_compilation_time,
+        compile_estimate,
         strategy,
     })
 }
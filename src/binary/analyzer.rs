@@ -4,6 +4,7 @@ use crate::execution::compatibility::AssessmentError;
 use crate::execution::rustle_plan::{BinaryCompatibility, TaskPlan};
 
 use super::module_registry::ModuleRegistry;
+use super::platform::{BinaryRequirements, HostLibc, HostPlatform, LibcRequirement};
 
 pub struct BinaryCompatibilityAnalyzer {
     module_registry: ModuleRegistry,
@@ -184,6 +185,71 @@ impl BinaryCompatibilityAnalyzer {
         }
     }
 
+    /// Check whether a compiled binary can run on a probed host.
+    ///
+    /// Architecture, OS, libc and kernel mismatches make it incompatible; a
+    /// libc that could not be determined is reported as a limitation.
+    pub fn assess_host_compatibility(
+        &self,
+        requirements: &BinaryRequirements,
+        host: &HostPlatform,
+    ) -> BinaryCompatibility {
+        let mut reasons = Vec::new();
+        let mut limitations = Vec::new();
+
+        if requirements.arch != host.arch {
+            reasons.push(format!(
+                "binary is built for {}, host is {}",
+                requirements.arch, host.arch
+            ));
+        }
+        if requirements.os != host.os {
+            reasons.push(format!(
+                "binary is built for {}, host runs {}",
+                requirements.os, host.os
+            ));
+        }
+
+        match (&requirements.libc, &host.libc) {
+            (LibcRequirement::None, _) => {}
+            (LibcRequirement::Glibc(required), HostLibc::Glibc(available)) => {
+                if available < required {
+                    reasons.push(format!(
+                        "binary needs glibc {required}, host has glibc {available}"
+                    ));
+                }
+            }
+            (LibcRequirement::Musl, HostLibc::Musl) => {}
+            (LibcRequirement::Glibc(_), HostLibc::Musl) => {
+                reasons.push("binary needs glibc, host uses musl".to_string());
+            }
+            (LibcRequirement::Musl, HostLibc::Glibc(_)) => {
+                reasons.push("binary needs the musl loader, host uses glibc".to_string());
+            }
+            (required, HostLibc::Unknown | HostLibc::None) => {
+                limitations.push(format!(
+                    "could not determine the host libc ({required:?} required)"
+                ));
+            }
+        }
+
+        if let (Some(required), Some(available)) = (requirements.min_kernel, host.kernel) {
+            if available < required {
+                reasons.push(format!(
+                    "binary needs kernel {required}, host runs {available}"
+                ));
+            }
+        }
+
+        if !reasons.is_empty() {
+            BinaryCompatibility::Incompatible { reasons }
+        } else if !limitations.is_empty() {
+            BinaryCompatibility::PartiallyCompatible { limitations }
+        } else {
+            BinaryCompatibility::FullyCompatible
+        }
+    }
+
     pub fn estimate_binary_efficiency(&self, task: &TaskPlan) -> Result<f32, AssessmentError> {
         let compatibility = self.assess_task_compatibility(task)?;
 
@@ -310,6 +376,21 @@ mod tests {
             can_run_parallel: true,
             estimated_duration: Duration::from_secs(10),
             risk_level: RiskLevel::Low,
+            run_once: false,
+            delegate_to: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            no_log: false,
+            r#become: None,
+            environment: HashMap::new(),
+            module_defaults: HashMap::new(),
+            timeout: None,
+            throttle: None,
         }
     }
 
@@ -367,6 +448,46 @@ mod tests {
         assert!(result.unwrap() > 0.0);
     }
 
+    #[test]
+    fn test_assess_host_compatibility() {
+        use crate::binary::platform::Version;
+
+        let analyzer = BinaryCompatibilityAnalyzer::new();
+        let requirements = BinaryRequirements {
+            libc: LibcRequirement::Glibc(Version(2, 28)),
+            ..BinaryRequirements::for_triple("x86_64-unknown-linux-gnu")
+        };
+        let host = HostPlatform {
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            kernel: Some(Version(5, 4)),
+            libc: HostLibc::Glibc(Version(2, 31)),
+        };
+        assert!(matches!(
+            analyzer.assess_host_compatibility(&requirements, &host),
+            BinaryCompatibility::FullyCompatible
+        ));
+
+        let old_host = HostPlatform {
+            arch: "aarch64".to_string(),
+            libc: HostLibc::Glibc(Version(2, 17)),
+            ..host.clone()
+        };
+        match analyzer.assess_host_compatibility(&requirements, &old_host) {
+            BinaryCompatibility::Incompatible { reasons } => assert_eq!(reasons.len(), 2),
+            other => panic!("expected incompatible, got {other:?}"),
+        }
+
+        let unknown_libc = HostPlatform {
+            libc: HostLibc::Unknown,
+            ..host
+        };
+        assert!(matches!(
+            analyzer.assess_host_compatibility(&requirements, &unknown_libc),
+            BinaryCompatibility::PartiallyCompatible { .. }
+        ));
+    }
+
     #[test]
     fn test_performance_analysis() {
         let analyzer = BinaryCompatibilityAnalyzer::new();
@@ -1,5 +1,5 @@
 use anyhow::{anyhow, Result};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 pub struct ArchitectureDetector {
     default_architecture: String,
@@ -45,6 +45,40 @@ impl ArchitectureDetector {
         Ok(architecture)
     }
 
+    /// Record the target triple of `host`, e.g. from the inventory or a probe
+    pub fn set_host_architecture(&mut self, host: &str, target_triple: &str) -> Result<()> {
+        let triple = self.normalize_target_triple(target_triple)?;
+        self.architecture_cache.insert(host.to_string(), triple);
+        Ok(())
+    }
+
+    /// Target triple of `host`: the recorded one, or a fresh detection
+    pub fn host_architecture(&self, host: &str) -> Result<String> {
+        match self.architecture_cache.get(host) {
+            Some(triple) => Ok(triple.clone()),
+            None => self.detect_architecture_for_host(host),
+        }
+    }
+
+    /// Group `hosts` by target triple, keeping their order within each group
+    pub fn group_hosts_by_architecture(
+        &self,
+        hosts: &[String],
+    ) -> Result<BTreeMap<String, Vec<String>>> {
+        if hosts.is_empty() {
+            return Err(anyhow!("No hosts provided for architecture detection"));
+        }
+
+        let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for host in hosts {
+            groups
+                .entry(self.host_architecture(host)?)
+                .or_default()
+                .push(host.clone());
+        }
+        Ok(groups)
+    }
+
     fn detect_architecture_for_host(&self, host: &str) -> Result<String> {
         // Mock implementation - in reality this would SSH and run commands
         match host {
@@ -107,6 +141,7 @@ impl ArchitectureDetector {
             "powerpc",
             "powerpc64",
             "riscv64",
+            "riscv64gc",
             "s390x",
         ];
         if !valid_archs.contains(&arch) {
@@ -130,6 +165,7 @@ impl ArchitectureDetector {
             "freebsd",
             "netbsd",
             "openbsd",
+            "illumos",
         ];
 
         let is_valid_os = valid_os_patterns
@@ -197,9 +233,12 @@ impl ArchitectureDetector {
             ("aarch64", os) if os.contains("linux") => {
                 requirements.push("gcc-aarch64-linux-gnu".to_string());
             }
-            ("arm", os) if os.contains("linux") => {
+            ("arm" | "armv7", os) if os.contains("linux") => {
                 requirements.push("gcc-arm-linux-gnueabihf".to_string());
             }
+            ("riscv64gc", os) if os.contains("linux") => {
+                requirements.push("gcc-riscv64-linux-gnu".to_string());
+            }
             ("x86_64", os) if os.contains("windows") => {
                 requirements.push("mingw-w64".to_string());
             }
@@ -346,6 +385,25 @@ mod tests {
         assert!(detector.architecture_cache.is_empty());
     }
 
+    #[test]
+    fn test_group_hosts_by_architecture() {
+        let mut detector = ArchitectureDetector::new();
+        detector
+            .set_host_architecture("pi", "aarch64-unknown-linux-gnu")
+            .unwrap();
+        detector
+            .set_host_architecture("win", "x86_64-windows")
+            .unwrap();
+        assert!(detector.set_host_architecture("bad", "invalid").is_err());
+
+        let hosts = ["web1", "pi", "win", "web2"].map(String::from);
+        let groups = detector.group_hosts_by_architecture(&hosts).unwrap();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups["x86_64-unknown-linux-gnu"], ["web1", "web2"]);
+        assert_eq!(groups["aarch64-unknown-linux-gnu"], ["pi"]);
+        assert_eq!(groups["x86_64-pc-windows-msvc"], ["win"]);
+    }
+
     #[test]
     fn test_set_default_architecture() {
         let mut detector = ArchitectureDetector::new();
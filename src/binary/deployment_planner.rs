@@ -1,5 +1,5 @@
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::time::Duration;
 use uuid::Uuid;
 
@@ -39,6 +39,43 @@ impl BinaryDeploymentPlanner {
         }
     }
 
+    /// Record the target triples of hosts known from the inventory, so they
+    /// are grouped without being detected
+    pub fn with_host_architectures<I, H, T>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = (H, T)>,
+        H: AsRef<str>,
+        T: AsRef<str>,
+    {
+        for (host, triple) in hosts {
+            if let Err(e) = self
+                .architecture_detector
+                .set_host_architecture(host.as_ref(), triple.as_ref())
+            {
+                tracing::warn!(
+                    "Ignoring target triple of {}: {}; it will be detected",
+                    host.as_ref(),
+                    e
+                );
+            }
+        }
+        self
+    }
+
+    /// Group `hosts` by target triple; each group gets its own binary
+    pub fn group_hosts_by_architecture(
+        &self,
+        hosts: &[String],
+    ) -> Result<BTreeMap<String, Vec<String>>, AnalysisError> {
+        self.architecture_detector
+            .group_hosts_by_architecture(hosts)
+            .map_err(|_e| AnalysisError::ArchitectureDetection {
+                hosts: hosts.to_vec(),
+            })
+    }
+
+    /// One deployment plan per target triple among `hosts`, each covering
+    /// the binary-compatible tasks that run on its hosts
     pub fn create_deployment_plans(
         &self,
         tasks: &[TaskPlan],
@@ -47,13 +84,17 @@ impl BinaryDeploymentPlanner {
     ) -> Result<Vec<BinaryDeploymentPlan>, AnalysisError> {
         let mut deployment_plans = Vec::new();
 
-        // Group tasks by target architecture
-        let architecture_groups = self.group_tasks_by_architecture(tasks, hosts)?;
+        let compatible_tasks = self.compatible_tasks(tasks);
+        for (architecture, group_hosts) in self.group_hosts_by_architecture(hosts)? {
+            let task_group: Vec<TaskPlan> = compatible_tasks
+                .iter()
+                .filter(|task| Self::runs_on(task, &group_hosts, hosts))
+                .cloned()
+                .collect();
 
-        for (architecture, task_group) in architecture_groups {
             if task_group.len() >= threshold as usize {
                 let deployment_plan =
-                    self.create_single_deployment_plan(&task_group, hosts, &architecture)?;
+                    self.create_single_deployment_plan(&task_group, &group_hosts, &architecture)?;
                 deployment_plans.push(deployment_plan);
             }
         }
@@ -61,21 +102,19 @@ impl BinaryDeploymentPlanner {
         Ok(deployment_plans)
     }
 
-    fn group_tasks_by_architecture(
-        &self,
-        tasks: &[TaskPlan],
-        hosts: &[String],
-    ) -> Result<HashMap<String, Vec<TaskPlan>>, AnalysisError> {
-        let mut groups = HashMap::new();
-
-        // For now, assume all hosts have the same architecture
-        // In a real implementation, this would query each host
-        let architecture = self
-            .architecture_detector
-            .detect_primary_architecture(hosts)
-            .map_err(|_e| AnalysisError::ArchitectureDetection {
-                hosts: hosts.to_vec(),
-            })?;
+    /// Whether `task` runs on any of `group_hosts`. Tasks without hosts, for
+    /// `all`, or for hosts outside `all_hosts` (such as group names) run
+    /// everywhere.
+    fn runs_on(task: &TaskPlan, group_hosts: &[String], all_hosts: &[String]) -> bool {
+        if task.hosts.is_empty() || task.hosts.iter().any(|host| host == "all") {
+            return true;
+        }
+        let targets = |candidates: &[String]| task.hosts.iter().any(|h| candidates.contains(h));
+        targets(group_hosts) || !targets(all_hosts)
+    }
+
+    fn compatible_tasks(&self, tasks: &[TaskPlan]) -> Vec<TaskPlan> {
+        let mut compatible = Vec::new();
 
         // Filter tasks that are compatible with binary deployment
         for task in tasks {
@@ -84,9 +123,7 @@ impl BinaryDeploymentPlanner {
                     match compat {
                         crate::execution::rustle_plan::BinaryCompatibility::FullyCompatible |
                         crate::execution::rustle_plan::BinaryCompatibility::PartiallyCompatible { .. } => {
-                            groups.entry(architecture.clone())
-                                .or_insert_with(Vec::new)
-                                .push(task.clone());
+                            compatible.push(task.clone());
                         }
                         crate::execution::rustle_plan::BinaryCompatibility::Incompatible { .. } => {
                             // Skip incompatible tasks
@@ -105,7 +142,7 @@ impl BinaryDeploymentPlanner {
             }
         }
 
-        Ok(groups)
+        compatible
     }
 
     fn create_single_deployment_plan(
@@ -417,6 +454,21 @@ mod tests {
             can_run_parallel: true,
             estimated_duration: Duration::from_secs(10),
             risk_level: RiskLevel::Low,
+            run_once: false,
+            delegate_to: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            no_log: false,
+            r#become: None,
+            environment: HashMap::new(),
+            module_defaults: HashMap::new(),
+            timeout: None,
+            throttle: None,
         }
     }
 
@@ -443,6 +495,37 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_one_plan_per_architecture() {
+        let planner = BinaryDeploymentPlanner::new().with_host_architectures([
+            ("web1", "x86_64-unknown-linux-gnu"),
+            ("web2", "x86_64-unknown-linux-gnu"),
+            ("pi", "aarch64-unknown-linux-gnu"),
+        ]);
+        let mut pi_only = create_test_task("task3", "debug");
+        pi_only.hosts = vec!["pi".to_string()];
+        let tasks = vec![
+            create_test_task("task1", "debug"),
+            create_test_task("task2", "debug"),
+            pi_only,
+        ];
+        let hosts = ["web1", "pi", "web2"].map(String::from);
+
+        let plans = planner.create_deployment_plans(&tasks, &hosts, 1).unwrap();
+        assert_eq!(plans.len(), 2);
+        let arm = &plans[0];
+        assert_eq!(
+            arm.target_architecture.as_deref(),
+            Some("aarch64-unknown-linux-gnu")
+        );
+        assert_eq!(arm.target_hosts, ["pi"]);
+        assert_eq!(arm.tasks.len(), 3);
+        let x86 = &plans[1];
+        assert_eq!(x86.target_hosts, ["web1", "web2"]);
+        assert_eq!(x86.tasks, ["task1", "task2"]);
+        assert_eq!(x86.compilation_requirements.target_arch, "x86_64",);
+    }
+
     #[test]
     fn test_calculate_time_savings() {
         let planner = BinaryDeploymentPlanner::new();
@@ -2,8 +2,10 @@ pub mod analyzer;
 pub mod architecture_detector;
 pub mod deployment_planner;
 pub mod module_registry;
+pub mod platform;
 
 pub use analyzer::BinaryCompatibilityAnalyzer;
 pub use architecture_detector::ArchitectureDetector;
 pub use deployment_planner::BinaryDeploymentPlanner;
 pub use module_registry::ModuleRegistry;
+pub use platform::{BinaryRequirements, HostLibc, HostPlatform, LibcRequirement, Version};
@@ -0,0 +1,350 @@
+//! Host platforms as probed before deployment, and what a compiled binary
+//! needs from them.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// POSIX probe run on the target; prints `key=value` lines and always succeeds
+pub const POSIX_PROBE_SCRIPT: &str = concat!(
+    "echo \"os=$(uname -s)\"\n",
+    "echo \"arch=$(uname -m)\"\n",
+    "echo \"kernel=$(uname -r)\"\n",
+    "echo \"glibc=$(getconf GNU_LIBC_VERSION 2>/dev/null)\"\n",
+    "for f in /lib/ld-musl-*.so.1; do [ -e \"$f\" ] && echo \"musl=$f\"; done\n",
+    "exit 0\n",
+);
+
+/// PowerShell equivalent of [`POSIX_PROBE_SCRIPT`]
+pub const POWERSHELL_PROBE_SCRIPT: &str = concat!(
+    "\"os=Windows\"\n",
+    "\"arch=$env:PROCESSOR_ARCHITECTURE\"\n",
+    "\"kernel=$([Environment]::OSVersion.Version)\"\n",
+);
+
+/// A `major.minor` version, as used for glibc and kernel releases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Version(pub u32, pub u32);
+
+impl Version {
+    /// Parse the leading `major.minor` of strings like `2.35` or `5.15.0-91-generic`
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value
+            .trim()
+            .split(|c: char| !c.is_ascii_digit())
+            .map(str::parse::<u32>);
+        let major = parts.next()?.ok()?;
+        let minor = parts.next().and_then(|minor| minor.ok()).unwrap_or(0);
+        Some(Self(major, minor))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.0, self.1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostLibc {
+    Glibc(Version),
+    Musl,
+    /// Not a libc-based system, e.g. Windows or macOS
+    None,
+    Unknown,
+}
+
+/// What a probe found out about a deployment target
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostPlatform {
+    /// `linux`, `macos`, `freebsd`, `windows`, ...
+    pub os: String,
+    /// Normalised to Rust's names, e.g. `x86_64` or `aarch64`
+    pub arch: String,
+    pub kernel: Option<Version>,
+    pub libc: HostLibc,
+}
+
+impl HostPlatform {
+    /// Parse the output of [`POSIX_PROBE_SCRIPT`] or [`POWERSHELL_PROBE_SCRIPT`]
+    pub fn parse_probe(output: &str) -> Option<Self> {
+        let mut os = None;
+        let mut arch = None;
+        let mut kernel = None;
+        let mut glibc = None;
+        let mut musl = false;
+
+        for line in output.lines() {
+            let Some((key, value)) = line.trim().split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key {
+                "os" if !value.is_empty() => os = Some(normalize_os(value)),
+                "arch" if !value.is_empty() => arch = Some(normalize_arch(value)),
+                "kernel" => kernel = Version::parse(value),
+                "glibc" => glibc = value.strip_prefix("glibc").and_then(Version::parse),
+                "musl" if !value.is_empty() => musl = true,
+                _ => {}
+            }
+        }
+
+        let os = os?;
+        let libc = match (glibc, musl) {
+            (Some(version), _) => HostLibc::Glibc(version),
+            (None, true) => HostLibc::Musl,
+            (None, false) if os == "linux" => HostLibc::Unknown,
+            (None, false) => HostLibc::None,
+        };
+        Some(Self {
+            os,
+            arch: arch?,
+            kernel,
+            libc,
+        })
+    }
+}
+
+impl fmt::Display for HostPlatform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.arch, self.os)?;
+        match self.libc {
+            HostLibc::Glibc(version) => write!(f, " glibc {version}")?,
+            HostLibc::Musl => write!(f, " musl")?,
+            HostLibc::None | HostLibc::Unknown => {}
+        }
+        if let Some(kernel) = self.kernel {
+            write!(f, " (kernel {kernel})")?;
+        }
+        Ok(())
+    }
+}
+
+fn normalize_os(value: &str) -> String {
+    match value.to_ascii_lowercase().as_str() {
+        "darwin" | "macos" => "macos".to_string(),
+        "sunos" | "illumos" => "illumos".to_string(),
+        os if os.starts_with("windows") || os.starts_with("mingw") || os.starts_with("msys") => {
+            "windows".to_string()
+        }
+        os => os.to_string(),
+    }
+}
+
+fn normalize_arch(value: &str) -> String {
+    match value.to_ascii_lowercase().as_str() {
+        // illumos reports the machine of 64-bit x86 as `i86pc`
+        "x86_64" | "amd64" | "x64" | "i86pc" => "x86_64".to_string(),
+        "aarch64" | "arm64" | "armv8l" => "aarch64".to_string(),
+        "i386" | "i486" | "i586" | "i686" | "x86" => "i686".to_string(),
+        arch if arch.starts_with("armv7") => "armv7".to_string(),
+        arch if arch.starts_with("riscv64") => "riscv64".to_string(),
+        arch => arch.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LibcRequirement {
+    /// Statically linked, or no libc involved
+    None,
+    /// Dynamically linked against glibc of at least this version
+    Glibc(Version),
+    /// Dynamically linked against musl
+    Musl,
+}
+
+/// What a compiled binary needs from the host it runs on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BinaryRequirements {
+    pub os: String,
+    pub arch: String,
+    pub libc: LibcRequirement,
+    pub min_kernel: Option<Version>,
+}
+
+impl BinaryRequirements {
+    /// Baseline requirements of Rust's standard library for a target triple
+    pub fn for_triple(triple: &str) -> Self {
+        let mut parts = triple.split('-');
+        let arch = normalize_arch(parts.next().unwrap_or_default());
+        let rest: Vec<&str> = parts.collect();
+        let has = |name: &str| rest.iter().any(|part| part.starts_with(name));
+
+        let os = if has("linux") {
+            "linux"
+        } else if has("darwin") {
+            "macos"
+        } else if has("windows") {
+            "windows"
+        } else if has("freebsd") {
+            "freebsd"
+        } else if has("illumos") || has("solaris") {
+            "illumos"
+        } else {
+            rest.get(1).copied().unwrap_or("unknown")
+        }
+        .to_string();
+
+        let (libc, min_kernel) = if os == "linux" {
+            let min_kernel = if arch == "aarch64" {
+                Version(4, 1)
+            } else {
+                Version(3, 2)
+            };
+            // Rust links musl statically by default
+            let libc = if has("musl") {
+                LibcRequirement::None
+            } else {
+                LibcRequirement::Glibc(Version(2, 17))
+            };
+            (libc, Some(min_kernel))
+        } else {
+            (LibcRequirement::None, None)
+        };
+
+        Self {
+            os,
+            arch,
+            libc,
+            min_kernel,
+        }
+    }
+
+    /// Refine the triple's baseline with what the binary itself declares:
+    /// its ELF machine, dynamic loader and newest versioned glibc symbol.
+    pub fn with_binary(mut self, binary: &[u8]) -> Self {
+        if let Some(arch) = elf_arch(binary) {
+            self.arch = arch.to_string();
+        } else {
+            return self;
+        }
+
+        if contains(binary, b"/ld-musl-") {
+            self.libc = LibcRequirement::Musl;
+        } else if contains(binary, b"/ld-linux") {
+            let newest = newest_glibc_symbol(binary);
+            let baseline = match self.libc {
+                LibcRequirement::Glibc(version) => Some(version),
+                _ => None,
+            };
+            if let Some(version) = newest.max(baseline) {
+                self.libc = LibcRequirement::Glibc(version);
+            }
+        } else {
+            self.libc = LibcRequirement::None;
+        }
+        self
+    }
+}
+
+/// Architecture from an ELF header's `e_machine`
+fn elf_arch(binary: &[u8]) -> Option<&'static str> {
+    if binary.len() < 20 || &binary[..4] != b"\x7fELF" {
+        return None;
+    }
+    let machine = match binary[5] {
+        1 => u16::from_le_bytes([binary[18], binary[19]]),
+        _ => u16::from_be_bytes([binary[18], binary[19]]),
+    };
+    Some(match machine {
+        0x03 => "i686",
+        0x28 => "armv7",
+        0x3e => "x86_64",
+        0xb7 => "aarch64",
+        0xf3 => "riscv64gc",
+        0x15 => "powerpc64",
+        0x16 => "s390x",
+        _ => "unknown",
+    })
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+/// Highest `GLIBC_x.y` symbol version referenced by the binary
+fn newest_glibc_symbol(binary: &[u8]) -> Option<Version> {
+    const PREFIX: &[u8] = b"GLIBC_";
+    let mut newest = None;
+    let mut rest = binary;
+    while let Some(index) = rest
+        .windows(PREFIX.len())
+        .position(|window| window == PREFIX)
+    {
+        rest = &rest[index + PREFIX.len()..];
+        let end = rest
+            .iter()
+            .position(|b| !(b.is_ascii_digit() || *b == b'.'))
+            .unwrap_or(rest.len());
+        let version = std::str::from_utf8(&rest[..end])
+            .ok()
+            .filter(|v| v.contains('.'))
+            .and_then(Version::parse);
+        newest = newest.max(version);
+    }
+    newest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe() {
+        let platform = HostPlatform::parse_probe(
+            "os=Linux\narch=x86_64\nkernel=5.15.0-91-generic\nglibc=glibc 2.35\n",
+        )
+        .unwrap();
+        assert_eq!(platform.os, "linux");
+        assert_eq!(platform.kernel, Some(Version(5, 15)));
+        assert_eq!(platform.libc, HostLibc::Glibc(Version(2, 35)));
+
+        let alpine = HostPlatform::parse_probe(
+            "os=Linux\narch=aarch64\nkernel=6.1.0\nglibc=\nmusl=/lib/ld-musl-aarch64.so.1\n",
+        )
+        .unwrap();
+        assert_eq!(alpine.libc, HostLibc::Musl);
+
+        let windows =
+            HostPlatform::parse_probe("os=Windows\r\narch=AMD64\r\nkernel=10.0.20348.0\r\n")
+                .unwrap();
+        assert_eq!(windows.arch, "x86_64");
+        assert_eq!(windows.libc, HostLibc::None);
+
+        assert_eq!(HostPlatform::parse_probe("garbage"), None);
+    }
+
+    #[test]
+    fn test_requirements_for_triple() {
+        let gnu = BinaryRequirements::for_triple("x86_64-unknown-linux-gnu");
+        assert_eq!(gnu.os, "linux");
+        assert_eq!(gnu.libc, LibcRequirement::Glibc(Version(2, 17)));
+        assert_eq!(gnu.min_kernel, Some(Version(3, 2)));
+
+        let musl = BinaryRequirements::for_triple("aarch64-unknown-linux-musl");
+        assert_eq!(musl.libc, LibcRequirement::None);
+        assert_eq!(musl.min_kernel, Some(Version(4, 1)));
+
+        let mac = BinaryRequirements::for_triple("aarch64-apple-darwin");
+        assert_eq!((mac.os.as_str(), mac.arch.as_str()), ("macos", "aarch64"));
+    }
+
+    #[test]
+    fn test_requirements_from_elf() {
+        let mut elf = vec![0u8; 64];
+        elf[..4].copy_from_slice(b"\x7fELF");
+        elf[5] = 1;
+        elf[18] = 0xb7;
+        elf.extend_from_slice(
+            b"/lib/ld-linux-aarch64.so.1\0GLIBC_2.17\0GLIBC_2.34\0GLIBC_PRIVATE\0",
+        );
+
+        let requirements =
+            BinaryRequirements::for_triple("x86_64-unknown-linux-gnu").with_binary(&elf);
+        assert_eq!(requirements.arch, "aarch64");
+        assert_eq!(requirements.libc, LibcRequirement::Glibc(Version(2, 34)));
+    }
+}
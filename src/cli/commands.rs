@@ -308,6 +308,7 @@ impl RustleDeployCliImpl {
                 secrets: EncryptedSecrets { encrypted_data: std::collections::HashMap::new() },
                 facts_cache: None,
             },
+            sections: crate::template::EmbeddedSections::default(),
             cargo_toml: "[package]\nname = \"mock\"\nversion = \"0.1.0\"\n".to_string(),
             build_script: None,
             target_info: TargetInfo {
@@ -321,6 +322,7 @@ impl RustleDeployCliImpl {
             compilation_flags: vec![],
             estimated_binary_size: 1024 * 1024, // 1MB
             cache_key: "mock-cache-key".to_string(),
+            data_layout: crate::template::DataLayout::Compiled,
         };
 
         let mut hosts = std::collections::HashMap::new();
@@ -1,5 +1,9 @@
 /// Cargo-based compilation backend
 use super::traits::{BackendCapabilities, CompilationBackend};
+use crate::compilation::cancellation::output_unless_cancelled;
+use crate::compilation::compiler::CompilationError;
+use crate::compilation::diagnostics::CompilationDiagnostics;
+use crate::compilation::TargetDetector;
 use crate::template::GeneratedTemplate;
 use crate::types::compilation::{
     BinarySourceInfo, BinarySourceType, BuildMetadata, CompiledBinary, OptimizationLevel,
@@ -10,12 +14,14 @@ use async_trait::async_trait;
 use std::path::PathBuf;
 use std::time::Instant;
 use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, info};
 
 #[derive(Debug, Clone)]
 pub struct CargoBackend {
     #[allow(dead_code)]
     cache_dir: PathBuf,
+    cancel: CancellationToken,
 }
 
 #[derive(Debug, Clone)]
@@ -48,11 +54,21 @@ impl CargoBackend {
                 .unwrap_or_else(|| PathBuf::from("."))
                 .join("rustle-deploy")
                 .join("cargo"),
+            cancel: CancellationToken::new(),
         }
     }
 
     pub fn with_cache_dir(cache_dir: PathBuf) -> Self {
-        Self { cache_dir }
+        Self {
+            cache_dir,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Kill the builds in progress once `cancel` is cancelled
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
     }
 
     fn optimization_level_to_profile(&self, level: &OptimizationLevel) -> &'static str {
@@ -83,6 +99,7 @@ impl CargoBackend {
 
         // Set target triple
         cmd.args(["--target", &target.target_triple]);
+        cmd.arg("--message-format=json");
 
         // Target directory
         if let Some(target_dir) = &config.target_dir {
@@ -114,12 +131,22 @@ impl CargoBackend {
 
         debug!("Running cargo command: {:?}", cmd);
 
-        let output = cmd
-            .output()
+        let output = output_unless_cancelled(&mut cmd, &self.cancel)
             .await
-            .context("Failed to execute cargo build")?;
+            .context("Failed to execute cargo build")?
+            .ok_or_else(|| CompilationError::Cancelled {
+                target: target.target_triple.clone(),
+            })?;
 
         if !output.status.success() {
+            let diagnostics = CompilationDiagnostics::from_cargo_output(
+                &target.target_triple,
+                &String::from_utf8_lossy(&output.stdout),
+                project_path,
+            );
+            if diagnostics.has_errors() {
+                return Err(diagnostics.into());
+            }
             let stderr = String::from_utf8_lossy(&output.stderr);
             anyhow::bail!("Cargo build failed: {}", stderr);
         }
@@ -251,6 +278,16 @@ impl CompilationBackend for CargoBackend {
         true
     }
 
+    fn preference(&self, target: &str) -> u8 {
+        // Nothing beats a native build, and little is worse than a cross one
+        let host = TargetDetector::new().detect_host_target().ok();
+        if host.as_deref() == Some(target) {
+            2
+        } else {
+            0
+        }
+    }
+
     fn get_capabilities(&self) -> BackendCapabilities {
         BackendCapabilities {
             supported_targets: vec![], // Would be populated from rustc --print target-list
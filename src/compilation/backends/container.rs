@@ -0,0 +1,442 @@
+/// Container-based cross-compilation backend
+///
+/// Builds inside pinned toolchain images the way cross-rs does: the image
+/// brings the target's linker, C toolchain and libc, and the host's Rust
+/// toolchain and cargo home are mounted into it. This covers the targets
+/// zig links poorly, such as older glibc versions and 32-bit ARM with musl.
+///
+/// A target may carry a glibc version, as `x86_64-unknown-linux-gnu.2.17`,
+/// which selects the CentOS-based image of the target.
+use super::traits::{BackendCapabilities, CompilationBackend};
+use crate::compilation::cancellation::output_unless_cancelled;
+use crate::compilation::compiler::CompilationError;
+use crate::compilation::diagnostics::CompilationDiagnostics;
+use crate::deploy::ContainerRuntime;
+use crate::template::GeneratedTemplate;
+use crate::types::compilation::{
+    BinarySourceInfo, BinarySourceType, BuildMetadata, CompiledBinary, OptimizationLevel,
+    TargetSpecification,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// Version of the cross-rs images the default images are pinned to
+const CROSS_IMAGE_VERSION: &str = "0.2.5";
+
+/// Targets with a cross-rs image that zig does not link well
+const CONTAINER_TARGETS: &[&str] = &[
+    "armv7-unknown-linux-gnueabihf",
+    "armv7-unknown-linux-musleabihf",
+    "arm-unknown-linux-gnueabi",
+    "arm-unknown-linux-gnueabihf",
+    "arm-unknown-linux-musleabi",
+    "arm-unknown-linux-musleabihf",
+    "i686-unknown-linux-gnu",
+    "i686-unknown-linux-musl",
+    "powerpc64le-unknown-linux-gnu",
+    "s390x-unknown-linux-gnu",
+    "riscv64gc-unknown-linux-gnu",
+    "mips64el-unknown-linux-gnuabi64",
+    "x86_64-unknown-freebsd",
+    "x86_64-unknown-netbsd",
+    "x86_64-unknown-illumos",
+    "aarch64-linux-android",
+];
+
+/// Targets whose images also come CentOS-based, for glibc 2.17
+const CENTOS_TARGETS: &[&str] = &["x86_64-unknown-linux-gnu", "aarch64-unknown-linux-gnu"];
+
+/// Where the mounted host toolchain lives in the container
+const CONTAINER_RUST: &str = "/rust";
+const CONTAINER_CARGO_HOME: &str = "/cargo";
+const CONTAINER_PROJECT: &str = "/project";
+
+#[derive(Debug, Clone)]
+pub struct ContainerBackend {
+    runtime: Option<ContainerRuntime>,
+    images: BTreeMap<String, String>,
+    cancel: CancellationToken,
+}
+
+/// Options of a container build, given as the backend's configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContainerConfig {
+    /// Use this runtime instead of the one found on the `PATH`
+    pub runtime: Option<ContainerRuntime>,
+    /// Images to build targets in, over the pinned cross-rs ones
+    pub images: BTreeMap<String, String>,
+    pub verbose: bool,
+}
+
+impl Default for ContainerBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContainerBackend {
+    /// A backend with the pinned cross-rs images, using Docker or else
+    /// Podman when either is installed
+    pub fn new() -> Self {
+        let runtime = [ContainerRuntime::Docker, ContainerRuntime::Podman]
+            .into_iter()
+            .find(|runtime| which::which(runtime.executable()).is_ok());
+        if runtime.is_none() {
+            debug!("Neither docker nor podman found, container backend disabled");
+        }
+        Self::with_runtime(runtime)
+    }
+
+    pub fn with_runtime(runtime: Option<ContainerRuntime>) -> Self {
+        let mut images = BTreeMap::new();
+        for target in CONTAINER_TARGETS {
+            images.insert(target.to_string(), cross_image(target, None));
+        }
+        Self {
+            runtime,
+            images,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Stop the builds in progress, and remove their containers, once
+    /// `cancel` is cancelled
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Build `target` in `image` instead of its default one
+    pub fn with_image(mut self, target: &str, image: &str) -> Self {
+        self.images.insert(target.to_string(), image.to_string());
+        self
+    }
+
+    /// The image `target` is built in, if any
+    pub fn image_for(&self, target: &str) -> Option<String> {
+        if let Some(image) = self.images.get(target) {
+            return Some(image.clone());
+        }
+        match split_glibc(target) {
+            (triple, Some(minor)) if CENTOS_TARGETS.contains(&triple) && minor <= 17 => {
+                Some(cross_image(triple, Some("centos")))
+            }
+            _ => None,
+        }
+    }
+
+    fn optimization_level_to_profile(&self, level: &OptimizationLevel) -> &'static str {
+        match level {
+            OptimizationLevel::Debug => "debug",
+            _ => "release",
+        }
+    }
+
+    async fn run_container_build(
+        &self,
+        project_path: &Path,
+        target: &TargetSpecification,
+        config: &ContainerConfig,
+    ) -> Result<PathBuf> {
+        let Some(runtime) = config.runtime.or(self.runtime) else {
+            anyhow::bail!("Container backend is not available (neither docker nor podman found)");
+        };
+        let image = config
+            .images
+            .get(&target.target_triple)
+            .cloned()
+            .or_else(|| self.image_for(&target.target_triple))
+            .with_context(|| format!("No toolchain image for {}", target.target_triple))?;
+        let (triple, _) = split_glibc(&target.target_triple);
+
+        let sysroot = host_sysroot().await?;
+        let cargo_home = std::env::var_os("CARGO_HOME")
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|home| home.join(".cargo")))
+            .context("Cannot find the cargo home to mount")?;
+
+        // Named, to remove it when the build is cancelled: killing the
+        // client leaves the container running
+        let container = format!("rustle-build-{}", uuid::Uuid::new_v4());
+        let mut cmd = Command::new(runtime.executable());
+        cmd.args(["run", "--rm", "--name", &container]);
+        #[cfg(unix)]
+        if runtime == ContainerRuntime::Docker {
+            // Leave the build output owned by the user
+            cmd.args([
+                "--user",
+                &format!("{}:{}", nix::unistd::getuid(), nix::unistd::getgid()),
+            ]);
+        }
+        cmd.arg("--volume")
+            .arg(format!("{}:{CONTAINER_PROJECT}", project_path.display()))
+            .arg("--volume")
+            .arg(format!("{}:{CONTAINER_RUST}:ro", sysroot.display()))
+            .arg("--volume")
+            .arg(format!("{}:{CONTAINER_CARGO_HOME}", cargo_home.display()))
+            .args(["--workdir", CONTAINER_PROJECT])
+            .args(["--env", &format!("CARGO_HOME={CONTAINER_CARGO_HOME}")])
+            .args(["--env", &format!("CARGO_TARGET_DIR={CONTAINER_PROJECT}/target")])
+            .args([
+                "--env",
+                &format!("PATH={CONTAINER_RUST}/bin:/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin"),
+            ]);
+
+        let mut rustflags = Vec::new();
+        if matches!(
+            target.optimization_level,
+            OptimizationLevel::MinSize | OptimizationLevel::MinimalSize
+        ) {
+            rustflags.push("-C opt-level=z -C codegen-units=1");
+        }
+        if target.compilation_options.enable_lto {
+            rustflags.push("-C lto=fat");
+        }
+        if target.compilation_options.static_linking {
+            rustflags.push("-C target-feature=+crt-static");
+        }
+        if !rustflags.is_empty() {
+            cmd.args(["--env", &format!("RUSTFLAGS={}", rustflags.join(" "))]);
+        }
+
+        cmd.arg(&image).args([
+            "cargo",
+            "build",
+            "--target",
+            triple,
+            "--message-format=json",
+        ]);
+        let profile = self.optimization_level_to_profile(&target.optimization_level);
+        if profile == "release" {
+            cmd.arg("--release");
+        }
+        if config.verbose {
+            cmd.arg("--verbose");
+        }
+
+        debug!("Running container build: {:?}", cmd);
+
+        let Some(output) = output_unless_cancelled(&mut cmd, &self.cancel)
+            .await
+            .with_context(|| format!("Failed to run {}", runtime.executable()))?
+        else {
+            let removed = Command::new(runtime.executable())
+                .args(["rm", "--force", &container])
+                .output()
+                .await;
+            if !matches!(&removed, Ok(output) if output.status.success()) {
+                warn!("Failed to remove build container {}", container);
+            }
+            return Err(CompilationError::Cancelled {
+                target: target.target_triple.clone(),
+            }
+            .into());
+        };
+
+        if !output.status.success() {
+            let diagnostics = CompilationDiagnostics::from_cargo_output(
+                triple,
+                &String::from_utf8_lossy(&output.stdout),
+                project_path,
+            );
+            if diagnostics.has_errors() {
+                return Err(diagnostics.into());
+            }
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Container build in {} failed: {}", image, stderr);
+        }
+
+        let binary_path = project_path
+            .join("target")
+            .join(triple)
+            .join(profile)
+            .join("rustle-binary"); // Assuming binary name
+
+        if !binary_path.exists() {
+            anyhow::bail!("Built binary not found at: {}", binary_path.display());
+        }
+
+        Ok(binary_path)
+    }
+
+    async fn get_toolchain_version(&self, image: &str) -> Result<String> {
+        let output = Command::new("rustc")
+            .arg("--version")
+            .output()
+            .await
+            .context("Failed to get rustc version")?;
+
+        if !output.status.success() {
+            anyhow::bail!("Failed to get rustc version");
+        }
+
+        let rustc = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok(format!("{rustc} in {image}"))
+    }
+}
+
+#[async_trait]
+impl CompilationBackend for ContainerBackend {
+    type Error = anyhow::Error;
+    type Config = serde_json::Value;
+
+    async fn compile_binary(
+        &self,
+        template: &GeneratedTemplate,
+        target: &TargetSpecification,
+        config: &Self::Config,
+    ) -> Result<CompiledBinary> {
+        let start_time = Instant::now();
+
+        info!(
+            "Starting container compilation for target: {}",
+            target.target_triple
+        );
+
+        let config: ContainerConfig = if config.is_null() {
+            ContainerConfig::default()
+        } else {
+            serde_json::from_value(config.clone()).context("Invalid container backend config")?
+        };
+
+        // Create temporary project directory
+        let temp_dir = tempfile::tempdir().context("Failed to create temporary directory")?;
+        let project_path = temp_dir.path();
+
+        // Write template files to project directory
+        template
+            .write_to_directory(project_path)
+            .await
+            .context("Failed to write template to directory")?;
+
+        let binary_path = self
+            .run_container_build(project_path, target, &config)
+            .await?;
+
+        // Read binary data
+        let binary_data = tokio::fs::read(&binary_path)
+            .await
+            .context("Failed to read compiled binary")?;
+
+        let size = binary_data.len() as u64;
+
+        // Calculate checksum
+        let checksum = {
+            use sha2::{Digest, Sha256};
+            let mut hasher = Sha256::new();
+            hasher.update(&binary_data);
+            format!("{:x}", hasher.finalize())
+        };
+
+        let compilation_time = start_time.elapsed();
+
+        let image = config
+            .images
+            .get(&target.target_triple)
+            .cloned()
+            .or_else(|| self.image_for(&target.target_triple))
+            .unwrap_or_default();
+        let toolchain_version = self
+            .get_toolchain_version(&image)
+            .await
+            .unwrap_or_else(|_| "unknown".to_string());
+
+        // Create source info
+        let source_info = BinarySourceInfo {
+            source_type: BinarySourceType::FreshCompilation {
+                project_path: project_path.to_path_buf(),
+            },
+            template_hash: template.calculate_hash(),
+            build_metadata: BuildMetadata {
+                created_at: chrono::Utc::now(),
+                toolchain_version,
+                features: target.compilation_options.custom_features.clone(),
+            },
+        };
+
+        let compiled_binary = CompiledBinary {
+            compilation_id: uuid::Uuid::new_v4().to_string(),
+            target_triple: target.target_triple.clone(),
+            binary_data,
+            checksum,
+            size,
+            compilation_time,
+            optimization_level: target.optimization_level.clone(),
+            source_info,
+        };
+
+        info!(
+            "Container compilation completed in {:?}, binary size: {} bytes",
+            compilation_time, size
+        );
+
+        Ok(compiled_binary)
+    }
+
+    fn supports_target(&self, target: &str) -> bool {
+        self.runtime.is_some() && self.image_for(target).is_some()
+    }
+
+    fn preference(&self, _target: &str) -> u8 {
+        // Only claims the targets the other backends do not build well
+        3
+    }
+
+    fn get_capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            supported_targets: self.images.keys().cloned().collect(),
+            supports_cross_compilation: true,
+            supports_static_linking: true,
+            supports_lto: true,
+            requires_toolchain: true,
+        }
+    }
+
+    fn backend_name(&self) -> &'static str {
+        "container"
+    }
+}
+
+/// The pinned cross-rs image of `target`, with a variant such as `centos`
+fn cross_image(target: &str, variant: Option<&str>) -> String {
+    match variant {
+        Some(variant) => format!("ghcr.io/cross-rs/{target}:{CROSS_IMAGE_VERSION}-{variant}"),
+        None => format!("ghcr.io/cross-rs/{target}:{CROSS_IMAGE_VERSION}"),
+    }
+}
+
+/// `target` without its glibc version suffix, `.2.N`, and the minor
+/// version `N`
+fn split_glibc(target: &str) -> (&str, Option<u32>) {
+    match target.split_once(".2.") {
+        Some((triple, minor)) if triple.contains("-gnu") => match minor.parse() {
+            Ok(minor) => (triple, Some(minor)),
+            Err(_) => (target, None),
+        },
+        _ => (target, None),
+    }
+}
+
+/// The sysroot of the host's Rust toolchain, mounted into the container
+async fn host_sysroot() -> Result<PathBuf> {
+    let output = Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .output()
+        .await
+        .context("Failed to run rustc to find its sysroot")?;
+    if !output.status.success() {
+        warn!("rustc --print sysroot failed");
+        anyhow::bail!("Failed to find the Rust sysroot");
+    }
+    Ok(PathBuf::from(
+        String::from_utf8_lossy(&output.stdout).trim().to_string(),
+    ))
+}
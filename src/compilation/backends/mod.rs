@@ -1,7 +1,13 @@
 pub mod cargo;
+pub mod container;
+pub mod prebuilt;
+pub mod remote;
 pub mod traits;
 pub mod zigbuild;
 
+pub use container::{ContainerBackend, ContainerConfig};
+pub use prebuilt::{PrebuiltBackend, PREBUILT_TARGETS};
+pub use remote::{BuildFarm, RemoteBackend, RemoteBuilder, RemoteConfig, RemoteTransport};
 pub use traits::{BackendCapabilities, CompilationBackend};
 
 use anyhow::Result;
@@ -35,11 +41,18 @@ impl BackendRegistry {
         self.backends.get(name).cloned()
     }
 
+    /// The backend supporting `target` that prefers it most, by name among
+    /// equals
     pub fn select_backend_for_target(&self, target: &str) -> Option<BackendRef> {
         self.backends
-            .values()
-            .find(|backend| backend.supports_target(target))
-            .cloned()
+            .iter()
+            .filter(|(_, backend)| backend.supports_target(target))
+            .max_by(|(a_name, a), (b_name, b)| {
+                a.preference(target)
+                    .cmp(&b.preference(target))
+                    .then_with(|| b_name.cmp(a_name))
+            })
+            .map(|(_, backend)| backend.clone())
     }
 
     pub fn list_backends(&self) -> Vec<String> {
@@ -52,6 +65,13 @@ impl BackendRegistry {
         // Register default backends
         registry.register(cargo::CargoBackend::new())?;
         registry.register(zigbuild::ZigBuildBackend::new())?;
+        registry.register(container::ContainerBackend::new())?;
+        if let Some(remote) = remote::RemoteBackend::from_env()? {
+            registry.register(remote)?;
+        }
+        if let Some(prebuilt) = prebuilt::PrebuiltBackend::from_env()? {
+            registry.register(prebuilt)?;
+        }
 
         Ok(registry)
     }
@@ -0,0 +1,292 @@
+/// Precompiled runner download backend
+///
+/// For machines without a Rust toolchain: rather than compiling the
+/// generated project, a generic runner built for the target is downloaded
+/// from a release URL and the execution data is appended to it as an
+/// archive of its sections (see [`crate::template::appended`]). Runners are
+/// published as
+///
+/// ```text
+/// <release_url>/<version>/rustle-runner-<target>[.exe|.wasm]
+/// <release_url>/<version>/rustle-runner-<target>[.exe|.wasm].minisig
+/// ```
+///
+/// and every runner must carry a minisign signature by the trusted key, so
+/// a compromised mirror cannot hand out a runner of its own. Verified
+/// runners are kept in a local cache and checked again before each use.
+/// The default registry has the backend when `RUSTLE_RUNNER_RELEASES`
+/// names the release URL and `RUSTLE_RUNNER_RELEASE_KEY` the public key,
+/// as a file or its contents.
+use super::remote::is_executable_for;
+use super::traits::{BackendCapabilities, CompilationBackend};
+use crate::runtime::signing::{signature_path, BinarySignature, TrustedKey, SIGNATURE_SUFFIX};
+use crate::template::{append_archive, pack_archive, GeneratedTemplate};
+use crate::types::compilation::{
+    BinarySourceInfo, BinarySourceType, BuildMetadata, CompiledBinary, TargetSpecification,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tracing::{debug, info, warn};
+
+/// Names the release URL of the default registry's prebuilt backend
+pub const RUNNER_RELEASES_ENV: &str = "RUSTLE_RUNNER_RELEASES";
+/// Names the key prebuilt runners must be signed with
+pub const RUNNER_RELEASE_KEY_ENV: &str = "RUSTLE_RUNNER_RELEASE_KEY";
+
+/// Targets generic runners are published for
+pub const PREBUILT_TARGETS: &[&str] = &[
+    "x86_64-unknown-linux-musl",
+    "aarch64-unknown-linux-musl",
+    "x86_64-apple-darwin",
+    "aarch64-apple-darwin",
+    "x86_64-pc-windows-msvc",
+    "wasm32-wasip1",
+];
+
+pub struct PrebuiltBackend {
+    release_url: String,
+    trusted_key: TrustedKey,
+    version: String,
+    targets: Vec<String>,
+    cache_dir: Option<PathBuf>,
+    client: reqwest::Client,
+}
+
+impl PrebuiltBackend {
+    /// Download runners of this version of rustle-deploy from `release_url`
+    pub fn new(release_url: impl Into<String>, trusted_key: TrustedKey) -> Self {
+        Self {
+            release_url: release_url.into(),
+            trusted_key,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            targets: PREBUILT_TARGETS.iter().map(|t| t.to_string()).collect(),
+            cache_dir: dirs::cache_dir().map(|dir| dir.join("rustle").join("runners")),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// The backend of `RUSTLE_RUNNER_RELEASES`, if set
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(release_url) = std::env::var(RUNNER_RELEASES_ENV) else {
+            return Ok(None);
+        };
+        let key = std::env::var(RUNNER_RELEASE_KEY_ENV).with_context(|| {
+            format!("{RUNNER_RELEASES_ENV} is set but {RUNNER_RELEASE_KEY_ENV} is not")
+        })?;
+        let key = match std::fs::read_to_string(&key) {
+            Ok(contents) => contents,
+            Err(_) => key,
+        };
+        let trusted_key = TrustedKey::parse(&key).context("Invalid prebuilt runner release key")?;
+        Ok(Some(Self::new(release_url, trusted_key)))
+    }
+
+    /// Download runners of `version` instead of this one's
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = version.into();
+        self
+    }
+
+    /// Targets runners are published for
+    pub fn with_targets(mut self, targets: Vec<String>) -> Self {
+        self.targets = targets;
+        self
+    }
+
+    /// Keep downloaded runners in `dir`, or nowhere
+    pub fn with_cache_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.cache_dir = dir;
+        self
+    }
+
+    /// Where the runner for `target` is published
+    pub fn runner_url(&self, target: &str) -> String {
+        format!(
+            "{}/{}/{}",
+            self.release_url.trim_end_matches('/'),
+            self.version,
+            runner_file_name(target)
+        )
+    }
+
+    /// The verified generic runner for `target`, from the cache or downloaded
+    pub async fn fetch_runner(&self, target: &str) -> Result<Vec<u8>> {
+        let cached = self
+            .cache_dir
+            .as_ref()
+            .map(|dir| dir.join(&self.version).join(runner_file_name(target)));
+        if let Some(path) = &cached {
+            match self.load_cached(path, target) {
+                Ok(Some(runner)) => {
+                    debug!("Using cached runner {}", path.display());
+                    return Ok(runner);
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Ignoring cached runner {}: {:#}", path.display(), e),
+            }
+        }
+
+        let url = self.runner_url(target);
+        info!("Downloading prebuilt runner {}", url);
+        let runner = self.download(&url).await?;
+        let signature = self.download(&format!("{url}{SIGNATURE_SUFFIX}")).await?;
+        let signature = String::from_utf8(signature).context("Runner signature is not text")?;
+        self.verify(&runner, &signature, target)
+            .with_context(|| format!("Rejected the runner at {url}"))?;
+
+        if let Some(path) = &cached {
+            if let Err(e) = store(path, &runner, &signature) {
+                warn!("Failed to cache runner at {}: {:#}", path.display(), e);
+            }
+        }
+        Ok(runner)
+    }
+
+    /// The runner cached at `path`, verified again, if there is one
+    fn load_cached(&self, path: &Path, target: &str) -> Result<Option<Vec<u8>>> {
+        let runner = match std::fs::read(path) {
+            Ok(runner) => runner,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let signature = std::fs::read_to_string(signature_path(path))?;
+        self.verify(&runner, &signature, target)?;
+        Ok(Some(runner))
+    }
+
+    /// Check that `runner` is signed by the trusted key and runs on `target`
+    pub fn verify(&self, runner: &[u8], signature: &str, target: &str) -> Result<()> {
+        self.trusted_key
+            .verify(runner, &BinarySignature::parse(signature)?)?;
+        if !is_executable_for(runner, target) {
+            anyhow::bail!("Runner is not an executable for {target}");
+        }
+        Ok(())
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to download {url}"))?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("Failed to download {url}: {status}");
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+#[async_trait]
+impl CompilationBackend for PrebuiltBackend {
+    type Error = anyhow::Error;
+    type Config = serde_json::Value;
+
+    async fn compile_binary(
+        &self,
+        template: &GeneratedTemplate,
+        target: &TargetSpecification,
+        _config: &Self::Config,
+    ) -> Result<CompiledBinary> {
+        let start_time = Instant::now();
+
+        info!(
+            "Packaging a prebuilt runner for target: {}",
+            target.target_triple
+        );
+
+        let runner = self.fetch_runner(&target.target_triple).await?;
+        let archive =
+            pack_archive(&template.sections).context("Failed to pack the execution data")?;
+        let binary_data = append_archive(&runner, &archive, None);
+
+        let size = binary_data.len() as u64;
+        let compilation_time = start_time.elapsed();
+
+        let source_info = BinarySourceInfo {
+            source_type: BinarySourceType::Appended {
+                runner_checksum: format!("{:x}", Sha256::digest(&runner)),
+            },
+            template_hash: template.calculate_hash(),
+            build_metadata: BuildMetadata {
+                created_at: chrono::Utc::now(),
+                toolchain_version: format!("prebuilt runner {}", self.version),
+                features: target.compilation_options.custom_features.clone(),
+            },
+        };
+
+        let compiled_binary = CompiledBinary {
+            compilation_id: uuid::Uuid::new_v4().to_string(),
+            target_triple: target.target_triple.clone(),
+            checksum: format!("{:x}", Sha256::digest(&binary_data)),
+            binary_data,
+            size,
+            compilation_time,
+            optimization_level: target.optimization_level.clone(),
+            source_info,
+        };
+
+        info!(
+            "Prebuilt runner packaged in {:?}: {} byte runner, {} bytes of data",
+            compilation_time,
+            runner.len(),
+            archive.len()
+        );
+
+        Ok(compiled_binary)
+    }
+
+    fn supports_target(&self, target: &str) -> bool {
+        self.targets.iter().any(|t| t == target)
+    }
+
+    fn preference(&self, _target: &str) -> u8 {
+        // A last resort while cargo is around, which builds the runner the
+        // plan needs; the only way to get a runner without it
+        if which::which("cargo").is_ok() {
+            0
+        } else {
+            5
+        }
+    }
+
+    fn get_capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            supported_targets: self.targets.clone(),
+            supports_cross_compilation: true,
+            supports_static_linking: true,
+            supports_lto: false,
+            requires_toolchain: false,
+        }
+    }
+
+    fn backend_name(&self) -> &'static str {
+        "prebuilt"
+    }
+}
+
+/// The file name runners for `target` are published under
+pub fn runner_file_name(target: &str) -> String {
+    let extension = if target.contains("windows") {
+        ".exe"
+    } else if target.starts_with("wasm") {
+        ".wasm"
+    } else {
+        ""
+    };
+    format!("rustle-runner-{target}{extension}")
+}
+
+fn store(path: &Path, runner: &[u8], signature: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, runner)?;
+    std::fs::write(signature_path(path), signature)?;
+    Ok(())
+}
@@ -0,0 +1,544 @@
+/// Remote build-farm compilation backend
+///
+/// Ships the generated project to a build host and brings the binary back,
+/// so that a laptop need not cross-compile every target itself. Builders
+/// are listed in a YAML file, named by `RUSTLE_BUILD_FARM` for the default
+/// registry:
+///
+/// ```yaml
+/// builders:
+///   - name: arm
+///     ssh: builder@arm-box
+///     work_dir: /var/tmp/rustle-builds
+///     targets: [aarch64-unknown-linux-gnu]
+///     max_jobs: 2
+///   - name: farm
+///     url: https://build.example.com
+///     token: s3cr3t
+/// ```
+///
+/// An SSH builder gets the project as a tarball over SFTP, runs `cargo
+/// build` in it and reports the binary's SHA-256, and the binary is read
+/// back over SFTP. An HTTP builder gets the tarball POSTed to
+/// `<url>/v1/builds?target=<triple>&profile=<profile>` and answers with the
+/// binary, its SHA-256 in the `X-Checksum-Sha256` header.
+///
+/// Each builder runs at most `max_jobs` builds at once. A binary whose
+/// checksum differs from the reported one, or which is not an executable
+/// for the target, is rejected, and the build is tried on the next builder.
+use super::traits::{BackendCapabilities, CompilationBackend};
+use crate::deploy::ssh::shell_quote;
+use crate::deploy::{resolve_connection, SshConfig, SshConnection, SshConnectionConfig};
+use crate::template::GeneratedTemplate;
+use crate::types::compilation::{
+    BinarySourceInfo, BinarySourceType, BuildMetadata, CompiledBinary, OptimizationLevel,
+    TargetSpecification,
+};
+use crate::types::deployment::HostConnectionVars;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tracing::{debug, info, warn};
+
+/// Names the builders file of the default registry's remote backend
+pub const BUILD_FARM_ENV: &str = "RUSTLE_BUILD_FARM";
+
+const DEFAULT_WORK_DIR: &str = "/tmp/rustle-builds";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const CHECKSUM_HEADER: &str = "X-Checksum-Sha256";
+
+/// A build host, as listed in the builders file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteBuilder {
+    pub name: String,
+    #[serde(flatten)]
+    pub transport: RemoteTransport,
+    /// Targets the builder is meant for; any target when empty
+    #[serde(default)]
+    pub targets: Vec<String>,
+    #[serde(default = "default_max_jobs")]
+    pub max_jobs: usize,
+}
+
+/// How a build host is reached
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RemoteTransport {
+    /// A host with cargo, logged into as `[user@]host[:port]` and its
+    /// `~/.ssh/config`
+    Ssh {
+        ssh: String,
+        work_dir: Option<String>,
+    },
+    /// A build service answering the build endpoint
+    Http { url: String, token: Option<String> },
+}
+
+/// The builders file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BuildFarm {
+    pub builders: Vec<RemoteBuilder>,
+}
+
+/// Options of a remote build, given as the backend's configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteConfig {
+    /// Only build on this builder
+    pub builder: Option<String>,
+    pub verbose: bool,
+}
+
+pub struct RemoteBackend {
+    builders: Vec<(RemoteBuilder, Arc<Semaphore>)>,
+    client: reqwest::Client,
+    timeout: Duration,
+}
+
+/// A binary as a builder returned it
+struct RemoteArtifact {
+    data: Vec<u8>,
+    checksum: String,
+    toolchain_version: String,
+}
+
+fn default_max_jobs() -> usize {
+    1
+}
+
+impl BuildFarm {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read builders file {}", path.display()))?;
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("Invalid builders file {}", path.display()))
+    }
+}
+
+impl RemoteBackend {
+    pub fn new(farm: BuildFarm) -> Self {
+        let builders = farm
+            .builders
+            .into_iter()
+            .map(|builder| {
+                let permits = Arc::new(Semaphore::new(builder.max_jobs.max(1)));
+                (builder, permits)
+            })
+            .collect();
+        Self {
+            builders,
+            client: reqwest::Client::new(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// The backend of the builders file named by `RUSTLE_BUILD_FARM`, if set
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var_os(BUILD_FARM_ENV) {
+            Some(path) => Ok(Some(Self::new(BuildFarm::load(Path::new(&path))?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Give up on a build after `timeout`, shipping the project included
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The builders that can build `target`, those with free jobs first
+    fn builders_for(&self, target: &str) -> Vec<&(RemoteBuilder, Arc<Semaphore>)> {
+        let mut builders: Vec<_> = self
+            .builders
+            .iter()
+            .filter(|(builder, _)| {
+                builder.targets.is_empty() || builder.targets.iter().any(|t| t == target)
+            })
+            .collect();
+        builders.sort_by_key(|(_, permits)| std::cmp::Reverse(permits.available_permits()));
+        builders
+    }
+
+    async fn build_on(
+        &self,
+        builder: &RemoteBuilder,
+        tarball: &[u8],
+        target: &TargetSpecification,
+        config: &RemoteConfig,
+    ) -> Result<RemoteArtifact> {
+        let build = async {
+            match &builder.transport {
+                RemoteTransport::Ssh { ssh, work_dir } => {
+                    let work_dir = work_dir.as_deref().unwrap_or(DEFAULT_WORK_DIR);
+                    self.build_over_ssh(ssh, work_dir, tarball, target, config)
+                        .await
+                }
+                RemoteTransport::Http { url, token } => {
+                    self.build_over_http(url, token.as_deref(), tarball, target)
+                        .await
+                }
+            }
+        };
+        let artifact = tokio::time::timeout(self.timeout, build)
+            .await
+            .map_err(|_| anyhow::anyhow!("No binary within {:?}", self.timeout))??;
+        verify_artifact(&artifact, &target.target_triple)?;
+        Ok(artifact)
+    }
+
+    async fn build_over_ssh(
+        &self,
+        host: &str,
+        work_dir: &str,
+        tarball: &[u8],
+        target: &TargetSpecification,
+        config: &RemoteConfig,
+    ) -> Result<RemoteArtifact> {
+        let resolved = resolve_connection(
+            host,
+            &HostConnectionVars::default(),
+            &SshConfig::load_default(),
+            &SshConnectionConfig::default(),
+        );
+        let connection = SshConnection::connect(&resolved.host_spec, &resolved.config)
+            .await
+            .with_context(|| format!("Failed to connect to builder {host}"))?;
+
+        let build_dir = format!("{work_dir}/{}", uuid::Uuid::new_v4());
+        let archive = format!("{build_dir}.tar.gz");
+        let setup = connection
+            .execute_command(&format!("mkdir -p {}", shell_quote(&build_dir)))
+            .await?;
+        if !setup.success {
+            anyhow::bail!("Failed to create {build_dir} on {host}: {}", setup.stderr);
+        }
+        connection.upload_bytes(tarball, &archive, 0o600).await?;
+
+        let triple = &target.target_triple;
+        let profile = profile(&target.optimization_level);
+        let binary = format!("{build_dir}/target/{triple}/{profile}/rustle-binary");
+        let mut cargo = format!("cargo build --target {}", shell_quote(triple));
+        if profile == "release" {
+            cargo.push_str(" --release");
+        }
+        if config.verbose {
+            cargo.push_str(" --verbose");
+        }
+        let rustflags = rustflags(target);
+        let command = format!(
+            "tar -xzf {archive} -C {dir} && cd {dir} && RUSTFLAGS={flags} {cargo} >&2 && \
+             sha256sum {binary} && rustc --version",
+            archive = shell_quote(&archive),
+            dir = shell_quote(&build_dir),
+            flags = shell_quote(&rustflags),
+            binary = shell_quote(&binary),
+        );
+        debug!("Building on {}: {}", host, command);
+        let built = connection.execute_command(&command).await;
+
+        let data = match &built {
+            Ok(result) if result.success => connection.download_bytes(&binary).await,
+            _ => Ok(Vec::new()),
+        };
+        let cleanup = format!(
+            "rm -rf {} {}",
+            shell_quote(&build_dir),
+            shell_quote(&archive)
+        );
+        if let Err(e) = connection.execute_command(&cleanup).await {
+            warn!("Failed to clean up {} on {}: {}", build_dir, host, e);
+        }
+
+        let built = built?;
+        if !built.success {
+            anyhow::bail!("Build on {host} failed: {}", built.stderr);
+        }
+        let mut lines = built.stdout.lines();
+        let checksum = lines
+            .next()
+            .and_then(|line| line.split_whitespace().next())
+            .with_context(|| format!("Builder {host} did not report a checksum"))?
+            .to_string();
+        let toolchain_version = lines.next().unwrap_or("unknown").trim().to_string();
+        Ok(RemoteArtifact {
+            data: data?,
+            checksum,
+            toolchain_version: format!("{toolchain_version} on {host}"),
+        })
+    }
+
+    async fn build_over_http(
+        &self,
+        url: &str,
+        token: Option<&str>,
+        tarball: &[u8],
+        target: &TargetSpecification,
+    ) -> Result<RemoteArtifact> {
+        let endpoint = format!("{}/v1/builds", url.trim_end_matches('/'));
+        let mut request = self
+            .client
+            .post(&endpoint)
+            .query(&[
+                ("target", target.target_triple.as_str()),
+                ("profile", profile(&target.optimization_level)),
+                ("rustflags", rustflags(target).as_str()),
+            ])
+            .header(reqwest::header::CONTENT_TYPE, "application/gzip")
+            .body(tarball.to_vec());
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to submit the build to {endpoint}"))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Build at {endpoint} failed ({status}): {body}");
+        }
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let checksum = header(CHECKSUM_HEADER)
+            .with_context(|| format!("Builder {url} did not report a checksum"))?;
+        let toolchain_version = header("X-Toolchain-Version").unwrap_or_else(|| "unknown".into());
+        let data = response.bytes().await?.to_vec();
+        Ok(RemoteArtifact {
+            data,
+            checksum,
+            toolchain_version: format!("{toolchain_version} at {url}"),
+        })
+    }
+}
+
+#[async_trait]
+impl CompilationBackend for RemoteBackend {
+    type Error = anyhow::Error;
+    type Config = serde_json::Value;
+
+    async fn compile_binary(
+        &self,
+        template: &GeneratedTemplate,
+        target: &TargetSpecification,
+        config: &Self::Config,
+    ) -> Result<CompiledBinary> {
+        let start_time = Instant::now();
+
+        info!(
+            "Starting remote compilation for target: {}",
+            target.target_triple
+        );
+
+        let config: RemoteConfig = if config.is_null() {
+            RemoteConfig::default()
+        } else {
+            serde_json::from_value(config.clone()).context("Invalid remote backend config")?
+        };
+
+        // Pack the project to ship it
+        let temp_dir = tempfile::tempdir().context("Failed to create temporary directory")?;
+        template
+            .write_to_directory(temp_dir.path())
+            .await
+            .context("Failed to write template to directory")?;
+        let tarball = pack_project(temp_dir.path())?;
+
+        let builders: Vec<_> = self
+            .builders_for(&target.target_triple)
+            .into_iter()
+            .filter(|(builder, _)| {
+                config
+                    .builder
+                    .as_ref()
+                    .is_none_or(|name| *name == builder.name)
+            })
+            .collect();
+        if builders.is_empty() {
+            anyhow::bail!("No remote builder for {}", target.target_triple);
+        }
+
+        let mut failures = Vec::new();
+        let mut built = None;
+        for (builder, permits) in builders {
+            let _permit = permits.acquire().await?;
+            debug!("Building {} on {}", target.target_triple, builder.name);
+            match self.build_on(builder, &tarball, target, &config).await {
+                Ok(artifact) => {
+                    built = Some((builder.name.clone(), artifact));
+                    break;
+                }
+                Err(e) => {
+                    warn!("Build on {} failed: {:#}", builder.name, e);
+                    failures.push(format!("{}: {e:#}", builder.name));
+                }
+            }
+        }
+        let Some((builder, artifact)) = built else {
+            anyhow::bail!(
+                "Remote builds of {} failed: {}",
+                target.target_triple,
+                failures.join("; ")
+            );
+        };
+
+        let size = artifact.data.len() as u64;
+        let compilation_time = start_time.elapsed();
+
+        let source_info = BinarySourceInfo {
+            source_type: BinarySourceType::InMemory,
+            template_hash: template.calculate_hash(),
+            build_metadata: BuildMetadata {
+                created_at: chrono::Utc::now(),
+                toolchain_version: artifact.toolchain_version,
+                features: target.compilation_options.custom_features.clone(),
+            },
+        };
+
+        let compiled_binary = CompiledBinary {
+            compilation_id: uuid::Uuid::new_v4().to_string(),
+            target_triple: target.target_triple.clone(),
+            binary_data: artifact.data,
+            checksum: artifact.checksum,
+            size,
+            compilation_time,
+            optimization_level: target.optimization_level.clone(),
+            source_info,
+        };
+
+        info!(
+            "Remote compilation on {} completed in {:?}, binary size: {} bytes",
+            builder, compilation_time, size
+        );
+
+        Ok(compiled_binary)
+    }
+
+    fn supports_target(&self, target: &str) -> bool {
+        !self.builders_for(target).is_empty()
+    }
+
+    fn preference(&self, target: &str) -> u8 {
+        // Over any local cross build; a builder listing the target is
+        // meant for it, and otherwise a native build still wins a tie
+        let listed = self
+            .builders
+            .iter()
+            .any(|(builder, _)| builder.targets.iter().any(|t| t == target));
+        if listed {
+            4
+        } else {
+            2
+        }
+    }
+
+    fn get_capabilities(&self) -> BackendCapabilities {
+        let mut supported_targets: Vec<String> = self
+            .builders
+            .iter()
+            .flat_map(|(builder, _)| builder.targets.iter().cloned())
+            .collect();
+        supported_targets.sort();
+        supported_targets.dedup();
+        BackendCapabilities {
+            supported_targets,
+            supports_cross_compilation: true,
+            supports_static_linking: true,
+            supports_lto: true,
+            requires_toolchain: false,
+        }
+    }
+
+    fn backend_name(&self) -> &'static str {
+        "remote"
+    }
+}
+
+fn profile(level: &OptimizationLevel) -> &'static str {
+    match level {
+        OptimizationLevel::Debug => "debug",
+        _ => "release",
+    }
+}
+
+fn rustflags(target: &TargetSpecification) -> String {
+    let mut rustflags = Vec::new();
+    if matches!(
+        target.optimization_level,
+        OptimizationLevel::MinSize | OptimizationLevel::MinimalSize
+    ) {
+        rustflags.push("-C opt-level=z -C codegen-units=1");
+    }
+    if target.compilation_options.enable_lto {
+        rustflags.push("-C lto=fat");
+    }
+    if target.compilation_options.static_linking {
+        rustflags.push("-C target-feature=+crt-static");
+    }
+    rustflags.join(" ")
+}
+
+/// The project at `path` as a gzipped tarball, without its build output
+fn pack_project(path: &Path) -> Result<Vec<u8>> {
+    use flate2::{write::GzEncoder, Compression};
+
+    let encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    let mut archive = tar::Builder::new(encoder);
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == "target" {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            archive.append_dir_all(&name, entry.path())?;
+        } else {
+            archive.append_path_with_name(entry.path(), &name)?;
+        }
+    }
+    let tarball = archive.into_inner()?.finish()?;
+    Ok(tarball)
+}
+
+/// Reject a binary that was corrupted on the way or is not an executable
+/// of `target`
+fn verify_artifact(artifact: &RemoteArtifact, target: &str) -> Result<()> {
+    let checksum = format!("{:x}", Sha256::digest(&artifact.data));
+    if !checksum.eq_ignore_ascii_case(artifact.checksum.trim()) {
+        anyhow::bail!(
+            "Checksum mismatch: builder reported {}, received {}",
+            artifact.checksum,
+            checksum
+        );
+    }
+    if !is_executable_for(&artifact.data, target) {
+        anyhow::bail!("Received binary is not an executable for {target}");
+    }
+    Ok(())
+}
+
+/// Whether `binary` is an executable of `target`, by its magic number
+pub(super) fn is_executable_for(binary: &[u8], target: &str) -> bool {
+    let magic = binary.get(..4).unwrap_or_default();
+    if target.contains("windows") {
+        magic.starts_with(b"MZ")
+    } else if target.contains("apple") {
+        matches!(
+            magic,
+            [0xcf, 0xfa, 0xed, 0xfe] | [0xfe, 0xed, 0xfa, 0xcf] | [0xca, 0xfe, 0xba, 0xbe]
+        )
+    } else if target.starts_with("wasm") {
+        magic == b"\0asm"
+    } else {
+        magic == b"\x7fELF"
+    }
+}
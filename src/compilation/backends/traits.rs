@@ -16,6 +16,13 @@ pub trait CompilationBackend: Send + Sync {
     ) -> Result<CompiledBinary, Self::Error>;
 
     fn supports_target(&self, target: &str) -> bool;
+
+    /// How strongly the backend should be picked for `target` among those
+    /// supporting it; the highest wins
+    fn preference(&self, _target: &str) -> u8 {
+        0
+    }
+
     fn get_capabilities(&self) -> BackendCapabilities;
     fn backend_name(&self) -> &'static str;
 }
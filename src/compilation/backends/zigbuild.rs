@@ -1,5 +1,8 @@
 /// Zig-based cross-compilation backend
 use super::traits::{BackendCapabilities, CompilationBackend};
+use crate::compilation::cancellation::output_unless_cancelled;
+use crate::compilation::compiler::CompilationError;
+use crate::compilation::diagnostics::CompilationDiagnostics;
 use crate::template::GeneratedTemplate;
 use crate::types::compilation::{
     BinarySourceInfo, BinarySourceType, BuildMetadata, CompiledBinary, OptimizationLevel,
@@ -10,6 +13,7 @@ use async_trait::async_trait;
 use std::path::PathBuf;
 use std::time::Instant;
 use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, warn};
 
 #[derive(Debug, Clone)]
@@ -17,6 +21,7 @@ pub struct ZigBuildBackend {
     #[allow(dead_code)]
     cache_dir: PathBuf,
     zig_available: bool,
+    cancel: CancellationToken,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -40,9 +45,16 @@ impl ZigBuildBackend {
                 .join("rustle-deploy")
                 .join("zigbuild"),
             zig_available: false, // Will be checked during initialization
+            cancel: CancellationToken::new(),
         }
     }
 
+    /// Kill the builds in progress once `cancel` is cancelled
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
     pub async fn initialize(&mut self) -> Result<()> {
         // Check if cargo-zigbuild is available
         let output = Command::new("cargo")
@@ -93,6 +105,7 @@ impl ZigBuildBackend {
 
         // Set target triple
         cmd.args(["--target", &target.target_triple]);
+        cmd.arg("--message-format=json");
 
         // Target directory
         if let Some(target_dir) = &config.target_dir {
@@ -137,12 +150,22 @@ impl ZigBuildBackend {
 
         debug!("Running zigbuild command: {:?}", cmd);
 
-        let output = cmd
-            .output()
+        let output = output_unless_cancelled(&mut cmd, &self.cancel)
             .await
-            .context("Failed to execute cargo zigbuild")?;
+            .context("Failed to execute cargo zigbuild")?
+            .ok_or_else(|| CompilationError::Cancelled {
+                target: target.target_triple.clone(),
+            })?;
 
         if !output.status.success() {
+            let diagnostics = CompilationDiagnostics::from_cargo_output(
+                &target.target_triple,
+                &String::from_utf8_lossy(&output.stdout),
+                project_path,
+            );
+            if diagnostics.has_errors() {
+                return Err(diagnostics.into());
+            }
             let stderr = String::from_utf8_lossy(&output.stderr);
             anyhow::bail!("Cargo zigbuild failed: {}", stderr);
         }
@@ -186,6 +209,8 @@ impl ZigBuildBackend {
             "x86_64-unknown-linux-musl".to_string(),
             "aarch64-unknown-linux-gnu".to_string(),
             "aarch64-unknown-linux-musl".to_string(),
+            "armv7-unknown-linux-gnueabihf".to_string(),
+            "riscv64gc-unknown-linux-gnu".to_string(),
             "x86_64-pc-windows-gnu".to_string(),
             "x86_64-apple-darwin".to_string(),
             "aarch64-apple-darwin".to_string(),
@@ -289,6 +314,10 @@ impl CompilationBackend for ZigBuildBackend {
         Self::get_supported_targets().contains(&target.to_string())
     }
 
+    fn preference(&self, _target: &str) -> u8 {
+        1
+    }
+
     fn get_capabilities(&self) -> BackendCapabilities {
         BackendCapabilities {
             supported_targets: Self::get_supported_targets(),
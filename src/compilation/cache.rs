@@ -1,16 +1,63 @@
 use crate::compilation::compiler::CompiledBinary;
+use crate::compilation::estimate::{CompileSample, TemplateComplexity};
+use crate::compilation::store::CacheStore;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tracing::{debug, info, warn};
 
-/// Simplified compilation cache for storing and reusing compiled binaries
+/// Size the cache is kept under unless configured otherwise
+pub const DEFAULT_CACHE_MAX_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Content-addressed compilation cache for storing and reusing compiled
+/// binaries, across plans producing the same template. Least recently used
+/// binaries are evicted to keep it under its size limit.
 #[derive(Debug, Clone)]
 pub struct CompilationCache {
     cache_dir: PathBuf,
     enable_cache: bool,
+    max_size: Option<u64>,
     cache_index: CacheIndex,
+    /// Where binaries are shared beyond this machine
+    store: Option<Arc<dyn CacheStore>>,
+}
+
+/// What a binary is built from; binaries with equal keys are the same
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CacheKey {
+    /// Content hash of the generated template
+    pub template_hash: String,
+    pub target_triple: String,
+    pub toolchain_version: String,
+    /// Cargo features, sorted
+    pub features: Vec<String>,
+    /// Optimization level and codegen options
+    pub profile: String,
+}
+
+/// Figures of the cache, for `cache stats`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CompilationCacheStats {
+    pub entries: usize,
+    pub total_size_bytes: u64,
+    pub max_size_bytes: Option<u64>,
+    /// Times a cached binary was reused
+    pub hits: u64,
+    pub oldest_used: Option<SystemTime>,
+    pub newest_used: Option<SystemTime>,
+    /// Entries per target triple
+    pub targets: BTreeMap<String, usize>,
+}
+
+/// What a cleanup removed
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CacheCleanup {
+    pub removed: usize,
+    pub freed_bytes: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -21,13 +68,59 @@ struct CacheIndex {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct CacheEntry {
-    cache_key: String,
-    target_triple: String,
+    key: CacheKey,
     binary_path: PathBuf,
     size_bytes: u64,
     checksum: String,
-    created_at: std::time::SystemTime,
-    template_hash: String,
+    created_at: SystemTime,
+    last_used: SystemTime,
+    #[serde(default)]
+    hits: u64,
+    /// How long the binary took to compile, when it was compiled here
+    #[serde(default)]
+    compile_time: Option<Duration>,
+    /// Complexity of the template it was compiled from
+    #[serde(default)]
+    complexity: Option<TemplateComplexity>,
+}
+
+impl CacheKey {
+    pub fn new(
+        template_hash: &str,
+        target_triple: &str,
+        toolchain_version: &str,
+        features: &[String],
+        profile: &str,
+    ) -> Self {
+        let mut features = features.to_vec();
+        features.sort();
+        features.dedup();
+        Self {
+            template_hash: template_hash.to_string(),
+            target_triple: target_triple.to_string(),
+            toolchain_version: toolchain_version.to_string(),
+            features,
+            profile: profile.to_string(),
+        }
+    }
+
+    /// The address of the key's binary in the cache
+    pub fn digest(&self) -> String {
+        let mut hasher = Sha256::new();
+        for part in [
+            &self.template_hash,
+            &self.target_triple,
+            &self.toolchain_version,
+            &self.profile,
+        ]
+        .into_iter()
+        .chain(&self.features)
+        {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        format!("{:x}", hasher.finalize())
+    }
 }
 
 impl CompilationCache {
@@ -39,6 +132,7 @@ impl CompilationCache {
         let cache_index = if enable_cache {
             let index_path = cache_dir.join("index.json");
             if index_path.exists() {
+                // An index of an older layout is started over
                 std::fs::read_to_string(&index_path)
                     .ok()
                     .and_then(|content| serde_json::from_str(&content).ok())
@@ -53,86 +147,284 @@ impl CompilationCache {
         Self {
             cache_dir,
             enable_cache,
+            max_size: Some(DEFAULT_CACHE_MAX_SIZE),
             cache_index,
+            store: None,
         }
     }
 
-    pub fn get_binary(&self, template_hash: &str, target: &str) -> Option<CompiledBinary> {
+    /// Evict binaries to keep the cache under `max_size` bytes, or never
+    pub fn with_max_size(mut self, max_size: Option<u64>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Share binaries through `store`, publishing those built here and
+    /// fetching those built elsewhere
+    pub fn with_store(mut self, store: Arc<dyn CacheStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    pub fn get_binary(&mut self, key: &CacheKey) -> Option<CompiledBinary> {
         if !self.enable_cache {
             return None;
         }
 
-        let cache_key = format!("{template_hash}:{target}");
-
-        if let Some(entry) = self.cache_index.entries.get(&cache_key) {
-            if entry.binary_path.exists() {
-                debug!("Cache hit for {} {}", template_hash, target);
-
-                // Read binary data
-                if let Ok(binary_data) = std::fs::read(&entry.binary_path) {
-                    return Some(CompiledBinary {
-                        binary_id: uuid::Uuid::new_v4().to_string(),
-                        target_triple: entry.target_triple.clone(),
-                        binary_path: entry.binary_path.clone(),
-                        binary_data,
-                        effective_source: crate::compilation::compiler::BinarySource::Cache {
-                            cache_path: entry.binary_path.clone(),
-                        },
-                        size: entry.size_bytes,
-                        checksum: entry.checksum.clone(),
-                        compilation_time: std::time::Duration::from_secs(0), // Cached, so no compilation time
-                        optimization_level: crate::types::compilation::OptimizationLevel::Release, // Default for cached
-                        template_hash: entry.template_hash.clone(),
-                        created_at: chrono::DateTime::from(entry.created_at),
-                    });
-                }
-            } else {
+        let digest = key.digest();
+        let entry = match self.cache_index.entries.get_mut(&digest) {
+            Some(entry) => entry,
+            None => {
+                debug!("Cache miss for {} {}", key.template_hash, key.target_triple);
+                return None;
+            }
+        };
+
+        let binary_data = match std::fs::read(&entry.binary_path) {
+            Ok(data) if format!("{:x}", Sha256::digest(&data)) == entry.checksum => data,
+            Ok(_) => {
+                warn!("Cached binary corrupted: {}", entry.binary_path.display());
+                self.remove_entry(&digest);
+                self.save_cache_index_logged();
+                return None;
+            }
+            Err(_) => {
                 warn!("Cached binary missing: {}", entry.binary_path.display());
+                self.remove_entry(&digest);
+                self.save_cache_index_logged();
+                return None;
             }
-        }
+        };
 
-        debug!("Cache miss for {} {}", template_hash, target);
-        None
+        debug!("Cache hit for {} {}", key.template_hash, key.target_triple);
+        entry.last_used = SystemTime::now();
+        entry.hits += 1;
+        let binary = CompiledBinary {
+            binary_id: uuid::Uuid::new_v4().to_string(),
+            target_triple: entry.key.target_triple.clone(),
+            binary_path: entry.binary_path.clone(),
+            binary_data,
+            effective_source: crate::compilation::compiler::BinarySource::Cache {
+                cache_path: entry.binary_path.clone(),
+            },
+            size: entry.size_bytes,
+            checksum: entry.checksum.clone(),
+            compilation_time: Duration::from_secs(0), // Cached, so no compilation time
+            optimization_level: crate::types::compilation::OptimizationLevel::Release, // Default for cached
+            template_hash: entry.key.template_hash.clone(),
+            created_at: chrono::DateTime::from(entry.created_at),
+            size_report: None,
+        };
+        self.save_cache_index_logged();
+        Some(binary)
     }
 
-    pub async fn store_binary(&mut self, binary: &CompiledBinary) -> Result<()> {
+    /// Cache `binary`, and share it through the store if there is one
+    pub async fn store_binary(&mut self, key: &CacheKey, binary: &CompiledBinary) -> Result<()> {
+        self.store_build(key, binary, None).await
+    }
+
+    /// Cache `binary` as [`Self::store_binary`] does, recording its compile
+    /// time and the `complexity` of its template to predict later builds
+    pub async fn store_build(
+        &mut self,
+        key: &CacheKey,
+        binary: &CompiledBinary,
+        complexity: Option<TemplateComplexity>,
+    ) -> Result<()> {
         if !self.enable_cache {
             return Ok(());
         }
 
-        let cache_key = format!("{}:{}", binary.template_hash, binary.target_triple);
+        let compile_time = Some(binary.compilation_time).filter(|time| !time.is_zero());
+        self.store_local(
+            key,
+            &binary.binary_data,
+            &binary.checksum,
+            compile_time,
+            complexity,
+        )
+        .await?;
+
+        if let Some(store) = &self.store {
+            if let Err(e) = store
+                .put_artifact(&key.digest(), &binary.binary_data, &key.target_triple)
+                .await
+            {
+                warn!(
+                    "Failed to share binary for {} with {}: {:#}",
+                    key.target_triple,
+                    store.describe(),
+                    e
+                );
+            }
+        }
+
+        info!(
+            "Cached binary for {} ({} bytes)",
+            binary.target_triple, binary.size
+        );
+        Ok(())
+    }
+
+    /// The binary of `key` from the shared store, cached locally once its
+    /// checksum is verified
+    pub async fn fetch_shared(&mut self, key: &CacheKey) -> Option<CompiledBinary> {
+        if !self.enable_cache {
+            return None;
+        }
+        let store = self.store.clone()?;
+
+        let artifact = match store.fetch_artifact(&key.digest()).await {
+            Ok(Some(artifact)) => artifact,
+            Ok(None) => {
+                debug!(
+                    "{} has no binary for {}",
+                    store.describe(),
+                    key.target_triple
+                );
+                return None;
+            }
+            Err(e) => {
+                warn!("Failed to fetch from {}: {:#}", store.describe(), e);
+                return None;
+            }
+        };
+        info!(
+            "Fetched binary for {} from {}",
+            key.target_triple,
+            store.describe()
+        );
+        if let Err(e) = self
+            .store_local(key, &artifact.data, &artifact.metadata.checksum, None, None)
+            .await
+        {
+            warn!("Failed to cache the shared binary: {}", e);
+            return None;
+        }
+        self.get_binary(key)
+    }
+
+    async fn store_local(
+        &mut self,
+        key: &CacheKey,
+        data: &[u8],
+        checksum: &str,
+        compile_time: Option<Duration>,
+        complexity: Option<TemplateComplexity>,
+    ) -> Result<()> {
+        let digest = key.digest();
 
         // Create cache entry directory
-        let entry_dir = self.cache_dir.join(&cache_key);
+        let entry_dir = self.cache_dir.join(&digest);
         tokio::fs::create_dir_all(&entry_dir).await?;
 
-        // Copy binary to cache
+        // Write binary to cache; its project may already be cleaned up
         let cached_binary_path = entry_dir.join("binary");
-        tokio::fs::copy(&binary.binary_path, &cached_binary_path).await?;
+        tokio::fs::write(&cached_binary_path, data).await?;
 
         // Create cache entry
+        let now = SystemTime::now();
+        let size = data.len() as u64;
         let entry = CacheEntry {
-            cache_key: cache_key.clone(),
-            target_triple: binary.target_triple.clone(),
+            key: key.clone(),
             binary_path: cached_binary_path,
-            size_bytes: binary.size,
-            checksum: binary.checksum.clone(),
-            created_at: std::time::SystemTime::now(),
-            template_hash: binary.template_hash.clone(),
+            size_bytes: size,
+            checksum: checksum.to_string(),
+            created_at: now,
+            last_used: now,
+            hits: 0,
+            compile_time,
+            complexity,
         };
 
-        // Update cache index
-        self.cache_index.entries.insert(cache_key, entry);
-        self.cache_index.total_size_bytes += binary.size;
+        // Update cache index, replacing an entry rebuilt in place
+        if let Some(replaced) = self.cache_index.entries.insert(digest.clone(), entry) {
+            self.cache_index.total_size_bytes = self
+                .cache_index
+                .total_size_bytes
+                .saturating_sub(replaced.size_bytes);
+        }
+        self.cache_index.total_size_bytes += size;
+
+        if let Some(max_size) = self.max_size {
+            self.evict_to(max_size, Some(&digest));
+        }
 
         // Save cache index
-        self.save_cache_index().await?;
+        self.save_cache_index()
+    }
 
-        info!(
-            "Cached binary for {} ({} bytes)",
-            binary.target_triple, binary.size
-        );
-        Ok(())
+    pub fn stats(&self) -> CompilationCacheStats {
+        let entries = self.cache_index.entries.values();
+        let mut targets = BTreeMap::new();
+        for entry in entries.clone() {
+            *targets.entry(entry.key.target_triple.clone()).or_insert(0) += 1;
+        }
+        CompilationCacheStats {
+            entries: self.cache_index.entries.len(),
+            total_size_bytes: self.cache_index.total_size_bytes,
+            max_size_bytes: self.max_size,
+            hits: entries.clone().map(|entry| entry.hits).sum(),
+            oldest_used: entries.clone().map(|entry| entry.last_used).min(),
+            newest_used: entries.map(|entry| entry.last_used).max(),
+            targets,
+        }
+    }
+
+    /// The builds recorded with their compile time and complexity, oldest
+    /// first
+    pub fn compile_history(&self) -> Vec<CompileSample> {
+        let mut history: Vec<CompileSample> = self
+            .cache_index
+            .entries
+            .values()
+            .filter_map(|entry| {
+                Some(CompileSample {
+                    target_triple: entry.key.target_triple.clone(),
+                    compile_time: entry.compile_time?,
+                    complexity: entry.complexity?,
+                    built_at: entry.created_at,
+                })
+            })
+            .collect();
+        history.sort_by_key(|sample| sample.built_at);
+        history
+    }
+
+    /// Remove the binaries not used for `older_than`, or all of them
+    pub fn clean(&mut self, older_than: Option<Duration>) -> Result<CacheCleanup> {
+        let now = SystemTime::now();
+        let stale: Vec<String> = self
+            .cache_index
+            .entries
+            .iter()
+            .filter(|(_, entry)| match older_than {
+                Some(age) => now
+                    .duration_since(entry.last_used)
+                    .is_ok_and(|unused| unused >= age),
+                None => true,
+            })
+            .map(|(digest, _)| digest.clone())
+            .collect();
+
+        let mut cleanup = CacheCleanup::default();
+        for digest in stale {
+            if let Some(entry) = self.remove_entry(&digest) {
+                cleanup.removed += 1;
+                cleanup.freed_bytes += entry.size_bytes;
+            }
+        }
+        self.save_cache_index()?;
+        Ok(cleanup)
+    }
+
+    /// Evict the least recently used binaries until the cache holds at most
+    /// `max_size` bytes
+    pub fn shrink_to(&mut self, max_size: u64) -> Result<CacheCleanup> {
+        let cleanup = self.evict_to(max_size, None);
+        self.save_cache_index()?;
+        Ok(cleanup)
     }
 
     pub async fn clear_cache(&mut self) -> Result<()> {
@@ -141,34 +433,67 @@ impl CompilationCache {
         }
 
         info!("Clearing compilation cache");
+        self.clean(None)?;
+        Ok(())
+    }
+
+    pub fn get_cache_path(&self, key: &CacheKey) -> PathBuf {
+        self.cache_dir.join(key.digest()).join("binary")
+    }
 
-        // Remove all cached files
-        for entry in self.cache_index.entries.values() {
-            if let Some(parent) = entry.binary_path.parent() {
-                let _ = tokio::fs::remove_dir_all(parent).await;
+    fn evict_to(&mut self, max_size: u64, keep: Option<&str>) -> CacheCleanup {
+        let mut by_use: Vec<(SystemTime, String)> = self
+            .cache_index
+            .entries
+            .iter()
+            .filter(|(digest, _)| Some(digest.as_str()) != keep)
+            .map(|(digest, entry)| (entry.last_used, digest.clone()))
+            .collect();
+        by_use.sort();
+
+        let mut cleanup = CacheCleanup::default();
+        for (_, digest) in by_use {
+            if self.cache_index.total_size_bytes <= max_size {
+                break;
+            }
+            if let Some(entry) = self.remove_entry(&digest) {
+                debug!(
+                    "Evicted cached binary for {} ({} bytes)",
+                    entry.key.target_triple, entry.size_bytes
+                );
+                cleanup.removed += 1;
+                cleanup.freed_bytes += entry.size_bytes;
             }
         }
+        cleanup
+    }
 
-        // Reset cache index
-        self.cache_index = CacheIndex::default();
-        self.save_cache_index().await?;
-
-        Ok(())
+    fn remove_entry(&mut self, digest: &str) -> Option<CacheEntry> {
+        let entry = self.cache_index.entries.remove(digest)?;
+        self.cache_index.total_size_bytes = self
+            .cache_index
+            .total_size_bytes
+            .saturating_sub(entry.size_bytes);
+        if let Some(parent) = entry.binary_path.parent() {
+            let _ = std::fs::remove_dir_all(parent);
+        }
+        Some(entry)
     }
 
-    pub fn get_cache_path(&self, template_hash: &str, target: &str) -> PathBuf {
-        let cache_key = format!("{template_hash}:{target}");
-        self.cache_dir.join(&cache_key).join("binary")
+    fn save_cache_index_logged(&self) {
+        if let Err(e) = self.save_cache_index() {
+            warn!("Failed to save the compilation cache index: {}", e);
+        }
     }
 
-    async fn save_cache_index(&self) -> Result<()> {
+    fn save_cache_index(&self) -> Result<()> {
         if !self.enable_cache {
             return Ok(());
         }
 
         let index_path = self.cache_dir.join("index.json");
         let index_json = serde_json::to_string_pretty(&self.cache_index)?;
-        tokio::fs::write(&index_path, index_json).await?;
+        std::fs::write(&index_path, index_json)?;
         Ok(())
     }
 }
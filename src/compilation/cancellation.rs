@@ -0,0 +1,37 @@
+//! Cancellation of builds
+//!
+//! Builds run cargo, which runs a compiler per crate. A cancelled build
+//! kills the whole process tree rather than only cargo, so that no compiler
+//! keeps running, or holding the lock of the target directory, after the
+//! build was abandoned.
+
+use crate::modules::core::process::spawn_tree;
+use std::process::{Output, Stdio};
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+/// Run `command` to completion, collecting its output, unless `cancel` is
+/// cancelled first; the command and everything it started are then killed
+/// and `None` is returned
+pub async fn output_unless_cancelled(
+    command: &mut Command,
+    cancel: &CancellationToken,
+) -> std::io::Result<Option<Output>> {
+    if cancel.is_cancelled() {
+        return Ok(None);
+    }
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let (child, tree) = spawn_tree(command)?;
+    tokio::select! {
+        biased;
+        // Dropping the tree kills it
+        _ = cancel.cancelled() => Ok(None),
+        output = child.wait_with_output() => {
+            tree.disarm();
+            output.map(Some)
+        }
+    }
+}
@@ -1,16 +1,32 @@
-use crate::template::GeneratedTemplate;
-use crate::types::compilation::{OptimizationLevel, TargetSpecification};
+use crate::runtime::signing::BinarySigner;
+use crate::template::{package_appended, DataLayout, GeneratedTemplate};
+use crate::types::compilation::{OptimizationLevel, RunnerProfile, TargetSpecification};
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sha2::Digest;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use thiserror::Error;
+use tokio_util::sync::CancellationToken;
 use tracing::warn;
 use uuid::Uuid;
 
-use super::cache::CompilationCache;
+use super::cache::{CacheKey, CompilationCache, DEFAULT_CACHE_MAX_SIZE};
+use super::cancellation::output_unless_cancelled;
+use super::diagnostics::CompilationDiagnostics;
+use super::estimate::TemplateComplexity;
+use super::incremental::IncrementalWorkspace;
+use super::optimizer::{SizeOptimizer, SizeProfile, SizeReport};
+use super::profile::{is_wasi_target, MINIMAL_PROFILE_SIZE_TARGET};
+use super::reproducible::ReproducibleBuild;
+use super::store::CacheStore;
+use super::toolchain::{
+    rust_toolchain_toml, verify_zig_toolchain, RustupTargets, TargetInstallPolicy,
+};
+use crate::deploy::CompilerVersions;
 
 #[derive(Error, Debug)]
 pub enum CompilationError {
@@ -26,15 +42,34 @@ pub enum CompilationError {
     #[error("Zigbuild compilation failed for target {target}: {stderr}")]
     ZigbuildCompilationFailed { target: String, stderr: String },
 
+    #[error("Compilation failed for target {target}: {diagnostics}")]
+    Diagnostics {
+        target: String,
+        diagnostics: CompilationDiagnostics,
+    },
+
+    #[error("Build of {target} is not reproducible: built {first}, then {second}")]
+    NotReproducible {
+        target: String,
+        first: String,
+        second: String,
+    },
+
     #[error("Binary not found after compilation: {expected_path}")]
     BinaryNotFound { expected_path: String },
 
+    #[error("Compilation for target {target} was cancelled")]
+    Cancelled { target: String },
+
     #[error("Compilation timeout exceeded: {timeout_secs}s")]
     CompilationTimeout { timeout_secs: u64 },
 
     #[error("Unsupported target architecture: {target}")]
     UnsupportedTarget { target: String },
 
+    #[error("Toolchain not ready for {target}: {reason}")]
+    ToolchainNotReady { target: String, reason: String },
+
     #[error("Cache corruption detected: {cache_path}")]
     CacheCorruption { cache_path: String },
 
@@ -78,6 +113,7 @@ pub struct BinaryCompiler {
     cache: CompilationCache,
     project_manager: ProjectManager,
     process_executor: ProcessExecutor,
+    incremental: Option<IncrementalWorkspace>,
 }
 
 #[derive(Debug, Clone)]
@@ -87,9 +123,30 @@ pub struct CompilerConfig {
     pub compilation_timeout: Duration,
     pub max_parallel_compilations: usize,
     pub enable_cache: bool,
+    /// Evict least recently used binaries above this many cached bytes
+    pub cache_max_size: Option<u64>,
+    /// Share cached binaries through this store
+    pub cache_store: Option<Arc<dyn CacheStore>>,
     pub default_optimization: OptimizationLevel,
     pub zigbuild_fallback: bool,
     pub binary_size_limit: Option<u64>,
+    pub default_runner_profile: RunnerProfile,
+    /// Per-target-triple overrides of `default_runner_profile`
+    pub runner_profiles: HashMap<String, RunnerProfile>,
+    /// Stripping and packing of the binaries built
+    pub size_optimizer: SizeOptimizer,
+    /// Keep project directories here between builds and only rewrite the
+    /// generated files that changed; fresh projects are built otherwise
+    pub incremental_dir: Option<PathBuf>,
+    /// Rust toolchain the projects are pinned to, e.g. `1.79.0`
+    pub toolchain: Option<String>,
+    /// Whether missing rustup targets are installed or only suggested
+    pub target_install: TargetInstallPolicy,
+    /// Build byte-identical binaries, pinned to the toolchain given here
+    /// when `toolchain` is not
+    pub reproducible: Option<ReproducibleBuild>,
+    /// Signs the execution data appended to runners
+    pub data_signer: Option<BinarySigner>,
 }
 
 impl Default for CompilerConfig {
@@ -103,9 +160,38 @@ impl Default for CompilerConfig {
             compilation_timeout: Duration::from_secs(300), // 5 minutes
             max_parallel_compilations: num_cpus::get(),
             enable_cache: true,
+            cache_max_size: Some(DEFAULT_CACHE_MAX_SIZE),
+            cache_store: None,
             default_optimization: OptimizationLevel::Release,
             zigbuild_fallback: true,
             binary_size_limit: Some(50 * 1024 * 1024), // 50MB
+            default_runner_profile: RunnerProfile::Standard,
+            runner_profiles: HashMap::new(),
+            size_optimizer: SizeOptimizer::default(),
+            incremental_dir: None,
+            toolchain: None,
+            target_install: TargetInstallPolicy::Suggest,
+            reproducible: None,
+            data_signer: None,
+        }
+    }
+}
+
+impl CompilerConfig {
+    /// Runner profile to build for `target_triple`
+    pub fn runner_profile_for(&self, target_triple: &str) -> RunnerProfile {
+        // WASI targets only build the WASI runner, which builds for nothing else
+        if is_wasi_target(target_triple) {
+            return RunnerProfile::Wasi;
+        }
+        match self
+            .runner_profiles
+            .get(target_triple)
+            .copied()
+            .unwrap_or(self.default_runner_profile)
+        {
+            RunnerProfile::Wasi => RunnerProfile::Standard,
+            profile => profile,
         }
     }
 }
@@ -123,13 +209,24 @@ pub struct CompiledBinary {
     pub optimization_level: OptimizationLevel,
     pub template_hash: String,
     pub created_at: DateTime<Utc>,
+    /// How the size optimization stage went, for fresh builds
+    #[serde(default)]
+    pub size_report: Option<SizeReport>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BinarySource {
-    FreshCompilation { project_path: PathBuf },
-    Cache { cache_path: PathBuf },
+    FreshCompilation {
+        project_path: PathBuf,
+    },
+    Cache {
+        cache_path: PathBuf,
+    },
     InMemory,
+    /// A reused runner with the execution data appended
+    Appended {
+        runner_checksum: String,
+    },
 }
 
 // TargetSpecification and OptimizationLevel moved to crate::types::compilation
@@ -155,6 +252,15 @@ pub struct RustProject {
 pub struct ProcessExecutor {
     zigbuild_available: bool,
     cargo_path: PathBuf,
+    /// Passed to cargo as `--jobs`, to share the CPUs between builds
+    jobs: Option<usize>,
+    size_optimizer: SizeOptimizer,
+    /// Turn on cargo's incremental compilation whatever the profile
+    incremental: bool,
+    rustup: RustupTargets,
+    reproducible: Option<ReproducibleBuild>,
+    /// Kills the builds in progress once cancelled
+    cancel: CancellationToken,
 }
 
 #[derive(Debug, Clone)]
@@ -169,63 +275,220 @@ pub struct FileWriter;
 pub struct CargoTomlGenerator;
 
 impl BinaryCompiler {
-    pub fn new(config: CompilerConfig) -> Self {
-        let cache = CompilationCache::new(config.cache_dir.clone(), config.enable_cache);
+    pub fn new(mut config: CompilerConfig) -> Self {
+        if config.toolchain.is_none() {
+            config.toolchain = config
+                .reproducible
+                .as_ref()
+                .map(|reproducible| reproducible.toolchain.clone());
+        }
+        let mut cache = CompilationCache::new(config.cache_dir.clone(), config.enable_cache)
+            .with_max_size(config.cache_max_size);
+        if let Some(store) = &config.cache_store {
+            cache = cache.with_store(store.clone());
+        }
         let project_manager = ProjectManager::new(config.temp_dir.clone());
-        let process_executor = ProcessExecutor::new();
+        let incremental = config
+            .incremental_dir
+            .clone()
+            .map(IncrementalWorkspace::new);
+        let process_executor = ProcessExecutor::new()
+            .with_size_optimizer(config.size_optimizer.clone())
+            .with_incremental(incremental.is_some())
+            .with_reproducible(config.reproducible.clone())
+            .with_rustup(
+                RustupTargets::new(config.target_install).with_toolchain(config.toolchain.clone()),
+            );
 
         Self {
             config,
             cache,
             project_manager,
             process_executor,
+            incremental,
         }
     }
 
+    /// Kill the builds in progress, and start no more, once `cancel` is
+    /// cancelled
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.process_executor = self.process_executor.with_cancellation(cancel);
+        self
+    }
+
     pub async fn compile_binary(
         &mut self,
         template: &GeneratedTemplate,
         target_spec: &TargetSpecification,
     ) -> Result<CompiledBinary, CompilationError> {
-        let compilation_start = Instant::now();
-
-        // Calculate template hash for caching
-        let template_hash = self.calculate_template_hash(template)?;
+        // Address the binary by what it is built from
+        let cache_key = self.cache_key(template, target_spec);
 
         // Check cache first
-        if let Some(cached) = self.check_cache(&template_hash, &target_spec.target_triple) {
+        if let Some(cached) = self.check_cache(&cache_key).await {
             tracing::info!(
                 "Found cached binary for template {} target {}",
-                template_hash,
+                cache_key.template_hash,
                 target_spec.target_triple
             );
-            return Ok(cached);
+            return self.append_data(template, &cached);
         }
 
+        let compiled = self
+            .build_binary(
+                template,
+                target_spec,
+                cache_key.template_hash.clone(),
+                &self.process_executor,
+            )
+            .await?;
+        self.store_in_cache(
+            &cache_key,
+            &compiled,
+            TemplateComplexity::of_template(template),
+        )
+        .await;
+        self.append_data(template, &compiled)
+    }
+
+    /// `runner` with the execution data of `template` appended, signed by
+    /// the data signer if there is one, when the template's data is
+    /// appended; the cache keeps the bare runner, for every plan with its
+    /// modules
+    pub fn append_data(
+        &self,
+        template: &GeneratedTemplate,
+        runner: &CompiledBinary,
+    ) -> Result<CompiledBinary, CompilationError> {
+        if template.data_layout != DataLayout::Appended {
+            return Ok(runner.clone());
+        }
+
+        let binary_data = package_appended(
+            &runner.binary_data,
+            &template.sections,
+            self.config.data_signer.as_ref(),
+        )?;
+        tracing::info!(
+            "Appended {} bytes of execution data to the {} runner",
+            binary_data.len() as u64 - runner.size,
+            runner.target_triple
+        );
+        Ok(CompiledBinary {
+            effective_source: BinarySource::Appended {
+                runner_checksum: runner.checksum.clone(),
+            },
+            size: binary_data.len() as u64,
+            checksum: format!("{:x}", sha2::Sha256::digest(&binary_data)),
+            binary_data,
+            ..runner.clone()
+        })
+    }
+
+    /// Compile `template` for `target_spec` with `executor`, bypassing the
+    /// cache
+    pub(crate) async fn build_binary(
+        &self,
+        template: &GeneratedTemplate,
+        target_spec: &TargetSpecification,
+        template_hash: String,
+        executor: &ProcessExecutor,
+    ) -> Result<CompiledBinary, CompilationError> {
+        let compilation_start = Instant::now();
+
         tracing::info!(
             "Compiling binary for target {} with optimization {:?}",
             target_spec.target_triple,
             target_spec.optimization_level
         );
 
-        // Create temporary Rust project
-        let project = self.project_manager.create_rust_project(template).await?;
+        // Minimal runners are always built for size
+        let mut build_spec = target_spec.clone();
+        if self.config.runner_profile_for(&target_spec.target_triple) == RunnerProfile::Minimal
+            && matches!(
+                target_spec.optimization_level,
+                OptimizationLevel::Release | OptimizationLevel::Aggressive
+            )
+        {
+            build_spec.optimization_level = OptimizationLevel::MinSize;
+        }
+
+        // Sync into the kept project of the target, or create a temporary one
+        let project = match &self.incremental {
+            Some(workspace) => {
+                let profile = format!(
+                    "{:?} static={}",
+                    build_spec.optimization_level, build_spec.compilation_options.static_linking
+                );
+                let (project, sync) = workspace.prepare(template, &profile).await?;
+                tracing::info!(
+                    "Incremental build of {}: {} files changed, {} unchanged, {} removed",
+                    build_spec.target_triple,
+                    sync.written.len(),
+                    sync.unchanged,
+                    sync.removed.len()
+                );
+                project
+            }
+            None => {
+                let project = self.project_manager.create_rust_project(template).await?;
+                self.project_manager
+                    .write_template_to_project(&project, template)
+                    .await?;
+                project
+            }
+        };
 
-        // Write template to project files
-        self.project_manager
-            .write_template_to_project(&project, template)
+        if let Some(channel) = &self.config.toolchain {
+            let toolchain_toml = rust_toolchain_toml(channel, &[&build_spec.target_triple]);
+            let toolchain_path = project.project_dir.join("rust-toolchain.toml");
+            if tokio::fs::read_to_string(&toolchain_path).await.ok() != Some(toolchain_toml.clone())
+            {
+                tokio::fs::write(&toolchain_path, toolchain_toml).await?;
+            }
+        }
+
+        let mut binary_path = executor
+            .compile_project(&project, &build_spec, self.config.zigbuild_fallback)
             .await?;
+        if self.incremental.is_some() {
+            // Pack a copy, cargo's output has to stay as cargo left it
+            let output_path = project.project_dir.join("dist").join(
+                binary_path
+                    .file_name()
+                    .unwrap_or_else(|| std::ffi::OsStr::new("rustle-runner")),
+            );
+            if let Some(parent) = output_path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::copy(&binary_path, &output_path).await?;
+            binary_path = output_path;
+        }
 
-        // Compile the project
-        let binary_path = self
-            .process_executor
-            .compile_project(&project, target_spec, self.config.zigbuild_fallback)
+        let size_report = self
+            .config
+            .size_optimizer
+            .optimize(
+                &binary_path,
+                &build_spec.target_triple,
+                &build_spec.optimization_level,
+            )
             .await?;
 
         // Read binary data and create CompiledBinary
         let binary_data = tokio::fs::read(&binary_path).await?;
         let checksum = format!("{:x}", sha2::Sha256::digest(&binary_data));
 
+        if self
+            .config
+            .reproducible
+            .as_ref()
+            .is_some_and(|reproducible| reproducible.verify)
+        {
+            self.verify_reproducible(template, &build_spec, &project, executor, &checksum)
+                .await?;
+        }
+
         let compiled = CompiledBinary {
             binary_id: format!("binary-{}", Uuid::new_v4()),
             target_triple: target_spec.target_triple.clone(),
@@ -240,6 +503,7 @@ impl BinaryCompiler {
             optimization_level: target_spec.optimization_level.clone(),
             template_hash,
             created_at: Utc::now(),
+            size_report: Some(size_report),
         };
 
         // Check binary size limit
@@ -249,15 +513,21 @@ impl BinaryCompiler {
             }
         }
 
-        // Cache the result
-        if self.config.enable_cache {
-            if let Err(e) = self.cache.store_binary(&compiled).await {
-                warn!("Failed to cache binary: {}", e);
-            }
+        if self.config.runner_profile_for(&compiled.target_triple) == RunnerProfile::Minimal
+            && compiled.size > MINIMAL_PROFILE_SIZE_TARGET
+        {
+            tracing::warn!(
+                "Minimal profile binary for {} is {} bytes, above the {} byte target",
+                compiled.target_triple,
+                compiled.size,
+                MINIMAL_PROFILE_SIZE_TARGET
+            );
         }
 
-        // Cleanup temporary project
-        self.project_manager.cleanup_project(&project).await?;
+        // Cleanup temporary project, the incremental ones are kept
+        if self.incremental.is_none() {
+            self.project_manager.cleanup_project(&project).await?;
+        }
 
         tracing::info!(
             "Binary compiled successfully in {:?} (size: {} bytes)",
@@ -268,12 +538,133 @@ impl BinaryCompiler {
         Ok(compiled)
     }
 
-    pub fn check_cache(&self, template_hash: &str, target: &str) -> Option<CompiledBinary> {
+    /// Build `template` again in a fresh project, with the toolchain file
+    /// and lock file of `project`, and check the binary hashes `checksum`
+    async fn verify_reproducible(
+        &self,
+        template: &GeneratedTemplate,
+        build_spec: &TargetSpecification,
+        project: &RustProject,
+        executor: &ProcessExecutor,
+        checksum: &str,
+    ) -> Result<(), CompilationError> {
+        let rebuild = self.project_manager.create_rust_project(template).await?;
+        self.project_manager
+            .write_template_to_project(&rebuild, template)
+            .await?;
+        for file in ["rust-toolchain.toml", "Cargo.lock"] {
+            let source = project.project_dir.join(file);
+            if source.exists() {
+                tokio::fs::copy(&source, rebuild.project_dir.join(file)).await?;
+            }
+        }
+
+        let binary_path = executor
+            .compile_project(&rebuild, build_spec, self.config.zigbuild_fallback)
+            .await?;
+        self.config
+            .size_optimizer
+            .optimize(
+                &binary_path,
+                &build_spec.target_triple,
+                &build_spec.optimization_level,
+            )
+            .await?;
+        let second = format!(
+            "{:x}",
+            sha2::Sha256::digest(tokio::fs::read(&binary_path).await?)
+        );
+        self.project_manager.cleanup_project(&rebuild).await?;
+
+        if second != checksum {
+            return Err(CompilationError::NotReproducible {
+                target: build_spec.target_triple.clone(),
+                first: checksum.to_string(),
+                second,
+            });
+        }
+        tracing::info!(
+            "Build of {} is reproducible: {}",
+            build_spec.target_triple,
+            checksum
+        );
+        Ok(())
+    }
+
+    /// Cache `compiled`, recording how long a template of `complexity`
+    /// took to build
+    pub(crate) async fn store_in_cache(
+        &mut self,
+        key: &CacheKey,
+        compiled: &CompiledBinary,
+        complexity: Option<TemplateComplexity>,
+    ) {
+        if self.config.enable_cache {
+            if let Err(e) = self.cache.store_build(key, compiled, complexity).await {
+                warn!("Failed to cache binary: {}", e);
+            }
+        }
+    }
+
+    /// The cached binary of `key`, from the local cache or else the shared
+    /// store
+    pub async fn check_cache(&mut self, key: &CacheKey) -> Option<CompiledBinary> {
         if !self.config.enable_cache {
             return None;
         }
 
-        self.cache.get_binary(template_hash, target)
+        match self.cache.get_binary(key) {
+            Some(binary) => Some(binary),
+            None => self.cache.fetch_shared(key).await,
+        }
+    }
+
+    /// The cache key of `template` built for `target_spec` with the
+    /// installed toolchain
+    pub fn cache_key(
+        &self,
+        template: &GeneratedTemplate,
+        target_spec: &TargetSpecification,
+    ) -> CacheKey {
+        let versions = CompilerVersions::detect();
+        let mut toolchain_version = match &self.config.toolchain {
+            Some(channel) => format!("rustc {channel}"),
+            None => versions.rustc.clone().unwrap_or_default(),
+        };
+        if let Some(zig) = versions
+            .zig
+            .as_ref()
+            .filter(|_| self.process_executor.zigbuild_available)
+        {
+            toolchain_version.push_str(&format!(", zig {zig}"));
+        }
+
+        let mut features = target_spec.compilation_options.custom_features.clone();
+        features.extend(template.target_info.features.iter().cloned());
+
+        let options = &target_spec.compilation_options;
+        let mut profile = format!(
+            "{:?} lto={} static={} strip={} {}",
+            target_spec.optimization_level,
+            options.enable_lto || target_spec.enable_lto,
+            options.static_linking,
+            target_spec.strip_debug,
+            self.config.size_optimizer.describe()
+        );
+        if let Some(reproducible) = &self.config.reproducible {
+            profile.push_str(&format!(
+                " reproducible epoch={}",
+                reproducible.source_date_epoch
+            ));
+        }
+
+        CacheKey::new(
+            &template.content_hash(),
+            &target_spec.target_triple,
+            &toolchain_version,
+            &features,
+            &profile,
+        )
     }
 
     pub async fn cleanup_temp_projects(&self) -> Result<(), std::io::Error> {
@@ -284,23 +675,13 @@ impl BinaryCompiler {
         &self.cache
     }
 
-    fn calculate_template_hash(&self, template: &GeneratedTemplate) -> Result<String> {
-        let mut hasher = sha2::Sha256::new();
-
-        // Hash the template cache key
-        hasher.update(&template.cache_key);
-
-        // Hash target info
-        hasher.update(&template.target_info.target_triple);
-        hasher.update(&template.target_info.architecture);
-        hasher.update(&template.target_info.os_family);
-
-        // Hash optimization flags
-        for flag in &template.compilation_flags {
-            hasher.update(flag);
-        }
+    pub fn config(&self) -> &CompilerConfig {
+        &self.config
+    }
 
-        Ok(format!("{:x}", hasher.finalize()))
+    /// The executor running the compilers, as configured
+    pub fn process_executor(&self) -> &ProcessExecutor {
+        &self.process_executor
     }
 }
 
@@ -400,16 +781,92 @@ impl ProcessExecutor {
         Self {
             zigbuild_available,
             cargo_path,
+            jobs: None,
+            size_optimizer: SizeOptimizer::default(),
+            incremental: false,
+            rustup: RustupTargets::default(),
+            reproducible: None,
+            cancel: CancellationToken::new(),
         }
     }
 
+    /// Build with the profiles of `size_optimizer`
+    pub fn with_size_optimizer(mut self, size_optimizer: SizeOptimizer) -> Self {
+        self.size_optimizer = size_optimizer;
+        self
+    }
+
+    /// Build incrementally, reusing the artifacts of earlier builds
+    pub fn with_incremental(mut self, incremental: bool) -> Self {
+        self.incremental = incremental;
+        self
+    }
+
+    /// Build reproducibly with the settings of `reproducible`
+    pub fn with_reproducible(mut self, reproducible: Option<ReproducibleBuild>) -> Self {
+        self.reproducible = reproducible;
+        self
+    }
+
+    /// Check and install targets with `rustup`
+    pub fn with_rustup(mut self, rustup: RustupTargets) -> Self {
+        self.rustup = rustup;
+        self
+    }
+
+    /// Run at most `jobs` compiler processes per build
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = Some(jobs.max(1));
+        self
+    }
+
+    /// Kill the builds in progress, and start no more, once `cancel` is
+    /// cancelled
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
     pub async fn compile_project(
         &self,
         project: &RustProject,
         target_spec: &TargetSpecification,
         zigbuild_fallback: bool,
     ) -> Result<PathBuf, CompilationError> {
-        let binary_path = if self.zigbuild_available {
+        if self.cancel.is_cancelled() {
+            return Err(CompilationError::Cancelled {
+                target: target_spec.target_triple.clone(),
+            });
+        }
+
+        // Both cargo and zigbuild need the standard library of the target
+        self.rustup
+            .ensure(&target_spec.target_triple)
+            .await
+            .map_err(|e| CompilationError::ToolchainNotReady {
+                target: target_spec.target_triple.clone(),
+                reason: e.to_string(),
+            })?;
+
+        let zigbuild_ready = if self.zigbuild_available {
+            match verify_zig_toolchain().await {
+                Ok(()) => true,
+                Err(e) if zigbuild_fallback => {
+                    tracing::warn!("Not building with zigbuild: {}", e);
+                    false
+                }
+                Err(e) => {
+                    return Err(CompilationError::ToolchainNotReady {
+                        target: target_spec.target_triple.clone(),
+                        reason: e.to_string(),
+                    })
+                }
+            }
+        } else {
+            false
+        };
+
+        let binary_path = if zigbuild_ready {
             // Try zigbuild first
             match self
                 .execute_cargo_zigbuild(
@@ -420,6 +877,7 @@ impl ProcessExecutor {
                 .await
             {
                 Ok(path) => path,
+                Err(cancelled @ CompilationError::Cancelled { .. }) => return Err(cancelled),
                 Err(zigbuild_error) => {
                     if zigbuild_fallback {
                         tracing::warn!(
@@ -438,18 +896,6 @@ impl ProcessExecutor {
                 }
             }
         } else {
-            // Check if target is installed before attempting build
-            if !self
-                .is_target_installed(&target_spec.target_triple)
-                .await
-                .unwrap_or(false)
-            {
-                tracing::info!(
-                    "Target '{}' not installed. Consider running 'rustup target add {}' or 'just install-targets'",
-                    target_spec.target_triple,
-                    target_spec.target_triple
-                );
-            }
             self.execute_cargo_build(
                 &project.project_dir,
                 &target_spec.target_triple,
@@ -479,7 +925,11 @@ impl ProcessExecutor {
         cmd.arg("zigbuild")
             .arg("--target")
             .arg(target)
+            .arg("--message-format=json")
             .current_dir(project_dir);
+        if let Some(jobs) = self.jobs {
+            cmd.arg("--jobs").arg(jobs.to_string());
+        }
 
         // Set macOS-specific environment variables for zigbuild first
         if cfg!(target_os = "macos") {
@@ -487,21 +937,36 @@ impl ProcessExecutor {
         }
 
         self.add_optimization_flags(&mut cmd, optimization);
+        self.add_reproducible_env(&mut cmd, project_dir);
+        if let Some(toolchain) = self.rustup.toolchain() {
+            cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+        }
 
-        let output =
-            cmd.output()
-                .await
-                .map_err(|e| CompilationError::ZigbuildCompilationFailed {
-                    target: target.to_string(),
-                    stderr: e.to_string(),
-                })?;
+        let output = output_unless_cancelled(&mut cmd, &self.cancel)
+            .await
+            .map_err(|e| CompilationError::ZigbuildCompilationFailed {
+                target: target.to_string(),
+                stderr: e.to_string(),
+            })?
+            .ok_or_else(|| CompilationError::Cancelled {
+                target: target.to_string(),
+            })?;
 
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let diagnostics = CompilationDiagnostics::from_cargo_output(target, &stdout, project_dir);
         if !output.status.success() {
+            if diagnostics.has_errors() {
+                return Err(CompilationError::Diagnostics {
+                    target: target.to_string(),
+                    diagnostics,
+                });
+            }
             return Err(CompilationError::ZigbuildCompilationFailed {
                 target: target.to_string(),
                 stderr: String::from_utf8_lossy(&output.stderr).to_string(),
             });
         }
+        log_warnings(&diagnostics);
 
         self.determine_binary_path(project_dir, target, optimization)
     }
@@ -573,81 +1038,72 @@ impl ProcessExecutor {
         target: &str,
         optimization: &OptimizationLevel,
     ) -> Result<PathBuf, CompilationError> {
-        // Check if target is installed before attempting compilation
-        if !self.is_target_installed(target).await? {
-            tracing::warn!(
-                "Target '{}' is not installed. Run 'rustup target add {}' to install it.",
-                target,
-                target
-            );
-            return Err(CompilationError::UnsupportedTarget {
-                target: format!("{target} (not installed)"),
-            });
-        }
-
         let mut cmd = tokio::process::Command::new(&self.cargo_path);
 
         cmd.arg("build")
             .arg("--target")
             .arg(target)
+            .arg("--message-format=json")
             .current_dir(project_dir);
+        if let Some(jobs) = self.jobs {
+            cmd.arg("--jobs").arg(jobs.to_string());
+        }
 
         self.add_optimization_flags(&mut cmd, optimization);
+        self.add_reproducible_env(&mut cmd, project_dir);
+        if let Some(toolchain) = self.rustup.toolchain() {
+            cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+        }
 
-        let output = cmd
-            .output()
+        let output = output_unless_cancelled(&mut cmd, &self.cancel)
             .await
             .map_err(|e| CompilationError::CargoCompilationFailed {
                 target: target.to_string(),
                 stderr: e.to_string(),
+            })?
+            .ok_or_else(|| CompilationError::Cancelled {
+                target: target.to_string(),
             })?;
 
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let diagnostics = CompilationDiagnostics::from_cargo_output(target, &stdout, project_dir);
         if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            if is_missing_target_error(&stderr)
+                || diagnostics
+                    .errors()
+                    .any(|error| is_missing_target_error(&error.message))
+            {
+                return Err(CompilationError::ToolchainNotReady {
+                    target: target.to_string(),
+                    reason: format!(
+                        "the standard library is missing, run '{}'",
+                        self.rustup.install_command(target)
+                    ),
+                });
+            }
+            if diagnostics.has_errors() {
+                return Err(CompilationError::Diagnostics {
+                    target: target.to_string(),
+                    diagnostics,
+                });
+            }
             return Err(CompilationError::CargoCompilationFailed {
                 target: target.to_string(),
-                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+                stderr,
             });
         }
+        log_warnings(&diagnostics);
 
         self.determine_binary_path(project_dir, target, optimization)
     }
 
-    async fn is_target_installed(&self, target: &str) -> Result<bool, CompilationError> {
-        let output = tokio::process::Command::new("rustup")
-            .args(["target", "list", "--installed"])
-            .output()
-            .await
-            .map_err(|e| {
-                CompilationError::ProcessExecution(format!(
-                    "Failed to check installed targets: {e}"
-                ))
-            })?;
-
-        if !output.status.success() {
-            return Err(CompilationError::ProcessExecution(
-                "Failed to list installed targets".to_string(),
-            ));
-        }
-
-        let installed_targets = String::from_utf8_lossy(&output.stdout);
-        Ok(installed_targets.lines().any(|line| line.trim() == target))
-    }
-
     fn add_optimization_flags(
         &self,
         cmd: &mut tokio::process::Command,
         optimization: &OptimizationLevel,
     ) {
         match optimization {
-            OptimizationLevel::Release | OptimizationLevel::Aggressive => {
-                cmd.arg("--release");
-            }
-            OptimizationLevel::MinimalSize
-            | OptimizationLevel::MinSize
-            | OptimizationLevel::MinSizeRelease => {
-                cmd.arg("--release");
-                self.append_rustflags(cmd, "-C opt-level=z -C lto=fat -C strip=symbols");
-            }
             OptimizationLevel::Debug => {
                 // Default debug build
             }
@@ -655,12 +1111,46 @@ impl ProcessExecutor {
                 cmd.arg("--release");
                 self.append_rustflags(cmd, "-C debug-assertions=on");
             }
+            _ => {
+                cmd.arg("--release");
+            }
+        }
+
+        // The profile of the level wins over the generated Cargo.toml
+        let profile = SizeProfile::cargo_profile(optimization);
+        for (name, value) in self.size_optimizer.profile(optimization).cargo_env(profile) {
+            cmd.env(name, value);
+        }
+        if self.incremental {
+            cmd.env(
+                format!("CARGO_PROFILE_{}_INCREMENTAL", profile.to_uppercase()),
+                "true",
+            );
+        }
+    }
+
+    fn add_reproducible_env(
+        &self,
+        cmd: &mut tokio::process::Command,
+        project_dir: &std::path::Path,
+    ) {
+        if let Some(reproducible) = &self.reproducible {
+            for (name, value) in reproducible.cargo_env() {
+                cmd.env(name, value);
+            }
+            self.append_rustflags(cmd, &reproducible.remap_flags(project_dir));
         }
     }
 
     fn append_rustflags(&self, cmd: &mut tokio::process::Command, new_flags: &str) {
-        // Get existing RUSTFLAGS from system environment
-        let existing_flags = std::env::var("RUSTFLAGS").unwrap_or_default();
+        // Flags set on the command already, else those of the environment
+        let existing_flags = cmd
+            .as_std()
+            .get_envs()
+            .find(|(name, _)| *name == "RUSTFLAGS")
+            .and_then(|(_, value)| value)
+            .map(|value| value.to_string_lossy().into_owned())
+            .unwrap_or_else(|| std::env::var("RUSTFLAGS").unwrap_or_default());
 
         let combined_flags = if existing_flags.is_empty() {
             new_flags.to_string()
@@ -687,6 +1177,8 @@ impl ProcessExecutor {
         let mut expected_binary_path = target_dir.join("rustle-runner");
         if target.contains("windows") {
             expected_binary_path.set_extension("exe");
+        } else if is_wasi_target(target) {
+            expected_binary_path.set_extension("wasm");
         }
 
         if expected_binary_path.exists() {
@@ -763,8 +1255,8 @@ impl TemplateWriter {
             .write_file(&project.cargo_toml_path, &template.cargo_toml)
             .await?;
 
-        // Write all source files
-        for (relative_path, content) in &template.source_files {
+        // Write all source files and embedded data sections
+        for (relative_path, content) in template.project_files() {
             let full_path = project.project_dir.join(relative_path);
 
             // Create parent directory if it doesn't exist
@@ -776,8 +1268,9 @@ impl TemplateWriter {
         }
 
         tracing::debug!(
-            "Wrote {} source files to project {}",
+            "Wrote {} source files and {} sections to project {}",
             template.source_files.len(),
+            template.sections.sections.len(),
             project.project_id
         );
 
@@ -789,7 +1282,7 @@ impl FileWriter {
     pub async fn write_file(
         &self,
         path: &std::path::Path,
-        content: &str,
+        content: impl AsRef<[u8]>,
     ) -> Result<(), ProjectError> {
         tokio::fs::write(path, content)
             .await
@@ -811,3 +1304,23 @@ impl CargoTomlGenerator {
         Ok(template.cargo_toml.clone())
     }
 }
+
+/// Warnings of generated code are for maintainers, not users
+fn log_warnings(diagnostics: &CompilationDiagnostics) {
+    let warnings = diagnostics.warnings().count();
+    if warnings > 0 {
+        tracing::debug!(
+            "Build of {} had {} warning(s):\n{}",
+            diagnostics.target,
+            warnings,
+            diagnostics.render()
+        );
+    }
+}
+
+/// Whether cargo failed for lack of the target's standard library
+fn is_missing_target_error(stderr: &str) -> bool {
+    stderr.contains("target may not be installed")
+        || stderr.contains("can't find crate for `std`")
+        || stderr.contains("can't find crate for `core`")
+}
@@ -0,0 +1,367 @@
+//! Structured diagnostics of runner builds
+//!
+//! Builds run cargo with `--message-format=json`, and the compiler messages
+//! it prints are parsed into [`CompileDiagnostic`]s rather than handing
+//! users cargo's stderr. Since the code compiled is generated, each
+//! diagnostic is traced back to what generated it: the builtin module whose
+//! implementation or parameter handler it is in, or the section of the
+//! runner's `main.rs`, named after its enclosing item. Diagnostics of
+//! dependencies are kept apart, as they usually mean a toolchain problem.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticLevel {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl DiagnosticLevel {
+    fn parse(level: &str) -> Self {
+        match level {
+            level if level.starts_with("error") => Self::Error,
+            "warning" => Self::Warning,
+            "help" => Self::Help,
+            _ => Self::Note,
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+            Self::Note => write!(f, "note"),
+            Self::Help => write!(f, "help"),
+        }
+    }
+}
+
+/// What generated the code a diagnostic is about
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DiagnosticOrigin {
+    /// A section of the runner's `main.rs`, such as `fn main`
+    Template { section: String },
+    /// The implementation or parameter handler of a builtin module
+    Module { name: String },
+    /// Another generated file
+    Generated,
+    /// A crate the runner depends on
+    Dependency { package: String },
+}
+
+impl fmt::Display for DiagnosticOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Template { section } => write!(f, "template section `{section}`"),
+            Self::Module { name } => write!(f, "module {name}"),
+            Self::Generated => write!(f, "generated code"),
+            Self::Dependency { package } => write!(f, "dependency {package}"),
+        }
+    }
+}
+
+/// Where in a file a diagnostic points
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiagnosticLocation {
+    /// Relative to the project for generated files
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for DiagnosticLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.line, self.column)
+    }
+}
+
+/// One compiler message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompileDiagnostic {
+    pub level: DiagnosticLevel,
+    /// Error code, such as `E0308`
+    pub code: Option<String>,
+    pub message: String,
+    pub location: Option<DiagnosticLocation>,
+    pub origin: DiagnosticOrigin,
+    /// Help and notes attached to the message
+    pub notes: Vec<String>,
+    /// The message as rustc renders it
+    pub rendered: Option<String>,
+}
+
+/// The diagnostics of building one target
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompilationDiagnostics {
+    pub target: String,
+    pub diagnostics: Vec<CompileDiagnostic>,
+}
+
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    #[serde(default)]
+    package_id: String,
+    #[serde(default)]
+    manifest_path: PathBuf,
+    message: Option<RustcMessage>,
+}
+
+#[derive(Deserialize)]
+struct RustcMessage {
+    message: String,
+    level: String,
+    code: Option<RustcCode>,
+    #[serde(default)]
+    spans: Vec<RustcSpan>,
+    #[serde(default)]
+    children: Vec<RustcMessage>,
+    rendered: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RustcCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct RustcSpan {
+    file_name: PathBuf,
+    line_start: usize,
+    column_start: usize,
+    is_primary: bool,
+}
+
+impl CompilationDiagnostics {
+    /// Parse what `cargo build --message-format=json` printed on stdout
+    /// while building the project in `project_dir` for `target`
+    pub fn from_cargo_output(target: &str, stdout: &str, project_dir: &Path) -> Self {
+        let manifest = project_dir.join("Cargo.toml");
+        let mut sources = HashMap::new();
+        let diagnostics = stdout
+            .lines()
+            .filter_map(|line| serde_json::from_str::<CargoMessage>(line).ok())
+            .filter(|message| message.reason == "compiler-message")
+            .filter_map(|cargo| {
+                let message = cargo.message?;
+                if is_summary(&message) {
+                    return None;
+                }
+                let location = message
+                    .spans
+                    .iter()
+                    .find(|span| span.is_primary)
+                    .or(message.spans.first())
+                    .map(|span| DiagnosticLocation {
+                        file: span.file_name.clone(),
+                        line: span.line_start,
+                        column: span.column_start,
+                    });
+                // Builds in containers see the project at another path, but
+                // rustc names the files of the built package relatively
+                let generated = cargo.manifest_path == manifest
+                    || cargo.manifest_path.as_os_str().is_empty()
+                    || location.as_ref().is_some_and(|at| at.file.is_relative());
+                let origin = if generated {
+                    location.as_ref().map_or(DiagnosticOrigin::Generated, |at| {
+                        generated_origin(at, project_dir, &mut sources)
+                    })
+                } else {
+                    DiagnosticOrigin::Dependency {
+                        package: package_name(&cargo.package_id),
+                    }
+                };
+                Some(CompileDiagnostic {
+                    level: DiagnosticLevel::parse(&message.level),
+                    code: message.code.map(|code| code.code),
+                    notes: message
+                        .children
+                        .iter()
+                        .map(|child| format!("{}: {}", child.level, child.message))
+                        .collect(),
+                    message: message.message,
+                    location,
+                    origin,
+                    rendered: message.rendered,
+                })
+            })
+            .collect();
+
+        Self {
+            target: target.to_string(),
+            diagnostics,
+        }
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &CompileDiagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.level == DiagnosticLevel::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &CompileDiagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.level == DiagnosticLevel::Warning)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    /// Every diagnostic, with where it points and what generated it
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for diagnostic in &self.diagnostics {
+            match &diagnostic.code {
+                Some(code) => out.push_str(&format!(
+                    "{}[{}]: {}\n",
+                    diagnostic.level, code, diagnostic.message
+                )),
+                None => out.push_str(&format!("{}: {}\n", diagnostic.level, diagnostic.message)),
+            }
+            match &diagnostic.location {
+                Some(location) => {
+                    out.push_str(&format!("  --> {} ({})\n", location, diagnostic.origin))
+                }
+                None => out.push_str(&format!("  in {}\n", diagnostic.origin)),
+            }
+            for note in &diagnostic.notes {
+                out.push_str(&format!("   = {note}\n"));
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for CompilationDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors = self.errors().count();
+        write!(
+            f,
+            "{} error(s), {} warning(s)",
+            errors,
+            self.warnings().count()
+        )?;
+        if let Some(first) = self.errors().next() {
+            write!(f, ", first: {}", first.message)?;
+            if let Some(location) = &first.location {
+                write!(f, " at {location}")?;
+            }
+            write!(f, " in {}", first.origin)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CompilationDiagnostics {}
+
+/// Whether `message` only sums the others up, like `aborting due to 2
+/// previous errors`
+fn is_summary(message: &RustcMessage) -> bool {
+    message.level == "failure-note"
+        || message.message.starts_with("aborting due to")
+        || (message.spans.is_empty()
+            && message.level == "warning"
+            && message.message.contains("warning")
+            && message.message.contains("emitted"))
+}
+
+/// The name of the package `package_id` identifies, in either of the
+/// formats cargo has used
+fn package_name(package_id: &str) -> String {
+    match package_id.split_once('#') {
+        // registry+https://github.com/rust-lang/crates.io-index#serde@1.0.0
+        Some((path, fragment)) => match fragment.split_once('@') {
+            Some((name, _)) => name.to_string(),
+            // path+file:///work/serde#1.0.0
+            None => path.rsplit('/').next().unwrap_or(path).to_string(),
+        },
+        // serde 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)
+        None => package_id
+            .split_whitespace()
+            .next()
+            .unwrap_or(package_id)
+            .to_string(),
+    }
+}
+
+/// What generated the file `location` points into
+fn generated_origin(
+    location: &DiagnosticLocation,
+    project_dir: &Path,
+    sources: &mut HashMap<PathBuf, Option<String>>,
+) -> DiagnosticOrigin {
+    let relative = location
+        .file
+        .strip_prefix(project_dir)
+        .unwrap_or(&location.file);
+    let components: Vec<_> = relative
+        .iter()
+        .map(|component| component.to_string_lossy())
+        .collect();
+    let stem = relative
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    match components
+        .iter()
+        .map(|component| component.as_ref())
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["src", "main.rs"] => {
+            let source = sources
+                .entry(relative.to_path_buf())
+                .or_insert_with(|| std::fs::read_to_string(project_dir.join(relative)).ok());
+            DiagnosticOrigin::Template {
+                section: source
+                    .as_deref()
+                    .and_then(|source| enclosing_item(source, location.line))
+                    .unwrap_or_else(|| "main.rs".to_string()),
+            }
+        }
+        ["src", "modules", "parameter_mapping", "handlers", _] if stem != "mod" => {
+            DiagnosticOrigin::Module { name: stem }
+        }
+        ["src", "modules", "parameter_mapping", ..] => DiagnosticOrigin::Template {
+            section: "parameter mapping".to_string(),
+        },
+        ["src", "modules", _] if stem != "mod" => DiagnosticOrigin::Module { name: stem },
+        _ => DiagnosticOrigin::Generated,
+    }
+}
+
+/// The item around the 1-based `line` of `source`, such as `fn main`
+fn enclosing_item(source: &str, line: usize) -> Option<String> {
+    const KEYWORDS: &[&str] = &["fn", "mod", "impl", "struct", "enum", "trait"];
+    source
+        .lines()
+        .take(line)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .find_map(|text| {
+            let mut words = text
+                .split_whitespace()
+                .skip_while(|word| matches!(*word, "pub" | "pub(crate)" | "async" | "unsafe"));
+            let keyword = words.next().filter(|word| KEYWORDS.contains(word))?;
+            let name: String = words
+                .next()?
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect();
+            (!name.is_empty()).then(|| format!("{keyword} {name}"))
+        })
+}
@@ -0,0 +1,334 @@
+//! Compilation diagnostics per target
+//!
+//! Where [`CompilationCapabilities`](super::CompilationCapabilities) sums up
+//! what the machine can build, the doctor answers for each target asked
+//! about: what exactly is missing to build it here, the command that fixes
+//! each gap, which backend would build it and whether another backend, a
+//! container or a build farm, could build it instead.
+
+use super::backends::BackendRegistry;
+use super::capabilities::{
+    detect_rust_installation, detect_zig_installation, is_zigbuild_available, ZIG_SUPPORTED_TARGETS,
+};
+use super::target_detection::TargetDetector;
+use super::toolchain::{parse_version, MIN_ZIG_VERSION};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+const MINGW_LINKER: &str = "x86_64-w64-mingw32-gcc";
+
+/// The build tools found on this machine
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HostToolchain {
+    pub host_target: String,
+    pub rust_version: Option<String>,
+    /// Targets rustup has the standard library of
+    pub installed_targets: BTreeSet<String>,
+    pub zig_version: Option<String>,
+    pub zigbuild: bool,
+    /// The macOS SDK, from `SDKROOT` or `xcrun`
+    pub macos_sdk: Option<PathBuf>,
+    /// Whether the MinGW-w64 linker is on the `PATH`
+    pub mingw: bool,
+}
+
+impl HostToolchain {
+    pub async fn detect() -> Self {
+        let host_target = TargetDetector::new()
+            .detect_host_target()
+            .unwrap_or_else(|_| "unknown".to_string());
+        let (rust_version, installed_targets) = match detect_rust_installation().await {
+            Ok(rust) => (Some(rust.version), rust.targets.into_iter().collect()),
+            Err(_) => (None, BTreeSet::new()),
+        };
+        let zig_version = detect_zig_installation()
+            .await
+            .ok()
+            .flatten()
+            .map(|zig| zig.version);
+        let zigbuild = zig_version.is_some() && is_zigbuild_available().await.unwrap_or(false);
+
+        Self {
+            host_target,
+            rust_version,
+            installed_targets,
+            zig_version,
+            zigbuild,
+            macos_sdk: detect_macos_sdk(),
+            mingw: which::which(MINGW_LINKER).is_ok(),
+        }
+    }
+
+    /// Whether the Zig found is recent enough
+    pub fn zig_usable(&self) -> bool {
+        self.zig_version
+            .as_deref()
+            .and_then(parse_version)
+            .is_some_and(|version| version >= MIN_ZIG_VERSION)
+    }
+}
+
+fn detect_macos_sdk() -> Option<PathBuf> {
+    if let Some(sdk) = std::env::var_os("SDKROOT") {
+        return Some(PathBuf::from(sdk));
+    }
+    if !cfg!(target_os = "macos") {
+        return None;
+    }
+    let output = std::process::Command::new("xcrun")
+        .arg("--show-sdk-path")
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+}
+
+/// One requirement of a target and whether it is met
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DoctorCheck {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+    /// The command or step that fixes it, when it is not met
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+impl DoctorCheck {
+    fn passed(name: &str, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            ok: true,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn failed(name: &str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            ok: false,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// What building a target takes, and who can
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetDiagnosis {
+    pub target: String,
+    pub checks: Vec<DoctorCheck>,
+    /// The local backend that builds the target, when one can
+    pub backend: Option<String>,
+    /// Backends that can build the target without the local toolchain
+    pub fallbacks: Vec<String>,
+}
+
+impl TargetDiagnosis {
+    /// Whether the target can be built, locally or by a fallback
+    pub fn is_buildable(&self) -> bool {
+        self.backend.is_some() || !self.fallbacks.is_empty()
+    }
+
+    pub fn failed_checks(&self) -> impl Iterator<Item = &DoctorCheck> {
+        self.checks.iter().filter(|check| !check.ok)
+    }
+}
+
+/// Diagnoses targets against a toolchain and the backends registered
+pub struct CompilationDoctor {
+    toolchain: HostToolchain,
+    registry: BackendRegistry,
+}
+
+impl CompilationDoctor {
+    pub fn new(toolchain: HostToolchain, registry: BackendRegistry) -> Self {
+        Self {
+            toolchain,
+            registry,
+        }
+    }
+
+    /// The doctor of this machine, with the default backends
+    pub async fn detect() -> anyhow::Result<Self> {
+        Ok(Self::new(
+            HostToolchain::detect().await,
+            BackendRegistry::create_default()?,
+        ))
+    }
+
+    pub fn toolchain(&self) -> &HostToolchain {
+        &self.toolchain
+    }
+
+    pub fn diagnose_all(&self, targets: &[String]) -> Vec<TargetDiagnosis> {
+        targets.iter().map(|target| self.diagnose(target)).collect()
+    }
+
+    pub fn diagnose(&self, target: &str) -> TargetDiagnosis {
+        let toolchain = &self.toolchain;
+        let mut checks = Vec::new();
+
+        let rust_ok = match &toolchain.rust_version {
+            Some(version) => {
+                checks.push(DoctorCheck::passed("rust", format!("rustc {version}")));
+                true
+            }
+            None => {
+                checks.push(DoctorCheck::failed(
+                    "rust",
+                    "rustc not found",
+                    "curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh",
+                ));
+                false
+            }
+        };
+
+        let target_installed = toolchain.installed_targets.contains(target);
+        if target_installed {
+            checks.push(DoctorCheck::passed(
+                "rust-target",
+                "standard library installed",
+            ));
+        } else {
+            checks.push(DoctorCheck::failed(
+                "rust-target",
+                "standard library not installed",
+                format!("rustup target add {target}"),
+            ));
+        }
+
+        let linker = if target == toolchain.host_target {
+            checks.push(DoctorCheck::passed("linker", "native build"));
+            Some("cargo")
+        } else {
+            self.cross_linker(target, &mut checks)
+        };
+
+        let backend = linker
+            .filter(|_| rust_ok && target_installed)
+            .map(str::to_string);
+        let fallbacks = ["container", "remote", "prebuilt"]
+            .into_iter()
+            .filter(|name| Some(*name) != backend.as_deref())
+            .filter(|name| {
+                self.registry
+                    .get_backend(name)
+                    .is_some_and(|fallback| fallback.supports_target(target))
+            })
+            .map(str::to_string)
+            .collect();
+
+        TargetDiagnosis {
+            target: target.to_string(),
+            checks,
+            backend,
+            fallbacks,
+        }
+    }
+
+    /// Check what cross-compiling `target` needs, returning the backend
+    /// that would link it
+    fn cross_linker(&self, target: &str, checks: &mut Vec<DoctorCheck>) -> Option<&'static str> {
+        let toolchain = &self.toolchain;
+        let apple = target.contains("-apple-");
+        let host_apple = toolchain.host_target.contains("-apple-");
+
+        // Between the two macOS architectures Apple's linker does it all
+        if apple && host_apple {
+            checks.push(DoctorCheck::passed("linker", "Apple toolchain"));
+            return Some("cargo");
+        }
+
+        if target.ends_with("-windows-msvc") {
+            checks.push(DoctorCheck::failed(
+                "linker",
+                "MSVC targets are only built on Windows",
+                format!("build {target} on Windows, or use x86_64-pc-windows-gnu"),
+            ));
+            return None;
+        }
+
+        if target.ends_with("-windows-gnu") && toolchain.mingw {
+            checks.push(DoctorCheck::passed(
+                "linker",
+                format!("{MINGW_LINKER} found"),
+            ));
+            return Some("cargo");
+        }
+
+        if !ZIG_SUPPORTED_TARGETS.contains(&target) {
+            checks.push(DoctorCheck::failed(
+                "linker",
+                "no cross linker known for the target",
+                format!("build {target} natively or on a build farm"),
+            ));
+            return None;
+        }
+
+        let mut zig_ok = true;
+        match &toolchain.zig_version {
+            Some(version) if toolchain.zig_usable() => {
+                checks.push(DoctorCheck::passed("zig", format!("zig {version}")));
+            }
+            Some(version) => {
+                zig_ok = false;
+                checks.push(DoctorCheck::failed(
+                    "zig",
+                    format!(
+                        "zig {version} is older than {}.{}",
+                        MIN_ZIG_VERSION.0, MIN_ZIG_VERSION.1
+                    ),
+                    "install a newer Zig from https://ziglang.org/download/",
+                ));
+            }
+            None => {
+                zig_ok = false;
+                checks.push(DoctorCheck::failed(
+                    "zig",
+                    "zig not found",
+                    "pip3 install ziglang, or see https://ziglang.org/download/",
+                ));
+            }
+        }
+        if toolchain.zigbuild {
+            checks.push(DoctorCheck::passed("cargo-zigbuild", "installed"));
+        } else {
+            zig_ok = false;
+            checks.push(DoctorCheck::failed(
+                "cargo-zigbuild",
+                "not installed",
+                "cargo install cargo-zigbuild",
+            ));
+        }
+        if target.ends_with("-windows-gnu") && !zig_ok {
+            checks.push(DoctorCheck::failed(
+                "mingw",
+                format!("{MINGW_LINKER} not found"),
+                "install mingw-w64 (apt install mingw-w64, brew install mingw-w64)",
+            ));
+        }
+
+        if apple {
+            match &toolchain.macos_sdk {
+                Some(sdk) => {
+                    checks.push(DoctorCheck::passed("macos-sdk", sdk.display().to_string()))
+                }
+                None => {
+                    zig_ok = false;
+                    checks.push(DoctorCheck::failed(
+                        "macos-sdk",
+                        "no macOS SDK found",
+                        "download a MacOSX.sdk and export SDKROOT=/path/to/MacOSX.sdk",
+                    ));
+                }
+            }
+        }
+
+        zig_ok.then_some("zigbuild")
+    }
+}
@@ -0,0 +1,241 @@
+//! Predicting how long runners take to compile
+//!
+//! A runner's compile time grows mostly with the modules compiled into it,
+//! and a little with the tasks it embeds, on top of the runner's own code.
+//! [`TemplateComplexity`] weighs those in units of a module. The builds
+//! recorded in the compilation cache give the seconds a unit takes on each
+//! target, the median of the latest builds; a target never built here is
+//! predicted from the builds of the others, or else from a cold build
+//! taking about half a minute.
+
+use super::cache::CompilationCache;
+use super::target_detection::TargetDetector;
+use crate::execution::plan::ExecutionPlan;
+use crate::execution::rustle_plan::RustlePlanOutput;
+use crate::template::tree_shaker::ModuleUsage;
+use crate::template::GeneratedTemplate;
+use crate::types::compilation::OptimizationLevel;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+/// Weight of the runner's own code, in modules
+const BASE_UNITS: f64 = 15.0;
+
+/// Tasks weighing as much as a module
+const TASKS_PER_UNIT: f64 = 25.0;
+
+/// Seconds a unit takes when no build was recorded
+const DEFAULT_SECONDS_PER_UNIT: f64 = 2.0;
+
+/// Recorded builds considered per target, the latest
+const MAX_SAMPLES: usize = 10;
+
+/// What a runner's compile time grows with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateComplexity {
+    /// Tasks and handlers embedded
+    pub tasks: usize,
+    /// Distinct modules run
+    pub modules: usize,
+}
+
+/// A build recorded in the compilation cache
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompileSample {
+    pub target_triple: String,
+    pub compile_time: Duration,
+    pub complexity: TemplateComplexity,
+    pub built_at: SystemTime,
+}
+
+/// What a prediction is based on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "basis", rename_all = "snake_case")]
+pub enum EstimateBasis {
+    /// Earlier builds of the target
+    TargetHistory { samples: usize },
+    /// Earlier builds of other targets only
+    OtherTargets { samples: usize },
+    /// No earlier build
+    Default,
+}
+
+/// Predicted compile time of the runner of a target
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TargetEstimate {
+    pub target_triple: String,
+    pub compile_time: Duration,
+    #[serde(flatten)]
+    pub basis: EstimateBasis,
+}
+
+/// Predicted compile time of the runners of a plan
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompileEstimate {
+    pub complexity: TemplateComplexity,
+    pub targets: Vec<TargetEstimate>,
+    /// Time of every build together; concurrent builds share the CPUs, so
+    /// they take about as long as one after another
+    pub total: Duration,
+}
+
+/// Predicts compile times from recorded builds
+#[derive(Debug, Clone, Default)]
+pub struct CompileEstimator {
+    history: Vec<CompileSample>,
+}
+
+impl TemplateComplexity {
+    pub fn of_plan(plan: &RustlePlanOutput) -> Self {
+        let tasks = plan
+            .plays
+            .iter()
+            .map(|play| {
+                play.batches
+                    .iter()
+                    .map(|batch| batch.tasks.len())
+                    .sum::<usize>()
+                    + play.handlers.len()
+            })
+            .sum();
+        Self {
+            tasks,
+            modules: ModuleUsage::analyze(plan).modules.len(),
+        }
+    }
+
+    /// Complexity of the plan `template` embeds, if it can be read
+    pub fn of_template(template: &GeneratedTemplate) -> Option<Self> {
+        let plan: ExecutionPlan =
+            serde_json::from_str(&template.embedded_data.execution_plan).ok()?;
+        let modules: BTreeSet<&str> = plan
+            .tasks
+            .iter()
+            .map(|task| task.module.as_str())
+            .chain(plan.handlers.iter().map(|handler| handler.module.as_str()))
+            .collect();
+        Some(Self {
+            tasks: plan.tasks.len() + plan.handlers.len(),
+            modules: modules.len(),
+        })
+    }
+
+    /// Weight in units of a module compiled
+    pub fn units(&self) -> f64 {
+        BASE_UNITS + self.modules as f64 + self.tasks as f64 / TASKS_PER_UNIT
+    }
+}
+
+impl fmt::Display for EstimateBasis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TargetHistory { samples } => write!(f, "from {samples} earlier builds"),
+            Self::OtherTargets { samples } => {
+                write!(f, "from {samples} earlier builds of other targets")
+            }
+            Self::Default => write!(f, "no earlier builds"),
+        }
+    }
+}
+
+impl CompileEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Predict from the builds recorded in `cache`
+    pub fn from_cache(cache: &CompilationCache) -> Self {
+        Self {
+            history: cache.compile_history(),
+        }
+    }
+
+    pub fn with_sample(mut self, sample: CompileSample) -> Self {
+        self.history.push(sample);
+        self
+    }
+
+    /// Compile time of the runners of `plan` for `targets`
+    pub fn estimate(&self, plan: &RustlePlanOutput, targets: &[String]) -> CompileEstimate {
+        let complexity = TemplateComplexity::of_plan(plan);
+        let targets: Vec<TargetEstimate> = targets
+            .iter()
+            .map(|target| self.estimate_target(complexity, target))
+            .collect();
+        CompileEstimate {
+            complexity,
+            total: targets.iter().map(|target| target.compile_time).sum(),
+            targets,
+        }
+    }
+
+    /// Compile time of a runner of `complexity` for `target_triple`
+    pub fn estimate_target(
+        &self,
+        complexity: TemplateComplexity,
+        target_triple: &str,
+    ) -> TargetEstimate {
+        let of_target = self.rates(|sample| sample.target_triple == target_triple);
+        let (rates, basis) = if !of_target.is_empty() {
+            let samples = of_target.len();
+            (of_target, EstimateBasis::TargetHistory { samples })
+        } else {
+            let others = self.rates(|_| true);
+            let samples = others.len();
+            if samples > 0 {
+                (others, EstimateBasis::OtherTargets { samples })
+            } else {
+                (vec![DEFAULT_SECONDS_PER_UNIT], EstimateBasis::Default)
+            }
+        };
+        TargetEstimate {
+            target_triple: target_triple.to_string(),
+            compile_time: Duration::from_secs_f64(median(rates) * complexity.units()),
+            basis,
+        }
+    }
+
+    /// Seconds per unit of the latest recorded builds `matching`
+    fn rates(&self, matching: impl Fn(&CompileSample) -> bool) -> Vec<f64> {
+        let mut samples: Vec<&CompileSample> = self
+            .history
+            .iter()
+            .filter(|sample| matching(sample))
+            .collect();
+        samples.sort_by_key(|sample| std::cmp::Reverse(sample.built_at));
+        samples
+            .into_iter()
+            .take(MAX_SAMPLES)
+            .map(|sample| sample.compile_time.as_secs_f64() / sample.complexity.units())
+            .collect()
+    }
+}
+
+/// The distinct targets the binary deployments of `plan` compile for
+pub fn plan_targets(plan: &RustlePlanOutput) -> Vec<String> {
+    let detector = TargetDetector::new();
+    let mut targets: Vec<String> = Vec::new();
+    for deployment in &plan.binary_deployments {
+        if let Ok(spec) = detector.create_target_spec_from_requirements(
+            &deployment.compilation_requirements,
+            OptimizationLevel::Release,
+        ) {
+            if !targets.contains(&spec.target_triple) {
+                targets.push(spec.target_triple);
+            }
+        }
+    }
+    targets
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    if values.len() % 2 == 1 {
+        values[middle]
+    } else {
+        (values[middle - 1] + values[middle]) / 2.0
+    }
+}
@@ -0,0 +1,162 @@
+//! Incremental template-to-binary recompilation
+//!
+//! Normally a runner is built in a fresh project directory that is removed
+//! afterwards, so every plan edit rebuilds it and all its dependencies from
+//! scratch. With incremental builds each target and profile gets a project
+//! directory that is kept between builds along with its `target/`, and the
+//! generated files are synced into it: only files whose contents changed are
+//! written, so cargo's fingerprints and incremental compilation see a
+//! one-task edit as a one-file change.
+//!
+//! A state file records the template hash the directory was last synced to
+//! and the hash of every file written, to spot files that changed or are no
+//! longer generated.
+
+use crate::compilation::compiler::{ProjectError, RustProject};
+use crate::template::GeneratedTemplate;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const STATE_FILE: &str = ".rustle-incremental.json";
+
+/// What a project directory was last synced to
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct WorkspaceState {
+    template_hash: String,
+    /// Content hash of each file written, by path relative to the project
+    files: BTreeMap<PathBuf, String>,
+}
+
+/// How syncing a template into its project directory went
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Files whose contents changed or that are new
+    pub written: Vec<PathBuf>,
+    pub unchanged: usize,
+    /// Files of the previous template that are no longer generated
+    pub removed: Vec<PathBuf>,
+}
+
+impl SyncReport {
+    pub fn is_up_to_date(&self) -> bool {
+        self.written.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Persistent project directories for incremental builds
+#[derive(Debug, Clone)]
+pub struct IncrementalWorkspace {
+    root: PathBuf,
+}
+
+impl IncrementalWorkspace {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The project directory of builds for `target_triple` with `profile`;
+    /// builds with other profiles get their own so as not to invalidate
+    /// each other's artifacts
+    pub fn project_dir(&self, target_triple: &str, profile: &str) -> PathBuf {
+        let digest = format!("{:x}", Sha256::digest(profile.as_bytes()));
+        self.root
+            .join(format!("{}-{}", target_triple, &digest[..12]))
+    }
+
+    /// Sync `template` into the project directory of its target and
+    /// `profile`, writing only what changed
+    pub async fn prepare(
+        &self,
+        template: &GeneratedTemplate,
+        profile: &str,
+    ) -> Result<(RustProject, SyncReport), ProjectError> {
+        let project_dir = self.project_dir(&template.target_info.target_triple, profile);
+        tokio::fs::create_dir_all(project_dir.join("src"))
+            .await
+            .map_err(|e| ProjectError::DirectoryCreationFailed {
+                path: project_dir.display().to_string(),
+                reason: e.to_string(),
+            })?;
+
+        let state_path = project_dir.join(STATE_FILE);
+        let previous: WorkspaceState = match tokio::fs::read(&state_path).await {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_default(),
+            Err(_) => WorkspaceState::default(),
+        };
+
+        let mut files: BTreeMap<PathBuf, &[u8]> = template
+            .project_files()
+            .map(|(path, content)| (path.to_path_buf(), content))
+            .collect();
+        files.insert(PathBuf::from("Cargo.toml"), template.cargo_toml.as_bytes());
+
+        let mut state = WorkspaceState {
+            template_hash: template.content_hash(),
+            files: BTreeMap::new(),
+        };
+        let mut report = SyncReport::default();
+        for (relative_path, content) in &files {
+            let hash = format!("{:x}", Sha256::digest(content));
+            let full_path = project_dir.join(relative_path);
+            let unchanged = previous.files.get(relative_path) == Some(&hash)
+                && tokio::fs::try_exists(&full_path).await.unwrap_or(false);
+            if unchanged {
+                report.unchanged += 1;
+            } else {
+                if let Some(parent) = full_path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::write(&full_path, content).await.map_err(|e| {
+                    ProjectError::FileWriteFailed {
+                        file: full_path.display().to_string(),
+                        reason: e.to_string(),
+                    }
+                })?;
+                report.written.push(relative_path.clone());
+            }
+            state.files.insert(relative_path.clone(), hash);
+        }
+
+        for relative_path in previous.files.keys() {
+            if !files.contains_key(relative_path) {
+                let _ = tokio::fs::remove_file(project_dir.join(relative_path)).await;
+                report.removed.push(relative_path.clone());
+            }
+        }
+
+        let state_json =
+            serde_json::to_vec_pretty(&state).map_err(|e| ProjectError::FileWriteFailed {
+                file: state_path.display().to_string(),
+                reason: e.to_string(),
+            })?;
+        tokio::fs::write(&state_path, state_json).await?;
+
+        tracing::debug!(
+            "Synced template {} into {}: {} written, {} unchanged, {} removed",
+            state.template_hash,
+            project_dir.display(),
+            report.written.len(),
+            report.unchanged,
+            report.removed.len()
+        );
+
+        let project = RustProject {
+            project_id: project_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            cargo_toml_path: project_dir.join("Cargo.toml"),
+            main_rs_path: project_dir.join("src").join("main.rs"),
+            project_dir,
+            created_at: Utc::now(),
+        };
+        Ok((project, report))
+    }
+}
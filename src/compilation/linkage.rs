@@ -0,0 +1,116 @@
+//! Static or dynamic linkage of runners
+//!
+//! A `*-linux-gnu` runner needs a recent enough glibc on every host it is
+//! deployed to. When a host has none, being musl-based, too old or of a
+//! libc the probe could not tell, the runner is built for the matching
+//! `*-linux-musl` target instead: statically linked, it runs on any Linux
+//! kernel of its architecture. Static runners carry their own TLS stack
+//! (rustls) rather than linking the host's OpenSSL.
+//!
+//! The choice can be forced either way.
+
+use crate::binary::platform::{BinaryRequirements, HostLibc, HostPlatform, LibcRequirement};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How a runner is linked against the C library
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Linkage {
+    Static,
+    Dynamic,
+}
+
+impl Linkage {
+    /// The linkage of a binary built for `target_triple`, as its contents
+    /// tell when they are given
+    pub fn of(target_triple: &str, binary: Option<&[u8]>) -> Self {
+        let mut requirements = BinaryRequirements::for_triple(target_triple);
+        if let Some(binary) = binary {
+            requirements = requirements.with_binary(binary);
+        }
+        match requirements.libc {
+            LibcRequirement::None => Self::Static,
+            LibcRequirement::Glibc(_) | LibcRequirement::Musl => Self::Dynamic,
+        }
+    }
+}
+
+impl fmt::Display for Linkage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Static => write!(f, "static"),
+            Self::Dynamic => write!(f, "dynamic"),
+        }
+    }
+}
+
+/// The target a runner is built for after weighing linkage
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkageDecision {
+    pub target_triple: String,
+    pub linkage: Linkage,
+    /// Why the target was changed, when it was
+    pub reason: Option<String>,
+}
+
+/// The musl target matching the glibc target `target_triple`, if it has one
+pub fn musl_variant(target_triple: &str) -> Option<String> {
+    let (prefix, abi) = target_triple.rsplit_once("-linux-")?;
+    let abi = abi.strip_prefix("gnu")?;
+    Some(format!("{prefix}-linux-musl{abi}"))
+}
+
+/// Whether the glibc a `target_triple` runner needs is missing on `host`
+fn lacks_glibc(target_triple: &str, host: &HostPlatform) -> Option<String> {
+    let requirements = BinaryRequirements::for_triple(target_triple);
+    let LibcRequirement::Glibc(required) = requirements.libc else {
+        return None;
+    };
+    if host.os != requirements.os || host.arch != requirements.arch {
+        return None;
+    }
+    match host.libc {
+        HostLibc::Glibc(available) if available >= required => None,
+        HostLibc::Glibc(available) => Some(format!(
+            "a host has glibc {available}, older than the {required} {target_triple} needs"
+        )),
+        HostLibc::Musl => Some("a host is musl-based".to_string()),
+        HostLibc::Unknown => Some("a host's libc could not be determined".to_string()),
+        HostLibc::None => None,
+    }
+}
+
+/// Choose the target of a runner built for `target_triple` and deployed to
+/// `hosts`; `force_static` overrides the choice
+pub fn choose_linkage(
+    target_triple: &str,
+    hosts: &[HostPlatform],
+    force_static: Option<bool>,
+) -> LinkageDecision {
+    let unchanged = LinkageDecision {
+        target_triple: target_triple.to_string(),
+        linkage: Linkage::of(target_triple, None),
+        reason: None,
+    };
+    let Some(musl) = musl_variant(target_triple) else {
+        return unchanged;
+    };
+
+    let reason = match force_static {
+        Some(false) => return unchanged,
+        Some(true) => "static linking was requested".to_string(),
+        None => match hosts
+            .iter()
+            .find_map(|host| lacks_glibc(target_triple, host))
+        {
+            Some(reason) => reason,
+            None => return unchanged,
+        },
+    };
+    LinkageDecision {
+        target_triple: musl,
+        linkage: Linkage::Static,
+        reason: Some(reason),
+    }
+}
@@ -1,9 +1,20 @@
 pub mod backends;
 pub mod cache;
+pub mod cancellation;
 pub mod capabilities;
 pub mod compiler;
+pub mod diagnostics;
+pub mod doctor;
+pub mod estimate;
+pub mod incremental;
+pub mod linkage;
 pub mod optimizer;
 pub mod output;
+pub mod profile;
+pub mod reproducible;
+pub mod scheduler;
+pub mod smoke_test;
+pub mod store;
 pub mod target_detection;
 pub mod toolchain;
 pub mod zero_infra;
@@ -12,10 +23,36 @@ pub mod zigbuild;
 // Public API - only export what external modules should use
 pub use backends::{BackendRegistry, CompilationConfig as BackendConfig};
 pub use cache::*;
+pub use cancellation::output_unless_cancelled;
 pub use capabilities::*;
 pub use compiler::{BinaryCompiler, CompilerConfig};
+pub use diagnostics::{
+    CompilationDiagnostics, CompileDiagnostic, DiagnosticLevel, DiagnosticLocation,
+    DiagnosticOrigin,
+};
+pub use doctor::{CompilationDoctor, DoctorCheck, HostToolchain, TargetDiagnosis};
+pub use estimate::{
+    plan_targets, CompileEstimate, CompileEstimator, CompileSample, EstimateBasis, TargetEstimate,
+    TemplateComplexity,
+};
+pub use incremental::{IncrementalWorkspace, SyncReport};
+pub use linkage::{choose_linkage, Linkage, LinkageDecision};
 pub use optimizer::*;
 pub use output::*;
+pub use profile::{
+    check_module_compatibility, check_profile_compatibility, is_wasi_target,
+    ProfileCompatibilityReport, RunnerSubsystem, UnsupportedFeature, MINIMAL_PROFILE_SIZE_TARGET,
+    WASI_MODULES,
+};
+pub use reproducible::{
+    canonical_value, rustc_release, ReproducibleBuild, REMAPPED_CARGO_HOME, REMAPPED_PROJECT_DIR,
+};
+pub use scheduler::{CompileJob, CompileProgress, CompileReport, CompileScheduler};
+pub use smoke_test::{
+    check_self_test_output, SmokeTest, SmokeTestReport, SmokeTestStatus, REQUIRED_FACTS,
+    SELF_TEST_ARG,
+};
+pub use store::{CacheStore, CacheStoreConfig, LocalStore};
 pub use target_detection::*;
 pub use toolchain::*;
 pub use zero_infra::*;
@@ -23,5 +60,5 @@ pub use zero_infra::*;
 // Re-export canonical types from the types module
 pub use crate::types::compilation::{
     BinarySourceInfo, BinarySourceType, CompilationOptions, CompiledBinary, OptimizationLevel,
-    TargetSpecification,
+    RunnerProfile, TargetSpecification,
 };
@@ -1,16 +1,26 @@
+pub mod size;
+
+pub use size::{LtoMode, SizeOptimizer, SizeProfile, SizeReport, StripMode, UpxConfig};
+
 use crate::compilation::capabilities::CompilationCapabilities;
 use crate::compilation::zero_infra::{BinaryDeployment, SshDeployment};
 use crate::deploy::{DeployError, Result};
 use crate::execution::{RustlePlanOutput, TaskPlan};
+use crate::inventory::PreflightReport;
 use crate::ParsedInventory;
 use std::collections::HashMap;
 use std::time::Duration;
 use tracing::{debug, info};
 
+/// Round trip over which running tasks one by one over SSH costs more than
+/// shipping a binary that runs them all
+const HIGH_LATENCY: Duration = Duration::from_millis(100);
+
 /// Analyzes execution plans and determines optimal deployment strategies
 pub struct DeploymentOptimizer {
     binary_analyzer: BinaryDeploymentAnalyzer,
     performance_predictor: PerformancePredictor,
+    preflight: Option<PreflightReport>,
 }
 
 #[derive(Debug, Clone)]
@@ -115,9 +125,18 @@ impl DeploymentOptimizer {
         Self {
             binary_analyzer: BinaryDeploymentAnalyzer::new(),
             performance_predictor: PerformancePredictor::new(),
+            preflight: None,
         }
     }
 
+    /// Plan with what a connection preflight found of the hosts: those it
+    /// could not reach or log into are left out, and binaries are preferred
+    /// for those far away
+    pub fn with_preflight(mut self, report: PreflightReport) -> Self {
+        self.preflight = Some(report);
+        self
+    }
+
     /// Analyze optimization potential for an execution plan
     pub async fn analyze_optimization_potential(
         &self,
@@ -160,7 +179,7 @@ impl DeploymentOptimizer {
         let estimated_speedup = self.performance_predictor.estimate_speedup(
             binary_compatible_tasks,
             total_tasks,
-            inventory.hosts.len(),
+            self.usable_host_count(inventory),
         );
 
         // Calculate optimization score
@@ -309,8 +328,59 @@ impl DeploymentOptimizer {
         }
     }
 
+    /// Like [`Self::should_use_binary_deployment`], for `host` as the
+    /// preflight found it: binaries are not deployed to hosts that cannot be
+    /// logged into, and are worth it on those with a high latency even when
+    /// fewer tasks are binary-compatible
+    pub fn should_use_binary_deployment_on(
+        &self,
+        host: &str,
+        tasks: &[TaskPlan],
+        capabilities: &CompilationCapabilities,
+        target: &str,
+    ) -> BinaryDeploymentDecision {
+        let reachability = self.preflight.as_ref().and_then(|report| report.get(host));
+        if let Some(reachability) = reachability.filter(|r| !r.is_usable()) {
+            return BinaryDeploymentDecision::NotRecommended {
+                reasons: vec![format!(
+                    "Host {host} failed the connection preflight: {}",
+                    reachability.error.as_deref().unwrap_or("unreachable")
+                )],
+            };
+        }
+
+        let decision = self.should_use_binary_deployment(tasks, capabilities, target);
+        let latency = reachability.and_then(|r| r.latency);
+        match (decision, latency) {
+            (BinaryDeploymentDecision::Feasible { .. }, Some(latency))
+                if latency >= HIGH_LATENCY =>
+            {
+                let compatible = self
+                    .binary_analyzer
+                    .count_binary_compatible_tasks(tasks)
+                    .unwrap_or(0);
+                BinaryDeploymentDecision::Recommended {
+                    confidence: compatible as f32 / tasks.len() as f32,
+                }
+            }
+            (decision, _) => decision,
+        }
+    }
+
     // Private helper methods
 
+    /// The hosts of `inventory` the preflight, if any, did not rule out
+    fn usable_host_count(&self, inventory: &ParsedInventory) -> usize {
+        match &self.preflight {
+            Some(report) => inventory
+                .hosts
+                .keys()
+                .filter(|host| report.is_usable(host))
+                .count(),
+            None => inventory.hosts.len(),
+        }
+    }
+
     async fn analyze_targets(
         &self,
         inventory: &ParsedInventory,
@@ -323,7 +393,7 @@ impl DeploymentOptimizer {
 
         let analysis = TargetAnalysis {
             target_triple: default_target.clone(),
-            host_count: inventory.hosts.len(),
+            host_count: self.usable_host_count(inventory),
             compatible_tasks: 0, // Will be calculated later
             compilation_feasible: capabilities.supports_target(&default_target),
             estimated_benefit: 5.0, // Estimated 5x speedup
@@ -0,0 +1,330 @@
+//! Binary size optimization
+//!
+//! Runners are shipped to every host, so their size is paid once per host
+//! and deployment. Each [`OptimizationLevel`] maps to a cargo profile
+//! ([`SizeProfile`]): opt-level, thin or fat LTO, `panic = "abort"` and
+//! stripping, passed to cargo as `CARGO_PROFILE_*` variables so that they
+//! win over the generated `Cargo.toml`.
+//!
+//! After the build a [`SizeOptimizer`] may pack the binary with UPX. A
+//! packed binary is smaller but decompresses itself on every start, so the
+//! [`SizeReport`] of each target gives the sizes before and after packing
+//! along with the time UPX takes to unpack it, for users to pick.
+
+use crate::types::compilation::OptimizationLevel;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Link-time optimization of a profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LtoMode {
+    Off,
+    Thin,
+    Fat,
+}
+
+/// What cargo strips from a binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StripMode {
+    None,
+    Debuginfo,
+    Symbols,
+}
+
+impl std::str::FromStr for StripMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "debuginfo" => Ok(Self::Debuginfo),
+            "symbols" => Ok(Self::Symbols),
+            other => Err(format!(
+                "unknown strip mode '{other}' (none, debuginfo, symbols)"
+            )),
+        }
+    }
+}
+
+/// The cargo profile a binary is built with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeProfile {
+    pub opt_level: String,
+    pub lto: LtoMode,
+    pub panic_abort: bool,
+    pub strip: StripMode,
+    pub codegen_units: u32,
+    pub debug: bool,
+}
+
+impl SizeProfile {
+    pub fn for_level(level: &OptimizationLevel) -> Self {
+        match level {
+            OptimizationLevel::Debug => Self {
+                opt_level: "0".to_string(),
+                lto: LtoMode::Off,
+                panic_abort: false,
+                strip: StripMode::None,
+                codegen_units: 256,
+                debug: true,
+            },
+            OptimizationLevel::ReleaseWithDebugInfo => Self {
+                opt_level: "3".to_string(),
+                lto: LtoMode::Thin,
+                panic_abort: false,
+                strip: StripMode::None,
+                codegen_units: 16,
+                debug: true,
+            },
+            OptimizationLevel::Release => Self {
+                opt_level: "3".to_string(),
+                lto: LtoMode::Thin,
+                panic_abort: true,
+                strip: StripMode::Debuginfo,
+                codegen_units: 16,
+                debug: false,
+            },
+            OptimizationLevel::Aggressive => Self {
+                opt_level: "3".to_string(),
+                lto: LtoMode::Fat,
+                panic_abort: true,
+                strip: StripMode::Debuginfo,
+                codegen_units: 1,
+                debug: false,
+            },
+            OptimizationLevel::MinSize
+            | OptimizationLevel::MinSizeRelease
+            | OptimizationLevel::MinimalSize => Self {
+                opt_level: "z".to_string(),
+                lto: LtoMode::Fat,
+                panic_abort: true,
+                strip: StripMode::Symbols,
+                codegen_units: 1,
+                debug: false,
+            },
+        }
+    }
+
+    /// The cargo profile the level builds with, `dev` or `release`
+    pub fn cargo_profile(level: &OptimizationLevel) -> &'static str {
+        match level {
+            OptimizationLevel::Debug => "dev",
+            _ => "release",
+        }
+    }
+
+    /// `CARGO_PROFILE_*` variables setting this profile up as `profile`
+    pub fn cargo_env(&self, profile: &str) -> Vec<(String, String)> {
+        let prefix = format!("CARGO_PROFILE_{}", profile.to_uppercase());
+        vec![
+            (format!("{prefix}_OPT_LEVEL"), self.opt_level.clone()),
+            (
+                format!("{prefix}_LTO"),
+                match self.lto {
+                    LtoMode::Off => "off",
+                    LtoMode::Thin => "thin",
+                    LtoMode::Fat => "fat",
+                }
+                .to_string(),
+            ),
+            (
+                format!("{prefix}_PANIC"),
+                if self.panic_abort { "abort" } else { "unwind" }.to_string(),
+            ),
+            (
+                format!("{prefix}_STRIP"),
+                match self.strip {
+                    StripMode::None => "none",
+                    StripMode::Debuginfo => "debuginfo",
+                    StripMode::Symbols => "symbols",
+                }
+                .to_string(),
+            ),
+            (
+                format!("{prefix}_CODEGEN_UNITS"),
+                self.codegen_units.to_string(),
+            ),
+            (format!("{prefix}_DEBUG"), self.debug.to_string()),
+        ]
+    }
+}
+
+/// How UPX packs binaries
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpxConfig {
+    /// Compression level, 1 to 9; 10 and above mean `--best`
+    pub level: u8,
+    /// Use LZMA, smaller and slower to unpack
+    pub lzma: bool,
+}
+
+impl Default for UpxConfig {
+    fn default() -> Self {
+        Self {
+            level: 7,
+            lzma: false,
+        }
+    }
+}
+
+/// The size of a target's binary through the optimization stages
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeReport {
+    pub target_triple: String,
+    pub profile: SizeProfile,
+    /// As cargo built it
+    pub built_size: u64,
+    /// After UPX, when it packed the binary
+    pub packed_size: Option<u64>,
+    /// What unpacking costs every start of the packed binary
+    pub unpack_time: Option<Duration>,
+}
+
+impl SizeReport {
+    pub fn final_size(&self) -> u64 {
+        self.packed_size.unwrap_or(self.built_size)
+    }
+}
+
+/// The size optimization stage of compilation
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeOptimizer {
+    /// Strip this much whatever the optimization level
+    pub strip: Option<StripMode>,
+    /// Pack binaries with UPX
+    pub upx: Option<UpxConfig>,
+}
+
+impl SizeOptimizer {
+    pub fn with_strip(mut self, strip: StripMode) -> Self {
+        self.strip = Some(strip);
+        self
+    }
+
+    pub fn with_upx(mut self, upx: UpxConfig) -> Self {
+        self.upx = Some(upx);
+        self
+    }
+
+    /// The profile `level` builds with, stripped as configured
+    pub fn profile(&self, level: &OptimizationLevel) -> SizeProfile {
+        let mut profile = SizeProfile::for_level(level);
+        if let Some(strip) = self.strip {
+            profile.strip = strip;
+        }
+        profile
+    }
+
+    /// What sets the binaries built apart, for cache keys
+    pub fn describe(&self) -> String {
+        let mut description = match self.strip {
+            Some(strip) => format!("strip={strip:?}"),
+            None => "strip=profile".to_string(),
+        };
+        if let Some(upx) = &self.upx {
+            description.push_str(&format!(
+                " upx={}{}",
+                upx.level,
+                if upx.lzma { "+lzma" } else { "" }
+            ));
+        }
+        description
+    }
+
+    /// Pack the binary at `binary_path` as configured, in place
+    pub async fn optimize(
+        &self,
+        binary_path: &Path,
+        target_triple: &str,
+        level: &OptimizationLevel,
+    ) -> std::io::Result<SizeReport> {
+        let built_size = tokio::fs::metadata(binary_path).await?.len();
+        let mut report = SizeReport {
+            target_triple: target_triple.to_string(),
+            profile: self.profile(level),
+            built_size,
+            packed_size: None,
+            unpack_time: None,
+        };
+
+        if let Some(upx) = &self.upx {
+            match pack_with_upx(binary_path, target_triple, upx).await {
+                Ok(Some((packed_size, unpack_time))) => {
+                    report.packed_size = Some(packed_size);
+                    report.unpack_time = Some(unpack_time);
+                }
+                Ok(None) => {}
+                Err(e) => warn!("UPX could not pack the {} binary: {}", target_triple, e),
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Pack with UPX, returning the packed size and the time unpacking takes;
+/// `None` when UPX is missing or does not support the target
+async fn pack_with_upx(
+    binary_path: &Path,
+    target_triple: &str,
+    upx: &UpxConfig,
+) -> anyhow::Result<Option<(u64, Duration)>> {
+    let Ok(upx_path) = which::which("upx") else {
+        warn!("UPX packing requested but upx is not installed");
+        return Ok(None);
+    };
+    // UPX does not produce working macOS binaries on recent releases, nor
+    // support WebAssembly
+    if target_triple.contains("apple") || target_triple.starts_with("wasm") {
+        warn!("UPX does not support {}, not packing", target_triple);
+        return Ok(None);
+    }
+
+    let packed_path = PathBuf::from(format!("{}.upx", binary_path.display()));
+    let _ = tokio::fs::remove_file(&packed_path).await;
+    let level = if upx.level >= 10 {
+        "--best".to_string()
+    } else {
+        format!("-{}", upx.level.max(1))
+    };
+    let mut cmd = tokio::process::Command::new(&upx_path);
+    cmd.arg("-q").arg(level);
+    if upx.lzma {
+        cmd.arg("--lzma");
+    }
+    let output = cmd
+        .arg("-o")
+        .arg(&packed_path)
+        .arg(binary_path)
+        .output()
+        .await?;
+    if !output.status.success() {
+        let _ = tokio::fs::remove_file(&packed_path).await;
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    // `upx -t` decompresses in memory as the packed binary does on start
+    let started = Instant::now();
+    let test = tokio::process::Command::new(&upx_path)
+        .arg("-q")
+        .arg("-t")
+        .arg(&packed_path)
+        .output()
+        .await?;
+    let unpack_time = started.elapsed();
+    if !test.status.success() {
+        let _ = tokio::fs::remove_file(&packed_path).await;
+        anyhow::bail!("packed binary failed its test");
+    }
+
+    let packed_size = tokio::fs::metadata(&packed_path).await?.len();
+    tokio::fs::rename(&packed_path, binary_path).await?;
+    debug!(
+        "Packed {} binary with UPX: {} bytes, unpacks in {:?}",
+        target_triple, packed_size, unpack_time
+    );
+    Ok(Some((packed_size, unpack_time)))
+}
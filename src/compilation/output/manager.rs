@@ -1,6 +1,7 @@
 use crate::compilation::output::error::OutputError;
 use crate::compilation::output::strategies::{
-    CacheOutputStrategy, CopyResult, InMemoryOutputStrategy, OutputStrategy, ProjectOutputStrategy,
+    AppendedOutputStrategy, CacheOutputStrategy, CopyResult, InMemoryOutputStrategy,
+    OutputStrategy, ProjectOutputStrategy,
 };
 use crate::compilation::CompilationCache;
 use crate::types::compilation::CompiledBinary;
@@ -20,6 +21,7 @@ impl BinaryOutputManager {
             Box<dyn OutputStrategy<Error = crate::compilation::output::error::OutputError>>,
         > = vec![
             Box::new(CacheOutputStrategy::new()),
+            Box::new(AppendedOutputStrategy::new()),
             Box::new(ProjectOutputStrategy::new()),
             Box::new(InMemoryOutputStrategy::new()),
         ];
@@ -43,7 +45,7 @@ impl BinaryOutputManager {
         let mut compatible_strategies: Vec<_> = self
             .output_strategies
             .iter()
-            .filter(|_s| true) // Remove effective_source check for now
+            .filter(|s| s.can_handle(&binary.source_info.source_type))
             .collect();
         compatible_strategies.sort_by_key(|s| std::cmp::Reverse(s.priority()));
 
@@ -0,0 +1,88 @@
+use super::{CopyResult, OutputStrategy};
+use crate::compilation::output::error::OutputError;
+use crate::template::split_appended;
+use crate::types::compilation::{BinarySourceType, CompiledBinary};
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::time::Instant;
+use tracing::debug;
+
+/// Writes runners carrying appended execution data, checking that the
+/// data is intact and appended to the runner it was packaged with
+#[derive(Debug, Default)]
+pub struct AppendedOutputStrategy;
+
+impl AppendedOutputStrategy {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl OutputStrategy for AppendedOutputStrategy {
+    type Error = OutputError;
+    async fn copy_binary(
+        &self,
+        binary: &CompiledBinary,
+        output_path: &Path,
+    ) -> Result<CopyResult, Self::Error> {
+        let start_time = Instant::now();
+
+        let BinarySourceType::Appended { runner_checksum } = &binary.source_info.source_type else {
+            return Err(OutputError::IncompatibleSource);
+        };
+        let copy_failed = |message: String| OutputError::CopyFailed {
+            source_path: "binary_data".into(),
+            destination: output_path.to_path_buf(),
+            message,
+        };
+        let parts = split_appended(&binary.binary_data)
+            .ok_or_else(|| copy_failed("no execution data is appended".to_string()))?;
+        let checksum = format!("{:x}", Sha256::digest(parts.runner));
+        if checksum != *runner_checksum {
+            return Err(copy_failed(format!(
+                "data is appended to runner {checksum}, expected {runner_checksum}"
+            )));
+        }
+
+        // Create output directory if needed
+        if let Some(parent) = output_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Atomic write operation
+        let temp_path = output_path.with_extension("tmp");
+        tokio::fs::write(&temp_path, &binary.binary_data).await?;
+        tokio::fs::rename(&temp_path, output_path).await?;
+
+        // Verify copy integrity
+        let copied_size = tokio::fs::metadata(output_path).await?.len();
+
+        debug!(
+            "Wrote runner {} with {} bytes of appended data to {}",
+            runner_checksum,
+            parts.archive.len(),
+            output_path.display()
+        );
+
+        Ok(CopyResult {
+            output_path: output_path.to_path_buf(),
+            bytes_copied: copied_size,
+            copy_duration: start_time.elapsed(),
+            source_verified: copied_size == binary.size,
+        })
+    }
+
+    fn can_handle(&self, source_type: &BinarySourceType) -> bool {
+        matches!(source_type, BinarySourceType::Appended { .. })
+    }
+
+    fn priority(&self) -> u8 {
+        90 // Above a fresh project, which it is built from
+    }
+
+    fn name(&self) -> &'static str {
+        "appended"
+    }
+}
@@ -61,9 +61,10 @@ impl OutputStrategy for InMemoryOutputStrategy {
         })
     }
 
-    fn can_handle(&self, _source_type: &BinarySourceType) -> bool {
-        // Can handle any source type as fallback using binary_data
-        true
+    fn can_handle(&self, source_type: &BinarySourceType) -> bool {
+        // Can handle any source type as fallback using binary_data, except
+        // appended data, which is only written once checked
+        !matches!(source_type, BinarySourceType::Appended { .. })
     }
 
     fn priority(&self) -> u8 {
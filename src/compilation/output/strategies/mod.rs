@@ -1,7 +1,9 @@
+pub mod appended_strategy;
 pub mod cache_strategy;
 pub mod memory_strategy;
 pub mod project_strategy;
 
+pub use appended_strategy::*;
 pub use cache_strategy::*;
 pub use memory_strategy::*;
 pub use project_strategy::*;
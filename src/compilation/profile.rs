@@ -0,0 +1,235 @@
+use crate::execution::rustle_plan::RustlePlanOutput;
+use crate::types::compilation::RunnerProfile;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Binary size the minimal profile aims to stay under
+pub const MINIMAL_PROFILE_SIZE_TARGET: u64 = 2 * 1024 * 1024; // 2MB
+
+/// Modules a WASI runner can run: they only touch files, which the
+/// sandbox reaches through its preopened directories
+pub const WASI_MODULES: &[&str] = &[
+    "file",
+    "copy",
+    "template",
+    "stat",
+    "lineinfile",
+    "blockinfile",
+    "replace",
+    "debug",
+    "set_fact",
+    "assert",
+    "fail",
+    "meta",
+];
+
+/// Whether `target_triple` is a WebAssembly System Interface target
+pub fn is_wasi_target(target_triple: &str) -> bool {
+    target_triple.starts_with("wasm32-wasi")
+}
+
+/// Runner subsystems that restricted profiles compile out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RunnerSubsystem {
+    Http,
+    Archive,
+    Git,
+    /// Spawning processes, which WASI does not offer
+    Process,
+}
+
+impl RunnerSubsystem {
+    fn for_module(module: &str) -> Option<Self> {
+        match module {
+            "get_url" | "uri" => Some(Self::Http),
+            "unarchive" | "archive" => Some(Self::Archive),
+            "git" => Some(Self::Git),
+            _ => None,
+        }
+    }
+
+    /// What keeps `module` out of the `profile` runner, if anything
+    fn missing_for(module: &str, profile: RunnerProfile) -> Option<Self> {
+        let short = module.rsplit('.').next().unwrap_or(module);
+        match profile {
+            RunnerProfile::Standard => None,
+            RunnerProfile::Minimal => Self::for_module(short),
+            RunnerProfile::Wasi if WASI_MODULES.contains(&short) => None,
+            RunnerProfile::Wasi => Some(Self::for_module(short).unwrap_or(Self::Process)),
+        }
+    }
+}
+
+/// A plan feature that cannot be provided by the selected profile
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnsupportedFeature {
+    pub module: String,
+    pub subsystem: RunnerSubsystem,
+}
+
+/// Result of checking an execution plan against a runner profile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileCompatibilityReport {
+    pub target_triple: String,
+    pub profile: RunnerProfile,
+    pub supported_modules: Vec<String>,
+    pub unsupported: Vec<UnsupportedFeature>,
+    /// Non-fatal observations, e.g. a target that will not link statically
+    pub notes: Vec<String>,
+    pub size_target: Option<u64>,
+}
+
+impl ProfileCompatibilityReport {
+    pub fn is_compatible(&self) -> bool {
+        self.unsupported.is_empty()
+    }
+
+    /// Whether a compiled binary of `size` bytes meets the profile's size target
+    pub fn within_size_target(&self, size: u64) -> bool {
+        self.size_target.is_none_or(|target| size <= target)
+    }
+}
+
+/// Check which modules of `plan` the runner can provide under `profile`
+pub fn check_profile_compatibility(
+    plan: &RustlePlanOutput,
+    target_triple: &str,
+    profile: RunnerProfile,
+) -> ProfileCompatibilityReport {
+    let modules = plan.plays.iter().flat_map(|play| {
+        play.batches
+            .iter()
+            .flat_map(|batch| batch.tasks.iter().map(|task| task.module.as_str()))
+            .chain(play.handlers.iter().map(|handler| handler.module.as_str()))
+    });
+
+    check_module_compatibility(modules, target_triple, profile)
+}
+
+/// Check a set of module names against `profile`; duplicates are ignored
+pub fn check_module_compatibility<'a>(
+    modules: impl IntoIterator<Item = &'a str>,
+    target_triple: &str,
+    profile: RunnerProfile,
+) -> ProfileCompatibilityReport {
+    let modules: BTreeSet<&str> = modules.into_iter().collect();
+
+    let mut supported_modules = Vec::new();
+    let mut unsupported = Vec::new();
+    for module in modules {
+        match RunnerSubsystem::missing_for(module, profile) {
+            Some(subsystem) => unsupported.push(UnsupportedFeature {
+                module: module.to_string(),
+                subsystem,
+            }),
+            None => supported_modules.push(module.to_string()),
+        }
+    }
+
+    let mut notes = Vec::new();
+    let mut size_target = None;
+    if profile == RunnerProfile::Minimal {
+        size_target = Some(MINIMAL_PROFILE_SIZE_TARGET);
+        notes.push("Controller result reporting over HTTP is disabled".to_string());
+        if !target_triple.contains("musl") && !target_triple.contains("windows") {
+            notes.push(format!(
+                "Target {target_triple} links libc dynamically; use a musl target for a fully static binary"
+            ));
+        }
+    }
+    if profile == RunnerProfile::Wasi {
+        notes.push("Controller result reporting over HTTP is disabled".to_string());
+        notes.push(
+            "Runs under wasmtime on each host, seeing only the runner's directory".to_string(),
+        );
+    }
+
+    ProfileCompatibilityReport {
+        target_triple: target_triple.to_string(),
+        profile,
+        supported_modules,
+        unsupported,
+        notes,
+        size_target,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standard_profile_supports_everything() {
+        let report = check_module_compatibility(
+            ["command", "get_url", "git"],
+            "x86_64-unknown-linux-gnu",
+            RunnerProfile::Standard,
+        );
+
+        assert!(report.is_compatible());
+        assert_eq!(report.supported_modules.len(), 3);
+        assert!(report.size_target.is_none());
+        assert!(report.notes.is_empty());
+    }
+
+    #[test]
+    fn test_minimal_profile_reports_dropped_subsystems() {
+        let report = check_module_compatibility(
+            ["command", "unarchive", "get_url", "copy", "command"],
+            "armv7-unknown-linux-musleabihf",
+            RunnerProfile::Minimal,
+        );
+
+        assert!(!report.is_compatible());
+        assert_eq!(report.supported_modules, ["command", "copy"]);
+        assert_eq!(
+            report.unsupported,
+            [
+                UnsupportedFeature {
+                    module: "get_url".to_string(),
+                    subsystem: RunnerSubsystem::Http,
+                },
+                UnsupportedFeature {
+                    module: "unarchive".to_string(),
+                    subsystem: RunnerSubsystem::Archive,
+                },
+            ]
+        );
+        assert!(report.within_size_target(MINIMAL_PROFILE_SIZE_TARGET));
+        assert!(!report.within_size_target(MINIMAL_PROFILE_SIZE_TARGET + 1));
+    }
+
+    #[test]
+    fn test_wasi_profile_only_runs_file_modules() {
+        let report = check_module_compatibility(
+            [
+                "ansible.builtin.copy",
+                "template",
+                "shell",
+                "get_url",
+                "apt",
+            ],
+            "wasm32-wasi",
+            RunnerProfile::Wasi,
+        );
+
+        assert!(!report.is_compatible());
+        assert_eq!(
+            report.supported_modules,
+            ["ansible.builtin.copy", "template"]
+        );
+        assert_eq!(
+            report
+                .unsupported
+                .iter()
+                .map(|feature| (feature.module.as_str(), feature.subsystem))
+                .collect::<Vec<_>>(),
+            [
+                ("apt", RunnerSubsystem::Process),
+                ("get_url", RunnerSubsystem::Http),
+                ("shell", RunnerSubsystem::Process),
+            ]
+        );
+        assert!(report.size_target.is_none());
+    }
+}
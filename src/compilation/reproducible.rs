@@ -0,0 +1,127 @@
+//! Reproducible builds
+//!
+//! In reproducible mode the same plan built twice, here or on another
+//! machine with the same toolchain, gives byte-identical runners, so that a
+//! deployed binary can be rebuilt and audited against its provenance. That
+//! takes:
+//!
+//! - a pinned Rust toolchain, the installed one unless one is given;
+//! - `SOURCE_DATE_EPOCH` for anything embedding a build time, from the
+//!   environment or else the time the plan was created;
+//! - the project and cargo home directories remapped out of the binary with
+//!   `--remap-path-prefix`, since every build gets a fresh project directory;
+//! - no incremental compilation;
+//! - embedded data serialized in a stable order, which the template
+//!   generator always does.
+//!
+//! Each target is then built a second time in a new project with the lock
+//! file of the first, and the build fails unless both binaries hash the same.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Where the project directory appears to be in reproducible binaries
+pub const REMAPPED_PROJECT_DIR: &str = "/rustle-runner";
+/// Where the cargo home appears to be in reproducible binaries
+pub const REMAPPED_CARGO_HOME: &str = "/cargo";
+
+/// The settings of a reproducible build, recorded with its artifacts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReproducibleBuild {
+    /// Rust toolchain the build is pinned to, e.g. `1.79.0`
+    pub toolchain: String,
+    pub source_date_epoch: i64,
+    /// Build every target twice and compare the binaries
+    pub verify: bool,
+}
+
+impl ReproducibleBuild {
+    pub fn new(toolchain: impl Into<String>, source_date_epoch: i64) -> Self {
+        Self {
+            toolchain: toolchain.into(),
+            source_date_epoch,
+            verify: true,
+        }
+    }
+
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// `SOURCE_DATE_EPOCH` from the environment, `fallback` without one
+    pub fn source_date_epoch_or(fallback: i64) -> i64 {
+        std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|epoch| epoch.trim().parse().ok())
+            .unwrap_or(fallback)
+    }
+
+    /// Environment variables of the cargo builds
+    pub fn cargo_env(&self) -> Vec<(String, String)> {
+        vec![
+            (
+                "SOURCE_DATE_EPOCH".to_string(),
+                self.source_date_epoch.to_string(),
+            ),
+            ("CARGO_INCREMENTAL".to_string(), "0".to_string()),
+        ]
+    }
+
+    /// Rustflags keeping the paths of `project_dir` and the cargo home out
+    /// of the binary
+    pub fn remap_flags(&self, project_dir: &Path) -> String {
+        let mut flags = format!(
+            "--remap-path-prefix={}={REMAPPED_PROJECT_DIR}",
+            project_dir.display()
+        );
+        if let Some(cargo_home) = cargo_home() {
+            flags.push_str(&format!(
+                " --remap-path-prefix={}={REMAPPED_CARGO_HOME}",
+                cargo_home.display()
+            ));
+        }
+        flags
+    }
+}
+
+fn cargo_home() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".cargo")))
+}
+
+/// The version of a `rustc --version` line, `1.79.0` of
+/// `rustc 1.79.0 (129f3b996 2024-06-10)`, to pin builds to
+pub fn rustc_release(version: &str) -> Option<String> {
+    version
+        .split_whitespace()
+        .nth(1)
+        .filter(|release| release.starts_with(|c: char| c.is_ascii_digit()))
+        .map(str::to_string)
+}
+
+/// `value` as JSON with the keys of every object in sorted order,
+/// whatever order its maps iterate in
+pub fn canonical_value<T: Serialize>(value: &T) -> serde_json::Result<serde_json::Value> {
+    Ok(sort_keys(serde_json::to_value(value)?))
+}
+
+fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            serde_json::Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sort_keys(value)))
+                    .collect(),
+            )
+        }
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(sort_keys).collect())
+        }
+        value => value,
+    }
+}
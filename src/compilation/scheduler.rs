@@ -0,0 +1,202 @@
+//! Parallel compilation of several targets
+//!
+//! A plan deploying to mixed hosts needs one binary per target triple.
+//! [`CompileScheduler`] builds them at once, up to a bound, each in its own
+//! project and so its own cargo target directory. The CPUs are split
+//! between the concurrent builds through cargo's `--jobs`, so that three
+//! builds do not each start a compiler per core.
+//!
+//! Progress is reported per target as [`CompileProgress`] events. A failed
+//! target does not stop the others: the [`CompileReport`] holds the
+//! binaries that were built and the errors of those that were not, with the
+//! compiler diagnostics of the builds that failed on them.
+
+use super::compiler::{BinaryCompiler, CompilationError, CompiledBinary};
+use super::diagnostics::CompilationDiagnostics;
+use super::estimate::TemplateComplexity;
+use crate::template::GeneratedTemplate;
+use crate::types::compilation::TargetSpecification;
+use futures::stream::{self, StreamExt};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::warn;
+
+/// A binary to build
+#[derive(Debug, Clone)]
+pub struct CompileJob {
+    pub template: GeneratedTemplate,
+    pub target: TargetSpecification,
+}
+
+/// What happened to the build of a target
+#[derive(Debug, Clone)]
+pub enum CompileProgress {
+    /// Found in the compilation cache, not built
+    Cached {
+        target: String,
+    },
+    Started {
+        target: String,
+    },
+    Finished {
+        target: String,
+        elapsed: Duration,
+        size: u64,
+    },
+    Failed {
+        target: String,
+        error: String,
+    },
+}
+
+/// The outcome of a scheduled compilation, by target triple
+#[derive(Debug, Default)]
+pub struct CompileReport {
+    pub binaries: BTreeMap<String, CompiledBinary>,
+    pub failures: BTreeMap<String, String>,
+    /// What the compiler reported for failed builds, when it did
+    pub diagnostics: BTreeMap<String, CompilationDiagnostics>,
+}
+
+/// Builds the binaries of several targets concurrently
+#[derive(Debug, Clone)]
+pub struct CompileScheduler {
+    max_jobs: usize,
+    cpus: usize,
+    progress: Option<UnboundedSender<CompileProgress>>,
+}
+
+impl CompileScheduler {
+    /// A scheduler running up to `max_jobs` builds at once
+    pub fn new(max_jobs: usize) -> Self {
+        Self {
+            max_jobs: max_jobs.max(1),
+            cpus: num_cpus::get(),
+            progress: None,
+        }
+    }
+
+    /// Send the progress of each target to `sender`
+    pub fn with_progress(mut self, sender: UnboundedSender<CompileProgress>) -> Self {
+        self.progress = Some(sender);
+        self
+    }
+
+    /// Share `cpus` compiler processes between the builds
+    pub fn with_cpus(mut self, cpus: usize) -> Self {
+        self.cpus = cpus.max(1);
+        self
+    }
+
+    /// Build `jobs` with `compiler`, one per target triple; a later job of
+    /// the same target is not built
+    pub async fn run(&self, compiler: &mut BinaryCompiler, jobs: Vec<CompileJob>) -> CompileReport {
+        let mut report = CompileReport::default();
+        let mut pending = Vec::new();
+        let mut seen = BTreeSet::new();
+        for job in jobs {
+            let target = job.target.target_triple.clone();
+            if !seen.insert(target.clone()) {
+                warn!("Skipping a second compilation job for {}", target);
+                continue;
+            }
+            let cache_key = compiler.cache_key(&job.template, &job.target);
+            match compiler.check_cache(&cache_key).await {
+                Some(cached) => match compiler.append_data(&job.template, &cached) {
+                    Ok(binary) => {
+                        self.send(CompileProgress::Cached {
+                            target: target.clone(),
+                        });
+                        report.binaries.insert(target, binary);
+                    }
+                    Err(e) => {
+                        report.failures.insert(target, e.to_string());
+                    }
+                },
+                None => pending.push((job, cache_key)),
+            }
+        }
+        if pending.is_empty() {
+            return report;
+        }
+
+        let concurrency = self.max_jobs.min(pending.len());
+        let executor = compiler
+            .process_executor()
+            .clone()
+            .with_jobs(self.cpus / concurrency);
+        let compiler_ref = &*compiler;
+        let built: Vec<_> = stream::iter(pending)
+            .map(|(job, cache_key)| {
+                let executor = &executor;
+                async move {
+                    let target = job.target.target_triple.clone();
+                    self.send(CompileProgress::Started {
+                        target: target.clone(),
+                    });
+                    let started = Instant::now();
+                    let complexity = TemplateComplexity::of_template(&job.template);
+                    // The runner is cached, and the binary it becomes with
+                    // the data appended is deployed
+                    let result = compiler_ref
+                        .build_binary(
+                            &job.template,
+                            &job.target,
+                            cache_key.template_hash.clone(),
+                            executor,
+                        )
+                        .await
+                        .and_then(|runner| {
+                            let binary = compiler_ref.append_data(&job.template, &runner)?;
+                            Ok((runner, binary))
+                        });
+                    match &result {
+                        Ok((_, binary)) => self.send(CompileProgress::Finished {
+                            target: target.clone(),
+                            elapsed: started.elapsed(),
+                            size: binary.size,
+                        }),
+                        Err(e) => self.send(CompileProgress::Failed {
+                            target: target.clone(),
+                            error: e.to_string(),
+                        }),
+                    }
+                    (target, cache_key, complexity, result)
+                }
+            })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+
+        for (target, cache_key, complexity, result) in built {
+            match result {
+                Ok((runner, binary)) => {
+                    compiler
+                        .store_in_cache(&cache_key, &runner, complexity)
+                        .await;
+                    report.binaries.insert(target, binary);
+                }
+                Err(e) => {
+                    report.failures.insert(target.clone(), e.to_string());
+                    if let CompilationError::Diagnostics { diagnostics, .. } = e {
+                        report.diagnostics.insert(target, diagnostics);
+                    }
+                }
+            }
+        }
+        report
+    }
+
+    fn send(&self, progress: CompileProgress) {
+        if let Some(sender) = &self.progress {
+            let _ = sender.send(progress);
+        }
+    }
+}
+
+impl CompileReport {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
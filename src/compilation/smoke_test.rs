@@ -0,0 +1,289 @@
+//! Smoke testing compiled runners before they are deployed
+//!
+//! A runner that compiled can still be unusable: its embedded sections may
+//! not decode, its plan may use a module that was not compiled in, or it may
+//! not start at all on the target. `--localhost-test` runs every runner the
+//! host can execute with [`SELF_TEST_ARG`], copied into an empty sandbox
+//! directory with a cleared environment. In self-test mode a runner executes
+//! no task and cleans nothing up; it decodes all its embedded data, checks
+//! the plan against its modules, gathers basic facts and streams the events
+//! a run starts with, which are checked here before the artifact is recorded
+//! in the manifest as deployable.
+
+use crate::compilation::profile::is_wasi_target;
+use crate::compilation::target_detection::TargetDetector;
+use crate::deploy::wasi::WasmtimeExecutor;
+use crate::runtime::event_stream::{EventDecoder, StreamItem, EVENT_STREAM_ENV};
+use crate::runtime::ProgressEvent;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tracing::debug;
+
+/// Argument running a runner in self-test mode
+pub const SELF_TEST_ARG: &str = "--self-test";
+/// Execution id of the events a self-test streams
+pub const SELF_TEST_EXECUTION_ID: &str = "self-test";
+/// Facts every runner must gather in its self-test
+pub const REQUIRED_FACTS: &[&str] = &["ansible_system", "ansible_architecture"];
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How a runner fared in its smoke test
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SmokeTestStatus {
+    Passed,
+    /// The host cannot execute the runner
+    Skipped {
+        reason: String,
+    },
+    Failed {
+        failures: Vec<String>,
+    },
+}
+
+impl SmokeTestStatus {
+    /// Whether the runner may be deployed, which a skipped test allows
+    pub fn is_deployable(&self) -> bool {
+        !matches!(self, Self::Failed { .. })
+    }
+}
+
+/// The outcome of smoke testing one runner
+#[derive(Debug, Clone)]
+pub struct SmokeTestReport {
+    pub target_triple: String,
+    pub status: SmokeTestStatus,
+    /// The events the runner streamed
+    pub events: Vec<ProgressEvent>,
+    pub duration: Duration,
+    /// What the runner wrote to stderr, for diagnosing failures
+    pub stderr: String,
+}
+
+/// Runs runners in self-test mode in a sandbox
+#[derive(Debug, Clone)]
+pub struct SmokeTest {
+    timeout: Duration,
+    wasmtime: WasmtimeExecutor,
+}
+
+impl Default for SmokeTest {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT,
+            wasmtime: WasmtimeExecutor::default(),
+        }
+    }
+}
+
+impl SmokeTest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail runners that have not finished after `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Run WebAssembly runners with `wasmtime`
+    pub fn with_wasmtime(mut self, wasmtime: WasmtimeExecutor) -> Self {
+        self.wasmtime = wasmtime;
+        self
+    }
+
+    /// Why this host cannot execute runners built for `target_triple`, if
+    /// it cannot
+    pub fn unrunnable_reason(&self, target_triple: &str) -> Option<String> {
+        if is_wasi_target(target_triple) {
+            return which::which(self.wasmtime.program())
+                .is_err()
+                .then(|| format!("{} is not installed", self.wasmtime.program()));
+        }
+        let host = match TargetDetector::new().detect_host_target() {
+            Ok(host) => host,
+            Err(e) => return Some(format!("the host target is unknown: {e}")),
+        };
+        // Static musl runners execute on glibc hosts of the same architecture
+        let runs_on_host = target_triple == host
+            || (target_triple.contains("-linux-")
+                && host.contains("-linux-")
+                && target_arch(target_triple) == target_arch(&host));
+        (!runs_on_host).then(|| format!("{target_triple} runners do not execute on {host}"))
+    }
+
+    /// Self-test the runner at `binary`, built for `target_triple` with a
+    /// plan of `total_tasks` tasks
+    pub async fn run(
+        &self,
+        binary: &Path,
+        target_triple: &str,
+        total_tasks: usize,
+    ) -> Result<SmokeTestReport> {
+        let mut report = SmokeTestReport {
+            target_triple: target_triple.to_string(),
+            status: SmokeTestStatus::Passed,
+            events: Vec::new(),
+            duration: Duration::ZERO,
+            stderr: String::new(),
+        };
+        if let Some(reason) = self.unrunnable_reason(target_triple) {
+            report.status = SmokeTestStatus::Skipped { reason };
+            return Ok(report);
+        }
+
+        let sandbox = TempDir::new().context("Failed to create smoke test sandbox")?;
+        let file_name = binary.file_name().context("Runner path has no file name")?;
+        let runner = sandbox.path().join(file_name);
+        tokio::fs::copy(binary, &runner)
+            .await
+            .with_context(|| format!("Failed to copy {} into the sandbox", binary.display()))?;
+
+        let mut command = if is_wasi_target(target_triple) {
+            let mut command = tokio::process::Command::new(self.wasmtime.program());
+            command.args(self.wasmtime.arguments(
+                &runner.to_string_lossy(),
+                &[SELF_TEST_ARG.to_string()],
+                &[(EVENT_STREAM_ENV, "1")],
+            ));
+            command
+        } else {
+            let mut command = tokio::process::Command::new(&runner);
+            command.arg(SELF_TEST_ARG);
+            command
+        };
+        command
+            .current_dir(sandbox.path())
+            .env_clear()
+            .env(EVENT_STREAM_ENV, "1")
+            .env("HOME", sandbox.path())
+            .env("TMPDIR", sandbox.path())
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        if let Some(path) = std::env::var_os("PATH") {
+            command.env("PATH", path);
+        }
+
+        debug!(
+            "Smoke testing {} in {}",
+            binary.display(),
+            sandbox.path().display()
+        );
+        let started = Instant::now();
+        let output = tokio::time::timeout(self.timeout, command.output()).await;
+        report.duration = started.elapsed();
+        let output = match output {
+            Ok(output) => {
+                output.with_context(|| format!("Failed to execute {}", runner.display()))?
+            }
+            Err(_) => {
+                report.status = SmokeTestStatus::Failed {
+                    failures: vec![format!("did not finish within {:?}", self.timeout)],
+                };
+                return Ok(report);
+            }
+        };
+
+        report.stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        let (events, mut failures) = check_self_test_output(
+            &String::from_utf8_lossy(&output.stdout),
+            target_triple,
+            total_tasks,
+        );
+        if !output.status.success() {
+            failures.insert(
+                0,
+                match output.status.code() {
+                    Some(code) => format!("exited with status {code}"),
+                    None => "was killed by a signal".to_string(),
+                },
+            );
+        }
+        report.events = events;
+        if !failures.is_empty() {
+            report.status = SmokeTestStatus::Failed { failures };
+        }
+        Ok(report)
+    }
+}
+
+/// Decode the stdout of a self-test of a runner built for `target_triple`,
+/// returning its events and what is wrong with them
+pub fn check_self_test_output(
+    stdout: &str,
+    target_triple: &str,
+    total_tasks: usize,
+) -> (Vec<ProgressEvent>, Vec<String>) {
+    let mut decoder = EventDecoder::new();
+    let mut events = Vec::new();
+    let mut failures = Vec::new();
+    for item in decoder.push(stdout).into_iter().chain(decoder.finish()) {
+        match item {
+            StreamItem::Event(event) => events.push(*event),
+            StreamItem::Malformed { line, reason } => {
+                failures.push(format!("malformed event frame ({reason}): {line}"))
+            }
+            StreamItem::Output(_) => {}
+        }
+    }
+
+    match events.first() {
+        Some(ProgressEvent::ExecutionStarted {
+            total_tasks: reported,
+            ..
+        }) => {
+            if *reported != total_tasks {
+                failures.push(format!(
+                    "reported {reported} tasks but the plan has {total_tasks}"
+                ));
+            }
+        }
+        _ => failures.push("did not start with an ExecutionStarted event".to_string()),
+    }
+
+    let facts = events.iter().find_map(|event| match event {
+        ProgressEvent::FactsCollected { facts, .. } => Some(facts),
+        _ => None,
+    });
+    match facts {
+        Some(facts) => {
+            for fact in REQUIRED_FACTS {
+                if !facts.contains_key(*fact) {
+                    failures.push(format!("did not gather the {fact} fact"));
+                }
+            }
+            if let Some(arch) = facts.get("ansible_architecture").and_then(|v| v.as_str()) {
+                if arch != target_arch(target_triple) {
+                    failures.push(format!("runs as {arch} but was built for {target_triple}"));
+                }
+            }
+        }
+        None => failures.push("did not report its facts".to_string()),
+    }
+
+    for event in &events {
+        if let ProgressEvent::ExecutionFailed { error, .. } = event {
+            failures.push(error.clone());
+        }
+    }
+    (events, failures)
+}
+
+/// The architecture a runner built for `target_triple` reports, as Rust
+/// names it at run time
+fn target_arch(target_triple: &str) -> &str {
+    let arch = target_triple.split('-').next().unwrap_or(target_triple);
+    if arch.starts_with("arm") || arch.starts_with("thumb") {
+        "arm"
+    } else if matches!(arch, "i386" | "i586" | "i686") {
+        "x86"
+    } else {
+        arch
+    }
+}
@@ -0,0 +1,315 @@
+//! Shared storage for cached binaries
+//!
+//! The compilation caches keep binaries on local disk. A [`CacheStore`]
+//! shares them further, between CI runners and operators' laptops: a
+//! binary built once is published to the store and fetched by everyone
+//! else instead of being rebuilt.
+//!
+//! Stores hold two objects per binary: `<name>.bin` and `<name>.json`, the
+//! latter an [`ArtifactMetadata`] with the binary's SHA-256. A fetched
+//! binary whose digest differs, or whose size does, is rejected as if it
+//! were missing.
+//!
+//! Three stores come with the crate, configured with [`CacheStoreConfig`]:
+//!
+//! - a local (or network-mounted) directory,
+//! - S3 or an S3-compatible service, authenticated with SigV4,
+//! - plain HTTP, PUT and GET at `{base_url}/{name}`, optionally with URLs
+//!   signed by a shared key: `?expires=<unix time>&signature=<hex
+//!   HMAC-SHA256 of "METHOD\n/name\nexpires">`.
+
+use crate::runtime::object_store::{ObjectStoreClient, ObjectStoreConfig, S3Credentials};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+const DEFAULT_URL_TTL_SECS: u64 = 300;
+
+/// Where cached binaries are shared
+#[async_trait]
+pub trait CacheStore: Debug + Send + Sync {
+    /// The object `name`, or `None` when the store does not have it
+    async fn get(&self, name: &str) -> Result<Option<Vec<u8>>>;
+
+    async fn put(&self, name: &str, data: &[u8]) -> Result<()>;
+
+    /// Where the store is, for logs
+    fn describe(&self) -> String;
+
+    /// The binary published as `name`, verified against its metadata
+    async fn fetch_artifact(&self, name: &str) -> Result<Option<StoredArtifact>> {
+        let Some(metadata) = self.get(&format!("{name}.json")).await? else {
+            return Ok(None);
+        };
+        let metadata: ArtifactMetadata = serde_json::from_slice(&metadata)
+            .with_context(|| format!("Invalid metadata of {name} in {}", self.describe()))?;
+        let Some(data) = self.get(&format!("{name}.bin")).await? else {
+            return Ok(None);
+        };
+
+        let checksum = format!("{:x}", Sha256::digest(&data));
+        if checksum != metadata.checksum || data.len() as u64 != metadata.size {
+            warn!(
+                "Rejecting {} from {}: expected {} ({} bytes), got {} ({} bytes)",
+                name,
+                self.describe(),
+                metadata.checksum,
+                metadata.size,
+                checksum,
+                data.len()
+            );
+            return Ok(None);
+        }
+        debug!("Fetched {} from {}", name, self.describe());
+        Ok(Some(StoredArtifact { data, metadata }))
+    }
+
+    /// Publish `data` as `name`; the binary goes first so that a reader
+    /// never finds metadata without it
+    async fn put_artifact(&self, name: &str, data: &[u8], target_triple: &str) -> Result<()> {
+        let metadata = ArtifactMetadata {
+            checksum: format!("{:x}", Sha256::digest(data)),
+            size: data.len() as u64,
+            target_triple: target_triple.to_string(),
+            created_at: chrono::Utc::now(),
+        };
+        self.put(&format!("{name}.bin"), data).await?;
+        self.put(&format!("{name}.json"), &serde_json::to_vec(&metadata)?)
+            .await?;
+        debug!("Published {} to {}", name, self.describe());
+        Ok(())
+    }
+}
+
+/// What a stored binary is checked against
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactMetadata {
+    pub checksum: String,
+    pub size: u64,
+    pub target_triple: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A binary fetched from a store, its checksum verified
+#[derive(Debug, Clone)]
+pub struct StoredArtifact {
+    pub data: Vec<u8>,
+    pub metadata: ArtifactMetadata,
+}
+
+/// A cache store, as configured
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CacheStoreConfig {
+    Local {
+        path: PathBuf,
+    },
+    /// PUT and GET at `{base_url}/{name}`
+    Http {
+        base_url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        /// Sign the URLs with this key
+        #[serde(default)]
+        signing_key: Option<String>,
+        /// How long signed URLs are valid for
+        #[serde(default = "default_url_ttl")]
+        url_ttl_secs: u64,
+    },
+    S3 {
+        bucket: String,
+        region: String,
+        #[serde(default)]
+        endpoint: Option<String>,
+        #[serde(default)]
+        prefix: String,
+        #[serde(default)]
+        credentials: Option<S3Credentials>,
+    },
+}
+
+fn default_url_ttl() -> u64 {
+    DEFAULT_URL_TTL_SECS
+}
+
+impl CacheStoreConfig {
+    /// The configuration in the JSON or YAML file at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read cache store config {}", path.display()))?;
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("Invalid cache store config {}", path.display()))
+    }
+
+    pub fn into_store(self) -> Arc<dyn CacheStore> {
+        match self {
+            Self::Local { path } => Arc::new(LocalStore::new(path)),
+            Self::Http {
+                base_url,
+                headers,
+                signing_key,
+                url_ttl_secs,
+            } => Arc::new(HttpStore {
+                base_url,
+                headers,
+                signing_key,
+                url_ttl: Duration::from_secs(url_ttl_secs),
+                client: reqwest::Client::new(),
+            }),
+            Self::S3 {
+                bucket,
+                region,
+                endpoint,
+                prefix,
+                credentials,
+            } => {
+                let describe = format!("s3://{bucket}/{prefix}");
+                Arc::new(ObjectCacheStore {
+                    client: ObjectStoreClient::new(ObjectStoreConfig::S3 {
+                        bucket,
+                        region,
+                        endpoint,
+                        prefix,
+                        credentials,
+                    }),
+                    describe,
+                })
+            }
+        }
+    }
+}
+
+/// A directory of cached binaries, possibly on a shared mount
+#[derive(Debug, Clone)]
+pub struct LocalStore {
+    dir: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+#[async_trait]
+impl CacheStore for LocalStore {
+    async fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.dir.join(name)).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn put(&self, name: &str, data: &[u8]) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        // Written aside and renamed, so readers never see half a file
+        let partial = self.dir.join(format!(".{name}.{}", uuid::Uuid::new_v4()));
+        tokio::fs::write(&partial, data).await?;
+        tokio::fs::rename(&partial, self.dir.join(name)).await?;
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        self.dir.display().to_string()
+    }
+}
+
+/// An object store bucket of cached binaries
+#[derive(Debug, Clone)]
+pub struct ObjectCacheStore {
+    client: ObjectStoreClient,
+    describe: String,
+}
+
+#[async_trait]
+impl CacheStore for ObjectCacheStore {
+    async fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.client.get(name).await?)
+    }
+
+    async fn put(&self, name: &str, data: &[u8]) -> Result<()> {
+        Ok(self.client.put(name, data.to_vec()).await?)
+    }
+
+    fn describe(&self) -> String {
+        self.describe.clone()
+    }
+}
+
+/// A plain HTTP server of cached binaries
+#[derive(Debug, Clone)]
+pub struct HttpStore {
+    base_url: String,
+    headers: HashMap<String, String>,
+    signing_key: Option<String>,
+    url_ttl: Duration,
+    client: reqwest::Client,
+}
+
+impl HttpStore {
+    fn request(&self, method: reqwest::Method, name: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/{name}", self.base_url.trim_end_matches('/'));
+        let mut request = self.client.request(method.clone(), url);
+        if let Some(key) = &self.signing_key {
+            let expires = (SystemTime::now() + self.url_ttl)
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let signature = sign_url(key, method.as_str(), name, expires);
+            request = request.query(&[("expires", expires.to_string()), ("signature", signature)]);
+        }
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        request
+    }
+}
+
+#[async_trait]
+impl CacheStore for HttpStore {
+    async fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let response = self.request(reqwest::Method::GET, name).send().await?;
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(response.bytes().await?.to_vec())),
+            status => anyhow::bail!("GET {name} from {} failed: {status}", self.base_url),
+        }
+    }
+
+    async fn put(&self, name: &str, data: &[u8]) -> Result<()> {
+        let response = self
+            .request(reqwest::Method::PUT, name)
+            .body(data.to_vec())
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "PUT {name} to {} failed: {}",
+                self.base_url,
+                response.status()
+            );
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        self.base_url.clone()
+    }
+}
+
+/// The signature of a `method` request of `name`, valid until `expires`
+pub fn sign_url(key: &str, method: &str, name: &str, expires: u64) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{method}\n/{name}\n{expires}").as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}
@@ -102,6 +102,72 @@ impl TargetDetector {
             },
         );
 
+        supported_targets.insert(
+            "armv7-unknown-linux-gnueabihf".to_string(),
+            TargetInfo {
+                target_triple: "armv7-unknown-linux-gnueabihf".to_string(),
+                platform: Platform::Linux,
+                architecture: "armv7".to_string(),
+                os_family: "unix".to_string(),
+                libc: Some("gnu".to_string()),
+                default_features: vec![],
+                zigbuild_supported: true,
+            },
+        );
+
+        supported_targets.insert(
+            "armv7-unknown-linux-musleabihf".to_string(),
+            TargetInfo {
+                target_triple: "armv7-unknown-linux-musleabihf".to_string(),
+                platform: Platform::Linux,
+                architecture: "armv7".to_string(),
+                os_family: "unix".to_string(),
+                libc: Some("musl".to_string()),
+                default_features: vec![],
+                zigbuild_supported: false, // Built in a container, zig links 32-bit ARM musl poorly
+            },
+        );
+
+        supported_targets.insert(
+            "riscv64gc-unknown-linux-gnu".to_string(),
+            TargetInfo {
+                target_triple: "riscv64gc-unknown-linux-gnu".to_string(),
+                platform: Platform::Linux,
+                architecture: "riscv64".to_string(),
+                os_family: "unix".to_string(),
+                libc: Some("gnu".to_string()),
+                default_features: vec![],
+                zigbuild_supported: true,
+            },
+        );
+
+        // BSD and illumos targets, built in containers
+        supported_targets.insert(
+            "x86_64-unknown-freebsd".to_string(),
+            TargetInfo {
+                target_triple: "x86_64-unknown-freebsd".to_string(),
+                platform: Platform::FreeBSD,
+                architecture: "x86_64".to_string(),
+                os_family: "unix".to_string(),
+                libc: None,
+                default_features: vec![],
+                zigbuild_supported: false,
+            },
+        );
+
+        supported_targets.insert(
+            "x86_64-unknown-illumos".to_string(),
+            TargetInfo {
+                target_triple: "x86_64-unknown-illumos".to_string(),
+                platform: Platform::Illumos,
+                architecture: "x86_64".to_string(),
+                os_family: "unix".to_string(),
+                libc: None,
+                default_features: vec![],
+                zigbuild_supported: false,
+            },
+        );
+
         // Windows targets
         supported_targets.insert(
             "x86_64-pc-windows-msvc".to_string(),
@@ -188,6 +254,10 @@ impl TargetDetector {
                 ("x86_64", "darwin") | ("x86_64", "macos") => "x86_64-apple-darwin".to_string(),
                 ("aarch64", "darwin") | ("aarch64", "macos") => "aarch64-apple-darwin".to_string(),
                 ("x86_64", "windows") => "x86_64-pc-windows-msvc".to_string(),
+                ("armv7" | "arm", "linux") => "armv7-unknown-linux-gnueabihf".to_string(),
+                ("riscv64" | "riscv64gc", "linux") => "riscv64gc-unknown-linux-gnu".to_string(),
+                (arch, "freebsd") => format!("{arch}-unknown-freebsd"),
+                (arch, "illumos" | "solaris" | "sunos") => format!("{arch}-unknown-illumos"),
                 _ => format!("{arch}-unknown-{os}-gnu"),
             }
         };
@@ -321,6 +391,27 @@ mod tests {
         assert!(host_target.is_ok());
     }
 
+    #[test]
+    fn test_bsd_illumos_and_embedded_linux_targets() {
+        let detector = TargetDetector::new();
+        for (triple, platform) in [
+            ("x86_64-unknown-freebsd", Platform::FreeBSD),
+            ("x86_64-unknown-illumos", Platform::Illumos),
+            ("armv7-unknown-linux-gnueabihf", Platform::Linux),
+            ("armv7-unknown-linux-musleabihf", Platform::Linux),
+            ("riscv64gc-unknown-linux-gnu", Platform::Linux),
+        ] {
+            let info = detector.get_target_info(triple).unwrap();
+            assert_eq!(info.platform, platform);
+            let spec = detector
+                .create_target_spec(triple, OptimizationLevel::Release)
+                .unwrap();
+            assert_eq!(spec.platform, platform);
+        }
+        assert!(!detector.is_zigbuild_supported("x86_64-unknown-freebsd"));
+        assert!(detector.is_zigbuild_supported("riscv64gc-unknown-linux-gnu"));
+    }
+
     #[test]
     fn test_localhost_target_spec() {
         let detector = TargetDetector::new();
@@ -1,9 +1,17 @@
 use crate::compilation::capabilities::{CompilationCapabilities, SetupRecommendation};
 use crate::deploy::{DeployError, Result};
 use crate::types::compilation::TargetSpecification;
+use std::collections::BTreeSet;
 use tokio::process::Command;
 use tracing::{debug, info, warn};
 
+/// Oldest Zig cargo-zigbuild links reliably with
+pub const MIN_ZIG_VERSION: (u32, u32) = (0, 10);
+
+/// Oldest cargo-zigbuild supporting the glibc-suffixed targets and
+/// `--target` for macOS universal builds
+pub const MIN_ZIGBUILD_VERSION: (u32, u32) = (0, 17);
+
 /// Detects and manages cross-compilation toolchain
 pub struct ToolchainDetector {
     cache: DetectionCache,
@@ -289,3 +297,165 @@ impl Default for ToolchainDetector {
         Self::new()
     }
 }
+
+/// `major.minor` of a version such as `0.11.0`, `0.12.0-dev.1+abc` or
+/// `cargo-zigbuild 0.19.1`
+pub fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let version = version.trim().rsplit(' ').next()?;
+    let mut parts = version.split(['.', '-', '+']);
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+async fn tool_version(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().await.ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Check that zig and cargo-zigbuild are recent enough to build with
+pub async fn verify_zig_toolchain() -> std::result::Result<(), DetectionError> {
+    let checks = [
+        (
+            "zig",
+            tool_version("zig", &["version"]).await,
+            MIN_ZIG_VERSION,
+            "install a newer Zig from https://ziglang.org/download/",
+        ),
+        (
+            "cargo-zigbuild",
+            tool_version("cargo", &["zigbuild", "--version"]).await,
+            MIN_ZIGBUILD_VERSION,
+            "cargo install --force cargo-zigbuild",
+        ),
+    ];
+    for (tool, version, (major, minor), fix) in checks {
+        let Some(version) = version else {
+            return Err(DetectionError::ToolchainMissing(tool.to_string()));
+        };
+        if parse_version(&version).is_some_and(|found| found < (major, minor)) {
+            return Err(DetectionError::VersionIncompatible(format!(
+                "{tool} {version} is older than {major}.{minor}, {fix}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// What happens when a build needs a rustup target that is not installed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TargetInstallPolicy {
+    /// Fail, giving the command that installs it
+    #[default]
+    Suggest,
+    /// Install it with `rustup target add`
+    Install,
+}
+
+/// The rustup targets of the toolchain builds use
+#[derive(Debug, Clone, Default)]
+pub struct RustupTargets {
+    /// Toolchain builds are pinned to, the default one otherwise
+    toolchain: Option<String>,
+    policy: TargetInstallPolicy,
+}
+
+impl RustupTargets {
+    pub fn new(policy: TargetInstallPolicy) -> Self {
+        Self {
+            toolchain: None,
+            policy,
+        }
+    }
+
+    pub fn with_toolchain(mut self, toolchain: Option<String>) -> Self {
+        self.toolchain = toolchain;
+        self
+    }
+
+    pub fn toolchain(&self) -> Option<&str> {
+        self.toolchain.as_deref()
+    }
+
+    /// The command installing `target`
+    pub fn install_command(&self, target: &str) -> String {
+        match &self.toolchain {
+            Some(toolchain) => format!("rustup target add --toolchain {toolchain} {target}"),
+            None => format!("rustup target add {target}"),
+        }
+    }
+
+    pub async fn installed(&self) -> std::result::Result<BTreeSet<String>, DetectionError> {
+        let mut cmd = Command::new("rustup");
+        cmd.args(["target", "list", "--installed"]);
+        if let Some(toolchain) = &self.toolchain {
+            cmd.args(["--toolchain", toolchain]);
+        }
+        let output = cmd
+            .output()
+            .await
+            .map_err(|e| DetectionError::ToolchainMissing(format!("rustup: {e}")))?;
+        if !output.status.success() {
+            return Err(DetectionError::ToolchainMissing(format!(
+                "rustup: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect())
+    }
+
+    /// Make sure `target` is installed, installing it when the policy says
+    /// so; without rustup cargo is left to find out
+    pub async fn ensure(&self, target: &str) -> std::result::Result<(), DetectionError> {
+        match self.installed().await {
+            Ok(installed) if installed.contains(target) => return Ok(()),
+            Ok(_) => {}
+            Err(e) => {
+                debug!("Not checking rust targets: {}", e);
+                return Ok(());
+            }
+        }
+        if self.policy == TargetInstallPolicy::Suggest {
+            return Err(DetectionError::ToolchainMissing(format!(
+                "rust target {target}, run '{}' or pass --setup to install it",
+                self.install_command(target)
+            )));
+        }
+
+        info!("Installing rust target {}", target);
+        let mut cmd = Command::new("rustup");
+        cmd.args(["target", "add"]);
+        if let Some(toolchain) = &self.toolchain {
+            cmd.args(["--toolchain", toolchain]);
+        }
+        let output = cmd
+            .arg(target)
+            .output()
+            .await
+            .map_err(|e| DetectionError::InstallationFailed(format!("rustup: {e}")))?;
+        if !output.status.success() {
+            return Err(DetectionError::InstallationFailed(format!(
+                "{}: {}",
+                self.install_command(target),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A `rust-toolchain.toml` pinning a project to `channel` with `targets`
+pub fn rust_toolchain_toml(channel: &str, targets: &[&str]) -> String {
+    let targets: Vec<String> = targets.iter().map(|t| format!("\"{t}\"")).collect();
+    format!(
+        "[toolchain]\nchannel = \"{channel}\"\ntargets = [{}]\nprofile = \"minimal\"\n",
+        targets.join(", ")
+    )
+}
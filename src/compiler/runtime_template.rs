@@ -111,6 +111,26 @@ impl RuntimeTemplateGenerator {
         runtime_code.push_str(include_str!("../runtime/progress.rs"));
         runtime_code.push('\n');
 
+        // Built-in callback plugins
+        runtime_code.push_str(include_str!("../runtime/callbacks.rs"));
+        runtime_code.push('\n');
+
+        // Trace spans shipped back to the controller
+        runtime_code.push_str(include_str!("../runtime/telemetry.rs"));
+        runtime_code.push('\n');
+
+        // Changes shipped back for the controller's audit log
+        runtime_code.push_str(include_str!("../runtime/audit.rs"));
+        runtime_code.push('\n');
+
+        // Event stream back to the controller
+        runtime_code.push_str(include_str!("../runtime/event_stream.rs"));
+        runtime_code.push('\n');
+
+        // Controller-coordinated delegated tasks
+        runtime_code.push_str(include_str!("../runtime/delegation.rs"));
+        runtime_code.push('\n');
+
         // Facts collection
         runtime_code.push_str(include_str!("../runtime/facts.rs"));
         runtime_code.push('\n');
@@ -119,10 +139,44 @@ impl RuntimeTemplateGenerator {
         runtime_code.push_str(include_str!("../runtime/conditions.rs"));
         runtime_code.push('\n');
 
+        // Jinja2 expressions
+        runtime_code.push_str(include_str!("../runtime/expressions.rs"));
+        runtime_code.push('\n');
+
+        // Task loops
+        runtime_code.push_str(include_str!("../runtime/loops.rs"));
+        runtime_code.push('\n');
+
+        // Linear and free execution strategies
+        runtime_code.push_str(include_str!("../runtime/strategy.rs"));
+        runtime_code.push('\n');
+
+        // Privilege escalation
+        runtime_code.push_str(include_str!("../runtime/privilege.rs"));
+        runtime_code.push('\n');
+
+        // Secrets lookups, resolved on the target
+        runtime_code.push_str(include_str!("../runtime/sigv4.rs"));
+        runtime_code.push('\n');
+        runtime_code.push_str(include_str!("../runtime/lookups.rs"));
+        runtime_code.push('\n');
+
+        // Secrets the controller delivers sealed instead of embedding them
+        runtime_code.push_str(include_str!("../runtime/sealed_secrets.rs"));
+        runtime_code.push('\n');
+
         // Main executor
         runtime_code.push_str(include_str!("../runtime/executor.rs"));
         runtime_code.push('\n');
 
+        // Self-verification of signed runners
+        runtime_code.push_str(include_str!("../runtime/signing.rs"));
+        runtime_code.push('\n');
+
+        // Resident agent mode
+        runtime_code.push_str(include_str!("../runtime/agent.rs"));
+        runtime_code.push('\n');
+
         // Module interface and registry
         runtime_code.push_str(include_str!("../modules/interface.rs"));
         runtime_code.push('\n');
@@ -134,12 +188,16 @@ impl RuntimeTemplateGenerator {
         // Core modules
         runtime_code.push_str(include_str!("../modules/core/debug.rs"));
         runtime_code.push('\n');
+        runtime_code.push_str(include_str!("../modules/core/process.rs"));
+        runtime_code.push('\n');
         runtime_code.push_str(include_str!("../modules/core/command.rs"));
         runtime_code.push('\n');
         runtime_code.push_str(include_str!("../modules/core/package.rs"));
         runtime_code.push('\n');
         runtime_code.push_str(include_str!("../modules/core/service.rs"));
         runtime_code.push('\n');
+        runtime_code.push_str(include_str!("../modules/core/async_status.rs"));
+        runtime_code.push('\n');
 
         // Execution plan types
         runtime_code.push_str(include_str!("../execution/plan.rs"));
@@ -266,6 +324,23 @@ mod tests {
                 timeout: None,
                 retry_policy: None,
                 failure_policy: FailurePolicy::Abort,
+                run_once: false,
+                delegate_to: None,
+                notify: vec![],
+                play_id: None,
+                task_loop: None,
+                until: None,
+                async_job: None,
+                failed_when: None,
+                changed_when: None,
+                ignore_errors: false,
+                register: None,
+                when: Vec::new(),
+                no_log: false,
+                r#become: None,
+                environment: Default::default(),
+                parallel: false,
+                throttle: None,
             }],
             inventory: crate::execution::InventorySpec {
                 format: crate::execution::InventoryFormat::Json,
@@ -288,8 +363,16 @@ mod tests {
                 verify_deployment: false,
                 cleanup_on_success: false,
                 deployment_timeout: None,
+                maintenance_windows: vec![],
+                wait_for_window: false,
+                hooks: Default::default(),
+                serial: None,
+                max_fail_percentage: None,
             },
             modules: vec![],
+            handlers: vec![],
+            blocks: vec![],
+            plays: vec![],
         };
 
         let runtime_config = RuntimeConfig::default();
@@ -0,0 +1,176 @@
+//! The runners built earlier, as deploying without compiling finds them.
+//!
+//! An [`ArtifactIndex`] lists the runners in an output directory: the
+//! entries of its manifest, checked against the binaries on disk, or, when
+//! there is no manifest, the runners found where compiling writes them. A
+//! runner serves a plan's hosts of its target only if the plan embedded in
+//! it is the one being deployed. A runner built from another plan, or one
+//! whose plan is unknown because no manifest records it, is stale.
+
+use crate::deploy::manifest::{DeploymentManifest, MANIFEST_FILE};
+use crate::deploy::{DeployError, Result};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// File names runners are written under
+const RUNNER_NAMES: [&str; 2] = ["rustle-runner", "rustle-runner.wasm"];
+
+/// A runner in the output directory
+#[derive(Debug, Clone)]
+pub struct IndexedArtifact {
+    pub path: PathBuf,
+    /// Unknown for a runner found at the top of the output directory
+    /// without a manifest, where the only target's runner goes
+    pub target_triple: Option<String>,
+    pub sha256: String,
+    pub size: u64,
+    /// Hash of the plan embedded in the runner, when the manifest records it
+    pub plan_hash: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArtifactIndex {
+    pub artifacts: Vec<IndexedArtifact>,
+    /// The manifest the runners were listed from, if any
+    pub manifest: Option<DeploymentManifest>,
+}
+
+impl ArtifactIndex {
+    /// Index the runners of `output_dir`, from its manifest if it has one
+    pub fn load(output_dir: &Path) -> Result<Self> {
+        if !output_dir.join(MANIFEST_FILE).is_file() {
+            return Self::scan(output_dir);
+        }
+        let manifest = DeploymentManifest::load(output_dir)?;
+        manifest.verify_all(output_dir)?;
+        let artifacts = manifest
+            .artifacts
+            .iter()
+            .map(|artifact| IndexedArtifact {
+                path: output_dir.join(&artifact.name),
+                target_triple: Some(artifact.target_triple.clone()),
+                sha256: artifact.sha256.clone(),
+                size: artifact.size,
+                plan_hash: Some(artifact.plan_hash.clone()),
+            })
+            .collect();
+        Ok(Self {
+            artifacts,
+            manifest: Some(manifest),
+        })
+    }
+
+    /// Index the runners of `output_dir` where compiling writes them: at the
+    /// top for a single target, in a directory named after each target
+    /// otherwise
+    pub fn scan(output_dir: &Path) -> Result<Self> {
+        let mut artifacts = Vec::new();
+        for name in RUNNER_NAMES {
+            if let Some(artifact) = indexed(output_dir.join(name), None)? {
+                artifacts.push(artifact);
+            }
+        }
+        let mut entries: Vec<_> = std::fs::read_dir(output_dir)?.collect::<std::io::Result<_>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let target = entry.file_name().to_string_lossy().into_owned();
+            for name in RUNNER_NAMES {
+                if let Some(artifact) = indexed(entry.path().join(name), Some(target.clone()))? {
+                    artifacts.push(artifact);
+                }
+            }
+        }
+        Ok(Self {
+            artifacts,
+            manifest: None,
+        })
+    }
+
+    /// The runner for each target of `expected`, a map of target triples to
+    /// the hash of the plan their runner has to embed. A target without a
+    /// runner of its own takes one of the same plan built for its
+    /// architecture and OS with another C library, as compiling builds
+    /// static musl runners for hosts without a compatible glibc. Stale
+    /// runners are refused unless `force`d.
+    pub fn select(
+        &self,
+        expected: &BTreeMap<String, String>,
+        force: bool,
+    ) -> Result<BTreeMap<String, &IndexedArtifact>> {
+        let mut selected = BTreeMap::new();
+        for (target, plan_hash) in expected {
+            let artifact = self
+                .artifacts
+                .iter()
+                .find(|artifact| artifact.target_triple.as_ref() == Some(target))
+                .or_else(|| {
+                    self.artifacts.iter().find(|artifact| {
+                        artifact.plan_hash.as_ref() == Some(plan_hash)
+                            && artifact
+                                .target_triple
+                                .as_deref()
+                                .is_some_and(|built| same_platform(built, target))
+                    })
+                })
+                .or_else(|| match self.artifacts.as_slice() {
+                    [only] if only.target_triple.is_none() && expected.len() == 1 => Some(only),
+                    _ => None,
+                })
+                .ok_or_else(|| DeployError::MissingArtifact {
+                    target: target.clone(),
+                })?;
+
+            let stale = match &artifact.plan_hash {
+                Some(built) if built == plan_hash => None,
+                Some(_) => Some("it was built from another plan"),
+                None => Some("no manifest records the plan it was built from"),
+            };
+            if let Some(reason) = stale {
+                if !force {
+                    return Err(DeployError::StaleArtifact {
+                        path: artifact.path.display().to_string(),
+                        reason: reason.to_string(),
+                    });
+                }
+                warn!(
+                    "Deploying {} although {}, as forced",
+                    artifact.path.display(),
+                    reason
+                );
+            }
+            selected.insert(target.clone(), artifact);
+        }
+        Ok(selected)
+    }
+}
+
+/// Whether two target triples share their architecture and OS
+fn same_platform(a: &str, b: &str) -> bool {
+    platform(a) == platform(b)
+}
+
+/// The architecture and OS of a target triple
+fn platform(triple: &str) -> (Option<&str>, Option<&str>) {
+    let parts: Vec<&str> = triple.split('-').collect();
+    (parts.first().copied(), parts.get(2).copied())
+}
+
+/// The runner at `path`, if there is one
+fn indexed(path: PathBuf, target_triple: Option<String>) -> Result<Option<IndexedArtifact>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let binary = std::fs::read(&path)?;
+    Ok(Some(IndexedArtifact {
+        sha256: format!("{:x}", Sha256::digest(&binary)),
+        size: binary.len() as u64,
+        path,
+        target_triple,
+        plan_hash: None,
+    }))
+}
@@ -0,0 +1,272 @@
+//! Tamper-evident audit log of the changes deployments make.
+//!
+//! Runners report every change a task made as an [`AuditEntry`] (see
+//! [`crate::runtime::audit`]). The controller appends each one to the audit
+//! log as a line of JSON, an [`AuditRecord`] naming the deployment and the
+//! user who started it. Every record carries the SHA-256 of its contents
+//! together with the hash of the record before it, so editing, removing or
+//! reordering a record breaks the chain from there on, which
+//! [`AuditLog::verify`] reports. Records dropped from the end of the log
+//! leave the chain intact: compare the head hash `verify` returns with one
+//! kept elsewhere to detect that.
+
+use crate::deploy::{DeployError, Result};
+use crate::runtime::AuditEntry;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, PoisonError};
+
+/// What the first record of a log is chained to
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// A change recorded in the audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Position in the log, from 1
+    pub sequence: u64,
+    pub deployment_id: String,
+    /// Who started the deployment on the controller
+    pub user: String,
+    #[serde(flatten)]
+    pub entry: AuditEntry,
+    /// Hash of the record before this one, or [`GENESIS_HASH`]
+    pub previous_hash: String,
+    pub hash: String,
+}
+
+impl AuditRecord {
+    /// The hash the record should carry: that of its contents and the hash
+    /// of the record before it
+    pub fn digest(&self) -> String {
+        let unhashed = Self {
+            hash: String::new(),
+            ..self.clone()
+        };
+        let mut hasher = Sha256::new();
+        hasher.update(self.previous_hash.as_bytes());
+        hasher.update(serde_json::to_vec(&unhashed).unwrap_or_default());
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// Formats the audit log can be exported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditFormat {
+    /// An array of the records
+    Json,
+    /// A row per record, with the parameters as JSON
+    Csv,
+}
+
+impl FromStr for AuditFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            other => Err(format!(
+                "unknown audit export format '{other}', expected json or csv"
+            )),
+        }
+    }
+}
+
+/// The append-only audit log in one file
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    user: String,
+    /// Sequence number and hash of the last record, once read
+    head: Mutex<Option<(u64, String)>>,
+}
+
+impl AuditLog {
+    /// The log at `path`, attributing changes to the user running the
+    /// controller
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            user: current_user(),
+            head: Mutex::new(None),
+        }
+    }
+
+    /// `~/.rustle/audit.log`
+    pub fn default_path() -> PathBuf {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".rustle")
+            .join("audit.log")
+    }
+
+    /// Attribute the changes appended from now on to `user`
+    pub fn with_user(mut self, user: impl Into<String>) -> Self {
+        self.user = user.into();
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append `entries`, made by the deployment `deployment_id`, to the log
+    pub fn append(&self, deployment_id: &str, entries: &[AuditEntry]) -> Result<Vec<AuditRecord>> {
+        if entries.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut head = self.head.lock().unwrap_or_else(PoisonError::into_inner);
+        let (mut sequence, mut previous_hash) = match head.take() {
+            Some(head) => head,
+            None => self.read_head()?,
+        };
+
+        let mut records = Vec::with_capacity(entries.len());
+        let mut lines = Vec::new();
+        for entry in entries {
+            sequence += 1;
+            let mut record = AuditRecord {
+                sequence,
+                deployment_id: deployment_id.to_string(),
+                user: self.user.clone(),
+                entry: entry.clone(),
+                previous_hash,
+                hash: String::new(),
+            };
+            record.hash = record.digest();
+            previous_hash = record.hash.clone();
+            serde_json::to_writer(&mut lines, &record)?;
+            lines.push(b'\n');
+            records.push(record);
+        }
+
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&lines)?;
+        file.sync_data()?;
+
+        *head = Some((sequence, previous_hash));
+        Ok(records)
+    }
+
+    /// Sequence number and hash of the last record in the file
+    fn read_head(&self) -> Result<(u64, String)> {
+        Ok(self
+            .read()?
+            .last()
+            .map(|record| (record.sequence, record.hash.clone()))
+            .unwrap_or((0, GENESIS_HASH.to_string())))
+    }
+
+    /// Every record of the log, in order; none if it does not exist yet
+    pub fn read(&self) -> Result<Vec<AuditRecord>> {
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut records = Vec::new();
+        for (index, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str(&line)
+                .map_err(|e| self.tampered(index + 1, format!("unreadable record: {e}")))?;
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    /// Check the hash chain of the whole log, returning the number of
+    /// records and the hash of the last one
+    pub fn verify(&self) -> Result<(usize, String)> {
+        let records = self.read()?;
+        let mut previous_hash = GENESIS_HASH.to_string();
+        for (index, record) in records.iter().enumerate() {
+            let line = index + 1;
+            if record.sequence != line as u64 {
+                return Err(self.tampered(
+                    line,
+                    format!("expected record {line}, found {}", record.sequence),
+                ));
+            }
+            if record.previous_hash != previous_hash {
+                return Err(self.tampered(line, "not chained to the record before it"));
+            }
+            if record.digest() != record.hash {
+                return Err(self.tampered(line, "contents do not match its hash"));
+            }
+            previous_hash = record.hash.clone();
+        }
+        Ok((records.len(), previous_hash))
+    }
+
+    /// The verified log in `format`
+    pub fn export(&self, format: AuditFormat) -> Result<String> {
+        self.verify()?;
+        let records = self.read()?;
+        Ok(match format {
+            AuditFormat::Json => serde_json::to_string_pretty(&records)?,
+            AuditFormat::Csv => csv(&records),
+        })
+    }
+
+    fn tampered(&self, line: usize, reason: impl Into<String>) -> DeployError {
+        DeployError::AuditLogTampered {
+            path: self.path.display().to_string(),
+            line,
+            reason: reason.into(),
+        }
+    }
+}
+
+fn csv(records: &[AuditRecord]) -> String {
+    let field = |value: &str| {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    };
+    let mut out = String::from(
+        "sequence,timestamp,deployment_id,user,host,task_id,task_name,module,params,before_checksum,after_checksum,hash\n",
+    );
+    for record in records {
+        let entry = &record.entry;
+        let row = [
+            record.sequence.to_string(),
+            entry.timestamp.to_rfc3339(),
+            record.deployment_id.clone(),
+            record.user.clone(),
+            entry.host.clone(),
+            entry.task_id.clone(),
+            entry.task_name.clone(),
+            entry.module.clone(),
+            entry.params.to_string(),
+            entry.before_checksum.clone().unwrap_or_default(),
+            entry.after_checksum.clone().unwrap_or_default(),
+            record.hash.clone(),
+        ];
+        let row: Vec<_> = row.iter().map(|value| field(value)).collect();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// The user running the controller
+fn current_user() -> String {
+    ["USER", "USERNAME", "LOGNAME"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|user| !user.is_empty()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
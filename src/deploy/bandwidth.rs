@@ -0,0 +1,176 @@
+//! Transfer rate limiting for binary uploads.
+//!
+//! Runner binaries are tens of megabytes, and pushing one to every host at
+//! once saturates WAN links. [`BandwidthLimits`] caps the rate of each host's
+//! transfer and of the deployment as a whole; [`Bandwidth`] turns the limits
+//! into shared [`RateLimiter`]s that every uploaded segment is paced by. A
+//! segment is the smallest unit paced, so short bursts of up to one segment
+//! are allowed.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// Transfer rate caps, in bytes per second
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandwidthLimits {
+    /// Cap for each host's transfer
+    #[serde(default)]
+    pub per_host: Option<u64>,
+    /// Cap for all transfers of the deployment together
+    #[serde(default)]
+    pub total: Option<u64>,
+    /// Caps for individual hosts, overriding `per_host`
+    #[serde(default)]
+    pub hosts: HashMap<String, u64>,
+}
+
+impl BandwidthLimits {
+    pub fn per_host(mut self, bytes_per_sec: u64) -> Self {
+        self.per_host = Some(bytes_per_sec);
+        self
+    }
+
+    pub fn total(mut self, bytes_per_sec: u64) -> Self {
+        self.total = Some(bytes_per_sec);
+        self
+    }
+
+    pub fn host(mut self, host: impl Into<String>, bytes_per_sec: u64) -> Self {
+        self.hosts.insert(host.into(), bytes_per_sec);
+        self
+    }
+
+    /// Cap for `host`'s own transfer
+    pub fn host_limit(&self, host: &str) -> Option<u64> {
+        self.hosts.get(host).copied().or(self.per_host)
+    }
+
+    /// The lowest cap that applies to `host`, for copy commands that can
+    /// only be given a single rate
+    pub fn effective_limit(&self, host: &str) -> Option<u64> {
+        match (self.host_limit(host), self.total) {
+            (Some(host), Some(total)) => Some(host.min(total)),
+            (host, total) => host.or(total),
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.per_host.is_none() && self.total.is_none() && self.hosts.is_empty()
+    }
+}
+
+/// Paces transfers sharing it to an average of `bytes_per_sec`
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    next_free: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.max(1),
+            next_free: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    /// Wait until `bytes` may be sent. Each caller reserves the time its
+    /// bytes take at the configured rate, so concurrent callers queue up
+    /// behind each other.
+    pub async fn acquire(&self, bytes: u64) {
+        let start = {
+            let mut next_free = self.next_free.lock().await;
+            let start = (*next_free).max(Instant::now());
+            *next_free = start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+            start
+        };
+        tokio::time::sleep_until(start).await;
+    }
+}
+
+/// The rate limiters for one deployer, created from [`BandwidthLimits`]
+#[derive(Debug, Default)]
+pub struct Bandwidth {
+    limits: BandwidthLimits,
+    total: Option<Arc<RateLimiter>>,
+    hosts: std::sync::Mutex<HashMap<String, Arc<RateLimiter>>>,
+}
+
+impl Bandwidth {
+    pub fn new(limits: BandwidthLimits) -> Self {
+        Self {
+            total: limits.total.map(|rate| Arc::new(RateLimiter::new(rate))),
+            limits,
+            hosts: Default::default(),
+        }
+    }
+
+    pub fn limits(&self) -> &BandwidthLimits {
+        &self.limits
+    }
+
+    /// Every limiter a transfer to `host` has to pass
+    pub fn limiters(&self, host: &str) -> Vec<Arc<RateLimiter>> {
+        let mut limiters = Vec::new();
+        if let Some(rate) = self.limits.host_limit(host) {
+            let mut hosts = self.hosts.lock().unwrap_or_else(PoisonError::into_inner);
+            let limiter = hosts
+                .entry(host.to_string())
+                .or_insert_with(|| Arc::new(RateLimiter::new(rate)));
+            limiters.push(limiter.clone());
+        }
+        limiters.extend(self.total.clone());
+        limiters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rate_limiter_paces_callers() {
+        let limiter = RateLimiter::new(10_000);
+        let start = Instant::now();
+
+        // The first reservation starts immediately, the rest wait their turn
+        limiter.acquire(500).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+        limiter.acquire(2000).await;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        limiter.acquire(1).await;
+        assert!(start.elapsed() >= Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_host_limits_and_shared_limiters() {
+        let limits = BandwidthLimits::default()
+            .per_host(1000)
+            .total(1500)
+            .host("slow", 100);
+        assert_eq!(limits.host_limit("web1"), Some(1000));
+        assert_eq!(limits.host_limit("slow"), Some(100));
+        assert_eq!(limits.effective_limit("web1"), Some(1000));
+        assert_eq!(
+            BandwidthLimits::default().total(10).effective_limit("web1"),
+            Some(10)
+        );
+
+        let bandwidth = Bandwidth::new(limits);
+        let web1 = bandwidth.limiters("web1");
+        let web2 = bandwidth.limiters("web2");
+        assert_eq!(web1.len(), 2);
+        assert!(Arc::ptr_eq(&web1[0], &bandwidth.limiters("web1")[0]));
+        assert!(!Arc::ptr_eq(&web1[0], &web2[0]));
+        assert!(Arc::ptr_eq(&web1[1], &web2[1]));
+        assert!(Bandwidth::default().limiters("web1").is_empty());
+    }
+}
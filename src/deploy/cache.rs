@@ -1,8 +1,10 @@
+use crate::compilation::store::CacheStore;
 use crate::deploy::Result;
 use crate::types::CompiledBinary;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::fs;
 use tracing::{debug, info, warn};
 
@@ -10,6 +12,7 @@ use tracing::{debug, info, warn};
 pub struct CompilationCache {
     cache_dir: PathBuf,
     memory_cache: std::sync::Arc<tokio::sync::RwLock<HashMap<String, CacheEntry>>>,
+    store: Option<Arc<dyn CacheStore>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,9 +42,16 @@ impl CompilationCache {
         Self {
             cache_dir,
             memory_cache: std::sync::Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            store: None,
         }
     }
 
+    /// Share binaries through `store` as well as the cache directory
+    pub fn with_store(mut self, store: Arc<dyn CacheStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
     pub async fn initialize(&self) -> Result<()> {
         info!("Initializing compilation cache at {:?}", self.cache_dir);
 
@@ -96,6 +106,75 @@ impl CompilationCache {
         })
     }
 
+    /// The binary cached locally or, failing that, in the shared store; a
+    /// fetched binary is kept in the cache directory
+    pub async fn fetch_binary(&self, checksum: &str) -> Option<CompiledBinary> {
+        if let Some(cached) = self.get_cached_binary(checksum) {
+            return Some(cached);
+        }
+        let store = self.store.as_ref()?;
+        let artifact = match store.fetch_artifact(checksum).await {
+            Ok(artifact) => artifact?,
+            Err(e) => {
+                warn!(
+                    "Failed to fetch {} from {}: {}",
+                    checksum,
+                    store.describe(),
+                    e
+                );
+                return None;
+            }
+        };
+
+        info!("Using binary {} from {}", checksum, store.describe());
+        let binary = CompiledBinary {
+            compilation_id: checksum.to_string(),
+            target_triple: artifact.metadata.target_triple.clone(),
+            size: artifact.metadata.size,
+            checksum: artifact.metadata.checksum.clone(),
+            binary_data: artifact.data,
+            compilation_time: std::time::Duration::ZERO,
+            optimization_level: crate::types::compilation::OptimizationLevel::Release,
+            source_info: crate::types::compilation::BinarySourceInfo {
+                source_type: crate::types::compilation::BinarySourceType::Cache {
+                    cache_path: self
+                        .cache_dir
+                        .join("binaries")
+                        .join(format!("{checksum}.bin")),
+                },
+                template_hash: "shared".to_string(),
+                build_metadata: crate::types::compilation::BuildMetadata {
+                    created_at: artifact.metadata.created_at,
+                    toolchain_version: "unknown".to_string(),
+                    features: Vec::new(),
+                },
+            },
+        };
+        if let Err(e) = self.store_binary(checksum, &binary) {
+            warn!("Failed to cache shared binary {}: {}", checksum, e);
+        }
+        Some(binary)
+    }
+
+    /// Publish `binary` to the shared store, if there is one; a failure
+    /// only costs other machines a rebuild
+    pub async fn share_binary(&self, checksum: &str, binary: &CompiledBinary) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        if let Err(e) = store
+            .put_artifact(checksum, &binary.binary_data, &binary.target_triple)
+            .await
+        {
+            warn!(
+                "Failed to publish {} to {}: {}",
+                checksum,
+                store.describe(),
+                e
+            );
+        }
+    }
+
     pub fn store_binary(&self, checksum: &str, binary: &CompiledBinary) -> Result<()> {
         let binary_filename = format!("{checksum}.bin");
         let metadata_filename = format!("{checksum}.json");
@@ -1,18 +1,30 @@
+use crate::compilation::output_unless_cancelled;
 use crate::deploy::{CompilationCache, DeployError, Result};
 use crate::types::*;
 use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
 use tokio::fs;
 use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, info};
 
 pub struct BinaryCompiler {
     cache: CompilationCache,
+    cancel: CancellationToken,
 }
 
 impl BinaryCompiler {
     pub fn new(cache: CompilationCache) -> Self {
-        Self { cache }
+        Self {
+            cache,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Kill the build in progress once `cancel` is cancelled
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
     }
 
     pub async fn compile_binary(&self, compilation: &BinaryCompilation) -> Result<CompiledBinary> {
@@ -35,7 +47,7 @@ impl BinaryCompiler {
 
         // Check cache first (with module checksum included)
         let cache_key = self.calculate_compilation_checksum(compilation, compiled_modules);
-        if let Some(cached) = self.check_cache(&cache_key) {
+        if let Some(cached) = self.cache.fetch_binary(&cache_key).await {
             info!("Using cached binary for {}", compilation.binary_name);
             return Ok(cached);
         }
@@ -87,6 +99,7 @@ impl BinaryCompiler {
 
         // Cache the result
         self.cache.store_binary(&cache_key, &compiled_binary)?;
+        self.cache.share_binary(&cache_key, &compiled_binary).await;
 
         info!(
             "Compilation completed for {} in {:?}",
@@ -155,13 +168,13 @@ impl BinaryCompiler {
 
         // Execute compilation
         debug!("Running cargo build command");
-        let output = cmd
-            .output()
+        let output = output_unless_cancelled(&mut cmd, &self.cancel)
             .await
             .map_err(|e| DeployError::CompilationFailed {
                 target: target_triple.to_string(),
                 reason: format!("Failed to execute cargo: {e}"),
-            })?;
+            })?
+            .ok_or(DeployError::Cancelled)?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -476,6 +489,9 @@ anyhow = "1"
 tracing = "0.1"
 tracing-subscriber = "0.3"
 async-trait = "0.1"
+ed25519-dalek = "2"
+base64 = "0.22"
+sha2 = "0.10"
 "#,
             compilation.binary_name
         );
@@ -0,0 +1,119 @@
+//! Runs the binary inside a chroot on the controller, for
+//! `ansible_connection: chroot`. Files are written straight into the chroot
+//! directory; commands go through `chroot(8)`, which needs root.
+
+use crate::deploy::connection::local::{
+    assemble_file, copy_file, decompress_file, file_sha256, read_file, remove_file, rename_file,
+    write_file,
+};
+use crate::deploy::connection::{run_process, ConnectionPlugin, FilePart};
+use crate::deploy::ssh::{CommandResult, OutputChunk};
+use crate::deploy::Result;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::debug;
+
+pub struct ChrootConnection {
+    host: String,
+    root: PathBuf,
+}
+
+impl ChrootConnection {
+    pub fn new(host: &str, root: impl Into<PathBuf>) -> Self {
+        Self {
+            host: host.to_string(),
+            root: root.into(),
+        }
+    }
+
+    /// Controller path of `path` as seen from inside the chroot
+    pub fn host_path(&self, path: &str) -> PathBuf {
+        self.root.join(path.trim_start_matches('/'))
+    }
+
+    fn chroot_command(&self) -> Command {
+        let mut command = Command::new("chroot");
+        command.arg(&self.root);
+        command
+    }
+}
+
+#[async_trait]
+impl ConnectionPlugin for ChrootConnection {
+    fn host(&self) -> &str {
+        &self.host
+    }
+
+    fn transport(&self) -> &'static str {
+        "chroot"
+    }
+
+    async fn execute(
+        &self,
+        command: &str,
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        debug!(
+            "Executing command in chroot {}: {}",
+            self.root.display(),
+            command
+        );
+        let mut chroot = self.chroot_command();
+        chroot.arg("/bin/sh").arg("-c").arg(command);
+        run_process(&self.host, chroot, None, sink).await
+    }
+
+    async fn upload(&self, data: &[u8], path: &str, mode: u32) -> Result<()> {
+        write_file(&self.host, &self.host_path(path), data, mode).await
+    }
+
+    async fn execute_program(
+        &self,
+        program: &str,
+        args: &[String],
+        env: &[(&str, &str)],
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        let mut chroot = self.chroot_command();
+        chroot.arg(program).args(args).envs(env.iter().copied());
+        run_process(&self.host, chroot, None, sink).await
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        rename_file(&self.host, &self.host_path(from), &self.host_path(to)).await
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<bool> {
+        copy_file(&self.host, &self.host_path(from), &self.host_path(to)).await
+    }
+
+    async fn remove(&self, path: &str) -> Result<()> {
+        remove_file(&self.host, &self.host_path(path)).await
+    }
+
+    async fn assemble(&self, output: &str, parts: &[FilePart], mode: u32) -> Result<()> {
+        let parts: Vec<_> = parts
+            .iter()
+            .map(|part| (self.host_path(&part.path), part.offset, part.len))
+            .collect();
+        assemble_file(&self.host, &self.host_path(output), &parts, mode).await
+    }
+
+    async fn supports_zstd(&self) -> bool {
+        true
+    }
+
+    async fn decompress_zstd(&self, from: &str, to: &str) -> Result<()> {
+        decompress_file(&self.host, &self.host_path(from), &self.host_path(to)).await
+    }
+
+    async fn read_to_string(&self, path: &str) -> Result<Option<String>> {
+        read_file(&self.host_path(path)).await
+    }
+
+    async fn sha256(&self, path: &str) -> Result<Option<String>> {
+        file_sha256(&self.host_path(path)).await
+    }
+}
@@ -0,0 +1,161 @@
+//! Runs the binary inside an already running container, for
+//! `ansible_connection: docker` and `ansible_connection: podman`.
+//!
+//! Everything goes through `<runtime> exec`, so the usual client settings
+//! (`DOCKER_HOST`, `CONTAINER_HOST`, contexts) select the engine.
+
+use crate::deploy::connection::{check_result, run_process, ConnectionPlugin};
+use crate::deploy::ssh::{remote_parent, shell_quote, CommandResult, OutputChunk};
+use crate::deploy::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::debug;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContainerRuntime {
+    Docker,
+    Podman,
+}
+
+impl ContainerRuntime {
+    pub fn executable(&self) -> &'static str {
+        match self {
+            Self::Docker => "docker",
+            Self::Podman => "podman",
+        }
+    }
+}
+
+pub struct ContainerConnection {
+    host: String,
+    runtime: ContainerRuntime,
+    container: String,
+    user: Option<String>,
+}
+
+impl ContainerConnection {
+    pub fn new(host: &str, runtime: ContainerRuntime, container: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            runtime,
+            container: container.to_string(),
+            user: None,
+        }
+    }
+
+    /// Run commands as `user` inside the container instead of its default user
+    pub fn with_user(mut self, user: Option<&str>) -> Self {
+        self.user = user.map(str::to_string);
+        self
+    }
+
+    /// Arguments for `<runtime> exec`, up to and including the container name
+    fn exec_args(&self, interactive: bool, env: &[(&str, &str)]) -> Vec<String> {
+        let mut args = vec!["exec".to_string()];
+        if interactive {
+            args.push("-i".to_string());
+        }
+        if let Some(ref user) = self.user {
+            args.extend(["-u".to_string(), user.clone()]);
+        }
+        for (name, value) in env {
+            args.extend(["-e".to_string(), format!("{name}={value}")]);
+        }
+        args.push(self.container.clone());
+        args
+    }
+
+    fn exec_command(&self, interactive: bool, env: &[(&str, &str)]) -> Command {
+        let mut command = Command::new(self.runtime.executable());
+        command.args(self.exec_args(interactive, env));
+        command
+    }
+}
+
+#[async_trait]
+impl ConnectionPlugin for ContainerConnection {
+    fn host(&self) -> &str {
+        &self.host
+    }
+
+    fn transport(&self) -> &'static str {
+        self.runtime.executable()
+    }
+
+    async fn execute(
+        &self,
+        command: &str,
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        debug!(
+            "Executing command in container {}: {}",
+            self.container, command
+        );
+        let mut exec = self.exec_command(false, &[]);
+        exec.arg("sh").arg("-c").arg(command);
+        run_process(&self.host, exec, None, sink).await
+    }
+
+    async fn upload(&self, data: &[u8], path: &str, mode: u32) -> Result<()> {
+        let quoted = shell_quote(path);
+        let mut script = String::new();
+        if let Some(parent) = remote_parent(path) {
+            script.push_str(&format!("mkdir -p {} && ", shell_quote(&parent)));
+        }
+        script.push_str(&format!("cat > {quoted} && chmod {mode:o} {quoted}"));
+
+        let mut exec = self.exec_command(true, &[]);
+        exec.arg("sh").arg("-c").arg(&script);
+        let result = run_process(&self.host, exec, Some(data), None).await?;
+        check_result(&self.host, &format!("Failed to upload {path}"), result)?;
+
+        debug!(
+            "Uploaded {} bytes to {}:{}",
+            data.len(),
+            self.container,
+            path
+        );
+        Ok(())
+    }
+
+    async fn execute_program(
+        &self,
+        program: &str,
+        args: &[String],
+        env: &[(&str, &str)],
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        let mut exec = self.exec_command(false, env);
+        exec.arg(program).args(args);
+        run_process(&self.host, exec, None, sink).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exec_args() {
+        let connection = ContainerConnection::new("web", ContainerRuntime::Podman, "web-1")
+            .with_user(Some("app"));
+        assert_eq!(
+            connection.exec_args(true, &[("RUSTLE_HOST_ID", "web")]),
+            [
+                "exec",
+                "-i",
+                "-u",
+                "app",
+                "-e",
+                "RUSTLE_HOST_ID=web",
+                "web-1"
+            ]
+        );
+        assert_eq!(
+            ContainerConnection::new("db", ContainerRuntime::Docker, "db").exec_args(false, &[]),
+            ["exec", "db"]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Runs the binary inside a container of a Kubernetes pod, for
+//! `ansible_connection: kubectl`.
+//!
+//! Everything goes through `kubectl exec`, with the namespace, container,
+//! context and kubeconfig of the host's `ansible_kubectl_*` variables; the
+//! usual client settings apply otherwise.
+
+use crate::deploy::connection::{check_result, redact_env, run_process, ConnectionPlugin};
+use crate::deploy::ssh::{remote_parent, shell_quote, CommandResult, OutputChunk};
+use crate::deploy::Result;
+use async_trait::async_trait;
+use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::debug;
+
+pub struct KubectlConnection {
+    host: String,
+    pod: String,
+    namespace: Option<String>,
+    container: Option<String>,
+    context: Option<String>,
+    kubeconfig: Option<String>,
+}
+
+impl KubectlConnection {
+    pub fn new(host: &str, pod: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            pod: pod.to_string(),
+            namespace: None,
+            container: None,
+            context: None,
+            kubeconfig: None,
+        }
+    }
+
+    pub fn with_namespace(mut self, namespace: Option<&str>) -> Self {
+        self.namespace = namespace.map(str::to_string);
+        self
+    }
+
+    /// Run in `container` instead of the pod's default container
+    pub fn with_container(mut self, container: Option<&str>) -> Self {
+        self.container = container.map(str::to_string);
+        self
+    }
+
+    pub fn with_context(mut self, context: Option<&str>) -> Self {
+        self.context = context.map(str::to_string);
+        self
+    }
+
+    pub fn with_kubeconfig(mut self, kubeconfig: Option<&str>) -> Self {
+        self.kubeconfig = kubeconfig.map(str::to_string);
+        self
+    }
+
+    /// Arguments for `kubectl`, up to and including the `--` before the
+    /// command
+    fn exec_args(&self, interactive: bool) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(ref kubeconfig) = self.kubeconfig {
+            args.extend(["--kubeconfig".to_string(), kubeconfig.clone()]);
+        }
+        if let Some(ref context) = self.context {
+            args.extend(["--context".to_string(), context.clone()]);
+        }
+        if let Some(ref namespace) = self.namespace {
+            args.extend(["-n".to_string(), namespace.clone()]);
+        }
+        args.push("exec".to_string());
+        if interactive {
+            args.push("-i".to_string());
+        }
+        if let Some(ref container) = self.container {
+            args.extend(["-c".to_string(), container.clone()]);
+        }
+        args.extend([self.pod.clone(), "--".to_string()]);
+        args
+    }
+
+    fn exec_command(&self, interactive: bool) -> Command {
+        let mut command = Command::new("kubectl");
+        command.args(self.exec_args(interactive));
+        command
+    }
+}
+
+#[async_trait]
+impl ConnectionPlugin for KubectlConnection {
+    fn host(&self) -> &str {
+        &self.host
+    }
+
+    fn transport(&self) -> &'static str {
+        "kubectl"
+    }
+
+    async fn execute(
+        &self,
+        command: &str,
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        debug!(
+            "Executing command in pod {}: {}",
+            self.pod,
+            redact_env(command)
+        );
+        let mut exec = self.exec_command(false);
+        exec.arg("sh").arg("-c").arg(command);
+        run_process(&self.host, exec, None, sink).await
+    }
+
+    async fn upload(&self, data: &[u8], path: &str, mode: u32) -> Result<()> {
+        let quoted = shell_quote(path);
+        let mut script = String::new();
+        if let Some(parent) = remote_parent(path) {
+            script.push_str(&format!("mkdir -p {} && ", shell_quote(&parent)));
+        }
+        script.push_str(&format!("cat > {quoted} && chmod {mode:o} {quoted}"));
+
+        let mut exec = self.exec_command(true);
+        exec.arg("sh").arg("-c").arg(&script);
+        let result = run_process(&self.host, exec, Some(data), None).await?;
+        check_result(&self.host, &format!("Failed to upload {path}"), result)?;
+
+        debug!("Uploaded {} bytes to {}:{}", data.len(), self.pod, path);
+        Ok(())
+    }
+
+    async fn execute_program(
+        &self,
+        program: &str,
+        args: &[String],
+        env: &[(&str, &str)],
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        // `kubectl exec` cannot set variables; `env` does it in the pod
+        let mut exec = self.exec_command(false);
+        if !env.is_empty() {
+            exec.arg("env");
+            exec.args(env.iter().map(|(name, value)| format!("{name}={value}")));
+        }
+        exec.arg(program).args(args);
+        run_process(&self.host, exec, None, sink).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exec_args() {
+        let connection = KubectlConnection::new("web-0_app", "web-0")
+            .with_namespace(Some("shop"))
+            .with_container(Some("app"))
+            .with_context(Some("prod"));
+        assert_eq!(
+            connection.exec_args(true),
+            [
+                "--context",
+                "prod",
+                "-n",
+                "shop",
+                "exec",
+                "-i",
+                "-c",
+                "app",
+                "web-0",
+                "--"
+            ]
+        );
+        assert_eq!(
+            KubectlConnection::new("db", "db-0").exec_args(false),
+            ["exec", "db-0", "--"]
+        );
+    }
+}
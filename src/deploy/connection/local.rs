@@ -0,0 +1,291 @@
+//! Runs the binary on the controller itself, for `ansible_connection: local`.
+
+use crate::deploy::connection::{redact_env, run_process, ConnectionPlugin, FilePart};
+use crate::deploy::ssh::{CommandResult, OutputChunk};
+use crate::deploy::{DeployError, Result};
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::debug;
+
+pub struct LocalConnection {
+    host: String,
+}
+
+impl LocalConnection {
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl ConnectionPlugin for LocalConnection {
+    fn host(&self) -> &str {
+        &self.host
+    }
+
+    fn transport(&self) -> &'static str {
+        "local"
+    }
+
+    async fn execute(
+        &self,
+        command: &str,
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        debug!(
+            "Executing local command for {}: {}",
+            self.host,
+            redact_env(command)
+        );
+        run_process(&self.host, shell_command(command), None, sink).await
+    }
+
+    async fn upload(&self, data: &[u8], path: &str, mode: u32) -> Result<()> {
+        write_file(&self.host, Path::new(path), data, mode).await
+    }
+
+    async fn execute_program(
+        &self,
+        program: &str,
+        args: &[String],
+        env: &[(&str, &str)],
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        let mut command = Command::new(program);
+        command.args(args).envs(env.iter().copied());
+        run_process(&self.host, command, None, sink).await
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        rename_file(&self.host, Path::new(from), Path::new(to)).await
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<bool> {
+        copy_file(&self.host, Path::new(from), Path::new(to)).await
+    }
+
+    async fn remove(&self, path: &str) -> Result<()> {
+        remove_file(&self.host, Path::new(path)).await
+    }
+
+    async fn assemble(&self, output: &str, parts: &[FilePart], mode: u32) -> Result<()> {
+        let parts: Vec<_> = parts
+            .iter()
+            .map(|part| (PathBuf::from(&part.path), part.offset, part.len))
+            .collect();
+        assemble_file(&self.host, Path::new(output), &parts, mode).await
+    }
+
+    async fn supports_zstd(&self) -> bool {
+        true
+    }
+
+    async fn decompress_zstd(&self, from: &str, to: &str) -> Result<()> {
+        decompress_file(&self.host, Path::new(from), Path::new(to)).await
+    }
+
+    async fn read_to_string(&self, path: &str) -> Result<Option<String>> {
+        read_file(Path::new(path)).await
+    }
+
+    async fn sha256(&self, path: &str) -> Result<Option<String>> {
+        file_sha256(Path::new(path)).await
+    }
+}
+
+#[cfg(unix)]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+/// Write `data` to a path on the controller's filesystem, creating parents.
+pub(super) async fn write_file(host: &str, path: &Path, data: &[u8], mode: u32) -> Result<()> {
+    let write_error = |e: std::io::Error| DeployError::DeploymentFailed {
+        host: host.to_string(),
+        reason: format!("Failed to write {}: {e}", path.display()),
+    };
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(write_error)?;
+    }
+    tokio::fs::write(path, data).await.map_err(write_error)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .await
+            .map_err(write_error)?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    debug!("Wrote {} bytes to {}", data.len(), path.display());
+    Ok(())
+}
+
+/// Concatenate `(path, offset, len)` ranges of controller files into `output`.
+pub(super) async fn assemble_file(
+    host: &str,
+    output: &Path,
+    parts: &[(PathBuf, u64, u64)],
+    mode: u32,
+) -> Result<()> {
+    let assemble_error = |e: std::io::Error| DeployError::DeploymentFailed {
+        host: host.to_string(),
+        reason: format!("Failed to assemble {}: {e}", output.display()),
+    };
+
+    let mut data = Vec::new();
+    for (path, offset, len) in parts {
+        let mut file = tokio::fs::File::open(path).await.map_err(assemble_error)?;
+        file.seek(SeekFrom::Start(*offset))
+            .await
+            .map_err(assemble_error)?;
+        let start = data.len();
+        data.resize(start + *len as usize, 0);
+        file.read_exact(&mut data[start..])
+            .await
+            .map_err(assemble_error)?;
+    }
+    write_file(host, output, &data, mode).await
+}
+
+/// Decompress the zstd file `from` into `to`, keeping the mode of `from`.
+pub(super) async fn decompress_file(host: &str, from: &Path, to: &Path) -> Result<()> {
+    let decompress_error = |e: std::io::Error| DeployError::DeploymentFailed {
+        host: host.to_string(),
+        reason: format!("Failed to decompress {}: {e}", from.display()),
+    };
+
+    let compressed = tokio::fs::read(from).await.map_err(decompress_error)?;
+    let data = zstd::decode_all(compressed.as_slice()).map_err(decompress_error)?;
+    let mode = file_mode(from).await.map_err(decompress_error)?;
+    write_file(host, to, &data, mode).await
+}
+
+#[cfg(unix)]
+async fn file_mode(path: &Path) -> std::io::Result<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(tokio::fs::metadata(path).await?.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+async fn file_mode(_path: &Path) -> std::io::Result<u32> {
+    Ok(0o644)
+}
+
+pub(super) async fn rename_file(host: &str, from: &Path, to: &Path) -> Result<()> {
+    tokio::fs::rename(from, to)
+        .await
+        .map_err(|e| DeployError::DeploymentFailed {
+            host: host.to_string(),
+            reason: format!("Failed to move {} to {}: {e}", from.display(), to.display()),
+        })
+}
+
+pub(super) async fn copy_file(host: &str, from: &Path, to: &Path) -> Result<bool> {
+    match tokio::fs::copy(from, to).await {
+        Ok(_) => Ok(true),
+        Err(_) if !from.is_file() => Ok(false),
+        Err(e) => Err(DeployError::DeploymentFailed {
+            host: host.to_string(),
+            reason: format!("Failed to copy {} to {}: {e}", from.display(), to.display()),
+        }),
+    }
+}
+
+pub(super) async fn remove_file(host: &str, path: &Path) -> Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(DeployError::DeploymentFailed {
+            host: host.to_string(),
+            reason: format!("Failed to remove {}: {e}", path.display()),
+        }),
+        _ => Ok(()),
+    }
+}
+
+pub(super) async fn read_file(path: &Path) -> Result<Option<String>> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub(super) async fn file_sha256(path: &Path) -> Result<Option<String>> {
+    match tokio::fs::read(path).await {
+        Ok(data) => Ok(Some(format!("{:x}", Sha256::digest(&data)))),
+        Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_upload_rename_and_execute() {
+        let dir = tempfile::tempdir().unwrap();
+        let connection = LocalConnection::new("localhost");
+        let temp_path = dir.path().join("bin/.runner.tmp");
+        let target_path = dir.path().join("bin/runner");
+        let temp_path = temp_path.to_str().unwrap();
+        let target_path = target_path.to_str().unwrap();
+
+        let script = b"#!/bin/sh\necho \"$GREETING $1\"\necho oops >&2\nexit 3\n";
+        connection.upload(script, temp_path, 0o755).await.unwrap();
+        connection.rename(temp_path, target_path).await.unwrap();
+        assert_eq!(connection.sha256(temp_path).await.unwrap(), None);
+        assert_eq!(
+            connection.sha256(target_path).await.unwrap(),
+            Some(format!("{:x}", Sha256::digest(script)))
+        );
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let result = connection
+            .execute_program(
+                target_path,
+                &["world".to_string()],
+                &[("GREETING", "hello")],
+                Some(sender),
+            )
+            .await
+            .unwrap();
+        assert!(!result.success);
+        assert_eq!(result.exit_code, 3);
+        assert_eq!(result.stdout, "hello world\n");
+        assert_eq!(result.stderr, "oops\n");
+
+        let mut streamed = String::new();
+        while let Some(chunk) = receiver.recv().await {
+            assert_eq!(chunk.host, "localhost");
+            streamed.push_str(&chunk.data);
+        }
+        assert!(streamed.contains("hello world"));
+
+        connection.remove(target_path).await.unwrap();
+        connection.remove(target_path).await.unwrap();
+        assert_eq!(connection.sha256(target_path).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,339 @@
+//! Transports used to place the runner binary on a target and run it.
+//!
+//! Every [`DeploymentMethod`](crate::types::DeploymentMethod) except the
+//! external copy commands (scp, rsync, custom) is backed by a
+//! [`ConnectionPlugin`]: SSH and WinRM for remote hosts, [`LocalConnection`]
+//! for the controller itself, [`ContainerConnection`] for running Docker or
+//! Podman containers, [`KubectlConnection`] for containers of Kubernetes
+//! pods and [`ChrootConnection`] for a chroot on the controller.
+//! The deployer only talks to the trait, so a new transport needs an
+//! implementation and a deployment method that selects it.
+
+pub mod chroot;
+pub mod container;
+pub mod kubectl;
+pub mod local;
+
+pub use chroot::ChrootConnection;
+pub use container::{ContainerConnection, ContainerRuntime};
+pub use kubectl::KubectlConnection;
+pub use local::LocalConnection;
+
+use crate::binary::platform::{HostPlatform, POSIX_PROBE_SCRIPT};
+use crate::deploy::ssh::{shell_quote, CommandResult, OutputChunk, OutputStream};
+use crate::deploy::{DeployError, Result};
+use async_trait::async_trait;
+use regex::Regex;
+use std::borrow::Cow;
+use std::process::Stdio;
+use std::sync::OnceLock;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// A way of running commands on, and copying files to, one deployment target.
+///
+/// Only [`execute`](Self::execute) and [`upload`](Self::upload) are required.
+/// The remaining operations default to POSIX shell commands run through
+/// `execute`; transports without a POSIX shell, or with cheaper native
+/// equivalents, override them.
+#[async_trait]
+pub trait ConnectionPlugin: Send + Sync {
+    /// Inventory name of the target, used in output chunks and errors
+    fn host(&self) -> &str;
+
+    /// Short transport name for logs, e.g. `ssh` or `docker`
+    fn transport(&self) -> &'static str;
+
+    /// Run `command` in the target's shell (`sh`, or PowerShell over WinRM),
+    /// forwarding output chunks to `sink` as they arrive.
+    async fn execute(
+        &self,
+        command: &str,
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult>;
+
+    /// Write `data` to `path`, creating its parent directory, and apply
+    /// `mode` where the target supports Unix permissions.
+    async fn upload(&self, data: &[u8], path: &str, mode: u32) -> Result<()>;
+
+    /// Run `program` with `args` and additional environment variables.
+    async fn execute_program(
+        &self,
+        program: &str,
+        args: &[String],
+        env: &[(&str, &str)],
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        let mut command = String::new();
+        for (name, value) in env {
+            command.push_str(&format!("{name}={} ", shell_quote(value)));
+        }
+        command.push_str(&shell_quote(program));
+        for arg in args {
+            command.push(' ');
+            command.push_str(&shell_quote(arg));
+        }
+        self.execute(&command, sink).await
+    }
+
+    /// Replace `to` with `from`; atomic when both are on the same filesystem.
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let command = format!("mv -f {} {}", shell_quote(from), shell_quote(to));
+        let result = self.execute(&command, None).await?;
+        check_result(
+            self.host(),
+            &format!("Failed to move {from} to {to}"),
+            result,
+        )
+    }
+
+    /// Copy `from` to `to`, keeping its mode. Returns `false` without
+    /// touching `to` if `from` is not a regular file.
+    async fn copy(&self, from: &str, to: &str) -> Result<bool> {
+        let (from_quoted, to_quoted) = (shell_quote(from), shell_quote(to));
+        let command = format!("test -f {from_quoted} || exit 3; cp -p {from_quoted} {to_quoted}");
+        let result = self.execute(&command, None).await?;
+        if result.exit_code == 3 {
+            return Ok(false);
+        }
+        check_result(
+            self.host(),
+            &format!("Failed to copy {from} to {to}"),
+            result,
+        )?;
+        Ok(true)
+    }
+
+    /// Remove `path`; succeeds if it does not exist.
+    async fn remove(&self, path: &str) -> Result<()> {
+        let result = self
+            .execute(&format!("rm -f {}", shell_quote(path)), None)
+            .await?;
+        check_result(self.host(), &format!("Failed to remove {path}"), result)
+    }
+
+    /// Write the concatenation of `parts` to `output` and apply `mode`.
+    async fn assemble(&self, output: &str, parts: &[FilePart], mode: u32) -> Result<()> {
+        let mut script = String::from("{\n:\n");
+        for part in parts {
+            let path = shell_quote(&part.path);
+            if part.offset == 0 {
+                script.push_str(&format!("head -c {} {path} || exit 1\n", part.len));
+            } else {
+                script.push_str(&format!(
+                    "tail -c +{} {path} | head -c {} || exit 1\n",
+                    part.offset + 1,
+                    part.len
+                ));
+            }
+        }
+        let output_quoted = shell_quote(output);
+        script.push_str(&format!(
+            "}} > {output_quoted} && chmod {mode:o} {output_quoted}"
+        ));
+
+        let result = self.execute(&script, None).await?;
+        check_result(self.host(), &format!("Failed to assemble {output}"), result)
+    }
+
+    /// Whether [`decompress_zstd`](Self::decompress_zstd) works on the target.
+    async fn supports_zstd(&self) -> bool {
+        self.execute("command -v zstd >/dev/null", None)
+            .await
+            .is_ok_and(|result| result.success)
+    }
+
+    /// Decompress the zstd file `from` into `to`, replacing `to`.
+    async fn decompress_zstd(&self, from: &str, to: &str) -> Result<()> {
+        let command = format!("zstd -d -q -f -o {} {}", shell_quote(to), shell_quote(from));
+        let result = self.execute(&command, None).await?;
+        check_result(self.host(), &format!("Failed to decompress {from}"), result)
+    }
+
+    /// Probe the target's OS, architecture, kernel and libc.
+    async fn probe_platform(&self) -> Result<HostPlatform> {
+        let result = self.execute(POSIX_PROBE_SCRIPT, None).await?;
+        parse_platform(self.host(), &result)
+    }
+
+    /// Contents of the text file at `path`, or `None` if it is not a regular file.
+    async fn read_to_string(&self, path: &str) -> Result<Option<String>> {
+        let quoted = shell_quote(path);
+        let result = self
+            .execute(&format!("test -f {quoted} && cat {quoted}"), None)
+            .await?;
+        Ok(result.success.then_some(result.stdout))
+    }
+
+    /// Lowercase hex SHA-256 of `path`, or `None` if it is not a regular file.
+    async fn sha256(&self, path: &str) -> Result<Option<String>> {
+        let quoted = shell_quote(path);
+        let command = format!("test -f {quoted} && sha256sum {quoted} | cut -d' ' -f1");
+        let result = self.execute(&command, None).await?;
+        let hash = result.stdout.trim();
+        Ok((result.success && !hash.is_empty()).then(|| hash.to_string()))
+    }
+}
+
+/// A byte range of a file already on the target
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePart {
+    pub path: String,
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Interpret the output of a platform probe
+pub(crate) fn parse_platform(host: &str, result: &CommandResult) -> Result<HostPlatform> {
+    HostPlatform::parse_probe(&result.stdout).ok_or_else(|| DeployError::DeploymentFailed {
+        host: host.to_string(),
+        reason: format!(
+            "Could not determine the host platform: {}",
+            result.stderr.trim()
+        ),
+    })
+}
+
+/// `command` with the values of the environment variables it sets masked,
+/// for logs: the `NAME=value` words a shell command starts with, as
+/// [`ConnectionPlugin::execute_program`] writes them, and the
+/// `$env:NAME = value` lines of a PowerShell script.
+pub(crate) fn redact_env(command: &str) -> Cow<'_, str> {
+    static ASSIGNMENTS: OnceLock<Regex> = OnceLock::new();
+    static ASSIGNMENT: OnceLock<Regex> = OnceLock::new();
+    static POWERSHELL: OnceLock<Regex> = OnceLock::new();
+    const VALUE: &str = r"(?:'[^']*'|\\.|[^\s'\\])*";
+
+    let assignments = ASSIGNMENTS.get_or_init(|| {
+        Regex::new(&format!(r"^(?:[A-Za-z_][A-Za-z0-9_]*={VALUE}\s+)+"))
+            .expect("assignments pattern is valid")
+    });
+    let assignment = ASSIGNMENT.get_or_init(|| {
+        Regex::new(&format!(r"([A-Za-z_][A-Za-z0-9_]*)={VALUE}"))
+            .expect("assignment pattern is valid")
+    });
+    let powershell = POWERSHELL.get_or_init(|| {
+        Regex::new(r"(?m)^(\$env:\w+ = ).*$").expect("PowerShell pattern is valid")
+    });
+
+    if let Some(prefix) = assignments.find(command) {
+        let masked = assignment.replace_all(prefix.as_str(), "$1=***");
+        return Cow::Owned(format!("{masked}{}", &command[prefix.end()..]));
+    }
+    powershell.replace_all(command, "${1}***")
+}
+
+/// Turn a failed command into a [`DeployError::DeploymentFailed`].
+pub(crate) fn check_result(host: &str, action: &str, result: CommandResult) -> Result<()> {
+    if result.success {
+        return Ok(());
+    }
+    Err(DeployError::DeploymentFailed {
+        host: host.to_string(),
+        reason: format!("{action}: {}", result.stderr.trim()),
+    })
+}
+
+/// Run a local process, optionally feeding it `stdin`, and forward its
+/// output to `sink` as it is produced.
+pub(crate) async fn run_process(
+    host: &str,
+    mut command: Command,
+    stdin: Option<&[u8]>,
+    sink: Option<UnboundedSender<OutputChunk>>,
+) -> Result<CommandResult> {
+    let program = command.as_std().get_program().to_string_lossy().to_string();
+    command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let mut child = command.spawn().map_err(|e| DeployError::DeploymentFailed {
+        host: host.to_string(),
+        reason: format!("Failed to run {program}: {e}"),
+    })?;
+
+    let stdin_pipe = child.stdin.take();
+    let write_stdin = async move {
+        if let (Some(data), Some(mut pipe)) = (stdin, stdin_pipe) {
+            // Closing the pipe on drop signals end of input
+            match pipe.write_all(data).await {
+                Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    };
+    let stdout = forward_output(
+        host,
+        child.stdout.take(),
+        OutputStream::Stdout,
+        sink.clone(),
+    );
+    let stderr = forward_output(host, child.stderr.take(), OutputStream::Stderr, sink);
+
+    let (written, stdout, stderr) = tokio::join!(write_stdin, stdout, stderr);
+    let status = child.wait().await?;
+    written?;
+
+    Ok(CommandResult {
+        success: status.success(),
+        exit_code: status.code().unwrap_or(-1),
+        stdout: stdout?,
+        stderr: stderr?,
+    })
+}
+
+async fn forward_output(
+    host: &str,
+    reader: Option<impl AsyncRead + Unpin>,
+    stream: OutputStream,
+    sink: Option<UnboundedSender<OutputChunk>>,
+) -> std::io::Result<String> {
+    let Some(mut reader) = reader else {
+        return Ok(String::new());
+    };
+
+    let mut output = Vec::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        if let Some(ref sink) = sink {
+            let _ = sink.send(OutputChunk {
+                host: host.to_string(),
+                stream,
+                data: String::from_utf8_lossy(&buf[..n]).to_string(),
+            });
+        }
+        output.extend_from_slice(&buf[..n]);
+    }
+    Ok(String::from_utf8_lossy(&output).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_values_are_redacted() {
+        assert_eq!(
+            redact_env("RUSTLE_HOST_ID=web1 RUSTLE_TOKEN='it'\\''s s3cret' /opt/runner --verbose"),
+            "RUSTLE_HOST_ID=*** RUSTLE_TOKEN=*** /opt/runner --verbose"
+        );
+        assert_eq!(
+            redact_env("$env:RUSTLE_TOKEN = 's3cret'\n& 'C:\\runner.exe'\nexit $LASTEXITCODE"),
+            "$env:RUSTLE_TOKEN = ***\n& 'C:\\runner.exe'\nexit $LASTEXITCODE"
+        );
+        // Assignments only count ahead of the command
+        assert_eq!(redact_env("echo A=b"), "echo A=b");
+    }
+}
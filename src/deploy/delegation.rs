@@ -0,0 +1,145 @@
+//! Controller side of delegated and run-once tasks.
+//!
+//! Runners hand tasks with `delegate_to` or `run_once` back to the controller
+//! (see [`crate::runtime::delegation`]). [`Delegation`] answers those requests
+//! for one execution of a deployment: it executes the task on the delegate
+//! target, or in-process for `localhost`, and the deployer delivers the result
+//! to the host that asked. A run-once task is executed for the first request
+//! only, on its delegate or else on the first target of the deployment, and
+//! every host receives that same result.
+
+use crate::deploy::{BinaryDeployer, DeployError, Result};
+use crate::execution::Task;
+use crate::runtime::{DelegatedResult, DelegationContext, LocalExecutor, RuntimeConfig, LOCALHOST};
+use crate::types::{DeploymentPlan, DeploymentTarget};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::OnceCell;
+use tracing::{info, warn};
+
+/// Answers the delegation requests of one execution
+#[derive(Debug, Default)]
+pub struct Delegation {
+    tasks: HashMap<String, Task>,
+    targets: Vec<DeploymentTarget>,
+    run_once: Mutex<HashMap<String, Arc<OnceCell<DelegatedResult>>>>,
+}
+
+impl Delegation {
+    pub fn new(plan: &DeploymentPlan) -> Self {
+        Self {
+            tasks: plan
+                .delegated_tasks
+                .iter()
+                .map(|task| (task.id.clone(), task.clone()))
+                .collect(),
+            targets: plan.deployment_targets.clone(),
+            run_once: Default::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// The result of `task_id` for the host described by `context`. Errors
+    /// become failed results, as the runner is waiting for one either way.
+    pub async fn resolve(
+        &self,
+        deployer: &BinaryDeployer,
+        task_id: &str,
+        context: DelegationContext,
+    ) -> DelegatedResult {
+        // Only tasks the plan delegates are run, whatever a runner asks for
+        let Some(task) = self.tasks.get(task_id) else {
+            return DelegatedResult::failed(
+                task_id,
+                task_id,
+                format!("Task {task_id} is not delegated by the deployment plan"),
+            );
+        };
+        if !task.run_once {
+            return self.execute(deployer, task, context).await;
+        }
+
+        let once = self
+            .run_once
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(task.id.clone())
+            .or_default()
+            .clone();
+        once.get_or_init(|| self.execute(deployer, task, context))
+            .await
+            .clone()
+    }
+
+    async fn execute(
+        &self,
+        deployer: &BinaryDeployer,
+        task: &Task,
+        context: DelegationContext,
+    ) -> DelegatedResult {
+        let result = match self.delegate(task) {
+            Ok(Some(target)) => {
+                info!(
+                    "Executing task {} on {} for {}",
+                    task.id, target.host, context.host
+                );
+                deployer
+                    .execute_delegated_task(target, &task.id, &context)
+                    .await
+            }
+            Ok(None) => {
+                info!(
+                    "Executing task {} on the controller for {}",
+                    task.id, context.host
+                );
+                execute_on_controller(task, context).await
+            }
+            Err(e) => Err(e),
+        };
+        result.unwrap_or_else(|e| {
+            warn!("Delegated task {} failed: {}", task.id, e);
+            DelegatedResult::failed(&task.id, &task.name, e.to_string())
+        })
+    }
+
+    /// The target `task` executes on, `None` for the controller
+    fn delegate(&self, task: &Task) -> Result<Option<&DeploymentTarget>> {
+        match task.delegate_to.as_deref() {
+            Some(LOCALHOST | "127.0.0.1") => Ok(None),
+            Some(host) => self
+                .targets
+                .iter()
+                .find(|target| target.host == host)
+                .map(Some)
+                .ok_or_else(|| {
+                    DeployError::Configuration(format!(
+                        "Task {} is delegated to {host}, which is not a target of the deployment",
+                        task.id
+                    ))
+                }),
+            None => self.targets.first().map(Some).ok_or_else(|| {
+                DeployError::Configuration("The deployment has no targets".to_string())
+            }),
+        }
+    }
+}
+
+/// Execute `task` with the controller's own module registry
+async fn execute_on_controller(task: &Task, context: DelegationContext) -> Result<DelegatedResult> {
+    let mut executor = LocalExecutor::new(RuntimeConfig::default());
+    if let Err(e) = executor.collect_facts() {
+        warn!("Failed to collect controller facts: {}", e);
+    }
+    let task_result = executor
+        .execute_delegated_task(task, context)
+        .await
+        .map_err(|e| DeployError::DelegationFailed {
+            task_id: task.id.clone(),
+            host: LOCALHOST.to_string(),
+            reason: e.to_string(),
+        })?;
+    Ok(DelegatedResult::new(task_result))
+}
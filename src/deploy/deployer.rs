@@ -1,12 +1,71 @@
+use crate::binary::platform::HostPlatform;
+use crate::deploy::bandwidth::{Bandwidth, BandwidthLimits};
+use crate::deploy::connection::{
+    ChrootConnection, ConnectionPlugin, ContainerConnection, ContainerRuntime, KubectlConnection,
+    LocalConnection,
+};
+use crate::deploy::delegation::Delegation;
+use crate::deploy::events::{EventSink, HostEvent};
+use crate::deploy::metrics::DeploymentMetrics;
+use crate::deploy::retry::{retry, DeployPhase, RetryConfig, RetryCounts};
+use crate::deploy::rollback::HostSnapshot;
+use crate::deploy::ssh::{shell_quote, OutputStream};
+use crate::deploy::ssh_config::{resolve_connection, SshConfig};
+use crate::deploy::strategy::Coordinator;
+use crate::deploy::transfer::{upload_binary, TransferCache, TransferOptions, TransferOutcome};
+use crate::deploy::verification::{ExecutionVerificationReport, ExecutionVerifier};
+use crate::deploy::wasi::{is_wasm_runner, WasmtimeExecutor};
+use crate::deploy::winrm::{WinRmConfig, WinRmConnectionManager};
 use crate::deploy::{DeployError, Result};
+use crate::runtime::delegation::result_file_name;
+use crate::runtime::strategy::{abort_file_name, release_file_name};
+use crate::runtime::{
+    decode_events, signature_path, BinarySignature, DelegatedResult, DelegationContext,
+    EventDecoder, FaultInjector, ProgressEvent, SealedSecrets, StreamItem, TraceContext,
+    TrustedKey, BECOME_PASSWORD_FILE_ENV, DELEGATED_TASK_ENV, DELEGATION_CONTEXT_ENV,
+    DELEGATION_DIR_ENV, EVENT_STREAM_ENV, HOST_ID_ENV, RESUME_ENV, SECRETS_FILE_ENV,
+    SECRETS_KEY_FILE_ENV, SIGNATURE_SUFFIX, STATE_FILE_ENV, STOP_FILE_ENV, SYNC_DIR_ENV,
+    TRACEPARENT_ENV,
+};
 use crate::types::*;
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::{Arc, PoisonError};
+use std::time::Duration;
 use tokio::process::Command;
+use tokio::sync::{mpsc, Mutex, OnceCell};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, warn};
 
+pub use crate::deploy::ssh::{CommandResult, OutputChunk, SshConnection, SshConnectionConfig};
+
+const EXECUTABLE_MODE: u32 = 0o755;
+
+/// How long a runner asked to stop may take to finish its current task
+const STOP_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+/// What runners are told when the deployment is cancelled
+const STOP_REASON: &str = "deployment cancelled by the controller";
+
 pub struct BinaryDeployer {
     connection_manager: ConnectionManager,
+    winrm_manager: WinRmConnectionManager,
+    faults: FaultInjector,
+    transfers: Option<TransferCache>,
+    retry: RetryConfig,
+    retries: std::sync::Mutex<HashMap<String, RetryCounts>>,
+    trusted_key: Option<TrustedKey>,
+    events: Option<EventSink>,
+    bandwidth: Bandwidth,
+    compression: Option<i32>,
+    become_password: Option<String>,
+    /// Sealed secrets and the key they open with
+    sealed_secrets: Option<(SealedSecrets, String)>,
+    resume: bool,
+    metrics: Option<Arc<DeploymentMetrics>>,
+    wasmtime: WasmtimeExecutor,
+    cancel: CancellationToken,
 }
 
 impl Default for BinaryDeployer {
@@ -19,14 +78,158 @@ impl BinaryDeployer {
     pub fn new() -> Self {
         Self {
             connection_manager: ConnectionManager::new(),
+            winrm_manager: WinRmConnectionManager::default(),
+            faults: FaultInjector::from_env_or(None),
+            transfers: None,
+            retry: RetryConfig::default(),
+            retries: Default::default(),
+            trusted_key: None,
+            events: None,
+            bandwidth: Bandwidth::default(),
+            compression: None,
+            become_password: None,
+            sealed_secrets: None,
+            resume: false,
+            metrics: None,
+            wasmtime: WasmtimeExecutor::default(),
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    pub fn with_ssh_config(config: SshConnectionConfig) -> Self {
+        Self {
+            connection_manager: ConnectionManager::with_config(config),
+            winrm_manager: WinRmConnectionManager::default(),
+            faults: FaultInjector::from_env_or(None),
+            transfers: None,
+            retry: RetryConfig::default(),
+            retries: Default::default(),
+            trusted_key: None,
+            events: None,
+            bandwidth: Bandwidth::default(),
+            compression: None,
+            become_password: None,
+            sealed_secrets: None,
+            resume: false,
+            metrics: None,
+            wasmtime: WasmtimeExecutor::default(),
+            cancel: CancellationToken::new(),
         }
     }
 
+    /// Use `config` as the defaults for hosts deployed over WinRM
+    pub fn with_winrm_config(mut self, config: WinRmConfig) -> Self {
+        self.winrm_manager = WinRmConnectionManager::new(config);
+        self
+    }
+
+    /// Track transfers in `cache` so redeployments send deltas and
+    /// interrupted transfers resume; see [`crate::deploy::transfer`]
+    pub fn with_transfer_cache(mut self, cache: TransferCache) -> Self {
+        self.transfers = Some(cache);
+        self
+    }
+
+    /// Retry transient failures according to `config`; see [`crate::deploy::retry`]
+    pub fn with_retry_config(mut self, config: RetryConfig) -> Self {
+        self.retry = config;
+        self
+    }
+
+    /// Retries made for `host` since the last call, by phase
+    pub fn take_retry_counts(&self, host: &str) -> RetryCounts {
+        self.retries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(host)
+            .unwrap_or_default()
+    }
+
+    /// Only deploy binaries signed with `key`, and check the deployed copy
+    /// against its signature before every execution
+    pub fn with_trusted_key(mut self, key: TrustedKey) -> Self {
+        self.trusted_key = Some(key);
+        self
+    }
+
+    /// Forward events streamed by runners to `sink` as they arrive; see
+    /// [`crate::deploy::events`]
+    pub fn with_event_sink(mut self, sink: EventSink) -> Self {
+        self.events = Some(sink);
+        self
+    }
+
+    /// Count the bytes sent to each host in `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<DeploymentMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Cap transfer rates per host and for the whole deployment; see
+    /// [`crate::deploy::bandwidth`]
+    pub fn with_bandwidth_limits(mut self, limits: BandwidthLimits) -> Self {
+        self.bandwidth = Bandwidth::new(limits);
+        self
+    }
+
+    /// Compress binaries with zstd at `level` while they are transferred,
+    /// for hosts that can decompress them
+    pub fn with_transfer_compression(mut self, level: i32) -> Self {
+        self.compression = Some(level);
+        self
+    }
+
+    /// Hand `password` to runners for tasks that become another user. It
+    /// travels as a file only the connecting user can read, which the runner
+    /// removes once read, rather than on the command line or in the environment
+    pub fn with_become_password(mut self, password: impl Into<String>) -> Self {
+        self.become_password = Some(password.into());
+        self
+    }
+
+    /// Hand `secrets` to runners for the references in their plans, along
+    /// with `key`, the secret key they open with. They travel as a file only
+    /// the connecting user can read, which the runner removes once read,
+    /// while the key is passed in the runner's environment.
+    pub fn with_sealed_secrets(mut self, secrets: SealedSecrets, key: impl Into<String>) -> Self {
+        self.sealed_secrets = Some((secrets, key.into()));
+        self
+    }
+
+    /// Have runners resume from the progress a failed run persisted next to
+    /// the binary; see [`crate::runtime::state`]
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Run WebAssembly runners with `executor`; see [`crate::deploy::wasi`]
+    pub fn with_wasmtime(mut self, executor: WasmtimeExecutor) -> Self {
+        self.wasmtime = executor;
+        self
+    }
+
+    /// Simulate upload corruption, crashes and partitions; see [`crate::runtime::fault_injection`]
+    pub fn with_fault_injector(mut self, faults: FaultInjector) -> Self {
+        self.faults = faults;
+        self
+    }
+
+    /// Abandon transfers once `token` is cancelled, and ask runners to stop
+    /// after their current task
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
     pub async fn deploy_to_host(
         &self,
         compilation: &BinaryCompilation,
         target: &DeploymentTarget,
     ) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            return Err(DeployError::Cancelled);
+        }
         info!("Deploying binary to host: {}", target.host);
 
         // Read the compiled binary
@@ -35,55 +238,88 @@ impl BinaryDeployer {
                 host: target.host.clone(),
                 reason: format!("Failed to read binary: {e}"),
             })?;
-
-        match target.deployment_method {
-            DeploymentMethod::Ssh => self.deploy_via_ssh(&binary_data, target).await,
-            DeploymentMethod::Scp => self.deploy_via_scp(&binary_data, target).await,
-            DeploymentMethod::Rsync => {
-                self.deploy_via_rsync(&compilation.output_path, target)
-                    .await
-            }
-            DeploymentMethod::Custom { ref command } => {
-                self.deploy_via_custom(command, &compilation.output_path, target)
-                    .await
+        let signature = self.check_signature(compilation, &binary_data, target)?;
+
+        // An abandoned transfer resumes with the next deployment when the
+        // transfers are cached
+        let transfer = self.with_retry(target, DeployPhase::Transfer, || async {
+            self.check_partition(target)?;
+            match target.deployment_method {
+                DeploymentMethod::Scp => self.deploy_via_scp(&binary_data, target).await,
+                DeploymentMethod::Rsync => {
+                    self.deploy_via_rsync(&compilation.output_path, target)
+                        .await
+                }
+                DeploymentMethod::Custom { ref command } => {
+                    self.deploy_via_custom(command, &compilation.output_path, target)
+                        .await
+                }
+                DeploymentMethod::Ssh
+                | DeploymentMethod::WinRm
+                | DeploymentMethod::Local
+                | DeploymentMethod::Docker { .. }
+                | DeploymentMethod::Podman { .. }
+                | DeploymentMethod::Kubectl { .. }
+                | DeploymentMethod::Chroot { .. } => {
+                    self.deploy_via_connection(&binary_data, target).await
+                }
             }
+        });
+        tokio::select! {
+            biased;
+            _ = self.cancel.cancelled() => return Err(DeployError::Cancelled),
+            result = transfer => result?,
         }
+
+        if let Some(signature) = signature {
+            self.upload_signature(&signature, target).await?;
+        }
+        Ok(())
+    }
+
+    /// Probe the platform of `target`; `None` for custom deployments, which
+    /// have no connection to probe through
+    pub async fn probe_platform(&self, target: &DeploymentTarget) -> Result<Option<HostPlatform>> {
+        if matches!(target.deployment_method, DeploymentMethod::Custom { .. }) {
+            return Ok(None);
+        }
+        self.check_partition(target)?;
+
+        let platform = self.connection(target).await?.probe_platform().await?;
+        debug!("Probed {}: {}", target.host, platform);
+        Ok(Some(platform))
     }
 
     pub async fn verify_deployment(&self, target: &DeploymentTarget) -> Result<bool> {
         info!("Verifying deployment on host: {}", target.host);
+        self.check_partition(target)?;
 
-        let connection = self.connection_manager.get_connection(&target.host).await?;
-
-        // Check if binary exists and is executable
-        let check_cmd = format!("test -x {}", target.target_path);
-        let result = connection.execute_command(&check_cmd).await?;
+        let connection = self.connection(target).await?;
 
-        if !result.success {
-            debug!("Binary not found or not executable on {}", target.host);
+        let Some(deployed_checksum) = connection.sha256(&target.target_path).await? else {
+            debug!("Binary not found on {}", target.host);
             return Ok(false);
-        }
+        };
 
         // Verify checksum if available
-        if !target.version.is_empty() {
-            let checksum_cmd = format!("sha256sum {} | cut -d' ' -f1", target.target_path);
-            let checksum_result = connection.execute_command(&checksum_cmd).await?;
-
-            if checksum_result.success {
-                let deployed_checksum = checksum_result.stdout.trim();
-                if deployed_checksum != target.version {
-                    warn!(
-                        "Checksum mismatch on {}: expected {}, got {}",
-                        target.host, target.version, deployed_checksum
-                    );
-                    return Ok(false);
-                }
-            }
+        if !target.version.is_empty() && deployed_checksum != target.version {
+            warn!(
+                "Checksum mismatch on {}: expected {}, got {}",
+                target.host, target.version, deployed_checksum
+            );
+            return Ok(false);
         }
 
         // Try to run binary with --version flag to ensure it's working
-        let version_cmd = format!("{} --version", target.target_path);
-        let version_result = connection.execute_command(&version_cmd).await?;
+        let version_result = self
+            .run_runner(
+                connection.as_ref(),
+                target,
+                &["--version".to_string()],
+                &[],
+                None,
+            )
+            .await?;
 
         if !version_result.success {
             debug!("Binary failed version check on {}", target.host);
@@ -94,24 +330,235 @@ impl BinaryDeployer {
         Ok(true)
     }
 
+    /// Run a shell command on `target` over its deployment connection
+    pub async fn run_command(
+        &self,
+        target: &DeploymentTarget,
+        command: &str,
+    ) -> Result<CommandResult> {
+        self.check_partition(target)?;
+        let connection = self.connection(target).await?;
+        connection.execute(command, None).await
+    }
+
     pub async fn execute_binary(
         &self,
         target: &DeploymentTarget,
         args: &[String],
     ) -> Result<ExecutionResult> {
-        info!("Executing binary on host: {}", target.host);
+        self.execute_binary_delegating(target, args, None, None, None)
+            .await
+    }
 
-        let connection = self.connection_manager.get_connection(&target.host).await?;
+    /// Execute the deployed binary, answering the delegated and run-once
+    /// tasks its runner hands back with `delegation`, and the tasks it
+    /// reaches with `coordinator`, which paces it against the other hosts;
+    /// see [`crate::deploy::delegation`] and [`crate::deploy::strategy`].
+    /// The runner records trace spans under `trace` if given.
+    pub async fn execute_binary_delegating(
+        &self,
+        target: &DeploymentTarget,
+        args: &[String],
+        delegation: Option<&Delegation>,
+        coordinator: Option<&Coordinator>,
+        trace: Option<&TraceContext>,
+    ) -> Result<ExecutionResult> {
+        let delegation = delegation.filter(|delegation| !delegation.is_empty());
+        // Fresh directories per run, so a result that arrives too late is never
+        // taken for one of a later run
+        let delegation_dir = delegation
+            .map(|_| format!("{}.delegated/{}", target.target_path, uuid::Uuid::new_v4()));
+        let sync_dir =
+            coordinator.map(|_| format!("{}.sync/{}", target.target_path, uuid::Uuid::new_v4()));
+
+        let (sender, mut receiver) = mpsc::unbounded_channel::<OutputChunk>();
+        let events = self.events.as_ref();
+        let forward = async {
+            let mut decoder = EventDecoder::new();
+            while let Some(chunk) = receiver.recv().await {
+                match chunk.stream {
+                    OutputStream::Stdout => {
+                        for item in decoder.push(&chunk.data) {
+                            if let StreamItem::Event(event) = &item {
+                                // The runner waits until it is answered
+                                match (event.as_ref(), delegation, &delegation_dir, coordinator) {
+                                    (
+                                        ProgressEvent::DelegationRequested {
+                                            task_id, context, ..
+                                        },
+                                        Some(delegation),
+                                        Some(dir),
+                                        _,
+                                    ) => {
+                                        self.answer_delegation(
+                                            delegation,
+                                            target,
+                                            dir,
+                                            task_id,
+                                            context.clone(),
+                                        )
+                                        .await;
+                                    }
+                                    (
+                                        ProgressEvent::TaskReached {
+                                            task_id,
+                                            position,
+                                            throttle,
+                                            ..
+                                        },
+                                        _,
+                                        _,
+                                        Some(coordinator),
+                                    ) => {
+                                        let outcome = coordinator
+                                            .reach(&target.host, task_id, *position, *throttle)
+                                            .await;
+                                        if let Some(dir) = &sync_dir {
+                                            self.release_task(
+                                                target,
+                                                dir,
+                                                *position,
+                                                outcome.err().as_deref(),
+                                            )
+                                            .await;
+                                        }
+                                    }
+                                    (
+                                        ProgressEvent::TaskCompleted { task_result, .. },
+                                        _,
+                                        _,
+                                        Some(coordinator),
+                                    ) => coordinator.complete(&target.host, &task_result.task_id),
+                                    (
+                                        ProgressEvent::FatalFailure {
+                                            task_id, play_id, ..
+                                        },
+                                        _,
+                                        _,
+                                        Some(coordinator),
+                                    ) => coordinator.fail_fatally(&target.host, task_id, play_id),
+                                    _ => {}
+                                }
+                            }
+                            forward_item(&target.host, item, events);
+                        }
+                    }
+                    OutputStream::Stderr => {
+                        for line in chunk.data.lines() {
+                            warn!("[{}] {}", chunk.host, line);
+                        }
+                    }
+                }
+            }
+            if let Some(item) = decoder.finish() {
+                forward_item(&target.host, item, events);
+            }
+        };
 
-        let mut cmd = target.target_path.to_string();
-        if !args.is_empty() {
-            cmd.push(' ');
-            cmd.push_str(&args.join(" "));
+        let mut env = Vec::new();
+        if let Some(dir) = &delegation_dir {
+            env.push((DELEGATION_DIR_ENV, dir.as_str()));
+        }
+        let traceparent = trace.map(TraceContext::traceparent);
+        if let Some(traceparent) = &traceparent {
+            env.push((TRACEPARENT_ENV, traceparent.as_str()));
         }
+        if let Some(dir) = &sync_dir {
+            env.push((SYNC_DIR_ENV, dir.as_str()));
+        }
+        if let Some(coordinator) = coordinator {
+            coordinator.join(&target.host);
+        }
+        let (result, ()) = tokio::join!(self.run_binary(target, args, &env, sender), forward);
+        if let Some(coordinator) = coordinator {
+            coordinator.leave(&target.host);
+        }
+        result
+    }
 
+    /// Execute the deployed binary, sending output chunks to `sink` as they are produced.
+    pub async fn execute_binary_streaming(
+        &self,
+        target: &DeploymentTarget,
+        args: &[String],
+        sink: mpsc::UnboundedSender<OutputChunk>,
+    ) -> Result<ExecutionResult> {
+        self.run_binary(target, args, &[], sink).await
+    }
+
+    async fn run_binary(
+        &self,
+        target: &DeploymentTarget,
+        args: &[String],
+        extra_env: &[(&str, &str)],
+        sink: mpsc::UnboundedSender<OutputChunk>,
+    ) -> Result<ExecutionResult> {
+        info!("Executing binary on host: {}", target.host);
+        self.check_partition(target)?;
+
+        let connection = self.connection(target).await?;
+        self.verify_deployed_signature(connection.as_ref(), target)
+            .await?;
+
+        // Lets runners name their uploaded result bundles after the inventory
+        // host, has them stream events back on stdout
+        // and persist their progress for a failed run to be resumed. The stop
+        // file is fresh per run, so a request to stop never outlives its run.
+        let state_file = state_file_path(&target.target_path);
+        let stop_file = format!("{}.stop-{}", target.target_path, uuid::Uuid::new_v4());
+        let mut env = vec![
+            (HOST_ID_ENV, target.host.as_str()),
+            (EVENT_STREAM_ENV, "1"),
+            (STATE_FILE_ENV, state_file.as_str()),
+            (STOP_FILE_ENV, stop_file.as_str()),
+        ];
+        if self.resume {
+            env.push((RESUME_ENV, "1"));
+        }
+        env.extend_from_slice(extra_env);
+        let password_file = self
+            .stage_become_password(connection.as_ref(), target)
+            .await?;
+        if let Some(path) = &password_file {
+            env.push((BECOME_PASSWORD_FILE_ENV, path.as_str()));
+        }
+        let (secrets_file, key_file) = self
+            .stage_sealed_secrets(connection.as_ref(), target)
+            .await?
+            .unzip();
+        if let (Some(path), Some(key_path)) = (&secrets_file, &key_file) {
+            env.push((SECRETS_FILE_ENV, path.as_str()));
+            env.push((SECRETS_KEY_FILE_ENV, key_path.as_str()));
+        }
+        // A retried runner skips the tasks its failed attempt completed
+        let mut resumed_env = env.clone();
+        if !self.resume {
+            resumed_env.push((RESUME_ENV, "1"));
+        }
+        let mut attempts = 0;
         let start_time = std::time::Instant::now();
-        let result = connection.execute_command(&cmd).await?;
+        let execution = self.with_retry(target, DeployPhase::Execute, || {
+            attempts += 1;
+            let env = if attempts > 1 { &resumed_env } else { &env };
+            self.run_runner(connection.as_ref(), target, args, env, Some(sink.clone()))
+        });
+        let result = self
+            .stop_on_cancel(connection.as_ref(), target, &stop_file, execution)
+            .await;
         let execution_time = start_time.elapsed();
+        self.discard_staged_file(connection.as_ref(), password_file)
+            .await;
+        self.discard_staged_file(connection.as_ref(), secrets_file)
+            .await;
+        self.discard_staged_file(connection.as_ref(), key_file)
+            .await;
+        let result = result.map_err(|e| match e {
+            DeployError::Cancelled => e,
+            e => DeployError::DeploymentFailed {
+                host: target.host.clone(),
+                reason: format!("Failed to execute binary: {e}"),
+            },
+        })?;
 
         Ok(ExecutionResult {
             exit_code: result.exit_code,
@@ -121,20 +568,371 @@ impl BinaryDeployer {
         })
     }
 
-    pub async fn cleanup_deployment(&self, target: &DeploymentTarget) -> Result<()> {
-        info!("Cleaning up deployment on host: {}", target.host);
+    /// Wait for `execution` of the runner on `target`. Once the deployment is
+    /// cancelled, the runner is asked through `stop_file` to stop after its
+    /// current task, keeping its progress for a resumed run, and given up on
+    /// if it has not within [`STOP_GRACE_PERIOD`].
+    async fn stop_on_cancel<T>(
+        &self,
+        connection: &dyn ConnectionPlugin,
+        target: &DeploymentTarget,
+        stop_file: &str,
+        execution: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        tokio::pin!(execution);
+        tokio::select! {
+            biased;
+            result = &mut execution => return result,
+            _ = self.cancel.cancelled() => {}
+        }
 
-        let connection = self.connection_manager.get_connection(&target.host).await?;
+        info!(
+            "Asking the runner on {} to stop after its current task",
+            target.host
+        );
+        // The runner reads the reason as soon as the file appears
+        let partial = format!("{stop_file}.partial");
+        let requested = async {
+            connection
+                .upload(STOP_REASON.as_bytes(), &partial, 0o600)
+                .await?;
+            connection.rename(&partial, stop_file).await
+        };
+        if let Err(e) = requested.await {
+            warn!("Failed to ask the runner on {} to stop: {}", target.host, e);
+        }
 
-        let cleanup_cmd = format!("rm -f {}", target.target_path);
-        let result = connection.execute_command(&cleanup_cmd).await?;
+        let result = tokio::time::timeout(STOP_GRACE_PERIOD, execution)
+            .await
+            .map_err(|_| DeployError::Cancelled)?;
+        self.discard_staged_file(connection, Some(stop_file.to_string()))
+            .await;
+        result
+    }
 
-        if !result.success {
-            return Err(DeployError::DeploymentFailed {
+    /// Resolve a delegation request from the runner on `target` and deliver
+    /// the result to `dir`, where the runner waits for it
+    async fn answer_delegation(
+        &self,
+        delegation: &Delegation,
+        target: &DeploymentTarget,
+        dir: &str,
+        task_id: &str,
+        context: DelegationContext,
+    ) {
+        let result = delegation.resolve(self, task_id, context).await;
+        if let Err(e) = self
+            .deliver_delegated_result(target, dir, task_id, &result)
+            .await
+        {
+            warn!(
+                "Failed to deliver delegated task {} to {}: {}",
+                task_id, target.host, e
+            );
+        }
+    }
+
+    /// Let the runner on `target`, waiting in `dir`, start the task at
+    /// `position`, or stop it there for the reason `abort`
+    async fn release_task(
+        &self,
+        target: &DeploymentTarget,
+        dir: &str,
+        position: usize,
+        abort: Option<&str>,
+    ) {
+        let released = async {
+            self.check_partition(target)?;
+            let connection = self.connection(target).await?;
+            match abort {
+                None => {
+                    let path = format!("{dir}/{}", release_file_name(position));
+                    connection.upload(&[], &path, 0o600).await
+                }
+                // The runner reads the reason as soon as the file appears
+                Some(reason) => {
+                    let path = format!("{dir}/{}", abort_file_name(position));
+                    let partial = format!("{path}.partial");
+                    connection
+                        .upload(reason.as_bytes(), &partial, 0o600)
+                        .await?;
+                    connection.rename(&partial, &path).await
+                }
+            }
+        };
+        if let Err(e) = released.await {
+            warn!(
+                "Failed to release task {} on {}: {}",
+                position, target.host, e
+            );
+        }
+    }
+
+    /// Run only the task `task_id` of the runner deployed to `target`, on
+    /// behalf of the host described by `context`
+    pub async fn execute_delegated_task(
+        &self,
+        target: &DeploymentTarget,
+        task_id: &str,
+        context: &DelegationContext,
+    ) -> Result<DelegatedResult> {
+        self.check_partition(target)?;
+        let connection = self.connection(target).await?;
+        self.verify_deployed_signature(connection.as_ref(), target)
+            .await?;
+
+        let context = serde_json::to_string(context)?;
+        let mut env = vec![
+            (HOST_ID_ENV, target.host.as_str()),
+            (EVENT_STREAM_ENV, "1"),
+            (DELEGATED_TASK_ENV, task_id),
+            (DELEGATION_CONTEXT_ENV, context.as_str()),
+        ];
+        let password_file = self
+            .stage_become_password(connection.as_ref(), target)
+            .await?;
+        if let Some(path) = &password_file {
+            env.push((BECOME_PASSWORD_FILE_ENV, path.as_str()));
+        }
+        let (secrets_file, key_file) = self
+            .stage_sealed_secrets(connection.as_ref(), target)
+            .await?
+            .unzip();
+        if let (Some(path), Some(key_path)) = (&secrets_file, &key_file) {
+            env.push((SECRETS_FILE_ENV, path.as_str()));
+            env.push((SECRETS_KEY_FILE_ENV, key_path.as_str()));
+        }
+        let result = self
+            .run_runner(connection.as_ref(), target, &[], &env, None)
+            .await;
+        self.discard_staged_file(connection.as_ref(), password_file)
+            .await;
+        self.discard_staged_file(connection.as_ref(), secrets_file)
+            .await;
+        self.discard_staged_file(connection.as_ref(), key_file)
+            .await;
+        let result = result?;
+
+        decode_events(&result.stdout)
+            .into_iter()
+            .find_map(|event| match event {
+                ProgressEvent::TaskCompleted { task_result, .. }
+                    if task_result.task_id == task_id =>
+                {
+                    Some(DelegatedResult::new(task_result))
+                }
+                _ => None,
+            })
+            .ok_or_else(|| DeployError::DelegationFailed {
+                task_id: task_id.to_string(),
                 host: target.host.clone(),
-                reason: format!("Failed to cleanup binary: {}", result.stderr),
-            });
+                reason: format!(
+                    "runner exited with {} without a result: {}",
+                    result.exit_code,
+                    result.stderr.trim()
+                ),
+            })
+    }
+
+    /// Run the runner deployed to `target`, under wasmtime when it is a
+    /// WebAssembly module
+    async fn run_runner(
+        &self,
+        connection: &dyn ConnectionPlugin,
+        target: &DeploymentTarget,
+        args: &[String],
+        env: &[(&str, &str)],
+        sink: Option<mpsc::UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        if is_wasm_runner(&target.target_path) {
+            self.wasmtime
+                .run(connection, &target.target_path, args, env, sink)
+                .await
+        } else {
+            connection
+                .execute_program(&target.target_path, args, env, sink)
+                .await
         }
+    }
+
+    /// Upload the become password next to the runner on `target`, readable
+    /// only by the connecting user
+    async fn stage_become_password(
+        &self,
+        connection: &dyn ConnectionPlugin,
+        target: &DeploymentTarget,
+    ) -> Result<Option<String>> {
+        let Some(password) = &self.become_password else {
+            return Ok(None);
+        };
+        let path = format!("{}.become-{}", target.target_path, uuid::Uuid::new_v4());
+        connection.upload(password.as_bytes(), &path, 0o600).await?;
+        Ok(Some(path))
+    }
+
+    /// Upload the sealed secrets and their key next to the runner on
+    /// `target`, readable only by the connecting user, answering their paths
+    async fn stage_sealed_secrets(
+        &self,
+        connection: &dyn ConnectionPlugin,
+        target: &DeploymentTarget,
+    ) -> Result<Option<(String, String)>> {
+        let Some((secrets, key)) = &self.sealed_secrets else {
+            return Ok(None);
+        };
+        let staged = format!("{}.secrets-{}", target.target_path, uuid::Uuid::new_v4());
+        let path = format!("{staged}.json");
+        let key_path = format!("{staged}.key");
+        connection
+            .upload(&serde_json::to_vec(secrets)?, &path, 0o600)
+            .await?;
+        if let Err(e) = connection.upload(key.as_bytes(), &key_path, 0o600).await {
+            self.discard_staged_file(connection, Some(path)).await;
+            return Err(e);
+        }
+        Ok(Some((path, key_path)))
+    }
+
+    /// Remove a password or secrets file the runner left behind, as one
+    /// that fails before starting its tasks does
+    async fn discard_staged_file(&self, connection: &dyn ConnectionPlugin, path: Option<String>) {
+        if let Some(path) = path {
+            if let Err(e) = connection.remove(&path).await {
+                warn!("Failed to remove staged file {}: {}", path, e);
+            }
+        }
+    }
+
+    /// Write `result` into the delegation directory `dir` on `target`,
+    /// renaming it into place so the waiting runner never reads part of it
+    pub async fn deliver_delegated_result(
+        &self,
+        target: &DeploymentTarget,
+        dir: &str,
+        task_id: &str,
+        result: &DelegatedResult,
+    ) -> Result<()> {
+        self.check_partition(target)?;
+        let connection = self.connection(target).await?;
+        let path = format!("{dir}/{}", result_file_name(task_id));
+        let partial = format!("{path}.partial");
+        connection
+            .upload(&serde_json::to_vec(result)?, &partial, 0o600)
+            .await?;
+        connection.rename(&partial, &path).await
+    }
+
+    /// Keep a copy of the binary currently deployed on `target` so it can be
+    /// restored; `None` for custom deployments, which cannot be rolled back
+    pub async fn snapshot_binary(&self, target: &DeploymentTarget) -> Result<Option<HostSnapshot>> {
+        if matches!(target.deployment_method, DeploymentMethod::Custom { .. }) {
+            return Ok(None);
+        }
+        self.check_partition(target)?;
+
+        let connection = self.connection(target).await?;
+        let previous_path = HostSnapshot::previous_path(&target.target_path);
+        let previous_binary = connection
+            .copy(&target.target_path, &previous_path)
+            .await?
+            .then_some(previous_path);
+        if self.trusted_key.is_some() && previous_binary.is_some() {
+            let signature = format!("{}{SIGNATURE_SUFFIX}", target.target_path);
+            connection
+                .copy(&signature, &HostSnapshot::previous_path(&signature))
+                .await?;
+        }
+        debug!(
+            "Snapshot of {} on {}: {:?}",
+            target.target_path, target.host, previous_binary
+        );
+
+        Ok(Some(HostSnapshot {
+            host: target.host.clone(),
+            target_path: target.target_path.clone(),
+            previous_binary,
+            backups: Vec::new(),
+        }))
+    }
+
+    /// Restore module backups, newest first, and then the previous binary
+    /// (or remove the binary if there was none before). Returns how many
+    /// backed-up files were restored.
+    pub async fn restore_snapshot(
+        &self,
+        target: &DeploymentTarget,
+        snapshot: &HostSnapshot,
+    ) -> Result<usize> {
+        info!("Rolling back deployment on {}", target.host);
+        self.check_partition(target)?;
+        let connection = self.connection(target).await?;
+
+        let mut restored_files = 0;
+        for backup in snapshot.backups.iter().rev() {
+            if connection.copy(&backup.backup, &backup.path).await? {
+                restored_files += 1;
+            } else {
+                warn!(
+                    "Backup {} of {} is missing on {}",
+                    backup.backup, backup.path, target.host
+                );
+            }
+        }
+
+        // The signature goes back with the binary, so signed runners still pass their checks
+        let signature = format!("{}{SIGNATURE_SUFFIX}", snapshot.target_path);
+        match snapshot.previous_binary {
+            Some(ref previous) => {
+                // Copy aside and rename, since the binary may still be running
+                let staged = format!("{}.rustle-rollback", snapshot.target_path);
+                if !connection.copy(previous, &staged).await? {
+                    return Err(DeployError::DeploymentFailed {
+                        host: target.host.clone(),
+                        reason: format!("Previous binary {previous} is missing"),
+                    });
+                }
+                connection.rename(&staged, &snapshot.target_path).await?;
+                if !connection
+                    .copy(&HostSnapshot::previous_path(&signature), &signature)
+                    .await?
+                {
+                    connection.remove(&signature).await?;
+                }
+            }
+            None => {
+                connection.remove(&snapshot.target_path).await?;
+                connection.remove(&signature).await?;
+            }
+        }
+
+        Ok(restored_files)
+    }
+
+    /// Check the result of a finished run of `compilation` on `target`
+    pub async fn verify_execution(
+        &self,
+        target: &DeploymentTarget,
+        compilation: &BinaryCompilation,
+        run: &ExecutionResult,
+        verifier: &ExecutionVerifier,
+    ) -> Result<ExecutionVerificationReport> {
+        let connection = self.connection(target).await?;
+        let mut report = verifier
+            .verify(connection.as_ref(), compilation, run)
+            .await?;
+        report.host = target.host.clone();
+        Ok(report)
+    }
+
+    pub async fn cleanup_deployment(&self, target: &DeploymentTarget) -> Result<()> {
+        info!("Cleaning up deployment on host: {}", target.host);
+        self.check_partition(target)?;
+
+        let connection = self.connection(target).await?;
+        connection.remove(&target.target_path).await?;
+        connection
+            .remove(&state_file_path(&target.target_path))
+            .await?;
 
         info!("Successfully cleaned up deployment on {}", target.host);
         Ok(())
@@ -142,43 +940,97 @@ impl BinaryDeployer {
 
     // Private deployment methods
 
-    async fn deploy_via_ssh(&self, binary_data: &[u8], target: &DeploymentTarget) -> Result<()> {
-        let connection = self.connection_manager.get_connection(&target.host).await?;
-
-        // Create temporary file for binary transfer
-        let temp_path = format!("/tmp/rustle-runner-{}", uuid::Uuid::new_v4());
-
-        // Upload binary data
-        connection.upload_bytes(binary_data, &temp_path).await?;
-
-        // Set executable permissions and move to target location
-        let setup_cmd = format!(
-            "chmod +x {} && mkdir -p {} && mv {} {}",
-            temp_path,
-            Path::new(&target.target_path)
-                .parent()
-                .ok_or_else(|| DeployError::DeploymentFailed {
-                    host: target.host.clone(),
-                    reason: format!("Invalid target path: {}", target.target_path),
-                })?
-                .display(),
-            temp_path,
-            target.target_path
-        );
+    /// Resolve the transport used to reach `target`. scp, rsync and custom
+    /// deployments transfer with external commands and are finished over SSH.
+    async fn connection(&self, target: &DeploymentTarget) -> Result<Arc<dyn ConnectionPlugin>> {
+        self.with_retry(target, DeployPhase::Connect, || {
+            self.open_connection(target)
+        })
+        .await
+    }
 
-        let result = connection.execute_command(&setup_cmd).await?;
+    async fn open_connection(
+        &self,
+        target: &DeploymentTarget,
+    ) -> Result<Arc<dyn ConnectionPlugin>> {
+        let connection: Arc<dyn ConnectionPlugin> = match target.deployment_method {
+            DeploymentMethod::Ssh
+            | DeploymentMethod::Scp
+            | DeploymentMethod::Rsync
+            | DeploymentMethod::Custom { .. } => {
+                self.connection_manager.get_connection(target).await?
+            }
+            DeploymentMethod::WinRm => {
+                self.winrm_manager
+                    .get_connection(&target.host, &target.connection)
+                    .await?
+            }
+            DeploymentMethod::Local => Arc::new(LocalConnection::new(&target.host)),
+            DeploymentMethod::Docker { ref container } => Arc::new(
+                ContainerConnection::new(&target.host, ContainerRuntime::Docker, container)
+                    .with_user(target.connection.user.as_deref()),
+            ),
+            DeploymentMethod::Podman { ref container } => Arc::new(
+                ContainerConnection::new(&target.host, ContainerRuntime::Podman, container)
+                    .with_user(target.connection.user.as_deref()),
+            ),
+            DeploymentMethod::Kubectl {
+                ref pod,
+                ref namespace,
+                ref container,
+                ref context,
+                ref kubeconfig,
+            } => Arc::new(
+                KubectlConnection::new(&target.host, pod)
+                    .with_namespace(namespace.as_deref())
+                    .with_container(container.as_deref())
+                    .with_context(context.as_deref())
+                    .with_kubeconfig(kubeconfig.as_deref()),
+            ),
+            DeploymentMethod::Chroot { ref root } => {
+                Arc::new(ChrootConnection::new(&target.host, root))
+            }
+        };
+        Ok(connection)
+    }
 
-        if !result.success {
-            return Err(DeployError::DeploymentFailed {
-                host: target.host.clone(),
-                reason: format!("Failed to setup binary: {}", result.stderr),
-            });
+    async fn deploy_via_connection(
+        &self,
+        binary_data: &[u8],
+        target: &DeploymentTarget,
+    ) -> Result<()> {
+        let connection = self.connection(target).await?;
+        let options = TransferOptions {
+            compression: self.compression,
+            limiters: self.bandwidth.limiters(&target.host),
+        };
+
+        let outcome = upload_binary(
+            connection.as_ref(),
+            self.transfers.as_ref(),
+            &self.faults,
+            &options,
+            binary_data,
+            &target.target_path,
+            EXECUTABLE_MODE,
+        )
+        .await?;
+        let sent = match outcome {
+            TransferOutcome::UpToDate => {
+                info!("{} already has this binary", target.host);
+                return Ok(());
+            }
+            TransferOutcome::Installed { sent, .. } => sent,
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.record_transfer(&target.host, sent);
         }
 
-        // Verify the deployment
-        self.verify_binary_integrity(binary_data, target).await?;
-
-        info!("Successfully deployed via SSH to {}", target.host);
+        info!(
+            "Successfully deployed via {} to {}",
+            connection.transport(),
+            target.host
+        );
         Ok(())
     }
 
@@ -190,19 +1042,31 @@ impl BinaryDeployer {
                 reason: format!("Failed to create temp file: {e}"),
             })?;
 
-        std::fs::write(temp_file.path(), binary_data).map_err(|e| {
-            DeployError::DeploymentFailed {
+        let upload = self.faults.corrupt_upload(&target.host, binary_data);
+        std::fs::write(temp_file.path(), upload.as_deref().unwrap_or(binary_data)).map_err(
+            |e| DeployError::DeploymentFailed {
                 host: target.host.clone(),
                 reason: format!("Failed to write temp file: {e}"),
-            }
-        })?;
+            },
+        )?;
 
         // Use scp to transfer the file
         let mut cmd = Command::new("scp");
-        cmd.arg("-o")
-            .arg("StrictHostKeyChecking=no")
-            .arg(temp_file.path())
-            .arg(format!("{}:{}", target.host, target.target_path));
+        cmd.arg("-o").arg("StrictHostKeyChecking=no");
+        if let Some(port) = target.connection.port {
+            cmd.arg("-P").arg(port.to_string());
+        }
+        if let Some(ref key_file) = target.connection.private_key_file {
+            cmd.arg("-i").arg(key_file);
+        }
+        if let Some(limit) = self.bandwidth.limits().effective_limit(&target.host) {
+            // scp takes Kbit/s
+            cmd.arg("-l").arg((limit * 8 / 1000).max(1).to_string());
+        }
+        if self.compression.is_some() {
+            cmd.arg("-C");
+        }
+        cmd.arg(temp_file.path()).arg(remote_destination(target));
 
         let output = cmd
             .output()
@@ -218,11 +1082,15 @@ impl BinaryDeployer {
                 reason: format!("SCP failed: {}", String::from_utf8_lossy(&output.stderr)),
             });
         }
+        if let Some(metrics) = &self.metrics {
+            metrics.record_transfer(&target.host, binary_data.len() as u64);
+        }
+        self.crash_point(target)?;
 
         // Set executable permissions via SSH
-        let connection = self.connection_manager.get_connection(&target.host).await?;
+        let connection = self.connection_manager.get_connection(target).await?;
         let chmod_result = connection
-            .execute_command(&format!("chmod +x {}", target.target_path))
+            .execute_command(&format!("chmod +x {}", shell_quote(&target.target_path)))
             .await?;
 
         if !chmod_result.success {
@@ -244,10 +1112,22 @@ impl BinaryDeployer {
 
     async fn deploy_via_rsync(&self, binary_path: &Path, target: &DeploymentTarget) -> Result<()> {
         let mut cmd = Command::new("rsync");
-        cmd.arg("-avz")
-            .arg("--progress")
-            .arg(binary_path)
-            .arg(format!("{}:{}", target.host, target.target_path));
+        cmd.arg("-avz").arg("--progress");
+        if let Some(limit) = self.bandwidth.limits().effective_limit(&target.host) {
+            // rsync takes KiB/s
+            cmd.arg(format!("--bwlimit={}", (limit / 1024).max(1)));
+        }
+        if target.connection.port.is_some() || target.connection.private_key_file.is_some() {
+            let mut ssh = vec!["ssh".to_string()];
+            if let Some(port) = target.connection.port {
+                ssh.extend(["-p".to_string(), port.to_string()]);
+            }
+            if let Some(ref key_file) = target.connection.private_key_file {
+                ssh.extend(["-i".to_string(), key_file.clone()]);
+            }
+            cmd.arg("-e").arg(shell_words::join(ssh));
+        }
+        cmd.arg(binary_path).arg(remote_destination(target));
 
         let output = cmd
             .output()
@@ -263,11 +1143,12 @@ impl BinaryDeployer {
                 reason: format!("Rsync failed: {}", String::from_utf8_lossy(&output.stderr)),
             });
         }
+        self.crash_point(target)?;
 
         // Set executable permissions
-        let connection = self.connection_manager.get_connection(&target.host).await?;
+        let connection = self.connection_manager.get_connection(target).await?;
         let chmod_result = connection
-            .execute_command(&format!("chmod +x {}", target.target_path))
+            .execute_command(&format!("chmod +x {}", shell_quote(&target.target_path)))
             .await?;
 
         if !chmod_result.success {
@@ -316,6 +1197,7 @@ impl BinaryDeployer {
                 ),
             });
         }
+        self.crash_point(target)?;
 
         info!(
             "Successfully deployed via custom command to {}",
@@ -324,31 +1206,161 @@ impl BinaryDeployer {
         Ok(())
     }
 
-    async fn verify_binary_integrity(
+    /// Check the local binary against its signature before it leaves the
+    /// controller, so a tampered cache entry is never deployed
+    fn check_signature(
         &self,
+        compilation: &BinaryCompilation,
         binary_data: &[u8],
         target: &DeploymentTarget,
+    ) -> Result<Option<BinarySignature>> {
+        let Some(ref key) = self.trusted_key else {
+            return Ok(None);
+        };
+        let invalid = |reason: String| DeployError::SignatureInvalid {
+            host: target.host.clone(),
+            reason,
+        };
+
+        let path = signature_path(&compilation.output_path);
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| invalid(format!("cannot read {}: {e}", path.display())))?;
+        let signature = BinarySignature::parse(&text).map_err(|e| invalid(e.to_string()))?;
+        key.verify(binary_data, &signature)
+            .map_err(|e| invalid(e.to_string()))?;
+
+        debug!(
+            "{} is signed by trusted key {}",
+            compilation.output_path.display(),
+            key.key_id()
+        );
+        Ok(Some(signature))
+    }
+
+    /// Place the signature next to the deployed binary for the runner's own check
+    async fn upload_signature(
+        &self,
+        signature: &BinarySignature,
+        target: &DeploymentTarget,
+    ) -> Result<()> {
+        if matches!(target.deployment_method, DeploymentMethod::Custom { .. }) {
+            debug!("Not uploading a signature to custom target {}", target.host);
+            return Ok(());
+        }
+        let path = format!("{}{SIGNATURE_SUFFIX}", target.target_path);
+        self.connection(target)
+            .await?
+            .upload(signature.encode().as_bytes(), &path, 0o644)
+            .await
+    }
+
+    /// Check that the binary on the target is still the one that was signed,
+    /// comparing its checksum with the one in the signature's trusted comment
+    async fn verify_deployed_signature(
+        &self,
+        connection: &dyn ConnectionPlugin,
+        target: &DeploymentTarget,
     ) -> Result<()> {
-        let connection = self.connection_manager.get_connection(&target.host).await?;
+        let Some(ref key) = self.trusted_key else {
+            return Ok(());
+        };
+        let invalid = |reason: String| DeployError::SignatureInvalid {
+            host: target.host.clone(),
+            reason,
+        };
+
+        let path = format!("{}{SIGNATURE_SUFFIX}", target.target_path);
+        let text = connection
+            .read_to_string(&path)
+            .await?
+            .ok_or_else(|| invalid(format!("no signature at {path}")))?;
+        let signature = BinarySignature::parse(&text).map_err(|e| invalid(e.to_string()))?;
+        key.verify_comment(&signature)
+            .map_err(|e| invalid(e.to_string()))?;
+
+        let expected = signature
+            .sha256()
+            .ok_or_else(|| invalid("signature does not record a checksum".to_string()))?;
+        let actual = connection
+            .sha256(&target.target_path)
+            .await?
+            .ok_or_else(|| invalid(format!("no binary at {}", target.target_path)))?;
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(invalid(format!(
+                "deployed binary has checksum {actual}, signed {expected}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Run one phase of a deployment to `target` under its retry policy
+    async fn with_retry<T, F, Fut>(
+        &self,
+        target: &DeploymentTarget,
+        phase: DeployPhase,
+        operation: F,
+    ) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let policy = self.retry.policy(&target.host, phase);
+        let record = || {
+            self.retries
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .entry(target.host.clone())
+                .or_default()
+                .record(phase)
+        };
+        retry(policy, phase, &target.host, record, operation).await
+    }
+
+    /// Fail as if `target` were unreachable when a partition fault fires
+    fn check_partition(&self, target: &DeploymentTarget) -> Result<()> {
+        if self.faults.partitioned(&target.host) {
+            return Err(DeployError::Network(format!(
+                "{}: injected network partition",
+                target.host
+            )));
+        }
+        Ok(())
+    }
 
+    /// Stop part-way through a deployment, after the transfer but before it is finalised
+    fn crash_point(&self, target: &DeploymentTarget) -> Result<()> {
+        self.faults
+            .crash_point(&target.host)
+            .map_err(|reason| DeployError::DeploymentFailed {
+                host: target.host.clone(),
+                reason,
+            })
+    }
+
+    async fn verify_binary_integrity(
+        &self,
+        binary_data: &[u8],
+        target: &DeploymentTarget,
+    ) -> Result<()> {
         // Calculate expected checksum
         let mut hasher = Sha256::new();
         hasher.update(binary_data);
         let expected_checksum = format!("{:x}", hasher.finalize());
 
         // Get deployed binary checksum
-        let checksum_cmd = format!("sha256sum {} | cut -d' ' -f1", target.target_path);
-        let result = connection.execute_command(&checksum_cmd).await?;
+        let actual_checksum = self
+            .connection(target)
+            .await?
+            .sha256(&target.target_path)
+            .await?;
 
-        if !result.success {
+        let Some(actual_checksum) = actual_checksum else {
             return Err(DeployError::VerificationFailed {
                 host: target.host.clone(),
                 expected: expected_checksum,
                 actual: "checksum command failed".to_string(),
             });
-        }
-
-        let actual_checksum = result.stdout.trim();
+        };
         if actual_checksum != expected_checksum {
             return Err(DeployError::VerificationFailed {
                 host: target.host.clone(),
@@ -362,8 +1374,52 @@ impl BinaryDeployer {
     }
 }
 
-// Connection management - simplified for now
-pub struct ConnectionManager;
+/// Log a line of runner output, or pass a decoded event on to `events`
+fn forward_item(host: &str, item: StreamItem, events: Option<&EventSink>) {
+    match item {
+        StreamItem::Output(line) => info!("[{}] {}", host, line),
+        StreamItem::Event(event) => {
+            let event = HostEvent::new(host, *event);
+            match events {
+                Some(sink) => {
+                    let _ = sink.send(event);
+                }
+                None => debug!("{}", event.progress_line()),
+            }
+        }
+        StreamItem::Malformed { line, reason } => {
+            warn!("[{}] Undecodable event frame ({}): {}", host, reason, line)
+        }
+    }
+}
+
+/// Where the runner deployed to `target_path` persists its progress
+pub fn state_file_path(target_path: &str) -> String {
+    format!("{target_path}.state")
+}
+
+/// `[user@]host:path` for scp/rsync. The system ssh client reads
+/// `~/.ssh/config` itself, so only inventory variables are passed on.
+fn remote_destination(target: &DeploymentTarget) -> String {
+    match target.connection.user {
+        Some(ref user) if !target.host.contains('@') => {
+            format!("{user}@{}:{}", target.host, target.target_path)
+        }
+        _ => format!("{}:{}", target.host, target.target_path),
+    }
+}
+
+/// Opens and caches authenticated SSH sessions, one per host.
+///
+/// Per-host settings are resolved from the target, its inventory variables
+/// and `~/.ssh/config`; see [`crate::deploy::ssh_config`] for the precedence.
+pub struct ConnectionManager {
+    config: SshConnectionConfig,
+    ssh_config: SshConfig,
+    /// One cell per host, so that hosts connect concurrently while callers
+    /// for the same host share a single connection attempt
+    connections: Mutex<HashMap<String, Arc<OnceCell<Arc<SshConnection>>>>>,
+}
 
 impl Default for ConnectionManager {
     fn default() -> Self {
@@ -373,85 +1429,52 @@ impl Default for ConnectionManager {
 
 impl ConnectionManager {
     pub fn new() -> Self {
-        Self
+        Self::with_config(SshConnectionConfig::default())
     }
 
-    pub async fn get_connection(&self, host: &str) -> Result<Connection> {
-        // TODO: Implement actual SSH connection management
-        // This would handle connection pooling, authentication, etc.
-        Ok(Connection::new(host))
+    pub fn with_config(config: SshConnectionConfig) -> Self {
+        Self::with_ssh_config(config, SshConfig::load_default())
     }
-}
 
-pub struct Connection {
-    host: String,
-}
-
-impl Connection {
-    fn new(host: &str) -> Self {
+    pub fn with_ssh_config(config: SshConnectionConfig, ssh_config: SshConfig) -> Self {
         Self {
-            host: host.to_string(),
+            config,
+            ssh_config,
+            connections: Mutex::new(HashMap::new()),
         }
     }
 
-    pub async fn execute_command(&self, command: &str) -> Result<CommandResult> {
-        debug!("Executing command on {}: {}", self.host, command);
-
-        // Use SSH to execute the command
-        let mut cmd = Command::new("ssh");
-        cmd.arg("-o")
-            .arg("StrictHostKeyChecking=no")
-            .arg(&self.host)
-            .arg(command);
-
-        let output = cmd
-            .output()
+    pub async fn get_connection(&self, target: &DeploymentTarget) -> Result<Arc<SshConnection>> {
+        let cell = self
+            .connections
+            .lock()
             .await
-            .map_err(|e| DeployError::Network(format!("SSH command failed: {e}")))?;
-
-        Ok(CommandResult {
-            success: output.status.success(),
-            exit_code: output.status.code().unwrap_or(-1),
-            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
-            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
-        })
+            .entry(target.host.clone())
+            .or_default()
+            .clone();
+        let connection = cell
+            .get_or_try_init(|| async {
+                let resolved = resolve_connection(
+                    &target.host,
+                    &target.connection,
+                    &self.ssh_config,
+                    &self.config,
+                );
+                debug!(
+                    "Resolved SSH connection for {}: {}",
+                    target.host, resolved.host_spec
+                );
+                let connection = SshConnection::connect(&resolved.host_spec, &resolved.config)
+                    .await?
+                    .with_host_label(&target.host);
+                Ok::<_, DeployError>(Arc::new(connection))
+            })
+            .await?;
+        Ok(connection.clone())
     }
 
-    pub async fn upload_bytes(&self, data: &[u8], remote_path: &str) -> Result<()> {
-        // Create temporary local file
-        let temp_file = tempfile::NamedTempFile::new()
-            .map_err(|e| DeployError::Network(format!("Failed to create temp file: {e}")))?;
-
-        std::fs::write(temp_file.path(), data)
-            .map_err(|e| DeployError::Network(format!("Failed to write temp file: {e}")))?;
-
-        // Use scp to upload
-        let mut cmd = Command::new("scp");
-        cmd.arg("-o")
-            .arg("StrictHostKeyChecking=no")
-            .arg(temp_file.path())
-            .arg(format!("{}:{}", self.host, remote_path));
-
-        let output = cmd
-            .output()
-            .await
-            .map_err(|e| DeployError::Network(format!("SCP upload failed: {e}")))?;
-
-        if !output.status.success() {
-            return Err(DeployError::Network(format!(
-                "SCP upload failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            )));
-        }
-
-        Ok(())
+    /// Drop the cached session for a host, e.g. after a transport error.
+    pub async fn disconnect(&self, host: &str) {
+        self.connections.lock().await.remove(host);
     }
 }
-
-#[derive(Debug)]
-pub struct CommandResult {
-    pub success: bool,
-    pub exit_code: i32,
-    pub stdout: String,
-    pub stderr: String,
-}
@@ -18,6 +18,45 @@ pub enum DeployError {
         actual: String,
     },
 
+    #[error("Binary {path} does not match the manifest: {reason}")]
+    ManifestMismatch { path: String, reason: String },
+
+    #[error("No runner was built for {target}")]
+    MissingArtifact { target: String },
+
+    #[error("Runner {path} is stale: {reason}")]
+    StaleArtifact { path: String, reason: String },
+
+    #[error("Signature check of the binary for {host} failed: {reason}")]
+    SignatureInvalid { host: String, reason: String },
+
+    #[error("Binary {compilation_id} is not compatible with {host}: {reason}")]
+    IncompatibleTarget {
+        host: String,
+        compilation_id: String,
+        reason: String,
+    },
+
+    #[error("{host} is outside its maintenance window, which opens at {opens_at}")]
+    OutsideMaintenanceWindow {
+        host: String,
+        opens_at: chrono::DateTime<chrono::Utc>,
+    },
+
+    #[error("Hook `{command}` failed for {host}: {reason}")]
+    HookFailed {
+        host: String,
+        command: String,
+        reason: String,
+    },
+
+    #[error("Delegated task {task_id} failed on {host}: {reason}")]
+    DelegationFailed {
+        task_id: String,
+        host: String,
+        reason: String,
+    },
+
     #[error("Module {module} not compatible with static linking")]
     StaticLinkingError { module: String },
 
@@ -27,12 +66,22 @@ pub enum DeployError {
     #[error("Deployment timeout exceeded: {timeout}s")]
     DeploymentTimeout { timeout: u64 },
 
+    #[error("Deployment cancelled")]
+    Cancelled,
+
     #[error("Rollback failed for deployment {deployment_id}: {reason}")]
     RollbackFailed {
         deployment_id: String,
         reason: String,
     },
 
+    #[error("Audit log {path} has been tampered with at line {line}: {reason}")]
+    AuditLogTampered {
+        path: String,
+        line: usize,
+        reason: String,
+    },
+
     #[error("Cache corruption detected: {path}")]
     CacheCorruption { path: String },
 
@@ -49,6 +98,18 @@ pub enum DeployError {
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
 
+    #[error("SSH connection to {host} failed: {reason}")]
+    SshConnection { host: String, reason: String },
+
+    #[error("SSH authentication to {host} failed: {reason}")]
+    SshAuthentication { host: String, reason: String },
+
+    #[error("WinRM connection to {host} failed: {reason}")]
+    WinRmConnection { host: String, reason: String },
+
+    #[error("WinRM authentication to {host} failed: {reason}")]
+    WinRmAuthentication { host: String, reason: String },
+
     #[error("Network error: {0}")]
     Network(String),
 
@@ -57,6 +118,56 @@ pub enum DeployError {
 
     #[error("Template generation error: {0}")]
     TemplateGeneration(String),
+
+    #[error("Result upload error: {0}")]
+    ResultUpload(#[from] crate::runtime::ResultUploadError),
+}
+
+impl DeployError {
+    /// Whether the error may go away on its own, such as a dropped
+    /// connection, as opposed to a problem with the binary or configuration
+    pub fn is_transient(&self) -> bool {
+        match self {
+            DeployError::Network(_)
+            | DeployError::SshConnection { .. }
+            | DeployError::WinRmConnection { .. } => true,
+            DeployError::Io(e) => is_transient_io(e.kind()),
+            DeployError::DeploymentFailed { reason, .. } => {
+                let reason = reason.to_lowercase();
+                TRANSIENT_MESSAGES
+                    .iter()
+                    .any(|message| reason.contains(message))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Fragments of wrapped transport errors that indicate a network blip
+const TRANSIENT_MESSAGES: &[&str] = &[
+    "connection reset",
+    "connection refused",
+    "connection aborted",
+    "broken pipe",
+    "timed out",
+    "temporarily unavailable",
+    "unexpected eof",
+];
+
+fn is_transient_io(kind: std::io::ErrorKind) -> bool {
+    use std::io::ErrorKind;
+    matches!(
+        kind,
+        ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::BrokenPipe
+            | ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+            | ErrorKind::UnexpectedEof
+            | ErrorKind::WouldBlock
+    )
 }
 
 pub type Result<T> = std::result::Result<T, DeployError>;
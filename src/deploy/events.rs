@@ -0,0 +1,117 @@
+//! Events streamed back from runners while they execute.
+//!
+//! The deployer decodes the runner's event frames (see
+//! [`crate::runtime::event_stream`]) out of the SSH output as it arrives and
+//! forwards them, tagged with the inventory host, to the sink given to
+//! [`DeploymentManager::with_event_sink`](crate::deploy::DeploymentManager::with_event_sink).
+//! Each [`HostEvent`] serializes to one JSON line for machine-readable output
+//! and renders to one human-readable line for progress display.
+
+use crate::runtime::ProgressEvent;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+
+pub type EventSink = UnboundedSender<HostEvent>;
+
+/// An event from the runner on one host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostEvent {
+    pub host: String,
+    pub event: ProgressEvent,
+}
+
+impl HostEvent {
+    pub fn new(host: &str, event: ProgressEvent) -> Self {
+        Self {
+            host: host.to_string(),
+            event,
+        }
+    }
+
+    /// The event as one line of newline-delimited JSON
+    pub fn to_json_line(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    /// One line describing the event for progress output
+    pub fn progress_line(&self) -> String {
+        let host = &self.host;
+        match &self.event {
+            ProgressEvent::ExecutionStarted { total_tasks, .. } => {
+                format!("[{host}] started, {total_tasks} tasks")
+            }
+            ProgressEvent::TaskStarted { task_name, .. } => format!("[{host}] {task_name} ..."),
+            ProgressEvent::TaskCompleted { task_result, .. } => {
+                let outcome = if task_result.failed {
+                    "failed"
+                } else if task_result.skipped {
+                    "skipped"
+                } else if task_result.changed {
+                    "changed"
+                } else {
+                    "ok"
+                };
+                let mut line = format!(
+                    "[{host}] {}: {outcome} ({:.1}s)",
+                    task_result.name,
+                    task_result.duration.as_secs_f64()
+                );
+                if let Some(error) = &task_result.error {
+                    line.push_str(&format!(" - {error}"));
+                }
+                line
+            }
+            ProgressEvent::PlayStarted { play_id, name, .. } => {
+                format!("[{host}] play {}", name.as_deref().unwrap_or(play_id))
+            }
+            ProgressEvent::FactsCollected { facts, .. } => {
+                format!("[{host}] gathered {} facts", facts.len())
+            }
+            ProgressEvent::TaskDiff { task_id, diff, .. } => {
+                let header = diff
+                    .after_header
+                    .as_deref()
+                    .or(diff.before_header.as_deref())
+                    .unwrap_or(task_id);
+                format!("[{host}] diff: {header}")
+            }
+            ProgressEvent::ExecutionCompleted { result, .. } => {
+                let summary = &result.summary;
+                let mut line = format!(
+                    "[{host}] finished: {} tasks, {} changed, {} failed",
+                    summary.total_tasks, summary.changed_tasks, summary.failed_tasks
+                );
+                for (count, outcome) in [
+                    (summary.ignored_tasks, "ignored"),
+                    (summary.rescued_tasks, "rescued"),
+                ] {
+                    if count > 0 {
+                        line.push_str(&format!(", {count} {outcome}"));
+                    }
+                }
+                line
+            }
+            ProgressEvent::ExecutionFailed { error, .. } => format!("[{host}] failed: {error}"),
+            ProgressEvent::DelegationRequested { task_id, .. } => {
+                format!("[{host}] {task_id}: delegated")
+            }
+            ProgressEvent::TaskReached {
+                task_id,
+                throttle: Some(throttle),
+                ..
+            } => format!("[{host}] {task_id}: waiting for one of {throttle} slots"),
+            ProgressEvent::TaskReached { task_id, .. } => {
+                format!("[{host}] {task_id}: waiting for the other hosts")
+            }
+            ProgressEvent::FatalFailure {
+                task_id, play_id, ..
+            } => format!("[{host}] {task_id}: failed, ending play {play_id} on every host"),
+            ProgressEvent::TaskRetrying {
+                task_name,
+                attempt,
+                retries,
+                ..
+            } => format!("[{host}] {task_name}: retrying ({attempt}/{retries})"),
+        }
+    }
+}
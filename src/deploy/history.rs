@@ -0,0 +1,205 @@
+use crate::deploy::Result;
+use crate::runtime::{ExecutionResult, ModuleMetrics, ModuleStats};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+const RUNS_FILE: &str = "runs.jsonl";
+
+/// A single execution recorded in the history database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub execution_id: String,
+    pub host: Option<String>,
+    pub recorded_at: DateTime<Utc>,
+    pub success: bool,
+    pub duration: std::time::Duration,
+    pub module_metrics: ModuleMetrics,
+}
+
+/// Module statistics aggregated over every recorded run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleAggregate {
+    pub module: String,
+    pub runs: u64,
+    pub stats: ModuleStats,
+}
+
+/// Append-only history of execution runs, stored as JSON lines.
+///
+/// Each line is a self-contained [`RunRecord`], so concurrent writers only
+/// ever append and a truncated final line loses at most one run.
+#[derive(Debug, Clone)]
+pub struct ExecutionHistory {
+    history_dir: PathBuf,
+}
+
+impl ExecutionHistory {
+    pub fn new(history_dir: PathBuf) -> Self {
+        Self { history_dir }
+    }
+
+    /// The default history location, `~/.rustle/history`
+    pub fn default_dir() -> PathBuf {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".rustle")
+            .join("history")
+    }
+
+    pub fn history_dir(&self) -> &Path {
+        &self.history_dir
+    }
+
+    fn runs_path(&self) -> PathBuf {
+        self.history_dir.join(RUNS_FILE)
+    }
+
+    /// Append the metrics of a finished execution to the history
+    pub fn record_execution(&self, result: &ExecutionResult, host: Option<&str>) -> Result<()> {
+        self.append(&RunRecord {
+            execution_id: result.execution_id.clone(),
+            host: host.map(str::to_string),
+            recorded_at: Utc::now(),
+            success: result.success,
+            duration: result.duration,
+            module_metrics: result.module_metrics.clone(),
+        })
+    }
+
+    pub fn append(&self, record: &RunRecord) -> Result<()> {
+        std::fs::create_dir_all(&self.history_dir)?;
+
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.runs_path())?;
+        file.write_all(line.as_bytes())?;
+
+        debug!(
+            "Recorded run {} in {}",
+            record.execution_id,
+            self.runs_path().display()
+        );
+        Ok(())
+    }
+
+    /// Load every recorded run, skipping lines that fail to parse
+    pub fn load_runs(&self) -> Result<Vec<RunRecord>> {
+        let path = self.runs_path();
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let reader = BufReader::new(std::fs::File::open(&path)?);
+        let mut runs = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(record) => runs.push(record),
+                Err(e) => warn!(
+                    "Skipping malformed history entry at {}:{}: {}",
+                    path.display(),
+                    index + 1,
+                    e
+                ),
+            }
+        }
+        Ok(runs)
+    }
+
+    /// Aggregate per-module statistics across all runs, most expensive first
+    pub fn aggregate_modules(&self) -> Result<Vec<ModuleAggregate>> {
+        let runs = self.load_runs()?;
+
+        let mut totals = ModuleMetrics::new();
+        let mut run_counts = std::collections::HashMap::new();
+        for run in &runs {
+            totals.merge(&run.module_metrics);
+            for (module, _) in run.module_metrics.by_total_duration() {
+                *run_counts.entry(module.to_string()).or_insert(0u64) += 1;
+            }
+        }
+
+        Ok(totals
+            .by_total_duration()
+            .into_iter()
+            .map(|(module, stats)| ModuleAggregate {
+                module: module.to_string(),
+                runs: run_counts.get(module).copied().unwrap_or(0),
+                stats: stats.clone(),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn record(id: &str, entries: &[(&str, u64)]) -> RunRecord {
+        let mut metrics = ModuleMetrics::new();
+        for (module, millis) in entries {
+            metrics.record(module, Duration::from_millis(*millis), false, true);
+        }
+        RunRecord {
+            execution_id: id.to_string(),
+            host: None,
+            recorded_at: Utc::now(),
+            success: true,
+            duration: Duration::from_secs(1),
+            module_metrics: metrics,
+        }
+    }
+
+    #[test]
+    fn test_aggregate_modules_across_runs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let history = ExecutionHistory::new(temp_dir.path().to_path_buf());
+
+        history
+            .append(&record("run-1", &[("command", 100), ("copy", 20)]))
+            .unwrap();
+        history
+            .append(&record("run-2", &[("command", 300), ("command", 50)]))
+            .unwrap();
+
+        let aggregates = history.aggregate_modules().unwrap();
+        assert_eq!(aggregates.len(), 2);
+
+        let command = &aggregates[0];
+        assert_eq!(command.module, "command");
+        assert_eq!(command.runs, 2);
+        assert_eq!(command.stats.invocations, 3);
+        assert_eq!(command.stats.total_duration, Duration::from_millis(450));
+        assert_eq!(command.stats.min_duration, Some(Duration::from_millis(50)));
+        assert_eq!(command.stats.max_duration, Some(Duration::from_millis(300)));
+
+        assert_eq!(aggregates[1].module, "copy");
+        assert_eq!(aggregates[1].runs, 1);
+    }
+
+    #[test]
+    fn test_malformed_lines_are_skipped() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let history = ExecutionHistory::new(temp_dir.path().to_path_buf());
+
+        history.append(&record("run-1", &[("debug", 5)])).unwrap();
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(temp_dir.path().join(RUNS_FILE))
+            .unwrap();
+        writeln!(file, "{{not json").unwrap();
+
+        assert_eq!(history.load_runs().unwrap().len(), 1);
+    }
+}
@@ -1,24 +1,61 @@
-use crate::deploy::{BinaryCompiler, BinaryDeployer, CompilationCache, DeployError, Result};
-use crate::execution::{ExecutionPlan, ExecutionPlanParser, PlanFormat};
+use crate::binary::{BinaryCompatibilityAnalyzer, BinaryDeploymentPlanner, BinaryRequirements};
+use crate::deploy::manifest::{local_builder_id, plan_hash, ArtifactEntry};
+use crate::deploy::rollback::{backups_from_result, HostRollback, HostSnapshot, RollbackState};
+use crate::deploy::schedule::run_hook;
+use crate::deploy::{
+    AuditLog, BandwidthLimits, BinaryCompiler, BinaryDeployer, CompilationCache, CompilerVersions,
+    Coordinator, Delegation, DeployError, DeploymentManifest, DeploymentMetrics, EventSink,
+    ExecutionHistory, ExecutionVerificationConfig, ExecutionVerificationReport, ExecutionVerifier,
+    Result, RetryConfig, RetryCounts, RollbackPolicy, RollbackReport, RollbackStore, Tracer,
+    TransferCache,
+};
+use crate::execution::rustle_plan::BinaryCompatibility;
+use crate::execution::{ExecutionPlan, ExecutionPlanParser, PlanFormat, SerialBatch};
+use crate::runtime::{
+    signature_path, AgentConfig, BinarySigner, LookupConfig, SensitiveValues, SignedPlan, Span,
+    TraceContext, TrustedKey,
+};
 use crate::types::*;
 use chrono::Utc;
+use futures::stream::{self, StreamExt};
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+/// Why hosts were left alone once the deployment was cancelled
+const CANCELLED: &str = "deployment cancelled";
+
 pub struct DeploymentManager {
     config: DeploymentConfig,
     compiler: BinaryCompiler,
     deployer: BinaryDeployer,
     cache: CompilationCache,
     parser: ExecutionPlanParser,
+    history: ExecutionHistory,
+    verifier: ExecutionVerifier,
+    rollback: RollbackPolicy,
+    rollbacks: RollbackStore,
+    signer: Option<BinarySigner>,
+    provenance: bool,
+    check_manifest: bool,
+    agent: Option<AgentConfig>,
+    tracer: Option<Arc<Tracer>>,
+    metrics: Option<Arc<DeploymentMetrics>>,
+    audit: Option<AuditLog>,
+    lookups: Option<LookupConfig>,
+    sensitive: SensitiveValues,
+    cancel: CancellationToken,
 }
 
 impl DeploymentManager {
     pub fn new(config: DeploymentConfig) -> Self {
         let cache = CompilationCache::new(config.cache_dir.clone());
         let compiler = BinaryCompiler::new(cache.clone());
-        let deployer = BinaryDeployer::new();
+        let deployer = BinaryDeployer::new()
+            .with_transfer_cache(TransferCache::new(config.cache_dir.join("transfers")));
         let parser = ExecutionPlanParser::new();
+        let rollbacks = RollbackStore::new(config.cache_dir.join("rollback"));
 
         Self {
             config,
@@ -26,9 +63,241 @@ impl DeploymentManager {
             deployer,
             cache,
             parser,
+            history: ExecutionHistory::new(ExecutionHistory::default_dir()),
+            verifier: ExecutionVerifier::new(ExecutionVerificationConfig::default()),
+            rollback: RollbackPolicy::default(),
+            rollbacks,
+            signer: None,
+            provenance: false,
+            check_manifest: false,
+            agent: None,
+            tracer: None,
+            metrics: None,
+            audit: None,
+            lookups: None,
+            sensitive: SensitiveValues::new(),
+            cancel: CancellationToken::new(),
         }
     }
 
+    /// Share compiled binaries through `store`
+    pub fn with_cache_store(mut self, store: Arc<dyn crate::compilation::CacheStore>) -> Self {
+        self.cache = self.cache.with_store(store);
+        self.compiler =
+            BinaryCompiler::new(self.cache.clone()).with_cancellation(self.cancel.clone());
+        self
+    }
+
+    pub fn with_history(mut self, history: ExecutionHistory) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Configure how finished runs are checked by [`Self::execute_deployments`]
+    pub fn with_execution_verification(mut self, config: ExecutionVerificationConfig) -> Self {
+        self.verifier = ExecutionVerifier::new(config);
+        self
+    }
+
+    /// Configure when failed deployments are rolled back;
+    /// [`RollbackPolicy::disabled`] turns rollback off
+    pub fn with_rollback_policy(mut self, policy: RollbackPolicy) -> Self {
+        self.rollback = policy;
+        self
+    }
+
+    /// Configure how transient failures are retried, per phase and host
+    pub fn with_retry_config(mut self, config: RetryConfig) -> Self {
+        self.deployer = self.deployer.with_retry_config(config);
+        self
+    }
+
+    /// Sign compiled binaries with `signer` and only deploy and run binaries
+    /// carrying its signature. Runners built afterwards also check themselves.
+    pub fn with_binary_signing(mut self, signer: BinarySigner) -> Self {
+        self.deployer = self.deployer.with_trusted_key(signer.public_key());
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Only deploy and run binaries signed with `key`, for binaries signed elsewhere
+    pub fn with_trusted_key(mut self, key: TrustedKey) -> Self {
+        self.deployer = self.deployer.with_trusted_key(key);
+        self
+    }
+
+    /// Also write SLSA provenance next to the manifest of compiled binaries
+    pub fn with_provenance(mut self) -> Self {
+        self.provenance = true;
+        self
+    }
+
+    /// Check binaries against the manifest in the output directory before
+    /// deploying them, for binaries compiled by an earlier run
+    pub fn with_manifest_verification(mut self) -> Self {
+        self.check_manifest = true;
+        self
+    }
+
+    /// Build runners that stay resident after their embedded plan and run
+    /// further plans signed by [`Self::sign_plan`]. Needs binary signing.
+    pub fn with_agent_mode(mut self, config: AgentConfig) -> Self {
+        self.agent = Some(config);
+        self
+    }
+
+    /// Sign `plan` for resident agents to pull or be pushed
+    pub fn sign_plan(&self, plan: &ExecutionPlan) -> Result<SignedPlan> {
+        let signer = self.signer.as_ref().ok_or_else(|| {
+            DeployError::Configuration("Signing plans needs a binary signing key".to_string())
+        })?;
+        SignedPlan::sign(plan, signer).map_err(|e| DeployError::Configuration(e.to_string()))
+    }
+
+    /// Stream events from runners to `sink` while they execute, for live
+    /// progress and JSON output
+    pub fn with_event_sink(mut self, sink: EventSink) -> Self {
+        self.deployer = self.deployer.with_event_sink(sink);
+        self
+    }
+
+    /// Cap binary transfer rates per host and for the whole deployment
+    pub fn with_bandwidth_limits(mut self, limits: BandwidthLimits) -> Self {
+        self.deployer = self.deployer.with_bandwidth_limits(limits);
+        self
+    }
+
+    /// Compress binaries with zstd at `level` while they are transferred
+    pub fn with_transfer_compression(mut self, level: i32) -> Self {
+        self.deployer = self.deployer.with_transfer_compression(level);
+        self
+    }
+
+    /// Password runners authenticate with when tasks become another user
+    pub fn with_become_password(mut self, password: impl Into<String>) -> Self {
+        self.deployer = self.deployer.with_become_password(password);
+        self
+    }
+
+    /// Have runners continue from the first task a previous, failed run did
+    /// not complete instead of running the whole plan again
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.deployer = self.deployer.with_resume(resume);
+        self
+    }
+
+    /// Stop once `token` is cancelled: builds are killed, transfers
+    /// abandoned and runners stop after their current task, keeping their
+    /// progress for a run with [`Self::with_resume`]. Hosts not started yet
+    /// are skipped, and nothing is rolled back.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.compiler = self.compiler.with_cancellation(token.clone());
+        self.deployer = self.deployer.with_cancellation(token.clone());
+        self.cancel = token;
+        self
+    }
+
+    /// Inject simulated failures into deployments, for testing recovery paths
+    pub fn with_fault_injector(mut self, faults: crate::runtime::FaultInjector) -> Self {
+        self.deployer = self.deployer.with_fault_injector(faults);
+        self
+    }
+
+    /// Trace compilations, and deployments and executions per host, in
+    /// `tracer`, together with the plays and tasks the runners execute
+    pub fn with_tracer(mut self, tracer: Arc<Tracer>) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// Count hosts, compilations, transferred bytes and task durations in
+    /// `metrics`; see [`crate::deploy::metrics`]
+    pub fn with_metrics(mut self, metrics: Arc<DeploymentMetrics>) -> Self {
+        self.deployer = self.deployer.with_metrics(Arc::clone(&metrics));
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Append the changes runners report to `log`; see
+    /// [`crate::deploy::audit`]
+    pub fn with_audit_log(mut self, log: AuditLog) -> Self {
+        self.audit = Some(log);
+        self
+    }
+
+    /// Have runners resolve `lookup()` expressions with `config`. It is
+    /// embedded in the runners, so credentials are better left to their
+    /// hosts' environments.
+    pub fn with_lookups(mut self, config: LookupConfig) -> Self {
+        self.lookups = Some(config);
+        self
+    }
+
+    /// Keep `values` out of the plans embedded in runners: they are
+    /// replaced by references and delivered sealed to a key generated for
+    /// this deployment when runners execute; see
+    /// [`crate::runtime::sealed_secrets`]
+    pub fn with_sensitive_values(mut self, values: SensitiveValues) -> Result<Self> {
+        self.sensitive.extend(values);
+        let (secrets, key) = self
+            .sensitive
+            .seal()
+            .map_err(|e| DeployError::Configuration(e.to_string()))?;
+        self.deployer = self.deployer.with_sealed_secrets(secrets, key);
+        Ok(self)
+    }
+
+    /// `plan` with the sensitive values in the arguments of its tasks and
+    /// handlers replaced by references
+    fn sealed_plan(&self, plan: &ExecutionPlan) -> ExecutionPlan {
+        let mut plan = plan.clone();
+        if self.sensitive.is_empty() {
+            return plan;
+        }
+        let args = plan
+            .tasks
+            .iter_mut()
+            .map(|task| &mut task.args)
+            .chain(plan.handlers.iter_mut().map(|handler| &mut handler.args));
+        for args in args {
+            for value in args.values_mut() {
+                *value = self.sensitive.replace(value);
+            }
+        }
+        plan
+    }
+
+    /// Count the hosts of `report` in the metrics, if they are kept
+    fn count_hosts(&self, phase: &str, report: &DeploymentReport) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_report(phase, report);
+        }
+    }
+
+    /// Start a span of the trace, if the deployment is traced
+    fn start_span(&self, name: String) -> Option<Span> {
+        self.tracer.as_ref().map(|tracer| tracer.start(name))
+    }
+
+    /// End `span`, failed with `error` if given
+    fn end_span(&self, span: Option<Span>, error: Option<&str>) {
+        if let (Some(tracer), Some(mut span)) = (&self.tracer, span) {
+            if let Some(error) = error {
+                span.fail(error);
+            }
+            tracer.record([span.end()]);
+        }
+    }
+
+    /// Record the per-module metrics of a run reported back by a host
+    pub fn record_execution(
+        &self,
+        host: &str,
+        result: &crate::runtime::ExecutionResult,
+    ) -> Result<()> {
+        self.history.record_execution(result, Some(host))
+    }
+
     pub async fn create_deployment_plan_from_execution(
         &self,
         execution_plan: &ExecutionPlan,
@@ -39,9 +308,12 @@ impl DeploymentManager {
         let execution_plan_hash = self.calculate_hash_from_plan(execution_plan);
         let deployment_id = Uuid::new_v4().to_string();
 
-        // Create binary compilations for each unique target architecture
-        let binary_compilations =
+        // Create one binary compilation per target architecture and point
+        // each target at the binary built for it
+        let (binary_compilations, deployment_targets) =
             self.create_binary_compilations_from_plan(execution_plan, targets, &deployment_id)?;
+        let schedule = HostSchedule::from_plan(execution_plan, &deployment_targets)?;
+        let deployment_strategy = deployment_strategy(execution_plan, deployment_targets.len())?;
 
         let deployment_plan = DeploymentPlan {
             metadata: DeploymentMetadata {
@@ -52,9 +324,17 @@ impl DeploymentManager {
                 compiler_version: self.get_compiler_version(),
             },
             binary_compilations,
-            deployment_targets: targets.to_vec(),
-            deployment_strategy: DeploymentStrategy::Parallel, // Default strategy
+            deployment_targets,
+            deployment_strategy,
             rollback_info: None,
+            schedule,
+            delegated_tasks: execution_plan
+                .tasks
+                .iter()
+                .filter(|task| task.run_once || task.delegate_to.is_some())
+                .cloned()
+                .collect(),
+            strategy: execution_plan.strategy.clone(),
         };
 
         debug!(
@@ -100,13 +380,34 @@ impl DeploymentManager {
             if !self.config.binary_size_limit_mb > 0 {
                 if let Some(_cached) = self.cache.get_cached_binary(&compilation.checksum) {
                     info!("Using cached binary for {}", compilation.binary_name);
+                    if let Some(metrics) = &self.metrics {
+                        metrics.record_cache_hit();
+                    }
                     compiled_binaries.push(compilation.clone());
                     continue;
                 }
             }
 
             info!("Compiling binary: {}", compilation.binary_name);
-            let compiled = self.compiler.compile_binary(compilation).await?;
+            let span = self
+                .start_span(format!("compile {}", compilation.binary_name))
+                .map(|span| {
+                    span.with_attribute("rustle.binary", compilation.binary_name.as_str())
+                        .with_attribute("rustle.target", compilation.target_triple.as_str())
+                });
+            let compiled = self.compiler.compile_binary(compilation).await;
+            let error = compiled.as_ref().err().map(ToString::to_string);
+            self.end_span(
+                span.map(|span| match &compiled {
+                    Ok(compiled) => span.with_attribute("rustle.size", compiled.size),
+                    Err(_) => span,
+                }),
+                error.as_deref(),
+            );
+            let compiled = compiled?;
+            if let Some(metrics) = &self.metrics {
+                metrics.record_compilation(&compilation.target_triple, compiled.compilation_time);
+            }
 
             // Validate binary size
             if self.config.binary_size_limit_mb > 0 {
@@ -119,6 +420,23 @@ impl DeploymentManager {
                 }
             }
 
+            if let Some(parent) = compilation.output_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&compilation.output_path, &compiled.binary_data)?;
+
+            // Cached binaries above keep the signature made when they were
+            // built; re-signing them would vouch for whatever is in the cache
+            if let Some(ref signer) = self.signer {
+                let signature = signer.sign(&compiled.binary_data, &compilation.binary_name);
+                std::fs::write(signature_path(&compilation.output_path), signature.encode())?;
+                info!(
+                    "Signed {} with key {}",
+                    compilation.binary_name,
+                    signer.public_key().key_id()
+                );
+            }
+
             // Update compilation with actual results
             let mut updated_compilation = compilation.clone();
             updated_compilation.checksum = compiled.checksum;
@@ -128,91 +446,679 @@ impl DeploymentManager {
         }
 
         info!("Successfully compiled {} binaries", compiled_binaries.len());
+        self.write_manifest(plan, &compiled_binaries)?;
         Ok(compiled_binaries)
     }
 
+    /// Record `compilations` in the manifest of the output directory, and in
+    /// provenance if enabled. Binaries missing from disk are left out.
+    pub fn write_manifest(
+        &self,
+        plan: &DeploymentPlan,
+        compilations: &[BinaryCompilation],
+    ) -> Result<DeploymentManifest> {
+        let output_dir = &self.config.output_dir;
+        let mut manifest = DeploymentManifest::new(
+            &plan.metadata.deployment_id,
+            CompilerVersions::detect().clone(),
+        );
+        for compilation in compilations {
+            if !compilation.output_path.is_file() {
+                warn!(
+                    "Leaving {} out of the manifest: {} does not exist",
+                    compilation.binary_name,
+                    compilation.output_path.display()
+                );
+                continue;
+            }
+            manifest.add_artifact(ArtifactEntry::from_file(
+                output_dir,
+                &compilation.output_path,
+                &compilation.target_triple,
+                &plan_hash(&compilation.embedded_data.execution_plan),
+            )?);
+        }
+
+        let path = manifest.write(output_dir)?;
+        info!(
+            "Wrote manifest of {} binaries to {}",
+            manifest.artifacts.len(),
+            path.display()
+        );
+        if self.provenance {
+            let path = manifest.write_provenance(output_dir, &local_builder_id())?;
+            info!("Wrote provenance to {}", path.display());
+        }
+        Ok(manifest)
+    }
+
+    /// Check the binaries of `plan` against the manifest in the output
+    /// directory. Every binary on disk is checked, since hosts may be given
+    /// any compatible compilation of the plan.
+    pub fn verify_manifest(&self, plan: &DeploymentPlan) -> Result<()> {
+        let output_dir = &self.config.output_dir;
+        let manifest = DeploymentManifest::load(output_dir)?;
+        for compilation in &plan.binary_compilations {
+            if !compilation.output_path.is_file() {
+                continue;
+            }
+            let entry = manifest.verify_artifact(output_dir, &compilation.output_path)?;
+            if entry.target_triple != compilation.target_triple {
+                return Err(DeployError::ManifestMismatch {
+                    path: compilation.output_path.display().to_string(),
+                    reason: format!(
+                        "built for {}, plan expects {}",
+                        entry.target_triple, compilation.target_triple
+                    ),
+                });
+            }
+        }
+        debug!(
+            "Binaries of {} match the manifest",
+            plan.metadata.deployment_id
+        );
+        Ok(())
+    }
+
     pub async fn deploy_binaries(&self, plan: &DeploymentPlan) -> Result<DeploymentReport> {
+        let forks = self.config.forks.max(1);
         info!(
-            "Deploying binaries to {} targets",
-            plan.deployment_targets.len()
+            "Deploying binaries to {} targets ({} at a time)",
+            plan.deployment_targets.len(),
+            forks
         );
+        if self.check_manifest {
+            self.verify_manifest(plan)?;
+        }
 
-        let mut deployment_results = Vec::new();
-        let mut successful_deployments = 0;
-        let mut failed_deployments = 0;
+        let started_at = Utc::now();
+        let snapshots = Mutex::new(Vec::new());
 
-        for target in &plan.deployment_targets {
-            info!("Deploying to host: {}", target.host);
+        let indexed_results: Vec<(usize, DeploymentResult)> =
+            stream::iter(0..plan.deployment_targets.len())
+                .map(|index| {
+                    // Indexed, not iterated by reference, for the run to be
+                    // spawnable: closures taking references are not `Send`
+                    // enough for the compiler
+                    let target = &plan.deployment_targets[index];
+                    let snapshots = &snapshots;
+                    async move { (index, self.deploy_target(plan, target, snapshots).await) }
+                })
+                .buffer_unordered(forks)
+                .collect()
+                .await;
 
-            // Find the corresponding binary compilation
-            let compilation = plan
-                .binary_compilations
-                .iter()
-                .find(|c| c.compilation_id == target.binary_compilation_id)
-                .ok_or_else(|| {
-                    DeployError::Configuration(format!(
-                        "No compilation found for target {}",
-                        target.host
-                    ))
-                })?;
+        let mut report = DeploymentReport::new(plan, indexed_results, started_at);
+        info!(
+            "Deployment completed: {}/{} successful",
+            report.successful_deployments, report.total_targets
+        );
+
+        let snapshots = snapshots
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        if !snapshots.is_empty() {
+            let mut state = self.rollbacks.load(&plan.metadata.deployment_id).await;
+            for snapshot in snapshots {
+                state.hosts.insert(snapshot.host.clone(), snapshot);
+            }
+            self.save_rollback_state(&state).await;
+
+            if !self.cancel.is_cancelled()
+                && self
+                    .rollback
+                    .should_roll_back(report.failed_deployments, report.total_targets)
+            {
+                let reason = format!(
+                    "{} of {} hosts failed to deploy",
+                    report.failed_deployments, report.total_targets
+                );
+                let rollback = self.roll_back(plan, &state, None, reason).await;
+                report.apply_rollback(rollback);
+            }
+        }
+
+        self.count_hosts("deploy", &report);
+        Ok(report)
+    }
 
-            match self.deployer.deploy_to_host(compilation, target).await {
-                Ok(_) => {
-                    info!("Successfully deployed to {}", target.host);
-                    successful_deployments += 1;
-
-                    if self.config.verify_deployments {
-                        match self.deployer.verify_deployment(target).await {
-                            Ok(true) => {
-                                info!("Deployment verification successful for {}", target.host);
-                            }
-                            Ok(false) => {
-                                warn!("Deployment verification failed for {}", target.host);
-                                failed_deployments += 1;
-                            }
-                            Err(e) => {
-                                warn!("Deployment verification error for {}: {}", target.host, e);
-                                failed_deployments += 1;
-                            }
-                        }
+    /// Run the deployed binaries and verify what they report. Hosts whose
+    /// run fails verification are recorded as failed in the report.
+    pub async fn execute_deployments(
+        &self,
+        plan: &DeploymentPlan,
+        args: &[String],
+    ) -> Result<DeploymentReport> {
+        let forks = self.config.forks.max(1);
+        info!(
+            "Executing binaries on {} targets ({} at a time)",
+            plan.deployment_targets.len(),
+            forks
+        );
+
+        let started_at = Utc::now();
+        let targets = &plan.deployment_targets;
+        let batches = plan.deployment_strategy.batches(targets.len());
+        let mut indexed_results: Vec<(usize, DeploymentResult)> = Vec::new();
+        let mut abort_reason: Option<String> = None;
+        // Shared by every batch so run-once tasks run once per execution,
+        // throttles count every host and a fatal failure stops later batches
+        let delegation = &Delegation::new(plan);
+        let coordinator = (targets.len() > 1).then(|| Coordinator::new(&plan.strategy));
+        let coordinator = coordinator.as_ref();
+
+        for (number, batch) in batches.iter().enumerate() {
+            if abort_reason.is_none() && self.cancel.is_cancelled() {
+                abort_reason = Some(CANCELLED.to_string());
+            }
+            if let Some(reason) = &abort_reason {
+                indexed_results.extend(
+                    batch
+                        .clone()
+                        .map(|index| (index, DeploymentResult::skipped(&targets[index], reason))),
+                );
+                continue;
+            }
+            if batches.len() > 1 {
+                info!(
+                    "Starting batch {}/{}: {}",
+                    number + 1,
+                    batches.len(),
+                    targets[batch.clone()]
+                        .iter()
+                        .map(|target| target.host.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+
+            let results: Vec<(usize, DeploymentResult)> = stream::iter(batch.clone())
+                .map(|index| {
+                    let target = &targets[index];
+                    async move {
+                        (
+                            index,
+                            self.execute_target(plan, target, args, delegation, coordinator)
+                                .await,
+                        )
                     }
+                })
+                .buffer_unordered(forks)
+                .collect()
+                .await;
+
+            let failed = results
+                .iter()
+                .filter(|(_, result)| matches!(result.status, DeploymentStatus::Failed { .. }))
+                .count();
+            if batches.len() > 1 {
+                info!(
+                    "Finished batch {}/{}: {}/{} hosts failed",
+                    number + 1,
+                    batches.len(),
+                    failed,
+                    batch.len()
+                );
+            }
+            if let Some(max) = plan.deployment_strategy.max_fail_percentage() {
+                if failed * 100 > max as usize * batch.len() && number + 1 < batches.len() {
+                    let reason = format!(
+                        "{failed} of {} hosts in batch {} failed, more than max_fail_percentage {max}%",
+                        batch.len(),
+                        number + 1
+                    );
+                    warn!("Aborting remaining batches: {}", reason);
+                    abort_reason = Some(reason);
                 }
+            }
+            if let Some(reason) = coordinator.and_then(Coordinator::abort_reason) {
+                if abort_reason.is_none() && number + 1 < batches.len() {
+                    warn!("Aborting remaining batches: {}", reason);
+                    abort_reason = Some(reason);
+                }
+            }
+            indexed_results.extend(results);
+        }
+
+        let mut report = DeploymentReport::new(plan, indexed_results, started_at);
+        info!(
+            "Execution completed: {}/{} verified",
+            report.successful_deployments, report.total_targets
+        );
+        if self.cancel.is_cancelled() {
+            info!(
+                "Deployment cancelled; rerun with --resume to continue where the runners stopped"
+            );
+        }
+
+        let mut state = self.rollbacks.load(&plan.metadata.deployment_id).await;
+        if state.hosts.is_empty() {
+            self.count_hosts("execute", &report);
+            return Ok(report);
+        }
+        for result in &report.deployment_results {
+            let reported = result.verification.as_ref().and_then(|v| v.result.as_ref());
+            if let (Some(snapshot), Some(reported)) = (state.hosts.get_mut(&result.host), reported)
+            {
+                snapshot.backups.extend(backups_from_result(reported));
+            }
+        }
+        self.save_rollback_state(&state).await;
+
+        if !self.cancel.is_cancelled()
+            && self
+                .rollback
+                .should_roll_back(report.failed_deployments, report.total_targets)
+        {
+            let reason = format!(
+                "{} of {} hosts failed execution",
+                report.failed_deployments, report.total_targets
+            );
+            let rerun_args = self.rollback.rerun_previous.then_some(args);
+            let rollback = self.roll_back(plan, &state, rerun_args, reason).await;
+            report.apply_rollback(rollback);
+        }
+
+        self.count_hosts("execute", &report);
+        Ok(report)
+    }
+
+    /// Return every snapshotted host of the deployment to its previous
+    /// state, re-running the restored binary with `rerun_args` if given
+    async fn roll_back(
+        &self,
+        plan: &DeploymentPlan,
+        state: &RollbackState,
+        rerun_args: Option<&[String]>,
+        reason: String,
+    ) -> RollbackReport {
+        warn!(
+            "Rolling back deployment {}: {}",
+            state.deployment_id, reason
+        );
+
+        // Rollbacks only start once polled, a fork's worth at a time
+        let rollbacks: Vec<_> = plan
+            .deployment_targets
+            .iter()
+            .filter_map(|target| {
+                let snapshot = state.hosts.get(&target.host)?;
+                Some(self.roll_back_host(target, snapshot, rerun_args))
+            })
+            .collect();
+        let hosts = stream::iter(rollbacks)
+            .buffered(self.config.forks.max(1))
+            .collect()
+            .await;
+
+        RollbackReport { reason, hosts }
+    }
+
+    async fn roll_back_host(
+        &self,
+        target: &DeploymentTarget,
+        snapshot: &HostSnapshot,
+        rerun_args: Option<&[String]>,
+    ) -> HostRollback {
+        let mut rollback = HostRollback {
+            host: target.host.clone(),
+            restored_binary: false,
+            restored_files: 0,
+            rerun_success: None,
+            error: None,
+        };
+
+        match self.deployer.restore_snapshot(target, snapshot).await {
+            Ok(restored_files) => {
+                rollback.restored_binary = true;
+                rollback.restored_files = restored_files;
+            }
+            Err(e) => {
+                warn!("Failed to roll back {}: {}", target.host, e);
+                rollback.error = Some(e.to_string());
+                return rollback;
+            }
+        }
+
+        if let (Some(args), Some(_)) = (rerun_args, &snapshot.previous_binary) {
+            match self.deployer.execute_binary(target, args).await {
+                Ok(run) => rollback.rerun_success = Some(run.exit_code == 0),
                 Err(e) => {
-                    warn!("Failed to deploy to {}: {}", target.host, e);
-                    failed_deployments += 1;
+                    warn!("Failed to re-run previous binary on {}: {}", target.host, e);
+                    rollback.rerun_success = Some(false);
+                    rollback.error = Some(e.to_string());
                 }
             }
+        }
+        rollback
+    }
 
-            deployment_results.push(DeploymentResult {
-                host: target.host.clone(),
-                status: if successful_deployments > failed_deployments {
-                    DeploymentStatus::Deployed
-                } else {
-                    DeploymentStatus::Failed {
-                        error: "Deployment failed".to_string(),
-                    }
-                },
-                deployed_at: Some(Utc::now()),
-            });
+    async fn save_rollback_state(&self, state: &RollbackState) {
+        if let Err(e) = self.rollbacks.save(state).await {
+            warn!(
+                "Failed to save rollback state for {}: {}",
+                state.deployment_id, e
+            );
         }
+    }
 
-        let report = DeploymentReport {
-            deployment_id: plan.metadata.deployment_id.clone(),
-            total_targets: plan.deployment_targets.len(),
-            successful_deployments,
-            failed_deployments,
-            deployment_results,
-            started_at: Utc::now(), // TODO: Track actual start time
-            completed_at: Utc::now(),
+    async fn execute_target(
+        &self,
+        plan: &DeploymentPlan,
+        target: &DeploymentTarget,
+        args: &[String],
+        delegation: &Delegation,
+        coordinator: Option<&Coordinator>,
+    ) -> DeploymentResult {
+        // A host failed a play with any_errors_fatal while this one waited
+        // for a fork
+        if let Some(reason) = coordinator.and_then(Coordinator::abort_reason) {
+            return DeploymentResult::skipped(target, &reason);
+        }
+        if self.cancel.is_cancelled() {
+            return DeploymentResult::skipped(target, CANCELLED);
+        }
+        let start = std::time::Instant::now();
+        let span = self
+            .start_span(format!("execute {}", target.host))
+            .map(|span| span.with_attribute("host.name", target.host.as_str()));
+        let trace = span.as_ref().map(Span::context);
+        let outcome = async {
+            self.enter_window(plan, target).await?;
+            for hook in plan.schedule.pre_execution_hooks(&target.host) {
+                run_hook(&self.deployer, hook, target, &plan.metadata.deployment_id).await?;
+            }
+            let report = self
+                .execute_and_verify(plan, target, args, delegation, coordinator, trace.as_ref())
+                .await?;
+            if report.is_verified() {
+                for hook in plan.schedule.post_execution_hooks(&target.host) {
+                    run_hook(&self.deployer, hook, target, &plan.metadata.deployment_id).await?;
+                }
+            }
+            Ok::<_, DeployError>(report)
         };
+        let (status, verification) = match outcome.await {
+            Ok(report) if report.is_verified() => {
+                info!("Execution verified on {}", target.host);
+                (DeploymentStatus::Verified, Some(report))
+            }
+            Ok(report) => {
+                warn!(
+                    "Execution verification failed on {}: {}",
+                    target.host,
+                    report.summary()
+                );
+                let error = format!("Execution verification failed: {}", report.summary());
+                (DeploymentStatus::Failed { error }, Some(report))
+            }
+            Err(e) => {
+                warn!("Failed to execute on {}: {}", target.host, e);
+                let error = e.to_string();
+                (DeploymentStatus::Failed { error }, None)
+            }
+        };
+        // Stopped because of another host, or the deployment was cancelled,
+        // rather than failed
+        let aborted = coordinator
+            .and_then(|coordinator| coordinator.aborted(&target.host))
+            .or_else(|| self.cancel.is_cancelled().then(|| CANCELLED.to_string()));
+        let status = match aborted {
+            Some(reason) if !matches!(status, DeploymentStatus::Verified) => {
+                DeploymentStatus::Aborted { reason }
+            }
+            _ => status,
+        };
+        if let (Some(tracer), Some(result)) = (
+            &self.tracer,
+            verification.as_ref().and_then(|v| v.result.as_ref()),
+        ) {
+            tracer.record(result.spans.iter().cloned());
+        }
+        self.end_span(span, status_error(&status));
 
+        DeploymentResult {
+            host: target.host.clone(),
+            deployed_at: None,
+            status,
+            duration: start.elapsed(),
+            verification,
+            retries: self.deployer.take_retry_counts(&target.host),
+        }
+    }
+
+    async fn execute_and_verify(
+        &self,
+        plan: &DeploymentPlan,
+        target: &DeploymentTarget,
+        args: &[String],
+        delegation: &Delegation,
+        coordinator: Option<&Coordinator>,
+        trace: Option<&TraceContext>,
+    ) -> Result<ExecutionVerificationReport> {
+        let compilation = plan
+            .binary_compilations
+            .iter()
+            .find(|c| c.compilation_id == target.binary_compilation_id)
+            .ok_or_else(|| {
+                DeployError::Configuration(format!(
+                    "No compilation found for target {}",
+                    target.host
+                ))
+            })?;
+
+        let run = self
+            .deployer
+            .execute_binary_delegating(target, args, Some(delegation), coordinator, trace)
+            .await?;
+        let report = self
+            .deployer
+            .verify_execution(target, compilation, &run, &self.verifier)
+            .await?;
+
+        if let Some(ref result) = report.result {
+            if let Some(metrics) = &self.metrics {
+                metrics.record_execution(result);
+            }
+            if let Some(audit) = &self.audit {
+                if let Err(e) = audit.append(&plan.metadata.deployment_id, &result.audit) {
+                    warn!("Failed to audit the changes on {}: {}", target.host, e);
+                }
+            }
+            if let Err(e) = self.record_execution(&target.host, result) {
+                warn!("Failed to record execution for {}: {}", target.host, e);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Deploy (and optionally verify) a single host, bounded by the per-host timeout
+    async fn deploy_target(
+        &self,
+        plan: &DeploymentPlan,
+        target: &DeploymentTarget,
+        snapshots: &Mutex<Vec<HostSnapshot>>,
+    ) -> DeploymentResult {
+        if self.cancel.is_cancelled() {
+            return DeploymentResult::skipped(target, CANCELLED);
+        }
+        info!("Deploying to host: {}", target.host);
+        let start = std::time::Instant::now();
+        let span = self
+            .start_span(format!("deploy {}", target.host))
+            .map(|span| span.with_attribute("host.name", target.host.as_str()));
+
+        // Waiting for the maintenance window does not count towards the timeout
+        let outcome = match self.enter_window(plan, target).await {
+            Err(e) => Err(e),
+            Ok(()) => match self.config.default_timeout_secs {
+                0 => self.deploy_and_verify(plan, target, snapshots).await,
+                timeout_secs => tokio::time::timeout(
+                    std::time::Duration::from_secs(timeout_secs),
+                    self.deploy_and_verify(plan, target, snapshots),
+                )
+                .await
+                .unwrap_or(Err(DeployError::DeploymentTimeout {
+                    timeout: timeout_secs,
+                })),
+            },
+        };
+
+        let status = match outcome {
+            Ok(status) => {
+                info!("Successfully deployed to {}", target.host);
+                status
+            }
+            Err(DeployError::Cancelled) => DeploymentStatus::Aborted {
+                reason: CANCELLED.to_string(),
+            },
+            Err(e) => {
+                warn!("Failed to deploy to {}: {}", target.host, e);
+                DeploymentStatus::Failed {
+                    error: e.to_string(),
+                }
+            }
+        };
+        self.end_span(span, status_error(&status));
+
+        DeploymentResult {
+            host: target.host.clone(),
+            deployed_at: match status {
+                DeploymentStatus::Failed { .. } | DeploymentStatus::Aborted { .. } => None,
+                _ => Some(Utc::now()),
+            },
+            status,
+            duration: start.elapsed(),
+            verification: None,
+            retries: self.deployer.take_retry_counts(&target.host),
+        }
+    }
+
+    /// Return once `target` is inside its maintenance window, waiting for
+    /// the window to open if the plan asks to and failing otherwise
+    async fn enter_window(&self, plan: &DeploymentPlan, target: &DeploymentTarget) -> Result<()> {
+        let now = Utc::now();
+        let opens_at = plan.schedule.next_opening(&target.host, now)?;
+        if opens_at <= now {
+            return Ok(());
+        }
+        if !plan.schedule.wait_for_window {
+            return Err(DeployError::OutsideMaintenanceWindow {
+                host: target.host.clone(),
+                opens_at,
+            });
+        }
         info!(
-            "Deployment completed: {}/{} successful",
-            successful_deployments,
-            plan.deployment_targets.len()
+            "Waiting until {} for the maintenance window of {}",
+            opens_at, target.host
         );
+        tokio::time::sleep((opens_at - now).to_std().unwrap_or_default()).await;
+        Ok(())
+    }
 
-        Ok(report)
+    async fn deploy_and_verify(
+        &self,
+        plan: &DeploymentPlan,
+        target: &DeploymentTarget,
+        snapshots: &Mutex<Vec<HostSnapshot>>,
+    ) -> Result<DeploymentStatus> {
+        // Find the corresponding binary compilation
+        let compilation = plan
+            .binary_compilations
+            .iter()
+            .find(|c| c.compilation_id == target.binary_compilation_id)
+            .ok_or_else(|| {
+                DeployError::Configuration(format!(
+                    "No compilation found for target {}",
+                    target.host
+                ))
+            })?;
+        let compilation = self.select_compilation(plan, compilation, target).await?;
+
+        if self.rollback.enabled {
+            if let Some(snapshot) = self.deployer.snapshot_binary(target).await? {
+                snapshots
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(snapshot);
+            }
+        }
+        self.deployer.deploy_to_host(compilation, target).await?;
+
+        if !self.config.verify_deployments {
+            return Ok(DeploymentStatus::Deployed);
+        }
+
+        if self.deployer.verify_deployment(target).await? {
+            info!("Deployment verification successful for {}", target.host);
+            Ok(DeploymentStatus::Verified)
+        } else {
+            Err(DeployError::DeploymentFailed {
+                host: target.host.clone(),
+                reason: "Deployment verification failed".to_string(),
+            })
+        }
+    }
+
+    /// Check `compilation` against the probed platform of `target`. An
+    /// incompatible binary is swapped for another compilation in the plan
+    /// that does fit the host, or the deployment fails before anything is
+    /// transferred.
+    async fn select_compilation<'a>(
+        &self,
+        plan: &'a DeploymentPlan,
+        compilation: &'a BinaryCompilation,
+        target: &DeploymentTarget,
+    ) -> Result<&'a BinaryCompilation> {
+        let Some(platform) = self.deployer.probe_platform(target).await? else {
+            return Ok(compilation);
+        };
+
+        let analyzer = BinaryCompatibilityAnalyzer::new();
+        let assess = |candidate: &BinaryCompilation| {
+            let binary = std::fs::read(&candidate.output_path).unwrap_or_default();
+            let requirements =
+                BinaryRequirements::for_triple(&candidate.target_triple).with_binary(&binary);
+            analyzer.assess_host_compatibility(&requirements, &platform)
+        };
+
+        let reasons = match assess(compilation) {
+            BinaryCompatibility::FullyCompatible => return Ok(compilation),
+            BinaryCompatibility::PartiallyCompatible { limitations } => {
+                warn!(
+                    "Deploying {} to {} ({}) without full verification: {}",
+                    compilation.compilation_id,
+                    target.host,
+                    platform,
+                    limitations.join("; ")
+                );
+                return Ok(compilation);
+            }
+            BinaryCompatibility::Incompatible { reasons } => reasons,
+        };
+
+        let alternative = plan.binary_compilations.iter().find(|candidate| {
+            candidate.compilation_id != compilation.compilation_id
+                && matches!(assess(candidate), BinaryCompatibility::FullyCompatible)
+        });
+        match alternative {
+            Some(alternative) => {
+                warn!(
+                    "{} is not compatible with {} ({}), deploying {} ({}) instead",
+                    compilation.compilation_id,
+                    target.host,
+                    platform,
+                    alternative.compilation_id,
+                    alternative.target_triple
+                );
+                Ok(alternative)
+            }
+            None => Err(DeployError::IncompatibleTarget {
+                host: target.host.clone(),
+                compilation_id: compilation.compilation_id.clone(),
+                reason: reasons.join("; "),
+            }),
+        }
     }
 
     pub async fn verify_deployments(
@@ -286,8 +1192,10 @@ impl DeploymentManager {
     }
 
     fn get_compiler_version(&self) -> String {
-        // TODO: Get actual rustc version
-        "rustc 1.70.0".to_string()
+        CompilerVersions::detect()
+            .rustc
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string())
     }
 
     fn create_binary_compilations_from_plan(
@@ -295,25 +1203,56 @@ impl DeploymentManager {
         execution_plan: &ExecutionPlan,
         targets: &[DeploymentTarget],
         deployment_id: &str,
-    ) -> Result<Vec<BinaryCompilation>> {
-        // Group targets by architecture to minimize compilation work
-        let mut compilations = Vec::new();
-        let mut processed_targets = std::collections::HashSet::new();
+    ) -> Result<(Vec<BinaryCompilation>, Vec<DeploymentTarget>)> {
+        if self.agent.is_some() && self.signer.is_none() {
+            return Err(DeployError::Configuration(
+                "Agent mode needs binary signing so runners can check the plans they receive"
+                    .to_string(),
+            ));
+        }
 
-        for _target in targets {
-            // TODO: Extract actual target triple from target or inventory
-            let target_triple = "x86_64-unknown-linux-gnu".to_string();
+        if targets.is_empty() {
+            return Ok((Vec::new(), Vec::new()));
+        }
 
-            if processed_targets.contains(&target_triple) {
-                continue;
-            }
-            processed_targets.insert(target_triple.clone());
+        // Group targets by architecture to minimize compilation work
+        let hosts: Vec<String> = targets.iter().map(|t| t.host.clone()).collect();
+        let groups = BinaryDeploymentPlanner::new()
+            .with_host_architectures(targets.iter().filter_map(|target| {
+                let triple = target.binary_compilation_id.strip_prefix("rustle-")?;
+                Some((target.host.as_str(), triple))
+            }))
+            .group_hosts_by_architecture(&hosts)
+            .map_err(|e| DeployError::Configuration(e.to_string()))?;
+
+        let mut compilations = Vec::new();
+        let mut deployment_targets = targets.to_vec();
+        let embedded_plan =
+            serde_json::to_string(&self.sealed_plan(execution_plan)).unwrap_or_default();
 
+        for (target_triple, group_hosts) in groups {
             let compilation_id = format!("{deployment_id}-{target_triple}");
+            let binary_name = if target_triple.contains("windows") {
+                format!("rustle-runner-{target_triple}.exe")
+            } else {
+                format!("rustle-runner-{target_triple}")
+            };
+            for target in deployment_targets
+                .iter_mut()
+                .filter(|target| group_hosts.contains(&target.host))
+            {
+                target.binary_compilation_id = compilation_id.clone();
+            }
+            debug!(
+                "Building {} for {} hosts: {}",
+                binary_name,
+                group_hosts.len(),
+                group_hosts.join(", ")
+            );
 
             let compilation = BinaryCompilation {
                 compilation_id: compilation_id.clone(),
-                binary_name: format!("rustle-runner-{target_triple}"),
+                binary_name: binary_name.clone(),
                 target_triple: target_triple.clone(),
                 source_tasks: execution_plan
                     .tasks
@@ -321,7 +1260,7 @@ impl DeploymentManager {
                     .map(|t| t.name.clone())
                     .collect(),
                 embedded_data: EmbeddedExecutionData {
-                    execution_plan: serde_json::to_string(execution_plan).unwrap_or_default(),
+                    execution_plan: embedded_plan.clone(),
                     module_implementations: execution_plan
                         .modules
                         .iter()
@@ -348,6 +1287,19 @@ impl DeploymentManager {
                         facts_cache_ttl: std::time::Duration::from_secs(300),
                         retry_policy: None,
                         verbose: false,
+                        self_update: None,
+                        result_upload: None,
+                        fault_injection: None,
+                        signing_public_key: self
+                            .signer
+                            .as_ref()
+                            .map(|signer| signer.public_key().encoded()),
+                        agent: self.agent.clone(),
+                        async_dir: None,
+                        r#become: None,
+                        state_file: None,
+                        callbacks: Vec::new(),
+                        lookups: self.lookups.clone(),
                     },
                     facts_template: execution_plan.facts_template.global_facts.clone(),
                 },
@@ -359,10 +1311,7 @@ impl DeploymentManager {
                     custom_features: vec![],
                     target_cpu: None,
                 },
-                output_path: self
-                    .config
-                    .output_dir
-                    .join(format!("rustle-runner-{target_triple}")),
+                output_path: self.config.output_dir.join(&binary_name),
                 checksum: String::new(), // Will be calculated during compilation
                 size: 0,                 // Will be set during compilation
             };
@@ -370,10 +1319,46 @@ impl DeploymentManager {
             compilations.push(compilation);
         }
 
-        Ok(compilations)
+        Ok((compilations, deployment_targets))
     }
 }
 
+/// Why a host did not complete, for its span
+fn status_error(status: &DeploymentStatus) -> Option<&str> {
+    match status {
+        DeploymentStatus::Failed { error } => Some(error),
+        DeploymentStatus::Aborted { reason } => Some(reason),
+        _ => None,
+    }
+}
+
+/// Batch hosts as the plan's `serial` setting asks, resolving percentages
+/// against the number of targets
+fn deployment_strategy(plan: &ExecutionPlan, targets: usize) -> Result<DeploymentStrategy> {
+    let config = &plan.deployment_config;
+    let batch_size = match &config.serial {
+        None => return Ok(DeploymentStrategy::Parallel),
+        Some(SerialBatch::Hosts(hosts)) => *hosts,
+        Some(SerialBatch::Percentage(value)) => {
+            let percentage = value
+                .trim()
+                .trim_end_matches('%')
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|percentage| *percentage > 0.0 && *percentage <= 100.0)
+                .ok_or_else(|| {
+                    DeployError::Configuration(format!("Invalid serial percentage {value:?}"))
+                })?;
+            (targets as f64 * percentage / 100.0) as usize
+        }
+    };
+    Ok(DeploymentStrategy::Rolling {
+        batch_size: batch_size.max(1) as u32,
+        max_fail_percentage: config.max_fail_percentage,
+    })
+}
+
 // Supporting types for reports
 #[derive(Debug)]
 pub struct DeploymentReport {
@@ -381,9 +1366,95 @@ pub struct DeploymentReport {
     pub total_targets: usize,
     pub successful_deployments: usize,
     pub failed_deployments: usize,
+    /// Hosts left out because an earlier batch failed, or another host
+    /// failed a play with `any_errors_fatal` before they started
+    pub skipped_deployments: usize,
+    /// Hosts stopped part way because another host failed a play with
+    /// `any_errors_fatal`
+    pub aborted_deployments: usize,
     pub deployment_results: Vec<DeploymentResult>,
     pub started_at: chrono::DateTime<Utc>,
     pub completed_at: chrono::DateTime<Utc>,
+    /// Set when too many hosts failed and the deployment was rolled back
+    pub rollback: Option<RollbackReport>,
+}
+
+impl DeploymentReport {
+    /// Summarise per-host results, reported in plan order regardless of
+    /// completion order
+    fn new(
+        plan: &DeploymentPlan,
+        mut indexed_results: Vec<(usize, DeploymentResult)>,
+        started_at: chrono::DateTime<Utc>,
+    ) -> Self {
+        indexed_results.sort_by_key(|(index, _)| *index);
+        let deployment_results: Vec<DeploymentResult> = indexed_results
+            .into_iter()
+            .map(|(_, result)| result)
+            .collect();
+
+        let mut report = Self {
+            deployment_id: plan.metadata.deployment_id.clone(),
+            total_targets: plan.deployment_targets.len(),
+            successful_deployments: 0,
+            failed_deployments: 0,
+            skipped_deployments: 0,
+            aborted_deployments: 0,
+            deployment_results,
+            started_at,
+            completed_at: Utc::now(),
+            rollback: None,
+        };
+        report.tally();
+        report
+    }
+
+    /// Retries made across all hosts
+    pub fn total_retries(&self) -> u32 {
+        self.deployment_results
+            .iter()
+            .map(|result| result.retries.total())
+            .sum()
+    }
+
+    /// Mark hosts that were reverted without having failed themselves
+    fn apply_rollback(&mut self, rollback: RollbackReport) {
+        for result in &mut self.deployment_results {
+            let reverted = rollback
+                .hosts
+                .iter()
+                .any(|host| host.host == result.host && host.error.is_none());
+            if reverted && !matches!(result.status, DeploymentStatus::Failed { .. }) {
+                result.status = DeploymentStatus::RolledBack;
+            }
+        }
+        self.rollback = Some(rollback);
+        self.tally();
+    }
+
+    /// Rolled-back hosts count as failed
+    fn tally(&mut self) {
+        let count = |matches: fn(&DeploymentStatus) -> bool| {
+            self.deployment_results
+                .iter()
+                .filter(|result| matches(&result.status))
+                .count()
+        };
+        self.failed_deployments = count(|status| {
+            matches!(
+                status,
+                DeploymentStatus::Failed { .. } | DeploymentStatus::RolledBack
+            )
+        });
+        self.skipped_deployments =
+            count(|status| matches!(status, DeploymentStatus::Skipped { .. }));
+        self.aborted_deployments =
+            count(|status| matches!(status, DeploymentStatus::Aborted { .. }));
+        self.successful_deployments = self.deployment_results.len()
+            - self.failed_deployments
+            - self.skipped_deployments
+            - self.aborted_deployments;
+    }
 }
 
 #[derive(Debug)]
@@ -391,6 +1462,26 @@ pub struct DeploymentResult {
     pub host: String,
     pub status: DeploymentStatus,
     pub deployed_at: Option<chrono::DateTime<Utc>>,
+    pub duration: std::time::Duration,
+    /// Post-execution checks, for results of [`DeploymentManager::execute_deployments`]
+    pub verification: Option<ExecutionVerificationReport>,
+    /// Transient failures that were retried on the way
+    pub retries: RetryCounts,
+}
+
+impl DeploymentResult {
+    fn skipped(target: &DeploymentTarget, reason: &str) -> Self {
+        Self {
+            host: target.host.clone(),
+            status: DeploymentStatus::Skipped {
+                reason: reason.to_string(),
+            },
+            deployed_at: None,
+            duration: std::time::Duration::ZERO,
+            verification: None,
+            retries: RetryCounts::default(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,318 @@
+//! Checksum manifests and build provenance for compiled runners.
+//!
+//! Every build writes a [`DeploymentManifest`] to the output directory,
+//! recording what each binary is (its checksum, target and the hash of the
+//! plan embedded in it) and what produced it. Deploying binaries that were
+//! built earlier checks them against the manifest first, so a binary swapped
+//! out in the output directory is never pushed. The same facts can also be
+//! written as an in-toto statement with an SLSA provenance predicate for
+//! supply-chain tooling.
+
+use crate::compilation::linkage::Linkage;
+use crate::compilation::reproducible::ReproducibleBuild;
+use crate::compilation::smoke_test::SmokeTestStatus;
+use crate::deploy::{DeployError, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+pub const MANIFEST_FILE: &str = "rustle-manifest.json";
+pub const PROVENANCE_FILE: &str = "rustle-provenance.json";
+
+const MANIFEST_VERSION: u32 = 1;
+const BUILD_TYPE: &str = "https://github.com/iepathos/rustle-deploy/runner@v1";
+
+/// Versions of the toolchain that built a set of binaries
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompilerVersions {
+    pub rustc: Option<String>,
+    pub cargo: Option<String>,
+    /// Present when binaries were cross-compiled with cargo-zigbuild
+    #[serde(default)]
+    pub zig: Option<String>,
+}
+
+impl CompilerVersions {
+    /// Ask the installed toolchain; probed once per process
+    pub fn detect() -> &'static Self {
+        static VERSIONS: OnceLock<CompilerVersions> = OnceLock::new();
+        VERSIONS.get_or_init(|| Self {
+            rustc: command_version("rustc", &["--version"]),
+            cargo: command_version("cargo", &["--version"]),
+            zig: command_version("zig", &["version"]),
+        })
+    }
+}
+
+fn command_version(program: &str, args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .ok()?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !version.is_empty()).then_some(version)
+}
+
+/// One binary in the output directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactEntry {
+    /// Path relative to the output directory
+    pub name: String,
+    pub target_triple: String,
+    pub sha256: String,
+    pub size: u64,
+    /// SHA-256 of the execution plan embedded in the binary
+    pub plan_hash: String,
+    pub built_at: DateTime<Utc>,
+    /// How the binary links the C library, read from the binary itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub linkage: Option<Linkage>,
+    /// How the binary fared when smoke tested on the build host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smoke_test: Option<SmokeTestStatus>,
+}
+
+impl ArtifactEntry {
+    /// Describe the binary at `path`, which must be inside `output_dir`
+    pub fn from_file(
+        output_dir: &Path,
+        path: &Path,
+        target_triple: &str,
+        plan_hash: &str,
+    ) -> Result<Self> {
+        let binary = std::fs::read(path)?;
+        Ok(Self {
+            name: relative_name(output_dir, path),
+            target_triple: target_triple.to_string(),
+            sha256: format!("{:x}", Sha256::digest(&binary)),
+            size: binary.len() as u64,
+            plan_hash: plan_hash.to_string(),
+            built_at: Utc::now(),
+            linkage: Some(Linkage::of(target_triple, Some(&binary))),
+            smoke_test: None,
+        })
+    }
+}
+
+fn relative_name(output_dir: &Path, path: &Path) -> String {
+    path.strip_prefix(output_dir)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Hash of an execution plan as embedded in a runner
+pub fn plan_hash(execution_plan: &str) -> String {
+    format!("{:x}", Sha256::digest(execution_plan.as_bytes()))
+}
+
+/// What was built for a deployment, kept next to the binaries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentManifest {
+    pub manifest_version: u32,
+    pub deployment_id: String,
+    pub created_at: DateTime<Utc>,
+    /// Version of rustle-deploy that produced the manifest
+    pub tool_version: String,
+    pub compiler: CompilerVersions,
+    /// Settings of the build, when built reproducibly
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reproducible: Option<ReproducibleBuild>,
+    pub artifacts: Vec<ArtifactEntry>,
+}
+
+impl DeploymentManifest {
+    pub fn new(deployment_id: &str, compiler: CompilerVersions) -> Self {
+        Self {
+            manifest_version: MANIFEST_VERSION,
+            deployment_id: deployment_id.to_string(),
+            created_at: Utc::now(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            compiler,
+            reproducible: None,
+            artifacts: Vec::new(),
+        }
+    }
+
+    /// Add `artifact`, replacing an earlier entry for the same file
+    pub fn add_artifact(&mut self, artifact: ArtifactEntry) {
+        self.artifacts
+            .retain(|existing| existing.name != artifact.name);
+        self.artifacts.push(artifact);
+    }
+
+    pub fn load(output_dir: &Path) -> Result<Self> {
+        let path = output_dir.join(MANIFEST_FILE);
+        let content = std::fs::read(&path).map_err(|e| {
+            DeployError::Configuration(format!("Cannot read manifest {}: {e}", path.display()))
+        })?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    pub fn write(&self, output_dir: &Path) -> Result<PathBuf> {
+        std::fs::create_dir_all(output_dir)?;
+        let path = output_dir.join(MANIFEST_FILE);
+        std::fs::write(&path, serde_json::to_vec_pretty(self)?)?;
+        Ok(path)
+    }
+
+    pub fn artifact(&self, output_dir: &Path, path: &Path) -> Option<&ArtifactEntry> {
+        let name = relative_name(output_dir, path);
+        self.artifacts.iter().find(|artifact| artifact.name == name)
+    }
+
+    /// Check the binary at `path` against its manifest entry
+    pub fn verify_artifact(&self, output_dir: &Path, path: &Path) -> Result<&ArtifactEntry> {
+        let mismatch = |reason: String| DeployError::ManifestMismatch {
+            path: path.display().to_string(),
+            reason,
+        };
+        let entry = self
+            .artifact(output_dir, path)
+            .ok_or_else(|| mismatch("not listed in the manifest".to_string()))?;
+
+        let binary = std::fs::read(path).map_err(|e| mismatch(e.to_string()))?;
+        let actual = format!("{:x}", Sha256::digest(&binary));
+        if actual != entry.sha256 {
+            return Err(mismatch(format!(
+                "checksum is {actual}, manifest records {}",
+                entry.sha256
+            )));
+        }
+        Ok(entry)
+    }
+
+    /// Check every binary listed in the manifest
+    pub fn verify_all(&self, output_dir: &Path) -> Result<()> {
+        for artifact in &self.artifacts {
+            self.verify_artifact(output_dir, &output_dir.join(&artifact.name))?;
+        }
+        Ok(())
+    }
+
+    /// The manifest as an in-toto statement with an SLSA v1 provenance predicate
+    pub fn provenance(&self, builder_id: &str) -> serde_json::Value {
+        let subjects: Vec<_> = self
+            .artifacts
+            .iter()
+            .map(|artifact| {
+                serde_json::json!({
+                    "name": artifact.name,
+                    "digest": { "sha256": artifact.sha256 },
+                })
+            })
+            .collect();
+        let targets: Vec<_> = self
+            .artifacts
+            .iter()
+            .map(|artifact| {
+                serde_json::json!({
+                    "name": artifact.name,
+                    "target_triple": artifact.target_triple,
+                    "plan_hash": artifact.plan_hash,
+                })
+            })
+            .collect();
+        let finished_on = self
+            .artifacts
+            .iter()
+            .map(|artifact| artifact.built_at)
+            .max()
+            .unwrap_or(self.created_at);
+
+        serde_json::json!({
+            "_type": "https://in-toto.io/Statement/v1",
+            "subject": subjects,
+            "predicateType": "https://slsa.dev/provenance/v1",
+            "predicate": {
+                "buildDefinition": {
+                    "buildType": BUILD_TYPE,
+                    "externalParameters": { "artifacts": targets },
+                    "internalParameters": {
+                        "compiler": self.compiler,
+                        "reproducible": self.reproducible,
+                    },
+                    "resolvedDependencies": [],
+                },
+                "runDetails": {
+                    "builder": {
+                        "id": builder_id,
+                        "version": { "rustle-deploy": self.tool_version },
+                    },
+                    "metadata": {
+                        "invocationId": self.deployment_id,
+                        "startedOn": self.created_at,
+                        "finishedOn": finished_on,
+                    },
+                },
+            },
+        })
+    }
+
+    pub fn write_provenance(&self, output_dir: &Path, builder_id: &str) -> Result<PathBuf> {
+        std::fs::create_dir_all(output_dir)?;
+        let path = output_dir.join(PROVENANCE_FILE);
+        std::fs::write(
+            &path,
+            serde_json::to_vec_pretty(&self.provenance(builder_id))?,
+        )?;
+        Ok(path)
+    }
+}
+
+/// Builder id recorded in provenance: the controller's hostname
+pub fn local_builder_id() -> String {
+    let host = hostname::get()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "localhost".to_string());
+    format!("rustle-deploy://{host}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_detects_modified_binaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("rustle-runner-x86_64");
+        std::fs::write(&binary, b"runner").unwrap();
+
+        let mut manifest = DeploymentManifest::new("d1", CompilerVersions::default());
+        manifest.add_artifact(
+            ArtifactEntry::from_file(
+                dir.path(),
+                &binary,
+                "x86_64-unknown-linux-gnu",
+                &plan_hash("{}"),
+            )
+            .unwrap(),
+        );
+        manifest.write(dir.path()).unwrap();
+
+        let loaded = DeploymentManifest::load(dir.path()).unwrap();
+        assert_eq!(loaded.artifacts[0].name, "rustle-runner-x86_64");
+        assert!(loaded.verify_all(dir.path()).is_ok());
+
+        std::fs::write(&binary, b"tampered").unwrap();
+        assert!(matches!(
+            loaded.verify_all(dir.path()),
+            Err(DeployError::ManifestMismatch { .. })
+        ));
+        assert!(loaded
+            .verify_artifact(dir.path(), &dir.path().join("other"))
+            .is_err());
+
+        let provenance = loaded.provenance("rustle-deploy://ci");
+        assert_eq!(
+            provenance["subject"][0]["digest"]["sha256"],
+            loaded.artifacts[0].sha256
+        );
+        assert_eq!(
+            provenance["predicate"]["runDetails"]["metadata"]["invocationId"],
+            "d1"
+        );
+    }
+}
@@ -0,0 +1,382 @@
+//! Prometheus metrics of deployments.
+//!
+//! [`DeploymentMetrics`] counts what the controller did: hosts by outcome,
+//! compilations and how often the compilation cache spared one, bytes sent
+//! to each host, and how long the tasks the runners reported took. They are
+//! exposed in the Prometheus text format, either scraped from
+//! [`serve_metrics`] or, where nothing can reach the controller, written with
+//! [`DeploymentMetrics::write_textfile`] for node_exporter's textfile
+//! collector to pick up.
+
+use crate::deploy::manager::DeploymentReport;
+use crate::deploy::{CollectedResults, Result};
+use crate::runtime::ExecutionResult;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Upper bounds of the duration histograms, in seconds
+const DURATION_BUCKETS: [f64; 10] = [0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 900.0];
+
+/// Metrics of the deployments of one controller
+#[derive(Debug, Default)]
+pub struct DeploymentMetrics {
+    state: Mutex<MetricsState>,
+}
+
+#[derive(Debug, Default)]
+struct MetricsState {
+    /// Hosts by phase (`deploy` or `execute`) and outcome
+    hosts: BTreeMap<(String, String), u64>,
+    /// Durations of tasks reported by runners, by task name
+    task_durations: BTreeMap<String, Histogram>,
+    /// Durations of compilations, by target triple
+    compile_durations: BTreeMap<String, Histogram>,
+    cache_hits: u64,
+    cache_misses: u64,
+    /// Bytes sent to each host
+    transfer_bytes: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Observations at or below each of [`DURATION_BUCKETS`]
+    buckets: [u64; DURATION_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+impl DeploymentMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MetricsState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn count_hosts(&self, phase: &str, outcomes: &[(&str, usize)]) {
+        let mut state = self.state();
+        for (status, hosts) in outcomes {
+            *state
+                .hosts
+                .entry((phase.to_string(), status.to_string()))
+                .or_default() += *hosts as u64;
+        }
+    }
+
+    /// Count the hosts of `report` by outcome, for `phase`: `deploy` or
+    /// `execute`
+    pub fn record_report(&self, phase: &str, report: &DeploymentReport) {
+        self.count_hosts(
+            phase,
+            &[
+                ("succeeded", report.successful_deployments),
+                ("failed", report.failed_deployments),
+                ("skipped", report.skipped_deployments),
+                ("aborted", report.aborted_deployments),
+            ],
+        );
+    }
+
+    /// Count the hosts of results collected from object storage, and the
+    /// durations of the tasks they ran
+    pub fn record_collected(&self, collected: &CollectedResults) {
+        let failed = collected.failed_hosts().len();
+        self.count_hosts(
+            "execute",
+            &[
+                ("succeeded", collected.results.len() - failed),
+                ("failed", failed),
+                ("missing", collected.missing_hosts.len()),
+            ],
+        );
+        for result in collected.results.values() {
+            self.record_execution(result);
+        }
+    }
+
+    /// Observe the durations of the tasks and handlers a runner reported,
+    /// leaving out the skipped ones
+    pub fn record_execution(&self, result: &ExecutionResult) {
+        let mut state = self.state();
+        for task in result
+            .task_results
+            .values()
+            .chain(&result.handler_results)
+            .filter(|task| !task.skipped)
+        {
+            state
+                .task_durations
+                .entry(task.name.clone())
+                .or_default()
+                .observe(task.duration);
+        }
+    }
+
+    /// Observe a compilation for `target` that took `duration`
+    pub fn record_compilation(&self, target: &str, duration: Duration) {
+        let mut state = self.state();
+        state.cache_misses += 1;
+        state
+            .compile_durations
+            .entry(target.to_string())
+            .or_default()
+            .observe(duration);
+    }
+
+    /// A binary was found in the compilation cache instead of compiled
+    pub fn record_cache_hit(&self) {
+        self.state().cache_hits += 1;
+    }
+
+    /// `bytes` went over the wire to `host`
+    pub fn record_transfer(&self, host: &str, bytes: u64) {
+        *self
+            .state()
+            .transfer_bytes
+            .entry(host.to_string())
+            .or_default() += bytes;
+    }
+
+    /// The metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let state = self.state();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "rustle_hosts_total",
+            "counter",
+            "Hosts by phase and outcome",
+        );
+        for ((phase, status), hosts) in &state.hosts {
+            let _ = writeln!(
+                out,
+                "rustle_hosts_total{{phase=\"{}\",status=\"{}\"}} {hosts}",
+                escape(phase),
+                escape(status)
+            );
+        }
+
+        header(
+            &mut out,
+            "rustle_task_duration_seconds",
+            "histogram",
+            "Durations of the tasks runners reported",
+        );
+        for (task, histogram) in &state.task_durations {
+            write_histogram(
+                &mut out,
+                "rustle_task_duration_seconds",
+                "task",
+                task,
+                histogram,
+            );
+        }
+
+        header(
+            &mut out,
+            "rustle_compile_duration_seconds",
+            "histogram",
+            "Durations of binary compilations",
+        );
+        for (target, histogram) in &state.compile_durations {
+            write_histogram(
+                &mut out,
+                "rustle_compile_duration_seconds",
+                "target",
+                target,
+                histogram,
+            );
+        }
+
+        header(
+            &mut out,
+            "rustle_compile_cache_hits_total",
+            "counter",
+            "Binaries taken from the compilation cache",
+        );
+        let _ = writeln!(out, "rustle_compile_cache_hits_total {}", state.cache_hits);
+        header(
+            &mut out,
+            "rustle_compile_cache_misses_total",
+            "counter",
+            "Binaries that had to be compiled",
+        );
+        let _ = writeln!(
+            out,
+            "rustle_compile_cache_misses_total {}",
+            state.cache_misses
+        );
+        header(
+            &mut out,
+            "rustle_compile_cache_hit_ratio",
+            "gauge",
+            "Share of binaries taken from the compilation cache",
+        );
+        let lookups = state.cache_hits + state.cache_misses;
+        let ratio = if lookups == 0 {
+            0.0
+        } else {
+            state.cache_hits as f64 / lookups as f64
+        };
+        let _ = writeln!(out, "rustle_compile_cache_hit_ratio {ratio}");
+
+        header(
+            &mut out,
+            "rustle_transfer_bytes_total",
+            "counter",
+            "Bytes of binaries sent to each host, after compression",
+        );
+        for (host, bytes) in &state.transfer_bytes {
+            let _ = writeln!(
+                out,
+                "rustle_transfer_bytes_total{{host=\"{}\"}} {bytes}",
+                escape(host)
+            );
+        }
+        out
+    }
+
+    /// Write the metrics to `path` for node_exporter's textfile collector.
+    /// The file is replaced at once, so the collector never reads half of it.
+    pub fn write_textfile(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        std::fs::write(&partial, self.render())?;
+        std::fs::rename(&partial, path)?;
+        Ok(())
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn write_histogram(out: &mut String, name: &str, label: &str, value: &str, histogram: &Histogram) {
+    let value = escape(value);
+    for (bound, count) in DURATION_BUCKETS.iter().zip(histogram.buckets) {
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{label}=\"{value}\",le=\"{bound}\"}} {count}"
+        );
+    }
+    let _ = writeln!(
+        out,
+        "{name}_bucket{{{label}=\"{value}\",le=\"+Inf\"}} {}",
+        histogram.count
+    );
+    let _ = writeln!(out, "{name}_sum{{{label}=\"{value}\"}} {}", histogram.sum);
+    let _ = writeln!(
+        out,
+        "{name}_count{{{label}=\"{value}\"}} {}",
+        histogram.count
+    );
+}
+
+/// `value` escaped for a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Answer `GET /metrics` on `listener` with `metrics` until the task is
+/// dropped
+pub async fn serve_metrics(listener: TcpListener, metrics: Arc<DeploymentMetrics>) -> Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let metrics = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            if let Err(e) = answer_scrape(stream, &metrics).await {
+                tracing::debug!("Metrics request from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn answer_scrape(stream: TcpStream, metrics: &DeploymentMetrics) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    // The headers are of no interest
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics.render(),
+        ),
+        _ => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\ncontent-type: {content_type}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let stream = reader.get_mut();
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let metrics = DeploymentMetrics::new();
+        metrics.record_compilation("x86_64-unknown-linux-gnu", Duration::from_secs(3));
+        metrics.record_compilation("x86_64-unknown-linux-gnu", Duration::from_millis(400));
+        metrics.record_cache_hit();
+
+        let text = metrics.render();
+        let target = "target=\"x86_64-unknown-linux-gnu\"";
+        for (bound, count) in [("0.1", 0), ("0.5", 1), ("1", 1), ("5", 2), ("+Inf", 2)] {
+            let line = format!(
+                "rustle_compile_duration_seconds_bucket{{{target},le=\"{bound}\"}} {count}"
+            );
+            assert!(text.contains(&line), "{line} missing from\n{text}");
+        }
+        assert!(text.contains(&format!(
+            "rustle_compile_duration_seconds_sum{{{target}}} 3.4"
+        )));
+        assert!(text.contains("rustle_compile_cache_hits_total 1\n"));
+        assert!(text.contains("rustle_compile_cache_misses_total 2\n"));
+        assert!(text.contains("rustle_compile_cache_hit_ratio 0.3333"));
+    }
+
+    #[test]
+    fn test_label_values_are_escaped() {
+        assert_eq!(escape("say \"hi\"\\\n"), "say \\\"hi\\\"\\\\\\n");
+    }
+}
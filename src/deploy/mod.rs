@@ -1,11 +1,60 @@
+pub mod artifact_index;
+pub mod audit;
+pub mod bandwidth;
 pub mod cache;
 pub mod compiler;
+pub mod connection;
+pub mod delegation;
 pub mod deployer;
 pub mod error;
+pub mod events;
+pub mod history;
 pub mod manager;
+pub mod manifest;
+pub mod metrics;
+pub mod report;
+pub mod result_collector;
+pub mod retry;
+pub mod rollback;
+pub mod schedule;
+pub mod ssh;
+pub mod ssh_config;
+pub mod strategy;
+pub mod telemetry;
+pub mod transfer;
+pub mod verification;
+pub mod wasi;
+pub mod winrm;
 
+pub use artifact_index::{ArtifactIndex, IndexedArtifact};
+pub use audit::{AuditFormat, AuditLog, AuditRecord};
+pub use bandwidth::{Bandwidth, BandwidthLimits, RateLimiter};
 pub use cache::CompilationCache;
 pub use compiler::BinaryCompiler;
+pub use connection::{
+    ChrootConnection, ConnectionPlugin, ContainerConnection, ContainerRuntime, LocalConnection,
+};
+pub use delegation::Delegation;
 pub use deployer::BinaryDeployer;
 pub use error::*;
+pub use events::{EventSink, HostEvent};
+pub use history::ExecutionHistory;
 pub use manager::DeploymentManager;
+pub use manifest::{ArtifactEntry, CompilerVersions, DeploymentManifest};
+pub use metrics::{serve_metrics, DeploymentMetrics};
+pub use report::{HostOutcome, HostRun, ReportFormat, ReportTarget, RunReport};
+pub use result_collector::{CollectedResults, ResultCollector};
+pub use retry::{DeployPhase, RetryConfig, RetryCounts, RetryPolicies, RetryPolicy};
+pub use rollback::{HostRollback, RollbackPolicy, RollbackReport, RollbackStore};
+pub use schedule::ParsedWindow;
+pub use ssh::{HostKeyChecking, SshAuth, SshConnection, SshConnectionConfig};
+pub use ssh_config::{resolve_connection, ResolvedConnection, SshConfig, SshHostConfig};
+pub use strategy::{Coordinator, TaskBarrier};
+pub use telemetry::{OtlpExporter, Tracer};
+pub use transfer::{TransferCache, TransferOptions, TransferOutcome, TransferRecord};
+pub use verification::{
+    Discrepancy, ExecutionVerificationConfig, ExecutionVerificationReport, ExecutionVerifier,
+    HealthCheck, ResultSource,
+};
+pub use wasi::{WasiDir, WasmtimeExecutor};
+pub use winrm::{WinRmAuth, WinRmConfig, WinRmConnection, WinRmConnectionManager};
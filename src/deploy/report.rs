@@ -0,0 +1,360 @@
+//! Pass/fail artifacts of a deployment run for CI pipelines.
+//!
+//! A [`RunReport`] holds the outcome of every host of a run and what its
+//! runner reported for each task. It is written as JUnit XML, with one
+//! testcase per task and host, or as SARIF 2.1.0, whose results are the tasks
+//! that failed or changed: the findings of a compliance scan, which runs its
+//! plays in check mode so that any change is a deviation.
+
+use crate::deploy::manager::DeploymentReport;
+use crate::deploy::{CollectedResults, Result};
+use crate::runtime::callbacks::escape;
+use crate::runtime::{ExecutionResult, TaskResult};
+use crate::types::DeploymentStatus;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Name of the testcase or rule standing for a host as a whole
+const DEPLOYMENT_CASE: &str = "deployment";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    JUnit,
+    Sarif,
+}
+
+/// A report to write, given on the command line as `junit=path` or
+/// `sarif=path`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportTarget {
+    pub format: ReportFormat,
+    pub path: PathBuf,
+}
+
+impl std::str::FromStr for ReportTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (format, path) = s
+            .split_once('=')
+            .filter(|(_, path)| !path.is_empty())
+            .ok_or_else(|| format!("Expected FORMAT=PATH, got '{s}'"))?;
+        let format = match format.to_lowercase().as_str() {
+            "junit" => ReportFormat::JUnit,
+            "sarif" => ReportFormat::Sarif,
+            other => return Err(format!("Unknown report format '{other}' (junit, sarif)")),
+        };
+        Ok(Self {
+            format,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// How a host of the run ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostOutcome {
+    Completed,
+    Failed {
+        reason: String,
+    },
+    /// Not run, or stopped part way because of another host
+    Skipped {
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct HostRun {
+    pub host: String,
+    pub outcome: HostOutcome,
+    /// What the runner of the host reported, if it reported anything
+    pub result: Option<ExecutionResult>,
+}
+
+impl HostRun {
+    /// Results of the tasks, then of the handlers, in the order they ran
+    fn tasks(&self) -> Vec<&TaskResult> {
+        let Some(result) = &self.result else {
+            return Vec::new();
+        };
+        let mut tasks: Vec<_> = result.task_results.values().collect();
+        tasks.sort_by(|a, b| (a.start_time, &a.task_id).cmp(&(b.start_time, &b.task_id)));
+        tasks.extend(&result.handler_results);
+        tasks
+    }
+
+    /// How the host ended, unless a failed task already tells: a host whose
+    /// task failed needs no failure of its own
+    fn outcome_to_report(&self) -> &HostOutcome {
+        let task_failed = self.tasks().iter().any(|task| task.failed);
+        match &self.outcome {
+            HostOutcome::Failed { .. } if task_failed => &HostOutcome::Completed,
+            outcome => outcome,
+        }
+    }
+}
+
+/// Outcome of every host of a deployment run
+#[derive(Debug, Clone)]
+pub struct RunReport {
+    pub deployment_id: String,
+    pub hosts: Vec<HostRun>,
+}
+
+impl From<&DeploymentReport> for RunReport {
+    fn from(report: &DeploymentReport) -> Self {
+        let hosts = report
+            .deployment_results
+            .iter()
+            .map(|deployment| {
+                let outcome = match &deployment.status {
+                    DeploymentStatus::Failed { error } => HostOutcome::Failed {
+                        reason: error.clone(),
+                    },
+                    DeploymentStatus::RolledBack => HostOutcome::Failed {
+                        reason: "rolled back after the deployment failed".to_string(),
+                    },
+                    DeploymentStatus::Skipped { reason } | DeploymentStatus::Aborted { reason } => {
+                        HostOutcome::Skipped {
+                            reason: reason.clone(),
+                        }
+                    }
+                    _ => HostOutcome::Completed,
+                };
+                HostRun {
+                    host: deployment.host.clone(),
+                    outcome,
+                    result: deployment
+                        .verification
+                        .as_ref()
+                        .and_then(|verification| verification.result.clone()),
+                }
+            })
+            .collect();
+        Self {
+            deployment_id: report.deployment_id.clone(),
+            hosts,
+        }
+    }
+}
+
+impl From<&CollectedResults> for RunReport {
+    fn from(collected: &CollectedResults) -> Self {
+        let mut hosts: Vec<_> = collected
+            .results
+            .iter()
+            .map(|(host, result)| {
+                let outcome = if result.failed || !result.success {
+                    let reason = if result.errors.is_empty() {
+                        "execution failed".to_string()
+                    } else {
+                        result.errors.join("; ")
+                    };
+                    HostOutcome::Failed { reason }
+                } else {
+                    HostOutcome::Completed
+                };
+                HostRun {
+                    host: host.clone(),
+                    outcome,
+                    result: Some(result.clone()),
+                }
+            })
+            .collect();
+        hosts.extend(collected.missing_hosts.iter().map(|host| HostRun {
+            host: host.clone(),
+            outcome: HostOutcome::Failed {
+                reason: "no result was uploaded".to_string(),
+            },
+            result: None,
+        }));
+        Self {
+            deployment_id: collected.deployment_id.clone(),
+            hosts,
+        }
+    }
+}
+
+impl RunReport {
+    /// Write the report in the format and at the path of `target`
+    pub fn write(&self, target: &ReportTarget) -> Result<()> {
+        let content = match target.format {
+            ReportFormat::JUnit => self.junit(),
+            ReportFormat::Sarif => serde_json::to_string_pretty(&self.sarif())?,
+        };
+        if let Some(parent) = target.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&target.path, content)?;
+        Ok(())
+    }
+
+    /// The run as JUnit XML: a testsuite per host, a testcase per task, and
+    /// a `deployment` testcase for hosts that did not complete for another
+    /// reason
+    pub fn junit(&self) -> String {
+        let mut suites = String::new();
+        let (mut total, mut total_failures, mut total_skipped) = (0, 0, 0);
+        let mut total_time = Duration::ZERO;
+        for run in &self.hosts {
+            let tasks = run.tasks();
+            let mut cases = String::new();
+            let (mut failures, mut skipped) = (0, 0);
+            for task in &tasks {
+                cases.push_str(&format!(
+                    "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                    escape(&run.host),
+                    escape(&task.name),
+                    task.duration.as_secs_f64()
+                ));
+                if task.failed {
+                    failures += 1;
+                    let message = task.error.as_deref().unwrap_or("failed");
+                    cases.push_str(&format!(
+                        ">\n      <failure message=\"{}\"/>\n    </testcase>\n",
+                        escape(message)
+                    ));
+                } else if task.skipped {
+                    skipped += 1;
+                    cases.push_str(">\n      <skipped/>\n    </testcase>\n");
+                } else {
+                    cases.push_str("/>\n");
+                }
+            }
+            let mut tests = tasks.len();
+            match run.outcome_to_report() {
+                HostOutcome::Completed => {}
+                HostOutcome::Failed { reason } => {
+                    tests += 1;
+                    failures += 1;
+                    cases.push_str(&format!(
+                        "    <testcase classname=\"{}\" name=\"{DEPLOYMENT_CASE}\">\n      <failure message=\"{}\"/>\n    </testcase>\n",
+                        escape(&run.host),
+                        escape(reason)
+                    ));
+                }
+                HostOutcome::Skipped { reason } => {
+                    tests += 1;
+                    skipped += 1;
+                    cases.push_str(&format!(
+                        "    <testcase classname=\"{}\" name=\"{DEPLOYMENT_CASE}\">\n      <skipped message=\"{}\"/>\n    </testcase>\n",
+                        escape(&run.host),
+                        escape(reason)
+                    ));
+                }
+            }
+
+            let time = run
+                .result
+                .as_ref()
+                .map_or(Duration::ZERO, |result| result.duration);
+            suites.push_str(&format!(
+                "  <testsuite name=\"{}\" hostname=\"{}\" tests=\"{tests}\" failures=\"{failures}\" skipped=\"{skipped}\" time=\"{:.3}\">\n",
+                escape(&run.host),
+                escape(&run.host),
+                time.as_secs_f64()
+            ));
+            suites.push_str(&cases);
+            suites.push_str("  </testsuite>\n");
+            total += tests;
+            total_failures += failures;
+            total_skipped += skipped;
+            total_time += time;
+        }
+
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites name=\"{}\" tests=\"{total}\" failures=\"{total_failures}\" skipped=\"{total_skipped}\" time=\"{:.3}\">\n{suites}</testsuites>\n",
+            escape(&self.deployment_id),
+            total_time.as_secs_f64()
+        )
+    }
+
+    /// The run as a SARIF 2.1.0 log: a rule per task, and a result per task
+    /// that failed (an error) or changed a host (a warning), and per host
+    /// that did not complete for another reason
+    pub fn sarif(&self) -> serde_json::Value {
+        let mut rules: Vec<serde_json::Value> = Vec::new();
+        let mut results = Vec::new();
+        let mut rule_index = |id: &str, name: &str| {
+            if let Some(index) = rules.iter().position(|rule| rule["id"] == id) {
+                return index;
+            }
+            rules.push(serde_json::json!({
+                "id": id,
+                "name": name,
+                "shortDescription": { "text": name },
+            }));
+            rules.len() - 1
+        };
+        let location = |host: &str, task: &str| {
+            serde_json::json!([{
+                "logicalLocations": [{
+                    "name": task,
+                    "fullyQualifiedName": format!("{host}/{task}"),
+                    "kind": "resource",
+                }]
+            }])
+        };
+
+        for run in &self.hosts {
+            for task in run.tasks() {
+                let (level, text) = if task.failed {
+                    let error = task.error.as_deref().unwrap_or("failed");
+                    (
+                        "error",
+                        format!("{} failed on {}: {error}", task.name, run.host),
+                    )
+                } else if task.changed {
+                    ("warning", format!("{} changed {}", task.name, run.host))
+                } else {
+                    continue;
+                };
+                results.push(serde_json::json!({
+                    "ruleId": task.task_id,
+                    "ruleIndex": rule_index(&task.task_id, &task.name),
+                    "level": level,
+                    "message": { "text": text },
+                    "locations": location(&run.host, &task.task_id),
+                }));
+            }
+            let (level, text) = match run.outcome_to_report() {
+                HostOutcome::Completed => continue,
+                HostOutcome::Failed { reason } => {
+                    ("error", format!("{} failed: {reason}", run.host))
+                }
+                HostOutcome::Skipped { reason } => {
+                    ("note", format!("{} was skipped: {reason}", run.host))
+                }
+            };
+            results.push(serde_json::json!({
+                "ruleId": DEPLOYMENT_CASE,
+                "ruleIndex": rule_index(DEPLOYMENT_CASE, DEPLOYMENT_CASE),
+                "level": level,
+                "message": { "text": text },
+                "locations": location(&run.host, DEPLOYMENT_CASE),
+            }));
+        }
+
+        let successful = self
+            .hosts
+            .iter()
+            .all(|run| run.outcome == HostOutcome::Completed);
+        serde_json::json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "rustle-deploy",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    }
+                },
+                "automationDetails": { "id": self.deployment_id },
+                "invocations": [{ "executionSuccessful": successful }],
+                "results": results,
+            }]
+        })
+    }
+}
@@ -0,0 +1,110 @@
+use crate::deploy::Result;
+use crate::runtime::{
+    result_object_key, EncryptedResultBundle, ExecutionResult, ObjectStoreClient, ObjectStoreConfig,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Deployment report assembled from uploaded result bundles
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CollectedResults {
+    pub deployment_id: String,
+    pub results: BTreeMap<String, ExecutionResult>,
+    /// Hosts whose bundle had not appeared when collection stopped
+    pub missing_hosts: Vec<String>,
+}
+
+impl CollectedResults {
+    pub fn is_complete(&self) -> bool {
+        self.missing_hosts.is_empty()
+    }
+
+    pub fn failed_hosts(&self) -> Vec<&str> {
+        self.results
+            .iter()
+            .filter(|(_, result)| result.failed || !result.success)
+            .map(|(host, _)| host.as_str())
+            .collect()
+    }
+}
+
+/// Controller-side poller for encrypted result bundles in object storage
+pub struct ResultCollector {
+    store: ObjectStoreClient,
+    secret_key: String,
+    poll_interval: Duration,
+}
+
+impl ResultCollector {
+    /// `secret_key` is the base64-encoded controller key the runners sealed to
+    pub fn new(store: ObjectStoreConfig, secret_key: String) -> Self {
+        Self {
+            store: ObjectStoreClient::new(store),
+            secret_key,
+            poll_interval: Duration::from_secs(10),
+        }
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Fetch and decrypt a single host's result, if it has been uploaded
+    pub async fn fetch(&self, deployment_id: &str, host: &str) -> Result<Option<ExecutionResult>> {
+        let key = result_object_key(deployment_id, host);
+        let Some(body) = self.store.get(&key).await? else {
+            return Ok(None);
+        };
+
+        let bundle: EncryptedResultBundle = serde_json::from_slice(&body)?;
+        if bundle.deployment_id != deployment_id || bundle.host != host {
+            warn!(
+                "Ignoring result bundle at {} addressed to {}/{}",
+                key, bundle.deployment_id, bundle.host
+            );
+            return Ok(None);
+        }
+
+        Ok(Some(bundle.open(&self.secret_key)?))
+    }
+
+    /// Poll until every host has uploaded its result or `timeout` elapses
+    pub async fn collect(
+        &self,
+        deployment_id: &str,
+        hosts: &[String],
+        timeout: Duration,
+    ) -> Result<CollectedResults> {
+        let deadline = Instant::now() + timeout;
+        let mut pending: BTreeSet<&str> = hosts.iter().map(String::as_str).collect();
+        let mut collected = CollectedResults {
+            deployment_id: deployment_id.to_string(),
+            ..Default::default()
+        };
+
+        loop {
+            for host in pending.clone() {
+                match self.fetch(deployment_id, host).await {
+                    Ok(Some(result)) => {
+                        info!("Collected result for {}", host);
+                        collected.results.insert(host.to_string(), result);
+                        pending.remove(host);
+                    }
+                    Ok(None) => debug!("No result uploaded yet for {}", host),
+                    Err(e) => warn!("Failed to collect result for {}: {}", host, e),
+                }
+            }
+
+            if pending.is_empty() || Instant::now() + self.poll_interval > deadline {
+                break;
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+
+        collected.missing_hosts = pending.into_iter().map(str::to_string).collect();
+        Ok(collected)
+    }
+}
@@ -0,0 +1,310 @@
+//! Retries of transient failures while deploying to a host.
+//!
+//! Each phase of a deployment (opening a connection, transferring the
+//! binary, executing it) has its own [`RetryPolicy`]. Executing the runner
+//! again repeats tasks that already ran, so execution is only retried when
+//! configured to, and a retried runner resumes from its saved progress
+//! instead of starting over. Only errors that
+//! [`DeployError::is_transient`](crate::deploy::DeployError::is_transient)
+//! classifies as transient are retried; the delay between attempts grows
+//! exponentially with random jitter so hosts that failed together do not
+//! retry in lockstep.
+
+use crate::deploy::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeployPhase {
+    Connect,
+    Transfer,
+    Execute,
+}
+
+impl std::fmt::Display for DeployPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DeployPhase::Connect => "connect",
+            DeployPhase::Transfer => "transfer",
+            DeployPhase::Execute => "execute",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first; 1 disables retries
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    /// Fraction of each delay that is randomised, between 0 and 1
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Delay before retry number `retry` (starting at 1), without jitter
+    pub fn base_delay(&self, retry: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .max(1.0)
+            .powi(retry.saturating_sub(1) as i32);
+        self.initial_delay.mul_f64(factor).min(self.max_delay)
+    }
+
+    /// Delay before retry number `retry`, with up to `jitter` of it randomised
+    pub fn delay(&self, retry: u32) -> Duration {
+        let base = self.base_delay(retry);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        base.mul_f64(1.0 - jitter + jitter * 2.0 * random_unit())
+    }
+}
+
+/// Retry policies of each deployment phase
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryPolicies {
+    pub connect: RetryPolicy,
+    pub transfer: RetryPolicy,
+    /// Not retried by default: tasks that are not idempotent may have run
+    pub execute: RetryPolicy,
+}
+
+impl Default for RetryPolicies {
+    fn default() -> Self {
+        Self {
+            connect: RetryPolicy::default(),
+            transfer: RetryPolicy::default(),
+            execute: RetryPolicy::none(),
+        }
+    }
+}
+
+impl RetryPolicies {
+    pub fn none() -> Self {
+        Self {
+            connect: RetryPolicy::none(),
+            transfer: RetryPolicy::none(),
+            execute: RetryPolicy::none(),
+        }
+    }
+
+    pub fn phase(&self, phase: DeployPhase) -> &RetryPolicy {
+        match phase {
+            DeployPhase::Connect => &self.connect,
+            DeployPhase::Transfer => &self.transfer,
+            DeployPhase::Execute => &self.execute,
+        }
+    }
+}
+
+/// Default retry policies, with overrides for individual hosts
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RetryConfig {
+    pub default: RetryPolicies,
+    #[serde(default)]
+    pub hosts: HashMap<String, RetryPolicies>,
+}
+
+impl RetryConfig {
+    pub fn none() -> Self {
+        Self {
+            default: RetryPolicies::none(),
+            hosts: HashMap::new(),
+        }
+    }
+
+    pub fn with_host(mut self, host: &str, policies: RetryPolicies) -> Self {
+        self.hosts.insert(host.to_string(), policies);
+        self
+    }
+
+    pub fn policy(&self, host: &str, phase: DeployPhase) -> &RetryPolicy {
+        self.hosts.get(host).unwrap_or(&self.default).phase(phase)
+    }
+}
+
+/// How often each phase of a host's deployment was retried
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryCounts {
+    pub connect: u32,
+    pub transfer: u32,
+    pub execute: u32,
+}
+
+impl RetryCounts {
+    pub fn total(&self) -> u32 {
+        self.connect + self.transfer + self.execute
+    }
+
+    pub fn record(&mut self, phase: DeployPhase) {
+        match phase {
+            DeployPhase::Connect => self.connect += 1,
+            DeployPhase::Transfer => self.transfer += 1,
+            DeployPhase::Execute => self.execute += 1,
+        }
+    }
+}
+
+/// Run `operation` until it succeeds, fails with a permanent error or runs
+/// out of attempts, calling `on_retry` before each retry
+pub async fn retry<T, F, Fut>(
+    policy: &RetryPolicy,
+    phase: DeployPhase,
+    host: &str,
+    mut on_retry: impl FnMut(),
+    mut operation: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(e) if attempt < policy.max_attempts && e.is_transient() => {
+                let delay = policy.delay(attempt);
+                warn!(
+                    "{} to {} failed ({}), retrying in {:?} (attempt {}/{})",
+                    phase,
+                    host,
+                    e,
+                    delay,
+                    attempt + 1,
+                    policy.max_attempts
+                );
+                on_retry();
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            outcome => return outcome,
+        }
+    }
+}
+
+/// A random number in `[0, 1)`, from the standard library's per-process
+/// hash seed; plenty for spreading retries apart
+fn random_unit() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64,
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deploy::DeployError;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: 0.5,
+        };
+        assert_eq!(policy.base_delay(1), Duration::from_millis(100));
+        assert_eq!(policy.base_delay(3), Duration::from_millis(400));
+        assert_eq!(policy.base_delay(8), Duration::from_secs(1));
+        for _ in 0..20 {
+            let delay = policy.delay(2);
+            assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(300));
+        }
+    }
+
+    #[test]
+    fn test_error_classification() {
+        assert!(DeployError::Network("down".to_string()).is_transient());
+        assert!(DeployError::DeploymentFailed {
+            host: "web1".to_string(),
+            reason: "SFTP write failed: Connection reset by peer".to_string(),
+        }
+        .is_transient());
+        assert!(!DeployError::DeploymentFailed {
+            host: "web1".to_string(),
+            reason: "injected crash at web1".to_string(),
+        }
+        .is_transient());
+        assert!(!DeployError::Configuration("bad".to_string()).is_transient());
+        assert!(!DeployError::VerificationFailed {
+            host: "web1".to_string(),
+            expected: "abc".to_string(),
+            actual: "def".to_string(),
+        }
+        .is_transient());
+    }
+
+    #[test]
+    fn test_execution_is_not_retried_by_default() {
+        let policies = RetryPolicies::default();
+        assert_eq!(policies.execute.max_attempts, 1);
+        assert!(policies.connect.max_attempts > 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_stops_on_permanent_errors() {
+        let policy = RetryPolicy {
+            initial_delay: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let attempts = AtomicU32::new(0);
+        let mut retries = 0;
+        let result = retry(
+            &policy,
+            DeployPhase::Connect,
+            "web1",
+            || retries += 1,
+            || async {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(DeployError::Network("blip".to_string())),
+                    _ => Ok("connected"),
+                }
+            },
+        )
+        .await;
+        assert_eq!(result.unwrap(), "connected");
+        assert_eq!(retries, 1);
+
+        let result: Result<()> = retry(
+            &policy,
+            DeployPhase::Connect,
+            "web1",
+            || retries += 1,
+            || async { Err(DeployError::Configuration("bad".to_string())) },
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(retries, 1);
+    }
+}
@@ -0,0 +1,218 @@
+//! Rollback of failed deployments.
+//!
+//! Before a binary is replaced, the one already on the host is copied next
+//! to it with [`PREVIOUS_SUFFIX`]. Files backed up by modules run with
+//! `backup: yes` are collected from the execution results. Both are recorded
+//! per deployment under the cache directory, so that when too many hosts
+//! fail the manager can put the previous binary back, re-run it and restore
+//! the backed-up files.
+
+use crate::deploy::transfer::write_atomic;
+use crate::deploy::Result;
+use crate::runtime::ExecutionResult;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tracing::warn;
+
+/// Suffix of the copy of the previously deployed binary kept on each host
+pub const PREVIOUS_SUFFIX: &str = ".rustle-previous";
+
+/// When and how failed deployments are rolled back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollbackPolicy {
+    pub enabled: bool,
+    /// Percentage of failed hosts above which every host is rolled back;
+    /// 0 rolls back on any failure
+    pub failure_threshold_percent: f64,
+    /// Re-run the restored binary after a failed execution
+    pub rerun_previous: bool,
+}
+
+impl Default for RollbackPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            failure_threshold_percent: 0.0,
+            rerun_previous: true,
+        }
+    }
+}
+
+impl RollbackPolicy {
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+
+    pub fn should_roll_back(&self, failed: usize, total: usize) -> bool {
+        self.enabled
+            && failed > 0
+            && failed as f64 * 100.0 / total.max(1) as f64 > self.failure_threshold_percent
+    }
+}
+
+/// A file a module overwrote after copying it to `backup`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileBackup {
+    pub path: String,
+    pub backup: String,
+}
+
+/// What is needed to return one host to its state before a deployment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostSnapshot {
+    pub host: String,
+    pub target_path: String,
+    /// Copy of the binary that was deployed before, if there was one
+    pub previous_binary: Option<String>,
+    /// Module backups, in the order they were taken
+    #[serde(default)]
+    pub backups: Vec<FileBackup>,
+}
+
+impl HostSnapshot {
+    pub fn previous_path(target_path: &str) -> String {
+        format!("{target_path}{PREVIOUS_SUFFIX}")
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RollbackState {
+    pub deployment_id: String,
+    pub hosts: BTreeMap<String, HostSnapshot>,
+}
+
+/// Rollback state of each deployment, one JSON file per deployment id
+#[derive(Debug, Clone)]
+pub struct RollbackStore {
+    dir: PathBuf,
+}
+
+impl RollbackStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub async fn load(&self, deployment_id: &str) -> RollbackState {
+        let empty = RollbackState {
+            deployment_id: deployment_id.to_string(),
+            ..Default::default()
+        };
+        let Ok(content) = tokio::fs::read(self.state_path(deployment_id)).await else {
+            return empty;
+        };
+        serde_json::from_slice(&content).unwrap_or_else(|e| {
+            warn!(
+                "Ignoring unreadable rollback state for {}: {}",
+                deployment_id, e
+            );
+            empty
+        })
+    }
+
+    pub async fn save(&self, state: &RollbackState) -> Result<()> {
+        let path = self.state_path(&state.deployment_id);
+        write_atomic(&path, &serde_json::to_vec_pretty(state)?).await
+    }
+
+    fn state_path(&self, deployment_id: &str) -> PathBuf {
+        self.dir.join(format!("{deployment_id}.json"))
+    }
+}
+
+/// Backups reported by tasks of a finished run, oldest first
+pub fn backups_from_result(result: &ExecutionResult) -> Vec<FileBackup> {
+    let mut tasks: Vec<_> = result.task_results.values().collect();
+    tasks.sort_by_key(|task| task.start_time);
+
+    tasks
+        .into_iter()
+        .filter_map(|task| {
+            let backup = task.output.get("backup_file")?.as_str()?;
+            let path = task
+                .output
+                .get("dest")
+                .or_else(|| task.output.get("path"))?
+                .as_str()?;
+            Some(FileBackup {
+                path: path.to_string(),
+                backup: backup.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// What was rolled back on one host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostRollback {
+    pub host: String,
+    pub restored_binary: bool,
+    pub restored_files: usize,
+    /// Whether the restored binary ran successfully, if it was re-run
+    pub rerun_success: Option<bool>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollbackReport {
+    /// Why the rollback was triggered
+    pub reason: String,
+    pub hosts: Vec<HostRollback>,
+}
+
+impl RollbackReport {
+    pub fn is_complete(&self) -> bool {
+        self.hosts
+            .iter()
+            .all(|host| host.error.is_none() && host.rerun_success != Some(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_threshold() {
+        let policy = RollbackPolicy::default();
+        assert!(!policy.should_roll_back(0, 4));
+        assert!(policy.should_roll_back(1, 4));
+
+        let lenient = RollbackPolicy {
+            failure_threshold_percent: 25.0,
+            ..Default::default()
+        };
+        assert!(!lenient.should_roll_back(1, 4));
+        assert!(lenient.should_roll_back(2, 4));
+
+        assert!(!RollbackPolicy::disabled().should_roll_back(4, 4));
+    }
+
+    #[tokio::test]
+    async fn test_store_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RollbackStore::new(dir.path().to_path_buf());
+        assert!(store.load("d1").await.hosts.is_empty());
+
+        let mut state = store.load("d1").await;
+        state.hosts.insert(
+            "web1".to_string(),
+            HostSnapshot {
+                host: "web1".to_string(),
+                target_path: "/opt/runner".to_string(),
+                previous_binary: Some(HostSnapshot::previous_path("/opt/runner")),
+                backups: Vec::new(),
+            },
+        );
+        store.save(&state).await.unwrap();
+
+        let loaded = store.load("d1").await;
+        assert_eq!(
+            loaded.hosts["web1"].previous_binary.as_deref(),
+            Some("/opt/runner.rustle-previous")
+        );
+    }
+}
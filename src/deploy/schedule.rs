@@ -0,0 +1,352 @@
+//! Maintenance windows and host hooks.
+//!
+//! The deployment section of an execution plan can restrict when hosts are
+//! touched to [`MaintenanceWindow`]s, and wrap each host's execution in
+//! [`HostHook`]s, for example to drain it from a load balancer first and put
+//! it back once the run succeeded. Both apply to inventory groups; the groups
+//! of every target are resolved when the deployment plan is created and kept
+//! in its [`HostSchedule`].
+
+use crate::deploy::connection::{ConnectionPlugin, LocalConnection};
+use crate::deploy::ssh::shell_quote;
+use crate::deploy::{BinaryDeployer, DeployError, Result};
+use crate::execution::{ExecutionPlan, HookLocation, HostHook, MaintenanceWindow};
+use crate::types::{DeploymentTarget, HostSchedule};
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tracing::info;
+
+/// Group every host belongs to
+const ALL_GROUP: &str = "all";
+
+/// A [`MaintenanceWindow`] with its times and days parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    /// Empty for every day
+    pub days: Vec<Weekday>,
+}
+
+impl ParsedWindow {
+    pub fn parse(window: &MaintenanceWindow) -> Result<Self> {
+        let time = |value: &str| {
+            NaiveTime::parse_from_str(value, "%H:%M").map_err(|e| {
+                DeployError::Configuration(format!(
+                    "Invalid maintenance window time {value:?}: {e}"
+                ))
+            })
+        };
+        let (start, end) = (time(&window.start)?, time(&window.end)?);
+        if start == end {
+            return Err(DeployError::Configuration(format!(
+                "Maintenance window {}-{} is empty",
+                window.start, window.end
+            )));
+        }
+        let days = window
+            .days
+            .iter()
+            .map(|day| {
+                day.parse::<Weekday>().map_err(|_| {
+                    DeployError::Configuration(format!("Invalid maintenance window day {day:?}"))
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { start, end, days })
+    }
+
+    fn opens_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        let time = now.time();
+        let today = now.weekday();
+        if self.start < self.end {
+            self.opens_on(today) && self.start <= time && time < self.end
+        } else {
+            // Spans midnight: open late on opening days and early the day after
+            (self.opens_on(today) && time >= self.start)
+                || (self.opens_on(today.pred()) && time < self.end)
+        }
+    }
+
+    /// `now` if the window is open, otherwise when it next opens
+    pub fn next_opening(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if self.is_open(now) {
+            return Some(now);
+        }
+        (0..=7)
+            .map(|days| now.date_naive() + chrono::Days::new(days))
+            .filter(|date| self.opens_on(date.weekday()))
+            .map(|date| date.and_time(self.start).and_utc())
+            .find(|opening| *opening > now)
+    }
+}
+
+impl HostSchedule {
+    /// Take the windows and hooks of `plan` and resolve the inventory groups
+    /// of `targets`. Fails on windows that do not parse.
+    pub fn from_plan(plan: &ExecutionPlan, targets: &[DeploymentTarget]) -> Result<Self> {
+        let config = &plan.deployment_config;
+        for window in &config.maintenance_windows {
+            ParsedWindow::parse(window)?;
+        }
+
+        let members = group_members(plan);
+        let host_groups = targets
+            .iter()
+            .map(|target| {
+                let mut groups: Vec<String> = members
+                    .iter()
+                    .filter(|(_, hosts)| hosts.contains(&target.host))
+                    .map(|(group, _)| group.clone())
+                    .collect();
+                groups.push(ALL_GROUP.to_string());
+                groups.sort();
+                groups.dedup();
+                (target.host.clone(), groups)
+            })
+            .collect();
+
+        Ok(Self {
+            maintenance_windows: config.maintenance_windows.clone(),
+            wait_for_window: config.wait_for_window,
+            hooks: config.hooks.clone(),
+            host_groups,
+        })
+    }
+
+    /// Whether something restricted to `groups` applies to `host`
+    fn applies_to(&self, groups: &[String], host: &str) -> bool {
+        groups.is_empty()
+            || groups.iter().any(|group| {
+                group == ALL_GROUP
+                    || self
+                        .host_groups
+                        .get(host)
+                        .is_some_and(|host_groups| host_groups.contains(group))
+            })
+    }
+
+    /// When `host` may next be deployed to: `now` if it is inside one of its
+    /// windows or has none, otherwise the earliest time one opens
+    pub fn next_opening(&self, host: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+        let mut windows = self
+            .maintenance_windows
+            .iter()
+            .filter(|window| self.applies_to(&window.groups, host))
+            .peekable();
+        if windows.peek().is_none() {
+            return Ok(now);
+        }
+
+        let mut earliest: Option<DateTime<Utc>> = None;
+        for window in windows {
+            if let Some(opening) = ParsedWindow::parse(window)?.next_opening(now) {
+                earliest = Some(earliest.map_or(opening, |earliest| earliest.min(opening)));
+            }
+        }
+        earliest.ok_or_else(|| {
+            DeployError::Configuration(format!("No maintenance window of {host} ever opens"))
+        })
+    }
+
+    pub fn pre_execution_hooks(&self, host: &str) -> Vec<&HostHook> {
+        self.hooks_for(&self.hooks.pre_execution, host)
+    }
+
+    pub fn post_execution_hooks(&self, host: &str) -> Vec<&HostHook> {
+        self.hooks_for(&self.hooks.post_execution, host)
+    }
+
+    fn hooks_for<'a>(&self, hooks: &'a [HostHook], host: &str) -> Vec<&'a HostHook> {
+        hooks
+            .iter()
+            .filter(|hook| self.applies_to(&hook.groups, host))
+            .collect()
+    }
+}
+
+/// Every host of every inventory group, by name and by address, following
+/// child groups
+fn group_members(plan: &ExecutionPlan) -> HashMap<String, HashSet<String>> {
+    let inventory = &plan.inventory;
+    let mut members = HashMap::new();
+    for name in inventory.groups.keys() {
+        let mut hosts = HashSet::new();
+        let mut pending = vec![name.as_str()];
+        let mut visited = HashSet::new();
+        while let Some(group_name) = pending.pop() {
+            if !visited.insert(group_name) {
+                continue;
+            }
+            let Some(group) = inventory.groups.get(group_name) else {
+                continue;
+            };
+            for host in &group.hosts {
+                if let Some(spec) = inventory.hosts.get(host) {
+                    hosts.insert(spec.address.clone());
+                }
+                hosts.insert(host.clone());
+            }
+            pending.extend(group.children.iter().map(String::as_str));
+        }
+        members.insert(name.clone(), hosts);
+    }
+    members
+}
+
+/// Run `hook` for `target`, failing with [`DeployError::HookFailed`] if the
+/// command does not succeed
+pub async fn run_hook(
+    deployer: &BinaryDeployer,
+    hook: &HostHook,
+    target: &DeploymentTarget,
+    deployment_id: &str,
+) -> Result<()> {
+    let command = hook.command.replace("{host}", &shell_quote(&target.host));
+    info!("Running hook for {}: {}", target.host, command);
+    let failed = |reason: String| DeployError::HookFailed {
+        host: target.host.clone(),
+        command: command.clone(),
+        reason,
+    };
+
+    let run = async {
+        match hook.run_on {
+            HookLocation::Controller => {
+                let env = [
+                    ("RUSTLE_HOST", target.host.as_str()),
+                    ("RUSTLE_DEPLOYMENT_ID", deployment_id),
+                ];
+                LocalConnection::new(&target.host)
+                    .execute_program("sh", &["-c".to_string(), command.clone()], &env, None)
+                    .await
+            }
+            HookLocation::Host => deployer.run_command(target, &command).await,
+        }
+    };
+    let result = match hook.timeout_secs {
+        Some(secs) => tokio::time::timeout(Duration::from_secs(secs), run)
+            .await
+            .map_err(|_| failed(format!("timed out after {secs}s")))?,
+        None => run.await,
+    }
+    .map_err(|e| failed(e.to_string()))?;
+
+    if !result.success {
+        let output = result.stderr.trim();
+        return Err(failed(if output.is_empty() {
+            format!("exited with {}", result.exit_code)
+        } else {
+            output.to_string()
+        }));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn window(start: &str, end: &str, days: &[&str]) -> ParsedWindow {
+        ParsedWindow::parse(&MaintenanceWindow {
+            groups: vec![],
+            start: start.to_string(),
+            end: end.to_string(),
+            days: days.iter().map(|day| day.to_string()).collect(),
+        })
+        .unwrap()
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        // 2024-01-01 was a Monday
+        Utc.with_ymd_and_hms(2024, 1, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_window_spanning_midnight() {
+        let saturday_night = window("22:00", "02:00", &["sat"]);
+        assert!(saturday_night.is_open(at(6, 23, 0)));
+        assert!(saturday_night.is_open(at(7, 1, 59)));
+        assert!(!saturday_night.is_open(at(7, 2, 0)));
+        assert!(!saturday_night.is_open(at(5, 23, 0)));
+
+        assert_eq!(
+            saturday_night.next_opening(at(6, 23, 0)),
+            Some(at(6, 23, 0))
+        );
+        assert_eq!(
+            saturday_night.next_opening(at(1, 12, 0)),
+            Some(at(6, 22, 0))
+        );
+        assert_eq!(
+            saturday_night.next_opening(at(7, 3, 0)),
+            Some(at(13, 22, 0))
+        );
+    }
+
+    #[test]
+    fn test_daily_window() {
+        let nightly = window("01:00", "03:30", &[]);
+        assert!(nightly.is_open(at(3, 1, 0)));
+        assert!(!nightly.is_open(at(3, 3, 30)));
+        assert_eq!(nightly.next_opening(at(3, 4, 0)), Some(at(4, 1, 0)));
+        assert_eq!(nightly.next_opening(at(3, 0, 15)), Some(at(3, 1, 0)));
+    }
+
+    #[test]
+    fn test_invalid_windows_are_rejected() {
+        let parse = |start: &str, end: &str, day: &str| {
+            ParsedWindow::parse(&MaintenanceWindow {
+                groups: vec![],
+                start: start.to_string(),
+                end: end.to_string(),
+                days: vec![day.to_string()],
+            })
+        };
+        assert!(parse("25:00", "02:00", "mon").is_err());
+        assert!(parse("02:00", "02:00", "mon").is_err());
+        assert!(parse("01:00", "02:00", "someday").is_err());
+        assert!(parse("01:00", "02:00", "Monday").is_ok());
+    }
+
+    #[test]
+    fn test_windows_and_hooks_apply_to_their_groups() {
+        let schedule = HostSchedule {
+            maintenance_windows: vec![MaintenanceWindow {
+                groups: vec!["db".to_string()],
+                start: "01:00".to_string(),
+                end: "02:00".to_string(),
+                days: vec![],
+            }],
+            hooks: crate::execution::HostHooks {
+                pre_execution: vec![HostHook {
+                    command: "drain {host}".to_string(),
+                    run_on: HookLocation::Controller,
+                    groups: vec!["web".to_string()],
+                    timeout_secs: None,
+                }],
+                post_execution: vec![],
+            },
+            host_groups: HashMap::from([
+                ("db1".to_string(), vec!["all".to_string(), "db".to_string()]),
+                (
+                    "web1".to_string(),
+                    vec!["all".to_string(), "web".to_string()],
+                ),
+            ]),
+            ..Default::default()
+        };
+
+        let now = at(2, 12, 0);
+        assert_eq!(schedule.next_opening("web1", now).unwrap(), now);
+        assert_eq!(schedule.next_opening("db1", now).unwrap(), at(3, 1, 0));
+        assert_eq!(schedule.pre_execution_hooks("web1").len(), 1);
+        assert!(schedule.pre_execution_hooks("db1").is_empty());
+    }
+}
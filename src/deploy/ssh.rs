@@ -0,0 +1,917 @@
+use crate::deploy::connection::{check_result, redact_env, ConnectionPlugin};
+use crate::deploy::{DeployError, Result};
+use async_trait::async_trait;
+use base64::Engine;
+use ssh2::{CheckResult, HostKeyType, KnownHostFileKind, Session};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{debug, warn};
+
+const DEFAULT_SSH_PORT: u16 = 22;
+const STREAM_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How long a read of command output waits on one stream, in milliseconds
+const STREAM_READ_TIMEOUT_MS: u32 = 1;
+const TUNNEL_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Authentication method attempted when opening an SSH session
+#[derive(Debug, Clone)]
+pub enum SshAuth {
+    /// Use keys loaded into a running ssh-agent
+    Agent,
+    /// Use a private key file, optionally protected by a passphrase
+    KeyFile {
+        path: PathBuf,
+        passphrase: Option<String>,
+    },
+    /// Plain password authentication
+    Password(String),
+}
+
+/// What to do with a host whose key is not in `known_hosts`, like OpenSSH
+/// `StrictHostKeyChecking`; a key that differs from a known one is always
+/// refused
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HostKeyChecking {
+    /// Refuse the host
+    Strict,
+    /// Trust the key on first connection and add it to `known_hosts`
+    #[default]
+    AcceptNew,
+    /// Trust the key without recording it
+    Off,
+}
+
+/// Connection settings shared by every SSH session opened by the deployer
+#[derive(Debug, Clone)]
+pub struct SshConnectionConfig {
+    pub username: Option<String>,
+    pub port: u16,
+    /// Authentication methods, tried in order until one succeeds
+    pub auth_methods: Vec<SshAuth>,
+    pub connect_timeout: Duration,
+    pub host_key_checking: HostKeyChecking,
+    pub known_hosts_file: Option<PathBuf>,
+    /// Bastion hosts (`[user@]host[:port]`) to hop through, in order, like OpenSSH `ProxyJump`
+    pub jump_hosts: Vec<String>,
+    /// Command whose stdin/stdout carry the connection, like OpenSSH `ProxyCommand`.
+    /// `%h`, `%p`, `%r` and `%%` are expanded; ignored when `jump_hosts` is set.
+    pub proxy_command: Option<String>,
+}
+
+impl SshConnectionConfig {
+    /// Route connections through the hosts in an OpenSSH-style `ProxyJump` list
+    pub fn with_proxy_jump(mut self, proxy_jump: &str) -> Self {
+        self.jump_hosts = parse_proxy_jump(proxy_jump);
+        self
+    }
+}
+
+impl Default for SshConnectionConfig {
+    fn default() -> Self {
+        let ssh_dir = dirs::home_dir().map(|home| home.join(".ssh"));
+
+        let mut auth_methods = vec![SshAuth::Agent];
+        if let Some(ref dir) = ssh_dir {
+            for key in ["id_ed25519", "id_ecdsa", "id_rsa"] {
+                let path = dir.join(key);
+                if path.exists() {
+                    auth_methods.push(SshAuth::KeyFile {
+                        path,
+                        passphrase: None,
+                    });
+                }
+            }
+        }
+
+        Self {
+            username: None,
+            port: DEFAULT_SSH_PORT,
+            auth_methods,
+            connect_timeout: Duration::from_secs(30),
+            host_key_checking: HostKeyChecking::default(),
+            known_hosts_file: ssh_dir.map(|dir| dir.join("known_hosts")),
+            jump_hosts: Vec::new(),
+            proxy_command: None,
+        }
+    }
+}
+
+/// Which remote stream a chunk of command output came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// A chunk of output produced by a remote command, forwarded as it arrives
+#[derive(Debug, Clone)]
+pub struct OutputChunk {
+    pub host: String,
+    pub stream: OutputStream,
+    pub data: String,
+}
+
+#[derive(Debug)]
+pub struct CommandResult {
+    pub success: bool,
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// An authenticated SSH session to a single host.
+///
+/// libssh2 is blocking, so every operation runs on tokio's blocking pool.
+/// The session is held by one operation at a time: command output is read
+/// with short timeouts, which libssh2 sets for the whole session.
+#[derive(Clone)]
+pub struct SshConnection {
+    host: String,
+    session: Arc<Mutex<Session>>,
+}
+
+impl SshConnection {
+    /// Connect to `host_spec` (`[user@]host[:port]`) and authenticate.
+    pub async fn connect(host_spec: &str, config: &SshConnectionConfig) -> Result<Self> {
+        let host_spec = host_spec.to_string();
+        let config = config.clone();
+
+        tokio::task::spawn_blocking(move || Self::connect_blocking(&host_spec, &config))
+            .await
+            .map_err(|e| DeployError::Network(format!("SSH connect task failed: {e}")))?
+    }
+
+    fn connect_blocking(host_spec: &str, config: &SshConnectionConfig) -> Result<Self> {
+        let (spec_user, host, spec_port) = parse_host_spec(host_spec);
+        let port = spec_port.unwrap_or(config.port);
+        let username = spec_user
+            .or_else(|| config.username.clone())
+            .or_else(|| std::env::var("USER").ok())
+            .ok_or_else(|| DeployError::SshAuthentication {
+                host: host.clone(),
+                reason: "No username configured".to_string(),
+            })?;
+
+        let connection_error = |reason: String| DeployError::SshConnection {
+            host: host.clone(),
+            reason,
+        };
+
+        let tcp = match (
+            config.jump_hosts.split_last(),
+            config.proxy_command.as_deref(),
+        ) {
+            (None, Some(command)) => {
+                let command = expand_proxy_command(command, &host, port, &username);
+                open_proxy_command(&command, &host).map_err(|e| {
+                    connection_error(format!("ProxyCommand '{command}' failed: {e}"))
+                })?
+            }
+            (None, None) => {
+                let addr = (host.as_str(), port)
+                    .to_socket_addrs()
+                    .map_err(|e| connection_error(format!("Failed to resolve address: {e}")))?
+                    .next()
+                    .ok_or_else(|| connection_error("Address resolved to nothing".to_string()))?;
+
+                TcpStream::connect_timeout(&addr, config.connect_timeout)
+                    .map_err(|e| connection_error(format!("TCP connect to {addr} failed: {e}")))?
+            }
+            (Some((last_jump, earlier_jumps)), _) => {
+                // Reach the last jump host through any earlier ones, then tunnel from it.
+                // Hops name their own user and port; the target's are not theirs
+                let jump_config = SshConnectionConfig {
+                    username: None,
+                    port: DEFAULT_SSH_PORT,
+                    jump_hosts: earlier_jumps.to_vec(),
+                    proxy_command: None,
+                    ..config.clone()
+                };
+                let bastion = Self::connect_blocking(last_jump, &jump_config).map_err(|e| {
+                    connection_error(format!("Failed to reach jump host {last_jump}: {e}"))
+                })?;
+                let tunnel = open_tunnel(&lock(&bastion.session), &host, port);
+                tunnel
+                    .map_err(|e| connection_error(format!("Tunnel via {last_jump} failed: {e}")))?
+            }
+        };
+
+        let mut session =
+            Session::new().map_err(|e| connection_error(format!("Session init failed: {e}")))?;
+        session.set_tcp_stream(tcp);
+        session.set_timeout(config.connect_timeout.as_millis().min(u32::MAX as u128) as u32);
+        session
+            .handshake()
+            .map_err(|e| connection_error(format!("SSH handshake failed: {e}")))?;
+
+        Self::check_host_key(&session, &host, port, config)?;
+        Self::authenticate(&session, &host, &username, config)?;
+
+        // Commands may legitimately run for a long time; only the connect phase is bounded
+        session.set_timeout(0);
+
+        debug!("SSH session established to {}@{}:{}", username, host, port);
+        Ok(Self {
+            host,
+            session: Arc::new(Mutex::new(session)),
+        })
+    }
+
+    fn check_host_key(
+        session: &Session,
+        host: &str,
+        port: u16,
+        config: &SshConnectionConfig,
+    ) -> Result<()> {
+        let refused = |reason: &str| DeployError::SshConnection {
+            host: host.to_string(),
+            reason: reason.to_string(),
+        };
+        let Some(ref known_hosts_file) = config.known_hosts_file else {
+            if config.host_key_checking == HostKeyChecking::Strict {
+                return Err(refused("No known_hosts file to check the host key against"));
+            }
+            return Ok(());
+        };
+
+        let (key, key_type) = session
+            .host_key()
+            .ok_or_else(|| refused("Server did not present a host key"))?;
+
+        let mut known_hosts = session
+            .known_hosts()
+            .map_err(|e| refused(&format!("Failed to initialise known hosts: {e}")))?;
+        if known_hosts_file.exists() {
+            if let Err(e) = known_hosts.read_file(known_hosts_file, KnownHostFileKind::OpenSSH) {
+                warn!("Failed to read {}: {}", known_hosts_file.display(), e);
+            }
+        }
+
+        match (
+            known_hosts.check_port(host, port, key),
+            config.host_key_checking,
+        ) {
+            (CheckResult::Match, _) => Ok(()),
+            (CheckResult::Mismatch, _) => Err(refused("Host key does not match known_hosts entry")),
+            (CheckResult::NotFound | CheckResult::Failure, HostKeyChecking::Strict) => {
+                Err(refused("Host key not found in known_hosts"))
+            }
+            (CheckResult::NotFound, HostKeyChecking::AcceptNew) => {
+                match add_known_host(known_hosts_file, host, port, key, key_type) {
+                    Ok(()) => warn!(
+                        "Added the host key of {} to {}",
+                        host,
+                        known_hosts_file.display()
+                    ),
+                    Err(e) => warn!(
+                        "Accepting the host key of {} but failed to add it to {}: {}",
+                        host,
+                        known_hosts_file.display(),
+                        e
+                    ),
+                }
+                Ok(())
+            }
+            (CheckResult::NotFound | CheckResult::Failure, _) => {
+                warn!("Host key for {} is not in known_hosts, accepting", host);
+                Ok(())
+            }
+        }
+    }
+
+    fn authenticate(
+        session: &Session,
+        host: &str,
+        username: &str,
+        config: &SshConnectionConfig,
+    ) -> Result<()> {
+        let mut failures = Vec::new();
+
+        for method in &config.auth_methods {
+            let attempt = match method {
+                SshAuth::Agent => session.userauth_agent(username),
+                SshAuth::KeyFile { path, passphrase } => {
+                    session.userauth_pubkey_file(username, None, path, passphrase.as_deref())
+                }
+                SshAuth::Password(password) => session.userauth_password(username, password),
+            };
+
+            match attempt {
+                Ok(()) if session.authenticated() => return Ok(()),
+                Ok(()) => failures.push(format!("{}: not accepted", auth_label(method))),
+                Err(e) => failures.push(format!("{}: {e}", auth_label(method))),
+            }
+        }
+
+        Err(DeployError::SshAuthentication {
+            host: host.to_string(),
+            reason: if failures.is_empty() {
+                "No authentication methods configured".to_string()
+            } else {
+                failures.join("; ")
+            },
+        })
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Name this host in output chunks and logs, e.g. by its inventory alias
+    /// rather than the address it was resolved to.
+    pub fn with_host_label(mut self, host: &str) -> Self {
+        self.host = host.to_string();
+        self
+    }
+
+    /// Run a command and collect its output once it exits.
+    pub async fn execute_command(&self, command: &str) -> Result<CommandResult> {
+        self.run(command, None).await
+    }
+
+    /// Run a command, forwarding stdout/stderr chunks to `sink` as they arrive.
+    pub async fn execute_streaming(
+        &self,
+        command: &str,
+        sink: UnboundedSender<OutputChunk>,
+    ) -> Result<CommandResult> {
+        self.run(command, Some(sink)).await
+    }
+
+    async fn run(
+        &self,
+        command: &str,
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        debug!(
+            "Executing command on {}: {}",
+            self.host,
+            redact_env(command)
+        );
+
+        let session = self.session.clone();
+        let host = self.host.clone();
+        let command = command.to_string();
+
+        tokio::task::spawn_blocking(move || run_blocking(&session, &host, &command, sink))
+            .await
+            .map_err(|e| DeployError::Network(format!("SSH command task failed: {e}")))?
+    }
+
+    /// Upload `data` to `remote_path` over SFTP and apply `mode`.
+    pub async fn upload_bytes(&self, data: &[u8], remote_path: &str, mode: u32) -> Result<()> {
+        let session = self.session.clone();
+        let host = self.host.clone();
+        let data = data.to_vec();
+        let remote_path = PathBuf::from(remote_path);
+
+        tokio::task::spawn_blocking(move || {
+            let sftp_error = |reason: String| DeployError::DeploymentFailed {
+                host: host.clone(),
+                reason,
+            };
+
+            let session = lock(&session);
+            let sftp = session
+                .sftp()
+                .map_err(|e| sftp_error(format!("Failed to open SFTP channel: {e}")))?;
+            let mut file = sftp.create(&remote_path).map_err(|e| {
+                sftp_error(format!("Failed to create {}: {e}", remote_path.display()))
+            })?;
+            file.write_all(&data).map_err(|e| {
+                sftp_error(format!("Failed to write {}: {e}", remote_path.display()))
+            })?;
+            drop(file);
+
+            sftp.setstat(
+                &remote_path,
+                ssh2::FileStat {
+                    size: None,
+                    uid: None,
+                    gid: None,
+                    perm: Some(mode),
+                    atime: None,
+                    mtime: None,
+                },
+            )
+            .map_err(|e| {
+                sftp_error(format!(
+                    "Failed to set permissions on {}: {e}",
+                    remote_path.display()
+                ))
+            })?;
+
+            debug!(
+                "Uploaded {} bytes to {}:{}",
+                data.len(),
+                host,
+                remote_path.display()
+            );
+            Ok(())
+        })
+        .await
+        .map_err(|e| DeployError::Network(format!("SFTP upload task failed: {e}")))?
+    }
+
+    /// Read the file at `remote_path` over SFTP.
+    pub async fn download_bytes(&self, remote_path: &str) -> Result<Vec<u8>> {
+        let session = self.session.clone();
+        let host = self.host.clone();
+        let remote_path = PathBuf::from(remote_path);
+
+        tokio::task::spawn_blocking(move || {
+            let sftp_error = |reason: String| DeployError::DeploymentFailed {
+                host: host.clone(),
+                reason,
+            };
+
+            let session = lock(&session);
+            let sftp = session
+                .sftp()
+                .map_err(|e| sftp_error(format!("Failed to open SFTP channel: {e}")))?;
+            let mut file = sftp.open(&remote_path).map_err(|e| {
+                sftp_error(format!("Failed to open {}: {e}", remote_path.display()))
+            })?;
+            let mut data = Vec::new();
+            file.read_to_end(&mut data).map_err(|e| {
+                sftp_error(format!("Failed to read {}: {e}", remote_path.display()))
+            })?;
+
+            debug!(
+                "Downloaded {} bytes from {}:{}",
+                data.len(),
+                host,
+                remote_path.display()
+            );
+            Ok(data)
+        })
+        .await
+        .map_err(|e| DeployError::Network(format!("SFTP download task failed: {e}")))?
+    }
+}
+
+#[async_trait]
+impl ConnectionPlugin for SshConnection {
+    fn host(&self) -> &str {
+        &self.host
+    }
+
+    fn transport(&self) -> &'static str {
+        "ssh"
+    }
+
+    async fn execute(
+        &self,
+        command: &str,
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        self.run(command, sink).await
+    }
+
+    async fn upload(&self, data: &[u8], path: &str, mode: u32) -> Result<()> {
+        if let Some(parent) = remote_parent(path) {
+            let result = self
+                .run(&format!("mkdir -p {}", shell_quote(&parent)), None)
+                .await?;
+            check_result(&self.host, "Failed to create target directory", result)?;
+        }
+        self.upload_bytes(data, path, mode).await
+    }
+}
+
+fn run_blocking(
+    session: &Mutex<Session>,
+    host: &str,
+    command: &str,
+    sink: Option<UnboundedSender<OutputChunk>>,
+) -> Result<CommandResult> {
+    let channel_error = |reason: String| DeployError::Network(format!("{host}: {reason}"));
+
+    let mut channel = {
+        let session = lock(session);
+        let mut channel = session
+            .channel_session()
+            .map_err(|e| channel_error(format!("Failed to open channel: {e}")))?;
+        channel
+            .exec(command)
+            .map_err(|e| channel_error(format!("Failed to exec command: {e}")))?;
+        channel
+    };
+
+    // Read both streams in turn so neither can fill the channel's window and
+    // stall the other, letting other operations have the session in between
+    let mut output = (Vec::new(), Vec::new());
+    loop {
+        let read = {
+            let session = lock(session);
+            session.set_timeout(STREAM_READ_TIMEOUT_MS);
+            let read = read_output(&mut channel, host, &mut output, sink.as_ref());
+            session.set_timeout(0);
+            read
+        };
+        let progressed = read.map_err(|e| channel_error(format!("Failed to read output: {e}")))?;
+        if !progressed {
+            if channel.eof() {
+                break;
+            }
+            std::thread::sleep(STREAM_POLL_INTERVAL);
+        }
+    }
+
+    let _session = lock(session);
+    channel
+        .wait_close()
+        .map_err(|e| channel_error(format!("Failed to close channel: {e}")))?;
+    let exit_code = channel
+        .exit_status()
+        .map_err(|e| channel_error(format!("Failed to read exit status: {e}")))?;
+
+    let (stdout, stderr) = output;
+    Ok(CommandResult {
+        success: exit_code == 0,
+        exit_code,
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+    })
+}
+
+/// Read what has arrived on stdout and stderr of `channel` into `output`,
+/// forwarding it to `sink`; whether anything had
+fn read_output(
+    channel: &mut ssh2::Channel,
+    host: &str,
+    output: &mut (Vec<u8>, Vec<u8>),
+    sink: Option<&UnboundedSender<OutputChunk>>,
+) -> std::io::Result<bool> {
+    let mut buf = [0u8; 8192];
+    let mut progressed = false;
+    for stream in [OutputStream::Stdout, OutputStream::Stderr] {
+        let read = match stream {
+            OutputStream::Stdout => channel.read(&mut buf),
+            OutputStream::Stderr => channel.stderr().read(&mut buf),
+        };
+        let n = match read {
+            Ok(n) => n,
+            Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => 0,
+            Err(e) => return Err(e),
+        };
+        if n == 0 {
+            continue;
+        }
+        progressed = true;
+        let target = match stream {
+            OutputStream::Stdout => &mut output.0,
+            OutputStream::Stderr => &mut output.1,
+        };
+        target.extend_from_slice(&buf[..n]);
+        if let Some(sink) = sink {
+            let _ = sink.send(OutputChunk {
+                host: host.to_string(),
+                stream,
+                data: String::from_utf8_lossy(&buf[..n]).to_string(),
+            });
+        }
+    }
+    Ok(progressed)
+}
+
+/// The session, once no other operation holds it
+fn lock(session: &Mutex<Session>) -> MutexGuard<'_, Session> {
+    session.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Append the `key` of `host` to `known_hosts_file`, as OpenSSH names hosts
+/// on other ports than 22
+fn add_known_host(
+    known_hosts_file: &Path,
+    host: &str,
+    port: u16,
+    key: &[u8],
+    key_type: HostKeyType,
+) -> std::io::Result<()> {
+    let key_type = match key_type {
+        HostKeyType::Rsa => "ssh-rsa",
+        HostKeyType::Dss => "ssh-dss",
+        HostKeyType::Ecdsa256 => "ecdsa-sha2-nistp256",
+        HostKeyType::Ecdsa384 => "ecdsa-sha2-nistp384",
+        HostKeyType::Ecdsa521 => "ecdsa-sha2-nistp521",
+        HostKeyType::Ed25519 => "ssh-ed25519",
+        HostKeyType::Unknown => return Err(std::io::Error::other("unknown host key type")),
+    };
+    let name = match port {
+        DEFAULT_SSH_PORT => host.to_string(),
+        port => format!("[{host}]:{port}"),
+    };
+    if let Some(parent) = known_hosts_file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Appending leaves entries other connections add meanwhile in place
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(known_hosts_file)?;
+    let key = base64::engine::general_purpose::STANDARD.encode(key);
+    writeln!(file, "{name} {key_type} {key}")
+}
+
+/// Open a `direct-tcpip` channel from `bastion` to `host:port` and expose it as
+/// a local TCP stream that a nested [`Session`] can run over.
+fn open_tunnel(bastion: &Session, host: &str, port: u16) -> std::io::Result<TcpStream> {
+    let channel = bastion
+        .channel_direct_tcpip(host, port, None)
+        .map_err(std::io::Error::other)?;
+
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let local = TcpStream::connect(listener.local_addr()?)?;
+    let (bridged, _) = listener.accept()?;
+
+    let bastion = bastion.clone();
+    std::thread::Builder::new()
+        .name(format!("ssh-tunnel-{host}"))
+        .spawn(move || {
+            if let Err(e) = pump_tunnel(&bastion, channel, bridged) {
+                debug!("SSH tunnel closed: {}", e);
+            }
+        })?;
+
+    Ok(local)
+}
+
+/// Spawn a `ProxyCommand` and expose its stdin/stdout as a local TCP stream.
+fn open_proxy_command(command: &str, host: &str) -> std::io::Result<TcpStream> {
+    let mut child = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| std::io::Error::other("no stdin"))?;
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| std::io::Error::other("no stdout"))?;
+
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let local = TcpStream::connect(listener.local_addr()?)?;
+    let (mut bridged, _) = listener.accept()?;
+    let mut bridged_reader = bridged.try_clone()?;
+
+    std::thread::Builder::new()
+        .name(format!("ssh-proxy-in-{host}"))
+        .spawn(move || {
+            let _ = std::io::copy(&mut bridged_reader, &mut stdin);
+        })?;
+    std::thread::Builder::new()
+        .name(format!("ssh-proxy-out-{host}"))
+        .spawn(move || {
+            let _ = std::io::copy(&mut stdout, &mut bridged);
+            let _ = bridged.shutdown(std::net::Shutdown::Both);
+            let _ = child.kill();
+            let _ = child.wait();
+        })?;
+
+    Ok(local)
+}
+
+/// Expand the `ProxyCommand` tokens OpenSSH supports for the target
+fn expand_proxy_command(command: &str, host: &str, port: u16, user: &str) -> String {
+    let mut expanded = String::with_capacity(command.len());
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some('h') => expanded.push_str(host),
+            Some('p') => expanded.push_str(&port.to_string()),
+            Some('r') => expanded.push_str(user),
+            Some(other) => {
+                expanded.push('%');
+                expanded.push(other);
+            }
+            None => expanded.push('%'),
+        }
+    }
+    expanded
+}
+
+/// Shuttle bytes between a tunnel channel and its local socket until either side closes
+fn pump_tunnel(
+    bastion: &Session,
+    mut channel: ssh2::Channel,
+    mut socket: TcpStream,
+) -> std::io::Result<()> {
+    bastion.set_blocking(false);
+    socket.set_nonblocking(true)?;
+
+    let mut to_remote: Vec<u8> = Vec::new();
+    let mut to_local: Vec<u8> = Vec::new();
+    let mut buf = [0u8; 16384];
+
+    loop {
+        let mut progressed = false;
+
+        if to_remote.is_empty() {
+            match socket.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    to_remote.extend_from_slice(&buf[..n]);
+                    progressed = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !to_remote.is_empty() {
+            match channel.write(&to_remote) {
+                Ok(n) => {
+                    to_remote.drain(..n);
+                    progressed = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if to_local.is_empty() {
+            match channel.read(&mut buf) {
+                Ok(0) if channel.eof() => break,
+                Ok(0) => {}
+                Ok(n) => {
+                    to_local.extend_from_slice(&buf[..n]);
+                    progressed = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !to_local.is_empty() {
+            match socket.write(&to_local) {
+                Ok(n) => {
+                    to_local.drain(..n);
+                    progressed = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if !progressed {
+            std::thread::sleep(TUNNEL_POLL_INTERVAL);
+        }
+    }
+
+    let _ = channel.send_eof();
+    Ok(())
+}
+
+fn auth_label(method: &SshAuth) -> String {
+    match method {
+        SshAuth::Agent => "agent".to_string(),
+        SshAuth::KeyFile { path, .. } => format!("key {}", path.display()),
+        SshAuth::Password(_) => "password".to_string(),
+    }
+}
+
+/// Split `[user@]host[:port]` into its parts. IPv6 addresses with a port
+/// must be bracketed (`[::1]:2222`).
+pub fn parse_host_spec(spec: &str) -> (Option<String>, String, Option<u16>) {
+    let (user, rest) = match spec.rsplit_once('@') {
+        Some((user, rest)) if !user.is_empty() => (Some(user.to_string()), rest),
+        _ => (None, spec),
+    };
+
+    if let Some(stripped) = rest.strip_prefix('[') {
+        if let Some((host, tail)) = stripped.split_once(']') {
+            let port = tail.strip_prefix(':').and_then(|p| p.parse().ok());
+            return (user, host.to_string(), port);
+        }
+    }
+
+    match rest.rsplit_once(':') {
+        // A single colon separates host and port; more than one is a bare IPv6 address
+        Some((host, port)) if !host.contains(':') => match port.parse() {
+            Ok(port) => (user, host.to_string(), Some(port)),
+            Err(_) => (user, rest.to_string(), None),
+        },
+        _ => (user, rest.to_string(), None),
+    }
+}
+
+/// Split an OpenSSH `ProxyJump` value (`bastion1,user@bastion2:2222`) into hops.
+/// `none` disables jumping, as in `ssh_config`.
+pub fn parse_proxy_jump(value: &str) -> Vec<String> {
+    if value.trim().eq_ignore_ascii_case("none") {
+        return Vec::new();
+    }
+    value
+        .split(',')
+        .map(|hop| hop.trim().trim_start_matches("ssh://").to_string())
+        .filter(|hop| !hop.is_empty())
+        .collect()
+}
+
+/// Quote a remote path for use in a POSIX shell command.
+pub fn shell_quote(path: &str) -> String {
+    shell_words::quote(path).to_string()
+}
+
+/// Parent directory of a remote path, as a string.
+pub fn remote_parent(path: &str) -> Option<String> {
+    Path::new(path)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(|p| p.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_host_spec() {
+        assert_eq!(parse_host_spec("web01"), (None, "web01".to_string(), None));
+        assert_eq!(
+            parse_host_spec("deploy@web01:2222"),
+            (Some("deploy".to_string()), "web01".to_string(), Some(2222))
+        );
+        assert_eq!(
+            parse_host_spec("fe80::1"),
+            (None, "fe80::1".to_string(), None)
+        );
+        assert_eq!(
+            parse_host_spec("root@[fe80::1]:22"),
+            (Some("root".to_string()), "fe80::1".to_string(), Some(22))
+        );
+    }
+
+    #[test]
+    fn test_parse_proxy_jump() {
+        assert_eq!(
+            parse_proxy_jump("bastion, ops@inner:2222"),
+            vec!["bastion".to_string(), "ops@inner:2222".to_string()]
+        );
+        assert_eq!(
+            parse_proxy_jump("ssh://jump.example.com"),
+            vec!["jump.example.com".to_string()]
+        );
+        assert!(parse_proxy_jump("none").is_empty());
+    }
+
+    #[test]
+    fn test_remote_parent() {
+        assert_eq!(
+            remote_parent("/opt/rustle/runner"),
+            Some("/opt/rustle".to_string())
+        );
+        assert_eq!(remote_parent("runner"), None);
+    }
+
+    #[test]
+    fn test_expand_proxy_command() {
+        assert_eq!(
+            expand_proxy_command("ssh -W %h:%p -l %r gw # 100%%", "10.0.0.5", 2222, "ops"),
+            "ssh -W 10.0.0.5:2222 -l ops gw # 100%"
+        );
+    }
+
+    #[test]
+    fn test_new_host_keys_are_added_to_known_hosts() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join(".ssh/known_hosts");
+        let key = b"\0\0\0\x0bssh-ed25519\0\0\0\x20abcdefghijklmnopqrstuvwxyz012345";
+        add_known_host(&file, "web01", 22, key, HostKeyType::Ed25519).unwrap();
+        add_known_host(&file, "10.0.0.5", 2222, key, HostKeyType::Ed25519).unwrap();
+
+        let session = Session::new().unwrap();
+        let mut known_hosts = session.known_hosts().unwrap();
+        known_hosts
+            .read_file(&file, KnownHostFileKind::OpenSSH)
+            .unwrap();
+        assert!(matches!(
+            known_hosts.check_port("web01", 22, key),
+            CheckResult::Match
+        ));
+        assert!(matches!(
+            known_hosts.check_port("10.0.0.5", 2222, key),
+            CheckResult::Match
+        ));
+        assert!(matches!(
+            known_hosts.check_port("10.0.0.5", 22, key),
+            CheckResult::NotFound
+        ));
+        assert!(matches!(
+            known_hosts.check_port("web01", 22, b"other key"),
+            CheckResult::Mismatch
+        ));
+    }
+}
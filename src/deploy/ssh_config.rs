@@ -0,0 +1,640 @@
+//! OpenSSH client configuration support for the deploy transport.
+//!
+//! Connection settings for a target are resolved with this precedence,
+//! highest first:
+//!
+//! 1. `user@host:port` written explicitly in the target host
+//! 2. inventory variables (`ansible_host`, `ansible_port`, `ansible_user`,
+//!    `ansible_ssh_private_key_file`, and the `ProxyJump` or `ProxyCommand`
+//!    of `ansible_ssh_common_args`)
+//! 3. the first matching value in `~/.ssh/config`, as OpenSSH would pick it
+//! 4. the deployer's [`SshConnectionConfig`] defaults
+//!
+//! Identity files are cumulative: the inventory key is tried first, then any
+//! `IdentityFile` entries, then the default agent and key files.
+//!
+//! Jump hosts take their user and port from the hop itself, then its
+//! ssh_config entry, then the deployer defaults, never from the target.
+
+use crate::deploy::ssh::{parse_host_spec, parse_proxy_jump, SshAuth, SshConnectionConfig};
+use crate::types::HostConnectionVars;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Maximum depth of nested `Include` directives, as in OpenSSH
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A `Host` block from an ssh_config file
+#[derive(Debug, Clone, Default)]
+struct HostBlock {
+    patterns: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl HostBlock {
+    fn matches(&self, host: &str) -> bool {
+        let mut matched = false;
+        for pattern in &self.patterns {
+            if let Some(negated) = pattern.strip_prefix('!') {
+                if wildcard_match(negated, host) {
+                    return false;
+                }
+            } else if wildcard_match(pattern, host) {
+                matched = true;
+            }
+        }
+        matched
+    }
+}
+
+/// Settings that `~/.ssh/config` specifies for one host
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SshHostConfig {
+    pub hostname: Option<String>,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    pub identity_files: Vec<PathBuf>,
+    pub proxy_jump: Option<String>,
+    pub proxy_command: Option<String>,
+}
+
+/// Parsed OpenSSH client configuration
+#[derive(Debug, Clone, Default)]
+pub struct SshConfig {
+    blocks: Vec<HostBlock>,
+}
+
+impl SshConfig {
+    /// Load `~/.ssh/config`, or an empty configuration if there is none
+    pub fn load_default() -> Self {
+        dirs::home_dir()
+            .map(|home| home.join(".ssh").join("config"))
+            .filter(|path| path.exists())
+            .map(|path| Self::load(&path))
+            .unwrap_or_default()
+    }
+
+    pub fn load(path: &Path) -> Self {
+        let mut config = Self::default();
+        config.blocks.push(HostBlock {
+            patterns: vec!["*".to_string()],
+            options: Vec::new(),
+        });
+        config.read_file(path, 0);
+        config
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let mut config = Self::default();
+        config.blocks.push(HostBlock {
+            patterns: vec!["*".to_string()],
+            options: Vec::new(),
+        });
+        config.read_str(content, 0);
+        config
+    }
+
+    fn read_file(&mut self, path: &Path, depth: usize) {
+        match std::fs::read_to_string(path) {
+            Ok(content) => self.read_str(&content, depth),
+            Err(e) => debug!("Skipping ssh config {}: {}", path.display(), e),
+        }
+    }
+
+    fn read_str(&mut self, content: &str, depth: usize) {
+        for line in content.lines() {
+            let Some((keyword, args)) = split_directive(line) else {
+                continue;
+            };
+
+            match keyword.as_str() {
+                "host" => self.blocks.push(HostBlock {
+                    patterns: args,
+                    options: Vec::new(),
+                }),
+                "match" => {
+                    // Match criteria are not evaluated; options under them never apply
+                    debug!("Ignoring unsupported ssh config Match block");
+                    self.blocks.push(HostBlock::default());
+                }
+                "include" if depth < MAX_INCLUDE_DEPTH => {
+                    for pattern in &args {
+                        for path in expand_include(pattern) {
+                            self.read_file(&path, depth + 1);
+                        }
+                    }
+                }
+                _ => {
+                    if let Some(block) = self.blocks.last_mut() {
+                        block.options.push((keyword, args.join(" ")));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Collect the settings that apply to `host`, first value wins
+    pub fn resolve(&self, host: &str) -> SshHostConfig {
+        let mut resolved = SshHostConfig::default();
+
+        for block in self.blocks.iter().filter(|block| block.matches(host)) {
+            for (keyword, value) in &block.options {
+                match keyword.as_str() {
+                    "hostname" if resolved.hostname.is_none() => {
+                        resolved.hostname = Some(value.replace("%h", host));
+                    }
+                    "port" if resolved.port.is_none() => resolved.port = value.parse().ok(),
+                    "user" if resolved.user.is_none() => resolved.user = Some(value.clone()),
+                    "identityfile" => resolved.identity_files.push(PathBuf::from(value)),
+                    "proxyjump" if resolved.proxy_jump.is_none() => {
+                        resolved.proxy_jump = Some(value.clone());
+                    }
+                    "proxycommand" if resolved.proxy_command.is_none() => {
+                        resolved.proxy_command = Some(value.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        resolved
+    }
+}
+
+/// Connection target and settings for one deployment host
+#[derive(Debug, Clone)]
+pub struct ResolvedConnection {
+    /// `user@host:port` spec to hand to [`crate::deploy::SshConnection::connect`]
+    pub host_spec: String,
+    pub config: SshConnectionConfig,
+}
+
+/// Combine the target host, inventory variables, ssh_config and deployer
+/// defaults into the settings for a single connection
+pub fn resolve_connection(
+    target_host: &str,
+    vars: &HostConnectionVars,
+    ssh_config: &SshConfig,
+    defaults: &SshConnectionConfig,
+) -> ResolvedConnection {
+    let (spec_user, spec_host, spec_port) = parse_host_spec(target_host);
+
+    // ssh_config is matched against the name ssh would be invoked with
+    let alias = vars.host.clone().unwrap_or(spec_host);
+    let from_file = ssh_config.resolve(&alias);
+
+    let hostname = from_file.hostname.clone().unwrap_or_else(|| alias.clone());
+    let port = spec_port
+        .or(vars.port)
+        .or(from_file.port)
+        .unwrap_or(defaults.port);
+    let user = spec_user
+        .or_else(|| vars.user.clone())
+        .or_else(|| from_file.user.clone())
+        .or_else(|| defaults.username.clone());
+
+    let mut config = defaults.clone();
+    config.port = port;
+    config.username = user.clone();
+
+    let local_user = std::env::var("USER").unwrap_or_default();
+    let remote_user = user.clone().unwrap_or_else(|| local_user.clone());
+    let expand =
+        |path: &str| expand_tokens(path, &alias, &hostname, port, &remote_user, &local_user);
+
+    let mut identity_files: Vec<PathBuf> = vars
+        .private_key_file
+        .iter()
+        .map(|path| PathBuf::from(expand(path)))
+        .collect();
+    identity_files.extend(
+        from_file
+            .identity_files
+            .iter()
+            .map(|path| PathBuf::from(expand(&path.to_string_lossy()))),
+    );
+    let mut auth_methods: Vec<SshAuth> = identity_files
+        .into_iter()
+        .map(|path| SshAuth::KeyFile {
+            path,
+            passphrase: None,
+        })
+        .collect();
+    for method in &defaults.auth_methods {
+        let duplicate = match method {
+            SshAuth::KeyFile { path, .. } => auth_methods
+                .iter()
+                .any(|m| matches!(m, SshAuth::KeyFile { path: p, .. } if p == path)),
+            _ => false,
+        };
+        if !duplicate {
+            auth_methods.push(method.clone());
+        }
+    }
+    if let Some(ref password) = vars.password {
+        auth_methods.push(SshAuth::Password(password.clone()));
+    }
+    config.auth_methods = auth_methods;
+
+    // A proxy in the inventory replaces both of ssh_config's
+    let (proxy_jump, proxy_command) = match vars.ssh_common_args.as_deref().map(proxy_args) {
+        Some((None, None)) | None => (from_file.proxy_jump, from_file.proxy_command),
+        Some(proxy) => proxy,
+    };
+    if let Some(ref proxy_jump) = proxy_jump {
+        config.jump_hosts = parse_proxy_jump(proxy_jump)
+            .iter()
+            .map(|hop| resolve_jump_host(hop, ssh_config, defaults))
+            .collect();
+    }
+    // ProxyCommand tokens are expanded when the connection is opened
+    if config.jump_hosts.is_empty() {
+        config.proxy_command = proxy_command
+            .filter(|command| !command.eq_ignore_ascii_case("none"))
+            .or(config.proxy_command);
+    }
+
+    let host_spec = match user {
+        Some(user) => format!("{user}@{}", format_host_port(&hostname, port)),
+        None => format_host_port(&hostname, port),
+    };
+
+    ResolvedConnection { host_spec, config }
+}
+
+/// Apply ssh_config `HostName`, `User` and `Port` to a `ProxyJump` hop,
+/// then the deployer's user and port
+fn resolve_jump_host(hop: &str, ssh_config: &SshConfig, defaults: &SshConnectionConfig) -> String {
+    let (user, alias, port) = parse_host_spec(hop);
+    let from_file = ssh_config.resolve(&alias);
+
+    let hostname = from_file.hostname.unwrap_or(alias);
+    let port = port.or(from_file.port).unwrap_or(defaults.port);
+    let host_port = format_host_port(&hostname, port);
+    match user
+        .or(from_file.user)
+        .or_else(|| defaults.username.clone())
+    {
+        Some(user) => format!("{user}@{host_port}"),
+        None => host_port,
+    }
+}
+
+/// The `ProxyJump` and `ProxyCommand` of OpenSSH arguments such as
+/// `ansible_ssh_common_args`: `-J hops`, `-o ProxyJump=hops` and
+/// `-o ProxyCommand=command`, or `-o "ProxyCommand command"`
+fn proxy_args(args: &str) -> (Option<String>, Option<String>) {
+    let words = match shell_words::split(args) {
+        Ok(words) => words,
+        Err(e) => {
+            debug!("Ignoring unparsable ansible_ssh_common_args: {}", e);
+            return (None, None);
+        }
+    };
+    let (mut proxy_jump, mut proxy_command) = (None, None);
+    let mut words = words.into_iter();
+    while let Some(word) = words.next() {
+        let mut value = |flag: &str| match word.strip_prefix(flag)? {
+            "" => words.next(),
+            value => Some(value.to_string()),
+        };
+        if let Some(hops) = value("-J") {
+            proxy_jump.get_or_insert(hops);
+        } else if let Some(option) = value("-o") {
+            let Some((keyword, value)) = option
+                .split_once(|c: char| c == '=' || c.is_whitespace())
+                .map(|(keyword, value)| (keyword.to_ascii_lowercase(), value.trim()))
+            else {
+                continue;
+            };
+            // The first value wins, as in OpenSSH
+            match keyword.as_str() {
+                "proxyjump" => proxy_jump.get_or_insert(value.to_string()),
+                "proxycommand" => proxy_command.get_or_insert(value.to_string()),
+                _ => continue,
+            };
+        }
+    }
+    (proxy_jump, proxy_command)
+}
+
+fn format_host_port(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+/// Split a config line into a lowercased keyword and its arguments
+fn split_directive(line: &str) -> Option<(String, Vec<String>)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let (keyword, rest) = match line.find(|c: char| c.is_whitespace() || c == '=') {
+        Some(index) => (&line[..index], &line[index..]),
+        None => (line, ""),
+    };
+    let rest = rest.trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest).trim();
+
+    let args = shell_words::split(rest)
+        .unwrap_or_else(|_| rest.split_whitespace().map(str::to_string).collect());
+
+    Some((keyword.to_lowercase(), args))
+}
+
+/// Expand the `%` tokens and leading `~` OpenSSH supports in paths and commands
+fn expand_tokens(
+    value: &str,
+    alias: &str,
+    hostname: &str,
+    port: u16,
+    remote_user: &str,
+    local_user: &str,
+) -> String {
+    let home = dirs::home_dir()
+        .map(|home| home.display().to_string())
+        .unwrap_or_default();
+
+    let mut expanded = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some('h') => expanded.push_str(hostname),
+            Some('n') => expanded.push_str(alias),
+            Some('p') => expanded.push_str(&port.to_string()),
+            Some('r') => expanded.push_str(remote_user),
+            Some('u') => expanded.push_str(local_user),
+            Some('d') => expanded.push_str(&home),
+            Some(other) => {
+                expanded.push('%');
+                expanded.push(other);
+            }
+            None => expanded.push('%'),
+        }
+    }
+
+    match expanded.strip_prefix("~/") {
+        Some(rest) => format!("{home}/{rest}"),
+        None => expanded,
+    }
+}
+
+/// Resolve an `Include` argument, which may be relative to `~/.ssh` and use wildcards
+fn expand_include(pattern: &str) -> Vec<PathBuf> {
+    let ssh_dir = dirs::home_dir()
+        .map(|home| home.join(".ssh"))
+        .unwrap_or_default();
+    let pattern = match pattern.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        None if Path::new(pattern).is_absolute() => PathBuf::from(pattern),
+        None => ssh_dir.join(pattern),
+    };
+
+    let file_pattern = pattern
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if !file_pattern.contains(['*', '?']) {
+        return vec![pattern];
+    }
+
+    let Some(dir) = pattern.parent() else {
+        return Vec::new();
+    };
+    let mut matches: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| wildcard_match(&file_pattern, &entry.file_name().to_string_lossy()))
+        .map(|entry| entry.path())
+        .collect();
+    matches.sort();
+    matches
+}
+
+/// Match `text` against an ssh_config pattern with `*` and `?` wildcards
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+# Production bastion
+Host bastion
+    HostName bastion.example.com
+    User jump
+
+Host web-* !web-legacy
+    HostName %h.internal.example.com
+    Port 2222
+    IdentityFile ~/.ssh/web_ed25519
+    ProxyJump bastion
+
+Host db
+    HostName=10.0.0.5
+    ProxyCommand ssh -W %h:%p gateway
+
+Host *
+    User deploy
+    Port 22
+    IdentityFile ~/.ssh/fallback
+"#;
+
+    fn defaults() -> SshConnectionConfig {
+        SshConnectionConfig {
+            auth_methods: vec![SshAuth::Agent],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("web-*", "web-01"));
+        assert!(wildcard_match("db?", "db1"));
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("*.example.com", "a.b.example.com"));
+        assert!(!wildcard_match("web-*", "db-01"));
+        assert!(!wildcard_match("db?", "db12"));
+    }
+
+    #[test]
+    fn test_first_value_wins_and_identity_files_accumulate() {
+        let config = SshConfig::parse(CONFIG);
+
+        let web = config.resolve("web-01");
+        assert_eq!(web.hostname.as_deref(), Some("web-01.internal.example.com"));
+        assert_eq!(web.port, Some(2222));
+        assert_eq!(web.user.as_deref(), Some("deploy"));
+        assert_eq!(web.proxy_jump.as_deref(), Some("bastion"));
+        assert_eq!(
+            web.identity_files,
+            [
+                PathBuf::from("~/.ssh/web_ed25519"),
+                PathBuf::from("~/.ssh/fallback")
+            ]
+        );
+
+        let legacy = config.resolve("web-legacy");
+        assert_eq!(legacy.hostname, None);
+        assert_eq!(legacy.port, Some(22));
+
+        let db = config.resolve("db");
+        assert_eq!(db.hostname.as_deref(), Some("10.0.0.5"));
+        assert_eq!(db.proxy_command.as_deref(), Some("ssh -W %h:%p gateway"));
+    }
+
+    #[test]
+    fn test_inventory_variables_override_ssh_config() {
+        let config = SshConfig::parse(CONFIG);
+        let vars = HostConnectionVars {
+            port: Some(2200),
+            user: Some("ops".to_string()),
+            private_key_file: Some("/keys/web.pem".to_string()),
+            ..Default::default()
+        };
+
+        let resolved = resolve_connection("web-01", &vars, &config, &defaults());
+        assert_eq!(resolved.host_spec, "ops@web-01.internal.example.com:2200");
+        assert_eq!(resolved.config.jump_hosts, ["jump@bastion.example.com:22"]);
+        assert!(matches!(
+            &resolved.config.auth_methods[0],
+            SshAuth::KeyFile { path, .. } if path == Path::new("/keys/web.pem")
+        ));
+        assert!(matches!(
+            resolved.config.auth_methods.last(),
+            Some(SshAuth::Agent)
+        ));
+
+        // An explicit user@host:port beats everything
+        let resolved = resolve_connection("root@web-01:2022", &vars, &config, &defaults());
+        assert_eq!(resolved.host_spec, "root@web-01.internal.example.com:2022");
+    }
+
+    #[test]
+    fn test_ansible_host_is_used_for_lookup() {
+        let config = SshConfig::parse(CONFIG);
+        let vars = HostConnectionVars {
+            host: Some("db".to_string()),
+            ..Default::default()
+        };
+
+        let resolved = resolve_connection("database-primary", &vars, &config, &defaults());
+        assert_eq!(resolved.host_spec, "deploy@10.0.0.5:22");
+        assert_eq!(
+            resolved.config.proxy_command.as_deref(),
+            Some("ssh -W %h:%p gateway")
+        );
+    }
+
+    #[test]
+    fn test_jump_hosts_do_not_take_the_target_port_or_user() {
+        let config =
+            SshConfig::parse("Host inner\n    HostName 10.1.0.2\n    User relay\n    Port 2022\n");
+        let vars = HostConnectionVars {
+            port: Some(2200),
+            user: Some("app".to_string()),
+            ssh_common_args: Some("-J outer,inner".to_string()),
+            ..Default::default()
+        };
+        let defaults = SshConnectionConfig {
+            username: Some("ops".to_string()),
+            ..defaults()
+        };
+
+        let resolved = resolve_connection("web-01", &vars, &config, &defaults);
+        assert_eq!(resolved.host_spec, "app@web-01:2200");
+        assert_eq!(
+            resolved.config.jump_hosts,
+            ["ops@outer:22", "relay@10.1.0.2:2022"]
+        );
+    }
+
+    #[test]
+    fn test_proxy_args() {
+        assert_eq!(
+            proxy_args("-o StrictHostKeyChecking=no -J bastion,ops@inner:2222"),
+            (Some("bastion,ops@inner:2222".to_string()), None)
+        );
+        assert_eq!(
+            proxy_args("-oProxyJump=bastion -o ProxyJump=ignored"),
+            (Some("bastion".to_string()), None)
+        );
+        assert_eq!(
+            proxy_args(r#"-o ProxyCommand="ssh -W %h:%p -q gateway""#),
+            (None, Some("ssh -W %h:%p -q gateway".to_string()))
+        );
+        assert_eq!(
+            proxy_args(r#"-o "ProxyCommand nc -X 5 -x socks:1080 %h %p""#),
+            (None, Some("nc -X 5 -x socks:1080 %h %p".to_string()))
+        );
+        assert_eq!(proxy_args("-C -o ServerAliveInterval=30"), (None, None));
+        assert_eq!(proxy_args("-o ProxyCommand='unterminated"), (None, None));
+    }
+
+    #[test]
+    fn test_common_args_proxy_replaces_ssh_config() {
+        let config = SshConfig::parse(CONFIG);
+
+        // The inventory's ProxyCommand wins over the ProxyJump of web-*
+        let vars = HostConnectionVars {
+            ssh_common_args: Some("-o ProxyCommand='ssh -W %h:%p gw'".to_string()),
+            ..Default::default()
+        };
+        let resolved = resolve_connection("web-01", &vars, &config, &defaults());
+        assert!(resolved.config.jump_hosts.is_empty());
+        assert_eq!(
+            resolved.config.proxy_command.as_deref(),
+            Some("ssh -W %h:%p gw")
+        );
+
+        // Hops are looked up in ssh_config
+        let vars = HostConnectionVars {
+            ssh_common_args: Some("-o ProxyJump=bastion".to_string()),
+            ..Default::default()
+        };
+        let resolved = resolve_connection("db", &vars, &config, &defaults());
+        assert_eq!(resolved.config.jump_hosts, ["jump@bastion.example.com:22"]);
+        assert_eq!(resolved.config.proxy_command, None);
+
+        // Other options leave ssh_config's proxy in place
+        let vars = HostConnectionVars {
+            ssh_common_args: Some("-o ServerAliveInterval=30".to_string()),
+            ..Default::default()
+        };
+        let resolved = resolve_connection("web-01", &vars, &config, &defaults());
+        assert_eq!(resolved.config.jump_hosts, ["jump@bastion.example.com:22"]);
+    }
+}
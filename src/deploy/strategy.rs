@@ -0,0 +1,292 @@
+//! Controller side of the execution strategies.
+//!
+//! Runners report the tasks they reach and wait for the controller to
+//! release them (see [`crate::runtime::strategy`]). The [`Coordinator`] of an
+//! execution answers them:
+//!
+//! - Under the linear strategy its [`TaskBarrier`] keeps the hosts in step:
+//!   it releases the task at a position once every host whose runner is
+//!   running has reached that position or a later one. Hosts join when their
+//!   runner starts, so with fewer forks than hosts, the hosts running at the
+//!   same time are kept in step. A host whose runner exits leaves and holds
+//!   nobody back.
+//! - A task with a `throttle` is released to that many hosts at a time; a
+//!   host gives its slot back once it completes the task.
+//! - Once a host fails a task of a play with `any_errors_fatal`, every host
+//!   is stopped at the next task it reaches, and hosts that did not start
+//!   are left out.
+
+use crate::execution::ExecutionStrategy;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+
+/// Coordinates the runners of one execution at the tasks they reach
+#[derive(Debug, Default)]
+pub struct Coordinator {
+    /// Keep the hosts at the same task
+    linear: bool,
+    barrier: TaskBarrier,
+    state: Mutex<CoordinatorState>,
+}
+
+#[derive(Debug, Default)]
+struct CoordinatorState {
+    /// Slots of each throttled task, by position
+    throttles: HashMap<usize, Arc<Semaphore>>,
+    /// The slot each host holds, with the id of the task it holds it for
+    slots: HashMap<String, (String, OwnedSemaphorePermit)>,
+    /// Why every host stops, once one failed a play with `any_errors_fatal`
+    abort: Option<String>,
+    /// Hosts stopped part way because of that
+    aborted: HashSet<String>,
+}
+
+impl Coordinator {
+    pub fn new(strategy: &ExecutionStrategy) -> Self {
+        Self {
+            linear: !matches!(strategy, ExecutionStrategy::Free),
+            ..Self::default()
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, CoordinatorState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The runner of `host` is starting
+    pub fn join(&self, host: &str) {
+        if self.linear {
+            self.barrier.join(host);
+        }
+    }
+
+    /// Wait until `host` may start the task `task_id` at `position`, which
+    /// at most `throttle` hosts may run at the same time. Returns why the
+    /// host has to stop there instead.
+    pub async fn reach(
+        &self,
+        host: &str,
+        task_id: &str,
+        position: usize,
+        throttle: Option<u32>,
+    ) -> Result<(), String> {
+        // Whatever slot the host held, it is done with
+        self.state().slots.remove(host);
+        if self.linear {
+            self.barrier.arrive(host, position).await;
+        }
+        self.check_abort(host)?;
+
+        let Some(slots) = throttle.filter(|slots| *slots > 0) else {
+            return Ok(());
+        };
+        let semaphore = Arc::clone(
+            self.state()
+                .throttles
+                .entry(position)
+                .or_insert_with(|| Arc::new(Semaphore::new(slots as usize))),
+        );
+        // The semaphores are never closed
+        let Ok(permit) = semaphore.acquire_owned().await else {
+            return Ok(());
+        };
+        self.check_abort(host)?;
+        self.state()
+            .slots
+            .insert(host.to_string(), (task_id.to_string(), permit));
+        Ok(())
+    }
+
+    fn check_abort(&self, host: &str) -> Result<(), String> {
+        let mut state = self.state();
+        match state.abort.clone() {
+            Some(reason) => {
+                state.aborted.insert(host.to_string());
+                Err(reason)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// `host` completed the task `task_id`, giving back the slot it held
+    /// for it
+    pub fn complete(&self, host: &str, task_id: &str) {
+        let mut state = self.state();
+        if state
+            .slots
+            .get(host)
+            .is_some_and(|(held_for, _)| held_for == task_id)
+        {
+            state.slots.remove(host);
+        }
+    }
+
+    /// `host` failed the task `task_id` of `play_id`, a play with
+    /// `any_errors_fatal`: stop every host
+    pub fn fail_fatally(&self, host: &str, task_id: &str, play_id: &str) {
+        self.state().abort.get_or_insert_with(|| {
+            format!("{host} failed task {task_id} of play {play_id}, which has any_errors_fatal")
+        });
+    }
+
+    /// Why every host stops, once one failed a play with `any_errors_fatal`
+    pub fn abort_reason(&self) -> Option<String> {
+        self.state().abort.clone()
+    }
+
+    /// Why `host` was stopped part way, if it was
+    pub fn aborted(&self, host: &str) -> Option<String> {
+        let state = self.state();
+        state
+            .aborted
+            .contains(host)
+            .then(|| state.abort.clone())
+            .flatten()
+    }
+
+    /// The runner of `host` executes no further tasks
+    pub fn leave(&self, host: &str) {
+        self.state().slots.remove(host);
+        if self.linear {
+            self.barrier.leave(host);
+        }
+    }
+}
+
+/// Where a running host is
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Progress {
+    /// Its runner has not reached any task yet
+    Starting,
+    /// Its runner waits at or went past this position
+    Reached(usize),
+}
+
+/// Keeps the running hosts at the same task
+#[derive(Debug, Default)]
+pub struct TaskBarrier {
+    hosts: watch::Sender<HashMap<String, Progress>>,
+}
+
+impl TaskBarrier {
+    /// The runner of `host` is starting; the other hosts wait for it to
+    /// reach their task
+    pub fn join(&self, host: &str) {
+        self.hosts.send_modify(|hosts| {
+            hosts.insert(host.to_string(), Progress::Starting);
+        });
+    }
+
+    /// Record that `host` reached `position` and wait until every other
+    /// host still running has reached it too
+    pub async fn arrive(&self, host: &str, position: usize) {
+        self.hosts.send_modify(|hosts| {
+            if let Some(progress) = hosts.get_mut(host) {
+                *progress = Progress::Reached(position);
+            }
+        });
+        let mut hosts = self.hosts.subscribe();
+        // The sender lives as long as the barrier, so waiting cannot fail
+        let _ = hosts
+            .wait_for(|hosts| {
+                hosts.values().all(|progress| match progress {
+                    Progress::Starting => false,
+                    Progress::Reached(reached) => *reached >= position,
+                })
+            })
+            .await;
+    }
+
+    /// The runner of `host` executes no further tasks
+    pub fn leave(&self, host: &str) {
+        self.hosts.send_modify(|hosts| {
+            hosts.remove(host);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn released(arrival: tokio::task::JoinHandle<()>) {
+        tokio::time::timeout(Duration::from_secs(1), arrival)
+            .await
+            .expect("host was not released")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_hosts_are_released_together() {
+        let barrier = Arc::new(TaskBarrier::default());
+        for host in ["web1", "web2", "db1"] {
+            barrier.join(host);
+        }
+        let arrive = |host: &'static str, position| {
+            let barrier = Arc::clone(&barrier);
+            tokio::spawn(async move { barrier.arrive(host, position).await })
+        };
+
+        let web1 = arrive("web1", 0);
+        let web2 = arrive("web2", 2);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!web1.is_finished());
+
+        // A host that went past a position holds nobody back there
+        let db1 = arrive("db1", 1);
+        released(web1).await;
+        let web1 = arrive("web1", 3);
+        released(db1).await;
+        assert!(!web2.is_finished());
+
+        barrier.leave("db1");
+        released(web2).await;
+        barrier.leave("web2");
+        released(web1).await;
+    }
+
+    #[tokio::test]
+    async fn test_throttled_task_runs_on_that_many_hosts_at_a_time() {
+        let coordinator = Arc::new(Coordinator::new(&ExecutionStrategy::Free));
+        let reach = |host: &'static str| {
+            let coordinator = Arc::clone(&coordinator);
+            tokio::spawn(async move {
+                coordinator
+                    .reach(host, "restart", 3, Some(2))
+                    .await
+                    .unwrap()
+            })
+        };
+
+        released(reach("web1")).await;
+        released(reach("web2")).await;
+        let web3 = reach("web3");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!web3.is_finished());
+
+        // Completing another task gives back no slot
+        coordinator.complete("web1", "deploy");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!web3.is_finished());
+        coordinator.complete("web1", "restart");
+        released(web3).await;
+    }
+
+    #[tokio::test]
+    async fn test_fatal_failure_stops_every_host() {
+        let coordinator = Coordinator::new(&ExecutionStrategy::Free);
+        assert_eq!(coordinator.reach("web1", "deploy", 0, None).await, Ok(()));
+        coordinator.fail_fatally("web2", "deploy", "site");
+
+        let reason = coordinator
+            .reach("web1", "restart", 1, None)
+            .await
+            .unwrap_err();
+        assert!(reason.contains("web2 failed task deploy"), "{reason}");
+        assert_eq!(coordinator.abort_reason(), Some(reason.clone()));
+        assert_eq!(coordinator.aborted("web1"), Some(reason));
+        assert_eq!(coordinator.aborted("web2"), None);
+    }
+}
@@ -0,0 +1,222 @@
+//! OpenTelemetry traces of deployments.
+//!
+//! A [`Tracer`] holds the trace of one deployment: a span for the
+//! deployment, with a span for each compilation and for the deployment and
+//! execution on each host under it. Runners record the spans of their plays
+//! and tasks under the execution span of their host (see
+//! [`crate::runtime::telemetry`]) and ship them back with their result, which
+//! the controller adds to the trace. The [`OtlpExporter`] then sends the
+//! whole trace to a collector over OTLP/HTTP with JSON encoding.
+
+use crate::deploy::{DeployError, Result};
+use crate::runtime::{Span, TraceContext};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// Collector to send traces to; `/v1/traces` is appended
+pub const OTLP_ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+/// Full URL to send traces to, taking precedence over [`OTLP_ENDPOINT_ENV`]
+pub const OTLP_TRACES_ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT";
+/// Headers to send with traces, as `name=value` pairs separated by commas
+pub const OTLP_HEADERS_ENV: &str = "OTEL_EXPORTER_OTLP_HEADERS";
+pub const SERVICE_NAME_ENV: &str = "OTEL_SERVICE_NAME";
+
+/// The trace of one deployment
+#[derive(Debug)]
+pub struct Tracer {
+    root: Mutex<Span>,
+    spans: Mutex<Vec<Span>>,
+}
+
+impl Tracer {
+    /// Start the trace of the deployment `deployment_id` in a new trace
+    pub fn new(deployment_id: &str) -> Self {
+        let mut root = Span::start(
+            format!("deployment {deployment_id}"),
+            &TraceContext::new_root(),
+        )
+        .with_attribute("rustle.deployment_id", deployment_id);
+        root.parent_span_id = None;
+        Self {
+            root: Mutex::new(root),
+            spans: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Start a span under the deployment span
+    pub fn start(&self, name: impl Into<String>) -> Span {
+        let root = self.root.lock().unwrap_or_else(PoisonError::into_inner);
+        Span::start(name, &root.context())
+    }
+
+    /// Add ended spans to the trace, from the controller or shipped back by
+    /// a runner
+    pub fn record(&self, spans: impl IntoIterator<Item = Span>) {
+        self.spans
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend(spans);
+    }
+
+    /// End the deployment span and return every span of the trace, the
+    /// deployment span first
+    pub fn finish(&self) -> Vec<Span> {
+        let root = self
+            .root
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .end();
+        let mut spans = vec![root];
+        spans.extend(
+            self.spans
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .drain(..),
+        );
+        spans
+    }
+}
+
+/// Sends traces to an OpenTelemetry collector over OTLP/HTTP
+#[derive(Debug, Clone)]
+pub struct OtlpExporter {
+    client: reqwest::Client,
+    url: String,
+    headers: Vec<(String, String)>,
+    service_name: String,
+}
+
+impl OtlpExporter {
+    /// Send traces to the collector at `endpoint`, such as
+    /// `http://localhost:4318`
+    pub fn new(endpoint: &str) -> Self {
+        Self::with_url(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+    }
+
+    fn with_url(url: String) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            url,
+            headers: Vec::new(),
+            service_name: "rustle-deploy".to_string(),
+        }
+    }
+
+    /// The exporter the standard `OTEL_*` variables configure, if they name
+    /// a collector
+    pub fn from_env() -> Option<Self> {
+        let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let mut exporter = match (var(OTLP_TRACES_ENDPOINT_ENV), var(OTLP_ENDPOINT_ENV)) {
+            (Some(url), _) => Self::with_url(url),
+            (None, Some(endpoint)) => Self::new(&endpoint),
+            (None, None) => return None,
+        };
+        for pair in var(OTLP_HEADERS_ENV).iter().flat_map(|h| h.split(',')) {
+            if let Some((name, value)) = pair.split_once('=') {
+                exporter = exporter.with_header(name.trim(), value.trim());
+            }
+        }
+        if let Some(name) = var(SERVICE_NAME_ENV) {
+            exporter = exporter.with_service_name(name);
+        }
+        Some(exporter)
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_service_name(mut self, name: impl Into<String>) -> Self {
+        self.service_name = name.into();
+        self
+    }
+
+    /// Send `spans` to the collector
+    pub async fn export(&self, spans: &[Span]) -> Result<()> {
+        if spans.is_empty() {
+            return Ok(());
+        }
+        let mut request = self.client.post(&self.url).json(&self.request(spans));
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| DeployError::Network(format!("Failed to export traces: {e}")))?;
+        if !response.status().is_success() {
+            return Err(DeployError::Network(format!(
+                "Failed to export traces: collector answered {}",
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+
+    /// The OTLP `ExportTraceServiceRequest` carrying `spans`, in JSON
+    pub fn request(&self, spans: &[Span]) -> serde_json::Value {
+        let spans: Vec<_> = spans.iter().map(otlp_span).collect();
+        serde_json::json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [attribute("service.name", &self.service_name.as_str().into())],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "rustle-deploy", "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }]
+        })
+    }
+}
+
+fn otlp_span(span: &Span) -> serde_json::Value {
+    let nanos = |time: chrono::DateTime<chrono::Utc>| {
+        time.timestamp_nanos_opt().unwrap_or_default().to_string()
+    };
+    let status = match &span.error {
+        // STATUS_CODE_ERROR
+        Some(message) => serde_json::json!({ "code": 2, "message": message }),
+        // STATUS_CODE_OK
+        None => serde_json::json!({ "code": 1 }),
+    };
+    let mut otlp = serde_json::json!({
+        "traceId": span.trace_id,
+        "spanId": span.span_id,
+        "name": span.name,
+        // SPAN_KIND_INTERNAL
+        "kind": 1,
+        "startTimeUnixNano": nanos(span.start_time),
+        "endTimeUnixNano": nanos(span.end_time),
+        "attributes": span
+            .attributes
+            .iter()
+            .map(|(key, value)| attribute(key, value))
+            .collect::<Vec<_>>(),
+        "status": status,
+    });
+    if let Some(parent) = &span.parent_span_id {
+        otlp["parentSpanId"] = parent.as_str().into();
+    }
+    otlp
+}
+
+/// An OTLP `KeyValue`
+fn attribute(key: &str, value: &serde_json::Value) -> serde_json::Value {
+    let value = match value {
+        serde_json::Value::Bool(b) => serde_json::json!({ "boolValue": b }),
+        // 64-bit integers are strings in OTLP/JSON
+        serde_json::Value::Number(n) if n.is_i64() || n.is_u64() => {
+            serde_json::json!({ "intValue": n.to_string() })
+        }
+        serde_json::Value::Number(n) => serde_json::json!({ "doubleValue": n }),
+        serde_json::Value::String(s) => serde_json::json!({ "stringValue": s }),
+        other => serde_json::json!({ "stringValue": other.to_string() }),
+    };
+    serde_json::json!({ "key": key, "value": value })
+}
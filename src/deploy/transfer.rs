@@ -0,0 +1,736 @@
+//! Resumable, delta-encoded binary transfers.
+//!
+//! Binaries are split into content-defined chunks. When a host still has the
+//! binary it was last given, only the chunks that binary lacks are sent, and
+//! the new binary is assembled on the host from the old one plus the sent
+//! data. Sent data travels in fixed-size segments that the [`TransferCache`]
+//! records as they complete, so an interrupted transfer resumes from the last
+//! complete segment instead of starting over.
+//!
+//! [`TransferOptions`] can compress each segment with zstd before it is sent,
+//! when the host can decompress it, and pace segments through the
+//! [`RateLimiter`]s of the deployment's bandwidth limits.
+
+use crate::deploy::bandwidth::RateLimiter;
+use crate::deploy::connection::{ConnectionPlugin, FilePart};
+use crate::deploy::{DeployError, Result};
+use crate::runtime::FaultInjector;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+const MIN_CHUNK_SIZE: usize = 2 * 1024;
+const MAX_CHUNK_SIZE: usize = 64 * 1024;
+/// Cut when the low 13 bits of the rolling hash are zero, for ~8KB chunks
+const CHUNK_MASK: u64 = (1 << 13) - 1;
+
+/// Size of the pieces sent data is uploaded in; the unit of resumption
+pub const SEGMENT_SIZE: usize = 2 * 1024 * 1024;
+
+/// Deltas with more operations than this are sent in full instead, to keep
+/// the assembly step cheap
+const MAX_DELTA_OPS: usize = 1024;
+
+/// Random values for the gear rolling hash, generated at compile time
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x5275_7374_6c65_4344;
+    let mut i = 0;
+    while i < 256 {
+        // splitmix64
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Location and content hash of one content-defined chunk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkSignature {
+    pub offset: u64,
+    pub len: u64,
+    pub hash: String,
+}
+
+/// Split `data` into content-defined chunks. Boundaries depend only on
+/// nearby bytes, so an insertion shifts at most a chunk or two.
+pub fn chunk_signatures(data: &[u8]) -> Vec<ChunkSignature> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let len = next_cut(&data[start..]);
+        let hash = Sha256::digest(&data[start..start + len]);
+        chunks.push(ChunkSignature {
+            offset: start as u64,
+            len: len as u64,
+            hash: hex_prefix(&hash),
+        });
+        start += len;
+    }
+    chunks
+}
+
+fn next_cut(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK_SIZE {
+        return data.len();
+    }
+    let end = data.len().min(MAX_CHUNK_SIZE);
+    let mut hash = 0u64;
+    for (i, byte) in data.iter().enumerate().take(end).skip(MIN_CHUNK_SIZE) {
+        hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+        if hash & CHUNK_MASK == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// First 128 bits of a digest as hex; plenty to identify chunks of one binary
+fn hex_prefix(digest: &[u8]) -> String {
+    digest[..16].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// One step in rebuilding a binary on the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum DeltaOp {
+    /// Bytes taken from the binary already on the host
+    Copy { offset: u64, len: u64 },
+    /// Bytes sent with this transfer, located in the literal stream
+    Literal { offset: u64, len: u64 },
+}
+
+/// How to build a new binary from a base binary and a stream of sent bytes
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeltaPlan {
+    pub ops: Vec<DeltaOp>,
+}
+
+impl DeltaPlan {
+    /// Send every byte
+    pub fn full(len: usize) -> Self {
+        let mut plan = Self::default();
+        plan.push_literal(len as u64);
+        plan
+    }
+
+    /// Reuse every chunk of `data` that also appears in the base binary
+    pub fn compute(base: &[ChunkSignature], data: &[u8]) -> Self {
+        let known: HashMap<&str, &ChunkSignature> = base
+            .iter()
+            .map(|chunk| (chunk.hash.as_str(), chunk))
+            .collect();
+
+        let mut plan = Self::default();
+        for chunk in chunk_signatures(data) {
+            match known.get(chunk.hash.as_str()) {
+                Some(existing) if existing.len == chunk.len => {
+                    plan.push_copy(existing.offset, existing.len)
+                }
+                _ => plan.push_literal(chunk.len),
+            }
+        }
+        plan
+    }
+
+    fn push_copy(&mut self, offset: u64, len: u64) {
+        if let Some(DeltaOp::Copy {
+            offset: last_offset,
+            len: last_len,
+        }) = self.ops.last_mut()
+        {
+            if *last_offset + *last_len == offset {
+                *last_len += len;
+                return;
+            }
+        }
+        self.ops.push(DeltaOp::Copy { offset, len });
+    }
+
+    fn push_literal(&mut self, len: u64) {
+        if len == 0 {
+            return;
+        }
+        if let Some(DeltaOp::Literal { len: last_len, .. }) = self.ops.last_mut() {
+            *last_len += len;
+            return;
+        }
+        let offset = self.literal_len();
+        self.ops.push(DeltaOp::Literal { offset, len });
+    }
+
+    /// Number of bytes that have to be sent
+    pub fn literal_len(&self) -> u64 {
+        self.ops
+            .iter()
+            .map(|op| match op {
+                DeltaOp::Literal { len, .. } => *len,
+                DeltaOp::Copy { .. } => 0,
+            })
+            .sum()
+    }
+
+    /// Extract the bytes to send from the new binary
+    pub fn literal(&self, data: &[u8]) -> Vec<u8> {
+        let mut literal = Vec::with_capacity(self.literal_len() as usize);
+        let mut position = 0usize;
+        for op in &self.ops {
+            match *op {
+                DeltaOp::Copy { len, .. } => position += len as usize,
+                DeltaOp::Literal { len, .. } => {
+                    literal.extend_from_slice(&data[position..position + len as usize]);
+                    position += len as usize;
+                }
+            }
+        }
+        literal
+    }
+
+    /// Rebuild the new binary; what the host does with [`FilePart`]s
+    pub fn apply(&self, base: &[u8], literal: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        for op in &self.ops {
+            let (source, offset, len) = match *op {
+                DeltaOp::Copy { offset, len } => (base, offset, len),
+                DeltaOp::Literal { offset, len } => (literal, offset, len),
+            };
+            output.extend_from_slice(&source[offset as usize..(offset + len) as usize]);
+        }
+        output
+    }
+
+    /// File ranges to concatenate on the host, with the literal stream split
+    /// across segment files
+    fn file_parts(&self, base_path: &str, segment_path: impl Fn(usize) -> String) -> Vec<FilePart> {
+        let mut parts = Vec::new();
+        for op in &self.ops {
+            match *op {
+                DeltaOp::Copy { offset, len } => parts.push(FilePart {
+                    path: base_path.to_string(),
+                    offset,
+                    len,
+                }),
+                DeltaOp::Literal { mut offset, len } => {
+                    let end = offset + len;
+                    while offset < end {
+                        let segment = (offset / SEGMENT_SIZE as u64) as usize;
+                        let segment_offset = offset % SEGMENT_SIZE as u64;
+                        let take = (SEGMENT_SIZE as u64 - segment_offset).min(end - offset);
+                        parts.push(FilePart {
+                            path: segment_path(segment),
+                            offset: segment_offset,
+                            len: take,
+                        });
+                        offset += take;
+                    }
+                }
+            }
+        }
+        parts
+    }
+}
+
+/// What the controller knows about the binary at one host's target path
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransferRecord {
+    pub host: String,
+    pub target_path: String,
+    /// Checksum of the binary last deployed successfully
+    #[serde(default)]
+    pub deployed: Option<String>,
+    /// Bytes sent for the last successful deployment
+    #[serde(default)]
+    pub transferred_bytes: u64,
+    #[serde(default)]
+    pub pending: Option<PendingTransfer>,
+}
+
+/// A transfer that was started but has not been assembled yet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingTransfer {
+    /// Checksum of the binary being sent
+    pub checksum: String,
+    /// Checksum of the binary on the host the delta is relative to
+    #[serde(default)]
+    pub base: Option<String>,
+    pub plan: DeltaPlan,
+    /// Segments already on the host
+    #[serde(default)]
+    pub completed_segments: Vec<usize>,
+}
+
+impl PendingTransfer {
+    fn segment_count(&self) -> usize {
+        (self.plan.literal_len() as usize).div_ceil(SEGMENT_SIZE)
+    }
+}
+
+/// Per-host transfer state and chunk signatures of deployed binaries, kept
+/// under the deployment cache directory.
+#[derive(Debug, Clone)]
+pub struct TransferCache {
+    dir: PathBuf,
+}
+
+impl TransferCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub async fn record(&self, host: &str, target_path: &str) -> Option<TransferRecord> {
+        let content = tokio::fs::read(self.record_path(host, target_path))
+            .await
+            .ok()?;
+        match serde_json::from_slice(&content) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                warn!("Ignoring unreadable transfer record for {}: {}", host, e);
+                None
+            }
+        }
+    }
+
+    pub async fn save_record(&self, record: &TransferRecord) -> Result<()> {
+        let path = self.record_path(&record.host, &record.target_path);
+        write_atomic(&path, &serde_json::to_vec_pretty(record)?).await
+    }
+
+    pub async fn signatures(&self, checksum: &str) -> Option<Vec<ChunkSignature>> {
+        let content = tokio::fs::read(self.signatures_path(checksum)).await.ok()?;
+        serde_json::from_slice(&content).ok()
+    }
+
+    pub async fn save_signatures(&self, checksum: &str, chunks: &[ChunkSignature]) -> Result<()> {
+        let path = self.signatures_path(checksum);
+        if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            return Ok(());
+        }
+        write_atomic(&path, &serde_json::to_vec(chunks)?).await
+    }
+
+    fn record_path(&self, host: &str, target_path: &str) -> PathBuf {
+        let key = sha256_hex(format!("{host}\0{target_path}").as_bytes());
+        self.dir.join("hosts").join(format!("{}.json", &key[..32]))
+    }
+
+    fn signatures_path(&self, checksum: &str) -> PathBuf {
+        self.dir.join("signatures").join(format!("{checksum}.json"))
+    }
+}
+
+/// Write via a temporary file so concurrent readers never see partial JSON
+pub(crate) async fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let temp = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4().simple()));
+    tokio::fs::write(&temp, data).await?;
+    tokio::fs::rename(&temp, path).await?;
+    Ok(())
+}
+
+/// How segments travel to the host
+#[derive(Debug, Clone, Default)]
+pub struct TransferOptions {
+    /// zstd level to compress segments with, if the host can decompress them
+    pub compression: Option<i32>,
+    /// Limiters every segment waits on before it is sent
+    pub limiters: Vec<Arc<RateLimiter>>,
+}
+
+/// Result of [`upload_binary`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferOutcome {
+    /// The host already had this exact binary
+    UpToDate,
+    /// The binary was installed; `sent` bytes went over the wire, after
+    /// compression
+    Installed {
+        sent: u64,
+        delta: bool,
+        resumed: bool,
+    },
+}
+
+/// Install `data` at `target_path`, sending only what the host is missing
+/// and resuming an earlier interrupted transfer of the same binary. The
+/// installed file is checked against `data` before it is recorded.
+pub async fn upload_binary(
+    connection: &dyn ConnectionPlugin,
+    cache: Option<&TransferCache>,
+    faults: &FaultInjector,
+    options: &TransferOptions,
+    data: &[u8],
+    target_path: &str,
+    mode: u32,
+) -> Result<TransferOutcome> {
+    let reused = match cache {
+        Some(cache) => cache
+            .record(connection.host(), target_path)
+            .await
+            .is_some_and(|record| record.deployed.is_some() || record.pending.is_some()),
+        None => false,
+    };
+
+    let attempt = |reuse| {
+        transfer(
+            connection,
+            cache,
+            faults,
+            options,
+            data,
+            target_path,
+            mode,
+            reuse,
+        )
+    };
+    match attempt(true).await {
+        Err(e @ DeployError::VerificationFailed { .. }) if reused => {
+            // A resumed or delta transfer relies on files left on the host;
+            // if those were changed behind our back, start from scratch
+            warn!(
+                "Transfer to {} did not verify ({}), retrying in full",
+                connection.host(),
+                e
+            );
+            attempt(false).await
+        }
+        result => result,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn transfer(
+    connection: &dyn ConnectionPlugin,
+    cache: Option<&TransferCache>,
+    faults: &FaultInjector,
+    options: &TransferOptions,
+    data: &[u8],
+    target_path: &str,
+    mode: u32,
+    reuse: bool,
+) -> Result<TransferOutcome> {
+    let host = connection.host().to_string();
+    let checksum = sha256_hex(data);
+
+    let mut record = match cache {
+        Some(cache) if reuse => cache.record(&host, target_path).await,
+        _ => None,
+    }
+    .unwrap_or_else(|| TransferRecord {
+        host: host.clone(),
+        target_path: target_path.to_string(),
+        ..Default::default()
+    });
+
+    let remote_checksum = connection.sha256(target_path).await?;
+    if remote_checksum.as_deref() == Some(checksum.as_str()) {
+        debug!("{} already has binary {}", host, checksum);
+        if let Some(stale) = record.pending.take() {
+            remove_segments(connection, target_path, &stale).await;
+        }
+        record.deployed = Some(checksum);
+        save_record(cache, &record).await;
+        return Ok(TransferOutcome::UpToDate);
+    }
+
+    // The recorded binary can only serve as a base if it is still in place
+    let base = match (cache, record.deployed.as_deref()) {
+        (Some(cache), Some(deployed)) if remote_checksum.as_deref() == Some(deployed) => cache
+            .signatures(deployed)
+            .await
+            .map(|chunks| (deployed.to_string(), chunks)),
+        _ => None,
+    };
+    let base_checksum = base.as_ref().map(|(checksum, _)| checksum.clone());
+
+    let mut pending = match record.pending.take() {
+        // A full transfer does not depend on what is on the host
+        Some(pending)
+            if pending.checksum == checksum
+                && (pending.base.is_none() || pending.base == base_checksum) =>
+        {
+            info!(
+                "Resuming transfer to {}: {}/{} segments already sent",
+                host,
+                pending.completed_segments.len(),
+                pending.segment_count()
+            );
+            pending
+        }
+        stale => {
+            if let Some(stale) = stale {
+                remove_segments(connection, target_path, &stale).await;
+            }
+            let plan = match base {
+                Some((_, ref chunks)) => {
+                    let plan = DeltaPlan::compute(chunks, data);
+                    if plan.ops.len() <= MAX_DELTA_OPS
+                        && plan.literal_len() < data.len() as u64 / 10 * 9
+                    {
+                        plan
+                    } else {
+                        DeltaPlan::full(data.len())
+                    }
+                }
+                None => DeltaPlan::full(data.len()),
+            };
+            PendingTransfer {
+                checksum: checksum.clone(),
+                base: base_checksum
+                    .filter(|_| plan.ops.iter().any(|op| matches!(op, DeltaOp::Copy { .. }))),
+                plan,
+                completed_segments: Vec::new(),
+            }
+        }
+    };
+    let resumed = !pending.completed_segments.is_empty();
+    let delta = pending.base.is_some();
+
+    let literal = pending.plan.literal(data);
+    let corrupted = faults.corrupt_upload(&host, &literal);
+    let literal = corrupted.as_deref().unwrap_or(&literal);
+
+    let compression = match options.compression {
+        Some(level) if pending.completed_segments.len() < pending.segment_count() => {
+            let supported = connection.supports_zstd().await;
+            if !supported {
+                debug!("{} cannot decompress zstd, sending uncompressed", host);
+            }
+            supported.then_some(level)
+        }
+        _ => None,
+    };
+
+    let mut sent = 0u64;
+    for (index, segment) in literal.chunks(SEGMENT_SIZE).enumerate() {
+        if pending.completed_segments.contains(&index) {
+            continue;
+        }
+        sent += upload_segment(
+            connection,
+            options,
+            compression,
+            segment,
+            &segment_path(target_path, &checksum, index),
+        )
+        .await?;
+        pending.completed_segments.push(index);
+        record.pending = Some(pending.clone());
+        save_record(cache, &record).await;
+    }
+
+    faults
+        .crash_point(&host)
+        .map_err(|reason| DeployError::DeploymentFailed {
+            host: host.clone(),
+            reason,
+        })?;
+
+    // Assemble next to the final location so the rename below is atomic
+    let temp_path = format!(
+        "{}.rustle-{}.tmp",
+        target_path,
+        uuid::Uuid::new_v4().simple()
+    );
+    let parts = pending.plan.file_parts(target_path, |index| {
+        segment_path(target_path, &checksum, index)
+    });
+    let installed = async {
+        connection.assemble(&temp_path, &parts, mode).await?;
+        connection.rename(&temp_path, target_path).await
+    }
+    .await;
+    remove_segments(connection, target_path, &pending).await;
+    record.pending = None;
+
+    if let Err(e) = installed {
+        let _ = connection.remove(&temp_path).await;
+        save_record(cache, &record).await;
+        return Err(e);
+    }
+
+    let actual = connection.sha256(target_path).await?;
+    if actual.as_deref() != Some(checksum.as_str()) {
+        record.deployed = None;
+        save_record(cache, &record).await;
+        return Err(DeployError::VerificationFailed {
+            host,
+            expected: checksum,
+            actual: actual.unwrap_or_else(|| "missing".to_string()),
+        });
+    }
+
+    if let Some(cache) = cache {
+        if let Err(e) = cache
+            .save_signatures(&checksum, &chunk_signatures(data))
+            .await
+        {
+            warn!("Failed to cache chunk signatures for {}: {}", checksum, e);
+        }
+    }
+    record.deployed = Some(checksum);
+    record.transferred_bytes = sent;
+    save_record(cache, &record).await;
+
+    if delta {
+        info!(
+            "Sent {} of {} bytes to {} as a delta",
+            sent,
+            data.len(),
+            host
+        );
+    }
+    Ok(TransferOutcome::Installed {
+        sent,
+        delta,
+        resumed,
+    })
+}
+
+/// Upload one segment to `path`, compressed if that makes it smaller.
+/// Returns the number of bytes sent.
+async fn upload_segment(
+    connection: &dyn ConnectionPlugin,
+    options: &TransferOptions,
+    compression: Option<i32>,
+    segment: &[u8],
+    path: &str,
+) -> Result<u64> {
+    let compressed = compression
+        .map(|level| zstd::bulk::compress(segment, level))
+        .transpose()?
+        .filter(|compressed| compressed.len() < segment.len());
+    let payload = compressed.as_deref().unwrap_or(segment);
+
+    for limiter in &options.limiters {
+        limiter.acquire(payload.len() as u64).await;
+    }
+
+    if compressed.is_some() {
+        let compressed_path = format!("{path}.zst");
+        connection.upload(payload, &compressed_path, 0o600).await?;
+        let decompressed = connection.decompress_zstd(&compressed_path, path).await;
+        if let Err(e) = connection.remove(&compressed_path).await {
+            debug!("Failed to remove {}: {}", compressed_path, e);
+        }
+        decompressed?;
+    } else {
+        connection.upload(segment, path, 0o600).await?;
+    }
+    Ok(payload.len() as u64)
+}
+
+fn segment_path(target_path: &str, checksum: &str, index: usize) -> String {
+    format!("{target_path}.rustle-{}.{index:04}.part", &checksum[..16])
+}
+
+async fn remove_segments(
+    connection: &dyn ConnectionPlugin,
+    target_path: &str,
+    pending: &PendingTransfer,
+) {
+    for index in 0..pending.segment_count() {
+        let path = segment_path(target_path, &pending.checksum, index);
+        if let Err(e) = connection.remove(&path).await {
+            debug!("Failed to remove {}: {}", path, e);
+        }
+    }
+}
+
+/// Transfer state is an optimisation; failing to record it is not fatal
+async fn save_record(cache: Option<&TransferCache>, record: &TransferRecord) {
+    if let Some(cache) = cache {
+        if let Err(e) = cache.save_record(record).await {
+            warn!("Failed to save transfer record for {}: {}", record.host, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pseudo_random(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 33) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chunks_cover_data() {
+        let data = pseudo_random(300_000, 1);
+        let chunks = chunk_signatures(&data);
+
+        let mut offset = 0;
+        for chunk in &chunks {
+            assert_eq!(chunk.offset, offset);
+            assert!(chunk.len as usize <= MAX_CHUNK_SIZE);
+            offset += chunk.len;
+        }
+        assert_eq!(offset, data.len() as u64);
+        assert!(chunks.len() > 10, "expected content-defined cuts");
+        assert!(chunk_signatures(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_delta_sends_only_changed_region() {
+        let base = pseudo_random(400_000, 2);
+        let mut data = base.clone();
+        // Insert a few bytes in the middle; later chunks only shift
+        data.splice(200_000..200_000, b"inserted".iter().copied());
+        data[350_000] ^= 0xff;
+
+        let plan = DeltaPlan::compute(&chunk_signatures(&base), &data);
+        let literal = plan.literal(&data);
+
+        assert_eq!(plan.apply(&base, &literal), data);
+        assert!(
+            literal.len() < 4 * MAX_CHUNK_SIZE,
+            "sent {} bytes",
+            literal.len()
+        );
+    }
+
+    #[test]
+    fn test_full_plan_and_file_parts() {
+        let len = SEGMENT_SIZE * 2 + 10;
+        let plan = DeltaPlan::full(len);
+        assert_eq!(plan.literal_len(), len as u64);
+        assert_eq!(DeltaPlan::full(0).ops, []);
+
+        let parts = plan.file_parts("/opt/runner", |index| format!("seg{index}"));
+        assert_eq!(
+            parts
+                .iter()
+                .map(|part| (part.path.as_str(), part.offset, part.len))
+                .collect::<Vec<_>>(),
+            [
+                ("seg0", 0, SEGMENT_SIZE as u64),
+                ("seg1", 0, SEGMENT_SIZE as u64),
+                ("seg2", 0, 10),
+            ]
+        );
+    }
+}
@@ -0,0 +1,457 @@
+//! Checks run once a deployed binary has finished: its reported result must
+//! parse, agree with itself and with the plan, and any user-defined health
+//! checks must pass on the host.
+
+use crate::deploy::connection::ConnectionPlugin;
+use crate::deploy::Result;
+use crate::runtime::{decode_events, ExecutionResult, ProgressEvent};
+use crate::types::BinaryCompilation;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Where a finished runner leaves its [`ExecutionResult`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResultSource {
+    /// The last JSON document printed on stdout
+    #[default]
+    Stdout,
+    /// A JSON file on the target
+    File { path: String },
+}
+
+/// A command run on the target after execution to confirm it left the host
+/// in the expected state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthCheck {
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub expected_exit_code: i32,
+    /// Substring the command's stdout must contain
+    #[serde(default)]
+    pub expected_output: Option<String>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+impl HealthCheck {
+    pub fn new(name: &str, command: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            command: command.to_string(),
+            expected_exit_code: 0,
+            expected_output: None,
+            timeout_secs: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionVerificationConfig {
+    pub result_source: ResultSource,
+    /// Treat a run that reported no result as a failure
+    pub require_result: bool,
+    pub health_checks: Vec<HealthCheck>,
+}
+
+impl Default for ExecutionVerificationConfig {
+    fn default() -> Self {
+        Self {
+            result_source: ResultSource::Stdout,
+            require_result: true,
+            health_checks: Vec::new(),
+        }
+    }
+}
+
+/// Something about a finished run that does not add up
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Discrepancy {
+    #[error("runner exited with code {exit_code}")]
+    RunnerFailed { exit_code: i32 },
+
+    #[error("no execution result was reported")]
+    MissingResult,
+
+    #[error("execution result does not match the expected schema: {reason}")]
+    InvalidResult { reason: String },
+
+    #[error("execution result is inconsistent: {reason}")]
+    InconsistentResult { reason: String },
+
+    #[error("plan has {expected} tasks but {reported} were reported")]
+    TaskCountMismatch { expected: usize, reported: usize },
+
+    #[error("tasks missing from the result: {}", tasks.join(", "))]
+    MissingTasks { tasks: Vec<String> },
+
+    #[error("{failed} task(s) failed")]
+    TasksFailed { failed: usize },
+
+    #[error("health check '{name}' failed: {reason}")]
+    HealthCheckFailed { name: String, reason: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthCheckOutcome {
+    pub name: String,
+    pub passed: bool,
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+}
+
+/// Outcome of verifying one host's run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionVerificationReport {
+    pub host: String,
+    pub result: Option<ExecutionResult>,
+    pub discrepancies: Vec<Discrepancy>,
+    pub health_checks: Vec<HealthCheckOutcome>,
+}
+
+impl ExecutionVerificationReport {
+    pub fn is_verified(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+
+    /// One-line description of every discrepancy, for failure recaps
+    pub fn summary(&self) -> String {
+        self.discrepancies
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+pub struct ExecutionVerifier {
+    config: ExecutionVerificationConfig,
+}
+
+impl ExecutionVerifier {
+    pub fn new(config: ExecutionVerificationConfig) -> Self {
+        Self { config }
+    }
+
+    /// Verify a finished run of `compilation` on the host behind `connection`
+    pub async fn verify(
+        &self,
+        connection: &dyn ConnectionPlugin,
+        compilation: &BinaryCompilation,
+        run: &crate::types::ExecutionResult,
+    ) -> Result<ExecutionVerificationReport> {
+        let host = connection.host();
+        let mut discrepancies = Vec::new();
+
+        if run.exit_code != 0 {
+            discrepancies.push(Discrepancy::RunnerFailed {
+                exit_code: run.exit_code,
+            });
+        }
+
+        let output = match &self.config.result_source {
+            ResultSource::Stdout => Some(run.stdout.clone()),
+            ResultSource::File { path } => connection.read_to_string(path).await?,
+        };
+        let result = match output.as_deref().map(parse_result) {
+            Some(Ok(result)) => {
+                discrepancies.extend(check_result(&result, &compilation.source_tasks));
+                Some(result)
+            }
+            Some(Err(Discrepancy::MissingResult)) | None => {
+                if self.config.require_result {
+                    discrepancies.push(Discrepancy::MissingResult);
+                }
+                None
+            }
+            Some(Err(discrepancy)) => {
+                discrepancies.push(discrepancy);
+                None
+            }
+        };
+
+        let mut health_checks = Vec::new();
+        for check in &self.config.health_checks {
+            let (outcome, failure) = run_health_check(connection, check).await?;
+            if let Some(reason) = failure {
+                warn!(
+                    "Health check '{}' failed on {}: {}",
+                    check.name, host, reason
+                );
+                discrepancies.push(Discrepancy::HealthCheckFailed {
+                    name: check.name.clone(),
+                    reason,
+                });
+            }
+            health_checks.push(outcome);
+        }
+
+        debug!(
+            "Verified execution on {}: {} discrepancies",
+            host,
+            discrepancies.len()
+        );
+        Ok(ExecutionVerificationReport {
+            host: host.to_string(),
+            result,
+            discrepancies,
+            health_checks,
+        })
+    }
+}
+
+/// Parse a reported result: the summary of a streamed event stream, a whole
+/// JSON document or, for output mixed with logs, the last line that holds one
+pub fn parse_result(output: &str) -> std::result::Result<ExecutionResult, Discrepancy> {
+    let output = output.trim();
+    if output.is_empty() {
+        return Err(Discrepancy::MissingResult);
+    }
+    let streamed = decode_events(output)
+        .into_iter()
+        .rev()
+        .find_map(|event| match event {
+            ProgressEvent::ExecutionCompleted { result, .. } => Some(result),
+            _ => None,
+        });
+    if let Some(result) = streamed {
+        return Ok(result);
+    }
+    if let Ok(result) = serde_json::from_str(output) {
+        return Ok(result);
+    }
+
+    let Some(line) = output
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|line| line.starts_with('{'))
+    else {
+        return Err(Discrepancy::MissingResult);
+    };
+    serde_json::from_str(line).map_err(|e| Discrepancy::InvalidResult {
+        reason: e.to_string(),
+    })
+}
+
+/// Compare a result against itself and the tasks the binary was built from
+pub fn check_result(result: &ExecutionResult, planned_tasks: &[String]) -> Vec<Discrepancy> {
+    let mut discrepancies = Vec::new();
+    let summary = &result.summary;
+    let inconsistent = |reason: String| Discrepancy::InconsistentResult { reason };
+
+    if summary.total_tasks != result.task_results.len() {
+        discrepancies.push(inconsistent(format!(
+            "summary counts {} tasks but {} task results were reported",
+            summary.total_tasks,
+            result.task_results.len()
+        )));
+    }
+    if summary.completed_tasks + summary.failed_tasks + summary.skipped_tasks > summary.total_tasks
+    {
+        discrepancies.push(inconsistent(format!(
+            "{} completed, {} failed and {} skipped tasks exceed the total of {}",
+            summary.completed_tasks,
+            summary.failed_tasks,
+            summary.skipped_tasks,
+            summary.total_tasks
+        )));
+    }
+    let failed = result.task_results.values().filter(|t| t.failed).count();
+    if failed != summary.failed_tasks {
+        discrepancies.push(inconsistent(format!(
+            "summary counts {} failed tasks but {} task results failed",
+            summary.failed_tasks, failed
+        )));
+    }
+    if result.success == result.failed {
+        discrepancies.push(inconsistent(
+            "success and failed flags disagree".to_string(),
+        ));
+    }
+
+    if !planned_tasks.is_empty() {
+        if summary.total_tasks != planned_tasks.len() {
+            discrepancies.push(Discrepancy::TaskCountMismatch {
+                expected: planned_tasks.len(),
+                reported: summary.total_tasks,
+            });
+        }
+        let reported: BTreeSet<&str> = result
+            .task_results
+            .values()
+            .map(|t| t.task_id.as_str())
+            .collect();
+        let missing: Vec<String> = planned_tasks
+            .iter()
+            .filter(|task| !reported.contains(task.as_str()))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            discrepancies.push(Discrepancy::MissingTasks { tasks: missing });
+        }
+    }
+
+    if failed > 0 || result.failed {
+        discrepancies.push(Discrepancy::TasksFailed {
+            failed: failed.max(summary.failed_tasks),
+        });
+    }
+    discrepancies
+}
+
+async fn run_health_check(
+    connection: &dyn ConnectionPlugin,
+    check: &HealthCheck,
+) -> Result<(HealthCheckOutcome, Option<String>)> {
+    let execution = connection.execute(&check.command, None);
+    let result = match check.timeout_secs {
+        Some(secs) => match tokio::time::timeout(Duration::from_secs(secs), execution).await {
+            Ok(result) => Some(result?),
+            Err(_) => None,
+        },
+        None => Some(execution.await?),
+    };
+
+    let Some(result) = result else {
+        let outcome = HealthCheckOutcome {
+            name: check.name.clone(),
+            passed: false,
+            exit_code: None,
+            stdout: String::new(),
+        };
+        let reason = format!(
+            "timed out after {}s",
+            check.timeout_secs.unwrap_or_default()
+        );
+        return Ok((outcome, Some(reason)));
+    };
+
+    let failure = if result.exit_code != check.expected_exit_code {
+        Some(format!(
+            "exited with code {} (expected {}): {}",
+            result.exit_code,
+            check.expected_exit_code,
+            result.stderr.trim()
+        ))
+    } else {
+        check
+            .expected_output
+            .as_ref()
+            .filter(|expected| !result.stdout.contains(expected.as_str()))
+            .map(|expected| format!("output does not contain '{expected}'"))
+    };
+
+    let outcome = HealthCheckOutcome {
+        name: check.name.clone(),
+        passed: failure.is_none(),
+        exit_code: Some(result.exit_code),
+        stdout: result.stdout,
+    };
+    Ok((outcome, failure))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{ExecutionSummary, TaskResult, TaskStatus};
+    use chrono::Utc;
+    use std::collections::HashMap;
+
+    fn task(task_id: &str, failed: bool) -> TaskResult {
+        TaskResult {
+            task_id: task_id.to_string(),
+            name: task_id.to_string(),
+            status: if failed {
+                TaskStatus::Failed
+            } else {
+                TaskStatus::Success
+            },
+            changed: false,
+            failed,
+            skipped: false,
+            output: serde_json::Value::Null,
+            stdout: None,
+            stderr: None,
+            start_time: Utc::now(),
+            end_time: Utc::now(),
+            duration: Duration::ZERO,
+            error: None,
+        }
+    }
+
+    fn result(tasks: Vec<TaskResult>) -> ExecutionResult {
+        let failed_tasks = tasks.iter().filter(|t| t.failed).count();
+        ExecutionResult {
+            execution_id: "run".to_string(),
+            success: failed_tasks == 0,
+            failed: failed_tasks > 0,
+            summary: ExecutionSummary {
+                total_tasks: tasks.len(),
+                completed_tasks: tasks.len() - failed_tasks,
+                failed_tasks,
+                skipped_tasks: 0,
+                changed_tasks: 0,
+                ignored_tasks: 0,
+                rescued_tasks: 0,
+            },
+            task_results: tasks
+                .into_iter()
+                .map(|t| (t.task_id.clone(), t))
+                .collect::<HashMap<_, _>>(),
+            start_time: Utc::now(),
+            end_time: Utc::now(),
+            duration: Duration::ZERO,
+            errors: Vec::new(),
+            module_metrics: Default::default(),
+            handler_results: Vec::new(),
+            spans: Vec::new(),
+            audit: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_result_from_logs() {
+        let json = serde_json::to_string(&result(vec![task("a", false)])).unwrap();
+        let output = format!("INFO starting\n{json}\n");
+        assert_eq!(parse_result(&output).unwrap().summary.total_tasks, 1);
+
+        assert!(matches!(
+            parse_result("  \n"),
+            Err(Discrepancy::MissingResult)
+        ));
+        assert!(matches!(
+            parse_result("done\n{\"success\": true}"),
+            Err(Discrepancy::InvalidResult { .. })
+        ));
+    }
+
+    #[test]
+    fn test_check_result_against_plan() {
+        let planned = vec!["a".to_string(), "b".to_string()];
+        assert!(
+            check_result(&result(vec![task("a", false), task("b", false)]), &planned).is_empty()
+        );
+
+        let discrepancies = check_result(&result(vec![task("a", true)]), &planned);
+        assert!(discrepancies.contains(&Discrepancy::TaskCountMismatch {
+            expected: 2,
+            reported: 1
+        }));
+        assert!(discrepancies.contains(&Discrepancy::MissingTasks {
+            tasks: vec!["b".to_string()]
+        }));
+        assert!(discrepancies.contains(&Discrepancy::TasksFailed { failed: 1 }));
+
+        let mut tampered = result(vec![task("a", false), task("b", false)]);
+        tampered.summary.total_tasks = 5;
+        assert!(check_result(&tampered, &[])
+            .iter()
+            .any(|d| matches!(d, Discrepancy::InconsistentResult { .. })));
+    }
+}
@@ -0,0 +1,102 @@
+//! Running WebAssembly runners under wasmtime
+//!
+//! A `wasm32-wasi` runner is not executed directly: the host runs it with
+//! wasmtime, which only lets it see the directories preopened for it and the
+//! environment variables passed explicitly. The sandbox is the point of the
+//! target, so the runner only gets the directory it is deployed to, where
+//! its state file and staged secrets live, plus the directories configured.
+
+use crate::deploy::connection::ConnectionPlugin;
+use crate::deploy::ssh::{CommandResult, OutputChunk};
+use crate::deploy::Result;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Whether the runner at `path` is a WebAssembly module
+pub fn is_wasm_runner(path: &str) -> bool {
+    path.ends_with(".wasm")
+}
+
+/// A host directory the runner may access, and where it sees it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WasiDir {
+    pub host: String,
+    pub guest: String,
+}
+
+/// Runs WebAssembly runners on their hosts with the wasmtime CLI
+#[derive(Debug, Clone)]
+pub struct WasmtimeExecutor {
+    program: String,
+    dirs: Vec<WasiDir>,
+}
+
+impl Default for WasmtimeExecutor {
+    fn default() -> Self {
+        Self {
+            program: "wasmtime".to_string(),
+            dirs: Vec::new(),
+        }
+    }
+}
+
+impl WasmtimeExecutor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `program` rather than the `wasmtime` on the host's `PATH`
+    pub fn with_program(mut self, program: impl Into<String>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Let the runner access `host`, seen as `guest` from inside
+    pub fn with_dir(mut self, host: impl Into<String>, guest: impl Into<String>) -> Self {
+        self.dirs.push(WasiDir {
+            host: host.into(),
+            guest: guest.into(),
+        });
+        self
+    }
+
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    /// The wasmtime arguments running `module` with `args` and `env`
+    pub fn arguments(&self, module: &str, args: &[String], env: &[(&str, &str)]) -> Vec<String> {
+        let mut arguments = vec!["run".to_string()];
+        let runner_dir = match module.rsplit_once('/') {
+            Some(("", _)) => "/",
+            Some((dir, _)) => dir,
+            None => ".",
+        };
+        arguments.push("--dir".to_string());
+        arguments.push(format!("{runner_dir}::{runner_dir}"));
+        for dir in &self.dirs {
+            arguments.push("--dir".to_string());
+            arguments.push(format!("{}::{}", dir.host, dir.guest));
+        }
+        for (name, value) in env {
+            arguments.push("--env".to_string());
+            arguments.push(format!("{name}={value}"));
+        }
+        arguments.push(module.to_string());
+        arguments.extend(args.iter().cloned());
+        arguments
+    }
+
+    /// Run `module` on the host behind `connection`
+    pub async fn run(
+        &self,
+        connection: &dyn ConnectionPlugin,
+        module: &str,
+        args: &[String],
+        env: &[(&str, &str)],
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        connection
+            .execute_program(&self.program, &self.arguments(module, args, env), &[], sink)
+            .await
+    }
+}
@@ -0,0 +1,158 @@
+//! Kerberos (SPNEGO) tokens for WinRM, using the system GSSAPI library.
+//!
+//! The library is loaded at runtime so builds do not need Kerberos headers;
+//! a ticket must already be in the credential cache (e.g. from `kinit`).
+
+use libloading::{Library, Symbol};
+use std::ffi::c_void;
+use std::ptr;
+
+const GSS_S_COMPLETE: u32 = 0;
+const GSS_S_CONTINUE_NEEDED: u32 = 1;
+const GSS_C_MUTUAL_FLAG: u32 = 2;
+const GSS_C_SEQUENCE_FLAG: u32 = 8;
+
+/// 1.2.840.113554.1.2.1.4 (GSS_C_NT_HOSTBASED_SERVICE)
+const NT_HOSTBASED_SERVICE: [u8; 10] = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x12, 0x01, 0x02, 0x01, 0x04];
+/// 1.3.6.1.5.5.2 (SPNEGO), which WinRM's `Negotiate` scheme expects
+const SPNEGO_MECHANISM: [u8; 6] = [0x2b, 0x06, 0x01, 0x05, 0x05, 0x02];
+
+const LIBRARY_NAMES: &[&str] = &[
+    "libgssapi_krb5.so.2",
+    "libgssapi_krb5.so",
+    "libgssapi_krb5.dylib",
+];
+
+#[repr(C)]
+struct GssBuffer {
+    length: usize,
+    value: *mut c_void,
+}
+
+#[repr(C)]
+struct GssOid {
+    length: u32,
+    elements: *mut c_void,
+}
+
+type ImportName =
+    unsafe extern "C" fn(*mut u32, *mut GssBuffer, *mut GssOid, *mut *mut c_void) -> u32;
+type InitSecContext = unsafe extern "C" fn(
+    *mut u32,
+    *mut c_void,
+    *mut *mut c_void,
+    *mut c_void,
+    *mut GssOid,
+    u32,
+    u32,
+    *mut c_void,
+    *mut GssBuffer,
+    *mut *mut GssOid,
+    *mut GssBuffer,
+    *mut u32,
+    *mut u32,
+) -> u32;
+type ReleaseBuffer = unsafe extern "C" fn(*mut u32, *mut GssBuffer) -> u32;
+type ReleaseName = unsafe extern "C" fn(*mut u32, *mut *mut c_void) -> u32;
+type DeleteSecContext = unsafe extern "C" fn(*mut u32, *mut *mut c_void, *mut GssBuffer) -> u32;
+
+/// Produce the initial SPNEGO token for `HTTP@{hostname}` from the default
+/// credential cache.
+pub fn initial_token(hostname: &str) -> Result<Vec<u8>, String> {
+    let library = LIBRARY_NAMES
+        .iter()
+        // SAFETY: loading the system GSSAPI library runs only its initialisers
+        .find_map(|name| unsafe { Library::new(name) }.ok())
+        .ok_or_else(|| "GSSAPI library (libgssapi_krb5) not found".to_string())?;
+
+    // SAFETY: the symbol types match the GSSAPI C bindings (RFC 2744)
+    unsafe {
+        let import_name: Symbol<ImportName> = symbol(&library, b"gss_import_name\0")?;
+        let init_sec_context: Symbol<InitSecContext> = symbol(&library, b"gss_init_sec_context\0")?;
+        let release_buffer: Symbol<ReleaseBuffer> = symbol(&library, b"gss_release_buffer\0")?;
+        let release_name: Symbol<ReleaseName> = symbol(&library, b"gss_release_name\0")?;
+        let delete_sec_context: Symbol<DeleteSecContext> =
+            symbol(&library, b"gss_delete_sec_context\0")?;
+
+        let mut minor = 0u32;
+        let mut service = format!("HTTP@{hostname}").into_bytes();
+        let mut name_buffer = GssBuffer {
+            length: service.len(),
+            value: service.as_mut_ptr().cast(),
+        };
+        let mut name_type_oid = NT_HOSTBASED_SERVICE;
+        let mut name_type = GssOid {
+            length: name_type_oid.len() as u32,
+            elements: name_type_oid.as_mut_ptr().cast(),
+        };
+        let mut target_name: *mut c_void = ptr::null_mut();
+        let major = import_name(
+            &mut minor,
+            &mut name_buffer,
+            &mut name_type,
+            &mut target_name,
+        );
+        if major != GSS_S_COMPLETE {
+            return Err(format!(
+                "gss_import_name failed (major {major:#x}, minor {minor})"
+            ));
+        }
+
+        let mut mechanism_oid = SPNEGO_MECHANISM;
+        let mut mechanism = GssOid {
+            length: mechanism_oid.len() as u32,
+            elements: mechanism_oid.as_mut_ptr().cast(),
+        };
+        let mut context: *mut c_void = ptr::null_mut();
+        let mut output = GssBuffer {
+            length: 0,
+            value: ptr::null_mut(),
+        };
+        let major = init_sec_context(
+            &mut minor,
+            ptr::null_mut(),
+            &mut context,
+            target_name,
+            &mut mechanism,
+            GSS_C_MUTUAL_FLAG | GSS_C_SEQUENCE_FLAG,
+            0,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &mut output,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        );
+
+        let token = if output.value.is_null() {
+            Vec::new()
+        } else {
+            std::slice::from_raw_parts(output.value as *const u8, output.length).to_vec()
+        };
+        release_buffer(&mut minor, &mut output);
+        release_name(&mut minor, &mut target_name);
+        if !context.is_null() {
+            delete_sec_context(&mut minor, &mut context, ptr::null_mut());
+        }
+
+        if major != GSS_S_COMPLETE && major != GSS_S_CONTINUE_NEEDED {
+            return Err(format!(
+                "gss_init_sec_context for HTTP@{hostname} failed (major {major:#x}, minor {minor}); \
+                 is there a valid ticket (kinit)?"
+            ));
+        }
+        if token.is_empty() {
+            return Err("GSSAPI produced an empty token".to_string());
+        }
+        Ok(token)
+    }
+}
+
+unsafe fn symbol<'lib, T>(library: &'lib Library, name: &[u8]) -> Result<Symbol<'lib, T>, String> {
+    library.get(name).map_err(|e| {
+        format!(
+            "GSSAPI symbol {} missing: {e}",
+            String::from_utf8_lossy(&name[..name.len() - 1])
+        )
+    })
+}
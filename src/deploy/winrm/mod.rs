@@ -0,0 +1,771 @@
+//! WinRM (WS-Management) transport for Windows targets.
+//!
+//! Commands run in a Windows Remote Shell over HTTP(S); files are uploaded by
+//! streaming base64 lines to a PowerShell script's stdin. Hosts are selected
+//! for this transport with `ansible_connection: winrm`.
+
+mod kerberos;
+mod ntlm;
+mod soap;
+
+use crate::binary::platform::{HostPlatform, POWERSHELL_PROBE_SCRIPT};
+use crate::deploy::connection::{
+    check_result, parse_platform, redact_env, ConnectionPlugin, FilePart,
+};
+use crate::deploy::ssh::{CommandResult, OutputChunk, OutputStream};
+use crate::deploy::{DeployError, Result};
+use crate::types::HostConnectionVars;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use soap::EnvelopeSettings;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Mutex;
+use tracing::debug;
+
+const DEFAULT_HTTPS_PORT: u16 = 5986;
+const DEFAULT_HTTP_PORT: u16 = 5985;
+const SOAP_CONTENT_TYPE: &str = "application/soap+xml;charset=UTF-8";
+
+/// Raw bytes per stdin line when uploading; keeps each Send envelope well
+/// under the default 150KB `MaxEnvelopeSize`
+const UPLOAD_CHUNK_SIZE: usize = 48 * 1024;
+
+/// How the client authenticates to the WinRM listener
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WinRmAuth {
+    Basic,
+    Ntlm,
+    /// SPNEGO with a ticket from the local credential cache
+    Kerberos,
+}
+
+impl WinRmAuth {
+    /// Parse an `ansible_winrm_transport` value
+    pub fn from_transport(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "basic" | "plaintext" => Some(Self::Basic),
+            "ntlm" => Some(Self::Ntlm),
+            "kerberos" => Some(Self::Kerberos),
+            _ => None,
+        }
+    }
+}
+
+/// Connection settings shared by every WinRM session opened by the deployer
+#[derive(Debug, Clone)]
+pub struct WinRmConfig {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub auth: WinRmAuth,
+    /// NTLM and Kerberos are only supported over HTTPS unless the listener
+    /// allows unencrypted traffic, since message sealing is not implemented
+    pub use_https: bool,
+    /// Defaults to 5986 for HTTPS and 5985 for HTTP
+    pub port: Option<u16>,
+    pub path: String,
+    pub validate_certs: bool,
+    pub connect_timeout: Duration,
+    /// How long a single Receive waits for output before the server times it out
+    pub operation_timeout: Duration,
+    pub max_envelope_size: u32,
+}
+
+impl Default for WinRmConfig {
+    fn default() -> Self {
+        Self {
+            username: None,
+            password: None,
+            auth: WinRmAuth::Ntlm,
+            use_https: true,
+            port: None,
+            path: "/wsman".to_string(),
+            validate_certs: true,
+            connect_timeout: Duration::from_secs(30),
+            operation_timeout: Duration::from_secs(20),
+            max_envelope_size: 153_600,
+        }
+    }
+}
+
+impl WinRmConfig {
+    /// Apply a host's inventory connection variables on top of these defaults
+    pub fn for_host(&self, vars: &HostConnectionVars) -> Self {
+        let mut config = self.clone();
+        config.username = vars.user.clone().or(config.username);
+        config.password = vars.password.clone().or(config.password);
+        config.port = vars.port.or(config.port);
+
+        if let Some(ref scheme) = vars.winrm_scheme {
+            config.use_https = !scheme.eq_ignore_ascii_case("http");
+        }
+        if let Some(ref validation) = vars.winrm_server_cert_validation {
+            config.validate_certs = !validation.eq_ignore_ascii_case("ignore");
+        }
+        match vars
+            .winrm_transport
+            .as_deref()
+            .and_then(WinRmAuth::from_transport)
+        {
+            Some(auth) => config.auth = auth,
+            // Without a password the only option is an existing Kerberos ticket
+            None if config.password.is_none() => config.auth = WinRmAuth::Kerberos,
+            None => {}
+        }
+        config
+    }
+
+    pub fn endpoint(&self, hostname: &str) -> String {
+        let (scheme, default_port) = if self.use_https {
+            ("https", DEFAULT_HTTPS_PORT)
+        } else {
+            ("http", DEFAULT_HTTP_PORT)
+        };
+        let host = if hostname.contains(':') {
+            format!("[{hostname}]")
+        } else {
+            hostname.to_string()
+        };
+        format!(
+            "{scheme}://{host}:{}{}",
+            self.port.unwrap_or(default_port),
+            self.path
+        )
+    }
+}
+
+/// An authenticated WinRM endpoint for a single host.
+///
+/// Each command runs in its own remote shell. Requests are serialised because
+/// NTLM and Kerberos authenticate the underlying HTTP connection, not each request.
+pub struct WinRmConnection {
+    host: String,
+    hostname: String,
+    config: WinRmConfig,
+    client: reqwest::Client,
+    envelope: EnvelopeSettings,
+    authenticated: Mutex<bool>,
+}
+
+impl WinRmConnection {
+    /// Connect to `hostname` and authenticate by opening and closing a shell.
+    pub async fn connect(host: &str, hostname: &str, config: &WinRmConfig) -> Result<Self> {
+        let endpoint = config.endpoint(hostname);
+        let client = reqwest::Client::builder()
+            .connect_timeout(config.connect_timeout)
+            // A Receive may legitimately block for the whole operation timeout
+            .timeout(config.operation_timeout + Duration::from_secs(30))
+            .pool_max_idle_per_host(1)
+            .danger_accept_invalid_certs(!config.validate_certs)
+            .build()
+            .map_err(|e| DeployError::WinRmConnection {
+                host: host.to_string(),
+                reason: format!("Failed to build HTTP client: {e}"),
+            })?;
+
+        let connection = Self {
+            host: host.to_string(),
+            hostname: hostname.to_string(),
+            config: config.clone(),
+            client,
+            envelope: EnvelopeSettings {
+                endpoint,
+                max_envelope_size: config.max_envelope_size,
+                operation_timeout: config.operation_timeout,
+            },
+            authenticated: Mutex::new(false),
+        };
+
+        let shell_id = connection.create_shell().await?;
+        connection.delete_shell(&shell_id).await;
+
+        debug!(
+            "WinRM session established to {} ({:?})",
+            connection.envelope.endpoint, config.auth
+        );
+        Ok(connection)
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Run a `cmd.exe` command line and collect its output once it exits.
+    pub async fn execute_command(&self, command: &str) -> Result<CommandResult> {
+        self.run(command, None, None).await
+    }
+
+    /// Run a PowerShell script and collect its output once it exits.
+    pub async fn execute_powershell(&self, script: &str) -> Result<CommandResult> {
+        self.run(&powershell_command(script), None, None).await
+    }
+
+    /// Run a PowerShell script, forwarding stdout/stderr chunks to `sink` as they arrive.
+    pub async fn execute_powershell_streaming(
+        &self,
+        script: &str,
+        sink: UnboundedSender<OutputChunk>,
+    ) -> Result<CommandResult> {
+        self.run(&powershell_command(script), None, Some(sink))
+            .await
+    }
+
+    /// Upload `data` to `remote_path`, creating its parent directory.
+    pub async fn upload_bytes(&self, data: &[u8], remote_path: &str) -> Result<()> {
+        let script = format!(
+            concat!(
+                "$ErrorActionPreference = 'Stop'\n",
+                "$path = {path}\n",
+                "$dir = Split-Path -Parent $path\n",
+                "if ($dir) {{ New-Item -ItemType Directory -Force -Path $dir | Out-Null }}\n",
+                "$out = [IO.File]::Create($path)\n",
+                "try {{\n",
+                "    foreach ($line in $input) {{\n",
+                "        if ($line.Length -gt 0) {{\n",
+                "            $bytes = [Convert]::FromBase64String($line)\n",
+                "            $out.Write($bytes, 0, $bytes.Length)\n",
+                "        }}\n",
+                "    }}\n",
+                "}} finally {{ $out.Close() }}\n",
+            ),
+            path = powershell_quote(remote_path)
+        );
+
+        let mut stdin = Vec::with_capacity(data.len() * 4 / 3 + data.len() / UPLOAD_CHUNK_SIZE + 4);
+        for chunk in data.chunks(UPLOAD_CHUNK_SIZE) {
+            stdin.extend_from_slice(STANDARD.encode(chunk).as_bytes());
+            stdin.extend_from_slice(b"\r\n");
+        }
+
+        let result = self
+            .run(&powershell_command(&script), Some(&stdin), None)
+            .await?;
+        if !result.success {
+            return Err(DeployError::DeploymentFailed {
+                host: self.host.clone(),
+                reason: format!("Failed to upload {remote_path}: {}", result.stderr.trim()),
+            });
+        }
+
+        debug!(
+            "Uploaded {} bytes to {}:{}",
+            data.len(),
+            self.host,
+            remote_path
+        );
+        Ok(())
+    }
+
+    /// Lowercase hex SHA-256 of a remote file, or `None` if it does not exist
+    pub async fn file_sha256(&self, remote_path: &str) -> Result<Option<String>> {
+        let script = format!(
+            "$path = {}\nif (Test-Path -LiteralPath $path -PathType Leaf) {{ (Get-FileHash -Algorithm SHA256 -LiteralPath $path).Hash.ToLowerInvariant() }}",
+            powershell_quote(remote_path)
+        );
+        let result = self.execute_powershell(&script).await?;
+        let hash = result.stdout.trim();
+        Ok((result.success && !hash.is_empty()).then(|| hash.to_string()))
+    }
+
+    async fn run(
+        &self,
+        command: &str,
+        stdin: Option<&[u8]>,
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        debug!(
+            "Executing command on {} via WinRM: {}",
+            self.host,
+            redact_env(command)
+        );
+
+        let shell_id = self.create_shell().await?;
+        let result = self.run_in_shell(&shell_id, command, stdin, sink).await;
+        self.delete_shell(&shell_id).await;
+        result
+    }
+
+    async fn run_in_shell(
+        &self,
+        shell_id: &str,
+        command: &str,
+        stdin: Option<&[u8]>,
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        let response = self
+            .send(self.envelope.command(shell_id, command, &[]))
+            .await?;
+        let command_id =
+            soap::parse_command_id(&response).ok_or_else(|| self.protocol_error("CommandId"))?;
+
+        if let Some(data) = stdin {
+            // Base64 in the envelope grows data by a third; leave room for the headers
+            let chunk_size = (self.config.max_envelope_size as usize / 4 * 3)
+                .saturating_sub(4096)
+                .max(1024);
+            let mut chunks = data.chunks(chunk_size).peekable();
+            if chunks.peek().is_none() {
+                self.send(self.envelope.send_stdin(shell_id, &command_id, &[], true))
+                    .await?;
+            }
+            while let Some(chunk) = chunks.next() {
+                let end = chunks.peek().is_none();
+                self.send(self.envelope.send_stdin(shell_id, &command_id, chunk, end))
+                    .await?;
+            }
+        }
+
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let exit_code = loop {
+            let response = self
+                .send(self.envelope.receive(shell_id, &command_id))
+                .await?;
+            if soap::is_operation_timeout(&response) {
+                continue;
+            }
+
+            let output = soap::parse_receive(&response);
+            if let Some(ref sink) = sink {
+                for (stream, data) in [
+                    (OutputStream::Stdout, &output.stdout),
+                    (OutputStream::Stderr, &output.stderr),
+                ] {
+                    if !data.is_empty() {
+                        let _ = sink.send(OutputChunk {
+                            host: self.host.clone(),
+                            stream,
+                            data: String::from_utf8_lossy(data).into_owned(),
+                        });
+                    }
+                }
+            }
+            stdout.extend(output.stdout);
+            stderr.extend(output.stderr);
+
+            if let Some(code) = output.exit_code {
+                break code;
+            }
+        };
+
+        // The command is done; terminating releases its resources on the server
+        let _ = self
+            .send(self.envelope.terminate(shell_id, &command_id))
+            .await;
+
+        Ok(CommandResult {
+            success: exit_code == 0,
+            exit_code,
+            stdout: String::from_utf8_lossy(&stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        })
+    }
+
+    async fn create_shell(&self) -> Result<String> {
+        let response = self.send(self.envelope.create_shell()).await?;
+        soap::parse_shell_id(&response).ok_or_else(|| self.protocol_error("ShellId"))
+    }
+
+    async fn delete_shell(&self, shell_id: &str) {
+        if let Err(e) = self.send(self.envelope.delete_shell(shell_id)).await {
+            debug!("Failed to delete WinRM shell on {}: {}", self.host, e);
+        }
+    }
+
+    /// POST an envelope, authenticating the connection first if needed
+    async fn send(&self, envelope: String) -> Result<String> {
+        let mut authenticated = self.authenticated.lock().await;
+
+        for attempt in 0..2 {
+            let mut request = self
+                .client
+                .post(&self.envelope.endpoint)
+                .header(CONTENT_TYPE, SOAP_CONTENT_TYPE);
+
+            match self.config.auth {
+                WinRmAuth::Basic => {
+                    let credentials = format!(
+                        "{}:{}",
+                        self.username()?,
+                        self.config.password.as_deref().unwrap_or_default()
+                    );
+                    request = request.header(
+                        AUTHORIZATION,
+                        format!("Basic {}", STANDARD.encode(credentials)),
+                    );
+                }
+                WinRmAuth::Ntlm | WinRmAuth::Kerberos if !*authenticated => {
+                    request = request.header(AUTHORIZATION, self.authorization().await?);
+                }
+                _ => {}
+            }
+
+            let response = request.body(envelope.clone()).send().await.map_err(|e| {
+                DeployError::WinRmConnection {
+                    host: self.host.clone(),
+                    reason: format!("Request to {} failed: {e}", self.envelope.endpoint),
+                }
+            })?;
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+
+            match status {
+                StatusCode::UNAUTHORIZED if *authenticated && attempt == 0 => {
+                    // The authenticated connection was closed; start over on a new one
+                    *authenticated = false;
+                }
+                StatusCode::UNAUTHORIZED => {
+                    return Err(DeployError::WinRmAuthentication {
+                        host: self.host.clone(),
+                        reason: format!("{:?} authentication was rejected", self.config.auth),
+                    });
+                }
+                status if status.is_success() => {
+                    *authenticated = true;
+                    return Ok(body);
+                }
+                _ if soap::is_operation_timeout(&body) => return Ok(body),
+                status => {
+                    return Err(DeployError::WinRmConnection {
+                        host: self.host.clone(),
+                        reason: soap::fault_message(&body)
+                            .unwrap_or_else(|| format!("HTTP {status}")),
+                    });
+                }
+            }
+        }
+
+        Err(DeployError::WinRmAuthentication {
+            host: self.host.clone(),
+            reason: "Connection could not be re-authenticated".to_string(),
+        })
+    }
+
+    /// `Authorization` header that authenticates the next request's connection
+    async fn authorization(&self) -> Result<String> {
+        let auth_error = |reason: String| DeployError::WinRmAuthentication {
+            host: self.host.clone(),
+            reason,
+        };
+
+        match self.config.auth {
+            WinRmAuth::Kerberos => {
+                let token = kerberos::initial_token(&self.hostname).map_err(auth_error)?;
+                Ok(format!("Negotiate {}", STANDARD.encode(token)))
+            }
+            _ => {
+                // NTLM challenge/response; the final message rides on the real request
+                let response = self
+                    .client
+                    .post(&self.envelope.endpoint)
+                    .header(CONTENT_TYPE, SOAP_CONTENT_TYPE)
+                    .header(
+                        AUTHORIZATION,
+                        format!("Negotiate {}", STANDARD.encode(ntlm::negotiate_message())),
+                    )
+                    .send()
+                    .await
+                    .map_err(|e| DeployError::WinRmConnection {
+                        host: self.host.clone(),
+                        reason: format!("Request to {} failed: {e}", self.envelope.endpoint),
+                    })?;
+
+                let challenge = response
+                    .headers()
+                    .get_all(WWW_AUTHENTICATE)
+                    .iter()
+                    .filter_map(|value: &HeaderValue| value.to_str().ok())
+                    .find_map(|value| {
+                        value
+                            .strip_prefix("Negotiate ")
+                            .or_else(|| value.strip_prefix("NTLM "))
+                    })
+                    .and_then(|token| STANDARD.decode(token.trim()).ok())
+                    .and_then(|token| ntlm::parse_challenge(&token))
+                    .ok_or_else(|| {
+                        auth_error("Server did not send an NTLM challenge".to_string())
+                    })?;
+                let _ = response.bytes().await;
+
+                let username = self.username()?;
+                let (domain, user) = match username.split_once('\\') {
+                    Some((domain, user)) => (domain.to_string(), user.to_string()),
+                    None => (String::new(), username),
+                };
+                let workstation = hostname::get()
+                    .map(|name| name.to_string_lossy().to_uppercase())
+                    .unwrap_or_default();
+                let message = ntlm::authenticate_message(
+                    &challenge,
+                    &user,
+                    self.config.password.as_deref().unwrap_or_default(),
+                    &domain,
+                    &workstation,
+                );
+                Ok(format!("Negotiate {}", STANDARD.encode(message)))
+            }
+        }
+    }
+
+    fn username(&self) -> Result<String> {
+        self.config
+            .username
+            .clone()
+            .ok_or_else(|| DeployError::WinRmAuthentication {
+                host: self.host.clone(),
+                reason: "No username configured".to_string(),
+            })
+    }
+
+    fn protocol_error(&self, missing: &str) -> DeployError {
+        DeployError::WinRmConnection {
+            host: self.host.clone(),
+            reason: format!("Response did not contain a {missing}"),
+        }
+    }
+}
+
+#[async_trait]
+impl ConnectionPlugin for WinRmConnection {
+    fn host(&self) -> &str {
+        &self.host
+    }
+
+    fn transport(&self) -> &'static str {
+        "winrm"
+    }
+
+    /// Runs `command` as a PowerShell script
+    async fn execute(
+        &self,
+        command: &str,
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        self.run(&powershell_command(command), None, sink).await
+    }
+
+    async fn upload(&self, data: &[u8], path: &str, _mode: u32) -> Result<()> {
+        self.upload_bytes(data, path).await
+    }
+
+    async fn execute_program(
+        &self,
+        program: &str,
+        args: &[String],
+        env: &[(&str, &str)],
+        sink: Option<UnboundedSender<OutputChunk>>,
+    ) -> Result<CommandResult> {
+        let mut script = String::new();
+        for (name, value) in env {
+            script.push_str(&format!("$env:{name} = {}\n", powershell_quote(value)));
+        }
+        script.push_str(&format!("& {}", powershell_quote(program)));
+        for arg in args {
+            script.push(' ');
+            script.push_str(&powershell_quote(arg));
+        }
+        script.push_str("\nexit $LASTEXITCODE");
+        self.execute(&script, sink).await
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let script = format!(
+            "$ErrorActionPreference = 'Stop'\nMove-Item -LiteralPath {} -Destination {} -Force",
+            powershell_quote(from),
+            powershell_quote(to)
+        );
+        let result = self.execute(&script, None).await?;
+        check_result(
+            &self.host,
+            &format!("Failed to move {from} to {to}"),
+            result,
+        )
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<bool> {
+        let script = format!(
+            "$ErrorActionPreference = 'Stop'\nif (-not (Test-Path -LiteralPath {from} -PathType Leaf)) {{ exit 3 }}\nCopy-Item -LiteralPath {from} -Destination {to} -Force",
+            from = powershell_quote(from),
+            to = powershell_quote(to)
+        );
+        let result = self.execute(&script, None).await?;
+        if result.exit_code == 3 {
+            return Ok(false);
+        }
+        check_result(
+            &self.host,
+            &format!("Failed to copy {from} to {to}"),
+            result,
+        )?;
+        Ok(true)
+    }
+
+    async fn remove(&self, path: &str) -> Result<()> {
+        let script = format!(
+            "Remove-Item -LiteralPath {} -Force -ErrorAction SilentlyContinue; exit 0",
+            powershell_quote(path)
+        );
+        let result = self.execute(&script, None).await?;
+        check_result(&self.host, &format!("Failed to remove {path}"), result)
+    }
+
+    /// Parts are streamed over stdin, one `offset<TAB>len<TAB>path` line each,
+    /// so the part list is not limited by the command line length.
+    async fn assemble(&self, output: &str, parts: &[FilePart], _mode: u32) -> Result<()> {
+        let script = format!(
+            concat!(
+                "$ErrorActionPreference = 'Stop'\n",
+                "$out = [IO.File]::Create({output})\n",
+                "$buffer = New-Object byte[] 65536\n",
+                "try {{\n",
+                "    foreach ($line in $input) {{\n",
+                "        if ($line.Length -eq 0) {{ continue }}\n",
+                "        $fields = $line.Split(\"`t\", 3)\n",
+                "        $in = [IO.File]::OpenRead($fields[2])\n",
+                "        try {{\n",
+                "            [void]$in.Seek([long]$fields[0], 'Begin')\n",
+                "            $remaining = [long]$fields[1]\n",
+                "            while ($remaining -gt 0) {{\n",
+                "                $n = $in.Read($buffer, 0, [int][Math]::Min($buffer.Length, $remaining))\n",
+                "                if ($n -le 0) {{ throw \"Unexpected end of $($fields[2])\" }}\n",
+                "                $out.Write($buffer, 0, $n)\n",
+                "                $remaining -= $n\n",
+                "            }}\n",
+                "        }} finally {{ $in.Close() }}\n",
+                "    }}\n",
+                "}} finally {{ $out.Close() }}\n",
+            ),
+            output = powershell_quote(output)
+        );
+
+        let mut stdin = String::new();
+        for part in parts {
+            stdin.push_str(&format!("{}\t{}\t{}\r\n", part.offset, part.len, part.path));
+        }
+
+        let result = self
+            .run(&powershell_command(&script), Some(stdin.as_bytes()), None)
+            .await?;
+        check_result(&self.host, &format!("Failed to assemble {output}"), result)
+    }
+
+    /// Windows hosts have no zstd; segments are sent uncompressed
+    async fn supports_zstd(&self) -> bool {
+        false
+    }
+
+    async fn probe_platform(&self) -> Result<HostPlatform> {
+        let result = self.execute(POWERSHELL_PROBE_SCRIPT, None).await?;
+        parse_platform(&self.host, &result)
+    }
+
+    async fn read_to_string(&self, path: &str) -> Result<Option<String>> {
+        let script = format!(
+            "$path = {}\nif (Test-Path -LiteralPath $path -PathType Leaf) {{ Get-Content -Raw -LiteralPath $path }} else {{ exit 3 }}",
+            powershell_quote(path)
+        );
+        let result = self.execute_powershell(&script).await?;
+        Ok(result.success.then_some(result.stdout))
+    }
+
+    async fn sha256(&self, path: &str) -> Result<Option<String>> {
+        self.file_sha256(path).await
+    }
+}
+
+/// Opens and caches authenticated WinRM endpoints, one per host.
+pub struct WinRmConnectionManager {
+    config: WinRmConfig,
+    connections: Mutex<HashMap<String, Arc<WinRmConnection>>>,
+}
+
+impl Default for WinRmConnectionManager {
+    fn default() -> Self {
+        Self::new(WinRmConfig::default())
+    }
+}
+
+impl WinRmConnectionManager {
+    pub fn new(config: WinRmConfig) -> Self {
+        Self {
+            config,
+            connections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn get_connection(
+        &self,
+        host: &str,
+        vars: &HostConnectionVars,
+    ) -> Result<Arc<WinRmConnection>> {
+        let mut connections = self.connections.lock().await;
+        if let Some(connection) = connections.get(host) {
+            return Ok(connection.clone());
+        }
+
+        let hostname = vars.host.as_deref().unwrap_or(host);
+        let connection =
+            Arc::new(WinRmConnection::connect(host, hostname, &self.config.for_host(vars)).await?);
+        connections.insert(host.to_string(), connection.clone());
+        Ok(connection)
+    }
+}
+
+/// `powershell.exe` command line running `script` via `-EncodedCommand`
+pub fn powershell_command(script: &str) -> String {
+    let utf16: Vec<u8> = script.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    format!(
+        "powershell.exe -NoProfile -NonInteractive -ExecutionPolicy Bypass -EncodedCommand {}",
+        STANDARD.encode(utf16)
+    )
+}
+
+/// Quote a string as a PowerShell single-quoted literal.
+pub fn powershell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_from_inventory_variables() {
+        let vars = HostConnectionVars {
+            port: Some(5985),
+            user: Some("CORP\\deploy".to_string()),
+            password: Some("secret".to_string()),
+            winrm_scheme: Some("http".to_string()),
+            winrm_server_cert_validation: Some("ignore".to_string()),
+            ..Default::default()
+        };
+
+        let config = WinRmConfig::default().for_host(&vars);
+        assert_eq!(config.auth, WinRmAuth::Ntlm);
+        assert!(!config.validate_certs);
+        assert_eq!(config.endpoint("win01"), "http://win01:5985/wsman");
+
+        let kerberos = WinRmConfig::default().for_host(&HostConnectionVars {
+            user: Some("deploy@CORP.EXAMPLE.COM".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(kerberos.auth, WinRmAuth::Kerberos);
+        assert_eq!(kerberos.endpoint("win01"), "https://win01:5986/wsman");
+    }
+
+    #[test]
+    fn test_powershell_quoting() {
+        assert_eq!(powershell_quote(r"C:\it's here"), r"'C:\it''s here'");
+
+        let command = powershell_command("exit 0");
+        let encoded = command.rsplit(' ').next().unwrap();
+        let decoded = STANDARD.decode(encoded).unwrap();
+        assert_eq!(decoded, b"e\0x\0i\0t\0 \x000\0");
+    }
+}
@@ -0,0 +1,316 @@
+//! WS-Management SOAP envelopes for the Windows Remote Shell protocol.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use regex::Regex;
+use std::time::Duration;
+
+const SHELL_RESOURCE_URI: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/cmd";
+const ACTION_CREATE: &str = "http://schemas.xmlsoap.org/ws/2004/09/transfer/Create";
+const ACTION_DELETE: &str = "http://schemas.xmlsoap.org/ws/2004/09/transfer/Delete";
+const ACTION_COMMAND: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/Command";
+const ACTION_SEND: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/Send";
+const ACTION_RECEIVE: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/Receive";
+const ACTION_SIGNAL: &str = "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/Signal";
+const SIGNAL_TERMINATE: &str =
+    "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/signal/terminate";
+const COMMAND_STATE_DONE: &str =
+    "http://schemas.microsoft.com/wbem/wsman/1/windows/shell/CommandState/Done";
+
+/// WS-Man fault code for a Receive that timed out with no new output
+const OPERATION_TIMEOUT_FAULT: &str = "2150858793";
+
+/// Settings stamped into every envelope header
+#[derive(Debug, Clone)]
+pub struct EnvelopeSettings {
+    pub endpoint: String,
+    pub max_envelope_size: u32,
+    pub operation_timeout: Duration,
+}
+
+impl EnvelopeSettings {
+    fn envelope(
+        &self,
+        action: &str,
+        shell_id: Option<&str>,
+        options: &[(&str, &str)],
+        body: &str,
+    ) -> String {
+        let selector = shell_id
+            .map(|id| {
+                format!(
+                    r#"<w:SelectorSet><w:Selector Name="ShellId">{}</w:Selector></w:SelectorSet>"#,
+                    xml_escape(id)
+                )
+            })
+            .unwrap_or_default();
+        let options = if options.is_empty() {
+            String::new()
+        } else {
+            let options: String = options
+                .iter()
+                .map(|(name, value)| format!(r#"<w:Option Name="{name}">{value}</w:Option>"#))
+                .collect();
+            format!("<w:OptionSet>{options}</w:OptionSet>")
+        };
+
+        format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="utf-8"?>"#,
+                r#"<s:Envelope xmlns:s="http://www.w3.org/2003/05/soap-envelope""#,
+                r#" xmlns:a="http://schemas.xmlsoap.org/ws/2004/08/addressing""#,
+                r#" xmlns:w="http://schemas.dmtf.org/wbem/wsman/1/wsman.xsd""#,
+                r#" xmlns:p="http://schemas.microsoft.com/wbem/wsman/1/wsman.xsd""#,
+                r#" xmlns:rsp="http://schemas.microsoft.com/wbem/wsman/1/windows/shell">"#,
+                "<s:Header>",
+                "<a:To>{to}</a:To>",
+                r#"<a:ReplyTo><a:Address s:mustUnderstand="true">http://schemas.xmlsoap.org/ws/2004/08/addressing/role/anonymous</a:Address></a:ReplyTo>"#,
+                r#"<w:MaxEnvelopeSize s:mustUnderstand="true">{max}</w:MaxEnvelopeSize>"#,
+                "<a:MessageID>uuid:{id}</a:MessageID>",
+                r#"<w:Locale xml:lang="en-US" s:mustUnderstand="false"/>"#,
+                r#"<p:DataLocale xml:lang="en-US" s:mustUnderstand="false"/>"#,
+                "<w:OperationTimeout>PT{timeout}S</w:OperationTimeout>",
+                r#"<w:ResourceURI s:mustUnderstand="true">{resource}</w:ResourceURI>"#,
+                r#"<a:Action s:mustUnderstand="true">{action}</a:Action>"#,
+                "{selector}{options}",
+                "</s:Header>",
+                "<s:Body>{body}</s:Body>",
+                "</s:Envelope>"
+            ),
+            to = xml_escape(&self.endpoint),
+            max = self.max_envelope_size,
+            id = uuid::Uuid::new_v4(),
+            timeout = self.operation_timeout.as_secs().max(1),
+            resource = SHELL_RESOURCE_URI,
+            action = action,
+            selector = selector,
+            options = options,
+            body = body,
+        )
+    }
+
+    pub fn create_shell(&self) -> String {
+        self.envelope(
+            ACTION_CREATE,
+            None,
+            &[("WINRS_NOPROFILE", "FALSE"), ("WINRS_CODEPAGE", "65001")],
+            concat!(
+                "<rsp:Shell>",
+                "<rsp:InputStreams>stdin</rsp:InputStreams>",
+                "<rsp:OutputStreams>stdout stderr</rsp:OutputStreams>",
+                "</rsp:Shell>"
+            ),
+        )
+    }
+
+    pub fn delete_shell(&self, shell_id: &str) -> String {
+        self.envelope(ACTION_DELETE, Some(shell_id), &[], "")
+    }
+
+    pub fn command(&self, shell_id: &str, command: &str, arguments: &[&str]) -> String {
+        let arguments: String = arguments
+            .iter()
+            .map(|arg| format!("<rsp:Arguments>{}</rsp:Arguments>", xml_escape(arg)))
+            .collect();
+        self.envelope(
+            ACTION_COMMAND,
+            Some(shell_id),
+            &[
+                ("WINRS_CONSOLEMODE_STDIN", "TRUE"),
+                ("WINRS_SKIP_CMD_SHELL", "FALSE"),
+            ],
+            &format!(
+                "<rsp:CommandLine><rsp:Command>{}</rsp:Command>{arguments}</rsp:CommandLine>",
+                xml_escape(command)
+            ),
+        )
+    }
+
+    pub fn send_stdin(&self, shell_id: &str, command_id: &str, data: &[u8], end: bool) -> String {
+        let end = if end { r#" End="true""# } else { "" };
+        self.envelope(
+            ACTION_SEND,
+            Some(shell_id),
+            &[],
+            &format!(
+                r#"<rsp:Send><rsp:Stream Name="stdin" CommandId="{}"{end}>{}</rsp:Stream></rsp:Send>"#,
+                xml_escape(command_id),
+                STANDARD.encode(data)
+            ),
+        )
+    }
+
+    pub fn receive(&self, shell_id: &str, command_id: &str) -> String {
+        self.envelope(
+            ACTION_RECEIVE,
+            Some(shell_id),
+            &[("WSMAN_CMDSHELL_OPTION_KEEPALIVE", "TRUE")],
+            &format!(
+                r#"<rsp:Receive><rsp:DesiredStream CommandId="{}">stdout stderr</rsp:DesiredStream></rsp:Receive>"#,
+                xml_escape(command_id)
+            ),
+        )
+    }
+
+    pub fn terminate(&self, shell_id: &str, command_id: &str) -> String {
+        self.envelope(
+            ACTION_SIGNAL,
+            Some(shell_id),
+            &[],
+            &format!(
+                r#"<rsp:Signal CommandId="{}"><rsp:Code>{SIGNAL_TERMINATE}</rsp:Code></rsp:Signal>"#,
+                xml_escape(command_id)
+            ),
+        )
+    }
+}
+
+/// Output decoded from one Receive response
+#[derive(Debug, Default, PartialEq)]
+pub struct ReceiveOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Set once the command has finished
+    pub exit_code: Option<i32>,
+}
+
+pub fn parse_shell_id(response: &str) -> Option<String> {
+    element_text(response, "ShellId")
+        .or_else(|| capture(response, r#"<[\w:]*Selector Name="ShellId">([^<]+)<"#))
+}
+
+pub fn parse_command_id(response: &str) -> Option<String> {
+    element_text(response, "CommandId")
+}
+
+pub fn parse_receive(response: &str) -> ReceiveOutput {
+    let stream_re = Regex::new(r#"<[\w]+:Stream\s+([^>]*?)(?:/>|>([^<]*)</[\w]+:Stream>)"#)
+        .expect("valid regex");
+    let mut output = ReceiveOutput::default();
+
+    for caps in stream_re.captures_iter(response) {
+        let attributes = &caps[1];
+        let Some(data) = caps
+            .get(2)
+            .map(|m| m.as_str().trim())
+            .filter(|d| !d.is_empty())
+        else {
+            continue;
+        };
+        let Ok(bytes) = STANDARD.decode(data) else {
+            continue;
+        };
+        if attributes.contains(r#"Name="stderr""#) {
+            output.stderr.extend(bytes);
+        } else if attributes.contains(r#"Name="stdout""#) {
+            output.stdout.extend(bytes);
+        }
+    }
+
+    if response.contains(COMMAND_STATE_DONE) {
+        output.exit_code = Some(
+            element_text(response, "ExitCode")
+                .and_then(|code| code.parse::<i64>().ok())
+                // Windows exit codes are unsigned 32-bit; keep NTSTATUS values recognisable
+                .map(|code| code as i32)
+                .unwrap_or(0),
+        );
+    }
+
+    output
+}
+
+/// True for the fault a long-poll Receive returns when no output arrived in time
+pub fn is_operation_timeout(response: &str) -> bool {
+    response.contains(OPERATION_TIMEOUT_FAULT)
+}
+
+/// Human-readable message from a SOAP fault, if the response is one
+pub fn fault_message(response: &str) -> Option<String> {
+    if !response.contains(":Fault>") {
+        return None;
+    }
+    element_text(response, "Message")
+        .or_else(|| element_text(response, "Text"))
+        .or_else(|| Some("unknown WS-Management fault".to_string()))
+}
+
+fn element_text(xml: &str, local_name: &str) -> Option<String> {
+    capture(
+        xml,
+        &format!(r#"<(?:[\w]+:)?{local_name}(?:\s[^>]*)?>([^<]*)</(?:[\w]+:)?{local_name}>"#),
+    )
+    .map(|text| xml_unescape(text.trim()))
+}
+
+fn capture(text: &str, pattern: &str) -> Option<String> {
+    Regex::new(pattern)
+        .ok()?
+        .captures(text)
+        .and_then(|caps| caps.get(1))
+        .map(|m| m.as_str().to_string())
+}
+
+pub fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn xml_unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_receive_response() {
+        let response = r#"<s:Envelope><s:Body><rsp:ReceiveResponse>
+            <rsp:Stream Name="stdout" CommandId="1">aGVsbG8K</rsp:Stream>
+            <rsp:Stream Name="stderr" CommandId="1">b29wcw==</rsp:Stream>
+            <rsp:Stream Name="stdout" CommandId="1" End="true"></rsp:Stream>
+            <rsp:Stream Name="stderr" CommandId="1" End="true"/>
+            <rsp:CommandState CommandId="1" State="http://schemas.microsoft.com/wbem/wsman/1/windows/shell/CommandState/Done">
+            <rsp:ExitCode>3</rsp:ExitCode></rsp:CommandState>
+            </rsp:ReceiveResponse></s:Body></s:Envelope>"#;
+
+        let output = parse_receive(response);
+        assert_eq!(output.stdout, b"hello\n");
+        assert_eq!(output.stderr, b"oops");
+        assert_eq!(output.exit_code, Some(3));
+    }
+
+    #[test]
+    fn test_parse_ids() {
+        let created = r#"<s:Body><x:ResourceCreated><w:ReferenceParameters><w:SelectorSet>
+            <w:Selector Name="ShellId">11111111-AAAA</w:Selector></w:SelectorSet>
+            </w:ReferenceParameters></x:ResourceCreated></s:Body>"#;
+        assert_eq!(parse_shell_id(created).as_deref(), Some("11111111-AAAA"));
+
+        let command =
+            r#"<rsp:CommandResponse><rsp:CommandId>C-1</rsp:CommandId></rsp:CommandResponse>"#;
+        assert_eq!(parse_command_id(command).as_deref(), Some("C-1"));
+    }
+
+    #[test]
+    fn test_command_envelope_escapes_xml() {
+        let settings = EnvelopeSettings {
+            endpoint: "https://win01:5986/wsman".to_string(),
+            max_envelope_size: 153600,
+            operation_timeout: Duration::from_secs(20),
+        };
+        let envelope = settings.command("S-1", "echo", &["a<b & c"]);
+        assert!(envelope.contains("<rsp:Arguments>a&lt;b &amp; c</rsp:Arguments>"));
+        assert!(envelope.contains(r#"<w:Selector Name="ShellId">S-1</w:Selector>"#));
+        assert!(envelope.contains("<w:OperationTimeout>PT20S</w:OperationTimeout>"));
+    }
+}
@@ -449,6 +449,21 @@ mod tests {
             can_run_parallel: true,
             estimated_duration: Duration::from_secs(10),
             risk_level: super::super::rustle_plan::RiskLevel::Low,
+            run_once: false,
+            delegate_to: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            no_log: false,
+            r#become: None,
+            environment: HashMap::new(),
+            module_defaults: HashMap::new(),
+            timeout: None,
+            throttle: None,
         }
     }
 
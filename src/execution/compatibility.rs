@@ -149,6 +149,7 @@ impl SchemaValidator {
             "type": "object",
             "required": ["metadata", "plays", "total_tasks", "hosts"],
             "properties": {
+                "schema_version": { "type": "integer", "minimum": 1 },
                 "metadata": {
                     "type": "object",
                     "required": ["created_at", "rustle_plan_version", "playbook_hash", "inventory_hash", "planning_options"],
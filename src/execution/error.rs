@@ -35,6 +35,9 @@ pub enum ParseError {
 
     #[error("Invalid field value: {field} = {value}")]
     InvalidFieldValue { field: String, value: String },
+
+    #[error("Vault error: {0}")]
+    Vault(#[from] VaultError),
 }
 
 #[derive(Debug, Error)]
@@ -102,3 +105,146 @@ pub enum OrderingError {
     #[error("Topological sort failed: {reason}")]
     TopologicalSortFailed { reason: String },
 }
+
+#[derive(Debug, Error)]
+pub enum VaultError {
+    #[error("Found vault-encrypted data but no vault password was given")]
+    NoSecrets,
+
+    #[error("Invalid vault format: {reason}")]
+    InvalidFormat { reason: String },
+
+    #[error("Unsupported vault cipher: {cipher}")]
+    UnsupportedCipher { cipher: String },
+
+    #[error("Decryption failed, no vault password matched (tried: {tried})")]
+    DecryptionFailed { tried: String },
+
+    #[error("Failed to get the password of vault id '{id}': {reason}")]
+    Password { id: String, reason: String },
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum VarsFileError {
+    #[error("Vault error in {path}: {source}")]
+    Vault { path: String, source: VaultError },
+
+    #[error("SOPS error in {path}: {source}")]
+    Sops { path: String, source: SopsError },
+
+    #[error("Invalid variables file {path}: {reason}")]
+    Invalid { path: String, reason: String },
+
+    #[error("Failed to read {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum SopsError {
+    #[error("Invalid SOPS file: {reason}")]
+    InvalidFormat { reason: String },
+
+    #[error("No key of the SOPS file is available ({tried})")]
+    NoKey { tried: String },
+
+    #[error("Failed to decrypt the SOPS value at '{path}'")]
+    DecryptionFailed { path: String },
+
+    #[error("SOPS MAC mismatch, the file was modified after it was encrypted")]
+    MacMismatch,
+
+    #[error("Unsupported SOPS file: {reason}")]
+    Unsupported { reason: String },
+
+    #[error("Invalid age identity: {reason}")]
+    InvalidIdentity { reason: String },
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum PlaybookError {
+    #[error("Failed to read {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid playbook {path}: {reason}")]
+    Invalid { path: String, reason: String },
+
+    #[error("Role {role} not found (searched {searched})")]
+    RoleNotFound { role: String, searched: String },
+
+    #[error("Roles depend on each other in a cycle: {chain}")]
+    RoleCycle { chain: String },
+
+    #[error("{keyword} of {location} is not supported in playbooks, plan it with rustle-plan")]
+    Unsupported { keyword: String, location: String },
+
+    #[error("{} included files not found: {}", .0.len(), missing_files(.0))]
+    MissingFiles(Vec<MissingFile>),
+
+    #[error("Vault error: {0}")]
+    Vault(#[from] VaultError),
+
+    #[error("{0}")]
+    VarsFile(#[from] VarsFileError),
+
+    #[error("Variable error: {0}")]
+    Variables(#[from] crate::inventory::VariableError),
+}
+
+/// Why a [`crate::execution::PlanBuilder`] cannot build its plan
+#[derive(Debug, Error)]
+pub enum PlanBuildError {
+    #[error("Play '{play}' has no hosts")]
+    NoHosts { play: String },
+
+    #[error("Id {id} is used twice")]
+    DuplicateId { id: String },
+
+    #[error("Task {task} depends on unknown task {dependency}")]
+    UnknownDependency { task: String, dependency: String },
+
+    #[error("Task {task} notifies unknown handler '{handler}'")]
+    UnknownHandler { task: String, handler: String },
+}
+
+/// A task, variables or playbook file that a playbook or plan includes and
+/// that does not exist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingFile {
+    pub path: std::path::PathBuf,
+    /// The keyword including it, such as `include_tasks`
+    pub keyword: String,
+    /// Name of the task or play including it
+    pub included_by: String,
+}
+
+impl std::fmt::Display for MissingFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({} of '{}')",
+            self.path.display(),
+            self.keyword,
+            self.included_by
+        )
+    }
+}
+
+fn missing_files(missing: &[MissingFile]) -> String {
+    missing
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
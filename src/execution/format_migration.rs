@@ -25,6 +25,9 @@ pub enum MigrationError {
     #[error("Unsupported format version: {version}")]
     UnsupportedVersion { version: String },
 
+    #[error("Plan schema {version} is newer than the {supported} this version supports")]
+    NewerVersion { version: u32, supported: u32 },
+
     #[error("Missing required field in new format: {field}")]
     MissingRequiredField { field: String },
 
@@ -4,14 +4,20 @@ pub mod extractor;
 pub mod inventory;
 pub mod parser;
 pub mod plan;
+pub mod sops;
 pub mod template;
 pub mod validator;
+pub mod vars_file;
+pub mod vault;
 
 // Rustle Plan Output compatibility modules
 pub mod binary_analyzer;
 pub mod compatibility;
 pub mod format_migration;
+pub mod plan_builder;
 pub mod plan_converter;
+pub mod plan_schema;
+pub mod playbook;
 pub mod rustle_plan;
 pub mod validation;
 
@@ -22,6 +28,17 @@ pub use format_migration::{FormatMigrator, MigrationConfig, MigrationError, Migr
 pub use inventory::*;
 pub use parser::*;
 pub use plan::*;
+pub use plan_builder::{HandlerBuilder, PlanBuilder, PlayBuilder, TaskBuilder};
 pub use plan_converter::*;
+pub use plan_schema::{
+    plan_json_schema, MigrationReport, MigrationStep, ParseMode, PlanMigrations,
+    PLAN_SCHEMA_VERSION,
+};
+pub use playbook::{is_playbook, PlaybookLoader};
 pub use rustle_plan::*;
+pub use sops::{is_sops_document, SopsKeys};
 pub use validation::{validate_rustle_plan_json, RustlePlanValidator};
+pub use vars_file::{
+    render_plan_variables, resolve_plan_lookups, resolve_variable_lookups, VarsFileLoader,
+};
+pub use vault::{is_vaulted, VaultIdentity, VaultSecret, VaultSecrets};
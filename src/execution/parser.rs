@@ -1,8 +1,8 @@
 use crate::execution::{
     DependencyError, ExecutionPlan, ExtractionError, OrderingError, ParseError, TemplateError,
-    ValidationError,
+    ValidationError, VaultSecrets,
 };
-use crate::types::DeploymentTarget;
+use crate::types::{DeploymentTarget, HostConnectionVars};
 use serde_json;
 use serde_yaml;
 use std::collections::{HashMap, HashSet};
@@ -17,6 +17,7 @@ pub enum PlanFormat {
 pub struct ExecutionPlanParser {
     schema_validator: SchemaValidator,
     template_processor: TemplateProcessor,
+    vault: VaultSecrets,
 }
 
 impl ExecutionPlanParser {
@@ -24,25 +25,39 @@ impl ExecutionPlanParser {
         Self {
             schema_validator: SchemaValidator::new(),
             template_processor: TemplateProcessor::new(),
+            vault: VaultSecrets::new(),
         }
     }
 
+    /// Decrypt the vault-encrypted values of plans with `secrets`
+    pub fn with_vault(mut self, secrets: VaultSecrets) -> Self {
+        self.vault = secrets;
+        self
+    }
+
     pub fn parse(&self, content: &str, format: PlanFormat) -> Result<ExecutionPlan, ParseError> {
         let detected_format = match format {
             PlanFormat::Auto => self.detect_format(content)?,
             format => format,
         };
 
+        // Vaulted values are decrypted before the plan takes shape
         let plan: ExecutionPlan = match detected_format {
             PlanFormat::Json => {
-                serde_json::from_str(content).map_err(|e| ParseError::InvalidJson {
+                let invalid = |e: serde_json::Error| ParseError::InvalidJson {
                     reason: e.to_string(),
-                })?
+                };
+                let mut value = serde_json::from_str(content).map_err(invalid)?;
+                self.vault.decrypt_json(&mut value)?;
+                serde_json::from_value(value).map_err(invalid)?
             }
             PlanFormat::Yaml => {
-                serde_yaml::from_str(content).map_err(|e| ParseError::InvalidYaml {
+                let invalid = |e: serde_yaml::Error| ParseError::InvalidYaml {
                     reason: e.to_string(),
-                })?
+                };
+                let mut value = serde_yaml::from_str(content).map_err(invalid)?;
+                self.vault.decrypt_yaml(&mut value)?;
+                serde_yaml::from_value(value).map_err(invalid)?
             }
             PlanFormat::Auto => unreachable!("Auto format should be resolved by now"),
         };
@@ -82,10 +97,52 @@ impl ExecutionPlanParser {
                 host: host.address.clone(),
                 target_path: plan.deployment_config.target_path.clone(),
                 binary_compilation_id: format!("rustle-{target_triple}"),
-                deployment_method: crate::types::DeploymentMethod::Ssh,
+                deployment_method: match host.connection.method {
+                    crate::execution::plan::ConnectionMethod::Ssh => {
+                        crate::types::DeploymentMethod::Ssh
+                    }
+                    crate::execution::plan::ConnectionMethod::WinRm => {
+                        crate::types::DeploymentMethod::WinRm
+                    }
+                    crate::execution::plan::ConnectionMethod::Local => {
+                        crate::types::DeploymentMethod::Local
+                    }
+                    crate::execution::plan::ConnectionMethod::Docker => {
+                        crate::types::DeploymentMethod::Docker {
+                            container: host.address.clone(),
+                        }
+                    }
+                    crate::execution::plan::ConnectionMethod::Podman => {
+                        crate::types::DeploymentMethod::Podman {
+                            container: host.address.clone(),
+                        }
+                    }
+                    crate::execution::plan::ConnectionMethod::Kubectl => {
+                        crate::types::DeploymentMethod::Kubectl {
+                            pod: host.address.clone(),
+                            namespace: None,
+                            container: None,
+                            context: None,
+                            kubeconfig: None,
+                        }
+                    }
+                    crate::execution::plan::ConnectionMethod::Chroot => {
+                        crate::types::DeploymentMethod::Chroot {
+                            root: host.address.clone(),
+                        }
+                    }
+                },
                 status: crate::types::DeploymentStatus::Pending,
                 deployed_at: None,
                 version: "1.0.0".to_string(),
+                connection: HostConnectionVars {
+                    port: host.connection.port,
+                    user: host.connection.username.clone(),
+                    private_key_file: host.connection.key_file.clone(),
+                    password: host.connection.password.clone(),
+                    ..Default::default()
+                }
+                .or(HostConnectionVars::from_variables(&host.variables)),
             });
         }
 
@@ -1,8 +1,11 @@
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::time::Duration;
 
+use super::plan_schema::DurationSchema;
+
 /// Complete execution plan from rustle-plan
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecutionPlan {
@@ -13,6 +16,12 @@ pub struct ExecutionPlan {
     pub facts_template: FactsTemplate,
     pub deployment_config: DeploymentConfig,
     pub modules: Vec<ModuleSpec>,
+    #[serde(default)]
+    pub handlers: Vec<Handler>,
+    #[serde(default)]
+    pub blocks: Vec<Block>,
+    #[serde(default)]
+    pub plays: Vec<Play>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,6 +49,242 @@ pub struct Task {
     pub timeout: Option<Duration>,
     pub retry_policy: Option<RetryPolicy>,
     pub failure_policy: FailurePolicy,
+    /// Execute on a single host and share the result with every other host
+    #[serde(default)]
+    pub run_once: bool,
+    /// Execute on this host instead, `localhost` being the controller, while
+    /// keeping the variables of the host the task was planned for
+    #[serde(default)]
+    pub delegate_to: Option<String>,
+    /// Handler names or `listen` topics to notify when the task changes something
+    #[serde(default)]
+    pub notify: Vec<String>,
+    /// Play the task belongs to; notified handlers run when the play ends
+    #[serde(default)]
+    pub play_id: Option<String>,
+    /// Execute once per item instead of once
+    #[serde(default)]
+    pub task_loop: Option<TaskLoop>,
+    /// Execute again until the result satisfies conditions
+    #[serde(default)]
+    pub until: Option<UntilPolicy>,
+    /// Execute in a background job
+    #[serde(default)]
+    pub async_job: Option<AsyncPolicy>,
+    /// Decides whether the task failed instead of the module
+    #[serde(default)]
+    pub failed_when: Option<ResultCondition>,
+    /// Decides whether the task changed something instead of the module
+    #[serde(default)]
+    pub changed_when: Option<ResultCondition>,
+    /// Carry on as if the task succeeded when it fails
+    #[serde(default)]
+    pub ignore_errors: bool,
+    /// Variable the result is bound to for the tasks that follow
+    #[serde(default)]
+    pub register: Option<String>,
+    /// Jinja2 expressions that all have to hold for the task to execute
+    #[serde(default)]
+    pub when: Vec<String>,
+    /// Keep the arguments and results of the task out of output, events,
+    /// state and logs
+    #[serde(default)]
+    pub no_log: bool,
+    /// Execute the module as another user
+    #[serde(default)]
+    pub r#become: Option<BecomePolicy>,
+    /// Environment of the processes the module starts
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+    /// May execute alongside other ready tasks marked `parallel`, the
+    /// planner having found them independent
+    #[serde(default)]
+    pub parallel: bool,
+    /// Most hosts that may run the task at the same time, counted across
+    /// the hosts of the deployment by the controller
+    #[serde(default)]
+    pub throttle: Option<u32>,
+}
+
+/// `become`, `become_user`, `become_method` and `become_flags`. What a task
+/// leaves unset it takes from the runtime configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct BecomePolicy {
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    /// `root`, or `SYSTEM` for runas, by default
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub method: Option<BecomeMethod>,
+    /// Extra arguments for the become method, split the way a shell would
+    #[serde(default)]
+    pub flags: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BecomeMethod {
+    #[default]
+    Sudo,
+    Doas,
+    Su,
+    Runas,
+}
+
+/// `failed_when` or `changed_when`: a constant, or conditions or Jinja2
+/// expressions that all have to hold, evaluated with the module's result
+/// bound to the task's `register`, or `result` without one
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ResultCondition {
+    Constant(bool),
+    Conditions(Vec<Condition>),
+    Expression(String),
+    Expressions(Vec<String>),
+}
+
+/// `async` and `poll`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AsyncPolicy {
+    /// Longest the job may run
+    #[serde(with = "serde_duration")]
+    #[schemars(with = "DurationSchema")]
+    pub timeout: Duration,
+    /// How often to check whether the job finished. Zero moves on at once,
+    /// leaving `async_status` to report on the job.
+    #[serde(default = "default_async_poll", with = "serde_duration")]
+    #[schemars(with = "DurationSchema")]
+    pub poll: Duration,
+}
+
+fn default_async_poll() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// `until`, `retries` and `delay`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UntilPolicy {
+    /// Evaluated with the result of each attempt bound to `register`
+    pub conditions: Vec<Condition>,
+    /// Variable the result of an attempt is bound to, `result` by default
+    #[serde(default)]
+    pub register: Option<String>,
+    /// Attempts after the first
+    #[serde(default = "default_until_retries")]
+    pub retries: u32,
+    #[serde(default = "default_until_delay", with = "serde_duration")]
+    #[schemars(with = "DurationSchema")]
+    pub delay: Duration,
+}
+
+fn default_until_retries() -> u32 {
+    3
+}
+
+fn default_until_delay() -> Duration {
+    Duration::from_secs(5)
+}
+
+/// The items a task iterates over, from `loop`, `with_items`, `with_dict` or
+/// `with_fileglob`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TaskLoop {
+    pub kind: LoopKind,
+    /// A list, a mapping for `with_dict` or glob patterns for
+    /// `with_fileglob`; or a `"{{ variable }}"` holding one
+    pub items: serde_json::Value,
+    #[serde(default)]
+    pub control: LoopControl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LoopKind {
+    Loop,
+    /// Like `loop`, flattening nested lists one level
+    WithItems,
+    /// One `{key, value}` item per entry of a mapping
+    WithDict,
+    /// One item per file matching the patterns
+    WithFileglob,
+}
+
+/// `loop_control`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct LoopControl {
+    /// Variable the item is bound to, `item` by default
+    #[serde(default)]
+    pub loop_var: Option<String>,
+    /// Variable the zero-based index is bound to
+    #[serde(default)]
+    pub index_var: Option<String>,
+    /// What identifies an item in its result instead of the whole item
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Seconds to wait between items
+    #[serde(default)]
+    pub pause: Option<f64>,
+    /// Bind `ansible_loop` with the position of the item
+    #[serde(default)]
+    pub extended: bool,
+}
+
+/// Tasks that run together, with a `rescue` section that runs when one of
+/// them fails and an `always` section that runs either way. Each section
+/// lists task ids and the ids of nested blocks, in execution order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Block {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub block: Vec<String>,
+    #[serde(default)]
+    pub rescue: Vec<String>,
+    #[serde(default)]
+    pub always: Vec<String>,
+}
+
+/// Settings of a play that apply to its tasks and handlers together
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Play {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Deadline for the tasks and handlers of the play, which fail once it
+    /// passes
+    #[serde(default, with = "serde_duration_opt")]
+    pub timeout: Option<Duration>,
+    /// A task failing on one host ends the play on every host
+    #[serde(default)]
+    pub any_errors_fatal: bool,
+}
+
+/// A task that runs only when notified, once per play no matter how often
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Handler {
+    pub id: String,
+    pub name: String,
+    pub module: String,
+    #[serde(default)]
+    pub args: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    /// Jinja2 expressions that all have to hold for the handler to execute
+    #[serde(default)]
+    pub when: Vec<String>,
+    /// Topics that notify the handler besides its name
+    #[serde(default)]
+    pub listen: Vec<String>,
+    /// Handlers the handler notifies when it changes something
+    #[serde(default)]
+    pub notify: Vec<String>,
+    /// Play the handler belongs to, `None` for handlers of every play
+    #[serde(default)]
+    pub play_id: Option<String>,
+    /// Environment of the processes the module starts
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,14 +297,14 @@ pub enum TaskType {
     Custom { module_name: String },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Condition {
     pub variable: String,
     pub operator: ConditionOperator,
     pub value: serde_json::Value,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum ConditionOperator {
     Equals,
     NotEquals,
@@ -158,6 +403,10 @@ pub enum ConnectionMethod {
     Ssh,
     WinRm,
     Local,
+    Docker,
+    Podman,
+    Kubectl,
+    Chroot,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -213,9 +462,12 @@ pub enum ModuleSource {
     },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub enum ExecutionStrategy {
+    /// Every host finishes a task before any host starts the next
+    #[default]
     Linear,
+    /// Every host goes through the tasks at its own pace
     Free,
     BinaryHybrid,
     BinaryOnly,
@@ -230,6 +482,84 @@ pub struct DeploymentConfig {
     pub cleanup_on_success: bool,
     #[serde(with = "serde_duration_opt")]
     pub deployment_timeout: Option<Duration>,
+    /// When hosts may be deployed to; hosts covered by no window may be
+    /// deployed to at any time
+    #[serde(default)]
+    pub maintenance_windows: Vec<MaintenanceWindow>,
+    /// Wait for a closed window to open instead of failing the host
+    #[serde(default)]
+    pub wait_for_window: bool,
+    /// Commands run around each host's execution
+    #[serde(default)]
+    pub hooks: HostHooks,
+    /// Execute on batches of hosts, one batch after another, instead of on
+    /// every host at once
+    #[serde(default)]
+    pub serial: Option<SerialBatch>,
+    /// Stop before the next batch once more than this percentage of the
+    /// hosts in a batch failed
+    #[serde(default)]
+    pub max_fail_percentage: Option<u32>,
+}
+
+/// Size of the host batches of a serial execution
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SerialBatch {
+    /// A number of hosts, e.g. `2`
+    Hosts(usize),
+    /// A percentage of all hosts, e.g. `"25%"`
+    Percentage(String),
+}
+
+/// A daily time range, in UTC, during which hosts of `groups` may be
+/// deployed to. A window whose `end` is before its `start` spans midnight.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceWindow {
+    /// Inventory groups the window applies to; empty for every host
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// `HH:MM`
+    pub start: String,
+    /// `HH:MM`
+    pub end: String,
+    /// Days the window opens on, e.g. `sat`; empty for every day
+    #[serde(default)]
+    pub days: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostHooks {
+    /// Run before the binary is executed, e.g. to drain the host from a
+    /// load balancer; a failing hook keeps the host from executing
+    #[serde(default)]
+    pub pre_execution: Vec<HostHook>,
+    /// Run after an execution that succeeded, e.g. to put the host back
+    #[serde(default)]
+    pub post_execution: Vec<HostHook>,
+}
+
+/// A shell command run for one host. `{host}` in the command is replaced
+/// with the host name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostHook {
+    pub command: String,
+    #[serde(default)]
+    pub run_on: HookLocation,
+    /// Inventory groups the hook applies to; empty for every host
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HookLocation {
+    /// On the controller, with `RUSTLE_HOST` set to the host
+    #[default]
+    Controller,
+    /// On the host itself, over its deployment connection
+    Host,
 }
 
 // Custom serialization for Duration fields
@@ -0,0 +1,567 @@
+//! Typed builders of execution plans, for tools that write plans instead of
+//! planning playbooks
+//!
+//! Plans are built the way [`crate::execution::PlaybookLoader`] plans
+//! playbooks: one batch per play, ids and execution orders assigned in the
+//! order tasks and handlers are added, and the plan's hosts those of its
+//! plays. What the builders cannot check as they go, such as tasks notifying
+//! handlers that do not exist, [`PlanBuilder::build`] refuses.
+//!
+//! ```
+//! use rustle_deploy::execution::{PlanBuilder, PlayBuilder, TaskBuilder};
+//!
+//! let plan = PlanBuilder::new()
+//!     .with_play(
+//!         PlayBuilder::new("web")
+//!             .with_hosts(["web1", "web2"])
+//!             .with_task(TaskBuilder::new("Install nginx", "package").with_arg("name", "nginx")),
+//!     )
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(plan.total_tasks, 1);
+//! ```
+
+use crate::compilation::reproducible::canonical_value;
+use crate::execution::error::PlanBuildError;
+use crate::execution::plan::{
+    AsyncPolicy, BecomePolicy, ExecutionStrategy, ResultCondition, TaskLoop, UntilPolicy,
+};
+use crate::execution::plan_schema::PLAN_SCHEMA_VERSION;
+use crate::execution::rustle_plan::{
+    BinaryDeploymentPlan, HandlerDefinition, ModuleDefaults, PlanningOptions, PlayPlan, RiskLevel,
+    RustlePlanMetadata, RustlePlanOutput, TaskBatch, TaskCondition, TaskPlan,
+};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Builds a [`RustlePlanOutput`] of the plays given to it
+#[derive(Debug, Clone)]
+pub struct PlanBuilder {
+    plays: Vec<PlayBuilder>,
+    binary_deployments: Vec<BinaryDeploymentPlan>,
+    planning_options: PlanningOptions,
+    playbook_hash: Option<String>,
+}
+
+impl PlanBuilder {
+    pub fn new() -> Self {
+        Self {
+            plays: Vec::new(),
+            binary_deployments: Vec::new(),
+            planning_options: PlanningOptions {
+                limit: None,
+                tags: vec![],
+                skip_tags: vec![],
+                check_mode: false,
+                diff_mode: false,
+                forks: 5,
+                serial: None,
+                strategy: ExecutionStrategy::Linear,
+                binary_threshold: 0,
+                force_binary: true,
+                force_ssh: false,
+            },
+            playbook_hash: None,
+        }
+    }
+
+    pub fn with_play(mut self, play: PlayBuilder) -> Self {
+        self.plays.push(play);
+        self
+    }
+
+    pub fn with_binary_deployment(mut self, deployment: BinaryDeploymentPlan) -> Self {
+        self.binary_deployments.push(deployment);
+        self
+    }
+
+    pub fn with_planning_options(mut self, options: PlanningOptions) -> Self {
+        self.planning_options = options;
+        self
+    }
+
+    /// Record the hash of the source the plan was built from, instead of
+    /// that of its plays
+    pub fn with_playbook_hash(mut self, hash: impl Into<String>) -> Self {
+        self.playbook_hash = Some(hash.into());
+        self
+    }
+
+    /// The plan, of the current schema version
+    pub fn build(self) -> Result<RustlePlanOutput, PlanBuildError> {
+        let mut task_ids = HashSet::new();
+        let mut handler_ids = HashSet::new();
+        let (mut tasks, mut handlers) = (0, 0);
+        let mut plays = Vec::with_capacity(self.plays.len());
+        for (index, play) in self.plays.into_iter().enumerate() {
+            let play = play.build(index, &mut tasks, &mut handlers)?;
+            let play_tasks = play.batches.iter().flat_map(|batch| &batch.tasks);
+            for id in play_tasks.map(|task| &task.task_id) {
+                if !task_ids.insert(id.clone()) {
+                    return Err(PlanBuildError::DuplicateId { id: id.clone() });
+                }
+            }
+            for id in play.handlers.iter().map(|handler| &handler.handler_id) {
+                if !handler_ids.insert(id.clone()) {
+                    return Err(PlanBuildError::DuplicateId { id: id.clone() });
+                }
+            }
+            plays.push(play);
+        }
+
+        let all_tasks = || {
+            plays
+                .iter()
+                .flat_map(|play| &play.batches)
+                .flat_map(|batch| &batch.tasks)
+        };
+        for task in all_tasks() {
+            if let Some(dependency) = task.dependencies.iter().find(|id| !task_ids.contains(*id)) {
+                return Err(PlanBuildError::UnknownDependency {
+                    task: task.task_id.clone(),
+                    dependency: dependency.clone(),
+                });
+            }
+        }
+        // Handlers are notified by their names or the topics they listen to,
+        // within their play
+        for play in &plays {
+            let notifiable: HashSet<&str> = play
+                .handlers
+                .iter()
+                .flat_map(|handler| std::iter::once(&handler.name).chain(&handler.listen))
+                .map(String::as_str)
+                .collect();
+            let play_tasks = play.batches.iter().flat_map(|batch| &batch.tasks);
+            let notifiers = play_tasks.map(|task| (&task.task_id, &task.notify)).chain(
+                play.handlers
+                    .iter()
+                    .map(|handler| (&handler.handler_id, &handler.notify)),
+            );
+            for (notifier, notify) in notifiers {
+                if let Some(handler) = notify
+                    .iter()
+                    .find(|handler| !notifiable.contains(handler.as_str()))
+                {
+                    return Err(PlanBuildError::UnknownHandler {
+                        task: notifier.clone(),
+                        handler: handler.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut hosts: Vec<String> = Vec::new();
+        for host in plays.iter().flat_map(|play| &play.hosts) {
+            if !hosts.contains(host) {
+                hosts.push(host.clone());
+            }
+        }
+        let mut sorted_hosts = hosts.clone();
+        sorted_hosts.sort();
+        let playbook_hash = match self.playbook_hash {
+            Some(hash) => hash,
+            None => {
+                let plays = canonical_value(&plays).unwrap_or_default().to_string();
+                format!("{:x}", Sha256::digest(plays))
+            }
+        };
+
+        Ok(RustlePlanOutput {
+            schema_version: PLAN_SCHEMA_VERSION,
+            metadata: RustlePlanMetadata {
+                created_at: chrono::Utc::now(),
+                rustle_plan_version: env!("CARGO_PKG_VERSION").to_string(),
+                playbook_hash,
+                inventory_hash: format!("{:x}", Sha256::digest(sorted_hosts.join("\n"))),
+                planning_options: self.planning_options,
+            },
+            total_tasks: all_tasks().count() as u32,
+            plays,
+            binary_deployments: self.binary_deployments,
+            estimated_duration: None,
+            estimated_compilation_time: None,
+            parallelism_score: 0.0,
+            network_efficiency_score: 0.0,
+            hosts,
+        })
+    }
+}
+
+impl Default for PlanBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds a [`PlayPlan`], its tasks run in the order they are added
+#[derive(Debug, Clone)]
+pub struct PlayBuilder {
+    name: String,
+    hosts: Vec<String>,
+    strategy: ExecutionStrategy,
+    serial: Option<u32>,
+    tasks: Vec<TaskBuilder>,
+    handlers: Vec<HandlerBuilder>,
+    environment: HashMap<String, String>,
+    module_defaults: ModuleDefaults,
+    timeout: Option<Duration>,
+    any_errors_fatal: bool,
+}
+
+impl PlayBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            hosts: Vec::new(),
+            strategy: ExecutionStrategy::Linear,
+            serial: None,
+            tasks: Vec::new(),
+            handlers: Vec::new(),
+            environment: HashMap::new(),
+            module_defaults: ModuleDefaults::new(),
+            timeout: None,
+            any_errors_fatal: false,
+        }
+    }
+
+    pub fn with_hosts<I, S>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.hosts.extend(hosts.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_strategy(mut self, strategy: ExecutionStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn with_serial(mut self, serial: u32) -> Self {
+        self.serial = Some(serial);
+        self
+    }
+
+    pub fn with_task(mut self, task: TaskBuilder) -> Self {
+        self.tasks.push(task);
+        self
+    }
+
+    pub fn with_handler(mut self, handler: HandlerBuilder) -> Self {
+        self.handlers.push(handler);
+        self
+    }
+
+    pub fn with_environment(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.environment.insert(name.into(), value.into());
+        self
+    }
+
+    /// Default parameters of `module` for the play's tasks
+    pub fn with_module_defaults(
+        mut self,
+        module: impl Into<String>,
+        defaults: HashMap<String, Value>,
+    ) -> Self {
+        self.module_defaults.insert(module.into(), defaults);
+        self
+    }
+
+    /// Deadline for the tasks and handlers of the play together
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_any_errors_fatal(mut self, any_errors_fatal: bool) -> Self {
+        self.any_errors_fatal = any_errors_fatal;
+        self
+    }
+
+    /// The play at `index` of its plan, numbering its tasks and handlers
+    /// after the `tasks` and `handlers` of the plays before it
+    fn build(
+        self,
+        index: usize,
+        tasks: &mut usize,
+        handlers: &mut usize,
+    ) -> Result<PlayPlan, PlanBuildError> {
+        if self.hosts.is_empty() {
+            return Err(PlanBuildError::NoHosts { play: self.name });
+        }
+        let planned: Vec<TaskPlan> = self
+            .tasks
+            .into_iter()
+            .map(|task| {
+                let mut task = task.task;
+                if task.task_id.is_empty() {
+                    task.task_id = format!("task_{tasks}");
+                }
+                if task.hosts.is_empty() {
+                    task.hosts = self.hosts.clone();
+                }
+                task.execution_order = *tasks as u32;
+                *tasks += 1;
+                task
+            })
+            .collect();
+        let handler_definitions = self
+            .handlers
+            .into_iter()
+            .map(|handler| {
+                let mut handler = handler.handler;
+                if handler.handler_id.is_empty() {
+                    handler.handler_id = format!("handler_{handlers}");
+                }
+                handler.execution_order = *handlers as u32;
+                *handlers += 1;
+                handler
+            })
+            .collect();
+
+        Ok(PlayPlan {
+            play_id: format!("play-{index}"),
+            name: self.name,
+            strategy: self.strategy,
+            serial: self.serial,
+            hosts: self.hosts.clone(),
+            batches: vec![TaskBatch {
+                batch_id: format!("play-{index}-tasks"),
+                hosts: self.hosts,
+                tasks: planned,
+                parallel_groups: vec![],
+                dependencies: vec![],
+                estimated_duration: None,
+            }],
+            handlers: handler_definitions,
+            blocks: vec![],
+            estimated_duration: None,
+            environment: self.environment,
+            module_defaults: self.module_defaults,
+            timeout: self.timeout,
+            any_errors_fatal: self.any_errors_fatal,
+        })
+    }
+}
+
+/// Builds a [`TaskPlan`] running a module with the arguments given to it
+#[derive(Debug, Clone)]
+pub struct TaskBuilder {
+    task: TaskPlan,
+}
+
+impl TaskBuilder {
+    /// A task running `module`, on every host of its play unless limited
+    pub fn new(name: impl Into<String>, module: impl Into<String>) -> Self {
+        Self {
+            task: TaskPlan {
+                task_id: String::new(),
+                name: name.into(),
+                module: module.into(),
+                args: HashMap::new(),
+                hosts: vec![],
+                dependencies: vec![],
+                conditions: vec![],
+                tags: vec![],
+                notify: vec![],
+                execution_order: 0,
+                can_run_parallel: false,
+                estimated_duration: Duration::from_secs(1),
+                // Failed tasks stop the host unless they ignore errors
+                risk_level: RiskLevel::High,
+                run_once: false,
+                delegate_to: None,
+                task_loop: None,
+                until: None,
+                async_job: None,
+                failed_when: None,
+                changed_when: None,
+                ignore_errors: false,
+                register: None,
+                no_log: false,
+                r#become: None,
+                environment: HashMap::new(),
+                module_defaults: ModuleDefaults::new(),
+                timeout: None,
+                throttle: None,
+            },
+        }
+    }
+
+    /// Use `id` instead of numbering the task after those before it
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.task.task_id = id.into();
+        self
+    }
+
+    pub fn with_arg(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.task.args.insert(name.into(), value.into());
+        self
+    }
+
+    /// Only run the task on these hosts of its play
+    pub fn with_hosts<I, S>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.task.hosts.extend(hosts.into_iter().map(Into::into));
+        self
+    }
+
+    /// Run the task after the task `id`
+    pub fn with_dependency(mut self, id: impl Into<String>) -> Self {
+        self.task.dependencies.push(id.into());
+        self
+    }
+
+    /// Only run the task when the Jinja2 `expression` holds
+    pub fn with_when(mut self, expression: impl Into<String>) -> Self {
+        self.task.conditions.push(TaskCondition::When {
+            expression: expression.into(),
+        });
+        self
+    }
+
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.task.tags.push(tag.into());
+        self
+    }
+
+    /// Notify the handler named, or listening to, `handler` when the task
+    /// changes something
+    pub fn with_notify(mut self, handler: impl Into<String>) -> Self {
+        self.task.notify.push(handler.into());
+        self
+    }
+
+    pub fn with_register(mut self, variable: impl Into<String>) -> Self {
+        self.task.register = Some(variable.into());
+        self
+    }
+
+    pub fn with_loop(mut self, task_loop: TaskLoop) -> Self {
+        self.task.task_loop = Some(task_loop);
+        self
+    }
+
+    pub fn with_until(mut self, until: UntilPolicy) -> Self {
+        self.task.until = Some(until);
+        self
+    }
+
+    pub fn with_async(mut self, async_job: AsyncPolicy) -> Self {
+        self.task.async_job = Some(async_job);
+        self
+    }
+
+    pub fn with_failed_when(mut self, condition: ResultCondition) -> Self {
+        self.task.failed_when = Some(condition);
+        self
+    }
+
+    pub fn with_changed_when(mut self, condition: ResultCondition) -> Self {
+        self.task.changed_when = Some(condition);
+        self
+    }
+
+    pub fn with_become(mut self, policy: BecomePolicy) -> Self {
+        self.task.r#become = Some(policy);
+        self
+    }
+
+    pub fn with_delegate_to(mut self, host: impl Into<String>) -> Self {
+        self.task.delegate_to = Some(host.into());
+        self
+    }
+
+    pub fn with_environment(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.task.environment.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.task.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_throttle(mut self, throttle: u32) -> Self {
+        self.task.throttle = Some(throttle);
+        self
+    }
+
+    pub fn with_run_once(mut self, run_once: bool) -> Self {
+        self.task.run_once = run_once;
+        self
+    }
+
+    pub fn with_ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.task.ignore_errors = ignore_errors;
+        self
+    }
+
+    pub fn with_no_log(mut self, no_log: bool) -> Self {
+        self.task.no_log = no_log;
+        self
+    }
+}
+
+/// Builds a [`HandlerDefinition`], run once at the end of its play when
+/// notified
+#[derive(Debug, Clone)]
+pub struct HandlerBuilder {
+    handler: HandlerDefinition,
+}
+
+impl HandlerBuilder {
+    pub fn new(name: impl Into<String>, module: impl Into<String>) -> Self {
+        Self {
+            handler: HandlerDefinition {
+                handler_id: String::new(),
+                name: name.into(),
+                module: module.into(),
+                args: HashMap::new(),
+                conditions: vec![],
+                execution_order: 0,
+                listen: vec![],
+                notify: vec![],
+            },
+        }
+    }
+
+    /// Use `id` instead of numbering the handler after those before it
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.handler.handler_id = id.into();
+        self
+    }
+
+    pub fn with_arg(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.handler.args.insert(name.into(), value.into());
+        self
+    }
+
+    /// Also run the handler when `topic` is notified
+    pub fn with_listen(mut self, topic: impl Into<String>) -> Self {
+        self.handler.listen.push(topic.into());
+        self
+    }
+
+    /// Notify the handler named, or listening to, `handler` when this one
+    /// changes something
+    pub fn with_notify(mut self, handler: impl Into<String>) -> Self {
+        self.handler.notify.push(handler.into());
+        self
+    }
+
+    /// Only run the handler when the Jinja2 `expression` holds
+    pub fn with_when(mut self, expression: impl Into<String>) -> Self {
+        self.handler.conditions.push(TaskCondition::When {
+            expression: expression.into(),
+        });
+        self
+    }
+}
@@ -3,13 +3,15 @@ use std::collections::HashMap;
 use super::binary_analyzer::BinaryDeploymentAnalyzer;
 use super::compatibility::ConversionError;
 use super::plan::{
-    BackoffStrategy, Condition, ConditionOperator, ConnectionConfig, ConnectionMethod,
+    BackoffStrategy, Block, Condition, ConditionOperator, ConnectionConfig, ConnectionMethod,
     DeploymentConfig, ExecutionPlan, ExecutionPlanMetadata, FactDefinition, FactParser,
-    FactsTemplate, FailurePolicy, Host, HostGroup, InventoryFormat, InventorySource, InventorySpec,
-    ModuleSource, ModuleSpec, RetryPolicy, TargetSelector, Task, TaskType,
+    FactsTemplate, FailurePolicy, Handler, Host, HostGroup, InventoryFormat, InventorySource,
+    InventorySpec, ModuleSource, ModuleSpec, Play, RetryPolicy, SerialBatch, TargetSelector, Task,
+    TaskType,
 };
 use super::rustle_plan::{
-    BinaryDeploymentPlan, RiskLevel, RustlePlanOutput, TaskCondition, TaskPlan,
+    BinaryDeploymentPlan, BlockDefinition, ModuleDefaults, PlayPlan, RiskLevel, RustlePlanOutput,
+    TaskCondition, TaskPlan,
 };
 
 pub struct RustlePlanConverter {
@@ -28,22 +30,81 @@ impl RustlePlanConverter {
         rustle_plan: &RustlePlanOutput,
     ) -> Result<ExecutionPlan, ConversionError> {
         let mut tasks = Vec::new();
+        let mut handlers = Vec::new();
+        let mut blocks = Vec::new();
+        let mut plays = Vec::new();
 
         // Convert play-based structure to flat task list
         for play in &rustle_plan.plays {
             for batch in &play.batches {
                 for task in &batch.tasks {
-                    let converted_task = self.convert_task(task, play.play_id.as_str())?;
+                    let mut converted_task = self.convert_task(task, play.play_id.as_str())?;
+                    // Task settings override those of the blocks around it,
+                    // which override the play's
+                    let blocks = enclosing_blocks(play, &task.task_id);
+                    converted_task.environment = merge_environments(
+                        std::iter::once(&play.environment)
+                            .chain(blocks.iter().map(|block| &block.environment))
+                            .chain([&task.environment]),
+                    );
+                    converted_task.args = with_module_defaults(
+                        &task.module,
+                        &task.args,
+                        std::iter::once(&play.module_defaults)
+                            .chain(blocks.iter().map(|block| &block.module_defaults))
+                            .chain([&task.module_defaults]),
+                    );
                     tasks.push(converted_task);
                 }
             }
+
+            let mut definitions: Vec<_> = play.handlers.iter().collect();
+            definitions.sort_by_key(|handler| handler.execution_order);
+            for handler in definitions {
+                let (conditions, when) = self.convert_conditions(&handler.conditions)?;
+                handlers.push(Handler {
+                    id: handler.handler_id.clone(),
+                    name: handler.name.clone(),
+                    module: handler.module.clone(),
+                    args: with_module_defaults(
+                        &handler.module,
+                        &handler.args,
+                        [&play.module_defaults],
+                    ),
+                    conditions,
+                    when,
+                    listen: handler.listen.clone(),
+                    notify: handler.notify.clone(),
+                    play_id: Some(play.play_id.clone()),
+                    environment: play.environment.clone(),
+                });
+            }
+            blocks.extend(play.blocks.iter().map(|block| Block {
+                id: block.block_id.clone(),
+                name: block.name.clone(),
+                block: block.block.clone(),
+                rescue: block.rescue.clone(),
+                always: block.always.clone(),
+            }));
+            plays.push(Play {
+                id: play.play_id.clone(),
+                name: Some(play.name.clone()),
+                timeout: play.timeout,
+                any_errors_fatal: play.any_errors_fatal,
+            });
         }
 
         let metadata = self.convert_metadata(rustle_plan)?;
         let inventory = self.construct_inventory_spec(&rustle_plan.hosts)?;
         let strategy = rustle_plan.metadata.planning_options.strategy.clone();
         let facts_template = self.create_default_facts_template();
-        let deployment_config = self.create_default_deployment_config();
+        let mut deployment_config = self.create_default_deployment_config();
+        deployment_config.serial = rustle_plan
+            .metadata
+            .planning_options
+            .serial
+            .or_else(|| rustle_plan.plays.iter().find_map(|play| play.serial))
+            .map(|hosts| SerialBatch::Hosts(hosts as usize));
         let modules = self.extract_module_specs(rustle_plan)?;
 
         Ok(ExecutionPlan {
@@ -54,6 +115,9 @@ impl RustlePlanConverter {
             facts_template,
             deployment_config,
             modules,
+            handlers,
+            blocks,
+            plays,
         })
     }
 
@@ -86,9 +150,9 @@ impl RustlePlanConverter {
         }
     }
 
-    fn convert_task(&self, task: &TaskPlan, _play_id: &str) -> Result<Task, ConversionError> {
+    fn convert_task(&self, task: &TaskPlan, play_id: &str) -> Result<Task, ConversionError> {
         let task_type = self.convert_module_to_task_type(&task.module)?;
-        let conditions = self.convert_conditions(&task.conditions)?;
+        let (conditions, when) = self.convert_conditions(&task.conditions)?;
         let target_hosts = TargetSelector::Hosts(task.hosts.clone());
         let failure_policy = self.determine_failure_policy(&task.risk_level);
 
@@ -101,9 +165,26 @@ impl RustlePlanConverter {
             dependencies: task.dependencies.clone(),
             conditions,
             target_hosts,
-            timeout: Some(task.estimated_duration),
+            timeout: task.timeout,
             retry_policy: self.create_retry_policy(&task.risk_level),
             failure_policy,
+            run_once: task.run_once,
+            delegate_to: task.delegate_to.clone(),
+            notify: task.notify.clone(),
+            play_id: Some(play_id.to_string()),
+            task_loop: task.task_loop.clone(),
+            until: task.until.clone(),
+            async_job: task.async_job.clone(),
+            failed_when: task.failed_when.clone(),
+            changed_when: task.changed_when.clone(),
+            ignore_errors: task.ignore_errors,
+            register: task.register.clone(),
+            when,
+            no_log: task.no_log,
+            r#become: task.r#become.clone(),
+            environment: task.environment.clone(),
+            parallel: task.can_run_parallel,
+            throttle: task.throttle,
         })
     }
 
@@ -135,20 +216,18 @@ impl RustlePlanConverter {
         }
     }
 
+    /// Tag and host conditions, and the `when` expressions of `when` and
+    /// `skip` conditions
     fn convert_conditions(
         &self,
         conditions: &[TaskCondition],
-    ) -> Result<Vec<Condition>, ConversionError> {
+    ) -> Result<(Vec<Condition>, Vec<String>), ConversionError> {
         let mut converted = Vec::new();
+        let mut when = Vec::new();
 
         for condition in conditions {
             match condition {
-                TaskCondition::When { expression } => {
-                    // Parse simple when expressions
-                    if let Some(condition) = self.parse_when_expression(expression) {
-                        converted.push(condition);
-                    }
-                }
+                TaskCondition::When { expression } => when.push(expression.clone()),
                 TaskCondition::Tag { tags } => {
                     // Convert tag conditions to existence checks
                     for tag in tags {
@@ -172,80 +251,11 @@ impl RustlePlanConverter {
                         ),
                     });
                 }
-                TaskCondition::Skip { condition } => {
-                    // Convert skip conditions to negated when conditions
-                    if let Some(mut skip_condition) = self.parse_when_expression(condition) {
-                        // Negate the operator
-                        skip_condition.operator = match skip_condition.operator {
-                            ConditionOperator::Equals => ConditionOperator::NotEquals,
-                            ConditionOperator::NotEquals => ConditionOperator::Equals,
-                            ConditionOperator::Exists => ConditionOperator::NotExists,
-                            ConditionOperator::NotExists => ConditionOperator::Exists,
-                            other => other, // Keep others as-is
-                        };
-                        converted.push(skip_condition);
-                    }
-                }
+                TaskCondition::Skip { condition } => when.push(format!("not ({condition})")),
             }
         }
 
-        Ok(converted)
-    }
-
-    fn parse_when_expression(&self, expression: &str) -> Option<Condition> {
-        // Parse simple expressions like "var is defined", "var == 'value'", etc.
-        let trimmed = expression.trim();
-
-        if trimmed.ends_with("is defined") {
-            let var_name = trimmed.strip_suffix("is defined")?.trim();
-            return Some(Condition {
-                variable: var_name.to_string(),
-                operator: ConditionOperator::Exists,
-                value: serde_json::Value::Bool(true),
-            });
-        }
-
-        if trimmed.ends_with("is not defined") {
-            let var_name = trimmed.strip_suffix("is not defined")?.trim();
-            return Some(Condition {
-                variable: var_name.to_string(),
-                operator: ConditionOperator::NotExists,
-                value: serde_json::Value::Bool(true),
-            });
-        }
-
-        if let Some(eq_pos) = trimmed.find(" == ") {
-            let var_name = trimmed[..eq_pos].trim();
-            let value_str = trimmed[eq_pos + 4..]
-                .trim()
-                .trim_matches('"')
-                .trim_matches('\'');
-            return Some(Condition {
-                variable: var_name.to_string(),
-                operator: ConditionOperator::Equals,
-                value: serde_json::Value::String(value_str.to_string()),
-            });
-        }
-
-        if let Some(ne_pos) = trimmed.find(" != ") {
-            let var_name = trimmed[..ne_pos].trim();
-            let value_str = trimmed[ne_pos + 4..]
-                .trim()
-                .trim_matches('"')
-                .trim_matches('\'');
-            return Some(Condition {
-                variable: var_name.to_string(),
-                operator: ConditionOperator::NotEquals,
-                value: serde_json::Value::String(value_str.to_string()),
-            });
-        }
-
-        // Default: treat the whole expression as a variable existence check
-        Some(Condition {
-            variable: trimmed.to_string(),
-            operator: ConditionOperator::Exists,
-            value: serde_json::Value::Bool(true),
-        })
+        Ok((converted, when))
     }
 
     fn determine_failure_policy(&self, risk_level: &RiskLevel) -> FailurePolicy {
@@ -356,6 +366,11 @@ impl RustlePlanConverter {
             verify_deployment: true,
             cleanup_on_success: false,
             deployment_timeout: Some(std::time::Duration::from_secs(1800)), // 30 minutes
+            maintenance_windows: Vec::new(),
+            wait_for_window: false,
+            hooks: Default::default(),
+            serial: None,
+            max_fail_percentage: None,
         }
     }
 
@@ -415,6 +430,67 @@ impl Default for RustlePlanConverter {
     }
 }
 
+/// The blocks of `play` around the task or block `id`, the outermost first
+fn enclosing_blocks<'a>(play: &'a PlayPlan, id: &str) -> Vec<&'a BlockDefinition> {
+    let mut blocks = Vec::new();
+    let mut id = id;
+    while let Some(block) = play.blocks.iter().find(|block| {
+        block
+            .block
+            .iter()
+            .chain(&block.rescue)
+            .chain(&block.always)
+            .any(|member| member == id)
+    }) {
+        // Blocks cannot contain themselves; stop at a plan that says otherwise
+        if blocks.len() == play.blocks.len() {
+            break;
+        }
+        blocks.push(block);
+        id = &block.block_id;
+    }
+    blocks.reverse();
+    blocks
+}
+
+/// `environments`, least specific first, merged so that later ones win
+fn merge_environments<'a>(
+    environments: impl IntoIterator<Item = &'a HashMap<String, String>>,
+) -> HashMap<String, String> {
+    environments
+        .into_iter()
+        .flat_map(|environment| environment.clone())
+        .collect()
+}
+
+/// `args` of `module` completed with its defaults in `scopes`, least specific
+/// first. Later scopes win, and the arguments a task gives win over all.
+fn with_module_defaults<'a>(
+    module: &str,
+    args: &HashMap<String, serde_json::Value>,
+    scopes: impl IntoIterator<Item = &'a ModuleDefaults>,
+) -> HashMap<String, serde_json::Value> {
+    fn short_name(name: &str) -> &str {
+        name.rsplit('.').next().unwrap_or(name)
+    }
+    let mut resolved = HashMap::new();
+    for defaults in scopes {
+        // Within a scope, defaults for the exact name win over the others
+        let mut matching: Vec<_> = defaults
+            .iter()
+            .filter(|(name, _)| {
+                name.as_str() == module || short_name(name) == module || *name == short_name(module)
+            })
+            .collect();
+        matching.sort_by_key(|(name, _)| name.as_str() == module);
+        for (_, values) in matching {
+            resolved.extend(values.clone());
+        }
+    }
+    resolved.extend(args.clone());
+    resolved
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -426,6 +502,7 @@ mod tests {
         use super::super::rustle_plan::*;
 
         RustlePlanOutput {
+            schema_version: super::super::plan_schema::PLAN_SCHEMA_VERSION,
             metadata: RustlePlanMetadata {
                 created_at: Utc::now(),
                 rustle_plan_version: "0.1.0".to_string(),
@@ -475,13 +552,33 @@ mod tests {
                         can_run_parallel: true,
                         estimated_duration: Duration::from_secs(1),
                         risk_level: RiskLevel::Low,
+                        run_once: false,
+                        delegate_to: None,
+                        task_loop: None,
+                        until: None,
+                        async_job: None,
+                        failed_when: None,
+                        changed_when: None,
+                        ignore_errors: false,
+                        register: None,
+                        no_log: false,
+                        r#become: None,
+                        environment: HashMap::new(),
+                        module_defaults: HashMap::new(),
+                        timeout: None,
+                        throttle: None,
                     }],
                     parallel_groups: vec![],
                     dependencies: vec![],
                     estimated_duration: None,
                 }],
                 handlers: vec![],
+                blocks: vec![],
                 estimated_duration: None,
+                environment: HashMap::new(),
+                module_defaults: HashMap::new(),
+                timeout: None,
+                any_errors_fatal: false,
             }],
             binary_deployments: vec![],
             total_tasks: 1,
@@ -507,6 +604,39 @@ mod tests {
         assert_eq!(execution_plan.tasks[0].module, "debug");
     }
 
+    #[test]
+    fn test_convert_handlers_and_notify() {
+        use super::super::rustle_plan::HandlerDefinition;
+
+        let converter = RustlePlanConverter::new();
+        let mut rustle_plan = create_test_rustle_plan();
+        let play = &mut rustle_plan.plays[0];
+        play.batches[0].tasks[0].notify = vec!["restart web".to_string()];
+        let handler = |id: &str, order: u32| HandlerDefinition {
+            handler_id: id.to_string(),
+            name: id.to_string(),
+            module: "service".to_string(),
+            args: HashMap::new(),
+            conditions: vec![],
+            execution_order: order,
+            listen: vec!["restart web".to_string()],
+            notify: vec![],
+        };
+        play.handlers = vec![handler("reload", 2), handler("restart", 1)];
+
+        let execution_plan = converter.convert_to_execution_plan(&rustle_plan).unwrap();
+        let task = &execution_plan.tasks[0];
+        assert_eq!(task.notify, ["restart web"]);
+        assert_eq!(task.play_id.as_deref(), Some("play-1"));
+        let handlers: Vec<&str> = execution_plan
+            .handlers
+            .iter()
+            .map(|handler| handler.id.as_str())
+            .collect();
+        assert_eq!(handlers, ["restart", "reload"]);
+        assert_eq!(execution_plan.handlers[0].listen, ["restart web"]);
+    }
+
     #[test]
     fn test_convert_task() {
         let converter = RustlePlanConverter::new();
@@ -524,6 +654,21 @@ mod tests {
             can_run_parallel: true,
             estimated_duration: Duration::from_secs(5),
             risk_level: RiskLevel::Medium,
+            run_once: false,
+            delegate_to: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            no_log: false,
+            r#become: None,
+            environment: HashMap::new(),
+            module_defaults: HashMap::new(),
+            timeout: None,
+            throttle: None,
         };
 
         let result = converter.convert_task(&task_plan, "play-1");
@@ -536,20 +681,77 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_when_expression() {
+    fn test_environment_and_module_defaults_precedence() {
+        let converter = RustlePlanConverter::new();
+        let mut rustle_plan = create_test_rustle_plan();
+        let play = &mut rustle_plan.plays[0];
+        let scope = |environment: serde_json::Value, module_defaults: serde_json::Value| {
+            (
+                serde_json::from_value::<HashMap<String, String>>(environment).unwrap(),
+                serde_json::from_value::<ModuleDefaults>(module_defaults).unwrap(),
+            )
+        };
+        (play.environment, play.module_defaults) = scope(
+            serde_json::json!({"LANG": "C", "PROXY": "play"}),
+            serde_json::json!({"debug": {"msg": "play", "verbosity": 1}}),
+        );
+        let (environment, module_defaults) = scope(
+            serde_json::json!({"PROXY": "block"}),
+            serde_json::json!({
+                "ansible.builtin.debug": {"verbosity": 2, "var": "fqcn"},
+                "debug": {"var": "block"},
+                "command": {"chdir": "/srv"},
+            }),
+        );
+        play.blocks = vec![BlockDefinition {
+            block_id: "block-1".to_string(),
+            name: None,
+            block: vec!["task-1".to_string()],
+            rescue: vec![],
+            always: vec![],
+            environment,
+            module_defaults,
+        }];
+        let task = &mut play.batches[0].tasks[0];
+        (task.environment, task.module_defaults) =
+            scope(serde_json::json!({"TZ": "UTC"}), serde_json::json!({}));
+
+        let execution_plan = converter.convert_to_execution_plan(&rustle_plan).unwrap();
+        let task = &execution_plan.tasks[0];
+        assert_eq!(
+            task.environment,
+            serde_json::from_value::<HashMap<String, String>>(
+                serde_json::json!({"LANG": "C", "PROXY": "block", "TZ": "UTC"})
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&task.args).unwrap(),
+            serde_json::json!({"msg": "Hello", "verbosity": 2, "var": "block"})
+        );
+    }
+
+    #[test]
+    fn test_convert_when_conditions() {
         let converter = RustlePlanConverter::new();
 
-        let condition = converter.parse_when_expression("test_var is defined");
-        assert!(condition.is_some());
-        let cond = condition.unwrap();
-        assert_eq!(cond.variable, "test_var");
-        assert!(matches!(cond.operator, ConditionOperator::Exists));
-
-        let condition = converter.parse_when_expression("var == 'value'");
-        assert!(condition.is_some());
-        let cond = condition.unwrap();
-        assert_eq!(cond.variable, "var");
-        assert!(matches!(cond.operator, ConditionOperator::Equals));
+        let (conditions, when) = converter
+            .convert_conditions(&[
+                TaskCondition::When {
+                    expression: "test_var is defined".to_string(),
+                },
+                TaskCondition::Skip {
+                    condition: "var == 'value'".to_string(),
+                },
+                TaskCondition::Tag {
+                    tags: vec!["web".to_string()],
+                },
+            ])
+            .unwrap();
+        assert_eq!(when, ["test_var is defined", "not (var == 'value')"]);
+        assert_eq!(conditions.len(), 1);
+        assert_eq!(conditions[0].variable, "tags.web");
+        assert!(matches!(conditions[0].operator, ConditionOperator::Exists));
     }
 
     #[test]
@@ -0,0 +1,339 @@
+//! Versions of the rustle-plan schema and the steps migrating plans between
+//! them.
+//!
+//! A plan records the version of the schema it was written in, and plans
+//! written before the schema was versioned are of
+//! [`LEGACY_SCHEMA_VERSION`]. Each registered [`MigrationStep`] rewrites a
+//! plan from one version to the next, on its JSON so that plans the current
+//! types cannot read are migrated too. In [`ParseMode::Strict`] a step that
+//! cannot migrate a plan, or a plan newer than this version of
+//! rustle-deploy, is an error; in [`ParseMode::Lenient`] both are reported
+//! as warnings.
+//!
+//! [`plan_json_schema`] is the JSON Schema of the current version, generated
+//! from the types plans are parsed into, for tools writing plans to check
+//! them against.
+
+use crate::execution::format_migration::MigrationError;
+use crate::execution::rustle_plan::RustlePlanOutput;
+use schemars::JsonSchema;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Version of the schema plans are parsed into
+pub const PLAN_SCHEMA_VERSION: u32 = 2;
+
+/// Version of plans without a `schema_version`, written before the schema
+/// was versioned
+pub const LEGACY_SCHEMA_VERSION: u32 = 1;
+
+/// Field of the plan recording the version of its schema
+pub const SCHEMA_VERSION_FIELD: &str = "schema_version";
+
+/// How a plan that does not fit the current schema is handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Refuse plans that cannot be fully migrated, or are newer than the
+    /// current schema
+    Strict,
+    /// Migrate what can be, warning about the rest
+    #[default]
+    Lenient,
+}
+
+/// Rewrites plans of one schema version into the next
+#[derive(Clone)]
+pub struct MigrationStep {
+    /// Version the step migrates from, to the one after it
+    pub from: u32,
+    pub description: String,
+    migrate: fn(&mut Value, &mut Vec<String>) -> Result<(), MigrationError>,
+}
+
+impl MigrationStep {
+    /// A step rewriting plans of version `from`, pushing what the caller
+    /// should know about the plan to the warnings it is given
+    pub fn new(
+        from: u32,
+        description: impl Into<String>,
+        migrate: fn(&mut Value, &mut Vec<String>) -> Result<(), MigrationError>,
+    ) -> Self {
+        Self {
+            from,
+            description: description.into(),
+            migrate,
+        }
+    }
+}
+
+impl std::fmt::Debug for MigrationStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MigrationStep")
+            .field("from", &self.from)
+            .field("description", &self.description)
+            .finish()
+    }
+}
+
+/// What migrating a plan did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    /// Descriptions of the steps applied, in order
+    pub applied: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl MigrationReport {
+    /// Whether the plan was already of the current schema
+    pub fn is_current(&self) -> bool {
+        self.applied.is_empty()
+    }
+}
+
+/// The registered migration steps, by the version they migrate from
+#[derive(Debug, Clone)]
+pub struct PlanMigrations {
+    steps: BTreeMap<u32, MigrationStep>,
+    current: u32,
+}
+
+impl PlanMigrations {
+    /// The steps migrating every earlier schema to [`PLAN_SCHEMA_VERSION`]
+    pub fn new() -> Self {
+        Self {
+            steps: BTreeMap::new(),
+            current: PLAN_SCHEMA_VERSION,
+        }
+        .with_step(MigrationStep::new(
+            1,
+            "Move legacy binary deployment fields to their current names",
+            migrate_legacy_deployments,
+        ))
+    }
+
+    /// Register `step`, replacing any registered from the same version
+    pub fn with_step(mut self, step: MigrationStep) -> Self {
+        self.steps.insert(step.from, step);
+        self
+    }
+
+    /// Migrate to `version` instead of [`PLAN_SCHEMA_VERSION`]
+    pub fn with_current_version(mut self, version: u32) -> Self {
+        self.current = version;
+        self
+    }
+
+    pub fn current_version(&self) -> u32 {
+        self.current
+    }
+
+    /// Rewrite `plan` into the current schema, one step at a time
+    pub fn migrate(
+        &self,
+        plan: &mut Value,
+        mode: ParseMode,
+    ) -> Result<MigrationReport, MigrationError> {
+        let version = schema_version(plan)?;
+        let mut report = MigrationReport {
+            from_version: version,
+            to_version: version,
+            ..MigrationReport::default()
+        };
+
+        if version > self.current {
+            let error = MigrationError::NewerVersion {
+                version,
+                supported: self.current,
+            };
+            if mode == ParseMode::Strict {
+                return Err(error);
+            }
+            report
+                .warnings
+                .push(format!("{error}; fields it does not know are ignored"));
+            return Ok(report);
+        }
+
+        while report.to_version < self.current {
+            let step = self.steps.get(&report.to_version).ok_or_else(|| {
+                MigrationError::UnsupportedVersion {
+                    version: report.to_version.to_string(),
+                }
+            })?;
+            let mut warnings = Vec::new();
+            match (step.migrate)(plan, &mut warnings) {
+                Ok(()) => {}
+                Err(e) if mode == ParseMode::Strict => return Err(e),
+                Err(e) => warnings.push(format!("{}: {e}", step.description)),
+            }
+            report.warnings.append(&mut warnings);
+            report.applied.push(step.description.clone());
+            report.to_version += 1;
+            if let Some(plan) = plan.as_object_mut() {
+                plan.insert(SCHEMA_VERSION_FIELD.to_string(), report.to_version.into());
+            }
+        }
+        Ok(report)
+    }
+}
+
+impl Default for PlanMigrations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Version of the schema `plan` was written in
+pub fn schema_version(plan: &Value) -> Result<u32, MigrationError> {
+    match plan.get(SCHEMA_VERSION_FIELD) {
+        None | Some(Value::Null) => Ok(LEGACY_SCHEMA_VERSION),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .filter(|version| *version >= LEGACY_SCHEMA_VERSION)
+            .ok_or_else(|| MigrationError::UnsupportedVersion {
+                version: version.to_string(),
+            }),
+    }
+}
+
+pub(crate) fn legacy_schema_version() -> u32 {
+    LEGACY_SCHEMA_VERSION
+}
+
+/// JSON Schema of plans of [`PLAN_SCHEMA_VERSION`]
+pub fn plan_json_schema() -> Value {
+    let mut schema = schemars::schema_for!(RustlePlanOutput);
+    schema.schema.metadata().description = Some(format!(
+        "rustle-plan execution plan, schema version {PLAN_SCHEMA_VERSION}"
+    ));
+    serde_json::to_value(schema).expect("JSON Schemas serialize to JSON")
+}
+
+/// How plans write durations, for their schema
+#[derive(JsonSchema)]
+#[allow(dead_code)] // Only its schema is used
+pub(crate) struct DurationSchema {
+    secs: u64,
+    #[schemars(range(max = 999_999_999))]
+    nanos: u32,
+}
+
+/// Version 1 to 2: `task_ids` became `tasks`, and the target triple of
+/// compilation requirements became their architecture and OS
+fn migrate_legacy_deployments(
+    plan: &mut Value,
+    warnings: &mut Vec<String>,
+) -> Result<(), MigrationError> {
+    let Some(deployments) = plan
+        .get_mut("binary_deployments")
+        .and_then(Value::as_array_mut)
+    else {
+        return Ok(());
+    };
+
+    // Every deployment is migrated, the first that cannot be failing the
+    // step and the others reported
+    let mut failed = None;
+    for deployment in deployments {
+        if let Err(e) = migrate_legacy_deployment(deployment, warnings) {
+            match failed {
+                None => failed = Some(e),
+                Some(_) => warnings.push(e.to_string()),
+            }
+        }
+    }
+    failed.map_or(Ok(()), Err)
+}
+
+fn migrate_legacy_deployment(
+    deployment: &mut Value,
+    warnings: &mut Vec<String>,
+) -> Result<(), MigrationError> {
+    let Some(deployment) = deployment.as_object_mut() else {
+        return Ok(());
+    };
+    let id = deployment
+        .get("deployment_id")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+
+    let has_tasks = deployment
+        .get("tasks")
+        .and_then(Value::as_array)
+        .is_some_and(|tasks| !tasks.is_empty());
+    if !has_tasks {
+        match deployment.get("task_ids").filter(|ids| ids.is_array()) {
+            Some(task_ids) => {
+                let task_ids = task_ids.clone();
+                deployment.insert("tasks".to_string(), task_ids);
+            }
+            None => {
+                return Err(MigrationError::MissingRequiredField {
+                    field: format!("tasks or task_ids of deployment {id}"),
+                })
+            }
+        }
+    }
+
+    let unnamed = deployment
+        .get("binary_name")
+        .and_then(Value::as_str)
+        .is_none_or(str::is_empty);
+    if unnamed {
+        deployment.insert(
+            "binary_name".to_string(),
+            format!("rustle-runner-{id}").into(),
+        );
+    }
+
+    let Some(requirements) = deployment
+        .get_mut("compilation_requirements")
+        .and_then(Value::as_object_mut)
+    else {
+        return Ok(());
+    };
+    let missing = |field: &str| {
+        requirements
+            .get(field)
+            .and_then(Value::as_str)
+            .is_none_or(str::is_empty)
+    };
+    let (missing_arch, missing_os, missing_rust) = (
+        missing("target_arch"),
+        missing("target_os"),
+        missing("rust_version"),
+    );
+    if missing_arch || missing_os {
+        let (arch, os) = match requirements.get("target_triple").and_then(Value::as_str) {
+            Some(triple) => {
+                let parts: Vec<&str> = triple.split('-').collect();
+                if parts.len() < 3 {
+                    return Err(MigrationError::TargetArchitectureParsingFailed {
+                        target_triple: triple.to_string(),
+                    });
+                }
+                (parts[0].to_string(), parts[2].to_string())
+            }
+            None => {
+                warnings.push(format!(
+                    "Deployment {id} names no target, assuming x86_64 Linux"
+                ));
+                ("x86_64".to_string(), "linux".to_string())
+            }
+        };
+        if missing_arch {
+            requirements.insert("target_arch".to_string(), arch.into());
+        }
+        if missing_os {
+            requirements.insert("target_os".to_string(), os.into());
+        }
+    }
+    if missing_rust {
+        requirements.insert("rust_version".to_string(), "1.70.0".into());
+    }
+    Ok(())
+}
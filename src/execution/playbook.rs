@@ -0,0 +1,1553 @@
+//! Ansible playbooks as plans.
+//!
+//! [`PlaybookLoader`] reads a playbook, with the playbooks it imports, the
+//! task files it includes and the roles it applies, and plans its plays for
+//! the hosts of an inventory as rustle-plan would: a batch per play holding
+//! its tasks in order, along with their blocks and handlers. Playbooks then
+//! run without rustle-plan.
+//!
+//! The variables of plays, roles, blocks and tasks are layered by their
+//! precedence and rendered into the arguments and conditions of the tasks
+//! while planning. Expressions referring to other variables, such as facts
+//! and registered results, are left for the runner.
+//!
+//! Roles are applied as Ansible applies them: their dependencies first,
+//! once per play for the same parameters, with their defaults below and
+//! their variables above those of the play. The files and templates their
+//! tasks copy are looked up in the role, so that they are bundled into the
+//! runner like any other local source.
+//!
+//! Task files are inlined where `include_tasks` and `import_tasks` name
+//! them, once per item of their loop, and `include_vars` adds the variables
+//! it loads to the tasks after it; plans from rustle-plan that leave these
+//! to the runner are resolved the same way by
+//! [`PlaybookLoader::resolve_includes`]. Files that do not exist are all
+//! reported together before anything is built.
+
+use crate::execution::error::{MissingFile, PlaybookError};
+use crate::execution::plan::{
+    AsyncPolicy, BecomePolicy, ExecutionStrategy, LoopControl, LoopKind, ResultCondition, TaskLoop,
+};
+use crate::execution::plan_schema::PLAN_SCHEMA_VERSION;
+use crate::execution::rustle_plan::{
+    BlockDefinition, HandlerDefinition, ModuleDefaults, PlanningOptions, PlayPlan, RiskLevel,
+    RustlePlanMetadata, RustlePlanOutput, TaskBatch, TaskCondition, TaskPlan,
+};
+use crate::execution::{VarsFileLoader, VaultSecrets};
+use crate::inventory::{HostPattern, PrecedenceResolver, VariablePrecedence};
+use crate::modules::files::template_engine::AdvancedTemplateProcessor;
+use crate::types::inventory::ParsedInventory;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+
+/// Keywords of tasks, as opposed to the module they run
+const TASK_KEYWORDS: &[&str] = &[
+    "name",
+    "when",
+    "tags",
+    "notify",
+    "listen",
+    "register",
+    "loop",
+    "loop_control",
+    "ignore_errors",
+    "no_log",
+    "become",
+    "become_user",
+    "become_method",
+    "become_flags",
+    "environment",
+    "delegate_to",
+    "run_once",
+    "changed_when",
+    "failed_when",
+    "async",
+    "poll",
+    "timeout",
+    "throttle",
+    "vars",
+    "module_defaults",
+    "args",
+];
+
+/// Keywords accepted without changing how the runner runs the task
+const IGNORED_KEYWORDS: &[&str] = &["check_mode", "diff", "debugger", "collections"];
+
+/// Keywords that would change what a task does and are not planned yet
+const UNSUPPORTED_KEYWORDS: &[&str] = &[
+    "until",
+    "retries",
+    "delay",
+    "action",
+    "local_action",
+    "delegate_facts",
+];
+
+/// The loop keywords, and how they iterate
+const LOOP_KEYWORDS: &[(&str, LoopKind)] = &[
+    ("loop", LoopKind::Loop),
+    ("with_list", LoopKind::Loop),
+    ("with_items", LoopKind::WithItems),
+    ("with_dict", LoopKind::WithDict),
+    ("with_fileglob", LoopKind::WithFileglob),
+];
+
+/// Modules taking a free-form command rather than `key=value` arguments
+const FREE_FORM_MODULES: &[&str] = &["command", "shell", "raw", "script"];
+
+/// Modules reading a local `src`, and the directory of roles it is looked
+/// up in first
+const LOCAL_SOURCE_MODULES: &[(&str, &str)] = &[
+    ("copy", "files"),
+    ("unarchive", "files"),
+    ("template", "templates"),
+];
+
+/// Keywords including tasks, which are planned once per item of their loop
+const INCLUDES: &[&str] = &[
+    "include_tasks",
+    "import_tasks",
+    "include_role",
+    "import_role",
+];
+
+/// Collections whose modules are known by their short names
+const BUILTIN_COLLECTIONS: &[&str] = &["ansible.builtin.", "ansible.legacy."];
+
+/// Where plays targeting `localhost` run when the inventory does not name it
+const LOCALHOST: &str = "localhost";
+
+/// Reads playbooks into plans
+#[derive(Debug, Clone, Default)]
+pub struct PlaybookLoader {
+    roles_paths: Vec<PathBuf>,
+    vault: VaultSecrets,
+    vars_loader: VarsFileLoader,
+    extra_vars: HashMap<String, Value>,
+}
+
+impl PlaybookLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also look for roles in `path`, after the `roles/` next to the
+    /// playbook
+    pub fn with_roles_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.roles_paths.push(path.into());
+        self
+    }
+
+    /// Decrypt the vaulted values of playbooks, task files and roles with
+    /// `secrets`
+    pub fn with_vault(mut self, secrets: VaultSecrets) -> Self {
+        self.vault = secrets;
+        self
+    }
+
+    /// Load the `vars_files` of plays with `loader`
+    pub fn with_vars_loader(mut self, loader: VarsFileLoader) -> Self {
+        self.vars_loader = loader;
+        self
+    }
+
+    /// Plan with `vars` over those the playbook defines, as `-e` would
+    pub fn with_extra_vars(mut self, vars: HashMap<String, Value>) -> Self {
+        self.extra_vars = vars;
+        self
+    }
+
+    /// Plan the playbook at `path` for the hosts of `inventory`, or for
+    /// localhost alone without one
+    pub fn load(
+        &self,
+        path: &Path,
+        inventory: Option<&ParsedInventory>,
+    ) -> Result<RustlePlanOutput, PlaybookError> {
+        let content = std::fs::read(path).map_err(|source| PlaybookError::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+        let mut planner = Planner::new(self, inventory);
+        planner.plan_playbook(path)?;
+        if !planner.missing.is_empty() {
+            return Err(PlaybookError::MissingFiles(planner.missing));
+        }
+        let plays = planner.plays;
+        if plays.is_empty() {
+            return Err(PlaybookError::Invalid {
+                path: path.display().to_string(),
+                reason: "no play selects any host".to_string(),
+            });
+        }
+
+        let mut hosts: Vec<String> = Vec::new();
+        for host in plays.iter().flat_map(|play| &play.hosts) {
+            if !hosts.contains(host) {
+                hosts.push(host.clone());
+            }
+        }
+        let total_tasks = plays
+            .iter()
+            .flat_map(|play| &play.batches)
+            .map(|batch| batch.tasks.len() as u32)
+            .sum();
+        let mut sorted_hosts = hosts.clone();
+        sorted_hosts.sort();
+
+        Ok(RustlePlanOutput {
+            schema_version: PLAN_SCHEMA_VERSION,
+            metadata: RustlePlanMetadata {
+                created_at: chrono::Utc::now(),
+                rustle_plan_version: env!("CARGO_PKG_VERSION").to_string(),
+                playbook_hash: format!("{:x}", Sha256::digest(&content)),
+                inventory_hash: format!("{:x}", Sha256::digest(sorted_hosts.join("\n"))),
+                planning_options: PlanningOptions {
+                    limit: None,
+                    tags: vec![],
+                    skip_tags: vec![],
+                    check_mode: false,
+                    diff_mode: false,
+                    forks: 5,
+                    serial: None,
+                    strategy: plays[0].strategy.clone(),
+                    binary_threshold: 0,
+                    force_binary: true,
+                    force_ssh: false,
+                },
+            },
+            plays,
+            binary_deployments: vec![],
+            total_tasks,
+            estimated_duration: None,
+            estimated_compilation_time: None,
+            parallelism_score: 0.0,
+            network_efficiency_score: 0.0,
+            hosts,
+        })
+    }
+
+    /// Inline into `plan` the task files its `include_tasks` and
+    /// `import_tasks` name, relative to `base_dir`, once per item of their
+    /// loops, and render the variables its `include_vars` load into the
+    /// tasks after them. The tasks inlined inherit the conditions and tags
+    /// of the include, and take its place among the dependencies of other
+    /// tasks. Every file missing is reported at once.
+    pub fn resolve_includes(
+        &self,
+        plan: &mut RustlePlanOutput,
+        base_dir: &Path,
+    ) -> Result<(), PlaybookError> {
+        let mut planner = Planner::new(self, None);
+        // The ids of the tasks, and of the tasks and blocks, each include
+        // was replaced by
+        let mut inlined_tasks: HashMap<String, Vec<String>> = HashMap::new();
+        let mut inlined: HashMap<String, Vec<String>> = HashMap::new();
+
+        for play in &mut plan.plays {
+            let mut vars = HashMap::new();
+            for batch in &mut play.batches {
+                for mut task in std::mem::take(&mut batch.tasks) {
+                    let module = BUILTIN_COLLECTIONS
+                        .iter()
+                        .find_map(|prefix| task.module.strip_prefix(prefix))
+                        .unwrap_or(&task.module)
+                        .to_string();
+                    if !matches!(
+                        module.as_str(),
+                        "include_tasks" | "import_tasks" | "include_vars"
+                    ) {
+                        if !vars.is_empty() {
+                            for value in task.args.values_mut() {
+                                *value = planner.processor.render_value(value, &vars);
+                            }
+                        }
+                        batch.tasks.push(task);
+                        continue;
+                    }
+
+                    let scope = Scope {
+                        dir: base_dir.to_path_buf(),
+                        playbook_dir: base_dir.to_path_buf(),
+                        when: task.conditions.clone(),
+                        tags: task.tags.clone(),
+                        r#become: task.r#become.clone(),
+                        ignore_errors: task.ignore_errors,
+                        ..Scope::default()
+                    }
+                    .with_vars(VariablePrecedence::IncludeVars, vars.clone())?;
+                    let args = Value::Object(task.args.clone().into_iter().collect());
+                    let mut entry = Map::from_iter([
+                        ("name".to_string(), Value::String(task.name.clone())),
+                        (module.clone(), args.clone()),
+                    ]);
+                    if let Some(task_loop) = &task.task_loop {
+                        let (keyword, _) = LOOP_KEYWORDS
+                            .iter()
+                            .find(|(_, kind)| *kind == task_loop.kind)
+                            .expect("every kind of loop has a keyword");
+                        entry.insert(keyword.to_string(), task_loop.items.clone());
+                        entry.insert(
+                            "loop_control".to_string(),
+                            serde_json::to_value(&task_loop.control)
+                                .map_err(|e| invalid(base_dir, &e.to_string()))?,
+                        );
+                    }
+
+                    if module == "include_vars" {
+                        vars = planner.include_vars(&entry, &args, scope)?.vars;
+                        inlined_tasks.insert(task.task_id.clone(), Vec::new());
+                        inlined.insert(task.task_id, Vec::new());
+                        continue;
+                    }
+                    planner.prefix = format!("{}.", task.task_id);
+                    let mut planned = PlayTasks::default();
+                    let ids = planner.plan_entry(&entry, &scope, &task.hosts, &mut planned)?;
+                    inlined_tasks.insert(
+                        task.task_id.clone(),
+                        planned.tasks.iter().map(|t| t.task_id.clone()).collect(),
+                    );
+                    inlined.insert(task.task_id.clone(), ids);
+                    for mut included in planned.tasks {
+                        included.dependencies = task.dependencies.clone();
+                        included.execution_order = task.execution_order;
+                        batch.tasks.push(included);
+                    }
+                    play.blocks.extend(planned.blocks);
+                    play.handlers.extend(planned.handlers);
+                }
+            }
+        }
+        if !planner.missing.is_empty() {
+            return Err(PlaybookError::MissingFiles(planner.missing));
+        }
+        if inlined.is_empty() {
+            return Ok(());
+        }
+
+        let replace = |ids: &mut Vec<String>, replaced: &HashMap<String, Vec<String>>| {
+            *ids = ids
+                .iter()
+                .flat_map(|id| {
+                    replaced
+                        .get(id)
+                        .cloned()
+                        .unwrap_or_else(|| vec![id.clone()])
+                })
+                .collect();
+        };
+        for play in &mut plan.plays {
+            for task in play.batches.iter_mut().flat_map(|batch| &mut batch.tasks) {
+                replace(&mut task.dependencies, &inlined_tasks);
+            }
+            for block in &mut play.blocks {
+                replace(&mut block.block, &inlined);
+                replace(&mut block.rescue, &inlined);
+                replace(&mut block.always, &inlined);
+            }
+        }
+        for deployment in &mut plan.binary_deployments {
+            replace(&mut deployment.tasks, &inlined_tasks);
+        }
+        plan.total_tasks = plan
+            .plays
+            .iter()
+            .flat_map(|play| &play.batches)
+            .map(|batch| batch.tasks.len() as u32)
+            .sum();
+        Ok(())
+    }
+}
+
+/// Whether the file at `path` is a YAML playbook rather than a JSON plan
+pub fn is_playbook(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("yml" | "yaml")
+    )
+}
+
+/// Where the tasks being planned come from, and what they inherit
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    /// Directory the files the tasks name are relative to
+    dir: PathBuf,
+    /// Directory of the playbook, which roles are looked up next to
+    playbook_dir: PathBuf,
+    layers: Vec<(VariablePrecedence, HashMap<String, Value>)>,
+    /// The variables of the layers, rendered
+    vars: HashMap<String, Value>,
+    when: Vec<TaskCondition>,
+    tags: Vec<String>,
+    r#become: Option<BecomePolicy>,
+    ignore_errors: bool,
+    /// Role the tasks are from, which prefixes their names
+    role: Option<String>,
+    /// Directory of that role, where the files its tasks copy are
+    role_dir: Option<PathBuf>,
+}
+
+impl Scope {
+    /// The scope with `vars` from `precedence` over those it has
+    fn with_vars(
+        mut self,
+        precedence: VariablePrecedence,
+        vars: HashMap<String, Value>,
+    ) -> Result<Self, PlaybookError> {
+        if vars.is_empty() {
+            return Ok(self);
+        }
+        self.layers.push((precedence, vars));
+        let mut resolver = PrecedenceResolver::new();
+        for (precedence, layer) in &self.layers {
+            resolver.extend(*precedence, layer.clone());
+        }
+        self.vars = resolver.resolve_all()?;
+        Ok(self)
+    }
+}
+
+/// What the tasks of a play plan into
+#[derive(Default)]
+struct PlayTasks {
+    tasks: Vec<TaskPlan>,
+    blocks: Vec<BlockDefinition>,
+    handlers: Vec<HandlerDefinition>,
+    /// Roles whose handlers were added
+    roles: HashSet<PathBuf>,
+    /// Roles the play or their dependencies applied, with their parameters
+    applied: HashSet<String>,
+    /// Directories and names of the roles whose dependencies are being
+    /// planned, outermost first
+    role_chain: Vec<(PathBuf, String)>,
+}
+
+struct Planner<'a> {
+    loader: &'a PlaybookLoader,
+    inventory: Option<&'a ParsedInventory>,
+    processor: AdvancedTemplateProcessor,
+    /// Tasks, handlers and blocks planned so far, numbering the next
+    tasks: usize,
+    handlers: usize,
+    blocks: usize,
+    /// Prefix of the ids of what is planned, keeping the tasks inlined into
+    /// a plan apart from those it has
+    prefix: String,
+    plays: Vec<PlayPlan>,
+    /// Included files not found, all reported once planning is done
+    missing: Vec<MissingFile>,
+}
+
+impl<'a> Planner<'a> {
+    fn new(loader: &'a PlaybookLoader, inventory: Option<&'a ParsedInventory>) -> Self {
+        Self {
+            loader,
+            inventory,
+            processor: AdvancedTemplateProcessor::default(),
+            tasks: 0,
+            handlers: 0,
+            blocks: 0,
+            prefix: String::new(),
+            plays: Vec::new(),
+            missing: Vec::new(),
+        }
+    }
+
+    fn plan_playbook(&mut self, path: &Path) -> Result<(), PlaybookError> {
+        let dir = parent_dir(path);
+        let Value::Array(plays) = self.read_yaml(path)? else {
+            return Err(invalid(path, "a playbook is a list of plays"));
+        };
+        for play in &plays {
+            let Value::Object(play) = play else {
+                return Err(invalid(path, "a play is a mapping"));
+            };
+            if let Some(imported) = play.get("import_playbook") {
+                let imported = imported
+                    .as_str()
+                    .ok_or_else(|| invalid(path, "import_playbook names a file"))?;
+                let imported = dir.join(imported);
+                if imported.is_file() {
+                    self.plan_playbook(&imported)?;
+                } else {
+                    self.missing.push(MissingFile {
+                        path: imported,
+                        keyword: "import_playbook".to_string(),
+                        included_by: path.display().to_string(),
+                    });
+                }
+                continue;
+            }
+            if let Some(play) = self.plan_play(play, &dir, path)? {
+                self.plays.push(play);
+            }
+        }
+        Ok(())
+    }
+
+    fn plan_play(
+        &mut self,
+        play: &Map<String, Value>,
+        dir: &Path,
+        path: &Path,
+    ) -> Result<Option<PlayPlan>, PlaybookError> {
+        let pattern = match play.get("hosts") {
+            Some(Value::String(pattern)) => pattern.clone(),
+            Some(Value::Array(patterns)) => patterns
+                .iter()
+                .map(scalar_string)
+                .collect::<Vec<_>>()
+                .join(","),
+            _ => return Err(invalid(path, "every play names its hosts")),
+        };
+        let name = play
+            .get("name")
+            .map(scalar_string)
+            .unwrap_or_else(|| pattern.clone());
+        let hosts = self.select_hosts(&pattern, &name, path)?;
+        if hosts.is_empty() {
+            warn!("Skipping play '{}': {} matches no host", name, pattern);
+            return Ok(None);
+        }
+
+        let mut vars_files = HashMap::new();
+        for file in as_list(play.get("vars_files")) {
+            let file = dir.join(scalar_string(&file));
+            if !file.is_file() {
+                self.missing.push(MissingFile {
+                    path: file,
+                    keyword: "vars_files".to_string(),
+                    included_by: name.clone(),
+                });
+                continue;
+            }
+            vars_files.extend(self.loader.vars_loader.load(&file)?);
+        }
+        let scope = Scope {
+            dir: dir.to_path_buf(),
+            playbook_dir: dir.to_path_buf(),
+            r#become: become_policy(play, None, path)?,
+            ..Scope::default()
+        }
+        .with_vars(
+            VariablePrecedence::PlayVars,
+            mapping(play.get("vars"), path)?,
+        )?
+        .with_vars(VariablePrecedence::PlayVarsFiles, vars_files)?
+        .with_vars(
+            VariablePrecedence::ExtraVars,
+            self.loader.extra_vars.clone(),
+        )?;
+
+        let mut planned = PlayTasks::default();
+        self.plan_tasks(play.get("pre_tasks"), &scope, &hosts, &mut planned)?;
+        for role in as_list(play.get("roles")) {
+            self.plan_play_role(&role, &scope, &hosts, &mut planned, path)?;
+        }
+        self.plan_tasks(play.get("tasks"), &scope, &hosts, &mut planned)?;
+        self.plan_tasks(play.get("post_tasks"), &scope, &hosts, &mut planned)?;
+        self.plan_handlers(play.get("handlers"), &scope, &mut planned)?;
+
+        let strategy = match play.get("strategy").and_then(Value::as_str) {
+            None | Some("linear") => ExecutionStrategy::Linear,
+            Some("free") => ExecutionStrategy::Free,
+            Some(other) => {
+                return Err(PlaybookError::Unsupported {
+                    keyword: format!("strategy {other}"),
+                    location: format!("play '{name}'"),
+                })
+            }
+        };
+        let serial = match play.get("serial") {
+            None | Some(Value::Null) => None,
+            Some(serial) => Some(
+                serial
+                    .as_u64()
+                    .and_then(|serial| u32::try_from(serial).ok())
+                    .ok_or_else(|| PlaybookError::Unsupported {
+                        keyword: format!("serial {serial}"),
+                        location: format!("play '{name}'"),
+                    })?,
+            ),
+        };
+
+        let index = self.plays.len();
+        Ok(Some(PlayPlan {
+            play_id: format!("play-{index}"),
+            name,
+            strategy,
+            serial,
+            hosts: hosts.clone(),
+            batches: vec![TaskBatch {
+                batch_id: format!("play-{index}-tasks"),
+                hosts,
+                tasks: planned.tasks,
+                parallel_groups: vec![],
+                dependencies: vec![],
+                estimated_duration: None,
+            }],
+            handlers: planned.handlers,
+            blocks: planned.blocks,
+            estimated_duration: None,
+            environment: self.environment(play.get("environment"), &scope),
+            module_defaults: module_defaults(play.get("module_defaults"), path)?,
+            timeout: None,
+            any_errors_fatal: play.get("any_errors_fatal").is_some_and(is_true),
+        }))
+    }
+
+    /// The hosts `pattern` selects, in the inventory or, for plays
+    /// targeting it, on localhost
+    fn select_hosts(
+        &self,
+        pattern: &str,
+        play: &str,
+        path: &Path,
+    ) -> Result<Vec<String>, PlaybookError> {
+        let names_localhost = pattern
+            .split([',', ':'])
+            .any(|term| matches!(term.trim(), LOCALHOST | "127.0.0.1"));
+        let hosts: Vec<String> = match self.inventory {
+            Some(inventory) => HostPattern::parse(pattern)
+                .map_err(|e| invalid(path, &format!("play '{play}': {e}")))?
+                .hosts(inventory)
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            None if names_localhost => Vec::new(),
+            None => {
+                return Err(invalid(
+                    path,
+                    &format!("play '{play}' targets {pattern}, which takes an inventory"),
+                ))
+            }
+        };
+        if hosts.is_empty() && names_localhost {
+            return Ok(vec![LOCALHOST.to_string()]);
+        }
+        Ok(hosts)
+    }
+
+    /// Plan the list of tasks `tasks`, returning the ids of the tasks and
+    /// blocks it holds
+    fn plan_tasks(
+        &mut self,
+        tasks: Option<&Value>,
+        scope: &Scope,
+        hosts: &[String],
+        planned: &mut PlayTasks,
+    ) -> Result<Vec<String>, PlaybookError> {
+        let mut ids = Vec::new();
+        // The variables include_vars loads are those of the tasks after it
+        let mut scope = scope.clone();
+        for entry in as_list(tasks) {
+            let Value::Object(entry) = entry else {
+                return Err(invalid(&scope.dir, "a task is a mapping"));
+            };
+            if let Some((_, args)) = builtin(&entry, &["include_vars"]) {
+                scope = self.include_vars(&entry, args, scope)?;
+                continue;
+            }
+            ids.extend(self.plan_entry(&entry, &scope, hosts, planned)?);
+        }
+        Ok(ids)
+    }
+
+    fn plan_entry(
+        &mut self,
+        entry: &Map<String, Value>,
+        scope: &Scope,
+        hosts: &[String],
+        planned: &mut PlayTasks,
+    ) -> Result<Vec<String>, PlaybookError> {
+        if entry.contains_key("block") {
+            return self.plan_block(entry, scope, hosts, planned);
+        }
+        if let Some((keyword, file)) = builtin(entry, &["include_tasks", "import_tasks"]) {
+            let mut ids = Vec::new();
+            for item in self.loop_items(entry, scope, keyword)? {
+                let scope = scope
+                    .clone()
+                    .with_vars(VariablePrecedence::IncludeParams, item)?;
+                let scope =
+                    self.inherited(entry, &scope, VariablePrecedence::IncludeParams, keyword)?;
+                ids.extend(self.plan_include(entry, keyword, file, scope, hosts, planned)?);
+            }
+            return Ok(ids);
+        }
+        if let Some((keyword, role)) = builtin(entry, &["include_role", "import_role"]) {
+            let args = role.as_object().cloned().unwrap_or_default();
+            let name = args
+                .get("name")
+                .map(scalar_string)
+                .ok_or_else(|| invalid(&scope.dir, &format!("{keyword} names no role")))?;
+            let tasks_from = args
+                .get("tasks_from")
+                .map(scalar_string)
+                .unwrap_or_else(|| "main".to_string());
+            let mut ids = Vec::new();
+            for item in self.loop_items(entry, scope, keyword)? {
+                let scope = scope
+                    .clone()
+                    .with_vars(VariablePrecedence::RoleParams, item)?;
+                let scope =
+                    self.inherited(entry, &scope, VariablePrecedence::RoleParams, keyword)?;
+                ids.extend(self.plan_role(&name, &tasks_from, scope, hosts, planned)?);
+            }
+            return Ok(ids);
+        }
+
+        let task = self.plan_task(entry, scope, hosts)?;
+        let id = task.task_id.clone();
+        planned.tasks.push(task);
+        Ok(vec![id])
+    }
+
+    /// The scope of the tasks `entry` includes, which inherit its
+    /// conditions, tags and variables
+    fn inherited(
+        &self,
+        entry: &Map<String, Value>,
+        scope: &Scope,
+        precedence: VariablePrecedence,
+        keyword: &str,
+    ) -> Result<Scope, PlaybookError> {
+        // Includes are planned once per item, nothing else loops but tasks
+        if let Some(loop_keyword) = loop_keyword(entry).filter(|_| !INCLUDES.contains(&keyword)) {
+            return Err(PlaybookError::Unsupported {
+                keyword: loop_keyword.to_string(),
+                location: format!("{} in {}", keyword, scope.dir.display()),
+            });
+        }
+        let mut scope = scope
+            .clone()
+            .with_vars(precedence, mapping(entry.get("vars"), &scope.dir)?)?;
+        scope
+            .when
+            .extend(self.conditions(entry.get("when"), &scope));
+        scope.tags.extend(tags(entry.get("tags")));
+        scope.r#become = become_policy(entry, scope.r#become.as_ref(), &scope.dir)?;
+        if entry.get("ignore_errors").is_some_and(is_true) {
+            scope.ignore_errors = true;
+        }
+        Ok(scope)
+    }
+
+    /// The variables binding each item of the loop of an include, a single
+    /// empty binding without a loop. The items have to be known when
+    /// planning, as the tasks included are planned for each.
+    fn loop_items(
+        &self,
+        entry: &Map<String, Value>,
+        scope: &Scope,
+        keyword: &str,
+    ) -> Result<Vec<HashMap<String, Value>>, PlaybookError> {
+        let Some(loop_keyword) = loop_keyword(entry) else {
+            return Ok(vec![HashMap::new()]);
+        };
+        let unknown = || PlaybookError::Unsupported {
+            keyword: format!("{loop_keyword} over items only known when running"),
+            location: format!("{} in {}", keyword, scope.dir.display()),
+        };
+        if loop_keyword == "with_fileglob" {
+            return Err(unknown());
+        }
+        let items = match self
+            .processor
+            .render_value(&entry[loop_keyword], &scope.vars)
+        {
+            Value::Array(items) if loop_keyword == "with_items" => items
+                .into_iter()
+                .flat_map(|item| match item {
+                    Value::Array(nested) => nested,
+                    item => vec![item],
+                })
+                .collect(),
+            Value::Array(items) if loop_keyword != "with_dict" => items,
+            Value::Object(entries) if loop_keyword == "with_dict" => entries
+                .into_iter()
+                .map(|(key, value)| serde_json::json!({ "key": key, "value": value }))
+                .collect(),
+            _ => return Err(unknown()),
+        };
+        let control = match entry.get("loop_control") {
+            Some(control) => serde_json::from_value::<LoopControl>(control.clone())
+                .map_err(|e| invalid(&scope.dir, &format!("{keyword}: loop_control: {e}")))?,
+            None => LoopControl::default(),
+        };
+        let loop_var = control.loop_var.unwrap_or_else(|| "item".to_string());
+        Ok(items
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let mut vars = HashMap::from([(loop_var.clone(), item)]);
+                if let Some(index_var) = &control.index_var {
+                    vars.insert(index_var.clone(), index.into());
+                }
+                vars
+            })
+            .collect())
+    }
+
+    /// Plan the tasks of the file an `include_tasks` or `import_tasks`
+    /// names, relative to the file including it
+    fn plan_include(
+        &mut self,
+        entry: &Map<String, Value>,
+        keyword: &str,
+        file: &Value,
+        scope: Scope,
+        hosts: &[String],
+        planned: &mut PlayTasks,
+    ) -> Result<Vec<String>, PlaybookError> {
+        let file = match file {
+            Value::Object(args) => args.get("file").or_else(|| args.get("_raw_params")),
+            other => Some(other),
+        }
+        .map(|file| scalar_string(&self.processor.render_value(file, &scope.vars)))
+        .ok_or_else(|| invalid(&scope.dir, &format!("{keyword} names no file")))?;
+        if file.contains("{{") {
+            return Err(PlaybookError::Unsupported {
+                keyword: format!("{keyword} of {file}, only named when running"),
+                location: scope.dir.display().to_string(),
+            });
+        }
+
+        let path = scope.dir.join(&file);
+        if !path.is_file() {
+            self.missing.push(MissingFile {
+                path,
+                keyword: keyword.to_string(),
+                included_by: entry
+                    .get("name")
+                    .map(scalar_string)
+                    .unwrap_or_else(|| scope.dir.display().to_string()),
+            });
+            return Ok(Vec::new());
+        }
+        let tasks = self.read_yaml(&path)?;
+        let scope = Scope {
+            dir: parent_dir(&path),
+            ..scope
+        };
+        self.plan_tasks(Some(&tasks), &scope, hosts, planned)
+    }
+
+    /// The scope of the tasks after an `include_vars`, with the variables it
+    /// loads. Whether it applies has to be known when planning.
+    fn include_vars(
+        &mut self,
+        entry: &Map<String, Value>,
+        args: &Value,
+        scope: Scope,
+    ) -> Result<Scope, PlaybookError> {
+        let location = format!("include_vars in {}", scope.dir.display());
+        if let Some(loop_keyword) = loop_keyword(entry) {
+            return Err(PlaybookError::Unsupported {
+                keyword: loop_keyword.to_string(),
+                location,
+            });
+        }
+        // Conditions decided when planning are `true` or `false`
+        let mut conditions = scope.when.clone();
+        conditions.extend(self.conditions(entry.get("when"), &scope));
+        let decided: Vec<Option<bool>> = conditions
+            .iter()
+            .map(|condition| match condition {
+                TaskCondition::When { expression } => expression.parse().ok(),
+                TaskCondition::Tag { .. } => Some(true),
+                _ => None,
+            })
+            .collect();
+        if decided.contains(&Some(false)) {
+            return Ok(scope);
+        }
+        if decided.contains(&None) {
+            return Err(PlaybookError::Unsupported {
+                keyword: "when only decided when running".to_string(),
+                location,
+            });
+        }
+
+        let (file, name) = match args {
+            Value::Object(args) => (
+                args.get("file").or_else(|| args.get("_raw_params")),
+                args.get("name").map(scalar_string),
+            ),
+            other => (Some(other), None),
+        };
+        let file = file
+            .map(|file| scalar_string(&self.processor.render_value(file, &scope.vars)))
+            .ok_or_else(|| PlaybookError::Unsupported {
+                keyword: "include_vars without a file".to_string(),
+                location: location.clone(),
+            })?;
+        let Some(path) = search_dirs(&scope, "vars")
+            .into_iter()
+            .map(|dir| dir.join(&file))
+            .find(|path| path.is_file())
+        else {
+            self.missing.push(MissingFile {
+                path: scope.dir.join(&file),
+                keyword: "include_vars".to_string(),
+                included_by: entry.get("name").map(scalar_string).unwrap_or(location),
+            });
+            return Ok(scope);
+        };
+
+        let mut vars = self.loader.vars_loader.load(&path)?;
+        if let Some(name) = name {
+            vars = HashMap::from([(name, Value::Object(vars.into_iter().collect()))]);
+        }
+        scope.with_vars(VariablePrecedence::IncludeVars, vars)
+    }
+
+    fn plan_block(
+        &mut self,
+        entry: &Map<String, Value>,
+        scope: &Scope,
+        hosts: &[String],
+        planned: &mut PlayTasks,
+    ) -> Result<Vec<String>, PlaybookError> {
+        let scope = self.inherited(entry, scope, VariablePrecedence::BlockVars, "block")?;
+        let block = self.plan_tasks(entry.get("block"), &scope, hosts, planned)?;
+        let rescue = self.plan_tasks(entry.get("rescue"), &scope, hosts, planned)?;
+        let always = self.plan_tasks(entry.get("always"), &scope, hosts, planned)?;
+
+        let block_id = format!("{}block_{}", self.prefix, self.blocks);
+        self.blocks += 1;
+        planned.blocks.push(BlockDefinition {
+            block_id: block_id.clone(),
+            name: entry.get("name").map(scalar_string),
+            block,
+            rescue,
+            always,
+            environment: self.environment(entry.get("environment"), &scope),
+            module_defaults: module_defaults(entry.get("module_defaults"), &scope.dir)?,
+        });
+        Ok(vec![block_id])
+    }
+
+    /// Plan an entry of the `roles` of a play or the dependencies of a
+    /// role, a role name or a mapping naming the role along with its
+    /// parameters. A role applied with the same parameters before is not
+    /// applied again, unless its `meta/main.yml` allows duplicates.
+    fn plan_play_role(
+        &mut self,
+        role: &Value,
+        scope: &Scope,
+        hosts: &[String],
+        planned: &mut PlayTasks,
+        path: &Path,
+    ) -> Result<(), PlaybookError> {
+        let (name, entry) = match role {
+            Value::String(name) => (name.clone(), Map::new()),
+            Value::Object(entry) => {
+                let name = entry
+                    .get("role")
+                    .or_else(|| entry.get("name"))
+                    .map(scalar_string)
+                    .ok_or_else(|| invalid(path, "a role entry names no role"))?;
+                (name, entry.clone())
+            }
+            _ => return Err(invalid(path, "a role is a name or a mapping")),
+        };
+        // Keys other than keywords are parameters of the role
+        let params: Map<String, Value> = entry
+            .iter()
+            .filter(|(key, _)| {
+                !matches!(key.as_str(), "role" | "name" | "vars")
+                    && !TASK_KEYWORDS.contains(&key.as_str())
+            })
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        let dir = self.find_role(&name, &scope.playbook_dir)?;
+        let meta = match yaml_file(&dir.join("meta"), "main") {
+            Some(path) => self.read_yaml(&path)?,
+            None => Value::Null,
+        };
+        let applied = format!("{}:{}", dir.display(), Value::Object(params.clone()));
+        let allow_duplicates = meta.get("allow_duplicates").is_some_and(is_true);
+        if !planned.applied.insert(applied) && !allow_duplicates {
+            return Ok(());
+        }
+
+        // Duplicates allowed, a role depending on itself would never end
+        if let Some(start) = planned.role_chain.iter().position(|(role, _)| *role == dir) {
+            let chain: Vec<&str> = planned.role_chain[start..]
+                .iter()
+                .map(|(_, name)| name.as_str())
+                .chain([name.as_str()])
+                .collect();
+            return Err(PlaybookError::RoleCycle {
+                chain: chain.join(" -> "),
+            });
+        }
+
+        let scope = self.inherited(&entry, scope, VariablePrecedence::RoleParams, "roles")?;
+        // Dependencies run first, with the conditions and tags of the role
+        // but not its variables
+        planned.role_chain.push((dir.clone(), name.clone()));
+        for dependency in as_list(meta.get("dependencies")) {
+            self.plan_play_role(&dependency, &scope, hosts, planned, &dir)?;
+        }
+        planned.role_chain.pop();
+        let scope =
+            scope.with_vars(VariablePrecedence::RoleParams, params.into_iter().collect())?;
+        self.plan_role(&name, "main", scope, hosts, planned)?;
+        Ok(())
+    }
+
+    /// Plan the tasks of `tasks_from` of role `name`, adding its handlers
+    /// to the play the first time it is applied
+    fn plan_role(
+        &mut self,
+        name: &str,
+        tasks_from: &str,
+        scope: Scope,
+        hosts: &[String],
+        planned: &mut PlayTasks,
+    ) -> Result<Vec<String>, PlaybookError> {
+        let dir = self.find_role(name, &scope.playbook_dir)?;
+        let defaults = self.read_vars(&dir.join("defaults"), "main")?;
+        let vars = self.read_vars(&dir.join("vars"), "main")?;
+        let scope = Scope {
+            dir: dir.join("tasks"),
+            role: Some(name.to_string()),
+            role_dir: Some(dir.clone()),
+            ..scope
+        }
+        .with_vars(VariablePrecedence::RoleDefaults, defaults)?
+        .with_vars(VariablePrecedence::RoleVars, vars)?;
+
+        let ids = match yaml_file(&dir.join("tasks"), tasks_from) {
+            Some(path) => {
+                let tasks = self.read_yaml(&path)?;
+                self.plan_tasks(Some(&tasks), &scope, hosts, planned)?
+            }
+            None if tasks_from == "main" => Vec::new(),
+            None => {
+                return Err(invalid(
+                    &dir,
+                    &format!("role {name} has no tasks/{tasks_from}.yml"),
+                ))
+            }
+        };
+        if planned.roles.insert(dir.clone()) {
+            if let Some(path) = yaml_file(&dir.join("handlers"), "main") {
+                let handlers = self.read_yaml(&path)?;
+                self.plan_handlers(Some(&handlers), &scope, planned)?;
+            }
+        }
+        Ok(ids)
+    }
+
+    /// The directory of role `name`: in `roles/` next to the playbook, in
+    /// the roles paths, or next to the playbook
+    fn find_role(&self, name: &str, playbook_dir: &Path) -> Result<PathBuf, PlaybookError> {
+        let mut searched = vec![playbook_dir.join("roles")];
+        searched.extend(self.loader.roles_paths.iter().cloned());
+        searched.push(playbook_dir.to_path_buf());
+        searched
+            .iter()
+            .map(|dir| dir.join(name))
+            .find(|dir| dir.is_dir())
+            .ok_or_else(|| PlaybookError::RoleNotFound {
+                role: name.to_string(),
+                searched: searched
+                    .iter()
+                    .map(|dir| dir.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            })
+    }
+
+    fn plan_handlers(
+        &mut self,
+        handlers: Option<&Value>,
+        scope: &Scope,
+        planned: &mut PlayTasks,
+    ) -> Result<(), PlaybookError> {
+        for handler in as_list(handlers) {
+            let Value::Object(handler) = handler else {
+                return Err(invalid(&scope.dir, "a handler is a mapping"));
+            };
+            // Handlers are notified by their own names, even in roles
+            let scope = Scope {
+                role: None,
+                ..scope.clone()
+            };
+            let task = self.plan_task(&handler, &scope, &[])?;
+            planned.handlers.push(HandlerDefinition {
+                handler_id: format!("{}handler_{}", self.prefix, self.handlers),
+                name: task.name,
+                module: task.module,
+                args: task.args,
+                conditions: task.conditions,
+                execution_order: self.handlers as u32,
+                listen: as_list(handler.get("listen"))
+                    .iter()
+                    .map(scalar_string)
+                    .collect(),
+                notify: task.notify,
+            });
+            self.handlers += 1;
+        }
+        Ok(())
+    }
+
+    fn plan_task(
+        &mut self,
+        task: &Map<String, Value>,
+        scope: &Scope,
+        hosts: &[String],
+    ) -> Result<TaskPlan, PlaybookError> {
+        let scope = scope.clone().with_vars(
+            VariablePrecedence::TaskVars,
+            mapping(task.get("vars"), &scope.dir)?,
+        )?;
+        let (module, mut args) = module_of(task, &scope.dir)?;
+        let name = task
+            .get("name")
+            .map(scalar_string)
+            .unwrap_or_else(|| module.clone());
+        let location = format!("task '{name}' of {}", scope.dir.display());
+        if let Some(keyword) = task.keys().find(|keyword| {
+            UNSUPPORTED_KEYWORDS.contains(&keyword.as_str())
+                || (keyword.starts_with("with_")
+                    && !LOOP_KEYWORDS
+                        .iter()
+                        .any(|(known, _)| *known == keyword.as_str()))
+        }) {
+            return Err(PlaybookError::Unsupported {
+                keyword: keyword.clone(),
+                location,
+            });
+        }
+
+        if !scope.vars.is_empty() {
+            for value in args.values_mut() {
+                *value = self.processor.render_value(value, &scope.vars);
+            }
+        }
+        locate_source(&module, &mut args, &scope);
+        let mut conditions = scope.when.clone();
+        conditions.extend(self.conditions(task.get("when"), &scope));
+        let mut task_tags = scope.tags.clone();
+        task_tags.extend(tags(task.get("tags")));
+
+        let task_loop = LOOP_KEYWORDS
+            .iter()
+            .find_map(|(keyword, kind)| Some((*kind, task.get(*keyword)?)))
+            .map(|(kind, items)| {
+                let control = match task.get("loop_control") {
+                    Some(control) => serde_json::from_value::<LoopControl>(control.clone())
+                        .map_err(|e| invalid(&scope.dir, &format!("{location}: {e}")))?,
+                    None => LoopControl::default(),
+                };
+                Ok::<_, PlaybookError>(TaskLoop {
+                    kind,
+                    items: self.processor.render_value(items, &scope.vars),
+                    control,
+                })
+            })
+            .transpose()?;
+        let result_condition = |keyword: &str| {
+            task.get(keyword)
+                .map(|condition| {
+                    serde_json::from_value::<ResultCondition>(condition.clone())
+                        .map_err(|e| invalid(&scope.dir, &format!("{location}: {keyword}: {e}")))
+                })
+                .transpose()
+        };
+        let async_job = task
+            .get("async")
+            .and_then(Value::as_u64)
+            .map(|timeout| AsyncPolicy {
+                timeout: Duration::from_secs(timeout),
+                poll: Duration::from_secs(task.get("poll").and_then(Value::as_u64).unwrap_or(10)),
+            });
+
+        let task_id = format!("{}task_{}", self.prefix, self.tasks);
+        let execution_order = self.tasks as u32;
+        self.tasks += 1;
+        Ok(TaskPlan {
+            task_id,
+            name: match &scope.role {
+                Some(role) => format!("{role} : {name}"),
+                None => name,
+            },
+            module,
+            args: args.into_iter().collect(),
+            hosts: hosts.to_vec(),
+            dependencies: vec![],
+            conditions,
+            tags: task_tags,
+            notify: as_list(task.get("notify"))
+                .iter()
+                .map(scalar_string)
+                .collect(),
+            execution_order,
+            can_run_parallel: false,
+            estimated_duration: Duration::from_secs(1),
+            // Failed tasks stop the host unless they ignore errors
+            risk_level: RiskLevel::High,
+            run_once: task.get("run_once").is_some_and(is_true),
+            delegate_to: task
+                .get("delegate_to")
+                .map(|host| scalar_string(&self.processor.render_value(host, &scope.vars))),
+            task_loop,
+            until: None,
+            async_job,
+            failed_when: result_condition("failed_when")?,
+            changed_when: result_condition("changed_when")?,
+            ignore_errors: scope.ignore_errors || task.get("ignore_errors").is_some_and(is_true),
+            register: task.get("register").map(scalar_string),
+            no_log: task.get("no_log").is_some_and(is_true),
+            r#become: become_policy(task, scope.r#become.as_ref(), &scope.dir)?,
+            environment: self.environment(task.get("environment"), &scope),
+            module_defaults: module_defaults(task.get("module_defaults"), &scope.dir)?,
+            timeout: task
+                .get("timeout")
+                .and_then(Value::as_u64)
+                .map(Duration::from_secs),
+            throttle: task
+                .get("throttle")
+                .and_then(Value::as_u64)
+                .and_then(|throttle| u32::try_from(throttle).ok()),
+        })
+    }
+
+    /// The `when` conditions of an entry, with the variables of `scope`
+    /// rendered into them. A condition they decide becomes `true` or
+    /// `false`; the others are left for the runner.
+    fn conditions(&self, when: Option<&Value>, scope: &Scope) -> Vec<TaskCondition> {
+        as_list(when)
+            .iter()
+            .map(|condition| {
+                let expression = match condition {
+                    Value::Bool(decided) => decided.to_string(),
+                    other => scalar_string(other).trim().to_string(),
+                };
+                let template = Value::String(format!("{{{{ {expression} }}}}"));
+                let expression = match self.processor.render_value(&template, &scope.vars) {
+                    Value::Bool(decided) => decided.to_string(),
+                    Value::String(_) => expression,
+                    rendered => is_true(&rendered).to_string(),
+                };
+                TaskCondition::When { expression }
+            })
+            .collect()
+    }
+
+    fn environment(&self, environment: Option<&Value>, scope: &Scope) -> HashMap<String, String> {
+        let Some(Value::Object(environment)) = environment else {
+            return HashMap::new();
+        };
+        environment
+            .iter()
+            .map(|(name, value)| {
+                let value = self.processor.render_value(value, &scope.vars);
+                (name.clone(), scalar_string(&value))
+            })
+            .collect()
+    }
+
+    /// The variables of `{dir}/{stem}.yml`, or of the files of the
+    /// directory `{dir}/{stem}` in the order of their names; none when there
+    /// are neither
+    fn read_vars(&self, dir: &Path, stem: &str) -> Result<HashMap<String, Value>, PlaybookError> {
+        let vars_dir = dir.join(stem);
+        if !vars_dir.is_dir() {
+            return match yaml_file(dir, stem) {
+                Some(path) => {
+                    let vars = self.read_yaml(&path)?;
+                    mapping(Some(&vars), &path)
+                }
+                None => Ok(HashMap::new()),
+            };
+        }
+        let io = |source| PlaybookError::Io {
+            path: vars_dir.display().to_string(),
+            source,
+        };
+        let mut files: Vec<PathBuf> = std::fs::read_dir(&vars_dir)
+            .map_err(io)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()
+            .map_err(io)?;
+        files.retain(|path| path.is_file() && is_playbook(path));
+        files.sort();
+        let mut vars = HashMap::new();
+        for path in files {
+            let file = self.read_yaml(&path)?;
+            vars.extend(mapping(Some(&file), &path)?);
+        }
+        Ok(vars)
+    }
+
+    /// The YAML document at `path` as JSON, its vaulted values decrypted
+    fn read_yaml(&self, path: &Path) -> Result<Value, PlaybookError> {
+        let content = std::fs::read_to_string(path).map_err(|source| PlaybookError::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+        let mut document: serde_yaml::Value =
+            serde_yaml::from_str(&content).map_err(|e| invalid(path, &e.to_string()))?;
+        self.loader.vault.decrypt_yaml(&mut document)?;
+        serde_json::to_value(document).map_err(|e| invalid(path, &e.to_string()))
+    }
+}
+
+/// The module a task runs and its arguments: those given to the module,
+/// over those of `args`
+fn module_of(
+    task: &Map<String, Value>,
+    dir: &Path,
+) -> Result<(String, Map<String, Value>), PlaybookError> {
+    let modules: Vec<&String> = task
+        .keys()
+        .filter(|key| {
+            !TASK_KEYWORDS.contains(&key.as_str())
+                && !IGNORED_KEYWORDS.contains(&key.as_str())
+                && !UNSUPPORTED_KEYWORDS.contains(&key.as_str())
+                && !key.starts_with("with_")
+        })
+        .collect();
+    let name = task.get("name").map(scalar_string).unwrap_or_default();
+    let key = match modules.as_slice() {
+        [module] => *module,
+        [] => return Err(invalid(dir, &format!("task '{name}' runs no module"))),
+        several => {
+            let several: Vec<&str> = several.iter().map(|module| module.as_str()).collect();
+            return Err(invalid(
+                dir,
+                &format!("task '{name}' runs several modules: {}", several.join(", ")),
+            ));
+        }
+    };
+    let module = BUILTIN_COLLECTIONS
+        .iter()
+        .find_map(|prefix| key.strip_prefix(prefix))
+        .unwrap_or(key)
+        .to_string();
+
+    let mut args = match task.get("args") {
+        Some(Value::Object(args)) => args.clone(),
+        _ => Map::new(),
+    };
+    match &task[key] {
+        Value::Null => {}
+        Value::Object(given) => args.extend(given.clone()),
+        Value::String(command) if FREE_FORM_MODULES.contains(&module.as_str()) => {
+            args.insert("_raw_params".to_string(), command.clone().into());
+        }
+        Value::String(given) => {
+            let words = shell_words::split(given)
+                .map_err(|e| invalid(dir, &format!("task '{name}': {e}")))?;
+            for word in words {
+                let (arg, value) = word.split_once('=').ok_or_else(|| {
+                    invalid(
+                        dir,
+                        &format!("task '{name}': {word} is not a key=value argument"),
+                    )
+                })?;
+                args.insert(arg.to_string(), value.into());
+            }
+        }
+        other => {
+            return Err(invalid(
+                dir,
+                &format!("task '{name}': {other} is not arguments of {module}"),
+            ))
+        }
+    }
+    Ok((module, args))
+}
+
+/// Point the relative local `src` of a task at the file Ansible would read:
+/// in the directory of its kind of the role the task is from, next to the
+/// tasks, or next to the playbook. Sources found nowhere are left for the
+/// target, as are templated ones and `remote_src` copies.
+fn locate_source(module: &str, args: &mut Map<String, Value>, scope: &Scope) {
+    let Some((_, kind)) = LOCAL_SOURCE_MODULES
+        .iter()
+        .find(|(local_module, _)| *local_module == module)
+    else {
+        return;
+    };
+    if args.get("remote_src").is_some_and(is_true) {
+        return;
+    }
+    let Some(src) = args.get("src").and_then(Value::as_str) else {
+        return;
+    };
+    if src.contains("{{") || Path::new(src).is_absolute() {
+        return;
+    }
+
+    if let Some(found) = search_dirs(scope, kind)
+        .iter()
+        .map(|dir| dir.join(src))
+        .find(|path| path.exists())
+    {
+        args.insert("src".to_string(), found.display().to_string().into());
+    }
+}
+
+/// Where the files of `kind`, such as `files` or `vars`, that tasks name
+/// are looked up: in the role the tasks are from, next to the tasks, then
+/// next to the playbook
+fn search_dirs(scope: &Scope, kind: &str) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(role_dir) = &scope.role_dir {
+        dirs.extend([role_dir.join(kind), role_dir.clone()]);
+    }
+    dirs.extend([
+        scope.dir.join(kind),
+        scope.dir.clone(),
+        scope.playbook_dir.join(kind),
+        scope.playbook_dir.clone(),
+    ]);
+    dirs
+}
+
+/// The keyword of `names` that `entry` uses, bare or in a builtin
+/// collection, and its value
+fn builtin<'a>(
+    entry: &'a Map<String, Value>,
+    names: &[&'static str],
+) -> Option<(&'static str, &'a Value)> {
+    names.iter().find_map(|name| {
+        let value = entry.get(*name).or_else(|| {
+            BUILTIN_COLLECTIONS
+                .iter()
+                .find_map(|prefix| entry.get(&format!("{prefix}{name}")))
+        })?;
+        Some((*name, value))
+    })
+}
+
+/// The loop keyword of `entry`, if it loops
+fn loop_keyword(entry: &Map<String, Value>) -> Option<&'static str> {
+    LOOP_KEYWORDS
+        .iter()
+        .map(|(keyword, _)| *keyword)
+        .find(|keyword| entry.contains_key(*keyword))
+}
+
+/// `become` and its options on `entry` over those `inherited`
+fn become_policy(
+    entry: &Map<String, Value>,
+    inherited: Option<&BecomePolicy>,
+    dir: &Path,
+) -> Result<Option<BecomePolicy>, PlaybookError> {
+    let keywords = ["become", "become_user", "become_method", "become_flags"];
+    if !keywords.iter().any(|keyword| entry.contains_key(*keyword)) {
+        return Ok(inherited.cloned());
+    }
+    let mut policy = inherited.cloned().unwrap_or_default();
+    if let Some(enabled) = entry.get("become") {
+        policy.enabled = Some(is_true(enabled));
+    }
+    if let Some(user) = entry.get("become_user") {
+        policy.user = Some(scalar_string(user));
+    }
+    if let Some(method) = entry.get("become_method") {
+        let method = Value::String(scalar_string(method).to_lowercase());
+        policy.method = Some(
+            serde_json::from_value(method)
+                .map_err(|e| invalid(dir, &format!("become_method: {e}")))?,
+        );
+    }
+    if let Some(flags) = entry.get("become_flags") {
+        policy.flags = Some(scalar_string(flags));
+    }
+    Ok(Some(policy))
+}
+
+fn module_defaults(defaults: Option<&Value>, dir: &Path) -> Result<ModuleDefaults, PlaybookError> {
+    match defaults {
+        None | Some(Value::Null) => Ok(ModuleDefaults::new()),
+        Some(defaults) => serde_json::from_value(defaults.clone())
+            .map_err(|e| invalid(dir, &format!("module_defaults: {e}"))),
+    }
+}
+
+/// A mapping of variables, none for a missing or empty one
+fn mapping(vars: Option<&Value>, path: &Path) -> Result<HashMap<String, Value>, PlaybookError> {
+    match vars {
+        None | Some(Value::Null) => Ok(HashMap::new()),
+        Some(Value::Object(vars)) => Ok(vars.clone().into_iter().collect()),
+        Some(_) => Err(invalid(path, "variables are a mapping")),
+    }
+}
+
+/// `tags`, a list or a comma-separated string
+fn tags(tags: Option<&Value>) -> Vec<String> {
+    match tags {
+        Some(Value::String(tags)) => tags
+            .split(',')
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect(),
+        other => as_list(other).iter().map(scalar_string).collect(),
+    }
+}
+
+/// A keyword taking a list or a single item, as a list
+fn as_list(value: Option<&Value>) -> Vec<Value> {
+    match value {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Array(items)) => items.clone(),
+        Some(item) => vec![item.clone()],
+    }
+}
+
+/// A scalar as the string YAML wrote it
+fn scalar_string(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Whether a boolean keyword holds, YAML 1.1 words included
+fn is_true(value: &Value) -> bool {
+    match value {
+        Value::Bool(value) => *value,
+        Value::String(text) => matches!(
+            text.to_lowercase().as_str(),
+            "yes" | "y" | "true" | "on" | "1"
+        ),
+        Value::Number(number) => number.as_f64().is_some_and(|number| number != 0.0),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(entries) => !entries.is_empty(),
+        Value::Null => false,
+    }
+}
+
+/// `{dir}/{stem}.yml`, `.yaml` or without extension, whichever exists
+fn yaml_file(dir: &Path, stem: &str) -> Option<PathBuf> {
+    ["yml", "yaml"]
+        .iter()
+        .map(|extension| dir.join(format!("{stem}.{extension}")))
+        .chain(std::iter::once(dir.join(stem)))
+        .find(|path| path.is_file())
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    path.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+fn invalid(path: &Path, reason: &str) -> PlaybookError {
+    PlaybookError::Invalid {
+        path: path.display().to_string(),
+        reason: reason.to_string(),
+    }
+}
@@ -1,27 +1,37 @@
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::time::Duration;
 
-use super::plan::ExecutionStrategy;
+use super::plan::{
+    AsyncPolicy, BecomePolicy, ExecutionStrategy, ResultCondition, TaskLoop, UntilPolicy,
+};
+use super::plan_schema::DurationSchema;
 
 /// Rustle-plan compatible execution plan format
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct RustlePlanOutput {
+    /// Version of the schema the plan was written in, see
+    /// [`crate::execution::plan_schema`]
+    #[serde(default = "super::plan_schema::legacy_schema_version")]
+    pub schema_version: u32,
     pub metadata: RustlePlanMetadata,
     pub plays: Vec<PlayPlan>,
     pub binary_deployments: Vec<BinaryDeploymentPlan>,
     pub total_tasks: u32,
     #[serde(with = "serde_duration_opt")]
+    #[schemars(with = "Option<DurationSchema>")]
     pub estimated_duration: Option<Duration>,
     #[serde(with = "serde_duration_opt")]
+    #[schemars(with = "Option<DurationSchema>")]
     pub estimated_compilation_time: Option<Duration>,
     pub parallelism_score: f32,
     pub network_efficiency_score: f32,
     pub hosts: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct RustlePlanMetadata {
     pub created_at: DateTime<Utc>,
     pub rustle_plan_version: String,
@@ -30,7 +40,7 @@ pub struct RustlePlanMetadata {
     pub planning_options: PlanningOptions,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PlanningOptions {
     pub limit: Option<String>,
     pub tags: Vec<String>,
@@ -45,7 +55,7 @@ pub struct PlanningOptions {
     pub force_ssh: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PlayPlan {
     pub play_id: String,
     pub name: String,
@@ -54,11 +64,29 @@ pub struct PlayPlan {
     pub hosts: Vec<String>,
     pub batches: Vec<TaskBatch>,
     pub handlers: Vec<HandlerDefinition>,
+    #[serde(default)]
+    pub blocks: Vec<BlockDefinition>,
     #[serde(with = "serde_duration_opt")]
+    #[schemars(with = "Option<DurationSchema>")]
     pub estimated_duration: Option<Duration>,
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+    #[serde(default)]
+    pub module_defaults: ModuleDefaults,
+    /// Deadline for the tasks and handlers of the play together
+    #[serde(default, with = "serde_duration_opt")]
+    #[schemars(with = "Option<DurationSchema>")]
+    pub timeout: Option<Duration>,
+    /// End the play on every host as soon as a task fails on one
+    #[serde(default)]
+    pub any_errors_fatal: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// `module_defaults`: default parameters by module name, short or fully
+/// qualified
+pub type ModuleDefaults = HashMap<String, HashMap<String, serde_json::Value>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TaskBatch {
     pub batch_id: String,
     pub hosts: Vec<String>,
@@ -66,10 +94,11 @@ pub struct TaskBatch {
     pub parallel_groups: Vec<ParallelGroup>,
     pub dependencies: Vec<String>,
     #[serde(with = "serde_duration_opt")]
+    #[schemars(with = "Option<DurationSchema>")]
     pub estimated_duration: Option<Duration>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TaskPlan {
     pub task_id: String,
     pub name: String,
@@ -83,11 +112,44 @@ pub struct TaskPlan {
     pub execution_order: u32,
     pub can_run_parallel: bool,
     #[serde(with = "serde_duration")]
+    #[schemars(with = "DurationSchema")]
     pub estimated_duration: Duration,
     pub risk_level: RiskLevel,
+    #[serde(default)]
+    pub run_once: bool,
+    #[serde(default)]
+    pub delegate_to: Option<String>,
+    #[serde(default)]
+    pub task_loop: Option<TaskLoop>,
+    #[serde(default)]
+    pub until: Option<UntilPolicy>,
+    #[serde(default)]
+    pub async_job: Option<AsyncPolicy>,
+    #[serde(default)]
+    pub failed_when: Option<ResultCondition>,
+    #[serde(default)]
+    pub changed_when: Option<ResultCondition>,
+    #[serde(default)]
+    pub ignore_errors: bool,
+    #[serde(default)]
+    pub register: Option<String>,
+    #[serde(default)]
+    pub no_log: bool,
+    #[serde(default)]
+    pub r#become: Option<BecomePolicy>,
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+    #[serde(default)]
+    pub module_defaults: ModuleDefaults,
+    #[serde(default, with = "serde_duration_opt")]
+    #[schemars(with = "Option<DurationSchema>")]
+    pub timeout: Option<Duration>,
+    /// Most hosts that may run the task at the same time
+    #[serde(default)]
+    pub throttle: Option<u32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum TaskCondition {
     Tag { tags: Vec<String> },
     When { expression: String },
@@ -95,7 +157,7 @@ pub enum TaskCondition {
     Only { hosts: Vec<String> },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum RiskLevel {
     Low,
     Medium,
@@ -103,14 +165,14 @@ pub enum RiskLevel {
     Critical,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ParallelGroup {
     pub group_id: String,
     pub tasks: Vec<String>,
     pub max_parallelism: Option<u32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct HandlerDefinition {
     pub handler_id: String,
     pub name: String,
@@ -118,9 +180,30 @@ pub struct HandlerDefinition {
     pub args: HashMap<String, serde_json::Value>,
     pub conditions: Vec<TaskCondition>,
     pub execution_order: u32,
+    #[serde(default)]
+    pub listen: Vec<String>,
+    #[serde(default)]
+    pub notify: Vec<String>,
+}
+
+/// A block of the play; sections hold task ids and nested block ids
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BlockDefinition {
+    pub block_id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub block: Vec<String>,
+    #[serde(default)]
+    pub rescue: Vec<String>,
+    #[serde(default)]
+    pub always: Vec<String>,
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+    #[serde(default)]
+    pub module_defaults: ModuleDefaults,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct BinaryDeploymentPlan {
     pub deployment_id: String,
     pub target_hosts: Vec<String>,
@@ -148,14 +231,17 @@ pub struct BinaryDeploymentPlan {
         skip_serializing_if = "Option::is_none",
         with = "serde_duration_opt_legacy"
     )]
+    #[schemars(with = "Option<DurationSchema>")]
     pub estimated_savings: Option<Duration>,
 
     // Existing template generation fields (unchanged)
     #[serde(default)]
     pub controller_endpoint: Option<String>,
     #[serde(default, with = "serde_duration_opt")]
+    #[schemars(with = "Option<DurationSchema>")]
     pub execution_timeout: Option<Duration>,
     #[serde(default, with = "serde_duration_opt")]
+    #[schemars(with = "Option<DurationSchema>")]
     pub report_interval: Option<Duration>,
     #[serde(default)]
     pub cleanup_on_completion: Option<bool>,
@@ -171,7 +257,7 @@ pub struct BinaryDeploymentPlan {
     pub verbose: Option<bool>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct CompilationRequirements {
     // New format fields
     #[serde(default)]
@@ -198,7 +284,7 @@ pub struct CompilationRequirements {
     pub features: Option<Vec<String>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct StaticFileRef {
     pub source_path: String,
     pub target_path: String,
@@ -206,21 +292,21 @@ pub struct StaticFileRef {
     pub compress: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SecretRef {
     pub key: String,
     pub source: SecretSource,
     pub target_env_var: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum SecretSource {
     File { path: String },
     Environment { var: String },
     Vault { path: String, key: String },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 pub struct EmbeddedData {
     #[serde(default)]
     pub execution_plan: String,
@@ -232,7 +318,7 @@ pub struct EmbeddedData {
     pub facts_required: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct EmbeddedStaticFile {
     pub src_path: String,
     pub dest_path: String,
@@ -240,7 +326,7 @@ pub struct EmbeddedStaticFile {
     pub size: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 pub enum ExecutionMode {
     #[default]
     Controller,
@@ -0,0 +1,522 @@
+//! SOPS decryption.
+//!
+//! Reads YAML and JSON files encrypted with SOPS. Every value of such a file
+//! is encrypted with AES-256-GCM under a data key and bound to its path in
+//! the document; the data key itself is encrypted for each age recipient,
+//! PGP key and AWS KMS key listed in the file's `sops` section. The data key
+//! is recovered with the first of those keys available on the controller:
+//! age identities directly, PGP keys through `gpg` and its agent, KMS keys
+//! through the `aws` CLI and its credentials. A MAC over all the values,
+//! encrypted with the data key, shows the file is as it was encrypted.
+
+use crate::execution::SopsError;
+use aes::Aes256;
+use aes_gcm::aead::{consts::U32, Aead, KeyInit, Payload};
+use aes_gcm::AesGcm;
+use base64::Engine;
+use regex::Regex;
+use serde::Deserialize;
+use serde_yaml::Value;
+use sha2::{Digest, Sha512};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+/// Age identities, one per line
+pub const SOPS_AGE_KEY_ENV: &str = "SOPS_AGE_KEY";
+/// File holding age identities
+pub const SOPS_AGE_KEY_FILE_ENV: &str = "SOPS_AGE_KEY_FILE";
+
+/// Key of the section holding the keys and MAC
+const METADATA_KEY: &str = "sops";
+const DATA_KEY_LEN: usize = 32;
+const ENCRYPTED_PREFIX: &str = "ENC[AES256_GCM,";
+
+/// AES-256-GCM with the 256-bit nonces SOPS uses
+type SopsCipher = AesGcm<Aes256, U32>;
+
+/// Whether `document` is SOPS-encrypted
+pub fn is_sops_document(document: &Value) -> bool {
+    document
+        .get(METADATA_KEY)
+        .and_then(|metadata| metadata.get("mac"))
+        .is_some()
+}
+
+/// The keys SOPS files are decrypted with
+#[derive(Clone, Default)]
+pub struct SopsKeys {
+    age: Vec<age::x25519::Identity>,
+}
+
+impl std::fmt::Debug for SopsKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SopsKeys")
+            .field("age", &format!("{} identities", self.age.len()))
+            .finish()
+    }
+}
+
+impl SopsKeys {
+    /// No age identities; PGP and KMS keys are still used where `gpg` and
+    /// `aws` can decrypt with them
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The age identities SOPS itself would use: those of
+    /// [`SOPS_AGE_KEY_ENV`], of the file [`SOPS_AGE_KEY_FILE_ENV`] names,
+    /// and of `sops/age/keys.txt` in the user's config directory
+    pub fn load() -> Result<Self, SopsError> {
+        let mut keys = Self::new();
+        if let Ok(identities) = std::env::var(SOPS_AGE_KEY_ENV) {
+            keys = keys.with_age_keys(&identities)?;
+        }
+        if let Some(path) = std::env::var_os(SOPS_AGE_KEY_FILE_ENV).filter(|p| !p.is_empty()) {
+            keys = keys.with_age_key_file(Path::new(&path))?;
+        }
+        let default = dirs::config_dir().map(|dir| dir.join("sops").join("age").join("keys.txt"));
+        if let Some(path) = default.filter(|path| path.is_file()) {
+            keys = keys.with_age_key_file(&path)?;
+        }
+        Ok(keys)
+    }
+
+    pub fn with_age_identity(mut self, identity: age::x25519::Identity) -> Self {
+        self.age.push(identity);
+        self
+    }
+
+    /// Add the age identities of `keys`, one `AGE-SECRET-KEY-1…` per line;
+    /// lines starting with `#` are comments
+    pub fn with_age_keys(mut self, keys: &str) -> Result<Self, SopsError> {
+        for line in keys.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let identity = age::x25519::Identity::from_str(line).map_err(|reason| {
+                SopsError::InvalidIdentity {
+                    reason: reason.to_string(),
+                }
+            })?;
+            self.age.push(identity);
+        }
+        Ok(self)
+    }
+
+    /// Add the age identities of the key file at `path`
+    pub fn with_age_key_file(self, path: &Path) -> Result<Self, SopsError> {
+        let keys = std::fs::read_to_string(path)?;
+        self.with_age_keys(&keys).map_err(|e| match e {
+            SopsError::InvalidIdentity { reason } => SopsError::InvalidIdentity {
+                reason: format!("{}: {reason}", path.display()),
+            },
+            other => other,
+        })
+    }
+
+    /// Decrypt the SOPS-encrypted YAML or JSON document `data`, returning it
+    /// without its `sops` section
+    pub fn decrypt(&self, data: &[u8]) -> Result<Value, SopsError> {
+        let invalid = |reason: String| SopsError::InvalidFormat { reason };
+        let document: Value = serde_yaml::from_slice(data).map_err(|e| invalid(e.to_string()))?;
+        let Value::Mapping(mut tree) = document else {
+            return Err(invalid("the document is not a mapping".to_string()));
+        };
+        let metadata = tree
+            .remove(METADATA_KEY)
+            .ok_or_else(|| invalid("no sops section".to_string()))?;
+        let metadata: Metadata =
+            serde_yaml::from_value(metadata).map_err(|e| invalid(format!("sops section: {e}")))?;
+
+        let key = self.data_key(&metadata)?;
+        let rules = Rules::new(&metadata)?;
+        let mut tree = Value::Mapping(tree);
+        let mut mac = Sha512::new();
+        decrypt_tree(&mut tree, &mut Vec::new(), &key, &rules, &mut mac)?;
+
+        // Encrypted comments are part of the MAC, but YAML parsing drops
+        // them; the values are still authenticated one by one
+        if String::from_utf8_lossy(data).contains(",type:comment]") {
+            tracing::warn!("Not checking the MAC of a SOPS file with encrypted comments");
+        } else {
+            let expected = decrypt_value(&metadata.mac, &key, &metadata.lastmodified, "mac")?;
+            let computed = format!("{:X}", mac.finalize());
+            if expected.as_str() != Some(computed.as_str()) {
+                return Err(SopsError::MacMismatch);
+            }
+        }
+        Ok(tree)
+    }
+
+    /// The data key of the file, from the first of its keys available here
+    fn data_key(&self, metadata: &Metadata) -> Result<Vec<u8>, SopsError> {
+        let mut groups = metadata.key_groups.clone();
+        if groups.len() > 1 {
+            return Err(SopsError::Unsupported {
+                reason: "data keys split across key groups with Shamir's secret sharing"
+                    .to_string(),
+            });
+        }
+        let group = groups.pop().unwrap_or_else(|| metadata.keys.clone());
+
+        let mut tried = Vec::new();
+        for key in &group.age {
+            match self.age_data_key(key) {
+                Ok(data_key) => return Ok(data_key),
+                Err(reason) => tried.push(format!("age {}: {reason}", key.recipient)),
+            }
+        }
+        for key in &group.pgp {
+            match key.data_key() {
+                Ok(data_key) => return Ok(data_key),
+                Err(reason) => tried.push(format!("pgp {}: {reason}", key.fp)),
+            }
+        }
+        for key in &group.kms {
+            match key.data_key() {
+                Ok(data_key) => return Ok(data_key),
+                Err(reason) => tried.push(format!("kms {}: {reason}", key.arn)),
+            }
+        }
+        for (backend, count) in [
+            ("gcp_kms", group.gcp_kms.len()),
+            ("azure_kv", group.azure_kv.len()),
+            ("hc_vault", group.hc_vault.len()),
+        ] {
+            if count > 0 {
+                tried.push(format!("{backend}: not supported"));
+            }
+        }
+        Err(SopsError::NoKey {
+            tried: if tried.is_empty() {
+                "the file lists no keys".to_string()
+            } else {
+                tried.join("; ")
+            },
+        })
+    }
+
+    fn age_data_key(&self, key: &AgeKey) -> Result<Vec<u8>, String> {
+        if self.age.is_empty() {
+            return Err("no age identity".to_string());
+        }
+        let armored = age::armor::ArmoredReader::new(key.enc.as_bytes());
+        let decryptor = age::Decryptor::new_buffered(armored).map_err(|e| e.to_string())?;
+        let mut reader = decryptor
+            .decrypt(
+                self.age
+                    .iter()
+                    .map(|identity| identity as &dyn age::Identity),
+            )
+            .map_err(|e| e.to_string())?;
+        let mut data_key = Vec::new();
+        reader
+            .read_to_end(&mut data_key)
+            .map_err(|e| e.to_string())?;
+        check_data_key(data_key)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct Metadata {
+    #[serde(flatten)]
+    keys: KeyGroup,
+    key_groups: Vec<KeyGroup>,
+    lastmodified: String,
+    mac: String,
+    mac_only_encrypted: bool,
+    unencrypted_suffix: Option<String>,
+    encrypted_suffix: Option<String>,
+    unencrypted_regex: Option<String>,
+    encrypted_regex: Option<String>,
+}
+
+/// Keys any one of which decrypts the data key
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct KeyGroup {
+    age: Vec<AgeKey>,
+    pgp: Vec<PgpKey>,
+    kms: Vec<KmsKey>,
+    gcp_kms: Vec<serde_yaml::Value>,
+    azure_kv: Vec<serde_yaml::Value>,
+    hc_vault: Vec<serde_yaml::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct AgeKey {
+    recipient: String,
+    enc: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PgpKey {
+    fp: String,
+    enc: String,
+}
+
+impl PgpKey {
+    fn data_key(&self) -> Result<Vec<u8>, String> {
+        let output = run(
+            Command::new("gpg").args(["--batch", "--quiet", "--no-tty", "--decrypt"]),
+            self.enc.as_bytes(),
+        )?;
+        check_data_key(output)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct KmsKey {
+    arn: String,
+    enc: String,
+    #[serde(default)]
+    context: BTreeMap<String, String>,
+    #[serde(default)]
+    aws_profile: Option<String>,
+}
+
+impl KmsKey {
+    fn data_key(&self) -> Result<Vec<u8>, String> {
+        let base64 = base64::engine::general_purpose::STANDARD;
+        let blob = base64.decode(self.enc.trim()).map_err(|e| e.to_string())?;
+        let mut blob_file = tempfile::NamedTempFile::new().map_err(|e| e.to_string())?;
+        blob_file.write_all(&blob).map_err(|e| e.to_string())?;
+
+        let mut command = Command::new("aws");
+        command
+            .args(["kms", "decrypt", "--output", "text", "--query", "Plaintext"])
+            .arg("--key-id")
+            .arg(&self.arn)
+            .arg("--ciphertext-blob")
+            .arg(format!("fileb://{}", blob_file.path().display()));
+        // arn:aws:kms:<region>:<account>:key/<id>
+        if let Some(region) = self.arn.split(':').nth(3).filter(|r| !r.is_empty()) {
+            command.arg("--region").arg(region);
+        }
+        if !self.context.is_empty() {
+            let context = serde_json::to_string(&self.context).map_err(|e| e.to_string())?;
+            command.arg("--encryption-context").arg(context);
+        }
+        if let Some(profile) = &self.aws_profile {
+            command.arg("--profile").arg(profile);
+        }
+        let output = run(&mut command, &[])?;
+        let plaintext = String::from_utf8_lossy(&output);
+        let data_key = base64
+            .decode(plaintext.trim())
+            .map_err(|e| format!("unexpected output of aws: {e}"))?;
+        check_data_key(data_key)
+    }
+}
+
+/// Run `command` with `input` on stdin, returning what it prints
+fn run(command: &mut Command, input: &[u8]) -> Result<Vec<u8>, String> {
+    let program = PathBuf::from(command.get_program());
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run {}: {e}", program.display()))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input).map_err(|e| e.to_string())?;
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} exited with {}: {}",
+            program.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+fn check_data_key(data_key: Vec<u8>) -> Result<Vec<u8>, String> {
+    if data_key.len() == DATA_KEY_LEN {
+        Ok(data_key)
+    } else {
+        Err(format!(
+            "decrypted a {}-byte data key, expected {DATA_KEY_LEN}",
+            data_key.len()
+        ))
+    }
+}
+
+/// Which values of the document are encrypted, by their path
+struct Rules {
+    unencrypted_suffix: Option<String>,
+    encrypted_suffix: Option<String>,
+    unencrypted_regex: Option<Regex>,
+    encrypted_regex: Option<Regex>,
+    mac_only_encrypted: bool,
+}
+
+impl Rules {
+    fn new(metadata: &Metadata) -> Result<Self, SopsError> {
+        let regex = |pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .filter(|pattern| !pattern.is_empty())
+                .map(Regex::new)
+                .transpose()
+                .map_err(|e| SopsError::InvalidFormat {
+                    reason: e.to_string(),
+                })
+        };
+        let suffix = |suffix: &Option<String>| suffix.clone().filter(|s| !s.is_empty());
+        Ok(Self {
+            unencrypted_suffix: suffix(&metadata.unencrypted_suffix),
+            encrypted_suffix: suffix(&metadata.encrypted_suffix),
+            unencrypted_regex: regex(&metadata.unencrypted_regex)?,
+            encrypted_regex: regex(&metadata.encrypted_regex)?,
+            mac_only_encrypted: metadata.mac_only_encrypted,
+        })
+    }
+
+    /// Whether the value at `path` is encrypted, as SOPS decides it
+    fn encrypted(&self, path: &[String]) -> bool {
+        let mut encrypted = true;
+        if let Some(suffix) = &self.unencrypted_suffix {
+            encrypted = !path.iter().any(|key| key.ends_with(suffix.as_str()));
+        }
+        if let Some(suffix) = &self.encrypted_suffix {
+            encrypted = path.iter().any(|key| key.ends_with(suffix.as_str()));
+        }
+        if let Some(regex) = &self.unencrypted_regex {
+            if path.iter().any(|key| regex.is_match(key)) {
+                encrypted = false;
+            }
+        }
+        if let Some(regex) = &self.encrypted_regex {
+            encrypted = path.iter().any(|key| regex.is_match(key));
+        }
+        encrypted
+    }
+}
+
+/// Decrypt the values under `value`, at `path`, adding them to `mac` in
+/// document order
+fn decrypt_tree(
+    value: &mut Value,
+    path: &mut Vec<String>,
+    key: &[u8],
+    rules: &Rules,
+    mac: &mut Sha512,
+) -> Result<(), SopsError> {
+    match value {
+        Value::Mapping(map) => {
+            for (name, item) in map.iter_mut() {
+                path.push(match name {
+                    Value::String(name) => name.clone(),
+                    other => scalar_bytes(other),
+                });
+                decrypt_tree(item, path, key, rules, mac)?;
+                path.pop();
+            }
+        }
+        // Items of a sequence share its path
+        Value::Sequence(items) => {
+            for item in items {
+                decrypt_tree(item, path, key, rules, mac)?;
+            }
+        }
+        Value::Tagged(tagged) => decrypt_tree(&mut tagged.value, path, key, rules, mac)?,
+        leaf => {
+            let encrypted = rules.encrypted(path);
+            if encrypted {
+                if let Value::String(text) = leaf {
+                    if !text.is_empty() {
+                        let additional_data = format!("{}:", path.join(":"));
+                        *leaf = decrypt_value(text, key, &additional_data, &path.join(":"))?;
+                    }
+                }
+            }
+            if encrypted || !rules.mac_only_encrypted {
+                mac.update(scalar_bytes(leaf));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A value as SOPS adds it to the MAC
+fn scalar_bytes(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Bool(true) => "True".to_string(),
+        Value::Bool(false) => "False".to_string(),
+        // Floats as Go formats them, never with an exponent
+        Value::Number(number) => match number.as_f64().filter(|_| number.is_f64()) {
+            Some(float) => float.to_string(),
+            None => number.to_string(),
+        },
+        _ => String::new(),
+    }
+}
+
+/// Decrypt `ENC[AES256_GCM,data:…,iv:…,tag:…,type:…]`, authenticated with
+/// `additional_data`
+fn decrypt_value(
+    encrypted: &str,
+    key: &[u8],
+    additional_data: &str,
+    path: &str,
+) -> Result<Value, SopsError> {
+    let invalid = || SopsError::InvalidFormat {
+        reason: format!("'{path}' is not a SOPS-encrypted value"),
+    };
+    let fields = encrypted
+        .strip_prefix(ENCRYPTED_PREFIX)
+        .and_then(|rest| rest.strip_suffix(']'))
+        .ok_or_else(invalid)?;
+    let fields: BTreeMap<_, _> = fields
+        .split(',')
+        .filter_map(|field| field.split_once(':'))
+        .collect();
+    let base64 = base64::engine::general_purpose::STANDARD;
+    let decode = |name: &str| {
+        fields
+            .get(name)
+            .and_then(|value| base64.decode(value).ok())
+            .ok_or_else(invalid)
+    };
+    let (mut data, iv, tag) = (decode("data")?, decode("iv")?, decode("tag")?);
+    let value_type = *fields.get("type").ok_or_else(invalid)?;
+    if iv.len() != 32 {
+        return Err(invalid());
+    }
+
+    data.extend_from_slice(&tag);
+    let cipher = SopsCipher::new_from_slice(key).map_err(|_| invalid())?;
+    let failed = || SopsError::DecryptionFailed {
+        path: path.to_string(),
+    };
+    let plaintext = cipher
+        .decrypt(
+            iv.as_slice().into(),
+            Payload {
+                msg: &data,
+                aad: additional_data.as_bytes(),
+            },
+        )
+        .map_err(|_| failed())?;
+    let plaintext = String::from_utf8(plaintext).map_err(|_| failed())?;
+
+    Ok(match value_type {
+        "str" | "bytes" | "comment" => Value::String(plaintext),
+        "int" => Value::Number(plaintext.parse::<i64>().map_err(|_| failed())?.into()),
+        "float" => Value::Number(plaintext.parse::<f64>().map_err(|_| failed())?.into()),
+        "bool" => Value::Bool(plaintext.eq_ignore_ascii_case("true")),
+        other => {
+            return Err(SopsError::Unsupported {
+                reason: format!("value type '{other}' at '{path}'"),
+            })
+        }
+    })
+}
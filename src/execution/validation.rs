@@ -411,6 +411,7 @@ mod tests {
         use super::super::rustle_plan::*;
 
         RustlePlanOutput {
+            schema_version: super::super::plan_schema::PLAN_SCHEMA_VERSION,
             metadata: RustlePlanMetadata {
                 created_at: Utc::now(),
                 rustle_plan_version: "0.1.0".to_string(),
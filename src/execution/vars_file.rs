@@ -0,0 +1,252 @@
+//! Variables files.
+//!
+//! A variables file is a YAML or JSON mapping of variable names to values,
+//! given to a deployment as `ansible-playbook -e @FILE` would take it. It
+//! may be encrypted with Ansible Vault, as a whole or value by value, or
+//! with SOPS. Files are decrypted in memory while the plan is processed, so
+//! no decrypted copy of them is written out, and their variables are
+//! rendered into the arguments of the plan's tasks and handlers. The
+//! strings that were encrypted can be kept out of the plans embedded in
+//! runners; see [`crate::runtime::sealed_secrets`].
+//!
+//! Variable definitions and task arguments may call lookup plugins, which
+//! are evaluated here when the controller evaluates them; see
+//! [`crate::modules::files::template_engine::lookup_plugins`].
+
+use crate::execution::rustle_plan::RustlePlanOutput;
+use crate::execution::{is_sops_document, is_vaulted, SopsKeys, VarsFileError, VaultSecrets};
+use crate::modules::files::template_engine::{
+    AdvancedTemplateProcessor, LookupContext, LookupRegistry, LookupSide,
+};
+use crate::runtime::{LookupError, SensitiveValues};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Loads variables files, decrypting them with the keys it was given
+#[derive(Debug, Clone, Default)]
+pub struct VarsFileLoader {
+    vault: VaultSecrets,
+    sops: SopsKeys,
+}
+
+impl VarsFileLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decrypt vaulted files and values with `secrets`
+    pub fn with_vault(mut self, secrets: VaultSecrets) -> Self {
+        self.vault = secrets;
+        self
+    }
+
+    /// Decrypt SOPS files with `keys`
+    pub fn with_sops(mut self, keys: SopsKeys) -> Self {
+        self.sops = keys;
+        self
+    }
+
+    /// The variables of the file at `path`
+    pub fn load(&self, path: &Path) -> Result<HashMap<String, serde_json::Value>, VarsFileError> {
+        Ok(self.read(path)?.0)
+    }
+
+    /// Like [`Self::load`], also returning the strings that were encrypted
+    pub fn load_with_sensitive(
+        &self,
+        path: &Path,
+    ) -> Result<(HashMap<String, serde_json::Value>, SensitiveValues), VarsFileError> {
+        self.read(path)
+    }
+
+    /// The variables of all of `paths`, those of later files taking
+    /// precedence
+    pub fn load_all(
+        &self,
+        paths: &[PathBuf],
+    ) -> Result<HashMap<String, serde_json::Value>, VarsFileError> {
+        Ok(self.load_all_with_sensitive(paths)?.0)
+    }
+
+    /// Like [`Self::load_all`], also returning the strings that were
+    /// encrypted: all those of encrypted files, and the vaulted values of
+    /// the others
+    pub fn load_all_with_sensitive(
+        &self,
+        paths: &[PathBuf],
+    ) -> Result<(HashMap<String, serde_json::Value>, SensitiveValues), VarsFileError> {
+        let mut vars = HashMap::new();
+        let mut sensitive = SensitiveValues::new();
+        for path in paths {
+            let (file_vars, file_sensitive) = self.read(path)?;
+            vars.extend(file_vars);
+            sensitive.extend(file_sensitive);
+        }
+        Ok((vars, sensitive))
+    }
+
+    fn read(
+        &self,
+        path: &Path,
+    ) -> Result<(HashMap<String, serde_json::Value>, SensitiveValues), VarsFileError> {
+        let display = || path.display().to_string();
+        let vault_error = |source| VarsFileError::Vault {
+            path: display(),
+            source,
+        };
+        let invalid = |reason: String| VarsFileError::Invalid {
+            path: display(),
+            reason,
+        };
+
+        let mut data = std::fs::read(path).map_err(|source| VarsFileError::Io {
+            path: display(),
+            source,
+        })?;
+        let mut encrypted = is_vaulted(&data);
+        if encrypted {
+            data = self.vault.decrypt(&data).map_err(vault_error)?;
+        }
+        let mut vars: serde_yaml::Value =
+            serde_yaml::from_slice(&data).map_err(|e| invalid(e.to_string()))?;
+        if is_sops_document(&vars) {
+            encrypted = true;
+            vars = self
+                .sops
+                .decrypt(&data)
+                .map_err(|source| VarsFileError::Sops {
+                    path: display(),
+                    source,
+                })?;
+        }
+        let vaulted = vars.clone();
+        self.vault.decrypt_yaml(&mut vars).map_err(vault_error)?;
+        let mut sensitive = SensitiveValues::new();
+        collect_vaulted(&vaulted, &vars, &mut sensitive);
+
+        let vars = match serde_json::to_value(vars).map_err(|e| invalid(e.to_string()))? {
+            serde_json::Value::Object(vars) => vars.into_iter().collect::<HashMap<_, _>>(),
+            serde_json::Value::Null => HashMap::new(),
+            _ => return Err(invalid("expected a mapping of variables".to_string())),
+        };
+        if encrypted {
+            vars.values().for_each(|value| sensitive.insert_all(value));
+        }
+        Ok((vars, sensitive))
+    }
+}
+
+/// Add the strings of `decrypted` that were vaulted in `vaulted` to
+/// `sensitive`
+fn collect_vaulted(
+    vaulted: &serde_yaml::Value,
+    decrypted: &serde_yaml::Value,
+    sensitive: &mut SensitiveValues,
+) {
+    use serde_yaml::Value;
+    match (vaulted, decrypted) {
+        (Value::Tagged(tagged), Value::String(secret)) if tagged.tag == "vault" => {
+            sensitive.insert(secret.as_str());
+        }
+        (Value::String(vaulted), Value::String(secret)) if is_vaulted(vaulted.as_bytes()) => {
+            sensitive.insert(secret.as_str());
+        }
+        (Value::Tagged(vaulted), Value::Tagged(decrypted)) => {
+            collect_vaulted(&vaulted.value, &decrypted.value, sensitive)
+        }
+        (Value::Mapping(vaulted), Value::Mapping(decrypted)) => {
+            for (key, item) in vaulted {
+                if let Some(decrypted) = decrypted.get(key) {
+                    collect_vaulted(item, decrypted, sensitive);
+                }
+            }
+        }
+        (Value::Sequence(vaulted), Value::Sequence(decrypted)) => {
+            for (item, decrypted) in vaulted.iter().zip(decrypted) {
+                collect_vaulted(item, decrypted, sensitive);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Render the Jinja2 expressions and statements referring to `variables`
+/// only in the arguments of the tasks and handlers of `plan`; the others
+/// are left for the runner
+pub fn render_plan_variables(
+    plan: &mut RustlePlanOutput,
+    variables: &HashMap<String, serde_json::Value>,
+) {
+    if variables.is_empty() {
+        return;
+    }
+    let processor = AdvancedTemplateProcessor::default();
+    let render_args = |args: &mut HashMap<String, serde_json::Value>| {
+        for value in args.values_mut() {
+            *value = processor.render_value(value, variables);
+        }
+    };
+    for play in &mut plan.plays {
+        for batch in &mut play.batches {
+            for task in &mut batch.tasks {
+                render_args(&mut task.args);
+            }
+        }
+        for handler in &mut play.handlers {
+            render_args(&mut handler.args);
+        }
+    }
+}
+
+/// Evaluate the lookups in the arguments of the tasks and handlers of
+/// `plan` whose plugins are evaluated on one of `sides`, leaving the others
+/// for the runners. Returns the strings sensitive lookups returned, to keep
+/// them out of embedded plans.
+pub async fn resolve_plan_lookups(
+    plan: &mut RustlePlanOutput,
+    lookups: &LookupRegistry,
+    context: &LookupContext,
+    sides: &[LookupSide],
+) -> Result<SensitiveValues, LookupError> {
+    let mut sensitive = SensitiveValues::new();
+    for play in &mut plan.plays {
+        let tasks = play.batches.iter_mut().flat_map(|batch| &mut batch.tasks);
+        let args = tasks
+            .map(|task| &mut task.args)
+            .chain(play.handlers.iter_mut().map(|handler| &mut handler.args));
+        for args in args {
+            for value in args.values_mut() {
+                *value = lookups
+                    .evaluate(value, context, sides, &mut sensitive)
+                    .await?;
+            }
+        }
+    }
+    Ok(sensitive)
+}
+
+/// Evaluate the lookups of variable definitions the controller evaluates,
+/// in the order of their names. A definition's lookups may refer to the
+/// variables defined. Returns the strings sensitive lookups returned.
+pub async fn resolve_variable_lookups(
+    variables: &mut HashMap<String, serde_json::Value>,
+    lookups: &LookupRegistry,
+    context: &LookupContext,
+) -> Result<SensitiveValues, LookupError> {
+    let mut sensitive = SensitiveValues::new();
+    let mut names: Vec<String> = variables.keys().cloned().collect();
+    names.sort();
+    for name in names {
+        let context = context.clone().with_variables(variables.clone());
+        let evaluated = lookups
+            .evaluate(
+                &variables[&name],
+                &context,
+                &[LookupSide::Controller],
+                &mut sensitive,
+            )
+            .await?;
+        variables.insert(name, evaluated);
+    }
+    Ok(sensitive)
+}
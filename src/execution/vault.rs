@@ -0,0 +1,514 @@
+//! Ansible Vault decryption.
+//!
+//! Reads data encrypted with `ansible-vault`: whole files starting with a
+//! `$ANSIBLE_VAULT;1.1;AES256` or `$ANSIBLE_VAULT;1.2;AES256;<vault id>`
+//! header, and strings encrypted inline, which YAML marks with a `!vault`
+//! tag and JSON carries as `{"__ansible_vault": "..."}`. The vault format
+//! derives an AES-256-CTR key, an HMAC-SHA256 key and the counter from the
+//! password and a salt with PBKDF2, and authenticates the ciphertext with
+//! the HMAC before decrypting it.
+//!
+//! Passwords come from a prompt, a file, or a script printing them, one per
+//! vault id, as `ansible-playbook --vault-id` takes them. Data labelled with
+//! a vault id is tried with the password of that id first, then with the
+//! others.
+
+use crate::execution::VaultError;
+use aes::Aes256;
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// What vault-encrypted data starts with
+pub const VAULT_HEADER: &str = "$ANSIBLE_VAULT";
+/// Key of the object a vault-encrypted string is in JSON
+pub const JSON_VAULT_KEY: &str = "__ansible_vault";
+/// Password file used when no vault id is given
+pub const VAULT_PASSWORD_FILE_ENV: &str = "ANSIBLE_VAULT_PASSWORD_FILE";
+/// Vault id of passwords given without one
+pub const DEFAULT_VAULT_ID: &str = "default";
+
+const PBKDF2_ITERATIONS: u32 = 10_000;
+const KEY_LEN: usize = 32;
+const IV_LEN: usize = 16;
+const SALT_LEN: usize = 32;
+/// Line length of the hex body `ansible-vault` writes
+const LINE_LEN: usize = 80;
+
+type Aes256Ctr = ctr::Ctr128BE<Aes256>;
+
+/// Whether `data` is vault-encrypted
+pub fn is_vaulted(data: &[u8]) -> bool {
+    data.starts_with(VAULT_HEADER.as_bytes())
+}
+
+/// Whether the file at `path` is vault-encrypted
+pub fn file_is_vaulted(path: &Path) -> bool {
+    use std::io::Read;
+    let mut header = [0u8; VAULT_HEADER.len()];
+    std::fs::File::open(path).is_ok_and(|mut file| file.read_exact(&mut header).is_ok())
+        && is_vaulted(&header)
+}
+
+/// The password of one vault id
+#[derive(Clone)]
+pub struct VaultSecret {
+    pub id: String,
+    password: Vec<u8>,
+}
+
+impl VaultSecret {
+    pub fn new(id: impl Into<String>, password: impl Into<Vec<u8>>) -> Self {
+        Self {
+            id: id.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Encrypt `plaintext` the way `ansible-vault encrypt` does, labelled
+    /// with the vault id unless it is [`DEFAULT_VAULT_ID`]
+    pub fn encrypt(&self, plaintext: &[u8]) -> String {
+        use crypto_box::aead::{rand_core::RngCore, OsRng};
+
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let (key, hmac_key, iv) = derive_keys(&self.password, &salt);
+
+        let padding = 16 - plaintext.len() % 16;
+        let mut ciphertext = plaintext.to_vec();
+        ciphertext.resize(plaintext.len() + padding, padding as u8);
+        Aes256Ctr::new(&key.into(), &iv.into()).apply_keystream(&mut ciphertext);
+        let mut mac = hmac_for(&hmac_key);
+        mac.update(&ciphertext);
+
+        let inner = format!(
+            "{}\n{}\n{}",
+            hex_encode(&salt),
+            hex_encode(&mac.finalize().into_bytes()),
+            hex_encode(&ciphertext)
+        );
+        let body = hex_encode(inner.as_bytes());
+        let mut vaulted = if self.id == DEFAULT_VAULT_ID {
+            format!("{VAULT_HEADER};1.1;AES256\n")
+        } else {
+            format!("{VAULT_HEADER};1.2;AES256;{}\n", self.id)
+        };
+        for line in body.as_bytes().chunks(LINE_LEN) {
+            vaulted.push_str(std::str::from_utf8(line).unwrap_or_default());
+            vaulted.push('\n');
+        }
+        vaulted
+    }
+}
+
+impl std::fmt::Debug for VaultSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VaultSecret")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Where the password of a vault id comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PasswordSource {
+    /// Asked for on the terminal
+    Prompt,
+    /// Read from a file, or printed by it when it is executable
+    File(PathBuf),
+}
+
+/// A vault id and where its password comes from, given as `id@source` or
+/// just `source`, where `source` is `prompt` or a path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultIdentity {
+    pub id: String,
+    pub source: PasswordSource,
+}
+
+impl VaultIdentity {
+    pub fn prompt(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            source: PasswordSource::Prompt,
+        }
+    }
+
+    pub fn file(id: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            id: id.into(),
+            source: PasswordSource::File(path.into()),
+        }
+    }
+
+    /// Get the password: ask for it, read it from the file, or run the file
+    /// if it is executable and take what it prints. Scripts named
+    /// `*-client` are told the vault id with `--vault-id`.
+    pub fn load(&self) -> Result<VaultSecret, VaultError> {
+        let failed = |reason: String| VaultError::Password {
+            id: self.id.clone(),
+            reason,
+        };
+        let password = match &self.source {
+            PasswordSource::Prompt => {
+                rpassword::prompt_password(format!("Vault password ({}): ", self.id))
+                    .map_err(|e| failed(e.to_string()))?
+            }
+            PasswordSource::File(path) if is_executable(path) => {
+                let mut command = std::process::Command::new(path);
+                let client = path
+                    .file_stem()
+                    .is_some_and(|stem| stem.to_string_lossy().ends_with("-client"));
+                if client {
+                    command.arg("--vault-id").arg(&self.id);
+                }
+                let output = command
+                    .output()
+                    .map_err(|e| failed(format!("failed to run {}: {e}", path.display())))?;
+                if !output.status.success() {
+                    return Err(failed(format!(
+                        "{} exited with {}: {}",
+                        path.display(),
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )));
+                }
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            }
+            PasswordSource::File(path) => std::fs::read_to_string(path)
+                .map_err(|e| failed(format!("failed to read {}: {e}", path.display())))?,
+        };
+        let password = password.trim();
+        if password.is_empty() {
+            return Err(failed("the password is empty".to_string()));
+        }
+        Ok(VaultSecret::new(self.id.clone(), password))
+    }
+}
+
+impl FromStr for VaultIdentity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (id, source) = match s.split_once('@') {
+            Some((id, source)) => (id, source),
+            None => (DEFAULT_VAULT_ID, s),
+        };
+        if id.is_empty() || source.is_empty() {
+            return Err(format!("invalid vault id '{s}', expected ID@SOURCE"));
+        }
+        Ok(match source {
+            "prompt" => Self::prompt(id),
+            path => Self::file(id, path),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path)
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("exe") || ext.eq_ignore_ascii_case("bat"))
+}
+
+/// The vault passwords of a run
+#[derive(Debug, Clone, Default)]
+pub struct VaultSecrets {
+    secrets: Vec<VaultSecret>,
+}
+
+impl VaultSecrets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_secret(mut self, secret: VaultSecret) -> Self {
+        self.secrets.push(secret);
+        self
+    }
+
+    /// Get the password of each of `identities`, in order, falling back to
+    /// [`VAULT_PASSWORD_FILE_ENV`] when there are none
+    pub fn load(identities: &[VaultIdentity]) -> Result<Self, VaultError> {
+        let from_env = std::env::var_os(VAULT_PASSWORD_FILE_ENV)
+            .filter(|path| !path.is_empty())
+            .map(|path| VaultIdentity::file(DEFAULT_VAULT_ID, path));
+        let identities = match (identities, &from_env) {
+            ([], Some(identity)) => std::slice::from_ref(identity),
+            _ => identities,
+        };
+        let mut secrets = Self::new();
+        for identity in identities {
+            secrets = secrets.with_secret(identity.load()?);
+        }
+        Ok(secrets)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+
+    /// Decrypt vault-encrypted `data`
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, VaultError> {
+        let envelope = Envelope::parse(data)?;
+        if self.secrets.is_empty() {
+            return Err(VaultError::NoSecrets);
+        }
+        // The labelled vault id first, then the others
+        let mut secrets: Vec<_> = self.secrets.iter().collect();
+        secrets.sort_by_key(|secret| Some(secret.id.as_str()) != envelope.id.as_deref());
+        secrets
+            .into_iter()
+            .find_map(|secret| envelope.decrypt(&secret.password))
+            .ok_or_else(|| VaultError::DecryptionFailed {
+                tried: self
+                    .secrets
+                    .iter()
+                    .map(|secret| secret.id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            })
+    }
+
+    /// Decrypt a vault-encrypted string
+    pub fn decrypt_str(&self, vaulted: &str) -> Result<String, VaultError> {
+        String::from_utf8(self.decrypt(vaulted.trim().as_bytes())?).map_err(|_| {
+            VaultError::InvalidFormat {
+                reason: "decrypted value is not UTF-8".to_string(),
+            }
+        })
+    }
+
+    /// Decrypt the vault-encrypted strings anywhere in `value`
+    pub fn decrypt_json(&self, value: &mut serde_json::Value) -> Result<(), VaultError> {
+        match value {
+            serde_json::Value::String(s) if is_vaulted(s.as_bytes()) => {
+                *s = self.decrypt_str(s)?;
+            }
+            serde_json::Value::Object(map) => {
+                let vaulted = match map.get(JSON_VAULT_KEY) {
+                    Some(serde_json::Value::String(s)) if map.len() == 1 => Some(s.clone()),
+                    _ => None,
+                };
+                match vaulted {
+                    Some(vaulted) => *value = self.decrypt_str(&vaulted)?.into(),
+                    None => {
+                        for item in map.values_mut() {
+                            self.decrypt_json(item)?;
+                        }
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    self.decrypt_json(item)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Decrypt the `!vault` strings anywhere in `value`
+    pub fn decrypt_yaml(&self, value: &mut serde_yaml::Value) -> Result<(), VaultError> {
+        match value {
+            serde_yaml::Value::Tagged(tagged) if tagged.tag == "vault" => {
+                let vaulted = tagged
+                    .value
+                    .as_str()
+                    .ok_or_else(|| VaultError::InvalidFormat {
+                        reason: "!vault value is not a string".to_string(),
+                    })?;
+                *value = self.decrypt_str(vaulted)?.into();
+            }
+            serde_yaml::Value::Tagged(tagged) => self.decrypt_yaml(&mut tagged.value)?,
+            serde_yaml::Value::String(s) if is_vaulted(s.as_bytes()) => {
+                *s = self.decrypt_str(s)?;
+            }
+            serde_yaml::Value::Mapping(map) => {
+                for (_, item) in map.iter_mut() {
+                    self.decrypt_yaml(item)?;
+                }
+            }
+            serde_yaml::Value::Sequence(items) => {
+                for item in items {
+                    self.decrypt_yaml(item)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The contents of `path`, decrypted if the whole file is vaulted
+    pub fn read_file(&self, path: &Path) -> Result<Vec<u8>, VaultError> {
+        let data = std::fs::read(path)?;
+        if is_vaulted(&data) {
+            self.decrypt(&data)
+        } else {
+            Ok(data)
+        }
+    }
+}
+
+/// The parts of vault-encrypted data
+struct Envelope {
+    /// Vault id of format 1.2
+    id: Option<String>,
+    salt: Vec<u8>,
+    hmac: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+impl Envelope {
+    fn parse(data: &[u8]) -> Result<Self, VaultError> {
+        let invalid = |reason: &str| VaultError::InvalidFormat {
+            reason: reason.to_string(),
+        };
+        let text = std::str::from_utf8(data).map_err(|_| invalid("not ASCII"))?;
+        let mut lines = text.trim().lines();
+        let header = lines.next().unwrap_or_default().trim();
+        let fields: Vec<_> = header.split(';').collect();
+        let id = match fields.as_slice() {
+            [VAULT_HEADER, "1.1" | "1.0", cipher] => {
+                check_cipher(cipher)?;
+                None
+            }
+            [VAULT_HEADER, "1.2", cipher, id] => {
+                check_cipher(cipher)?;
+                Some(id.to_string())
+            }
+            _ => return Err(invalid("unknown header")),
+        };
+
+        let body: String = lines.map(str::trim).collect();
+        let inner = hex_decode(&body).ok_or_else(|| invalid("body is not hex"))?;
+        let inner = String::from_utf8(inner).map_err(|_| invalid("body is not hex"))?;
+        let mut parts = inner.split('\n');
+        let mut part = || {
+            parts
+                .next()
+                .and_then(hex_decode)
+                .ok_or_else(|| invalid("expected salt, HMAC and ciphertext"))
+        };
+        let (salt, hmac, ciphertext) = (part()?, part()?, part()?);
+        if ciphertext.is_empty() || !ciphertext.len().is_multiple_of(16) {
+            return Err(invalid("ciphertext is not a whole number of blocks"));
+        }
+        Ok(Self {
+            id,
+            salt,
+            hmac,
+            ciphertext,
+        })
+    }
+
+    /// The plaintext, if `password` is the right one
+    fn decrypt(&self, password: &[u8]) -> Option<Vec<u8>> {
+        let (key, hmac_key, iv) = derive_keys(password, &self.salt);
+        let mut mac = hmac_for(&hmac_key);
+        mac.update(&self.ciphertext);
+        mac.verify_slice(&self.hmac).ok()?;
+
+        let mut plaintext = self.ciphertext.clone();
+        Aes256Ctr::new(&key.into(), &iv.into()).apply_keystream(&mut plaintext);
+        // PKCS#7 padding
+        let padding = *plaintext.last()? as usize;
+        if padding == 0 || padding > 16 || padding > plaintext.len() {
+            return None;
+        }
+        plaintext.truncate(plaintext.len() - padding);
+        Some(plaintext)
+    }
+}
+
+fn check_cipher(cipher: &str) -> Result<(), VaultError> {
+    if cipher.trim() == "AES256" {
+        Ok(())
+    } else {
+        Err(VaultError::UnsupportedCipher {
+            cipher: cipher.to_string(),
+        })
+    }
+}
+
+/// The AES key, HMAC key and initial counter for `password` and `salt`
+fn derive_keys(password: &[u8], salt: &[u8]) -> ([u8; KEY_LEN], [u8; KEY_LEN], [u8; IV_LEN]) {
+    let mut derived = [0u8; 2 * KEY_LEN + IV_LEN];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, PBKDF2_ITERATIONS, &mut derived);
+    let mut key = [0u8; KEY_LEN];
+    let mut hmac_key = [0u8; KEY_LEN];
+    let mut iv = [0u8; IV_LEN];
+    key.copy_from_slice(&derived[..KEY_LEN]);
+    hmac_key.copy_from_slice(&derived[KEY_LEN..2 * KEY_LEN]);
+    iv.copy_from_slice(&derived[2 * KEY_LEN..]);
+    (key, hmac_key, iv)
+}
+
+fn hmac_for(key: &[u8]) -> Hmac<Sha256> {
+    // HMAC takes keys of any length
+    Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length")
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault_ids_parse() {
+        assert_eq!(
+            "prod@prompt".parse::<VaultIdentity>().unwrap(),
+            VaultIdentity::prompt("prod")
+        );
+        assert_eq!(
+            "~/.vault_pass".parse::<VaultIdentity>().unwrap(),
+            VaultIdentity::file(DEFAULT_VAULT_ID, "~/.vault_pass")
+        );
+        assert!("prod@".parse::<VaultIdentity>().is_err());
+    }
+
+    #[test]
+    fn test_encrypted_data_round_trips() {
+        let secret = VaultSecret::new("prod", "hunter2");
+        let vaulted = secret.encrypt(b"db_password: s3cret\n");
+        assert!(vaulted.starts_with("$ANSIBLE_VAULT;1.2;AES256;prod\n"));
+        assert!(vaulted.lines().skip(1).all(|line| line.len() <= LINE_LEN));
+
+        let secrets = VaultSecrets::new()
+            .with_secret(VaultSecret::new("dev", "wrong"))
+            .with_secret(secret);
+        assert_eq!(
+            secrets.decrypt(vaulted.as_bytes()).unwrap(),
+            b"db_password: s3cret\n"
+        );
+        assert!(matches!(
+            VaultSecrets::new()
+                .with_secret(VaultSecret::new("dev", "wrong"))
+                .decrypt(vaulted.as_bytes()),
+            Err(VaultError::DecryptionFailed { .. })
+        ));
+    }
+}
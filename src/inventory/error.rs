@@ -1,3 +1,4 @@
+use crate::execution::VarsFileError;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -20,8 +21,14 @@ pub enum InventoryError {
     #[error("Permission denied: {path}")]
     PermissionDenied { path: String },
 
-    #[error("Dynamic inventory script failed: {script}")]
-    DynamicScriptFailed { script: String },
+    #[error("Dynamic inventory script {script} failed: {reason}")]
+    DynamicScriptFailed { script: String, reason: String },
+
+    #[error("Unknown inventory plugin: {plugin}")]
+    UnknownPlugin { plugin: String },
+
+    #[error("Inventory plugin {plugin} failed: {reason}")]
+    PluginFailed { plugin: String, reason: String },
 
     #[error("Variable resolution failed: {variable}")]
     VariableResolution { variable: String },
@@ -39,6 +46,18 @@ pub enum InventoryError {
     ConversionError { reason: String },
 }
 
+#[derive(Debug, Error)]
+pub enum PatternError {
+    #[error("Empty host pattern")]
+    Empty,
+
+    #[error("Invalid regular expression in host pattern {pattern}: {reason}")]
+    InvalidRegex { pattern: String, reason: String },
+
+    #[error("Invalid glob in host pattern {pattern}: {reason}")]
+    InvalidGlob { pattern: String, reason: String },
+}
+
 #[derive(Debug, Error)]
 pub enum ValidationError {
     #[error("Duplicate host name: {host}")]
@@ -86,6 +105,9 @@ pub enum VariableError {
     #[error("Invalid host: {host}")]
     InvalidHost { host: String },
 
+    #[error("Failed to load inventory variables: {0}")]
+    VarsFile(#[from] VarsFileError),
+
     #[error("Internal error: {message}")]
     InternalError { message: String },
 }
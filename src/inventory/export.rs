@@ -0,0 +1,251 @@
+//! Dumps of a merged inventory
+//!
+//! [`InventoryExport`] prints an inventory as `ansible-inventory` does, to
+//! see which groups a host ended up in and with which variables:
+//!
+//! - [`InventoryExport::to_json`] is the JSON of `--list`: each group with
+//!   its hosts and children, and the resolved variables of each host in
+//!   `_meta.hostvars`.
+//! - [`InventoryExport::graph`] is the tree of `--graph`, from `all` or
+//!   another group down to the hosts.
+//!
+//! Values that were encrypted, with Ansible Vault or SOPS, are masked, as
+//! are vaulted values left encrypted.
+
+use crate::execution::is_vaulted;
+use crate::runtime::SensitiveValues;
+use crate::types::inventory::ParsedInventory;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// What an encrypted value is shown as
+pub const MASKED_VALUE: &str = "********";
+
+const UNGROUPED: &str = "ungrouped";
+
+/// A merged inventory, as printed for debugging
+pub struct InventoryExport<'a> {
+    inventory: &'a ParsedInventory,
+    sensitive: SensitiveValues,
+}
+
+impl<'a> InventoryExport<'a> {
+    pub fn new(inventory: &'a ParsedInventory) -> Self {
+        Self {
+            inventory,
+            sensitive: SensitiveValues::new(),
+        }
+    }
+
+    /// Mask the strings holding any of `sensitive`
+    pub fn with_sensitive(mut self, sensitive: SensitiveValues) -> Self {
+        self.sensitive = sensitive;
+        self
+    }
+
+    /// The inventory in the JSON format of `ansible-inventory --list`
+    pub fn to_json(&self) -> Value {
+        let mut list = Map::new();
+        list.insert("_meta".to_string(), json!({ "hostvars": self.hostvars() }));
+        list.insert(
+            "all".to_string(),
+            json!({ "children": self.children("all") }),
+        );
+        for name in self.group_names() {
+            let mut group = Map::new();
+            let hosts = self.hosts(name);
+            if !hosts.is_empty() {
+                group.insert("hosts".to_string(), json!(hosts));
+            }
+            let children = self.children(name);
+            if !children.is_empty() {
+                group.insert("children".to_string(), json!(children));
+            }
+            list.insert(name.to_string(), Value::Object(group));
+        }
+        Value::Object(list)
+    }
+
+    /// The tree of the groups and hosts under `root`, as `ansible-inventory
+    /// --graph` draws it, with the variables of each when `vars` is set.
+    /// `None` when there is no group `root`.
+    pub fn graph(&self, root: &str, vars: bool) -> Option<String> {
+        if root != "all" && !self.inventory.groups.contains_key(root) {
+            return None;
+        }
+        let mut graph = String::new();
+        let mut path = Vec::new();
+        self.draw_group(&mut graph, root, 0, vars, &mut path);
+        Some(graph)
+    }
+
+    /// The resolved variables of each host, masked
+    pub fn hostvars(&self) -> BTreeMap<&str, Value> {
+        self.inventory
+            .hosts
+            .iter()
+            .map(|(name, host)| {
+                let variables: Map<String, Value> = host
+                    .variables
+                    .iter()
+                    .map(|(key, value)| (key.clone(), self.mask(value)))
+                    .collect();
+                (name.as_str(), Value::Object(variables))
+            })
+            .collect()
+    }
+
+    /// `value` with the strings holding encrypted values masked
+    pub fn mask(&self, value: &Value) -> Value {
+        match value {
+            Value::String(text) if self.is_sensitive(text) => Value::from(MASKED_VALUE),
+            Value::Array(items) => Value::Array(items.iter().map(|item| self.mask(item)).collect()),
+            Value::Object(entries) => Value::Object(
+                entries
+                    .iter()
+                    .map(|(key, item)| (key.clone(), self.mask(item)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    fn is_sensitive(&self, text: &str) -> bool {
+        if is_vaulted(text.trim_start().as_bytes()) {
+            return true;
+        }
+        let text = Value::from(text);
+        !self.sensitive.is_empty() && self.sensitive.replace(&text) != text
+    }
+
+    fn group_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .inventory
+            .groups
+            .keys()
+            .map(String::as_str)
+            .filter(|name| *name != "all")
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// The groups directly under `group`; under `all`, those without a
+    /// parent and `ungrouped` last
+    fn children(&self, group: &str) -> Vec<&str> {
+        if group != "all" {
+            let mut children: Vec<&str> = self
+                .inventory
+                .groups
+                .get(group)
+                .into_iter()
+                .flat_map(|group| group.children.iter().map(String::as_str))
+                .collect();
+            children.sort();
+            children.dedup();
+            return children;
+        }
+        let mut children: Vec<&str> = self
+            .group_names()
+            .into_iter()
+            .filter(|name| *name != UNGROUPED)
+            .filter(|name| {
+                self.inventory.groups[*name]
+                    .parent_groups
+                    .iter()
+                    .all(|parent| parent == "all")
+            })
+            .collect();
+        if !self.hosts(UNGROUPED).is_empty() {
+            children.push(UNGROUPED);
+        }
+        children
+    }
+
+    /// The hosts directly in `group`; in `ungrouped`, also those in no
+    /// group at all
+    fn hosts(&self, group: &str) -> Vec<&str> {
+        let mut hosts: Vec<&str> = self
+            .inventory
+            .groups
+            .get(group)
+            .into_iter()
+            .flat_map(|group| group.hosts.iter().map(String::as_str))
+            .filter(|host| self.inventory.hosts.contains_key(*host))
+            .collect();
+        if group == UNGROUPED {
+            hosts.extend(
+                self.inventory
+                    .hosts
+                    .values()
+                    .filter(|host| host.groups.is_empty())
+                    .map(|host| host.name.as_str()),
+            );
+        }
+        hosts.sort();
+        hosts.dedup();
+        hosts
+    }
+
+    fn draw_group<'b>(
+        &'b self,
+        graph: &mut String,
+        group: &'b str,
+        depth: usize,
+        vars: bool,
+        path: &mut Vec<&'b str>,
+    ) {
+        let _ = writeln!(graph, "{}@{group}:", prefix(depth));
+        // A group among its own ancestors is drawn once
+        if path.contains(&group) {
+            return;
+        }
+        path.push(group);
+        if vars {
+            let variables = match group {
+                "all" => Some(&self.inventory.global_vars),
+                group => self
+                    .inventory
+                    .groups
+                    .get(group)
+                    .map(|group| &group.variables),
+            };
+            self.draw_vars(graph, variables.into_iter().flatten(), depth + 1);
+        }
+        for child in self.children(group) {
+            self.draw_group(graph, child, depth + 1, vars, path);
+        }
+        for host in self.hosts(group) {
+            let _ = writeln!(graph, "{}{host}", prefix(depth + 1));
+            if let Some(host) = self.inventory.hosts.get(host).filter(|_| vars) {
+                self.draw_vars(graph, &host.variables, depth + 2);
+            }
+        }
+        path.pop();
+    }
+
+    fn draw_vars<'b>(
+        &self,
+        graph: &mut String,
+        variables: impl IntoIterator<Item = (&'b String, &'b Value)>,
+        depth: usize,
+    ) {
+        let variables: BTreeMap<_, _> = variables.into_iter().collect();
+        for (name, value) in variables {
+            let value = match self.mask(value) {
+                Value::String(text) => text,
+                value => value.to_string(),
+            };
+            let _ = writeln!(graph, "{}{{{name} = {value}}}", prefix(depth));
+        }
+    }
+}
+
+/// The branches leading to an entry `depth` levels under the root
+fn prefix(depth: usize) -> String {
+    match depth {
+        0 => String::new(),
+        depth => format!("{}--", "  |".repeat(depth)),
+    }
+}
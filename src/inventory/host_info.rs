@@ -27,6 +27,7 @@ impl HostInfoProber {
             kernel_version: self.detect_local_kernel(),
             target_triple: self.detect_local_target_triple(),
             capabilities: self.detect_local_capabilities(),
+            reachability: None,
         })
     }
 
@@ -40,6 +41,7 @@ impl HostInfoProber {
             kernel_version: "unknown".to_string(),
             target_triple: "x86_64-unknown-linux-gnu".to_string(),
             capabilities: vec!["ssh".to_string()],
+            reachability: None,
         })
     }
 
@@ -53,6 +55,7 @@ impl HostInfoProber {
             kernel_version: "unknown".to_string(),
             target_triple: "x86_64-pc-windows-msvc".to_string(),
             capabilities: vec!["winrm".to_string()],
+            reachability: None,
         })
     }
 
@@ -0,0 +1,398 @@
+//! Classic INI inventories
+//!
+//! ```ini
+//! mail.example.com
+//!
+//! [web]
+//! web[01:20].example.com http_port=80
+//! badwolf.example.com:5309 ansible_user=admin
+//!
+//! [web:vars]
+//! ntp_server=ntp.example.com
+//!
+//! [datacenter:children]
+//! web
+//! ```
+//!
+//! As in Ansible, variables given inline with a host are Python literals,
+//! so `port=80` is a number and `name='80'` a string, while those of
+//! `[group:vars]` sections are strings. The variables of `[all:vars]` are
+//! the global variables of the inventory, and hosts in no group are in
+//! `ungrouped`.
+
+use crate::inventory::error::InventoryError;
+use crate::inventory::JsonInventoryProcessor;
+use crate::types::inventory::{
+    InventoryFormat, InventoryGroup, InventoryMetadata, ParsedInventory,
+};
+use chrono::Utc;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+const UNGROUPED: &str = "ungrouped";
+
+/// Parser of INI inventories
+pub struct IniInventoryParser;
+
+#[derive(Clone, Copy)]
+enum Section<'a> {
+    Hosts(&'a str),
+    Vars(&'a str),
+    Children(&'a str),
+}
+
+impl IniInventoryParser {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// The inventory `content` defines; `source` names where it comes from
+    pub fn parse(&self, content: &str, source: &str) -> Result<ParsedInventory, InventoryError> {
+        let mut host_order: Vec<String> = Vec::new();
+        let mut host_vars: HashMap<String, Map<String, Value>> = HashMap::new();
+        let mut groups: HashMap<String, InventoryGroup> = HashMap::new();
+        let mut global_vars = HashMap::new();
+        let mut section = Section::Hosts(UNGROUPED);
+
+        for (index, line) in content.lines().enumerate() {
+            let invalid = |reason: String| InventoryError::InvalidIni {
+                reason: format!("line {}: {reason}", index + 1),
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header
+                    .split_once(']')
+                    .filter(|(_, rest)| is_comment(rest))
+                    .map(|(header, _)| header.trim())
+                    .ok_or_else(|| invalid(format!("invalid section header '{line}'")))?;
+                section = match header.split_once(':') {
+                    None => Section::Hosts(header),
+                    Some((group, "vars")) => Section::Vars(group),
+                    Some((group, "children")) => Section::Children(group),
+                    Some((_, kind)) => {
+                        return Err(invalid(format!("unknown section type '{kind}'")))
+                    }
+                };
+                let group = match section {
+                    Section::Hosts(group) | Section::Vars(group) | Section::Children(group) => {
+                        group
+                    }
+                };
+                if group.is_empty() || group.contains(char::is_whitespace) {
+                    return Err(invalid(format!("invalid group name '{group}'")));
+                }
+                if group != "all" {
+                    group_entry(&mut groups, group);
+                }
+                continue;
+            }
+
+            match section {
+                Section::Hosts(group) => {
+                    let tokens = split_tokens(line).map_err(invalid)?;
+                    let Some((pattern, assignments)) = tokens.split_first() else {
+                        continue;
+                    };
+                    let (pattern, port) = split_port(pattern);
+                    let mut vars = Map::new();
+                    if let Some(port) = port {
+                        vars.insert("ansible_port".to_string(), Value::from(port));
+                    }
+                    for assignment in assignments {
+                        let (name, value) = assignment.split_once('=').ok_or_else(|| {
+                            invalid(format!("expected key=value, found '{assignment}'"))
+                        })?;
+                        vars.insert(name.to_string(), literal(value));
+                    }
+
+                    for host in expand_host_range(pattern).map_err(invalid)? {
+                        if !host_vars.contains_key(&host) {
+                            host_order.push(host.clone());
+                        }
+                        host_vars
+                            .entry(host.clone())
+                            .or_default()
+                            .extend(vars.clone());
+                        if group != UNGROUPED && group != "all" {
+                            let members = &mut group_entry(&mut groups, group).hosts;
+                            if !members.contains(&host) {
+                                members.push(host);
+                            }
+                        }
+                    }
+                }
+                Section::Vars(group) => {
+                    let (name, value) = line
+                        .split_once('=')
+                        .ok_or_else(|| invalid(format!("expected key=value, found '{line}'")))?;
+                    let (name, value) = (name.trim().to_string(), string_value(value.trim()));
+                    if group == "all" {
+                        global_vars.insert(name, value);
+                    } else {
+                        group_entry(&mut groups, group)
+                            .variables
+                            .insert(name, value);
+                    }
+                }
+                Section::Children(group) => {
+                    let child = strip_comment(line);
+                    if child.contains(char::is_whitespace) {
+                        return Err(invalid(format!("invalid group name '{child}'")));
+                    }
+                    if group == "all" {
+                        group_entry(&mut groups, child);
+                        continue;
+                    }
+                    let parents = &mut group_entry(&mut groups, child).parent_groups;
+                    if !parents.iter().any(|parent| parent == group) {
+                        parents.push(group.to_string());
+                    }
+                    let children = &mut group_entry(&mut groups, group).children;
+                    if !children.iter().any(|existing| existing == child) {
+                        children.push(child.to_string());
+                    }
+                }
+            }
+        }
+
+        let json_processor = JsonInventoryProcessor::new();
+        let mut hosts = HashMap::new();
+        for name in host_order {
+            let vars = Value::Object(host_vars.remove(&name).unwrap_or_default());
+            let mut host = json_processor.create_host_from_vars(&name, &vars)?;
+            host.groups = groups
+                .values()
+                .filter(|group| group.hosts.contains(&name))
+                .map(|group| group.name.clone())
+                .collect();
+            host.groups.sort();
+            if host.groups.is_empty() {
+                host.groups.push(UNGROUPED.to_string());
+                group_entry(&mut groups, UNGROUPED).hosts.push(name.clone());
+            }
+            hosts.insert(name, host);
+        }
+
+        let metadata = InventoryMetadata {
+            format: InventoryFormat::Ini,
+            source: source.to_string(),
+            parsed_at: Utc::now(),
+            host_count: hosts.len(),
+            group_count: groups.len(),
+        };
+
+        Ok(ParsedInventory {
+            hosts,
+            groups,
+            global_vars,
+            metadata,
+        })
+    }
+}
+
+impl Default for IniInventoryParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn group_entry<'a>(
+    groups: &'a mut HashMap<String, InventoryGroup>,
+    name: &str,
+) -> &'a mut InventoryGroup {
+    groups
+        .entry(name.to_string())
+        .or_insert_with(|| InventoryGroup {
+            name: name.to_string(),
+            hosts: Vec::new(),
+            children: Vec::new(),
+            variables: HashMap::new(),
+            parent_groups: Vec::new(),
+        })
+}
+
+fn is_comment(text: &str) -> bool {
+    let text = text.trim();
+    text.is_empty() || text.starts_with('#') || text.starts_with(';')
+}
+
+fn strip_comment(line: &str) -> &str {
+    line.split_once(" #").map_or(line, |(line, _)| line).trim()
+}
+
+/// The whitespace separated tokens of a host line, up to a comment, with
+/// the quotes of their quoted parts kept
+fn split_tokens(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quote = None;
+    for ch in line.chars() {
+        match quote {
+            Some(open) => {
+                token.push(ch);
+                if ch == open {
+                    quote = None;
+                }
+            }
+            None if ch == '\'' || ch == '"' => {
+                token.push(ch);
+                quote = Some(ch);
+            }
+            None if ch == '#' && token.is_empty() => break,
+            None if ch.is_whitespace() => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            None => token.push(ch),
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("unterminated quote in '{line}'"));
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// A host pattern and the port it ends with, as in `host:2222`; IPv6
+/// addresses, which have several colons, take no port
+fn split_port(pattern: &str) -> (&str, Option<u16>) {
+    let mut depth = 0;
+    let mut colons = Vec::new();
+    for (index, ch) in pattern.char_indices() {
+        match ch {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ':' if depth == 0 => colons.push(index),
+            _ => {}
+        }
+    }
+    if let [colon] = colons.as_slice() {
+        if let Ok(port) = pattern[colon + 1..].parse() {
+            return (&pattern[..*colon], Some(port));
+        }
+    }
+    (pattern, None)
+}
+
+/// The value of a variable given inline with a host: a Python literal,
+/// which is a string unless it is a number, a boolean, `None`, a quoted
+/// string, or a list or dict in JSON
+fn literal(text: &str) -> Value {
+    match text {
+        "True" | "true" => return Value::Bool(true),
+        "False" | "false" => return Value::Bool(false),
+        "None" => return Value::Null,
+        _ => {}
+    }
+    if let Some(unquoted) = unquote(text) {
+        return Value::String(unquoted.to_string());
+    }
+    if let Ok(number) = text.parse::<i64>() {
+        return Value::from(number);
+    }
+    if let Some(number) = text.parse::<f64>().ok().filter(|number| number.is_finite()) {
+        return Value::from(number);
+    }
+    if text.starts_with('[') || text.starts_with('{') {
+        if let Ok(value) = serde_json::from_str(&text.replace('\'', "\"")) {
+            return value;
+        }
+    }
+    Value::String(text.to_string())
+}
+
+/// The value of a variable of a `[group:vars]` section, a string without
+/// its quotes
+fn string_value(text: &str) -> Value {
+    Value::String(unquote(text).unwrap_or(text).to_string())
+}
+
+fn unquote(text: &str) -> Option<&str> {
+    ['"', '\''].into_iter().find_map(|quote| {
+        text.strip_prefix(quote)
+            .and_then(|text| text.strip_suffix(quote))
+    })
+}
+
+/// The hosts of a pattern with ranges, such as `web[01:20].example.com`,
+/// `db-[a:f]` or `node[0:10:2]`; numbers keep the width of the start of
+/// their range when it has a leading zero
+pub fn expand_host_range(pattern: &str) -> Result<Vec<String>, String> {
+    let Some(start) = pattern.find('[') else {
+        return Ok(vec![pattern.to_string()]);
+    };
+    let Some(end) = pattern[start..].find(']').map(|end| start + end) else {
+        return Err(format!("unterminated host range in '{pattern}'"));
+    };
+    let (head, range, tail) = (
+        &pattern[..start],
+        &pattern[start + 1..end],
+        &pattern[end + 1..],
+    );
+    let invalid = || format!("invalid host range '[{range}]' in '{pattern}'");
+
+    let parts: Vec<&str> = range.split(':').collect();
+    let (first, last, step) = match parts.as_slice() {
+        [first, last] => (*first, *last, 1),
+        [first, last, step] => (*first, *last, step.parse().map_err(|_| invalid())?),
+        _ => return Err(invalid()),
+    };
+    if step == 0 {
+        return Err(invalid());
+    }
+
+    let start_number = match first {
+        "" => Some(0),
+        first => first.parse::<u64>().ok(),
+    };
+    let items: Vec<String> = if let (Some(low), Ok(high)) = (start_number, last.parse::<u64>()) {
+        if low > high {
+            return Err(invalid());
+        }
+        let width = if first.len() > 1 && first.starts_with('0') {
+            if first.len() != last.len() {
+                return Err(format!(
+                    "host range '[{range}]' must have equal-length bounds to be zero-padded"
+                ));
+            }
+            first.len()
+        } else {
+            0
+        };
+        (low..=high)
+            .step_by(step)
+            .map(|number| format!("{number:0width$}"))
+            .collect()
+    } else {
+        let letter = |text: &str| {
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) if ch.is_ascii_alphabetic() => Some(ch),
+                _ => None,
+            }
+        };
+        match (letter(first), letter(last)) {
+            (Some(low), Some(high)) if low <= high => (low..=high)
+                .filter(char::is_ascii_alphabetic)
+                .step_by(step)
+                .map(String::from)
+                .collect(),
+            _ => return Err(invalid()),
+        }
+    };
+
+    let mut hosts = Vec::new();
+    for item in items {
+        for rest in expand_host_range(tail)? {
+            hosts.push(format!("{head}{item}{rest}"));
+        }
+    }
+    Ok(hosts)
+}
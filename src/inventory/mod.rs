@@ -1,15 +1,27 @@
 pub mod detector;
 pub mod error;
+pub mod export;
 pub mod host_info;
+pub mod ini_parser;
+pub mod pattern;
 pub mod plan_processor;
+pub mod preflight;
 pub mod processor;
+pub mod sources;
 pub mod validator;
 pub mod variables;
+pub mod vars_directories;
 
 pub use detector::*;
 pub use error::*;
+pub use export::{InventoryExport, MASKED_VALUE};
 pub use host_info::*;
+pub use ini_parser::*;
+pub use pattern::HostPattern;
 pub use plan_processor::*;
+pub use preflight::{ConnectionPreflight, PreflightReport};
 pub use processor::*;
+pub use sources::*;
 pub use validator::*;
 pub use variables::*;
+pub use vars_directories::*;
@@ -0,0 +1,379 @@
+//! Ansible host patterns
+//!
+//! A [`HostPattern`] selects hosts of an inventory as the `hosts` of a
+//! play, `--limit` and `delegate_to` name them:
+//!
+//! - `all` or `*` is every host. Other names are groups, standing for the
+//!   hosts of the group and its children, or hosts.
+//! - Names with `*`, `?` or `[...]` are globs over the names of groups and
+//!   hosts, and names starting with `~` regular expressions matching at
+//!   their start.
+//! - `name[i]` is the `i`th host of `name`, from the end when `i` is
+//!   negative, and `name[i:j]` or `name[i-j]` its hosts from the `i`th to
+//!   the `j`th included, or to the last without `j`. Regular expressions
+//!   take no subscript, their brackets are character classes.
+//! - Terms are separated by `,`, or by `:` when there is no comma.
+//!   `&term` keeps only the hosts also in `term`, and `!term` leaves the
+//!   hosts of `term` out, wherever they are in the pattern. A pattern of
+//!   only such terms applies them to all hosts.
+//!
+//! ```text
+//! webservers:dbservers:&staging:!phoenix
+//! ~web\d+\.example\.com,db[0]
+//! ```
+
+use crate::inventory::error::PatternError;
+use crate::types::inventory::ParsedInventory;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+
+/// A host pattern, selecting hosts of an inventory
+#[derive(Debug, Clone)]
+pub struct HostPattern {
+    pattern: String,
+    terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Union,
+    Intersection,
+    Exclusion,
+}
+
+#[derive(Debug, Clone)]
+struct Term {
+    operator: Operator,
+    selector: Selector,
+    subscript: Option<Subscript>,
+}
+
+#[derive(Debug, Clone)]
+enum Selector {
+    All,
+    Name(String),
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Subscript {
+    Index(isize),
+    Range(usize, Option<usize>),
+}
+
+impl HostPattern {
+    pub fn parse(pattern: &str) -> Result<Self, PatternError> {
+        let mut terms = Vec::new();
+        for term in split_terms(pattern) {
+            let term = term.trim();
+            let (operator, term) = if let Some(term) = term.strip_prefix('&') {
+                (Operator::Intersection, term)
+            } else if let Some(term) = term.strip_prefix('!') {
+                (Operator::Exclusion, term)
+            } else {
+                (Operator::Union, term)
+            };
+            if term.is_empty() {
+                continue;
+            }
+            let (name, subscript) = split_subscript(term);
+            terms.push(Term {
+                operator,
+                selector: Selector::parse(name)?,
+                subscript,
+            });
+        }
+        if terms.is_empty() {
+            return Err(PatternError::Empty);
+        }
+        // Intersections and exclusions alone apply to all hosts
+        if terms.iter().all(|term| term.operator != Operator::Union) {
+            terms.insert(
+                0,
+                Term {
+                    operator: Operator::Union,
+                    selector: Selector::All,
+                    subscript: None,
+                },
+            );
+        }
+        Ok(Self {
+            pattern: pattern.to_string(),
+            terms,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The hosts of `inventory` the pattern selects, in the order its terms
+    /// name them
+    pub fn hosts<'a>(&self, inventory: &'a ParsedInventory) -> Vec<&'a str> {
+        let mut names: Vec<&str> = inventory.hosts.keys().map(String::as_str).collect();
+        names.sort();
+
+        let mut selected: Vec<&str> = Vec::new();
+        let mut seen = HashSet::new();
+        let terms = |operator| {
+            self.terms
+                .iter()
+                .filter(move |term| term.operator == operator)
+        };
+        for term in terms(Operator::Union) {
+            for host in term.hosts(inventory, &names) {
+                if seen.insert(host) {
+                    selected.push(host);
+                }
+            }
+        }
+        for term in terms(Operator::Intersection) {
+            let hosts: HashSet<&str> = term.hosts(inventory, &names).into_iter().collect();
+            selected.retain(|host| hosts.contains(host));
+        }
+        for term in terms(Operator::Exclusion) {
+            let hosts: HashSet<&str> = term.hosts(inventory, &names).into_iter().collect();
+            selected.retain(|host| !hosts.contains(host));
+        }
+        selected
+    }
+
+    /// Whether the pattern selects `host` of `inventory`
+    pub fn matches(&self, inventory: &ParsedInventory, host: &str) -> bool {
+        self.hosts(inventory).contains(&host)
+    }
+
+    /// `inventory` with only the hosts the pattern selects, in the groups
+    /// they were in
+    pub fn filter(&self, inventory: &ParsedInventory) -> ParsedInventory {
+        let selected: HashSet<String> = self
+            .hosts(inventory)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        let mut filtered = inventory.clone();
+        filtered.hosts.retain(|name, _| selected.contains(name));
+        for group in filtered.groups.values_mut() {
+            group.hosts.retain(|host| selected.contains(host));
+        }
+        filtered.metadata.host_count = filtered.hosts.len();
+        filtered
+    }
+}
+
+impl Term {
+    /// The hosts the term selects, before its operator applies
+    fn hosts<'a>(&self, inventory: &'a ParsedInventory, names: &[&'a str]) -> Vec<&'a str> {
+        let hosts = match &self.selector {
+            Selector::All => names.to_vec(),
+            selector => {
+                let mut groups: Vec<&str> = inventory
+                    .groups
+                    .keys()
+                    .map(String::as_str)
+                    .filter(|group| selector.matches(group))
+                    .collect();
+                groups.sort();
+                let mut hosts = Vec::new();
+                let mut seen = HashSet::new();
+                for group in &groups {
+                    for host in group_hosts(inventory, group, names) {
+                        if seen.insert(host) {
+                            hosts.push(host);
+                        }
+                    }
+                }
+                // Names of hosts may look like those of groups, or match
+                // with them
+                if groups.is_empty() || selector.is_wildcard() {
+                    for host in names.iter().filter(|host| selector.matches(host)) {
+                        if seen.insert(host) {
+                            hosts.push(host);
+                        }
+                    }
+                }
+                hosts
+            }
+        };
+        match self.subscript {
+            None => hosts,
+            Some(subscript) => subscript.apply(hosts),
+        }
+    }
+}
+
+impl Selector {
+    fn parse(name: &str) -> Result<Self, PatternError> {
+        if matches!(name, "all" | "*") {
+            return Ok(Self::All);
+        }
+        if let Some(expression) = name.strip_prefix('~') {
+            return Regex::new(&format!("^(?:{expression})"))
+                .map(Self::Regex)
+                .map_err(|e| PatternError::InvalidRegex {
+                    pattern: name.to_string(),
+                    reason: e.to_string(),
+                });
+        }
+        if name.contains(['*', '?', '[']) {
+            return glob::Pattern::new(name).map(Self::Glob).map_err(|e| {
+                PatternError::InvalidGlob {
+                    pattern: name.to_string(),
+                    reason: e.to_string(),
+                }
+            });
+        }
+        Ok(Self::Name(name.to_string()))
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::Name(expected) => expected == name,
+            Self::Glob(pattern) => pattern.matches(name),
+            Self::Regex(regex) => regex.is_match(name),
+        }
+    }
+
+    /// Whether the selector may match both groups and hosts
+    fn is_wildcard(&self) -> bool {
+        match self {
+            Self::Name(name) => name.contains('.'),
+            _ => true,
+        }
+    }
+}
+
+impl Subscript {
+    fn apply(self, hosts: Vec<&str>) -> Vec<&str> {
+        let last = hosts.len().checked_sub(1);
+        match self {
+            Self::Index(index) => {
+                let index = if index < 0 {
+                    hosts.len().checked_sub(index.unsigned_abs())
+                } else {
+                    Some(index.unsigned_abs())
+                };
+                index
+                    .and_then(|index| hosts.get(index))
+                    .map(|host| vec![*host])
+                    .unwrap_or_default()
+            }
+            Self::Range(start, end) => match (last, end.or(last)) {
+                (Some(last), Some(end)) if start <= end => {
+                    hosts[start.min(hosts.len())..=end.min(last)].to_vec()
+                }
+                _ => Vec::new(),
+            },
+        }
+    }
+}
+
+/// The hosts of `group` and of its children, the listed ones first
+fn group_hosts<'a>(inventory: &'a ParsedInventory, group: &str, names: &[&'a str]) -> Vec<&'a str> {
+    let mut hosts = Vec::new();
+    let mut seen = HashSet::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![group];
+    while let Some(group) = pending.pop() {
+        if !visited.insert(group) {
+            continue;
+        }
+        let Some(entry) = inventory.groups.get(group) else {
+            continue;
+        };
+        for host in &entry.hosts {
+            if let Some((name, _)) = inventory.hosts.get_key_value(host) {
+                if seen.insert(name.as_str()) {
+                    hosts.push(name.as_str());
+                }
+            }
+        }
+        // Hosts recording the group without being listed in it
+        for name in names {
+            let in_group = inventory.hosts[*name]
+                .groups
+                .iter()
+                .any(|member_of| member_of == group);
+            if in_group && seen.insert(name) {
+                hosts.push(name);
+            }
+        }
+        pending.extend(entry.children.iter().rev().map(String::as_str));
+    }
+    hosts
+}
+
+/// The terms of `pattern`: separated by commas, or else by colons outside
+/// brackets unless it is an IPv6 address
+fn split_terms(pattern: &str) -> Vec<&str> {
+    if pattern.contains(',') {
+        return pattern.split(',').collect();
+    }
+    if pattern.parse::<Ipv6Addr>().is_ok() {
+        return vec![pattern];
+    }
+    let mut terms = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (index, ch) in pattern.char_indices() {
+        match ch {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            ':' if depth == 0 => {
+                terms.push(&pattern[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    terms.push(&pattern[start..]);
+    terms
+}
+
+/// `term` without its trailing `[i]`, `[i:j]` or `[i-j]`, and that
+/// subscript
+fn split_subscript(term: &str) -> (&str, Option<Subscript>) {
+    if term.starts_with('~') {
+        return (term, None);
+    }
+    let Some(open) = term.strip_suffix(']').and_then(|rest| rest.rfind('[')) else {
+        return (term, None);
+    };
+    let (name, inner) = (&term[..open], &term[open + 1..term.len() - 1]);
+    if name.is_empty() {
+        return (term, None);
+    }
+    let digits = |text: &str| text.chars().all(|ch| ch.is_ascii_digit());
+    if let Ok(index) = inner.parse::<isize>() {
+        return (name, Some(Subscript::Index(index)));
+    }
+    match inner.split_once([':', '-']) {
+        Some((start, end))
+            if digits(start) && digits(end) && !(start.is_empty() && end.is_empty()) =>
+        {
+            let start = start.parse().unwrap_or(0);
+            let end = end.parse().ok();
+            (name, Some(Subscript::Range(start, end)))
+        }
+        _ => (term, None),
+    }
+}
+
+impl FromStr for HostPattern {
+    type Err = PatternError;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        Self::parse(pattern)
+    }
+}
+
+impl fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
@@ -48,6 +48,18 @@ impl JsonInventoryProcessor {
                         crate::execution::plan::ConnectionMethod::Ssh => ConnectionMethod::Ssh,
                         crate::execution::plan::ConnectionMethod::WinRm => ConnectionMethod::WinRm,
                         crate::execution::plan::ConnectionMethod::Local => ConnectionMethod::Local,
+                        crate::execution::plan::ConnectionMethod::Docker => {
+                            ConnectionMethod::Docker
+                        }
+                        crate::execution::plan::ConnectionMethod::Podman => {
+                            ConnectionMethod::Podman
+                        }
+                        crate::execution::plan::ConnectionMethod::Kubectl => {
+                            ConnectionMethod::Kubectl
+                        }
+                        crate::execution::plan::ConnectionMethod::Chroot => {
+                            ConnectionMethod::Chroot
+                        }
                     },
                     host: Some(host_spec.address.clone()),
                     port: host_spec.connection.port,
@@ -220,7 +232,8 @@ impl JsonInventoryProcessor {
         })
     }
 
-    fn create_host_from_vars(
+    /// A host whose connection is configured by its `ansible_*` variables
+    pub(crate) fn create_host_from_vars(
         &self,
         host_name: &str,
         host_vars: &serde_json::Value,
@@ -248,6 +261,10 @@ impl JsonInventoryProcessor {
                 "ssh" => ConnectionMethod::Ssh,
                 "winrm" => ConnectionMethod::WinRm,
                 "local" => ConnectionMethod::Local,
+                "docker" | "community.docker.docker" => ConnectionMethod::Docker,
+                "podman" | "containers.podman.podman" => ConnectionMethod::Podman,
+                "kubectl" | "kubernetes.core.kubectl" => ConnectionMethod::Kubectl,
+                "chroot" | "community.general.chroot" => ConnectionMethod::Chroot,
                 _ => ConnectionMethod::Ssh,
             })
             .unwrap_or(ConnectionMethod::Ssh);
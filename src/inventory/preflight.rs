@@ -0,0 +1,258 @@
+//! Connection preflight
+//!
+//! Before deploying, [`ConnectionPreflight`] connects to the SSH and WinRM
+//! hosts of an inventory, a few at a time, as the deployer would: with
+//! their connection variables, `~/.ssh/config` and the deployer's
+//! defaults. Each host is reachable when its transport answers, and
+//! authenticated when the login succeeds, after which the round trip of a
+//! no-op command is its latency and the platform probe tells what the host
+//! runs. Hosts with other connections are not checked.
+//!
+//! The [`PreflightReport`] tells the deployment planner which hosts cannot
+//! be deployed to at all, and which are far enough away that running tasks
+//! over SSH one by one would cost more than shipping a binary.
+
+use crate::binary::platform::HostPlatform;
+use crate::deploy::{
+    resolve_connection, ConnectionPlugin, DeployError, SshConfig, SshConnection,
+    SshConnectionConfig, WinRmConfig, WinRmConnection,
+};
+use crate::inventory::error::ValidationError;
+use crate::inventory::InventoryProcessor;
+use crate::types::inventory::{ConnectionMethod, HostInfo, HostReachability, ParsedInventory};
+use crate::types::DeploymentTarget;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+const DEFAULT_CONCURRENCY: usize = 16;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Checks that the hosts of an inventory can be connected and logged into
+#[derive(Debug, Clone)]
+pub struct ConnectionPreflight {
+    concurrency: usize,
+    timeout: Duration,
+    ssh: SshConnectionConfig,
+    ssh_config: SshConfig,
+    winrm: WinRmConfig,
+}
+
+/// The reachability of each checked host, by inventory name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PreflightReport {
+    pub hosts: BTreeMap<String, HostReachability>,
+}
+
+impl ConnectionPreflight {
+    pub fn new() -> Self {
+        Self {
+            concurrency: DEFAULT_CONCURRENCY,
+            timeout: DEFAULT_TIMEOUT,
+            ssh: SshConnectionConfig::default(),
+            ssh_config: SshConfig::load_default(),
+            winrm: WinRmConfig::default(),
+        }
+    }
+
+    /// Check up to `concurrency` hosts at once
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Give up on a host after `timeout`, connecting and logging in included
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Connect to SSH hosts with `config` and `ssh_config`, as the deployer
+    /// was given
+    pub fn with_ssh_config(mut self, config: SshConnectionConfig, ssh_config: SshConfig) -> Self {
+        self.ssh = config;
+        self.ssh_config = ssh_config;
+        self
+    }
+
+    pub fn with_winrm_config(mut self, config: WinRmConfig) -> Self {
+        self.winrm = config;
+        self
+    }
+
+    /// Check the SSH and WinRM hosts of `inventory`, which `processor`
+    /// turns into deployment targets
+    pub async fn check(
+        &self,
+        inventory: &ParsedInventory,
+        processor: &InventoryProcessor,
+    ) -> PreflightReport {
+        let targets: Vec<(String, ConnectionMethod, DeploymentTarget)> = inventory
+            .hosts
+            .iter()
+            .filter(|(_, host)| {
+                matches!(
+                    host.connection.method,
+                    ConnectionMethod::Ssh | ConnectionMethod::WinRm
+                )
+            })
+            .map(|(name, host)| {
+                (
+                    name.clone(),
+                    host.connection.method.clone(),
+                    processor.to_deployment_target(name, host),
+                )
+            })
+            .collect();
+
+        let hosts = stream::iter(targets)
+            .map(|(name, method, target)| async move {
+                let checked = self.check_target(&method, &target);
+                let reachability = tokio::time::timeout(self.timeout, checked)
+                    .await
+                    .unwrap_or_else(|_| HostReachability {
+                        reachable: false,
+                        authenticated: false,
+                        latency: None,
+                        error: Some(format!("No answer within {:?}", self.timeout)),
+                        platform: None,
+                    });
+                (name, reachability)
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+        PreflightReport { hosts }
+    }
+
+    async fn check_target(
+        &self,
+        method: &ConnectionMethod,
+        target: &DeploymentTarget,
+    ) -> HostReachability {
+        let connected = match method {
+            ConnectionMethod::WinRm => self.check_winrm(target).await,
+            _ => self.check_ssh(target).await,
+        };
+        match connected {
+            Ok((latency, platform)) => HostReachability {
+                reachable: true,
+                authenticated: true,
+                latency: Some(latency),
+                error: None,
+                platform,
+            },
+            Err(e) => HostReachability {
+                // The host answered and refused the credentials
+                reachable: matches!(
+                    e,
+                    DeployError::SshAuthentication { .. } | DeployError::WinRmAuthentication { .. }
+                ),
+                authenticated: false,
+                latency: None,
+                error: Some(e.to_string()),
+                platform: None,
+            },
+        }
+    }
+
+    /// Log into `target` over SSH, time a no-op command and probe the
+    /// platform
+    async fn check_ssh(
+        &self,
+        target: &DeploymentTarget,
+    ) -> Result<(Duration, Option<HostPlatform>), DeployError> {
+        let mut defaults = self.ssh.clone();
+        defaults.connect_timeout = defaults.connect_timeout.min(self.timeout);
+        let resolved = resolve_connection(
+            &target.host,
+            &target.connection,
+            &self.ssh_config,
+            &defaults,
+        );
+        let connection = SshConnection::connect(&resolved.host_spec, &resolved.config).await?;
+        let started = Instant::now();
+        connection.execute_command("true").await?;
+        let latency = started.elapsed();
+        Ok((latency, connection.probe_platform().await.ok()))
+    }
+
+    /// Log into `target` over WinRM, time a no-op command and probe the
+    /// platform
+    async fn check_winrm(
+        &self,
+        target: &DeploymentTarget,
+    ) -> Result<(Duration, Option<HostPlatform>), DeployError> {
+        let mut config = self.winrm.for_host(&target.connection);
+        config.connect_timeout = config.connect_timeout.min(self.timeout);
+        let hostname = target.connection.host.as_deref().unwrap_or(&target.host);
+        let connection = WinRmConnection::connect(&target.host, hostname, &config).await?;
+        let started = Instant::now();
+        connection.execute_command("exit 0").await?;
+        let latency = started.elapsed();
+        Ok((latency, connection.probe_platform().await.ok()))
+    }
+}
+
+impl Default for ConnectionPreflight {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PreflightReport {
+    pub fn get(&self, host: &str) -> Option<&HostReachability> {
+        self.hosts.get(host)
+    }
+
+    /// The probed platforms of those of `hosts` that were checked
+    pub fn platforms<'a>(
+        &'a self,
+        hosts: impl IntoIterator<Item = &'a String>,
+    ) -> Vec<HostPlatform> {
+        hosts
+            .into_iter()
+            .filter_map(|host| self.get(host)?.platform.clone())
+            .collect()
+    }
+
+    /// Whether tasks can be run on `host`; hosts that were not checked are
+    /// assumed to be
+    pub fn is_usable(&self, host: &str) -> bool {
+        self.get(host).is_none_or(HostReachability::is_usable)
+    }
+
+    /// The hosts that could not be reached or logged into, in order
+    pub fn unusable_hosts(&self) -> Vec<&str> {
+        self.hosts
+            .iter()
+            .filter(|(_, reachability)| !reachability.is_usable())
+            .map(|(host, _)| host.as_str())
+            .collect()
+    }
+
+    /// Fail unless every checked host can be deployed to
+    pub fn require_all(&self) -> Result<(), ValidationError> {
+        let hosts = self.unusable_hosts();
+        if hosts.is_empty() {
+            return Ok(());
+        }
+        let hosts: Vec<String> = hosts
+            .into_iter()
+            .map(|host| {
+                let reason = self.hosts[host].error.as_deref().unwrap_or("unreachable");
+                format!("{host} ({reason})")
+            })
+            .collect();
+        Err(ValidationError::UnreachableHost {
+            host: hosts.join(", "),
+        })
+    }
+
+    /// `info` with what the preflight found of `host`
+    pub fn annotate(&self, host: &str, mut info: HostInfo) -> HostInfo {
+        info.reachability = self.get(host).cloned();
+        info
+    }
+}
@@ -1,10 +1,17 @@
+use crate::execution::VarsFileLoader;
 use crate::inventory::{
-    ArchitectureDetector, ConversionError, DetectionError, HostInfoProber, InventoryError,
-    InventoryValidatorSet, JsonInventoryProcessor, ValidationError, VariableError,
-    VariableResolver,
+    inventory_root, ArchitectureDetector, ConnectionPreflight, Constructed, ConversionError,
+    DetectionError, DirectoryVars, HostInfoProber, IniInventoryParser, InventoryError,
+    InventoryLoader, InventoryValidatorSet, JsonInventoryProcessor, PreflightReport,
+    ValidationError, VariableError, VariableResolver,
+};
+use crate::types::inventory::WinRmTransport;
+use crate::types::{
+    DeploymentMethod, DeploymentStatus, DeploymentTarget, HostConnectionVars, InventoryHost,
+    ParsedInventory,
 };
-use crate::types::{DeploymentMethod, DeploymentStatus, DeploymentTarget, ParsedInventory};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 pub struct InventoryProcessor {
     detector: ArchitectureDetector,
@@ -12,6 +19,10 @@ pub struct InventoryProcessor {
     variable_resolver: VariableResolver,
     host_prober: HostInfoProber,
     json_processor: JsonInventoryProcessor,
+    vars_root: Option<PathBuf>,
+    vars_loader: VarsFileLoader,
+    sources: InventoryLoader,
+    constructed: Vec<Constructed>,
 }
 
 impl InventoryProcessor {
@@ -22,9 +33,53 @@ impl InventoryProcessor {
             variable_resolver: VariableResolver::new(),
             host_prober: HostInfoProber::new(),
             json_processor: JsonInventoryProcessor::new(),
+            vars_root: None,
+            vars_loader: VarsFileLoader::new(),
+            sources: InventoryLoader::builtin(),
+            constructed: Vec::new(),
         }
     }
 
+    /// Load the `group_vars/` and `host_vars/` directories next to the
+    /// inventory file at `path`, or in it when it is a directory
+    pub fn with_inventory_path(mut self, path: &Path) -> Self {
+        self.vars_root = Some(inventory_root(path));
+        self
+    }
+
+    /// Load inventory sources with `loader`, and the plugins it has
+    pub fn with_sources(mut self, loader: InventoryLoader) -> Self {
+        self.sources = loader;
+        self
+    }
+
+    /// Decrypt the files of `group_vars/` and `host_vars/` with `loader`'s
+    /// keys
+    pub fn with_vars_loader(mut self, loader: VarsFileLoader) -> Self {
+        self.vars_loader = loader;
+        self
+    }
+
+    /// Connect to the SSH and WinRM hosts on [`Self::preflight`]
+    pub fn with_preflight(mut self, preflight: ConnectionPreflight) -> Self {
+        self.validators = self.validators.with_preflight(preflight);
+        self
+    }
+
+    /// Accept hosts the inventory names no architecture for, as playbooks
+    /// learn it from the facts they gather
+    pub fn with_architecture_from_facts(mut self) -> Self {
+        self.validators = self.validators.with_architecture_from_facts();
+        self
+    }
+
+    /// Regroup the hosts with `constructed` once they are probed, after
+    /// the `constructed` sources of the inventory
+    pub fn with_constructed(mut self, constructed: Constructed) -> Self {
+        self.constructed.push(constructed);
+        self
+    }
+
     pub fn process_from_plan(
         &self,
         plan_output: &serde_json::Value,
@@ -34,9 +89,50 @@ impl InventoryProcessor {
         Ok(inventory)
     }
 
+    /// Parse and process the INI inventory `content`; `source` names where
+    /// it comes from
+    pub fn process_from_ini(
+        &self,
+        content: &str,
+        source: &str,
+    ) -> Result<ParsedInventory, InventoryError> {
+        let mut inventory = IniInventoryParser::new().parse(content, source)?;
+        self.process_inventory_data(&mut inventory)?;
+        Ok(inventory)
+    }
+
+    /// Load and process the inventory source at `path`, a file, a script,
+    /// a plugin configuration or a directory of them, with the
+    /// `group_vars/` and `host_vars/` next to it unless told otherwise
+    pub async fn process_from_source(
+        &self,
+        path: &Path,
+    ) -> Result<ParsedInventory, InventoryError> {
+        let mut inventory = self.sources.load(path).await?;
+        let constructed = self.sources.constructed_sources(path)?;
+        let vars_root = self
+            .vars_root
+            .clone()
+            .unwrap_or_else(|| inventory_root(path));
+        self.process_with_vars_root(&mut inventory, Some(&vars_root), &constructed)?;
+        Ok(inventory)
+    }
+
     pub fn process_inventory_data(
         &self,
         inventory: &mut ParsedInventory,
+    ) -> Result<(), InventoryError> {
+        self.process_with_vars_root(inventory, self.vars_root.as_deref(), &[])
+    }
+
+    /// Validate `inventory`, resolve its variables, probe its hosts, and
+    /// regroup them with the `constructed` sources and then the options of
+    /// [`Self::with_constructed`]
+    fn process_with_vars_root(
+        &self,
+        inventory: &mut ParsedInventory,
+        vars_root: Option<&Path>,
+        constructed: &[Constructed],
     ) -> Result<(), InventoryError> {
         // Validate the inventory structure
         self.validate(inventory)
@@ -45,7 +141,7 @@ impl InventoryProcessor {
             })?;
 
         // Resolve variables and inheritance
-        self.resolve_variables(inventory)
+        self.resolve_variables_from(inventory, vars_root)
             .map_err(|e| InventoryError::VariableResolution {
                 variable: format!("Variable resolution failed: {e}"),
             })?;
@@ -57,15 +153,59 @@ impl InventoryProcessor {
             }
         })?;
 
+        for constructed in constructed {
+            Self::apply_constructed(constructed, inventory)?;
+        }
+        self.regroup(inventory)
+    }
+
+    /// Regroup the hosts of `inventory` with the options of
+    /// [`Self::with_constructed`], over their variables and what is known
+    /// of them, as after gathering facts
+    pub fn regroup(&self, inventory: &mut ParsedInventory) -> Result<(), InventoryError> {
+        for constructed in &self.constructed {
+            Self::apply_constructed(constructed, inventory)?;
+        }
         Ok(())
     }
 
+    fn apply_constructed(
+        constructed: &Constructed,
+        inventory: &mut ParsedInventory,
+    ) -> Result<(), InventoryError> {
+        constructed
+            .apply(inventory)
+            .map_err(|reason| InventoryError::PluginFailed {
+                plugin: "constructed".to_string(),
+                reason,
+            })
+    }
+
     pub fn validate(&self, inventory: &ParsedInventory) -> Result<(), ValidationError> {
         self.validators.validate(inventory)
     }
 
+    /// How reachable the hosts of `inventory` are, when
+    /// [`Self::with_preflight`] asked for a preflight
+    pub async fn preflight(&self, inventory: &ParsedInventory) -> Option<PreflightReport> {
+        self.validators.preflight(inventory, self).await
+    }
+
     pub fn resolve_variables(&self, inventory: &mut ParsedInventory) -> Result<(), VariableError> {
-        self.variable_resolver.resolve_variables(inventory)
+        self.resolve_variables_from(inventory, self.vars_root.as_deref())
+    }
+
+    fn resolve_variables_from(
+        &self,
+        inventory: &mut ParsedInventory,
+        vars_root: Option<&Path>,
+    ) -> Result<(), VariableError> {
+        let directory_vars = match vars_root {
+            Some(root) => DirectoryVars::load(root, inventory, &self.vars_loader)?,
+            None => DirectoryVars::default(),
+        };
+        self.variable_resolver
+            .resolve_variables_with(inventory, &directory_vars)
     }
 
     pub fn detect_architectures(
@@ -107,50 +247,96 @@ impl InventoryProcessor {
         &self,
         inventory: &ParsedInventory,
     ) -> Result<Vec<DeploymentTarget>, ConversionError> {
-        let mut targets = Vec::new();
-
-        for (host_name, host) in &inventory.hosts {
-            let target_triple = host
-                .target_triple
-                .clone()
-                .or_else(|| self.detector.detect_target_triple(host))
-                .unwrap_or_else(|| "x86_64-unknown-linux-gnu".to_string());
-
-            let deployment_method = match host.connection.method {
-                crate::types::inventory::ConnectionMethod::Ssh => DeploymentMethod::Ssh,
-                crate::types::inventory::ConnectionMethod::WinRm => DeploymentMethod::Custom {
-                    command: format!(
-                        "winrm copy {{binary_path}} {}/rustle-runner.exe",
-                        host.connection.host.as_ref().unwrap_or(host_name)
-                    ),
-                },
-                crate::types::inventory::ConnectionMethod::Local => DeploymentMethod::Scp,
-                crate::types::inventory::ConnectionMethod::Podman => DeploymentMethod::Custom {
-                    command: format!("podman cp {{binary_path}} {host_name}:/tmp/rustle-runner"),
-                },
-            };
-
-            let target_path = self.determine_target_path(&target_triple, &host.variables);
-
-            // Use the host address or connection host, fallback to host name
-            let deployment_host = host
-                .address
-                .clone()
-                .or_else(|| host.connection.host.clone())
-                .unwrap_or_else(|| host_name.clone());
-
-            targets.push(DeploymentTarget {
-                host: deployment_host,
-                target_path,
-                binary_compilation_id: format!("rustle-{target_triple}"),
-                deployment_method,
-                status: DeploymentStatus::Pending,
-                deployed_at: None,
-                version: "1.0.0".to_string(),
-            });
-        }
+        Ok(inventory
+            .hosts
+            .iter()
+            .map(|(host_name, host)| self.to_deployment_target(host_name, host))
+            .collect())
+    }
+
+    /// The target deploying to the inventory host `host_name`
+    pub fn to_deployment_target(&self, host_name: &str, host: &InventoryHost) -> DeploymentTarget {
+        let target_triple = host
+            .target_triple
+            .clone()
+            .or_else(|| self.detector.detect_target_triple(host))
+            .unwrap_or_else(|| "x86_64-unknown-linux-gnu".to_string());
+
+        let target_path = self.determine_target_path(&target_triple, &host.variables);
+
+        // Use the host address or connection host, fallback to host name
+        let deployment_host = host
+            .address
+            .clone()
+            .or_else(|| host.connection.host.clone())
+            .unwrap_or_else(|| host_name.to_string());
 
-        Ok(targets)
+        // Like Ansible, container and chroot hosts are addressed by name or `ansible_host`
+        let deployment_method = match host.connection.method {
+            crate::types::inventory::ConnectionMethod::Ssh => DeploymentMethod::Ssh,
+            crate::types::inventory::ConnectionMethod::WinRm => DeploymentMethod::WinRm,
+            crate::types::inventory::ConnectionMethod::Local => DeploymentMethod::Local,
+            crate::types::inventory::ConnectionMethod::Docker => DeploymentMethod::Docker {
+                container: deployment_host.clone(),
+            },
+            crate::types::inventory::ConnectionMethod::Podman => DeploymentMethod::Podman {
+                container: deployment_host.clone(),
+            },
+            crate::types::inventory::ConnectionMethod::Kubectl => {
+                let var = |name: &str| {
+                    host.variables
+                        .get(name)
+                        .and_then(|value| value.as_str())
+                        .map(str::to_string)
+                };
+                DeploymentMethod::Kubectl {
+                    pod: var("ansible_kubectl_pod").unwrap_or_else(|| deployment_host.clone()),
+                    namespace: var("ansible_kubectl_namespace"),
+                    container: var("ansible_kubectl_container"),
+                    context: var("ansible_kubectl_context"),
+                    kubeconfig: var("ansible_kubectl_kubeconfig"),
+                }
+            }
+            crate::types::inventory::ConnectionMethod::Chroot => DeploymentMethod::Chroot {
+                root: deployment_host.clone(),
+            },
+        };
+
+        DeploymentTarget {
+            host: deployment_host,
+            target_path,
+            binary_compilation_id: format!("rustle-{target_triple}"),
+            deployment_method,
+            status: DeploymentStatus::Pending,
+            deployed_at: None,
+            version: "1.0.0".to_string(),
+            connection: HostConnectionVars {
+                port: host.connection.port,
+                user: host.connection.username.clone(),
+                private_key_file: host.connection.private_key_file.clone(),
+                password: host.connection.password.clone(),
+                winrm_transport: host
+                    .connection
+                    .winrm_transport
+                    .as_ref()
+                    .and_then(|transport| match transport {
+                        WinRmTransport::Kerberos => Some("kerberos".to_string()),
+                        WinRmTransport::Ntlm => Some("ntlm".to_string()),
+                        WinRmTransport::Http | WinRmTransport::Https => None,
+                    }),
+                winrm_scheme: host
+                    .connection
+                    .winrm_transport
+                    .as_ref()
+                    .and_then(|transport| match transport {
+                        WinRmTransport::Http => Some("http".to_string()),
+                        WinRmTransport::Https => Some("https".to_string()),
+                        _ => None,
+                    }),
+                ..Default::default()
+            }
+            .or(HostConnectionVars::from_variables(&host.variables)),
+        }
     }
 
     fn determine_target_path(
@@ -165,17 +351,24 @@ impl InventoryProcessor {
             }
         }
 
+        // WebAssembly runners keep their extension, which has them run under wasmtime
+        let extension = if target_triple.starts_with("wasm32-wasi") {
+            ".wasm"
+        } else {
+            ""
+        };
+
         // Check for ansible-style paths
         if let Some(path_val) = variables.get("ansible_remote_tmp") {
             if let Some(path_str) = path_val.as_str() {
-                return format!("{path_str}/rustle-runner");
+                return format!("{path_str}/rustle-runner{extension}");
             }
         }
 
         // Default based on target platform
         match target_triple.contains("windows") {
             true => "C:\\temp\\rustle-runner.exe".to_string(),
-            false => "/tmp/rustle-runner".to_string(),
+            false => format!("/tmp/rustle-runner{extension}"),
         }
     }
 
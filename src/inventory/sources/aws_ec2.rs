@@ -0,0 +1,376 @@
+//! Amazon EC2 instances as an inventory
+//!
+//! ```yaml
+//! plugin: amazon.aws.aws_ec2
+//! regions: [us-east-1, eu-west-1]
+//! filters:
+//!   instance-state-name: running
+//!   tag:Environment: [staging, production]
+//! hostnames: [tag:Name, private-dns-name]
+//! keyed_groups:
+//!   - key: tags.Role
+//!     prefix: role
+//! compose:
+//!   ansible_host: private_ip_address
+//! cache: true
+//! ```
+//!
+//! Instances are listed with the `DescribeInstances` action of the EC2
+//! query API, in each of `regions` or, without them, in every region
+//! `DescribeRegions` lists, with the API's `filters`. Their variables are
+//! those the API describes them with, in snake_case as Ansible's `aws_ec2`
+//! plugin names them: `instance_id`, `private_ip_address`,
+//! `public_dns_name`, `state.name`, `placement.region`, `tags` as a dict,
+//! and so on. A host is named after the first of `hostnames` it has a
+//! value for, by default its public then its private DNS name, and
+//! instances with none, such as terminated ones, are left out. Every host
+//! is in the `aws_ec2` group, and in the groups of the
+//! [`Constructed`] options; see [`CacheOptions`] for caching.
+//!
+//! Credentials are those of the configuration, `access_key`, `secret_key`
+//! and `session_token`, or of its `profile` in the shared credentials and
+//! config files; without either, they are found as the AWS CLI finds them,
+//! in the environment, the profile of `AWS_PROFILE` or `default`, then the
+//! instance profile of the controller.
+
+use super::cache::CacheOptions;
+use super::constructed::{Constructed, InventoryBuilder};
+use super::xml::{self, Element};
+use super::InventoryPlugin;
+use crate::inventory::error::InventoryError;
+use crate::runtime::sigv4::{resolve_credentials, resolve_region, AwsCredentials, Signer};
+use crate::types::inventory::{InventoryFormat, ParsedInventory};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+const NAME: &str = "aws_ec2";
+const API_VERSION: &str = "2016-11-15";
+const DEFAULT_HOSTNAMES: &[&str] = &["dns-name", "private-dns-name"];
+
+/// The `aws_ec2` inventory plugin
+#[derive(Debug, Clone, Default)]
+pub struct Ec2Inventory {
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct Ec2Config {
+    #[serde(default)]
+    regions: Vec<String>,
+    #[serde(default)]
+    filters: BTreeMap<String, Value>,
+    #[serde(default)]
+    hostnames: Vec<String>,
+    #[serde(default, alias = "aws_endpoint_url")]
+    endpoint_url: Option<String>,
+    #[serde(default, alias = "aws_access_key_id", alias = "aws_access_key")]
+    access_key: Option<String>,
+    #[serde(default, alias = "aws_secret_access_key", alias = "aws_secret_key")]
+    secret_key: Option<String>,
+    #[serde(default, alias = "aws_session_token")]
+    session_token: Option<String>,
+    #[serde(default, alias = "aws_profile", alias = "boto_profile")]
+    profile: Option<String>,
+    #[serde(flatten)]
+    constructed: Constructed,
+    #[serde(flatten)]
+    cache: CacheOptions,
+}
+
+impl Ec2Inventory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The instances of the regions of `config`, as host variables
+    async fn instances(&self, config: &Ec2Config) -> Result<Vec<Value>, String> {
+        let credentials = match (&config.access_key, &config.secret_key) {
+            (Some(access_key), Some(secret_key)) => AwsCredentials {
+                access_key_id: access_key.clone(),
+                secret_access_key: secret_key.clone(),
+                session_token: config.session_token.clone(),
+            },
+            _ => resolve_credentials(&self.client, config.profile.as_deref()).await?,
+        };
+
+        let regions = if config.regions.is_empty() {
+            let region = resolve_region(config.profile.as_deref());
+            let response = self
+                .call(config, &credentials, &region, "DescribeRegions", Vec::new())
+                .await?;
+            response
+                .child("regionInfo")
+                .into_iter()
+                .flat_map(|regions| &regions.children)
+                .filter_map(|region| region.child_text("regionName"))
+                .map(str::to_string)
+                .collect()
+        } else {
+            config.regions.clone()
+        };
+
+        let mut filters = Vec::new();
+        for (index, (name, values)) in config.filters.iter().enumerate() {
+            filters.push((format!("Filter.{}.Name", index + 1), name.clone()));
+            let values = match values {
+                Value::Array(values) => values.clone(),
+                value => vec![value.clone()],
+            };
+            for (value_index, value) in values.iter().enumerate() {
+                let value = match value {
+                    Value::String(text) => text.clone(),
+                    value => value.to_string(),
+                };
+                filters.push((
+                    format!("Filter.{}.Value.{}", index + 1, value_index + 1),
+                    value,
+                ));
+            }
+        }
+
+        let mut instances = Vec::new();
+        for region in &regions {
+            let mut next_token: Option<String> = None;
+            loop {
+                let mut params = filters.clone();
+                if let Some(token) = next_token.take() {
+                    params.push(("NextToken".to_string(), token));
+                }
+                let response = self
+                    .call(config, &credentials, region, "DescribeInstances", params)
+                    .await?;
+                let reservations = response.child("reservationSet").into_iter();
+                for reservation in reservations.flat_map(|set| &set.children) {
+                    let set = reservation.child("instancesSet").into_iter();
+                    for instance in set.flat_map(|set| &set.children) {
+                        let mut vars = to_value(instance);
+                        if let Some(placement) =
+                            vars.get_mut("placement").and_then(Value::as_object_mut)
+                        {
+                            placement.insert("region".to_string(), Value::from(region.as_str()));
+                        }
+                        instances.push(vars);
+                    }
+                }
+                match response.child_text("nextToken").filter(|t| !t.is_empty()) {
+                    Some(token) => next_token = Some(token.to_string()),
+                    None => break,
+                }
+            }
+        }
+        Ok(instances)
+    }
+
+    /// Run the EC2 `action` in `region` with `params`
+    async fn call(
+        &self,
+        config: &Ec2Config,
+        credentials: &AwsCredentials,
+        region: &str,
+        action: &str,
+        params: Vec<(String, String)>,
+    ) -> Result<Element, String> {
+        let endpoint = match &config.endpoint_url {
+            Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+            None => format!("https://ec2.{region}.amazonaws.com"),
+        };
+        let url = reqwest::Url::parse(&format!("{endpoint}/")).map_err(|e| e.to_string())?;
+
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("Action", action)
+            .append_pair("Version", API_VERSION)
+            .extend_pairs(params)
+            .finish();
+        let payload_hash = format!("{:x}", Sha256::digest(body.as_bytes()));
+        let content_type = "application/x-www-form-urlencoded; charset=utf-8";
+        let signer = Signer {
+            credentials,
+            region,
+            service: "ec2",
+        };
+        let headers = signer.sign(
+            "POST",
+            &url,
+            &[("content-type", content_type)],
+            &payload_hash,
+            Utc::now(),
+        );
+        let mut request = self
+            .client
+            .post(url)
+            .header("content-type", content_type)
+            .body(body);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("{action} in {region}: {e}"))?;
+        let status = response.status();
+        let text = response.text().await.map_err(|e| e.to_string())?;
+        let document = xml::parse(&text).map_err(|e| format!("{action} in {region}: {e}"));
+        if !status.is_success() {
+            let error = document
+                .ok()
+                .and_then(|document| document.child("Errors")?.child("Error").cloned());
+            let reason = match error {
+                Some(error) => format!(
+                    "{}: {}",
+                    error.child_text("Code").unwrap_or_default(),
+                    error.child_text("Message").unwrap_or_default()
+                ),
+                None => status.to_string(),
+            };
+            return Err(format!("{action} in {region} failed: {reason}"));
+        }
+        document
+    }
+}
+
+#[async_trait]
+impl InventoryPlugin for Ec2Inventory {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn aliases(&self) -> &[&'static str] {
+        &["amazon.aws.aws_ec2"]
+    }
+
+    async fn parse(&self, config: &Value, _path: &Path) -> Result<ParsedInventory, InventoryError> {
+        let failed = |reason: String| InventoryError::PluginFailed {
+            plugin: NAME.to_string(),
+            reason,
+        };
+        let config: Ec2Config =
+            serde_json::from_value(config.clone()).map_err(|e| failed(e.to_string()))?;
+
+        let cache_key = serde_json::json!({
+            "regions": config.regions,
+            "filters": config.filters,
+            "endpoint_url": config.endpoint_url,
+            "profile": config.profile,
+        });
+        let instances = match config.cache.get(NAME, &cache_key) {
+            Some(Value::Array(instances)) => instances,
+            _ => {
+                let instances = self.instances(&config).await.map_err(failed)?;
+                config
+                    .cache
+                    .put(NAME, &cache_key, &Value::from(instances.clone()));
+                instances
+            }
+        };
+
+        let hostnames: Vec<&str> = if config.hostnames.is_empty() {
+            DEFAULT_HOSTNAMES.to_vec()
+        } else {
+            config.hostnames.iter().map(String::as_str).collect()
+        };
+        let mut builder = InventoryBuilder::new();
+        for instance in instances {
+            let Value::Object(vars) = instance else {
+                continue;
+            };
+            let Some(host) = hostnames.iter().find_map(|name| hostname(&vars, name)) else {
+                continue;
+            };
+            builder.add_to_group(NAME, &host);
+            config
+                .constructed
+                .add_host(&mut builder, &host, vars)
+                .map_err(failed)?;
+        }
+
+        let mut inventory = builder.build()?;
+        inventory.metadata.format = InventoryFormat::Dynamic;
+        Ok(inventory)
+    }
+}
+
+/// The name `preference` gives the instance of `vars`: a filter name such
+/// as `dns-name` or `private-ip-address`, or `tag:NAME`
+fn hostname(vars: &Map<String, Value>, preference: &str) -> Option<String> {
+    let value = match preference.strip_prefix("tag:") {
+        Some(tag) => vars.get("tags")?.get(tag),
+        None => {
+            let name = match preference {
+                "dns-name" => "public_dns_name",
+                "ip-address" => "public_ip_address",
+                name => &name.replace('-', "_"),
+            };
+            vars.get(name)
+        }
+    };
+    value
+        .and_then(Value::as_str)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+/// The JSON of an element of an EC2 response: lists of `item`s are arrays,
+/// `tagSet`s dicts of tags, and other elements with children objects of
+/// them
+fn to_value(element: &Element) -> Value {
+    if element.name == "tagSet" {
+        let tags = element.children.iter().filter_map(|tag| {
+            let key = tag.child_text("key")?;
+            Some((key.to_string(), Value::from(tag.child_text("value")?)))
+        });
+        return Value::Object(tags.collect());
+    }
+    let is_list = element.name.ends_with("Set")
+        || !element.children.is_empty() && element.children.iter().all(|c| c.name == "item");
+    if is_list {
+        return element.children.iter().map(to_value).collect();
+    }
+    if element.children.is_empty() {
+        return match element.text.as_str() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            text => Value::from(text),
+        };
+    }
+    Value::Object(
+        element
+            .children
+            .iter()
+            .map(|child| (var_name(&child.name), to_value(child)))
+            .collect(),
+    )
+}
+
+/// The variable name of an element, as boto3 and Ansible name it
+fn var_name(element: &str) -> String {
+    let name = match element {
+        "dnsName" => "publicDnsName",
+        "ipAddress" => "publicIpAddress",
+        "instanceState" => "state",
+        "groupSet" => "securityGroups",
+        "tagSet" => "tags",
+        name => name,
+    };
+    let name = match name.strip_suffix("Set") {
+        Some(plural) if plural.ends_with('s') => plural.to_string(),
+        Some(singular) => format!("{singular}s"),
+        None => name.to_string(),
+    };
+
+    let mut snake = String::with_capacity(name.len() + 4);
+    let mut previous_lower = false;
+    for ch in name.chars() {
+        if ch.is_ascii_uppercase() && previous_lower {
+            snake.push('_');
+        }
+        previous_lower = ch.is_ascii_lowercase() || ch.is_ascii_digit();
+        snake.push(ch.to_ascii_lowercase());
+    }
+    snake
+}
@@ -0,0 +1,546 @@
+//! Azure virtual machines as an inventory
+//!
+//! ```yaml
+//! plugin: azure.azcollection.azure_rm
+//! include_vm_resource_groups: [shop-prod, shop-shared]
+//! auth_source: cli
+//! plain_host_names: true
+//! keyed_groups:
+//!   - key: resource_group
+//!     prefix: rg
+//!   - key: tags
+//!     prefix: tag
+//! ```
+//!
+//! The virtual machines of the subscription are listed with the Azure
+//! Resource Manager API, in `include_vm_resource_groups`, or all of them
+//! with `'*'`, the default. Their variables are those of Ansible's
+//! `azure_rm` plugin: `name`, `id`, `location`, `resource_group`, `tags`,
+//! `powerstate`, `provisioning_state`, `virtual_machine_size`,
+//! `os_profile.system`, `image`, `computer_name`, `availability_zone`, and
+//! the `mac_address`, `network_interface`, `security_group`,
+//! `private_ipv4_addresses`, `public_ipv4_addresses` and
+//! `public_dns_hostnames` of its primary network interface. `ansible_host`
+//! is its first public address, or its first private one.
+//!
+//! As in Ansible, hosts are named after their machine, with a short hash
+//! of its id unless `plain_host_names` is set, and machines for which one
+//! of the conditions of `default_host_filters`, by default those not
+//! running, or of `exclude_host_filters` holds are left out. Every host is
+//! in the `azure_rm` group, and in the groups of the [`Constructed`]
+//! options.
+//!
+//! With `auth_source: auto`, the default, the API is called with the
+//! service principal of the configuration or of the environment
+//! (`AZURE_CLIENT_ID`, `AZURE_SECRET`, `AZURE_TENANT`,
+//! `AZURE_SUBSCRIPTION_ID`), else as the user logged in with `az login`,
+//! else with the managed identity of the machine; `env`, `cli` and `msi`
+//! select one of them.
+
+use super::cache::CacheOptions;
+use super::constructed::{Constructed, InventoryBuilder};
+use super::InventoryPlugin;
+use crate::inventory::error::InventoryError;
+use crate::types::inventory::{InventoryFormat, ParsedInventory};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::path::Path;
+
+const NAME: &str = "azure_rm";
+const DEFAULT_ENDPOINT: &str = "https://management.azure.com";
+const DEFAULT_AUTHORITY: &str = "https://login.microsoftonline.com";
+const MSI_TOKEN_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+const COMPUTE_API_VERSION: &str = "2024-03-01";
+const NETWORK_API_VERSION: &str = "2023-09-01";
+
+/// The `azure_rm` inventory plugin
+#[derive(Debug, Clone, Default)]
+pub struct AzureRmInventory {
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct AzureConfig {
+    #[serde(default = "all_resource_groups")]
+    include_vm_resource_groups: Vec<String>,
+    #[serde(default)]
+    plain_host_names: bool,
+    #[serde(default = "default_host_filters")]
+    default_host_filters: Vec<String>,
+    #[serde(default)]
+    exclude_host_filters: Vec<String>,
+    #[serde(default)]
+    auth_source: Option<String>,
+    #[serde(default)]
+    subscription_id: Option<String>,
+    #[serde(default)]
+    client_id: Option<String>,
+    #[serde(default, alias = "client_secret")]
+    secret: Option<String>,
+    #[serde(default, alias = "tenant_id")]
+    tenant: Option<String>,
+    #[serde(default)]
+    endpoint_url: Option<String>,
+    #[serde(default)]
+    authority_url: Option<String>,
+    #[serde(flatten)]
+    constructed: Constructed,
+    #[serde(flatten)]
+    cache: CacheOptions,
+}
+
+fn all_resource_groups() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+fn default_host_filters() -> Vec<String> {
+    vec!["powerstate != 'running'".to_string()]
+}
+
+/// An access token and the subscription it is for
+struct Session {
+    token: String,
+    subscription: String,
+}
+
+impl AzureRmInventory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The variables of the virtual machines of `config`'s subscription
+    async fn machines(&self, config: &AzureConfig) -> Result<Vec<Value>, String> {
+        let session = self.session(config).await?;
+        let endpoint = config
+            .endpoint_url
+            .as_deref()
+            .unwrap_or(DEFAULT_ENDPOINT)
+            .trim_end_matches('/');
+        let subscription = format!("{endpoint}/subscriptions/{}", session.subscription);
+
+        let mut machines = Vec::new();
+        let mut statuses = Vec::new();
+        for group in &config.include_vm_resource_groups {
+            let url = match group.as_str() {
+                "*" => format!("{subscription}/providers/Microsoft.Compute/virtualMachines"),
+                group => format!(
+                    "{subscription}/resourceGroups/{group}/providers/Microsoft.Compute/virtualMachines"
+                ),
+            };
+            let list = format!("{url}?api-version={COMPUTE_API_VERSION}");
+            machines.extend(self.list(&list, &session.token).await?);
+            let status = format!("{list}&statusOnly=true");
+            statuses.extend(self.list(&status, &session.token).await?);
+        }
+        let interfaces = self
+            .list(
+                &format!(
+                    "{subscription}/providers/Microsoft.Network/networkInterfaces?api-version={NETWORK_API_VERSION}"
+                ),
+                &session.token,
+            )
+            .await?;
+        let public_ips = self
+            .list(
+                &format!(
+                    "{subscription}/providers/Microsoft.Network/publicIPAddresses?api-version={NETWORK_API_VERSION}"
+                ),
+                &session.token,
+            )
+            .await?;
+
+        let by_id = |items: Vec<Value>| -> HashMap<String, Value> {
+            items
+                .into_iter()
+                .filter_map(|item| Some((item.get("id")?.as_str()?.to_lowercase(), item)))
+                .collect()
+        };
+        let (statuses, interfaces, public_ips) =
+            (by_id(statuses), by_id(interfaces), by_id(public_ips));
+        Ok(machines
+            .iter()
+            .map(|machine| machine_vars(machine, &statuses, &interfaces, &public_ips))
+            .collect())
+    }
+
+    /// The items of the list at `url`, following its `nextLink`s
+    async fn list(&self, url: &str, token: &str) -> Result<Vec<Value>, String> {
+        let mut items = Vec::new();
+        let mut next = Some(url.to_string());
+        while let Some(url) = next.take() {
+            let response = self
+                .client
+                .get(&url)
+                .bearer_auth(token)
+                .send()
+                .await
+                .map_err(|e| format!("{url}: {e}"))?;
+            let status = response.status();
+            let page: Value = response.json().await.unwrap_or(Value::Null);
+            if !status.is_success() {
+                let message = page
+                    .pointer("/error/message")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                return Err(format!("{url}: {status} {message}"));
+            }
+            if let Some(page_items) = page.get("value").and_then(Value::as_array) {
+                items.extend(page_items.iter().cloned());
+            }
+            next = page
+                .get("nextLink")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+        }
+        Ok(items)
+    }
+
+    /// A token for the credentials `auth_source` selects
+    async fn session(&self, config: &AzureConfig) -> Result<Session, String> {
+        let env = |name: &str| std::env::var(name).ok();
+        let subscription = config
+            .subscription_id
+            .clone()
+            .or_else(|| env("AZURE_SUBSCRIPTION_ID"));
+        let client_id = config.client_id.clone().or_else(|| env("AZURE_CLIENT_ID"));
+        let secret = config
+            .secret
+            .clone()
+            .or_else(|| env("AZURE_SECRET"))
+            .or_else(|| env("AZURE_CLIENT_SECRET"));
+        let tenant = config
+            .tenant
+            .clone()
+            .or_else(|| env("AZURE_TENANT"))
+            .or_else(|| env("AZURE_TENANT_ID"));
+
+        let auth_source = config.auth_source.as_deref().unwrap_or("auto");
+        let service_principal = client_id.zip(secret).zip(tenant);
+        let token = match (auth_source, service_principal) {
+            ("auto" | "env", Some(((client_id, secret), tenant))) => {
+                self.service_principal_token(config, &client_id, &secret, &tenant)
+                    .await?
+            }
+            ("env", None) => {
+                return Err("auth_source env needs a client id, secret and tenant".to_string())
+            }
+            ("cli", _) => return cli_session(subscription).await,
+            ("msi", _) => self.msi_token().await?,
+            ("auto", None) => match cli_session(subscription.clone()).await {
+                Ok(session) => return Ok(session),
+                Err(_) => self.msi_token().await?,
+            },
+            (other, _) => return Err(format!("unknown auth_source '{other}'")),
+        };
+        Ok(Session {
+            token,
+            subscription: subscription.ok_or("no subscription_id given")?,
+        })
+    }
+
+    async fn service_principal_token(
+        &self,
+        config: &AzureConfig,
+        client_id: &str,
+        secret: &str,
+        tenant: &str,
+    ) -> Result<String, String> {
+        let authority = config
+            .authority_url
+            .as_deref()
+            .unwrap_or(DEFAULT_AUTHORITY)
+            .trim_end_matches('/');
+        let url = format!("{authority}/{tenant}/oauth2/v2.0/token");
+        let scope = format!("{DEFAULT_ENDPOINT}/.default");
+        let response = self
+            .client
+            .post(&url)
+            .form(&[
+                ("grant_type", "client_credentials"),
+                ("client_id", client_id),
+                ("client_secret", secret),
+                ("scope", &scope),
+            ])
+            .send()
+            .await
+            .map_err(|e| format!("{url}: {e}"))?;
+        access_token(response, &url).await
+    }
+
+    /// The token of the managed identity of the machine
+    async fn msi_token(&self) -> Result<String, String> {
+        let response = self
+            .client
+            .get(MSI_TOKEN_URL)
+            .query(&[
+                ("api-version", "2018-02-01"),
+                ("resource", &format!("{DEFAULT_ENDPOINT}/")),
+            ])
+            .header("Metadata", "true")
+            .send()
+            .await
+            .map_err(|e| format!("managed identity: {e}"))?;
+        access_token(response, "managed identity").await
+    }
+}
+
+async fn access_token(response: reqwest::Response, source: &str) -> Result<String, String> {
+    let status = response.status();
+    let token: Value = response.json().await.unwrap_or(Value::Null);
+    match token.get("access_token").and_then(Value::as_str) {
+        Some(access_token) if status.is_success() => Ok(access_token.to_string()),
+        _ => Err(format!(
+            "{source}: {status} {}",
+            token
+                .get("error_description")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+        )),
+    }
+}
+
+/// The token of the user logged in with `az login`, for `subscription` or
+/// the CLI's default one
+async fn cli_session(subscription: Option<String>) -> Result<Session, String> {
+    let mut command = tokio::process::Command::new("az");
+    command.args([
+        "account",
+        "get-access-token",
+        "--resource",
+        &format!("{DEFAULT_ENDPOINT}/"),
+        "--output",
+        "json",
+    ]);
+    if let Some(subscription) = &subscription {
+        command.args(["--subscription", subscription]);
+    }
+    let output = command.output().await.map_err(|e| format!("az: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("az: {}", stderr.trim()));
+    }
+    let token: Value = serde_json::from_slice(&output.stdout).map_err(|e| format!("az: {e}"))?;
+    let field = |name: &str| token.get(name).and_then(Value::as_str).map(str::to_string);
+    Ok(Session {
+        token: field("accessToken").ok_or("az: no accessToken")?,
+        subscription: subscription
+            .or_else(|| field("subscription"))
+            .ok_or("az: no subscription")?,
+    })
+}
+
+/// The variables of `machine`, with its status and the addresses of its
+/// primary network interface
+fn machine_vars(
+    machine: &Value,
+    statuses: &HashMap<String, Value>,
+    interfaces: &HashMap<String, Value>,
+    public_ips: &HashMap<String, Value>,
+) -> Value {
+    let text = |pointer: &str| {
+        machine
+            .pointer(pointer)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    let id = text("/id");
+    let resource_group = id
+        .split('/')
+        .skip_while(|segment| !segment.eq_ignore_ascii_case("resourceGroups"))
+        .nth(1)
+        .unwrap_or_default()
+        .to_string();
+
+    let status_codes: Vec<String> = statuses
+        .get(&id.to_lowercase())
+        .and_then(|status| status.pointer("/properties/instanceView/statuses"))
+        .or_else(|| machine.pointer("/properties/instanceView/statuses"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|status| status.get("code")?.as_str().map(str::to_string))
+        .collect();
+    let status = |prefix: &str| {
+        status_codes
+            .iter()
+            .find_map(|code| code.strip_prefix(prefix))
+            .map(str::to_lowercase)
+    };
+    let powerstate = status("PowerState/").unwrap_or_else(|| "unknown".to_string());
+    let provisioning_state = status("ProvisioningState/")
+        .unwrap_or_else(|| text("/properties/provisioningState").to_lowercase());
+
+    let nics = machine
+        .pointer("/properties/networkProfile/networkInterfaces")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    let primary = nics
+        .iter()
+        .find(|nic| nic.pointer("/properties/primary") == Some(&Value::Bool(true)))
+        .or_else(|| nics.first())
+        .and_then(|nic| nic.get("id")?.as_str())
+        .and_then(|nic_id| interfaces.get(&nic_id.to_lowercase()));
+
+    let mut private_ips = Vec::new();
+    let mut public_addresses = Vec::new();
+    let mut public_dns = Vec::new();
+    let configurations = primary
+        .and_then(|nic| nic.pointer("/properties/ipConfigurations"))
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    for configuration in &configurations {
+        if let Some(ip) = configuration
+            .pointer("/properties/privateIPAddress")
+            .and_then(Value::as_str)
+        {
+            private_ips.push(Value::from(ip));
+        }
+        let public = configuration
+            .pointer("/properties/publicIPAddress/id")
+            .and_then(Value::as_str)
+            .and_then(|ip_id| public_ips.get(&ip_id.to_lowercase()));
+        if let Some(public) = public {
+            if let Some(ip) = public.pointer("/properties/ipAddress") {
+                public_addresses.push(ip.clone());
+            }
+            if let Some(fqdn) = public.pointer("/properties/dnsSettings/fqdn") {
+                public_dns.push(fqdn.clone());
+            }
+        }
+    }
+    let nic_text = |pointer: &str| {
+        primary
+            .and_then(|nic| nic.pointer(pointer))
+            .cloned()
+            .unwrap_or(Value::Null)
+    };
+    let security_group_id = nic_text("/properties/networkSecurityGroup/id");
+    let security_group = security_group_id
+        .as_str()
+        .and_then(|id| id.rsplit('/').next())
+        .map_or(Value::Null, Value::from);
+
+    let image = machine
+        .pointer("/properties/storageProfile/imageReference")
+        .cloned()
+        .unwrap_or(Value::Null);
+    let os_type = text("/properties/storageProfile/osDisk/osType").to_lowercase();
+    let ansible_host = public_addresses
+        .first()
+        .or_else(|| private_ips.first())
+        .cloned()
+        .unwrap_or(Value::Null);
+
+    json!({
+        "ansible_host": ansible_host,
+        "id": id,
+        "name": text("/name"),
+        "location": text("/location"),
+        "resource_group": resource_group,
+        "tags": machine.get("tags").cloned().unwrap_or_else(|| json!({})),
+        "powerstate": powerstate,
+        "provisioning_state": provisioning_state,
+        "virtual_machine_size": text("/properties/hardwareProfile/vmSize"),
+        "computer_name": text("/properties/osProfile/computerName"),
+        "os_profile": { "system": os_type },
+        "os_disk": {
+            "name": text("/properties/storageProfile/osDisk/name"),
+            "operating_system_type": os_type,
+        },
+        "image": image,
+        "availability_zone": machine.pointer("/zones/0").cloned().unwrap_or(Value::Null),
+        "mac_address": nic_text("/properties/macAddress"),
+        "network_interface": nic_text("/name"),
+        "network_interface_id": nic_text("/id"),
+        "security_group": security_group,
+        "security_group_id": security_group_id,
+        "private_ipv4_addresses": private_ips,
+        "public_ipv4_addresses": public_addresses,
+        "public_dns_hostnames": public_dns,
+    })
+}
+
+/// The name of the host of the machine of `vars`
+fn host_name(vars: &Map<String, Value>, plain: bool) -> Option<String> {
+    let name = vars.get("name")?.as_str().filter(|name| !name.is_empty())?;
+    if plain {
+        return Some(name.to_string());
+    }
+    let id = vars.get("id").and_then(Value::as_str).unwrap_or_default();
+    let digest = format!("{:x}", Sha1::digest(id.as_bytes()));
+    Some(format!("{name}_{}", &digest[..4]))
+}
+
+#[async_trait]
+impl InventoryPlugin for AzureRmInventory {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn aliases(&self) -> &[&'static str] {
+        &["azure.azcollection.azure_rm"]
+    }
+
+    async fn parse(&self, config: &Value, _path: &Path) -> Result<ParsedInventory, InventoryError> {
+        let failed = |reason: String| InventoryError::PluginFailed {
+            plugin: NAME.to_string(),
+            reason,
+        };
+        let config: AzureConfig =
+            serde_json::from_value(config.clone()).map_err(|e| failed(e.to_string()))?;
+
+        let cache_key = json!({
+            "include_vm_resource_groups": config.include_vm_resource_groups,
+            "subscription_id": config.subscription_id,
+            "endpoint_url": config.endpoint_url,
+        });
+        let machines = match config.cache.get(NAME, &cache_key) {
+            Some(Value::Array(machines)) => machines,
+            _ => {
+                let machines = self.machines(&config).await.map_err(failed)?;
+                config
+                    .cache
+                    .put(NAME, &cache_key, &Value::from(machines.clone()));
+                machines
+            }
+        };
+
+        let mut builder = InventoryBuilder::new();
+        'machines: for machine in machines {
+            let Value::Object(vars) = machine else {
+                continue;
+            };
+            let Some(host) = host_name(&vars, config.plain_host_names) else {
+                continue;
+            };
+            let filters = config
+                .default_host_filters
+                .iter()
+                .chain(&config.exclude_host_filters);
+            for filter in filters {
+                if config
+                    .constructed
+                    .holds(&host, &vars, filter)
+                    .map_err(failed)?
+                {
+                    continue 'machines;
+                }
+            }
+            builder.add_to_group(NAME, &host);
+            config
+                .constructed
+                .add_host(&mut builder, &host, vars)
+                .map_err(failed)?;
+        }
+
+        let mut inventory = builder.build()?;
+        inventory.metadata.format = InventoryFormat::Dynamic;
+        Ok(inventory)
+    }
+}
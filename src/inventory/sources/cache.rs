@@ -0,0 +1,112 @@
+//! Caching of the hosts inventory plugins fetch from cloud APIs
+//!
+//! As with Ansible's `cache` options, a plugin configured with
+//! `cache: true` keeps what its API answered in a file for
+//! `cache_timeout` seconds, 0 for ever, and reuses it instead of asking
+//! again. Entries are keyed by the plugin and the options that select the
+//! hosts, so that changing a filter does not answer from a stale cache,
+//! while grouping and `compose` options, applied afterwards, take effect
+//! at once.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+/// Ansible's default `cache_timeout`
+const DEFAULT_TIMEOUT_SECS: u64 = 3600;
+
+/// The cache options of an inventory plugin's configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheOptions {
+    #[serde(default)]
+    pub cache: bool,
+    #[serde(default = "default_timeout")]
+    pub cache_timeout: u64,
+    /// Directory of the cache files; a directory of the user's cache by
+    /// default
+    #[serde(default)]
+    pub cache_connection: Option<PathBuf>,
+    #[serde(default = "default_prefix")]
+    pub cache_prefix: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    cached_at: i64,
+    data: Value,
+}
+
+fn default_timeout() -> u64 {
+    DEFAULT_TIMEOUT_SECS
+}
+
+fn default_prefix() -> String {
+    "rustle_inventory_".to_string()
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            cache: false,
+            cache_timeout: DEFAULT_TIMEOUT_SECS,
+            cache_connection: None,
+            cache_prefix: default_prefix(),
+        }
+    }
+}
+
+impl CacheOptions {
+    /// What `plugin` cached for `key`, when caching is on and it has not
+    /// expired
+    pub fn get(&self, plugin: &str, key: &impl Serialize) -> Option<Value> {
+        if !self.cache {
+            return None;
+        }
+        let content = std::fs::read(self.path(plugin, key)?).ok()?;
+        let entry: CacheEntry = serde_json::from_slice(&content).ok()?;
+        let age = chrono::Utc::now().timestamp() - entry.cached_at;
+        let fresh = self.cache_timeout == 0 || (0..self.cache_timeout as i64).contains(&age);
+        fresh.then_some(entry.data)
+    }
+
+    /// Cache `data` of `plugin` for `key`, when caching is on. A cache
+    /// that cannot be written is only worth a warning.
+    pub fn put(&self, plugin: &str, key: &impl Serialize, data: &Value) {
+        if !self.cache {
+            return;
+        }
+        let Some(path) = self.path(plugin, key) else {
+            return;
+        };
+        let entry = CacheEntry {
+            cached_at: chrono::Utc::now().timestamp(),
+            data: data.clone(),
+        };
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| {
+                let content = serde_json::to_vec(&entry).map_err(std::io::Error::other)?;
+                std::fs::write(&path, content)
+            });
+        if let Err(e) = written {
+            tracing::warn!("Failed to cache the {plugin} inventory in {path:?}: {e}");
+        }
+    }
+
+    fn path(&self, plugin: &str, key: &impl Serialize) -> Option<PathBuf> {
+        let dir = match &self.cache_connection {
+            Some(dir) => dir.clone(),
+            None => dirs::cache_dir()?.join("rustle-deploy").join("inventory"),
+        };
+        let key = serde_json::to_vec(key).ok()?;
+        let digest = format!("{:x}", Sha256::digest(&key));
+        let name = plugin.replace(|ch: char| !ch.is_ascii_alphanumeric(), "_");
+        Some(dir.join(format!(
+            "{}{name}_{}.json",
+            self.cache_prefix,
+            &digest[..16]
+        )))
+    }
+}
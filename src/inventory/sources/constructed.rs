@@ -0,0 +1,384 @@
+//! Variables and groups constructed from the variables of hosts
+//!
+//! Inventory plugins take Ansible's `constructed` options:
+//!
+//! ```yaml
+//! compose:
+//!   ansible_host: private_ip_address
+//! groups:
+//!   webservers: "'web' in inventory_hostname"
+//!   arm: ansible_architecture == 'aarch64'
+//! keyed_groups:
+//!   - key: tags
+//!     prefix: tag
+//!   - key: placement.region
+//!     prefix: aws_region
+//! ```
+//!
+//! `compose` sets variables to Jinja expressions over the variables of a
+//! host, in the order of their names. `groups` put a host in each group
+//! whose condition holds over them. `keyed_groups` put a host in a group
+//! named after the value of `key`, with `prefix` and `separator` before it:
+//! in one group per item of a list, and one per `key_value` pair of a dict.
+//! Names are made valid group names by replacing characters other than
+//! letters, digits and underscores with underscores. Expressions that fail,
+//! such as those naming variables a host lacks, are skipped unless
+//! `strict` is set.
+//!
+//! Plugins apply them to the hosts they fetch. A `constructed` source, a
+//! configuration file whose `plugin` is `constructed`, applies them to the
+//! hosts of the sources before it in a directory of sources, and
+//! [`InventoryProcessor`](crate::inventory::InventoryProcessor) applies
+//! them again once it has probed the hosts, when their variables are
+//! resolved and `ansible_architecture` and `ansible_system` are known.
+
+use super::dynamic_inventory;
+use crate::inventory::error::InventoryError;
+use crate::modules::files::template_engine::ansible_environment;
+use crate::types::inventory::{InventoryGroup, ParsedInventory};
+use minijinja::Environment;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+/// A group keyed by the value of an expression
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeyedGroup {
+    pub key: String,
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default = "default_separator")]
+    pub separator: String,
+    /// Group the keyed groups are children of
+    #[serde(default)]
+    pub parent_group: Option<String>,
+    /// Whether the separator starts the names of groups without a prefix
+    #[serde(default = "default_leading_separator")]
+    pub leading_separator: bool,
+    /// Value used when `key` is an empty string
+    #[serde(default)]
+    pub default_value: Option<String>,
+}
+
+fn default_separator() -> String {
+    "_".to_string()
+}
+
+fn default_leading_separator() -> bool {
+    true
+}
+
+/// Names `plugin` takes in the configuration of a `constructed` source
+pub const CONSTRUCTED_PLUGINS: &[&str] = &["constructed", "ansible.builtin.constructed"];
+
+/// The `compose`, `groups` and `keyed_groups` options of an inventory
+/// plugin
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Constructed {
+    #[serde(default)]
+    pub compose: BTreeMap<String, String>,
+    /// Groups and the conditions putting hosts in them
+    #[serde(default)]
+    pub groups: BTreeMap<String, String>,
+    #[serde(default)]
+    pub keyed_groups: Vec<KeyedGroup>,
+    #[serde(default)]
+    pub strict: bool,
+    #[serde(skip)]
+    env: OnceLock<Environment<'static>>,
+}
+
+impl Constructed {
+    /// Compose the variables of `host` into `vars`, and add it with them to
+    /// `builder`, in the groups `groups` and `keyed_groups` put it in
+    pub fn add_host(
+        &self,
+        builder: &mut InventoryBuilder,
+        host: &str,
+        mut vars: Map<String, Value>,
+    ) -> Result<(), String> {
+        for (group, parent) in self.construct(host, &mut vars)? {
+            builder.add_to_group(&group, host);
+            if let Some(parent) = parent {
+                builder.add_child(&parent, &group);
+            }
+        }
+        builder.add_host(host, vars);
+        Ok(())
+    }
+
+    /// Compose the variables of the hosts of `inventory`, and add them to
+    /// the groups `groups` and `keyed_groups` put them in. Expressions see
+    /// the variables of a host, `group_names`, and `ansible_architecture`
+    /// and `ansible_system` when they are known.
+    pub fn apply(&self, inventory: &mut ParsedInventory) -> Result<(), String> {
+        let mut names: Vec<String> = inventory.hosts.keys().cloned().collect();
+        names.sort();
+        for name in names {
+            let host = &inventory.hosts[&name];
+            let mut vars: Map<String, Value> = host
+                .variables
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            let facts = [
+                ("ansible_architecture", &host.architecture),
+                ("ansible_system", &host.operating_system),
+            ];
+            for (fact, value) in facts {
+                if let Some(value) = value {
+                    vars.entry(fact)
+                        .or_insert_with(|| Value::from(value.as_str()));
+                }
+            }
+            let mut group_names = host.groups.clone();
+            group_names.sort();
+            vars.entry("group_names")
+                .or_insert_with(|| Value::from(group_names));
+
+            let memberships = self.construct(&name, &mut vars)?;
+            let Some(host) = inventory.hosts.get_mut(&name) else {
+                continue;
+            };
+            for composed in self.compose.keys() {
+                if let Some(value) = vars.remove(composed) {
+                    host.variables.insert(composed.clone(), value);
+                }
+            }
+            for (group, parent) in memberships {
+                if !host.groups.contains(&group) {
+                    host.groups.push(group.clone());
+                }
+                host.groups.retain(|group| group != UNGROUPED);
+                add_group_member(
+                    &mut inventory.groups,
+                    &group,
+                    |group| &mut group.hosts,
+                    &name,
+                );
+                if let Some(parent) = parent {
+                    add_group_member(
+                        &mut inventory.groups,
+                        &parent,
+                        |group| &mut group.children,
+                        &group,
+                    );
+                    if let Some(child) = inventory.groups.get_mut(&group) {
+                        if !child.parent_groups.contains(&parent) {
+                            child.parent_groups.push(parent);
+                        }
+                    }
+                }
+                if let Some(ungrouped) = inventory.groups.get_mut(UNGROUPED) {
+                    ungrouped.hosts.retain(|host| host != &name);
+                }
+            }
+        }
+        inventory.metadata.group_count = inventory.groups.len();
+        Ok(())
+    }
+
+    /// Compose the variables of `host` into `vars`, and the groups it is
+    /// in, each with the parent group of its keyed group
+    fn construct(
+        &self,
+        host: &str,
+        vars: &mut Map<String, Value>,
+    ) -> Result<Vec<(String, Option<String>)>, String> {
+        for (name, expression) in &self.compose {
+            if let Some(value) = self.eval(host, vars, expression)? {
+                vars.insert(name.clone(), value);
+            }
+        }
+        let mut groups = Vec::new();
+        for (group, condition) in &self.groups {
+            if self.holds(host, vars, condition)? {
+                groups.push((safe_group_name(group), None));
+            }
+        }
+        for keyed in &self.keyed_groups {
+            let Some(value) = self.eval(host, vars, &keyed.key)? else {
+                continue;
+            };
+            for key in group_keys(&value, &keyed.separator) {
+                let key = match &keyed.default_value {
+                    Some(default) if key.is_empty() => default.clone(),
+                    _ => key,
+                };
+                let name = if keyed.prefix.is_empty() && !keyed.leading_separator {
+                    key
+                } else {
+                    format!("{}{}{key}", keyed.prefix, keyed.separator)
+                };
+                groups.push((safe_group_name(&name), keyed.parent_group.clone()));
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Whether the condition `expression` holds over the variables of
+    /// `host`; a condition that fails does not, unless `strict` is set
+    pub fn holds(
+        &self,
+        host: &str,
+        vars: &Map<String, Value>,
+        expression: &str,
+    ) -> Result<bool, String> {
+        let value = self.eval(host, vars, expression)?;
+        Ok(match value {
+            None | Some(Value::Bool(false)) => false,
+            Some(Value::Number(number)) => number.as_f64() != Some(0.0),
+            Some(Value::String(text)) => !text.is_empty(),
+            Some(Value::Array(items)) => !items.is_empty(),
+            Some(Value::Object(entries)) => !entries.is_empty(),
+            Some(_) => true,
+        })
+    }
+
+    /// The value of `expression` over `vars`; `None` when it is undefined,
+    /// or fails and `strict` is not set
+    fn eval(
+        &self,
+        host: &str,
+        vars: &Map<String, Value>,
+        expression: &str,
+    ) -> Result<Option<Value>, String> {
+        let env = self.env.get_or_init(ansible_environment);
+        let mut context = vars.clone();
+        context
+            .entry("inventory_hostname")
+            .or_insert_with(|| Value::from(host));
+        let value = env
+            .compile_expression(expression)
+            .and_then(|compiled| compiled.eval(&context))
+            .map_err(|e| e.to_string())
+            .and_then(|value| {
+                if value.is_undefined() {
+                    return Ok(None);
+                }
+                serde_json::to_value(value)
+                    .map(Some)
+                    .map_err(|e| e.to_string())
+            });
+        match value {
+            Err(e) if self.strict => Err(format!("{host}: '{expression}': {e}")),
+            Err(_) => Ok(None),
+            Ok(value) => Ok(value.filter(|value| !value.is_null())),
+        }
+    }
+}
+
+const UNGROUPED: &str = "ungrouped";
+
+/// Add `member` to the members `field` selects of `group`, creating it
+fn add_group_member(
+    groups: &mut HashMap<String, InventoryGroup>,
+    group: &str,
+    field: impl Fn(&mut InventoryGroup) -> &mut Vec<String>,
+    member: &str,
+) {
+    let group = groups
+        .entry(group.to_string())
+        .or_insert_with(|| InventoryGroup {
+            name: group.to_string(),
+            hosts: Vec::new(),
+            children: Vec::new(),
+            variables: HashMap::new(),
+            parent_groups: Vec::new(),
+        });
+    let members = field(group);
+    if !members.iter().any(|existing| existing == member) {
+        members.push(member.to_string());
+    }
+}
+
+/// The keys of the groups `value` puts a host in
+fn group_keys(value: &Value, separator: &str) -> Vec<String> {
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .filter(|item| !item.is_null())
+            .map(scalar)
+            .collect(),
+        Value::Object(entries) => entries
+            .iter()
+            .map(|(name, value)| format!("{name}{separator}{}", scalar(value)))
+            .collect(),
+        value => vec![scalar(value)],
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// `name` with the characters not valid in group names replaced
+pub fn safe_group_name(name: &str) -> String {
+    name.chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '_' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// An inventory in the JSON format of dynamic inventories, built host by
+/// host
+#[derive(Debug, Clone, Default)]
+pub struct InventoryBuilder {
+    groups: Map<String, Value>,
+    hostvars: Map<String, Value>,
+}
+
+impl InventoryBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `host`, or add `vars` to its variables
+    pub fn add_host(&mut self, host: &str, vars: Map<String, Value>) {
+        match self.hostvars.get_mut(host) {
+            Some(Value::Object(existing)) => existing.extend(vars),
+            _ => {
+                self.hostvars.insert(host.to_string(), Value::Object(vars));
+            }
+        }
+    }
+
+    pub fn add_to_group(&mut self, group: &str, host: &str) {
+        self.push(group, "hosts", host);
+    }
+
+    pub fn add_child(&mut self, parent: &str, child: &str) {
+        self.push(parent, "children", child);
+    }
+
+    pub fn build(mut self) -> Result<ParsedInventory, InventoryError> {
+        self.groups.insert(
+            "_meta".to_string(),
+            serde_json::json!({ "hostvars": self.hostvars }),
+        );
+        dynamic_inventory(&Value::Object(self.groups))
+    }
+
+    fn push(&mut self, group: &str, field: &str, member: &str) {
+        let group = self
+            .groups
+            .entry(group)
+            .or_insert_with(|| serde_json::json!({ "hosts": [], "children": [] }));
+        if let Some(members) = group.get_mut(field).and_then(Value::as_array_mut) {
+            let member = Value::from(member);
+            if !members.contains(&member) {
+                members.push(member);
+            }
+        }
+    }
+}
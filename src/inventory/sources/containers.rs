@@ -0,0 +1,291 @@
+//! Docker and Podman containers as an inventory
+//!
+//! ```yaml
+//! plugin: community.docker.docker_containers
+//! docker_host: unix:///var/run/docker.sock
+//! keyed_groups:
+//!   - key: docker_labels['com.docker.compose.service']
+//!     prefix: service
+//! ```
+//!
+//! The containers of the engine at `docker_host`, running or not, are
+//! listed through its API, on a Unix socket or over TCP, and each is a host
+//! named after the container. By default hosts are reached by running
+//! commands in them through the engine's CLI; with `connection_type: ssh`,
+//! they are reached over SSH at the port `private_ssh_port` is published
+//! on, at its address or `default_ip`. They are described by
+//! `docker_name`, `docker_id`, `docker_short_id`, `docker_image`,
+//! `docker_state`, `docker_status`, `docker_labels` and `docker_networks`,
+//! the addresses of the container in each of its networks, or the same
+//! variables prefixed with `podman_` for Podman.
+//!
+//! Without `docker_host`, Docker's is `DOCKER_HOST` or its system socket,
+//! and Podman's `CONTAINER_HOST`, the socket of the user's service or the
+//! system socket.
+
+use super::cache::CacheOptions;
+use super::constructed::{Constructed, InventoryBuilder};
+use super::InventoryPlugin;
+use crate::deploy::connection::ContainerRuntime;
+use crate::inventory::error::InventoryError;
+use crate::types::inventory::{InventoryFormat, ParsedInventory};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::path::Path;
+
+const DOCKER_SOCKET: &str = "unix:///var/run/docker.sock";
+const PODMAN_SOCKET: &str = "unix:///run/podman/podman.sock";
+
+/// The `docker_containers` and `podman_containers` inventory plugins
+#[derive(Debug, Clone)]
+pub struct ContainerInventory {
+    runtime: ContainerRuntime,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContainersConfig {
+    #[serde(default, alias = "podman_host")]
+    docker_host: Option<String>,
+    #[serde(default)]
+    connection_type: Option<String>,
+    #[serde(default = "default_ip")]
+    default_ip: String,
+    #[serde(default = "default_ssh_port")]
+    private_ssh_port: u64,
+    #[serde(flatten)]
+    constructed: Constructed,
+    #[serde(flatten)]
+    cache: CacheOptions,
+}
+
+fn default_ip() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_ssh_port() -> u64 {
+    22
+}
+
+impl ContainerInventory {
+    pub fn docker() -> Self {
+        Self {
+            runtime: ContainerRuntime::Docker,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn podman() -> Self {
+        Self {
+            runtime: ContainerRuntime::Podman,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn engine_host(&self, config: &ContainersConfig) -> String {
+        if let Some(host) = &config.docker_host {
+            return host.clone();
+        }
+        match self.runtime {
+            ContainerRuntime::Docker => {
+                std::env::var("DOCKER_HOST").unwrap_or_else(|_| DOCKER_SOCKET.to_string())
+            }
+            ContainerRuntime::Podman => std::env::var("CONTAINER_HOST")
+                .ok()
+                .or_else(|| {
+                    let runtime_dir = std::env::var("XDG_RUNTIME_DIR").ok()?;
+                    let socket = Path::new(&runtime_dir).join("podman/podman.sock");
+                    socket
+                        .exists()
+                        .then(|| format!("unix://{}", socket.display()))
+                })
+                .unwrap_or_else(|| PODMAN_SOCKET.to_string()),
+        }
+    }
+
+    /// The JSON the engine at `host` answers to `GET path`
+    async fn get(&self, host: &str, path: &str) -> Result<Value, String> {
+        if let Some(socket) = host.strip_prefix("unix://") {
+            return unix_get(socket, path).await;
+        }
+        let base = match host.strip_prefix("tcp://") {
+            Some(address) => format!("http://{address}"),
+            None => host.to_string(),
+        };
+        let response = self
+            .client
+            .get(format!("{}{path}", base.trim_end_matches('/')))
+            .send()
+            .await
+            .map_err(|e| format!("{host}: {e}"))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("{host}: GET {path}: {status}"));
+        }
+        response.json().await.map_err(|e| format!("{host}: {e}"))
+    }
+
+    /// The variables of the host of the container `container` lists
+    fn host_vars(&self, config: &ContainersConfig, container: &Value) -> Option<(String, Value)> {
+        let name = container
+            .get("Names")?
+            .as_array()?
+            .first()?
+            .as_str()?
+            .trim_start_matches('/')
+            .to_string();
+        let id = container.get("Id").and_then(Value::as_str).unwrap_or("");
+        let field = |key: &str| container.get(key).cloned().unwrap_or(Value::Null);
+        let networks: Map<String, Value> = container
+            .pointer("/NetworkSettings/Networks")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+            .map(|(network, settings)| {
+                let address = settings.get("IPAddress").cloned().unwrap_or(Value::Null);
+                (network.clone(), address)
+            })
+            .collect();
+
+        let prefix = self.runtime.executable();
+        let mut vars = Map::new();
+        if config.connection_type.as_deref() == Some("ssh") {
+            let published = container
+                .get("Ports")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .find(|port| {
+                    port.get("PrivatePort").and_then(Value::as_u64) == Some(config.private_ssh_port)
+                        && port.get("PublicPort").is_some()
+                });
+            let address = published
+                .and_then(|port| port.get("IP")?.as_str())
+                .filter(|ip| !matches!(*ip, "" | "0.0.0.0" | "::"))
+                .unwrap_or(&config.default_ip);
+            vars.insert("ansible_connection".to_string(), json!("ssh"));
+            vars.insert("ansible_host".to_string(), json!(address));
+            if let Some(port) = published.and_then(|port| port.get("PublicPort")) {
+                vars.insert("ansible_port".to_string(), port.clone());
+            }
+        } else {
+            let connection = match self.runtime {
+                ContainerRuntime::Docker => "community.docker.docker",
+                ContainerRuntime::Podman => "containers.podman.podman",
+            };
+            vars.insert("ansible_connection".to_string(), json!(connection));
+            vars.insert("ansible_host".to_string(), json!(name));
+        }
+        vars.extend([
+            (format!("{prefix}_name"), json!(name)),
+            (format!("{prefix}_id"), json!(id)),
+            (
+                format!("{prefix}_short_id"),
+                json!(id.get(..13).unwrap_or(id)),
+            ),
+            (format!("{prefix}_image"), field("Image")),
+            (format!("{prefix}_state"), field("State")),
+            (format!("{prefix}_status"), field("Status")),
+            (
+                format!("{prefix}_labels"),
+                container
+                    .get("Labels")
+                    .filter(|labels| labels.is_object())
+                    .cloned()
+                    .unwrap_or_else(|| json!({})),
+            ),
+            (format!("{prefix}_networks"), Value::Object(networks)),
+        ]);
+        Some((name, Value::Object(vars)))
+    }
+}
+
+/// The JSON answered to `GET path` on the Unix socket `socket`, over
+/// HTTP/1.0 so that the body is neither chunked nor kept alive
+#[cfg(unix)]
+async fn unix_get(socket: &str, path: &str) -> Result<Value, String> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let failed = |e: std::io::Error| format!("{socket}: {e}");
+    let mut stream = tokio::net::UnixStream::connect(socket)
+        .await
+        .map_err(failed)?;
+    stream
+        .write_all(format!("GET {path} HTTP/1.0\r\nHost: localhost\r\n\r\n").as_bytes())
+        .await
+        .map_err(failed)?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.map_err(failed)?;
+
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| format!("{socket}: invalid HTTP response"))?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let status = head.split_whitespace().nth(1).unwrap_or_default();
+    if !status.starts_with('2') {
+        return Err(format!("{socket}: GET {path}: {status}"));
+    }
+    serde_json::from_slice(&response[split + 4..]).map_err(|e| format!("{socket}: {e}"))
+}
+
+#[cfg(not(unix))]
+async fn unix_get(socket: &str, _path: &str) -> Result<Value, String> {
+    Err(format!("{socket}: Unix sockets are not supported here"))
+}
+
+#[async_trait]
+impl InventoryPlugin for ContainerInventory {
+    fn name(&self) -> &'static str {
+        match self.runtime {
+            ContainerRuntime::Docker => "docker_containers",
+            ContainerRuntime::Podman => "podman_containers",
+        }
+    }
+
+    fn aliases(&self) -> &[&'static str] {
+        match self.runtime {
+            ContainerRuntime::Docker => &["community.docker.docker_containers"],
+            ContainerRuntime::Podman => &["containers.podman.podman_containers"],
+        }
+    }
+
+    async fn parse(&self, config: &Value, _path: &Path) -> Result<ParsedInventory, InventoryError> {
+        let failed = |reason: String| InventoryError::PluginFailed {
+            plugin: self.name().to_string(),
+            reason,
+        };
+        let config: ContainersConfig =
+            serde_json::from_value(config.clone()).map_err(|e| failed(e.to_string()))?;
+
+        let host = self.engine_host(&config);
+        let cache_key = json!({ "host": host });
+        let containers = match config.cache.get(self.name(), &cache_key) {
+            Some(containers) => containers,
+            None => {
+                let containers = self
+                    .get(&host, "/containers/json?all=1")
+                    .await
+                    .map_err(failed)?;
+                config.cache.put(self.name(), &cache_key, &containers);
+                containers
+            }
+        };
+
+        let mut builder = InventoryBuilder::new();
+        for container in containers.as_array().into_iter().flatten() {
+            let Some((name, Value::Object(vars))) = self.host_vars(&config, container) else {
+                continue;
+            };
+            config
+                .constructed
+                .add_host(&mut builder, &name, vars)
+                .map_err(failed)?;
+        }
+
+        let mut inventory = builder.build()?;
+        inventory.metadata.format = InventoryFormat::Dynamic;
+        Ok(inventory)
+    }
+}
@@ -0,0 +1,437 @@
+//! Google Compute Engine instances as an inventory
+//!
+//! ```yaml
+//! plugin: google.cloud.gcp_compute
+//! projects: [shop-prod]
+//! zones: [europe-west1-b, europe-west1-c]
+//! filters:
+//!   - status = RUNNING
+//!   - labels.env = prod
+//! auth_kind: serviceaccount
+//! service_account_file: /etc/rustle/gcp.json
+//! keyed_groups:
+//!   - key: labels
+//!     prefix: label
+//!   - key: zone
+//!     prefix: zone
+//! ```
+//!
+//! Instances are listed with the Compute Engine API, in the `zones` of each
+//! of `projects`, or in all zones of a project without them, matching all
+//! of `filters`. Their variables are those of the API, with `zone` and
+//! `machineType` reduced to their names, and `project` added. A host is
+//! named after the first of `hostnames` it has a value for, by default
+//! `public_ip`, `private_ip` and then `name`, and reached at its public
+//! address, or its private one. Every host is in the
+//! `gcp_compute` group, and in the groups of the [`Constructed`] options.
+//!
+//! As Ansible's `auth_kind` selects, the API is called with an
+//! `access_token`, the token of a service account key, of the instance's
+//! own service account, or of the application default credentials, a
+//! service account key or the user credentials of `gcloud auth
+//! application-default login`. `GCP_AUTH_KIND`, `GCP_SERVICE_ACCOUNT_FILE`
+//! and `GCP_ACCESS_TOKEN` stand for missing options.
+
+use super::cache::CacheOptions;
+use super::constructed::{Constructed, InventoryBuilder};
+use super::InventoryPlugin;
+use crate::inventory::error::InventoryError;
+use crate::types::inventory::{InventoryFormat, ParsedInventory};
+use async_trait::async_trait;
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+
+const NAME: &str = "gcp_compute";
+const DEFAULT_ENDPOINT: &str = "https://compute.googleapis.com";
+const DEFAULT_SCOPE: &str = "https://www.googleapis.com/auth/compute.readonly";
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+const DEFAULT_HOSTNAMES: &[&str] = &["public_ip", "private_ip", "name"];
+
+/// The `gcp_compute` inventory plugin
+#[derive(Debug, Clone, Default)]
+pub struct GcpComputeInventory {
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct GcpConfig {
+    #[serde(default)]
+    projects: Vec<String>,
+    #[serde(default)]
+    zones: Vec<String>,
+    #[serde(default)]
+    filters: Vec<String>,
+    #[serde(default)]
+    hostnames: Vec<String>,
+    #[serde(default)]
+    auth_kind: Option<String>,
+    #[serde(default)]
+    service_account_file: Option<PathBuf>,
+    #[serde(default)]
+    service_account_contents: Option<Value>,
+    #[serde(default)]
+    access_token: Option<String>,
+    #[serde(default)]
+    scopes: Vec<String>,
+    #[serde(default)]
+    endpoint_url: Option<String>,
+    #[serde(flatten)]
+    constructed: Constructed,
+    #[serde(flatten)]
+    cache: CacheOptions,
+}
+
+impl GcpComputeInventory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The instances of the projects of `config`
+    async fn instances(&self, config: &GcpConfig) -> Result<Vec<Value>, String> {
+        let token = self.token(config).await?;
+        let endpoint = config
+            .endpoint_url
+            .as_deref()
+            .unwrap_or(DEFAULT_ENDPOINT)
+            .trim_end_matches('/');
+        let filter = config
+            .filters
+            .iter()
+            .map(|filter| format!("({filter})"))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut instances = Vec::new();
+        for project in &config.projects {
+            let urls: Vec<String> = if config.zones.is_empty() {
+                vec![format!(
+                    "{endpoint}/compute/v1/projects/{project}/aggregated/instances"
+                )]
+            } else {
+                config
+                    .zones
+                    .iter()
+                    .map(|zone| {
+                        format!("{endpoint}/compute/v1/projects/{project}/zones/{zone}/instances")
+                    })
+                    .collect()
+            };
+            for url in urls {
+                for page in self.pages(&url, &filter, &token).await? {
+                    let items = match page.get("items") {
+                        // Aggregated lists are keyed by zone
+                        Some(Value::Object(zones)) => zones
+                            .values()
+                            .filter_map(|zone| zone.get("instances")?.as_array())
+                            .flatten()
+                            .cloned()
+                            .collect(),
+                        Some(Value::Array(items)) => items.clone(),
+                        _ => Vec::new(),
+                    };
+                    instances.extend(items.into_iter().map(|item| instance_vars(item, project)));
+                }
+            }
+        }
+        Ok(instances)
+    }
+
+    /// The pages of the list at `url`
+    async fn pages(&self, url: &str, filter: &str, token: &str) -> Result<Vec<Value>, String> {
+        let mut pages = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut request = self.client.get(url).bearer_auth(token);
+            if !filter.is_empty() {
+                request = request.query(&[("filter", filter)]);
+            }
+            if let Some(page_token) = page_token.take() {
+                request = request.query(&[("pageToken", page_token)]);
+            }
+            let response = request.send().await.map_err(|e| format!("{url}: {e}"))?;
+            let status = response.status();
+            let page: Value = response.json().await.unwrap_or(Value::Null);
+            if !status.is_success() {
+                let message = page
+                    .pointer("/error/message")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                return Err(format!("{url}: {status} {message}"));
+            }
+            page_token = page
+                .get("nextPageToken")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            pages.push(page);
+            if page_token.is_none() {
+                return Ok(pages);
+            }
+        }
+    }
+
+    /// An OAuth access token for the credentials `auth_kind` selects
+    async fn token(&self, config: &GcpConfig) -> Result<String, String> {
+        let auth_kind = config
+            .auth_kind
+            .clone()
+            .or_else(|| std::env::var("GCP_AUTH_KIND").ok())
+            .unwrap_or_else(|| "application".to_string());
+        let scopes = if config.scopes.is_empty() {
+            DEFAULT_SCOPE.to_string()
+        } else {
+            config.scopes.join(" ")
+        };
+        match auth_kind.as_str() {
+            "accesstoken" => config
+                .access_token
+                .clone()
+                .or_else(|| std::env::var("GCP_ACCESS_TOKEN").ok())
+                .ok_or_else(|| "auth_kind accesstoken needs an access_token".to_string()),
+            "serviceaccount" => {
+                let key = match &config.service_account_contents {
+                    Some(Value::String(contents)) => {
+                        serde_json::from_str(contents).map_err(|e| e.to_string())?
+                    }
+                    Some(contents) => contents.clone(),
+                    None => {
+                        let path = config
+                            .service_account_file
+                            .clone()
+                            .or_else(|| {
+                                std::env::var_os("GCP_SERVICE_ACCOUNT_FILE").map(PathBuf::from)
+                            })
+                            .ok_or("auth_kind serviceaccount needs a service_account_file")?;
+                        read_json(&path)?
+                    }
+                };
+                self.credentials_token(&key, &scopes).await
+            }
+            "machineaccount" => {
+                let response = self
+                    .client
+                    .get(METADATA_TOKEN_URL)
+                    .header("Metadata-Flavor", "Google")
+                    .send()
+                    .await
+                    .map_err(|e| format!("instance metadata: {e}"))?;
+                let token: Value = response.json().await.map_err(|e| e.to_string())?;
+                token
+                    .get("access_token")
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .ok_or_else(|| "instance metadata: no access_token".to_string())
+            }
+            "application" => {
+                let path = std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS")
+                    .map(PathBuf::from)
+                    .or_else(|| {
+                        dirs::config_dir()
+                            .map(|dir| dir.join("gcloud/application_default_credentials.json"))
+                    })
+                    .ok_or("no application default credentials")?;
+                self.credentials_token(&read_json(&path)?, &scopes).await
+            }
+            other => Err(format!("unknown auth_kind '{other}'")),
+        }
+    }
+
+    /// The access token of a service account key, or of the refresh token
+    /// of user credentials
+    async fn credentials_token(&self, credentials: &Value, scopes: &str) -> Result<String, String> {
+        let field = |name: &str| {
+            credentials
+                .get(name)
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("credentials without '{name}'"))
+        };
+        let (token_uri, form) = match credentials.get("type").and_then(Value::as_str) {
+            Some("authorized_user") => (
+                "https://oauth2.googleapis.com/token".to_string(),
+                vec![
+                    ("grant_type", "refresh_token".to_string()),
+                    ("client_id", field("client_id")?.to_string()),
+                    ("client_secret", field("client_secret")?.to_string()),
+                    ("refresh_token", field("refresh_token")?.to_string()),
+                ],
+            ),
+            _ => {
+                let token_uri = credentials
+                    .get("token_uri")
+                    .and_then(Value::as_str)
+                    .unwrap_or("https://oauth2.googleapis.com/token")
+                    .to_string();
+                let now = chrono::Utc::now().timestamp();
+                let claims = json!({
+                    "iss": field("client_email")?,
+                    "scope": scopes,
+                    "aud": token_uri,
+                    "iat": now,
+                    "exp": now + 3600,
+                });
+                let assertion = signed_jwt(&claims, field("private_key")?)?;
+                (
+                    token_uri,
+                    vec![
+                        (
+                            "grant_type",
+                            "urn:ietf:params:oauth:grant-type:jwt-bearer".to_string(),
+                        ),
+                        ("assertion", assertion),
+                    ],
+                )
+            }
+        };
+
+        let response = self
+            .client
+            .post(&token_uri)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| format!("{token_uri}: {e}"))?;
+        let status = response.status();
+        let token: Value = response.json().await.unwrap_or(Value::Null);
+        match token.get("access_token").and_then(Value::as_str) {
+            Some(access_token) if status.is_success() => Ok(access_token.to_string()),
+            _ => Err(format!(
+                "{token_uri}: {status} {}",
+                token
+                    .get("error_description")
+                    .or_else(|| token.get("error"))
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+            )),
+        }
+    }
+}
+
+/// `claims` as a JWT signed with RS256 by the PEM key `private_key`
+fn signed_jwt(claims: &Value, private_key: &str) -> Result<String, String> {
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::sign::Signer;
+
+    let encode = |bytes: &[u8]| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+    let header = json!({ "alg": "RS256", "typ": "JWT" });
+    let unsigned = format!(
+        "{}.{}",
+        encode(header.to_string().as_bytes()),
+        encode(claims.to_string().as_bytes())
+    );
+    let key = PKey::private_key_from_pem(private_key.as_bytes())
+        .map_err(|e| format!("invalid service account key: {e}"))?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).map_err(|e| e.to_string())?;
+    signer
+        .update(unsigned.as_bytes())
+        .map_err(|e| e.to_string())?;
+    let signature = signer.sign_to_vec().map_err(|e| e.to_string())?;
+    Ok(format!("{unsigned}.{}", encode(&signature)))
+}
+
+fn read_json(path: &Path) -> Result<Value, String> {
+    let content = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    serde_json::from_slice(&content).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// The variables of the instance `item` of `project`: those of the API,
+/// with the URLs of its zone and machine type reduced to their names
+fn instance_vars(mut item: Value, project: &str) -> Value {
+    if let Some(vars) = item.as_object_mut() {
+        for key in ["zone", "machineType"] {
+            if let Some(Value::String(url)) = vars.get_mut(key) {
+                if let Some((_, name)) = url.rsplit_once('/') {
+                    *url = name.to_string();
+                }
+            }
+        }
+        vars.insert("project".to_string(), Value::from(project));
+    }
+    item
+}
+
+/// The name `preference` gives the instance of `vars`: `public_ip`,
+/// `private_ip`, or the name of one of its variables
+fn hostname(vars: &Map<String, Value>, preference: &str) -> Option<String> {
+    let interface = vars.get("networkInterfaces").and_then(|nics| nics.get(0));
+    let value = match preference {
+        "public_ip" => interface?.pointer("/accessConfigs/0/natIP"),
+        "private_ip" => interface?.get("networkIP"),
+        name => vars.get(name),
+    };
+    value
+        .and_then(Value::as_str)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+#[async_trait]
+impl InventoryPlugin for GcpComputeInventory {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn aliases(&self) -> &[&'static str] {
+        &["google.cloud.gcp_compute"]
+    }
+
+    async fn parse(&self, config: &Value, _path: &Path) -> Result<ParsedInventory, InventoryError> {
+        let failed = |reason: String| InventoryError::PluginFailed {
+            plugin: NAME.to_string(),
+            reason,
+        };
+        let config: GcpConfig =
+            serde_json::from_value(config.clone()).map_err(|e| failed(e.to_string()))?;
+        if config.projects.is_empty() {
+            return Err(failed("no projects given".to_string()));
+        }
+
+        let cache_key = json!({
+            "projects": config.projects,
+            "zones": config.zones,
+            "filters": config.filters,
+            "endpoint_url": config.endpoint_url,
+        });
+        let instances = match config.cache.get(NAME, &cache_key) {
+            Some(Value::Array(instances)) => instances,
+            _ => {
+                let instances = self.instances(&config).await.map_err(failed)?;
+                config
+                    .cache
+                    .put(NAME, &cache_key, &Value::from(instances.clone()));
+                instances
+            }
+        };
+
+        let hostnames: Vec<&str> = if config.hostnames.is_empty() {
+            DEFAULT_HOSTNAMES.to_vec()
+        } else {
+            config.hostnames.iter().map(String::as_str).collect()
+        };
+        let mut builder = InventoryBuilder::new();
+        for instance in instances {
+            let Value::Object(mut vars) = instance else {
+                continue;
+            };
+            let Some(host) = hostnames.iter().find_map(|name| hostname(&vars, name)) else {
+                continue;
+            };
+            if let Some(address) =
+                hostname(&vars, "public_ip").or_else(|| hostname(&vars, "private_ip"))
+            {
+                vars.insert("ansible_host".to_string(), Value::from(address));
+            }
+            builder.add_to_group(NAME, &host);
+            config
+                .constructed
+                .add_host(&mut builder, &host, vars)
+                .map_err(failed)?;
+        }
+
+        let mut inventory = builder.build()?;
+        inventory.metadata.format = InventoryFormat::Dynamic;
+        Ok(inventory)
+    }
+}
@@ -0,0 +1,627 @@
+//! Containers of Kubernetes pods, and optionally nodes, as an inventory
+//!
+//! ```yaml
+//! plugin: kubernetes.core.k8s
+//! connections:
+//!   - name: prod
+//!     kubeconfig: ~/.kube/config
+//!     context: prod-admin
+//!     namespaces: [shop, payments]
+//!     nodes: true
+//! keyed_groups:
+//!   - key: pod_labels.tier
+//!     prefix: tier
+//! ```
+//!
+//! As with Ansible's `k8s` plugin, each container of a pod is a host named
+//! `<pod>_<container>`, reached with `kubectl exec` through the
+//! `ansible_kubectl_*` variables, and described by `pod_name`, `pod_ip`,
+//! `pod_labels`, `pod_annotations`, `pod_node_name`, `pod_phase`,
+//! `container_name`, `container_image`, `container_ready` and
+//! `container_state`. Containers are in the group of their cluster, named
+//! after the connection or its context, in `namespace_<ns>` and
+//! `namespace_<ns>_pods`, and in `label_<key>_<value>` for each label of
+//! their pod. With `nodes: true`, each node is a host too, in the `nodes`
+//! group, reached over SSH at its internal address.
+//!
+//! A connection without `kubeconfig` reads `KUBECONFIG` or
+//! `~/.kube/config`; without `context`, its current context; without
+//! `namespaces`, all of them. Clusters are authenticated with the bearer
+//! token, token file, exec plugin, client certificate or basic
+//! credentials of the context's user.
+
+use super::cache::CacheOptions;
+use super::constructed::{safe_group_name, Constructed, InventoryBuilder};
+use super::InventoryPlugin;
+use crate::inventory::error::InventoryError;
+use crate::types::inventory::{InventoryFormat, ParsedInventory};
+use async_trait::async_trait;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+
+const NAME: &str = "k8s";
+const PAGE_SIZE: usize = 500;
+
+/// The `k8s` inventory plugin
+#[derive(Debug, Clone, Default)]
+pub struct KubernetesInventory;
+
+#[derive(Debug, Deserialize)]
+struct K8sConfig {
+    #[serde(default)]
+    connections: Vec<K8sConnection>,
+    #[serde(flatten)]
+    constructed: Constructed,
+    #[serde(flatten)]
+    cache: CacheOptions,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct K8sConnection {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    kubeconfig: Option<PathBuf>,
+    #[serde(default)]
+    context: Option<String>,
+    #[serde(default)]
+    namespaces: Vec<String>,
+    #[serde(default)]
+    nodes: bool,
+}
+
+/// A host found in a cluster, before the constructed options apply
+#[derive(Debug, Serialize, Deserialize)]
+struct FoundHost {
+    name: String,
+    vars: Map<String, Value>,
+    groups: Vec<String>,
+    children: Vec<(String, String)>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Kubeconfig {
+    #[serde(default)]
+    clusters: Vec<NamedCluster>,
+    #[serde(default)]
+    users: Vec<NamedUser>,
+    #[serde(default)]
+    contexts: Vec<NamedContext>,
+    #[serde(default)]
+    current_context: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamedCluster {
+    name: String,
+    cluster: Cluster,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Cluster {
+    server: String,
+    #[serde(default)]
+    certificate_authority_data: Option<String>,
+    #[serde(default)]
+    certificate_authority: Option<PathBuf>,
+    #[serde(default)]
+    insecure_skip_tls_verify: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamedUser {
+    name: String,
+    #[serde(default)]
+    user: User,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct User {
+    #[serde(default)]
+    token: Option<String>,
+    #[serde(default, rename = "tokenFile")]
+    token_file: Option<PathBuf>,
+    #[serde(default)]
+    client_certificate_data: Option<String>,
+    #[serde(default)]
+    client_key_data: Option<String>,
+    #[serde(default)]
+    client_certificate: Option<PathBuf>,
+    #[serde(default)]
+    client_key: Option<PathBuf>,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    exec: Option<ExecCredentials>,
+}
+
+/// A client-go credential plugin, such as `aws eks get-token`
+#[derive(Debug, Deserialize)]
+struct ExecCredentials {
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: Option<Vec<ExecEnv>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExecEnv {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamedContext {
+    name: String,
+    context: Context,
+}
+
+#[derive(Debug, Deserialize)]
+struct Context {
+    cluster: String,
+    #[serde(default)]
+    user: Option<String>,
+}
+
+/// A client of the API of one cluster
+struct ClusterClient {
+    client: reqwest::Client,
+    server: String,
+    token: Option<String>,
+    basic: Option<(String, String)>,
+}
+
+impl KubernetesInventory {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// The hosts of the cluster of `connection`
+    async fn hosts(&self, connection: &K8sConnection) -> Result<Vec<FoundHost>, String> {
+        let kubeconfig_path = match &connection.kubeconfig {
+            Some(path) => expand_home(path),
+            None => std::env::var_os("KUBECONFIG")
+                .and_then(|paths| std::env::split_paths(&paths).next())
+                .or_else(|| dirs::home_dir().map(|home| home.join(".kube").join("config")))
+                .ok_or("no kubeconfig")?,
+        };
+        let content = std::fs::read_to_string(&kubeconfig_path)
+            .map_err(|e| format!("{}: {e}", kubeconfig_path.display()))?;
+        let kubeconfig: Kubeconfig = serde_yaml::from_str(&content)
+            .map_err(|e| format!("{}: {e}", kubeconfig_path.display()))?;
+        let base = kubeconfig_path.parent().unwrap_or(Path::new("."));
+
+        let context_name = connection
+            .context
+            .clone()
+            .or_else(|| kubeconfig.current_context.clone())
+            .ok_or("no context given and no current context")?;
+        let client = ClusterClient::new(&kubeconfig, &context_name, base).await?;
+
+        let cluster = safe_group_name(connection.name.as_deref().unwrap_or(&context_name));
+        let mut connection_vars = Map::new();
+        connection_vars.insert(
+            "ansible_connection".to_string(),
+            Value::from("kubernetes.core.kubectl"),
+        );
+        if connection.context.is_some() {
+            connection_vars.insert(
+                "ansible_kubectl_context".to_string(),
+                Value::from(context_name.as_str()),
+            );
+        }
+        if connection.kubeconfig.is_some() {
+            connection_vars.insert(
+                "ansible_kubectl_kubeconfig".to_string(),
+                Value::from(kubeconfig_path.display().to_string()),
+            );
+        }
+
+        let mut pods = Vec::new();
+        if connection.namespaces.is_empty() {
+            pods = client.list("/api/v1/pods").await?;
+        } else {
+            for namespace in &connection.namespaces {
+                pods.extend(
+                    client
+                        .list(&format!("/api/v1/namespaces/{namespace}/pods"))
+                        .await?,
+                );
+            }
+        }
+
+        let mut hosts = Vec::new();
+        for pod in &pods {
+            hosts.extend(pod_hosts(pod, &cluster, &connection_vars));
+        }
+        if connection.nodes {
+            for node in client.list("/api/v1/nodes").await? {
+                hosts.extend(node_host(&node, &cluster));
+            }
+        }
+        Ok(hosts)
+    }
+}
+
+impl ClusterClient {
+    /// The client of the cluster of the context `context_name`, whose
+    /// relative paths are relative to `base`
+    async fn new(kubeconfig: &Kubeconfig, context_name: &str, base: &Path) -> Result<Self, String> {
+        let context = kubeconfig
+            .contexts
+            .iter()
+            .find(|context| context.name == context_name)
+            .map(|context| &context.context)
+            .ok_or_else(|| format!("no context '{context_name}' in the kubeconfig"))?;
+        let cluster = kubeconfig
+            .clusters
+            .iter()
+            .find(|cluster| cluster.name == context.cluster)
+            .map(|cluster| &cluster.cluster)
+            .ok_or_else(|| format!("no cluster '{}' in the kubeconfig", context.cluster))?;
+        let anonymous = User::default();
+        let user = match &context.user {
+            Some(name) => kubeconfig
+                .users
+                .iter()
+                .find(|user| &user.name == name)
+                .map(|user| &user.user)
+                .ok_or_else(|| format!("no user '{name}' in the kubeconfig"))?,
+            None => &anonymous,
+        };
+
+        let mut builder = reqwest::Client::builder();
+        if let Some(ca) = pem(
+            &cluster.certificate_authority_data,
+            &cluster.certificate_authority,
+            base,
+        )? {
+            let certificate = reqwest::Certificate::from_pem(&ca).map_err(|e| e.to_string())?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        if cluster.insecure_skip_tls_verify {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        let certificate = pem(
+            &user.client_certificate_data,
+            &user.client_certificate,
+            base,
+        )?;
+        let key = pem(&user.client_key_data, &user.client_key, base)?;
+        if let (Some(certificate), Some(key)) = (certificate, key) {
+            let identity =
+                reqwest::Identity::from_pkcs8_pem(&certificate, &key).map_err(|e| e.to_string())?;
+            builder = builder.identity(identity);
+        }
+
+        let token = match (&user.token, &user.token_file, &user.exec) {
+            (Some(token), _, _) => Some(token.clone()),
+            (None, Some(file), _) => Some(
+                std::fs::read_to_string(base.join(expand_home(file)))
+                    .map_err(|e| format!("{}: {e}", file.display()))?
+                    .trim()
+                    .to_string(),
+            ),
+            (None, None, Some(exec)) => Some(exec_token(exec).await?),
+            (None, None, None) => None,
+        };
+
+        Ok(Self {
+            client: builder.build().map_err(|e| e.to_string())?,
+            server: cluster.server.trim_end_matches('/').to_string(),
+            token,
+            basic: user.username.clone().zip(user.password.clone()),
+        })
+    }
+
+    /// The items of the list at `path`, following its pages
+    async fn list(&self, path: &str) -> Result<Vec<Value>, String> {
+        let mut items = Vec::new();
+        let mut next: Option<String> = None;
+        loop {
+            let mut request = self
+                .client
+                .get(format!("{}{path}", self.server))
+                .query(&[("limit", PAGE_SIZE.to_string())]);
+            if let Some(token) = next.take() {
+                request = request.query(&[("continue", token)]);
+            }
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            } else if let Some((username, password)) = &self.basic {
+                request = request.basic_auth(username, Some(password));
+            }
+
+            let response = request
+                .send()
+                .await
+                .map_err(|e| format!("GET {path}: {e}"))?;
+            let status = response.status();
+            let body: Value = response.json().await.unwrap_or(Value::Null);
+            if !status.is_success() {
+                let message = body.get("message").and_then(Value::as_str).unwrap_or("");
+                return Err(format!("GET {path}: {status} {message}"));
+            }
+            if let Some(page) = body.get("items").and_then(Value::as_array) {
+                items.extend(page.iter().cloned());
+            }
+            match body
+                .pointer("/metadata/continue")
+                .and_then(Value::as_str)
+                .filter(|token| !token.is_empty())
+            {
+                Some(token) => next = Some(token.to_string()),
+                None => return Ok(items),
+            }
+        }
+    }
+}
+
+/// The hosts of the containers of `pod`
+fn pod_hosts(pod: &Value, cluster: &str, connection_vars: &Map<String, Value>) -> Vec<FoundHost> {
+    let text = |pointer: &str| pod.pointer(pointer).cloned().unwrap_or(Value::Null);
+    let Some(pod_name) = pod.pointer("/metadata/name").and_then(Value::as_str) else {
+        return Vec::new();
+    };
+    let namespace = pod
+        .pointer("/metadata/namespace")
+        .and_then(Value::as_str)
+        .unwrap_or("default");
+    let labels = pod
+        .pointer("/metadata/labels")
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+    let statuses = pod
+        .pointer("/status/containerStatuses")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+
+    let namespace_group = safe_group_name(&format!("namespace_{namespace}"));
+    let pods_group = format!("{namespace_group}_pods");
+    let mut groups = vec![
+        cluster.to_string(),
+        namespace_group.clone(),
+        pods_group.clone(),
+    ];
+    for (key, value) in &labels {
+        let value = value.as_str().unwrap_or_default();
+        groups.push(safe_group_name(&format!("label_{key}_{value}")));
+    }
+    let children = vec![
+        (cluster.to_string(), namespace_group.clone()),
+        (namespace_group, pods_group),
+    ];
+
+    let containers = pod
+        .pointer("/spec/containers")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    containers
+        .iter()
+        .filter_map(|container| {
+            let container_name = container.get("name")?.as_str()?;
+            let status = statuses
+                .iter()
+                .find(|status| status.get("name").and_then(Value::as_str) == Some(container_name));
+            let state = status
+                .and_then(|status| status.get("state")?.as_object()?.keys().next().cloned())
+                .map(|state| {
+                    let mut chars = state.chars();
+                    chars
+                        .next()
+                        .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                        .unwrap_or_default()
+                });
+
+            let mut vars = connection_vars.clone();
+            vars.extend([
+                ("ansible_kubectl_pod".to_string(), Value::from(pod_name)),
+                (
+                    "ansible_kubectl_container".to_string(),
+                    Value::from(container_name),
+                ),
+                (
+                    "ansible_kubectl_namespace".to_string(),
+                    Value::from(namespace),
+                ),
+                ("pod_name".to_string(), Value::from(pod_name)),
+                ("pod_ip".to_string(), text("/status/podIP")),
+                ("pod_labels".to_string(), Value::Object(labels.clone())),
+                ("pod_annotations".to_string(), text("/metadata/annotations")),
+                ("pod_node_name".to_string(), text("/spec/nodeName")),
+                ("pod_phase".to_string(), text("/status/phase")),
+                ("container_name".to_string(), Value::from(container_name)),
+                (
+                    "container_image".to_string(),
+                    container.get("image").cloned().unwrap_or(Value::Null),
+                ),
+                (
+                    "container_ready".to_string(),
+                    status
+                        .and_then(|status| status.get("ready").cloned())
+                        .unwrap_or(Value::Bool(false)),
+                ),
+                ("container_state".to_string(), json!(state)),
+            ]);
+            Some(FoundHost {
+                name: format!("{pod_name}_{container_name}"),
+                vars,
+                groups: groups.clone(),
+                children: children.clone(),
+            })
+        })
+        .collect()
+}
+
+/// The host of `node`, reached over SSH at its internal address
+fn node_host(node: &Value, cluster: &str) -> Option<FoundHost> {
+    let name = node.pointer("/metadata/name")?.as_str()?;
+    let info = |field: &str| {
+        node.pointer(&format!("/status/nodeInfo/{field}"))
+            .cloned()
+            .unwrap_or(Value::Null)
+    };
+    let addresses: Map<String, Value> = node
+        .pointer("/status/addresses")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|address| {
+            Some((
+                address.get("type")?.as_str()?.to_string(),
+                address.get("address")?.clone(),
+            ))
+        })
+        .collect();
+
+    let mut vars = Map::new();
+    if let Some(address) = addresses
+        .get("InternalIP")
+        .or_else(|| addresses.get("ExternalIP"))
+    {
+        vars.insert("ansible_host".to_string(), address.clone());
+    }
+    vars.extend([
+        ("node_name".to_string(), Value::from(name)),
+        (
+            "node_labels".to_string(),
+            node.pointer("/metadata/labels")
+                .cloned()
+                .unwrap_or_else(|| json!({})),
+        ),
+        ("node_addresses".to_string(), Value::Object(addresses)),
+        ("node_architecture".to_string(), info("architecture")),
+        ("node_os".to_string(), info("operatingSystem")),
+        ("node_os_image".to_string(), info("osImage")),
+        ("node_kernel_version".to_string(), info("kernelVersion")),
+        ("node_kubelet_version".to_string(), info("kubeletVersion")),
+    ]);
+    Some(FoundHost {
+        name: name.to_string(),
+        vars,
+        groups: vec![cluster.to_string(), "nodes".to_string()],
+        children: Vec::new(),
+    })
+}
+
+/// The PEM of inline base64 `data`, or of the file at `path`
+fn pem(
+    data: &Option<String>,
+    path: &Option<PathBuf>,
+    base: &Path,
+) -> Result<Option<Vec<u8>>, String> {
+    if let Some(data) = data {
+        return base64::engine::general_purpose::STANDARD
+            .decode(data.trim())
+            .map(Some)
+            .map_err(|e| format!("invalid base64 in the kubeconfig: {e}"));
+    }
+    path.as_ref()
+        .map(|path| {
+            let path = base.join(expand_home(path));
+            std::fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))
+        })
+        .transpose()
+}
+
+/// The token an exec credential plugin prints
+async fn exec_token(exec: &ExecCredentials) -> Result<String, String> {
+    let mut command = tokio::process::Command::new(&exec.command);
+    command.args(&exec.args).kill_on_drop(true);
+    for env in exec.env.iter().flatten() {
+        command.env(&env.name, &env.value);
+    }
+    let output = command
+        .output()
+        .await
+        .map_err(|e| format!("{}: {e}", exec.command))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("{}: {}", exec.command, stderr.trim()));
+    }
+    let credential: Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("{}: invalid ExecCredential: {e}", exec.command))?;
+    credential
+        .pointer("/status/token")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| format!("{}: no token in its ExecCredential", exec.command))
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[async_trait]
+impl InventoryPlugin for KubernetesInventory {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn aliases(&self) -> &[&'static str] {
+        &["kubernetes.core.k8s"]
+    }
+
+    async fn parse(&self, config: &Value, _path: &Path) -> Result<ParsedInventory, InventoryError> {
+        let failed = |reason: String| InventoryError::PluginFailed {
+            plugin: NAME.to_string(),
+            reason,
+        };
+        let mut config: K8sConfig =
+            serde_json::from_value(config.clone()).map_err(|e| failed(e.to_string()))?;
+        if config.connections.is_empty() {
+            config.connections.push(K8sConnection::default());
+        }
+
+        let cache_key = json!({ "connections": config.connections });
+        let hosts: Vec<FoundHost> = match config
+            .cache
+            .get(NAME, &cache_key)
+            .and_then(|cached| serde_json::from_value(cached).ok())
+        {
+            Some(hosts) => hosts,
+            None => {
+                let mut hosts = Vec::new();
+                for connection in &config.connections {
+                    hosts.extend(self.hosts(connection).await.map_err(failed)?);
+                }
+                if let Ok(data) = serde_json::to_value(&hosts) {
+                    config.cache.put(NAME, &cache_key, &data);
+                }
+                hosts
+            }
+        };
+
+        let mut builder = InventoryBuilder::new();
+        for host in hosts {
+            for group in &host.groups {
+                builder.add_to_group(group, &host.name);
+            }
+            for (parent, child) in &host.children {
+                builder.add_child(parent, child);
+            }
+            config
+                .constructed
+                .add_host(&mut builder, &host.name, host.vars)
+                .map_err(failed)?;
+        }
+
+        let mut inventory = builder.build()?;
+        inventory.metadata.format = InventoryFormat::Dynamic;
+        Ok(inventory)
+    }
+}
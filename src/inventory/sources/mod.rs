@@ -0,0 +1,430 @@
+//! Inventory sources.
+//!
+//! An inventory is loaded from a path, as `ansible-playbook -i PATH` takes
+//! it:
+//!
+//! - an executable file is a dynamic inventory script, run with `--list`,
+//!   and with `--host HOST` for each host when its output has no
+//!   `_meta.hostvars`; see [`ScriptInventory`];
+//! - a YAML file whose `plugin` key names an [`InventoryPlugin`] is the
+//!   configuration of that plugin;
+//! - other files are JSON in the format of dynamic inventories, YAML
+//!   inventories or INI inventories;
+//! - a directory is a directory of sources, whose inventories are merged
+//!   in the order of their names. Hidden files, files with Ansible's
+//!   ignored extensions, and the `group_vars/` and `host_vars/`
+//!   directories are left out. A `constructed` source there applies its
+//!   [`Constructed`] options to the hosts of the sources before it.
+//!
+//! [`InventoryLoader::builtin`] has the plugins of this crate:
+//! [`Ec2Inventory`] for EC2 instances, [`GcpComputeInventory`] for Compute
+//! Engine instances, [`AzureRmInventory`] for Azure virtual machines,
+//! [`KubernetesInventory`] for pods and nodes, and [`ContainerInventory`]
+//! for Docker and Podman containers.
+//! Plugins share the [`Constructed`] options building
+//! variables and groups from those of hosts, and the [`CacheOptions`] of
+//! what they fetch.
+
+mod aws_ec2;
+mod azure_rm;
+mod cache;
+mod constructed;
+mod containers;
+mod gcp_compute;
+mod kubernetes;
+mod script;
+mod xml;
+mod yaml;
+
+pub use aws_ec2::Ec2Inventory;
+pub use azure_rm::AzureRmInventory;
+pub use cache::CacheOptions;
+pub use constructed::{
+    safe_group_name, Constructed, InventoryBuilder, KeyedGroup, CONSTRUCTED_PLUGINS,
+};
+pub use containers::ContainerInventory;
+pub use gcp_compute::GcpComputeInventory;
+pub use kubernetes::KubernetesInventory;
+pub use script::ScriptInventory;
+pub use yaml::yaml_to_dynamic;
+
+use crate::inventory::error::InventoryError;
+use crate::inventory::{IniInventoryParser, JsonInventoryProcessor};
+use crate::types::inventory::{InventoryFormat, InventoryMetadata, ParsedInventory};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Extensions of the files a directory of sources ignores, as Ansible's
+/// `INVENTORY_IGNORE_EXTS` does
+const IGNORED_EXTENSIONS: &[&str] = &[
+    "pyc", "pyo", "swp", "bak", "rpm", "md", "txt", "rst", "orig", "ini", "cfg", "retry",
+];
+
+/// Subdirectories of a directory of sources holding variables, not sources
+const VARS_DIRECTORIES: &[&str] = &["group_vars", "host_vars"];
+
+/// An inventory plugin, named by the `plugin` key of its configuration file
+#[async_trait]
+pub trait InventoryPlugin: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Other names the plugin answers to, such as its fully qualified one
+    fn aliases(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// The inventory described by `config`, the configuration file at `path`
+    async fn parse(&self, config: &Value, path: &Path) -> Result<ParsedInventory, InventoryError>;
+}
+
+/// Loads inventories from files, scripts, plugins and directories of them
+#[derive(Clone, Default)]
+pub struct InventoryLoader {
+    plugins: HashMap<String, Arc<dyn InventoryPlugin>>,
+}
+
+impl std::fmt::Debug for InventoryLoader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut names: Vec<_> = self.plugins.keys().collect();
+        names.sort();
+        f.debug_struct("InventoryLoader")
+            .field("plugins", &names)
+            .finish()
+    }
+}
+
+impl InventoryLoader {
+    /// A loader without plugins
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A loader with the plugins of this crate
+    pub fn builtin() -> Self {
+        Self::new()
+            .with_plugin(Arc::new(Ec2Inventory::new()))
+            .with_plugin(Arc::new(GcpComputeInventory::new()))
+            .with_plugin(Arc::new(AzureRmInventory::new()))
+            .with_plugin(Arc::new(KubernetesInventory::new()))
+            .with_plugin(Arc::new(ContainerInventory::docker()))
+            .with_plugin(Arc::new(ContainerInventory::podman()))
+    }
+
+    /// Add `plugin`, replacing any plugin of the same name or alias
+    pub fn register(&mut self, plugin: Arc<dyn InventoryPlugin>) {
+        for alias in plugin.aliases() {
+            self.plugins.insert(alias.to_string(), Arc::clone(&plugin));
+        }
+        self.plugins.insert(plugin.name().to_string(), plugin);
+    }
+
+    pub fn with_plugin(mut self, plugin: Arc<dyn InventoryPlugin>) -> Self {
+        self.register(plugin);
+        self
+    }
+
+    /// The plugin `name` answers to
+    pub fn plugin(&self, name: &str) -> Result<Arc<dyn InventoryPlugin>, InventoryError> {
+        self.plugins
+            .get(name)
+            .cloned()
+            .ok_or_else(|| InventoryError::UnknownPlugin {
+                plugin: name.to_string(),
+            })
+    }
+
+    /// The inventory of the source at `path`
+    pub async fn load(&self, path: &Path) -> Result<ParsedInventory, InventoryError> {
+        let mut inventory = if path.is_dir() {
+            let mut merged = None;
+            for source in directory_sources(path)? {
+                if let Some(constructed) = constructed_source(&source)? {
+                    if let Some(merged) = &mut merged {
+                        constructed
+                            .apply(merged)
+                            .map_err(|reason| constructed_failed(&source, reason))?;
+                    }
+                    continue;
+                }
+                let inventory = Box::pin(self.load(&source)).await?;
+                merged = Some(match merged {
+                    Some(mut merged) => {
+                        merge_inventories(&mut merged, inventory)?;
+                        merged
+                    }
+                    None => inventory,
+                });
+            }
+            let mut inventory = merged.unwrap_or_else(|| empty_inventory(path));
+            inventory.metadata.source = path.display().to_string();
+            inventory
+        } else {
+            self.load_file(path).await?
+        };
+        link_parent_groups(&mut inventory);
+        inventory.metadata.host_count = inventory.hosts.len();
+        inventory.metadata.group_count = inventory.groups.len();
+        Ok(inventory)
+    }
+
+    /// The options of the `constructed` sources at `path`, a source or a
+    /// directory of them, in the order they apply in
+    pub fn constructed_sources(&self, path: &Path) -> Result<Vec<Constructed>, InventoryError> {
+        if !path.is_dir() {
+            return Ok(constructed_source(path)?.into_iter().collect());
+        }
+        let mut constructed = Vec::new();
+        for source in directory_sources(path)? {
+            constructed.extend(self.constructed_sources(&source)?);
+        }
+        Ok(constructed)
+    }
+
+    async fn load_file(&self, path: &Path) -> Result<ParsedInventory, InventoryError> {
+        let source = path.display().to_string();
+        if is_executable(path) {
+            return ScriptInventory::new(path).load().await;
+        }
+        let content = std::fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+
+        let mut inventory = if let Ok(data) = serde_json::from_str::<Value>(&content) {
+            dynamic_inventory(&data)?
+        } else if let Some(data) = serde_yaml::from_str::<Value>(&content)
+            .ok()
+            .filter(Value::is_object)
+        {
+            match data.get("plugin").and_then(Value::as_str) {
+                // Applies to the sources before it, of which there are none
+                Some(plugin) if CONSTRUCTED_PLUGINS.contains(&plugin) => empty_inventory(path),
+                Some(plugin) => self.plugin(plugin)?.parse(&data, path).await?,
+                None => {
+                    let mut inventory = dynamic_inventory(&yaml_to_dynamic(&data)?)?;
+                    inventory.metadata.format = InventoryFormat::Yaml;
+                    inventory
+                }
+            }
+        } else {
+            IniInventoryParser::new().parse(&content, &source)?
+        };
+        inventory.metadata.source = source;
+        Ok(inventory)
+    }
+}
+
+/// The options of the file at `path` when it configures a `constructed`
+/// source
+fn constructed_source(path: &Path) -> Result<Option<Constructed>, InventoryError> {
+    if path.is_dir() || is_executable(path) {
+        return Ok(None);
+    }
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return Ok(None);
+    };
+    let Ok(data) = serde_yaml::from_str::<Value>(&content) else {
+        return Ok(None);
+    };
+    match data.get("plugin").and_then(Value::as_str) {
+        Some(plugin) if CONSTRUCTED_PLUGINS.contains(&plugin) => serde_json::from_value(data)
+            .map(Some)
+            .map_err(|e| constructed_failed(path, e.to_string())),
+        _ => Ok(None),
+    }
+}
+
+fn constructed_failed(path: &Path, reason: String) -> InventoryError {
+    InventoryError::PluginFailed {
+        plugin: "constructed".to_string(),
+        reason: format!("{}: {reason}", path.display()),
+    }
+}
+
+/// The inventory of `data`, in the JSON format of dynamic inventories,
+/// where a group may also be the list of its hosts. Hosts in no group but
+/// `all` are in `ungrouped`.
+pub fn dynamic_inventory(data: &Value) -> Result<ParsedInventory, InventoryError> {
+    let Value::Object(entries) = data else {
+        return Err(InventoryError::InvalidJson {
+            reason: "expected an object of groups".to_string(),
+        });
+    };
+    let mut normalized: serde_json::Map<String, Value> = entries
+        .iter()
+        .map(|(name, group)| match group {
+            Value::Array(hosts) => (name.clone(), serde_json::json!({ "hosts": hosts })),
+            group => (name.clone(), group.clone()),
+        })
+        .collect();
+
+    let grouped: Vec<&str> = normalized
+        .iter()
+        .filter(|(name, _)| !matches!(name.as_str(), "all" | "_meta"))
+        .filter_map(|(_, group)| group.get("hosts").and_then(Value::as_array))
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    let hostvars = data.pointer("/_meta/hostvars").and_then(Value::as_object);
+    let all_hosts = data.pointer("/all/hosts").and_then(Value::as_array);
+    let ungrouped: Vec<Value> = all_hosts
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .chain(
+            hostvars
+                .into_iter()
+                .flat_map(|hostvars| hostvars.keys().map(String::as_str)),
+        )
+        .filter(|host| !grouped.contains(host))
+        .map(Value::from)
+        .collect();
+    if !ungrouped.is_empty() {
+        let group = normalized
+            .entry("ungrouped")
+            .or_insert_with(|| serde_json::json!({ "hosts": [] }));
+        if let Some(hosts) = group.get_mut("hosts").and_then(Value::as_array_mut) {
+            for host in ungrouped {
+                if !hosts.contains(&host) {
+                    hosts.push(host);
+                }
+            }
+        }
+    }
+    JsonInventoryProcessor::new().process_inventory_json(&Value::Object(normalized))
+}
+
+/// Merge `other` into `inventory`: its groups and hosts are added to those
+/// of the same name, and its variables override theirs
+pub fn merge_inventories(
+    inventory: &mut ParsedInventory,
+    other: ParsedInventory,
+) -> Result<(), InventoryError> {
+    fn union(items: &mut Vec<String>, others: Vec<String>) {
+        for other in others {
+            if !items.contains(&other) {
+                items.push(other);
+            }
+        }
+    }
+
+    inventory.global_vars.extend(other.global_vars);
+    for (name, group) in other.groups {
+        match inventory.groups.get_mut(&name) {
+            Some(existing) => {
+                union(&mut existing.hosts, group.hosts);
+                union(&mut existing.children, group.children);
+                union(&mut existing.parent_groups, group.parent_groups);
+                existing.variables.extend(group.variables);
+            }
+            None => {
+                inventory.groups.insert(name, group);
+            }
+        }
+    }
+
+    let processor = JsonInventoryProcessor::new();
+    for (name, host) in other.hosts {
+        let Some(existing) = inventory.hosts.remove(&name) else {
+            inventory.hosts.insert(name, host);
+            continue;
+        };
+        // The connection follows from the merged variables
+        let mut variables: serde_json::Map<String, Value> =
+            existing.variables.into_iter().collect();
+        variables.extend(host.variables);
+        let mut merged = processor.create_host_from_vars(&name, &Value::Object(variables))?;
+        merged.groups = existing.groups;
+        union(&mut merged.groups, host.groups);
+        merged.target_triple = merged
+            .target_triple
+            .or(host.target_triple)
+            .or(existing.target_triple);
+        inventory.hosts.insert(name, merged);
+    }
+    Ok(())
+}
+
+/// Record each group as a parent of its children
+fn link_parent_groups(inventory: &mut ParsedInventory) {
+    let links: Vec<(String, String)> = inventory
+        .groups
+        .values()
+        .flat_map(|group| {
+            group
+                .children
+                .iter()
+                .map(|child| (child.clone(), group.name.clone()))
+        })
+        .collect();
+    for (child, parent) in links {
+        if let Some(child) = inventory.groups.get_mut(&child) {
+            if !child.parent_groups.contains(&parent) {
+                child.parent_groups.push(parent);
+            }
+        }
+    }
+}
+
+/// The sources of the directory `dir`, in the order of their names
+fn directory_sources(dir: &Path) -> Result<Vec<PathBuf>, InventoryError> {
+    let mut sources = std::fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| io_error(dir, e))?;
+    sources.retain(|path| {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            return false;
+        };
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let ignored = name.starts_with('.')
+            || name.ends_with('~')
+            || path.is_dir() && VARS_DIRECTORIES.contains(&name)
+            || extension.is_some_and(|ext| IGNORED_EXTENSIONS.contains(&ext));
+        !ignored
+    });
+    sources.sort();
+    Ok(sources)
+}
+
+fn empty_inventory(path: &Path) -> ParsedInventory {
+    ParsedInventory {
+        hosts: HashMap::new(),
+        groups: HashMap::new(),
+        global_vars: HashMap::new(),
+        metadata: InventoryMetadata {
+            format: InventoryFormat::Dynamic,
+            source: path.display().to_string(),
+            parsed_at: Utc::now(),
+            host_count: 0,
+            group_count: 0,
+        },
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    false
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> InventoryError {
+    let path = path.display().to_string();
+    match error.kind() {
+        std::io::ErrorKind::NotFound => InventoryError::FileNotFound { path },
+        std::io::ErrorKind::PermissionDenied => InventoryError::PermissionDenied { path },
+        _ => InventoryError::ConversionError {
+            reason: format!("failed to read {path}: {error}"),
+        },
+    }
+}
@@ -0,0 +1,111 @@
+//! Dynamic inventory scripts
+
+use super::dynamic_inventory;
+use crate::inventory::error::InventoryError;
+use crate::types::inventory::{InventoryFormat, ParsedInventory};
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long a script may take to answer; Ansible waits for ever, but a
+/// hung script should not hang a deployment
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// An executable printing an inventory in JSON: all of it with `--list`,
+/// and the variables of a host with `--host HOST`, which is only run when
+/// the inventory has no `_meta.hostvars`
+#[derive(Debug, Clone)]
+pub struct ScriptInventory {
+    path: PathBuf,
+    timeout: Duration,
+}
+
+impl ScriptInventory {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn load(&self) -> Result<ParsedInventory, InventoryError> {
+        let mut data = self.run(&["--list"]).await?;
+        let Some(groups) = data.as_object_mut() else {
+            return Err(self.failed("--list did not print a JSON object".to_string()));
+        };
+
+        if groups
+            .get("_meta")
+            .and_then(|meta| meta.get("hostvars"))
+            .is_none()
+        {
+            let mut hostvars = Map::new();
+            for host in hosts(groups) {
+                let vars = self.run(&["--host", &host]).await?;
+                if !vars.is_object() {
+                    return Err(self.failed(format!("--host {host} did not print a JSON object")));
+                }
+                hostvars.insert(host, vars);
+            }
+            groups.insert(
+                "_meta".to_string(),
+                serde_json::json!({ "hostvars": hostvars }),
+            );
+        }
+
+        let mut inventory = dynamic_inventory(&data)?;
+        inventory.metadata.format = InventoryFormat::Dynamic;
+        inventory.metadata.source = self.path.display().to_string();
+        Ok(inventory)
+    }
+
+    async fn run(&self, args: &[&str]) -> Result<Value, InventoryError> {
+        // Scripts run in their own directory, as they often read files
+        // next to them
+        let path = std::fs::canonicalize(&self.path).map_err(|e| self.failed(e.to_string()))?;
+        let mut command = tokio::process::Command::new(&path);
+        command.args(args).kill_on_drop(true);
+        if let Some(dir) = path.parent() {
+            command.current_dir(dir);
+        }
+        let output = tokio::time::timeout(self.timeout, command.output())
+            .await
+            .map_err(|_| self.failed(format!("timed out after {}s", self.timeout.as_secs())))?
+            .map_err(|e| self.failed(e.to_string()))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(self.failed(format!("{}: {}", output.status, stderr.trim())));
+        }
+        serde_json::from_slice(&output.stdout)
+            .map_err(|e| self.failed(format!("invalid JSON for {}: {e}", args.join(" "))))
+    }
+
+    fn failed(&self, reason: String) -> InventoryError {
+        InventoryError::DynamicScriptFailed {
+            script: self.path.display().to_string(),
+            reason,
+        }
+    }
+}
+
+/// The hosts of the groups of a `--list` output, in order
+fn hosts(groups: &Map<String, Value>) -> Vec<String> {
+    let mut hosts: Vec<String> = Vec::new();
+    for group in groups.values() {
+        let members = match group {
+            Value::Array(members) => Some(members),
+            group => group.get("hosts").and_then(Value::as_array),
+        };
+        for host in members.into_iter().flatten().filter_map(Value::as_str) {
+            if !hosts.iter().any(|known| known == host) {
+                hosts.push(host.to_string());
+            }
+        }
+    }
+    hosts
+}
@@ -0,0 +1,93 @@
+//! The responses of AWS query APIs as trees of elements, read with
+//! `quick-xml`: they are plain elements and text, so attributes,
+//! processing instructions, comments and doctypes are skipped, and
+//! namespace prefixes dropped
+
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+/// An element, with its child elements and the text directly in it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Element {
+    pub name: String,
+    pub children: Vec<Element>,
+    pub text: String,
+}
+
+impl Element {
+    /// The first child named `name`
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// The text of the first child named `name`
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|child| child.text.as_str())
+    }
+}
+
+/// The root element of `document`
+pub(crate) fn parse(document: &str) -> Result<Element, String> {
+    let mut reader = Reader::from_str(document);
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root = None;
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("at byte {}: {e}", reader.error_position()))?;
+        match event {
+            Event::Start(start) => stack.push(Element {
+                name: start.local_name().as_ref().to_string(),
+                ..Element::default()
+            }),
+            Event::Empty(start) => {
+                let element = Element {
+                    name: start.local_name().as_ref().to_string(),
+                    ..Element::default()
+                };
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => root = Some(element),
+                }
+            }
+            Event::End(_) => {
+                let element = stack.pop().ok_or("unexpected closing tag")?;
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => root = Some(element),
+                }
+            }
+            Event::Text(text) => append_text(&mut stack, &text.xml10_content())?,
+            Event::CData(data) => append_text(&mut stack, &data.xml10_content())?,
+            Event::GeneralRef(reference) => {
+                let resolved = match reference.resolve_char_ref().map_err(|e| e.to_string())? {
+                    Some(ch) => ch.to_string(),
+                    None => resolve_predefined_entity(&reference)
+                        .ok_or_else(|| format!("unknown entity '&{};'", &*reference))?
+                        .to_string(),
+                };
+                append_text(&mut stack, &resolved)?;
+            }
+            Event::Eof => break,
+            Event::Comment(_) | Event::Decl(_) | Event::PI(_) | Event::DocType(_) => {}
+        }
+    }
+
+    if let Some(element) = stack.last() {
+        return Err(format!("unclosed element '{}'", element.name));
+    }
+    root.ok_or_else(|| "no root element".to_string())
+}
+
+fn append_text(stack: &mut [Element], text: &str) -> Result<(), String> {
+    match stack.last_mut() {
+        Some(element) => {
+            element.text.push_str(text);
+            Ok(())
+        }
+        None if text.trim().is_empty() => Ok(()),
+        None => Err("text outside of the root element".to_string()),
+    }
+}
@@ -0,0 +1,123 @@
+//! YAML inventories
+
+use crate::inventory::error::InventoryError;
+use crate::inventory::expand_host_range;
+use serde_json::{json, Map, Value};
+use std::sync::OnceLock;
+
+/// The inventory of a YAML inventory, in the JSON format of dynamic
+/// inventories:
+///
+/// ```yaml
+/// all:
+///   vars:
+///     ntp_server: ntp.example.com
+///   children:
+///     web:
+///       hosts:
+///         web[01:03].example.com:
+///           http_port: 80
+/// ```
+///
+/// Groups are nested by `children`, hosts are mappings of their variables
+/// and may use ranges; a group or host appearing several times has the
+/// hosts, children and variables of all its appearances.
+pub fn yaml_to_dynamic(data: &Value) -> Result<Value, InventoryError> {
+    let Value::Object(groups) = data else {
+        return Err(invalid("expected a mapping of groups".to_string()));
+    };
+    let mut inventory = Map::new();
+    let mut hostvars = Map::new();
+    for (name, group) in groups {
+        add_group(name, group, &mut inventory, &mut hostvars)?;
+    }
+    inventory.insert("_meta".to_string(), json!({ "hostvars": hostvars }));
+    Ok(Value::Object(inventory))
+}
+
+fn add_group(
+    name: &str,
+    body: &Value,
+    inventory: &mut Map<String, Value>,
+    hostvars: &mut Map<String, Value>,
+) -> Result<(), InventoryError> {
+    let body = match body {
+        Value::Null => empty(),
+        Value::Object(body) => body,
+        _ => return Err(invalid(format!("group '{name}' is not a mapping"))),
+    };
+    let entry = group(inventory, name);
+
+    let mut hosts = Vec::new();
+    for (pattern, vars) in mapping(body.get("hosts"), name, "hosts")? {
+        let vars = mapping(Some(vars), name, pattern)?;
+        for host in expand_host_range(pattern).map_err(invalid)? {
+            if let Value::Object(known) = hostvars
+                .entry(host.clone())
+                .or_insert_with(|| Value::Object(Map::new()))
+            {
+                known.extend(vars.clone());
+            }
+            hosts.push(Value::from(host));
+        }
+    }
+    push_unique(entry, "hosts", hosts);
+    if let Some(Value::Object(vars)) = entry.get_mut("vars") {
+        vars.extend(mapping(body.get("vars"), name, "vars")?.clone());
+    }
+
+    let children = mapping(body.get("children"), name, "children")?;
+    push_unique(
+        group(inventory, name),
+        "children",
+        children
+            .keys()
+            .map(|child| Value::from(child.as_str()))
+            .collect(),
+    );
+    for (child, child_body) in children {
+        add_group(child, child_body, inventory, hostvars)?;
+    }
+    Ok(())
+}
+
+/// The entry of group `name` in the inventory, added when missing
+fn group<'a>(inventory: &'a mut Map<String, Value>, name: &str) -> &'a mut Value {
+    inventory
+        .entry(name.to_string())
+        .or_insert_with(|| json!({ "hosts": [], "vars": {}, "children": [] }))
+}
+
+fn push_unique(group: &mut Value, key: &str, items: Vec<Value>) {
+    if let Some(Value::Array(existing)) = group.get_mut(key) {
+        for item in items {
+            if !existing.contains(&item) {
+                existing.push(item);
+            }
+        }
+    }
+}
+
+/// The mapping `value` of `key` in group `group`; nothing is an empty one
+fn mapping<'a>(
+    value: Option<&'a Value>,
+    group: &str,
+    key: &str,
+) -> Result<&'a Map<String, Value>, InventoryError> {
+    match value {
+        None | Some(Value::Null) => Ok(empty()),
+        Some(Value::Object(entries)) => Ok(entries),
+        Some(_) => Err(invalid(format!(
+            "'{key}' of group '{group}' is not a mapping"
+        ))),
+    }
+}
+
+fn empty() -> &'static Map<String, Value> {
+    static EMPTY: OnceLock<Map<String, Value>> = OnceLock::new();
+    EMPTY.get_or_init(Map::new)
+}
+
+fn invalid(reason: String) -> InventoryError {
+    InventoryError::InvalidYaml { reason }
+}
@@ -1,7 +1,8 @@
 use crate::inventory::error::ValidationError;
+use crate::inventory::{ConnectionPreflight, InventoryProcessor, PreflightReport};
 use crate::types::inventory::{ConnectionMethod, ParsedInventory};
 
-pub trait InventoryValidator {
+pub trait InventoryValidator: Send + Sync {
     fn validate(&self, inventory: &ParsedInventory) -> Result<(), ValidationError>;
 }
 
@@ -131,6 +132,7 @@ impl VariableValidator {
 
 pub struct InventoryValidatorSet {
     validators: Vec<Box<dyn InventoryValidator>>,
+    preflight: Option<ConnectionPreflight>,
 }
 
 impl InventoryValidatorSet {
@@ -141,15 +143,43 @@ impl InventoryValidatorSet {
                 Box::new(ArchitectureValidator),
                 Box::new(VariableValidator),
             ],
+            preflight: None,
         }
     }
 
+    /// Leave the architecture of the hosts to the facts gathered when they
+    /// run, rather than requiring the inventory to describe it
+    pub fn with_architecture_from_facts(mut self) -> Self {
+        self.validators = vec![Box::new(ConnectivityValidator), Box::new(VariableValidator)];
+        self
+    }
+
+    /// Also connect to the hosts when preflighting, beyond checking how
+    /// the inventory describes them
+    pub fn with_preflight(mut self, preflight: ConnectionPreflight) -> Self {
+        self.preflight = Some(preflight);
+        self
+    }
+
     pub fn validate(&self, inventory: &ParsedInventory) -> Result<(), ValidationError> {
         for validator in &self.validators {
             validator.validate(inventory)?;
         }
         Ok(())
     }
+
+    /// Check that the hosts of `inventory` can be reached and logged into,
+    /// when a preflight was asked for
+    pub async fn preflight(
+        &self,
+        inventory: &ParsedInventory,
+        processor: &InventoryProcessor,
+    ) -> Option<PreflightReport> {
+        match &self.preflight {
+            Some(preflight) => Some(preflight.check(inventory, processor).await),
+            None => None,
+        }
+    }
 }
 
 impl Default for InventoryValidatorSet {
@@ -1,6 +1,206 @@
+//! Variable resolution: inventory inheritance and Ansible's precedence
+//!
+//! Ansible gives a variable the value of the source with the highest
+//! precedence among those defining it, from the options of the command line
+//! up to extra variables, as [`VariablePrecedence`] orders them. The
+//! [`PrecedenceResolver`] layers the sources so, and renders the expressions
+//! of a value only when the variable is looked up, after the variables they
+//! refer to, so that the order variables are defined in does not matter.
+
 use crate::inventory::error::VariableError;
+use crate::inventory::vars_directories::DirectoryVars;
+use crate::modules::files::template_engine::AdvancedTemplateProcessor;
 use crate::types::inventory::{InventoryGroup, ParsedInventory};
-use std::collections::HashMap;
+use serde_json::Value;
+use std::cell::OnceCell;
+use std::collections::{BTreeMap, HashMap};
+
+/// The sources of variables, from the lowest precedence to the highest, as
+/// Ansible's documentation lists them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum VariablePrecedence {
+    /// Values of command line options, such as `-u user`
+    CommandLineValues,
+    /// `defaults/main.yml` of roles
+    RoleDefaults,
+    /// Group variables of an inventory file or script
+    InventoryFileGroupVars,
+    /// `group_vars/all` next to the inventory
+    InventoryGroupVarsAll,
+    /// `group_vars/all` next to the playbook
+    PlaybookGroupVarsAll,
+    /// `group_vars/*` next to the inventory
+    InventoryGroupVars,
+    /// `group_vars/*` next to the playbook
+    PlaybookGroupVars,
+    /// Host variables of an inventory file or script
+    InventoryFileHostVars,
+    /// `host_vars/*` next to the inventory
+    InventoryHostVars,
+    /// `host_vars/*` next to the playbook
+    PlaybookHostVars,
+    /// Gathered facts and cached `set_fact`s
+    HostFacts,
+    /// `vars` of a play
+    PlayVars,
+    /// `vars_prompt` of a play
+    PlayVarsPrompt,
+    /// `vars_files` of a play
+    PlayVarsFiles,
+    /// `vars/main.yml` of roles
+    RoleVars,
+    /// `vars` of a block
+    BlockVars,
+    /// `vars` of a task
+    TaskVars,
+    /// Variables loaded by `include_vars`
+    IncludeVars,
+    /// `set_fact` and registered results
+    SetFacts,
+    /// Parameters of roles and `include_role`
+    RoleParams,
+    /// Parameters of `include_tasks`
+    IncludeParams,
+    /// Extra variables, `-e` and `--vars-file`, which always win
+    ExtraVars,
+}
+
+/// Variables layered by the precedence of their sources, whose values are
+/// rendered when looked up
+pub struct PrecedenceResolver {
+    layers: BTreeMap<VariablePrecedence, HashMap<String, Value>>,
+    processor: OnceCell<AdvancedTemplateProcessor>,
+}
+
+/// Variables rendered and being rendered while resolving
+#[derive(Default)]
+struct Resolution {
+    resolved: HashMap<String, Value>,
+    stack: Vec<String>,
+}
+
+impl PrecedenceResolver {
+    pub fn new() -> Self {
+        Self {
+            layers: BTreeMap::new(),
+            processor: OnceCell::new(),
+        }
+    }
+
+    /// The processor rendering values, created on the first lookup as
+    /// merging needs none
+    fn processor(&self) -> &AdvancedTemplateProcessor {
+        self.processor
+            .get_or_init(AdvancedTemplateProcessor::default)
+    }
+
+    /// Add `variables` to the source `precedence`, over those it defines
+    /// already
+    pub fn with_layer(
+        mut self,
+        precedence: VariablePrecedence,
+        variables: HashMap<String, Value>,
+    ) -> Self {
+        self.extend(precedence, variables);
+        self
+    }
+
+    pub fn extend(&mut self, precedence: VariablePrecedence, variables: HashMap<String, Value>) {
+        self.layers.entry(precedence).or_default().extend(variables);
+    }
+
+    pub fn set(&mut self, precedence: VariablePrecedence, name: &str, value: Value) {
+        self.layers
+            .entry(precedence)
+            .or_default()
+            .insert(name.to_string(), value);
+    }
+
+    /// The unrendered value of `name` and the source it is taken from
+    pub fn get_raw(&self, name: &str) -> Option<(VariablePrecedence, &Value)> {
+        self.layers
+            .iter()
+            .rev()
+            .find_map(|(precedence, layer)| layer.get(name).map(|value| (*precedence, value)))
+    }
+
+    /// The unrendered values of all the variables
+    pub fn merged(&self) -> HashMap<String, Value> {
+        let mut merged = HashMap::new();
+        for layer in self.layers.values() {
+            merged.extend(
+                layer
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone())),
+            );
+        }
+        merged
+    }
+
+    /// The value of `name` with its expressions rendered, `None` when no
+    /// source defines it. Expressions referring to undefined variables are
+    /// left for the runner; those referring back to the variable fail.
+    pub fn resolve(&self, name: &str) -> Result<Option<Value>, VariableError> {
+        let mut resolution = Resolution::default();
+        self.resolve_in(name, &mut resolution)
+    }
+
+    /// All the variables, rendered
+    pub fn resolve_all(&self) -> Result<HashMap<String, Value>, VariableError> {
+        let mut resolution = Resolution::default();
+        let mut names: Vec<String> = self.merged().into_keys().collect();
+        names.sort();
+        for name in &names {
+            self.resolve_in(name, &mut resolution)?;
+        }
+        Ok(resolution.resolved)
+    }
+
+    fn resolve_in(
+        &self,
+        name: &str,
+        resolution: &mut Resolution,
+    ) -> Result<Option<Value>, VariableError> {
+        if let Some(value) = resolution.resolved.get(name) {
+            return Ok(Some(value.clone()));
+        }
+        let Some((_, value)) = self.get_raw(name) else {
+            return Ok(None);
+        };
+        if let Some(start) = resolution.stack.iter().position(|entry| entry == name) {
+            let mut cycle = resolution.stack[start..].to_vec();
+            cycle.push(name.to_string());
+            return Err(VariableError::CircularDependency { cycle });
+        }
+
+        resolution.stack.push(name.to_string());
+        let mut referenced: Vec<String> = self
+            .processor()
+            .referenced_variables(value)
+            .into_iter()
+            .collect();
+        referenced.sort();
+        let mut dependencies = HashMap::new();
+        for reference in referenced {
+            if let Some(dependency) = self.resolve_in(&reference, resolution)? {
+                dependencies.insert(reference, dependency);
+            }
+        }
+        resolution.stack.pop();
+
+        let rendered = self.processor().render_value(value, &dependencies);
+        resolution
+            .resolved
+            .insert(name.to_string(), rendered.clone());
+        Ok(Some(rendered))
+    }
+}
+
+impl Default for PrecedenceResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 pub struct VariableResolver;
 
@@ -10,10 +210,18 @@ impl VariableResolver {
     }
 
     pub fn resolve_variables(&self, inventory: &mut ParsedInventory) -> Result<(), VariableError> {
-        for host_name in inventory.hosts.keys().cloned().collect::<Vec<_>>() {
-            let mut resolved_vars = inventory.global_vars.clone();
+        self.resolve_variables_with(inventory, &DirectoryVars::default())
+    }
 
-            // Collect variables from all groups (in order)
+    /// Resolve the variables of the hosts of `inventory` with those of its
+    /// `group_vars/` and `host_vars/` directories, which override the
+    /// group and host variables of the inventory itself respectively
+    pub fn resolve_variables_with(
+        &self,
+        inventory: &mut ParsedInventory,
+        directory_vars: &DirectoryVars,
+    ) -> Result<(), VariableError> {
+        for host_name in inventory.hosts.keys().cloned().collect::<Vec<_>>() {
             let host =
                 inventory
                     .hosts
@@ -21,21 +229,46 @@ impl VariableResolver {
                     .ok_or_else(|| VariableError::InvalidHost {
                         host: host_name.clone(),
                     })?;
+
+            // Collect the groups of the host, each after its parents
+            let mut group_names = Vec::new();
             for group_name in &host.groups {
                 if let Some(group) = inventory.groups.get(group_name) {
-                    // Recursively resolve parent group variables
-                    Self::resolve_group_variables(group, &inventory.groups, &mut resolved_vars)?;
-
-                    // Apply group variables
-                    for (key, value) in &group.variables {
-                        resolved_vars.insert(key.clone(), value.clone());
-                    }
+                    Self::collect_parent_groups(group, &inventory.groups, &mut group_names);
+                    group_names.push(group_name.as_str());
                 }
             }
 
-            // Apply host-specific variables (highest priority)
-            for (key, value) in &host.variables {
-                resolved_vars.insert(key.clone(), value.clone());
+            let mut resolver = PrecedenceResolver::new()
+                .with_layer(
+                    VariablePrecedence::InventoryFileGroupVars,
+                    inventory.global_vars.clone(),
+                )
+                .with_layer(
+                    VariablePrecedence::InventoryFileHostVars,
+                    host.variables.clone(),
+                );
+            if let Some(vars) = directory_vars.groups.get("all") {
+                resolver.extend(VariablePrecedence::InventoryGroupVarsAll, vars.clone());
+            }
+            if let Some(vars) = directory_vars.hosts.get(&host_name) {
+                resolver.extend(VariablePrecedence::InventoryHostVars, vars.clone());
+            }
+            // Groups override the global variables, in order
+            for group_name in group_names {
+                if let Some(group) = inventory.groups.get(group_name) {
+                    resolver.extend(
+                        VariablePrecedence::InventoryFileGroupVars,
+                        group.variables.clone(),
+                    );
+                }
+                if let Some(vars) = directory_vars
+                    .groups
+                    .get(group_name)
+                    .filter(|_| group_name != "all")
+                {
+                    resolver.extend(VariablePrecedence::InventoryGroupVars, vars.clone());
+                }
             }
 
             // Update host with resolved variables
@@ -45,28 +278,24 @@ impl VariableResolver {
                 .ok_or_else(|| VariableError::InvalidHost {
                     host: host_name.clone(),
                 })?
-                .variables = resolved_vars;
+                .variables = resolver.merged();
         }
 
         Ok(())
     }
 
-    fn resolve_group_variables(
+    fn collect_parent_groups<'a>(
         group: &InventoryGroup,
-        all_groups: &HashMap<String, InventoryGroup>,
-        vars: &mut HashMap<String, serde_json::Value>,
-    ) -> Result<(), VariableError> {
-        // Recursively resolve parent group variables first
+        all_groups: &'a HashMap<String, InventoryGroup>,
+        names: &mut Vec<&'a str>,
+    ) {
+        // Recursively collect parent groups first
         for parent_name in &group.parent_groups {
-            if let Some(parent_group) = all_groups.get(parent_name) {
-                Self::resolve_group_variables(parent_group, all_groups, vars)?;
-                for (key, value) in &parent_group.variables {
-                    vars.insert(key.clone(), value.clone());
-                }
+            if let Some((name, parent_group)) = all_groups.get_key_value(parent_name) {
+                Self::collect_parent_groups(parent_group, all_groups, names);
+                names.push(name.as_str());
             }
         }
-
-        Ok(())
     }
 
     pub fn validate_no_circular_dependencies(
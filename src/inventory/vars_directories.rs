@@ -0,0 +1,136 @@
+//! `group_vars/` and `host_vars/` directories next to an inventory
+//!
+//! As Ansible does, the variables of a group are loaded from
+//! `group_vars/<group>`, and those of a host from `host_vars/<host>`, which
+//! are either a YAML or JSON file, with a `.yml`, `.yaml` or `.json`
+//! extension or none, or a directory whose files are all loaded in the
+//! order of their names. Files may be encrypted with Ansible Vault, as a
+//! whole or value by value, or with SOPS, as variables files can be; see
+//! [`VarsFileLoader`].
+
+use crate::execution::{VarsFileError, VarsFileLoader};
+use crate::runtime::SensitiveValues;
+use crate::types::inventory::ParsedInventory;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the files of variables directories; files with others,
+/// such as editor backups, are ignored
+const VARS_EXTENSIONS: &[&str] = &["yml", "yaml", "json"];
+
+/// Variables of the groups and hosts of an inventory loaded from the
+/// `group_vars/` and `host_vars/` directories next to it
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryVars {
+    pub groups: HashMap<String, HashMap<String, Value>>,
+    pub hosts: HashMap<String, HashMap<String, Value>>,
+    /// The strings of the variables that were encrypted
+    pub sensitive: SensitiveValues,
+}
+
+impl DirectoryVars {
+    /// The variables of the groups, `all` included, and hosts of
+    /// `inventory` in the directories of `root`, the directory of the
+    /// inventory
+    pub fn load(
+        root: &Path,
+        inventory: &ParsedInventory,
+        loader: &VarsFileLoader,
+    ) -> Result<Self, VarsFileError> {
+        let mut group_names: Vec<&str> = inventory.groups.keys().map(String::as_str).collect();
+        if !group_names.contains(&"all") {
+            group_names.push("all");
+        }
+
+        let mut vars = Self::default();
+        for name in group_names {
+            let group_vars =
+                load_entry(&root.join("group_vars"), name, loader, &mut vars.sensitive)?;
+            if !group_vars.is_empty() {
+                vars.groups.insert(name.to_string(), group_vars);
+            }
+        }
+        for name in inventory.hosts.keys() {
+            let host_vars = load_entry(&root.join("host_vars"), name, loader, &mut vars.sensitive)?;
+            if !host_vars.is_empty() {
+                vars.hosts.insert(name.clone(), host_vars);
+            }
+        }
+        Ok(vars)
+    }
+}
+
+/// The directory the `group_vars/` and `host_vars/` of the inventory at
+/// `path` are in: the inventory's own when it is a directory
+pub fn inventory_root(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        return path.to_path_buf();
+    }
+    path.parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .map_or_else(|| PathBuf::from("."), Path::to_path_buf)
+}
+
+/// The variables of `name` in `dir`: those of its file, with or without an
+/// extension, and those of the files of its directory
+fn load_entry(
+    dir: &Path,
+    name: &str,
+    loader: &VarsFileLoader,
+    sensitive: &mut SensitiveValues,
+) -> Result<HashMap<String, Value>, VarsFileError> {
+    let mut vars = HashMap::new();
+    let candidates = std::iter::once(dir.join(name)).chain(
+        VARS_EXTENSIONS
+            .iter()
+            .map(|ext| dir.join(format!("{name}.{ext}"))),
+    );
+    for path in candidates {
+        let files = if path.is_file() {
+            vec![path]
+        } else if path.is_dir() {
+            vars_files(&path)?
+        } else {
+            Vec::new()
+        };
+        for file in files {
+            let (file_vars, file_sensitive) = loader.load_with_sensitive(&file)?;
+            vars.extend(file_vars);
+            sensitive.extend(file_sensitive);
+        }
+    }
+    Ok(vars)
+}
+
+/// The files of variables in `dir` and its subdirectories, in the order of
+/// their paths, leaving out hidden files
+fn vars_files(dir: &Path) -> Result<Vec<PathBuf>, VarsFileError> {
+    let io_error = |source| VarsFileError::Io {
+        path: dir.display().to_string(),
+        source,
+    };
+    let mut entries = std::fs::read_dir(dir)
+        .map_err(io_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io_error)?;
+    entries.sort();
+
+    let mut files = Vec::new();
+    for path in entries {
+        let file_name = path.file_name().and_then(|name| name.to_str());
+        if file_name.is_none_or(|name| name.starts_with('.')) {
+            continue;
+        }
+        if path.is_dir() {
+            files.extend(vars_files(&path)?);
+            continue;
+        }
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        if extension.is_none_or(|ext| VARS_EXTENSIONS.contains(&ext)) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
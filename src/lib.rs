@@ -5,6 +5,7 @@
 
 #![recursion_limit = "256"]
 
+pub mod api;
 pub mod binary;
 // pub mod cli;  // Temporarily disabled to fix compilation
 pub mod compilation;
@@ -7,7 +7,7 @@ use std::{
     path::{Path, PathBuf},
 };
 use tokio::task;
-use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ZipError {
@@ -77,8 +77,12 @@ impl ZipHandler {
 
             // Check if we should keep newer files
             if options.keep_newer && dest_path.exists() {
-                let dest_mtime = dest_path.metadata()?.modified()?;
-                if let Ok(archive_mtime) = file.last_modified().to_time() {
+                let dest_mtime =
+                    chrono::DateTime::<chrono::Utc>::from(dest_path.metadata()?.modified()?)
+                        .naive_utc();
+                if let Some(Ok(archive_mtime)) =
+                    file.last_modified().map(chrono::NaiveDateTime::try_from)
+                {
                     if dest_mtime > archive_mtime {
                         continue;
                     }
@@ -153,9 +157,9 @@ impl ZipHandler {
 
         // Set compression method and level
         let compression_method = CompressionMethod::Deflated;
-        let options = FileOptions::default()
+        let options = SimpleFileOptions::default()
             .compression_method(compression_method)
-            .compression_level(compression_level.map(|l| l as i32));
+            .compression_level(compression_level.map(i64::from));
 
         for source in sources {
             if source.is_file() {
@@ -172,7 +176,7 @@ impl ZipHandler {
     fn add_file_to_zip(
         zip: &mut ZipWriter<BufWriter<File>>,
         file_path: &Path,
-        options: &FileOptions,
+        options: &SimpleFileOptions,
     ) -> Result<(), ZipError> {
         let name = file_path
             .file_name()
@@ -192,7 +196,7 @@ impl ZipHandler {
         zip: &mut ZipWriter<BufWriter<File>>,
         dir_path: &Path,
         base_path: &Path,
-        options: &FileOptions,
+        options: &SimpleFileOptions,
     ) -> Result<(), ZipError> {
         let walker = walkdir::WalkDir::new(dir_path);
 
@@ -0,0 +1,235 @@
+//! Async status module - reports on and cleans up async jobs
+//!
+//! A task executed with `async` runs in a background job whose state is kept
+//! as a JSON job file, named after the job id, in the async job directory.
+//! The file holds `started` and `finished` flags and, once finished, the
+//! outcome of the task.
+
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::modules::{
+    error::{ModuleExecutionError, ValidationError},
+    interface::{
+        ArgumentSpec, ExecutionContext, ExecutionModule, ModuleArgs, ModuleDocumentation,
+        ModuleResult, Platform, ReturnValueSpec,
+    },
+};
+
+/// Async job directory, overriding the runtime configuration
+pub const ASYNC_DIR_ENV: &str = "RUSTLE_ASYNC_DIR";
+
+/// `$RUSTLE_ASYNC_DIR`, or `.rustle_async` in the home directory
+pub fn default_async_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os(ASYNC_DIR_ENV) {
+        return PathBuf::from(dir);
+    }
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join(".rustle_async")
+}
+
+pub fn job_path(dir: &Path, jid: &str) -> PathBuf {
+    dir.join(jid)
+}
+
+/// Replace the job file of `jid` with `state`
+pub fn write_job(dir: &Path, jid: &str, state: &Map<String, Value>) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let path = job_path(dir, jid);
+    let partial = path.with_extension("tmp");
+    std::fs::write(&partial, serde_json::to_vec(state)?)?;
+    std::fs::rename(partial, path)
+}
+
+pub fn read_job(dir: &Path, jid: &str) -> std::io::Result<Map<String, Value>> {
+    let data = std::fs::read(job_path(dir, jid))?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Async status module - reports on and cleans up async jobs
+pub struct AsyncStatusModule;
+
+#[async_trait]
+impl ExecutionModule for AsyncStatusModule {
+    fn name(&self) -> &'static str {
+        "async_status"
+    }
+
+    fn version(&self) -> &'static str {
+        "1.0.0"
+    }
+
+    fn supported_platforms(&self) -> &[Platform] {
+        &[
+            Platform::Linux,
+            Platform::MacOS,
+            Platform::Windows,
+            Platform::FreeBSD,
+            Platform::OpenBSD,
+            Platform::NetBSD,
+        ]
+    }
+
+    async fn execute(
+        &self,
+        args: &ModuleArgs,
+        context: &ExecutionContext,
+    ) -> Result<ModuleResult, ModuleExecutionError> {
+        let jid = args
+            .args
+            .get("jid")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ModuleExecutionError::InvalidArgs {
+                message: "jid is required".to_string(),
+            })?;
+        let mode = args
+            .args
+            .get("mode")
+            .and_then(|v| v.as_str())
+            .unwrap_or("status");
+        let dir = context
+            .environment
+            .get(ASYNC_DIR_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(default_async_dir);
+
+        let mut results = HashMap::from([("ansible_job_id".to_string(), Value::from(jid))]);
+        if mode == "cleanup" {
+            let path = job_path(&dir, jid);
+            match std::fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+            results.insert(
+                "erased".to_string(),
+                Value::from(path.to_string_lossy().into_owned()),
+            );
+            return Ok(result(false, false, None, results));
+        }
+
+        let job = match read_job(&dir, jid) {
+            Ok(job) => job,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                results.insert("started".to_string(), Value::from(1));
+                results.insert("finished".to_string(), Value::from(1));
+                return Ok(result(
+                    false,
+                    true,
+                    Some(format!("could not find job {jid}")),
+                    results,
+                ));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let flag = |name: &str| job.get(name).and_then(Value::as_bool).unwrap_or(false);
+        let msg = job.get("msg").and_then(Value::as_str).map(str::to_string);
+        let (changed, failed) = (flag("changed"), flag("failed"));
+        let mut module_result = result(changed, failed, msg, job.clone().into_iter().collect());
+        module_result.stdout = job
+            .get("stdout")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        module_result.stderr = job
+            .get("stderr")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        module_result.rc = job
+            .get("rc")
+            .and_then(Value::as_i64)
+            .and_then(|rc| i32::try_from(rc).ok());
+        Ok(module_result)
+    }
+
+    fn validate_args(&self, args: &ModuleArgs) -> Result<(), ValidationError> {
+        if !args.args.contains_key("jid") {
+            return Err(ValidationError::MissingRequiredArg {
+                arg: "jid".to_string(),
+            });
+        }
+        match args.args.get("mode").and_then(|v| v.as_str()) {
+            None | Some("status" | "cleanup") => Ok(()),
+            Some(mode) => Err(ValidationError::InvalidArgValue {
+                arg: "mode".to_string(),
+                value: mode.to_string(),
+                reason: "must be status or cleanup".to_string(),
+            }),
+        }
+    }
+
+    async fn check_mode(
+        &self,
+        args: &ModuleArgs,
+        context: &ExecutionContext,
+    ) -> Result<ModuleResult, ModuleExecutionError> {
+        // Reading a job file changes nothing; cleaning one up is skipped
+        if args.args.get("mode").and_then(|v| v.as_str()) == Some("cleanup") {
+            return Ok(result(false, false, None, HashMap::new()));
+        }
+        self.execute(args, context).await
+    }
+
+    fn documentation(&self) -> ModuleDocumentation {
+        ModuleDocumentation {
+            description: "Obtain the status of an async task, or clean up its job".to_string(),
+            arguments: vec![
+                ArgumentSpec {
+                    name: "jid".to_string(),
+                    description: "Job id returned by the async task".to_string(),
+                    required: true,
+                    argument_type: "str".to_string(),
+                    default: None,
+                },
+                ArgumentSpec {
+                    name: "mode".to_string(),
+                    description: "status to report on the job, cleanup to remove its job file"
+                        .to_string(),
+                    required: false,
+                    argument_type: "str".to_string(),
+                    default: Some("status".to_string()),
+                },
+            ],
+            examples: vec![r#"async_status:
+    jid: "{{ sleeper.ansible_job_id }}""#
+                .to_string()],
+            return_values: vec![
+                ReturnValueSpec {
+                    name: "finished".to_string(),
+                    description: "Whether the job has finished".to_string(),
+                    returned: "always".to_string(),
+                    value_type: "int".to_string(),
+                },
+                ReturnValueSpec {
+                    name: "started".to_string(),
+                    description: "Whether the job has started".to_string(),
+                    returned: "always".to_string(),
+                    value_type: "int".to_string(),
+                },
+            ],
+        }
+    }
+}
+
+fn result(
+    changed: bool,
+    failed: bool,
+    msg: Option<String>,
+    results: HashMap<String, Value>,
+) -> ModuleResult {
+    ModuleResult {
+        changed,
+        failed,
+        msg,
+        stdout: None,
+        stderr: None,
+        rc: None,
+        results,
+        diff: None,
+        warnings: Vec::new(),
+        ansible_facts: HashMap::new(),
+    }
+}
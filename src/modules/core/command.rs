@@ -3,9 +3,11 @@
 use async_trait::async_trait;
 use std::collections::HashMap;
 use std::path::Path;
+use std::process::Stdio;
 use tokio::process::Command;
 
 use crate::modules::{
+    core::process::spawn_tree,
     error::{ModuleExecutionError, ValidationError},
     interface::{
         ArgumentSpec, ExecutionContext, ExecutionModule, ModuleArgs, ModuleDocumentation,
@@ -103,7 +105,13 @@ impl ExecutionModule for CommandModule {
             cmd.current_dir(dir);
         }
 
-        let output = cmd.output().await?;
+        // A timeout drops this future, which kills whatever the command started
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let (child, tree) = spawn_tree(&mut cmd)?;
+        let output = child.wait_with_output().await?;
+        tree.disarm();
         let stdout = String::from_utf8_lossy(&output.stdout).to_string();
         let stderr = String::from_utf8_lossy(&output.stderr).to_string();
         let rc = output.status.code().unwrap_or(-1);
@@ -1,11 +1,15 @@
 //! Core execution modules
 
+pub mod async_status;
 pub mod command;
 pub mod debug;
 pub mod package;
+pub mod process;
 pub mod service;
 
+pub use async_status::AsyncStatusModule;
 pub use command::CommandModule;
 pub use debug::DebugModule;
 pub use package::PackageModule;
+pub use process::{spawn_tree, ProcessTree};
 pub use service::ServiceModule;
@@ -0,0 +1,63 @@
+//! Termination of the process trees modules start
+//!
+//! A module future that is dropped before its command exits, as happens when
+//! the task or play times out, must not leave the command or anything it
+//! started running behind it.
+
+use tokio::process::{Child, Command};
+
+/// Start `command` as the root of its own process tree, in a new process
+/// group on Unix
+pub fn spawn_tree(command: &mut Command) -> std::io::Result<(Child, ProcessTree)> {
+    #[cfg(unix)]
+    command.process_group(0);
+    let child = command.kill_on_drop(true).spawn()?;
+    let tree = ProcessTree { pid: child.id() };
+    Ok((child, tree))
+}
+
+/// Kills the process tree of a command when dropped before being disarmed
+#[derive(Debug)]
+pub struct ProcessTree {
+    pid: Option<u32>,
+}
+
+impl ProcessTree {
+    /// The command exited, whatever it left running in the background stays
+    pub fn disarm(mut self) {
+        self.pid = None;
+    }
+}
+
+impl Drop for ProcessTree {
+    fn drop(&mut self) {
+        if let Some(pid) = self.pid.take() {
+            tracing::debug!("Killing the process tree of {}", pid);
+            kill_tree(pid);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn kill_tree(pid: u32) {
+    // The command leads its process group, whose id is its pid
+    if unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) } != 0 {
+        tracing::warn!(
+            "Failed to kill process group {}: {}",
+            pid,
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(windows)]
+fn kill_tree(pid: u32) {
+    let status = std::process::Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &pid.to_string()])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status();
+    if !matches!(status, Ok(status) if status.success()) {
+        tracing::warn!("Failed to kill process tree {}: {:?}", pid, status);
+    }
+}
@@ -0,0 +1,246 @@
+//! Custom modules found on the module search path
+//!
+//! Each directory of a [`ModuleSearchPath`] holds modules either as
+//! `<name>.rs` files or as `<name>/` directories with a `mod.rs`, the other
+//! sources next to it and an optional `module.yml` declaring the crates the
+//! module needs:
+//!
+//! ```yaml
+//! dependencies:
+//!   - name: chrono
+//!     version: "0.4"
+//!     features: [serde]
+//! ```
+//!
+//! A module is compiled into the runners of plans that use it, which call
+//! it the way they call builtin modules, so its source must define
+//! `pub async fn execute(args: HashMap<String, Value>) -> Result<Value>`.
+//! When several directories hold a module of the same name, the first one
+//! on the path wins.
+
+use crate::modules::ast_parser::AstParser;
+use crate::modules::error::ResolveError;
+use crate::modules::resolver::ModuleSourceCode;
+use crate::template::tree_shaker::builtin_module;
+use crate::template::ModuleDependency;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use syn::{File, FnArg, Item, Visibility};
+use walkdir::WalkDir;
+
+/// Environment variable listing module directories, searched after those
+/// given explicitly
+pub const MODULE_PATH_ENV: &str = "RUSTLE_MODULE_PATH";
+
+/// Manifest of a directory module, declaring the crates it needs
+pub const MODULE_MANIFEST: &str = "module.yml";
+
+/// Main file of a directory module
+const MODULE_MAIN: &str = "mod.rs";
+
+/// A module compiled into runners from its source
+#[derive(Debug, Clone)]
+pub struct CustomModule {
+    /// Name plans run it by
+    pub name: String,
+    /// File or directory it was found at
+    pub path: PathBuf,
+    /// Its main file, and for directory modules the other sources by path
+    /// relative to the directory
+    pub source: ModuleSourceCode,
+    /// Crates it declares
+    pub dependencies: Vec<ModuleDependency>,
+}
+
+impl CustomModule {
+    /// Whether it is a directory with several sources
+    pub fn is_directory(&self) -> bool {
+        self.path.is_dir()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModuleManifest {
+    #[serde(default)]
+    dependencies: Vec<ModuleDependency>,
+}
+
+/// Directories custom modules are looked up in, in order
+#[derive(Debug, Clone, Default)]
+pub struct ModuleSearchPath {
+    dirs: Vec<PathBuf>,
+}
+
+impl ModuleSearchPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dirs.push(dir.into());
+        self
+    }
+
+    pub fn with_dirs(mut self, dirs: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.dirs.extend(dirs);
+        self
+    }
+
+    /// Add the directories [`MODULE_PATH_ENV`] lists
+    pub fn with_env(self) -> Self {
+        match std::env::var_os(MODULE_PATH_ENV) {
+            Some(paths) => self.with_dirs(std::env::split_paths(&paths)),
+            None => self,
+        }
+    }
+
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+
+    /// Every module on the path, by name, each checked to provide the
+    /// interface runners call
+    pub fn discover(&self) -> Result<Vec<CustomModule>, ResolveError> {
+        let mut modules = BTreeMap::new();
+        for dir in &self.dirs {
+            if !dir.is_dir() {
+                return Err(ResolveError::FileNotFound {
+                    path: dir.display().to_string(),
+                });
+            }
+            let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)
+                .map_err(|e| io_error("read module directory", e))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<_, _>>()
+                .map_err(|e| io_error("read module directory", e))?;
+            entries.sort();
+
+            for path in entries {
+                let Some(name) = module_name(&path) else {
+                    continue;
+                };
+                if modules.contains_key(&name) {
+                    continue;
+                }
+                let module = load_module(&name, &path)?;
+                modules.insert(name, module);
+            }
+        }
+        Ok(modules.into_values().collect())
+    }
+}
+
+/// Name of the module at `path`, if it is one
+fn module_name(path: &Path) -> Option<String> {
+    let name = if path.is_dir() {
+        if !path.join(MODULE_MAIN).is_file() {
+            return None;
+        }
+        path.file_name()?
+    } else if path.extension().is_some_and(|ext| ext == "rs") {
+        path.file_stem()?
+    } else {
+        return None;
+    };
+    name.to_str().map(str::to_string)
+}
+
+fn load_module(name: &str, path: &Path) -> Result<CustomModule, ResolveError> {
+    let invalid = |reason: String| ResolveError::InvalidModule {
+        reason: format!("{}: {reason}", path.display()),
+    };
+    if syn::parse_str::<syn::Ident>(name).is_err() {
+        return Err(invalid(format!("'{name}' is not a valid module name")));
+    }
+    if builtin_module(name).is_some() {
+        return Err(invalid(format!("'{name}' is a builtin module")));
+    }
+
+    let (main_file, additional_files, dependencies) = if path.is_dir() {
+        let main_file = read(&path.join(MODULE_MAIN))?;
+        let mut additional_files = HashMap::new();
+        for entry in WalkDir::new(path).sort_by_file_name() {
+            let entry = entry.map_err(|e| ResolveError::IoError {
+                operation: "scan module directory".to_string(),
+                error: e.to_string(),
+            })?;
+            let file = entry.path();
+            let relative = file.strip_prefix(path).unwrap_or(file);
+            if entry.file_type().is_file()
+                && file.extension().is_some_and(|ext| ext == "rs")
+                && relative != Path::new(MODULE_MAIN)
+            {
+                additional_files.insert(relative.display().to_string(), read(file)?);
+            }
+        }
+
+        let manifest_path = path.join(MODULE_MANIFEST);
+        let manifest: ModuleManifest = if manifest_path.is_file() {
+            serde_yaml::from_str(&read(&manifest_path)?)
+                .map_err(|e| invalid(format!("invalid {MODULE_MANIFEST}: {e}")))?
+        } else {
+            ModuleManifest::default()
+        };
+        (main_file, additional_files, manifest.dependencies)
+    } else {
+        (read(path)?, HashMap::new(), Vec::new())
+    };
+
+    validate_module_interface(&main_file).map_err(invalid)?;
+
+    Ok(CustomModule {
+        name: name.to_string(),
+        path: path.to_path_buf(),
+        source: ModuleSourceCode {
+            main_file,
+            additional_files,
+            cargo_toml: None,
+        },
+        dependencies,
+    })
+}
+
+/// Check that `source` defines the `execute` function runners call
+pub fn validate_module_interface(source: &str) -> Result<(), String> {
+    let ast: File = syn::parse_str(source).map_err(|e| format!("invalid Rust: {e}"))?;
+    let execute = ast
+        .items
+        .iter()
+        .find_map(|item| match item {
+            Item::Fn(func) if func.sig.ident == "execute" => Some(func),
+            _ => None,
+        })
+        .ok_or("no `execute` function")?;
+
+    AstParser::new()
+        .validate_execute_signature(execute)
+        .map_err(|e| e.to_string())?;
+    if !matches!(execute.vis, Visibility::Public(_)) {
+        return Err("`execute` must be public".to_string());
+    }
+    let arguments = execute.sig.inputs.iter().collect::<Vec<_>>();
+    if !matches!(arguments.as_slice(), [FnArg::Typed(_)]) {
+        return Err("`execute` must take the module arguments only".to_string());
+    }
+    Ok(())
+}
+
+fn read(path: &Path) -> Result<String, ResolveError> {
+    std::fs::read_to_string(path).map_err(|e| ResolveError::IoError {
+        operation: format!("read {}", path.display()),
+        error: e.to_string(),
+    })
+}
+
+fn io_error(operation: &str, error: std::io::Error) -> ResolveError {
+    ResolveError::IoError {
+        operation: operation.to_string(),
+        error: error.to_string(),
+    }
+}
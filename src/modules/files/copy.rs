@@ -5,8 +5,8 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
 use tokio::fs;
-use tokio::process::Command;
 
+use crate::execution::vault::file_is_vaulted;
 use crate::modules::error::{ModuleExecutionError, ValidationError};
 use crate::modules::interface::{
     ArgumentSpec, ExecutionContext, ExecutionModule, ModuleArgs, ModuleDocumentation, ModuleResult,
@@ -19,6 +19,7 @@ use super::utils::{
     checksum::{verify_file_checksum, ChecksumAlgorithm},
     ownership::set_ownership,
     permissions::{get_permissions, set_permissions},
+    validate::{check_validate_command, validate_file},
 };
 
 /// Copy module arguments
@@ -112,6 +113,13 @@ impl CopyArgs {
 
         if let Some(validate) = args.args.get("validate") {
             copy_args.validate = validate.as_str().map(|s| s.to_string());
+            if let Some(command) = &copy_args.validate {
+                check_validate_command(command).map_err(|_| ValidationError::InvalidArgValue {
+                    arg: "validate".to_string(),
+                    value: command.clone(),
+                    reason: "validate must contain %s".to_string(),
+                })?;
+            }
         }
 
         if let Some(checksum) = args.args.get("checksum") {
@@ -219,7 +227,7 @@ impl ExecutionModule for CopyModule {
                 ArgumentSpec {
                     name: "validate".to_string(),
                     description:
-                        "Command to validate copied file (%s will be replaced with file path)"
+                        "Command validating the file before it replaces the destination (%s is replaced with its path)"
                             .to_string(),
                     required: false,
                     argument_type: "str".to_string(),
@@ -285,6 +293,16 @@ impl CopyModule {
                 message: format!("Source file does not exist: {}", args.src),
             });
         }
+        // The controller decrypts vaulted sources when it embeds them; one
+        // reaching the host as is would be copied as ciphertext
+        if file_is_vaulted(src_path) {
+            return Err(ModuleExecutionError::ExecutionFailed {
+                message: format!(
+                    "Source file {} is encrypted with Ansible Vault; it has to be decrypted on the controller",
+                    args.src
+                ),
+            });
+        }
 
         // Handle destination path based on whether it's a directory
         let dest_path = self.resolve_destination_path(src_path, original_dest_path)?;
@@ -307,41 +325,22 @@ impl CopyModule {
             }
         }
 
-        // Perform the copy operation based on source type
+        // Perform the copy operation based on source type; a file is
+        // validated before it replaces the destination
         changed = if src_path.is_dir() {
             self.copy_directory(src_path, &dest_path, args).await?
         } else {
-            self.copy_file(src_path, &dest_path, args).await?
-        };
-
-        // Run validation command if specified (only for files)
-        if let Some(validate_cmd) = &args.validate {
-            if src_path.is_file() {
-                let cmd = validate_cmd.replace("%s", &dest_path.to_string_lossy());
-                let output = Command::new("sh")
-                    .arg("-c")
-                    .arg(&cmd)
-                    .output()
-                    .await
-                    .map_err(|e| ModuleExecutionError::ExecutionFailed {
-                        message: format!("Failed to run validation command: {e}"),
-                    })?;
-
-                if !output.status.success() {
-                    return Err(ModuleExecutionError::ExecutionFailed {
-                        message: format!(
-                            "Validation command failed: {}",
-                            String::from_utf8_lossy(&output.stderr)
-                        ),
-                    });
-                }
-
+            let (copied, validation_output) = self
+                .copy_file(src_path, &dest_path, args, args.validate.as_deref())
+                .await?;
+            if let Some(output) = validation_output {
                 results.insert(
                     "validation_output".to_string(),
-                    serde_json::Value::String(String::from_utf8_lossy(&output.stdout).to_string()),
+                    serde_json::Value::String(output),
                 );
             }
-        }
+            copied
+        };
 
         results.insert(
             "src".to_string(),
@@ -489,31 +488,26 @@ impl CopyModule {
         }
     }
 
+    /// Copy a file unless the destination already has its content: whether
+    /// it was copied, and what `validate`, which the copy has to pass before
+    /// it replaces the destination, printed
     async fn copy_file(
         &self,
         src_path: &Path,
         dest_path: &Path,
         args: &CopyArgs,
-    ) -> Result<bool, ModuleExecutionError> {
+        validate: Option<&str>,
+    ) -> Result<(bool, Option<String>), ModuleExecutionError> {
         // Check if destination exists and whether we should proceed
         let dest_exists = dest_path.exists();
         if dest_exists && !args.force.unwrap_or(false) {
             // Check if files are different
             let files_different = self.files_are_different(src_path, dest_path).await?;
             if !files_different {
-                return Ok(false); // No changes needed
+                return Ok((false, None)); // No changes needed
             }
         }
 
-        // Create backup if requested and destination exists
-        if args.backup.unwrap_or(false) && dest_exists {
-            create_backup(dest_path, None).await.map_err(|e| {
-                ModuleExecutionError::ExecutionFailed {
-                    message: format!("Failed to create backup: {e}"),
-                }
-            })?;
-        }
-
         // Create destination directory if it doesn't exist
         if let Some(parent_dir) = dest_path.parent() {
             if !parent_dir.exists() {
@@ -555,6 +549,37 @@ impl CopyModule {
                 message: format!("Failed to write destination file: {e}"),
             })?;
 
+        let validation_output = match validate {
+            Some(command) => {
+                writer
+                    .flush()
+                    .await
+                    .map_err(|e| ModuleExecutionError::ExecutionFailed {
+                        message: format!("Failed to write destination file: {e}"),
+                    })?;
+                match validate_file(command, writer.temp_path()).await {
+                    Ok(output) => Some(output),
+                    Err(e) => {
+                        // The destination is left as it is
+                        let _ = writer.abort().await;
+                        return Err(ModuleExecutionError::ExecutionFailed {
+                            message: e.to_string(),
+                        });
+                    }
+                }
+            }
+            None => None,
+        };
+
+        // Create backup if requested and destination exists
+        if args.backup.unwrap_or(false) && dest_exists {
+            create_backup(dest_path, None).await.map_err(|e| {
+                ModuleExecutionError::ExecutionFailed {
+                    message: format!("Failed to create backup: {e}"),
+                }
+            })?;
+        }
+
         writer
             .commit()
             .await
@@ -592,7 +617,7 @@ impl CopyModule {
                 })?;
         }
 
-        Ok(true) // File was copied
+        Ok((true, validation_output)) // File was copied
     }
 
     async fn copy_directory(
@@ -647,7 +672,11 @@ impl CopyModule {
                 if result {
                     changed = true;
                 }
-            } else if self.copy_file(&entry_path, &dest_entry_path, args).await? {
+            } else if self
+                .copy_file(&entry_path, &dest_entry_path, args, None)
+                .await?
+                .0
+            {
                 changed = true;
             }
         }
@@ -6,6 +6,7 @@ use std::collections::HashMap;
 use std::path::Path;
 use tokio::fs;
 
+use crate::execution::is_vaulted;
 use crate::modules::error::{ModuleExecutionError, ValidationError};
 use crate::modules::interface::{
     ArgumentSpec, ExecutionContext, ExecutionModule, ModuleArgs, ModuleDocumentation, ModuleResult,
@@ -13,12 +14,20 @@ use crate::modules::interface::{
 };
 
 use super::utils::{
-    atomic::AtomicWriter, backup::create_backup, ownership::set_ownership,
+    atomic::AtomicWriter,
+    backup::create_backup,
+    ownership::set_ownership,
     permissions::set_permissions,
+    validate::{check_validate_command, validate_file},
 };
 
 // Import the advanced template processing components
-use super::template_engine::{AdvancedTemplateProcessor, TemplateError};
+use super::template_engine::jinja_environment::ansible_search_path;
+use super::template_engine::{
+    AdvancedTemplateProcessor, LookupContext, LookupRegistry, TemplateError,
+};
+use crate::runtime::{LookupConfig, SecretLookups};
+use std::sync::Arc;
 
 /// Template module arguments
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,6 +106,13 @@ impl TemplateArgs {
 
         if let Some(validate) = args.args.get("validate") {
             template_args.validate = validate.as_str().map(|s| s.to_string());
+            if let Some(command) = &template_args.validate {
+                check_validate_command(command).map_err(|_| ValidationError::InvalidArgValue {
+                    arg: "validate".to_string(),
+                    value: command.clone(),
+                    reason: "validate must contain %s".to_string(),
+                })?;
+            }
         }
 
         if let Some(variables) = args.args.get("variables") {
@@ -134,6 +150,29 @@ impl TemplateProcessor {
         self.advanced_processor
             .render_template(template_content, variables)
     }
+
+    /// Render a template, evaluating its lookups with the built-in plugins
+    /// and the secret stores the environment configures. Relative paths of
+    /// lookups are resolved against `base_dir`, the template's directory,
+    /// which is also where the templates it includes are looked up first,
+    /// before the directories of `ansible_search_path`.
+    pub async fn render_template_with_lookups(
+        &self,
+        template_content: &str,
+        variables: &serde_json::Value,
+        base_dir: &Path,
+    ) -> Result<String, TemplateError> {
+        let lookups = LookupRegistry::builtin()
+            .with_secret_lookups(Arc::new(SecretLookups::new(LookupConfig::default())));
+        self.advanced_processor
+            .render_template_with_lookups(
+                template_content,
+                variables,
+                &lookups,
+                &LookupContext::new(base_dir).with_search_path(ansible_search_path(variables)),
+            )
+            .await
+    }
 }
 
 /// Template module implementation
@@ -191,8 +230,7 @@ impl ExecutionModule for TemplateModule {
 
     fn documentation(&self) -> ModuleDocumentation {
         ModuleDocumentation {
-            description: "Process templates with variable substitution using Handlebars"
-                .to_string(),
+            description: "Render Jinja2 templates to files as Ansible does".to_string(),
             arguments: vec![
                 ArgumentSpec {
                     name: "src".to_string(),
@@ -232,7 +270,7 @@ impl ExecutionModule for TemplateModule {
                 ArgumentSpec {
                     name: "validate".to_string(),
                     description:
-                        "Command to validate generated file (%s will be replaced with file path)"
+                        "Command validating the file before it replaces the destination (%s is replaced with its path)"
                             .to_string(),
                     required: false,
                     argument_type: "str".to_string(),
@@ -299,6 +337,14 @@ impl TemplateModule {
                 message: format!("Failed to read template file: {e}"),
             }
         })?;
+        if is_vaulted(template_content.as_bytes()) {
+            return Err(ModuleExecutionError::ExecutionFailed {
+                message: format!(
+                    "Template {} is encrypted with Ansible Vault; it has to be decrypted on the controller",
+                    args.src
+                ),
+            });
+        }
 
         // Prepare template variables
         let mut template_vars = serde_json::Map::new();
@@ -338,8 +384,10 @@ impl TemplateModule {
 
         // Process template
         let processor = TemplateProcessor::new();
+        let template_dir = src_path.parent().unwrap_or_else(|| Path::new("."));
         let rendered_content = processor
-            .render_template(&template_content, &variables)
+            .render_template_with_lookups(&template_content, &variables, template_dir)
+            .await
             .map_err(|e| ModuleExecutionError::ExecutionFailed {
                 message: format!("Template rendering failed: {e}"),
             })?;
@@ -357,16 +405,6 @@ impl TemplateModule {
         };
 
         if content_changed {
-            // Create backup if requested and destination exists
-            if args.backup.unwrap_or(false) && dest_path.exists() {
-                if let Ok(Some(backup_path)) = create_backup(dest_path, None).await {
-                    results.insert(
-                        "backup_file".to_string(),
-                        serde_json::Value::String(backup_path.display().to_string()),
-                    );
-                }
-            }
-
             // Create destination directory if it doesn't exist
             if let Some(parent_dir) = dest_path.parent() {
                 if !parent_dir.exists() {
@@ -392,6 +430,41 @@ impl TemplateModule {
                     message: format!("Failed to write template output: {e}"),
                 })?;
 
+            // Validate the rendered file before it replaces the destination,
+            // which is left as it is when the validation fails
+            if let Some(validate_cmd) = &args.validate {
+                writer
+                    .flush()
+                    .await
+                    .map_err(|e| ModuleExecutionError::ExecutionFailed {
+                        message: format!("Failed to write template output: {e}"),
+                    })?;
+                match validate_file(validate_cmd, writer.temp_path()).await {
+                    Ok(output) => {
+                        results.insert(
+                            "validation_output".to_string(),
+                            serde_json::Value::String(output),
+                        );
+                    }
+                    Err(e) => {
+                        let _ = writer.abort().await;
+                        return Err(ModuleExecutionError::ExecutionFailed {
+                            message: e.to_string(),
+                        });
+                    }
+                }
+            }
+
+            // Create backup if requested and destination exists
+            if args.backup.unwrap_or(false) && dest_path.exists() {
+                if let Ok(Some(backup_path)) = create_backup(dest_path, None).await {
+                    results.insert(
+                        "backup_file".to_string(),
+                        serde_json::Value::String(backup_path.display().to_string()),
+                    );
+                }
+            }
+
             writer
                 .commit()
                 .await
@@ -420,33 +493,6 @@ impl TemplateModule {
                 })?;
         }
 
-        // Run validation command if specified
-        if let Some(validate_cmd) = &args.validate {
-            let cmd = validate_cmd.replace("%s", &dest_path.to_string_lossy());
-            let output = tokio::process::Command::new("sh")
-                .arg("-c")
-                .arg(&cmd)
-                .output()
-                .await
-                .map_err(|e| ModuleExecutionError::ExecutionFailed {
-                    message: format!("Failed to run validation command: {e}"),
-                })?;
-
-            if !output.status.success() {
-                return Err(ModuleExecutionError::ExecutionFailed {
-                    message: format!(
-                        "Validation command failed: {}",
-                        String::from_utf8_lossy(&output.stderr)
-                    ),
-                });
-            }
-
-            results.insert(
-                "validation_output".to_string(),
-                serde_json::Value::String(String::from_utf8_lossy(&output.stdout).to_string()),
-            );
-        }
-
         results.insert(
             "src".to_string(),
             serde_json::Value::String(args.src.clone()),
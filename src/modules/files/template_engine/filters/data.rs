@@ -0,0 +1,248 @@
+//! Filters of data: JSON and YAML, dictionaries, and lists as sets
+
+use super::{invalid, items, to_serde};
+use minijinja::value::{Kwargs, Rest, Value, ValueKind};
+use minijinja::Error;
+
+pub fn to_json(value: &Value, kwargs: Kwargs) -> Result<String, Error> {
+    let indent: Option<usize> = kwargs.get("indent")?;
+    let _: Option<bool> = kwargs.get("sort_keys")?;
+    kwargs.assert_all_used()?;
+    match indent {
+        Some(indent) => pretty_json(&to_serde(value)?, indent),
+        None => serde_json::to_string(&to_serde(value)?).map_err(|e| invalid(e.to_string())),
+    }
+}
+
+pub fn to_nice_json(value: &Value, kwargs: Kwargs) -> Result<String, Error> {
+    let indent: Option<usize> = kwargs.get("indent")?;
+    let _: Option<bool> = kwargs.get("sort_keys")?;
+    kwargs.assert_all_used()?;
+    pretty_json(&to_serde(value)?, indent.unwrap_or(4))
+}
+
+fn pretty_json(value: &serde_json::Value, indent: usize) -> Result<String, Error> {
+    use serde::Serialize;
+    let indent = " ".repeat(indent);
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+    let mut out = Vec::new();
+    let mut serializer = serde_json::Serializer::with_formatter(&mut out, formatter);
+    value
+        .serialize(&mut serializer)
+        .map_err(|e| invalid(e.to_string()))?;
+    String::from_utf8(out).map_err(|e| invalid(e.to_string()))
+}
+
+pub fn from_json(text: &str) -> Result<Value, Error> {
+    serde_json::from_str::<serde_json::Value>(text)
+        .map(Value::from_serialize)
+        .map_err(|e| invalid(format!("invalid JSON: {e}")))
+}
+
+pub fn to_yaml(value: &Value, kwargs: Kwargs) -> Result<String, Error> {
+    // serde_yaml always indents by two spaces
+    let _: Option<usize> = kwargs.get("indent")?;
+    let _: Option<usize> = kwargs.get("width")?;
+    kwargs.assert_all_used()?;
+    serde_yaml::to_string(&to_serde(value)?).map_err(|e| invalid(e.to_string()))
+}
+
+pub fn from_yaml(text: &str) -> Result<Value, Error> {
+    serde_yaml::from_str::<serde_json::Value>(text)
+        .map(Value::from_serialize)
+        .map_err(|e| invalid(format!("invalid YAML: {e}")))
+}
+
+pub fn combine(value: &Value, others: Rest<Value>, kwargs: Kwargs) -> Result<Value, Error> {
+    let recursive: Option<bool> = kwargs.get("recursive")?;
+    let list_merge: Option<String> = kwargs.get("list_merge")?;
+    kwargs.assert_all_used()?;
+    let recursive = recursive.unwrap_or(false);
+    let list_merge = list_merge.as_deref().unwrap_or("replace");
+
+    let mut combined = to_serde(value)?;
+    for other in others.iter() {
+        // A list of dicts is combined in order
+        let other = to_serde(other)?;
+        let others = match other {
+            serde_json::Value::Array(items) => items,
+            other => vec![other],
+        };
+        for other in others {
+            merge(&mut combined, other, recursive, list_merge)?;
+        }
+    }
+    Ok(Value::from_serialize(&combined))
+}
+
+fn merge(
+    base: &mut serde_json::Value,
+    other: serde_json::Value,
+    recursive: bool,
+    list_merge: &str,
+) -> Result<(), Error> {
+    let (serde_json::Value::Object(base), serde_json::Value::Object(other)) = (base, other) else {
+        return Err(invalid("combine expects dictionaries"));
+    };
+    for (key, value) in other {
+        match (base.get_mut(&key), value) {
+            (
+                Some(existing @ serde_json::Value::Object(_)),
+                value @ serde_json::Value::Object(_),
+            ) if recursive => {
+                merge(existing, value, recursive, list_merge)?;
+            }
+            (Some(serde_json::Value::Array(existing)), serde_json::Value::Array(value)) => {
+                match list_merge {
+                    "keep" => {}
+                    "append" => existing.extend(value),
+                    "prepend" => {
+                        let mut merged = value;
+                        merged.append(existing);
+                        *existing = merged;
+                    }
+                    "append_rp" => {
+                        existing.retain(|item| !value.contains(item));
+                        existing.extend(value);
+                    }
+                    "prepend_rp" => {
+                        existing.retain(|item| !value.contains(item));
+                        let mut merged = value;
+                        merged.append(existing);
+                        *existing = merged;
+                    }
+                    "replace" => *existing = value,
+                    other => return Err(invalid(format!("unknown list_merge '{other}'"))),
+                }
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn dict2items(value: &Value, kwargs: Kwargs) -> Result<Value, Error> {
+    let key_name: Option<String> = kwargs.get("key_name")?;
+    let value_name: Option<String> = kwargs.get("value_name")?;
+    kwargs.assert_all_used()?;
+    let serde_json::Value::Object(entries) = to_serde(value)? else {
+        return Err(invalid("dict2items expects a dictionary"));
+    };
+    let key_name = key_name.unwrap_or_else(|| "key".to_string());
+    let value_name = value_name.unwrap_or_else(|| "value".to_string());
+    let items: Vec<serde_json::Value> = entries
+        .into_iter()
+        .map(|(key, value)| {
+            let mut item = serde_json::Map::new();
+            item.insert(key_name.clone(), serde_json::Value::String(key));
+            item.insert(value_name.clone(), value);
+            serde_json::Value::Object(item)
+        })
+        .collect();
+    Ok(Value::from_serialize(&items))
+}
+
+pub fn items2dict(value: &Value, kwargs: Kwargs) -> Result<Value, Error> {
+    let key_name: Option<String> = kwargs.get("key_name")?;
+    let value_name: Option<String> = kwargs.get("value_name")?;
+    kwargs.assert_all_used()?;
+    let key_name = key_name.unwrap_or_else(|| "key".to_string());
+    let value_name = value_name.unwrap_or_else(|| "value".to_string());
+    let mut entries = serde_json::Map::new();
+    for item in items(value)? {
+        let item = to_serde(&item)?;
+        let (Some(key), Some(value)) = (item.get(&key_name), item.get(&value_name)) else {
+            return Err(invalid(format!(
+                "items2dict expects items with '{key_name}' and '{value_name}'"
+            )));
+        };
+        let key = match key {
+            serde_json::Value::String(key) => key.clone(),
+            key => key.to_string(),
+        };
+        entries.insert(key, value.clone());
+    }
+    Ok(Value::from_serialize(&entries))
+}
+
+/// `groups['web'] | map('extract', hostvars, 'ansible_host')`: the item of
+/// `container` at the key filtered, and then at `morekeys`, one key or a
+/// list of them
+pub fn extract(key: &Value, container: &Value, morekeys: Option<Value>) -> Result<Value, Error> {
+    let mut value = container.get_item(key)?;
+    let morekeys = match morekeys {
+        Some(keys) if keys.kind() == ValueKind::Seq => items(&keys)?,
+        Some(key) => vec![key],
+        None => Vec::new(),
+    };
+    for key in morekeys {
+        value = value.get_item(&key)?;
+    }
+    Ok(value)
+}
+
+/// `list | flatten(levels=n)`: the items of nested lists, down to `levels`
+/// levels when given
+pub fn flatten(value: &Value, levels: Option<usize>, kwargs: Kwargs) -> Result<Value, Error> {
+    let levels = match levels {
+        Some(levels) => Some(levels),
+        None => kwargs.get("levels")?,
+    };
+    kwargs.assert_all_used()?;
+    fn flatten_into(items: Vec<Value>, levels: Option<usize>, flat: &mut Vec<Value>) {
+        for item in items {
+            if item.kind() != ValueKind::Seq || levels == Some(0) {
+                flat.push(item);
+                continue;
+            }
+            let nested = item.try_iter().map(Iterator::collect).unwrap_or_default();
+            flatten_into(nested, levels.map(|levels| levels - 1), flat);
+        }
+    }
+    let mut flat = Vec::new();
+    flatten_into(items(value)?, levels, &mut flat);
+    Ok(Value::from(flat))
+}
+
+fn unique(values: impl IntoIterator<Item = Value>) -> Vec<Value> {
+    let mut unique: Vec<Value> = Vec::new();
+    for value in values {
+        if !unique.contains(&value) {
+            unique.push(value);
+        }
+    }
+    unique
+}
+
+pub fn difference(value: &Value, other: &Value) -> Result<Value, Error> {
+    let other = items(other)?;
+    Ok(Value::from(unique(
+        items(value)?
+            .into_iter()
+            .filter(|item| !other.contains(item)),
+    )))
+}
+
+pub fn intersect(value: &Value, other: &Value) -> Result<Value, Error> {
+    let other = items(other)?;
+    Ok(Value::from(unique(
+        items(value)?
+            .into_iter()
+            .filter(|item| other.contains(item)),
+    )))
+}
+
+pub fn union(value: &Value, other: &Value) -> Result<Value, Error> {
+    Ok(Value::from(unique(
+        items(value)?.into_iter().chain(items(other)?),
+    )))
+}
+
+pub fn symmetric_difference(value: &Value, other: &Value) -> Result<Value, Error> {
+    let (value, other) = (items(value)?, items(other)?);
+    let only_value = value.iter().filter(|item| !other.contains(item));
+    let only_other = other.iter().filter(|item| !value.contains(item));
+    Ok(Value::from(unique(only_value.chain(only_other).cloned())))
+}
@@ -0,0 +1,250 @@
+//! Filters hashing values and passwords
+
+use super::invalid;
+use crypto_box::aead::rand_core::RngCore;
+use crypto_box::aead::OsRng;
+use minijinja::value::{Kwargs, Value};
+use minijinja::Error;
+use sha2::digest::Digest;
+
+/// Alphabet of the salts and hashes of `crypt(3)`
+const CRYPT_ALPHABET: &[u8] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Rounds SHA-crypt hashes with unless told otherwise; they are then left
+/// out of the hash
+const DEFAULT_ROUNDS: u32 = 5000;
+
+/// Longest salt SHA-crypt uses
+const MAX_SALT_LENGTH: usize = 16;
+
+/// Byte orders in which SHA-crypt encodes the final digest, three bytes
+/// for every four characters
+const SHA512_ORDER: [(usize, usize, usize); 21] = [
+    (0, 21, 42),
+    (22, 43, 1),
+    (44, 2, 23),
+    (3, 24, 45),
+    (25, 46, 4),
+    (47, 5, 26),
+    (6, 27, 48),
+    (28, 49, 7),
+    (50, 8, 29),
+    (9, 30, 51),
+    (31, 52, 10),
+    (53, 11, 32),
+    (12, 33, 54),
+    (34, 55, 13),
+    (56, 14, 35),
+    (15, 36, 57),
+    (37, 58, 16),
+    (59, 17, 38),
+    (18, 39, 60),
+    (40, 61, 19),
+    (62, 20, 41),
+];
+const SHA256_ORDER: [(usize, usize, usize); 10] = [
+    (0, 10, 20),
+    (21, 1, 11),
+    (12, 22, 2),
+    (3, 13, 23),
+    (24, 4, 14),
+    (15, 25, 5),
+    (6, 16, 26),
+    (27, 7, 17),
+    (18, 28, 8),
+    (9, 19, 29),
+];
+
+fn text(value: &Value) -> String {
+    match value.as_str() {
+        Some(text) => text.to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// The hex digest of a value with `md5`, `sha1` (the default), `sha224`,
+/// `sha256`, `sha384` or `sha512`
+pub fn hash(value: &Value, algorithm: Option<&str>) -> Result<String, Error> {
+    let data = text(value);
+    Ok(match algorithm.unwrap_or("sha1") {
+        "md5" => format!("{:x}", md5::Md5::digest(&data)),
+        "sha1" => format!("{:x}", sha1::Sha1::digest(&data)),
+        "sha224" => format!("{:x}", sha2::Sha224::digest(&data)),
+        "sha256" => format!("{:x}", sha2::Sha256::digest(&data)),
+        "sha384" => format!("{:x}", sha2::Sha384::digest(&data)),
+        "sha512" => format!("{:x}", sha2::Sha512::digest(&data)),
+        other => return Err(invalid(format!("unsupported hash algorithm '{other}'"))),
+    })
+}
+
+/// The SHA-1 digest Ansible's `checksum` filter gives
+pub fn checksum(value: &Value) -> Result<String, Error> {
+    hash(value, Some("sha1"))
+}
+
+/// `password | password_hash('sha512', salt, rounds=...)`: the `crypt(3)`
+/// hash of a password, as `/etc/shadow` and the user module take it, with
+/// SHA-crypt: `sha512`, the default, or `sha256`. The salt is random unless
+/// given, in which case the hash is the same every time.
+pub fn password_hash(
+    password: &Value,
+    scheme: Option<&str>,
+    salt: Option<&str>,
+    kwargs: Kwargs,
+) -> Result<String, Error> {
+    let rounds: Option<u32> = kwargs.get("rounds")?;
+    let salt = match salt {
+        Some(salt) => Some(salt.to_string()),
+        None => kwargs.get::<Option<String>>("salt")?,
+    };
+    kwargs.assert_all_used()?;
+
+    let salt = salt.unwrap_or_else(random_salt);
+    if let Some(invalid_char) = salt.bytes().find(|byte| !CRYPT_ALPHABET.contains(byte)) {
+        return Err(invalid(format!(
+            "invalid character '{}' in salt",
+            char::from(invalid_char)
+        )));
+    }
+    let salt = &salt[..salt.len().min(MAX_SALT_LENGTH)];
+    let rounds = rounds.unwrap_or(DEFAULT_ROUNDS).clamp(1000, 999_999_999);
+    let password = text(password);
+
+    let (id, encoded) = match scheme.unwrap_or("sha512") {
+        "sha512" | "sha512_crypt" => {
+            let digest = sha_crypt::<sha2::Sha512>(password.as_bytes(), salt.as_bytes(), rounds);
+            ("6", encode(&digest, &SHA512_ORDER, (0, 0, digest[63], 2)))
+        }
+        "sha256" | "sha256_crypt" => {
+            let digest = sha_crypt::<sha2::Sha256>(password.as_bytes(), salt.as_bytes(), rounds);
+            (
+                "5",
+                encode(&digest, &SHA256_ORDER, (0, digest[31], digest[30], 3)),
+            )
+        }
+        other => return Err(invalid(format!("unsupported password scheme '{other}'"))),
+    };
+    Ok(if rounds == DEFAULT_ROUNDS {
+        format!("${id}${salt}${encoded}")
+    } else {
+        format!("${id}$rounds={rounds}${salt}${encoded}")
+    })
+}
+
+fn random_salt() -> String {
+    let mut bytes = [0u8; MAX_SALT_LENGTH];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+        .iter()
+        .map(|byte| char::from(CRYPT_ALPHABET[usize::from(byte % 64)]))
+        .collect()
+}
+
+/// `bytes` repeated up to `length` bytes
+fn repeated(bytes: &[u8], length: usize) -> Vec<u8> {
+    bytes.iter().copied().cycle().take(length).collect()
+}
+
+/// The digest of SHA-crypt, as Ulrich Drepper specified it
+fn sha_crypt<D: Digest>(password: &[u8], salt: &[u8], rounds: u32) -> Vec<u8> {
+    let alternate = D::new()
+        .chain_update(password)
+        .chain_update(salt)
+        .chain_update(password)
+        .finalize();
+
+    let mut digest = D::new().chain_update(password).chain_update(salt);
+    digest.update(repeated(&alternate, password.len()));
+    let mut length = password.len();
+    while length > 0 {
+        if length & 1 == 1 {
+            digest.update(&alternate);
+        } else {
+            digest.update(password);
+        }
+        length >>= 1;
+    }
+    let mut result = digest.finalize().to_vec();
+
+    let mut password_digest = D::new();
+    for _ in 0..password.len() {
+        password_digest.update(password);
+    }
+    let p_bytes = repeated(&password_digest.finalize(), password.len());
+
+    let mut salt_digest = D::new();
+    for _ in 0..16 + usize::from(result[0]) {
+        salt_digest.update(salt);
+    }
+    let s_bytes = repeated(&salt_digest.finalize(), salt.len());
+
+    for round in 0..rounds {
+        let mut digest = D::new();
+        if round % 2 == 1 {
+            digest.update(&p_bytes);
+        } else {
+            digest.update(&result);
+        }
+        if round % 3 != 0 {
+            digest.update(&s_bytes);
+        }
+        if round % 7 != 0 {
+            digest.update(&p_bytes);
+        }
+        if round % 2 == 1 {
+            digest.update(&result);
+        } else {
+            digest.update(&p_bytes);
+        }
+        result = digest.finalize().to_vec();
+    }
+    result
+}
+
+/// `digest` in the base64 of `crypt(3)`, in the byte order of the scheme;
+/// `last` is the final, shorter group and its number of characters
+fn encode(digest: &[u8], order: &[(usize, usize, usize)], last: (u8, u8, u8, usize)) -> String {
+    let groups = order
+        .iter()
+        .map(|&(high, mid, low)| (digest[high], digest[mid], digest[low], 4));
+    let mut encoded = String::new();
+    for (high, mid, low, chars) in groups.chain([last]) {
+        let mut word = u32::from(high) << 16 | u32::from(mid) << 8 | u32::from(low);
+        for _ in 0..chars {
+            encoded.push(char::from(CRYPT_ALPHABET[(word & 0x3f) as usize]));
+            word >>= 6;
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha_crypt_matches_the_specification() {
+        let hash = |scheme, rounds: Option<u32>| {
+            let kwargs = Kwargs::from_iter(rounds.map(|rounds| ("rounds", Value::from(rounds))));
+            password_hash(
+                &Value::from("Hello world!"),
+                Some(scheme),
+                Some("saltstring"),
+                kwargs,
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            hash("sha512", None),
+            "$6$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1"
+        );
+        assert_eq!(
+            hash("sha256", None),
+            "$5$saltstring$5B8vYYiY.CVt1RlTTf8KbXBH3hsxY/GNooZaBBGWEc5"
+        );
+        assert_eq!(
+            hash("sha512", Some(10000)),
+            "$6$rounds=10000$saltstring$buk9gc9MDdd3Z11.ZzxK8sKnFNbxNdTnCf.XHjjiTHcgFuFgkKvBQPLIaUn4Ixl3TLN8ZgCk52MPgbWjATwhH0"
+        );
+    }
+}
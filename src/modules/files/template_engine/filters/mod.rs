@@ -0,0 +1,81 @@
+//! Filters of Ansible's templating.
+//!
+//! Jinja's own filters, such as `default`, `map`, `selectattr` or `join`,
+//! are minijinja's builtins. These add the filters of Ansible playbooks
+//! use, grouped as:
+//!
+//! - [`text`]: truth values, regular expressions, base64 and paths;
+//! - [`data`]: JSON and YAML, dictionaries and lists as sets;
+//! - [`hashing`]: `hash` and `password_hash`;
+//! - [`network`]: `ipaddr`, `ipv4` and `ipv6`.
+
+mod data;
+mod hashing;
+mod network;
+mod text;
+
+use minijinja::value::{Kwargs, Value};
+use minijinja::{Environment, Error, ErrorKind};
+use regex::{Regex, RegexBuilder};
+
+/// Add the filters to `env`
+pub fn register(env: &mut Environment<'static>) {
+    env.add_filter("bool", text::to_bool);
+    env.add_filter("ternary", text::ternary);
+    env.add_filter("mandatory", text::mandatory);
+    env.add_filter("b64encode", text::b64encode);
+    env.add_filter("b64decode", text::b64decode);
+    env.add_filter("regex_replace", text::regex_replace);
+    env.add_filter("regex_search", text::regex_search);
+    env.add_filter("regex_findall", text::regex_findall);
+    env.add_filter("regex_escape", text::regex_escape);
+    env.add_filter("quote", text::quote);
+    env.add_filter("basename", text::basename);
+    env.add_filter("dirname", text::dirname);
+
+    env.add_filter("to_json", data::to_json);
+    env.add_filter("to_nice_json", data::to_nice_json);
+    env.add_filter("from_json", data::from_json);
+    env.add_filter("to_yaml", data::to_yaml);
+    env.add_filter("to_nice_yaml", data::to_yaml);
+    env.add_filter("from_yaml", data::from_yaml);
+    env.add_filter("combine", data::combine);
+    env.add_filter("dict2items", data::dict2items);
+    env.add_filter("items2dict", data::items2dict);
+    env.add_filter("extract", data::extract);
+    env.add_filter("flatten", data::flatten);
+    env.add_filter("difference", data::difference);
+    env.add_filter("intersect", data::intersect);
+    env.add_filter("union", data::union);
+    env.add_filter("symmetric_difference", data::symmetric_difference);
+
+    env.add_filter("hash", hashing::hash);
+    env.add_filter("checksum", hashing::checksum);
+    env.add_filter("password_hash", hashing::password_hash);
+
+    env.add_filter("ipaddr", network::ipaddr);
+    env.add_filter("ipv4", network::ipv4);
+    env.add_filter("ipv6", network::ipv6);
+}
+
+pub(crate) fn invalid(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidOperation, message.into())
+}
+
+pub(crate) fn to_serde(value: &Value) -> Result<serde_json::Value, Error> {
+    serde_json::to_value(value).map_err(|e| invalid(format!("cannot convert value: {e}")))
+}
+
+pub(crate) fn items(value: &Value) -> Result<Vec<Value>, Error> {
+    Ok(value.try_iter()?.collect())
+}
+
+pub(crate) fn build_regex(pattern: &str, kwargs: &Kwargs) -> Result<Regex, Error> {
+    let ignorecase: Option<bool> = kwargs.get("ignorecase")?;
+    let multiline: Option<bool> = kwargs.get("multiline")?;
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignorecase.unwrap_or(false))
+        .multi_line(multiline.unwrap_or(false))
+        .build()
+        .map_err(|e| invalid(format!("invalid regular expression '{pattern}': {e}")))
+}
@@ -0,0 +1,186 @@
+//! Filters of IP addresses and networks
+
+use super::invalid;
+use ipnet::IpNet;
+use minijinja::value::{Value, ValueKind};
+use minijinja::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// `value | ipaddr(query)`: the addresses and networks of a value, or of
+/// the items of a list, which `query` selects or turns into another value:
+///
+/// - nothing: the value when it is an address or a network;
+/// - `address`, `network`, `netmask`, `hostmask`, `broadcast`, `prefix`,
+///   `size` and `revdns`: that part of the address or network;
+/// - `host`, `net`, `private`, `public`: the value in `address/prefix`
+///   form when it is an address of a network, a network, a private or a
+///   public address;
+/// - `address/prefix`, or `host/prefix`: the value in that form;
+/// - an integer: the address of the network at that index, from its end
+///   when negative.
+///
+/// Values the query does not select give `false` and are dropped from lists,
+/// as Ansible's do.
+pub fn ipaddr(value: &Value, query: Option<Value>) -> Result<Value, Error> {
+    filter_addresses(value, query.as_ref(), None)
+}
+
+/// `ipaddr` for IPv4 addresses and networks only
+pub fn ipv4(value: &Value, query: Option<Value>) -> Result<Value, Error> {
+    filter_addresses(value, query.as_ref(), Some(4))
+}
+
+/// `ipaddr` for IPv6 addresses and networks only
+pub fn ipv6(value: &Value, query: Option<Value>) -> Result<Value, Error> {
+    filter_addresses(value, query.as_ref(), Some(6))
+}
+
+fn filter_addresses(
+    value: &Value,
+    query: Option<&Value>,
+    version: Option<u8>,
+) -> Result<Value, Error> {
+    if value.kind() == ValueKind::Seq {
+        let mut selected = Vec::new();
+        for item in value.try_iter()? {
+            let result = filter_address(&item, query, version)?;
+            if result.is_true() {
+                selected.push(result);
+            }
+        }
+        return Ok(Value::from(selected));
+    }
+    filter_address(value, query, version)
+}
+
+fn filter_address(
+    value: &Value,
+    query: Option<&Value>,
+    version: Option<u8>,
+) -> Result<Value, Error> {
+    let Some(net) = value.as_str().and_then(parse) else {
+        return Ok(Value::from(false));
+    };
+    let is_v4 = matches!(net, IpNet::V4(_));
+    if version.is_some_and(|version| (version == 4) != is_v4) {
+        return Ok(Value::from(false));
+    }
+    let address = net.addr();
+    let with_prefix = || Value::from(format!("{address}/{}", net.prefix_len()));
+    let is_network = address == net.network() && net.prefix_len() < net.max_prefix_len();
+
+    let query = match query {
+        None => return Ok(value.clone()),
+        Some(query) if query.kind() == ValueKind::Number => {
+            return nth_address(&net, i64::try_from(query.clone())?)
+        }
+        Some(query) => query.as_str().unwrap_or_default(),
+    };
+    if let Ok(index) = query.parse::<i64>() {
+        return nth_address(&net, index);
+    }
+    Ok(match query {
+        "" => value.clone(),
+        "address" => Value::from(address.to_string()),
+        "network" => Value::from(net.network().to_string()),
+        "netmask" => Value::from(net.netmask().to_string()),
+        "hostmask" => Value::from(net.hostmask().to_string()),
+        "broadcast" => match net {
+            IpNet::V4(net) if net.prefix_len() < 31 => Value::from(net.broadcast().to_string()),
+            _ => Value::from(false),
+        },
+        "prefix" => Value::from(net.prefix_len()),
+        "size" => Value::from(size(&net)),
+        "revdns" => Value::from(reverse_pointer(&address)),
+        "address/prefix" | "host/prefix" | "cidr" => with_prefix(),
+        "host" if !is_network => with_prefix(),
+        "net" | "subnet" if is_network => Value::from(net.trunc().to_string()),
+        "private" if is_private(&address) => value.clone(),
+        "public" if is_public(&address) => value.clone(),
+        "ipv4" if is_v4 => value.clone(),
+        "ipv6" if !is_v4 => value.clone(),
+        "host" | "net" | "subnet" | "private" | "public" | "ipv4" | "ipv6" => Value::from(false),
+        other => return Err(invalid(format!("unknown ipaddr query '{other}'"))),
+    })
+}
+
+/// An address, which is its own network, or an address within a network
+fn parse(text: &str) -> Option<IpNet> {
+    let text = text.trim();
+    if text.contains('/') {
+        return text.parse().ok();
+    }
+    text.parse::<IpAddr>().ok().map(IpNet::from)
+}
+
+fn size(net: &IpNet) -> u128 {
+    1u128
+        .checked_shl(u32::from(net.max_prefix_len() - net.prefix_len()))
+        .unwrap_or(u128::MAX)
+}
+
+fn nth_address(net: &IpNet, index: i64) -> Result<Value, Error> {
+    let size = size(net);
+    let offset = if index < 0 {
+        size.checked_sub(u128::from(index.unsigned_abs()))
+    } else {
+        Some(u128::from(index.unsigned_abs())).filter(|offset| *offset < size)
+    };
+    let Some(offset) = offset else {
+        return Ok(Value::from(false));
+    };
+    let address = match net.network() {
+        IpAddr::V4(network) => IpAddr::V4(Ipv4Addr::from(u32::from(network) + offset as u32)),
+        IpAddr::V6(network) => IpAddr::V6(Ipv6Addr::from(u128::from(network) + offset)),
+    };
+    Ok(Value::from(format!("{address}/{}", net.prefix_len())))
+}
+
+fn is_private(address: &IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => address.is_private(),
+        // Unique local addresses, fc00::/7
+        IpAddr::V6(address) => address.segments()[0] & 0xfe00 == 0xfc00,
+    }
+}
+
+fn is_public(address: &IpAddr) -> bool {
+    let special = match address {
+        IpAddr::V4(address) => {
+            address.is_link_local()
+                || address.is_broadcast()
+                || address.is_documentation()
+                || address.octets()[0] == 100 && address.octets()[1] & 0xc0 == 64
+        }
+        // Link-local addresses, fe80::/10
+        IpAddr::V6(address) => address.segments()[0] & 0xffc0 == 0xfe80,
+    };
+    !special
+        && !is_private(address)
+        && !address.is_loopback()
+        && !address.is_unspecified()
+        && !address.is_multicast()
+}
+
+/// The name of an address's PTR record
+fn reverse_pointer(address: &IpAddr) -> String {
+    match address {
+        IpAddr::V4(address) => {
+            let octets = address.octets();
+            format!(
+                "{}.{}.{}.{}.in-addr.arpa.",
+                octets[3], octets[2], octets[1], octets[0]
+            )
+        }
+        IpAddr::V6(address) => {
+            let nibbles: Vec<String> = address
+                .octets()
+                .iter()
+                .rev()
+                .flat_map(|byte| [byte & 0x0f, byte >> 4])
+                .map(|nibble| format!("{nibble:x}"))
+                .collect();
+            format!("{}.ip6.arpa.", nibbles.join("."))
+        }
+    }
+}
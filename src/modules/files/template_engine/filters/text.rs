@@ -0,0 +1,176 @@
+//! Filters of text: truth values, regular expressions, base64 and paths
+
+use super::{build_regex, invalid};
+use base64::Engine as _;
+use minijinja::value::{Kwargs, Rest, Value};
+use minijinja::{Error, ErrorKind};
+
+/// Ansible's truth values: `yes`, `on`, `true` and `1` in any case
+pub fn to_bool(value: &Value) -> bool {
+    match value.as_str() {
+        Some(text) => matches!(
+            text.trim().to_ascii_lowercase().as_str(),
+            "yes" | "on" | "true" | "1" | "y" | "t"
+        ),
+        None => value.is_true(),
+    }
+}
+
+pub fn ternary(
+    value: &Value,
+    true_value: Value,
+    false_value: Value,
+    none_value: Option<Value>,
+) -> Value {
+    match none_value {
+        Some(none_value) if value.is_none() => none_value,
+        _ if value.is_true() => true_value,
+        _ => false_value,
+    }
+}
+
+pub fn mandatory(value: Value, message: Option<String>) -> Result<Value, Error> {
+    if value.is_undefined() {
+        return Err(Error::new(
+            ErrorKind::UndefinedError,
+            message.unwrap_or_else(|| "Mandatory variable has not been overridden".to_string()),
+        ));
+    }
+    Ok(value)
+}
+
+pub fn b64encode(text: &str) -> String {
+    base64::engine::general_purpose::STANDARD.encode(text)
+}
+
+pub fn b64decode(text: &str) -> Result<String, Error> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(text.trim())
+        .map_err(|e| invalid(format!("invalid base64: {e}")))?;
+    String::from_utf8(bytes).map_err(|e| invalid(format!("decoded base64 is not UTF-8: {e}")))
+}
+
+/// Python's `\1` and `\g<name>` group references as the regex crate's
+fn python_replacement(replacement: &str) -> String {
+    let mut converted = String::with_capacity(replacement.len());
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '$' => converted.push_str("$$"),
+            '\\' => match chars.peek().copied() {
+                Some(digit) if digit.is_ascii_digit() => {
+                    let mut group = String::new();
+                    while let Some(digit) = chars.peek().copied().filter(char::is_ascii_digit) {
+                        group.push(digit);
+                        chars.next();
+                    }
+                    converted.push_str(&format!("${{{group}}}"));
+                }
+                Some('g') => {
+                    chars.next();
+                    if chars.peek() == Some(&'<') {
+                        chars.next();
+                        let group: String = chars.by_ref().take_while(|c| *c != '>').collect();
+                        converted.push_str(&format!("${{{group}}}"));
+                    } else {
+                        converted.push_str("\\g");
+                    }
+                }
+                Some('\\') => {
+                    chars.next();
+                    converted.push('\\');
+                }
+                _ => converted.push('\\'),
+            },
+            c => converted.push(c),
+        }
+    }
+    converted
+}
+
+pub fn regex_replace(
+    text: &str,
+    pattern: &str,
+    replacement: Option<&str>,
+    kwargs: Kwargs,
+) -> Result<String, Error> {
+    let regex = build_regex(pattern, &kwargs)?;
+    kwargs.assert_all_used()?;
+    let replacement = python_replacement(replacement.unwrap_or_default());
+    Ok(regex.replace_all(text, replacement.as_str()).into_owned())
+}
+
+/// The first match of `pattern`, or with group references such as `'\\1'`
+/// or `'\\g<name>'` the list of those groups of the first match
+pub fn regex_search(
+    text: &str,
+    pattern: &str,
+    groups: Rest<String>,
+    kwargs: Kwargs,
+) -> Result<Value, Error> {
+    let regex = build_regex(pattern, &kwargs)?;
+    kwargs.assert_all_used()?;
+    let Some(captures) = regex.captures(text) else {
+        return Ok(Value::from(()));
+    };
+    if groups.is_empty() {
+        return Ok(Value::from(&captures[0]));
+    }
+    let mut found = Vec::with_capacity(groups.len());
+    for group in groups.iter() {
+        let reference = group.strip_prefix('\\').unwrap_or(group);
+        let capture = match reference.parse::<usize>() {
+            Ok(index) => captures.get(index),
+            Err(_) => reference
+                .strip_prefix("g<")
+                .and_then(|name| name.strip_suffix('>'))
+                .and_then(|name| captures.name(name)),
+        };
+        found.push(capture.map_or(Value::from(()), |m| Value::from(m.as_str())));
+    }
+    Ok(Value::from(found))
+}
+
+pub fn regex_findall(text: &str, pattern: &str, kwargs: Kwargs) -> Result<Value, Error> {
+    let regex = build_regex(pattern, &kwargs)?;
+    kwargs.assert_all_used()?;
+    let found: Vec<Value> = regex
+        .captures_iter(text)
+        .map(|captures| match captures.len() {
+            1 => Value::from(&captures[0]),
+            2 => Value::from(captures.get(1).map_or("", |m| m.as_str())),
+            _ => Value::from(
+                captures
+                    .iter()
+                    .skip(1)
+                    .map(|m| Value::from(m.map_or("", |m| m.as_str())))
+                    .collect::<Vec<_>>(),
+            ),
+        })
+        .collect();
+    Ok(Value::from(found))
+}
+
+pub fn regex_escape(text: &str) -> String {
+    regex::escape(text)
+}
+
+pub fn quote(value: &Value) -> String {
+    let text = match value.as_str() {
+        Some(text) => text.to_string(),
+        None => value.to_string(),
+    };
+    shell_words::quote(&text).into_owned()
+}
+
+pub fn basename(path: &str) -> String {
+    path.rsplit('/').next().unwrap_or_default().to_string()
+}
+
+pub fn dirname(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) => "/".to_string(),
+        Some(at) => path[..at].to_string(),
+        None => String::new(),
+    }
+}
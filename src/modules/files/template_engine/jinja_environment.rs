@@ -0,0 +1,278 @@
+//! Jinja2 environment rendering templates the way Ansible does
+//!
+//! Templates are rendered by minijinja with Ansible's settings: the first
+//! newline after a block is removed, a trailing newline is kept, and
+//! undefined variables fail the rendering unless they are tested or given a
+//! default. Booleans are written `true` and `false`, whichever version of
+//! minijinja renders them. Python's string, list and dict methods, such as `.split()` or
+//! `.items()`, the [`filters`](super::filters) of Ansible and its tests, such
+//! as `is version('2.0', '>=')` or `is succeeded`, are available besides
+//! Jinja's own. The tests are those of the
+//! [expressions](crate::runtime::expressions) of `when` conditions.
+
+use super::filters::{self, invalid, to_serde};
+use crate::runtime::expressions;
+use minijinja::value::{Kwargs, Value, ValueKind};
+use minijinja::{Environment, Error, Output, State, UndefinedBehavior};
+use regex::Regex;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Ansible's tests, which templates share with `when`, `failed_when` and
+/// `changed_when` so that both agree on what a version or a failed result is
+const SHARED_TESTS: &[&str] = &[
+    "match",
+    "search",
+    "regex",
+    "version",
+    "version_compare",
+    "subset",
+    "superset",
+    "truthy",
+    "falsy",
+    "succeeded",
+    "success",
+    "failed",
+    "failure",
+    "changed",
+    "change",
+    "skipped",
+    "skip",
+    "finished",
+    "started",
+];
+
+/// The environment templates and variables are rendered with
+pub fn ansible_environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    env.set_keep_trailing_newline(true);
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_formatter(format_value);
+    env.set_unknown_method_callback(minijinja_contrib::pycompat::unknown_method_callback);
+
+    filters::register(&mut env);
+
+    for name in SHARED_TESTS {
+        env.add_test(*name, move |value: &Value, args: &[Value]| {
+            shared_test(name, value, args)
+        });
+    }
+    env
+}
+
+/// Write `value` as minijinja does, but for booleans in lowercase, which
+/// newer versions of minijinja capitalize as Python does
+fn format_value(out: &mut Output, state: &State, value: &Value) -> Result<(), Error> {
+    if value.kind() == ValueKind::Bool {
+        out.write_str(if value.is_true() { "true" } else { "false" })?;
+        return Ok(());
+    }
+    minijinja::escape_formatter(out, state, value)
+}
+
+/// Loader of the templates `extends`, `include` and `import` name, looked
+/// up in each directory of `search_path` in turn and normalized as the
+/// templates that name them are
+pub fn search_path_loader(
+    search_path: &[PathBuf],
+) -> impl Fn(&str) -> Result<Option<String>, Error> + Send + Sync + 'static {
+    let loaders: Vec<_> = search_path
+        .iter()
+        .map(|dir| minijinja::path_loader(dir.clone()))
+        .collect();
+    move |name| {
+        for loader in &loaders {
+            if let Some(source) = loader(name)? {
+                return Ok(Some(normalize_template(&source).into_owned()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The directories of the `ansible_search_path` variable, each after its
+/// `templates` subdirectory, which Ansible looks up the templates a
+/// template includes in after the template's own directory
+pub fn ansible_search_path(variables: &serde_json::Value) -> Vec<PathBuf> {
+    let dirs = match variables.get("ansible_search_path") {
+        Some(serde_json::Value::Array(dirs)) => {
+            dirs.iter().filter_map(|dir| dir.as_str()).collect()
+        }
+        Some(serde_json::Value::String(dir)) => vec![dir.as_str()],
+        _ => Vec::new(),
+    };
+    dirs.into_iter()
+        .flat_map(|dir| [Path::new(dir).join("templates"), PathBuf::from(dir)])
+        .collect()
+}
+
+/// The names of the templates `template` extends, includes or imports, as
+/// far as they are string literals; names computed when rendering cannot be
+/// known beforehand
+pub fn referenced_templates(template: &str) -> Vec<String> {
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    static LITERAL: OnceLock<Regex> = OnceLock::new();
+    let tag = TAG.get_or_init(|| {
+        Regex::new(r"(?s)\{%[-+]?\s*(extends|include|import|from)\s+(.*?)[-+]?%\}")
+            .expect("tag pattern is valid")
+    });
+    let literal = LITERAL.get_or_init(|| {
+        Regex::new(r#"'([^'\\]*)'|"([^"\\]*)""#).expect("literal pattern is valid")
+    });
+
+    let mut names = Vec::new();
+    for captures in tag.captures_iter(template) {
+        let body = &captures[2];
+        let mut end = 0;
+        for found in literal.captures_iter(body) {
+            let whole = found.get(0).expect("a match has a whole");
+            // `include ['a.j2', 'b.j2']` names several templates; anything
+            // but a list around literals computes the names
+            let after = body[whole.end()..].trim_start();
+            if body[end..whole.start()].contains(|c: char| c.is_alphanumeric() || c == '(')
+                || after.starts_with(['~', '+', '%', '|', '.', '['])
+            {
+                break;
+            }
+            let name = found
+                .get(1)
+                .or_else(|| found.get(2))
+                .expect("a literal has text");
+            if !names.iter().any(|known| known == name.as_str()) {
+                names.push(name.as_str().to_string());
+            }
+            end = whole.end();
+            if &captures[1] != "include" {
+                break;
+            }
+        }
+    }
+    names
+}
+
+/// `template` with the item lookups Jinja2 allows as attributes, such as
+/// `servers.0.name`, written as subscripts, `servers[0].name`, which is how
+/// minijinja reads them
+pub fn normalize_template(template: &str) -> Cow<'_, str> {
+    if !template.contains('.') {
+        return Cow::Borrowed(template);
+    }
+    let bytes = template.as_bytes();
+    let mut normalized = String::new();
+    let mut rest = 0;
+    let mut in_code = false;
+    let mut quote = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        let next = bytes.get(i + 1).copied();
+        if !in_code {
+            if byte == b'{' && next == Some(b'%') && is_raw_block(&template[i + 2..]) {
+                // Nothing is rewritten up to the end of a raw block
+                i = template[i..]
+                    .find("endraw")
+                    .and_then(|end| template[i + end..].find("%}").map(|close| i + end + close))
+                    .map_or(bytes.len(), |close| close + 2);
+                continue;
+            }
+            if byte == b'{' && matches!(next, Some(b'{') | Some(b'%')) {
+                in_code = true;
+                i += 1;
+            }
+            i += 1;
+            continue;
+        }
+        if let Some(open) = quote {
+            if byte == b'\\' {
+                i += 1;
+            } else if byte == open {
+                quote = None;
+            }
+            i += 1;
+            continue;
+        }
+        match byte {
+            b'\'' | b'"' => quote = Some(byte),
+            b'}' | b'%' if next == Some(b'}') => {
+                in_code = false;
+                i += 1;
+            }
+            b'.' if next.is_some_and(|next| next.is_ascii_digit()) && follows_value(bytes, i) => {
+                let end = bytes[i + 1..]
+                    .iter()
+                    .position(|byte| !byte.is_ascii_digit())
+                    .map_or(bytes.len(), |len| i + 1 + len);
+                if !bytes.get(end).is_some_and(|byte| is_name_byte(*byte)) {
+                    normalized.push_str(&template[rest..i]);
+                    normalized.push('[');
+                    normalized.push_str(&template[i + 1..end]);
+                    normalized.push(']');
+                    rest = end;
+                    i = end;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    if rest == 0 {
+        return Cow::Borrowed(template);
+    }
+    normalized.push_str(&template[rest..]);
+    Cow::Owned(normalized)
+}
+
+fn is_raw_block(statement: &str) -> bool {
+    let statement = statement.trim_start_matches(['-', '+']).trim_start();
+    statement.starts_with("raw")
+        && !statement[3..].starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_name_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+/// Whether the `.` at `dot` follows a value rather than the digits of a
+/// number, as in `1.5`
+fn follows_value(bytes: &[u8], dot: usize) -> bool {
+    let Some(previous) = dot.checked_sub(1).map(|i| bytes[i]) else {
+        return false;
+    };
+    if previous == b']' || previous == b')' {
+        return true;
+    }
+    if !is_name_byte(previous) {
+        return false;
+    }
+    let start = bytes[..dot]
+        .iter()
+        .rposition(|byte| !is_name_byte(*byte))
+        .map_or(0, |i| i + 1);
+    // A number is a value when it is itself an item, as the `0` of `a.0.1`
+    !bytes[start].is_ascii_digit() || start > 0 && bytes[start - 1] == b'.'
+}
+
+/// The test `name` of the expressions `when` conditions are evaluated with
+fn shared_test(name: &str, value: &Value, args: &[Value]) -> Result<bool, Error> {
+    let mut positional = Vec::new();
+    let mut named = serde_json::Map::new();
+    for arg in args {
+        if arg.is_kwargs() {
+            let kwargs = Kwargs::try_from(arg.clone())?;
+            for key in kwargs.args() {
+                named.insert(key.to_string(), to_serde(&kwargs.get::<Value>(key)?)?);
+            }
+        } else {
+            positional.push(to_serde(arg)?);
+        }
+    }
+    let value = if value.is_undefined() {
+        None
+    } else {
+        Some(to_serde(value)?)
+    };
+    expressions::apply_test(name, value, positional, named).map_err(invalid)
+}
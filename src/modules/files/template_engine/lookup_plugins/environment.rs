@@ -0,0 +1,81 @@
+//! Lookups reading the controller's environment and commands
+
+use super::{failed, option_str, LookupContext, LookupPlugin};
+use crate::runtime::LookupError;
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+
+/// `lookup('env', 'NAME')`: environment variables of the controller, or
+/// `default`, an empty string by default, when they are not set
+pub struct EnvLookup;
+
+#[async_trait]
+impl LookupPlugin for EnvLookup {
+    fn name(&self) -> &'static str {
+        "env"
+    }
+
+    async fn run(
+        &self,
+        terms: &[String],
+        options: &Map<String, Value>,
+        _context: &LookupContext,
+    ) -> Result<Vec<Value>, LookupError> {
+        let default = option_str(options, "default").unwrap_or_default();
+        Ok(terms
+            .iter()
+            .map(|name| Value::String(std::env::var(name).unwrap_or_else(|_| default.clone())))
+            .collect())
+    }
+}
+
+/// `lookup('pipe', 'command')`: the output of shell commands run on the
+/// controller in the base directory, without its trailing whitespace. A
+/// command exiting with an error fails the lookup.
+pub struct PipeLookup;
+
+#[async_trait]
+impl LookupPlugin for PipeLookup {
+    fn name(&self) -> &'static str {
+        "pipe"
+    }
+
+    async fn run(
+        &self,
+        terms: &[String],
+        _options: &Map<String, Value>,
+        context: &LookupContext,
+    ) -> Result<Vec<Value>, LookupError> {
+        let mut values = Vec::with_capacity(terms.len());
+        for command in terms {
+            let mut shell = if cfg!(windows) {
+                let mut shell = tokio::process::Command::new("cmd");
+                shell.arg("/C");
+                shell
+            } else {
+                let mut shell = tokio::process::Command::new("sh");
+                shell.arg("-c");
+                shell
+            };
+            if context.base_dir.is_dir() {
+                shell.current_dir(&context.base_dir);
+            }
+            let output = shell
+                .arg(command)
+                .output()
+                .await
+                .map_err(|e| failed(self, command, e))?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(failed(
+                    self,
+                    command,
+                    format!("{}: {}", output.status, stderr.trim()),
+                ));
+            }
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            values.push(Value::String(stdout.trim_end().to_string()));
+        }
+        Ok(values)
+    }
+}
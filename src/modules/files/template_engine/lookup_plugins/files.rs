@@ -0,0 +1,268 @@
+//! Lookups reading the controller's files
+
+use super::{failed, option_bool, option_str, parse_term, read_file, LookupContext, LookupPlugin};
+use crate::modules::files::template_engine::AdvancedTemplateProcessor;
+use crate::runtime::LookupError;
+use async_trait::async_trait;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+/// `lookup('file', 'path')`: the content of files. Trailing whitespace is
+/// stripped unless `rstrip=false`; leading whitespace with `lstrip=true`.
+pub struct FileLookup;
+
+#[async_trait]
+impl LookupPlugin for FileLookup {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn run(
+        &self,
+        terms: &[String],
+        options: &Map<String, Value>,
+        context: &LookupContext,
+    ) -> Result<Vec<Value>, LookupError> {
+        let rstrip = option_bool(options, "rstrip", true);
+        let lstrip = option_bool(options, "lstrip", false);
+        terms
+            .iter()
+            .map(|term| {
+                let (_, mut content) = read_file(self, context, term)?;
+                if rstrip {
+                    content.truncate(content.trim_end().len());
+                }
+                if lstrip {
+                    content = content.trim_start().to_string();
+                }
+                Ok(Value::String(content))
+            })
+            .collect()
+    }
+}
+
+/// `lookup('template', 'path')`: templates rendered with the variables of
+/// the context, and those passed as `template_vars`; the templates they
+/// include are looked up next to them, then along the context's search path
+pub struct TemplateLookup;
+
+#[async_trait]
+impl LookupPlugin for TemplateLookup {
+    fn name(&self) -> &'static str {
+        "template"
+    }
+
+    async fn run(
+        &self,
+        terms: &[String],
+        options: &Map<String, Value>,
+        context: &LookupContext,
+    ) -> Result<Vec<Value>, LookupError> {
+        let mut variables: Map<String, Value> = context
+            .variables
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        if let Some(Value::Object(extra)) = options.get("template_vars") {
+            variables.extend(extra.clone());
+        }
+        let variables = Value::Object(variables);
+        let processor =
+            AdvancedTemplateProcessor::new().map_err(|e| failed(self, "", e.to_string()))?;
+        terms
+            .iter()
+            .map(|term| {
+                let (path, template) = read_file(self, context, term)?;
+                let mut search_path = context.template_search_path();
+                if let Some(dir) = path.parent() {
+                    search_path.insert(0, dir.to_path_buf());
+                }
+                processor
+                    .render_template_with_search_path(&template, &variables, &search_path)
+                    .map(Value::String)
+                    .map_err(|e| failed(self, term, e))
+            })
+            .collect()
+    }
+}
+
+/// `lookup('csvfile', 'key file=users.csv delimiter=, col=1')`: the field
+/// in column `col` (from 0) of the row whose first field is `key`, or
+/// `default`. The delimiter defaults to a tab, the file to `ansible.csv`.
+pub struct CsvFileLookup;
+
+#[async_trait]
+impl LookupPlugin for CsvFileLookup {
+    fn name(&self) -> &'static str {
+        "csvfile"
+    }
+
+    async fn run(
+        &self,
+        terms: &[String],
+        options: &Map<String, Value>,
+        context: &LookupContext,
+    ) -> Result<Vec<Value>, LookupError> {
+        let mut values = Vec::with_capacity(terms.len());
+        for term in terms {
+            let (key, options) = parse_term(term, options);
+            let file = option_str(&options, "file").unwrap_or_else(|| "ansible.csv".to_string());
+            let delimiter = match option_str(&options, "delimiter").as_deref() {
+                None | Some("TAB") | Some("\\t") | Some("t") => '\t',
+                Some(delimiter) => {
+                    let mut chars = delimiter.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(delimiter), None) => delimiter,
+                        _ => return Err(failed(self, term, "the delimiter must be one character")),
+                    }
+                }
+            };
+            let column = match option_str(&options, "col") {
+                Some(column) => column
+                    .parse::<usize>()
+                    .map_err(|_| failed(self, term, format!("invalid column '{column}'")))?,
+                None => 1,
+            };
+
+            let (_, content) = read_file(self, context, &file)?;
+            let found = content
+                .lines()
+                .map(|line| csv_fields(line, delimiter))
+                .find(|fields| fields.first() == Some(&key));
+            let value = match found {
+                Some(fields) => fields
+                    .get(column)
+                    .map(|field| Value::String(field.clone()))
+                    .ok_or_else(|| {
+                        failed(
+                            self,
+                            term,
+                            format!("the row of '{key}' has no column {column}"),
+                        )
+                    })?,
+                None => options.get("default").cloned().unwrap_or(Value::Null),
+            };
+            values.push(value);
+        }
+        Ok(values)
+    }
+}
+
+/// The fields of a CSV line, which may be quoted with `"`
+fn csv_fields(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// `lookup('ini', 'key section=db file=app.ini')`: the value of `key` in
+/// `section` (`global` by default) of an INI file, `ansible.ini` by
+/// default, or in a Java properties file with `type=properties`. With
+/// `re=true` the key is a regular expression and every value whose key it
+/// matches is returned. A missing key gives `default`, an empty string by
+/// default.
+pub struct IniLookup;
+
+#[async_trait]
+impl LookupPlugin for IniLookup {
+    fn name(&self) -> &'static str {
+        "ini"
+    }
+
+    async fn run(
+        &self,
+        terms: &[String],
+        options: &Map<String, Value>,
+        context: &LookupContext,
+    ) -> Result<Vec<Value>, LookupError> {
+        let mut values = Vec::with_capacity(terms.len());
+        for term in terms {
+            let (key, options) = parse_term(term, options);
+            let properties = option_str(&options, "type").as_deref() == Some("properties");
+            let default_file = if properties {
+                "ansible.properties"
+            } else {
+                "ansible.ini"
+            };
+            let file = option_str(&options, "file").unwrap_or_else(|| default_file.to_string());
+            let section = option_str(&options, "section").unwrap_or_else(|| "global".to_string());
+            let case_sensitive = option_bool(&options, "case_sensitive", false);
+
+            let (_, content) = read_file(self, context, &file)?;
+            let entries = ini_entries(&content, properties);
+            let in_section = |entry_section: &Option<String>| {
+                properties
+                    || entry_section.as_deref().is_some_and(|name| {
+                        name == section || !case_sensitive && name.eq_ignore_ascii_case(&section)
+                    })
+            };
+
+            if option_bool(&options, "re", false) {
+                let pattern =
+                    Regex::new(&format!("^(?:{key})$")).map_err(|e| failed(self, term, e))?;
+                values.extend(
+                    entries
+                        .iter()
+                        .filter(|(entry_section, name, _)| {
+                            in_section(entry_section) && pattern.is_match(name)
+                        })
+                        .map(|(_, _, value)| Value::String(value.clone())),
+                );
+                continue;
+            }
+            let found = entries.iter().find(|(entry_section, name, _)| {
+                in_section(entry_section)
+                    && (*name == key || !case_sensitive && name.eq_ignore_ascii_case(&key))
+            });
+            values.push(match found {
+                Some((_, _, value)) => Value::String(value.clone()),
+                None => options
+                    .get("default")
+                    .cloned()
+                    .unwrap_or_else(|| Value::String(String::new())),
+            });
+        }
+        Ok(values)
+    }
+}
+
+/// The `(section, key, value)` entries of an INI or properties file
+fn ini_entries(content: &str, properties: bool) -> Vec<(Option<String>, String, String)> {
+    let mut entries = Vec::new();
+    let mut section = None;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if !properties && line.starts_with('[') && line.ends_with(']') {
+            section = Some(line[1..line.len() - 1].trim().to_string());
+            continue;
+        }
+        let separator = line.find(['=', ':']);
+        let (name, value) = match separator {
+            Some(at) => (&line[..at], &line[at + 1..]),
+            None => (line, ""),
+        };
+        entries.push((
+            section.clone(),
+            name.trim().to_string(),
+            value.trim().to_string(),
+        ));
+    }
+    entries
+}
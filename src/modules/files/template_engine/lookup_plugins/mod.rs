@@ -0,0 +1,746 @@
+//! Lookup plugins.
+//!
+//! `lookup('plugin', terms..., option=value)` calls a lookup plugin from a
+//! template, a task argument or a variable definition. It returns the value
+//! the plugin finds for its terms; when there are several, strings are
+//! joined with commas and other values returned as a list. `query()`, or
+//! `q()`, always returns the list. Two options apply to every plugin:
+//! `wantlist=true` makes `lookup()` return the list too, and `errors`
+//! decides whether a failing lookup fails (`strict`, the default), or
+//! returns nothing with (`warn`) or without (`ignore`) a warning.
+//!
+//! Each plugin says on which side of the deployment it is evaluated:
+//!
+//! - [`LookupSide::Controller`]: the built-in `file`, `env`, `pipe`,
+//!   `password`, `template`, `url`, `csvfile` and `ini` plugins, as in
+//!   Ansible, read the controller's files, environment and network when it
+//!   processes the plan. Relative paths are resolved against
+//!   [`LookupContext::base_dir`], and nothing they read reaches a target
+//!   except through the values they return.
+//! - [`LookupSide::Target`]: the secret store plugins of
+//!   [`crate::runtime::lookups`] are left for runners to evaluate on their
+//!   targets, unless the controller is asked to evaluate them as well.
+//!
+//! Values returned by sensitive plugins, such as `password` or the secret
+//! stores, are collected in a [`SensitiveValues`] to keep them out of the
+//! plans embedded in runners.
+
+mod environment;
+mod files;
+mod password;
+mod url;
+
+pub use environment::{EnvLookup, PipeLookup};
+pub use files::{CsvFileLookup, FileLookup, IniLookup, TemplateLookup};
+pub use password::PasswordLookup;
+pub use url::UrlLookup;
+
+use crate::runtime::expressions;
+use crate::runtime::lookups::SECRET_STORE_LOOKUPS;
+use crate::runtime::{LookupError, SecretLookups, SensitiveValues};
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Prefix of the fully qualified names of the built-in plugins
+const BUILTIN_PREFIX: &str = "ansible.builtin.";
+
+/// Prefix of the variables lookups are bound to while an expression that
+/// calls them is evaluated
+const PLACEHOLDER_PREFIX: &str = "__lookup_";
+
+/// Where a lookup plugin is evaluated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupSide {
+    /// By the controller, when it processes the plan
+    Controller,
+    /// By runners on their targets, just before a task runs
+    Target,
+}
+
+/// What lookups are evaluated against
+#[derive(Debug, Clone)]
+pub struct LookupContext {
+    /// Directory relative paths are resolved against
+    pub base_dir: PathBuf,
+    /// Variables the arguments of lookups and rendered templates refer to
+    pub variables: HashMap<String, Value>,
+    /// Directories templates are looked up in after the base directory
+    pub search_path: Vec<PathBuf>,
+}
+
+impl LookupContext {
+    pub fn new(base_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            base_dir: base_dir.into(),
+            variables: HashMap::new(),
+            search_path: Vec::new(),
+        }
+    }
+
+    /// A context resolving relative paths against the working directory
+    pub fn current_dir() -> Self {
+        Self::new(std::env::current_dir().unwrap_or_default())
+    }
+
+    pub fn with_variables(mut self, variables: HashMap<String, Value>) -> Self {
+        self.variables = variables;
+        self
+    }
+
+    pub fn with_search_path(mut self, search_path: Vec<PathBuf>) -> Self {
+        self.search_path = search_path;
+        self
+    }
+
+    /// Where the templates rendered in this context look up the templates
+    /// they include: the base directory, then the search path
+    pub fn template_search_path(&self) -> Vec<PathBuf> {
+        std::iter::once(self.base_dir.clone())
+            .chain(self.search_path.iter().cloned())
+            .collect()
+    }
+
+    /// `path` resolved against the base directory, with `~` expanded
+    pub fn path(&self, path: &str) -> PathBuf {
+        let expanded = match path.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir().map(|home| home.join(rest)),
+            None => None,
+        };
+        let path = expanded.unwrap_or_else(|| PathBuf::from(path));
+        if path.is_absolute() {
+            path
+        } else {
+            self.base_dir.join(path)
+        }
+    }
+}
+
+/// A lookup plugin, called as `lookup('name', ...)`
+#[async_trait]
+pub trait LookupPlugin: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Other names the plugin answers to
+    fn aliases(&self) -> &[&'static str] {
+        &[]
+    }
+
+    fn side(&self) -> LookupSide {
+        LookupSide::Controller
+    }
+
+    /// Whether the values the plugin returns are secrets
+    fn sensitive(&self) -> bool {
+        false
+    }
+
+    /// The values for `terms`, given the `options` the call passed by name
+    async fn run(
+        &self,
+        terms: &[String],
+        options: &Map<String, Value>,
+        context: &LookupContext,
+    ) -> Result<Vec<Value>, LookupError>;
+}
+
+/// The lookup plugins calls are resolved with
+#[derive(Clone, Default)]
+pub struct LookupRegistry {
+    plugins: HashMap<String, Arc<dyn LookupPlugin>>,
+}
+
+impl std::fmt::Debug for LookupRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut names: Vec<_> = self.plugins.keys().collect();
+        names.sort();
+        f.debug_struct("LookupRegistry")
+            .field("plugins", &names)
+            .finish()
+    }
+}
+
+impl LookupRegistry {
+    /// A registry without plugins
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry with the plugins the controller evaluates
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(Arc::new(FileLookup));
+        registry.register(Arc::new(EnvLookup));
+        registry.register(Arc::new(PipeLookup));
+        registry.register(Arc::new(PasswordLookup));
+        registry.register(Arc::new(TemplateLookup));
+        registry.register(Arc::new(UrlLookup::new()));
+        registry.register(Arc::new(CsvFileLookup));
+        registry.register(Arc::new(IniLookup));
+        registry
+    }
+
+    /// Add the secret store plugins, which fetch through `lookups`
+    pub fn with_secret_lookups(mut self, lookups: Arc<SecretLookups>) -> Self {
+        for (name, aliases) in SECRET_STORE_LOOKUPS {
+            self.register(Arc::new(SecretStoreLookup {
+                name,
+                aliases,
+                lookups: Arc::clone(&lookups),
+            }));
+        }
+        self
+    }
+
+    /// Add `plugin`, replacing any plugin of the same name or alias
+    pub fn register(&mut self, plugin: Arc<dyn LookupPlugin>) {
+        for alias in plugin.aliases() {
+            self.plugins.insert(alias.to_string(), Arc::clone(&plugin));
+        }
+        self.plugins.insert(plugin.name().to_string(), plugin);
+    }
+
+    /// The plugin `name` answers to, which may be fully qualified
+    pub fn plugin(&self, name: &str) -> Result<Arc<dyn LookupPlugin>, LookupError> {
+        self.plugins
+            .get(name)
+            .or_else(|| {
+                name.strip_prefix(BUILTIN_PREFIX)
+                    .and_then(|short| self.plugins.get(short))
+            })
+            .cloned()
+            .ok_or_else(|| LookupError::UnknownPlugin {
+                plugin: name.to_string(),
+            })
+    }
+
+    /// Call the plugin `name` as `lookup()` does, or as `query()` does when
+    /// `query` is set. What a sensitive plugin returns is added to
+    /// `sensitive`.
+    pub async fn call(
+        &self,
+        name: &str,
+        query: bool,
+        terms: &[Value],
+        mut options: Map<String, Value>,
+        context: &LookupContext,
+        sensitive: &mut SensitiveValues,
+    ) -> Result<Value, LookupError> {
+        let plugin = self.plugin(name)?;
+        let wantlist = query || options.remove("wantlist").is_some_and(|v| truthy(&v));
+        let errors = options
+            .remove("errors")
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_else(|| "strict".to_string());
+        let terms: Vec<String> = terms.iter().flat_map(term_strings).collect();
+
+        let values = match plugin.run(&terms, &options, context).await {
+            Ok(values) => values,
+            Err(error) => {
+                match errors.as_str() {
+                    "ignore" => {}
+                    "warn" => warn!("{error}"),
+                    _ => return Err(error),
+                }
+                return Ok(if wantlist {
+                    Value::Array(Vec::new())
+                } else {
+                    Value::Null
+                });
+            }
+        };
+        if plugin.sensitive() {
+            values.iter().for_each(|value| sensitive.insert_all(value));
+        }
+        Ok(join_values(values, wantlist))
+    }
+
+    /// `value` with the lookups in its `{{ }}` expressions evaluated, as far
+    /// as their plugins are evaluated on one of `sides`. A string that is a
+    /// single expression takes its value as is; expressions within text are
+    /// replaced by their value as text. Expressions calling a plugin of
+    /// another side are left as they are.
+    pub async fn evaluate(
+        &self,
+        value: &Value,
+        context: &LookupContext,
+        sides: &[LookupSide],
+        sensitive: &mut SensitiveValues,
+    ) -> Result<Value, LookupError> {
+        Ok(match value {
+            Value::String(text) => self.evaluate_str(text, context, sides, sensitive).await?,
+            Value::Array(items) => {
+                let mut evaluated = Vec::with_capacity(items.len());
+                for item in items {
+                    evaluated.push(Box::pin(self.evaluate(item, context, sides, sensitive)).await?);
+                }
+                Value::Array(evaluated)
+            }
+            Value::Object(entries) => {
+                let mut evaluated = Map::new();
+                for (key, item) in entries {
+                    let item = Box::pin(self.evaluate(item, context, sides, sensitive)).await?;
+                    evaluated.insert(key.clone(), item);
+                }
+                Value::Object(evaluated)
+            }
+            other => other.clone(),
+        })
+    }
+
+    async fn evaluate_str(
+        &self,
+        text: &str,
+        context: &LookupContext,
+        sides: &[LookupSide],
+        sensitive: &mut SensitiveValues,
+    ) -> Result<Value, LookupError> {
+        if !text.contains("{{") {
+            return Ok(Value::String(text.to_string()));
+        }
+        let mut rendered = String::with_capacity(text.len());
+        let mut rest = 0;
+        for (start, end) in expression_blocks(text, "{{", "}}") {
+            let source = text[start + 2..end - 2].trim();
+            let mut bound = Map::new();
+            let Some(rewritten) = self
+                .bind_calls(source, context, sides, &mut bound, sensitive)
+                .await?
+            else {
+                continue;
+            };
+            if bound.is_empty() {
+                continue;
+            }
+            let value = match bound.get(rewritten.trim()) {
+                Some(value) => value.clone(),
+                None => {
+                    let lookup = |name: &str| {
+                        bound
+                            .get(name)
+                            .or_else(|| context.variables.get(name))
+                            .cloned()
+                    };
+                    expressions::evaluate(&rewritten, &lookup).map_err(|reason| {
+                        LookupError::Expression {
+                            expression: source.to_string(),
+                            reason,
+                        }
+                    })?
+                }
+            };
+            if text[..start].trim().is_empty() && text[end..].trim().is_empty() {
+                return Ok(value);
+            }
+            rendered.push_str(&text[rest..start]);
+            match value {
+                Value::String(value) => rendered.push_str(&value),
+                value => rendered.push_str(&value.to_string()),
+            }
+            rest = end;
+        }
+        rendered.push_str(&text[rest..]);
+        Ok(Value::String(rendered))
+    }
+
+    /// `template` with the lookups in its expressions and statements
+    /// replaced by variables, and the values those variables are bound to.
+    /// Plugins of both sides are evaluated, since a template is rendered
+    /// where it is evaluated.
+    pub async fn bind_template_lookups(
+        &self,
+        template: &str,
+        context: &LookupContext,
+        sensitive: &mut SensitiveValues,
+    ) -> Result<(String, Map<String, Value>), LookupError> {
+        let sides = [LookupSide::Controller, LookupSide::Target];
+        let mut blocks: Vec<(usize, usize)> = expression_blocks(template, "{{", "}}");
+        blocks.extend(expression_blocks(template, "{%", "%}"));
+        blocks.sort_unstable();
+
+        let mut bound = Map::new();
+        let mut rewritten = String::with_capacity(template.len());
+        let mut rest = 0;
+        for (start, end) in blocks {
+            let source = &template[start + 2..end - 2];
+            if let Some(block) = self
+                .bind_calls(source, context, &sides, &mut bound, sensitive)
+                .await?
+            {
+                rewritten.push_str(&template[rest..start + 2]);
+                rewritten.push_str(&block);
+                rest = end - 2;
+            }
+        }
+        rewritten.push_str(&template[rest..]);
+        Ok((rewritten, bound))
+    }
+
+    /// `source` with each lookup it calls replaced by a variable bound in
+    /// `bound` to its value, or `None` when it calls a plugin evaluated on
+    /// none of `sides`
+    async fn bind_calls(
+        &self,
+        source: &str,
+        context: &LookupContext,
+        sides: &[LookupSide],
+        bound: &mut Map<String, Value>,
+        sensitive: &mut SensitiveValues,
+    ) -> Result<Option<String>, LookupError> {
+        let calls = find_calls(source);
+        if calls.is_empty() {
+            return Ok(Some(source.to_string()));
+        }
+        let mut rewritten = String::with_capacity(source.len());
+        let mut rest = 0;
+        for call in calls {
+            // Lookups passed to the call are bound first
+            let Some(arguments) = Box::pin(self.bind_calls(
+                &source[call.arguments.clone()],
+                context,
+                sides,
+                bound,
+                sensitive,
+            ))
+            .await?
+            else {
+                return Ok(None);
+            };
+            if let Some(name) = plugin_literal(&arguments) {
+                if !sides.contains(&self.plugin(&name)?.side()) {
+                    return Ok(None);
+                }
+            }
+            let lookup = |name: &str| {
+                bound
+                    .get(name)
+                    .or_else(|| context.variables.get(name))
+                    .cloned()
+            };
+            let (mut terms, options) = expressions::evaluate_arguments(&arguments, &lookup)
+                .map_err(|reason| LookupError::Expression {
+                    expression: source[call.start..call.end].to_string(),
+                    reason,
+                })?;
+            if terms.is_empty() {
+                return Err(LookupError::Expression {
+                    expression: source[call.start..call.end].to_string(),
+                    reason: "no lookup plugin named".to_string(),
+                });
+            }
+            let name = match terms.remove(0) {
+                Value::String(name) => name,
+                other => other.to_string(),
+            };
+            if !sides.contains(&self.plugin(&name)?.side()) {
+                return Ok(None);
+            }
+            let value = self
+                .call(&name, call.query, &terms, options, context, sensitive)
+                .await?;
+
+            let placeholder = format!("{PLACEHOLDER_PREFIX}{}", bound.len());
+            bound.insert(placeholder.clone(), value);
+            rewritten.push_str(&source[rest..call.start]);
+            rewritten.push_str(&placeholder);
+            rest = call.end;
+        }
+        rewritten.push_str(&source[rest..]);
+        Ok(Some(rewritten))
+    }
+}
+
+/// A secret store plugin of [`crate::runtime::lookups`]
+struct SecretStoreLookup {
+    name: &'static str,
+    aliases: &'static [&'static str],
+    lookups: Arc<SecretLookups>,
+}
+
+#[async_trait]
+impl LookupPlugin for SecretStoreLookup {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn aliases(&self) -> &[&'static str] {
+        self.aliases
+    }
+
+    fn side(&self) -> LookupSide {
+        LookupSide::Target
+    }
+
+    fn sensitive(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        terms: &[String],
+        _options: &Map<String, Value>,
+        _context: &LookupContext,
+    ) -> Result<Vec<Value>, LookupError> {
+        let mut values = Vec::with_capacity(terms.len());
+        for term in terms {
+            values.push(self.lookups.lookup(self.name, term).await?);
+        }
+        Ok(values)
+    }
+}
+
+/// A `lookup()`, `query()` or `q()` call within an expression
+struct Call {
+    start: usize,
+    end: usize,
+    query: bool,
+    /// What is between the parentheses
+    arguments: std::ops::Range<usize>,
+}
+
+/// The calls of lookups in `source` that are not within another call or a
+/// string
+fn find_calls(source: &str) -> Vec<Call> {
+    let bytes = source.as_bytes();
+    let mut calls = Vec::new();
+    let mut quote = None;
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let byte = bytes[pos];
+        if let Some(open) = quote {
+            if byte == b'\\' {
+                pos += 1;
+            } else if byte == open {
+                quote = None;
+            }
+            pos += 1;
+            continue;
+        }
+        if byte == b'\'' || byte == b'"' {
+            quote = Some(byte);
+            pos += 1;
+            continue;
+        }
+        let at_word = pos == 0 || !is_name_byte(bytes[pos - 1]) && bytes[pos - 1] != b'.';
+        let function = ["lookup(", "query(", "q("]
+            .into_iter()
+            .find(|function| source[pos..].starts_with(function));
+        match function {
+            Some(function) if at_word => {
+                let open = pos + function.len();
+                let Some(close) = closing_parenthesis(bytes, open) else {
+                    break;
+                };
+                calls.push(Call {
+                    start: pos,
+                    end: close + 1,
+                    query: function != "lookup(",
+                    arguments: open..close,
+                });
+                pos = close + 1;
+            }
+            _ => pos += 1,
+        }
+    }
+    calls
+}
+
+fn is_name_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+/// Position of the parenthesis closing the one before `from`
+fn closing_parenthesis(bytes: &[u8], from: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut pos = from;
+    while pos < bytes.len() {
+        let byte = bytes[pos];
+        match quote {
+            Some(_) if byte == b'\\' => pos += 1,
+            Some(open) if byte == open => quote = None,
+            Some(_) => {}
+            None => match byte {
+                b'\'' | b'"' => quote = Some(byte),
+                b'(' | b'[' | b'{' => depth += 1,
+                b')' if depth == 0 => return Some(pos),
+                b')' | b']' | b'}' => depth -= 1,
+                _ => {}
+            },
+        }
+        pos += 1;
+    }
+    None
+}
+
+/// The spans of the `open ... close` blocks of `text`
+fn expression_blocks(text: &str, open: &str, close: &str) -> Vec<(usize, usize)> {
+    let mut blocks = Vec::new();
+    let mut from = 0;
+    while let Some(start) = text[from..].find(open).map(|start| from + start) {
+        let Some(end) = text[start + open.len()..]
+            .find(close)
+            .map(|end| start + open.len() + end + close.len())
+        else {
+            break;
+        };
+        blocks.push((start, end));
+        from = end;
+    }
+    blocks
+}
+
+/// The plugin of call arguments that name it with a string literal
+fn plugin_literal(arguments: &str) -> Option<String> {
+    let arguments = arguments.trim_start();
+    let quote = arguments
+        .chars()
+        .next()
+        .filter(|c| *c == '\'' || *c == '"')?;
+    let rest = &arguments[1..];
+    let name = &rest[..rest.find(quote)?];
+    (!name.contains('\\')).then(|| name.to_string())
+}
+
+/// The strings a term stands for: lists are flattened
+fn term_strings(term: &Value) -> Vec<String> {
+    match term {
+        Value::String(term) => vec![term.clone()],
+        Value::Array(items) => items.iter().flat_map(term_strings).collect(),
+        Value::Null => Vec::new(),
+        other => vec![other.to_string()],
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::String(text) => matches!(text.to_lowercase().as_str(), "true" | "yes" | "1"),
+        other => expressions::truthy(other),
+    }
+}
+
+/// What `lookup()` returns for `values`: strings joined with commas, a
+/// single value as is, or the list of them
+fn join_values(values: Vec<Value>, wantlist: bool) -> Value {
+    if wantlist {
+        return Value::Array(values);
+    }
+    if values.iter().all(Value::is_string) {
+        let strings: Vec<&str> = values.iter().filter_map(Value::as_str).collect();
+        return Value::String(strings.join(","));
+    }
+    match <[Value; 1]>::try_from(values) {
+        Ok([value]) => value,
+        Err(values) => Value::Array(values),
+    }
+}
+
+/// The failure of the lookup `term` of `plugin`
+fn failed(plugin: &dyn LookupPlugin, term: &str, reason: impl std::fmt::Display) -> LookupError {
+    LookupError::Failed {
+        plugin: plugin.name().to_string(),
+        term: term.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// The key of a term of the form `key option=value ...` and its options,
+/// to which the options the call passed by name are added
+fn parse_term(term: &str, options: &Map<String, Value>) -> (String, Map<String, Value>) {
+    let mut key = Vec::new();
+    let mut parsed = options.clone();
+    let words = shell_words::split(term)
+        .unwrap_or_else(|_| term.split_whitespace().map(str::to_string).collect());
+    for word in words {
+        match word.split_once('=') {
+            Some((name, value))
+                if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+            {
+                parsed
+                    .entry(name.to_string())
+                    .or_insert_with(|| Value::String(value.to_string()));
+            }
+            _ => key.push(word),
+        }
+    }
+    (key.join(" "), parsed)
+}
+
+fn option_str(options: &Map<String, Value>, name: &str) -> Option<String> {
+    options.get(name).and_then(|value| match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    })
+}
+
+fn option_bool(options: &Map<String, Value>, name: &str, default: bool) -> bool {
+    options.get(name).map_or(default, truthy)
+}
+
+fn read_file(
+    plugin: &dyn LookupPlugin,
+    context: &LookupContext,
+    path: &str,
+) -> Result<(PathBuf, String), LookupError> {
+    let resolved = context.path(path);
+    let content = std::fs::read_to_string(&resolved).map_err(|e| {
+        failed(
+            plugin,
+            path,
+            format!("could not read {}: {e}", resolved.display()),
+        )
+    })?;
+    Ok((resolved, content))
+}
+
+/// Whether `path` exists, for plugins that create what they do not find
+fn exists(path: &Path) -> bool {
+    path.try_exists().unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calls_are_found_outside_strings_and_other_calls() {
+        let source = "lookup('env', 'HOME') ~ 'lookup(x)' ~ query('file', lookup('env', 'F'))";
+        let calls = find_calls(source);
+        assert_eq!(calls.len(), 2);
+        assert_eq!(&source[calls[0].arguments.clone()], "'env', 'HOME'");
+        assert!(!calls[0].query);
+        assert_eq!(
+            &source[calls[1].start..calls[1].end],
+            "query('file', lookup('env', 'F'))"
+        );
+        assert!(calls[1].query);
+
+        assert!(find_calls("mylookup('x') ~ item.lookup('y')").is_empty());
+    }
+
+    #[test]
+    fn test_terms_take_inline_options() {
+        let mut options = Map::new();
+        options.insert("length".to_string(), Value::from(12));
+        let (key, parsed) = parse_term("creds/db length=20 chars='ascii_letters,digits'", &options);
+        assert_eq!(key, "creds/db");
+        // Options passed by name win
+        assert_eq!(parsed["length"], 12);
+        assert_eq!(parsed["chars"], "ascii_letters,digits");
+    }
+
+    #[test]
+    fn test_lookup_values_are_joined() {
+        let strings = vec![Value::from("a"), Value::from("b")];
+        assert_eq!(join_values(strings.clone(), false), "a,b");
+        assert_eq!(join_values(strings, true), serde_json::json!(["a", "b"]));
+        assert_eq!(
+            join_values(vec![serde_json::json!({"k": 1})], false),
+            serde_json::json!({"k": 1})
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! Generated passwords kept on the controller
+
+use super::{exists, failed, option_str, parse_term, LookupContext, LookupPlugin};
+use crate::runtime::LookupError;
+use async_trait::async_trait;
+use crypto_box::aead::rand_core::RngCore;
+use crypto_box::aead::OsRng;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::Path;
+
+const DEFAULT_LENGTH: usize = 20;
+const DEFAULT_CHARS: &[&str] = &["ascii_letters", "digits", ".,:-_"];
+
+/// `lookup('password', 'credentials/db length=24 chars=ascii_letters,digits')`:
+/// the password stored in a file on the controller, generated and stored
+/// there, readable by its owner only, the first time it is looked up. The
+/// path `/dev/null` generates a new password every time. `chars` lists
+/// character sets by their Python names, such as `digits`, or literally;
+/// `seed` makes the generated password depend on it alone.
+pub struct PasswordLookup;
+
+#[async_trait]
+impl LookupPlugin for PasswordLookup {
+    fn name(&self) -> &'static str {
+        "password"
+    }
+
+    fn sensitive(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        terms: &[String],
+        options: &Map<String, Value>,
+        context: &LookupContext,
+    ) -> Result<Vec<Value>, LookupError> {
+        let mut values = Vec::with_capacity(terms.len());
+        for term in terms {
+            let (path, options) = parse_term(term, options);
+            if path.is_empty() {
+                return Err(failed(self, term, "no path to keep the password in"));
+            }
+            let length = match option_str(&options, "length") {
+                Some(length) => length
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|length| *length > 0)
+                    .ok_or_else(|| failed(self, term, format!("invalid length '{length}'")))?,
+                None => DEFAULT_LENGTH,
+            };
+            let chars = match option_str(&options, "chars") {
+                Some(chars) => charset(&split_chars(&chars)),
+                None => charset(DEFAULT_CHARS),
+            };
+            if chars.is_empty() {
+                return Err(failed(self, term, "no characters to generate from"));
+            }
+
+            let persisted = path != "/dev/null";
+            let path = context.path(&path);
+            if persisted && exists(&path) {
+                let content = std::fs::read_to_string(&path).map_err(|e| failed(self, term, e))?;
+                values.push(Value::String(stored_password(&content)));
+                continue;
+            }
+            let password = generate(length, &chars, option_str(&options, "seed").as_deref());
+            if persisted {
+                store(&path, &password).map_err(|e| {
+                    failed(
+                        self,
+                        term,
+                        format!("could not store {}: {e}", path.display()),
+                    )
+                })?;
+            }
+            values.push(Value::String(password));
+        }
+        Ok(values)
+    }
+}
+
+/// The sets of a `chars` option; `,,` stands for a comma
+fn split_chars(chars: &str) -> Vec<&str> {
+    // A comma between two others splits into two empty sets
+    let sets: Vec<&str> = chars.split(',').collect();
+    let mut merged = Vec::with_capacity(sets.len());
+    let mut i = 0;
+    while i < sets.len() {
+        if sets[i].is_empty() && i + 1 < sets.len() && sets[i + 1].is_empty() {
+            merged.push(",");
+            i += 2;
+        } else {
+            merged.push(sets[i]);
+            i += 1;
+        }
+    }
+    merged.into_iter().filter(|set| !set.is_empty()).collect()
+}
+
+/// The characters of the sets named as Python's `string` constants, or
+/// given literally
+fn charset(sets: &[&str]) -> Vec<char> {
+    const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+    const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    const DIGITS: &str = "0123456789";
+    const PUNCTUATION: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+    let mut chars: Vec<char> = Vec::new();
+    for set in sets {
+        let expanded = match *set {
+            "ascii_letters" | "letters" => format!("{LOWERCASE}{UPPERCASE}"),
+            "ascii_lowercase" | "lowercase" => LOWERCASE.to_string(),
+            "ascii_uppercase" | "uppercase" => UPPERCASE.to_string(),
+            "digits" => DIGITS.to_string(),
+            "hexdigits" => "0123456789abcdefABCDEF".to_string(),
+            "octdigits" => "01234567".to_string(),
+            "punctuation" => PUNCTUATION.to_string(),
+            literal => literal.to_string(),
+        };
+        for c in expanded.chars() {
+            if !chars.contains(&c) {
+                chars.push(c);
+            }
+        }
+    }
+    chars
+}
+
+/// A password of `length` characters drawn uniformly from `chars`, from
+/// the operating system's generator or derived from `seed`
+fn generate(length: usize, chars: &[char], seed: Option<&str>) -> String {
+    let mut random = Vec::new();
+    let mut block = 0u64;
+    let mut next_byte = || {
+        if random.is_empty() {
+            random = match seed {
+                Some(seed) => {
+                    let mut hasher = Sha256::new();
+                    hasher.update(seed.as_bytes());
+                    hasher.update(block.to_be_bytes());
+                    block += 1;
+                    hasher.finalize().to_vec()
+                }
+                None => {
+                    let mut bytes = vec![0; 64];
+                    OsRng.fill_bytes(&mut bytes);
+                    bytes
+                }
+            };
+        }
+        random.pop().expect("random bytes were just drawn")
+    };
+    // Bytes past the largest multiple of the set's size are drawn again,
+    // so every character is as likely
+    let limit = 256 - 256 % chars.len();
+    let mut password = String::with_capacity(length);
+    while password.chars().count() < length {
+        let byte = usize::from(next_byte());
+        if chars.len() > 256 {
+            let wide = (byte << 8 | usize::from(next_byte())) % chars.len();
+            password.push(chars[wide]);
+        } else if byte < limit {
+            password.push(chars[byte % chars.len()]);
+        }
+    }
+    password
+}
+
+/// The password of a password file, which Ansible may follow with the salt
+/// it encrypts it with
+fn stored_password(content: &str) -> String {
+    let line = content.lines().next().unwrap_or_default();
+    let end = [" salt=", " ident="]
+        .iter()
+        .filter_map(|marker| line.find(marker))
+        .min()
+        .unwrap_or(line.len());
+    line[..end].to_string()
+}
+
+fn store(path: &Path, password: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    writeln!(file, "{password}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passwords_use_the_requested_characters() {
+        let chars = charset(&split_chars("digits,,,-"));
+        assert_eq!(chars.iter().collect::<String>(), "0123456789,-");
+
+        let password = generate(64, &chars, None);
+        assert_eq!(password.chars().count(), 64);
+        assert!(password.chars().all(|c| chars.contains(&c)));
+
+        let seeded = generate(16, &charset(DEFAULT_CHARS), Some("db"));
+        assert_eq!(seeded, generate(16, &charset(DEFAULT_CHARS), Some("db")));
+        assert_ne!(seeded, generate(16, &charset(DEFAULT_CHARS), Some("web")));
+
+        assert_eq!(stored_password("s3cret salt=abcdefgh\n"), "s3cret");
+    }
+}
@@ -0,0 +1,91 @@
+//! Content fetched over HTTP by the controller
+
+use super::{failed, option_bool, option_str, LookupContext, LookupPlugin};
+use crate::runtime::LookupError;
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+/// `lookup('url', 'https://...')`: the content of URLs, one value per line
+/// unless `split_lines=false`. `headers`, `username` and `password`,
+/// `timeout` in seconds and `validate_certs` shape the requests; anything
+/// but a success status fails the lookup.
+pub struct UrlLookup {
+    client: reqwest::Client,
+    insecure_client: reqwest::Client,
+}
+
+impl UrlLookup {
+    pub fn new() -> Self {
+        let insecure_client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap_or_default();
+        Self {
+            client: reqwest::Client::new(),
+            insecure_client,
+        }
+    }
+}
+
+impl Default for UrlLookup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl LookupPlugin for UrlLookup {
+    fn name(&self) -> &'static str {
+        "url"
+    }
+
+    async fn run(
+        &self,
+        terms: &[String],
+        options: &Map<String, Value>,
+        _context: &LookupContext,
+    ) -> Result<Vec<Value>, LookupError> {
+        let client = if option_bool(options, "validate_certs", true) {
+            &self.client
+        } else {
+            &self.insecure_client
+        };
+        let timeout = option_str(options, "timeout")
+            .and_then(|timeout| timeout.parse::<f64>().ok())
+            .map_or(Duration::from_secs(DEFAULT_TIMEOUT_SECS), |secs| {
+                Duration::from_secs_f64(secs.max(0.0))
+            });
+        let split_lines = option_bool(options, "split_lines", true);
+
+        let mut values = Vec::new();
+        for url in terms {
+            let mut request = client.get(url).timeout(timeout);
+            if let Some(Value::Object(headers)) = options.get("headers") {
+                for (name, value) in headers {
+                    let value = value
+                        .as_str()
+                        .map_or_else(|| value.to_string(), str::to_string);
+                    request = request.header(name.as_str(), value);
+                }
+            }
+            if let Some(username) = option_str(options, "username") {
+                request = request.basic_auth(username, option_str(options, "password"));
+            }
+            let response = request.send().await.map_err(|e| failed(self, url, e))?;
+            let status = response.status();
+            if !status.is_success() {
+                return Err(failed(self, url, format!("the server answered {status}")));
+            }
+            let body = response.text().await.map_err(|e| failed(self, url, e))?;
+            if split_lines {
+                values.extend(body.lines().map(|line| Value::String(line.to_string())));
+            } else {
+                values.push(Value::String(body));
+            }
+        }
+        Ok(values)
+    }
+}
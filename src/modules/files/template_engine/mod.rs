@@ -1,9 +1,14 @@
 //! Advanced template processing module with comprehensive Jinja2 compatibility
 
+pub mod filters;
 pub mod handlebars_helpers;
+pub mod jinja_environment;
 pub mod jinja_parser;
+pub mod lookup_plugins;
 pub mod template_processor;
 
 pub use handlebars_helpers::*;
+pub use jinja_environment::ansible_environment;
 pub use jinja_parser::{ConversionResult, Jinja2Parser, ParseError};
+pub use lookup_plugins::{LookupContext, LookupPlugin, LookupRegistry, LookupSide};
 pub use template_processor::{AdvancedTemplateProcessor, TemplateError};
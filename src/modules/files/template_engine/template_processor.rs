@@ -1,14 +1,25 @@
 //! Advanced template processor with comprehensive Jinja2 compatibility
+//!
+//! Templates are rendered by a Jinja2 engine configured as Ansible's. A
+//! template the engine cannot parse is converted to Handlebars and rendered
+//! by it instead, as templates used to be, before it fails.
 
 use handlebars::Handlebars;
+use minijinja::Environment;
 use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use thiserror::Error;
+use tracing::warn;
 
 use super::handlebars_helpers::{
     default_helper, equality_helper, greater_than_helper, less_than_helper, not_equal_helper,
     quote_helper,
 };
+use super::jinja_environment::{ansible_environment, normalize_template, search_path_loader};
 use super::jinja_parser::{Jinja2Parser, ParseError};
+use super::lookup_plugins::{LookupContext, LookupRegistry};
+use crate::runtime::{LookupError, SensitiveValues};
 
 #[derive(Debug, Error)]
 pub enum TemplateError {
@@ -23,6 +34,12 @@ pub enum TemplateError {
 
     #[error("Template validation failed: {message}")]
     ValidationFailed { message: String },
+
+    #[error("Template syntax error: {message}")]
+    Syntax { message: String },
+
+    #[error("Template lookup failed: {0}")]
+    Lookup(#[from] LookupError),
 }
 
 impl From<handlebars::RenderError> for TemplateError {
@@ -33,6 +50,21 @@ impl From<handlebars::RenderError> for TemplateError {
     }
 }
 
+impl From<minijinja::Error> for TemplateError {
+    fn from(error: minijinja::Error) -> Self {
+        let message = error.to_string();
+        match error.kind() {
+            minijinja::ErrorKind::SyntaxError if message.contains("unexpected end of input") => {
+                TemplateError::UnbalancedBlocks {
+                    block_type: error.detail().unwrap_or(&message).to_string(),
+                }
+            }
+            minijinja::ErrorKind::SyntaxError => TemplateError::Syntax { message },
+            _ => TemplateError::RenderingFailed { message },
+        }
+    }
+}
+
 impl From<ParseError> for TemplateError {
     fn from(error: ParseError) -> Self {
         TemplateError::ConversionFailed {
@@ -43,6 +75,7 @@ impl From<ParseError> for TemplateError {
 
 /// Advanced template processor with comprehensive Jinja2 compatibility
 pub struct AdvancedTemplateProcessor {
+    jinja: Environment<'static>,
     handlebars: Handlebars<'static>,
     jinja_parser: Jinja2Parser,
 }
@@ -65,6 +98,7 @@ impl AdvancedTemplateProcessor {
         })?;
 
         Ok(Self {
+            jinja: ansible_environment(),
             handlebars,
             jinja_parser,
         })
@@ -74,6 +108,65 @@ impl AdvancedTemplateProcessor {
         &self,
         template_content: &str,
         variables: &Value,
+    ) -> Result<String, TemplateError> {
+        self.render_jinja(&self.jinja, template_content, variables)
+    }
+
+    /// Render a template whose `include`, `import` and `extends` statements
+    /// name templates relative to `template_dir`
+    pub fn render_template_in(
+        &self,
+        template_content: &str,
+        variables: &Value,
+        template_dir: &Path,
+    ) -> Result<String, TemplateError> {
+        self.render_template_with_search_path(
+            template_content,
+            variables,
+            &[template_dir.to_path_buf()],
+        )
+    }
+
+    /// Render a template whose `include`, `import` and `extends` statements
+    /// name templates relative to the first directory of `search_path` that
+    /// has them
+    pub fn render_template_with_search_path(
+        &self,
+        template_content: &str,
+        variables: &Value,
+        search_path: &[PathBuf],
+    ) -> Result<String, TemplateError> {
+        let mut jinja = self.jinja.clone();
+        jinja.set_loader(search_path_loader(search_path));
+        self.render_jinja(&jinja, template_content, variables)
+    }
+
+    fn render_jinja(
+        &self,
+        jinja: &Environment<'static>,
+        template_content: &str,
+        variables: &Value,
+    ) -> Result<String, TemplateError> {
+        match jinja.render_str(&normalize_template(template_content), variables) {
+            Ok(rendered) => Ok(rendered),
+            Err(error) if error.kind() == minijinja::ErrorKind::SyntaxError => {
+                match self.render_converted(template_content, variables) {
+                    Ok(rendered) => {
+                        warn!("Rendered a template Jinja2 cannot parse with Handlebars: {error}");
+                        Ok(rendered)
+                    }
+                    Err(_) => Err(error.into()),
+                }
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Render a template converted to Handlebars
+    fn render_converted(
+        &self,
+        template_content: &str,
+        variables: &Value,
     ) -> Result<String, TemplateError> {
         // Validate template syntax
         self.validate_template_syntax(template_content)?;
@@ -89,6 +182,137 @@ impl AdvancedTemplateProcessor {
         Ok(rendered)
     }
 
+    /// Render a template whose `lookup()` and `query()` calls are evaluated
+    /// with `lookups` first, against `context` and `variables`
+    pub async fn render_template_with_lookups(
+        &self,
+        template_content: &str,
+        variables: &Value,
+        lookups: &LookupRegistry,
+        context: &LookupContext,
+    ) -> Result<String, TemplateError> {
+        let mut context = context.clone();
+        if let Value::Object(entries) = variables {
+            context.variables.extend(
+                entries
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone())),
+            );
+        }
+        let (template, bound) = lookups
+            .bind_template_lookups(template_content, &context, &mut SensitiveValues::new())
+            .await?;
+        let mut variables = match variables {
+            Value::Object(entries) => entries.clone(),
+            _ => serde_json::Map::new(),
+        };
+        variables.extend(bound);
+        self.render_template_with_search_path(
+            &template,
+            &Value::Object(variables),
+            &context.template_search_path(),
+        )
+    }
+
+    /// `value` with the expressions in its strings rendered, as far as they
+    /// refer to `variables` only; the others are left as they are. A string
+    /// that is a single `{{ expression }}` takes its value as is.
+    pub fn render_value(&self, value: &Value, variables: &HashMap<String, Value>) -> Value {
+        match value {
+            Value::String(text) => self.render_str(text, variables),
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.render_value(item, variables))
+                    .collect(),
+            ),
+            Value::Object(entries) => Value::Object(
+                entries
+                    .iter()
+                    .map(|(key, item)| (key.clone(), self.render_value(item, variables)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    /// The names of the variables the expressions in the strings of `value`
+    /// refer to, leaving out those they define themselves. Strings that are
+    /// not valid templates refer to none.
+    pub fn referenced_variables(&self, value: &Value) -> HashSet<String> {
+        match value {
+            Value::String(text) if text.contains("{{") || text.contains("{%") => self
+                .jinja
+                .template_from_str(&normalize_template(text))
+                .map(|template| template.undeclared_variables(false))
+                .unwrap_or_default(),
+            Value::Array(items) => items
+                .iter()
+                .flat_map(|item| self.referenced_variables(item))
+                .collect(),
+            Value::Object(entries) => entries
+                .values()
+                .flat_map(|item| self.referenced_variables(item))
+                .collect(),
+            _ => HashSet::new(),
+        }
+    }
+
+    fn render_str(&self, text: &str, variables: &HashMap<String, Value>) -> Value {
+        if !text.contains("{{") && !text.contains("{%") {
+            return Value::String(text.to_string());
+        }
+        let globals: HashSet<&str> = self.jinja.globals().map(|(name, _)| name).collect();
+        let known = |names: HashSet<String>| {
+            names
+                .iter()
+                .all(|name| variables.contains_key(name) || globals.contains(name.as_str()))
+        };
+
+        let normalized = normalize_template(text);
+        if let Some(expression) = single_expression(&normalized) {
+            let value = self
+                .jinja
+                .compile_expression(expression)
+                .ok()
+                .filter(|compiled| known(compiled.undeclared_variables(false)))
+                .and_then(|compiled| compiled.eval(variables).ok())
+                .filter(|value| !value.is_undefined())
+                .and_then(|value| serde_json::to_value(value).ok());
+            return value.unwrap_or_else(|| Value::String(text.to_string()));
+        }
+        if text.contains("{%") {
+            let rendered = self
+                .jinja
+                .template_from_str(&normalized)
+                .ok()
+                .filter(|template| known(template.undeclared_variables(false)))
+                .and_then(|template| template.render(variables).ok());
+            return Value::String(rendered.unwrap_or_else(|| text.to_string()));
+        }
+
+        let mut rendered = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start..].find("}}").map(|end| start + end + 2) else {
+                break;
+            };
+            let block = &rest[start..end];
+            let source = normalize_template(block);
+            let rendered_block = self
+                .jinja
+                .compile_expression(source[2..source.len() - 2].trim())
+                .ok()
+                .filter(|compiled| known(compiled.undeclared_variables(false)))
+                .and_then(|_| self.jinja.render_str(&source, variables).ok());
+            rendered.push_str(&rest[..start]);
+            rendered.push_str(rendered_block.as_deref().unwrap_or(block));
+            rest = &rest[end..];
+        }
+        rendered.push_str(rest);
+        Value::String(rendered)
+    }
+
     fn validate_template_syntax(&self, template: &str) -> Result<(), TemplateError> {
         // Check for balanced control structures
         self.check_balanced_blocks(template)?;
@@ -234,6 +458,14 @@ impl AdvancedTemplateProcessor {
     }
 }
 
+/// The expression of a string that is nothing but `{{ expression }}`; the
+/// expression may itself end in `}}`, as dict literals do, which compiling
+/// it tells apart from a second block
+fn single_expression(text: &str) -> Option<&str> {
+    let inner = text.trim().strip_prefix("{{")?.strip_suffix("}}")?;
+    (!inner.contains("{{")).then(|| inner.trim())
+}
+
 impl Default for AdvancedTemplateProcessor {
     fn default() -> Self {
         Self::new().expect("Failed to create AdvancedTemplateProcessor")
@@ -346,13 +578,55 @@ host = "{{server.host}}"
     }
 
     #[test]
-    fn test_unsupported_features_error() {
+    fn test_statements_handlebars_could_not_render() {
         let processor = AdvancedTemplateProcessor::new().unwrap();
-        let template = "{% set var = 'value' %}{{var}}"; // set is not supported
-        let variables = json!({});
+        let template = "{% set ns = namespace(total=0) %}\
+                        {% macro port(p) %}{{ p.port }}{% endmacro %}\
+                        {% for s in servers %}{% set ns.total = ns.total + 1 %}{{ port(s) }},{% endfor %}\
+                        {{ ns.total }}";
+        let variables = json!({"servers": [{"port": 80}, {"port": 443}]});
 
-        let result = processor.render_template(template, &variables);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("Unsupported"));
+        let result = processor.render_template(template, &variables).unwrap();
+        assert_eq!(result, "80,443,2");
+    }
+
+    #[test]
+    fn test_handlebars_fallback() {
+        let processor = AdvancedTemplateProcessor::new().unwrap();
+        let template = "{{#if enabled}}on{{/if}}";
+
+        let result = processor
+            .render_template(template, &json!({"enabled": true}))
+            .unwrap();
+        assert_eq!(result, "on");
+    }
+
+    #[test]
+    fn test_render_value_leaves_unknown_variables() {
+        let processor = AdvancedTemplateProcessor::new().unwrap();
+        let variables = HashMap::from([
+            ("port".to_string(), json!(8080)),
+            ("users".to_string(), json!(["alice", "bob"])),
+        ]);
+
+        let value = json!({
+            "port": "{{ port + 1 }}",
+            "users": "{{ users | join(',') }}",
+            "first": "{{ users.0 | upper }}",
+            "url": "http://{{ host }}:{{ port }}",
+            "loop": "{{ item.name }}",
+            "block": "{% for u in users %}{{ u }};{% endfor %}",
+        });
+        assert_eq!(
+            processor.render_value(&value, &variables),
+            json!({
+                "port": 8081,
+                "users": "alice,bob",
+                "first": "ALICE",
+                "url": "http://{{ host }}:8080",
+                "loop": "{{ item.name }}",
+                "block": "alice;bob;",
+            })
+        );
     }
 }
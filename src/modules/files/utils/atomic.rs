@@ -46,6 +46,18 @@ impl AtomicWriter {
         Ok(())
     }
 
+    /// Where the data is written until it is committed, such as for a
+    /// command validating it
+    pub fn temp_path(&self) -> &Path {
+        &self.temp_path
+    }
+
+    /// Flush the data written so far to the temporary file
+    pub async fn flush(&mut self) -> Result<(), FileError> {
+        self.temp_file.flush().await?;
+        Ok(())
+    }
+
     /// Flush and commit the atomic operation
     pub async fn commit(mut self) -> Result<(), FileError> {
         self.temp_file.flush().await?;
@@ -5,12 +5,14 @@ pub mod backup;
 pub mod checksum;
 pub mod ownership;
 pub mod permissions;
+pub mod validate;
 
 pub use atomic::*;
 pub use backup::*;
 pub use checksum::*;
 pub use ownership::*;
 pub use permissions::*;
+pub use validate::*;
 
 use thiserror::Error;
 
@@ -29,6 +31,9 @@ pub enum FileError {
     #[error("Checksum mismatch: expected {expected}, got {actual}")]
     ChecksumMismatch { expected: String, actual: String },
 
+    #[error("Validation command '{command}' failed: {output}")]
+    ValidationFailed { command: String, output: String },
+
     #[error("Template rendering failed: {source}")]
     TemplateError { source: handlebars::RenderError },
 
@@ -0,0 +1,44 @@
+//! Validation of files before they replace their destination
+
+use std::path::Path;
+use tokio::process::Command;
+
+use super::FileError;
+
+/// Check that a `validate` command has the `%s` its file is passed as
+pub fn check_validate_command(command: &str) -> Result<(), FileError> {
+    if !command.contains("%s") {
+        return Err(FileError::ValidationFailed {
+            command: command.to_string(),
+            output: "validate must contain %s, which is replaced by the file to validate"
+                .to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Run a `validate` command, such as `nginx -t -c %s`, on `path`, which
+/// replaces `%s`, returning what it printed when it succeeds
+pub async fn validate_file(command: &str, path: &Path) -> Result<String, FileError> {
+    check_validate_command(command)?;
+    let command = command.replace("%s", &shell_quote(&path.to_string_lossy()));
+    let output = Command::new("sh").arg("-c").arg(&command).output().await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let printed = if stderr.trim().is_empty() {
+            stdout
+        } else {
+            stderr
+        };
+        return Err(FileError::ValidationFailed {
+            command,
+            output: printed.trim().to_string(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
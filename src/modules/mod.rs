@@ -5,11 +5,13 @@ pub mod ast_parser;
 pub mod cache;
 pub mod compiler;
 pub mod core;
+pub mod custom;
 pub mod error;
 pub mod files;
 pub mod interface;
 pub mod loader;
 pub mod net;
+pub mod python;
 pub mod registry;
 pub mod resolver;
 pub mod source_control;
@@ -19,10 +21,12 @@ pub mod validator;
 // Re-export commonly used types
 pub use cache::ModuleCache;
 pub use compiler::CodeGenerator;
+pub use custom::{CustomModule, ModuleSearchPath, MODULE_PATH_ENV};
 pub use error::*;
 pub use files::{CopyModule, FileModule, StatModule, TemplateModule};
 pub use interface::*;
 pub use loader::{CompiledModule, LoadedModule, ModuleCompiler};
+pub use python::{FallbackTask, PythonModules, ANSIBLE_LIBRARY_ENV};
 pub use registry::ModuleRegistry;
 pub use resolver::{ModuleSourceCode, ModuleSourceResolver};
 pub use validator::{ModuleValidator, ValidationResult};
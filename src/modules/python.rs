@@ -0,0 +1,323 @@
+//! Ansible's Python modules, run by runners for the modules they are not
+//! built with
+//!
+//! Each module is packaged the way Ansible's AnsiballZ packages them: a zip
+//! of the module and the `ansible.module_utils` it imports, transitively,
+//! run by the host's Python as `python3 <module>.zip` with the task's
+//! arguments on standard input. Modules are looked up by their short name,
+//! or as `ansible.builtin.<name>` or `ansible.legacy.<name>`, in the library
+//! directories first and then in the modules of the Ansible installation
+//! whose `module_utils` they import. Modules of collections are not
+//! supported.
+
+use crate::execution::rustle_plan::RustlePlanOutput;
+use crate::modules::error::ResolveError;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Environment variable listing library directories of modules, searched
+/// after those given explicitly
+pub const ANSIBLE_LIBRARY_ENV: &str = "ANSIBLE_LIBRARY";
+
+/// Where the packages of modules are embedded into runners
+pub const PYTHON_PAYLOAD_PREFIX: &str = "python_modules/";
+
+/// Runs the packaged module, as AnsiballZ's wrapper does
+const MAIN_PY: &str = "import runpy\n\nrunpy.run_module(\"ansible.modules.{module}\", run_name=\"__main__\", alter_sys=True)\n";
+
+/// A task run by a Python module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FallbackTask {
+    /// Id of the task, or of the handler
+    pub task_id: String,
+    pub name: String,
+    pub module: String,
+    /// Source of the module
+    pub source: PathBuf,
+}
+
+/// Where Ansible's Python modules and their `module_utils` are found
+#[derive(Debug, Clone, Default)]
+pub struct PythonModules {
+    library: Vec<PathBuf>,
+    ansible_dir: Option<PathBuf>,
+}
+
+impl PythonModules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Look for modules in `dir` before the Ansible installation
+    pub fn with_library(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.library.push(dir.into());
+        self
+    }
+
+    /// Add the library directories [`ANSIBLE_LIBRARY_ENV`] lists
+    pub fn with_env(mut self) -> Self {
+        if let Some(paths) = std::env::var_os(ANSIBLE_LIBRARY_ENV) {
+            self.library
+                .extend(std::env::split_paths(&paths).filter(|path| !path.as_os_str().is_empty()));
+        }
+        self
+    }
+
+    /// Use the `ansible` package at `dir`, holding `modules` and
+    /// `module_utils`
+    pub fn with_ansible_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.ansible_dir = Some(dir.into());
+        self
+    }
+
+    /// Use the `ansible` package the controller's `python3` imports
+    pub fn with_installed_ansible(self) -> Result<Self, ResolveError> {
+        let output = std::process::Command::new("python3")
+            .args([
+                "-c",
+                "import os, ansible; print(os.path.dirname(ansible.__file__))",
+            ])
+            .output()
+            .map_err(|e| ResolveError::IoError {
+                operation: "run python3".to_string(),
+                error: e.to_string(),
+            })?;
+        if !output.status.success() {
+            return Err(ResolveError::NotFound {
+                name: format!(
+                    "Ansible installation ({})",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            });
+        }
+        let dir = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok(self.with_ansible_dir(dir))
+    }
+
+    pub fn ansible_dir(&self) -> Option<&Path> {
+        self.ansible_dir.as_deref()
+    }
+
+    /// Source of the module running `module`, if there is one
+    pub fn find(&self, module: &str) -> Option<PathBuf> {
+        let name = module
+            .strip_prefix("ansible.builtin.")
+            .or_else(|| module.strip_prefix("ansible.legacy."))
+            .unwrap_or(module);
+        if syn::parse_str::<syn::Ident>(name).is_err() {
+            return None;
+        }
+        let file = format!("{name}.py");
+        self.library
+            .iter()
+            .map(|dir| dir.join(&file))
+            .chain(
+                self.ansible_dir
+                    .iter()
+                    .map(|dir| dir.join("modules").join(&file)),
+            )
+            .find(|path| path.is_file())
+    }
+
+    /// The tasks and handlers of `plan` run by Python modules, those whose
+    /// modules are not `compiled` into the runner
+    pub fn fallback_tasks(
+        &self,
+        plan: &RustlePlanOutput,
+        compiled: impl Fn(&str) -> bool,
+    ) -> Vec<FallbackTask> {
+        let tasks = plan.plays.iter().flat_map(|play| {
+            play.batches
+                .iter()
+                .flat_map(|batch| &batch.tasks)
+                .map(|task| (&task.task_id, &task.name, &task.module))
+                .chain(
+                    play.handlers
+                        .iter()
+                        .map(|handler| (&handler.handler_id, &handler.name, &handler.module)),
+                )
+        });
+        tasks
+            .filter(|(_, _, module)| !compiled(module))
+            .filter_map(|(task_id, name, module)| {
+                Some(FallbackTask {
+                    task_id: task_id.clone(),
+                    name: name.clone(),
+                    module: module.clone(),
+                    source: self.find(module)?,
+                })
+            })
+            .collect()
+    }
+
+    /// The zip running `module` with the host's Python
+    pub fn package(&self, module: &str) -> Result<Vec<u8>, ResolveError> {
+        let source = self.find(module).ok_or_else(|| ResolveError::NotFound {
+            name: format!("Python module {module}"),
+        })?;
+        let name = source
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(module)
+            .to_string();
+        let code = read(&source)?;
+
+        // The module_utils the module imports, and those they import
+        let mut files = BTreeMap::new();
+        let mut pending: Vec<(String, String)> = imports(&code, "ansible.modules")
+            .into_iter()
+            .map(|import| (import, source.display().to_string()))
+            .collect();
+        while let Some((import, importer)) = pending.pop() {
+            let Some(relative) = import.strip_prefix("ansible.") else {
+                continue;
+            };
+            let ansible_dir =
+                self.ansible_dir
+                    .as_ref()
+                    .ok_or_else(|| ResolveError::InvalidModule {
+                        reason: format!(
+                            "{importer} imports {import}, and no Ansible installation was found"
+                        ),
+                    })?;
+            for (archive_path, path) in module_files(ansible_dir, relative) {
+                if files.contains_key(&archive_path) {
+                    continue;
+                }
+                let content = read(&path)?;
+                let package = archive_path
+                    .trim_end_matches(".py")
+                    .trim_end_matches("/__init__")
+                    .replace('/', ".");
+                let package = if path.ends_with("__init__.py") {
+                    package
+                } else {
+                    package
+                        .rsplit_once('.')
+                        .map_or(package.clone(), |(parent, _)| parent.to_string())
+                };
+                pending.extend(
+                    imports(&content, &package)
+                        .into_iter()
+                        .map(|import| (import, path.display().to_string())),
+                );
+                files.insert(archive_path, content);
+            }
+        }
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        // Fixed timestamps, for the same module to package the same
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .last_modified_time(zip::DateTime::default());
+        let entries = [
+            (
+                "__main__.py".to_string(),
+                MAIN_PY.replace("{module}", &name),
+            ),
+            ("ansible/__init__.py".to_string(), String::new()),
+            ("ansible/modules/__init__.py".to_string(), String::new()),
+            (format!("ansible/modules/{name}.py"), code),
+        ];
+        for (path, content) in entries.into_iter().chain(files) {
+            zip.start_file(path.as_str(), options)
+                .and_then(|()| zip.write_all(content.as_bytes()).map_err(Into::into))
+                .map_err(|e| ResolveError::IoError {
+                    operation: format!("package {path} of {module}"),
+                    error: e.to_string(),
+                })?;
+        }
+        let zip = zip.finish().map_err(|e| ResolveError::IoError {
+            operation: format!("package {module}"),
+            error: e.to_string(),
+        })?;
+        Ok(zip.into_inner())
+    }
+}
+
+/// Path in the embedded data of the package of `module`
+pub fn payload_path(module: &str) -> String {
+    format!("{PYTHON_PAYLOAD_PREFIX}{module}.zip")
+}
+
+/// The `ansible.module_utils` modules `code`, of `package`, may import
+fn imports(code: &str, package: &str) -> BTreeSet<String> {
+    static FROM: OnceLock<Regex> = OnceLock::new();
+    static IMPORT: OnceLock<Regex> = OnceLock::new();
+    let from = FROM.get_or_init(|| {
+        Regex::new(r"(?m)^[ \t]*from[ \t]+(\.*)([\w.]*)[ \t]+import[ \t]+(?:\(([^)]*)\)|([^\n#]*))")
+            .unwrap()
+    });
+    let import = IMPORT.get_or_init(|| Regex::new(r"(?m)^[ \t]*import[ \t]+([\w.]+)").unwrap());
+
+    let mut modules = BTreeSet::new();
+    for captures in from.captures_iter(code) {
+        let dots = captures[1].len();
+        let module = if dots == 0 {
+            captures[2].to_string()
+        } else {
+            // Relative to the package, one level up per dot after the first
+            let mut parts: Vec<&str> = package.split('.').collect();
+            parts.truncate(parts.len().saturating_sub(dots - 1));
+            let mut base = parts.join(".");
+            if !captures[2].is_empty() {
+                base = format!("{base}.{}", &captures[2]);
+            }
+            base
+        };
+        if !module.starts_with("ansible.module_utils") {
+            continue;
+        }
+        // Names imported may be modules themselves
+        let names = captures.get(3).or_else(|| captures.get(4));
+        for name in names.map_or("", |names| names.as_str()).split(',') {
+            let name = name.split_whitespace().next().unwrap_or_default();
+            if !name.is_empty() {
+                modules.insert(format!("{module}.{name}"));
+            }
+        }
+        modules.insert(module);
+    }
+    for captures in import.captures_iter(code) {
+        if captures[1].starts_with("ansible.module_utils") {
+            modules.insert(captures[1].to_string());
+        }
+    }
+    modules
+}
+
+/// The files of the module `relative` to the `ansible` package, with the
+/// `__init__.py` of the packages holding it, by their path in the zip
+fn module_files(ansible_dir: &Path, relative: &str) -> Vec<(String, PathBuf)> {
+    let parts: Vec<&str> = relative.split('.').collect();
+    let mut files = Vec::new();
+    for depth in 1..parts.len() {
+        let package = parts[..depth].join("/");
+        let init = ansible_dir.join(&package).join("__init__.py");
+        if !init.is_file() {
+            return files;
+        }
+        files.push((format!("ansible/{package}/__init__.py"), init));
+    }
+
+    let path = parts.join("/");
+    let module = ansible_dir.join(format!("{path}.py"));
+    let package = ansible_dir.join(&path).join("__init__.py");
+    if module.is_file() {
+        files.push((format!("ansible/{path}.py"), module));
+    } else if package.is_file() {
+        files.push((format!("ansible/{path}/__init__.py"), package));
+    }
+    // Otherwise an attribute rather than a module, of the packages found
+    files
+}
+
+fn read(path: &Path) -> Result<String, ResolveError> {
+    std::fs::read_to_string(path).map_err(|e| ResolveError::IoError {
+        operation: format!("read {}", path.display()),
+        error: e.to_string(),
+    })
+}
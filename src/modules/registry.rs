@@ -1,20 +1,24 @@
 //! Central registry for all execution modules
 
 use crate::modules::{
+    custom::CustomModule,
     error::ModuleExecutionError,
     interface::{ExecutionContext, ExecutionModule, ModuleArgs, ModuleResult},
 };
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 /// Central registry for all execution modules
 pub struct ModuleRegistry {
     modules: HashMap<String, Box<dyn ExecutionModule>>,
+    /// Modules only runners run, compiled in from their source
+    custom_modules: BTreeMap<String, CustomModule>,
 }
 
 impl ModuleRegistry {
     pub fn new() -> Self {
         Self {
             modules: HashMap::new(),
+            custom_modules: BTreeMap::new(),
         }
     }
 
@@ -27,6 +31,7 @@ impl ModuleRegistry {
         registry.register(Box::new(crate::modules::core::CommandModule));
         registry.register(Box::new(crate::modules::core::PackageModule::new()));
         registry.register(Box::new(crate::modules::core::ServiceModule::new()));
+        registry.register(Box::new(crate::modules::core::AsyncStatusModule));
 
         // Register system modules
         registry.register(Box::new(crate::modules::system::setup::SetupModule::new()));
@@ -45,12 +50,35 @@ impl ModuleRegistry {
         self.modules.insert(module.name().to_string(), module);
     }
 
+    /// Register a custom module, replacing any of the same name
+    pub fn register_custom(&mut self, module: CustomModule) {
+        self.custom_modules.insert(module.name.clone(), module);
+    }
+
     pub fn get_module(&self, name: &str) -> Option<&dyn ExecutionModule> {
         self.modules.get(name).map(|m| m.as_ref())
     }
 
+    pub fn get_custom_module(&self, name: &str) -> Option<&CustomModule> {
+        self.custom_modules.get(name)
+    }
+
+    /// The custom modules, by name
+    pub fn custom_modules(&self) -> impl Iterator<Item = &CustomModule> {
+        self.custom_modules.values()
+    }
+
+    /// Whether plans can use `name`, run here or compiled into runners
+    pub fn provides(&self, name: &str) -> bool {
+        self.modules.contains_key(name) || self.custom_modules.contains_key(name)
+    }
+
     pub fn list_modules(&self) -> Vec<&str> {
-        self.modules.keys().map(|s| s.as_str()).collect()
+        self.modules
+            .keys()
+            .chain(self.custom_modules.keys())
+            .map(|s| s.as_str())
+            .collect()
     }
 
     pub async fn execute_module(
@@ -0,0 +1,302 @@
+//! Resident agent mode for compiled runners.
+//!
+//! After running its embedded plan, a runner configured with an
+//! [`AgentConfig`] stays on the host and waits for more work: it either polls
+//! a URL for new plans, which lets fleets behind NAT pull deployments without
+//! inbound SSH, or listens on a socket the controller pushes plans to. Plans
+//! arrive as [`SignedPlan`]s and are only run when signed by the key the
+//! runner was built with. Results are posted to `report_url` when polling,
+//! and written back on the connection when listening.
+
+use crate::execution::ExecutionPlan;
+use crate::runtime::error::AgentError;
+use crate::runtime::signing::{BinarySignature, BinarySigner, TrustedKey};
+use crate::runtime::{ExecutionResult, LocalExecutor, RuntimeConfig, HOST_ID_ENV};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Header carrying the agent's host id on every request it makes
+pub const HOST_HEADER: &str = "X-Rustle-Host";
+/// Header carrying the id of the plan a result belongs to
+pub const PLAN_HEADER: &str = "X-Rustle-Plan";
+
+/// Where a resident agent gets its plans from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlanSource {
+    /// GET a [`SignedPlan`] from `url` every poll interval
+    Poll { url: String },
+    /// Accept [`SignedPlan`]s pushed by the controller on `address`
+    Listen { address: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentConfig {
+    pub source: PlanSource,
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// Where results of pulled plans are posted
+    #[serde(default)]
+    pub report_url: Option<String>,
+    /// Identity sent to the plan server; defaults to `RUSTLE_HOST_ID` or the hostname
+    #[serde(default)]
+    pub host_id: Option<String>,
+}
+
+fn default_poll_interval_secs() -> u64 {
+    60
+}
+
+impl AgentConfig {
+    pub fn poll(url: impl Into<String>) -> Self {
+        Self {
+            source: PlanSource::Poll { url: url.into() },
+            poll_interval_secs: default_poll_interval_secs(),
+            report_url: None,
+            host_id: None,
+        }
+    }
+
+    pub fn listen(address: impl Into<String>) -> Self {
+        Self {
+            source: PlanSource::Listen {
+                address: address.into(),
+            },
+            ..Self::poll(String::new())
+        }
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.poll_interval_secs)
+    }
+
+    pub fn host_id(&self) -> String {
+        self.host_id
+            .clone()
+            .or_else(|| std::env::var(HOST_ID_ENV).ok())
+            .or_else(|| {
+                hostname::get()
+                    .ok()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+}
+
+/// An execution plan with a detached signature over its JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedPlan {
+    /// The plan, serialized exactly as it was signed
+    pub plan: String,
+    /// Minisign signature over `plan`
+    pub signature: String,
+}
+
+impl SignedPlan {
+    pub fn sign(plan: &ExecutionPlan, signer: &BinarySigner) -> Result<Self, AgentError> {
+        let plan_json = serde_json::to_string(plan)?;
+        let signature = signer
+            .sign(
+                plan_json.as_bytes(),
+                &format!("{}.json", plan.metadata.plan_id),
+            )
+            .encode();
+        Ok(Self {
+            plan: plan_json,
+            signature,
+        })
+    }
+
+    /// SHA-256 of the plan; agents run each plan once
+    pub fn id(&self) -> String {
+        format!("{:x}", Sha256::digest(self.plan.as_bytes()))
+    }
+
+    /// Check the signature against `key` and parse the plan
+    pub fn verify(&self, key: &TrustedKey) -> Result<ExecutionPlan, AgentError> {
+        let signature = BinarySignature::parse(&self.signature)?;
+        key.verify(self.plan.as_bytes(), &signature)?;
+        Ok(serde_json::from_str(&self.plan)?)
+    }
+}
+
+/// What a listening agent writes back for a pushed plan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentResponse {
+    Result(Box<ExecutionResult>),
+    Error(String),
+}
+
+/// Long-running loop that receives signed plans and executes them
+pub struct Agent {
+    config: AgentConfig,
+    runtime_config: RuntimeConfig,
+    key: TrustedKey,
+    client: reqwest::Client,
+    last_plan: Option<String>,
+}
+
+impl Agent {
+    /// Requires `runtime_config.agent`, and a signing key to check plans with
+    pub fn new(runtime_config: RuntimeConfig) -> Result<Self, AgentError> {
+        let config = runtime_config
+            .agent
+            .clone()
+            .ok_or_else(|| AgentError::Configuration {
+                reason: "agent mode is not configured".to_string(),
+            })?;
+        let public_key = runtime_config
+            .signing_public_key
+            .as_deref()
+            .ok_or_else(|| AgentError::Configuration {
+                reason: "agent mode needs a signing public key to check plans with".to_string(),
+            })?;
+        let key = TrustedKey::parse(public_key)?;
+
+        Ok(Self {
+            config,
+            runtime_config,
+            key,
+            client: reqwest::Client::new(),
+            last_plan: None,
+        })
+    }
+
+    /// Serve plans until the process is stopped
+    pub async fn run(&mut self) -> Result<(), AgentError> {
+        match self.config.source.clone() {
+            PlanSource::Poll { url } => {
+                tracing::info!("Agent polling {} for plans", url);
+                loop {
+                    match self.poll_once().await {
+                        Ok(Some(result)) => tracing::info!(
+                            "Pulled plan finished: {} tasks, {} failed",
+                            result.summary.total_tasks,
+                            result.summary.failed_tasks
+                        ),
+                        Ok(None) => {}
+                        Err(e) => tracing::warn!("Agent poll failed: {}", e),
+                    }
+                    tokio::time::sleep(self.config.poll_interval()).await;
+                }
+            }
+            PlanSource::Listen { address } => {
+                let listener = TcpListener::bind(&address).await?;
+                tracing::info!("Agent listening for plans on {}", listener.local_addr()?);
+                self.serve(listener).await
+            }
+        }
+    }
+
+    /// Fetch the current plan once and run it if it is new. Returns `None`
+    /// when the server has nothing, or only the plan that already ran.
+    pub async fn poll_once(&mut self) -> Result<Option<ExecutionResult>, AgentError> {
+        let PlanSource::Poll { url } = &self.config.source else {
+            return Err(AgentError::Configuration {
+                reason: "agent is not in poll mode".to_string(),
+            });
+        };
+
+        let mut request = self
+            .client
+            .get(url)
+            .header(HOST_HEADER, self.config.host_id());
+        if let Some(last_plan) = &self.last_plan {
+            request = request.header(reqwest::header::IF_NONE_MATCH, format!("\"{last_plan}\""));
+        }
+        let response = request.send().await?.error_for_status()?;
+        if matches!(
+            response.status(),
+            reqwest::StatusCode::NO_CONTENT | reqwest::StatusCode::NOT_MODIFIED
+        ) {
+            return Ok(None);
+        }
+
+        let signed: SignedPlan = response.json().await?;
+        if self.last_plan.as_deref() == Some(signed.id().as_str()) {
+            return Ok(None);
+        }
+        let result = self.handle(&signed).await?;
+
+        if let Some(report_url) = &self.config.report_url {
+            self.client
+                .post(report_url)
+                .header(HOST_HEADER, self.config.host_id())
+                .header(PLAN_HEADER, signed.id())
+                .json(&result)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        Ok(Some(result))
+    }
+
+    /// Accept pushed plans on `listener`, one connection at a time
+    pub async fn serve(&mut self, listener: TcpListener) -> Result<(), AgentError> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            if let Err(e) = self.serve_connection(stream).await {
+                tracing::warn!("Agent connection from {} failed: {}", peer, e);
+            }
+        }
+    }
+
+    async fn serve_connection(&mut self, stream: TcpStream) -> Result<(), AgentError> {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).await?;
+
+        let response = match serde_json::from_str::<SignedPlan>(&line) {
+            Ok(signed) => match self.handle(&signed).await {
+                Ok(result) => AgentResponse::Result(Box::new(result)),
+                Err(e) => AgentResponse::Error(e.to_string()),
+            },
+            Err(e) => AgentResponse::Error(format!("Malformed plan: {e}")),
+        };
+
+        let mut reply = serde_json::to_vec(&response)?;
+        reply.push(b'\n');
+        let stream = reader.get_mut();
+        stream.write_all(&reply).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    /// Verify and execute one plan
+    pub async fn handle(&mut self, signed: &SignedPlan) -> Result<ExecutionResult, AgentError> {
+        let plan = signed.verify(&self.key)?;
+        tracing::info!(
+            "Running plan {} with {} tasks",
+            plan.metadata.plan_id,
+            plan.tasks.len()
+        );
+
+        let result = LocalExecutor::new(self.runtime_config.clone())
+            .execute_plan(plan)
+            .await?;
+        self.last_plan = Some(signed.id());
+        Ok(result)
+    }
+}
+
+/// Controller side of listen mode: push `plan` to the agent at `address`
+/// and wait for its result
+pub async fn push_plan(address: &str, plan: &SignedPlan) -> Result<ExecutionResult, AgentError> {
+    let stream = TcpStream::connect(address).await?;
+    let mut reader = BufReader::new(stream);
+
+    let mut request = serde_json::to_vec(plan)?;
+    request.push(b'\n');
+    reader.get_mut().write_all(&request).await?;
+
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    match serde_json::from_str(&line)? {
+        AgentResponse::Result(result) => Ok(*result),
+        AgentResponse::Error(reason) => Err(AgentError::Rejected { reason }),
+    }
+}
@@ -0,0 +1,71 @@
+//! Records of the changes tasks make on a host.
+//!
+//! Every task that reports a change, outside check mode, leaves an
+//! [`AuditEntry`]: the module and the arguments it ran with, and checksums
+//! of what it changed before and after. The arguments of `no_log` tasks are
+//! censored like the rest of their result. The entries travel back to the
+//! controller in [`ExecutionResult::audit`], which appends them to its
+//! hash-chained audit log (see [`crate::deploy::audit`]).
+
+use crate::execution::Task;
+use crate::modules::interface::Diff;
+use crate::runtime::state::NO_LOG_MESSAGE;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+
+/// A change a task made on a host
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub host: String,
+    pub task_id: String,
+    pub task_name: String,
+    pub module: String,
+    /// Arguments the module ran with, after templating
+    pub params: serde_json::Value,
+    /// SHA-256 of what the task changed, before and after it ran, when the
+    /// module reported either
+    pub before_checksum: Option<String>,
+    pub after_checksum: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl AuditEntry {
+    /// The change `task` made on `host`, running its module with `args`.
+    /// Checksums the module reported in its `output` are preferred to those
+    /// of its `diff`.
+    pub fn new(
+        host: &str,
+        task: &Task,
+        args: &HashMap<String, serde_json::Value>,
+        diff: Option<&Diff>,
+        output: &HashMap<String, serde_json::Value>,
+    ) -> Self {
+        let reported = |key: &str| output.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        let hashed = |content: Option<&String>| content.map(|c| checksum(c.as_bytes()));
+        let params = if task.no_log {
+            serde_json::Value::String(NO_LOG_MESSAGE.to_string())
+        } else {
+            // Sorted, so the same arguments always read the same in the log
+            serde_json::to_value(args.iter().collect::<BTreeMap<_, _>>()).unwrap_or_default()
+        };
+        Self {
+            host: host.to_string(),
+            task_id: task.id.clone(),
+            task_name: task.name.clone(),
+            module: task.module.clone(),
+            params,
+            before_checksum: reported("before_checksum")
+                .or_else(|| hashed(diff.and_then(|d| d.before.as_ref()))),
+            after_checksum: reported("checksum")
+                .or_else(|| hashed(diff.and_then(|d| d.after.as_ref()))),
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+/// Hex SHA-256 of `data`
+fn checksum(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
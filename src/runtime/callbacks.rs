@@ -0,0 +1,527 @@
+//! Built-in callback plugins.
+//!
+//! Enabled by name through [`RuntimeConfig::callbacks`], or registered with
+//! [`LocalExecutor::with_callback`] next to plugins of one's own:
+//!
+//! - `default`: play and task headers, one line per result and a recap
+//! - `dense`: one line per task and a one-line recap
+//! - `junit`: a JUnit XML report with a test case per task, written to
+//!   [`JUNIT_OUTPUT_DIR_ENV`] or the working directory
+//! - `profile_tasks`: how long each task took, slowest first, and in total
+//!
+//! Console plugins write to stdout, where runners also stream their event
+//! frames; the controller shows lines that are not frames as output.
+//!
+//! [`RuntimeConfig::callbacks`]: crate::runtime::RuntimeConfig::callbacks
+//! [`LocalExecutor::with_callback`]: crate::runtime::LocalExecutor::with_callback
+
+use crate::execution::{Play, Task};
+use crate::runtime::{CallbackPlugin, ExecutionResult, TaskResult, TaskStatus};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// Directory the `junit` plugin writes its report to
+pub const JUNIT_OUTPUT_DIR_ENV: &str = "JUNIT_OUTPUT_DIR";
+
+/// The built-in plugin called `name`, reporting for `host`
+pub fn builtin_callback(name: &str, host: &str) -> Option<Arc<dyn CallbackPlugin>> {
+    let plugin: Arc<dyn CallbackPlugin> = match name {
+        "default" => Arc::new(DefaultCallback::new(host)),
+        "dense" => Arc::new(DenseCallback::new(host)),
+        "junit" => {
+            let dir = std::env::var_os(JUNIT_OUTPUT_DIR_ENV)
+                .map(PathBuf::from)
+                .unwrap_or_default();
+            Arc::new(JUnitCallback::new(dir, host))
+        }
+        "profile_tasks" => Arc::new(ProfileTasksCallback::new()),
+        _ => return None,
+    };
+    Some(plugin)
+}
+
+/// Where a console plugin writes
+struct Console(Mutex<Box<dyn Write + Send>>);
+
+impl Console {
+    fn stdout() -> Self {
+        Self(Mutex::new(Box::new(std::io::stdout())))
+    }
+
+    fn line(&self, line: &str) {
+        let mut output = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        // Output is best effort, it never fails the execution
+        let _ = writeln!(output, "{line}");
+        let _ = output.flush();
+    }
+}
+
+/// How a result is shown: `ok`, `changed`, `failed`, `skipping`, ...
+fn outcome(result: &TaskResult) -> &'static str {
+    match result.status {
+        TaskStatus::Ignored => "ignored",
+        TaskStatus::Rescued => "rescued",
+        TaskStatus::Timeout => "timed out",
+        _ if result.failed => "failed",
+        _ if result.skipped => "skipping",
+        _ if result.changed => "changed",
+        _ => "ok",
+    }
+}
+
+/// Counts of the recap line: ok, changed, failed, skipped, rescued, ignored
+fn recap(host: &str, result: &ExecutionResult) -> String {
+    let summary = &result.summary;
+    let ok = result
+        .task_results
+        .values()
+        .filter(|result| !result.failed && !result.skipped)
+        .count();
+    format!(
+        "{host} : ok={ok} changed={} failed={} skipped={} rescued={} ignored={}",
+        summary.changed_tasks,
+        summary.failed_tasks,
+        summary.skipped_tasks,
+        summary.rescued_tasks,
+        summary.ignored_tasks
+    )
+}
+
+/// Play and task headers, one line per result and a recap, like
+/// `ansible-playbook` prints them
+pub struct DefaultCallback {
+    host: String,
+    console: Console,
+}
+
+impl DefaultCallback {
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            console: Console::stdout(),
+        }
+    }
+
+    /// Write to `output` instead of stdout
+    pub fn with_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.console = Console(Mutex::new(Box::new(output)));
+        self
+    }
+
+    fn header(&self, kind: &str, name: &str) {
+        let header = format!("{kind} [{name}] ");
+        let width = 80usize.saturating_sub(header.len()).max(3);
+        self.console.line("");
+        self.console.line(&format!("{header}{}", "*".repeat(width)));
+    }
+}
+
+impl CallbackPlugin for DefaultCallback {
+    fn name(&self) -> &str {
+        "default"
+    }
+
+    fn on_play_start(&self, play: &Play) {
+        self.header("PLAY", play.name.as_deref().unwrap_or(&play.id));
+    }
+
+    fn on_task_start(&self, task: &Task) {
+        self.header("TASK", &task.name);
+    }
+
+    fn on_task_result(&self, result: &TaskResult) {
+        let mut line = format!("{}: [{}]", outcome(result), self.host);
+        if let Some(error) = result.error.as_ref().filter(|_| result.failed) {
+            line.push_str(&format!(" => {error}"));
+        }
+        self.console.line(&line);
+    }
+
+    fn on_stats(&self, result: &ExecutionResult) {
+        self.header("PLAY RECAP", &self.host);
+        self.console.line(&recap(&self.host, result));
+    }
+}
+
+/// One line per task and a one-line recap
+pub struct DenseCallback {
+    host: String,
+    console: Console,
+    /// Tasks seen so far, which numbers them
+    tasks: Mutex<usize>,
+}
+
+impl DenseCallback {
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            console: Console::stdout(),
+            tasks: Mutex::new(0),
+        }
+    }
+
+    /// Write to `output` instead of stdout
+    pub fn with_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.console = Console(Mutex::new(Box::new(output)));
+        self
+    }
+}
+
+impl CallbackPlugin for DenseCallback {
+    fn name(&self) -> &str {
+        "dense"
+    }
+
+    fn on_task_result(&self, result: &TaskResult) {
+        let number = {
+            let mut tasks = self.tasks.lock().unwrap_or_else(PoisonError::into_inner);
+            *tasks += 1;
+            *tasks
+        };
+        self.console.line(&format!(
+            "[{}] task {number}: {} {}",
+            self.host,
+            result.name,
+            outcome(result)
+        ));
+    }
+
+    fn on_stats(&self, result: &ExecutionResult) {
+        self.console
+            .line(&format!("[{}] {}", self.host, recap(&self.host, result)));
+    }
+}
+
+/// A JUnit XML report of the execution, with a test suite per play and a
+/// test case per task, written to `<dir>/<host>-<execution id>.xml` once the
+/// execution is over
+pub struct JUnitCallback {
+    dir: PathBuf,
+    host: String,
+    state: Mutex<JUnitState>,
+}
+
+#[derive(Default)]
+struct JUnitState {
+    /// The play being executed
+    play: Option<String>,
+    /// Results so far, with the play each belongs to, in order
+    results: Vec<(String, TaskResult)>,
+}
+
+impl JUnitCallback {
+    pub fn new(dir: impl Into<PathBuf>, host: &str) -> Self {
+        Self {
+            dir: dir.into(),
+            host: host.to_string(),
+            state: Mutex::new(JUnitState::default()),
+        }
+    }
+
+    /// Path of the report of the execution `execution_id`
+    pub fn report_path(&self, execution_id: &str) -> PathBuf {
+        self.dir.join(format!("{}-{execution_id}.xml", self.host))
+    }
+
+    fn report(&self, results: &[(String, TaskResult)]) -> String {
+        let mut suites: Vec<(&str, Vec<&TaskResult>)> = Vec::new();
+        for (play, result) in results {
+            match suites.iter_mut().find(|(name, _)| name == play) {
+                Some((_, cases)) => cases.push(result),
+                None => suites.push((play, vec![result])),
+            }
+        }
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+        for (play, cases) in suites {
+            let failures = cases.iter().filter(|case| case.failed).count();
+            let skipped = cases.iter().filter(|case| case.skipped).count();
+            let time: Duration = cases.iter().map(|case| case.duration).sum();
+            xml.push_str(&format!(
+                "  <testsuite name=\"{}\" hostname=\"{}\" tests=\"{}\" failures=\"{failures}\" skipped=\"{skipped}\" time=\"{:.3}\">\n",
+                escape(play),
+                escape(&self.host),
+                cases.len(),
+                time.as_secs_f64()
+            ));
+            for case in cases {
+                xml.push_str(&format!(
+                    "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                    escape(play),
+                    escape(&case.name),
+                    case.duration.as_secs_f64()
+                ));
+                if case.failed {
+                    let message = case.error.as_deref().unwrap_or("failed");
+                    xml.push_str(&format!(
+                        ">\n      <failure message=\"{}\"/>\n    </testcase>\n",
+                        escape(message)
+                    ));
+                } else if case.skipped {
+                    xml.push_str(">\n      <skipped/>\n    </testcase>\n");
+                } else {
+                    xml.push_str("/>\n");
+                }
+            }
+            xml.push_str("  </testsuite>\n");
+        }
+        xml.push_str("</testsuites>\n");
+        xml
+    }
+}
+
+impl CallbackPlugin for JUnitCallback {
+    fn name(&self) -> &str {
+        "junit"
+    }
+
+    fn on_play_start(&self, play: &Play) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.play = Some(play.name.clone().unwrap_or_else(|| play.id.clone()));
+    }
+
+    fn on_task_result(&self, result: &TaskResult) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let play = state.play.clone().unwrap_or_else(|| self.host.clone());
+        state.results.push((play, result.clone()));
+    }
+
+    fn on_stats(&self, result: &ExecutionResult) {
+        let xml = {
+            let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            self.report(&state.results)
+        };
+        let path = self.report_path(&result.execution_id);
+        let written = std::fs::create_dir_all(&self.dir).and_then(|()| std::fs::write(&path, xml));
+        if let Err(e) = written {
+            tracing::warn!("Failed to write JUnit report {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// `value` escaped for an XML attribute
+pub(crate) fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// How long each task took, slowest first, and the whole execution
+pub struct ProfileTasksCallback {
+    console: Console,
+    /// Duration of each task so far, in order
+    timings: Mutex<Vec<(String, Duration)>>,
+}
+
+impl ProfileTasksCallback {
+    pub fn new() -> Self {
+        Self {
+            console: Console::stdout(),
+            timings: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Write to `output` instead of stdout
+    pub fn with_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.console = Console(Mutex::new(Box::new(output)));
+        self
+    }
+}
+
+impl Default for ProfileTasksCallback {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CallbackPlugin for ProfileTasksCallback {
+    fn name(&self) -> &str {
+        "profile_tasks"
+    }
+
+    fn on_task_result(&self, result: &TaskResult) {
+        self.timings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((result.name.clone(), result.duration));
+    }
+
+    fn on_stats(&self, result: &ExecutionResult) {
+        let mut timings = self
+            .timings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        // Stable, so tasks that took as long keep their order
+        timings.sort_by_key(|(_, duration)| std::cmp::Reverse(*duration));
+        self.console.line("");
+        for (name, duration) in timings {
+            self.console
+                .line(&format!("{name:<64} {:>9.2}s", duration.as_secs_f64()));
+        }
+        self.console.line(&format!(
+            "Playbook run took {:.2}s",
+            result.duration.as_secs_f64()
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    /// Output the test reads back after the plugin wrote it
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Captured {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    fn result(name: &str, status: TaskStatus, secs: u64) -> TaskResult {
+        TaskResult {
+            task_id: name.to_string(),
+            name: name.to_string(),
+            failed: status == TaskStatus::Failed,
+            skipped: status == TaskStatus::Skipped,
+            changed: status == TaskStatus::Success,
+            status,
+            output: serde_json::Value::Null,
+            stdout: None,
+            stderr: None,
+            start_time: Utc::now(),
+            end_time: Utc::now(),
+            duration: Duration::from_secs(secs),
+            error: Some("exit code 1 <stderr>".to_string()),
+        }
+    }
+
+    fn execution(results: &[TaskResult]) -> ExecutionResult {
+        let count =
+            |matches: fn(&TaskResult) -> bool| results.iter().filter(|r| matches(r)).count();
+        ExecutionResult {
+            execution_id: "run-1".to_string(),
+            success: false,
+            failed: true,
+            task_results: results
+                .iter()
+                .map(|result| (result.task_id.clone(), result.clone()))
+                .collect(),
+            summary: crate::runtime::ExecutionSummary {
+                total_tasks: results.len(),
+                completed_tasks: results.len(),
+                failed_tasks: count(|r| r.failed),
+                skipped_tasks: count(|r| r.skipped),
+                changed_tasks: count(|r| r.changed),
+                ignored_tasks: 0,
+                rescued_tasks: 0,
+            },
+            start_time: Utc::now(),
+            end_time: Utc::now(),
+            duration: Duration::from_secs(6),
+            errors: vec![],
+            module_metrics: Default::default(),
+            handler_results: vec![],
+            spans: vec![],
+            audit: vec![],
+        }
+    }
+
+    fn play() -> Play {
+        Play {
+            id: "site".to_string(),
+            name: Some("Deploy <web>".to_string()),
+            ..Play::default()
+        }
+    }
+
+    #[test]
+    fn test_default_callback_prints_results_and_recap() {
+        let output = Captured::default();
+        let plugin = DefaultCallback::new("web1").with_output(output.clone());
+        let results = [
+            result("install", TaskStatus::Success, 1),
+            result("migrate", TaskStatus::Failed, 1),
+            result("cleanup", TaskStatus::Skipped, 0),
+        ];
+        plugin.on_play_start(&play());
+        for result in &results {
+            plugin.on_task_result(result);
+        }
+        plugin.on_stats(&execution(&results));
+
+        let text = output.text();
+        assert!(text.contains("PLAY [Deploy <web>] ***"), "{text}");
+        assert!(text.contains("changed: [web1]\n"), "{text}");
+        assert!(
+            text.contains("failed: [web1] => exit code 1 <stderr>\n"),
+            "{text}"
+        );
+        assert!(text.contains("skipping: [web1]\n"), "{text}");
+        assert!(
+            text.ends_with("web1 : ok=1 changed=1 failed=1 skipped=1 rescued=0 ignored=0\n"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn test_junit_callback_writes_a_test_case_per_task() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let plugin = JUnitCallback::new(dir.path(), "web1");
+        let results = [
+            result("install", TaskStatus::Success, 1),
+            result("migrate", TaskStatus::Failed, 2),
+        ];
+        plugin.on_play_start(&play());
+        for result in &results {
+            plugin.on_task_result(result);
+        }
+        plugin.on_stats(&execution(&results));
+
+        let xml = std::fs::read_to_string(plugin.report_path("run-1")).unwrap();
+        assert!(xml.contains(
+            "<testsuite name=\"Deploy &lt;web&gt;\" hostname=\"web1\" tests=\"2\" failures=\"1\" skipped=\"0\" time=\"3.000\">"
+        ), "{xml}");
+        assert!(xml.contains(
+            "<testcase classname=\"Deploy &lt;web&gt;\" name=\"install\" time=\"1.000\"/>"
+        ));
+        assert!(xml.contains("<failure message=\"exit code 1 &lt;stderr&gt;\"/>"));
+    }
+
+    #[test]
+    fn test_profile_tasks_lists_the_slowest_tasks_first() {
+        let output = Captured::default();
+        let plugin = ProfileTasksCallback::new().with_output(output.clone());
+        let results = [
+            result("install", TaskStatus::Success, 1),
+            result("migrate", TaskStatus::Success, 5),
+        ];
+        for result in &results {
+            plugin.on_task_result(result);
+        }
+        plugin.on_stats(&execution(&results));
+
+        let text = output.text();
+        let lines: Vec<&str> = text.lines().filter(|line| !line.is_empty()).collect();
+        assert!(lines[0].starts_with("migrate") && lines[0].ends_with("5.00s"));
+        assert!(lines[1].starts_with("install") && lines[1].ends_with("1.00s"));
+        assert_eq!(lines[2], "Playbook run took 6.00s");
+    }
+}
@@ -1,5 +1,5 @@
 use crate::execution::{Condition, ConditionOperator};
-use crate::runtime::ExecutionError;
+use crate::runtime::{expressions, ExecutionError};
 use serde_json::Value;
 use std::collections::HashMap;
 
@@ -25,6 +25,45 @@ impl ConditionEvaluator {
         Ok(true)
     }
 
+    /// Evaluate a list of Jinja2 expressions (all must be true)
+    pub fn evaluate_expressions(
+        expressions: &[String],
+        context: &ConditionContext,
+    ) -> Result<bool, ExecutionError> {
+        for expression in expressions {
+            if !Self::evaluate_expression(expression, context)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Evaluate a Jinja2 expression such as `result is succeeded and
+    /// ansible_distribution_version is version('22.04', '>=')`
+    pub fn evaluate_expression(
+        expression: &str,
+        context: &ConditionContext,
+    ) -> Result<bool, ExecutionError> {
+        // Variables first, then facts, which are also under `ansible_facts`
+        let lookup = |name: &str| {
+            context
+                .variables
+                .get(name)
+                .or_else(|| context.facts.get(name))
+                .cloned()
+                .or_else(|| {
+                    (name == "ansible_facts")
+                        .then(|| Value::Object(context.facts.clone().into_iter().collect()))
+                })
+        };
+        expressions::is_true(expression, &lookup).map_err(|reason| {
+            ExecutionError::ConditionFailed {
+                condition: format!("{expression}: {reason}"),
+            }
+        })
+    }
+
     /// Evaluate a single condition
     pub fn evaluate_condition(
         condition: &Condition,
@@ -283,4 +322,28 @@ mod tests {
 
         assert!(ConditionEvaluator::evaluate_condition(&condition, &context).unwrap());
     }
+
+    #[test]
+    fn test_expressions_see_registered_results_and_facts() {
+        let context = ConditionContext::new(
+            [(String::from("os_family"), json!("Debian"))].into(),
+            [(
+                String::from("install"),
+                json!({"changed": true, "failed": false, "stdout": "ok"}),
+            )]
+            .into(),
+            HashMap::new(),
+        );
+
+        let expressions = [
+            "install is changed and install.stdout == 'ok'".to_string(),
+            "ansible_facts.os_family == os_family".to_string(),
+        ];
+        assert!(ConditionEvaluator::evaluate_expressions(&expressions, &context).unwrap());
+        assert!(!ConditionEvaluator::evaluate_expression("install is failed", &context).unwrap());
+        assert!(matches!(
+            ConditionEvaluator::evaluate_expression("missing", &context),
+            Err(ExecutionError::ConditionFailed { .. })
+        ));
+    }
 }
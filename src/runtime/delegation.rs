@@ -0,0 +1,201 @@
+//! Delegated and run-once tasks.
+//!
+//! A task with `delegate_to` executes on another host, and a `run_once` task
+//! executes on a single host and shares its result with all others. A runner
+//! cannot reach other hosts itself, so the controller coordinates both: the
+//! runner writes a [`ProgressEvent::DelegationRequested`] frame carrying its
+//! facts and variables on the event stream, then waits for the controller to
+//! drop a [`DelegatedResult`] into the directory named by
+//! [`DELEGATION_DIR_ENV`].
+//!
+//! To execute the task elsewhere, the controller runs the runner deployed to
+//! the delegate host with [`DELEGATED_TASK_ENV`] set, which executes only that
+//! task of the embedded plan in the [`DelegationContext`] passed in
+//! [`DELEGATION_CONTEXT_ENV`]. Tasks delegated to [`LOCALHOST`] execute on the
+//! controller itself.
+//!
+//! [`ProgressEvent::DelegationRequested`]: crate::runtime::ProgressEvent::DelegationRequested
+
+use crate::execution::Task;
+use crate::runtime::{ExecutionError, TaskResult, TaskStatus};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Directory the controller delivers delegated results to
+pub const DELEGATION_DIR_ENV: &str = "RUSTLE_DELEGATION_DIR";
+
+/// Id of the single task a runner should execute on behalf of another host
+pub const DELEGATED_TASK_ENV: &str = "RUSTLE_DELEGATED_TASK";
+
+/// JSON [`DelegationContext`] for [`DELEGATED_TASK_ENV`]
+pub const DELEGATION_CONTEXT_ENV: &str = "RUSTLE_DELEGATION_CONTEXT";
+
+/// `delegate_to` value naming the controller
+pub const LOCALHOST: &str = "localhost";
+
+/// How often a waiting runner checks for its result
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The variable context of the host a delegated task was planned for
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DelegationContext {
+    pub host: String,
+    #[serde(default)]
+    pub facts: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    pub variables: HashMap<String, serde_json::Value>,
+}
+
+impl DelegationContext {
+    /// Variables to execute the task with on the delegate: the planned
+    /// host's facts and variables, and its name as `inventory_hostname`
+    pub fn into_variables(self) -> HashMap<String, serde_json::Value> {
+        let mut variables = self.facts;
+        variables.extend(self.variables);
+        variables.insert(
+            "inventory_hostname".to_string(),
+            serde_json::Value::String(self.host),
+        );
+        variables
+    }
+}
+
+/// The outcome of a delegated task, handed back to the runner that asked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DelegatedResult {
+    pub task_result: TaskResult,
+    /// Facts the task set, which every host sharing the result adopts
+    #[serde(default)]
+    pub facts: HashMap<String, serde_json::Value>,
+}
+
+impl DelegatedResult {
+    /// Wrap `task_result`, picking up the facts it set under `ansible_facts`
+    pub fn new(task_result: TaskResult) -> Self {
+        let facts = match task_result.output.get("ansible_facts") {
+            Some(serde_json::Value::Object(facts)) => facts.clone().into_iter().collect(),
+            _ => HashMap::new(),
+        };
+        Self { task_result, facts }
+    }
+
+    /// A failed result for a task that could not be delegated at all
+    pub fn failed(task_id: &str, name: &str, error: String) -> Self {
+        let now = Utc::now();
+        Self::new(TaskResult {
+            task_id: task_id.to_string(),
+            name: name.to_string(),
+            status: TaskStatus::Failed,
+            changed: false,
+            failed: true,
+            skipped: false,
+            output: serde_json::Value::Null,
+            stdout: None,
+            stderr: None,
+            start_time: now,
+            end_time: now,
+            duration: Duration::ZERO,
+            error: Some(error),
+        })
+    }
+}
+
+/// Whether the runner on `host` has to hand `task` to the controller
+pub fn needs_controller(task: &Task, host: Option<&str>) -> bool {
+    task.run_once
+        || task
+            .delegate_to
+            .as_deref()
+            .is_some_and(|delegate| Some(delegate) != host)
+}
+
+/// Name of the file the result of `task_id` is delivered in
+pub fn result_file_name(task_id: &str) -> String {
+    let name: String = task_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{name}.json")
+}
+
+pub fn result_path(dir: &Path, task_id: &str) -> PathBuf {
+    dir.join(result_file_name(task_id))
+}
+
+/// Wait up to `timeout` for the controller to deliver the result of `task_id`
+pub async fn wait_for_result(
+    dir: &Path,
+    task_id: &str,
+    timeout: Duration,
+) -> Result<DelegatedResult, ExecutionError> {
+    let path = result_path(dir, task_id);
+    let start = Instant::now();
+    loop {
+        // The controller renames complete results into place, but a result
+        // that does not parse yet is simply waited for again
+        if let Ok(data) = tokio::fs::read(&path).await {
+            if let Ok(result) = serde_json::from_slice(&data) {
+                let _ = tokio::fs::remove_file(&path).await;
+                return Ok(result);
+            }
+        }
+        if start.elapsed() >= timeout {
+            return Err(ExecutionError::TaskTimeout {
+                task_id: task_id.to_string(),
+                timeout: timeout.as_secs(),
+            });
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delegated_results_share_set_facts() {
+        let mut result = DelegatedResult::failed("t1", "Pick leader", "boom".to_string());
+        assert!(result.facts.is_empty());
+
+        result.task_result.output = serde_json::json!({"ansible_facts": {"leader": "db1"}});
+        let result = DelegatedResult::new(result.task_result);
+        assert_eq!(result.facts["leader"], "db1");
+        assert_eq!(result_file_name("play/1 task"), "play_1_task.json");
+
+        let context = DelegationContext {
+            host: "web1".to_string(),
+            facts: HashMap::from([("os".to_string(), serde_json::json!("linux"))]),
+            variables: HashMap::from([("port".to_string(), serde_json::json!(80))]),
+        };
+        let variables = context.into_variables();
+        assert_eq!(variables["inventory_hostname"], "web1");
+        assert_eq!(variables["os"], "linux");
+        assert_eq!(variables["port"], 80);
+    }
+
+    #[tokio::test]
+    async fn test_runner_waits_for_delivered_result() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let timeout = wait_for_result(dir.path(), "t1", Duration::from_millis(150)).await;
+        assert!(matches!(timeout, Err(ExecutionError::TaskTimeout { .. })));
+
+        let path = result_path(dir.path(), "t1");
+        let delivered = DelegatedResult::failed("t1", "Pick leader", "boom".to_string());
+        std::fs::write(&path, serde_json::to_vec(&delivered).unwrap()).unwrap();
+        let received = wait_for_result(dir.path(), "t1", Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(received.task_result.error.as_deref(), Some("boom"));
+        assert!(!path.exists());
+    }
+}
@@ -14,6 +14,18 @@ pub enum ExecutionError {
     #[error("Task timeout: {task_id} ({timeout}s)")]
     TaskTimeout { task_id: String, timeout: u64 },
 
+    #[error("Play timeout: {play_id} ({timeout}s)")]
+    PlayTimeout { play_id: String, timeout: u64 },
+
+    #[error("Task {task_id} failed in play {play_id}, which has any_errors_fatal")]
+    AnyErrorsFatal { task_id: String, play_id: String },
+
+    #[error("Play aborted: {reason}")]
+    PlayAborted { reason: String },
+
+    #[error("Run stopped: {reason}")]
+    Stopped { reason: String },
+
     #[error("Condition evaluation failed: {condition}")]
     ConditionFailed { condition: String },
 
@@ -42,6 +54,58 @@ pub enum ExecutionError {
     Report(#[from] ReportError),
 }
 
+#[derive(Debug, Error)]
+pub enum LookupError {
+    #[error("Unknown lookup plugin: {plugin}")]
+    UnknownPlugin { plugin: String },
+
+    #[error("Lookup {plugin} is evaluated on the controller, which left it unresolved")]
+    ControllerOnly { plugin: String },
+
+    #[error("Missing configuration for {plugin} lookups: {reason}")]
+    Configuration { plugin: String, reason: String },
+
+    #[error("Lookup {plugin}('{term}') failed: {reason}")]
+    Failed {
+        plugin: String,
+        term: String,
+        reason: String,
+    },
+
+    #[error("Invalid lookup expression '{expression}': {reason}")]
+    Expression { expression: String, reason: String },
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum SealedSecretError {
+    #[error("Invalid sealed secrets key: {reason}")]
+    InvalidKey { reason: String },
+
+    #[error("Failed to seal secrets")]
+    Encryption,
+
+    #[error("Failed to open sealed secrets")]
+    Decryption,
+
+    #[error("Unsupported sealed secrets format version {version}")]
+    UnsupportedBundle { version: u32 },
+
+    #[error("Sealed secrets were delivered without their key")]
+    MissingKey,
+
+    #[error("No sealed secret {id} was delivered")]
+    UnknownReference { id: String },
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
 #[derive(Debug, Error)]
 pub enum FactsError {
     #[error("Command failed: {command} - {error}")]
@@ -77,3 +141,119 @@ pub enum CleanupError {
     #[error("Cleanup failed: {reason}")]
     CleanupFailed { reason: String },
 }
+
+#[derive(Debug, Error)]
+pub enum SelfUpdateError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid version {version}: {reason}")]
+    InvalidVersion { version: String, reason: String },
+
+    #[error("Pinned version {version} is not published in the artifact repository")]
+    PinnedVersionMissing { version: String },
+
+    #[error("Release signature rejected: {0}")]
+    Signature(#[from] SignatureError),
+
+    #[error("Release {version} is signed for {field} {signed}, not {expected}")]
+    ManifestMismatch {
+        version: String,
+        field: String,
+        expected: String,
+        signed: String,
+    },
+
+    #[error("No previous version to roll back to at {path}")]
+    NoPreviousVersion { path: String },
+}
+
+#[derive(Debug, Error)]
+pub enum AgentError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Plan signature rejected: {0}")]
+    Signature(#[from] SignatureError),
+
+    #[error("Execution failed: {0}")]
+    Execution(#[from] ExecutionError),
+
+    #[error("Agent misconfigured: {reason}")]
+    Configuration { reason: String },
+
+    #[error("Agent refused the plan: {reason}")]
+    Rejected { reason: String },
+}
+
+#[derive(Debug, Error)]
+pub enum SignatureError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid signing key: {reason}")]
+    InvalidKey { reason: String },
+
+    #[error("Malformed signature: {reason}")]
+    Malformed { reason: String },
+
+    #[error("Signed by key {actual}, expected key {expected}")]
+    UnknownKey { expected: String, actual: String },
+
+    #[error("Signature does not match the binary")]
+    InvalidSignature,
+
+    #[error("Checksum mismatch: signed {expected}, got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+}
+
+#[derive(Debug, Error)]
+pub enum ResultUploadError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Invalid object store configuration: {reason}")]
+    InvalidStore { reason: String },
+
+    #[error("No S3 credentials configured or found in the environment")]
+    MissingCredentials,
+
+    #[error("Object store returned HTTP {status} for {key}")]
+    Storage { key: String, status: u16 },
+
+    #[error("Invalid result encryption key: {reason}")]
+    InvalidKey { reason: String },
+
+    #[error("Failed to encrypt result bundle")]
+    Encryption,
+
+    #[error("Failed to decrypt result bundle")]
+    Decryption,
+
+    #[error("Unsupported result bundle format version {version}")]
+    UnsupportedBundle { version: u32 },
+
+    #[error("Object store unreachable: {reason}")]
+    Unreachable { reason: String },
+}
+
+#[derive(Debug, Error)]
+pub enum FaultSpecError {
+    #[error("Invalid fault rule '{rule}': {reason}")]
+    InvalidRule { rule: String, reason: String },
+
+    #[error("Invalid fault seed: {0}")]
+    InvalidSeed(String),
+}
@@ -0,0 +1,180 @@
+//! Streaming of runner events back to the controller over the output channel.
+//!
+//! When the deployer sets [`EVENT_STREAM_ENV`], the runner writes every
+//! [`ProgressEvent`] to stdout as one frame per line:
+//!
+//! ```text
+//! @rustle:<length>:<json>\n
+//! ```
+//!
+//! where `<length>` is the byte length of the JSON document. Frames share
+//! stdout with ordinary log lines; [`EventDecoder`] picks them out of the
+//! output chunks as they arrive and the length guards against frames that
+//! were interleaved with other output or cut short.
+
+use crate::runtime::ProgressEvent;
+use std::io::Write;
+
+/// Environment variable asking a runner to stream events on stdout
+pub const EVENT_STREAM_ENV: &str = "RUSTLE_EVENT_STREAM";
+
+/// Marker that starts every event frame
+pub const FRAME_PREFIX: &str = "@rustle:";
+
+/// Whether the deployer asked this runner to stream events
+pub fn event_stream_requested() -> bool {
+    std::env::var(EVENT_STREAM_ENV).is_ok_and(|value| value == "1")
+}
+
+/// Encode `event` as one frame, including the trailing newline
+pub fn encode_frame(event: &ProgressEvent) -> Result<String, serde_json::Error> {
+    let json = serde_json::to_string(event)?;
+    Ok(format!("{FRAME_PREFIX}{}:{json}\n", json.len()))
+}
+
+/// Write `event` to stdout as a single frame
+pub fn write_frame(event: &ProgressEvent) -> Result<(), serde_json::Error> {
+    let frame = encode_frame(event)?;
+    let mut stdout = std::io::stdout().lock();
+    if let Err(e) = stdout
+        .write_all(frame.as_bytes())
+        .and_then(|_| stdout.flush())
+    {
+        tracing::warn!("Failed to stream event: {}", e);
+    }
+    Ok(())
+}
+
+/// A line of runner output, sorted into events and everything else
+#[derive(Debug, Clone)]
+pub enum StreamItem {
+    Event(Box<ProgressEvent>),
+    Output(String),
+    /// A line that looked like a frame but could not be decoded
+    Malformed {
+        line: String,
+        reason: String,
+    },
+}
+
+/// Splits output chunks into lines and decodes the frames among them
+#[derive(Debug, Default)]
+pub struct EventDecoder {
+    partial: String,
+}
+
+impl EventDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a chunk of output, returning the lines it completed
+    pub fn push(&mut self, data: &str) -> Vec<StreamItem> {
+        self.partial.push_str(data);
+        let Some(end) = self.partial.rfind('\n') else {
+            return Vec::new();
+        };
+        let rest = self.partial.split_off(end + 1);
+        let complete = std::mem::replace(&mut self.partial, rest);
+        complete.lines().map(decode_line).collect()
+    }
+
+    /// Decode whatever is left once the output has ended
+    pub fn finish(&mut self) -> Option<StreamItem> {
+        let rest = std::mem::take(&mut self.partial);
+        (!rest.is_empty()).then(|| decode_line(&rest))
+    }
+}
+
+/// Decode one line of output
+pub fn decode_line(line: &str) -> StreamItem {
+    let line = line.trim_end_matches('\r');
+    let Some(frame) = line.trim_start().strip_prefix(FRAME_PREFIX) else {
+        return StreamItem::Output(line.to_string());
+    };
+    let malformed = |reason: String| StreamItem::Malformed {
+        line: line.to_string(),
+        reason,
+    };
+
+    let Some((length, json)) = frame.split_once(':') else {
+        return malformed("missing frame length".to_string());
+    };
+    let Ok(length) = length.parse::<usize>() else {
+        return malformed(format!("invalid frame length {length:?}"));
+    };
+    if json.len() != length {
+        return malformed(format!(
+            "frame announced {length} bytes but carried {}",
+            json.len()
+        ));
+    }
+    match serde_json::from_str(json) {
+        Ok(event) => StreamItem::Event(Box::new(event)),
+        Err(e) => malformed(e.to_string()),
+    }
+}
+
+/// Decode every event in a finished run's output
+pub fn decode_events(output: &str) -> Vec<ProgressEvent> {
+    let mut decoder = EventDecoder::new();
+    decoder
+        .push(output)
+        .into_iter()
+        .chain(decoder.finish())
+        .filter_map(|item| match item {
+            StreamItem::Event(event) => Some(*event),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_are_picked_out_of_split_chunks() {
+        let event = ProgressEvent::TaskStarted {
+            execution_id: "e1".to_string(),
+            task_id: "t1".to_string(),
+            task_name: "Install nginx".to_string(),
+        };
+        let frame = encode_frame(&event).unwrap();
+        let output = format!("starting up\n{frame}done\n");
+        let (head, tail) = output.split_at(20);
+
+        let mut decoder = EventDecoder::new();
+        let mut items = decoder.push(head);
+        items.extend(decoder.push(tail));
+        assert!(decoder.finish().is_none());
+
+        assert_eq!(items.len(), 3);
+        assert!(matches!(&items[0], StreamItem::Output(line) if line == "starting up"));
+        assert!(matches!(
+            &items[1],
+            StreamItem::Event(event)
+                if matches!(event.as_ref(), ProgressEvent::TaskStarted { task_id, .. } if task_id == "t1")
+        ));
+        assert!(matches!(&items[2], StreamItem::Output(line) if line == "done"));
+    }
+
+    #[test]
+    fn test_truncated_frames_are_rejected() {
+        let frame = encode_frame(&ProgressEvent::ExecutionFailed {
+            execution_id: "e1".to_string(),
+            error: "boom".to_string(),
+        })
+        .unwrap();
+        let truncated = &frame[..frame.len() - 5];
+        assert!(matches!(
+            decode_line(truncated),
+            StreamItem::Malformed { .. }
+        ));
+        assert!(matches!(
+            decode_line("@rustle:abc:{}"),
+            StreamItem::Malformed { .. }
+        ));
+        assert_eq!(decode_events(&frame).len(), 1);
+    }
+}
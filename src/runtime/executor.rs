@@ -1,20 +1,52 @@
-use crate::execution::{ExecutionPlan, Task};
-use crate::modules::{ExecutionContext, HostInfo, ModuleArgs, ModuleRegistry, SpecialParameters};
+use crate::execution::{
+    AsyncPolicy, BecomePolicy, Block, ExecutionPlan, FailurePolicy, Handler, Play, ResultCondition,
+    TargetSelector, Task, TaskType,
+};
+use crate::modules::core::async_status::{default_async_dir, job_path, write_job, ASYNC_DIR_ENV};
+use crate::modules::{
+    ExecutionContext, HostInfo, ModuleArgs, ModuleRegistry, ModuleResult, SpecialParameters,
+};
 use crate::runtime::{
+    audit::AuditEntry,
+    callbacks::builtin_callback,
     conditions::{ConditionContext, ConditionEvaluator},
+    delegation::{needs_controller, wait_for_result, DelegatedResult, DelegationContext},
     error::{CleanupError, ExecutionError},
+    event_stream::event_stream_requested,
     facts::FactsCache,
-    progress::ProgressReporter,
-    state::{ExecutionResult, StateManager, TaskResult, TaskStatus},
+    fault_injection::FaultInjector,
+    lookups::{has_lookups, SecretLookups},
+    loops::{loop_items, loop_var, loop_variables, render},
+    privilege::{self, resolve_become},
+    progress::{CallbackPlugin, ProgressReporter},
+    result_upload::ResultUploader,
+    sealed_secrets::{has_secret_references, SecretValues},
+    shutdown::StopRequest,
+    state::{
+        ExecutionResult, PersistedState, StateManager, TaskResult, TaskStatus, NO_LOG_MESSAGE,
+        RESUME_ENV, STATE_FILE_ENV,
+    },
+    strategy::{wait_for_release, HostStrategy},
+    telemetry::{SpanRecorder, TraceContext},
+    DELEGATION_DIR_ENV, HOST_ID_ENV, LOCALHOST,
 };
 use chrono::Utc;
 use petgraph::{algo::toposort, Graph};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
 use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+/// Variable an attempt's result is bound to for `until`, `failed_when` and
+/// `changed_when` of a task without a `register`
+pub const DEFAULT_REGISTER: &str = "result";
+
 /// Runtime configuration for the executor
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RuntimeConfig {
@@ -28,12 +60,49 @@ pub struct RuntimeConfig {
     pub cleanup_on_completion: bool,
     pub log_level: String,
     pub check_mode: Option<bool>,
+    /// Forks within the host: how many ready tasks marked `parallel` may
+    /// execute side by side
     pub parallel_tasks: Option<usize>,
     #[serde(with = "serde_duration")]
     pub facts_cache_ttl: Duration,
     pub retry_policy: Option<RetryPolicyConfig>,
     #[serde(default)]
     pub verbose: bool,
+    /// Optional channel for long-lived runners to update themselves
+    #[serde(default)]
+    pub self_update: Option<crate::runtime::SelfUpdateConfig>,
+    /// Upload encrypted results to object storage for hosts that cannot reach the controller
+    #[serde(default)]
+    pub result_upload: Option<crate::runtime::ResultUploadConfig>,
+    /// Simulated failures for testing; `RUSTLE_FAULTS` overrides this
+    #[serde(default)]
+    pub fault_injection: Option<crate::runtime::FaultInjectionConfig>,
+    /// Minisign public key the runner checks its own executable against before running
+    #[serde(default)]
+    pub signing_public_key: Option<String>,
+    /// Stay resident after the embedded plan and run signed plans pulled or pushed later
+    #[serde(default)]
+    pub agent: Option<crate::runtime::AgentConfig>,
+    /// Where async jobs keep their state; `RUSTLE_ASYNC_DIR` or
+    /// `~/.rustle_async` when unset
+    #[serde(default)]
+    pub async_dir: Option<PathBuf>,
+    /// How tasks become another user unless they say otherwise
+    #[serde(default)]
+    pub r#become: Option<BecomePolicy>,
+    /// Where the runner persists its progress for failed runs to resume
+    /// from; `RUSTLE_STATE_FILE` overrides it, and nothing is persisted
+    /// without either
+    #[serde(default)]
+    pub state_file: Option<PathBuf>,
+    /// Built-in callback plugins the runner reports to, by name: `default`,
+    /// `dense`, `junit` or `profile_tasks`
+    #[serde(default)]
+    pub callbacks: Vec<String>,
+    /// How `lookup()` expressions in task arguments reach the secret
+    /// stores; they are resolved on the target just before the task runs
+    #[serde(default)]
+    pub lookups: Option<crate::runtime::LookupConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,6 +134,16 @@ impl Default for RuntimeConfig {
             facts_cache_ttl: Duration::from_secs(300), // 5 minutes
             retry_policy: None,
             verbose: false,
+            self_update: None,
+            result_upload: None,
+            fault_injection: None,
+            signing_public_key: None,
+            agent: None,
+            async_dir: None,
+            r#become: None,
+            state_file: None,
+            callbacks: Vec::new(),
+            lookups: None,
         }
     }
 }
@@ -72,29 +151,185 @@ impl Default for RuntimeConfig {
 /// Main execution engine for embedded execution plans
 pub struct LocalExecutor {
     config: RuntimeConfig,
-    module_registry: ModuleRegistry,
+    module_registry: Arc<ModuleRegistry>,
     facts_cache: FactsCache,
     state_manager: StateManager,
     progress_reporter: ProgressReporter,
+    faults: FaultInjector,
     execution_id: String,
+    variables: HashMap<String, serde_json::Value>,
+    /// Inventory name of this host, as given by the deployer
+    host_id: Option<String>,
+    /// Where the controller delivers delegated results, when it coordinates
+    delegation_dir: Option<PathBuf>,
+    handlers: Vec<Handler>,
+    /// Ids of the handlers notified since they last ran
+    notified: HashSet<String>,
+    blocks: HashMap<String, Block>,
+    /// Id of the block each task and nested block belongs to
+    parent_blocks: HashMap<String, String>,
+    async_dir: PathBuf,
+    /// Async jobs nobody waited for, by job id
+    async_jobs: Vec<(String, JoinHandle<AsyncJobState>)>,
+    /// Password for the become method, as the controller handed it over
+    become_password: Option<String>,
+    state_file: Option<PathBuf>,
+    /// Continue from the progress in the state file instead of starting over
+    resume: bool,
+    /// Id of the plan being executed, which persisted progress belongs to
+    plan_id: String,
+    /// Settings of the plays, by play id
+    plays: HashMap<String, Play>,
+    /// When the play being executed has to be done by
+    play_deadline: Option<PlayDeadline>,
+    strategy: HostStrategy,
+    /// Position of each task in the plan, by task id
+    positions: HashMap<String, usize>,
+    /// Records trace spans for the controller, when it traces the execution
+    spans: Option<Arc<SpanRecorder>>,
+    /// Changes tasks made, shared with forks; returned in
+    /// [`ExecutionResult::audit`]
+    audit: Arc<Mutex<Vec<AuditEntry>>>,
+    /// Resolves secrets lookups, sharing what it fetched with forks
+    lookups: Arc<SecretLookups>,
+    /// Secrets the controller delivered sealed, for the references to them
+    sealed_secrets: Option<Arc<SecretValues>>,
+    /// Whether the run has to stop before its next task
+    stop: StopRequest,
+}
+
+/// The deadline of a play with a timeout
+#[derive(Clone)]
+struct PlayDeadline {
+    play_id: String,
+    timeout: Duration,
+    at: Instant,
 }
 
+/// The contents of an async job file
+type AsyncJobState = serde_json::Map<String, serde_json::Value>;
+
+/// The failure a block did not recover from, if any
+type BlockOutcome<'a> =
+    Pin<Box<dyn Future<Output = Result<Option<TaskResult>, ExecutionError>> + Send + 'a>>;
+
 impl LocalExecutor {
     pub fn new(config: RuntimeConfig) -> Self {
         let execution_id = Uuid::new_v4().to_string();
         let facts_cache = FactsCache::new(config.facts_cache_ttl);
-        let progress_reporter = ProgressReporter::new(config.controller_endpoint.clone());
+        let faults = FaultInjector::from_env_or(config.fault_injection.as_ref());
+        let host_id = std::env::var(HOST_ID_ENV).ok();
+        let mut progress_reporter = ProgressReporter::new(config.controller_endpoint.clone())
+            .with_fault_injector(faults.clone())
+            .with_event_stream(event_stream_requested());
+        for name in &config.callbacks {
+            match builtin_callback(name, host_id.as_deref().unwrap_or(LOCALHOST)) {
+                Some(plugin) => progress_reporter = progress_reporter.with_callback(plugin),
+                None => tracing::warn!("Unknown callback plugin '{}'", name),
+            }
+        }
+        let spans = TraceContext::from_env().map(|parent| {
+            Arc::new(SpanRecorder::new(
+                host_id.as_deref().unwrap_or(LOCALHOST),
+                parent,
+            ))
+        });
+        if let Some(recorder) = &spans {
+            progress_reporter = progress_reporter.with_callback(recorder.clone());
+        }
+        let async_dir = config.async_dir.clone().unwrap_or_else(default_async_dir);
+        let lookups = Arc::new(SecretLookups::new(
+            config.lookups.clone().unwrap_or_default(),
+        ));
+        let state_file = std::env::var_os(STATE_FILE_ENV)
+            .map(PathBuf::from)
+            .or_else(|| config.state_file.clone());
 
         Self {
-            module_registry: ModuleRegistry::with_core_modules(),
+            module_registry: Arc::new(ModuleRegistry::with_core_modules()),
             state_manager: StateManager::new(execution_id.clone(), 0), // Will be updated when plan is loaded
             facts_cache,
             progress_reporter,
+            faults,
             execution_id,
             config,
+            variables: HashMap::new(),
+            host_id,
+            delegation_dir: std::env::var_os(DELEGATION_DIR_ENV).map(PathBuf::from),
+            handlers: Vec::new(),
+            notified: HashSet::new(),
+            blocks: HashMap::new(),
+            parent_blocks: HashMap::new(),
+            async_dir,
+            async_jobs: Vec::new(),
+            become_password: None,
+            state_file,
+            resume: std::env::var_os(RESUME_ENV).is_some_and(|value| !value.is_empty()),
+            plan_id: String::new(),
+            plays: HashMap::new(),
+            play_deadline: None,
+            strategy: HostStrategy::default(),
+            positions: HashMap::new(),
+            spans,
+            audit: Arc::default(),
+            lookups,
+            sealed_secrets: None,
+            stop: StopRequest::from_env(),
         }
     }
 
+    /// Variables tasks are executed and their conditions evaluated with
+    pub fn with_variables(mut self, variables: HashMap<String, serde_json::Value>) -> Self {
+        self.variables = variables;
+        self
+    }
+
+    /// Hand the lifecycle events of executions to `plugin` as well
+    pub fn with_callback(mut self, plugin: Arc<dyn CallbackPlugin>) -> Self {
+        self.progress_reporter = self.progress_reporter.with_callback(plugin);
+        self
+    }
+
+    /// Record trace spans of executions under `parent`, returned in
+    /// [`ExecutionResult::spans`]
+    pub fn with_trace_context(mut self, parent: TraceContext) -> Self {
+        let recorder = Arc::new(SpanRecorder::new(
+            self.host_id.as_deref().unwrap_or(LOCALHOST),
+            parent,
+        ));
+        self.progress_reporter = self.progress_reporter.with_callback(recorder.clone());
+        self.spans = Some(recorder);
+        self
+    }
+
+    /// Password tasks that become another user authenticate with
+    pub fn with_become_password(mut self, password: impl Into<String>) -> Self {
+        self.become_password = Some(password.into());
+        self
+    }
+
+    /// Secrets the references in task arguments stand for; see
+    /// [`crate::runtime::sealed_secrets`]
+    pub fn with_sealed_secrets(mut self, secrets: SecretValues) -> Self {
+        self.sealed_secrets = Some(Arc::new(secrets));
+        self
+    }
+
+    /// Continue from the progress a failed run left in the state file: its
+    /// completed tasks are not executed again, and its registered results
+    /// and notified handlers carry over
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Stop before the next task once `token` is cancelled, keeping the
+    /// progress so far for [`Self::with_resume`]
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.stop = self.stop.with_token(token);
+        self
+    }
+
     /// Execute a complete execution plan
     pub async fn execute_plan(
         &mut self,
@@ -104,6 +339,41 @@ impl LocalExecutor {
 
         // Initialize state manager with correct task count
         self.state_manager = StateManager::new(self.execution_id.clone(), plan.tasks.len());
+        self.handlers = plan.handlers.clone();
+        self.notified.clear();
+        self.blocks = plan
+            .blocks
+            .iter()
+            .map(|block| (block.id.clone(), block.clone()))
+            .collect();
+        self.parent_blocks = plan
+            .blocks
+            .iter()
+            .flat_map(|block| {
+                block
+                    .block
+                    .iter()
+                    .chain(&block.rescue)
+                    .chain(&block.always)
+                    .map(|member| (member.clone(), block.id.clone()))
+            })
+            .collect();
+        self.plan_id = plan.metadata.plan_id.clone();
+        self.plays = plan
+            .plays
+            .iter()
+            .map(|play| (play.id.clone(), play.clone()))
+            .collect();
+        self.strategy = HostStrategy::from_env(&plan.strategy);
+        self.positions = plan
+            .tasks
+            .iter()
+            .enumerate()
+            .map(|(position, task)| (task.id.clone(), position))
+            .collect();
+        if self.resume {
+            self.restore_progress();
+        }
 
         tracing::info!("Starting execution of plan with {} tasks", plan.tasks.len());
 
@@ -113,12 +383,31 @@ impl LocalExecutor {
             .await?;
 
         // Collect and cache facts
-        if let Err(e) = self.collect_facts() {
-            tracing::warn!("Failed to collect facts: {}", e);
+        match self.collect_facts() {
+            Ok(()) => {
+                self.progress_reporter
+                    .report_facts(&self.execution_id, self.facts_cache.get_all_facts())
+                    .await?;
+            }
+            Err(e) => tracing::warn!("Failed to collect facts: {}", e),
         }
 
         // Execute all tasks
-        let result = match self.execute_tasks(&plan.tasks).await {
+        let outcome = self.execute_plays(&plan.tasks).await;
+        // Jobs still running would end with the runner
+        for (jid, job) in std::mem::take(&mut self.async_jobs) {
+            if !job.is_finished() {
+                tracing::info!("Waiting for async job {}", jid);
+            }
+            let _ = job.await;
+        }
+        if let Some(dir) = &self.delegation_dir {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+        if let Some(dir) = self.strategy.sync_dir(true) {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+        let mut result = match outcome {
             Ok(_) => {
                 let end_time = Utc::now();
                 self.state_manager.build_execution_result(end_time)
@@ -137,11 +426,34 @@ impl LocalExecutor {
             }
         };
 
+        // A later run has nothing to resume once every task succeeded
+        if let (Some(path), false) = (&self.state_file, result.failed) {
+            if let Err(e) = std::fs::remove_file(path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    tracing::warn!("Failed to remove state file {}: {}", path.display(), e);
+                }
+            }
+        }
+
+        result.audit =
+            std::mem::take(&mut *self.audit.lock().unwrap_or_else(PoisonError::into_inner));
+        if let Some(recorder) = &self.spans {
+            result.spans = recorder.finish(&result);
+        }
+
         // Report execution completion
         self.progress_reporter
             .report_execution_complete(&result)
             .await?;
 
+        if let Some(ref upload_config) = self.config.result_upload {
+            let uploader =
+                ResultUploader::new(upload_config.clone()).with_fault_injector(self.faults.clone());
+            if let Err(e) = uploader.upload(&result).await {
+                tracing::warn!("Failed to upload result bundle: {}", e);
+            }
+        }
+
         // Cleanup if configured
         if self.config.cleanup_on_completion {
             if let Err(e) = self.cleanup() {
@@ -160,6 +472,74 @@ impl LocalExecutor {
         Ok(result)
     }
 
+    /// Execute the tasks play by play, running the handlers notified during
+    /// a play once it ends
+    async fn execute_plays(&mut self, tasks: &[Task]) -> Result<(), ExecutionError> {
+        for play in tasks.chunk_by(|a, b| a.play_id == b.play_id) {
+            self.check_stop()?;
+            let play_id = play[0].play_id.clone();
+            if let Some(id) = &play_id {
+                let settings = self.plays.get(id).cloned().unwrap_or_else(|| Play {
+                    id: id.clone(),
+                    ..Play::default()
+                });
+                self.progress_reporter
+                    .report_play_start(&self.execution_id, &settings)
+                    .await?;
+            }
+            self.play_deadline = play_id.as_ref().and_then(|id| {
+                let timeout = self.plays.get(id)?.timeout?;
+                Some(PlayDeadline {
+                    play_id: id.clone(),
+                    timeout,
+                    at: Instant::now() + timeout,
+                })
+            });
+            self.state_manager.set_current_play(play_id);
+            let outcome = async {
+                self.execute_tasks(play).await?;
+                self.flush_handlers().await
+            }
+            .await;
+            self.play_deadline = None;
+            outcome?;
+        }
+        Ok(())
+    }
+
+    /// Fail once the deadline of the play being executed has passed
+    fn check_play_deadline(&self) -> Result<(), ExecutionError> {
+        match &self.play_deadline {
+            Some(deadline) if Instant::now() >= deadline.at => Err(ExecutionError::PlayTimeout {
+                play_id: deadline.play_id.clone(),
+                timeout: deadline.timeout.as_secs(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fail once the run has been asked to stop
+    fn check_stop(&self) -> Result<(), ExecutionError> {
+        match self.stop.reason() {
+            Some(reason) => Err(ExecutionError::Stopped { reason }),
+            None => Ok(()),
+        }
+    }
+
+    /// How long the module of `task` may run: its timeout, cut short by the
+    /// deadline of the play
+    fn task_timeout(&self, task: &Task) -> Option<Duration> {
+        let timeout = task.timeout.or(self.config.task_timeout);
+        let remaining = self
+            .play_deadline
+            .as_ref()
+            .map(|deadline| deadline.at.saturating_duration_since(Instant::now()));
+        match (timeout, remaining) {
+            (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+            (timeout, remaining) => timeout.or(remaining),
+        }
+    }
+
     /// Execute tasks with dependency resolution
     async fn execute_tasks(&mut self, tasks: &[Task]) -> Result<(), ExecutionError> {
         if tasks.is_empty() {
@@ -169,11 +549,24 @@ impl LocalExecutor {
         // Build dependency graph
         let dependency_graph = self.build_dependency_graph(tasks)?;
 
-        // Execute tasks in dependency order
-        let mut completed = HashSet::new();
+        // Execute tasks in dependency order. Tasks of earlier plays that
+        // succeeded satisfy dependencies as well.
+        let mut completed: HashSet<String> = self
+            .state_manager
+            .get_all_task_results()
+            .values()
+            .filter(|result| !result.failed)
+            .map(|result| result.task_id.clone())
+            .collect();
         let mut failed = HashSet::new();
+        let by_id: HashMap<&str, &Task> = tasks.iter().map(|t| (t.id.as_str(), t)).collect();
 
-        while completed.len() + failed.len() < tasks.len() {
+        while tasks
+            .iter()
+            .any(|t| !completed.contains(&t.id) && !failed.contains(&t.id))
+        {
+            self.check_play_deadline()?;
+            self.check_stop()?;
             let ready_tasks = self.find_ready_tasks(tasks, &dependency_graph, &completed, &failed);
 
             if ready_tasks.is_empty() {
@@ -186,42 +579,591 @@ impl LocalExecutor {
                 return Err(ExecutionError::DependencyCycle { cycle: remaining });
             }
 
-            // Execute ready tasks (potentially in parallel)
-            let max_parallel = self.config.parallel_tasks.unwrap_or(1);
-            for chunk in ready_tasks.chunks(max_parallel) {
-                let mut results = Vec::new();
-                for task in chunk {
-                    let result = self.execute_task(task).await;
-                    results.push(result);
+            // Execute ready tasks in order, side by side where they may
+            let forks = self.config.parallel_tasks.unwrap_or(1).max(1);
+            let mut next = 0;
+            while next < ready_tasks.len() {
+                let task = ready_tasks[next];
+                // Already run as part of its block
+                if completed.contains(&task.id) || failed.contains(&task.id) {
+                    next += 1;
+                    continue;
                 }
 
-                for result in results {
-                    match result {
-                        Ok(task_result) => {
-                            let task_id = task_result.task_id.clone();
-                            if task_result.failed {
-                                failed.insert(task_id.clone());
-                            } else {
-                                completed.insert(task_id.clone());
-                            }
-                            self.state_manager.add_task_result(task_result);
-                        }
-                        Err(e) => {
-                            return Err(e);
+                // A task of a block runs the whole outermost block,
+                // which leaves a result for each of its tasks
+                if let Some(block_id) = self.outermost_block(&task.id) {
+                    // A throttled task waits for its turn within the block
+                    if task.throttle.is_none() {
+                        self.wait_turn(&[task]).await?;
+                    }
+                    let failure = self.execute_block(&block_id, &by_id).await?;
+                    for id in self.block_tasks(&block_id) {
+                        match self.state_manager.get_task_result(&id) {
+                            Some(result) if result.failed => failed.insert(id),
+                            _ => completed.insert(id),
+                        };
+                    }
+                    if let Some(failure) = failure {
+                        self.check_fatal(task, &failure).await?;
+                    }
+                    next += 1;
+                    continue;
+                }
+
+                let wave: Vec<&Task> = ready_tasks[next..]
+                    .iter()
+                    .take_while(|task| self.runs_in_parallel(task))
+                    .take(forks)
+                    .copied()
+                    .collect();
+                let wave = if wave.len() > 1 { wave } else { vec![task] };
+                next += wave.len();
+                self.wait_turn(&wave).await?;
+                let results = if wave.len() > 1 {
+                    self.execute_in_parallel(&wave).await?
+                } else {
+                    vec![self.execute_planned_task(task).await?]
+                };
+                let mut fatal = None;
+                for (task, task_result) in wave.into_iter().zip(results) {
+                    if task_result.failed {
+                        failed.insert(task_result.task_id.clone());
+                        fatal = fatal.or(Some((task, task_result.clone())));
+                    } else {
+                        completed.insert(task_result.task_id.clone());
+                    }
+                    self.record(task, task_result);
+                }
+                if let Some((task, failure)) = fatal {
+                    self.check_fatal(task, &failure).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether `task` may execute alongside other tasks: it is marked
+    /// `parallel` and needs neither the controller nor the rest of the play
+    fn runs_in_parallel(&self, task: &Task) -> bool {
+        task.parallel
+            && task.throttle.is_none()
+            && !is_flush_handlers(task)
+            && !needs_controller(task, self.host_id.as_deref())
+            && self.outermost_block(&task.id).is_none()
+    }
+
+    /// Wait until the controller lets this host start `tasks`: under the
+    /// linear strategy, until every other host has reached the last of them,
+    /// and for a throttled task, until few enough hosts run it. Fails if the
+    /// controller stops this host instead.
+    async fn wait_turn(&self, tasks: &[&Task]) -> Result<(), ExecutionError> {
+        let coordinated = tasks
+            .iter()
+            .any(|task| task.throttle.is_some() || self.any_errors_fatal(task));
+        let Some(dir) = self.strategy.sync_dir(coordinated) else {
+            return Ok(());
+        };
+        let Some((position, task)) = tasks
+            .iter()
+            .map(|task| (self.positions.get(&task.id).copied().unwrap_or(0), task))
+            .max_by_key(|(position, _)| *position)
+        else {
+            return Ok(());
+        };
+        // Throttled tasks execute on their own
+        let throttle = tasks.iter().find_map(|task| task.throttle);
+        tokio::fs::create_dir_all(dir).await?;
+        self.progress_reporter
+            .report_task_reached(&self.execution_id, &task.id, position, throttle)
+            .await?;
+        wait_for_release(dir, &task.id, position, self.config.execution_timeout).await
+    }
+
+    /// Whether `task` belongs to a play with `any_errors_fatal`
+    fn any_errors_fatal(&self, task: &Task) -> bool {
+        task.play_id
+            .as_ref()
+            .and_then(|play_id| self.plays.get(play_id))
+            .is_some_and(|play| play.any_errors_fatal)
+    }
+
+    /// End the run if `failure`, the failure of `task` or of the block it
+    /// starts, is fatal to its play, telling the controller to stop the
+    /// other hosts
+    async fn check_fatal(&self, task: &Task, failure: &TaskResult) -> Result<(), ExecutionError> {
+        let Some(play_id) = task
+            .play_id
+            .as_ref()
+            .filter(|_| self.any_errors_fatal(task))
+        else {
+            return Ok(());
+        };
+        self.progress_reporter
+            .report_fatal_failure(&self.execution_id, &failure.task_id, play_id)
+            .await?;
+        Err(ExecutionError::AnyErrorsFatal {
+            task_id: failure.task_id.clone(),
+            play_id: play_id.clone(),
+        })
+    }
+
+    /// Execute `tasks` side by side, each on a fork of this executor, then
+    /// adopt the facts they set and the module invocations they recorded
+    async fn execute_in_parallel(
+        &mut self,
+        tasks: &[&Task],
+    ) -> Result<Vec<TaskResult>, ExecutionError> {
+        let mut forks: Vec<Self> = tasks.iter().map(|_| self.fork()).collect();
+        let outcomes = futures::future::join_all(
+            forks
+                .iter_mut()
+                .zip(tasks)
+                .map(|(fork, task)| fork.execute_planned_task(task)),
+        )
+        .await;
+
+        let facts = self.facts_cache.get_all_facts();
+        for fork in forks {
+            for (name, value) in fork.facts_cache.get_all_facts() {
+                if facts.get(&name) != Some(&value) {
+                    self.facts_cache.set(name, value);
+                }
+            }
+            self.state_manager
+                .merge_module_metrics(fork.state_manager.get_module_metrics());
+            self.async_jobs.extend(fork.async_jobs);
+        }
+        outcomes.into_iter().collect()
+    }
+
+    /// A copy of this executor to execute one task alongside others. It sees
+    /// the results, facts and variables so far, but has no handlers or
+    /// blocks and persists nothing.
+    fn fork(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            module_registry: Arc::clone(&self.module_registry),
+            facts_cache: self.facts_cache.clone(),
+            state_manager: self.state_manager.clone(),
+            progress_reporter: self.progress_reporter.clone(),
+            faults: self.faults.clone(),
+            execution_id: self.execution_id.clone(),
+            variables: self.variables.clone(),
+            host_id: self.host_id.clone(),
+            delegation_dir: self.delegation_dir.clone(),
+            handlers: Vec::new(),
+            notified: HashSet::new(),
+            blocks: HashMap::new(),
+            parent_blocks: HashMap::new(),
+            async_dir: self.async_dir.clone(),
+            async_jobs: Vec::new(),
+            become_password: self.become_password.clone(),
+            state_file: None,
+            resume: false,
+            plan_id: self.plan_id.clone(),
+            plays: HashMap::new(),
+            play_deadline: self.play_deadline.clone(),
+            strategy: HostStrategy::default(),
+            positions: HashMap::new(),
+            // Its progress reporter records the spans of its task
+            spans: None,
+            audit: Arc::clone(&self.audit),
+            lookups: Arc::clone(&self.lookups),
+            sealed_secrets: self.sealed_secrets.clone(),
+            stop: self.stop.clone(),
+        }
+    }
+
+    /// Keep the result of `task`, binding it to the task's `register` and
+    /// notifying its handlers if it changed something. The variable holds
+    /// the whole result even for `no_log` tasks; what is kept is censored.
+    fn record(&mut self, task: &Task, result: TaskResult) {
+        if result.changed && !result.failed {
+            self.notify(task);
+        }
+        if let Some(register) = &task.register {
+            self.variables.insert(
+                register.clone(),
+                serde_json::Value::Object(registered_value(result.clone())),
+            );
+        }
+        self.state_manager.add_task_result(if task.no_log {
+            result.censored()
+        } else {
+            result
+        });
+        self.persist_progress();
+    }
+
+    /// Pick up the progress a failed run of the same plan persisted
+    fn restore_progress(&mut self) {
+        let Some(path) = &self.state_file else {
+            tracing::warn!("No state file to resume from, starting over");
+            return;
+        };
+        match PersistedState::load(path) {
+            Ok(Some(state)) if state.plan_id == self.plan_id => {
+                tracing::info!(
+                    "Resuming after {} completed tasks",
+                    state.completed_tasks.len()
+                );
+                for result in state.completed_tasks.into_values() {
+                    self.state_manager.add_task_result(result);
+                }
+                self.variables.extend(state.variables);
+                self.notified.extend(state.notified_handlers);
+            }
+            Ok(Some(state)) => tracing::warn!(
+                "State file {} belongs to plan {}, starting over",
+                path.display(),
+                state.plan_id
+            ),
+            Ok(None) => tracing::info!("No progress to resume from, starting over"),
+            Err(e) => tracing::warn!(
+                "Failed to read state file {}, starting over: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    /// Persist the progress of the run for a failed run to resume from
+    fn persist_progress(&self) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        let state = PersistedState {
+            plan_id: self.plan_id.clone(),
+            completed_tasks: self.state_manager.completed_task_results(),
+            variables: self.variables.clone(),
+            notified_handlers: self.notified.iter().cloned().collect(),
+        };
+        if let Err(e) = state.save(path) {
+            tracing::warn!("Failed to persist state to {}: {}", path.display(), e);
+        }
+    }
+
+    fn outermost_block(&self, id: &str) -> Option<String> {
+        let mut block = self.parent_blocks.get(id)?;
+        while let Some(parent) = self.parent_blocks.get(block) {
+            block = parent;
+        }
+        Some(block.clone())
+    }
+
+    /// Ids of the tasks in every section of `block_id` and its nested blocks
+    fn block_tasks(&self, block_id: &str) -> Vec<String> {
+        let Some(block) = self.blocks.get(block_id) else {
+            return Vec::new();
+        };
+        let members = block.block.iter().chain(&block.rescue).chain(&block.always);
+        self.section_tasks(members)
+    }
+
+    fn section_tasks<'a>(&self, members: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+        members
+            .into_iter()
+            .flat_map(|member| {
+                if self.blocks.contains_key(member) {
+                    self.block_tasks(member)
+                } else {
+                    vec![member.clone()]
+                }
+            })
+            .collect()
+    }
+
+    /// Run `block_id`: its block section until a task fails, then the rescue
+    /// section if one did, and the always section either way. The failed
+    /// task and its result are available to the rescue section as
+    /// `ansible_failed_task` and `ansible_failed_result`.
+    fn execute_block<'a>(
+        &'a mut self,
+        block_id: &'a str,
+        tasks: &'a HashMap<&'a str, &'a Task>,
+    ) -> BlockOutcome<'a> {
+        Box::pin(async move {
+            let Some(block) = self.blocks.get(block_id).cloned() else {
+                return Ok(None);
+            };
+            tracing::debug!(
+                "Executing block: {}",
+                block.name.as_deref().unwrap_or(&block.id)
+            );
+
+            let mut failure = self.execute_section(&block.block, tasks).await?;
+            match failure.take() {
+                Some(failed) if !block.rescue.is_empty() => {
+                    let failed_task = serde_json::json!({
+                        "id": failed.task_id,
+                        "name": failed.name,
+                    });
+                    let failed_result = serde_json::to_value(&failed)?;
+                    let outer = [
+                        self.variables
+                            .insert("ansible_failed_task".to_string(), failed_task),
+                        self.variables
+                            .insert("ansible_failed_result".to_string(), failed_result),
+                    ];
+                    failure = self.execute_section(&block.rescue, tasks).await?;
+                    // Nested rescue sections see their own failure only
+                    for (name, value) in ["ansible_failed_task", "ansible_failed_result"]
+                        .into_iter()
+                        .zip(outer)
+                    {
+                        match value {
+                            Some(value) => self.variables.insert(name.to_string(), value),
+                            None => self.variables.remove(name),
+                        };
+                    }
+
+                    if failure.is_none() {
+                        for id in self.section_tasks(&block.block) {
+                            self.state_manager.mark_rescued(&id);
                         }
                     }
                 }
+                Some(failed) => failure = Some(failed),
+                None => {
+                    self.skip_section(&block.rescue, tasks, "Block succeeded")
+                        .await?
+                }
+            }
+
+            let always_failure = self.execute_section(&block.always, tasks).await?;
+            Ok(failure.or(always_failure))
+        })
+    }
+
+    /// Run the tasks and nested blocks of a section in order, skipping the
+    /// rest once one fails. Returns the failure.
+    async fn execute_section(
+        &mut self,
+        members: &[String],
+        tasks: &HashMap<&str, &Task>,
+    ) -> Result<Option<TaskResult>, ExecutionError> {
+        for (index, member) in members.iter().enumerate() {
+            let failure = if self.blocks.contains_key(member) {
+                self.execute_block(member, tasks).await?
+            } else if let Some(task) = tasks.get(member.as_str()) {
+                if task.throttle.is_some() {
+                    self.wait_turn(&[task]).await?;
+                }
+                let result = self.execute_planned_task(task).await?;
+                let failure = result.failed.then(|| result.clone());
+                self.record(task, result);
+                failure
+            } else {
+                tracing::warn!("Block member '{}' is not a task of this play", member);
+                None
+            };
+            if failure.is_some() {
+                self.skip_section(&members[index + 1..], tasks, "Block failed")
+                    .await?;
+                return Ok(failure);
             }
         }
+        Ok(None)
+    }
 
+    async fn skip_section(
+        &mut self,
+        members: &[String],
+        tasks: &HashMap<&str, &Task>,
+        reason: &str,
+    ) -> Result<(), ExecutionError> {
+        for id in self.section_tasks(members) {
+            if let Some(task) = tasks.get(id.as_str()) {
+                let result = self
+                    .skip_task(task, Instant::now(), Utc::now(), reason)
+                    .await?;
+                self.state_manager.add_task_result(result);
+            }
+        }
         Ok(())
     }
 
-    /// Execute a single task
-    pub async fn execute_task(&mut self, task: &Task) -> Result<TaskResult, ExecutionError> {
+    /// Queue the handlers `task` notifies
+    fn notify(&mut self, task: &Task) {
+        for notification in &task.notify {
+            let handlers: Vec<&Handler> = self
+                .handlers
+                .iter()
+                .filter(|handler| handler.play_id.is_none() || handler.play_id == task.play_id)
+                .filter(|handler| {
+                    handler.name == *notification
+                        || handler.id == *notification
+                        || handler.listen.contains(notification)
+                })
+                .collect();
+            if handlers.is_empty() {
+                tracing::warn!(
+                    "Task '{}' notified '{}', which no handler listens to",
+                    task.name,
+                    notification
+                );
+            }
+            self.notified
+                .extend(handlers.iter().map(|handler| handler.id.clone()));
+        }
+    }
+
+    /// Run every notified handler once, in the order the plan defines them,
+    /// then those the handlers that changed something notified in turn.
+    /// A handler runs once per flush, however often it is notified.
+    async fn flush_handlers(&mut self) -> Result<Vec<String>, ExecutionError> {
+        let mut ran: Vec<String> = Vec::new();
+        loop {
+            let handlers: Vec<Task> = self
+                .handlers
+                .iter()
+                .filter(|handler| self.notified.contains(&handler.id))
+                .filter(|handler| !ran.contains(&handler.id))
+                .map(handler_task)
+                .collect();
+            self.notified.clear();
+            if handlers.is_empty() {
+                break;
+            }
+
+            for handler in &handlers {
+                tracing::info!("Running handler: {}", handler.name);
+                let result = self.execute_task(handler).await?;
+                if result.changed && !result.failed {
+                    self.notify(handler);
+                }
+                ran.push(handler.id.clone());
+                self.state_manager.add_handler_result(result);
+            }
+        }
+        // Until now a resumed run would run the handlers again
+        self.persist_progress();
+        Ok(ran)
+    }
+
+    /// Execute a task of the plan, handing delegated and run-once tasks to
+    /// the controller
+    async fn execute_planned_task(&mut self, task: &Task) -> Result<TaskResult, ExecutionError> {
+        if is_flush_handlers(task) {
+            let start_time = Instant::now();
+            let start_utc = Utc::now();
+            self.start_task(task).await?;
+            let flushed = self.flush_handlers().await?;
+            let result = TaskResult {
+                task_id: task.id.clone(),
+                name: task.name.clone(),
+                status: TaskStatus::Success,
+                changed: false,
+                failed: false,
+                skipped: false,
+                output: serde_json::json!({ "flushed_handlers": flushed }),
+                stdout: None,
+                stderr: None,
+                start_time: start_utc,
+                end_time: Utc::now(),
+                duration: start_time.elapsed(),
+                error: None,
+            };
+            self.progress_reporter
+                .report_task_complete(&self.execution_id, &result)
+                .await?;
+            return Ok(result);
+        }
+        if !needs_controller(task, self.host_id.as_deref()) {
+            return self.execute_task(task).await;
+        }
+        match self.delegation_dir.clone() {
+            Some(dir) => self.delegate_task(task, &dir).await,
+            // Without a controller this is the only host there is
+            None if task
+                .delegate_to
+                .as_deref()
+                .is_none_or(|delegate| delegate == LOCALHOST) =>
+            {
+                self.execute_task(task).await
+            }
+            None => {
+                let start_time = Instant::now();
+                self.start_task(task).await?;
+                let mut result = DelegatedResult::failed(
+                    &task.id,
+                    &task.name,
+                    "Delegated tasks require controller-coordinated execution".to_string(),
+                )
+                .task_result;
+                result.duration = start_time.elapsed();
+                self.progress_reporter
+                    .report_task_complete(&self.execution_id, &result)
+                    .await?;
+                Ok(result)
+            }
+        }
+    }
+
+    /// Ask the controller to execute `task` and wait for it to deliver the
+    /// result to `dir`
+    async fn delegate_task(
+        &mut self,
+        task: &Task,
+        dir: &Path,
+    ) -> Result<TaskResult, ExecutionError> {
         let start_time = Instant::now();
         let start_utc = Utc::now();
+        self.start_task(task).await?;
+        // Conditions are evaluated where the task was planned, those of
+        // loops for each item where it executes
+        if task.task_loop.is_none() && !self.conditions_met(task)? {
+            return self
+                .skip_task(task, start_time, start_utc, "Condition not met")
+                .await;
+        }
 
+        tokio::fs::create_dir_all(dir).await?;
+        let context = DelegationContext {
+            host: self.host_id.clone().unwrap_or_else(|| {
+                hostname::get()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|_| LOCALHOST.to_string())
+            }),
+            facts: self.facts_cache.get_all_facts(),
+            variables: self.variables.clone(),
+        };
+        self.progress_reporter
+            .report_delegation(&self.execution_id, &task.id, context)
+            .await?;
+        let delegated = wait_for_result(dir, &task.id, self.config.execution_timeout).await?;
+
+        for (name, value) in delegated.facts {
+            self.facts_cache.set(name, value);
+        }
+        let task_result = TaskResult {
+            task_id: task.id.clone(),
+            name: task.name.clone(),
+            ..delegated.task_result
+        };
+        self.progress_reporter
+            .report_task_complete(&self.execution_id, &shown(task, &task_result))
+            .await?;
+        Ok(task_result)
+    }
+
+    /// Execute `task` here on behalf of the host described by `context`,
+    /// without evaluating its conditions again
+    pub async fn execute_delegated_task(
+        &mut self,
+        task: &Task,
+        context: DelegationContext,
+    ) -> Result<TaskResult, ExecutionError> {
+        let start_time = Instant::now();
+        let start_utc = Utc::now();
+        self.variables = context.into_variables();
+        self.start_task(task).await?;
+        self.run_module(task, start_time, start_utc).await
+    }
+
+    async fn start_task(&mut self, task: &Task) -> Result<(), ExecutionError> {
         tracing::debug!("Executing task: {} ({})", task.name, task.id);
 
         // Update current task in state
@@ -231,54 +1173,404 @@ impl LocalExecutor {
         self.progress_reporter
             .report_task_start(&self.execution_id, task)
             .await?;
+        Ok(())
+    }
 
-        // Evaluate conditions
+    fn conditions_met(&self, task: &Task) -> Result<bool, ExecutionError> {
         let condition_context = ConditionContext::new(
             self.facts_cache.get_all_facts(),
-            HashMap::new(), // TODO: Get variables from execution context
+            self.variables.clone(),
             self.state_manager.get_all_task_results().clone(),
         );
+        Ok(
+            ConditionEvaluator::evaluate_conditions(&task.conditions, &condition_context)?
+                && ConditionEvaluator::evaluate_expressions(&task.when, &condition_context)?,
+        )
+    }
 
-        if !ConditionEvaluator::evaluate_conditions(&task.conditions, &condition_context)? {
-            let result = TaskResult {
-                task_id: task.id.clone(),
-                name: task.name.clone(),
-                status: TaskStatus::Skipped,
-                changed: false,
-                failed: false,
-                skipped: true,
-                output: serde_json::json!({"skipped": true, "reason": "Condition not met"}),
-                stdout: None,
-                stderr: None,
-                start_time: start_utc,
-                end_time: Utc::now(),
-                duration: start_time.elapsed(),
-                error: None,
+    async fn skip_task(
+        &self,
+        task: &Task,
+        start_time: Instant,
+        start_utc: chrono::DateTime<Utc>,
+        reason: &str,
+    ) -> Result<TaskResult, ExecutionError> {
+        let result = skipped_result(task, start_time, start_utc, reason);
+        self.progress_reporter
+            .report_task_complete(&self.execution_id, &result)
+            .await?;
+        Ok(result)
+    }
+
+    /// Execute a single task
+    pub async fn execute_task(&mut self, task: &Task) -> Result<TaskResult, ExecutionError> {
+        let start_time = Instant::now();
+        let start_utc = Utc::now();
+        self.start_task(task).await?;
+
+        if task.task_loop.is_none() && !self.conditions_met(task)? {
+            return self
+                .skip_task(task, start_time, start_utc, "Condition not met")
+                .await;
+        }
+        self.run_module(task, start_time, start_utc).await
+    }
+
+    async fn run_module(
+        &mut self,
+        task: &Task,
+        start_time: Instant,
+        start_utc: chrono::DateTime<Utc>,
+    ) -> Result<TaskResult, ExecutionError> {
+        let outcome = match &task.task_loop {
+            Some(_) => self.run_loop(task, start_time, start_utc).await,
+            None => self.invoke_module(task, start_time, start_utc).await,
+        };
+        // Errors may quote the arguments of the task
+        let mut task_result = outcome.map_err(|e| {
+            if task.no_log {
+                ExecutionError::TaskFailed {
+                    task_id: task.id.clone(),
+                    reason: NO_LOG_MESSAGE.to_string(),
+                }
+            } else {
+                e
+            }
+        })?;
+        if task.ignore_errors && task_result.failed {
+            tracing::info!("Ignoring the failure of task '{}'", task.name);
+            task_result.failed = false;
+            task_result.status = TaskStatus::Ignored;
+        }
+
+        let visible = shown(task, &task_result);
+        // Verbose logging for task results
+        if self.config.verbose {
+            tracing::info!(
+                "Task {} result: status={:?}, changed={}, failed={}, error={:?}",
+                task.id,
+                visible.status,
+                visible.changed,
+                visible.failed,
+                visible.error
+            );
+        }
+
+        // Report task completion
+        self.progress_reporter
+            .report_task_complete(&self.execution_id, &visible)
+            .await?;
+
+        tracing::debug!(
+            "Task completed: {} - {} in {:?}",
+            task.name,
+            if task_result.failed {
+                "FAILED"
+            } else if task_result.changed {
+                "CHANGED"
+            } else {
+                "OK"
+            },
+            task_result.duration
+        );
+
+        Ok(task_result)
+    }
+
+    /// Execute `task` once per item of its loop, evaluating its conditions
+    /// and rendering its arguments with the loop variables of each item
+    async fn run_loop(
+        &mut self,
+        task: &Task,
+        start_time: Instant,
+        start_utc: chrono::DateTime<Utc>,
+    ) -> Result<TaskResult, ExecutionError> {
+        let Some(task_loop) = &task.task_loop else {
+            return self.invoke_module(task, start_time, start_utc).await;
+        };
+        let control = &task_loop.control;
+        let items = match loop_items(task_loop, &self.variables) {
+            Ok(items) => items,
+            Err(reason) => {
+                return Ok(TaskResult {
+                    task_id: task.id.clone(),
+                    name: task.name.clone(),
+                    status: TaskStatus::Failed,
+                    changed: false,
+                    failed: true,
+                    skipped: false,
+                    output: serde_json::json!({ "msg": reason }),
+                    stdout: None,
+                    stderr: None,
+                    start_time: start_utc,
+                    end_time: Utc::now(),
+                    duration: start_time.elapsed(),
+                    error: Some(format!("Invalid loop: {reason}")),
+                })
+            }
+        };
+
+        let task_variables = self.variables.clone();
+        let mut results = Vec::with_capacity(items.len());
+        let (mut changed, mut failed, mut skipped) = (false, false, true);
+        for index in 0..items.len() {
+            if index > 0 {
+                if let Some(pause) = control.pause.filter(|pause| *pause > 0.0) {
+                    tokio::time::sleep(Duration::from_secs_f64(pause)).await;
+                }
+            }
+            let item_variables = loop_variables(control, &items, index);
+            self.variables.extend(item_variables.clone());
+            let item_task = Task {
+                args: task
+                    .args
+                    .iter()
+                    .map(|(name, value)| (name.clone(), render(value, &self.variables)))
+                    .collect(),
+                task_loop: None,
+                ..task.clone()
             };
 
+            let item_start = Instant::now();
+            let item_start_utc = Utc::now();
+            let item_result = match self.conditions_met(&item_task) {
+                Ok(true) => {
+                    self.invoke_module(&item_task, item_start, item_start_utc)
+                        .await
+                }
+                Ok(false) => Ok(skipped_result(
+                    task,
+                    item_start,
+                    item_start_utc,
+                    "Condition not met",
+                )),
+                Err(e) => Err(e),
+            };
+            self.variables = task_variables.clone();
+            let item_result = item_result?;
+
+            changed |= item_result.changed;
+            failed |= item_result.failed;
+            skipped &= item_result.skipped;
+            let mut entry = registered_value(item_result);
+            entry.insert("item".to_string(), items[index].clone());
+            entry.insert(
+                "ansible_loop_var".to_string(),
+                loop_var(control).to_string().into(),
+            );
+            if let Some(index_var) = &control.index_var {
+                entry.insert(index_var.clone(), index.into());
+            }
+            if let Some(label) = &control.label {
+                let mut label_variables = task_variables.clone();
+                label_variables.extend(item_variables);
+                entry.insert(
+                    "_ansible_item_label".to_string(),
+                    render(&label.clone().into(), &label_variables),
+                );
+            }
+            results.push(serde_json::Value::Object(entry));
+        }
+
+        let msg = if items.is_empty() {
+            "No items in the list"
+        } else if failed {
+            "One or more items failed"
+        } else {
+            "All items completed"
+        };
+        Ok(TaskResult {
+            task_id: task.id.clone(),
+            name: task.name.clone(),
+            status: if failed {
+                TaskStatus::Failed
+            } else if skipped {
+                TaskStatus::Skipped
+            } else {
+                TaskStatus::Success
+            },
+            changed,
+            failed,
+            skipped,
+            output: serde_json::json!({
+                "results": results,
+                "changed": changed,
+                "msg": msg,
+            }),
+            stdout: None,
+            stderr: None,
+            start_time: start_utc,
+            end_time: Utc::now(),
+            duration: start_time.elapsed(),
+            error: failed.then(|| msg.to_string()),
+        })
+    }
+
+    /// Execute the module of `task`, again and again while the result does
+    /// not satisfy its `until` conditions
+    async fn invoke_module(
+        &mut self,
+        task: &Task,
+        start_time: Instant,
+        start_utc: chrono::DateTime<Utc>,
+    ) -> Result<TaskResult, ExecutionError> {
+        let Some(until) = &task.until else {
+            return self.attempt(task, start_time, start_utc).await;
+        };
+        let register = until
+            .register
+            .as_deref()
+            .or(task.register.as_deref())
+            .unwrap_or(DEFAULT_REGISTER);
+
+        let mut attempt = 1;
+        loop {
+            let mut result = self.attempt(task, start_time, start_utc).await?;
+            let context = self.result_context(register, &result);
+            let met = !result.failed
+                && ConditionEvaluator::evaluate_conditions(&until.conditions, &context)?;
+
+            if let serde_json::Value::Object(output) = &mut result.output {
+                output.insert("attempts".to_string(), attempt.into());
+            }
+            if met {
+                return Ok(result);
+            }
+            if attempt > until.retries {
+                tracing::warn!(
+                    "Task '{}' did not succeed after {} attempts",
+                    task.name,
+                    attempt
+                );
+                result.status = TaskStatus::Failed;
+                result.failed = true;
+                result.error.get_or_insert_with(|| {
+                    format!("Until conditions not met after {attempt} attempts")
+                });
+                return Ok(result);
+            }
+
             self.progress_reporter
-                .report_task_complete(&self.execution_id, &result)
+                .report_retry(&self.execution_id, task, attempt, until.retries)
                 .await?;
+            tokio::time::sleep(until.delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Execute the module of `task` once, letting `changed_when` and
+    /// `failed_when` overrule the module's verdict
+    async fn attempt(
+        &mut self,
+        task: &Task,
+        start_time: Instant,
+        start_utc: chrono::DateTime<Utc>,
+    ) -> Result<TaskResult, ExecutionError> {
+        let mut result = self.invoke_once(task, start_time, start_utc).await?;
+        if task.changed_when.is_none() && task.failed_when.is_none() {
             return Ok(result);
         }
 
+        let register = task.register.as_deref().unwrap_or(DEFAULT_REGISTER);
+        let context = self.result_context(register, &result);
+        if let Some(changed_when) = &task.changed_when {
+            result.changed = result_condition_holds(changed_when, &context)?;
+        }
+        if let Some(failed_when) = &task.failed_when {
+            result.failed = result_condition_holds(failed_when, &context)?;
+            if result.failed {
+                result.status = TaskStatus::Failed;
+                result
+                    .error
+                    .get_or_insert_with(|| "failed_when conditions were met".to_string());
+            } else {
+                result.status = TaskStatus::Success;
+                result.error = None;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Conditions context with `result` bound to `register`
+    fn result_context(&self, register: &str, result: &TaskResult) -> ConditionContext {
+        let mut variables = self.variables.clone();
+        variables.insert(
+            register.to_string(),
+            serde_json::Value::Object(registered_value(result.clone())),
+        );
+        ConditionContext::new(
+            self.facts_cache.get_all_facts(),
+            variables,
+            self.state_manager.get_all_task_results().clone(),
+        )
+    }
+
+    /// Execute the module of `task` once and build its result
+    async fn invoke_once(
+        &mut self,
+        task: &Task,
+        start_time: Instant,
+        start_utc: chrono::DateTime<Utc>,
+    ) -> Result<TaskResult, ExecutionError> {
+        self.check_play_deadline()?;
+
         // Prepare execution context
+        let mut environment: HashMap<String, String> = std::env::vars().collect();
+        environment.insert(
+            ASYNC_DIR_ENV.to_string(),
+            self.async_dir.to_string_lossy().into_owned(),
+        );
+        environment.extend(task.environment.clone());
         let execution_context = ExecutionContext {
             facts: self.facts_cache.get_all_facts(),
-            variables: HashMap::new(), // TODO: Populate from execution plan
+            variables: self.variables.clone(),
             host_info: HostInfo::detect(),
             working_directory: std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
-            environment: std::env::vars().collect(),
+            environment,
             check_mode: self.config.check_mode.unwrap_or(false),
             diff_mode: false,
             verbosity: if self.config.verbose { 1 } else { 0 },
         };
 
+        let r#become = resolve_become(task.r#become.as_ref(), self.config.r#become.as_ref())
+            .map_err(|reason| ExecutionError::TaskFailed {
+                task_id: task.id.clone(),
+                reason,
+            })?;
+
+        // Secrets are substituted and fetched only now, and never kept
+        // with the task
+        let task_failed = |reason: String| ExecutionError::TaskFailed {
+            task_id: task.id.clone(),
+            reason,
+        };
+        let mut args = task.args.clone();
+        if args.values().any(has_secret_references) {
+            let secrets = self.sealed_secrets.as_ref().ok_or_else(|| {
+                task_failed(
+                    "the task references sealed secrets, but no sealed secrets were delivered"
+                        .into(),
+                )
+            })?;
+            for value in args.values_mut() {
+                *value = secrets
+                    .resolve(value)
+                    .map_err(|e| task_failed(e.to_string()))?;
+            }
+        }
+        if args.values().any(has_lookups) {
+            args = self
+                .lookups
+                .resolve_args(&args)
+                .await
+                .map_err(|e| task_failed(e.to_string()))?;
+        }
+
         // Prepare module arguments
         let module_args = ModuleArgs {
-            args: task.args.clone(),
+            args,
             special: SpecialParameters {
-                r#become: None, // TODO: Extract from task
+                r#become,
                 when: None,
                 changed_when: None,
                 failed_when: None,
@@ -287,11 +1579,23 @@ impl LocalExecutor {
             },
         };
 
-        // Execute the task with timeout
-        let module_result = match (
-            task.timeout.or(self.config.task_timeout),
-            &task.retry_policy,
-        ) {
+        if let Some(policy) = &task.async_job {
+            return self
+                .run_async(
+                    task,
+                    policy,
+                    module_args,
+                    execution_context,
+                    start_time,
+                    start_utc,
+                )
+                .await;
+        }
+
+        // Execute the task with timeout. Dropping the module on timeout kills
+        // the processes it started.
+        let timeout = self.task_timeout(task);
+        let outcome = match (timeout, &task.retry_policy) {
             (Some(timeout), Some(retry)) => {
                 self.execute_with_retry(
                     &task.module,
@@ -300,19 +1604,21 @@ impl LocalExecutor {
                     timeout,
                     retry,
                 )
-                .await?
-            }
-            (Some(timeout), None) => {
-                tokio::time::timeout(
-                    timeout,
-                    self.module_registry.execute_module(
-                        &task.module,
-                        &module_args,
-                        &execution_context,
-                    ),
-                )
-                .await??
+                .await
             }
+            (Some(timeout), None) => tokio::time::timeout(
+                timeout,
+                privilege::execute_module(
+                    &self.module_registry,
+                    &task.module,
+                    &module_args,
+                    &execution_context,
+                    self.become_password.as_deref(),
+                ),
+            )
+            .await
+            .map_err(ExecutionError::from)
+            .and_then(|result| result.map_err(ExecutionError::from)),
             (None, Some(retry)) => {
                 self.execute_with_retry(
                     &task.module,
@@ -321,19 +1627,47 @@ impl LocalExecutor {
                     Duration::from_secs(300),
                     retry,
                 )
-                .await?
+                .await
             }
-            (None, None) => {
-                self.module_registry
-                    .execute_module(&task.module, &module_args, &execution_context)
-                    .await?
+            (None, None) => privilege::execute_module(
+                &self.module_registry,
+                &task.module,
+                &module_args,
+                &execution_context,
+                self.become_password.as_deref(),
+            )
+            .await
+            .map_err(ExecutionError::from),
+        };
+        let module_result = match outcome {
+            Err(ExecutionError::Timeout(_)) => {
+                let timeout = timeout.unwrap_or(Duration::from_secs(300));
+                let task_result = timed_out_result(task, timeout, start_time, start_utc);
+                self.state_manager
+                    .record_module_invocation(&task.module, &task_result);
+                return Ok(task_result);
             }
+            outcome => outcome?,
         };
 
+        // The module's side effects have happened but nothing has been recorded yet
+        self.faults
+            .crash_point(&task.id)
+            .map_err(|reason| ExecutionError::TaskFailed {
+                task_id: task.id.clone(),
+                reason,
+            })?;
+
         let end_utc = Utc::now();
 
+        if let Some(diff) = module_result.diff.as_ref().filter(|_| !task.no_log) {
+            self.progress_reporter
+                .report_diff(&self.execution_id, &task.id, diff)
+                .await?;
+        }
+
         // Verbose logging for module results
-        if self.config.verbose {
+        if self.config.verbose && !task.no_log {
             tracing::info!(
                 "Module {} raw result: changed={}, failed={}, msg={:?}",
                 task.module,
@@ -342,6 +1676,21 @@ impl LocalExecutor {
                 module_result.msg
             );
         }
+        // Facts the module set apply to the tasks after it, and travel with
+        // its output to hosts sharing a delegated result
+        let mut output = module_result.results;
+        if let Some(rc) = module_result.rc {
+            output.entry("rc".to_string()).or_insert(rc.into());
+        }
+        if !module_result.ansible_facts.is_empty() {
+            for (name, value) in &module_result.ansible_facts {
+                self.facts_cache.set(name.clone(), value.clone());
+            }
+            output.insert(
+                "ansible_facts".to_string(),
+                serde_json::to_value(&module_result.ansible_facts)?,
+            );
+        }
         let task_result = TaskResult {
             task_id: task.id.clone(),
             name: task.name.clone(),
@@ -353,7 +1702,7 @@ impl LocalExecutor {
             changed: module_result.changed,
             failed: module_result.failed,
             skipped: false,
-            output: serde_json::to_value(&module_result.results)?,
+            output: serde_json::to_value(&output)?,
             stdout: module_result.stdout,
             stderr: module_result.stderr,
             start_time: start_utc,
@@ -370,37 +1719,109 @@ impl LocalExecutor {
             },
         };
 
-        // Verbose logging for task results
-        if self.config.verbose {
-            tracing::info!(
-                "Task {} result: status={:?}, changed={}, failed={}, error={:?}",
-                task.id,
-                task_result.status,
-                task_result.changed,
-                task_result.failed,
-                task_result.error
+        if task_result.changed && !task_result.failed && !execution_context.check_mode {
+            let entry = AuditEntry::new(
+                self.host_id.as_deref().unwrap_or(LOCALHOST),
+                task,
+                &task.args,
+                module_result.diff.as_ref(),
+                &output,
             );
+            self.audit
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(entry);
         }
 
-        // Report task completion
-        self.progress_reporter
-            .report_task_complete(&self.execution_id, &task_result)
-            .await?;
+        self.state_manager
+            .record_module_invocation(&task.module, &task_result);
 
-        tracing::debug!(
-            "Task completed: {} - {} in {:?}",
-            task.name,
-            if task_result.failed {
-                "FAILED"
-            } else if task_result.changed {
-                "CHANGED"
-            } else {
-                "OK"
-            },
-            task_result.duration
+        Ok(task_result)
+    }
+
+    /// Start the module of `task` in a background job and, unless it polls
+    /// never, wait for the job to finish
+    async fn run_async(
+        &mut self,
+        task: &Task,
+        policy: &AsyncPolicy,
+        module_args: ModuleArgs,
+        execution_context: ExecutionContext,
+        start_time: Instant,
+        start_utc: chrono::DateTime<Utc>,
+    ) -> Result<TaskResult, ExecutionError> {
+        let jid = format!("j{}", Uuid::new_v4().simple());
+        let dir = self.async_dir.clone();
+        let mut started = AsyncJobState::new();
+        started.insert("ansible_job_id".to_string(), jid.clone().into());
+        started.insert("started".to_string(), 1.into());
+        started.insert("finished".to_string(), 0.into());
+        started.insert(
+            "results_file".to_string(),
+            job_path(&dir, &jid).to_string_lossy().into_owned().into(),
         );
+        write_job(&dir, &jid, &started)?;
+
+        let mut job = {
+            let registry = Arc::clone(&self.module_registry);
+            let password = self.become_password.clone();
+            let module = task.module.clone();
+            let timeout = policy.timeout;
+            let (jid, dir, mut state) = (jid.clone(), dir.clone(), started.clone());
+            tokio::spawn(async move {
+                let outcome = tokio::time::timeout(
+                    timeout,
+                    privilege::execute_module(
+                        &registry,
+                        &module,
+                        &module_args,
+                        &execution_context,
+                        password.as_deref(),
+                    ),
+                )
+                .await;
+                match outcome {
+                    Ok(Ok(result)) => state.extend(module_result_value(result)),
+                    Ok(Err(e)) => {
+                        state.insert("failed".to_string(), true.into());
+                        state.insert("msg".to_string(), e.to_string().into());
+                    }
+                    Err(_) => {
+                        state.insert("failed".to_string(), true.into());
+                        state.insert(
+                            "msg".to_string(),
+                            format!("Job did not complete within {}s", timeout.as_secs()).into(),
+                        );
+                    }
+                }
+                state.insert("finished".to_string(), 1.into());
+                if let Err(e) = write_job(&dir, &jid, &state) {
+                    tracing::warn!("Failed to record async job {}: {}", jid, e);
+                }
+                state
+            })
+        };
 
-        Ok(task_result)
+        if policy.poll.is_zero() {
+            tracing::info!("Task '{}' continues as async job {}", task.name, jid);
+            self.async_jobs.push((jid, job));
+            return Ok(job_result(task, started, start_time, start_utc));
+        }
+
+        let state = loop {
+            match tokio::time::timeout(policy.poll, &mut job).await {
+                Ok(joined) => {
+                    break joined.map_err(|e| ExecutionError::TaskFailed {
+                        task_id: task.id.clone(),
+                        reason: format!("Async job {jid} ended abnormally: {e}"),
+                    })?
+                }
+                Err(_) => tracing::debug!("Async job {} of '{}' still running", jid, task.name),
+            }
+        };
+        // Polled to completion, nobody needs the job file anymore
+        let _ = std::fs::remove_file(job_path(&dir, &jid));
+        Ok(job_result(task, state, start_time, start_utc))
     }
 
     async fn execute_with_retry(
@@ -419,8 +1840,13 @@ impl LocalExecutor {
 
             let result = tokio::time::timeout(
                 timeout,
-                self.module_registry
-                    .execute_module(module_name, args, context),
+                privilege::execute_module(
+                    &self.module_registry,
+                    module_name,
+                    args,
+                    context,
+                    self.become_password.as_deref(),
+                ),
             )
             .await;
 
@@ -487,7 +1913,7 @@ impl LocalExecutor {
         for task in tasks {
             // Validate that all dependencies exist
             for dep in &task.dependencies {
-                if !task_ids.contains(dep) {
+                if !task_ids.contains(dep) && self.state_manager.get_task_result(dep).is_none() {
                     return Err(ExecutionError::InvalidExecutionPlan {
                         reason: format!(
                             "Task '{}' depends on non-existent task '{}'",
@@ -575,6 +2001,202 @@ impl LocalExecutor {
     }
 }
 
+fn result_condition_holds(
+    condition: &ResultCondition,
+    context: &ConditionContext,
+) -> Result<bool, ExecutionError> {
+    match condition {
+        ResultCondition::Constant(value) => Ok(*value),
+        ResultCondition::Conditions(conditions) => {
+            ConditionEvaluator::evaluate_conditions(conditions, context)
+        }
+        ResultCondition::Expression(expression) => {
+            ConditionEvaluator::evaluate_expression(expression, context)
+        }
+        ResultCondition::Expressions(expressions) => {
+            ConditionEvaluator::evaluate_expressions(expressions, context)
+        }
+    }
+}
+
+/// What a finished async job records of its module's result
+fn module_result_value(result: ModuleResult) -> AsyncJobState {
+    let mut value: AsyncJobState = result.results.into_iter().collect();
+    value.insert("changed".to_string(), result.changed.into());
+    value.insert("failed".to_string(), result.failed.into());
+    if let Some(rc) = result.rc {
+        value.insert("rc".to_string(), rc.into());
+    }
+    if let Some(stdout) = result.stdout {
+        value.insert("stdout".to_string(), stdout.into());
+    }
+    if let Some(stderr) = result.stderr {
+        value.insert("stderr".to_string(), stderr.into());
+    }
+    if let Some(msg) = result.msg {
+        value.insert("msg".to_string(), msg.into());
+    }
+    value
+}
+
+/// The result of an async task from the state of its job
+fn job_result(
+    task: &Task,
+    state: AsyncJobState,
+    start_time: Instant,
+    start_utc: chrono::DateTime<Utc>,
+) -> TaskResult {
+    let flag = |name: &str| state.get(name).and_then(|v| v.as_bool()).unwrap_or(false);
+    let text = |name: &str| state.get(name).and_then(|v| v.as_str()).map(str::to_string);
+    let failed = flag("failed");
+    TaskResult {
+        task_id: task.id.clone(),
+        name: task.name.clone(),
+        status: if failed {
+            TaskStatus::Failed
+        } else {
+            TaskStatus::Success
+        },
+        changed: flag("changed"),
+        failed,
+        skipped: false,
+        stdout: text("stdout"),
+        stderr: text("stderr"),
+        error: if failed {
+            Some(text("msg").unwrap_or_else(|| "Task failed".to_string()))
+        } else {
+            None
+        },
+        output: serde_json::Value::Object(state),
+        start_time: start_utc,
+        end_time: Utc::now(),
+        duration: start_time.elapsed(),
+    }
+}
+
+/// The failed result of a task whose module was killed after `timeout`
+fn timed_out_result(
+    task: &Task,
+    timeout: Duration,
+    start_time: Instant,
+    start_utc: chrono::DateTime<Utc>,
+) -> TaskResult {
+    let msg = format!("Task timed out after {}s", timeout.as_secs());
+    TaskResult {
+        task_id: task.id.clone(),
+        name: task.name.clone(),
+        status: TaskStatus::Timeout,
+        changed: false,
+        failed: true,
+        skipped: false,
+        output: serde_json::json!({ "msg": msg }),
+        stdout: None,
+        stderr: None,
+        start_time: start_utc,
+        end_time: Utc::now(),
+        duration: start_time.elapsed(),
+        error: Some(msg),
+    }
+}
+
+/// `result` as reported for `task`, censored if the task is `no_log`
+fn shown(task: &Task, result: &TaskResult) -> TaskResult {
+    if task.no_log {
+        result.censored()
+    } else {
+        result.clone()
+    }
+}
+
+/// A result as a variable: its output with its outcome alongside, the way a
+/// registered result looks
+fn registered_value(result: TaskResult) -> serde_json::Map<String, serde_json::Value> {
+    let mut value = match result.output {
+        serde_json::Value::Object(output) => output,
+        _ => serde_json::Map::new(),
+    };
+    value.insert("changed".to_string(), result.changed.into());
+    value.insert("failed".to_string(), result.failed.into());
+    value.insert("skipped".to_string(), result.skipped.into());
+    if let Some(stdout) = result.stdout {
+        value.insert("stdout".to_string(), stdout.into());
+    }
+    if let Some(stderr) = result.stderr {
+        value.insert("stderr".to_string(), stderr.into());
+    }
+    if let Some(error) = result.error {
+        value.entry("msg").or_insert(error.into());
+    }
+    value
+}
+
+fn skipped_result(
+    task: &Task,
+    start_time: Instant,
+    start_utc: chrono::DateTime<Utc>,
+    reason: &str,
+) -> TaskResult {
+    TaskResult {
+        task_id: task.id.clone(),
+        name: task.name.clone(),
+        status: TaskStatus::Skipped,
+        changed: false,
+        failed: false,
+        skipped: true,
+        output: serde_json::json!({"skipped": true, "reason": reason}),
+        stdout: None,
+        stderr: None,
+        start_time: start_utc,
+        end_time: Utc::now(),
+        duration: start_time.elapsed(),
+        error: None,
+    }
+}
+
+/// Whether `task` is `meta: flush_handlers`
+fn is_flush_handlers(task: &Task) -> bool {
+    task.module == "meta"
+        && ["_raw_params", "free_form"].iter().any(|key| {
+            task.args.get(*key).and_then(|value| value.as_str()) == Some("flush_handlers")
+        })
+}
+
+/// A notified handler as a task to execute
+fn handler_task(handler: &Handler) -> Task {
+    Task {
+        id: handler.id.clone(),
+        name: handler.name.clone(),
+        task_type: TaskType::Custom {
+            module_name: handler.module.clone(),
+        },
+        module: handler.module.clone(),
+        args: handler.args.clone(),
+        dependencies: Vec::new(),
+        conditions: handler.conditions.clone(),
+        target_hosts: TargetSelector::All,
+        timeout: None,
+        retry_policy: None,
+        failure_policy: FailurePolicy::Abort,
+        run_once: false,
+        delegate_to: None,
+        notify: handler.notify.clone(),
+        play_id: handler.play_id.clone(),
+        task_loop: None,
+        until: None,
+        async_job: None,
+        failed_when: None,
+        changed_when: None,
+        ignore_errors: false,
+        register: None,
+        when: handler.when.clone(),
+        no_log: false,
+        r#become: None,
+        environment: handler.environment.clone(),
+        parallel: false,
+        throttle: None,
+    }
+}
+
 // Custom serialization for Duration fields
 mod serde_duration {
     use serde::{Deserialize, Deserializer, Serialize, Serializer};
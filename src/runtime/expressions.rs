@@ -0,0 +1,1501 @@
+//! Jinja2 expressions.
+//!
+//! `when`, `failed_when`, `changed_when` and `until` take the Jinja2
+//! expressions Ansible uses: literals, variables with attribute, index and
+//! method access, arithmetic, `~` concatenation, comparisons, `in`,
+//! `and`/`or`/`not`, inline `if`, filters such as `default` or `length`, and
+//! tests such as `is defined`, `is succeeded`, `is match('^web')` or
+//! `is version('2.0', '>=')`.
+//!
+//! An expression is evaluated against a variable lookup. A variable the
+//! lookup does not know, or a missing attribute, is undefined: tests and
+//! `default` accept undefined values, anything else fails the evaluation.
+
+use regex::{Regex, RegexBuilder};
+use serde_json::{Map, Number, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Evaluate `expression`, resolving variables with `lookup`. An expression
+/// wrapped in `{{ }}` is evaluated without them.
+pub fn evaluate(expression: &str, lookup: &dyn Fn(&str) -> Option<Value>) -> Result<Value, String> {
+    let expr = parse(strip_braces(expression))?;
+    eval(&expr, lookup)?.defined()
+}
+
+/// Evaluate `expression` as a condition: whether its value is truthy
+pub fn is_true(expression: &str, lookup: &dyn Fn(&str) -> Option<Value>) -> Result<bool, String> {
+    evaluate(expression, lookup).map(|value| truthy(&value))
+}
+
+/// Evaluate the arguments of a call, `source` being what is between its
+/// parentheses, into its positional and named arguments
+pub fn evaluate_arguments(
+    source: &str,
+    lookup: &dyn Fn(&str) -> Option<Value>,
+) -> Result<(Vec<Value>, Map<String, Value>), String> {
+    let mut parser = Parser {
+        tokens: tokenize(&format!("{source})"))?,
+        pos: 0,
+    };
+    let args = parser.arguments()?;
+    if let Some(token) = parser.peek() {
+        return Err(format!("unexpected {}", describe(token)));
+    }
+    let evaluated = arguments(&args, lookup)?;
+    let positional = evaluated
+        .positional
+        .into_iter()
+        .map(Val::defined)
+        .collect::<Result<_, _>>()?;
+    let mut named = Map::new();
+    for (name, value) in evaluated.named {
+        named.insert(name, value.defined()?);
+    }
+    Ok((positional, named))
+}
+
+/// Apply the test `name`, as in `value is name(arguments)`, to a value
+/// evaluated elsewhere, such as by the template engine; `None` is an
+/// undefined value
+pub fn apply_test(
+    name: &str,
+    value: Option<Value>,
+    positional: Vec<Value>,
+    named: Map<String, Value>,
+) -> Result<bool, String> {
+    let operand = match value {
+        Some(value) => Val::Defined(value),
+        None => Val::Undefined("the value is undefined".to_string()),
+    };
+    let args = Args {
+        positional: positional.into_iter().map(Val::Defined).collect(),
+        named: named
+            .into_iter()
+            .map(|(name, value)| (name, Val::Defined(value)))
+            .collect(),
+    };
+    test(name, operand, &args)
+}
+
+/// Python truthiness: `none`, `false`, zero and empty values are false
+pub fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(number) => number.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(text) => !text.is_empty(),
+        Value::Array(values) => !values.is_empty(),
+        Value::Object(entries) => !entries.is_empty(),
+    }
+}
+
+fn strip_braces(expression: &str) -> &str {
+    let trimmed = expression.trim();
+    match trimmed
+        .strip_prefix("{{")
+        .and_then(|inner| inner.strip_suffix("}}"))
+    {
+        Some(inner) if !inner.contains("{{") && !inner.contains("}}") => inner,
+        _ => trimmed,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Name(String),
+    Op(&'static str),
+}
+
+const OPERATORS: &[&str] = &[
+    "//", "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "~", "(", ")", "[", "]", "{",
+    "}", ",", ":", ".", "|", "=",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            // `items.0.1` indexes twice rather than reading a float
+            let after_dot = tokens.last() == Some(&Token::Op("."));
+            let fraction = !after_dot
+                && i + 1 < chars.len()
+                && chars[i] == '.'
+                && chars[i + 1].is_ascii_digit();
+            if fraction {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                tokens.push(Token::Float(
+                    text.parse()
+                        .map_err(|_| format!("invalid number '{text}'"))?,
+                ));
+            } else {
+                let text: String = chars[start..i].iter().collect();
+                tokens.push(Token::Int(
+                    text.parse()
+                        .map_err(|_| format!("invalid number '{text}'"))?,
+                ));
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        } else if c == '\'' || c == '"' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("unterminated string".to_string()),
+                    Some(&quote) if quote == c => break,
+                    Some('\\') => {
+                        i += 1;
+                        match chars.get(i) {
+                            Some('n') => text.push('\n'),
+                            Some('t') => text.push('\t'),
+                            Some('r') => text.push('\r'),
+                            Some(&escaped @ ('\\' | '\'' | '"')) => text.push(escaped),
+                            // Unknown escapes stay, as in Python: '\d' is a backslash and a d
+                            Some(&other) => {
+                                text.push('\\');
+                                text.push(other);
+                            }
+                            None => return Err("unterminated string".to_string()),
+                        }
+                    }
+                    Some(&other) => text.push(other),
+                }
+                i += 1;
+            }
+            i += 1;
+            tokens.push(Token::Str(text));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| format!("unexpected character '{c}'"))?;
+            i += op.chars().count();
+            tokens.push(Token::Op(op));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Value),
+    Name(String),
+    List(Vec<Expr>),
+    Dict(Vec<(Expr, Expr)>),
+    Attr(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Method(Box<Expr>, String, Vec<Arg>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Compare(Box<Expr>, Vec<(&'static str, Expr)>),
+    Filter(Box<Expr>, String, Vec<Arg>),
+    Test(Box<Expr>, String, Vec<Arg>, bool),
+    If(Box<Expr>, Box<Expr>, Option<Box<Expr>>),
+}
+
+/// A call argument, positional or `name=value`
+#[derive(Debug, Clone)]
+struct Arg {
+    name: Option<String>,
+    value: Expr,
+}
+
+fn parse(source: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+    };
+    if parser.tokens.is_empty() {
+        return Err("empty expression".to_string());
+    }
+    let expr = parser.expression()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(format!("unexpected {}", describe(token))),
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Int(value) => format!("'{value}'"),
+        Token::Float(value) => format!("'{value}'"),
+        Token::Str(value) => format!("string '{value}'"),
+        Token::Name(name) => format!("'{name}'"),
+        Token::Op(op) => format!("'{op}'"),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| "unexpected end of expression".to_string())?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(next)) if *next == op) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.at_keyword(keyword) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Name(name)) if name == keyword)
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<(), String> {
+        match self.next()? {
+            Token::Op(next) if next == op => Ok(()),
+            other => Err(format!("expected '{op}', found {}", describe(&other))),
+        }
+    }
+
+    fn expect_name(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Name(name) => Ok(name),
+            other => Err(format!("expected a name, found {}", describe(&other))),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, String> {
+        let expr = self.or()?;
+        if !self.eat_keyword("if") {
+            return Ok(expr);
+        }
+        let condition = self.or()?;
+        let otherwise = if self.eat_keyword("else") {
+            Some(Box::new(self.expression()?))
+        } else {
+            None
+        };
+        Ok(Expr::If(Box::new(condition), Box::new(expr), otherwise))
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat_keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.eat_keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.eat_keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Expr, String> {
+        let first = self.concat()?;
+        let mut comparisons = Vec::new();
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op @ ("==" | "!=" | "<" | ">" | "<=" | ">="))) => *op,
+                Some(Token::Name(name)) if name == "in" => "in",
+                Some(Token::Name(name))
+                    if name == "not"
+                        && matches!(self.tokens.get(self.pos + 1), Some(Token::Name(next)) if next == "in") =>
+                {
+                    self.pos += 1;
+                    "not in"
+                }
+                _ => break,
+            };
+            self.pos += 1;
+            comparisons.push((op, self.concat()?));
+        }
+        if comparisons.is_empty() {
+            return Ok(first);
+        }
+        Ok(Expr::Compare(Box::new(first), comparisons))
+    }
+
+    fn concat(&mut self) -> Result<Expr, String> {
+        let mut expr = self.sum()?;
+        while self.eat_op("~") {
+            expr = Expr::Binary("~", Box::new(expr), Box::new(self.sum()?));
+        }
+        Ok(expr)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op @ ("+" | "-"))) => *op,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op @ ("*" | "/" | "//" | "%"))) => *op,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat_op("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if self.eat_op("+") {
+            return self.unary();
+        }
+        let mut expr = self.postfix()?;
+        loop {
+            if self.eat_op("|") {
+                let name = self.expect_name()?;
+                let args = if self.eat_op("(") {
+                    self.arguments()?
+                } else {
+                    Vec::new()
+                };
+                expr = Expr::Filter(Box::new(expr), name, args);
+            } else if self.eat_keyword("is") {
+                let negated = self.eat_keyword("not");
+                let name = self.expect_name()?;
+                let args = if self.eat_op("(") {
+                    self.arguments()?
+                } else if self.at_bare_argument() {
+                    vec![Arg {
+                        name: None,
+                        value: self.primary()?,
+                    }]
+                } else {
+                    Vec::new()
+                };
+                expr = Expr::Test(Box::new(expr), name, args, negated);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Whether a test is followed by an argument without parentheses, as in
+    /// `is divisibleby 3` or `is sameas true`
+    fn at_bare_argument(&self) -> bool {
+        match self.peek() {
+            Some(Token::Int(_) | Token::Float(_) | Token::Str(_)) => true,
+            Some(Token::Name(name)) => matches!(
+                name.as_str(),
+                "true" | "false" | "none" | "True" | "False" | "None"
+            ),
+            _ => false,
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat_op(".") {
+                let name = match self.next()? {
+                    Token::Name(name) => name,
+                    Token::Int(index) => index.to_string(),
+                    other => {
+                        return Err(format!("expected an attribute, found {}", describe(&other)))
+                    }
+                };
+                expr = if self.eat_op("(") {
+                    Expr::Method(Box::new(expr), name, self.arguments()?)
+                } else {
+                    Expr::Attr(Box::new(expr), name)
+                };
+            } else if self.eat_op("[") {
+                let index = self.expression()?;
+                self.expect_op("]")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next()? {
+            Token::Int(value) => Ok(Expr::Literal(value.into())),
+            Token::Float(value) => Ok(Expr::Literal(float(value))),
+            Token::Str(mut text) => {
+                // Adjacent strings concatenate, as in Python
+                while let Some(Token::Str(next)) = self.peek() {
+                    text.push_str(next);
+                    self.pos += 1;
+                }
+                Ok(Expr::Literal(Value::String(text)))
+            }
+            Token::Name(name) => Ok(match name.as_str() {
+                "true" | "True" => Expr::Literal(Value::Bool(true)),
+                "false" | "False" => Expr::Literal(Value::Bool(false)),
+                "none" | "None" => Expr::Literal(Value::Null),
+                _ => Expr::Name(name),
+            }),
+            Token::Op("(") => {
+                if self.eat_op(")") {
+                    return Ok(Expr::List(Vec::new()));
+                }
+                let expr = self.expression()?;
+                if !self.eat_op(",") {
+                    self.expect_op(")")?;
+                    return Ok(expr);
+                }
+                // A tuple, which behaves like a list
+                let mut items = vec![expr];
+                while !self.eat_op(")") {
+                    items.push(self.expression()?);
+                    if !self.eat_op(",") {
+                        self.expect_op(")")?;
+                        break;
+                    }
+                }
+                Ok(Expr::List(items))
+            }
+            Token::Op("[") => {
+                let mut items = Vec::new();
+                while !self.eat_op("]") {
+                    items.push(self.expression()?);
+                    if !self.eat_op(",") {
+                        self.expect_op("]")?;
+                        break;
+                    }
+                }
+                Ok(Expr::List(items))
+            }
+            Token::Op("{") => {
+                let mut entries = Vec::new();
+                while !self.eat_op("}") {
+                    let key = self.expression()?;
+                    self.expect_op(":")?;
+                    entries.push((key, self.expression()?));
+                    if !self.eat_op(",") {
+                        self.expect_op("}")?;
+                        break;
+                    }
+                }
+                Ok(Expr::Dict(entries))
+            }
+            other => Err(format!("unexpected {}", describe(&other))),
+        }
+    }
+
+    /// Arguments up to the closing parenthesis
+    fn arguments(&mut self) -> Result<Vec<Arg>, String> {
+        let mut args = Vec::new();
+        while !self.eat_op(")") {
+            let named = matches!(self.peek(), Some(Token::Name(_)))
+                && matches!(self.tokens.get(self.pos + 1), Some(Token::Op("=")));
+            let name = if named {
+                let name = self.expect_name()?;
+                self.pos += 1;
+                Some(name)
+            } else {
+                None
+            };
+            args.push(Arg {
+                name,
+                value: self.expression()?,
+            });
+            if !self.eat_op(",") {
+                self.expect_op(")")?;
+                break;
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// The value of an expression, which may be undefined
+#[derive(Debug, Clone)]
+enum Val {
+    Defined(Value),
+    /// Why the value is undefined
+    Undefined(String),
+}
+
+impl Val {
+    fn defined(self) -> Result<Value, String> {
+        match self {
+            Val::Defined(value) => Ok(value),
+            Val::Undefined(reason) => Err(reason),
+        }
+    }
+}
+
+/// Evaluated call arguments
+struct Args {
+    positional: Vec<Val>,
+    named: HashMap<String, Val>,
+}
+
+impl Args {
+    /// Argument `index`, or the one passed as `name`
+    fn get(&self, index: usize, name: &str) -> Option<Val> {
+        self.positional
+            .get(index)
+            .or_else(|| self.named.get(name))
+            .cloned()
+    }
+
+    fn value(&self, index: usize, name: &str) -> Result<Option<Value>, String> {
+        self.get(index, name).map(Val::defined).transpose()
+    }
+
+    fn required(&self, index: usize, name: &str) -> Result<Value, String> {
+        self.value(index, name)?
+            .ok_or_else(|| format!("missing argument '{name}'"))
+    }
+
+    fn string(&self, index: usize, name: &str) -> Result<Option<String>, String> {
+        self.value(index, name)
+            .map(|value| value.map(|v| display(&v)))
+    }
+
+    fn flag(&self, index: usize, name: &str) -> Result<bool, String> {
+        Ok(self.value(index, name)?.is_some_and(|value| truthy(&value)))
+    }
+}
+
+fn eval(expr: &Expr, lookup: &dyn Fn(&str) -> Option<Value>) -> Result<Val, String> {
+    let value = |expr: &Expr| eval(expr, lookup)?.defined();
+    Ok(Val::Defined(match expr {
+        Expr::Literal(value) => value.clone(),
+        Expr::Name(name) => {
+            return Ok(match lookup(name) {
+                Some(value) => Val::Defined(value),
+                None => Val::Undefined(format!("'{name}' is undefined")),
+            })
+        }
+        Expr::List(items) => Value::Array(items.iter().map(value).collect::<Result<_, _>>()?),
+        Expr::Dict(entries) => {
+            let mut map = Map::new();
+            for (key, entry) in entries {
+                map.insert(display(&value(key)?), value(entry)?);
+            }
+            Value::Object(map)
+        }
+        Expr::Attr(base, name) => return Ok(attribute(eval(base, lookup)?, name)),
+        Expr::Index(base, index) => {
+            let base = eval(base, lookup)?;
+            return Ok(match (base, value(index)?) {
+                (Val::Undefined(reason), _) => Val::Undefined(reason),
+                (Val::Defined(Value::Array(items)), Value::Number(n)) => {
+                    let index = n.as_i64().ok_or("list indices must be integers")?;
+                    let index = if index < 0 {
+                        items.len() as i64 + index
+                    } else {
+                        index
+                    };
+                    match usize::try_from(index).ok().and_then(|i| items.get(i)) {
+                        Some(item) => Val::Defined(item.clone()),
+                        None => Val::Undefined("list index out of range".to_string()),
+                    }
+                }
+                (Val::Defined(base), Value::String(key)) => attribute(Val::Defined(base), &key),
+                (Val::Defined(base), index) => {
+                    Val::Undefined(format!("{} has no item {index}", type_name(&base)))
+                }
+            });
+        }
+        Expr::Method(base, name, args) => {
+            let base = value(base)?;
+            method(&base, name, &arguments(args, lookup)?)?
+        }
+        Expr::Not(operand) => Value::Bool(!truthy(&value(operand)?)),
+        Expr::Neg(operand) => arithmetic("-", &Value::from(0), &value(operand)?)?,
+        Expr::And(left, right) => {
+            let left = value(left)?;
+            if !truthy(&left) {
+                left
+            } else {
+                value(right)?
+            }
+        }
+        Expr::Or(left, right) => {
+            let left = value(left)?;
+            if truthy(&left) {
+                left
+            } else {
+                value(right)?
+            }
+        }
+        Expr::Binary("~", left, right) => {
+            Value::String(display(&value(left)?) + &display(&value(right)?))
+        }
+        Expr::Binary(op, left, right) => arithmetic(op, &value(left)?, &value(right)?)?,
+        Expr::Compare(first, comparisons) => {
+            let mut left = value(first)?;
+            for (op, right) in comparisons {
+                let right = value(right)?;
+                if !compare(op, &left, &right)? {
+                    return Ok(Val::Defined(Value::Bool(false)));
+                }
+                left = right;
+            }
+            Value::Bool(true)
+        }
+        Expr::Filter(operand, name, args) => {
+            return filter(name, eval(operand, lookup)?, &arguments(args, lookup)?)
+        }
+        Expr::Test(operand, name, args, negated) => {
+            let passed = test(name, eval(operand, lookup)?, &arguments(args, lookup)?)?;
+            Value::Bool(passed != *negated)
+        }
+        Expr::If(condition, then, otherwise) => {
+            if truthy(&value(condition)?) {
+                return eval(then, lookup);
+            }
+            match otherwise {
+                Some(otherwise) => return eval(otherwise, lookup),
+                None => return Ok(Val::Undefined("the inline if has no else".to_string())),
+            }
+        }
+    }))
+}
+
+fn arguments(args: &[Arg], lookup: &dyn Fn(&str) -> Option<Value>) -> Result<Args, String> {
+    let mut evaluated = Args {
+        positional: Vec::new(),
+        named: HashMap::new(),
+    };
+    for arg in args {
+        let value = eval(&arg.value, lookup)?;
+        match &arg.name {
+            Some(name) => {
+                evaluated.named.insert(name.clone(), value);
+            }
+            None => evaluated.positional.push(value),
+        }
+    }
+    Ok(evaluated)
+}
+
+fn attribute(base: Val, name: &str) -> Val {
+    match base {
+        Val::Undefined(reason) => Val::Undefined(reason),
+        Val::Defined(Value::Object(entries)) => match entries.get(name) {
+            Some(value) => Val::Defined(value.clone()),
+            None => Val::Undefined(format!("dict object has no attribute '{name}'")),
+        },
+        Val::Defined(Value::Array(items)) => {
+            match name.parse::<usize>().ok().and_then(|i| items.get(i)) {
+                Some(item) => Val::Defined(item.clone()),
+                None => Val::Undefined(format!("list object has no attribute '{name}'")),
+            }
+        }
+        Val::Defined(other) => {
+            Val::Undefined(format!("{} has no attribute '{name}'", type_name(&other)))
+        }
+    }
+}
+
+fn float(value: f64) -> Value {
+    Number::from_f64(value).map_or(Value::Null, Value::Number)
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "none",
+        Value::Bool(_) => "bool",
+        Value::Number(n) if n.is_f64() => "float",
+        Value::Number(_) => "int",
+        Value::String(_) => "string",
+        Value::Array(_) => "list",
+        Value::Object(_) => "dict",
+    }
+}
+
+/// A value as Python's `str` renders it
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => "None".to_string(),
+        Value::Bool(true) => "True".to_string(),
+        Value::Bool(false) => "False".to_string(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::Bool(b) => Some(f64::from(u8::from(*b))),
+        _ => None,
+    }
+}
+
+fn integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64(),
+        Value::Bool(b) => Some(i64::from(*b)),
+        _ => None,
+    }
+}
+
+fn arithmetic(op: &str, left: &Value, right: &Value) -> Result<Value, String> {
+    match (op, left, right) {
+        ("+", Value::String(a), Value::String(b)) => return Ok(Value::String(format!("{a}{b}"))),
+        ("+", Value::Array(a), Value::Array(b)) => {
+            return Ok(Value::Array(a.iter().chain(b).cloned().collect()))
+        }
+        ("*", Value::String(text), count) | ("*", count, Value::String(text)) => {
+            if let Some(count) = integer(count) {
+                return Ok(Value::String(
+                    text.repeat(usize::try_from(count).unwrap_or(0)),
+                ));
+            }
+        }
+        _ => {}
+    }
+    let unsupported = || {
+        format!(
+            "unsupported operand types for {op}: {} and {}",
+            type_name(left),
+            type_name(right)
+        )
+    };
+
+    if let (Some(a), Some(b)) = (integer(left), integer(right)) {
+        let result = match op {
+            "+" => a.checked_add(b),
+            "-" => a.checked_sub(b),
+            "*" => a.checked_mul(b),
+            "/" => None,
+            "//" if b == 0 => return Err("integer division by zero".to_string()),
+            "//" => Some(a.div_euclid(b) - i64::from(b < 0 && a.rem_euclid(b) != 0)),
+            "%" if b == 0 => return Err("integer modulo by zero".to_string()),
+            "%" => Some(a.rem_euclid(b) + if b < 0 && a.rem_euclid(b) != 0 { b } else { 0 }),
+            _ => return Err(unsupported()),
+        };
+        if let Some(result) = result {
+            return Ok(result.into());
+        }
+    }
+    let (a, b) = match (number(left), number(right)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Err(unsupported()),
+    };
+    if matches!(op, "/" | "//" | "%") && b == 0.0 {
+        return Err("division by zero".to_string());
+    }
+    Ok(float(match op {
+        "+" => a + b,
+        "-" => a - b,
+        "*" => a * b,
+        "/" => a / b,
+        "//" => (a / b).floor(),
+        "%" => a - b * (a / b).floor(),
+        _ => return Err(unsupported()),
+    }))
+}
+
+fn equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(_), Value::Number(_)) => number(left) == number(right),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equal(a, b))
+        }
+        _ => left == right,
+    }
+}
+
+fn order(left: &Value, right: &Value) -> Result<Ordering, String> {
+    match (left, right) {
+        (Value::String(a), Value::String(b)) => Ok(a.cmp(b)),
+        (Value::Array(a), Value::Array(b)) => {
+            for (a, b) in a.iter().zip(b) {
+                match order(a, b)? {
+                    Ordering::Equal => {}
+                    ordering => return Ok(ordering),
+                }
+            }
+            Ok(a.len().cmp(&b.len()))
+        }
+        _ => match (number(left), number(right)) {
+            (Some(a), Some(b)) => a
+                .partial_cmp(&b)
+                .ok_or_else(|| "cannot order NaN".to_string()),
+            _ => Err(format!(
+                "'<' not supported between {} and {}",
+                type_name(left),
+                type_name(right)
+            )),
+        },
+    }
+}
+
+fn contains(container: &Value, item: &Value) -> Result<bool, String> {
+    match (container, item) {
+        (Value::String(text), Value::String(part)) => Ok(text.contains(part.as_str())),
+        (Value::Array(items), item) => Ok(items.iter().any(|candidate| equal(candidate, item))),
+        (Value::Object(entries), Value::String(key)) => Ok(entries.contains_key(key)),
+        (Value::Object(_), _) => Ok(false),
+        _ => Err(format!(
+            "'in' not supported between {} and {}",
+            type_name(item),
+            type_name(container)
+        )),
+    }
+}
+
+fn compare(op: &str, left: &Value, right: &Value) -> Result<bool, String> {
+    Ok(match op {
+        "==" => equal(left, right),
+        "!=" => !equal(left, right),
+        "<" => order(left, right)? == Ordering::Less,
+        "<=" => order(left, right)? != Ordering::Greater,
+        ">" => order(left, right)? == Ordering::Greater,
+        ">=" => order(left, right)? != Ordering::Less,
+        "in" => contains(right, left)?,
+        "not in" => !contains(right, left)?,
+        _ => return Err(format!("unknown comparison '{op}'")),
+    })
+}
+
+fn regex(pattern: &str, ignorecase: bool, multiline: bool) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignorecase)
+        .multi_line(multiline)
+        .build()
+        .map_err(|e| format!("invalid regular expression '{pattern}': {e}"))
+}
+
+/// Compare versions the way `LooseVersion` does: numeric components
+/// numerically, others alphabetically, a number ranking after a word so
+/// that `1.0rc1` comes before `1.0.1`
+pub fn compare_versions(left: &str, right: &str) -> Ordering {
+    fn components(version: &str) -> Vec<Result<u64, String>> {
+        let mut parts = Vec::new();
+        let mut current = String::new();
+        let mut numeric = false;
+        for c in version.chars() {
+            let is_digit = c.is_ascii_digit();
+            if !current.is_empty() && (!c.is_alphanumeric() || is_digit != numeric) {
+                parts.push(std::mem::take(&mut current));
+            }
+            if c.is_alphanumeric() {
+                numeric = is_digit;
+                current.push(c);
+            }
+        }
+        if !current.is_empty() {
+            parts.push(current);
+        }
+        parts
+            .into_iter()
+            .map(|part| part.parse::<u64>().map_err(|_| part.to_lowercase()))
+            .collect()
+    }
+
+    let (left, right) = (components(left), components(right));
+    for (a, b) in left.iter().zip(&right) {
+        let ordering = match (a, b) {
+            (Ok(a), Ok(b)) => a.cmp(b),
+            (Err(a), Err(b)) => a.cmp(b),
+            (Ok(_), Err(_)) => Ordering::Greater,
+            (Err(_), Ok(_)) => Ordering::Less,
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    left.len().cmp(&right.len())
+}
+
+fn version_test(value: &Value, args: &Args) -> Result<bool, String> {
+    let version = display(value);
+    let other = display(&args.required(0, "version")?);
+    let op = args
+        .string(1, "operator")?
+        .unwrap_or_else(|| "eq".to_string());
+    let ordering = compare_versions(&version, &other);
+    Ok(match op.as_str() {
+        "<" | "lt" => ordering == Ordering::Less,
+        "<=" | "le" => ordering != Ordering::Greater,
+        ">" | "gt" => ordering == Ordering::Greater,
+        ">=" | "ge" => ordering != Ordering::Less,
+        "==" | "=" | "eq" => ordering == Ordering::Equal,
+        "!=" | "<>" | "ne" => ordering != Ordering::Equal,
+        other => return Err(format!("invalid version operator '{other}'")),
+    })
+}
+
+/// A flag of a task result, for `is succeeded` and friends
+fn result_flag(name: &str, value: &Value, flag: &str) -> Result<bool, String> {
+    match value {
+        Value::Object(result) => Ok(result.get(flag).is_some_and(truthy)),
+        other => Err(format!(
+            "the '{name}' test expects a task result, got {}",
+            type_name(other)
+        )),
+    }
+}
+
+fn test(name: &str, operand: Val, args: &Args) -> Result<bool, String> {
+    let value = match (name, operand) {
+        ("defined", operand) => return Ok(matches!(operand, Val::Defined(_))),
+        ("undefined", operand) => return Ok(matches!(operand, Val::Undefined(_))),
+        (_, operand) => operand.defined()?,
+    };
+    let text = || match &value {
+        Value::String(text) => Ok(text.as_str()),
+        other => Err(format!(
+            "the '{name}' test expects a string, got {}",
+            type_name(other)
+        )),
+    };
+
+    Ok(match name {
+        "none" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "true" => value == Value::Bool(true),
+        "false" => value == Value::Bool(false),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "float" => value.is_f64(),
+        "string" => value.is_string(),
+        "mapping" => value.is_object(),
+        "sequence" | "iterable" => value.is_array() || value.is_string() || value.is_object(),
+        "truthy" => truthy(&value),
+        "falsy" => !truthy(&value),
+        "lower" => text()?.chars().all(|c| !c.is_uppercase()),
+        "upper" => text()?.chars().all(|c| !c.is_lowercase()),
+        "even" | "odd" | "divisibleby" => {
+            let n =
+                integer(&value).ok_or_else(|| format!("the '{name}' test expects an integer"))?;
+            let divisor = match name {
+                "divisibleby" => integer(&args.required(0, "num")?)
+                    .filter(|d| *d != 0)
+                    .ok_or("divisibleby expects a non-zero integer")?,
+                _ => 2,
+            };
+            (n.rem_euclid(divisor) == 0) != (name == "odd")
+        }
+        "eq" | "equalto" | "==" => equal(&value, &args.required(0, "other")?),
+        "ne" | "!=" => !equal(&value, &args.required(0, "other")?),
+        "lt" | "lessthan" => compare("<", &value, &args.required(0, "other")?)?,
+        "le" => compare("<=", &value, &args.required(0, "other")?)?,
+        "gt" | "greaterthan" => compare(">", &value, &args.required(0, "other")?)?,
+        "ge" => compare(">=", &value, &args.required(0, "other")?)?,
+        "sameas" => {
+            let other = args.required(0, "other")?;
+            type_name(&value) == type_name(&other) && equal(&value, &other)
+        }
+        "in" => contains(&args.required(0, "seq")?, &value)?,
+        "contains" => contains(&value, &args.required(0, "item")?)?,
+        "subset" | "superset" => {
+            let other = args.required(0, "other")?;
+            let (subset, superset) = if name == "subset" {
+                (&value, &other)
+            } else {
+                (&other, &value)
+            };
+            match (subset, superset) {
+                (Value::Array(subset), superset @ Value::Array(_)) => subset
+                    .iter()
+                    .map(|item| contains(superset, item))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter()
+                    .all(|contained| contained),
+                _ => return Err(format!("the '{name}' test expects lists")),
+            }
+        }
+        "succeeded" | "success" => !result_flag(name, &value, "failed")?,
+        "failed" | "failure" => result_flag(name, &value, "failed")?,
+        "changed" | "change" => result_flag(name, &value, "changed")?,
+        "skipped" | "skip" => result_flag(name, &value, "skipped")?,
+        "finished" => result_flag(name, &value, "finished")?,
+        "started" => result_flag(name, &value, "started")?,
+        "match" | "search" | "regex" => {
+            let pattern = display(&args.required(0, "pattern")?);
+            let re = regex(
+                &pattern,
+                args.flag(1, "ignorecase")?,
+                args.flag(2, "multiline")?,
+            )?;
+            let match_type = match name {
+                "regex" => args
+                    .string(3, "match_type")?
+                    .unwrap_or_else(|| "search".to_string()),
+                other => other.to_string(),
+            };
+            let text = text()?;
+            match match_type.as_str() {
+                "match" => re.find(text).is_some_and(|found| found.start() == 0),
+                _ => re.is_match(text),
+            }
+        }
+        "version" | "version_compare" => version_test(&value, args)?,
+        other => return Err(format!("no test named '{other}'")),
+    })
+}
+
+fn length(value: &Value) -> Result<usize, String> {
+    match value {
+        Value::String(text) => Ok(text.chars().count()),
+        Value::Array(items) => Ok(items.len()),
+        Value::Object(entries) => Ok(entries.len()),
+        other => Err(format!("{} has no length", type_name(other))),
+    }
+}
+
+fn items(value: Value) -> Result<Vec<Value>, String> {
+    match value {
+        Value::Array(items) => Ok(items),
+        Value::String(text) => Ok(text.chars().map(|c| Value::String(c.into())).collect()),
+        Value::Object(entries) => Ok(entries.into_iter().map(|(key, _)| key.into()).collect()),
+        other => Err(format!("{} is not iterable", type_name(&other))),
+    }
+}
+
+/// Sort order of values, numbers before strings and strings before the rest
+fn sort_key(a: &Value, b: &Value) -> Ordering {
+    order(a, b).unwrap_or_else(|_| type_name(a).cmp(type_name(b)))
+}
+
+fn filter(name: &str, operand: Val, args: &Args) -> Result<Val, String> {
+    let value = match (name, operand) {
+        ("default" | "d", operand) => {
+            let fallback = args
+                .get(0, "default_value")
+                .unwrap_or(Val::Defined("".into()));
+            return Ok(match operand {
+                Val::Undefined(_) => fallback,
+                Val::Defined(value) if args.flag(1, "boolean")? && !truthy(&value) => fallback,
+                defined => defined,
+            });
+        }
+        ("mandatory", Val::Undefined(reason)) => {
+            return Err(format!("mandatory variable not defined: {reason}"))
+        }
+        (_, operand) => operand.defined()?,
+    };
+    let text = || display(&value);
+
+    Ok(Val::Defined(match name {
+        "mandatory" => value,
+        "bool" => Value::Bool(match &value {
+            Value::String(text) => matches!(
+                text.trim().to_lowercase().as_str(),
+                "yes" | "on" | "1" | "true" | "y" | "t"
+            ),
+            other => truthy(other),
+        }),
+        "int" => {
+            let parsed = match &value {
+                Value::String(text) => text
+                    .trim()
+                    .parse::<i64>()
+                    .ok()
+                    .or_else(|| text.trim().parse::<f64>().ok().map(|f| f.trunc() as i64)),
+                other => number(other).map(|f| f.trunc() as i64),
+            };
+            match parsed {
+                Some(n) => n.into(),
+                None => args.value(0, "default")?.unwrap_or_else(|| 0.into()),
+            }
+        }
+        "float" => {
+            let parsed = match &value {
+                Value::String(text) => text.trim().parse::<f64>().ok(),
+                other => number(other),
+            };
+            match parsed {
+                Some(f) => float(f),
+                None => args.value(0, "default")?.unwrap_or_else(|| float(0.0)),
+            }
+        }
+        "string" => Value::String(text()),
+        "lower" => Value::String(text().to_lowercase()),
+        "upper" => Value::String(text().to_uppercase()),
+        "trim" => Value::String(text().trim().to_string()),
+        "capitalize" => {
+            let text = text().to_lowercase();
+            let mut chars = text.chars();
+            Value::String(match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            })
+        }
+        "length" | "count" => length(&value)?.into(),
+        "first" => items(value)?.into_iter().next().unwrap_or(Value::Null),
+        "last" => items(value)?.pop().unwrap_or(Value::Null),
+        "list" => Value::Array(items(value)?),
+        "join" => {
+            let separator = args.string(0, "d")?.unwrap_or_default();
+            Value::String(
+                items(value)?
+                    .iter()
+                    .map(display)
+                    .collect::<Vec<_>>()
+                    .join(&separator),
+            )
+        }
+        "unique" => {
+            let mut unique: Vec<Value> = Vec::new();
+            for item in items(value)? {
+                if !unique.iter().any(|seen| equal(seen, &item)) {
+                    unique.push(item);
+                }
+            }
+            Value::Array(unique)
+        }
+        "sort" => {
+            let mut sorted = items(value)?;
+            sorted.sort_by(sort_key);
+            if args.flag(0, "reverse")? {
+                sorted.reverse();
+            }
+            Value::Array(sorted)
+        }
+        "reverse" => match value {
+            Value::String(text) => Value::String(text.chars().rev().collect()),
+            other => Value::Array(items(other)?.into_iter().rev().collect()),
+        },
+        "min" => items(value)?
+            .into_iter()
+            .min_by(sort_key)
+            .unwrap_or(Value::Null),
+        "max" => items(value)?
+            .into_iter()
+            .max_by(sort_key)
+            .unwrap_or(Value::Null),
+        "sum" => {
+            let mut total = args.value(0, "start")?.unwrap_or_else(|| 0.into());
+            for item in items(value)? {
+                total = arithmetic("+", &total, &item)?;
+            }
+            total
+        }
+        "abs" => match &value {
+            Value::Number(n) if n.is_f64() => float(n.as_f64().unwrap_or_default().abs()),
+            other => integer(other)
+                .map(|n| Value::from(n.abs()))
+                .ok_or_else(|| format!("bad operand type for abs: {}", type_name(other)))?,
+        },
+        "replace" => {
+            let old = args.string(0, "old")?.unwrap_or_default();
+            let new = args.string(1, "new")?.unwrap_or_default();
+            Value::String(text().replace(&old, &new))
+        }
+        "regex_search" => {
+            let pattern = args.string(0, "regex")?.unwrap_or_default();
+            let re = regex(
+                &pattern,
+                args.flag(1, "ignorecase")?,
+                args.flag(2, "multiline")?,
+            )?;
+            re.find(&text())
+                .map_or(Value::Null, |found| found.as_str().into())
+        }
+        "regex_replace" => {
+            let pattern = args.string(0, "regex")?.unwrap_or_default();
+            let replacement = args.string(1, "replace")?.unwrap_or_default();
+            // Python's `\1` backreferences are `${1}` for the regex crate
+            let replacement = Regex::new(r"\\(\d+)")
+                .map_err(|e| e.to_string())?
+                .replace_all(&replacement, "$${$1}")
+                .into_owned();
+            let re = regex(
+                &pattern,
+                args.flag(2, "ignorecase")?,
+                args.flag(3, "multiline")?,
+            )?;
+            Value::String(re.replace_all(&text(), replacement.as_str()).into_owned())
+        }
+        "split" => split(&text(), args.string(0, "sep")?.as_deref()),
+        "ternary" => {
+            if truthy(&value) {
+                args.required(0, "true_val")?
+            } else {
+                args.required(1, "false_val")?
+            }
+        }
+        "to_json" => Value::String(value.to_string()),
+        "from_json" => serde_json::from_str(&text()).map_err(|e| format!("invalid JSON: {e}"))?,
+        "dict2items" => match value {
+            Value::Object(entries) => Value::Array(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| serde_json::json!({ "key": key, "value": value }))
+                    .collect(),
+            ),
+            other => {
+                return Err(format!(
+                    "dict2items expects a dict, got {}",
+                    type_name(&other)
+                ))
+            }
+        },
+        "basename" => Value::String(text().rsplit('/').next().unwrap_or_default().to_string()),
+        "dirname" => {
+            let text = text();
+            Value::String(match text.rfind('/') {
+                Some(0) => "/".to_string(),
+                Some(end) => text[..end].to_string(),
+                None => String::new(),
+            })
+        }
+        other => return Err(format!("no filter named '{other}'")),
+    }))
+}
+
+fn split(text: &str, separator: Option<&str>) -> Value {
+    let parts: Vec<Value> = match separator {
+        Some(separator) if !separator.is_empty() => {
+            text.split(separator).map(|part| part.into()).collect()
+        }
+        _ => text.split_whitespace().map(|part| part.into()).collect(),
+    };
+    Value::Array(parts)
+}
+
+/// Python string and dict methods, as in `ansible_distribution.startswith('Cent')`
+fn method(base: &Value, name: &str, args: &Args) -> Result<Value, String> {
+    if let Value::Object(entries) = base {
+        return Ok(match name {
+            "get" => {
+                let key = display(&args.required(0, "key")?);
+                match entries.get(&key) {
+                    Some(value) => value.clone(),
+                    None => args.value(1, "default")?.unwrap_or(Value::Null),
+                }
+            }
+            "keys" => Value::Array(entries.keys().map(|key| key.as_str().into()).collect()),
+            "values" => Value::Array(entries.values().cloned().collect()),
+            "items" => Value::Array(
+                entries
+                    .iter()
+                    .map(|(key, value)| serde_json::json!([key, value]))
+                    .collect(),
+            ),
+            other => return Err(format!("dict object has no method '{other}'")),
+        });
+    }
+    if let Value::Array(items) = base {
+        return Ok(match name {
+            "count" => {
+                let item = args.required(0, "value")?;
+                items
+                    .iter()
+                    .filter(|candidate| equal(candidate, &item))
+                    .count()
+                    .into()
+            }
+            "index" => {
+                let item = args.required(0, "value")?;
+                items
+                    .iter()
+                    .position(|candidate| equal(candidate, &item))
+                    .ok_or_else(|| format!("{} is not in list", display(&item)))?
+                    .into()
+            }
+            other => return Err(format!("list object has no method '{other}'")),
+        });
+    }
+    let Value::String(text) = base else {
+        return Err(format!("{} has no method '{name}'", type_name(base)));
+    };
+    let affixes = |index: usize, name: &str| -> Result<Vec<String>, String> {
+        Ok(match args.required(index, name)? {
+            Value::Array(options) => options.iter().map(display).collect(),
+            other => vec![display(&other)],
+        })
+    };
+
+    Ok(match name {
+        "startswith" => affixes(0, "prefix")?
+            .iter()
+            .any(|prefix| text.starts_with(prefix.as_str()))
+            .into(),
+        "endswith" => affixes(0, "suffix")?
+            .iter()
+            .any(|suffix| text.ends_with(suffix.as_str()))
+            .into(),
+        "lower" => text.to_lowercase().into(),
+        "upper" => text.to_uppercase().into(),
+        "strip" => text.trim().into(),
+        "lstrip" => text.trim_start().into(),
+        "rstrip" => text.trim_end().into(),
+        "split" => split(text, args.string(0, "sep")?.as_deref()),
+        "splitlines" => Value::Array(text.lines().map(|line| line.into()).collect()),
+        "find" => {
+            let part = args.string(0, "sub")?.unwrap_or_default();
+            match text.find(&part) {
+                Some(byte) => text[..byte].chars().count().into(),
+                None => (-1).into(),
+            }
+        }
+        "replace" => {
+            let old = args.string(0, "old")?.unwrap_or_default();
+            let new = args.string(1, "new")?.unwrap_or_default();
+            text.replace(&old, &new).into()
+        }
+        "count" => {
+            let part = args.string(0, "sub")?.unwrap_or_default();
+            text.matches(&part).count().into()
+        }
+        "join" => items(args.required(0, "iterable")?)?
+            .iter()
+            .map(display)
+            .collect::<Vec<_>>()
+            .join(text)
+            .into(),
+        "isdigit" => (!text.is_empty() && text.chars().all(|c| c.is_ascii_digit())).into(),
+        "isalpha" => (!text.is_empty() && text.chars().all(char::is_alphabetic)).into(),
+        other => return Err(format!("string has no method '{other}'")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn check(expression: &str, variables: &Value) -> Result<bool, String> {
+        let lookup = |name: &str| variables.get(name).cloned();
+        is_true(expression, &lookup)
+    }
+
+    #[test]
+    fn test_boolean_logic_and_comparisons() {
+        let variables = json!({
+            "port": 8080,
+            "os": "Ubuntu",
+            "features": ["tls", "http2"],
+            "limits": {"cpu": 2.5},
+        });
+        assert!(check("port > 1024 and port < 65536", &variables).unwrap());
+        assert!(check("not (os == 'CentOS' or port == 80)", &variables).unwrap());
+        assert!(check("'tls' in features and 'quic' not in features", &variables).unwrap());
+        assert!(check("limits.cpu * 2 == 5", &variables).unwrap());
+        assert!(check(
+            "features[-1] == 'http2' and features.0 == 'tls'",
+            &variables
+        )
+        .unwrap());
+        assert!(check("os ~ '-' ~ port == 'Ubuntu-8080'", &variables).unwrap());
+        assert!(check("1 < port // 1000 <= 8", &variables).unwrap());
+        assert!(check("os.startswith(('Deb', 'Ubu'))", &variables).unwrap());
+        assert!(check("{{ os | lower == 'ubuntu' }}", &variables).unwrap());
+        assert!(!check("'yes' if port == 80 else ''", &variables).unwrap());
+        assert!(check("features | length == 2", &variables).unwrap());
+        assert!(check("missing | default(3) | int == 3", &variables).unwrap());
+
+        assert_eq!(
+            check("missing == 1", &variables).unwrap_err(),
+            "'missing' is undefined"
+        );
+        assert!(check("port ==", &variables).is_err());
+        assert!(check("os > 1", &variables).is_err());
+    }
+
+    #[test]
+    fn test_jinja_tests() {
+        let variables = json!({
+            "install": {"changed": true, "failed": false, "rc": 0},
+            "probe": {"changed": false, "failed": true, "skipped": false},
+            "kernel": "5.15.0-91-generic",
+            "hostname": "web-01",
+            "count": 6,
+        });
+        assert!(check("undefined_var is not defined", &variables).unwrap());
+        assert!(check(
+            "install.rc is defined and install.missing is undefined",
+            &variables
+        )
+        .unwrap());
+        assert!(check("undefined_var.attribute is undefined", &variables).unwrap());
+        assert!(check("install is succeeded and install is changed", &variables).unwrap());
+        assert!(check("probe is failed and probe is not skipped", &variables).unwrap());
+        assert!(check("hostname is match('web-\\d+')", &variables).unwrap());
+        assert!(!check("hostname is match('\\d+')", &variables).unwrap());
+        assert!(check("hostname is search('\\d+')", &variables).unwrap());
+        assert!(check("kernel is version('5.4', '>=')", &variables).unwrap());
+        assert!(check("kernel is not version('5.15.0', '<=')", &variables).unwrap());
+        assert!(check(
+            "['tls'] is subset(['tls', 'h2']) and 0 is falsy",
+            &variables
+        )
+        .unwrap());
+        assert!(check(
+            "kernel is version_compare('6.0', operator='lt')",
+            &variables
+        )
+        .unwrap());
+        assert!(check("count is even and count is divisibleby 3", &variables).unwrap());
+        assert!(check("none is none and count is number", &variables).unwrap());
+        assert!(check("kernel is string and install is mapping", &variables).unwrap());
+        assert!(check("hostname is version", &variables).is_err());
+        assert!(check("kernel is succeeded", &variables).is_err());
+    }
+
+    #[test]
+    fn test_version_ordering() {
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("2.0", "2.0.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.0rc1", "1.0.1"), Ordering::Less);
+        assert_eq!(compare_versions("1.2.3", "1.2.3"), Ordering::Equal);
+    }
+}
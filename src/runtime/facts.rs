@@ -309,6 +309,7 @@ impl FactsCollector {
 }
 
 /// Cache for facts to avoid repeated collection
+#[derive(Clone)]
 pub struct FactsCache {
     cache: HashMap<String, CachedFact>,
     ttl: Duration,
@@ -0,0 +1,374 @@
+//! Fault injection for exercising retry, rollback and resume paths in tests.
+//!
+//! Disabled unless a [`FaultInjectionConfig`] enables it or `RUSTLE_FAULTS`
+//! is set. `RUSTLE_FAULTS` is a comma-separated list of rules of the form
+//! `kind[@target][:probability][*limit]`, for example
+//! `partition@web01:0.5,corrupt_upload*1,crash@install_nginx`.
+//! `RUSTLE_FAULTS_SEED` makes the random choices reproducible.
+
+use crate::runtime::error::FaultSpecError;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub const FAULTS_ENV: &str = "RUSTLE_FAULTS";
+pub const FAULTS_SEED_ENV: &str = "RUSTLE_FAULTS_SEED";
+
+/// Exit code used when an `abort` fault terminates the process
+pub const ABORT_EXIT_CODE: i32 = 86;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultKind {
+    /// Flip bytes in an upload (runner binary or result bundle) after checksumming
+    CorruptUpload,
+    /// Fail mid-operation, after side effects but before completion is recorded
+    Crash,
+    /// Like `crash`, but terminate the process with [`ABORT_EXIT_CODE`]
+    Abort,
+    /// Make the remote host, controller or object store unreachable
+    Partition,
+}
+
+impl FaultKind {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "corrupt_upload" | "corrupt" => Some(Self::CorruptUpload),
+            "crash" => Some(Self::Crash),
+            "abort" => Some(Self::Abort),
+            "partition" => Some(Self::Partition),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FaultRule {
+    pub kind: FaultKind,
+    /// Host or task id the rule applies to; every target when unset
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Chance that a matching injection point fires
+    #[serde(default = "default_probability")]
+    pub probability: f64,
+    /// Stop firing after this many injections
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+fn default_probability() -> f64 {
+    1.0
+}
+
+impl FaultRule {
+    fn parse(rule: &str) -> Result<Self, FaultSpecError> {
+        let invalid = |reason: &str| FaultSpecError::InvalidRule {
+            rule: rule.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let (rest, limit) = match rule.rsplit_once('*') {
+            Some((rest, limit)) => (
+                rest,
+                Some(
+                    limit
+                        .parse()
+                        .map_err(|_| invalid("limit must be an integer"))?,
+                ),
+            ),
+            None => (rule, None),
+        };
+        let (rest, probability) = match rest.rsplit_once(':') {
+            Some((rest, probability)) => (
+                rest,
+                probability
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|p| (0.0..=1.0).contains(p))
+                    .ok_or_else(|| invalid("probability must be between 0 and 1"))?,
+            ),
+            None => (rest, default_probability()),
+        };
+        let (kind, target) = match rest.split_once('@') {
+            Some((kind, target)) => (kind, Some(target.to_string())),
+            None => (rest, None),
+        };
+        let kind = FaultKind::parse(kind.trim())
+            .ok_or_else(|| invalid("expected corrupt_upload, crash, abort or partition"))?;
+
+        Ok(Self {
+            kind,
+            target,
+            probability,
+            limit,
+        })
+    }
+
+    fn matches(&self, kind: FaultKind, target: &str) -> bool {
+        self.kind == kind && self.target.as_deref().is_none_or(|t| t == target)
+    }
+}
+
+/// Fault injection settings; off by default
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FaultInjectionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Seed for the probability rolls; random when unset
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub rules: Vec<FaultRule>,
+}
+
+impl FaultInjectionConfig {
+    /// Parse a `RUSTLE_FAULTS`-style rule list
+    pub fn parse(spec: &str) -> Result<Self, FaultSpecError> {
+        let rules = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .map(FaultRule::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            enabled: !rules.is_empty(),
+            seed: None,
+            rules,
+        })
+    }
+
+    /// Read `RUSTLE_FAULTS` and `RUSTLE_FAULTS_SEED`, if set
+    pub fn from_env() -> Result<Option<Self>, FaultSpecError> {
+        let Ok(spec) = std::env::var(FAULTS_ENV) else {
+            return Ok(None);
+        };
+        let mut config = Self::parse(&spec)?;
+        if let Ok(seed) = std::env::var(FAULTS_SEED_ENV) {
+            config.seed = Some(
+                seed.trim()
+                    .parse()
+                    .map_err(|_| FaultSpecError::InvalidSeed(seed.clone()))?,
+            );
+        }
+        Ok(Some(config))
+    }
+}
+
+/// Decides whether an injection point fires. Cheap to clone; a disabled
+/// injector never fires and does no work.
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjector {
+    inner: Option<Arc<Injector>>,
+}
+
+#[derive(Debug)]
+struct Injector {
+    rules: Vec<FaultRule>,
+    fired: Vec<AtomicU32>,
+    rng_state: Mutex<u64>,
+}
+
+impl FaultInjector {
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn new(config: &FaultInjectionConfig) -> Self {
+        if !config.enabled || config.rules.is_empty() {
+            return Self::disabled();
+        }
+
+        let seed = config.seed.unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_nanos() as u64)
+                .unwrap_or_default()
+        });
+        tracing::warn!(
+            "Fault injection enabled with {} rule(s), seed {}",
+            config.rules.len(),
+            seed
+        );
+
+        Self {
+            inner: Some(Arc::new(Injector {
+                rules: config.rules.clone(),
+                fired: config.rules.iter().map(|_| AtomicU32::new(0)).collect(),
+                rng_state: Mutex::new(seed),
+            })),
+        }
+    }
+
+    /// Use `RUSTLE_FAULTS` when set, otherwise `config`. An invalid
+    /// environment spec is reported and ignored.
+    pub fn from_env_or(config: Option<&FaultInjectionConfig>) -> Self {
+        match FaultInjectionConfig::from_env() {
+            Ok(Some(config)) => Self::new(&config),
+            Ok(None) => config.map(Self::new).unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!("Ignoring {}: {}", FAULTS_ENV, e);
+                config.map(Self::new).unwrap_or_default()
+            }
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Roll for a fault of `kind` at `target`, recording it if it fires
+    pub fn should_inject(&self, kind: FaultKind, target: &str) -> bool {
+        let Some(ref injector) = self.inner else {
+            return false;
+        };
+
+        for (rule, fired) in injector.rules.iter().zip(&injector.fired) {
+            if !rule.matches(kind, target) {
+                continue;
+            }
+            if rule
+                .limit
+                .is_some_and(|limit| fired.load(Ordering::SeqCst) >= limit)
+            {
+                continue;
+            }
+            if rule.probability < 1.0 && injector.next_f64() >= rule.probability {
+                continue;
+            }
+
+            fired.fetch_add(1, Ordering::SeqCst);
+            tracing::warn!("Injecting {:?} fault at {}", kind, target);
+            return true;
+        }
+        false
+    }
+
+    /// Return a corrupted copy of `data` if a `corrupt_upload` fault fires
+    pub fn corrupt_upload(&self, target: &str, data: &[u8]) -> Option<Vec<u8>> {
+        if data.is_empty() || !self.should_inject(FaultKind::CorruptUpload, target) {
+            return None;
+        }
+
+        let mut corrupted = data.to_vec();
+        let index = self
+            .inner
+            .as_ref()
+            .map(|injector| injector.next_u64() as usize % corrupted.len())
+            .unwrap_or_default();
+        corrupted[index] ^= 0xff;
+        Some(corrupted)
+    }
+
+    /// Check for an injected crash at `target`. An `abort` fault terminates
+    /// the process; a `crash` fault is returned as an error message.
+    pub fn crash_point(&self, target: &str) -> Result<(), String> {
+        if self.should_inject(FaultKind::Abort, target) {
+            tracing::error!("Injected abort at {}", target);
+            std::process::exit(ABORT_EXIT_CODE);
+        }
+        if self.should_inject(FaultKind::Crash, target) {
+            return Err(format!("injected crash at {target}"));
+        }
+        Ok(())
+    }
+
+    /// True if `target` should be treated as unreachable
+    pub fn partitioned(&self, target: &str) -> bool {
+        self.should_inject(FaultKind::Partition, target)
+    }
+}
+
+impl Injector {
+    /// splitmix64; good enough to spread rolls and reproducible from a seed
+    fn next_u64(&self) -> u64 {
+        let mut state = self.rng_state.lock().unwrap_or_else(|e| e.into_inner());
+        *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn next_f64(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules() {
+        let config =
+            FaultInjectionConfig::parse("partition@web01:0.5, corrupt_upload*1,crash").unwrap();
+        assert!(config.enabled);
+        assert_eq!(
+            config.rules,
+            [
+                FaultRule {
+                    kind: FaultKind::Partition,
+                    target: Some("web01".to_string()),
+                    probability: 0.5,
+                    limit: None,
+                },
+                FaultRule {
+                    kind: FaultKind::CorruptUpload,
+                    target: None,
+                    probability: 1.0,
+                    limit: Some(1),
+                },
+                FaultRule {
+                    kind: FaultKind::Crash,
+                    target: None,
+                    probability: 1.0,
+                    limit: None,
+                },
+            ]
+        );
+
+        assert!(FaultInjectionConfig::parse("meteor").is_err());
+        assert!(FaultInjectionConfig::parse("crash:2").is_err());
+    }
+
+    #[test]
+    fn test_targets_and_limits() {
+        let mut config = FaultInjectionConfig::parse("partition@web01,corrupt_upload*1").unwrap();
+        config.seed = Some(7);
+        let faults = FaultInjector::new(&config);
+
+        assert!(faults.partitioned("web01"));
+        assert!(!faults.partitioned("web02"));
+
+        let corrupted = faults.corrupt_upload("web02", b"runner").unwrap();
+        assert_ne!(corrupted, b"runner");
+        assert_eq!(corrupted.len(), 6);
+        assert_eq!(faults.corrupt_upload("web02", b"runner"), None);
+        assert!(faults.crash_point("web02").is_ok());
+    }
+
+    #[test]
+    fn test_seeded_probability_is_reproducible() {
+        let config = FaultInjectionConfig {
+            seed: Some(42),
+            ..FaultInjectionConfig::parse("crash:0.5").unwrap()
+        };
+        let rolls = |faults: FaultInjector| -> Vec<bool> {
+            (0..32)
+                .map(|_| faults.crash_point("task").is_err())
+                .collect()
+        };
+
+        let first = rolls(FaultInjector::new(&config));
+        assert_eq!(first, rolls(FaultInjector::new(&config)));
+        assert!(first.contains(&true) && first.contains(&false));
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        let faults = FaultInjector::new(&FaultInjectionConfig::default());
+        assert!(!faults.is_enabled());
+        assert!(!faults.partitioned("web01"));
+    }
+}
@@ -0,0 +1,598 @@
+//! Secrets lookups.
+//!
+//! Task arguments fetch secrets from external stores with Ansible's lookup
+//! syntax:
+//!
+//! - `{{ lookup('hashivault', 'secret/data/app') }}` reads a HashiCorp Vault
+//!   secret, all of its data or one field as `secret/data/app:password`;
+//! - `{{ lookup('aws_secret', 'prod/db') }}` reads the value of an AWS
+//!   Secrets Manager secret;
+//! - `{{ lookup('aws_ssm', '/prod/db/password') }}` reads an SSM Parameter
+//!   Store parameter, decrypted.
+//!
+//! `query()`, or `q()`, takes the same arguments and returns a list.
+//!
+//! Runners resolve lookups on the target just before a task runs, with the
+//! credentials they find there, so secrets are fetched when they are needed
+//! instead of being compiled into the binary. The controller can resolve
+//! them when it processes the plan instead. Fetched secrets are reused for
+//! [`LookupConfig::cache_ttl_secs`].
+//!
+//! The other lookup plugins, such as `file` or `password`, read what the
+//! controller has and are always evaluated there, by the template engine's
+//! [`crate::modules::files::template_engine::lookup_plugins`]. A runner
+//! finding one of them refuses the task rather than read its own files.
+
+use crate::runtime::error::LookupError;
+use crate::runtime::sigv4::{instance_profile_credentials, AwsCredentials, Signer};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Address of the Vault server when none is configured
+pub const VAULT_ADDR_ENV: &str = "VAULT_ADDR";
+/// Vault token when none is configured
+pub const VAULT_TOKEN_ENV: &str = "VAULT_TOKEN";
+/// Vault Enterprise namespace when none is configured
+pub const VAULT_NAMESPACE_ENV: &str = "VAULT_NAMESPACE";
+/// AppRole secret id when none is configured
+pub const VAULT_SECRET_ID_ENV: &str = "VAULT_SECRET_ID";
+pub use crate::runtime::sigv4::IMDS_ENDPOINT_ENV;
+
+/// The lookup plugins runners evaluate, with their aliases
+pub const SECRET_STORE_LOOKUPS: &[(&str, &[&str])] = &[
+    (
+        "hashivault",
+        &["hashi_vault", "community.hashi_vault.hashi_vault"],
+    ),
+    (
+        "aws_secret",
+        &["amazon.aws.aws_secret", "amazon.aws.secretsmanager_secret"],
+    ),
+    (
+        "aws_ssm",
+        &["amazon.aws.aws_ssm", "amazon.aws.ssm_parameter"],
+    ),
+];
+
+/// The lookup plugins only the controller evaluates
+pub const CONTROLLER_LOOKUPS: &[&str] = &[
+    "file", "env", "pipe", "password", "template", "url", "csvfile", "ini",
+];
+
+/// Whether the controller alone evaluates the lookup plugin `name`
+pub fn is_controller_lookup(name: &str) -> bool {
+    CONTROLLER_LOOKUPS.contains(&name.strip_prefix("ansible.builtin.").unwrap_or(name))
+}
+
+/// How lookups reach the secret stores. Credentials are best left out, for
+/// runners to take from the environment of their host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LookupConfig {
+    #[serde(default)]
+    pub hashivault: HashiVaultConfig,
+    #[serde(default)]
+    pub aws: AwsLookupConfig,
+    /// How long a fetched secret is reused; 0 fetches it again every time
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    300
+}
+
+impl Default for LookupConfig {
+    fn default() -> Self {
+        Self {
+            hashivault: HashiVaultConfig::default(),
+            aws: AwsLookupConfig::default(),
+            cache_ttl_secs: default_cache_ttl_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HashiVaultConfig {
+    /// Address of the server; falls back to `VAULT_ADDR`
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Enterprise namespace; falls back to `VAULT_NAMESPACE`
+    #[serde(default)]
+    pub namespace: Option<String>,
+    #[serde(default)]
+    pub auth: HashiVaultAuth,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum HashiVaultAuth {
+    /// A token; falls back to `VAULT_TOKEN`, then to `~/.vault-token`
+    Token {
+        #[serde(default)]
+        token: Option<String>,
+    },
+    /// Log in with an AppRole; the secret id falls back to `VAULT_SECRET_ID`
+    AppRole {
+        role_id: String,
+        #[serde(default)]
+        secret_id: Option<String>,
+        #[serde(default = "default_approle_mount")]
+        mount: String,
+    },
+}
+
+impl Default for HashiVaultAuth {
+    fn default() -> Self {
+        Self::Token { token: None }
+    }
+}
+
+fn default_approle_mount() -> String {
+    "approle".to_string()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AwsLookupConfig {
+    /// Falls back to `AWS_REGION`, then to `AWS_DEFAULT_REGION`
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Endpoint of both services instead of AWS's, such as LocalStack's
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    /// Falls back to the `AWS_*` environment variables, then to the
+    /// instance profile of an EC2 host
+    #[serde(default)]
+    pub credentials: Option<AwsCredentials>,
+}
+
+/// A lookup plugin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Plugin {
+    HashiVault,
+    AwsSecret,
+    AwsSsm,
+}
+
+impl Plugin {
+    fn parse(name: &str) -> Result<Self, LookupError> {
+        let canonical = SECRET_STORE_LOOKUPS
+            .iter()
+            .find(|(plugin, aliases)| *plugin == name || aliases.contains(&name))
+            .map(|(plugin, _)| *plugin);
+        match canonical {
+            Some("hashivault") => Ok(Self::HashiVault),
+            Some("aws_secret") => Ok(Self::AwsSecret),
+            Some("aws_ssm") => Ok(Self::AwsSsm),
+            _ if is_controller_lookup(name) => Err(LookupError::ControllerOnly {
+                plugin: name.to_string(),
+            }),
+            _ => Err(LookupError::UnknownPlugin {
+                plugin: name.to_string(),
+            }),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::HashiVault => "hashivault",
+            Self::AwsSecret => "aws_secret",
+            Self::AwsSsm => "aws_ssm",
+        }
+    }
+}
+
+/// `{{ lookup('plugin', 'term') }}` or `query()` or `q()`, with either kind
+/// of quotes
+fn lookup_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r#"\{\{\s*(lookup|query|q)\(\s*(?:'([^']*)'|"([^"]*)")\s*,\s*(?:'([^']*)'|"([^"]*)")\s*\)\s*\}\}"#,
+        )
+        .expect("lookup pattern is valid")
+    })
+}
+
+/// Whether `value` holds a lookup anywhere
+pub fn has_lookups(value: &Value) -> bool {
+    match value {
+        Value::String(text) => lookup_pattern().is_match(text),
+        Value::Array(items) => items.iter().any(has_lookups),
+        Value::Object(entries) => entries.values().any(has_lookups),
+        _ => false,
+    }
+}
+
+/// Resolves lookups, caching what they fetch
+pub struct SecretLookups {
+    config: LookupConfig,
+    client: reqwest::Client,
+    cache: Mutex<HashMap<(Plugin, String), (Instant, Value)>>,
+    /// Token of the AppRole login
+    vault_token: tokio::sync::Mutex<Option<String>>,
+    /// Credentials of the instance profile and when they expire
+    instance_credentials: tokio::sync::Mutex<Option<(AwsCredentials, DateTime<Utc>)>>,
+}
+
+impl SecretLookups {
+    pub fn new(config: LookupConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+        Self {
+            config,
+            client,
+            cache: Mutex::new(HashMap::new()),
+            vault_token: tokio::sync::Mutex::new(None),
+            instance_credentials: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// `value` with its lookups replaced by what they fetch. A string that
+    /// is a single lookup takes the secret as is, which may be a mapping;
+    /// lookups within text are replaced by the secret as text.
+    pub async fn resolve(&self, value: &Value) -> Result<Value, LookupError> {
+        Ok(match value {
+            Value::String(text) => self.resolve_str(text).await?,
+            Value::Array(items) => {
+                let mut resolved = Vec::with_capacity(items.len());
+                for item in items {
+                    resolved.push(Box::pin(self.resolve(item)).await?);
+                }
+                Value::Array(resolved)
+            }
+            Value::Object(entries) => {
+                let mut resolved = serde_json::Map::new();
+                for (key, item) in entries {
+                    resolved.insert(key.clone(), Box::pin(self.resolve(item)).await?);
+                }
+                Value::Object(resolved)
+            }
+            other => other.clone(),
+        })
+    }
+
+    /// The arguments `args` with their lookups resolved
+    pub async fn resolve_args(
+        &self,
+        args: &HashMap<String, Value>,
+    ) -> Result<HashMap<String, Value>, LookupError> {
+        let mut resolved = HashMap::with_capacity(args.len());
+        for (name, value) in args {
+            resolved.insert(name.clone(), self.resolve(value).await?);
+        }
+        Ok(resolved)
+    }
+
+    async fn resolve_str(&self, text: &str) -> Result<Value, LookupError> {
+        let pattern = lookup_pattern();
+        let mut rendered = String::with_capacity(text.len());
+        let mut rest = 0;
+        for captures in pattern.captures_iter(text) {
+            let whole = captures.get(0).expect("a match has a whole");
+            let plugin = captures
+                .get(2)
+                .or(captures.get(3))
+                .map_or("", |m| m.as_str());
+            let term = captures
+                .get(4)
+                .or(captures.get(5))
+                .map_or("", |m| m.as_str());
+            let mut secret = self.lookup(plugin, term).await?;
+            if &captures[1] != "lookup" {
+                secret = Value::Array(vec![secret]);
+            }
+            if whole.as_str() == text.trim() {
+                return Ok(secret);
+            }
+            rendered.push_str(&text[rest..whole.start()]);
+            match secret {
+                Value::String(secret) => rendered.push_str(&secret),
+                secret => rendered.push_str(&secret.to_string()),
+            }
+            rest = whole.end();
+        }
+        rendered.push_str(&text[rest..]);
+        Ok(Value::String(rendered))
+    }
+
+    /// Fetch the secret `term` of the lookup plugin `plugin`
+    pub async fn lookup(&self, plugin: &str, term: &str) -> Result<Value, LookupError> {
+        let plugin = Plugin::parse(plugin)?;
+        let key = (plugin, term.to_string());
+        let ttl = Duration::from_secs(self.config.cache_ttl_secs);
+        if let Some((fetched, value)) = self.cached(&key) {
+            if fetched.elapsed() < ttl {
+                return Ok(value);
+            }
+        }
+
+        let value = match plugin {
+            Plugin::HashiVault => self.hashivault(term).await?,
+            Plugin::AwsSecret => {
+                let body = serde_json::json!({ "SecretId": term });
+                let response = self
+                    .aws_call(
+                        plugin,
+                        term,
+                        "secretsmanager",
+                        "secretsmanager.GetSecretValue",
+                        body,
+                    )
+                    .await?;
+                match (response.get("SecretString"), response.get("SecretBinary")) {
+                    (Some(Value::String(secret)), _) | (None, Some(Value::String(secret))) => {
+                        Value::String(secret.clone())
+                    }
+                    _ => return Err(failed(plugin, term, "the response holds no secret")),
+                }
+            }
+            Plugin::AwsSsm => {
+                let body = serde_json::json!({ "Name": term, "WithDecryption": true });
+                let response = self
+                    .aws_call(plugin, term, "ssm", "AmazonSSM.GetParameter", body)
+                    .await?;
+                response
+                    .pointer("/Parameter/Value")
+                    .cloned()
+                    .ok_or_else(|| failed(plugin, term, "the response holds no parameter"))?
+            }
+        };
+        if ttl > Duration::ZERO {
+            self.cache
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(key, (Instant::now(), value.clone()));
+        }
+        Ok(value)
+    }
+
+    fn cached(&self, key: &(Plugin, String)) -> Option<(Instant, Value)> {
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(key)
+            .cloned()
+    }
+
+    /// Read the secret at `term`, `PATH` or `PATH:FIELD`, from Vault. The
+    /// data of KV version 2 secrets is unwrapped from its metadata.
+    async fn hashivault(&self, term: &str) -> Result<Value, LookupError> {
+        let plugin = Plugin::HashiVault;
+        let (path, field) = match term.rsplit_once(':') {
+            Some((path, field)) => (path, Some(field)),
+            None => (term, None),
+        };
+        let url = self.vault_url()?;
+        let token = self.vault_token(&url).await?;
+
+        let mut request = self
+            .client
+            .get(format!("{url}/v1/{}", path.trim_start_matches('/')))
+            .header("X-Vault-Token", token);
+        if let Some(namespace) = self.vault_namespace() {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(failed(plugin, term, &format!("Vault answered {status}")));
+        }
+        let body: Value = response.json().await?;
+        let mut data = body.get("data").cloned().unwrap_or(Value::Null);
+        if data.get("metadata").is_some() {
+            if let Some(inner) = data.get("data") {
+                data = inner.clone();
+            }
+        }
+        match field {
+            Some(field) => data
+                .get(field)
+                .cloned()
+                .ok_or_else(|| failed(plugin, term, &format!("the secret has no field '{field}'"))),
+            None => Ok(data),
+        }
+    }
+
+    fn vault_url(&self) -> Result<String, LookupError> {
+        self.config
+            .hashivault
+            .url
+            .clone()
+            .or_else(|| std::env::var(VAULT_ADDR_ENV).ok())
+            .map(|url| url.trim_end_matches('/').to_string())
+            .ok_or_else(|| LookupError::Configuration {
+                plugin: Plugin::HashiVault.name().to_string(),
+                reason: format!("no Vault address; set it or {VAULT_ADDR_ENV}"),
+            })
+    }
+
+    fn vault_namespace(&self) -> Option<String> {
+        self.config
+            .hashivault
+            .namespace
+            .clone()
+            .or_else(|| std::env::var(VAULT_NAMESPACE_ENV).ok())
+    }
+
+    async fn vault_token(&self, url: &str) -> Result<String, LookupError> {
+        let missing = |reason: String| LookupError::Configuration {
+            plugin: Plugin::HashiVault.name().to_string(),
+            reason,
+        };
+        match &self.config.hashivault.auth {
+            HashiVaultAuth::Token { token } => token
+                .clone()
+                .or_else(|| std::env::var(VAULT_TOKEN_ENV).ok())
+                .or_else(|| {
+                    let home = std::env::var_os("HOME")?;
+                    std::fs::read_to_string(Path::new(&home).join(".vault-token"))
+                        .ok()
+                        .map(|token| token.trim().to_string())
+                })
+                .ok_or_else(|| {
+                    missing(format!(
+                        "no Vault token; set it, {VAULT_TOKEN_ENV} or ~/.vault-token"
+                    ))
+                }),
+            HashiVaultAuth::AppRole {
+                role_id,
+                secret_id,
+                mount,
+            } => {
+                let mut cached = self.vault_token.lock().await;
+                if let Some(token) = cached.as_ref() {
+                    return Ok(token.clone());
+                }
+                let secret_id = secret_id
+                    .clone()
+                    .or_else(|| std::env::var(VAULT_SECRET_ID_ENV).ok())
+                    .ok_or_else(|| {
+                        missing(format!(
+                            "no AppRole secret id; set it or {VAULT_SECRET_ID_ENV}"
+                        ))
+                    })?;
+                let mut request = self
+                    .client
+                    .post(format!("{url}/v1/auth/{mount}/login"))
+                    .json(&serde_json::json!({ "role_id": role_id, "secret_id": secret_id }));
+                if let Some(namespace) = self.vault_namespace() {
+                    request = request.header("X-Vault-Namespace", namespace);
+                }
+                let response = request.send().await?;
+                let status = response.status();
+                if !status.is_success() {
+                    return Err(missing(format!("AppRole login answered {status}")));
+                }
+                let body: Value = response.json().await?;
+                let token = body
+                    .pointer("/auth/client_token")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| missing("AppRole login returned no token".to_string()))?
+                    .to_string();
+                *cached = Some(token.clone());
+                Ok(token)
+            }
+        }
+    }
+
+    /// Call the JSON API `target` of the AWS service `service`
+    async fn aws_call(
+        &self,
+        plugin: Plugin,
+        term: &str,
+        service: &str,
+        target: &str,
+        body: Value,
+    ) -> Result<Value, LookupError> {
+        let aws = &self.config.aws;
+        let region = aws
+            .region
+            .clone()
+            .or_else(|| std::env::var("AWS_REGION").ok())
+            .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok())
+            .ok_or_else(|| LookupError::Configuration {
+                plugin: plugin.name().to_string(),
+                reason: "no AWS region; set it, AWS_REGION or AWS_DEFAULT_REGION".to_string(),
+            })?;
+        let credentials = self.aws_credentials(plugin).await?;
+        let endpoint = match &aws.endpoint {
+            Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+            None => format!("https://{service}.{region}.amazonaws.com"),
+        };
+        let url = reqwest::Url::parse(&format!("{endpoint}/"))
+            .map_err(|e| failed(plugin, term, &e.to_string()))?;
+
+        let payload = serde_json::to_vec(&body).unwrap_or_default();
+        let payload_hash = format!("{:x}", Sha256::digest(&payload));
+        let content_type = "application/x-amz-json-1.1";
+        let signer = Signer {
+            credentials: &credentials,
+            region: &region,
+            service,
+        };
+        let headers = signer.sign(
+            "POST",
+            &url,
+            &[("content-type", content_type), ("x-amz-target", target)],
+            &payload_hash,
+            Utc::now(),
+        );
+        let mut request = self
+            .client
+            .post(url)
+            .header("content-type", content_type)
+            .header("x-amz-target", target)
+            .body(payload);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let body: Value = response.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            let kind = body
+                .get("__type")
+                .and_then(Value::as_str)
+                .map(|kind| kind.rsplit('#').next().unwrap_or(kind))
+                .unwrap_or("error");
+            let message = body
+                .get("message")
+                .or_else(|| body.get("Message"))
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            return Err(failed(
+                plugin,
+                term,
+                &format!("AWS answered {status}: {kind} {message}"),
+            ));
+        }
+        Ok(body)
+    }
+
+    async fn aws_credentials(&self, plugin: Plugin) -> Result<AwsCredentials, LookupError> {
+        if let Some(credentials) = self
+            .config
+            .aws
+            .credentials
+            .clone()
+            .or_else(AwsCredentials::from_env)
+        {
+            return Ok(credentials);
+        }
+        let mut cached = self.instance_credentials.lock().await;
+        if let Some((credentials, expires)) = cached.as_ref() {
+            if *expires > Utc::now() + chrono::Duration::minutes(5) {
+                return Ok(credentials.clone());
+            }
+        }
+        let (credentials, expires) =
+            instance_profile_credentials(&self.client)
+                .await
+                .map_err(|reason| LookupError::Configuration {
+                    plugin: plugin.name().to_string(),
+                    reason: format!(
+                    "no AWS credentials in the environment or from an instance profile ({reason})"
+                ),
+                })?;
+        *cached = Some((credentials.clone(), expires));
+        Ok(credentials)
+    }
+}
+
+fn failed(plugin: Plugin, term: &str, reason: &str) -> LookupError {
+    LookupError::Failed {
+        plugin: plugin.name().to_string(),
+        term: term.to_string(),
+        reason: reason.to_string(),
+    }
+}
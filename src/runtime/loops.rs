@@ -0,0 +1,282 @@
+//! Task loops.
+//!
+//! A task with a [`TaskLoop`] executes once per item. While an item executes,
+//! it is bound to `item`, or the `loop_var` of the loop control, along with
+//! the `index_var` and, for extended loops, `ansible_loop`. The task's
+//! arguments are rendered with those variables. The per-item results are
+//! gathered into a single task result whose output carries them as
+//! `results`, the shape Ansible reports loops in.
+
+use crate::execution::{LoopControl, LoopKind, TaskLoop};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Variable the item is bound to without a `loop_var`
+pub const DEFAULT_LOOP_VAR: &str = "item";
+
+/// The items `task_loop` iterates over, resolving a `"{{ variable }}"` with
+/// `variables`
+pub fn loop_items(
+    task_loop: &TaskLoop,
+    variables: &HashMap<String, Value>,
+) -> Result<Vec<Value>, String> {
+    let items = match &task_loop.items {
+        Value::String(text) => match expression(text) {
+            Some(expr) => {
+                lookup(expr, variables).ok_or_else(|| format!("'{expr}' is undefined"))?
+            }
+            None => Value::String(text.clone()),
+        },
+        items => items.clone(),
+    };
+
+    match task_loop.kind {
+        LoopKind::Loop => match items {
+            Value::Array(items) => Ok(items),
+            other => Err(format!("loop requires a list, got {other}")),
+        },
+        LoopKind::WithItems => match items {
+            Value::Array(items) => Ok(items
+                .into_iter()
+                .flat_map(|item| match item {
+                    Value::Array(nested) => nested,
+                    item => vec![item],
+                })
+                .collect()),
+            Value::Null => Ok(Vec::new()),
+            item => Ok(vec![item]),
+        },
+        LoopKind::WithDict => match items {
+            Value::Object(entries) => Ok(entries
+                .into_iter()
+                .map(|(key, value)| serde_json::json!({ "key": key, "value": value }))
+                .collect()),
+            other => Err(format!("with_dict requires a mapping, got {other}")),
+        },
+        LoopKind::WithFileglob => {
+            let patterns = match items {
+                Value::Array(patterns) => patterns,
+                pattern => vec![pattern],
+            };
+            let mut files = Vec::new();
+            for pattern in &patterns {
+                let pattern = pattern
+                    .as_str()
+                    .ok_or_else(|| format!("with_fileglob requires patterns, got {pattern}"))?;
+                let paths = glob::glob(pattern)
+                    .map_err(|e| format!("Invalid glob pattern '{pattern}': {e}"))?;
+                let mut matched: Vec<String> = paths
+                    .filter_map(Result::ok)
+                    .filter(|path| path.is_file())
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .collect();
+                matched.sort();
+                files.extend(matched.into_iter().map(Value::String));
+            }
+            Ok(files)
+        }
+    }
+}
+
+/// Variables bound while item `index` of `items` executes
+pub fn loop_variables(
+    control: &LoopControl,
+    items: &[Value],
+    index: usize,
+) -> HashMap<String, Value> {
+    let mut variables = HashMap::from([
+        (loop_var(control).to_string(), items[index].clone()),
+        (
+            "ansible_loop_var".to_string(),
+            Value::String(loop_var(control).to_string()),
+        ),
+    ]);
+    if let Some(index_var) = &control.index_var {
+        variables.insert(index_var.clone(), Value::from(index));
+    }
+    if control.extended {
+        let length = items.len();
+        variables.insert(
+            "ansible_loop".to_string(),
+            serde_json::json!({
+                "index": index + 1,
+                "index0": index,
+                "revindex": length - index,
+                "revindex0": length - index - 1,
+                "first": index == 0,
+                "last": index + 1 == length,
+                "length": length,
+                "previtem": index.checked_sub(1).map(|i| items[i].clone()),
+                "nextitem": items.get(index + 1),
+            }),
+        );
+    }
+    variables
+}
+
+pub fn loop_var(control: &LoopControl) -> &str {
+    control.loop_var.as_deref().unwrap_or(DEFAULT_LOOP_VAR)
+}
+
+/// `value` with the `{{ variable }}` expressions in its strings replaced.
+/// A string that is a single expression takes the variable's value as is;
+/// expressions naming no variable are left for the module.
+pub fn render(value: &Value, variables: &HashMap<String, Value>) -> Value {
+    match value {
+        Value::String(text) => render_str(text, variables),
+        Value::Array(values) => Value::Array(
+            values
+                .iter()
+                .map(|value| render(value, variables))
+                .collect(),
+        ),
+        Value::Object(entries) => Value::Object(
+            entries
+                .iter()
+                .map(|(key, value)| (key.clone(), render(value, variables)))
+                .collect::<Map<_, _>>(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn render_str(text: &str, variables: &HashMap<String, Value>) -> Value {
+    if let Some(value) = expression(text).and_then(|expr| lookup(expr, variables)) {
+        return value;
+    }
+
+    let mut rendered = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|end| start + end + 2) else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        match lookup(rest[start + 2..end - 2].trim(), variables) {
+            Some(Value::String(value)) => rendered.push_str(&value),
+            Some(value) => rendered.push_str(&value.to_string()),
+            None => rendered.push_str(&rest[start..end]),
+        }
+        rest = &rest[end..];
+    }
+    rendered.push_str(rest);
+    Value::String(rendered)
+}
+
+/// The expression of a string that is nothing but `{{ expression }}`
+fn expression(text: &str) -> Option<&str> {
+    let inner = text.trim().strip_prefix("{{")?.strip_suffix("}}")?;
+    (!inner.contains("{{") && !inner.contains("}}")).then(|| inner.trim())
+}
+
+/// Resolve a dotted path such as `item.name` or `item.ports.0`
+fn lookup(path: &str, variables: &HashMap<String, Value>) -> Option<Value> {
+    let mut parts = path.split('.');
+    let mut value = variables.get(parts.next()?.trim())?;
+    for part in parts {
+        value = match value {
+            Value::Object(entries) => entries.get(part)?,
+            Value::Array(values) => values.get(part.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(value.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task_loop(kind: LoopKind, items: Value) -> TaskLoop {
+        TaskLoop {
+            kind,
+            items,
+            control: LoopControl::default(),
+        }
+    }
+
+    #[test]
+    fn test_loop_items() {
+        let variables = HashMap::from([(
+            "packages".to_string(),
+            serde_json::json!(["nginx", ["curl", "git"]]),
+        )]);
+        let items = loop_items(
+            &task_loop(LoopKind::WithItems, serde_json::json!("{{ packages }}")),
+            &variables,
+        )
+        .unwrap();
+        assert_eq!(items, ["nginx", "curl", "git"]);
+
+        let items = loop_items(
+            &task_loop(LoopKind::Loop, serde_json::json!("{{ packages }}")),
+            &variables,
+        )
+        .unwrap();
+        assert_eq!(items.len(), 2);
+
+        let items = loop_items(
+            &task_loop(LoopKind::WithDict, serde_json::json!({"alice": 1})),
+            &variables,
+        )
+        .unwrap();
+        assert_eq!(items, [serde_json::json!({"key": "alice", "value": 1})]);
+
+        assert!(loop_items(
+            &task_loop(LoopKind::Loop, serde_json::json!("{{ missing }}")),
+            &variables
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_fileglob_matches_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("b.conf"), "").unwrap();
+        std::fs::write(dir.path().join("a.conf"), "").unwrap();
+        std::fs::create_dir(dir.path().join("c.conf")).unwrap();
+        let pattern = format!("{}/*.conf", dir.path().display());
+
+        let items = loop_items(
+            &task_loop(LoopKind::WithFileglob, Value::String(pattern)),
+            &HashMap::new(),
+        )
+        .unwrap();
+        let names: Vec<&str> = items
+            .iter()
+            .map(|item| item.as_str().unwrap().rsplit('/').next().unwrap())
+            .collect();
+        assert_eq!(names, ["a.conf", "b.conf"]);
+    }
+
+    #[test]
+    fn test_render_with_loop_variables() {
+        let control = LoopControl {
+            loop_var: Some("user".to_string()),
+            index_var: Some("i".to_string()),
+            extended: true,
+            ..Default::default()
+        };
+        let items = [
+            serde_json::json!({"name": "alice", "groups": ["wheel"]}),
+            serde_json::json!({"name": "bob", "groups": []}),
+        ];
+        let variables = loop_variables(&control, &items, 1);
+        assert_eq!(variables["ansible_loop"]["last"], true);
+        assert_eq!(variables["ansible_loop"]["previtem"]["name"], "alice");
+
+        let args = serde_json::json!({
+            "cmd": "useradd {{ user.name }} # {{ i }} of {{ ansible_loop.length }}",
+            "groups": "{{ user.groups }}",
+            "shell": "{{ login_shell }}",
+        });
+        assert_eq!(
+            render(&args, &variables),
+            serde_json::json!({
+                "cmd": "useradd bob # 1 of 2",
+                "groups": [],
+                "shell": "{{ login_shell }}",
+            })
+        );
+    }
+}
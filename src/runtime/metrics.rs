@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Invocation statistics for a single module
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModuleStats {
+    pub invocations: u64,
+    pub failures: u64,
+    pub changed: u64,
+    pub total_duration: Duration,
+    pub min_duration: Option<Duration>,
+    pub max_duration: Option<Duration>,
+}
+
+impl ModuleStats {
+    pub fn record(&mut self, duration: Duration, failed: bool, changed: bool) {
+        self.invocations += 1;
+        if failed {
+            self.failures += 1;
+        }
+        if changed {
+            self.changed += 1;
+        }
+        self.total_duration += duration;
+        self.min_duration = Some(self.min_duration.map_or(duration, |min| min.min(duration)));
+        self.max_duration = Some(self.max_duration.map_or(duration, |max| max.max(duration)));
+    }
+
+    pub fn merge(&mut self, other: &ModuleStats) {
+        self.invocations += other.invocations;
+        self.failures += other.failures;
+        self.changed += other.changed;
+        self.total_duration += other.total_duration;
+        self.min_duration = match (self.min_duration, other.min_duration) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.max_duration = match (self.max_duration, other.max_duration) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+    }
+
+    pub fn average_duration(&self) -> Duration {
+        if self.invocations == 0 {
+            Duration::ZERO
+        } else {
+            self.total_duration / self.invocations as u32
+        }
+    }
+}
+
+/// Per-module execution metrics collected during a run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ModuleMetrics {
+    modules: HashMap<String, ModuleStats>,
+}
+
+impl ModuleMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, module: &str, duration: Duration, failed: bool, changed: bool) {
+        self.modules
+            .entry(module.to_string())
+            .or_default()
+            .record(duration, failed, changed);
+    }
+
+    pub fn merge(&mut self, other: &ModuleMetrics) {
+        for (module, stats) in &other.modules {
+            self.modules.entry(module.clone()).or_default().merge(stats);
+        }
+    }
+
+    pub fn get(&self, module: &str) -> Option<&ModuleStats> {
+        self.modules.get(module)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    /// Modules ordered by total time spent, most expensive first
+    pub fn by_total_duration(&self) -> Vec<(&str, &ModuleStats)> {
+        let mut entries: Vec<_> = self
+            .modules
+            .iter()
+            .map(|(name, stats)| (name.as_str(), stats))
+            .collect();
+        entries.sort_by(|a, b| {
+            b.1.total_duration
+                .cmp(&a.1.total_duration)
+                .then(a.0.cmp(b.0))
+        });
+        entries
+    }
+}
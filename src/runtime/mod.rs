@@ -1,13 +1,70 @@
+pub mod agent;
+pub mod audit;
+pub mod callbacks;
 pub mod conditions;
+pub mod delegation;
 pub mod error;
+pub mod event_stream;
 pub mod executor;
+pub mod expressions;
 pub mod facts;
+pub mod fault_injection;
+pub mod lookups;
+pub mod loops;
+pub mod metrics;
+pub mod object_store;
+pub mod privilege;
 pub mod progress;
+pub mod result_upload;
+pub mod sealed_secrets;
+pub mod self_update;
+pub mod shutdown;
+pub mod signing;
+pub mod sigv4;
 pub mod state;
+pub mod strategy;
+pub mod telemetry;
 
+pub use agent::{push_plan, Agent, AgentConfig, AgentResponse, PlanSource, SignedPlan};
+pub use audit::AuditEntry;
+pub use callbacks::{
+    builtin_callback, DefaultCallback, DenseCallback, JUnitCallback, ProfileTasksCallback,
+    JUNIT_OUTPUT_DIR_ENV,
+};
 pub use conditions::*;
+pub use delegation::{
+    DelegatedResult, DelegationContext, DELEGATED_TASK_ENV, DELEGATION_CONTEXT_ENV,
+    DELEGATION_DIR_ENV, LOCALHOST,
+};
 pub use error::*;
+pub use event_stream::{
+    decode_events, encode_frame, EventDecoder, StreamItem, EVENT_STREAM_ENV, FRAME_PREFIX,
+};
 pub use executor::*;
 pub use facts::*;
+pub use fault_injection::{FaultInjectionConfig, FaultInjector, FaultKind, FaultRule};
+pub use lookups::{
+    has_lookups, AwsLookupConfig, HashiVaultAuth, HashiVaultConfig, LookupConfig, SecretLookups,
+};
+pub use loops::DEFAULT_LOOP_VAR;
+pub use metrics::*;
+pub use object_store::{ObjectStoreClient, ObjectStoreConfig, S3Credentials};
+pub use privilege::{
+    resolve_become, InvocationOutcome, ModuleInvocation, BECOME_INVOCATION_ARG,
+    BECOME_PASSWORD_FILE_ENV,
+};
 pub use progress::*;
+pub use result_upload::*;
+pub use sealed_secrets::{
+    has_secret_references, take_sealed_secrets, SealedSecrets, SecretValues, SensitiveValues,
+    SECRETS_FILE_ENV, SECRETS_KEY_FILE_ENV,
+};
+pub use self_update::*;
+pub use shutdown::{StopRequest, STOP_FILE_ENV};
+pub use signing::{
+    signature_path, verify_executable, BinarySignature, BinarySigner, TrustedKey, SIGNATURE_SUFFIX,
+};
+pub use sigv4::AwsCredentials;
 pub use state::*;
+pub use strategy::{HostStrategy, SYNC_DIR_ENV};
+pub use telemetry::{Span, SpanRecorder, TraceContext, TRACEPARENT_ENV};
@@ -0,0 +1,171 @@
+use crate::runtime::error::ResultUploadError;
+use crate::runtime::sigv4::Signer;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub use crate::runtime::sigv4::AwsCredentials as S3Credentials;
+
+/// Object storage that runners upload result bundles to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ObjectStoreConfig {
+    /// Plain HTTP(S) store: objects are PUT and GET at `{base_url}/{key}`
+    Http {
+        base_url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+    /// S3 or an S3-compatible service, authenticated with SigV4
+    S3 {
+        bucket: String,
+        region: String,
+        /// Custom endpoint for S3-compatible services; uses path-style addressing
+        #[serde(default)]
+        endpoint: Option<String>,
+        #[serde(default)]
+        prefix: String,
+        /// Falls back to `AWS_ACCESS_KEY_ID` / `AWS_SECRET_ACCESS_KEY` / `AWS_SESSION_TOKEN`
+        #[serde(default)]
+        credentials: Option<S3Credentials>,
+    },
+}
+
+/// Minimal PUT/GET client for the configured object store
+#[derive(Debug, Clone)]
+pub struct ObjectStoreClient {
+    config: ObjectStoreConfig,
+    client: reqwest::Client,
+}
+
+impl ObjectStoreClient {
+    pub fn new(config: ObjectStoreConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+
+        Self { config, client }
+    }
+
+    pub async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), ResultUploadError> {
+        let request = self.request(reqwest::Method::PUT, key, &body)?.body(body);
+        let response = request.send().await?;
+
+        if !response.status().is_success() {
+            return Err(ResultUploadError::Storage {
+                key: key.to_string(),
+                status: response.status().as_u16(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Fetch an object, returning `None` if it does not exist (yet)
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, ResultUploadError> {
+        let response = self.request(reqwest::Method::GET, key, &[])?.send().await?;
+
+        // S3 only answers 404 for missing keys if the caller may list the bucket;
+        // without s3:ListBucket a missing key is a 403 and surfaces as an error.
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(response.bytes().await?.to_vec())),
+            status => Err(ResultUploadError::Storage {
+                key: key.to_string(),
+                status: status.as_u16(),
+            }),
+        }
+    }
+
+    fn request(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        body: &[u8],
+    ) -> Result<reqwest::RequestBuilder, ResultUploadError> {
+        match &self.config {
+            ObjectStoreConfig::Http { base_url, headers } => {
+                let url = format!("{}/{}", base_url.trim_end_matches('/'), encode_key(key));
+                let mut request = self.client.request(method, url);
+                for (name, value) in headers {
+                    request = request.header(name, value);
+                }
+                Ok(request)
+            }
+            ObjectStoreConfig::S3 {
+                bucket,
+                region,
+                endpoint,
+                prefix,
+                credentials,
+            } => {
+                let credentials = credentials
+                    .clone()
+                    .or_else(S3Credentials::from_env)
+                    .ok_or(ResultUploadError::MissingCredentials)?;
+
+                let object_key = format!("{prefix}{key}");
+                let url = match endpoint {
+                    Some(endpoint) => format!(
+                        "{}/{bucket}/{}",
+                        endpoint.trim_end_matches('/'),
+                        encode_key(&object_key)
+                    ),
+                    None => format!(
+                        "https://{bucket}.s3.{region}.amazonaws.com/{}",
+                        encode_key(&object_key)
+                    ),
+                };
+                let url = url::Url::parse(&url).map_err(|e| ResultUploadError::InvalidStore {
+                    reason: e.to_string(),
+                })?;
+
+                let payload_hash = format!("{:x}", Sha256::digest(body));
+                let signer = Signer {
+                    credentials: &credentials,
+                    region,
+                    service: "s3",
+                };
+                let headers = signer.sign(method.as_str(), &url, &[], &payload_hash, Utc::now());
+
+                let mut request = self.client.request(method, url);
+                for (name, value) in headers {
+                    request = request.header(name, value);
+                }
+                Ok(request)
+            }
+        }
+    }
+}
+
+/// URI-encode each segment of an object key, keeping the `/` separators
+fn encode_key(key: &str) -> String {
+    key.split('/').map(uri_encode).collect::<Vec<_>>().join("/")
+}
+
+fn uri_encode(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_key() {
+        assert_eq!(
+            encode_key("deploy-1/deploy@web 01.bundle.json"),
+            "deploy-1/deploy%40web%2001.bundle.json"
+        );
+    }
+}
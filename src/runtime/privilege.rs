@@ -0,0 +1,665 @@
+//! Privilege escalation (`become`).
+//!
+//! A task that becomes another user executes its module in a second runner
+//! process started through sudo, doas or su: the runner starts its own
+//! executable with [`BECOME_INVOCATION_ARG`], writes a [`ModuleInvocation`]
+//! to the child's stdin and reads the [`InvocationOutcome`] back from its
+//! stdout. runas cannot connect the two processes, so on Windows the
+//! invocation and its outcome are exchanged as files instead.
+//!
+//! The controller hands over a become password as a file only the connecting
+//! user can read, named by [`BECOME_PASSWORD_FILE_ENV`]; the runner reads it
+//! once at startup and removes it. sudo is given the password on its stdin,
+//! and only once it prompts for it with a prompt unique to the escalation:
+//! with `NOPASSWD` or cached credentials it never does. The escalated runner
+//! announces on stderr that it is reading its invocation, so neither the
+//! password nor the invocation ends up with the wrong reader. doas, su and
+//! runas only read passwords from a terminal, so with those the become user
+//! has to be reachable without one.
+
+use crate::execution::{BecomeMethod, BecomePolicy};
+use crate::modules::{
+    BecomeConfig, ExecutionContext, HostInfo, ModuleArgs, ModuleExecutionError, ModuleRegistry,
+    ModuleResult,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+/// Argument that makes the runner execute a single module invocation,
+/// followed by the invocation file or `-` for stdin
+pub const BECOME_INVOCATION_ARG: &str = "--become-invocation";
+
+/// File holding the become password, removed once read
+pub const BECOME_PASSWORD_FILE_ENV: &str = "RUSTLE_BECOME_PASSWORD_FILE";
+
+/// What the escalated runner writes to stderr before reading its invocation
+/// from stdin
+pub const BECOME_READY_MARKER: &str = "rustle-become-ready";
+
+const STDIN_SOURCE: &str = "-";
+const OUTCOME_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long a runner escalated through runas has to write its outcome
+const OUTCOME_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// The escalation of a task with `policy` under the runtime's `defaults`:
+/// task settings override the defaults, and nothing escalates unless become
+/// is enabled
+pub fn resolve_become(
+    policy: Option<&BecomePolicy>,
+    defaults: Option<&BecomePolicy>,
+) -> Result<Option<BecomeConfig>, String> {
+    let enabled = policy
+        .and_then(|p| p.enabled)
+        .or(defaults.and_then(|p| p.enabled))
+        .unwrap_or(false);
+    if !enabled {
+        return Ok(None);
+    }
+
+    let method = policy
+        .and_then(|p| p.method)
+        .or(defaults.and_then(|p| p.method))
+        .unwrap_or_default();
+    let user = policy
+        .and_then(|p| p.user.clone())
+        .or_else(|| defaults.and_then(|p| p.user.clone()))
+        .unwrap_or_else(|| match method {
+            BecomeMethod::Runas => "SYSTEM".to_string(),
+            _ => "root".to_string(),
+        });
+    let flags = match policy
+        .and_then(|p| p.flags.as_deref())
+        .or(defaults.and_then(|p| p.flags.as_deref()))
+    {
+        Some(flags) => {
+            shell_words::split(flags).map_err(|e| format!("Invalid become_flags '{flags}': {e}"))?
+        }
+        None => Vec::new(),
+    };
+
+    Ok(Some(BecomeConfig {
+        method: method_name(method).to_string(),
+        user,
+        password: None,
+        flags,
+    }))
+}
+
+fn method_name(method: BecomeMethod) -> &'static str {
+    match method {
+        BecomeMethod::Sudo => "sudo",
+        BecomeMethod::Doas => "doas",
+        BecomeMethod::Su => "su",
+        BecomeMethod::Runas => "runas",
+    }
+}
+
+/// What an escalated runner needs to execute a module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleInvocation {
+    pub module: String,
+    pub args: ModuleArgs,
+    pub facts: HashMap<String, Value>,
+    pub variables: HashMap<String, Value>,
+    pub environment: HashMap<String, String>,
+    pub working_directory: PathBuf,
+    pub check_mode: bool,
+    pub diff_mode: bool,
+    pub verbosity: u8,
+}
+
+impl ModuleInvocation {
+    /// The invocation of `module`, which the escalated runner executes as is
+    pub fn new(module: &str, args: &ModuleArgs, context: &ExecutionContext) -> Self {
+        let mut args = args.clone();
+        args.special.r#become = None;
+        Self {
+            module: module.to_string(),
+            args,
+            facts: context.facts.clone(),
+            variables: context.variables.clone(),
+            environment: context.environment.clone(),
+            working_directory: context.working_directory.clone(),
+            check_mode: context.check_mode,
+            diff_mode: context.diff_mode,
+            verbosity: context.verbosity,
+        }
+    }
+
+    fn context(&self) -> ExecutionContext {
+        ExecutionContext {
+            facts: self.facts.clone(),
+            variables: self.variables.clone(),
+            host_info: HostInfo::detect(),
+            working_directory: self.working_directory.clone(),
+            environment: self.environment.clone(),
+            check_mode: self.check_mode,
+            diff_mode: self.diff_mode,
+            verbosity: self.verbosity,
+        }
+    }
+}
+
+/// What the escalated runner reports back
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvocationOutcome {
+    Result(Box<ModuleResult>),
+    Error(String),
+}
+
+/// Execute `module` with `registry`, in an escalated runner when `args` ask
+/// to become another user
+pub async fn execute_module(
+    registry: &ModuleRegistry,
+    module: &str,
+    args: &ModuleArgs,
+    context: &ExecutionContext,
+    password: Option<&str>,
+) -> Result<ModuleResult, ModuleExecutionError> {
+    match &args.special.r#become {
+        None => registry.execute_module(module, args, context).await,
+        Some(become_config) => {
+            let runner = std::env::current_exe()?;
+            let invocation = ModuleInvocation::new(module, args, context);
+            execute_escalated(become_config, &runner, password, &invocation).await
+        }
+    }
+}
+
+/// Execute `invocation` as the become user, with `runner` as the escalated
+/// runner executable
+pub async fn execute_escalated(
+    become_config: &BecomeConfig,
+    runner: &Path,
+    password: Option<&str>,
+    invocation: &ModuleInvocation,
+) -> Result<ModuleResult, ModuleExecutionError> {
+    let password = become_config.password.as_deref().or(password);
+    let outcome = if become_config.method == "runas" {
+        run_through_files(become_config, runner, invocation).await?
+    } else {
+        let prompt = password.map(|_| password_prompt());
+        let command = escalation_command(become_config, runner, STDIN_SOURCE, prompt.as_deref())?;
+        let password = prompt.as_deref().zip(password);
+        let input = serde_json::to_vec(invocation)?;
+        run_through_pipes(&command, password, &input, &become_config.user).await?
+    };
+
+    match outcome {
+        InvocationOutcome::Result(result) => Ok(*result),
+        InvocationOutcome::Error(message) => Err(ModuleExecutionError::ExecutionFailed { message }),
+    }
+}
+
+/// A sudo prompt no module output can be mistaken for
+fn password_prompt() -> String {
+    format!(
+        "[rustle-become-{}] password:",
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+/// The command that starts `runner` as the become user, reading the
+/// invocation from `source`; sudo reads a password from stdin after writing
+/// `password_prompt` to stderr
+pub fn escalation_command(
+    become_config: &BecomeConfig,
+    runner: &Path,
+    source: &str,
+    password_prompt: Option<&str>,
+) -> Result<Vec<String>, ModuleExecutionError> {
+    let runner = runner.to_string_lossy().into_owned();
+    let user = become_config.user.clone();
+    let flags = become_config.flags.iter().cloned();
+    let invocation = [
+        runner,
+        BECOME_INVOCATION_ARG.to_string(),
+        source.to_string(),
+    ];
+
+    let command = match become_config.method.as_str() {
+        "sudo" => {
+            let mut command = vec!["sudo".to_string()];
+            if let Some(prompt) = password_prompt {
+                command.extend(["-S".to_string(), "-p".to_string(), prompt.to_string()]);
+            } else {
+                command.push("-n".to_string());
+            }
+            command.extend(["-u".to_string(), user]);
+            command.extend(flags);
+            command.push("--".to_string());
+            command.extend(invocation);
+            command
+        }
+        "doas" => {
+            let mut command = ["doas", "-n", "-u"].map(String::from).to_vec();
+            command.push(user);
+            command.extend(flags);
+            command.extend(invocation);
+            command
+        }
+        "su" => {
+            let mut command = vec!["su".to_string()];
+            command.extend(flags);
+            command.extend([user, "-c".to_string(), shell_words::join(invocation)]);
+            command
+        }
+        "runas" => {
+            let mut command = vec!["runas".to_string(), format!("/user:{user}")];
+            command.extend(flags);
+            let [runner, arg, source] = invocation;
+            command.push(format!("\"{runner}\" {arg} \"{source}\""));
+            command
+        }
+        other => {
+            return Err(ModuleExecutionError::InvalidArgs {
+                message: format!("Unsupported become method: {other}"),
+            })
+        }
+    };
+    Ok(command)
+}
+
+/// Run `command`, writing `input` once the escalated runner is ready for it.
+/// `password` is the prompt sudo may write and the password answering it;
+/// it is written at most once, and a second prompt closes stdin
+async fn run_through_pipes(
+    command: &[String],
+    password: Option<(&str, &str)>,
+    input: &[u8],
+    user: &str,
+) -> Result<InvocationOutcome, ModuleExecutionError> {
+    let mut child = tokio::process::Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| ModuleExecutionError::ExecutionFailed {
+            message: format!("Failed to start {}: {e}", command[0]),
+        })?;
+    let mut stdin = child.stdin.take();
+    let stdout = tokio::spawn(read_to_end(child.stdout.take()));
+    let mut stderr = child.stderr.take();
+
+    let (prompt, mut secret) = password.unzip();
+    // A marker may straddle two reads, so the tail of what was searched
+    // is searched again
+    let overlap = prompt.map_or(0, str::len).max(BECOME_READY_MARKER.len());
+    let mut errors = Vec::new();
+    let mut scanned = 0;
+    let mut chunk = [0u8; 4096];
+    while let Some(pipe) = stderr.as_mut() {
+        let read = pipe.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        errors.extend_from_slice(&chunk[..read]);
+
+        if let Some(prompt) = prompt {
+            while let Some(at) = find_bytes(&errors[scanned..], prompt.as_bytes()) {
+                errors.drain(scanned + at..scanned + at + prompt.len());
+                match (secret.take(), stdin.as_mut()) {
+                    (Some(secret), Some(pipe)) => {
+                        write_input(pipe, format!("{secret}\n").as_bytes()).await?
+                    }
+                    // Prompted again: the password was wrong
+                    _ => stdin = None,
+                }
+            }
+        }
+        if let Some(at) = find_bytes(&errors[scanned..], BECOME_READY_MARKER.as_bytes()) {
+            errors.drain(scanned + at..scanned + at + BECOME_READY_MARKER.len());
+            if let Some(mut pipe) = stdin.take() {
+                write_input(&mut pipe, input).await?;
+            }
+            // The rest is the escalated runner's own
+            let mut rest = Vec::new();
+            if let Some(mut pipe) = stderr.take() {
+                pipe.read_to_end(&mut rest).await?;
+            }
+            errors.extend(rest);
+        } else {
+            scanned = errors.len().saturating_sub(overlap).max(scanned);
+        }
+    }
+    drop(stdin);
+    let status = child.wait().await?;
+    let stdout = stdout.await.map_err(std::io::Error::other)??;
+
+    parse_outcome(&String::from_utf8_lossy(&stdout)).ok_or_else(|| {
+        ModuleExecutionError::ExecutionFailed {
+            message: format!(
+                "Failed to become {user} ({status}): {}",
+                String::from_utf8_lossy(&errors).trim()
+            ),
+        }
+    })
+}
+
+async fn read_to_end(pipe: Option<impl AsyncRead + Unpin>) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    if let Some(mut pipe) = pipe {
+        pipe.read_to_end(&mut data).await?;
+    }
+    Ok(data)
+}
+
+/// Write `data` to the child's stdin; a child that failed before reading
+/// its input has closed the pipe
+async fn write_input(
+    stdin: &mut tokio::process::ChildStdin,
+    data: &[u8],
+) -> Result<(), ModuleExecutionError> {
+    match stdin.write_all(data).await {
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+async fn run_through_files(
+    become_config: &BecomeConfig,
+    runner: &Path,
+    invocation: &ModuleInvocation,
+) -> Result<InvocationOutcome, ModuleExecutionError> {
+    let dir = std::env::temp_dir().join(format!("rustle-become-{}", uuid::Uuid::new_v4().simple()));
+    tokio::fs::create_dir_all(&dir).await?;
+    let path = dir.join("invocation.json");
+    tokio::fs::write(&path, serde_json::to_vec(invocation)?).await?;
+
+    let outcome = async {
+        let command = escalation_command(become_config, runner, &path.to_string_lossy(), None)?;
+        // runas returns once the escalated runner has started
+        let status = tokio::process::Command::new(&command[0])
+            .args(&command[1..])
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(ModuleExecutionError::ExecutionFailed {
+                message: format!("Failed to become {} ({status})", become_config.user),
+            });
+        }
+        let outcome_path = outcome_path(&path);
+        let waited = tokio::time::timeout(OUTCOME_TIMEOUT, async {
+            loop {
+                if let Ok(data) = tokio::fs::read(&outcome_path).await {
+                    return data;
+                }
+                tokio::time::sleep(OUTCOME_POLL_INTERVAL).await;
+            }
+        })
+        .await;
+        match waited {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(_) => Err(ModuleExecutionError::ExecutionFailed {
+                message: format!(
+                    "The runner escalated to {} reported no outcome within {}s",
+                    become_config.user,
+                    OUTCOME_TIMEOUT.as_secs()
+                ),
+            }),
+        }
+    }
+    .await;
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    outcome
+}
+
+/// The outcome the escalated runner printed last; modules may log to stdout
+/// before it
+fn parse_outcome(stdout: &str) -> Option<InvocationOutcome> {
+    stdout
+        .lines()
+        .rev()
+        .find_map(|line| serde_json::from_str(line.trim()).ok())
+}
+
+fn outcome_path(invocation_path: &Path) -> PathBuf {
+    invocation_path.with_extension("result")
+}
+
+/// Execute the invocation read from `source`, a file or `-` for stdin, and
+/// report its outcome on stdout, or next to the invocation file
+pub async fn run_invocation(registry: &ModuleRegistry, source: &str) -> std::io::Result<()> {
+    let mut data = Vec::new();
+    if source == STDIN_SOURCE {
+        let mut stderr = tokio::io::stderr();
+        stderr.write_all(BECOME_READY_MARKER.as_bytes()).await?;
+        stderr.flush().await?;
+        tokio::io::stdin().read_to_end(&mut data).await?;
+    } else {
+        data = tokio::fs::read(source).await?;
+    }
+    let outcome = match serde_json::from_slice::<ModuleInvocation>(&data) {
+        Ok(invocation) => execute_invocation(registry, &invocation).await,
+        Err(e) => InvocationOutcome::Error(format!("Invalid module invocation: {e}")),
+    };
+    let encoded = serde_json::to_vec(&outcome)?;
+
+    if source == STDIN_SOURCE {
+        let mut stdout = tokio::io::stdout();
+        stdout.write_all(b"\n").await?;
+        stdout.write_all(&encoded).await?;
+        stdout.write_all(b"\n").await?;
+        stdout.flush().await
+    } else {
+        let path = outcome_path(Path::new(source));
+        let partial = path.with_extension("tmp");
+        tokio::fs::write(&partial, encoded).await?;
+        tokio::fs::rename(partial, path).await
+    }
+}
+
+/// Execute `invocation` in this process
+pub async fn execute_invocation(
+    registry: &ModuleRegistry,
+    invocation: &ModuleInvocation,
+) -> InvocationOutcome {
+    match registry
+        .execute_module(&invocation.module, &invocation.args, &invocation.context())
+        .await
+    {
+        Ok(result) => InvocationOutcome::Result(Box::new(result)),
+        Err(e) => InvocationOutcome::Error(e.to_string()),
+    }
+}
+
+/// The become password the controller left in [`BECOME_PASSWORD_FILE_ENV`],
+/// removing its file
+pub fn take_become_password() -> std::io::Result<Option<String>> {
+    let Some(path) = std::env::var_os(BECOME_PASSWORD_FILE_ENV) else {
+        return Ok(None);
+    };
+    let password = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    Ok(Some(password?.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::SpecialParameters;
+
+    fn policy(
+        enabled: Option<bool>,
+        user: Option<&str>,
+        method: Option<BecomeMethod>,
+    ) -> BecomePolicy {
+        BecomePolicy {
+            enabled,
+            user: user.map(str::to_string),
+            method,
+            flags: None,
+        }
+    }
+
+    #[test]
+    fn test_task_settings_override_defaults() {
+        let defaults = BecomePolicy {
+            flags: Some("-H -E".to_string()),
+            ..policy(Some(true), Some("deploy"), None)
+        };
+        let resolved = resolve_become(None, Some(&defaults)).unwrap().unwrap();
+        assert_eq!(
+            (resolved.method.as_str(), resolved.user.as_str()),
+            ("sudo", "deploy")
+        );
+        assert_eq!(resolved.flags, ["-H", "-E"]);
+
+        let task = policy(None, None, Some(BecomeMethod::Runas));
+        let resolved = resolve_become(Some(&task), Some(&defaults))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (resolved.method.as_str(), resolved.user.as_str()),
+            ("runas", "deploy")
+        );
+
+        let task = policy(Some(false), None, None);
+        assert!(resolve_become(Some(&task), Some(&defaults))
+            .unwrap()
+            .is_none());
+        let task = policy(Some(true), None, Some(BecomeMethod::Runas));
+        assert_eq!(
+            resolve_become(Some(&task), None).unwrap().unwrap().user,
+            "SYSTEM"
+        );
+        assert!(resolve_become(None, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_escalation_commands() {
+        let runner = Path::new("/opt/rustle runner");
+        let mut config = BecomeConfig {
+            method: "sudo".to_string(),
+            user: "postgres".to_string(),
+            password: None,
+            flags: vec!["-H".to_string()],
+        };
+        assert_eq!(
+            escalation_command(&config, runner, "-", Some("[become] password:")).unwrap(),
+            [
+                "sudo",
+                "-S",
+                "-p",
+                "[become] password:",
+                "-u",
+                "postgres",
+                "-H",
+                "--",
+                "/opt/rustle runner",
+                BECOME_INVOCATION_ARG,
+                "-"
+            ]
+        );
+        assert_eq!(
+            escalation_command(&config, runner, "-", None).unwrap()[1],
+            "-n"
+        );
+
+        config.method = "su".to_string();
+        config.flags = vec!["-".to_string()];
+        assert_eq!(
+            escalation_command(&config, runner, "-", None).unwrap(),
+            [
+                "su",
+                "-",
+                "postgres",
+                "-c",
+                "'/opt/rustle runner' --become-invocation -"
+            ]
+        );
+
+        config.method = "pbrun".to_string();
+        assert!(escalation_command(&config, runner, "-", None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_invocation_executes_unescalated() {
+        let args = ModuleArgs {
+            args: HashMap::from([("msg".to_string(), Value::from("hello"))]),
+            special: SpecialParameters {
+                r#become: resolve_become(Some(&policy(Some(true), None, None)), None).unwrap(),
+                ..Default::default()
+            },
+        };
+        let context = ExecutionContext {
+            facts: HashMap::new(),
+            variables: HashMap::new(),
+            host_info: HostInfo::detect(),
+            working_directory: std::env::temp_dir(),
+            environment: HashMap::new(),
+            check_mode: false,
+            diff_mode: false,
+            verbosity: 0,
+        };
+        let invocation = ModuleInvocation::new("debug", &args, &context);
+        assert!(invocation.args.special.r#become.is_none());
+
+        let encoded = serde_json::to_string(&invocation).unwrap();
+        let invocation: ModuleInvocation = serde_json::from_str(&encoded).unwrap();
+        match execute_invocation(&ModuleRegistry::with_core_modules(), &invocation).await {
+            InvocationOutcome::Result(result) => assert_eq!(result.msg.as_deref(), Some("hello")),
+            InvocationOutcome::Error(e) => panic!("{e}"),
+        }
+    }
+
+    /// Run `script` as the escalation command with `password`, answering
+    /// what it read from stdin
+    #[cfg(unix)]
+    async fn escalate_through(script: &str, password: Option<(&str, &str)>) -> String {
+        let dir = tempfile::TempDir::new().unwrap();
+        let input = dir.path().join("input");
+        let script = format!(
+            "{script}\ncat > {}\necho '{{\"error\":\"done\"}}'",
+            input.display()
+        );
+        let command = ["sh", "-c", &script].map(String::from);
+        let outcome = run_through_pipes(&command, password, b"{}", "root")
+            .await
+            .unwrap();
+        assert!(matches!(outcome, InvocationOutcome::Error(message) if message == "done"));
+        std::fs::read_to_string(input).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_password_is_only_written_when_prompted() {
+        let password = Some(("[become] password:", "hunter2"));
+        // Without a prompt, as with NOPASSWD, the runner only reads its
+        // invocation
+        let ready = format!("printf '{BECOME_READY_MARKER}' >&2");
+        assert_eq!(escalate_through(&ready, password).await, "{}");
+
+        let prompted = format!(
+            "printf '[become] password:' >&2\n\
+             read password\n\
+             [ \"$password\" = hunter2 ] || exit 1\n\
+             {ready}"
+        );
+        assert_eq!(escalate_through(&prompted, password).await, "{}");
+    }
+
+    #[test]
+    fn test_outcome_follows_module_output() {
+        let stdout = "INFO starting\n{\"error\":\"Module not found\"}\n";
+        assert!(matches!(
+            parse_outcome(stdout),
+            Some(InvocationOutcome::Error(message)) if message == "Module not found"
+        ));
+        assert!(parse_outcome("sudo: a password is required\n").is_none());
+    }
+}
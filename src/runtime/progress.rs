@@ -1,13 +1,44 @@
-use crate::execution::Task;
-use crate::runtime::{ExecutionResult, ReportError, TaskResult};
+use crate::execution::{Play, Task};
+use crate::modules::interface::Diff;
+use crate::runtime::delegation::DelegationContext;
+use crate::runtime::event_stream::write_frame;
+use crate::runtime::{ExecutionResult, FaultInjector, ReportError, TaskResult};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
 
 /// Progress reporting for controller communication
+#[derive(Clone)]
 pub struct ProgressReporter {
     controller_endpoint: Option<String>,
     client: Option<Client>,
+    faults: FaultInjector,
+    stream_events: bool,
+    callbacks: Vec<Arc<dyn CallbackPlugin>>,
+}
+
+/// Receives the lifecycle events of an execution on this host, as they
+/// happen, to present or record them. Register one with
+/// [`ProgressReporter::with_callback`]; the built-in plugins are in
+/// [`crate::runtime::callbacks`].
+pub trait CallbackPlugin: Send + Sync {
+    /// Name the plugin is enabled by
+    fn name(&self) -> &str;
+
+    /// A play starts
+    fn on_play_start(&self, _play: &Play) {}
+
+    /// A task starts; tasks skipped before starting report a result only
+    fn on_task_start(&self, _task: &Task) {}
+
+    /// A task completed, failed or was skipped. The result of a `no_log`
+    /// task is censored.
+    fn on_task_result(&self, _result: &TaskResult) {}
+
+    /// The execution is over
+    fn on_stats(&self, _result: &ExecutionResult) {}
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +59,12 @@ pub enum ProgressEvent {
         execution_id: String,
         total_tasks: usize,
     },
+    PlayStarted {
+        execution_id: String,
+        play_id: String,
+        #[serde(default)]
+        name: Option<String>,
+    },
     TaskStarted {
         execution_id: String,
         task_id: String,
@@ -37,6 +74,15 @@ pub enum ProgressEvent {
         execution_id: String,
         task_result: TaskResult,
     },
+    FactsCollected {
+        execution_id: String,
+        facts: HashMap<String, serde_json::Value>,
+    },
+    TaskDiff {
+        execution_id: String,
+        task_id: String,
+        diff: Diff,
+    },
     ExecutionCompleted {
         execution_id: String,
         result: ExecutionResult,
@@ -45,6 +91,40 @@ pub enum ProgressEvent {
         execution_id: String,
         error: String,
     },
+    /// The runner waits for the controller to execute a delegated or
+    /// run-once task
+    DelegationRequested {
+        execution_id: String,
+        task_id: String,
+        context: DelegationContext,
+    },
+    /// The runner waits for the controller to release the task at
+    /// `position`, under the linear strategy or because the controller has
+    /// to count the hosts running it
+    TaskReached {
+        execution_id: String,
+        task_id: String,
+        position: usize,
+        /// Most hosts that may run the task at the same time
+        #[serde(default)]
+        throttle: Option<u32>,
+    },
+    /// A task failed in a play with `any_errors_fatal`, which ends the play
+    /// on every host
+    FatalFailure {
+        execution_id: String,
+        task_id: String,
+        play_id: String,
+    },
+    /// A task whose result did not satisfy its `until` conditions is
+    /// executed again
+    TaskRetrying {
+        execution_id: String,
+        task_id: String,
+        task_name: String,
+        attempt: u32,
+        retries: u32,
+    },
 }
 
 impl ProgressReporter {
@@ -63,9 +143,29 @@ impl ProgressReporter {
         Self {
             controller_endpoint,
             client,
+            faults: FaultInjector::disabled(),
+            stream_events: false,
+            callbacks: Vec::new(),
         }
     }
 
+    pub fn with_fault_injector(mut self, faults: FaultInjector) -> Self {
+        self.faults = faults;
+        self
+    }
+
+    /// Also write every event to stdout as a frame of the event stream
+    pub fn with_event_stream(mut self, enabled: bool) -> Self {
+        self.stream_events = enabled;
+        self
+    }
+
+    /// Also hand every lifecycle event to `plugin`
+    pub fn with_callback(mut self, plugin: Arc<dyn CallbackPlugin>) -> Self {
+        self.callbacks.push(plugin);
+        self
+    }
+
     pub async fn report_execution_start(
         &self,
         execution_id: &str,
@@ -78,11 +178,30 @@ impl ProgressReporter {
         self.send_event(&event).await
     }
 
+    pub async fn report_play_start(
+        &self,
+        execution_id: &str,
+        play: &Play,
+    ) -> Result<(), ReportError> {
+        for callback in &self.callbacks {
+            callback.on_play_start(play);
+        }
+        let event = ProgressEvent::PlayStarted {
+            execution_id: execution_id.to_string(),
+            play_id: play.id.clone(),
+            name: play.name.clone(),
+        };
+        self.send_event(&event).await
+    }
+
     pub async fn report_task_start(
         &self,
         execution_id: &str,
         task: &Task,
     ) -> Result<(), ReportError> {
+        for callback in &self.callbacks {
+            callback.on_task_start(task);
+        }
         let event = ProgressEvent::TaskStarted {
             execution_id: execution_id.to_string(),
             task_id: task.id.clone(),
@@ -96,6 +215,9 @@ impl ProgressReporter {
         execution_id: &str,
         result: &TaskResult,
     ) -> Result<(), ReportError> {
+        for callback in &self.callbacks {
+            callback.on_task_result(result);
+        }
         let event = ProgressEvent::TaskCompleted {
             execution_id: execution_id.to_string(),
             task_result: result.clone(),
@@ -103,10 +225,100 @@ impl ProgressReporter {
         self.send_event(&event).await
     }
 
+    pub async fn report_facts(
+        &self,
+        execution_id: &str,
+        facts: HashMap<String, serde_json::Value>,
+    ) -> Result<(), ReportError> {
+        let event = ProgressEvent::FactsCollected {
+            execution_id: execution_id.to_string(),
+            facts,
+        };
+        self.send_event(&event).await
+    }
+
+    pub async fn report_diff(
+        &self,
+        execution_id: &str,
+        task_id: &str,
+        diff: &Diff,
+    ) -> Result<(), ReportError> {
+        let event = ProgressEvent::TaskDiff {
+            execution_id: execution_id.to_string(),
+            task_id: task_id.to_string(),
+            diff: diff.clone(),
+        };
+        self.send_event(&event).await
+    }
+
+    pub async fn report_delegation(
+        &self,
+        execution_id: &str,
+        task_id: &str,
+        context: DelegationContext,
+    ) -> Result<(), ReportError> {
+        let event = ProgressEvent::DelegationRequested {
+            execution_id: execution_id.to_string(),
+            task_id: task_id.to_string(),
+            context,
+        };
+        self.send_event(&event).await
+    }
+
+    pub async fn report_task_reached(
+        &self,
+        execution_id: &str,
+        task_id: &str,
+        position: usize,
+        throttle: Option<u32>,
+    ) -> Result<(), ReportError> {
+        let event = ProgressEvent::TaskReached {
+            execution_id: execution_id.to_string(),
+            task_id: task_id.to_string(),
+            position,
+            throttle,
+        };
+        self.send_event(&event).await
+    }
+
+    pub async fn report_fatal_failure(
+        &self,
+        execution_id: &str,
+        task_id: &str,
+        play_id: &str,
+    ) -> Result<(), ReportError> {
+        let event = ProgressEvent::FatalFailure {
+            execution_id: execution_id.to_string(),
+            task_id: task_id.to_string(),
+            play_id: play_id.to_string(),
+        };
+        self.send_event(&event).await
+    }
+
+    pub async fn report_retry(
+        &self,
+        execution_id: &str,
+        task: &Task,
+        attempt: u32,
+        retries: u32,
+    ) -> Result<(), ReportError> {
+        let event = ProgressEvent::TaskRetrying {
+            execution_id: execution_id.to_string(),
+            task_id: task.id.clone(),
+            task_name: task.name.clone(),
+            attempt,
+            retries,
+        };
+        self.send_event(&event).await
+    }
+
     pub async fn report_execution_complete(
         &self,
         result: &ExecutionResult,
     ) -> Result<(), ReportError> {
+        for callback in &self.callbacks {
+            callback.on_stats(result);
+        }
         let event = ProgressEvent::ExecutionCompleted {
             execution_id: result.execution_id.clone(),
             result: result.clone(),
@@ -142,6 +354,9 @@ impl ProgressReporter {
             ProgressEvent::ExecutionStarted { total_tasks, .. } => {
                 tracing::info!("Execution started with {} tasks", total_tasks);
             }
+            ProgressEvent::PlayStarted { play_id, name, .. } => {
+                tracing::info!("Starting play: {}", name.as_deref().unwrap_or(play_id));
+            }
             ProgressEvent::TaskStarted { task_name, .. } => {
                 tracing::info!("Starting task: {}", task_name);
             }
@@ -166,6 +381,20 @@ impl ProgressReporter {
                     );
                 }
             }
+            ProgressEvent::FactsCollected { facts, .. } => {
+                tracing::debug!("Collected {} facts", facts.len());
+            }
+            ProgressEvent::TaskDiff { task_id, .. } => {
+                tracing::debug!("Task '{}' produced a diff", task_id);
+            }
+            ProgressEvent::TaskRetrying {
+                task_name,
+                attempt,
+                retries,
+                ..
+            } => {
+                tracing::warn!("Retrying task '{}' ({}/{})", task_name, attempt, retries);
+            }
             ProgressEvent::ExecutionCompleted { result, .. } => {
                 tracing::info!(
                     "Execution completed: {}/{} tasks successful",
@@ -176,10 +405,34 @@ impl ProgressReporter {
             ProgressEvent::ExecutionFailed { error, .. } => {
                 tracing::error!("Execution failed: {}", error);
             }
+            ProgressEvent::DelegationRequested { task_id, .. } => {
+                tracing::info!("Handing task '{}' to the controller", task_id);
+            }
+            ProgressEvent::TaskReached { task_id, .. } => {
+                tracing::debug!("Waiting for the other hosts to reach task '{}'", task_id);
+            }
+            ProgressEvent::FatalFailure {
+                task_id, play_id, ..
+            } => {
+                tracing::error!(
+                    "Task '{}' failed, ending play '{}' on every host",
+                    task_id,
+                    play_id
+                );
+            }
+        }
+
+        if self.stream_events {
+            write_frame(event)?;
         }
 
         // Send to controller if configured
         if let (Some(endpoint), Some(client)) = (&self.controller_endpoint, &self.client) {
+            if self.faults.partitioned(endpoint) {
+                tracing::warn!("Failed to send progress to controller: injected network partition");
+                return Ok(());
+            }
+
             let url = format!("{endpoint}/api/v1/progress");
 
             match client.post(&url).json(event).send().await {
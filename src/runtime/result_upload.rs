@@ -0,0 +1,203 @@
+use crate::runtime::error::ResultUploadError;
+use crate::runtime::fault_injection::FaultInjector;
+use crate::runtime::object_store::{ObjectStoreClient, ObjectStoreConfig};
+use crate::runtime::ExecutionResult;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use crypto_box::aead::OsRng;
+use crypto_box::{PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
+
+const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// Environment variable the deployer sets to tell a runner which inventory host it is
+pub const HOST_ID_ENV: &str = "RUSTLE_HOST_ID";
+
+/// Upload encrypted results to object storage instead of reporting to the controller.
+///
+/// Used for fleets where hosts cannot connect back to the controller; the
+/// controller polls the store and decrypts bundles with its secret key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultUploadConfig {
+    pub store: ObjectStoreConfig,
+    /// Base64-encoded X25519 public key of the controller
+    pub recipient_public_key: String,
+    pub deployment_id: String,
+    /// Defaults to `RUSTLE_HOST_ID`, then the system hostname
+    #[serde(default)]
+    pub host_id: Option<String>,
+}
+
+/// A result bundle sealed to the controller's public key.
+///
+/// Only routing metadata is stored in the clear; the execution result itself
+/// is an anonymous sealed box that only the controller can open.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedResultBundle {
+    pub format_version: u32,
+    pub deployment_id: String,
+    pub host: String,
+    pub created_at: DateTime<Utc>,
+    /// Base64-encoded sealed box containing the JSON execution result
+    pub ciphertext: String,
+}
+
+impl EncryptedResultBundle {
+    pub fn seal(
+        result: &ExecutionResult,
+        deployment_id: &str,
+        host: &str,
+        recipient_public_key: &str,
+    ) -> Result<Self, ResultUploadError> {
+        let public_key = PublicKey::from(decode_key(recipient_public_key)?);
+        let plaintext = serde_json::to_vec(result)?;
+        let ciphertext = public_key
+            .seal(&mut OsRng, &plaintext)
+            .map_err(|_| ResultUploadError::Encryption)?;
+
+        Ok(Self {
+            format_version: BUNDLE_FORMAT_VERSION,
+            deployment_id: deployment_id.to_string(),
+            host: host.to_string(),
+            created_at: Utc::now(),
+            ciphertext: STANDARD.encode(ciphertext),
+        })
+    }
+
+    pub fn open(&self, secret_key: &str) -> Result<ExecutionResult, ResultUploadError> {
+        if self.format_version != BUNDLE_FORMAT_VERSION {
+            return Err(ResultUploadError::UnsupportedBundle {
+                version: self.format_version,
+            });
+        }
+
+        let secret_key = SecretKey::from(decode_key(secret_key)?);
+        let ciphertext = STANDARD
+            .decode(&self.ciphertext)
+            .map_err(|_| ResultUploadError::Decryption)?;
+        let plaintext = secret_key
+            .unseal(&ciphertext)
+            .map_err(|_| ResultUploadError::Decryption)?;
+
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+/// Object key a host's bundle is stored under
+pub fn result_object_key(deployment_id: &str, host: &str) -> String {
+    format!("{deployment_id}/{host}.bundle.json")
+}
+
+/// Generate a controller keypair, returned as base64 `(secret, public)`
+pub fn generate_result_keypair() -> (String, String) {
+    let secret_key = SecretKey::generate(&mut OsRng);
+    (
+        STANDARD.encode(secret_key.to_bytes()),
+        STANDARD.encode(secret_key.public_key().to_bytes()),
+    )
+}
+
+fn decode_key(key: &str) -> Result<[u8; 32], ResultUploadError> {
+    STANDARD
+        .decode(key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| ResultUploadError::InvalidKey {
+            reason: "expected a base64-encoded 32 byte X25519 key".to_string(),
+        })
+}
+
+/// Runner-side uploader for encrypted result bundles
+pub struct ResultUploader {
+    config: ResultUploadConfig,
+    store: ObjectStoreClient,
+    faults: FaultInjector,
+}
+
+impl ResultUploader {
+    pub fn new(config: ResultUploadConfig) -> Self {
+        let store = ObjectStoreClient::new(config.store.clone());
+        Self {
+            config,
+            store,
+            faults: FaultInjector::disabled(),
+        }
+    }
+
+    pub fn with_fault_injector(mut self, faults: FaultInjector) -> Self {
+        self.faults = faults;
+        self
+    }
+
+    pub fn host_id(&self) -> String {
+        self.config
+            .host_id
+            .clone()
+            .or_else(|| std::env::var(HOST_ID_ENV).ok())
+            .or_else(|| {
+                hostname::get()
+                    .ok()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    /// Encrypt and upload `result`, returning the object key it was stored under
+    pub async fn upload(&self, result: &ExecutionResult) -> Result<String, ResultUploadError> {
+        let host = self.host_id();
+        let bundle = EncryptedResultBundle::seal(
+            result,
+            &self.config.deployment_id,
+            &host,
+            &self.config.recipient_public_key,
+        )?;
+
+        let key = result_object_key(&self.config.deployment_id, &host);
+        if self.faults.partitioned(&host) {
+            return Err(ResultUploadError::Unreachable {
+                reason: "injected network partition".to_string(),
+            });
+        }
+        let body = serde_json::to_vec(&bundle)?;
+        let body = self.faults.corrupt_upload(&host, &body).unwrap_or(body);
+        self.store.put(&key, body).await?;
+
+        tracing::info!("Uploaded encrypted result bundle to {}", key);
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::StateManager;
+
+    #[test]
+    fn test_bundle_roundtrip() {
+        let (secret_key, public_key) = generate_result_keypair();
+        let result = StateManager::new("exec-1".to_string(), 0).build_execution_result(Utc::now());
+
+        let bundle =
+            EncryptedResultBundle::seal(&result, "deploy-1", "web01", &public_key).unwrap();
+        assert_eq!(bundle.host, "web01");
+        assert!(!bundle.ciphertext.contains("exec-1"));
+
+        let opened = bundle.open(&secret_key).unwrap();
+        assert_eq!(opened.execution_id, "exec-1");
+
+        let (other_secret, _) = generate_result_keypair();
+        assert!(matches!(
+            bundle.open(&other_secret),
+            Err(ResultUploadError::Decryption)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_recipient_key() {
+        let result = StateManager::new("exec-1".to_string(), 0).build_execution_result(Utc::now());
+        assert!(matches!(
+            EncryptedResultBundle::seal(&result, "deploy-1", "web01", "not-a-key"),
+            Err(ResultUploadError::InvalidKey { .. })
+        ));
+    }
+}
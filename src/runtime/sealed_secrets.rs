@@ -0,0 +1,307 @@
+//! Sealed secret references.
+//!
+//! Runner binaries end up in compilation caches, transfer caches and object
+//! stores, so the plans embedded in them should not hold secrets. Before a
+//! plan is embedded, the controller replaces each secret in the arguments
+//! of its tasks with a reference, `{{ sealed_secret('N') }}`, and seals the
+//! secrets to a key pair it generates for the deployment. When it runs a
+//! runner, it uploads the sealed secrets and their key next to the binary,
+//! in two files readable only by the connecting user, and names them in
+//! [`SECRETS_FILE_ENV`] and [`SECRETS_KEY_FILE_ENV`]. The key never goes
+//! on a command line, where the target's process list would show it. The
+//! runner removes both files once read, keeps the secrets in memory only
+//! and substitutes them just before a task runs; the controller removes
+//! whatever a runner that died left behind.
+
+use crate::runtime::error::SealedSecretError;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use crypto_box::aead::OsRng;
+use crypto_box::SecretKey;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+
+/// File the controller left the sealed secrets in
+pub const SECRETS_FILE_ENV: &str = "RUSTLE_SECRETS_FILE";
+/// File holding the base64-encoded X25519 secret key the sealed secrets
+/// open with
+pub const SECRETS_KEY_FILE_ENV: &str = "RUSTLE_SECRETS_KEY_FILE";
+
+const SEALED_FORMAT_VERSION: u32 = 1;
+
+/// The reference to the secret `id` that stands in for it in a plan
+pub fn secret_reference(id: usize) -> String {
+    format!("{{{{ sealed_secret('{id}') }}}}")
+}
+
+fn reference_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"\{\{\s*sealed_secret\('(\d+)'\)\s*\}\}").expect("reference pattern is valid")
+    })
+}
+
+/// Whether `value` references a sealed secret anywhere
+pub fn has_secret_references(value: &Value) -> bool {
+    match value {
+        Value::String(text) => reference_pattern().is_match(text),
+        Value::Array(items) => items.iter().any(has_secret_references),
+        Value::Object(entries) => entries.values().any(has_secret_references),
+        _ => false,
+    }
+}
+
+/// Secrets known to the controller, which are kept out of embedded plans
+#[derive(Clone, Default)]
+pub struct SensitiveValues {
+    values: BTreeSet<String>,
+}
+
+impl std::fmt::Debug for SensitiveValues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SensitiveValues")
+            .field("values", &format!("{} secrets", self.values.len()))
+            .finish()
+    }
+}
+
+impl SensitiveValues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Treat `secret` as sensitive; empty strings are ignored
+    pub fn insert(&mut self, secret: impl Into<String>) {
+        let secret = secret.into();
+        if !secret.is_empty() {
+            self.values.insert(secret);
+        }
+    }
+
+    /// Treat every string within `value` as sensitive
+    pub fn insert_all(&mut self, value: &Value) {
+        match value {
+            Value::String(secret) => self.insert(secret.as_str()),
+            Value::Array(items) => items.iter().for_each(|item| self.insert_all(item)),
+            Value::Object(entries) => entries.values().for_each(|item| self.insert_all(item)),
+            _ => {}
+        }
+    }
+
+    pub fn extend(&mut self, other: SensitiveValues) {
+        self.values.extend(other.values);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// `value` with every secret in its strings replaced by a reference.
+    /// Where secrets overlap, the longest one starting first is replaced.
+    pub fn replace(&self, value: &Value) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.replace_str(text)),
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|item| self.replace(item)).collect())
+            }
+            Value::Object(entries) => Value::Object(
+                entries
+                    .iter()
+                    .map(|(key, item)| (key.clone(), self.replace(item)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    fn replace_str(&self, text: &str) -> String {
+        let mut replaced = String::with_capacity(text.len());
+        let mut rest = text;
+        while !rest.is_empty() {
+            let longest = self
+                .values
+                .iter()
+                .enumerate()
+                .filter(|(_, secret)| rest.starts_with(secret.as_str()))
+                .max_by_key(|(_, secret)| secret.len());
+            match longest {
+                Some((id, secret)) => {
+                    replaced.push_str(&secret_reference(id));
+                    rest = &rest[secret.len()..];
+                }
+                None => {
+                    let next = rest.chars().next().expect("rest is not empty");
+                    replaced.push(next);
+                    rest = &rest[next.len_utf8()..];
+                }
+            }
+        }
+        replaced
+    }
+
+    /// Seal the secrets to a key pair generated for the purpose, returning
+    /// them with the base64-encoded secret key they open with
+    pub fn seal(&self) -> Result<(SealedSecrets, String), SealedSecretError> {
+        let secret_key = SecretKey::generate(&mut OsRng);
+        let values: Vec<&String> = self.values.iter().collect();
+        let ciphertext = secret_key
+            .public_key()
+            .seal(&mut OsRng, &serde_json::to_vec(&values)?)
+            .map_err(|_| SealedSecretError::Encryption)?;
+        let sealed = SealedSecrets {
+            format_version: SEALED_FORMAT_VERSION,
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        Ok((sealed, STANDARD.encode(secret_key.to_bytes())))
+    }
+}
+
+/// Secrets sealed to a deployment's key, as runners receive them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedSecrets {
+    pub format_version: u32,
+    /// Base64-encoded sealed box containing the JSON list of secrets
+    pub ciphertext: String,
+}
+
+impl SealedSecrets {
+    pub fn open(&self, secret_key: &str) -> Result<SecretValues, SealedSecretError> {
+        if self.format_version != SEALED_FORMAT_VERSION {
+            return Err(SealedSecretError::UnsupportedBundle {
+                version: self.format_version,
+            });
+        }
+        let secret_key = STANDARD
+            .decode(secret_key.trim())
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .map(SecretKey::from)
+            .ok_or_else(|| SealedSecretError::InvalidKey {
+                reason: "expected a base64-encoded 32 byte X25519 key".to_string(),
+            })?;
+        let ciphertext = STANDARD
+            .decode(&self.ciphertext)
+            .map_err(|_| SealedSecretError::Decryption)?;
+        let plaintext = secret_key
+            .unseal(&ciphertext)
+            .map_err(|_| SealedSecretError::Decryption)?;
+        Ok(SecretValues {
+            values: serde_json::from_slice(&plaintext)?,
+        })
+    }
+}
+
+/// Opened secrets, which replace their references in task arguments
+pub struct SecretValues {
+    values: Vec<String>,
+}
+
+impl std::fmt::Debug for SecretValues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretValues")
+            .field("values", &format!("{} secrets", self.values.len()))
+            .finish()
+    }
+}
+
+impl SecretValues {
+    /// `value` with its references replaced by the secrets they stand for
+    pub fn resolve(&self, value: &Value) -> Result<Value, SealedSecretError> {
+        Ok(match value {
+            Value::String(text) => {
+                let mut resolved = String::with_capacity(text.len());
+                let mut rest = 0;
+                for captures in reference_pattern().captures_iter(text) {
+                    let whole = captures.get(0).expect("a match has a whole");
+                    let id = &captures[1];
+                    let secret = id
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|index| self.values.get(index))
+                        .ok_or_else(|| SealedSecretError::UnknownReference {
+                            id: id.to_string(),
+                        })?;
+                    resolved.push_str(&text[rest..whole.start()]);
+                    resolved.push_str(secret);
+                    rest = whole.end();
+                }
+                resolved.push_str(&text[rest..]);
+                Value::String(resolved)
+            }
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.resolve(item))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Object(entries) => {
+                let mut resolved = serde_json::Map::new();
+                for (key, item) in entries {
+                    resolved.insert(key.clone(), self.resolve(item)?);
+                }
+                Value::Object(resolved)
+            }
+            other => other.clone(),
+        })
+    }
+}
+
+/// The secrets the controller delivered in [`SECRETS_FILE_ENV`], opened
+/// with the key in [`SECRETS_KEY_FILE_ENV`]; both files are removed once
+/// read
+pub fn take_sealed_secrets() -> Result<Option<SecretValues>, SealedSecretError> {
+    let key = std::env::var_os(SECRETS_KEY_FILE_ENV).map(|path| {
+        let key = std::fs::read_to_string(&path);
+        let _ = std::fs::remove_file(&path);
+        key
+    });
+    let Some(path) = std::env::var_os(SECRETS_FILE_ENV) else {
+        return Ok(None);
+    };
+    let sealed = std::fs::read(&path);
+    let _ = std::fs::remove_file(&path);
+    let sealed: SealedSecrets = serde_json::from_slice(&sealed?)?;
+    let key = key.ok_or(SealedSecretError::MissingKey)??;
+    sealed.open(&key).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secrets_round_trip_through_their_references() {
+        let mut sensitive = SensitiveValues::new();
+        sensitive.insert("hunter2");
+        sensitive.insert("hunter22");
+        sensitive.insert("");
+        let args = serde_json::json!({
+            "url": "postgres://app:hunter22@db/hunter2",
+            "list": ["hunter2", 3],
+        });
+
+        let sealed = sensitive.replace(&args);
+        assert_eq!(
+            sealed["url"],
+            "postgres://app:{{ sealed_secret('1') }}@db/{{ sealed_secret('0') }}"
+        );
+        assert!(has_secret_references(&sealed));
+        assert!(!sealed.to_string().contains("hunter2"));
+
+        let (bundle, key) = sensitive.seal().unwrap();
+        assert!(!bundle.ciphertext.contains("hunter2"));
+        assert_eq!(bundle.open(&key).unwrap().resolve(&sealed).unwrap(), args);
+
+        let (_, other_key) = sensitive.seal().unwrap();
+        assert!(matches!(
+            bundle.open(&other_key),
+            Err(SealedSecretError::Decryption)
+        ));
+    }
+}
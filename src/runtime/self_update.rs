@@ -0,0 +1,453 @@
+use crate::runtime::error::SelfUpdateError;
+use crate::runtime::signing::{BinarySignature, TrustedKey};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Configuration for the optional runner self-update channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelfUpdateConfig {
+    /// Base URL of the artifact repository
+    pub repository_url: String,
+    pub plan_id: String,
+    pub target_triple: String,
+    /// Version of the running binary
+    pub current_version: String,
+    /// Only ever install this exact version, even if newer releases exist
+    #[serde(default)]
+    pub pinned_version: Option<String>,
+    /// Minisign public key, or bare base64-encoded ed25519 key, that release
+    /// binaries must be signed with
+    pub trusted_public_key: String,
+    #[serde(with = "serde_duration")]
+    pub check_interval: Duration,
+}
+
+/// A release entry published in the artifact repository index
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArtifactRelease {
+    pub version: String,
+    /// Download URL, absolute or relative to the release index
+    pub url: String,
+    /// Hex-encoded SHA-256 of the binary
+    pub sha256: String,
+    /// Minisign signature over the binary, whose trusted comment binds its
+    /// `version`, `plan`, `target` and `sha256`, as
+    /// [`BinarySigner::sign_with_fields`](crate::runtime::signing::BinarySigner::sign_with_fields)
+    /// writes them
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReleaseIndex {
+    pub releases: Vec<ArtifactRelease>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpdateOutcome {
+    UpToDate,
+    Updated { from: String, to: String },
+}
+
+/// Checks the artifact repository for newer signed builds of this runner and
+/// swaps them in place of the running executable.
+///
+/// The previous binary is kept next to the executable as `<name>.previous`
+/// so a bad update can be reverted with [`SelfUpdater::rollback`].
+pub struct SelfUpdater {
+    config: SelfUpdateConfig,
+    client: reqwest::Client,
+}
+
+impl SelfUpdater {
+    pub fn new(config: SelfUpdateConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn index_url(&self) -> String {
+        format!(
+            "{}/{}/{}/index.json",
+            self.config.repository_url.trim_end_matches('/'),
+            self.config.plan_id,
+            self.config.target_triple
+        )
+    }
+
+    /// Return the release that should be installed, if it differs from the running one
+    pub async fn check(&self) -> Result<Option<ArtifactRelease>, SelfUpdateError> {
+        let index: ReleaseIndex = self
+            .client
+            .get(self.index_url())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        select_release(&index, &self.config)
+    }
+
+    /// Download, verify and install `release` over `executable`
+    pub async fn apply(
+        &self,
+        release: &ArtifactRelease,
+        executable: &Path,
+    ) -> Result<UpdateOutcome, SelfUpdateError> {
+        let url = if release.url.contains("://") {
+            release.url.clone()
+        } else {
+            let index_url = self.index_url();
+            let base = index_url.trim_end_matches("index.json");
+            format!("{base}{}", release.url.trim_start_matches('/'))
+        };
+
+        let binary = self
+            .client
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        verify_release(&binary, release, &self.config)?;
+        install_binary(&binary, executable)?;
+
+        tracing::info!(
+            "Self-update installed version {} (was {})",
+            release.version,
+            self.config.current_version
+        );
+
+        Ok(UpdateOutcome::Updated {
+            from: self.config.current_version.clone(),
+            to: release.version.clone(),
+        })
+    }
+
+    /// Check for and install an update for the currently running executable
+    pub async fn check_and_apply(&self) -> Result<UpdateOutcome, SelfUpdateError> {
+        match self.check().await? {
+            Some(release) => {
+                let executable = std::env::current_exe()?;
+                self.apply(&release, &executable).await
+            }
+            None => Ok(UpdateOutcome::UpToDate),
+        }
+    }
+
+    /// Restore the binary that was replaced by the last update
+    pub fn rollback(executable: &Path) -> Result<(), SelfUpdateError> {
+        let previous = previous_path(executable);
+        if !previous.exists() {
+            return Err(SelfUpdateError::NoPreviousVersion {
+                path: previous.display().to_string(),
+            });
+        }
+
+        std::fs::rename(&previous, executable)?;
+        tracing::info!("Rolled back {} to previous version", executable.display());
+        Ok(())
+    }
+}
+
+/// Pick the pinned release, or the newest release above the running version.
+/// Only releases whose signed manifest names their version and this
+/// runner's plan and target are considered, since the index itself is not
+/// signed.
+pub fn select_release(
+    index: &ReleaseIndex,
+    config: &SelfUpdateConfig,
+) -> Result<Option<ArtifactRelease>, SelfUpdateError> {
+    let key = TrustedKey::parse(&config.trusted_public_key)?;
+    let signed: Vec<&ArtifactRelease> = index
+        .releases
+        .iter()
+        .filter(|release| match verify_manifest(release, config, &key) {
+            Ok(_) => true,
+            Err(e) => {
+                tracing::warn!("Ignoring release {}: {}", release.version, e);
+                false
+            }
+        })
+        .collect();
+
+    if let Some(ref pinned) = config.pinned_version {
+        if *pinned == config.current_version {
+            return Ok(None);
+        }
+        return signed
+            .into_iter()
+            .find(|r| r.version == *pinned)
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| SelfUpdateError::PinnedVersionMissing {
+                version: pinned.clone(),
+            });
+    }
+
+    let current = parse_version(&config.current_version)?;
+    let mut newest: Option<(Version, &ArtifactRelease)> = None;
+    for release in signed {
+        let Ok(version) = Version::parse(&release.version) else {
+            tracing::warn!("Ignoring release with invalid version {}", release.version);
+            continue;
+        };
+        if version > current && newest.as_ref().is_none_or(|(best, _)| version > *best) {
+            newest = Some((version, release));
+        }
+    }
+
+    Ok(newest.map(|(_, release)| release.clone()))
+}
+
+fn parse_version(version: &str) -> Result<Version, SelfUpdateError> {
+    Version::parse(version).map_err(|e| SelfUpdateError::InvalidVersion {
+        version: version.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// Check that the signed manifest of `release` is authentic and names the
+/// release's version and checksum and the plan and target of `config`,
+/// answering its signature
+pub fn verify_manifest(
+    release: &ArtifactRelease,
+    config: &SelfUpdateConfig,
+    key: &TrustedKey,
+) -> Result<BinarySignature, SelfUpdateError> {
+    let signature = BinarySignature::parse(&release.signature)?;
+    key.verify_comment(&signature)?;
+
+    let expected = [
+        ("version", release.version.as_str()),
+        ("plan", config.plan_id.as_str()),
+        ("target", config.target_triple.as_str()),
+        ("sha256", release.sha256.as_str()),
+    ];
+    for (field, expected) in expected {
+        let signed = signature.field(field).unwrap_or_default();
+        if !signed.eq_ignore_ascii_case(expected) {
+            return Err(SelfUpdateError::ManifestMismatch {
+                version: release.version.clone(),
+                field: field.to_string(),
+                expected: expected.to_string(),
+                signed: signed.to_string(),
+            });
+        }
+    }
+    Ok(signature)
+}
+
+/// Check a downloaded binary against the signed manifest of `release`
+pub fn verify_release(
+    binary: &[u8],
+    release: &ArtifactRelease,
+    config: &SelfUpdateConfig,
+) -> Result<(), SelfUpdateError> {
+    let key = TrustedKey::parse(&config.trusted_public_key)?;
+    let signature = verify_manifest(release, config, &key)?;
+    key.verify(binary, &signature)?;
+    Ok(())
+}
+
+fn previous_path(executable: &Path) -> PathBuf {
+    let mut name = executable.file_name().unwrap_or_default().to_os_string();
+    name.push(".previous");
+    executable.with_file_name(name)
+}
+
+/// Atomically replace `executable`, keeping the old binary as `.previous`
+fn install_binary(binary: &[u8], executable: &Path) -> Result<(), SelfUpdateError> {
+    let mut staged_name = executable.file_name().unwrap_or_default().to_os_string();
+    staged_name.push(".update");
+    let staged = executable.with_file_name(staged_name);
+
+    std::fs::write(&staged, binary)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    // Hard-link the running binary aside so there is never a moment without an executable
+    let previous = previous_path(executable);
+    let _ = std::fs::remove_file(&previous);
+    if std::fs::hard_link(executable, &previous).is_err() {
+        std::fs::copy(executable, &previous)?;
+    }
+
+    if let Err(e) = std::fs::rename(&staged, executable) {
+        let _ = std::fs::remove_file(&staged);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+mod serde_duration {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        duration.as_secs().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let secs = u64::deserialize(deserializer)?;
+        Ok(Duration::from_secs(secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::signing::BinarySigner;
+    use crate::runtime::SignatureError;
+    use sha2::{Digest, Sha256};
+
+    fn signer() -> BinarySigner {
+        BinarySigner::from_seed(&[7u8; 32])
+    }
+
+    fn config(current: &str, pinned: Option<&str>) -> SelfUpdateConfig {
+        SelfUpdateConfig {
+            repository_url: "https://artifacts.example.com".to_string(),
+            plan_id: "plan".to_string(),
+            target_triple: "x86_64-unknown-linux-gnu".to_string(),
+            current_version: current.to_string(),
+            pinned_version: pinned.map(str::to_string),
+            trusted_public_key: signer().public_key().encoded(),
+            check_interval: Duration::from_secs(60),
+        }
+    }
+
+    /// `binary` released as `version` of the plan `plan`, signed for the
+    /// version `signed_version`
+    fn signed_release(
+        version: &str,
+        signed_version: &str,
+        plan: &str,
+        binary: &[u8],
+    ) -> ArtifactRelease {
+        let fields = [
+            ("version", signed_version),
+            ("plan", plan),
+            ("target", "x86_64-unknown-linux-gnu"),
+        ];
+        ArtifactRelease {
+            version: version.to_string(),
+            url: format!("rustle-runner-{version}"),
+            sha256: format!("{:x}", Sha256::digest(binary)),
+            signature: signer()
+                .sign_with_fields(binary, "rustle-runner", &fields)
+                .encode(),
+        }
+    }
+
+    fn release(version: &str) -> ArtifactRelease {
+        signed_release(version, version, "plan", version.as_bytes())
+    }
+
+    #[test]
+    fn test_select_newest_release() {
+        let index = ReleaseIndex {
+            releases: vec![release("1.0.0"), release("1.2.0"), release("1.1.0")],
+        };
+
+        let selected = select_release(&index, &config("1.0.0", None)).unwrap();
+        assert_eq!(selected.unwrap().version, "1.2.0");
+
+        assert!(select_release(&index, &config("1.2.0", None))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_pinned_version_wins_even_if_older() {
+        let index = ReleaseIndex {
+            releases: vec![release("1.0.0"), release("1.2.0")],
+        };
+
+        let selected = select_release(&index, &config("1.2.0", Some("1.0.0"))).unwrap();
+        assert_eq!(selected.unwrap().version, "1.0.0");
+
+        assert!(matches!(
+            select_release(&index, &config("1.2.0", Some("9.9.9"))),
+            Err(SelfUpdateError::PinnedVersionMissing { .. })
+        ));
+    }
+
+    #[test]
+    fn test_releases_must_be_signed_for_their_version_and_plan() {
+        // An old signed build relabelled as an upgrade, and a build of
+        // another plan, are not candidates
+        let index = ReleaseIndex {
+            releases: vec![
+                signed_release("2.0.0", "1.0.0", "plan", b"old runner"),
+                signed_release("3.0.0", "3.0.0", "other-plan", b"other runner"),
+                release("1.1.0"),
+            ],
+        };
+        let selected = select_release(&index, &config("1.0.0", None)).unwrap();
+        assert_eq!(selected.unwrap().version, "1.1.0");
+        assert!(matches!(
+            select_release(&index, &config("1.0.0", Some("2.0.0"))),
+            Err(SelfUpdateError::PinnedVersionMissing { .. })
+        ));
+
+        let key = TrustedKey::parse(&config("1.0.0", None).trusted_public_key).unwrap();
+        assert!(matches!(
+            verify_manifest(&index.releases[1], &config("1.0.0", None), &key),
+            Err(SelfUpdateError::ManifestMismatch { field, .. }) if field == "plan"
+        ));
+    }
+
+    #[test]
+    fn test_verify_release_signature() {
+        let binary = b"new runner binary";
+        let signed = signed_release("1.1.0", "1.1.0", "plan", binary);
+        let config = config("1.0.0", None);
+
+        assert!(verify_release(binary, &signed, &config).is_ok());
+        assert!(matches!(
+            verify_release(b"tampered binary", &signed, &config),
+            Err(SelfUpdateError::Signature(SignatureError::InvalidSignature))
+        ));
+
+        let other = SelfUpdateConfig {
+            trusted_public_key: BinarySigner::from_seed(&[9u8; 32]).public_key().encoded(),
+            ..config
+        };
+        assert!(matches!(
+            verify_release(binary, &signed, &other),
+            Err(SelfUpdateError::Signature(
+                SignatureError::UnknownKey { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_install_and_rollback() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let executable = temp_dir.path().join("rustle-runner");
+        std::fs::write(&executable, b"v1").unwrap();
+
+        install_binary(b"v2", &executable).unwrap();
+        assert_eq!(std::fs::read(&executable).unwrap(), b"v2");
+
+        SelfUpdater::rollback(&executable).unwrap();
+        assert_eq!(std::fs::read(&executable).unwrap(), b"v1");
+        assert!(SelfUpdater::rollback(&executable).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+//! Graceful shutdown of runs.
+//!
+//! A run stops between tasks rather than in the middle of one: once asked
+//! to, it lets the task it is executing complete, keeps the progress it
+//! persisted (see [`crate::runtime::state`]) and ends failed, so that a run
+//! resumed later continues from the next task. Runners are asked by a
+//! signal, SIGINT or SIGTERM, or by the controller, which writes the reason
+//! to the file named by [`STOP_FILE_ENV`]. Programs embedding the executor
+//! cancel its token instead.
+
+use std::path::PathBuf;
+use tokio_util::sync::CancellationToken;
+
+/// File the controller writes the reason to when it stops the runner
+pub const STOP_FILE_ENV: &str = "RUSTLE_STOP_FILE";
+
+/// Whether a run has been asked to stop
+#[derive(Debug, Clone, Default)]
+pub struct StopRequest {
+    token: CancellationToken,
+    file: Option<PathBuf>,
+}
+
+impl StopRequest {
+    /// Stop when the controller writes the file named by [`STOP_FILE_ENV`],
+    /// if it set one
+    pub fn from_env() -> Self {
+        Self {
+            token: CancellationToken::new(),
+            file: std::env::var_os(STOP_FILE_ENV).map(PathBuf::from),
+        }
+    }
+
+    /// Stop once `token` is cancelled as well
+    pub fn with_token(mut self, token: CancellationToken) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Why the run has to stop before its next task, if it does
+    pub fn reason(&self) -> Option<String> {
+        if self.token.is_cancelled() {
+            return Some("cancelled".to_string());
+        }
+        let reason = std::fs::read_to_string(self.file.as_ref()?).ok()?;
+        Some(reason.trim().to_string())
+    }
+}
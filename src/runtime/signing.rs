@@ -0,0 +1,393 @@
+//! Ed25519 signatures over compiled runner binaries.
+//!
+//! Keys and signatures use the minisign file layout, with the original
+//! (non-prehashed) `Ed` algorithm, so `minisign -V` can check a runner by
+//! hand. The trusted comment records the binary's SHA-256; it is covered by
+//! the global signature, which lets the controller check a deployed binary
+//! by its checksum without downloading it. Runners built with a public key
+//! check their own executable with [`verify_executable`] before running.
+
+use crate::runtime::error::SignatureError;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// Suffix of the signature file kept next to a binary
+pub const SIGNATURE_SUFFIX: &str = ".minisig";
+
+const ALGORITHM: &[u8; 2] = b"Ed";
+const UNTRUSTED_PREFIX: &str = "untrusted comment:";
+const TRUSTED_PREFIX: &str = "trusted comment: ";
+
+pub fn signature_path(binary: &Path) -> PathBuf {
+    let mut name = binary.file_name().unwrap_or_default().to_os_string();
+    name.push(SIGNATURE_SUFFIX);
+    binary.with_file_name(name)
+}
+
+/// Signs binaries at build time
+#[derive(Clone)]
+pub struct BinarySigner {
+    key: SigningKey,
+    key_id: [u8; 8],
+}
+
+impl std::fmt::Debug for BinarySigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BinarySigner")
+            .field("key_id", &key_id_hex(&self.key_id))
+            .finish_non_exhaustive()
+    }
+}
+
+impl BinarySigner {
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        let key = SigningKey::from_bytes(seed);
+        let key_id = derive_key_id(key.verifying_key().as_bytes());
+        Self { key, key_id }
+    }
+
+    /// Parse a base64-encoded 32 byte ed25519 seed, optionally preceded by
+    /// an `untrusted comment:` line. Encrypted minisign secret keys are not
+    /// supported.
+    pub fn parse(text: &str) -> Result<Self, SignatureError> {
+        let seed: [u8; 32] = decode_line(text)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| SignatureError::InvalidKey {
+                reason: "expected a base64-encoded 32 byte ed25519 seed".to_string(),
+            })?;
+        Ok(Self::from_seed(&seed))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SignatureError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn public_key(&self) -> TrustedKey {
+        TrustedKey {
+            key: self.key.verifying_key(),
+            key_id: self.key_id,
+        }
+    }
+
+    /// Sign `binary`, recording `file_name` and its checksum in the trusted comment
+    pub fn sign(&self, binary: &[u8], file_name: &str) -> BinarySignature {
+        self.sign_with_fields(binary, file_name, &[])
+    }
+
+    /// Sign `binary` as [`sign`](Self::sign) does, also recording `fields`
+    /// in the trusted comment, which binds them to the binary. Names and
+    /// values must not contain tabs or line breaks.
+    pub fn sign_with_fields(
+        &self,
+        binary: &[u8],
+        file_name: &str,
+        fields: &[(&str, &str)],
+    ) -> BinarySignature {
+        let signature = self.key.sign(binary).to_bytes();
+        let mut trusted_comment = format!(
+            "timestamp:{}\tfile:{file_name}\tsha256:{:x}",
+            chrono::Utc::now().timestamp(),
+            Sha256::digest(binary)
+        );
+        for (name, value) in fields {
+            trusted_comment.push_str(&format!("\t{name}:{value}"));
+        }
+        let global_signature = self
+            .key
+            .sign(&global_message(&signature, &trusted_comment))
+            .to_bytes();
+
+        BinarySignature {
+            key_id: self.key_id,
+            signature,
+            trusted_comment,
+            global_signature,
+        }
+    }
+
+    /// A bare Ed25519 signature over `data`, for data that carries its own
+    /// signature, such as the execution data appended to runners
+    pub fn sign_detached(&self, data: &[u8]) -> [u8; 64] {
+        self.key.sign(data).to_bytes()
+    }
+
+    /// Sign the binary at `path` and write the signature next to it
+    pub fn sign_file(&self, path: &Path) -> Result<PathBuf, SignatureError> {
+        let binary = std::fs::read(path)?;
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let signature_path = signature_path(path);
+        std::fs::write(&signature_path, self.sign(&binary, &file_name).encode())?;
+        Ok(signature_path)
+    }
+}
+
+/// A public key binaries must be signed with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrustedKey {
+    key: VerifyingKey,
+    key_id: [u8; 8],
+}
+
+impl TrustedKey {
+    /// Parse a minisign public key (file contents or just its base64 line),
+    /// or a bare base64-encoded 32 byte ed25519 key
+    pub fn parse(text: &str) -> Result<Self, SignatureError> {
+        let invalid = |reason: &str| SignatureError::InvalidKey {
+            reason: reason.to_string(),
+        };
+        let bytes = decode_line(text).ok_or_else(|| invalid("not valid base64"))?;
+        let (key_id, key_bytes): ([u8; 8], [u8; 32]) = match bytes.len() {
+            42 if bytes.starts_with(ALGORITHM) => (
+                bytes[2..10].try_into().unwrap_or_default(),
+                bytes[10..].try_into().unwrap_or([0; 32]),
+            ),
+            32 => {
+                let key_bytes: [u8; 32] = bytes.try_into().unwrap_or([0; 32]);
+                (derive_key_id(&key_bytes), key_bytes)
+            }
+            _ => return Err(invalid("expected a minisign Ed25519 public key")),
+        };
+        let key = VerifyingKey::from_bytes(&key_bytes).map_err(|e| SignatureError::InvalidKey {
+            reason: e.to_string(),
+        })?;
+        Ok(Self { key, key_id })
+    }
+
+    pub fn key_id(&self) -> String {
+        key_id_hex(&self.key_id)
+    }
+
+    /// The base64 line of the minisign public key, as embedded in runners
+    pub fn encoded(&self) -> String {
+        let mut bytes = ALGORITHM.to_vec();
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(self.key.as_bytes());
+        STANDARD.encode(bytes)
+    }
+
+    /// Contents of a minisign public key file
+    pub fn to_file_contents(&self) -> String {
+        format!(
+            "{UNTRUSTED_PREFIX} rustle-deploy public key {}\n{}\n",
+            self.key_id(),
+            self.encoded()
+        )
+    }
+
+    /// The raw Ed25519 public key, as runners checking their appended data
+    /// embed it
+    pub fn key_bytes(&self) -> [u8; 32] {
+        self.key.to_bytes()
+    }
+
+    /// Check a bare Ed25519 `signature` made by this key over `data`
+    pub fn verify_detached(&self, data: &[u8], signature: &[u8]) -> Result<(), SignatureError> {
+        let signature =
+            Signature::from_slice(signature).map_err(|_| SignatureError::Malformed {
+                reason: "expected a 64 byte Ed25519 signature".to_string(),
+            })?;
+        self.key
+            .verify(data, &signature)
+            .map_err(|_| SignatureError::InvalidSignature)
+    }
+
+    /// Check that `signature` was made by this key over `binary`
+    pub fn verify(&self, binary: &[u8], signature: &BinarySignature) -> Result<(), SignatureError> {
+        self.verify_comment(signature)?;
+        self.key
+            .verify(binary, &Signature::from_bytes(&signature.signature))
+            .map_err(|_| SignatureError::InvalidSignature)?;
+
+        if let Some(expected) = signature.sha256() {
+            let actual = format!("{:x}", Sha256::digest(binary));
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(SignatureError::ChecksumMismatch {
+                    expected: expected.to_string(),
+                    actual,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that `signature` comes from this key and its trusted comment is
+    /// authentic, without the binary itself. Pair with a comparison against
+    /// [`BinarySignature::sha256`] to check a binary that is only reachable
+    /// by checksum.
+    pub fn verify_comment(&self, signature: &BinarySignature) -> Result<(), SignatureError> {
+        if signature.key_id != self.key_id {
+            return Err(SignatureError::UnknownKey {
+                expected: self.key_id(),
+                actual: key_id_hex(&signature.key_id),
+            });
+        }
+        let message = global_message(&signature.signature, &signature.trusted_comment);
+        self.key
+            .verify(
+                &message,
+                &Signature::from_bytes(&signature.global_signature),
+            )
+            .map_err(|_| SignatureError::InvalidSignature)
+    }
+}
+
+/// A detached signature over one binary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinarySignature {
+    pub key_id: [u8; 8],
+    pub signature: [u8; 64],
+    pub trusted_comment: String,
+    pub global_signature: [u8; 64],
+}
+
+impl BinarySignature {
+    pub fn parse(text: &str) -> Result<Self, SignatureError> {
+        let malformed = |reason: &str| SignatureError::Malformed {
+            reason: reason.to_string(),
+        };
+        let mut lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with(UNTRUSTED_PREFIX));
+
+        let signature_line = STANDARD
+            .decode(lines.next().ok_or_else(|| malformed("missing signature"))?)
+            .map_err(|_| malformed("signature is not valid base64"))?;
+        if signature_line.len() != 74 {
+            return Err(malformed("signature has the wrong length"));
+        }
+        if !signature_line.starts_with(ALGORITHM) {
+            return Err(malformed(
+                "only Ed25519 signatures over the whole file are supported",
+            ));
+        }
+
+        let trusted_comment = lines
+            .next()
+            .and_then(|line| line.strip_prefix(TRUSTED_PREFIX.trim_end()))
+            .ok_or_else(|| malformed("missing trusted comment"))?
+            .trim_start()
+            .to_string();
+        let global_signature: [u8; 64] = lines
+            .next()
+            .and_then(|line| STANDARD.decode(line).ok())
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| malformed("missing global signature"))?;
+
+        Ok(Self {
+            key_id: signature_line[2..10].try_into().unwrap_or_default(),
+            signature: signature_line[10..].try_into().unwrap_or([0; 64]),
+            trusted_comment,
+            global_signature,
+        })
+    }
+
+    pub fn encode(&self) -> String {
+        let mut signature_line = ALGORITHM.to_vec();
+        signature_line.extend_from_slice(&self.key_id);
+        signature_line.extend_from_slice(&self.signature);
+        format!(
+            "{UNTRUSTED_PREFIX} signature from rustle-deploy key {}\n{}\n{TRUSTED_PREFIX}{}\n{}\n",
+            key_id_hex(&self.key_id),
+            STANDARD.encode(signature_line),
+            self.trusted_comment,
+            STANDARD.encode(self.global_signature)
+        )
+    }
+
+    /// SHA-256 of the signed binary, from the trusted comment
+    pub fn sha256(&self) -> Option<&str> {
+        self.field("sha256")
+    }
+
+    /// The field `name` of the trusted comment
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.trusted_comment
+            .split('\t')
+            .find_map(|field| field.strip_prefix(name)?.strip_prefix(':'))
+    }
+}
+
+/// Check the running executable against the signature next to it. Runners
+/// call this before executing anything when they were built with a key.
+pub fn verify_executable(public_key: &str) -> Result<(), SignatureError> {
+    let key = TrustedKey::parse(public_key)?;
+    let executable = std::env::current_exe()?;
+    let binary = std::fs::read(&executable)?;
+    let signature = BinarySignature::parse(&std::fs::read_to_string(signature_path(&executable))?)?;
+    key.verify(&binary, &signature)
+}
+
+fn global_message(signature: &[u8; 64], trusted_comment: &str) -> Vec<u8> {
+    let mut message = signature.to_vec();
+    message.extend_from_slice(trusted_comment.as_bytes());
+    message
+}
+
+/// Key ids of bare ed25519 keys, which carry none of their own
+fn derive_key_id(key: &[u8; 32]) -> [u8; 8] {
+    Sha256::digest(key)[..8].try_into().unwrap_or_default()
+}
+
+fn key_id_hex(key_id: &[u8; 8]) -> String {
+    format!("{:016X}", u64::from_le_bytes(*key_id))
+}
+
+/// Decode the last line of `text` that is not a comment
+fn decode_line(text: &str) -> Option<Vec<u8>> {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty() && !line.starts_with(UNTRUSTED_PREFIX))?;
+    STANDARD.decode(line).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signer = BinarySigner::from_seed(&[7u8; 32]);
+        let signature = signer.sign(b"runner binary", "rustle-runner");
+        let key = TrustedKey::parse(&signer.public_key().to_file_contents()).unwrap();
+
+        let parsed = BinarySignature::parse(&signature.encode()).unwrap();
+        assert_eq!(parsed, signature);
+        assert!(key.verify(b"runner binary", &parsed).is_ok());
+        assert!(matches!(
+            key.verify(b"tampered binary", &parsed),
+            Err(SignatureError::InvalidSignature)
+        ));
+
+        let mut forged = parsed.clone();
+        forged.trusted_comment = forged.trusted_comment.replace("sha256:", "sha256:00");
+        assert!(matches!(
+            key.verify_comment(&forged),
+            Err(SignatureError::InvalidSignature)
+        ));
+
+        let other = BinarySigner::from_seed(&[9u8; 32]).public_key();
+        assert!(matches!(
+            other.verify(b"runner binary", &parsed),
+            Err(SignatureError::UnknownKey { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let seed = STANDARD.encode([7u8; 32]);
+        let signer = BinarySigner::parse(&format!("{UNTRUSTED_PREFIX} test\n{seed}\n")).unwrap();
+        let bare = STANDARD.encode(signer.public_key().key.as_bytes());
+
+        assert_eq!(TrustedKey::parse(&bare).unwrap(), signer.public_key());
+        assert_eq!(
+            TrustedKey::parse(&signer.public_key().encoded()).unwrap(),
+            signer.public_key()
+        );
+        assert!(TrustedKey::parse("not a key").is_err());
+        assert!(BinarySigner::parse(&bare[..20]).is_err());
+    }
+}
@@ -1,6 +1,10 @@
+use crate::runtime::audit::AuditEntry;
+use crate::runtime::metrics::ModuleMetrics;
+use crate::runtime::telemetry::Span;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
 use std::time::Duration;
 
 /// Task execution result
@@ -21,13 +25,52 @@ pub struct TaskResult {
     pub error: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// What is shown of the result of a `no_log` task instead of its data
+pub const NO_LOG_MESSAGE: &str =
+    "the output has been hidden due to the fact that 'no_log: true' was specified for this result";
+
+impl TaskResult {
+    /// The result as a `no_log` task shows it: its outcome without output,
+    /// stdout, stderr or error message, and only the outcome of each loop
+    /// item
+    pub fn censored(&self) -> Self {
+        let outcome = |result: &serde_json::Value| {
+            let flag = |name: &str| result.get(name).cloned().unwrap_or(false.into());
+            serde_json::json!({
+                "censored": NO_LOG_MESSAGE,
+                "changed": flag("changed"),
+                "failed": flag("failed"),
+                "skipped": flag("skipped"),
+            })
+        };
+        let mut output = serde_json::json!({
+            "censored": NO_LOG_MESSAGE,
+            "changed": self.changed,
+        });
+        if let Some(serde_json::Value::Array(items)) = self.output.get("results") {
+            output["results"] = items.iter().map(outcome).collect();
+        }
+        Self {
+            output,
+            stdout: None,
+            stderr: None,
+            error: self.error.as_ref().map(|_| NO_LOG_MESSAGE.to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TaskStatus {
     Pending,
     Running,
     Success,
     Failed,
     Skipped,
+    /// Failed, but the rescue section of its block recovered
+    Rescued,
+    /// Failed, but the task ignores errors
+    Ignored,
     Timeout,
     Cancelled,
 }
@@ -44,6 +87,17 @@ pub struct ExecutionResult {
     pub end_time: DateTime<Utc>,
     pub duration: Duration,
     pub errors: Vec<String>,
+    #[serde(default)]
+    pub module_metrics: ModuleMetrics,
+    /// Notified handlers that ran, in order; not counted in the summary
+    #[serde(default)]
+    pub handler_results: Vec<TaskResult>,
+    /// Trace spans of the execution, when the controller traces it
+    #[serde(default)]
+    pub spans: Vec<Span>,
+    /// Changes tasks made, for the controller's audit log
+    #[serde(default)]
+    pub audit: Vec<AuditEntry>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,6 +107,12 @@ pub struct ExecutionSummary {
     pub failed_tasks: usize,
     pub skipped_tasks: usize,
     pub changed_tasks: usize,
+    /// Failures the tasks ignored, not counted as failed
+    #[serde(default)]
+    pub ignored_tasks: usize,
+    /// Failures rescue sections recovered from, not counted as failed
+    #[serde(default)]
+    pub rescued_tasks: usize,
 }
 
 /// Play execution result
@@ -73,10 +133,13 @@ pub struct BatchResult {
 }
 
 /// Execution state management
+#[derive(Clone)]
 pub struct StateManager {
     task_results: HashMap<String, TaskResult>,
+    handler_results: Vec<TaskResult>,
     execution_state: ExecutionState,
     facts: HashMap<String, serde_json::Value>,
+    module_metrics: ModuleMetrics,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -95,6 +158,7 @@ impl StateManager {
     pub fn new(execution_id: String, total_tasks: usize) -> Self {
         Self {
             task_results: HashMap::new(),
+            handler_results: Vec::new(),
             execution_state: ExecutionState {
                 execution_id,
                 current_play: None,
@@ -106,6 +170,7 @@ impl StateManager {
                 start_time: Utc::now(),
             },
             facts: HashMap::new(),
+            module_metrics: ModuleMetrics::new(),
         }
     }
 
@@ -129,6 +194,27 @@ impl StateManager {
         self.task_results.insert(result.task_id.clone(), result);
     }
 
+    /// Mark the failed result of `task_id` as recovered by a rescue section
+    pub fn mark_rescued(&mut self, task_id: &str) {
+        if let Some(result) = self.task_results.get_mut(task_id) {
+            if result.failed {
+                result.failed = false;
+                result.status = TaskStatus::Rescued;
+                self.execution_state
+                    .failed_tasks
+                    .retain(|failed| failed != task_id);
+            }
+        }
+    }
+
+    pub fn add_handler_result(&mut self, result: TaskResult) {
+        self.handler_results.push(result);
+    }
+
+    pub fn get_handler_results(&self) -> &[TaskResult] {
+        &self.handler_results
+    }
+
     pub fn get_task_result(&self, task_id: &str) -> Option<&TaskResult> {
         self.task_results.get(task_id)
     }
@@ -137,6 +223,15 @@ impl StateManager {
         &self.task_results
     }
 
+    /// Results of the tasks that did not fail, which a resumed run keeps
+    pub fn completed_task_results(&self) -> HashMap<String, TaskResult> {
+        self.task_results
+            .iter()
+            .filter(|(_, result)| !result.failed)
+            .map(|(id, result)| (id.clone(), result.clone()))
+            .collect()
+    }
+
     pub fn set_current_play(&mut self, play_name: Option<String>) {
         self.execution_state.current_play = play_name;
     }
@@ -149,6 +244,23 @@ impl StateManager {
         &self.execution_state
     }
 
+    /// Record a module invocation for the per-module execution metrics
+    pub fn record_module_invocation(&mut self, module: &str, result: &TaskResult) {
+        if !result.skipped {
+            self.module_metrics
+                .record(module, result.duration, result.failed, result.changed);
+        }
+    }
+
+    /// Adopt the module invocations another state manager recorded
+    pub fn merge_module_metrics(&mut self, other: &ModuleMetrics) {
+        self.module_metrics.merge(other);
+    }
+
+    pub fn get_module_metrics(&self) -> &ModuleMetrics {
+        &self.module_metrics
+    }
+
     pub fn set_facts(&mut self, facts: HashMap<String, serde_json::Value>) {
         self.facts = facts;
     }
@@ -157,6 +269,13 @@ impl StateManager {
         &self.facts
     }
 
+    fn count_status(&self, status: TaskStatus) -> usize {
+        self.task_results
+            .values()
+            .filter(|r| r.status == status)
+            .count()
+    }
+
     pub fn build_execution_result(&self, end_time: DateTime<Utc>) -> ExecutionResult {
         let duration = (end_time - self.execution_state.start_time)
             .to_std()
@@ -168,14 +287,19 @@ impl StateManager {
             failed_tasks: self.execution_state.failed_tasks.len(),
             skipped_tasks: self.task_results.values().filter(|r| r.skipped).count(),
             changed_tasks: self.execution_state.changed_tasks.len(),
+            ignored_tasks: self.count_status(TaskStatus::Ignored),
+            rescued_tasks: self.count_status(TaskStatus::Rescued),
         };
 
-        let failed = !self.execution_state.failed_tasks.is_empty();
+        let failed = !self.execution_state.failed_tasks.is_empty()
+            || self.handler_results.iter().any(|r| r.failed);
         let success = !failed;
 
         let errors = self
             .task_results
             .values()
+            .chain(&self.handler_results)
+            .filter(|r| r.failed)
             .filter_map(|r| r.error.as_ref())
             .cloned()
             .collect();
@@ -190,7 +314,59 @@ impl StateManager {
             end_time,
             duration,
             errors,
+            module_metrics: self.module_metrics.clone(),
+            handler_results: self.handler_results.clone(),
+            spans: Vec::new(),
+            audit: Vec::new(),
+        }
+    }
+}
+
+/// File a runner persists its progress to, for a later run to resume from
+pub const STATE_FILE_ENV: &str = "RUSTLE_STATE_FILE";
+
+/// Set to resume from the persisted progress instead of starting over
+pub const RESUME_ENV: &str = "RUSTLE_RESUME";
+
+/// Progress of a run on one host, persisted after every task so that a
+/// failed run can resume from the first task that did not complete
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PersistedState {
+    /// Plan the progress belongs to; progress through another plan is ignored
+    pub plan_id: String,
+    /// Results of the completed tasks, by task id
+    pub completed_tasks: HashMap<String, TaskResult>,
+    /// Variables, including the registered results
+    pub variables: HashMap<String, serde_json::Value>,
+    /// Handlers notified that have not run yet
+    pub notified_handlers: Vec<String>,
+}
+
+impl PersistedState {
+    /// The state persisted at `path`, `None` if there is none
+    pub fn load(path: &Path) -> std::io::Result<Option<Self>> {
+        match std::fs::read(path) {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replace the state at `path`. Registered results may hold secrets, so
+    /// only the owner can read the file.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
         }
+        let partial = path.with_extension("partial");
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&partial)?;
+        std::io::Write::write_all(&mut file, &serde_json::to_vec(self)?)?;
+        file.sync_all()?;
+        std::fs::rename(partial, path)
     }
 }
 
@@ -0,0 +1,99 @@
+//! Execution strategies.
+//!
+//! Under the `linear` strategy the hosts of a deployment execute each task
+//! together: no host starts a task before every other host still running has
+//! reached it. Runners cannot see each other, so the controller keeps them in
+//! step: before each task, a runner writes a [`ProgressEvent::TaskReached`]
+//! frame with the position of the task in the plan, then waits for the
+//! controller to release that position with a file in the directory named by
+//! [`SYNC_DIR_ENV`]. Under `free`, and without a controller to coordinate,
+//! every runner goes at its own pace.
+//!
+//! Whatever the strategy, runners also wait for the controller before the
+//! tasks it has to count hosts for: tasks with a `throttle`, which only so
+//! many hosts may run at the same time, and the tasks of plays with
+//! `any_errors_fatal`, which the controller stops every host at once one of
+//! them failed. A runner the controller stops finds an abort file instead of
+//! the release file.
+//!
+//! Within a host, ready tasks marked `parallel` execute side by side, up to
+//! [`RuntimeConfig::parallel_tasks`] at a time, whatever the strategy.
+//!
+//! [`ProgressEvent::TaskReached`]: crate::runtime::ProgressEvent::TaskReached
+//! [`RuntimeConfig::parallel_tasks`]: crate::runtime::RuntimeConfig::parallel_tasks
+
+use crate::execution::ExecutionStrategy;
+use crate::runtime::ExecutionError;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Directory the controller releases task positions in
+pub const SYNC_DIR_ENV: &str = "RUSTLE_SYNC_DIR";
+
+/// How often a waiting runner checks whether it was released
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How a runner paces its tasks against the other hosts
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostStrategy {
+    /// Where the controller releases tasks, when one coordinates the hosts
+    dir: Option<PathBuf>,
+    /// Every task waits for the other hosts, not only the coordinated ones
+    linear: bool,
+}
+
+impl HostStrategy {
+    /// The way to execute a plan with `strategy`, coordinated by the
+    /// controller through [`SYNC_DIR_ENV`] if it set one
+    pub fn from_env(strategy: &ExecutionStrategy) -> Self {
+        Self {
+            dir: std::env::var_os(SYNC_DIR_ENV).map(PathBuf::from),
+            linear: !matches!(strategy, ExecutionStrategy::Free),
+        }
+    }
+
+    /// Where to wait for the controller before starting a task, if at all:
+    /// before every task under the linear strategy, and before `coordinated`
+    /// ones under any
+    pub fn sync_dir(&self, coordinated: bool) -> Option<&Path> {
+        self.dir.as_deref().filter(|_| self.linear || coordinated)
+    }
+}
+
+/// Name of the file releasing the task at `position`
+pub fn release_file_name(position: usize) -> String {
+    format!("{position}.released")
+}
+
+/// Name of the file stopping the runner at `position` instead, which holds
+/// the reason
+pub fn abort_file_name(position: usize) -> String {
+    format!("{position}.aborted")
+}
+
+/// Wait up to `timeout` for the controller to release the task `task_id` at
+/// `position`, or to stop the runner there
+pub async fn wait_for_release(
+    dir: &Path,
+    task_id: &str,
+    position: usize,
+    timeout: Duration,
+) -> Result<(), ExecutionError> {
+    let path = dir.join(release_file_name(position));
+    let aborted = dir.join(abort_file_name(position));
+    let start = Instant::now();
+    while !tokio::fs::try_exists(&path).await? {
+        if tokio::fs::try_exists(&aborted).await? {
+            let reason = tokio::fs::read_to_string(&aborted).await?;
+            return Err(ExecutionError::PlayAborted { reason });
+        }
+        if start.elapsed() >= timeout {
+            return Err(ExecutionError::TaskTimeout {
+                task_id: task_id.to_string(),
+                timeout: timeout.as_secs(),
+            });
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    Ok(())
+}
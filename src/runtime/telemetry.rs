@@ -0,0 +1,289 @@
+//! Trace spans of the tasks a runner executes.
+//!
+//! The controller hands a runner the span its execution belongs to in
+//! [`TRACEPARENT_ENV`], as a W3C `traceparent`. The runner then records a
+//! span for its execution, one for each play under it and one for each task
+//! under its play, with the host, task, module and outcome as attributes.
+//! Runners cannot reach a collector themselves, so they buffer the spans and
+//! ship them back in [`ExecutionResult::spans`] for the controller to export
+//! together with its own.
+
+use crate::execution::{Play, Task};
+use crate::runtime::progress::CallbackPlugin;
+use crate::runtime::state::{ExecutionResult, TaskResult};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, PoisonError};
+use uuid::Uuid;
+
+/// W3C `traceparent` of the span the execution of a runner belongs to
+pub const TRACEPARENT_ENV: &str = "TRACEPARENT";
+
+/// Where a span belongs: its trace, and the span new spans are children of
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceContext {
+    /// 32 lowercase hex digits
+    pub trace_id: String,
+    /// 16 lowercase hex digits
+    pub span_id: String,
+}
+
+impl TraceContext {
+    /// The context of a new trace
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: new_id(32),
+            span_id: new_id(16),
+        }
+    }
+
+    /// Parse a W3C `traceparent` such as
+    /// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`
+    pub fn parse(traceparent: &str) -> Option<Self> {
+        let mut parts = traceparent.trim().split('-');
+        let (version, trace_id, span_id) = (parts.next()?, parts.next()?, parts.next()?);
+        parts.next()?;
+        let valid = |id: &str, len: usize| {
+            id.len() == len
+                && id
+                    .bytes()
+                    .all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase())
+                && id.bytes().any(|b| b != b'0')
+        };
+        (version == "00" && valid(trace_id, 32) && valid(span_id, 16)).then(|| Self {
+            trace_id: trace_id.to_string(),
+            span_id: span_id.to_string(),
+        })
+    }
+
+    /// The context the controller handed over in [`TRACEPARENT_ENV`], if any
+    pub fn from_env() -> Option<Self> {
+        let value = std::env::var(TRACEPARENT_ENV).ok()?;
+        let context = Self::parse(&value);
+        if context.is_none() {
+            tracing::warn!("Ignoring invalid {}: {}", TRACEPARENT_ENV, value);
+        }
+        context
+    }
+
+    /// The context as a W3C `traceparent`, sampled
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-01", self.trace_id, self.span_id)
+    }
+}
+
+/// `len` random lowercase hex digits
+fn new_id(len: usize) -> String {
+    Uuid::new_v4().simple().to_string()[..len].to_string()
+}
+
+/// A timed operation of a trace
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, serde_json::Value>,
+    /// Why the operation failed, if it did
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl Span {
+    /// Start a span named `name` under `parent`
+    pub fn start(name: impl Into<String>, parent: &TraceContext) -> Self {
+        let now = Utc::now();
+        Self {
+            trace_id: parent.trace_id.clone(),
+            span_id: new_id(16),
+            parent_span_id: Some(parent.span_id.clone()),
+            name: name.into(),
+            start_time: now,
+            end_time: now,
+            attributes: BTreeMap::new(),
+            error: None,
+        }
+    }
+
+    pub fn with_attribute(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<serde_json::Value>,
+    ) -> Self {
+        self.set_attribute(key, value);
+        self
+    }
+
+    pub fn set_attribute(&mut self, key: impl Into<String>, value: impl Into<serde_json::Value>) {
+        self.attributes.insert(key.into(), value.into());
+    }
+
+    /// Mark the operation as failed because of `error`
+    pub fn fail(&mut self, error: impl Into<String>) {
+        self.error = Some(error.into());
+    }
+
+    /// The context of spans under this one
+    pub fn context(&self) -> TraceContext {
+        TraceContext {
+            trace_id: self.trace_id.clone(),
+            span_id: self.span_id.clone(),
+        }
+    }
+
+    /// End the span now
+    pub fn end(mut self) -> Self {
+        self.end_time = Utc::now();
+        self
+    }
+}
+
+/// Records the spans of executions from their lifecycle events
+pub struct SpanRecorder {
+    host: String,
+    parent: TraceContext,
+    state: Mutex<RecorderState>,
+}
+
+struct RecorderState {
+    execution: Span,
+    /// The span of the play being executed
+    play: Option<Span>,
+    /// Module of each started task, by task id
+    modules: HashMap<String, String>,
+    /// Spans ended so far
+    spans: Vec<Span>,
+}
+
+impl SpanRecorder {
+    /// Record the executions on `host` under `parent`
+    pub fn new(host: &str, parent: TraceContext) -> Self {
+        let execution = Self::execution_span(host, &parent);
+        Self {
+            host: host.to_string(),
+            parent,
+            state: Mutex::new(RecorderState {
+                execution,
+                play: None,
+                modules: HashMap::new(),
+                spans: Vec::new(),
+            }),
+        }
+    }
+
+    fn execution_span(host: &str, parent: &TraceContext) -> Span {
+        Span::start(format!("runner {host}"), parent).with_attribute("host.name", host)
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, RecorderState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// End the span of the execution that produced `result` and return every
+    /// span recorded for it, starting over for the next execution
+    pub fn finish(&self, result: &ExecutionResult) -> Vec<Span> {
+        let mut state = self.state();
+        let mut execution = std::mem::replace(
+            &mut state.execution,
+            Self::execution_span(&self.host, &self.parent),
+        );
+        if let Some(play) = state.play.take() {
+            state.spans.push(play.end());
+        }
+        execution.set_attribute("rustle.execution_id", result.execution_id.as_str());
+        execution.set_attribute("rustle.tasks", result.summary.total_tasks);
+        execution.set_attribute("rustle.changed_tasks", result.summary.changed_tasks);
+        execution.set_attribute("rustle.failed_tasks", result.summary.failed_tasks);
+        if result.failed {
+            execution.fail(if result.errors.is_empty() {
+                "execution failed".to_string()
+            } else {
+                result.errors.join("; ")
+            });
+        }
+        state.modules.clear();
+        let mut spans = std::mem::take(&mut state.spans);
+        spans.push(execution.end());
+        spans
+    }
+}
+
+impl CallbackPlugin for SpanRecorder {
+    fn name(&self) -> &str {
+        "opentelemetry"
+    }
+
+    fn on_play_start(&self, play: &Play) {
+        let mut state = self.state();
+        if let Some(previous) = state.play.take() {
+            state.spans.push(previous.end());
+        }
+        let name = play.name.as_deref().unwrap_or(&play.id);
+        let span = Span::start(format!("play {name}"), &state.execution.context())
+            .with_attribute("host.name", self.host.as_str())
+            .with_attribute("rustle.play.id", play.id.as_str());
+        state.play = Some(span);
+    }
+
+    fn on_task_start(&self, task: &Task) {
+        self.state()
+            .modules
+            .insert(task.id.clone(), task.module.clone());
+    }
+
+    fn on_task_result(&self, result: &TaskResult) {
+        let mut state = self.state();
+        let parent = state.play.as_ref().unwrap_or(&state.execution).context();
+        let mut span = Span::start(result.name.clone(), &parent)
+            .with_attribute("host.name", self.host.as_str())
+            .with_attribute("rustle.task.id", result.task_id.as_str())
+            .with_attribute("rustle.task.name", result.name.as_str())
+            .with_attribute("rustle.changed", result.changed)
+            .with_attribute("rustle.failed", result.failed)
+            .with_attribute("rustle.skipped", result.skipped)
+            .with_attribute(
+                "rustle.duration_ms",
+                result.duration.as_millis().min(u64::MAX as u128) as u64,
+            );
+        if let Some(module) = state.modules.get(&result.task_id) {
+            span.set_attribute("rustle.module", module.as_str());
+        }
+        if result.failed {
+            span.fail(result.error.as_deref().unwrap_or("failed"));
+        }
+        span.start_time = result.start_time;
+        span.end_time = result.end_time;
+        state.spans.push(span);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traceparent_round_trips() {
+        let context = TraceContext::new_root();
+        assert_eq!(context.trace_id.len(), 32);
+        assert_eq!(context.span_id.len(), 16);
+        assert_eq!(TraceContext::parse(&context.traceparent()), Some(context));
+
+        let parsed =
+            TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        assert_eq!(parsed.span_id, "00f067aa0ba902b7");
+        for invalid in [
+            "",
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        ] {
+            assert_eq!(TraceContext::parse(invalid), None, "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Execution data appended to a runner
+//!
+//! A runner built with [`DataLayout::Appended`] compiles no plan in: it is
+//! built once per target and module set, and finds its execution data at
+//! the end of its own executable instead:
+//!
+//! ```text
+//! <runner> <archive> <signature> <archive length> <signature length> <APPENDED_MAGIC>
+//! ```
+//!
+//! with both lengths as little-endian u64. The archive is a tar of the same
+//! sections a compiled runner includes, under the same paths, and an
+//! [`ARCHIVE_INDEX`] naming them. The signature is a bare Ed25519 signature
+//! over the archive, or empty; a runner built with a data key refuses data
+//! that is not signed by it. Loaders ignore data past the end of an
+//! executable's image, so the runner still starts as it was built, and a
+//! changed plan only needs a new archive appended to the same runner.
+
+use super::sections::{EmbeddedSection, EmbeddedSections, SectionEncoding, SectionKind};
+use crate::runtime::signing::BinarySigner;
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind, Read};
+use std::path::PathBuf;
+
+/// Marks the end of a binary with appended execution data
+pub const APPENDED_MAGIC: &[u8; 8] = b"RSTLDAT1";
+/// The archive member listing the sections
+pub const ARCHIVE_INDEX: &str = "index.json";
+
+const TRAILER_LEN: usize = 16 + APPENDED_MAGIC.len();
+
+/// Where a runner finds its execution data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataLayout {
+    /// Compiled into the runner as sections
+    #[default]
+    Compiled,
+    /// Appended to a runner that reads it at startup
+    Appended,
+}
+
+/// What the archive holds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppendedIndex {
+    pub encoding: SectionEncoding,
+    pub sections: Vec<AppendedSection>,
+}
+
+/// One section in the archive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppendedSection {
+    pub name: String,
+    pub kind: SectionKind,
+    /// The archive member holding the section
+    pub path: PathBuf,
+    pub original_size: u64,
+}
+
+/// Pack `sections` into an archive, byte for byte the same for the same
+/// sections
+pub fn pack_archive(sections: &EmbeddedSections) -> std::io::Result<Vec<u8>> {
+    let index = AppendedIndex {
+        encoding: sections.encoding,
+        sections: sections
+            .sections
+            .iter()
+            .map(|section| AppendedSection {
+                name: section.name.clone(),
+                kind: section.kind,
+                path: section.path.clone(),
+                original_size: section.original_size,
+            })
+            .collect(),
+    };
+    let index = serde_json::to_vec(&index)?;
+
+    let mut archive = tar::Builder::new(Vec::new());
+    let members = std::iter::once((PathBuf::from(ARCHIVE_INDEX), index.as_slice())).chain(
+        sections
+            .sections
+            .iter()
+            .map(|section| (section.path.clone(), section.data.as_slice())),
+    );
+    for (path, data) in members {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_cksum();
+        archive.append_data(&mut header, path, data)?;
+    }
+    archive.into_inner()
+}
+
+/// The sections packed into `archive`
+pub fn unpack_archive(archive: &[u8]) -> std::io::Result<EmbeddedSections> {
+    let mut index = None;
+    let mut members = std::collections::HashMap::new();
+    for entry in tar::Archive::new(archive).entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        if path == std::path::Path::new(ARCHIVE_INDEX) {
+            index = Some(serde_json::from_slice::<AppendedIndex>(&data)?);
+        } else {
+            members.insert(path, data);
+        }
+    }
+
+    let index = index.ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("archive has no {ARCHIVE_INDEX}"),
+        )
+    })?;
+    let sections = index
+        .sections
+        .into_iter()
+        .map(|section| {
+            let data = members.remove(&section.path).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("archive has no {}", section.path.display()),
+                )
+            })?;
+            Ok(EmbeddedSection {
+                name: section.name,
+                kind: section.kind,
+                path: section.path,
+                data,
+                original_size: section.original_size,
+            })
+        })
+        .collect::<std::io::Result<_>>()?;
+    Ok(EmbeddedSections {
+        encoding: index.encoding,
+        sections,
+    })
+}
+
+/// The parts of a binary with appended execution data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppendedParts<'a> {
+    pub runner: &'a [u8],
+    pub archive: &'a [u8],
+    pub signature: Option<&'a [u8]>,
+}
+
+/// `runner` with `archive` and its `signature` appended
+pub fn append_archive(runner: &[u8], archive: &[u8], signature: Option<&[u8]>) -> Vec<u8> {
+    let signature = signature.unwrap_or_default();
+    let mut binary =
+        Vec::with_capacity(runner.len() + archive.len() + signature.len() + TRAILER_LEN);
+    binary.extend_from_slice(runner);
+    binary.extend_from_slice(archive);
+    binary.extend_from_slice(signature);
+    binary.extend_from_slice(&(archive.len() as u64).to_le_bytes());
+    binary.extend_from_slice(&(signature.len() as u64).to_le_bytes());
+    binary.extend_from_slice(APPENDED_MAGIC);
+    binary
+}
+
+/// Split `binary` into the runner and the data appended to it, if any
+pub fn split_appended(binary: &[u8]) -> Option<AppendedParts<'_>> {
+    let body = binary.strip_suffix(APPENDED_MAGIC.as_slice())?;
+    let (body, lengths) = body.split_at_checked(body.len().checked_sub(16)?)?;
+    let length = |bytes: &[u8]| usize::try_from(u64::from_le_bytes(bytes.try_into().ok()?)).ok();
+    let archive_len = length(&lengths[..8])?;
+    let signature_len = length(&lengths[8..])?;
+    let (body, signature) = body.split_at_checked(body.len().checked_sub(signature_len)?)?;
+    let (runner, archive) = body.split_at_checked(body.len().checked_sub(archive_len)?)?;
+    Some(AppendedParts {
+        runner,
+        archive,
+        signature: (!signature.is_empty()).then_some(signature),
+    })
+}
+
+/// Append `sections` to `runner`, signed by `signer` if given
+pub fn package_appended(
+    runner: &[u8],
+    sections: &EmbeddedSections,
+    signer: Option<&BinarySigner>,
+) -> std::io::Result<Vec<u8>> {
+    let archive = pack_archive(sections)?;
+    let signature = signer.map(|signer| signer.sign_detached(&archive));
+    Ok(append_archive(
+        runner,
+        &archive,
+        signature.as_ref().map(|signature| signature.as_slice()),
+    ))
+}
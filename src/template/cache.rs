@@ -215,7 +215,7 @@ impl DurationExt for Duration {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::template::{EmbeddedData, EncryptedSecrets, TargetInfo};
+    use crate::template::{EmbeddedData, EmbeddedSections, EncryptedSecrets, TargetInfo};
     use crate::types::deployment::RuntimeConfig;
     use std::collections::HashMap;
 
@@ -242,6 +242,7 @@ mod tests {
                 },
                 facts_cache: None,
             },
+            sections: EmbeddedSections::default(),
             cargo_toml: String::new(),
             build_script: None,
             target_info: TargetInfo {
@@ -255,6 +256,7 @@ mod tests {
             compilation_flags: vec![],
             estimated_binary_size: 5_000_000,
             cache_key: id.to_string(),
+            data_layout: Default::default(),
         }
     }
 
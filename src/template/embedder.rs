@@ -1,8 +1,13 @@
+use crate::compilation::reproducible::canonical_value;
 use crate::execution::plan_converter::RustlePlanConverter;
 use crate::execution::rustle_plan::{BinaryDeploymentPlan, RustlePlanOutput, StaticFileRef};
+use crate::execution::{is_vaulted, VaultError, VaultSecrets};
+use crate::modules::files::template_engine::jinja_environment::referenced_templates;
 use crate::types::deployment::RuntimeConfig;
 use anyhow::Result;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 use super::{EmbeddedData, EncryptedSecrets, TargetInfo, TemplateConfig};
@@ -15,19 +20,106 @@ pub enum EmbedError {
     Io(#[from] std::io::Error),
     #[error("Plan conversion failed: {0}")]
     PlanConversion(#[from] crate::execution::compatibility::ConversionError),
+    #[error("Vault error: {0}")]
+    Vault(#[from] VaultError),
+    #[error("Cannot embed {path}: {size} bytes is above the {limit} byte limit")]
+    PayloadTooLarge { path: String, size: u64, limit: u64 },
+}
+
+/// What the `src` of a task names when the runner carries the file
+pub const EMBEDDED_SRC_PREFIX: &str = "embedded:";
+/// Static files holding payloads are named under this prefix
+pub const PAYLOAD_PREFIX: &str = "payload/";
+
+/// Modules whose local `src` files are embedded
+const PAYLOAD_MODULES: &[&str] = &["copy", "template"];
+
+/// Which local files tasks read are embedded into the runner
+#[derive(Debug, Clone)]
+pub struct PayloadPolicy {
+    /// Files above this size fail the build rather than bloat the runner
+    pub max_file_size: u64,
+    /// Limit of all payloads together
+    pub max_total_size: u64,
+    /// Glob patterns of files never embedded, which tasks then read on the
+    /// target as before
+    pub exclude: Vec<String>,
+}
+
+impl Default for PayloadPolicy {
+    fn default() -> Self {
+        Self {
+            max_file_size: 16 * 1024 * 1024,
+            max_total_size: 128 * 1024 * 1024,
+            exclude: ["*.git/*", "*.swp", "*~", "*.DS_Store"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+/// A local file embedded for a task
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedPayload {
+    pub task_id: String,
+    pub source: PathBuf,
+    /// Name of the file in the embedded store
+    pub key: String,
+    pub size: u64,
+}
+
+/// The local files of a plan embedded into its runner
+#[derive(Debug, Clone, Default)]
+pub struct PayloadBundle {
+    /// Contents by key, each file once however many tasks read it
+    pub files: HashMap<String, Vec<u8>>,
+    pub payloads: Vec<EmbeddedPayload>,
+    /// Local files left out by the exclusion rules
+    pub excluded: Vec<PathBuf>,
+}
+
+impl PayloadBundle {
+    pub fn total_size(&self) -> u64 {
+        self.files.values().map(|data| data.len() as u64).sum()
+    }
 }
 
 pub struct DataEmbedder {
     _config: TemplateConfig,
+    vault: VaultSecrets,
+    template_search_path: Vec<PathBuf>,
+    payload_policy: PayloadPolicy,
 }
 
 impl DataEmbedder {
     pub fn new(config: &TemplateConfig) -> Result<Self> {
         Ok(Self {
             _config: config.clone(),
+            vault: VaultSecrets::new(),
+            template_search_path: Vec::new(),
+            payload_policy: PayloadPolicy::default(),
         })
     }
 
+    /// Embed the local files of tasks as `policy` allows
+    pub fn with_payload_policy(mut self, policy: PayloadPolicy) -> Self {
+        self.payload_policy = policy;
+        self
+    }
+
+    /// Decrypt vaulted static files with `secrets` before embedding them
+    pub fn with_vault(mut self, secrets: VaultSecrets) -> Self {
+        self.vault = secrets;
+        self
+    }
+
+    /// Look up the templates static files extend, include or import in
+    /// these directories when they are not next to them
+    pub fn with_template_search_path(mut self, search_path: Vec<PathBuf>) -> Self {
+        self.template_search_path = search_path;
+        self
+    }
+
     pub async fn embed_execution_data(
         &self,
         execution_plan: &RustlePlanOutput,
@@ -37,7 +129,7 @@ impl DataEmbedder {
         // Convert RustlePlanOutput to ExecutionPlan to properly handle condition conversion
         let converter = RustlePlanConverter::new();
         let converted_plan = converter.convert_to_execution_plan(execution_plan)?;
-        let execution_plan_json = serde_json::to_string_pretty(&converted_plan)?;
+        let execution_plan_json = serde_json::to_string_pretty(&canonical_value(&converted_plan)?)?;
 
         let runtime_config = RuntimeConfig {
             controller_endpoint: binary_deployment.controller_endpoint.clone(),
@@ -66,8 +158,16 @@ impl DataEmbedder {
         for static_file_ref in &binary_deployment.static_files {
             match self.load_static_file(static_file_ref).await {
                 Ok((path, content)) => {
+                    let referenced = self
+                        .load_referenced_templates(static_file_ref, &content)
+                        .await?;
                     static_files.insert(path, content);
+                    for (path, content) in referenced {
+                        static_files.entry(path).or_insert(content);
+                    }
                 }
+                // Shipping the ciphertext instead would break the task silently
+                Err(EmbedError::Vault(e)) => return Err(e.into()),
                 Err(e) => {
                     tracing::warn!(
                         "Failed to load static file '{}': {}. Skipping file for stdin compatibility.",
@@ -90,12 +190,194 @@ impl DataEmbedder {
         })
     }
 
+    /// Embed the local files that copy and template tasks of `plan` name in
+    /// their `src`, returning the plan with those tasks reading them from
+    /// the embedded store. Sources not found here are expected on the
+    /// target and left alone, as are templated ones and `remote_src` copies.
+    pub async fn bundle_local_sources(
+        &self,
+        plan: &RustlePlanOutput,
+    ) -> Result<(RustlePlanOutput, PayloadBundle), EmbedError> {
+        let mut plan = plan.clone();
+        let mut bundle = PayloadBundle::default();
+        let exclude: Vec<glob::Pattern> = self
+            .payload_policy
+            .exclude
+            .iter()
+            .filter_map(|pattern| match glob::Pattern::new(pattern) {
+                Ok(pattern) => Some(pattern),
+                Err(e) => {
+                    tracing::warn!("Ignoring payload exclusion '{}': {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+
+        for play in &mut plan.plays {
+            let tasks = play
+                .batches
+                .iter_mut()
+                .flat_map(|batch| batch.tasks.iter_mut())
+                .map(|task| (task.task_id.as_str(), task.module.as_str(), &mut task.args));
+            let handlers = play.handlers.iter_mut().map(|handler| {
+                (
+                    handler.handler_id.as_str(),
+                    handler.module.as_str(),
+                    &mut handler.args,
+                )
+            });
+            for (task_id, module, args) in tasks.chain(handlers) {
+                let short_name = module.rsplit('.').next().unwrap_or(module);
+                if !PAYLOAD_MODULES.contains(&short_name)
+                    || args.get("remote_src").and_then(|v| v.as_bool()) == Some(true)
+                {
+                    continue;
+                }
+                let Some(src) = args.get("src").and_then(|v| v.as_str()) else {
+                    continue;
+                };
+                if src.contains("{{") || src.starts_with(EMBEDDED_SRC_PREFIX) {
+                    continue;
+                }
+                let Some(source) = self.find_local_source(src) else {
+                    tracing::debug!(
+                        "'{}' of task {} is not local, reading it on the target",
+                        src,
+                        task_id
+                    );
+                    continue;
+                };
+                if exclude.iter().any(|pattern| pattern.matches_path(&source)) {
+                    bundle.excluded.push(source);
+                    continue;
+                }
+
+                let size = tokio::fs::metadata(&source).await?.len();
+                self.check_payload_size(&source, size, self.payload_policy.max_file_size)?;
+                let content = self.read(&source).await?;
+                let digest = format!("{:x}", Sha256::digest(&content));
+                let file_name = source
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let key = format!("{PAYLOAD_PREFIX}{}/{file_name}", &digest[..16]);
+                if !bundle.files.contains_key(&key) {
+                    bundle.files.insert(key.clone(), content);
+                    self.check_payload_size(
+                        &source,
+                        bundle.total_size(),
+                        self.payload_policy.max_total_size,
+                    )?;
+                }
+
+                args.insert(
+                    "src".to_string(),
+                    serde_json::Value::String(format!("{EMBEDDED_SRC_PREFIX}{key}")),
+                );
+                bundle.payloads.push(EmbeddedPayload {
+                    task_id: task_id.to_string(),
+                    source,
+                    key,
+                    size,
+                });
+            }
+        }
+
+        Ok((plan, bundle))
+    }
+
+    /// The local file `src` names, as is or in the template search path
+    fn find_local_source(&self, src: &str) -> Option<PathBuf> {
+        let path = Path::new(src);
+        std::iter::once(path.to_path_buf())
+            .chain(
+                self.template_search_path
+                    .iter()
+                    .filter(|_| path.is_relative())
+                    .map(|root| root.join(path)),
+            )
+            .find(|candidate| candidate.is_file())
+    }
+
+    fn check_payload_size(&self, source: &Path, size: u64, limit: u64) -> Result<(), EmbedError> {
+        if size > limit {
+            return Err(EmbedError::PayloadTooLarge {
+                path: source.display().to_string(),
+                size,
+                limit,
+            });
+        }
+        Ok(())
+    }
+
     /// Load a static file, returning the target path and file contents
     async fn load_static_file(
         &self,
         static_file_ref: &StaticFileRef,
     ) -> Result<(String, Vec<u8>), EmbedError> {
-        let content = tokio::fs::read(&static_file_ref.source_path).await?;
+        let content = self.read(Path::new(&static_file_ref.source_path)).await?;
         Ok((static_file_ref.target_path.clone(), content))
     }
+
+    async fn read(&self, path: &Path) -> Result<Vec<u8>, EmbedError> {
+        let content = tokio::fs::read(path).await?;
+        // Hosts get the plaintext, as they would from Ansible
+        if is_vaulted(&content) {
+            return Ok(self.vault.decrypt(&content)?);
+        }
+        Ok(content)
+    }
+
+    /// The templates a static file extends, includes or imports, and those
+    /// they name in turn, placed next to it on the target, which is where
+    /// rendering it looks them up first. They are found next to it or in the
+    /// template search path; names only known when rendering are not.
+    async fn load_referenced_templates(
+        &self,
+        static_file_ref: &StaticFileRef,
+        content: &[u8],
+    ) -> Result<Vec<(String, Vec<u8>)>, EmbedError> {
+        let Ok(template) = std::str::from_utf8(content) else {
+            return Ok(Vec::new());
+        };
+        let source_dir = Path::new(&static_file_ref.source_path)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+        let target_dir = Path::new(&static_file_ref.target_path)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+        let roots: Vec<&Path> = std::iter::once(source_dir)
+            .chain(self.template_search_path.iter().map(PathBuf::as_path))
+            .collect();
+
+        let mut referenced = Vec::new();
+        let mut pending = referenced_templates(template);
+        let mut seen: Vec<String> = pending.clone();
+        while let Some(name) = pending.pop() {
+            let Some(source) = roots
+                .iter()
+                .map(|root| root.join(&name))
+                .find(|path| path.is_file())
+            else {
+                tracing::warn!(
+                    "Template '{}' that '{}' refers to was not found in the template search path",
+                    name,
+                    static_file_ref.source_path
+                );
+                continue;
+            };
+            let content = self.read(&source).await?;
+            if let Ok(template) = std::str::from_utf8(&content) {
+                for name in referenced_templates(template) {
+                    if !seen.contains(&name) {
+                        seen.push(name.clone());
+                        pending.push(name);
+                    }
+                }
+            }
+            let target = target_dir.join(&name).to_string_lossy().into_owned();
+            referenced.push((target, content));
+        }
+        Ok(referenced)
+    }
 }
@@ -1,16 +1,31 @@
+use crate::compilation::profile::is_wasi_target;
+use crate::compilation::reproducible::canonical_value;
 use crate::execution::plan::ModuleSpec;
 use crate::execution::rustle_plan::{BinaryDeploymentPlan, RustlePlanOutput};
-use crate::types::compilation::OptimizationLevel;
+use crate::execution::VaultSecrets;
+use crate::modules::custom::CustomModule;
+use crate::modules::python::{payload_path, FallbackTask, PythonModules};
+use crate::runtime::shutdown::STOP_FILE_ENV;
+use crate::runtime::signing::TrustedKey;
+use crate::types::compilation::{OptimizationLevel, RunnerProfile};
 use crate::types::deployment::RuntimeConfig;
 use crate::types::platform::Platform;
 use anyhow::Result;
 use handlebars::Handlebars;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::path::PathBuf;
 use thiserror::Error;
 
-use super::{DataEmbedder, TemplateCache, TemplateOptimizer};
+use super::appended::{DataLayout, APPENDED_MAGIC, ARCHIVE_INDEX};
+use super::sections::{
+    section_path, static_file_order, static_section_path, EmbeddedSections, SectionEncoding,
+    SectionReport,
+};
+use super::tree_shaker::{
+    builtin_module, gate_handlers, is_module_dependency, ModuleUsage, BUILTIN_MODULES,
+};
+use super::{DataEmbedder, PayloadPolicy, TemplateCache, TemplateOptimizer};
 
 #[derive(Error, Debug)]
 pub enum TemplateError {
@@ -41,6 +56,10 @@ pub struct BinaryTemplateGenerator {
     embedder: DataEmbedder,
     optimizer: TemplateOptimizer,
     handlebars: Handlebars<'static>,
+    /// Modules compiled in from their source when plans use them
+    custom_modules: Vec<CustomModule>,
+    /// Ansible's Python modules, run for the modules not compiled in
+    python_modules: Option<PythonModules>,
 }
 
 #[derive(Debug, Clone)]
@@ -53,6 +72,14 @@ pub struct TemplateConfig {
     pub compress_static_files: bool,
     pub compression_algorithm: CompressionType,
     pub encrypt_secrets: bool,
+    pub runner_profile: RunnerProfile,
+    /// Whether the execution data is compiled in or appended to the runner
+    pub data_layout: DataLayout,
+    /// The key appended execution data must be signed with, if any
+    pub data_key: Option<TrustedKey>,
+    /// Whether runners run the modules they are not built with from
+    /// WebAssembly components found on their hosts
+    pub wasm_modules: bool,
 }
 
 // OptimizationLevel moved to crate::types::compilation
@@ -78,6 +105,10 @@ impl Default for TemplateConfig {
             compress_static_files: true,
             compression_algorithm: CompressionType::Zstd,
             encrypt_secrets: true,
+            runner_profile: RunnerProfile::Standard,
+            data_layout: DataLayout::Compiled,
+            data_key: None,
+            wasm_modules: false,
         }
     }
 }
@@ -88,12 +119,17 @@ pub struct GeneratedTemplate {
     pub template_id: String,
     pub source_files: HashMap<PathBuf, String>,
     pub embedded_data: EmbeddedData,
+    /// The embedded data as written into the project
+    pub sections: EmbeddedSections,
     pub cargo_toml: String,
     pub build_script: Option<String>,
     pub target_info: TargetInfo,
     pub compilation_flags: Vec<String>,
     pub estimated_binary_size: u64,
     pub cache_key: String,
+    /// Whether the runner reads [`Self::sections`] from itself or has them
+    /// appended
+    pub data_layout: DataLayout,
 }
 
 #[derive(Debug, Clone)]
@@ -116,6 +152,68 @@ pub struct TargetInfo {
     pub features: Vec<String>,
 }
 
+impl TargetInfo {
+    /// What the template of a runner for `target_triple` needs to know
+    pub fn for_target(target_triple: &str) -> Result<Self, TemplateError> {
+        let platform = if target_triple.contains("apple-darwin") {
+            Platform::MacOS
+        } else if target_triple.contains("linux") {
+            Platform::Linux
+        } else if target_triple.contains("windows") {
+            Platform::Windows
+        } else if target_triple.contains("freebsd") {
+            Platform::FreeBSD
+        } else if target_triple.contains("illumos") {
+            Platform::Illumos
+        } else if is_wasi_target(target_triple) {
+            Platform::Unknown("wasi".to_string())
+        } else {
+            return Err(TemplateError::Generation(format!(
+                "Unsupported target platform: {target_triple}"
+            )));
+        };
+
+        let architecture = if target_triple.starts_with("aarch64") {
+            "aarch64"
+        } else if target_triple.starts_with("x86_64") {
+            "x86_64"
+        } else if target_triple.starts_with("armv7") {
+            "armv7"
+        } else if target_triple.starts_with("riscv64") {
+            "riscv64"
+        } else if target_triple.starts_with("wasm32") {
+            "wasm32"
+        } else {
+            "unknown"
+        };
+
+        let os_family = if target_triple.contains("windows") {
+            "windows"
+        } else if is_wasi_target(target_triple) {
+            "wasm"
+        } else {
+            "unix"
+        };
+
+        let libc = if target_triple.contains("musl") {
+            Some("musl".to_string())
+        } else if target_triple.contains("gnu") {
+            Some("gnu".to_string())
+        } else {
+            None
+        };
+
+        Ok(Self {
+            target_triple: target_triple.to_string(),
+            platform,
+            architecture: architecture.to_string(),
+            os_family: os_family.to_string(),
+            libc,
+            features: vec![],
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct EncryptedSecrets {
     pub vault_data: HashMap<String, Vec<u8>>,
@@ -127,7 +225,14 @@ pub struct EncryptedSecrets {
 pub struct ModuleDependency {
     pub name: String,
     pub version: String,
+    #[serde(default)]
     pub features: Vec<String>,
+    #[serde(default = "default_features_enabled")]
+    pub default_features: bool,
+}
+
+fn default_features_enabled() -> bool {
+    true
 }
 
 #[derive(Debug, Clone)]
@@ -164,9 +269,51 @@ impl BinaryTemplateGenerator {
             embedder,
             optimizer,
             handlebars,
+            custom_modules: Vec::new(),
+            python_modules: None,
         })
     }
 
+    /// Decrypt vaulted static files with `secrets` before embedding them
+    pub fn with_vault(mut self, secrets: VaultSecrets) -> Self {
+        self.embedder = self.embedder.with_vault(secrets);
+        self
+    }
+
+    /// Look up the templates that embedded templates extend, include or
+    /// import in these directories when they are not next to them
+    pub fn with_template_search_path(mut self, search_path: Vec<PathBuf>) -> Self {
+        self.embedder = self.embedder.with_template_search_path(search_path);
+        self
+    }
+
+    /// Embed the local files of copy and template tasks as `policy` allows
+    pub fn with_payload_policy(mut self, policy: PayloadPolicy) -> Self {
+        self.embedder = self.embedder.with_payload_policy(policy);
+        self
+    }
+
+    /// Compile these modules into the runners of plans that use them
+    pub fn with_custom_modules(mut self, modules: Vec<CustomModule>) -> Self {
+        self.custom_modules = modules;
+        self
+    }
+
+    /// Run the modules runners are not built with by embedding Ansible's
+    /// Python modules, run with the target's Python
+    pub fn with_python_modules(mut self, modules: PythonModules) -> Self {
+        self.python_modules = Some(modules);
+        self
+    }
+
+    /// The tasks and handlers of `plan` its runner runs with Python modules
+    pub fn python_fallback_tasks(&self, plan: &RustlePlanOutput) -> Vec<FallbackTask> {
+        match &self.python_modules {
+            Some(python) => python.fallback_tasks(plan, |module| self.is_compiled(module)),
+            None => Vec::new(),
+        }
+    }
+
     /// Generate complete binary template from execution plan
     pub async fn generate_binary_template(
         &self,
@@ -174,6 +321,24 @@ impl BinaryTemplateGenerator {
         binary_deployment: &BinaryDeploymentPlan,
         target_info: &TargetInfo,
     ) -> Result<GeneratedTemplate, TemplateError> {
+        if self.config.data_layout == DataLayout::Appended
+            && self.config.runner_profile == RunnerProfile::Wasi
+        {
+            return Err(TemplateError::Generation(
+                "WASI runners cannot read data appended to their module".to_string(),
+            ));
+        }
+        if self.config.wasm_modules && self.config.runner_profile == RunnerProfile::Wasi {
+            return Err(TemplateError::Generation(
+                "WASI runners cannot load WebAssembly modules".to_string(),
+            ));
+        }
+        if self.python_modules.is_some() && self.config.runner_profile == RunnerProfile::Wasi {
+            return Err(TemplateError::Generation(
+                "WASI runners cannot run Python modules".to_string(),
+            ));
+        }
+
         let template_id = uuid::Uuid::new_v4().to_string();
         let cache_key = self.generate_cache_key(execution_plan, target_info)?;
 
@@ -182,39 +347,62 @@ impl BinaryTemplateGenerator {
             return Ok(cached_template);
         }
 
+        // Embed the local files tasks read, and have them read the copies
+        let (execution_plan, payloads) = self.embedder.bundle_local_sources(execution_plan).await?;
+        let execution_plan = &execution_plan;
+        if !payloads.payloads.is_empty() {
+            tracing::info!(
+                "Embedding {} local files ({} bytes) read by {} tasks",
+                payloads.files.len(),
+                payloads.total_size(),
+                payloads.payloads.len()
+            );
+        }
+        for excluded in &payloads.excluded {
+            tracing::info!(
+                "Not embedding excluded {}, it is read on the target",
+                excluded.display()
+            );
+        }
+
         // Embed execution data
-        let embedded_data = self
+        let mut embedded_data = self
             .embedder
             .embed_execution_data(execution_plan, binary_deployment, target_info)
             .await?;
+        embedded_data.static_files.extend(payloads.files);
+        if let Some(python) = &self.python_modules {
+            for module in self.python_fallback_modules(&plan_modules(execution_plan)) {
+                let package = python
+                    .package(&module)
+                    .map_err(|e| TemplateError::Embedding(e.to_string()))?;
+                embedded_data
+                    .static_files
+                    .insert(payload_path(&module), package);
+            }
+        }
 
-        // Generate main.rs
+        // Pack the data into sections and generate the main.rs reading them
+        let plan_json = serde_json::to_string(&canonical_value(execution_plan)?)?;
+        let sections = EmbeddedSections::pack(&plan_json, &embedded_data, self.section_encoding())
+            .map_err(|e| TemplateError::Embedding(format!("Failed to pack sections: {e}")))?;
         let main_rs = self.generate_main_rs(execution_plan, &embedded_data)?;
 
-        // Generate Cargo.toml
-        let cargo_toml = self.generate_cargo_toml(
-            &self.extract_dependencies(execution_plan),
-            &target_info.target_triple,
-        )?;
+        // Generate Cargo.toml, with only the modules the plan uses enabled
+        let mut usage = ModuleUsage::analyze(execution_plan);
+        usage.modules = self.compiled_modules(usage.modules);
+        let mut dependencies = self.extract_dependencies(&target_info.target_triple);
+        let enabled =
+            add_custom_dependencies(&mut dependencies, &self.used_custom_modules(&usage.modules));
+        let mut features: Vec<&str> = usage.features();
+        features.extend(enabled.iter().map(String::as_str));
+        let cargo_toml =
+            self.render_cargo_toml(&dependencies, &target_info.target_triple, &features)?;
 
         // Generate module implementations
-        let mut modules = std::collections::HashSet::new();
-
-        // Collect modules from regular tasks and handlers
-        for play in &execution_plan.plays {
-            for batch in &play.batches {
-                for task in &batch.tasks {
-                    modules.insert(task.module.clone());
-                }
-            }
-            // Collect modules from handlers
-            for handler in &play.handlers {
-                modules.insert(handler.module.clone());
-            }
-        }
-
         let module_files = self.generate_module_implementations(
-            &modules
+            &usage
+                .modules
                 .into_iter()
                 .map(|m| ModuleSpec {
                     name: m,
@@ -232,20 +420,55 @@ impl BinaryTemplateGenerator {
         let mut source_files = HashMap::new();
         source_files.insert(PathBuf::from("src/main.rs"), main_rs);
 
+        if self.config.runner_profile == RunnerProfile::Minimal {
+            source_files.insert(
+                PathBuf::from(".cargo/config.toml"),
+                self.generate_minimal_cargo_config(&target_info.target_triple),
+            );
+        }
+
         for (path, content) in module_files {
             source_files.insert(PathBuf::from(format!("src/{path}")), content);
         }
 
+        source_files.insert(
+            PathBuf::from("src/facts.rs"),
+            include_str!("../templates/facts.rs").to_string(),
+        );
+
+        if !self
+            .python_fallback_modules(&plan_modules(execution_plan))
+            .is_empty()
+        {
+            source_files.insert(
+                PathBuf::from("src/python_fallback.rs"),
+                include_str!("../templates/python_fallback.rs").to_string(),
+            );
+        }
+
+        if self.config.wasm_modules {
+            source_files.insert(
+                PathBuf::from("src/wasm_host.rs"),
+                include_str!("../templates/wasm_host.rs").to_string(),
+            );
+            source_files.insert(
+                PathBuf::from("wit/module.wit"),
+                include_str!("../templates/wit/module.wit").to_string(),
+            );
+        }
+
         let template = GeneratedTemplate {
             template_id,
             source_files,
             embedded_data,
+            sections,
             cargo_toml,
             build_script: None,
             target_info: target_info.clone(),
             compilation_flags: self.generate_compilation_flags(target_info),
             estimated_binary_size: self.estimate_binary_size(execution_plan),
             cache_key: cache_key.clone(),
+            data_layout: self.config.data_layout,
         };
 
         // Cache the template
@@ -267,24 +490,55 @@ impl BinaryTemplateGenerator {
         ))
     }
 
-    /// Generate Cargo.toml with dependencies and optimizations
+    /// Generate Cargo.toml with dependencies and optimizations, all builtin
+    /// modules enabled
     pub fn generate_cargo_toml(
         &self,
         dependencies: &[ModuleDependency],
         target_triple: &str,
     ) -> Result<String, TemplateError> {
+        let all_features: Vec<_> = BUILTIN_MODULES.iter().map(|m| m.feature).collect();
+        self.render_cargo_toml(dependencies, target_triple, &all_features)
+    }
+
+    fn render_cargo_toml(
+        &self,
+        dependencies: &[ModuleDependency],
+        target_triple: &str,
+        default_features: &[&str],
+    ) -> Result<String, TemplateError> {
+        let minimal = self.config.runner_profile == RunnerProfile::Minimal;
+        let dependencies: Vec<serde_json::Value> = dependencies
+            .iter()
+            .map(|dependency| {
+                serde_json::json!({
+                    "name": dependency.name,
+                    "version": dependency.version,
+                    "features": dependency.features,
+                    "default_features": dependency.default_features,
+                    "optional": is_module_dependency(&dependency.name),
+                })
+            })
+            .collect();
+        let module_features: Vec<serde_json::Value> = BUILTIN_MODULES
+            .iter()
+            .map(|m| serde_json::json!({ "name": m.feature, "dependencies": m.dependencies }))
+            .collect();
         let template_data = serde_json::json!({
             "dependencies": dependencies,
+            "default_features": default_features,
+            "module_features": module_features,
             "target_triple": target_triple,
             "optimization_level": match self.config.optimization_level {
+                _ if minimal => "\"z\"",
                 OptimizationLevel::Debug => "0",
                 OptimizationLevel::Release => "3",
                 OptimizationLevel::Aggressive => "3",
                 _ => "3", // Default to release level for other variants
             },
-            "lto": matches!(self.config.optimization_level, OptimizationLevel::Release | OptimizationLevel::Aggressive),
-            "strip": !self.config.include_debug_info,
-            "panic_abort": matches!(self.config.optimization_level, OptimizationLevel::Release | OptimizationLevel::Aggressive),
+            "lto": minimal || matches!(self.config.optimization_level, OptimizationLevel::Release | OptimizationLevel::Aggressive),
+            "strip": minimal || !self.config.include_debug_info,
+            "panic_abort": minimal || matches!(self.config.optimization_level, OptimizationLevel::Release | OptimizationLevel::Aggressive),
         });
 
         self.handlebars
@@ -298,18 +552,19 @@ impl BinaryTemplateGenerator {
         execution_plan: &RustlePlanOutput,
         embedded_data: &EmbeddedData,
     ) -> Result<String, TemplateError> {
-        // Collect unique modules from the execution plan
-        let mut modules = std::collections::HashSet::new();
-        for play in &execution_plan.plays {
-            for batch in &play.batches {
-                for task in &batch.tasks {
-                    modules.insert(task.module.clone());
-                }
-            }
-            for handler in &play.handlers {
-                modules.insert(handler.module.clone());
-            }
-        }
+        // Collect unique modules from the execution plan, in a stable order
+        let plan_modules = plan_modules(execution_plan);
+        let python_modules: Vec<serde_json::Value> = self
+            .python_fallback_modules(&plan_modules)
+            .into_iter()
+            .map(|module| {
+                serde_json::json!({
+                    "payload": payload_path(&module),
+                    "name": module,
+                })
+            })
+            .collect();
+        let modules = self.compiled_modules(plan_modules);
 
         // Convert to template data format
         let modules_data: Vec<serde_json::Value> = modules
@@ -322,13 +577,49 @@ impl BinaryTemplateGenerator {
             })
             .collect();
 
+        // Custom modules have no parameter handler, and get their
+        // parameters as the task gives them
+        let custom_modules: Vec<&str> = self
+            .used_custom_modules(&modules)
+            .iter()
+            .map(|module| module.name.as_str())
+            .collect();
+
+        let encoding = self.section_encoding();
+        let appended = self.config.data_layout == DataLayout::Appended;
+        let data_public_key = self.config.data_key.as_ref().map(|key| {
+            key.key_bytes()
+                .iter()
+                .map(|byte| byte.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        });
+        // An appended runner is built once for every plan with its modules,
+        // so nothing of the plan's data goes into its sources
+        let static_files = if appended {
+            String::new()
+        } else {
+            self.generate_static_file_declarations(&embedded_data.static_files)?
+        };
         let template_data = serde_json::json!({
-            "execution_plan": serde_json::to_string(&execution_plan)?,
-            "runtime_config": serde_json::to_string(&embedded_data.runtime_config)?,
-            "static_files": self.generate_static_file_declarations(&embedded_data.static_files)?,
+            "appended_data": appended,
+            "appended_magic": String::from_utf8_lossy(APPENDED_MAGIC),
+            "archive_index": ARCHIVE_INDEX,
+            "stop_file_env": STOP_FILE_ENV,
+            "data_public_key": data_public_key,
+            "execution_plan_section": include_path(&section_path("execution_plan", encoding)),
+            "runtime_config_section": include_path(&section_path("runtime_config", encoding)),
+            "compressed_sections": encoding == SectionEncoding::Zstd,
+            "static_files": static_files,
             "module_implementations": self.generate_module_declarations(execution_plan)?,
             "modules": modules_data,
+            "custom_modules": custom_modules,
+            "wasm_modules": self.config.wasm_modules,
+            "python_modules": python_modules,
             "total_tasks": execution_plan.total_tasks,
+            "minimal_profile": self.config.runner_profile != RunnerProfile::Standard,
+            "wasi_profile": self.config.runner_profile == RunnerProfile::Wasi,
+            "profile_name": serde_json::to_value(self.config.runner_profile)?,
         });
 
         self.handlebars
@@ -389,16 +680,42 @@ impl BinaryTemplateGenerator {
         ];
 
         for (module_path, content) in param_mapping_modules {
-            implementations.insert(format!("modules/{module_path}.rs"), content.to_string());
+            let content = match module_path {
+                "parameter_mapping/handlers/mod" | "parameter_mapping/mapper" => {
+                    gate_handlers(content)
+                }
+                _ => content.to_string(),
+            };
+            implementations.insert(format!("modules/{module_path}.rs"), content);
         }
 
-        // Generate implementations for execution plan modules
+        // Generate implementations for execution plan modules, custom ones
+        // from their sources
         for module in modules {
-            let module_code = self.generate_module_wrapper(&module.name, target_platform)?;
-            implementations.insert(
-                format!("modules/{}.rs", module.name.replace(':', "_")),
-                module_code,
-            );
+            match self.custom_module(&module.name) {
+                Some(custom) if custom.is_directory() => {
+                    let dir = format!("modules/{}", custom.name);
+                    implementations
+                        .insert(format!("{dir}/mod.rs"), custom.source.main_file.clone());
+                    for (path, content) in &custom.source.additional_files {
+                        implementations.insert(format!("{dir}/{path}"), content.clone());
+                    }
+                }
+                Some(custom) => {
+                    implementations.insert(
+                        format!("modules/{}.rs", custom.name),
+                        custom.source.main_file.clone(),
+                    );
+                }
+                None => {
+                    let module_code =
+                        self.generate_module_wrapper(&module.name, target_platform)?;
+                    implementations.insert(
+                        format!("modules/{}.rs", module.name.replace(':', "_")),
+                        module_code,
+                    );
+                }
+            }
         }
 
         // Generate modules/mod.rs file to declare all modules
@@ -470,82 +787,227 @@ impl BinaryTemplateGenerator {
         hasher.update(serde_json::to_string(execution_plan)?);
         hasher.update(&target_info.target_triple);
         hasher.update(serde_json::to_string(&self.config.optimization_level)?);
+        hasher.update(serde_json::to_string(&self.config.runner_profile)?);
+        hasher.update(serde_json::to_string(&self.config.data_layout)?);
+        hasher.update([u8::from(self.config.wasm_modules)]);
+        if let Some(python) = &self.python_modules {
+            hasher.update(format!("{python:?}"));
+            for module in self.python_fallback_modules(&plan_modules(execution_plan)) {
+                if let Some(source) = python.find(&module) {
+                    hasher.update(std::fs::read(source)?);
+                }
+            }
+        }
+        if let Some(key) = &self.config.data_key {
+            hasher.update(key.key_bytes());
+        }
+        for module in &self.custom_modules {
+            hasher.update(&module.name);
+            hasher.update(&module.source.main_file);
+            let files: std::collections::BTreeMap<_, _> =
+                module.source.additional_files.iter().collect();
+            for (path, content) in files {
+                hasher.update(path);
+                hasher.update(content);
+            }
+            hasher.update(serde_json::to_string(&module.dependencies)?);
+        }
 
         Ok(format!("{:x}", hasher.finalize()))
     }
 
-    fn extract_dependencies(&self, execution_plan: &RustlePlanOutput) -> Vec<ModuleDependency> {
+    /// The modules of `modules` compiled into the runner: all of them,
+    /// unknown ones as placeholders, unless runners run those with Python
+    /// or load them from WebAssembly components
+    fn compiled_modules(&self, modules: BTreeSet<String>) -> BTreeSet<String> {
+        modules
+            .into_iter()
+            .filter(|module| self.is_compiled(module))
+            .collect()
+    }
+
+    fn is_compiled(&self, module: &str) -> bool {
+        if builtin_module(module).is_some() || self.custom_module(module).is_some() {
+            return true;
+        }
+        let python = self
+            .python_modules
+            .as_ref()
+            .is_some_and(|python| python.find(module).is_some());
+        !python && !self.config.wasm_modules
+    }
+
+    /// The modules of `modules` run with Python
+    fn python_fallback_modules(&self, modules: &BTreeSet<String>) -> Vec<String> {
+        let Some(python) = &self.python_modules else {
+            return Vec::new();
+        };
+        modules
+            .iter()
+            .filter(|module| !self.is_compiled(module) && python.find(module).is_some())
+            .cloned()
+            .collect()
+    }
+
+    fn custom_module(&self, name: &str) -> Option<&CustomModule> {
+        self.custom_modules
+            .iter()
+            .find(|module| module.name == name)
+    }
+
+    /// The custom modules among `modules`
+    fn used_custom_modules(&self, modules: &BTreeSet<String>) -> Vec<&CustomModule> {
+        self.custom_modules
+            .iter()
+            .filter(|module| modules.contains(&module.name))
+            .collect()
+    }
+
+    fn extract_dependencies(&self, target_triple: &str) -> Vec<ModuleDependency> {
+        let tokio_features: Vec<String> = match self.config.runner_profile {
+            // Single-threaded runtime with only what the builtin modules use
+            RunnerProfile::Minimal => {
+                ["rt", "macros", "time", "fs", "process", "io-util", "signal"]
+                    .iter()
+                    .map(|f| f.to_string())
+                    .collect()
+            }
+            // What tokio supports on WASI, which has no threads, processes
+            // nor signals
+            RunnerProfile::Wasi => ["rt", "macros", "time", "io-util", "sync"]
+                .iter()
+                .map(|f| f.to_string())
+                .collect(),
+            RunnerProfile::Standard => vec!["full".to_string()],
+        };
+
         let mut deps = vec![
             ModuleDependency {
                 name: "tokio".to_string(),
                 version: "1".to_string(),
-                features: vec!["full".to_string()],
+                features: tokio_features,
+                default_features: true,
             },
             ModuleDependency {
                 name: "serde".to_string(),
                 version: "1".to_string(),
                 features: vec!["derive".to_string()],
+                default_features: true,
             },
             ModuleDependency {
                 name: "serde_json".to_string(),
                 version: "1".to_string(),
                 features: vec![],
+                default_features: true,
             },
             ModuleDependency {
                 name: "anyhow".to_string(),
                 version: "1".to_string(),
                 features: vec![],
+                default_features: true,
             },
             ModuleDependency {
                 name: "tracing".to_string(),
                 version: "0.1".to_string(),
                 features: vec![],
+                default_features: true,
             },
             ModuleDependency {
                 name: "tracing-subscriber".to_string(),
                 version: "0.3".to_string(),
                 features: vec![],
-            },
-            ModuleDependency {
-                name: "reqwest".to_string(),
-                version: "0.11".to_string(),
-                features: vec!["json".to_string()],
+                default_features: true,
             },
             ModuleDependency {
                 name: "thiserror".to_string(),
                 version: "1".to_string(),
                 features: vec![],
+                default_features: true,
             },
         ];
 
-        // Add module-specific dependencies based on what modules are used
-        let used_modules: std::collections::HashSet<String> = execution_plan
-            .plays
-            .iter()
-            .flat_map(|p| &p.batches)
-            .flat_map(|b| &b.tasks)
-            .map(|t| t.module.clone())
-            .collect();
-
-        if used_modules.contains("command") || used_modules.contains("shell") {
+        // Pure Rust zstd decoding of the sections, which cross-compiles
+        // anywhere the runner does
+        if self.section_encoding() == SectionEncoding::Zstd {
             deps.push(ModuleDependency {
-                name: "shell-words".to_string(),
-                version: "1.1".to_string(),
+                name: "ruzstd".to_string(),
+                version: "0.7".to_string(),
                 features: vec![],
+                default_features: true,
             });
         }
 
-        if used_modules.contains("package") {
+        // Reading the archive appended to the runner, and checking its
+        // signature when the data is signed
+        if self.config.data_layout == DataLayout::Appended {
             deps.push(ModuleDependency {
-                name: "regex".to_string(),
-                version: "1.10".to_string(),
+                name: "tar".to_string(),
+                version: "0.4".to_string(),
                 features: vec![],
+                default_features: false,
             });
+            if self.config.data_key.is_some() {
+                deps.push(ModuleDependency {
+                    name: "ed25519-dalek".to_string(),
+                    version: "2".to_string(),
+                    features: vec![],
+                    default_features: true,
+                });
+            }
+        }
+
+        // Loading WebAssembly components, and their host interface
+        if self.config.wasm_modules {
+            for (name, version) in [("wasmtime", "29"), ("wasmtime-wasi", "29"), ("ureq", "2")] {
+                deps.push(ModuleDependency {
+                    name: name.to_string(),
+                    version: version.to_string(),
+                    features: vec![],
+                    default_features: true,
+                });
+            }
         }
 
+        // The minimal and WASI profiles have no HTTP subsystem, so results
+        // are not reported back
+        if self.config.runner_profile == RunnerProfile::Standard {
+            // Static musl runners carry rustls instead of linking OpenSSL
+            let static_tls = target_triple.contains("-musl");
+            let mut features = vec!["json".to_string()];
+            if static_tls {
+                features.push("rustls-tls".to_string());
+            }
+            deps.push(ModuleDependency {
+                name: "reqwest".to_string(),
+                version: "0.11".to_string(),
+                features,
+                default_features: !static_tls,
+            });
+        }
+
+        // Dependencies of builtin modules, optional and enabled by the
+        // features of the modules that need them
+        deps.push(ModuleDependency {
+            name: "shell-words".to_string(),
+            version: "1.1".to_string(),
+            features: vec![],
+            default_features: true,
+        });
+        deps.push(ModuleDependency {
+            name: "regex".to_string(),
+            version: "1.10".to_string(),
+            features: vec![],
+            default_features: true,
+        });
+
         deps
     }
 
+    /// Link the C runtime statically so minimal runners have no libc dependency
+    fn generate_minimal_cargo_config(&self, target_triple: &str) -> String {
+        format!("[target.{target_triple}]\nrustflags = [\"-C\", \"target-feature=+crt-static\"]\n")
+    }
+
     fn generate_compilation_flags(&self, _target_info: &TargetInfo) -> Vec<String> {
         let mut flags = vec![];
 
@@ -582,17 +1044,28 @@ impl BinaryTemplateGenerator {
             + (unique_modules * per_module_size)
     }
 
+    /// How the sections are stored: zstd whatever the algorithm configured,
+    /// which only turns compression off
+    fn section_encoding(&self) -> SectionEncoding {
+        match self.config.compression_algorithm {
+            CompressionType::None => SectionEncoding::Raw,
+            _ => SectionEncoding::Zstd,
+        }
+    }
+
     fn generate_static_file_declarations(
         &self,
         static_files: &HashMap<String, Vec<u8>>,
     ) -> Result<String> {
-        let declarations = static_files
-            .keys()
-            .map(|path| {
+        let encoding = self.section_encoding();
+        let declarations = static_file_order(static_files)
+            .into_iter()
+            .enumerate()
+            .map(|(index, path)| {
                 format!(
-                    r#"        files.insert("{}", include_bytes!("static_files/{}"));"#,
+                    r#"        ({:?}, include_bytes!("{}")),"#,
                     path,
-                    path.replace('/', "_")
+                    include_path(&static_section_path(index, encoding))
                 )
             })
             .collect::<Vec<_>>()
@@ -602,7 +1075,7 @@ impl BinaryTemplateGenerator {
     }
 
     fn generate_module_declarations(&self, execution_plan: &RustlePlanOutput) -> Result<String> {
-        let mut modules = std::collections::HashSet::new();
+        let mut modules = std::collections::BTreeSet::new();
 
         // Collect modules from regular tasks
         for play in &execution_plan.plays {
@@ -617,7 +1090,8 @@ impl BinaryTemplateGenerator {
             }
         }
 
-        let declarations = modules
+        let declarations = self
+            .compiled_modules(modules)
             .iter()
             .map(|module| format!("    pub mod {};", module.replace(':', "_")))
             .collect::<Vec<_>>()
@@ -668,9 +1142,80 @@ pub async fn execute(args: HashMap<String, Value>) -> Result<Value> {
     }
 }
 
+/// Add the crates `modules` declare to `dependencies`, adding their
+/// features to crates the runner already depends on. Returns the default
+/// features enabling those of them only builtin modules otherwise need
+fn add_custom_dependencies(
+    dependencies: &mut Vec<ModuleDependency>,
+    modules: &[&CustomModule],
+) -> BTreeSet<String> {
+    let mut enabled = BTreeSet::new();
+    for declared in modules.iter().flat_map(|module| &module.dependencies) {
+        if is_module_dependency(&declared.name) {
+            enabled.insert(format!("dep:{}", declared.name));
+        }
+        match dependencies.iter_mut().find(|d| d.name == declared.name) {
+            Some(existing) => {
+                for feature in &declared.features {
+                    if !existing.features.contains(feature) {
+                        existing.features.push(feature.clone());
+                    }
+                }
+                existing.default_features |= declared.default_features;
+            }
+            None => dependencies.push(declared.clone()),
+        }
+    }
+    enabled
+}
+
+/// The modules of the tasks and handlers of `plan`
+fn plan_modules(plan: &RustlePlanOutput) -> BTreeSet<String> {
+    plan.plays
+        .iter()
+        .flat_map(|play| {
+            play.batches
+                .iter()
+                .flat_map(|batch| &batch.tasks)
+                .map(|task| task.module.clone())
+                .chain(play.handlers.iter().map(|handler| handler.module.clone()))
+        })
+        .collect()
+}
+
+/// `path` relative to `src/main.rs`, for `include_bytes!`
+fn include_path(path: &std::path::Path) -> String {
+    path.strip_prefix("src")
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
 // OptimizationLevel serialization is handled by the derive macro in types::compilation
 
 impl GeneratedTemplate {
+    /// How much each embedded asset adds to the runner
+    pub fn section_report(&self) -> SectionReport {
+        self.sections.report()
+    }
+
+    /// The files of the project other than `Cargo.toml`, the generated
+    /// sources followed by the sections, unless they are appended
+    pub fn project_files(&self) -> impl Iterator<Item = (&std::path::Path, &[u8])> {
+        let sections: &[_] = match self.data_layout {
+            DataLayout::Compiled => &self.sections.sections,
+            DataLayout::Appended => &[],
+        };
+        self.source_files
+            .iter()
+            .map(|(path, content)| (path.as_path(), content.as_bytes()))
+            .chain(
+                sections
+                    .iter()
+                    .map(|section| (section.path.as_path(), section.data.as_slice())),
+            )
+    }
+
     /// Calculate a hash of the template for caching and comparison
     pub fn calculate_hash(&self) -> String {
         use sha2::{Digest, Sha256};
@@ -704,6 +1249,100 @@ impl GeneratedTemplate {
         format!("{:x}", hasher.finalize())
     }
 
+    /// Hash of everything that goes into the compiled binary, and nothing
+    /// else: two plans producing the same sources, data and flags have the
+    /// same content hash, whatever their plan metadata. With the data
+    /// appended, only the sources and flags go into the runner, so every
+    /// plan with the same modules has the same hash
+    pub fn content_hash(&self) -> String {
+        use sha2::{Digest, Sha256};
+
+        fn update_sorted<V: AsRef<[u8]>>(
+            hasher: &mut Sha256,
+            entries: impl IntoIterator<Item = (String, V)>,
+        ) {
+            let mut entries: Vec<_> = entries.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            hasher.update(entries.len().to_le_bytes());
+            for (name, value) in entries {
+                hasher.update(name.as_bytes());
+                hasher.update([0]);
+                hasher.update(value.as_ref().len().to_le_bytes());
+                hasher.update(value);
+            }
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(&self.target_info.target_triple);
+        hasher.update([0]);
+        update_sorted(
+            &mut hasher,
+            self.source_files
+                .iter()
+                .map(|(path, content)| (path.to_string_lossy().into_owned(), content)),
+        );
+        update_sorted(
+            &mut hasher,
+            [
+                ("Cargo.toml".to_string(), self.cargo_toml.as_bytes()),
+                (
+                    "build.rs".to_string(),
+                    self.build_script.as_deref().unwrap_or_default().as_bytes(),
+                ),
+                (
+                    "flags".to_string(),
+                    self.compilation_flags.join("\0").as_bytes(),
+                ),
+            ],
+        );
+        if self.data_layout == DataLayout::Appended {
+            return format!("{:x}", hasher.finalize());
+        }
+
+        update_sorted(
+            &mut hasher,
+            [
+                (
+                    "plan".to_string(),
+                    self.embedded_data.execution_plan.as_bytes(),
+                ),
+                (
+                    "facts".to_string(),
+                    self.embedded_data
+                        .facts_cache
+                        .as_deref()
+                        .unwrap_or_default()
+                        .as_bytes(),
+                ),
+            ],
+        );
+        update_sorted(
+            &mut hasher,
+            self.embedded_data
+                .static_files
+                .iter()
+                .map(|(name, data)| (name.clone(), data)),
+        );
+        update_sorted(
+            &mut hasher,
+            self.embedded_data
+                .module_binaries
+                .iter()
+                .map(|(name, data)| (name.clone(), data)),
+        );
+        update_sorted(
+            &mut hasher,
+            self.embedded_data
+                .secrets
+                .vault_data
+                .iter()
+                .map(|(name, data)| (name.clone(), data)),
+        );
+        hasher.update(serde_json::to_vec(&self.embedded_data.runtime_config).unwrap_or_default());
+
+        format!("{:x}", hasher.finalize())
+    }
+
     /// Write the template files to a directory
     pub async fn write_to_directory(&self, target_dir: &std::path::Path) -> anyhow::Result<()> {
         use anyhow::Context;
@@ -720,8 +1359,8 @@ impl GeneratedTemplate {
             .await
             .context("Failed to write Cargo.toml")?;
 
-        // Write source files
-        for (file_path, content) in &self.source_files {
+        // Write source files and sections
+        for (file_path, content) in self.project_files() {
             let full_path = target_dir.join(file_path);
 
             // Create parent directory if needed
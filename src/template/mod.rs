@@ -1,11 +1,23 @@
+pub mod appended;
 pub mod cache;
 pub mod embedder;
 pub mod generator;
 pub mod optimizer;
 pub mod platform;
+pub mod sections;
+pub mod tree_shaker;
 
+pub use appended::{
+    append_archive, pack_archive, package_appended, split_appended, unpack_archive, AppendedIndex,
+    AppendedParts, AppendedSection, DataLayout, APPENDED_MAGIC,
+};
 pub use cache::*;
 pub use embedder::*;
 pub use generator::*;
 pub use optimizer::*;
 pub use platform::*;
+pub use sections::{
+    EmbeddedAssetSize, EmbeddedSection, EmbeddedSections, SectionEncoding, SectionKind,
+    SectionReport,
+};
+pub use tree_shaker::{ModuleUsage, BUILTIN_MODULES};
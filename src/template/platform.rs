@@ -352,6 +352,196 @@ mod platform {{
     }
 }
 
+/// FreeBSD-specific template generation
+pub struct FreeBSDTemplateGenerator;
+
+impl PlatformTemplateGenerator for FreeBSDTemplateGenerator {
+    fn generate_platform_specific_code(
+        &self,
+        template: &mut GeneratedTemplate,
+        target_info: &TargetInfo,
+    ) -> Result<(), PlatformError> {
+        let freebsd_code = format!(
+            r#"
+#[cfg(target_os = "freebsd")]
+mod platform {{
+    use std::process::Command;
+    use anyhow::Result;
+    
+    pub fn get_system_info() -> Result<SystemInfo> {{
+        let output = Command::new("freebsd-version")
+            .output()?;
+        
+        let version_output = String::from_utf8_lossy(&output.stdout);
+        
+        Ok(SystemInfo {{
+            version: version_output.trim().to_string(),
+            architecture: "{}".to_string(),
+            jailed: is_jailed(),
+        }})
+    }}
+    
+    pub fn setup_signal_handlers() -> Result<()> {{
+        use nix::sys::signal::{{self, Signal}};
+        
+        extern "C" fn handle_sigterm(_: i32) {{
+            std::process::exit(0);
+        }}
+        
+        unsafe {{
+            signal::signal(Signal::SIGTERM, signal::SigHandler::Handler(handle_sigterm))?;
+            signal::signal(Signal::SIGINT, signal::SigHandler::Handler(handle_sigterm))?;
+        }}
+        
+        Ok(())
+    }}
+    
+    pub fn check_permissions() -> Result<bool> {{
+        use nix::unistd::{{getuid, geteuid}};
+        
+        Ok(getuid().is_root() || geteuid().is_root())
+    }}
+    
+    /// Whether the runner runs inside a jail, where some services and
+    /// kernel settings cannot be managed
+    pub fn is_jailed() -> bool {{
+        Command::new("sysctl")
+            .args(&["-n", "security.jail.jailed"])
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim() == "1")
+            .unwrap_or(false)
+    }}
+    
+    #[derive(Debug, Clone)]
+    pub struct SystemInfo {{
+        pub version: String,
+        pub architecture: String,
+        pub jailed: bool,
+    }}
+}}
+"#,
+            target_info.architecture
+        );
+
+        template.source_files.insert(
+            std::path::PathBuf::from("src/platform/freebsd.rs"),
+            freebsd_code,
+        );
+
+        Ok(())
+    }
+
+    fn add_platform_dependencies(&self, dependencies: &mut Vec<String>) {
+        dependencies.push("nix = \"0.27\"".to_string());
+        dependencies.push("libc = \"0.2\"".to_string());
+    }
+
+    fn get_compilation_flags(&self, _target_info: &TargetInfo) -> Vec<String> {
+        vec![]
+    }
+
+    fn get_runtime_features(&self) -> Vec<String> {
+        vec![
+            "unix_socket".to_string(),
+            "signal_handling".to_string(),
+            "process_control".to_string(),
+        ]
+    }
+}
+
+/// illumos-specific template generation
+pub struct IllumosTemplateGenerator;
+
+impl PlatformTemplateGenerator for IllumosTemplateGenerator {
+    fn generate_platform_specific_code(
+        &self,
+        template: &mut GeneratedTemplate,
+        target_info: &TargetInfo,
+    ) -> Result<(), PlatformError> {
+        let illumos_code = format!(
+            r#"
+#[cfg(target_os = "illumos")]
+mod platform {{
+    use std::process::Command;
+    use anyhow::Result;
+    
+    pub fn get_system_info() -> Result<SystemInfo> {{
+        let output = Command::new("uname")
+            .args(&["-v"])
+            .output()?;
+        
+        let version_output = String::from_utf8_lossy(&output.stdout);
+        let zone = Command::new("zonename")
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .unwrap_or_else(|_| "global".to_string());
+        
+        Ok(SystemInfo {{
+            version: version_output.trim().to_string(),
+            architecture: "{}".to_string(),
+            zone,
+        }})
+    }}
+    
+    pub fn setup_signal_handlers() -> Result<()> {{
+        use nix::sys::signal::{{self, Signal}};
+        
+        extern "C" fn handle_sigterm(_: i32) {{
+            std::process::exit(0);
+        }}
+        
+        unsafe {{
+            signal::signal(Signal::SIGTERM, signal::SigHandler::Handler(handle_sigterm))?;
+            signal::signal(Signal::SIGINT, signal::SigHandler::Handler(handle_sigterm))?;
+        }}
+        
+        Ok(())
+    }}
+    
+    pub fn check_permissions() -> Result<bool> {{
+        use nix::unistd::{{getuid, geteuid}};
+        
+        Ok(getuid().is_root() || geteuid().is_root())
+    }}
+    
+    #[derive(Debug, Clone)]
+    pub struct SystemInfo {{
+        pub version: String,
+        pub architecture: String,
+        /// The zone the runner runs in, `global` outside of zones
+        pub zone: String,
+    }}
+}}
+"#,
+            target_info.architecture
+        );
+
+        template.source_files.insert(
+            std::path::PathBuf::from("src/platform/illumos.rs"),
+            illumos_code,
+        );
+
+        Ok(())
+    }
+
+    fn add_platform_dependencies(&self, dependencies: &mut Vec<String>) {
+        dependencies.push("nix = \"0.27\"".to_string());
+        dependencies.push("libc = \"0.2\"".to_string());
+    }
+
+    fn get_compilation_flags(&self, _target_info: &TargetInfo) -> Vec<String> {
+        vec![]
+    }
+
+    fn get_runtime_features(&self) -> Vec<String> {
+        vec![
+            "unix_socket".to_string(),
+            "signal_handling".to_string(),
+            "process_control".to_string(),
+        ]
+    }
+}
+
 /// Platform template generator factory
 pub struct PlatformTemplateGeneratorFactory;
 
@@ -363,12 +553,20 @@ impl PlatformTemplateGeneratorFactory {
             Platform::Linux => Ok(Box::new(LinuxTemplateGenerator)),
             Platform::MacOS => Ok(Box::new(MacOSTemplateGenerator)),
             Platform::Windows => Ok(Box::new(WindowsTemplateGenerator)),
+            Platform::FreeBSD => Ok(Box::new(FreeBSDTemplateGenerator)),
+            Platform::Illumos => Ok(Box::new(IllumosTemplateGenerator)),
             _ => Err(PlatformError::UnsupportedPlatform(format!("{platform:?}"))),
         }
     }
 
     pub fn get_supported_platforms() -> Vec<Platform> {
-        vec![Platform::Linux, Platform::MacOS, Platform::Windows]
+        vec![
+            Platform::Linux,
+            Platform::MacOS,
+            Platform::Windows,
+            Platform::FreeBSD,
+            Platform::Illumos,
+        ]
     }
 }
 
@@ -411,6 +609,8 @@ pub fn enhance_template_for_platform(
             Platform::Linux => "mod platform { pub use super::platform::linux::*; }",
             Platform::MacOS => "mod platform { pub use super::platform::macos::*; }",
             Platform::Windows => "mod platform { pub use super::platform::windows::*; }",
+            Platform::FreeBSD => "mod platform { pub use super::platform::freebsd::*; }",
+            Platform::Illumos => "mod platform { pub use super::platform::illumos::*; }",
             _ => "",
         };
 
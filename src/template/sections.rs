@@ -0,0 +1,241 @@
+//! Embedded data sections
+//!
+//! The execution plan, runtime configuration, static files and templates a
+//! runner carries are not pasted into `main.rs` as string literals, which
+//! rustc has to lex, hold and codegen as source and which makes large plans
+//! slow and memory hungry to build. Each is packed into a zstd-compressed
+//! file under `src/sections/`, pulled in with `include_bytes!`, and decoded
+//! by the runner the first time it is needed, static files one at a time.
+
+use super::{EmbeddedData, PAYLOAD_PREFIX};
+use crate::compilation::reproducible::canonical_value;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Where sections are written, relative to the project
+pub const SECTIONS_DIR: &str = "src/sections";
+/// Compression level of the sections, a balance of size and build time
+pub const SECTION_COMPRESSION_LEVEL: i32 = 9;
+
+/// How the sections are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SectionEncoding {
+    Zstd,
+    /// Stored as is, when compression is turned off
+    Raw,
+}
+
+impl SectionEncoding {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Zstd => "zst",
+            Self::Raw => "bin",
+        }
+    }
+
+    fn encode(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Zstd => zstd::bulk::compress(data, SECTION_COMPRESSION_LEVEL),
+            Self::Raw => Ok(data.to_vec()),
+        }
+    }
+}
+
+/// What a section holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SectionKind {
+    ExecutionPlan,
+    RuntimeConfig,
+    StaticFile,
+    /// A static file rendered on the host, or one it includes
+    Template,
+    /// A local file a task reads
+    Payload,
+}
+
+impl fmt::Display for SectionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ExecutionPlan => write!(f, "execution plan"),
+            Self::RuntimeConfig => write!(f, "runtime config"),
+            Self::StaticFile => write!(f, "static file"),
+            Self::Template => write!(f, "template"),
+            Self::Payload => write!(f, "payload"),
+        }
+    }
+}
+
+/// One embedded asset as written into the project
+#[derive(Debug, Clone)]
+pub struct EmbeddedSection {
+    /// The asset's name, the target path for static files
+    pub name: String,
+    pub kind: SectionKind,
+    /// Relative to the project
+    pub path: PathBuf,
+    /// The encoded contents
+    pub data: Vec<u8>,
+    pub original_size: u64,
+}
+
+/// The sections of a runner
+#[derive(Debug, Clone)]
+pub struct EmbeddedSections {
+    pub encoding: SectionEncoding,
+    pub sections: Vec<EmbeddedSection>,
+}
+
+impl Default for EmbeddedSections {
+    fn default() -> Self {
+        Self {
+            encoding: SectionEncoding::Zstd,
+            sections: Vec::new(),
+        }
+    }
+}
+
+impl EmbeddedSections {
+    /// Encode `execution_plan`, the plan as the runner reads it, and
+    /// everything else `data` embeds
+    pub fn pack(
+        execution_plan: &str,
+        data: &EmbeddedData,
+        encoding: SectionEncoding,
+    ) -> std::io::Result<Self> {
+        let runtime_config = serde_json::to_vec(&canonical_value(&data.runtime_config)?)?;
+        let mut assets = vec![
+            (
+                "execution_plan".to_string(),
+                SectionKind::ExecutionPlan,
+                section_path("execution_plan", encoding),
+                execution_plan.as_bytes(),
+            ),
+            (
+                "runtime_config".to_string(),
+                SectionKind::RuntimeConfig,
+                section_path("runtime_config", encoding),
+                runtime_config.as_slice(),
+            ),
+        ];
+        for (index, path) in static_file_order(&data.static_files)
+            .into_iter()
+            .enumerate()
+        {
+            assets.push((
+                path.to_string(),
+                static_file_kind(path),
+                static_section_path(index, encoding),
+                data.static_files[path].as_slice(),
+            ));
+        }
+
+        let sections = assets
+            .into_iter()
+            .map(|(name, kind, path, contents)| {
+                Ok(EmbeddedSection {
+                    name,
+                    kind,
+                    path,
+                    data: encoding.encode(contents)?,
+                    original_size: contents.len() as u64,
+                })
+            })
+            .collect::<std::io::Result<_>>()?;
+        Ok(Self { encoding, sections })
+    }
+
+    pub fn report(&self) -> SectionReport {
+        SectionReport {
+            encoding: self.encoding,
+            assets: self
+                .sections
+                .iter()
+                .map(|section| EmbeddedAssetSize {
+                    name: section.name.clone(),
+                    kind: section.kind,
+                    original_size: section.original_size,
+                    embedded_size: section.data.len() as u64,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The size of one embedded asset before and after encoding
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddedAssetSize {
+    pub name: String,
+    pub kind: SectionKind,
+    pub original_size: u64,
+    pub embedded_size: u64,
+}
+
+/// How much each embedded asset adds to a runner
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionReport {
+    pub encoding: SectionEncoding,
+    pub assets: Vec<EmbeddedAssetSize>,
+}
+
+impl SectionReport {
+    pub fn original_size(&self) -> u64 {
+        self.assets.iter().map(|asset| asset.original_size).sum()
+    }
+
+    pub fn embedded_size(&self) -> u64 {
+        self.assets.iter().map(|asset| asset.embedded_size).sum()
+    }
+}
+
+impl fmt::Display for SectionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for asset in &self.assets {
+            writeln!(
+                f,
+                "{:>10} -> {:>10} bytes  {} ({})",
+                asset.original_size, asset.embedded_size, asset.name, asset.kind
+            )?;
+        }
+        write!(
+            f,
+            "{:>10} -> {:>10} bytes  total",
+            self.original_size(),
+            self.embedded_size()
+        )
+    }
+}
+
+/// The target paths of `static_files` in the order their sections are
+/// numbered
+pub fn static_file_order(static_files: &HashMap<String, Vec<u8>>) -> Vec<&str> {
+    let mut paths: Vec<_> = static_files.keys().map(String::as_str).collect();
+    paths.sort_unstable();
+    paths
+}
+
+/// The section of the `index`th static file, relative to the project
+pub fn static_section_path(index: usize, encoding: SectionEncoding) -> PathBuf {
+    section_path(&format!("static_{index:04}"), encoding)
+}
+
+/// The section of `name`, relative to the project
+pub fn section_path(name: &str, encoding: SectionEncoding) -> PathBuf {
+    PathBuf::from(SECTIONS_DIR).join(format!("{name}.{}", encoding.extension()))
+}
+
+fn static_file_kind(path: &str) -> SectionKind {
+    if path.starts_with(PAYLOAD_PREFIX) {
+        SectionKind::Payload
+    } else if [".j2", ".jinja", ".jinja2"]
+        .iter()
+        .any(|extension| path.ends_with(extension))
+    {
+        SectionKind::Template
+    } else {
+        SectionKind::StaticFile
+    }
+}
@@ -0,0 +1,155 @@
+//! Module tree-shaking for generated runners
+//!
+//! Every builtin module of the runner, with its parameter handler and the
+//! crates it needs, sits behind a cargo feature of the generated project.
+//! [`ModuleUsage`] finds the modules a plan runs, tasks and handlers alike,
+//! and only their features are enabled by default, so a plan that copies
+//! files does not build or embed the package and service modules.
+
+use crate::execution::rustle_plan::RustlePlanOutput;
+use std::collections::BTreeSet;
+
+/// A builtin module of the runner and what it pulls in
+#[derive(Debug, Clone, Copy)]
+pub struct BuiltinModule {
+    /// Cargo feature of the generated project
+    pub feature: &'static str,
+    /// Module names, short, that run it
+    pub names: &'static [&'static str],
+    /// File of its parameter handler, under `parameter_mapping/handlers`
+    pub handler_module: &'static str,
+    pub handler: &'static str,
+    /// Optional dependencies of the generated project it needs
+    pub dependencies: &'static [&'static str],
+}
+
+pub const BUILTIN_MODULES: &[BuiltinModule] = &[
+    BuiltinModule {
+        feature: "module-command",
+        names: &["command", "shell"],
+        handler_module: "command",
+        handler: "CommandParameterHandler",
+        dependencies: &["shell-words"],
+    },
+    BuiltinModule {
+        feature: "module-copy",
+        names: &["copy"],
+        handler_module: "copy",
+        handler: "CopyParameterHandler",
+        dependencies: &[],
+    },
+    BuiltinModule {
+        feature: "module-debug",
+        names: &["debug"],
+        handler_module: "debug",
+        handler: "DebugParameterHandler",
+        dependencies: &[],
+    },
+    BuiltinModule {
+        feature: "module-file",
+        names: &["file"],
+        handler_module: "file",
+        handler: "FileParameterHandler",
+        dependencies: &[],
+    },
+    BuiltinModule {
+        feature: "module-package",
+        names: &["package", "apt", "yum", "dnf", "zypper"],
+        handler_module: "package",
+        handler: "PackageParameterHandler",
+        dependencies: &["regex"],
+    },
+    BuiltinModule {
+        feature: "module-service",
+        names: &["service", "systemd"],
+        handler_module: "service",
+        handler: "ServiceParameterHandler",
+        dependencies: &[],
+    },
+    BuiltinModule {
+        feature: "module-wait-for",
+        names: &["wait_for"],
+        handler_module: "wait_for",
+        handler: "WaitForHandler",
+        dependencies: &[],
+    },
+];
+
+/// The builtin module running `name`, short or fully qualified
+pub fn builtin_module(name: &str) -> Option<&'static BuiltinModule> {
+    let short = name.rsplit('.').next().unwrap_or(name);
+    BUILTIN_MODULES.iter().find(|m| m.names.contains(&short))
+}
+
+/// The modules a plan runs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleUsage {
+    /// Module names as the plan gives them
+    pub modules: BTreeSet<String>,
+}
+
+impl ModuleUsage {
+    pub fn analyze(execution_plan: &RustlePlanOutput) -> Self {
+        let mut modules = BTreeSet::new();
+        for play in &execution_plan.plays {
+            for batch in &play.batches {
+                for task in &batch.tasks {
+                    modules.insert(task.module.clone());
+                }
+            }
+            for handler in &play.handlers {
+                modules.insert(handler.module.clone());
+            }
+        }
+        Self { modules }
+    }
+
+    /// The builtin modules used, in [`BUILTIN_MODULES`] order
+    pub fn builtin_modules(&self) -> Vec<&'static BuiltinModule> {
+        BUILTIN_MODULES
+            .iter()
+            .filter(|builtin| {
+                self.modules
+                    .iter()
+                    .any(|name| builtin_module(name).is_some_and(|m| m.feature == builtin.feature))
+            })
+            .collect()
+    }
+
+    /// Cargo features the runner is built with
+    pub fn features(&self) -> Vec<&'static str> {
+        self.builtin_modules().iter().map(|m| m.feature).collect()
+    }
+}
+
+/// Whether a dependency of the runner is only needed by some builtin module
+pub fn is_module_dependency(dependency: &str) -> bool {
+    BUILTIN_MODULES
+        .iter()
+        .any(|m| m.dependencies.contains(&dependency))
+}
+
+/// `source`, the handlers `mod.rs` or the mapper, with each handler's
+/// declarations and registrations behind its module's feature
+pub fn gate_handlers(source: &str) -> String {
+    let mut gated = String::with_capacity(source.len());
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+        let module = BUILTIN_MODULES.iter().find(|m| {
+            trimmed == format!("pub mod {};", m.handler_module)
+                || trimmed.starts_with(&format!("pub use {}::", m.handler_module))
+                || (trimmed.starts_with("handlers.insert(")
+                    && trimmed.contains(&format!("Box::new({})", m.handler)))
+        });
+        if let Some(module) = module {
+            let indent = &line[..line.len() - trimmed.len()];
+            gated.push_str(&format!(
+                "{indent}#[cfg(feature = \"{}\")]\n",
+                module.feature
+            ));
+        }
+        gated.push_str(line);
+        gated.push('\n');
+    }
+    gated
+}
@@ -0,0 +1,198 @@
+//! Facts gathered on their own, for `rustle-deploy facts`
+//!
+//! Run as `rustle-runner --gather-facts[=network,hardware]`, the runner
+//! executes none of its plan: it reports the facts of the subsets named,
+//! besides the minimal ones, in one `FactsCollected` event. The subsets are
+//! Ansible's `gather_subset` names: `min`, `hardware`, `network`, `virtual`,
+//! `env` and `all`.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Argument the runner gathers facts with instead of running its plan
+pub const GATHER_FACTS_ARG: &str = "--gather-facts";
+
+/// The subsets to gather, when the runner was asked to gather facts
+pub fn requested() -> Option<Vec<String>> {
+    std::env::args().find_map(|arg| {
+        let subsets = arg.strip_prefix(GATHER_FACTS_ARG)?;
+        if subsets.is_empty() {
+            return Some(Vec::new());
+        }
+        let subsets = subsets.strip_prefix('=')?;
+        Some(
+            subsets
+                .split(',')
+                .map(|subset| subset.trim().to_string())
+                .filter(|subset| !subset.is_empty())
+                .collect(),
+        )
+    })
+}
+
+/// The minimal facts and those of `subsets`
+pub fn gather(subsets: &[String]) -> HashMap<String, Value> {
+    let wants = |subset: &str| subsets.iter().any(|s| s == subset || s == "all");
+    let mut facts = crate::basic_facts();
+    minimal(&mut facts);
+    if wants("hardware") {
+        hardware(&mut facts);
+    }
+    if wants("network") {
+        network(&mut facts);
+    }
+    if wants("virtual") {
+        virtualization(&mut facts);
+    }
+    if wants("env") {
+        let env: HashMap<String, String> = std::env::vars().collect();
+        facts.insert("ansible_env".to_string(), json!(env));
+    }
+    facts
+}
+
+fn read(path: &str) -> Option<String> {
+    std::fs::read_to_string(path).ok()
+}
+
+/// Values of `key=value` lines, unquoted
+fn key_values(content: &str) -> HashMap<&str, &str> {
+    content
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim().trim_matches('"')))
+        .collect()
+}
+
+fn minimal(facts: &mut HashMap<String, Value>) {
+    if let Some(release) = read("/proc/sys/kernel/osrelease") {
+        facts.insert("ansible_kernel".to_string(), json!(release.trim()));
+    }
+    if let Some(os_release) = read("/etc/os-release") {
+        let fields = key_values(&os_release);
+        if let Some(&name) = fields.get("NAME") {
+            let distribution = name.split_whitespace().next().unwrap_or(name);
+            facts.insert("ansible_distribution".to_string(), json!(distribution));
+        }
+        if let Some(version) = fields.get("VERSION_ID") {
+            facts.insert("ansible_distribution_version".to_string(), json!(version));
+        }
+        if let Some(codename) = fields.get("VERSION_CODENAME") {
+            facts.insert("ansible_distribution_release".to_string(), json!(codename));
+        }
+        let family = fields.get("ID_LIKE").or(fields.get("ID")).map(|&id| {
+            match id.split_whitespace().next().unwrap_or(id) {
+                "debian" | "ubuntu" => "Debian",
+                "rhel" | "fedora" | "centos" => "RedHat",
+                "suse" | "opensuse" => "Suse",
+                "arch" => "Archlinux",
+                "alpine" => "Alpine",
+                other => other,
+            }
+        });
+        if let Some(family) = family {
+            facts.insert("ansible_os_family".to_string(), json!(family));
+        }
+    }
+    if let Ok(user) = std::env::var("USER").or_else(|_| std::env::var("USERNAME")) {
+        facts.insert("ansible_user_id".to_string(), json!(user));
+    }
+}
+
+fn hardware(facts: &mut HashMap<String, Value>) {
+    if let Ok(vcpus) = std::thread::available_parallelism() {
+        facts.insert("ansible_processor_vcpus".to_string(), json!(vcpus.get()));
+    }
+    if let Some(cpuinfo) = read("/proc/cpuinfo") {
+        let processors: Vec<&str> = cpuinfo
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .filter(|(key, _)| key.trim() == "model name")
+            .map(|(_, value)| value.trim())
+            .collect();
+        facts.insert("ansible_processor".to_string(), json!(processors));
+    }
+    if let Some(meminfo) = read("/proc/meminfo") {
+        for line in meminfo.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let fact = match key {
+                "MemTotal" => "ansible_memtotal_mb",
+                "MemFree" => "ansible_memfree_mb",
+                "SwapTotal" => "ansible_swaptotal_mb",
+                "SwapFree" => "ansible_swapfree_mb",
+                _ => continue,
+            };
+            let kb: u64 = value
+                .split_whitespace()
+                .next()
+                .and_then(|kb| kb.parse().ok())
+                .unwrap_or(0);
+            facts.insert(fact.to_string(), json!(kb / 1024));
+        }
+    }
+}
+
+fn network(facts: &mut HashMap<String, Value>) {
+    if let Ok(entries) = std::fs::read_dir("/sys/class/net") {
+        let mut interfaces: Vec<String> = entries
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        interfaces.sort();
+        facts.insert("ansible_interfaces".to_string(), json!(interfaces));
+    }
+    // The default route's interface and gateway, little-endian hex
+    let default_route = read("/proc/net/route").and_then(|routes| {
+        routes.lines().skip(1).find_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 3 || fields[1] != "00000000" {
+                return None;
+            }
+            let gateway = u32::from_str_radix(fields[2], 16).ok()?;
+            let gateway = std::net::Ipv4Addr::from(gateway.to_le_bytes());
+            Some((fields[0].to_string(), gateway.to_string()))
+        })
+    });
+    if let Some((interface, gateway)) = default_route {
+        facts.insert(
+            "ansible_default_ipv4".to_string(),
+            json!({ "interface": interface, "gateway": gateway }),
+        );
+    }
+    if let Some(fqdn) = read("/proc/sys/kernel/hostname") {
+        let domain = read("/proc/sys/kernel/domainname")
+            .map(|domain| domain.trim().to_string())
+            .filter(|domain| !domain.is_empty() && domain != "(none)");
+        let fqdn = match domain {
+            Some(domain) => format!("{}.{domain}", fqdn.trim()),
+            None => fqdn.trim().to_string(),
+        };
+        facts.insert("ansible_fqdn".to_string(), json!(fqdn));
+    }
+}
+
+fn virtualization(facts: &mut HashMap<String, Value>) {
+    let cgroup = read("/proc/1/cgroup").unwrap_or_default();
+    let product = read("/sys/class/dmi/id/product_name").unwrap_or_default();
+    let (virtualization_type, role) =
+        if std::path::Path::new("/.dockerenv").exists() || cgroup.contains("docker") {
+            ("docker", "guest")
+        } else if std::env::var("container").is_ok() || cgroup.contains("lxc") {
+            ("container", "guest")
+        } else if product.contains("KVM") || product.contains("QEMU") {
+            ("kvm", "guest")
+        } else if product.contains("VMware") {
+            ("VMware", "guest")
+        } else if product.contains("VirtualBox") {
+            ("virtualbox", "guest")
+        } else {
+            ("NA", "NA")
+        };
+    facts.insert(
+        "ansible_virtualization_type".to_string(),
+        json!(virtualization_type),
+    );
+    facts.insert("ansible_virtualization_role".to_string(), json!(role));
+}
@@ -9,15 +9,24 @@ pub async fn execute(args: HashMap<String, Value>) -> Result<Value> {
         .and_then(|v| v.as_str())
         .ok_or_else(|| anyhow::anyhow!("Missing 'cmd' or 'command' parameter"))?;
 
-    let output = if cfg!(target_os = "windows") {
-        Command::new("cmd")
-            .args(&["/C", cmd])
-            .output()?
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.args(&["/C", cmd]);
+        command
     } else {
-        Command::new("sh")
-            .args(&["-c", cmd])
-            .output()?
+        let mut command = Command::new("sh");
+        command.args(&["-c", cmd]);
+        command
     };
+    if let Some(environment) = args
+        .get(super::parameter_mapping::ENVIRONMENT_PARAM)
+        .and_then(|v| v.as_object())
+    {
+        for (name, value) in environment {
+            command.env(name, value.as_str().unwrap_or_default());
+        }
+    }
+    let output = command.output()?;
 
     let stdout = String::from_utf8_lossy(&output.stdout).to_string();
     let stderr = String::from_utf8_lossy(&output.stderr).to_string();
@@ -36,8 +36,14 @@ pub async fn execute(args: HashMap<String, Value>) -> Result<Value> {
         backup_file = Some(backup_path);
     }
 
-    // Copy the file
-    fs::copy(src_path, dest_path)?;
+    // Copy the file, from the runner itself when it was embedded
+    if let Some(key) = src.strip_prefix("embedded:") {
+        let data = crate::embedded_data::static_file(key)
+            .ok_or_else(|| anyhow::anyhow!("'{}' is not embedded in this runner", key))?;
+        fs::write(dest_path, data)?;
+    } else {
+        fs::copy(src_path, dest_path)?;
+    }
 
     // Set permissions if specified (Unix only)
     #[cfg(unix)]
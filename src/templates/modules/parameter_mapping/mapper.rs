@@ -2,16 +2,22 @@ use serde_json::Value;
 use std::collections::HashMap;
 use tracing::debug;
 
-use super::{
-    handlers::{
-        CommandParameterHandler, CopyParameterHandler, DebugParameterHandler, FileParameterHandler,
-        PackageParameterHandler, ServiceParameterHandler, WaitForHandler,
-    },
-    ModuleParameterHandler, ParameterError,
-};
+// Handlers are glob-imported: generated runners only build those of the
+// modules their plan uses
+use super::{handlers::*, ModuleParameterHandler, ParameterError};
+
+/// Parameter the environment of a task reaches modules in, the way Ansible
+/// passes its internal `_ansible_*` parameters
+pub const ENVIRONMENT_PARAM: &str = "_ansible_environment";
+
+/// `module_defaults`: default parameters by module name, short or fully
+/// qualified
+pub type ModuleDefaults = HashMap<String, HashMap<String, Value>>;
 
 pub struct ParameterMapper {
     module_handlers: HashMap<String, Box<dyn ModuleParameterHandler>>,
+    /// Modules without a handler, which get their parameters unmapped
+    passthrough: Vec<String>,
 }
 
 impl ParameterMapper {
@@ -38,9 +44,17 @@ impl ParameterMapper {
 
         Self {
             module_handlers: handlers,
+            passthrough: Vec::new(),
         }
     }
 
+    /// Pass the parameters of `modules` through as they are
+    pub fn with_passthrough(mut self, modules: &[&str]) -> Self {
+        self.passthrough
+            .extend(modules.iter().map(|module| module.to_string()));
+        self
+    }
+
     pub fn map_for_module(
         &self,
         module_name: &str,
@@ -51,6 +65,10 @@ impl ParameterMapper {
             module_name, params
         );
 
+        if self.passthrough.iter().any(|module| module == module_name) {
+            return Ok(params);
+        }
+
         let handler = self.module_handlers.get(module_name).ok_or_else(|| {
             ParameterError::UnknownParameter {
                 param: format!("module: {module_name}"),
@@ -63,6 +81,53 @@ impl ParameterMapper {
         debug!("Mapped parameters: {:?}", mapped);
         Ok(mapped)
     }
+
+    /// Map the parameters of a task, completed with the `module_defaults` of
+    /// `scopes`, from the play to the task itself. Defaults of later scopes
+    /// win, and the parameters the task gives win over all of them.
+    pub fn map_for_task(
+        &self,
+        module_name: &str,
+        params: HashMap<String, Value>,
+        scopes: &[&ModuleDefaults],
+        environment: &HashMap<String, String>,
+    ) -> Result<HashMap<String, Value>, ParameterError> {
+        let mut resolved = HashMap::new();
+        for defaults in scopes {
+            // Within a scope, defaults for the exact name win over the others
+            let mut matching: Vec<_> = defaults
+                .iter()
+                .filter(|(name, _)| {
+                    name.as_str() == module_name
+                        || short_name(name) == module_name
+                        || name.as_str() == short_name(module_name)
+                })
+                .collect();
+            matching.sort_by_key(|(name, _)| name.as_str() == module_name);
+            for (_, values) in matching {
+                resolved.extend(values.clone());
+            }
+        }
+        resolved.extend(params);
+
+        let mut mapped = self.map_for_module(module_name, resolved)?;
+        if !environment.is_empty() {
+            mapped.insert(
+                ENVIRONMENT_PARAM.to_string(),
+                Value::Object(
+                    environment
+                        .iter()
+                        .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+                        .collect(),
+                ),
+            );
+        }
+        Ok(mapped)
+    }
+}
+
+fn short_name(name: &str) -> &str {
+    name.rsplit('.').next().unwrap_or(name)
 }
 
 impl Default for ParameterMapper {
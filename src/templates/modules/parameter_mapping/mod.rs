@@ -6,7 +6,7 @@ pub mod handlers;
 pub mod mapper;
 
 pub use error::ParameterError;
-pub use mapper::ParameterMapper;
+pub use mapper::{ModuleDefaults, ParameterMapper, ENVIRONMENT_PARAM};
 
 pub trait ModuleParameterHandler {
     /// Map Ansible-style parameters to module-expected parameters
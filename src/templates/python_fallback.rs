@@ -0,0 +1,124 @@
+//! Ansible Python modules the runner was not built with, run with the
+//! host's Python
+//!
+//! Each is embedded as the zip Ansible would send, run as
+//! `python3 <module>.zip` with `{"ANSIBLE_MODULE_ARGS": ...}` on its
+//! standard input. The interpreter is RUSTLE_PYTHON, or else the first of
+//! `python3` and `python` found.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+
+/// Python interpreter modules are run with
+pub const PYTHON_ENV: &str = "RUSTLE_PYTHON";
+
+/// Embedded package of `module`, if it runs with Python
+pub fn payload(module: &str) -> Option<&'static str> {
+    crate::PYTHON_MODULES
+        .iter()
+        .find(|(name, _)| *name == module)
+        .map(|(_, payload)| *payload)
+}
+
+async fn interpreter() -> Result<String> {
+    if let Ok(python) = std::env::var(PYTHON_ENV) {
+        return Ok(python);
+    }
+    for python in ["python3", "python"] {
+        let found = tokio::process::Command::new(python)
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .is_ok_and(|status| status.success());
+        if found {
+            return Ok(python.to_string());
+        }
+    }
+    anyhow::bail!("No Python interpreter found to run Ansible modules with; set {PYTHON_ENV}")
+}
+
+/// Run the Python `module`, packaged in `payload`, with the task's `args`
+pub async fn execute(
+    module: &str,
+    payload: &str,
+    mut args: HashMap<String, Value>,
+) -> Result<Value> {
+    let package = crate::embedded_data::static_file(payload)
+        .with_context(|| format!("The package of module {module} is not embedded"))?;
+    let path = std::env::temp_dir().join(format!(
+        "rustle-{}-{}.zip",
+        module.replace('.', "_"),
+        std::process::id()
+    ));
+    tokio::fs::write(&path, package)
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    // The task's environment is the module's, not one of its arguments
+    let environment: HashMap<String, String> = args
+        .remove(crate::modules::parameter_mapping::ENVIRONMENT_PARAM)
+        .and_then(|environment| serde_json::from_value(environment).ok())
+        .unwrap_or_default();
+    args.insert("_ansible_module_name".to_string(), Value::from(module));
+    let input = serde_json::to_vec(&serde_json::json!({ "ANSIBLE_MODULE_ARGS": args }))?;
+
+    let run = async {
+        let mut child = tokio::process::Command::new(interpreter().await?)
+            .arg(&path)
+            .envs(&environment)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run module {module}"))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&input).await?;
+        }
+        child.wait_with_output().await.map_err(anyhow::Error::from)
+    };
+    let output = run.await;
+    let _ = tokio::fs::remove_file(&path).await;
+    let output = output?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut result = module_result(&stdout).with_context(|| {
+        format!(
+            "Module {module} printed no result: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    })?;
+    if let Value::Object(fields) = &mut result {
+        if !output.status.success() {
+            fields.entry("failed").or_insert(Value::Bool(true));
+        }
+        if !output.stderr.is_empty() {
+            fields
+                .entry("module_stderr")
+                .or_insert_with(|| String::from_utf8_lossy(&output.stderr).into());
+        }
+        fields.insert("python_fallback".to_string(), Value::Bool(true));
+    }
+    Ok(result)
+}
+
+/// The JSON result among what a module printed, as Ansible finds it
+fn module_result(stdout: &str) -> Result<Value> {
+    let lines: Vec<&str> = stdout.lines().collect();
+    let start = lines
+        .iter()
+        .position(|line| line.trim_start().starts_with('{'))
+        .context("no JSON object")?;
+    let end = lines
+        .iter()
+        .rposition(|line| line.trim_end().ends_with('}'))
+        .filter(|end| *end >= start)
+        .context("no JSON object")?;
+    let result: Value = serde_json::from_str(&lines[start..=end].join("\n"))?;
+    anyhow::ensure!(result.is_object(), "not a JSON object");
+    Ok(result)
+}
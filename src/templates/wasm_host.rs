@@ -0,0 +1,242 @@
+//! Modules loaded at run time from WebAssembly components
+//!
+//! A task whose module is not compiled into the runner runs
+//! `<module>.wasm`, looked up in the directories RUSTLE_WASM_MODULE_PATH
+//! lists, or else in `modules` next to the runner. Components implement the
+//! `rustle-module` world of `wit/module.wit`, and see nothing of the host
+//! but the `host` interface, within the capabilities `<module>.json` next
+//! to the component grants them:
+//!
+//! ```json
+//! {"read": ["/etc/app"], "write": ["/var/lib/app"], "exec": ["systemctl"],
+//!  "http": ["https://api.example.com/"]}
+//! ```
+//!
+//! A component without one may do nothing but compute and log.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Component as PathComponent, Path, PathBuf};
+use std::sync::OnceLock;
+use wasmtime::component::{Component, Linker, ResourceTable};
+use wasmtime::{Config, Engine, Store};
+use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiView};
+
+wasmtime::component::bindgen!({
+    world: "rustle-module",
+    path: "wit/module.wit",
+});
+
+use rustle::module::host::{Host, Output};
+
+/// Directories components are looked up in
+pub const MODULE_PATH_ENV: &str = "RUSTLE_WASM_MODULE_PATH";
+
+/// What a component may do through the host interface
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Capabilities {
+    /// Files, or directories of files, it may read
+    pub read: Vec<PathBuf>,
+    /// Files, or directories of files, it may write
+    pub write: Vec<PathBuf>,
+    /// Programs it may run, as it names them
+    pub exec: Vec<String>,
+    /// URLs it may fetch, and those under them
+    pub http: Vec<String>,
+}
+
+struct HostState {
+    module: String,
+    capabilities: Capabilities,
+    wasi: WasiCtx,
+    table: ResourceTable,
+}
+
+impl WasiView for HostState {
+    fn table(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+
+    fn ctx(&mut self) -> &mut WasiCtx {
+        &mut self.wasi
+    }
+}
+
+impl HostState {
+    fn denied(&self, what: &str) -> String {
+        format!("module {} may not {what}", self.module)
+    }
+}
+
+impl Host for HostState {
+    fn read_file(&mut self, path: String) -> Result<Vec<u8>, String> {
+        let path = Path::new(&path);
+        if !granted(&self.capabilities.read, path) {
+            return Err(self.denied(&format!("read {}", path.display())));
+        }
+        std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    fn write_file(&mut self, path: String, contents: Vec<u8>) -> Result<(), String> {
+        let path = Path::new(&path);
+        if !granted(&self.capabilities.write, path) {
+            return Err(self.denied(&format!("write {}", path.display())));
+        }
+        std::fs::write(path, contents).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    fn run(&mut self, program: String, args: Vec<String>) -> Result<Output, String> {
+        if !self.capabilities.exec.contains(&program) {
+            return Err(self.denied(&format!("run {program}")));
+        }
+        let output = std::process::Command::new(&program)
+            .args(&args)
+            .output()
+            .map_err(|e| format!("{program}: {e}"))?;
+        Ok(Output {
+            status: output.status.code().unwrap_or(-1),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+
+    fn http_get(&mut self, url: String) -> Result<String, String> {
+        let allowed = self.capabilities.http.iter().any(|grant| {
+            url == *grant
+                || url.strip_prefix(grant.as_str()).is_some_and(|rest| {
+                    grant.ends_with('/') || rest.starts_with('/') || rest.starts_with('?')
+                })
+        });
+        if !allowed {
+            return Err(self.denied(&format!("fetch {url}")));
+        }
+        ureq::get(&url)
+            .call()
+            .map_err(|e| format!("{url}: {e}"))?
+            .into_string()
+            .map_err(|e| format!("{url}: {e}"))
+    }
+
+    fn log(&mut self, message: String) {
+        tracing::info!("[{}] {}", self.module, message);
+    }
+}
+
+/// Whether `path`, absolute and without `..`, is one of `grants` or under
+/// one of them, symbolic links resolved
+fn granted(grants: &[PathBuf], path: &Path) -> bool {
+    if !path.is_absolute()
+        || path
+            .components()
+            .any(|component| component == PathComponent::ParentDir)
+    {
+        return false;
+    }
+    // What a path names once its links are resolved: the file itself when
+    // it exists, its directory when it is to be created
+    let resolved = std::fs::canonicalize(path).ok().or_else(|| {
+        let parent = std::fs::canonicalize(path.parent()?).ok()?;
+        Some(parent.join(path.file_name()?))
+    });
+    let Some(resolved) = resolved else {
+        return false;
+    };
+    grants.iter().any(|grant| {
+        let grant = std::fs::canonicalize(grant).unwrap_or_else(|_| grant.clone());
+        resolved.starts_with(grant)
+    })
+}
+
+fn module_dirs() -> Vec<PathBuf> {
+    if let Some(paths) = std::env::var_os(MODULE_PATH_ENV) {
+        return std::env::split_paths(&paths).collect();
+    }
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join("modules")))
+        .into_iter()
+        .collect()
+}
+
+/// The component running `module`, if there is one
+pub fn find(module: &str) -> Option<PathBuf> {
+    let plain = !module.is_empty()
+        && module
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        && !module.starts_with('.');
+    if !plain {
+        return None;
+    }
+    module_dirs()
+        .into_iter()
+        .map(|dir| dir.join(format!("{module}.wasm")))
+        .find(|path| path.is_file())
+}
+
+fn engine() -> Result<&'static Engine> {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    if let Some(engine) = ENGINE.get() {
+        return Ok(engine);
+    }
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    let engine = Engine::new(&config).context("Failed to start the WebAssembly engine")?;
+    Ok(ENGINE.get_or_init(|| engine))
+}
+
+/// Run the `component` of `module` with the task's `args`
+pub async fn execute(
+    module: &str,
+    component: PathBuf,
+    args: HashMap<String, Value>,
+) -> Result<Value> {
+    let module = module.to_string();
+    tokio::task::spawn_blocking(move || execute_blocking(&module, &component, args))
+        .await
+        .context("WebAssembly module panicked")?
+}
+
+fn execute_blocking(
+    module: &str,
+    path: &Path,
+    args: HashMap<String, Value>,
+) -> Result<Value> {
+    let grants = path.with_extension("json");
+    let capabilities: Capabilities = if grants.is_file() {
+        let content = std::fs::read_to_string(&grants)
+            .with_context(|| format!("Failed to read {}", grants.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Invalid capabilities in {}", grants.display()))?
+    } else {
+        Capabilities::default()
+    };
+
+    let engine = engine()?;
+    let component = Component::from_file(engine, path)
+        .with_context(|| format!("Failed to load the component {}", path.display()))?;
+    let mut linker = Linker::new(engine);
+    // WASI without preopened directories, network or environment, which
+    // components built from Rust need to start
+    wasmtime_wasi::add_to_linker_sync(&mut linker)?;
+    RustleModule::add_to_linker(&mut linker, |state: &mut HostState| state)?;
+
+    let mut store = Store::new(
+        engine,
+        HostState {
+            module: module.to_string(),
+            capabilities,
+            wasi: WasiCtxBuilder::new().inherit_stderr().build(),
+            table: ResourceTable::new(),
+        },
+    );
+    let instance = RustleModule::instantiate(&mut store, &component, &linker)
+        .with_context(|| format!("Failed to instantiate {}", path.display()))?;
+    let result = instance
+        .call_execute(&mut store, &serde_json::to_string(&args)?)
+        .with_context(|| format!("Module {module} trapped"))?
+        .map_err(|e| anyhow::anyhow!("Module {module} failed: {e}"))?;
+    serde_json::from_str(&result).with_context(|| format!("Module {module} returned invalid JSON"))
+}
@@ -41,6 +41,25 @@ pub enum OptimizationLevel {
     Aggressive,
 }
 
+/// Runtime profile the generated runner binary is built with.
+///
+/// The `Minimal` profile is experimental: it runs on a single-threaded tokio
+/// runtime, drops the HTTP, archive and git subsystems and is tuned for tiny
+/// static binaries on embedded/IoT hosts. The `Wasi` profile is the only
+/// one of `wasm32-wasi` runners, which run sandboxed under wasmtime and can
+/// neither fork nor exec.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunnerProfile {
+    /// Full-featured runner on a multi-threaded runtime
+    #[default]
+    Standard,
+    /// Single-threaded, size-optimized runner without network subsystems
+    Minimal,
+    /// WebAssembly runner limited to file and template modules
+    Wasi,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompiledBinary {
     pub compilation_id: String,
@@ -62,9 +81,17 @@ pub struct BinarySourceInfo {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BinarySourceType {
-    Cache { cache_path: PathBuf },
-    FreshCompilation { project_path: PathBuf },
+    Cache {
+        cache_path: PathBuf,
+    },
+    FreshCompilation {
+        project_path: PathBuf,
+    },
     InMemory,
+    /// A reused runner with the execution data appended
+    Appended {
+        runner_checksum: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -110,6 +137,8 @@ pub enum Platform {
     Linux,
     MacOS,
     Windows,
+    FreeBSD,
+    Illumos,
     Unknown,
 }
 
@@ -120,6 +149,7 @@ pub enum Architecture {
     Aarch64,
     X86,
     Arm,
+    Riscv64,
     Unknown,
 }
 
@@ -249,6 +279,9 @@ pub struct DeploymentConfig {
     pub cache_dir: PathBuf,
     pub output_dir: PathBuf,
     pub parallel_jobs: usize,
+    /// Maximum number of hosts deployed to concurrently
+    pub forks: usize,
+    /// Per-host deployment timeout; 0 disables the limit
     pub default_timeout_secs: u64,
     pub verify_deployments: bool,
     pub compression: bool,
@@ -366,6 +399,10 @@ impl Platform {
             Platform::MacOS
         } else if cfg!(target_os = "windows") {
             Platform::Windows
+        } else if cfg!(target_os = "freebsd") {
+            Platform::FreeBSD
+        } else if cfg!(target_os = "illumos") {
+            Platform::Illumos
         } else {
             Platform::Unknown
         }
@@ -379,6 +416,10 @@ impl Platform {
             Platform::MacOS
         } else if triple.contains("windows") || triple.contains("pc-windows") {
             Platform::Windows
+        } else if triple.contains("freebsd") {
+            Platform::FreeBSD
+        } else if triple.contains("illumos") || triple.contains("solaris") {
+            Platform::Illumos
         } else {
             Platform::Unknown
         }
@@ -391,6 +432,8 @@ impl std::fmt::Display for Platform {
             Platform::Linux => write!(f, "linux"),
             Platform::MacOS => write!(f, "macos"),
             Platform::Windows => write!(f, "windows"),
+            Platform::FreeBSD => write!(f, "freebsd"),
+            Platform::Illumos => write!(f, "illumos"),
             Platform::Unknown => write!(f, "unknown"),
         }
     }
@@ -408,6 +451,8 @@ impl Architecture {
             Architecture::X86
         } else if triple.starts_with("arm") {
             Architecture::Arm
+        } else if triple.starts_with("riscv64") {
+            Architecture::Riscv64
         } else {
             Architecture::Unknown
         }
@@ -421,6 +466,7 @@ impl std::fmt::Display for Architecture {
             Architecture::Aarch64 => write!(f, "aarch64"),
             Architecture::X86 => write!(f, "x86"),
             Architecture::Arm => write!(f, "arm"),
+            Architecture::Riscv64 => write!(f, "riscv64"),
             Architecture::Unknown => write!(f, "unknown"),
         }
     }
@@ -1,6 +1,8 @@
+use crate::execution::{ExecutionStrategy, HostHooks, MaintenanceWindow, Task};
 use crate::types::compilation::BinaryCompilation;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::time::Duration;
 
 /// Main deployment configuration
@@ -11,6 +13,30 @@ pub struct DeploymentPlan {
     pub deployment_targets: Vec<DeploymentTarget>,
     pub deployment_strategy: DeploymentStrategy,
     pub rollback_info: Option<RollbackInfo>,
+    #[serde(default)]
+    pub schedule: HostSchedule,
+    /// Tasks with `delegate_to` or `run_once`, which runners hand back to
+    /// the controller
+    #[serde(default)]
+    pub delegated_tasks: Vec<Task>,
+    /// Whether the hosts execute each task together or at their own pace
+    #[serde(default)]
+    pub strategy: ExecutionStrategy,
+}
+
+/// Maintenance windows and hooks from the execution plan, with the
+/// inventory groups of every target to match them against
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HostSchedule {
+    #[serde(default)]
+    pub maintenance_windows: Vec<MaintenanceWindow>,
+    #[serde(default)]
+    pub wait_for_window: bool,
+    #[serde(default)]
+    pub hooks: HostHooks,
+    /// Inventory groups of each target host, including `all`
+    #[serde(default)]
+    pub host_groups: HashMap<String, Vec<String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +57,96 @@ pub struct DeploymentTarget {
     pub status: DeploymentStatus,
     pub deployed_at: Option<DateTime<Utc>>,
     pub version: String,
+    /// Connection overrides from inventory variables
+    #[serde(default)]
+    pub connection: HostConnectionVars,
+}
+
+/// Per-host connection settings taken from inventory variables.
+///
+/// These take precedence over `~/.ssh/config` but not over a `user@host:port`
+/// written explicitly in the target host.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostConnectionVars {
+    /// `ansible_host`: address to connect to instead of the target host name
+    pub host: Option<String>,
+    /// `ansible_port`
+    pub port: Option<u16>,
+    /// `ansible_user`
+    pub user: Option<String>,
+    /// `ansible_ssh_private_key_file`
+    pub private_key_file: Option<String>,
+    /// `ansible_password`; never written out with the rest of the target
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    /// `ansible_ssh_common_args`: OpenSSH options, of which `-J`, `ProxyJump`
+    /// and `ProxyCommand` are honoured
+    pub ssh_common_args: Option<String>,
+    /// `ansible_winrm_transport`: `ntlm`, `kerberos` or `basic`
+    pub winrm_transport: Option<String>,
+    /// `ansible_winrm_scheme`: `https` or `http`
+    pub winrm_scheme: Option<String>,
+    /// `ansible_winrm_server_cert_validation`: `validate` or `ignore`
+    pub winrm_server_cert_validation: Option<String>,
+}
+
+impl HostConnectionVars {
+    /// Read the standard Ansible connection variables, including the legacy
+    /// `ansible_ssh_*` spellings
+    pub fn from_variables(variables: &HashMap<String, serde_json::Value>) -> Self {
+        let string_var = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| variables.get(*name))
+                .and_then(|value| match value {
+                    serde_json::Value::String(s) => Some(s.clone()),
+                    serde_json::Value::Number(n) => Some(n.to_string()),
+                    _ => None,
+                })
+        };
+
+        Self {
+            host: string_var(&["ansible_host", "ansible_ssh_host"]),
+            port: string_var(&["ansible_port", "ansible_ssh_port"])
+                .and_then(|port| port.parse().ok()),
+            user: string_var(&["ansible_user", "ansible_ssh_user"]),
+            private_key_file: string_var(&[
+                "ansible_ssh_private_key_file",
+                "ansible_private_key_file",
+            ]),
+            password: string_var(&[
+                "ansible_password",
+                "ansible_winrm_password",
+                "ansible_winrm_pass",
+                "ansible_ssh_pass",
+            ]),
+            ssh_common_args: string_var(&["ansible_ssh_common_args"]),
+            winrm_transport: string_var(&["ansible_winrm_transport"]),
+            winrm_scheme: string_var(&["ansible_winrm_scheme"]),
+            winrm_server_cert_validation: string_var(&["ansible_winrm_server_cert_validation"]),
+        }
+    }
+
+    /// Fill every unset field from `fallback`
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            host: self.host.or(fallback.host),
+            port: self.port.or(fallback.port),
+            user: self.user.or(fallback.user),
+            private_key_file: self.private_key_file.or(fallback.private_key_file),
+            password: self.password.or(fallback.password),
+            ssh_common_args: self.ssh_common_args.or(fallback.ssh_common_args),
+            winrm_transport: self.winrm_transport.or(fallback.winrm_transport),
+            winrm_scheme: self.winrm_scheme.or(fallback.winrm_scheme),
+            winrm_server_cert_validation: self
+                .winrm_server_cert_validation
+                .or(fallback.winrm_server_cert_validation),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,7 +154,33 @@ pub enum DeploymentMethod {
     Ssh,
     Scp,
     Rsync,
-    Custom { command: String },
+    /// Windows Remote Management; used for `ansible_connection: winrm` hosts
+    WinRm,
+    /// Runs on the controller itself; used for `ansible_connection: local` hosts
+    Local,
+    /// Runs inside a running Docker container
+    Docker {
+        container: String,
+    },
+    /// Runs inside a running Podman container
+    Podman {
+        container: String,
+    },
+    /// Runs inside a container of a Kubernetes pod, through `kubectl exec`
+    Kubectl {
+        pod: String,
+        namespace: Option<String>,
+        container: Option<String>,
+        context: Option<String>,
+        kubeconfig: Option<String>,
+    },
+    /// Runs inside a chroot directory on the controller
+    Chroot {
+        root: String,
+    },
+    Custom {
+        command: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,16 +190,62 @@ pub enum DeploymentStatus {
     Compiled,
     Deploying,
     Deployed,
-    Failed { error: String },
+    Failed {
+        error: String,
+    },
     Verified,
+    /// Deployed, then reverted because the deployment as a whole failed
+    RolledBack,
+    /// Not attempted, because an earlier batch failed or another host failed
+    /// a play with `any_errors_fatal`
+    Skipped {
+        reason: String,
+    },
+    /// Stopped part way, because another host failed a play with
+    /// `any_errors_fatal`
+    Aborted {
+        reason: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DeploymentStrategy {
     Parallel,
-    Rolling { batch_size: u32 },
+    /// Execute on `batch_size` hosts at a time, stopping once more than
+    /// `max_fail_percentage` of a batch failed
+    Rolling {
+        batch_size: u32,
+        #[serde(default)]
+        max_fail_percentage: Option<u32>,
+    },
     BlueGreen,
-    CanaryDeployment { percentage: u32 },
+    CanaryDeployment {
+        percentage: u32,
+    },
+}
+
+impl DeploymentStrategy {
+    /// Index ranges of the targets executed together, in order
+    pub fn batches(&self, targets: usize) -> Vec<std::ops::Range<usize>> {
+        let size = match self {
+            DeploymentStrategy::Rolling { batch_size, .. } => (*batch_size as usize).max(1),
+            _ => targets.max(1),
+        };
+        (0..targets)
+            .step_by(size)
+            .map(|start| start..(start + size).min(targets))
+            .collect()
+    }
+
+    pub fn max_fail_percentage(&self) -> Option<u32> {
+        match self {
+            DeploymentStrategy::Rolling {
+                max_fail_percentage,
+                ..
+            } => *max_fail_percentage,
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -71,7 +71,10 @@ pub enum ConnectionMethod {
     Ssh,
     WinRm,
     Local,
+    Docker,
     Podman,
+    Kubectl,
+    Chroot,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -91,6 +94,50 @@ pub struct HostInfo {
     pub kernel_version: String,
     pub target_triple: String,
     pub capabilities: Vec<String>,
+    /// What a connection preflight found, when one was run
+    pub reachability: Option<HostReachability>,
+}
+
+/// Whether a host could be reached and logged into, and how long a command
+/// takes to come back from it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostReachability {
+    pub reachable: bool,
+    pub authenticated: bool,
+    /// Round trip of a no-op command once logged in
+    #[serde(with = "serde_duration_ms_opt")]
+    pub latency: Option<Duration>,
+    pub error: Option<String>,
+    /// What the host runs, probed once logged in
+    #[serde(default)]
+    pub platform: Option<crate::binary::platform::HostPlatform>,
+}
+
+impl HostReachability {
+    /// Whether tasks can be run on the host at all
+    pub fn is_usable(&self) -> bool {
+        self.reachable && self.authenticated
+    }
+}
+
+mod serde_duration_ms_opt {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        duration.map(|d| d.as_millis() as u64).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let millis = Option::<u64>::deserialize(deserializer)?;
+        Ok(millis.map(Duration::from_millis))
+    }
 }
 
 mod serde_duration_opt {
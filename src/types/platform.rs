@@ -4,5 +4,6 @@ pub enum Platform {
     MacOS,
     Windows,
     FreeBSD,
+    Illumos,
     Unknown(String),
 }
@@ -0,0 +1,149 @@
+mod helpers;
+
+use helpers::TaskBuilder;
+use rustle_deploy::execution::ExecutionPlan;
+use rustle_deploy::runtime::{
+    push_plan, Agent, AgentConfig, AgentError, BinarySigner, RuntimeConfig, SignedPlan,
+};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+fn debug_plan(plan_id: &str) -> ExecutionPlan {
+    let hello = TaskBuilder::new(
+        "hello",
+        "debug",
+        serde_json::json!({ "msg": "pulled plan" }),
+    )
+    .set("name", "Say hello".into())
+    .build();
+    helpers::plan(plan_id, serde_json::json!({ "tasks": [hello] }))
+}
+
+fn agent_runtime_config(signer: &BinarySigner, agent: AgentConfig) -> RuntimeConfig {
+    RuntimeConfig {
+        signing_public_key: Some(signer.public_key().encoded()),
+        agent: Some(agent),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_listening_agent_runs_only_signed_plans() {
+    let signer = BinarySigner::from_seed(&[7; 32]);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let mut agent =
+        Agent::new(agent_runtime_config(&signer, AgentConfig::listen(&address))).unwrap();
+    tokio::spawn(async move { agent.serve(listener).await });
+
+    let signed = SignedPlan::sign(&debug_plan("pushed"), &signer).unwrap();
+    let result = push_plan(&address, &signed).await.unwrap();
+    assert!(result.success);
+    assert_eq!(result.summary.total_tasks, 1);
+
+    let mut tampered = signed.clone();
+    tampered.plan = tampered.plan.replace("pulled plan", "tampered plan");
+    assert!(matches!(
+        push_plan(&address, &tampered).await,
+        Err(AgentError::Rejected { .. })
+    ));
+
+    let other = BinarySigner::from_seed(&[8; 32]);
+    let foreign = SignedPlan::sign(&debug_plan("foreign"), &other).unwrap();
+    assert!(matches!(
+        push_plan(&address, &foreign).await,
+        Err(AgentError::Rejected { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_polling_agent_pulls_each_plan_once_and_reports() {
+    let signer = BinarySigner::from_seed(&[7; 32]);
+    let signed = SignedPlan::sign(&debug_plan("pulled"), &signer).unwrap();
+    let body = serde_json::to_string(&signed).unwrap();
+    let etag = format!("\"{}\"", signed.id());
+
+    // Serve the plan, answering 304 once the agent sends its etag, and
+    // record reported results
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let reports: Arc<Mutex<Vec<(String, serde_json::Value)>>> = Arc::default();
+    let received = reports.clone();
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                return;
+            };
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).await.unwrap();
+
+            let mut content_length = 0;
+            let mut if_none_match = None;
+            let mut host = String::new();
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).await.unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                let (name, value) = header.split_once(':').unwrap();
+                match name.to_ascii_lowercase().as_str() {
+                    "content-length" => content_length = value.trim().parse().unwrap(),
+                    "if-none-match" => if_none_match = Some(value.trim().to_string()),
+                    "x-rustle-host" => host = value.trim().to_string(),
+                    _ => {}
+                }
+            }
+            let mut request_body = vec![0; content_length];
+            reader.read_exact(&mut request_body).await.unwrap();
+
+            let response = if request_line.starts_with("POST") {
+                received
+                    .lock()
+                    .unwrap()
+                    .push((host, serde_json::from_slice(&request_body).unwrap()));
+                "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n".to_string()
+            } else if if_none_match.as_deref() == Some(etag.as_str()) {
+                "HTTP/1.1 304 Not Modified\r\ncontent-length: 0\r\n\r\n".to_string()
+            } else {
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            };
+            let stream = reader.get_mut();
+            stream.write_all(response.as_bytes()).await.unwrap();
+            stream.shutdown().await.unwrap();
+        }
+    });
+
+    let mut config = AgentConfig::poll(format!("{base_url}/plans/web1"));
+    config.report_url = Some(format!("{base_url}/results"));
+    config.host_id = Some("web1".to_string());
+    let mut agent = Agent::new(agent_runtime_config(&signer, config)).unwrap();
+
+    let result = agent.poll_once().await.unwrap().expect("plan should run");
+    assert!(result.success);
+    assert!(agent.poll_once().await.unwrap().is_none());
+
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].0, "web1");
+    assert_eq!(reports[0].1["execution_id"], result.execution_id.as_str());
+}
+
+#[test]
+fn test_agent_requires_a_signing_key() {
+    let config = RuntimeConfig {
+        agent: Some(AgentConfig::listen("127.0.0.1:0")),
+        ..Default::default()
+    };
+    assert!(matches!(
+        Agent::new(config),
+        Err(AgentError::Configuration { .. })
+    ));
+}
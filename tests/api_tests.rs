@@ -0,0 +1,149 @@
+use chrono::Utc;
+use rustle_deploy::api::{
+    parse_plan, runner_output_path, ApiError, CompileOutcome, CompiledRunner, Deployment,
+};
+use rustle_deploy::deploy::{CompilerVersions, DeploymentManifest};
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::execution::VaultSecrets;
+use rustle_deploy::template::TargetInfo;
+use rustle_deploy::types::deployment::DeploymentMethod;
+use rustle_deploy::types::platform::Platform;
+use std::path::{Path, PathBuf};
+
+const PLAN: &str = "tests/fixtures/execution_plans/file_operations_plan.json";
+
+fn load_plan() -> RustlePlanOutput {
+    let content = std::fs::read_to_string(PLAN).expect("Failed to read test fixture");
+    parse_plan(&content, &VaultSecrets::default()).expect("Failed to parse plan")
+}
+
+fn compiled(hosts: &[&str]) -> CompileOutcome {
+    CompileOutcome {
+        runners: vec![CompiledRunner {
+            target_triple: "x86_64-unknown-linux-gnu".to_string(),
+            path: PathBuf::from("out/rustle-runner"),
+            size: 1024,
+            checksum: "0".repeat(64),
+            hosts: hosts.iter().map(|host| host.to_string()).collect(),
+        }],
+        manifest: DeploymentManifest::new("test", CompilerVersions::default()),
+        manifest_path: PathBuf::from("out/rustle-manifest.json"),
+    }
+}
+
+#[tokio::test]
+async fn test_load_plan() {
+    let deployment = Deployment::load(PLAN, &VaultSecrets::default())
+        .await
+        .unwrap();
+    assert_eq!(deployment.plan().hosts, vec!["localhost"]);
+
+    let result = parse_plan("{ not json", &VaultSecrets::default());
+    assert!(matches!(result, Err(ApiError::InvalidPlan { .. })));
+}
+
+#[test]
+fn test_select_targets_of_plan() {
+    let targets = Deployment::new(load_plan()).select_targets().unwrap();
+    assert_eq!(targets.len(), 1);
+    assert!(targets[0].spec.target_triple.starts_with("x86_64"));
+    assert!(targets[0].spec.target_triple.contains("linux"));
+    assert_eq!(targets[0].deployment.target_hosts, vec!["localhost"]);
+
+    let result = Deployment::new(load_plan())
+        .with_targets(vec!["aarch64-apple-darwin".to_string()])
+        .select_targets();
+    assert!(matches!(result, Err(ApiError::TargetSelection { .. })));
+}
+
+#[test]
+fn test_select_given_targets_without_deployments() {
+    let mut plan = load_plan();
+    plan.binary_deployments.clear();
+    let targets = Deployment::new(plan)
+        .with_targets(vec!["aarch64-unknown-linux-gnu".to_string()])
+        .select_targets()
+        .unwrap();
+    assert_eq!(targets.len(), 1);
+    assert_eq!(targets[0].spec.target_triple, "aarch64-unknown-linux-gnu");
+    assert_eq!(targets[0].deployment.target_hosts, vec!["localhost"]);
+}
+
+#[tokio::test]
+async fn test_cancelled_before_compiling() {
+    let output = tempfile::TempDir::new().unwrap();
+    let deployment = Deployment::new(load_plan()).with_output_dir(output.path());
+    deployment.cancellation_token().cancel();
+
+    assert!(matches!(
+        deployment.compile().await,
+        Err(ApiError::Cancelled)
+    ));
+    assert!(matches!(
+        deployment.deploy(&compiled(&["localhost"])).await,
+        Err(ApiError::Cancelled)
+    ));
+}
+
+#[tokio::test]
+async fn test_deployment_plan_of_runners() {
+    let mut plan = load_plan();
+    plan.hosts = vec!["localhost".to_string(), "web1".to_string()];
+    let deployment = Deployment::new(plan);
+    let compiled = compiled(&["localhost", "web1", "web1"]);
+
+    let deployment_plan = deployment.deployment_plan(&compiled).await.unwrap();
+    assert_eq!(deployment_plan.binary_compilations.len(), 1);
+    let compilation = &deployment_plan.binary_compilations[0];
+    assert_eq!(compilation.binary_name, "rustle-runner");
+    assert_eq!(compilation.output_path, Path::new("out/rustle-runner"));
+
+    let targets = &deployment_plan.deployment_targets;
+    assert_eq!(targets.len(), 2);
+    assert!(matches!(
+        targets[0].deployment_method,
+        DeploymentMethod::Local
+    ));
+    assert!(matches!(
+        targets[1].deployment_method,
+        DeploymentMethod::Ssh
+    ));
+    assert_eq!(targets[1].host, "web1");
+    assert_eq!(targets[1].target_path, "/tmp/rustle-runner");
+    assert!(targets
+        .iter()
+        .all(|target| target.binary_compilation_id == compilation.compilation_id));
+    assert!(deployment_plan.metadata.created_at <= Utc::now());
+}
+
+#[test]
+fn test_runner_output_paths() {
+    let output = Path::new("out");
+    assert_eq!(
+        runner_output_path(output, "x86_64-unknown-linux-gnu", true),
+        Path::new("out/rustle-runner")
+    );
+    assert_eq!(
+        runner_output_path(output, "x86_64-unknown-linux-gnu", false),
+        Path::new("out/x86_64-unknown-linux-gnu/rustle-runner")
+    );
+    assert_eq!(
+        runner_output_path(output, "wasm32-wasip1", true),
+        Path::new("out/rustle-runner.wasm")
+    );
+}
+
+#[test]
+fn test_target_info_for_target() {
+    let info = TargetInfo::for_target("aarch64-unknown-linux-musl").unwrap();
+    assert_eq!(info.platform, Platform::Linux);
+    assert_eq!(info.architecture, "aarch64");
+    assert_eq!(info.os_family, "unix");
+    assert_eq!(info.libc.as_deref(), Some("musl"));
+
+    let info = TargetInfo::for_target("x86_64-pc-windows-msvc").unwrap();
+    assert_eq!(info.platform, Platform::Windows);
+    assert_eq!(info.os_family, "windows");
+
+    assert!(TargetInfo::for_target("sparc-unknown-none").is_err());
+}
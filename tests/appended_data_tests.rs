@@ -0,0 +1,185 @@
+use chrono::Utc;
+use rustle_deploy::compilation::output::BinaryOutputManager;
+use rustle_deploy::compilation::CompilationCache;
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::runtime::BinarySigner;
+use rustle_deploy::template::{
+    package_appended, split_appended, BinaryTemplateGenerator, DataLayout, GeneratedTemplate,
+    TargetInfo, TemplateConfig,
+};
+use rustle_deploy::types::compilation::{
+    BinarySourceInfo, BinarySourceType, BuildMetadata, CompiledBinary, OptimizationLevel,
+    RunnerProfile,
+};
+use rustle_deploy::types::platform::Platform;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use tempfile::TempDir;
+
+const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+fn load_plan() -> RustlePlanOutput {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    serde_json::from_str(&content).unwrap()
+}
+
+fn target_info() -> TargetInfo {
+    TargetInfo {
+        target_triple: TARGET.to_string(),
+        platform: Platform::Linux,
+        architecture: "x86_64".to_string(),
+        os_family: "unix".to_string(),
+        libc: Some("gnu".to_string()),
+        features: vec![],
+    }
+}
+
+async fn generate(plan: &RustlePlanOutput, config: TemplateConfig) -> GeneratedTemplate {
+    BinaryTemplateGenerator::new(config)
+        .unwrap()
+        .generate_binary_template(plan, &plan.binary_deployments[0], &target_info())
+        .await
+        .expect("Failed to generate template")
+}
+
+fn appended_config() -> TemplateConfig {
+    TemplateConfig {
+        data_layout: DataLayout::Appended,
+        ..Default::default()
+    }
+}
+
+fn appended_binary(binary_data: Vec<u8>, runner_checksum: String) -> CompiledBinary {
+    CompiledBinary {
+        compilation_id: "test-binary-id".to_string(),
+        target_triple: TARGET.to_string(),
+        checksum: format!("{:x}", Sha256::digest(&binary_data)),
+        size: binary_data.len() as u64,
+        binary_data,
+        compilation_time: std::time::Duration::from_secs(1),
+        optimization_level: OptimizationLevel::Release,
+        source_info: BinarySourceInfo {
+            source_type: BinarySourceType::Appended { runner_checksum },
+            template_hash: "test-template-hash".to_string(),
+            build_metadata: BuildMetadata {
+                created_at: Utc::now(),
+                toolchain_version: "rustc 1.70.0".to_string(),
+                features: vec![],
+            },
+        },
+    }
+}
+
+#[tokio::test]
+async fn test_plan_changes_keep_the_runner() {
+    let plan = load_plan();
+    let template = generate(&plan, appended_config()).await;
+
+    let mut changed = plan.clone();
+    changed.metadata.rustle_plan_version = "2.0.0".to_string();
+    changed.plays[0].batches[0].tasks[0].name = "Renamed task".to_string();
+    let changed = generate(&changed, appended_config()).await;
+    assert_ne!(
+        template.embedded_data.execution_plan,
+        changed.embedded_data.execution_plan
+    );
+    assert_eq!(template.content_hash(), changed.content_hash());
+
+    let compiled = generate(&plan, TemplateConfig::default()).await;
+    assert_ne!(template.content_hash(), compiled.content_hash());
+}
+
+#[tokio::test]
+async fn test_appended_runner_reads_itself() {
+    let template = generate(&load_plan(), appended_config()).await;
+
+    assert!(template
+        .project_files()
+        .all(|(path, _)| !path.starts_with("sections")));
+    let main_rs = &template.source_files[Path::new("src/main.rs")];
+    assert!(main_rs.contains("std::env::current_exe()"));
+    assert!(main_rs.contains(r#"const APPENDED_MAGIC: &[u8] = b"RSTLDAT1";"#));
+    assert!(!main_rs.contains("include_bytes!"));
+    assert!(!main_rs.contains("DATA_PUBLIC_KEY"));
+    assert!(template.cargo_toml.contains("tar"));
+    assert!(!template.cargo_toml.contains("ed25519-dalek"));
+
+    let signer = BinarySigner::from_seed(&[7; 32]);
+    let signed = generate(
+        &load_plan(),
+        TemplateConfig {
+            data_key: Some(signer.public_key()),
+            ..appended_config()
+        },
+    )
+    .await;
+    let main_rs = &signed.source_files[Path::new("src/main.rs")];
+    assert!(main_rs.contains("const DATA_PUBLIC_KEY: [u8; 32]"));
+    assert!(signed.cargo_toml.contains("ed25519-dalek"));
+}
+
+#[tokio::test]
+async fn test_wasi_runners_cannot_append_data() {
+    let plan = load_plan();
+    let result = BinaryTemplateGenerator::new(TemplateConfig {
+        runner_profile: RunnerProfile::Wasi,
+        ..appended_config()
+    })
+    .unwrap()
+    .generate_binary_template(&plan, &plan.binary_deployments[0], &target_info())
+    .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_signed_appended_data() {
+    let template = generate(&load_plan(), appended_config()).await;
+    let signer = BinarySigner::from_seed(&[7; 32]);
+    let runner = b"\x7fELF runner".to_vec();
+
+    let binary = package_appended(&runner, &template.sections, Some(&signer)).unwrap();
+    let parts = split_appended(&binary).unwrap();
+    assert_eq!(parts.runner, runner.as_slice());
+    let signature = parts.signature.expect("data is signed");
+    signer
+        .public_key()
+        .verify_detached(parts.archive, signature)
+        .unwrap();
+
+    let other = BinarySigner::from_seed(&[8; 32]).public_key();
+    assert!(other.verify_detached(parts.archive, signature).is_err());
+    let mut tampered = parts.archive.to_vec();
+    tampered[600] ^= 1;
+    assert!(signer
+        .public_key()
+        .verify_detached(&tampered, signature)
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_appended_output_checks_the_runner() {
+    let template = generate(&load_plan(), appended_config()).await;
+    let runner = b"\x7fELF runner".to_vec();
+    let binary_data = package_appended(&runner, &template.sections, None).unwrap();
+    let manager = BinaryOutputManager::new(CompilationCache::new(
+        TempDir::new().unwrap().path().to_path_buf(),
+        true,
+    ));
+    let output = TempDir::new().unwrap();
+
+    let binary = appended_binary(
+        binary_data.clone(),
+        format!("{:x}", Sha256::digest(&runner)),
+    );
+    let output_path = output.path().join("runner");
+    let result = manager.copy_to_output(&binary, &output_path).await.unwrap();
+    assert!(result.source_verified);
+    assert_eq!(std::fs::read(&output_path).unwrap(), binary_data);
+
+    let binary = appended_binary(binary_data, "0".repeat(64));
+    let output_path = output.path().join("mismatched");
+    assert!(manager.copy_to_output(&binary, &output_path).await.is_err());
+    assert!(!output_path.exists());
+}
@@ -0,0 +1,124 @@
+use rustle_deploy::deploy::manifest::{plan_hash, ArtifactEntry, DeploymentManifest};
+use rustle_deploy::deploy::{ArtifactIndex, CompilerVersions, DeployError};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+const GNU: &str = "x86_64-unknown-linux-gnu";
+const MUSL: &str = "x86_64-unknown-linux-musl";
+
+fn runner(dir: &Path, name: &str, content: &str) {
+    let path = dir.join(name);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+fn expected(targets: &[(&str, &str)]) -> BTreeMap<String, String> {
+    targets
+        .iter()
+        .map(|(target, plan)| (target.to_string(), plan_hash(plan)))
+        .collect()
+}
+
+fn manifest(dir: &Path, artifacts: &[(&str, &str, &str)]) {
+    let mut manifest = DeploymentManifest::new("plan", CompilerVersions::default());
+    for (name, target, plan) in artifacts {
+        manifest.add_artifact(
+            ArtifactEntry::from_file(dir, &dir.join(name), target, &plan_hash(plan)).unwrap(),
+        );
+    }
+    manifest.write(dir).unwrap();
+}
+
+#[test]
+fn test_scan_finds_runners_where_compiling_writes_them() {
+    let dir = tempfile::TempDir::new().unwrap();
+    runner(dir.path(), &format!("{GNU}/rustle-runner"), "gnu");
+    runner(dir.path(), "wasm32-wasip1/rustle-runner.wasm", "wasm");
+    std::fs::create_dir(dir.path().join("empty")).unwrap();
+
+    let index = ArtifactIndex::load(dir.path()).unwrap();
+    assert!(index.manifest.is_none());
+    let targets: Vec<_> = index
+        .artifacts
+        .iter()
+        .map(|artifact| artifact.target_triple.as_deref().unwrap())
+        .collect();
+    // In the order of their directories
+    assert_eq!(targets, ["wasm32-wasip1", GNU]);
+    assert!(index.artifacts.iter().all(|a| a.plan_hash.is_none()));
+}
+
+#[test]
+fn test_runners_of_unknown_plan_are_deployed_only_when_forced() {
+    let dir = tempfile::TempDir::new().unwrap();
+    runner(dir.path(), "rustle-runner", "only");
+    let index = ArtifactIndex::load(dir.path()).unwrap();
+    let expected = expected(&[(GNU, "plan")]);
+
+    let err = index.select(&expected, false).unwrap_err();
+    assert!(matches!(err, DeployError::StaleArtifact { .. }), "{err}");
+
+    let selected = index.select(&expected, true).unwrap();
+    assert_eq!(selected[GNU].path, dir.path().join("rustle-runner"));
+}
+
+#[test]
+fn test_manifest_matches_runners_by_plan() {
+    let dir = tempfile::TempDir::new().unwrap();
+    runner(dir.path(), &format!("{GNU}/rustle-runner"), "gnu");
+    runner(dir.path(), "aarch64-unknown-linux-gnu/rustle-runner", "arm");
+    manifest(
+        dir.path(),
+        &[
+            (&format!("{GNU}/rustle-runner"), GNU, "plan"),
+            (
+                "aarch64-unknown-linux-gnu/rustle-runner",
+                "aarch64-unknown-linux-gnu",
+                "old plan",
+            ),
+        ],
+    );
+    let index = ArtifactIndex::load(dir.path()).unwrap();
+    assert!(index.manifest.is_some());
+
+    let selected = index.select(&expected(&[(GNU, "plan")]), false).unwrap();
+    assert_eq!(selected[GNU].sha256, index.artifacts[0].sha256);
+
+    let stale = expected(&[("aarch64-unknown-linux-gnu", "plan")]);
+    let err = index.select(&stale, false).unwrap_err();
+    assert!(err.to_string().contains("another plan"), "{err}");
+
+    let missing = expected(&[("x86_64-pc-windows-msvc", "plan")]);
+    let err = index.select(&missing, false).unwrap_err();
+    assert!(matches!(err, DeployError::MissingArtifact { .. }), "{err}");
+}
+
+#[test]
+fn test_static_runner_serves_its_glibc_target() {
+    let dir = tempfile::TempDir::new().unwrap();
+    runner(dir.path(), &format!("{MUSL}/rustle-runner"), "musl");
+    manifest(
+        dir.path(),
+        &[(&format!("{MUSL}/rustle-runner"), MUSL, "plan")],
+    );
+    let index = ArtifactIndex::load(dir.path()).unwrap();
+
+    let selected = index.select(&expected(&[(GNU, "plan")]), false).unwrap();
+    assert_eq!(selected[GNU].target_triple.as_deref(), Some(MUSL));
+
+    let err = index
+        .select(&expected(&[(GNU, "another plan")]), false)
+        .unwrap_err();
+    assert!(matches!(err, DeployError::MissingArtifact { .. }), "{err}");
+}
+
+#[test]
+fn test_tampered_runner_fails_the_manifest_check() {
+    let dir = tempfile::TempDir::new().unwrap();
+    runner(dir.path(), "rustle-runner", "built");
+    manifest(dir.path(), &[("rustle-runner", GNU, "plan")]);
+    runner(dir.path(), "rustle-runner", "tampered");
+
+    let err = ArtifactIndex::load(dir.path()).unwrap_err();
+    assert!(matches!(err, DeployError::ManifestMismatch { .. }), "{err}");
+}
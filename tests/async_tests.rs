@@ -0,0 +1,100 @@
+mod helpers;
+
+use helpers::{plan, TaskBuilder};
+use rustle_deploy::runtime::{ExecutionResult, LocalExecutor, RuntimeConfig};
+use std::path::Path;
+
+fn task(id: &str, module: &str, args: serde_json::Value) -> serde_json::Value {
+    TaskBuilder::new(id, module, args).build()
+}
+
+fn async_task(id: &str, cmd: &str, timeout: u64, poll: u64) -> serde_json::Value {
+    let mut task = task(id, "command", serde_json::json!({ "cmd": cmd }));
+    task["async_job"] = serde_json::json!({ "timeout": timeout, "poll": poll });
+    task
+}
+
+async fn execute(tasks: Vec<serde_json::Value>, async_dir: &Path) -> ExecutionResult {
+    let plan = plan("async", serde_json::json!({ "tasks": tasks }));
+    let config = RuntimeConfig {
+        async_dir: Some(async_dir.to_path_buf()),
+        ..Default::default()
+    };
+    LocalExecutor::new(config).execute_plan(plan).await.unwrap()
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_polled_async_task_waits_for_its_job() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let result = execute(
+        vec![
+            async_task("build", "sh -c 'sleep 0.2; echo built'", 10, 1),
+            async_task("hang", "sleep 5", 1, 1),
+        ],
+        dir.path(),
+    )
+    .await;
+
+    let build = &result.task_results["build"];
+    assert!(!build.failed, "{:?}", build.error);
+    assert_eq!(build.stdout.as_deref().map(str::trim), Some("built"));
+    assert_eq!(build.output["finished"], 1);
+
+    let hang = &result.task_results["hang"];
+    assert!(hang.failed);
+    assert_eq!(
+        hang.error.as_deref(),
+        Some("Job did not complete within 1s")
+    );
+
+    // Polled jobs clean up after themselves
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_async_status_reports_and_cleans_up_background_jobs() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let result = execute(
+        vec![async_task(
+            "background",
+            "sh -c 'sleep 0.2; echo late'",
+            10,
+            0,
+        )],
+        dir.path(),
+    )
+    .await;
+    let started = &result.task_results["background"];
+    assert!(!started.failed, "{:?}", started.error);
+    assert_eq!(started.output["finished"], 0);
+    let jid = started.output["ansible_job_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let result = execute(
+        vec![
+            task("status", "async_status", serde_json::json!({ "jid": jid })),
+            task(
+                "cleanup",
+                "async_status",
+                serde_json::json!({ "jid": jid, "mode": "cleanup" }),
+            ),
+            task(
+                "missing",
+                "async_status",
+                serde_json::json!({ "jid": "j-unknown" }),
+            ),
+        ],
+        dir.path(),
+    )
+    .await;
+
+    let status = &result.task_results["status"];
+    assert_eq!(status.output["finished"], 1);
+    assert_eq!(status.stdout.as_deref().map(str::trim), Some("late"));
+    assert!(!dir.path().join(&jid).exists());
+    assert!(result.task_results["missing"].failed);
+}
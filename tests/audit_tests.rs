@@ -0,0 +1,143 @@
+mod helpers;
+
+use chrono::Utc;
+use helpers::TaskBuilder;
+use rustle_deploy::deploy::{AuditFormat, AuditLog, DeployError};
+use rustle_deploy::execution::ExecutionPlan;
+use rustle_deploy::runtime::{AuditEntry, LocalExecutor, RuntimeConfig, NO_LOG_MESSAGE};
+use tempfile::TempDir;
+
+fn task(id: &str, module: &str, args: serde_json::Value) -> serde_json::Value {
+    TaskBuilder::new(id, module, args)
+        .continue_on_failure()
+        .build()
+}
+
+fn plan(tasks: Vec<serde_json::Value>) -> ExecutionPlan {
+    helpers::plan("audit", serde_json::json!({ "tasks": tasks }))
+}
+
+fn entry(host: &str, task_id: &str) -> AuditEntry {
+    AuditEntry {
+        host: host.to_string(),
+        task_id: task_id.to_string(),
+        task_name: format!("Configure {task_id}"),
+        module: "copy".to_string(),
+        params: serde_json::json!({ "dest": "/etc/motd", "content": "hello, world" }),
+        before_checksum: None,
+        after_checksum: Some("ab12".to_string()),
+        timestamp: Utc::now(),
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_runner_audits_the_tasks_that_changed_something() {
+    let mut secret = task(
+        "set password",
+        "command",
+        serde_json::json!({ "cmd": "echo s3cret" }),
+    );
+    secret["no_log"] = true.into();
+    let mut executor = LocalExecutor::new(RuntimeConfig::default());
+    let result = executor
+        .execute_plan(plan(vec![
+            task(
+                "install",
+                "command",
+                serde_json::json!({ "cmd": "echo installed" }),
+            ),
+            task("report", "debug", serde_json::json!({ "msg": "unchanged" })),
+            secret,
+        ]))
+        .await
+        .unwrap();
+
+    let audited: Vec<_> = result
+        .audit
+        .iter()
+        .map(|entry| entry.task_id.as_str())
+        .collect();
+    assert_eq!(audited, ["install", "set password"]);
+
+    let install = &result.audit[0];
+    assert_eq!(install.host, "localhost");
+    assert_eq!(install.module, "command");
+    assert_eq!(install.params["cmd"], "echo installed");
+    assert_eq!(result.audit[1].params, NO_LOG_MESSAGE);
+}
+
+#[test]
+fn test_appended_records_are_chained_and_verify() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("audit").join("audit.log");
+    let log = AuditLog::new(&path).with_user("alice");
+    log.append("deploy-1", &[entry("web1", "motd"), entry("web2", "motd")])
+        .unwrap();
+    // A new log on the same file continues the chain
+    let records = AuditLog::new(&path)
+        .with_user("bob")
+        .append("deploy-2", &[entry("web1", "ntp")])
+        .unwrap();
+    assert_eq!(records[0].sequence, 3);
+    assert_eq!(records[0].user, "bob");
+
+    let (count, head) = log.verify().unwrap();
+    assert_eq!(count, 3);
+    assert_eq!(head, records[0].hash);
+
+    let all = log.read().unwrap();
+    assert_eq!(all[1].previous_hash, all[0].hash);
+    assert_eq!(all[0].user, "alice");
+    assert_eq!(all[0].deployment_id, "deploy-1");
+}
+
+fn tampered_line(error: DeployError) -> usize {
+    match error {
+        DeployError::AuditLogTampered { line, .. } => line,
+        other => panic!("expected tampering, got {other}"),
+    }
+}
+
+#[test]
+fn test_verify_detects_edited_and_removed_records() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("audit.log");
+    let log = AuditLog::new(&path);
+    log.append(
+        "deploy-1",
+        &[entry("web1", "a"), entry("web2", "b"), entry("web3", "c")],
+    )
+    .unwrap();
+    let original = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<_> = original.lines().collect();
+
+    std::fs::write(&path, original.replacen("web2", "web9", 1)).unwrap();
+    assert_eq!(tampered_line(log.verify().unwrap_err()), 2);
+
+    std::fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+    assert_eq!(tampered_line(log.verify().unwrap_err()), 2);
+    // Nothing is exported from a broken log
+    assert!(log.export(AuditFormat::Json).is_err());
+}
+
+#[test]
+fn test_export_writes_every_record() {
+    let dir = TempDir::new().unwrap();
+    let log = AuditLog::new(dir.path().join("audit.log")).with_user("alice");
+    log.append("deploy-1", &[entry("web1", "motd")]).unwrap();
+
+    let json: serde_json::Value =
+        serde_json::from_str(&log.export(AuditFormat::Json).unwrap()).unwrap();
+    assert_eq!(json[0]["host"], "web1");
+    assert_eq!(json[0]["after_checksum"], "ab12");
+
+    let csv = log.export(AuditFormat::Csv).unwrap();
+    let rows: Vec<_> = csv.lines().collect();
+    assert_eq!(rows.len(), 2);
+    assert!(rows[0].starts_with("sequence,timestamp,deployment_id,user,host"));
+    assert!(rows[1].contains(
+        ",deploy-1,alice,web1,motd,Configure motd,copy,\"{\"\"content\"\":\"\"hello, world\"\",\"\"dest\"\":\"\"/etc/motd\"\"}\",,ab12,"
+    ));
+    assert_eq!("CSV".parse::<AuditFormat>().unwrap(), AuditFormat::Csv);
+}
@@ -0,0 +1,147 @@
+mod helpers;
+
+use helpers::{plan, TaskBuilder};
+use rustle_deploy::runtime::{ExecutionResult, LocalExecutor, RuntimeConfig, TaskStatus};
+
+fn task(id: &str, cmd: &str) -> serde_json::Value {
+    TaskBuilder::command(id, cmd).play("site").build()
+}
+
+fn block(id: &str, block: &[&str], rescue: &[&str], always: &[&str]) -> serde_json::Value {
+    serde_json::json!({ "id": id, "block": block, "rescue": rescue, "always": always })
+}
+
+async fn execute(tasks: Vec<serde_json::Value>, blocks: Vec<serde_json::Value>) -> ExecutionResult {
+    let plan = plan(
+        "blocks",
+        serde_json::json!({ "tasks": tasks, "blocks": blocks }),
+    );
+    let mut executor = LocalExecutor::new(RuntimeConfig::default());
+    executor.execute_plan(plan).await.unwrap()
+}
+
+fn ran(result: &ExecutionResult, id: &str) -> bool {
+    let task = &result.task_results[id];
+    !task.skipped && task.stdout.is_some()
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_rescue_runs_with_the_failed_task_and_always_runs() {
+    let mut recover = task("recover", "echo recovered");
+    recover["conditions"] = serde_json::json!([{
+        "variable": "ansible_failed_task.id", "operator": "Equals", "value": "break"
+    }]);
+    let mut after = task("after", "echo after");
+    after["dependencies"] = serde_json::json!(["break"]);
+
+    let result = execute(
+        vec![
+            task("prepare", "echo prepare"),
+            task("break", "false"),
+            task("unreached", "echo unreached"),
+            recover,
+            task("cleanup", "echo cleanup"),
+            after,
+        ],
+        vec![block(
+            "deploy",
+            &["prepare", "break", "unreached"],
+            &["recover"],
+            &["cleanup"],
+        )],
+    )
+    .await;
+
+    assert!(result.success, "{:?}", result.errors);
+    assert_eq!(result.summary.total_tasks, 6);
+    assert_eq!(result.summary.failed_tasks, 0);
+    assert_eq!(result.summary.rescued_tasks, 1);
+    assert!(matches!(
+        result.task_results["break"].status,
+        TaskStatus::Rescued
+    ));
+    assert!(result.task_results["unreached"].skipped);
+    for id in ["prepare", "recover", "cleanup", "after"] {
+        assert!(ran(&result, id), "{id} did not run");
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_nested_block_failures_reach_the_outer_rescue() {
+    let result = execute(
+        vec![
+            task("inner-fail", "false"),
+            task("inner-always", "echo inner"),
+            task("outer-recover", "echo outer"),
+            task("outer-always", "echo done"),
+        ],
+        vec![
+            block("outer", &["inner"], &["outer-recover"], &["outer-always"]),
+            block("inner", &["inner-fail"], &[], &["inner-always"]),
+        ],
+    )
+    .await;
+
+    assert!(result.success, "{:?}", result.errors);
+    assert!(matches!(
+        result.task_results["inner-fail"].status,
+        TaskStatus::Rescued
+    ));
+    for id in ["inner-always", "outer-recover", "outer-always"] {
+        assert!(ran(&result, id), "{id} did not run");
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_unrescued_block_fails_after_running_always() {
+    let result = execute(
+        vec![task("break", "false"), task("cleanup", "echo cleanup")],
+        vec![
+            block("deploy", &["inner"], &[], &["cleanup"]),
+            block("inner", &["break"], &[], &[]),
+        ],
+    )
+    .await;
+
+    assert!(!result.success);
+    assert_eq!(result.summary.failed_tasks, 1);
+    assert!(result.task_results["break"].failed);
+    assert!(ran(&result, "cleanup"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_failed_when_changed_when_and_ignore_errors() {
+    let mut scan = task("scan", "sh -c 'echo ERROR: disk full'");
+    scan["failed_when"] = serde_json::json!([{
+        "variable": "result.stdout", "operator": "Contains", "value": "ERROR"
+    }]);
+    scan["ignore_errors"] = serde_json::json!(true);
+    let mut probe = task("probe", "false");
+    probe["failed_when"] = serde_json::json!([{
+        "variable": "result.rc", "operator": "GreaterThan", "value": 1
+    }]);
+    probe["changed_when"] = serde_json::json!(false);
+    let mut after = task("after", "echo after");
+    after["dependencies"] = serde_json::json!(["scan", "probe"]);
+
+    let result = execute(vec![scan, probe, after], vec![]).await;
+
+    assert!(result.success, "{:?}", result.errors);
+    let scan = &result.task_results["scan"];
+    assert_eq!(scan.status, TaskStatus::Ignored);
+    assert!(!scan.failed);
+    assert_eq!(
+        scan.error.as_deref(),
+        Some("failed_when conditions were met")
+    );
+    let probe = &result.task_results["probe"];
+    assert_eq!(probe.status, TaskStatus::Success);
+    assert!(!probe.changed);
+    assert!(ran(&result, "after"));
+    assert_eq!(result.summary.ignored_tasks, 1);
+    assert_eq!(result.summary.failed_tasks, 0);
+}
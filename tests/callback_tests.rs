@@ -0,0 +1,86 @@
+mod helpers;
+
+use helpers::TaskBuilder;
+use rustle_deploy::execution::{ExecutionPlan, Play, Task};
+use rustle_deploy::runtime::{
+    CallbackPlugin, ExecutionResult, LocalExecutor, RuntimeConfig, TaskResult,
+};
+use std::sync::{Arc, Mutex};
+
+/// Records every lifecycle event it receives
+#[derive(Default)]
+struct Recorder(Mutex<Vec<String>>);
+
+impl CallbackPlugin for Recorder {
+    fn name(&self) -> &str {
+        "recorder"
+    }
+
+    fn on_play_start(&self, play: &Play) {
+        self.0.lock().unwrap().push(format!("play {}", play.id));
+    }
+
+    fn on_task_start(&self, task: &Task) {
+        self.0.lock().unwrap().push(format!("start {}", task.id));
+    }
+
+    fn on_task_result(&self, result: &TaskResult) {
+        self.0.lock().unwrap().push(format!(
+            "result {} failed={}",
+            result.task_id, result.failed
+        ));
+    }
+
+    fn on_stats(&self, result: &ExecutionResult) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("stats {}", result.summary.total_tasks));
+    }
+}
+
+fn task(id: &str, play: &str, cmd: &str) -> serde_json::Value {
+    TaskBuilder::command(id, cmd)
+        .play(play)
+        .continue_on_failure()
+        .build()
+}
+
+fn plan(tasks: Vec<serde_json::Value>) -> ExecutionPlan {
+    helpers::plan(
+        "callbacks",
+        serde_json::json!({ "tasks": tasks, "plays": [{ "id": "setup", "name": "Set up" }] }),
+    )
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_registered_plugin_receives_the_lifecycle_events() {
+    let recorder = Arc::new(Recorder::default());
+    let mut skipped = task("skipped", "deploy", "echo skipped");
+    skipped["when"] = serde_json::json!(["false"]);
+    let tasks = vec![
+        task("prepare", "setup", "echo prepare"),
+        task("install", "deploy", "false"),
+        skipped,
+    ];
+
+    let mut executor = LocalExecutor::new(RuntimeConfig::default())
+        .with_callback(Arc::clone(&recorder) as Arc<dyn CallbackPlugin>);
+    executor.execute_plan(plan(tasks)).await.unwrap();
+
+    assert_eq!(
+        *recorder.0.lock().unwrap(),
+        [
+            "play setup",
+            "start prepare",
+            "result prepare failed=false",
+            "play deploy",
+            "start install",
+            "result install failed=true",
+            "start skipped",
+            "result skipped failed=false",
+            "stats 3",
+        ]
+    );
+}
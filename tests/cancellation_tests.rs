@@ -0,0 +1,141 @@
+mod helpers;
+
+use helpers::TaskBuilder;
+use rustle_deploy::compilation::output_unless_cancelled;
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::execution::ExecutionPlan;
+use rustle_deploy::runtime::{LocalExecutor, PersistedState, RuntimeConfig, STOP_FILE_ENV};
+use rustle_deploy::template::{BinaryTemplateGenerator, TargetInfo, TemplateConfig};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+fn task(id: &str, after: Option<&str>, script: &str) -> serde_json::Value {
+    TaskBuilder::script(id, script)
+        .after(after.as_slice())
+        .play("site")
+        .build()
+}
+
+fn plan(dir: &Path) -> ExecutionPlan {
+    let dir = dir.display();
+    let first = task(
+        "first",
+        None,
+        &format!("echo ran >> {dir}/first.log; sleep 1"),
+    );
+    let second = task(
+        "second",
+        Some("first"),
+        &format!("echo ran >> {dir}/second.log"),
+    );
+
+    helpers::plan(
+        "cancellation",
+        serde_json::json!({ "tasks": [first, second] }),
+    )
+}
+
+fn executor(dir: &Path) -> LocalExecutor {
+    LocalExecutor::new(RuntimeConfig {
+        state_file: Some(dir.join("runner.state")),
+        ..RuntimeConfig::default()
+    })
+}
+
+fn runs(dir: &Path, log: &str) -> usize {
+    std::fs::read_to_string(dir.join(log))
+        .map(|log| log.lines().count())
+        .unwrap_or(0)
+}
+
+#[tokio::test]
+async fn test_cancelled_build_is_not_started() {
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+
+    let output = output_unless_cancelled(&mut Command::new("true"), &cancel)
+        .await
+        .unwrap();
+    assert!(output.is_none());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_cancelled_build_is_killed() {
+    let cancel = CancellationToken::new();
+    let token = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        token.cancel();
+    });
+
+    let start = Instant::now();
+    let mut command = Command::new("sh");
+    command.args(["-c", "sleep 30"]);
+    let output = output_unless_cancelled(&mut command, &cancel)
+        .await
+        .unwrap();
+    assert!(output.is_none());
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    let mut command = Command::new("sh");
+    command.args(["-c", "echo built"]);
+    let output = output_unless_cancelled(&mut command, &CancellationToken::new())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "built");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_cancelled_run_stops_after_the_current_task() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let state_file = dir.path().join("runner.state");
+    let cancel = CancellationToken::new();
+    let token = cancel.clone();
+    let started = dir.path().join("first.log");
+    tokio::spawn(async move {
+        while !started.exists() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        token.cancel();
+    });
+
+    let mut stopped = executor(dir.path()).with_cancellation(cancel);
+    let result = stopped.execute_plan(plan(dir.path())).await.unwrap();
+    assert!(result.failed);
+    assert!(result.errors.iter().any(|e| e.starts_with("Run stopped")));
+    assert_eq!(runs(dir.path(), "first.log"), 1);
+    assert_eq!(runs(dir.path(), "second.log"), 0);
+    let state = PersistedState::load(&state_file).unwrap().unwrap();
+    assert!(state.completed_tasks.contains_key("first"));
+
+    let mut resumed = executor(dir.path()).with_resume(true);
+    let result = resumed.execute_plan(plan(dir.path())).await.unwrap();
+    assert!(result.success, "{:?}", result.errors);
+    assert_eq!(runs(dir.path(), "first.log"), 1);
+    assert_eq!(runs(dir.path(), "second.log"), 1);
+}
+
+#[tokio::test]
+async fn test_runner_stops_when_asked() {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    let plan: RustlePlanOutput = serde_json::from_str(&content).unwrap();
+    let target = TargetInfo::for_target("x86_64-unknown-linux-gnu").unwrap();
+
+    let template = BinaryTemplateGenerator::new(TemplateConfig::default())
+        .unwrap()
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &target)
+        .await
+        .expect("Failed to generate template");
+    let main_rs = &template.source_files[Path::new("src/main.rs")];
+    assert!(main_rs.contains(&format!(
+        r#"const STOP_FILE_ENV: &str = "{STOP_FILE_ENV}";"#
+    )));
+    assert!(main_rs.contains("stop_requested()"));
+}
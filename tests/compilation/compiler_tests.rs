@@ -97,6 +97,7 @@ fn create_test_config() -> CompilerConfig {
         default_optimization: OptimizationLevel::Release,
         zigbuild_fallback: true,
         binary_size_limit: Some(100 * 1024 * 1024), // 100MB
+        ..Default::default()
     }
 }
 
@@ -0,0 +1,162 @@
+use chrono::Utc;
+use rustle_deploy::compilation::compiler::{BinarySource, CompiledBinary};
+use rustle_deploy::compilation::{CacheKey, CacheStore, CompilationCache, LocalStore};
+use rustle_deploy::types::compilation::OptimizationLevel;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn binary(target: &str, data: &[u8]) -> CompiledBinary {
+    CompiledBinary {
+        binary_id: "binary-test".to_string(),
+        target_triple: target.to_string(),
+        binary_path: PathBuf::from("/nonexistent/rustle-runner"),
+        binary_data: data.to_vec(),
+        effective_source: BinarySource::InMemory,
+        size: data.len() as u64,
+        checksum: format!("{:x}", Sha256::digest(data)),
+        compilation_time: Duration::from_secs(1),
+        optimization_level: OptimizationLevel::Release,
+        template_hash: "template".to_string(),
+        created_at: Utc::now(),
+        size_report: None,
+    }
+}
+
+fn key(template_hash: &str, target: &str) -> CacheKey {
+    CacheKey::new(
+        template_hash,
+        target,
+        "rustc 1.80.0",
+        &["b".to_string(), "a".to_string()],
+        "Release",
+    )
+}
+
+#[tokio::test]
+async fn test_content_addressed_cache() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut cache = CompilationCache::new(temp_dir.path().to_path_buf(), true).with_max_size(None);
+
+    let gnu = key("template", "x86_64-unknown-linux-gnu");
+    cache
+        .store_binary(&gnu, &binary("x86_64-unknown-linux-gnu", b"gnu binary"))
+        .await
+        .unwrap();
+
+    // The same template from another plan, with features in another order
+    let same = CacheKey::new(
+        "template",
+        "x86_64-unknown-linux-gnu",
+        "rustc 1.80.0",
+        &["a".to_string(), "b".to_string()],
+        "Release",
+    );
+    assert_eq!(same.digest(), gnu.digest());
+    let cached = cache.get_binary(&same).unwrap();
+    assert_eq!(cached.binary_data, b"gnu binary");
+    assert!(matches!(
+        cached.effective_source,
+        BinarySource::Cache { .. }
+    ));
+
+    // Another toolchain or profile builds another binary
+    let newer = CacheKey::new(
+        "template",
+        "x86_64-unknown-linux-gnu",
+        "rustc 1.81.0",
+        &[],
+        "Release",
+    );
+    assert!(cache.get_binary(&newer).is_none());
+
+    // The index survives the process
+    let mut reopened = CompilationCache::new(temp_dir.path().to_path_buf(), true);
+    assert!(reopened.get_binary(&gnu).is_some());
+    let stats = reopened.stats();
+    assert_eq!(stats.entries, 1);
+    assert_eq!(stats.hits, 2);
+    assert_eq!(stats.targets["x86_64-unknown-linux-gnu"], 1);
+
+    // A corrupted binary is dropped
+    std::fs::write(reopened.get_cache_path(&gnu), b"tampered").unwrap();
+    assert!(reopened.get_binary(&gnu).is_none());
+    assert_eq!(reopened.stats().entries, 0);
+}
+
+#[tokio::test]
+async fn test_cache_lru_eviction_and_clean() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut cache =
+        CompilationCache::new(temp_dir.path().to_path_buf(), true).with_max_size(Some(20));
+
+    let first = key("first", "x86_64-unknown-linux-gnu");
+    let second = key("second", "x86_64-unknown-linux-musl");
+    let third = key("third", "aarch64-unknown-linux-gnu");
+    cache
+        .store_binary(&first, &binary("x86_64-unknown-linux-gnu", b"0123456789"))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    cache
+        .store_binary(&second, &binary("x86_64-unknown-linux-musl", b"0123456789"))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    // Using the first makes the second the least recently used
+    assert!(cache.get_binary(&first).is_some());
+    cache
+        .store_binary(&third, &binary("aarch64-unknown-linux-gnu", b"0123456789"))
+        .await
+        .unwrap();
+    assert!(cache.get_binary(&second).is_none());
+    assert!(cache.get_binary(&first).is_some());
+    assert!(cache.get_binary(&third).is_some());
+    assert_eq!(cache.stats().total_size_bytes, 20);
+
+    let kept = cache.clean(Some(Duration::from_secs(3600))).unwrap();
+    assert_eq!(kept.removed, 0);
+    let cleanup = cache.clean(None).unwrap();
+    assert_eq!(cleanup.removed, 2);
+    assert_eq!(cleanup.freed_bytes, 20);
+    assert_eq!(cache.stats().entries, 0);
+    assert!(!cache.get_cache_path(&first).exists());
+}
+
+#[tokio::test]
+async fn test_shared_store_verifies_fetched_binaries() {
+    let temp_dir = TempDir::new().unwrap();
+    let store: Arc<dyn CacheStore> = Arc::new(LocalStore::new(temp_dir.path().join("shared")));
+    let gnu = key("template", "x86_64-unknown-linux-gnu");
+
+    // Published by one machine...
+    let mut publisher =
+        CompilationCache::new(temp_dir.path().join("ci"), true).with_store(Arc::clone(&store));
+    publisher
+        .store_binary(&gnu, &binary("x86_64-unknown-linux-gnu", b"gnu binary"))
+        .await
+        .unwrap();
+
+    // ...and fetched by another, then kept in its own cache
+    let mut laptop =
+        CompilationCache::new(temp_dir.path().join("laptop"), true).with_store(Arc::clone(&store));
+    assert!(laptop.get_binary(&gnu).is_none());
+    let fetched = laptop.fetch_shared(&gnu).await.unwrap();
+    assert_eq!(fetched.binary_data, b"gnu binary");
+    assert!(laptop.get_binary(&gnu).is_some());
+
+    // A tampered object is refused
+    let name = gnu.digest();
+    std::fs::write(
+        temp_dir.path().join("shared").join(format!("{name}.bin")),
+        b"tampered!!",
+    )
+    .unwrap();
+    let artifact = store.fetch_artifact(&name).await.unwrap();
+    assert!(artifact.is_none());
+    let mut other = CompilationCache::new(temp_dir.path().join("other"), true).with_store(store);
+    assert!(other.fetch_shared(&gnu).await.is_none());
+}
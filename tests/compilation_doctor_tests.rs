@@ -0,0 +1,87 @@
+use rustle_deploy::compilation::backends::{BackendRegistry, ContainerBackend};
+use rustle_deploy::compilation::{CompilationDoctor, HostToolchain};
+use rustle_deploy::deploy::ContainerRuntime;
+
+fn linux_toolchain() -> HostToolchain {
+    HostToolchain {
+        host_target: "x86_64-unknown-linux-gnu".to_string(),
+        rust_version: Some("1.80.0".to_string()),
+        installed_targets: ["x86_64-unknown-linux-gnu".to_string()].into(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_native_target_builds_with_cargo() {
+    let doctor = CompilationDoctor::new(linux_toolchain(), BackendRegistry::new());
+    let diagnosis = doctor.diagnose("x86_64-unknown-linux-gnu");
+    assert_eq!(diagnosis.backend.as_deref(), Some("cargo"));
+    assert_eq!(diagnosis.failed_checks().count(), 0);
+}
+
+#[test]
+fn test_missing_cross_tools_reported_with_fixes() {
+    let doctor = CompilationDoctor::new(linux_toolchain(), BackendRegistry::new());
+    let diagnosis = doctor.diagnose("aarch64-apple-darwin");
+    assert!(diagnosis.backend.is_none());
+    assert!(!diagnosis.is_buildable());
+
+    let failed: Vec<_> = diagnosis
+        .failed_checks()
+        .map(|check| check.name.as_str())
+        .collect();
+    assert_eq!(
+        failed,
+        ["rust-target", "zig", "cargo-zigbuild", "macos-sdk"]
+    );
+    let rust_target = diagnosis.failed_checks().next().unwrap();
+    assert_eq!(
+        rust_target.fix.as_deref(),
+        Some("rustup target add aarch64-apple-darwin")
+    );
+
+    // With zig, cargo-zigbuild and the target's std the SDK is what is left
+    let mut toolchain = linux_toolchain();
+    toolchain.zig_version = Some("0.11.0".to_string());
+    toolchain.zigbuild = true;
+    toolchain
+        .installed_targets
+        .insert("aarch64-apple-darwin".to_string());
+    let doctor = CompilationDoctor::new(toolchain.clone(), BackendRegistry::new());
+    let diagnosis = doctor.diagnose("aarch64-apple-darwin");
+    let failed: Vec<_> = diagnosis.failed_checks().map(|c| c.name.clone()).collect();
+    assert_eq!(failed, ["macos-sdk"]);
+
+    toolchain.macos_sdk = Some("/opt/MacOSX.sdk".into());
+    let doctor = CompilationDoctor::new(toolchain, BackendRegistry::new());
+    let diagnosis = doctor.diagnose("aarch64-apple-darwin");
+    assert_eq!(diagnosis.backend.as_deref(), Some("zigbuild"));
+}
+
+#[test]
+fn test_old_zig_and_container_fallback() {
+    let mut toolchain = linux_toolchain();
+    toolchain.zig_version = Some("0.9.1".to_string());
+    toolchain.zigbuild = true;
+    toolchain
+        .installed_targets
+        .insert("armv7-unknown-linux-gnueabihf".to_string());
+
+    let mut registry = BackendRegistry::new();
+    registry
+        .register(ContainerBackend::with_runtime(Some(
+            ContainerRuntime::Docker,
+        )))
+        .unwrap();
+    let doctor = CompilationDoctor::new(toolchain, registry);
+    let diagnosis = doctor.diagnose("armv7-unknown-linux-gnueabihf");
+
+    assert!(diagnosis.backend.is_none());
+    assert_eq!(diagnosis.fallbacks, ["container"]);
+    assert!(diagnosis.is_buildable());
+    let zig = diagnosis.checks.iter().find(|c| c.name == "zig").unwrap();
+    assert!(!zig.ok);
+
+    let json = serde_json::to_value(&diagnosis).unwrap();
+    assert_eq!(json["fallbacks"][0], "container");
+}
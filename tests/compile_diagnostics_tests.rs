@@ -0,0 +1,164 @@
+use rustle_deploy::compilation::{CompilationDiagnostics, DiagnosticLevel, DiagnosticOrigin};
+use tempfile::TempDir;
+
+fn compiler_message(manifest: &str, package_id: &str, message: serde_json::Value) -> String {
+    serde_json::json!({
+        "reason": "compiler-message",
+        "package_id": package_id,
+        "manifest_path": manifest,
+        "message": message,
+    })
+    .to_string()
+}
+
+fn message(
+    level: &str,
+    text: &str,
+    code: Option<&str>,
+    file: &str,
+    line: usize,
+) -> serde_json::Value {
+    serde_json::json!({
+        "message": text,
+        "level": level,
+        "code": code.map(|code| serde_json::json!({ "code": code, "explanation": null })),
+        "spans": [{
+            "file_name": file,
+            "line_start": line,
+            "line_end": line,
+            "column_start": 9,
+            "column_end": 14,
+            "is_primary": true,
+        }],
+        "children": [{ "message": "expected `String`", "level": "help", "spans": [], "children": [], "rendered": null }],
+        "rendered": format!("{level}: {text}\n"),
+    })
+}
+
+#[test]
+fn test_diagnostics_mapped_to_their_origin() {
+    let project = TempDir::new().unwrap();
+    std::fs::create_dir_all(project.path().join("src")).unwrap();
+    std::fs::write(
+        project.path().join("src/main.rs"),
+        "mod embedded_data {\n}\n\nasync fn report_to_controller() {\n    let x = 1;\n}\n",
+    )
+    .unwrap();
+    let manifest = project.path().join("Cargo.toml");
+    let manifest = manifest.to_str().unwrap();
+    let runner = "path+file:///tmp/project#rustle-runner@1.0.0";
+
+    let stdout = [
+        serde_json::json!({ "reason": "compiler-artifact", "package_id": "x" }).to_string(),
+        compiler_message(
+            manifest,
+            runner,
+            message("error", "mismatched types", Some("E0308"), "src/main.rs", 5),
+        ),
+        compiler_message(
+            manifest,
+            runner,
+            message(
+                "error",
+                "cannot find value `dest`",
+                Some("E0425"),
+                "src/modules/copy.rs",
+                12,
+            ),
+        ),
+        compiler_message(
+            manifest,
+            runner,
+            message(
+                "warning",
+                "unused variable",
+                None,
+                "src/modules/parameter_mapping/handlers/file.rs",
+                3,
+            ),
+        ),
+        compiler_message(
+            "/home/me/.cargo/registry/src/openssl-sys-0.9.0/Cargo.toml",
+            "registry+https://github.com/rust-lang/crates.io-index#openssl-sys@0.9.0",
+            message(
+                "error",
+                "could not find system library",
+                None,
+                "/home/me/.cargo/registry/src/openssl-sys-0.9.0/build.rs",
+                1,
+            ),
+        ),
+        compiler_message(
+            manifest,
+            runner,
+            serde_json::json!({
+                "message": "aborting due to 3 previous errors",
+                "level": "error",
+                "code": null,
+                "spans": [],
+                "children": [],
+                "rendered": null,
+            }),
+        ),
+        "not json".to_string(),
+    ]
+    .join("\n");
+
+    let diagnostics =
+        CompilationDiagnostics::from_cargo_output("wasm32-wasi", &stdout, project.path());
+
+    assert_eq!(diagnostics.diagnostics.len(), 4);
+    assert_eq!(diagnostics.errors().count(), 3);
+    assert_eq!(diagnostics.warnings().count(), 1);
+    assert_eq!(diagnostics.diagnostics[2].level, DiagnosticLevel::Warning);
+
+    let first = &diagnostics.diagnostics[0];
+    assert_eq!(first.code.as_deref(), Some("E0308"));
+    assert_eq!(first.location.as_ref().unwrap().line, 5);
+    assert_eq!(
+        first.origin,
+        DiagnosticOrigin::Template {
+            section: "fn report_to_controller".to_string()
+        }
+    );
+    assert_eq!(first.notes, ["help: expected `String`"]);
+    assert_eq!(
+        diagnostics.diagnostics[1].origin,
+        DiagnosticOrigin::Module {
+            name: "copy".to_string()
+        }
+    );
+    assert_eq!(
+        diagnostics.diagnostics[2].origin,
+        DiagnosticOrigin::Module {
+            name: "file".to_string()
+        }
+    );
+    assert_eq!(
+        diagnostics.diagnostics[3].origin,
+        DiagnosticOrigin::Dependency {
+            package: "openssl-sys".to_string()
+        }
+    );
+
+    let summary = diagnostics.to_string();
+    assert!(summary.starts_with("3 error(s), 1 warning(s), first: mismatched types"));
+    assert!(summary.contains("template section `fn report_to_controller`"));
+
+    let rendered = diagnostics.render();
+    assert!(rendered.contains("error[E0308]: mismatched types\n  --> src/main.rs:5:9"));
+    assert!(rendered.contains("src/modules/copy.rs:12:9 (module copy)"));
+    assert!(rendered.contains("   = help: expected `String`"));
+}
+
+#[test]
+fn test_no_diagnostics_without_compiler_messages() {
+    let project = TempDir::new().unwrap();
+    let diagnostics = CompilationDiagnostics::from_cargo_output(
+        "x86_64-unknown-linux-gnu",
+        r#"{"reason":"build-finished","success":true}"#,
+        project.path(),
+    );
+    assert!(!diagnostics.has_errors());
+    assert!(diagnostics.render().is_empty());
+}
@@ -0,0 +1,199 @@
+use chrono::Utc;
+use rustle_deploy::api::Deployment;
+use rustle_deploy::compilation::compiler::{BinarySource, CompiledBinary, CompilerConfig};
+use rustle_deploy::compilation::{
+    CacheKey, CompilationCache, CompileEstimator, CompileSample, EstimateBasis, TemplateComplexity,
+};
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::types::compilation::OptimizationLevel;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+
+const GNU: &str = "x86_64-unknown-linux-gnu";
+const ARM: &str = "aarch64-unknown-linux-gnu";
+
+fn plan() -> RustlePlanOutput {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    serde_json::from_str(&content).expect("Failed to parse rustle plan")
+}
+
+fn sample(target: &str, secs: f64, complexity: TemplateComplexity, age: u64) -> CompileSample {
+    CompileSample {
+        target_triple: target.to_string(),
+        compile_time: Duration::from_secs_f64(secs),
+        complexity,
+        built_at: SystemTime::now() - Duration::from_secs(age),
+    }
+}
+
+fn binary(target: &str, compilation_time: Duration) -> CompiledBinary {
+    let data = b"runner";
+    CompiledBinary {
+        binary_id: "binary-test".to_string(),
+        target_triple: target.to_string(),
+        binary_path: PathBuf::from("/nonexistent/rustle-runner"),
+        binary_data: data.to_vec(),
+        effective_source: BinarySource::InMemory,
+        size: data.len() as u64,
+        checksum: format!("{:x}", Sha256::digest(data)),
+        compilation_time,
+        optimization_level: OptimizationLevel::Release,
+        template_hash: "template".to_string(),
+        created_at: Utc::now(),
+        size_report: None,
+    }
+}
+
+fn key(template_hash: &str) -> CacheKey {
+    CacheKey::new(template_hash, GNU, "rustc 1.80.0", &[], "Release")
+}
+
+fn assert_close(actual: Duration, expected: f64) {
+    assert!(
+        (actual.as_secs_f64() - expected).abs() < 0.01,
+        "{actual:?} is not {expected}s"
+    );
+}
+
+#[test]
+fn test_complexity_counts_tasks_and_distinct_modules() {
+    let plan = plan();
+    let complexity = TemplateComplexity::of_plan(&plan);
+    let tasks: usize = plan
+        .plays
+        .iter()
+        .map(|play| play.batches.iter().map(|b| b.tasks.len()).sum::<usize>() + play.handlers.len())
+        .sum();
+    assert_eq!(complexity.tasks, tasks);
+    assert!(complexity.modules > 0 && complexity.modules <= tasks);
+
+    // More modules weigh more than more tasks
+    let more_modules = TemplateComplexity {
+        modules: complexity.modules + 1,
+        ..complexity
+    };
+    let more_tasks = TemplateComplexity {
+        tasks: complexity.tasks + 1,
+        ..complexity
+    };
+    assert!(more_modules.units() > more_tasks.units());
+    assert!(more_tasks.units() > complexity.units());
+}
+
+#[test]
+fn test_estimates_from_the_builds_of_the_target() {
+    let complexity = TemplateComplexity {
+        tasks: 0,
+        modules: 5,
+    };
+    let bigger = TemplateComplexity {
+        tasks: 0,
+        modules: 25,
+    };
+    let estimator = CompileEstimator::new()
+        .with_sample(sample(GNU, 40.0, complexity, 300))
+        .with_sample(sample(GNU, 60.0, complexity, 200))
+        .with_sample(sample(GNU, 1000.0, complexity, 100));
+
+    // The median of the builds, whatever an outlier took
+    let estimate = estimator.estimate_target(complexity, GNU);
+    assert_close(estimate.compile_time, 60.0);
+    assert_eq!(estimate.basis, EstimateBasis::TargetHistory { samples: 3 });
+
+    // Scaled to the complexity of the template
+    let estimate = estimator.estimate_target(bigger, GNU);
+    assert_close(
+        estimate.compile_time,
+        60.0 * bigger.units() / complexity.units(),
+    );
+
+    // Other targets are predicted from the builds there are
+    let estimate = estimator.estimate_target(complexity, ARM);
+    assert_close(estimate.compile_time, 60.0);
+    assert_eq!(estimate.basis, EstimateBasis::OtherTargets { samples: 3 });
+}
+
+#[test]
+fn test_estimates_without_history_from_complexity() {
+    let plan = plan();
+    let estimate = CompileEstimator::new().estimate(&plan, &[GNU.to_string(), ARM.to_string()]);
+
+    assert_eq!(estimate.complexity, TemplateComplexity::of_plan(&plan));
+    assert_eq!(estimate.targets.len(), 2);
+    for target in &estimate.targets {
+        assert_eq!(target.basis, EstimateBasis::Default);
+        assert_close(target.compile_time, 2.0 * estimate.complexity.units());
+    }
+    assert_eq!(
+        estimate.total,
+        estimate.targets[0].compile_time + estimate.targets[1].compile_time
+    );
+}
+
+#[tokio::test]
+async fn test_cache_records_compile_times_of_builds() {
+    let temp_dir = TempDir::new().unwrap();
+    let complexity = TemplateComplexity {
+        tasks: 10,
+        modules: 3,
+    };
+    let mut cache = CompilationCache::new(temp_dir.path().to_path_buf(), true);
+    cache
+        .store_build(
+            &key("built"),
+            &binary(GNU, Duration::from_secs(42)),
+            Some(complexity),
+        )
+        .await
+        .unwrap();
+    // Binaries not compiled here, or of unknown templates, are not recorded
+    cache
+        .store_build(
+            &key("fetched"),
+            &binary(GNU, Duration::ZERO),
+            Some(complexity),
+        )
+        .await
+        .unwrap();
+    cache
+        .store_binary(&key("unknown"), &binary(GNU, Duration::from_secs(7)))
+        .await
+        .unwrap();
+
+    let reopened = CompilationCache::new(temp_dir.path().to_path_buf(), true);
+    let history = reopened.compile_history();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].target_triple, GNU);
+    assert_eq!(history[0].compile_time, Duration::from_secs(42));
+    assert_eq!(history[0].complexity, complexity);
+
+    let estimate = CompileEstimator::from_cache(&reopened).estimate_target(complexity, GNU);
+    assert_close(estimate.compile_time, 42.0);
+}
+
+#[tokio::test]
+async fn test_deployment_estimates_its_selected_targets() {
+    let temp_dir = TempDir::new().unwrap();
+    let plan = plan();
+    let deployment = Deployment::new(plan.clone())
+        .with_targets(vec![GNU.to_string()])
+        .with_compiler_config(CompilerConfig {
+            cache_dir: temp_dir.path().join("missing"),
+            ..Default::default()
+        });
+
+    let estimate = deployment.estimate_compilation().unwrap();
+    let targets: Vec<&str> = estimate
+        .targets
+        .iter()
+        .map(|target| target.target_triple.as_str())
+        .collect();
+    assert_eq!(targets, [GNU]);
+    assert_eq!(estimate.complexity, TemplateComplexity::of_plan(&plan));
+    // Estimating creates no cache
+    assert!(!temp_dir.path().join("missing").exists());
+}
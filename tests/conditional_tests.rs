@@ -0,0 +1,71 @@
+mod helpers;
+
+use helpers::{plan, TaskBuilder};
+use rustle_deploy::runtime::{ExecutionResult, LocalExecutor, RuntimeConfig, TaskStatus};
+
+fn task(id: &str, after: Option<&str>, cmd: &str) -> serde_json::Value {
+    TaskBuilder::command(id, cmd)
+        .after(after.as_slice())
+        .play("site")
+        .continue_on_failure()
+        .build()
+}
+
+async fn execute(tasks: Vec<serde_json::Value>) -> ExecutionResult {
+    let plan = plan("conditionals", serde_json::json!({ "tasks": tasks }));
+    let mut executor = LocalExecutor::new(RuntimeConfig::default())
+        .with_variables([("app_version".to_string(), serde_json::json!("2.10.1"))].into());
+    executor.execute_plan(plan).await.unwrap()
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_when_sees_registered_results() {
+    let mut probe = task("probe", None, "echo ready");
+    probe["register"] = "probe_out".into();
+    let mut upgrade = task("upgrade", Some("probe"), "echo upgrade");
+    upgrade["when"] = serde_json::json!([
+        "probe_out is succeeded and probe_out.stdout is search('ready')",
+        "app_version is version('2.9', '>=')",
+    ]);
+    let mut downgrade = task("downgrade", Some("upgrade"), "echo downgrade");
+    downgrade["when"] =
+        serde_json::json!(["probe_out.rc != 0 or app_version is version('2.0', '<')"]);
+    let mut broken = task("broken", Some("downgrade"), "echo broken");
+    broken["when"] = serde_json::json!(["undefined_var == 1"]);
+
+    let result = execute(vec![probe, upgrade, downgrade, broken]).await;
+    assert_eq!(result.task_results["upgrade"].status, TaskStatus::Success);
+    assert_eq!(
+        result.task_results["upgrade"]
+            .stdout
+            .as_deref()
+            .map(str::trim),
+        Some("upgrade")
+    );
+    assert_eq!(result.task_results["downgrade"].status, TaskStatus::Skipped);
+    // A condition that cannot be evaluated stops the run
+    assert!(result.failed);
+    assert!(result
+        .errors
+        .iter()
+        .any(|error| error.contains("'undefined_var' is undefined")));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_failed_when_expression_uses_the_register_name() {
+    let mut check = task("check", None, "echo ERROR: disk full");
+    check["register"] = "check_out".into();
+    check["failed_when"] = "'ERROR' in check_out.stdout".into();
+    check["changed_when"] = serde_json::json!(["check_out.rc == 0", "false"]);
+
+    let result = execute(vec![check]).await;
+    let check = &result.task_results["check"];
+    assert!(check.failed);
+    assert!(!check.changed);
+    assert_eq!(
+        check.error.as_deref(),
+        Some("failed_when conditions were met")
+    );
+}
@@ -0,0 +1,238 @@
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::modules::{ModuleRegistry, ModuleSearchPath};
+use rustle_deploy::template::{BinaryTemplateGenerator, TargetInfo, TemplateConfig};
+use rustle_deploy::types::platform::Platform;
+use std::path::Path;
+use tempfile::TempDir;
+
+const GREET: &str = r#"
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashMap;
+
+pub async fn execute(args: HashMap<String, Value>) -> Result<Value> {
+    Ok(serde_json::json!({ "changed": false, "msg": args.get("name") }))
+}
+"#;
+
+const STAMP: &str = r#"
+mod format;
+
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashMap;
+
+pub async fn execute(args: HashMap<String, Value>) -> Result<Value> {
+    Ok(serde_json::json!({ "changed": true, "stamp": format::stamp(&args) }))
+}
+"#;
+
+/// A module directory holding `files`
+fn module_dir(files: &[(&str, &str)]) -> TempDir {
+    let dir = TempDir::new().unwrap();
+    for (path, content) in files {
+        let path = dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+fn standard_modules() -> TempDir {
+    module_dir(&[
+        ("greet.rs", GREET),
+        ("stamp/mod.rs", STAMP),
+        (
+            "stamp/format.rs",
+            "pub fn stamp(_: &std::collections::HashMap<String, serde_json::Value>) -> String { String::new() }",
+        ),
+        (
+            "stamp/module.yml",
+            "dependencies:\n  - name: chrono\n    version: \"0.4\"\n    features: [serde]\n  - name: regex\n    version: \"1.10\"\n",
+        ),
+        ("README.md", "not a module"),
+    ])
+}
+
+/// The file operations plan, its first task running `module`
+fn plan_running(module: &str) -> RustlePlanOutput {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    let mut plan: RustlePlanOutput =
+        serde_json::from_str(&content).expect("Failed to parse rustle plan");
+    plan.plays[0].batches[0].tasks[0].module = module.to_string();
+    plan
+}
+
+fn target_info() -> TargetInfo {
+    TargetInfo {
+        target_triple: "x86_64-unknown-linux-gnu".to_string(),
+        platform: Platform::Linux,
+        architecture: "x86_64".to_string(),
+        os_family: "unix".to_string(),
+        libc: Some("glibc".to_string()),
+        features: vec![],
+    }
+}
+
+#[test]
+fn test_discovers_file_and_directory_modules() {
+    let dir = standard_modules();
+
+    let modules = ModuleSearchPath::new()
+        .with_dir(dir.path())
+        .discover()
+        .unwrap();
+
+    let names: Vec<_> = modules.iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(names, ["greet", "stamp"]);
+    assert!(!modules[0].is_directory());
+    assert!(modules[0].dependencies.is_empty());
+
+    let stamp = &modules[1];
+    assert!(stamp.is_directory());
+    assert_eq!(stamp.source.main_file, STAMP);
+    assert!(stamp.source.additional_files.contains_key("format.rs"));
+    let crates: Vec<_> = stamp.dependencies.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(crates, ["chrono", "regex"]);
+    assert_eq!(stamp.dependencies[0].features, ["serde"]);
+    assert!(stamp.dependencies[1].features.is_empty());
+}
+
+#[test]
+fn test_first_directory_on_the_path_wins() {
+    let first = module_dir(&[("greet.rs", GREET)]);
+    let second = module_dir(&[("greet.rs", "this is not checked"), ("other.rs", GREET)]);
+
+    let modules = ModuleSearchPath::new()
+        .with_dir(first.path())
+        .with_dir(second.path())
+        .discover()
+        .unwrap();
+
+    assert_eq!(modules.len(), 2);
+    assert_eq!(modules[0].path, first.path().join("greet.rs"));
+    assert_eq!(modules[1].name, "other");
+}
+
+#[test]
+fn test_modules_without_the_runner_interface_are_refused() {
+    let cases = [
+        ("sync_module.rs", GREET.replace("async fn", "fn")),
+        ("private_module.rs", GREET.replace("pub async", "async")),
+        ("no_execute.rs", GREET.replace("execute", "run")),
+        (
+            "extra_argument.rs",
+            GREET.replace("args: HashMap", "check: bool, args: HashMap"),
+        ),
+        ("broken.rs", "pub async fn execute(".to_string()),
+        ("copy.rs", GREET.to_string()),
+        ("bad-name.rs", GREET.to_string()),
+    ];
+    for (file, source) in cases {
+        let dir = module_dir(&[(file, &source)]);
+        let error = ModuleSearchPath::new()
+            .with_dir(dir.path())
+            .discover()
+            .expect_err(file)
+            .to_string();
+        assert!(error.contains(file), "{file}: {error}");
+    }
+}
+
+#[test]
+fn test_missing_module_directory_is_an_error() {
+    let error = ModuleSearchPath::new()
+        .with_dir("/does/not/exist")
+        .discover()
+        .unwrap_err();
+    assert!(error.to_string().contains("/does/not/exist"));
+}
+
+#[test]
+fn test_registry_exposes_custom_modules() {
+    let dir = standard_modules();
+    let mut registry = ModuleRegistry::with_core_modules();
+    for module in ModuleSearchPath::new()
+        .with_dir(dir.path())
+        .discover()
+        .unwrap()
+    {
+        registry.register_custom(module);
+    }
+
+    assert!(registry.provides("greet"));
+    assert!(registry.provides("debug"));
+    assert!(!registry.provides("missing"));
+    assert!(registry.get_custom_module("stamp").unwrap().is_directory());
+    assert!(registry.get_module("greet").is_none());
+    assert!(registry.list_modules().contains(&"stamp"));
+}
+
+#[tokio::test]
+async fn test_runner_compiles_used_custom_modules_in() {
+    let dir = standard_modules();
+    let modules = ModuleSearchPath::new()
+        .with_dir(dir.path())
+        .discover()
+        .unwrap();
+    let generator = BinaryTemplateGenerator::new(TemplateConfig::default())
+        .unwrap()
+        .with_custom_modules(modules);
+
+    let plan = plan_running("stamp");
+    let template = generator
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &target_info())
+        .await
+        .unwrap();
+
+    let files = &template.source_files;
+    assert_eq!(files[Path::new("src/modules/stamp/mod.rs")], STAMP);
+    assert!(files.contains_key(Path::new("src/modules/stamp/format.rs")));
+    assert!(!files.contains_key(Path::new("src/modules/greet.rs")));
+
+    let main_rs = &files[Path::new("src/main.rs")];
+    assert!(main_rs.contains(r#"const CUSTOM_MODULES: &[&str] = &["stamp", ];"#));
+    assert!(main_rs.contains("pub mod stamp;"));
+    assert!(main_rs.contains(".with_passthrough(CUSTOM_MODULES)"));
+
+    // Declared crates are added, those of builtin modules enabled
+    let cargo_toml = &template.cargo_toml;
+    assert!(cargo_toml.contains(r#"chrono = { version = "0.4", features = ["serde"] }"#));
+    assert!(cargo_toml.contains(r#"regex = { version = "1.10", optional = true }"#));
+    assert!(cargo_toml.contains(r#""dep:regex"]"#));
+}
+
+#[tokio::test]
+async fn test_single_file_module_and_cache_key() {
+    let dir = module_dir(&[("greet.rs", GREET)]);
+    let modules = ModuleSearchPath::new()
+        .with_dir(dir.path())
+        .discover()
+        .unwrap();
+    let plan = plan_running("greet");
+
+    let plain = BinaryTemplateGenerator::new(TemplateConfig::default())
+        .unwrap()
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &target_info())
+        .await
+        .unwrap();
+    let custom = BinaryTemplateGenerator::new(TemplateConfig::default())
+        .unwrap()
+        .with_custom_modules(modules)
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &target_info())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        custom.source_files[Path::new("src/modules/greet.rs")],
+        GREET
+    );
+    assert_ne!(
+        plain.source_files[Path::new("src/modules/greet.rs")],
+        GREET,
+        "without the module path it is a placeholder"
+    );
+    assert_ne!(plain.cache_key, custom.cache_key);
+}
@@ -0,0 +1,1416 @@
+use rustle_deploy::compilation::{CacheStore, LocalStore};
+use rustle_deploy::deploy::manager::DeploymentReport;
+use rustle_deploy::deploy::{
+    BandwidthLimits, DeploymentManager, ExecutionHistory, ExecutionVerificationConfig, HealthCheck,
+    RetryConfig, RetryPolicies, RetryPolicy, RollbackPolicy, Tracer, TransferCache,
+};
+use rustle_deploy::execution::{
+    ExecutionStrategy, HookLocation, HostHook, MaintenanceWindow, PlanFormat, Task,
+};
+use rustle_deploy::runtime::{
+    encode_frame, signature_path, BinarySignature, BinarySigner, DelegatedResult,
+    FaultInjectionConfig, FaultInjector, ProgressEvent, SealedSecrets, SensitiveValues,
+};
+use rustle_deploy::types::{
+    DeploymentConfig, DeploymentMethod, DeploymentPlan, DeploymentStatus, DeploymentStrategy,
+    DeploymentTarget, HostSchedule,
+};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+fn test_config(temp_dir: &TempDir, forks: usize, timeout_secs: u64) -> DeploymentConfig {
+    DeploymentConfig {
+        cache_dir: temp_dir.path().join("cache"),
+        output_dir: temp_dir.path().to_path_buf(),
+        parallel_jobs: 1,
+        forks,
+        default_timeout_secs: timeout_secs,
+        verify_deployments: false,
+        compression: false,
+        strip_symbols: false,
+        binary_size_limit_mb: 0,
+    }
+}
+
+/// Build a plan whose targets "deploy" by running a local shell command
+async fn custom_command_plan(
+    manager: &DeploymentManager,
+    temp_dir: &TempDir,
+    commands: &[&str],
+) -> DeploymentPlan {
+    let content = fs::read_to_string("tests/fixtures/execution_plans/simple_plan.json")
+        .expect("Failed to read test fixture");
+    let mut plan = manager
+        .create_deployment_plan(&content, PlanFormat::Json)
+        .await
+        .expect("Failed to create deployment plan");
+
+    let compilation = &mut plan.binary_compilations[0];
+    compilation.output_path = temp_dir.path().join("rustle-runner");
+    fs::write(&compilation.output_path, b"binary").unwrap();
+    let compilation_id = compilation.compilation_id.clone();
+
+    plan.deployment_targets = commands
+        .iter()
+        .enumerate()
+        .map(|(index, command)| DeploymentTarget {
+            host: format!("host-{index}"),
+            target_path: "/tmp/rustle-runner".to_string(),
+            binary_compilation_id: compilation_id.clone(),
+            deployment_method: DeploymentMethod::Custom {
+                command: command.to_string(),
+            },
+            status: DeploymentStatus::Pending,
+            deployed_at: None,
+            version: String::new(),
+            connection: Default::default(),
+        })
+        .collect();
+
+    plan
+}
+
+#[tokio::test]
+async fn test_deploy_binaries_runs_hosts_concurrently() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager = DeploymentManager::new(test_config(&temp_dir, 4, 30));
+    let plan = custom_command_plan(&manager, &temp_dir, &["sleep 0.5"; 4]).await;
+
+    let start = Instant::now();
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+
+    assert_eq!(report.successful_deployments, 4);
+    assert_eq!(report.failed_deployments, 0);
+    assert!(
+        start.elapsed() < Duration::from_millis(1500),
+        "Deployments did not overlap: {:?}",
+        start.elapsed()
+    );
+}
+
+#[tokio::test]
+async fn test_deploy_binaries_preserves_per_host_status() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager = DeploymentManager::new(test_config(&temp_dir, 2, 30));
+    let plan = custom_command_plan(&manager, &temp_dir, &["sleep 0.2", "exit 1", "true"]).await;
+
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+
+    assert_eq!(report.successful_deployments, 2);
+    assert_eq!(report.failed_deployments, 1);
+
+    let hosts: Vec<_> = report
+        .deployment_results
+        .iter()
+        .map(|r| r.host.as_str())
+        .collect();
+    assert_eq!(hosts, ["host-0", "host-1", "host-2"]);
+    assert!(matches!(
+        report.deployment_results[1].status,
+        DeploymentStatus::Failed { .. }
+    ));
+    assert!(report.deployment_results[1].deployed_at.is_none());
+}
+
+#[tokio::test]
+async fn test_deploy_binaries_enforces_per_host_timeout() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager = DeploymentManager::new(test_config(&temp_dir, 2, 1));
+    let plan = custom_command_plan(&manager, &temp_dir, &["sleep 5", "true"]).await;
+
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+
+    match &report.deployment_results[0].status {
+        DeploymentStatus::Failed { error } => assert!(error.contains("timeout"), "{error}"),
+        other => panic!("Expected timeout failure, got {other:?}"),
+    }
+    assert!(matches!(
+        report.deployment_results[1].status,
+        DeploymentStatus::Deployed
+    ));
+}
+
+#[tokio::test]
+async fn test_injected_faults_fail_only_targeted_hosts() {
+    let temp_dir = TempDir::new().unwrap();
+    let faults =
+        FaultInjector::new(&FaultInjectionConfig::parse("partition@host-0,crash@host-1").unwrap());
+    let manager = DeploymentManager::new(test_config(&temp_dir, 2, 30)).with_fault_injector(faults);
+
+    let marker = temp_dir.path().join("host-1-transferred");
+    let transfer = format!("touch {}", marker.display());
+    let plan = custom_command_plan(&manager, &temp_dir, &["true", &transfer, "true"]).await;
+
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+
+    let errors: Vec<_> = report
+        .deployment_results
+        .iter()
+        .map(|r| match &r.status {
+            DeploymentStatus::Failed { error } => error.as_str(),
+            _ => "",
+        })
+        .collect();
+    assert!(
+        errors[0].contains("injected network partition"),
+        "{}",
+        errors[0]
+    );
+    assert!(errors[1].contains("injected crash"), "{}", errors[1]);
+    assert_eq!(errors[2], "");
+
+    // The crash happens after the transfer, leaving its side effects behind
+    assert!(marker.exists());
+}
+
+#[tokio::test]
+async fn test_transient_failures_are_retried() {
+    let temp_dir = TempDir::new().unwrap();
+    let faults = FaultInjector::new(&FaultInjectionConfig::parse("partition@host-0*2").unwrap());
+    let fast = RetryPolicy {
+        initial_delay: Duration::from_millis(1),
+        ..Default::default()
+    };
+    let retry = RetryConfig {
+        default: RetryPolicies {
+            connect: fast.clone(),
+            transfer: fast.clone(),
+            execute: fast,
+        },
+        ..Default::default()
+    }
+    .with_host("host-1", RetryPolicies::none());
+    let manager = DeploymentManager::new(test_config(&temp_dir, 2, 30))
+        .with_fault_injector(faults)
+        .with_retry_config(retry)
+        .with_rollback_policy(RollbackPolicy::disabled());
+    let plan = custom_command_plan(&manager, &temp_dir, &["true", "true"]).await;
+
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+
+    let results = &report.deployment_results;
+    assert!(matches!(results[0].status, DeploymentStatus::Deployed));
+    assert_eq!(results[0].retries.transfer, 2);
+    assert_eq!(results[1].retries.total(), 0);
+    assert_eq!(report.total_retries(), 2);
+}
+
+#[tokio::test]
+async fn test_deploy_checks_binaries_against_manifest() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager = DeploymentManager::new(test_config(&temp_dir, 1, 30))
+        .with_provenance()
+        .with_manifest_verification();
+
+    let marker = temp_dir.path().join("pushed");
+    let push = format!("touch {}", marker.display());
+    let plan = custom_command_plan(&manager, &temp_dir, &[&push]).await;
+
+    // Nothing built yet, so there is no manifest to deploy from
+    assert!(manager.deploy_binaries(&plan).await.is_err());
+
+    let manifest = manager
+        .write_manifest(&plan, &plan.binary_compilations)
+        .unwrap();
+    assert_eq!(manifest.artifacts.len(), 1);
+    assert_eq!(manifest.artifacts[0].name, "rustle-runner");
+    let provenance: serde_json::Value =
+        serde_json::from_slice(&fs::read(temp_dir.path().join("rustle-provenance.json")).unwrap())
+            .unwrap();
+    assert_eq!(
+        provenance["subject"][0]["digest"]["sha256"],
+        manifest.artifacts[0].sha256
+    );
+
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+    assert_eq!(report.successful_deployments, 1);
+    assert!(marker.exists());
+
+    fs::remove_file(&marker).unwrap();
+    fs::write(&plan.binary_compilations[0].output_path, b"swapped").unwrap();
+    let error = manager.deploy_binaries(&plan).await.unwrap_err();
+    assert!(error.to_string().contains("does not match the manifest"));
+    assert!(!marker.exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_local_connection_deploys_and_verifies() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = DeploymentConfig {
+        verify_deployments: true,
+        ..test_config(&temp_dir, 2, 30)
+    };
+    let manager = DeploymentManager::new(config);
+    let mut plan = custom_command_plan(&manager, &temp_dir, &["true"]).await;
+
+    let runner = b"#!/bin/sh\necho rustle-runner 1.0.0\n";
+    fs::write(&plan.binary_compilations[0].output_path, runner).unwrap();
+    plan.binary_compilations[0].target_triple = host_triple();
+    let target_path = temp_dir.path().join("deployed/bin/rustle-runner");
+    plan.deployment_targets[0].target_path = target_path.display().to_string();
+    plan.deployment_targets[0].deployment_method = DeploymentMethod::Local;
+
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+
+    assert_eq!(
+        report.successful_deployments, 1,
+        "{:?}",
+        report.deployment_results
+    );
+    assert_eq!(fs::read(&target_path).unwrap(), runner);
+    let leftovers: Vec<_> = fs::read_dir(target_path.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(leftovers, ["rustle-runner"]);
+}
+
+fn pseudo_random(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as u8
+        })
+        .collect()
+}
+
+/// Triple matching the controller, which local deployments are checked against
+fn host_triple() -> String {
+    match std::env::consts::OS {
+        "macos" => format!("{}-apple-darwin", std::env::consts::ARCH),
+        _ => format!("{}-unknown-linux-gnu", std::env::consts::ARCH),
+    }
+}
+
+/// A single local target that receives `binary` at `<temp>/deployed/rustle-runner`
+async fn local_plan(
+    manager: &DeploymentManager,
+    temp_dir: &TempDir,
+    binary: &[u8],
+) -> DeploymentPlan {
+    let mut plan = custom_command_plan(manager, temp_dir, &["true"]).await;
+    fs::write(&plan.binary_compilations[0].output_path, binary).unwrap();
+    plan.binary_compilations[0].target_triple = host_triple();
+    plan.deployment_targets[0].target_path = temp_dir
+        .path()
+        .join("deployed/rustle-runner")
+        .display()
+        .to_string();
+    plan.deployment_targets[0].deployment_method = DeploymentMethod::Local;
+    plan
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_redeploy_sends_only_changed_chunks() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager = DeploymentManager::new(test_config(&temp_dir, 1, 30));
+    let transfers = TransferCache::new(temp_dir.path().join("cache/transfers"));
+
+    let v1 = pseudo_random(512 * 1024, 7);
+    let plan = local_plan(&manager, &temp_dir, &v1).await;
+    let target = plan.deployment_targets[0].clone();
+    assert_eq!(
+        manager
+            .deploy_binaries(&plan)
+            .await
+            .unwrap()
+            .successful_deployments,
+        1
+    );
+    let record = transfers
+        .record(&target.host, &target.target_path)
+        .await
+        .unwrap();
+    assert_eq!(record.transferred_bytes, v1.len() as u64);
+
+    let mut v2 = v1.clone();
+    v2.splice(100_000..100_000, b"patched".iter().copied());
+    let plan = local_plan(&manager, &temp_dir, &v2).await;
+    assert_eq!(
+        manager
+            .deploy_binaries(&plan)
+            .await
+            .unwrap()
+            .successful_deployments,
+        1
+    );
+
+    assert_eq!(fs::read(&target.target_path).unwrap(), v2);
+    let record = transfers
+        .record(&target.host, &target.target_path)
+        .await
+        .unwrap();
+    assert!(
+        record.transferred_bytes < v2.len() as u64 / 4,
+        "sent {} bytes",
+        record.transferred_bytes
+    );
+    assert!(record.pending.is_none());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_interrupted_transfer_resumes() {
+    let temp_dir = TempDir::new().unwrap();
+    let faults = FaultInjector::new(&FaultInjectionConfig::parse("crash*1").unwrap());
+    let manager = DeploymentManager::new(test_config(&temp_dir, 1, 30)).with_fault_injector(faults);
+    let transfers = TransferCache::new(temp_dir.path().join("cache/transfers"));
+
+    let binary = pseudo_random(5 * 1024 * 1024, 11);
+    let plan = local_plan(&manager, &temp_dir, &binary).await;
+    let target = plan.deployment_targets[0].clone();
+
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+    assert_eq!(report.failed_deployments, 1);
+    let record = transfers
+        .record(&target.host, &target.target_path)
+        .await
+        .unwrap();
+    assert_eq!(record.pending.unwrap().completed_segments, [0, 1, 2]);
+
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+    assert_eq!(report.successful_deployments, 1);
+    assert_eq!(fs::read(&target.target_path).unwrap(), binary);
+    let record = transfers
+        .record(&target.host, &target.target_path)
+        .await
+        .unwrap();
+    assert_eq!(record.transferred_bytes, 0);
+    assert!(record.pending.is_none());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_binaries_are_compressed_in_transfer() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager =
+        DeploymentManager::new(test_config(&temp_dir, 1, 30)).with_transfer_compression(3);
+    let transfers = TransferCache::new(temp_dir.path().join("cache/transfers"));
+
+    let binary = b"rustle runner section\n".repeat(150_000);
+    let plan = local_plan(&manager, &temp_dir, &binary).await;
+    let target = plan.deployment_targets[0].clone();
+
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+    assert_eq!(report.successful_deployments, 1);
+    assert_eq!(fs::read(&target.target_path).unwrap(), binary);
+    let record = transfers
+        .record(&target.host, &target.target_path)
+        .await
+        .unwrap();
+    assert!(
+        record.transferred_bytes < binary.len() as u64 / 20,
+        "sent {} bytes",
+        record.transferred_bytes
+    );
+    let leftovers: Vec<_> = fs::read_dir(temp_dir.path().join("deployed"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(leftovers, ["rustle-runner"]);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_transfers_respect_bandwidth_limits() {
+    let temp_dir = TempDir::new().unwrap();
+    // Two segments; the second waits until the first has had its share
+    let binary = pseudo_random(3 * 1024 * 1024, 13);
+    let manager = DeploymentManager::new(test_config(&temp_dir, 1, 30))
+        .with_bandwidth_limits(BandwidthLimits::default().per_host(4 * 1024 * 1024));
+    let plan = local_plan(&manager, &temp_dir, &binary).await;
+
+    let started = Instant::now();
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+    assert_eq!(report.successful_deployments, 1);
+    assert!(
+        started.elapsed() >= Duration::from_millis(500),
+        "took {:?}",
+        started.elapsed()
+    );
+    assert_eq!(
+        fs::read(&plan.deployment_targets[0].target_path).unwrap(),
+        binary
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_incompatible_binary_is_not_deployed() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager = DeploymentManager::new(test_config(&temp_dir, 1, 30));
+    let mut plan = local_plan(&manager, &temp_dir, b"riscv binary").await;
+    plan.binary_compilations[0].target_triple = "riscv64gc-unknown-none-elf".to_string();
+    let target = plan.deployment_targets[0].clone();
+
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+    assert_eq!(report.failed_deployments, 1);
+    match &report.deployment_results[0].status {
+        DeploymentStatus::Failed { error } => {
+            assert!(error.contains("not compatible"), "{error}")
+        }
+        status => panic!("unexpected status {status:?}"),
+    }
+    assert!(!std::path::Path::new(&target.target_path).exists());
+
+    // A compatible build in the same plan is picked instead
+    let mut native = plan.binary_compilations[0].clone();
+    native.compilation_id = "native".to_string();
+    native.target_triple = host_triple();
+    native.output_path = temp_dir.path().join("rustle-runner-native");
+    fs::write(&native.output_path, b"native binary").unwrap();
+    plan.binary_compilations.push(native);
+
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+    assert_eq!(report.successful_deployments, 1);
+    assert_eq!(fs::read(&target.target_path).unwrap(), b"native binary");
+}
+
+/// A task result as reported by a runner
+fn task_json(task: &str, failed: bool, output: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "task_id": task, "name": task, "status": if failed { "Failed" } else { "Success" },
+        "changed": true, "failed": failed, "skipped": false,
+        "output": output, "stdout": null, "stderr": null,
+        "start_time": "2024-01-01T00:00:00Z", "end_time": "2024-01-01T00:00:01Z",
+        "duration": {"secs": 1, "nanos": 0}, "error": null,
+    })
+}
+
+/// An execution result as reported by a runner that ran `tasks`
+fn execution_result(tasks: Vec<serde_json::Value>) -> serde_json::Value {
+    let failed = tasks.iter().filter(|task| task["failed"] == true).count();
+    let task_results: serde_json::Map<String, serde_json::Value> = tasks
+        .into_iter()
+        .map(|task| (task["task_id"].as_str().unwrap().to_string(), task))
+        .collect();
+    serde_json::json!({
+        "execution_id": "run-1", "success": failed == 0, "failed": failed > 0,
+        "summary": {
+            "total_tasks": task_results.len(), "completed_tasks": task_results.len() - failed,
+            "failed_tasks": failed, "skipped_tasks": 0, "changed_tasks": task_results.len(),
+        },
+        "task_results": task_results,
+        "start_time": "2024-01-01T00:00:00Z", "end_time": "2024-01-01T00:00:01Z",
+        "duration": {"secs": 1, "nanos": 0}, "errors": [],
+    })
+}
+
+/// A runner script that runs `prelude` and prints an execution result of `tasks`
+fn runner_script(prelude: &str, tasks: Vec<serde_json::Value>) -> Vec<u8> {
+    let result = execution_result(tasks);
+    let exit_code = i32::from(result["failed"] == true);
+    format!("#!/bin/sh\necho starting >&2\n{prelude}\necho '{result}'\nexit {exit_code}\n")
+        .into_bytes()
+}
+
+/// A runner script that prints a successful execution result covering `tasks`
+fn reporting_runner(tasks: &[&str]) -> Vec<u8> {
+    let tasks = tasks
+        .iter()
+        .map(|task| task_json(task, false, serde_json::Value::Null))
+        .collect();
+    runner_script("", tasks)
+}
+
+fn status_error(report: &DeploymentReport) -> &str {
+    match &report.deployment_results[0].status {
+        DeploymentStatus::Failed { error } => error.as_str(),
+        status => panic!("unexpected status {status:?}"),
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_only_signed_binaries_are_deployed_and_run() {
+    let temp_dir = TempDir::new().unwrap();
+    let signer = BinarySigner::from_seed(&[3u8; 32]);
+    let manager = DeploymentManager::new(test_config(&temp_dir, 1, 30))
+        .with_history(ExecutionHistory::new(temp_dir.path().join("history")))
+        .with_rollback_policy(RollbackPolicy::disabled())
+        .with_binary_signing(signer.clone());
+
+    let mut plan = local_plan(&manager, &temp_dir, &reporting_runner(&["install"])).await;
+    plan.binary_compilations[0].source_tasks = vec!["install".to_string()];
+    let binary = plan.binary_compilations[0].output_path.clone();
+    let deployed = temp_dir.path().join("deployed/rustle-runner");
+
+    // Unsigned binaries never leave the controller
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+    assert!(status_error(&report).contains("Signature check"));
+    assert!(!deployed.exists());
+
+    signer.sign_file(&binary).unwrap();
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+    assert_eq!(report.successful_deployments, 1);
+    assert!(temp_dir
+        .path()
+        .join("deployed/rustle-runner.minisig")
+        .exists());
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    assert_eq!(report.successful_deployments, 1);
+
+    // A binary changed on the host after deployment is not run
+    let mut tampered = fs::read(&deployed).unwrap();
+    tampered.extend_from_slice(b"\necho tampered\n");
+    fs::write(&deployed, &tampered).unwrap();
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    assert!(status_error(&report).contains("deployed binary has checksum"));
+
+    // Neither is a binary changed on the controller after signing
+    fs::write(&binary, &tampered).unwrap();
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+    assert!(status_error(&report).contains("Signature check"));
+}
+
+#[tokio::test]
+async fn test_binaries_are_signed_in_a_new_output_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let store: Arc<dyn CacheStore> = Arc::new(LocalStore::new(temp_dir.path().join("shared")));
+    let signer = BinarySigner::from_seed(&[3u8; 32]);
+    let manager = DeploymentManager::new(test_config(&temp_dir, 1, 30))
+        .with_cache_store(store.clone())
+        .with_binary_signing(signer.clone());
+
+    let mut plan = custom_command_plan(&manager, &temp_dir, &["true"]).await;
+    let output = temp_dir.path().join("out/runners/rustle-runner");
+    plan.binary_compilations[0].output_path = output.clone();
+    // Publish the binary under the compiler's cache key so nothing is built
+    let binary = reporting_runner(&["install"]);
+    let cache_key = format!(
+        "{:x}",
+        Sha256::digest(plan.binary_compilations[0].checksum.as_bytes())
+    );
+    store
+        .put_artifact(
+            &cache_key,
+            &binary,
+            &plan.binary_compilations[0].target_triple,
+        )
+        .await
+        .unwrap();
+
+    manager.compile_binaries(&plan).await.unwrap();
+    assert_eq!(fs::read(&output).unwrap(), binary);
+    let signature = fs::read_to_string(signature_path(&output)).unwrap();
+    let signature = BinarySignature::parse(&signature).unwrap();
+    signer.public_key().verify(&binary, &signature).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_execution_results_are_verified() {
+    let temp_dir = TempDir::new().unwrap();
+    let marker = temp_dir.path().join("healthy");
+    let config = ExecutionVerificationConfig {
+        health_checks: vec![HealthCheck::new(
+            "marker",
+            &format!("test -f {}", marker.display()),
+        )],
+        ..Default::default()
+    };
+    let manager = DeploymentManager::new(test_config(&temp_dir, 1, 30))
+        .with_history(ExecutionHistory::new(temp_dir.path().join("history")))
+        .with_execution_verification(config);
+
+    let mut plan = local_plan(
+        &manager,
+        &temp_dir,
+        &reporting_runner(&["install", "start"]),
+    )
+    .await;
+    plan.binary_compilations[0].source_tasks = vec!["install".to_string(), "start".to_string()];
+    assert_eq!(
+        manager
+            .deploy_binaries(&plan)
+            .await
+            .unwrap()
+            .successful_deployments,
+        1
+    );
+
+    fs::write(&marker, b"").unwrap();
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    let result = &report.deployment_results[0];
+    assert_eq!(report.successful_deployments, 1, "{result:?}");
+    let verification = result.verification.as_ref().unwrap();
+    assert_eq!(verification.result.as_ref().unwrap().summary.total_tasks, 2);
+    assert!(verification.health_checks[0].passed);
+    assert!(temp_dir.path().join("history/runs.jsonl").exists());
+
+    // The plan grew a task the binary never ran, and the host is unhealthy
+    plan.binary_compilations[0]
+        .source_tasks
+        .push("verify".to_string());
+    fs::remove_file(&marker).unwrap();
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    assert_eq!(report.failed_deployments, 1);
+    match &report.deployment_results[0].status {
+        DeploymentStatus::Failed { error } => {
+            assert!(
+                error.contains("plan has 3 tasks but 2 were reported"),
+                "{error}"
+            );
+            assert!(
+                error.contains("tasks missing from the result: verify"),
+                "{error}"
+            );
+            assert!(error.contains("health check 'marker' failed"), "{error}");
+        }
+        status => panic!("unexpected status {status:?}"),
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_failed_deploy_rolls_back_other_hosts() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager = DeploymentManager::new(test_config(&temp_dir, 2, 30));
+    let two_hosts = |mut plan: DeploymentPlan| {
+        let mut second = plan.deployment_targets[0].clone();
+        second.host = "host-1".to_string();
+        second.target_path = temp_dir.path().join("second/runner").display().to_string();
+        plan.deployment_targets.push(second);
+        plan
+    };
+
+    let v1 = two_hosts(local_plan(&manager, &temp_dir, b"v1").await);
+    assert_eq!(
+        manager
+            .deploy_binaries(&v1)
+            .await
+            .unwrap()
+            .successful_deployments,
+        2
+    );
+    let first_path = v1.deployment_targets[0].target_path.clone();
+
+    // host-1 can no longer be written to, so the whole deployment is reverted
+    fs::remove_dir_all(temp_dir.path().join("second")).unwrap();
+    fs::write(temp_dir.path().join("second"), b"not a directory").unwrap();
+    let v2 = two_hosts(local_plan(&manager, &temp_dir, b"v2").await);
+    let report = manager.deploy_binaries(&v2).await.unwrap();
+
+    assert_eq!(report.failed_deployments, 2);
+    assert!(matches!(
+        report.deployment_results[0].status,
+        DeploymentStatus::RolledBack
+    ));
+    assert!(matches!(
+        report.deployment_results[1].status,
+        DeploymentStatus::Failed { .. }
+    ));
+    assert!(report.rollback.as_ref().unwrap().hosts[0].restored_binary);
+    assert_eq!(fs::read(&first_path).unwrap(), b"v1");
+
+    let manager = manager.with_rollback_policy(RollbackPolicy::disabled());
+    let report = manager.deploy_binaries(&v2).await.unwrap();
+    assert_eq!(report.failed_deployments, 1);
+    assert!(report.rollback.is_none());
+    assert_eq!(fs::read(&first_path).unwrap(), b"v2");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_failed_execution_restores_backups_and_reruns_previous() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager = DeploymentManager::new(test_config(&temp_dir, 1, 30))
+        .with_history(ExecutionHistory::new(temp_dir.path().join("history")));
+    let conf = temp_dir.path().join("app.conf");
+    let runs = temp_dir.path().join("runs.log");
+    fs::write(&conf, "v1").unwrap();
+
+    let v1 = runner_script(
+        &format!("echo v1 >> {}", runs.display()),
+        vec![task_json("install", false, serde_json::Value::Null)],
+    );
+    let mut plan = local_plan(&manager, &temp_dir, &v1).await;
+    plan.binary_compilations[0].source_tasks = vec!["install".to_string()];
+    manager.deploy_binaries(&plan).await.unwrap();
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    assert_eq!(report.successful_deployments, 1);
+
+    // v2 backs up and rewrites the config, then fails
+    let backup = temp_dir.path().join("app.conf.backup");
+    let v2 = runner_script(
+        &format!(
+            "echo v2 >> {runs}\ncp {conf} {backup}\necho v2 > {conf}",
+            runs = runs.display(),
+            conf = conf.display(),
+            backup = backup.display()
+        ),
+        vec![task_json(
+            "install",
+            true,
+            serde_json::json!({"dest": conf, "backup_file": backup}),
+        )],
+    );
+    let mut plan = local_plan(&manager, &temp_dir, &v2).await;
+    plan.binary_compilations[0].source_tasks = vec!["install".to_string()];
+    assert_eq!(
+        manager
+            .deploy_binaries(&plan)
+            .await
+            .unwrap()
+            .successful_deployments,
+        1
+    );
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+
+    assert_eq!(report.failed_deployments, 1);
+    let rollback = report.rollback.unwrap();
+    assert_eq!(rollback.hosts[0].restored_files, 1);
+    assert_eq!(rollback.hosts[0].rerun_success, Some(true));
+    assert!(rollback.is_complete());
+    assert_eq!(fs::read_to_string(&conf).unwrap(), "v1");
+    assert_eq!(
+        fs::read(&plan.deployment_targets[0].target_path).unwrap(),
+        v1
+    );
+    assert_eq!(fs::read_to_string(&runs).unwrap(), "v1\nv2\nv1\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_runner_events_are_streamed_to_the_controller() {
+    let temp_dir = TempDir::new().unwrap();
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let manager = DeploymentManager::new(test_config(&temp_dir, 1, 30))
+        .with_history(ExecutionHistory::new(temp_dir.path().join("history")))
+        .with_event_sink(sender);
+
+    let task = task_json("install", false, serde_json::Value::Null);
+    let events: Vec<ProgressEvent> = vec![
+        serde_json::from_value(serde_json::json!({
+            "type": "TaskStarted", "execution_id": "run-1",
+            "task_id": "install", "task_name": "install",
+        }))
+        .unwrap(),
+        serde_json::from_value(serde_json::json!({
+            "type": "TaskCompleted", "execution_id": "run-1", "task_result": task.clone(),
+        }))
+        .unwrap(),
+        serde_json::from_value(serde_json::json!({
+            "type": "ExecutionCompleted", "execution_id": "run-1",
+            "result": execution_result(vec![task]),
+        }))
+        .unwrap(),
+    ];
+    // Frames are only written when the deployer asks for them, between log lines
+    let frames: String = events
+        .iter()
+        .map(|event| {
+            format!(
+                "echo '{}'\necho working\n",
+                encode_frame(event).unwrap().trim_end()
+            )
+        })
+        .collect();
+    let runner =
+        format!("#!/bin/sh\n[ \"$RUSTLE_EVENT_STREAM\" = 1 ] || exit 3\necho starting\n{frames}");
+
+    let mut plan = local_plan(&manager, &temp_dir, runner.as_bytes()).await;
+    plan.binary_compilations[0].source_tasks = vec!["install".to_string()];
+    manager.deploy_binaries(&plan).await.unwrap();
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+
+    // The final summary frame doubles as the result that is verified
+    let result = &report.deployment_results[0];
+    assert_eq!(report.successful_deployments, 1, "{result:?}");
+    let verified = result
+        .verification
+        .as_ref()
+        .unwrap()
+        .result
+        .as_ref()
+        .unwrap();
+    assert_eq!(verified.summary.total_tasks, 1);
+
+    let mut received = Vec::new();
+    while let Ok(event) = receiver.try_recv() {
+        received.push(event);
+    }
+    assert_eq!(received.len(), 3);
+    assert!(received
+        .iter()
+        .all(|event| event.host == plan.deployment_targets[0].host));
+    assert!(received[0].progress_line().ends_with("install ..."));
+    assert!(received[1].progress_line().contains("install: changed"));
+    assert!(matches!(
+        received[2].event,
+        ProgressEvent::ExecutionCompleted { .. }
+    ));
+    assert!(received[2].to_json_line().unwrap().starts_with("{\"host\""));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_runner_spans_join_the_deployment_trace() {
+    let temp_dir = TempDir::new().unwrap();
+    let tracer = Arc::new(Tracer::new("traced"));
+    let manager = DeploymentManager::new(test_config(&temp_dir, 1, 30))
+        .with_history(ExecutionHistory::new(temp_dir.path().join("history")))
+        .with_tracer(Arc::clone(&tracer));
+
+    let traceparent = temp_dir.path().join("traceparent");
+    let mut result = execution_result(vec![task_json("install", false, serde_json::Value::Null)]);
+    let remote = serde_json::json!({
+        "trace_id": "4bf92f3577b34da6a3ce929d0e0e4736", "span_id": "00f067aa0ba902b7",
+        "parent_span_id": null, "name": "install",
+        "start_time": "2024-01-01T00:00:00Z", "end_time": "2024-01-01T00:00:01Z",
+    });
+    result["spans"] = serde_json::json!([remote]);
+    let runner = format!(
+        "#!/bin/sh\necho \"$TRACEPARENT\" > {}\necho '{result}'\n",
+        traceparent.display()
+    );
+
+    let mut plan = local_plan(&manager, &temp_dir, runner.as_bytes()).await;
+    plan.binary_compilations[0].source_tasks = vec!["install".to_string()];
+    manager.deploy_binaries(&plan).await.unwrap();
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    assert_eq!(report.successful_deployments, 1);
+
+    let spans = tracer.finish();
+    let names: Vec<_> = spans.iter().map(|span| span.name.as_str()).collect();
+    assert_eq!(names[0], "deployment traced");
+    assert!(names.contains(&"deploy host-0"), "{names:?}");
+    let execute = spans
+        .iter()
+        .find(|span| span.name == "execute host-0")
+        .unwrap();
+    assert_eq!(execute.parent_span_id.as_ref(), Some(&spans[0].span_id));
+    assert!(execute.error.is_none());
+
+    // The runner was handed the execution span, and what it shipped back
+    // joined the trace
+    assert_eq!(
+        fs::read_to_string(&traceparent).unwrap().trim(),
+        execute.context().traceparent()
+    );
+    assert!(spans.iter().any(|span| span.span_id == "00f067aa0ba902b7"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_secrets_are_sealed_out_of_embedded_plans() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().display().to_string();
+    let mut sensitive = SensitiveValues::new();
+    sensitive.insert("curl");
+    let manager = DeploymentManager::new(test_config(&temp_dir, 1, 30))
+        .with_history(ExecutionHistory::new(temp_dir.path().join("history")))
+        .with_sensitive_values(sensitive)
+        .unwrap();
+
+    // The runner keeps what it was handed, as a real one opens it
+    let prelude = format!(
+        "cp \"$RUSTLE_SECRETS_FILE\" {dir}/sealed.json\n\
+         cp \"$RUSTLE_SECRETS_KEY_FILE\" {dir}/key\n\
+         env > {dir}/env\n\
+         rm \"$RUSTLE_SECRETS_FILE\" \"$RUSTLE_SECRETS_KEY_FILE\""
+    );
+    let runner = runner_script(
+        &prelude,
+        vec![task_json("install", false, serde_json::Value::Null)],
+    );
+    let mut plan = local_plan(&manager, &temp_dir, &runner).await;
+    let embedded = &plan.binary_compilations[0].embedded_data.execution_plan;
+    assert!(!embedded.contains("curl"), "{embedded}");
+    assert!(embedded.contains("{{ sealed_secret('0') }}"), "{embedded}");
+
+    plan.binary_compilations[0].source_tasks = vec!["install".to_string()];
+    manager.deploy_binaries(&plan).await.unwrap();
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    assert_eq!(report.successful_deployments, 1);
+
+    let sealed = fs::read_to_string(temp_dir.path().join("sealed.json")).unwrap();
+    assert!(!sealed.contains("curl"));
+    let sealed: SealedSecrets = serde_json::from_str(&sealed).unwrap();
+    let key = fs::read_to_string(temp_dir.path().join("key")).unwrap();
+    let secrets = sealed.open(&key).unwrap();
+    // The key is handed over in a file, never on the command line
+    let env = fs::read_to_string(temp_dir.path().join("env")).unwrap();
+    assert!(!env.contains(key.trim()));
+    assert_eq!(
+        secrets
+            .resolve(&serde_json::json!({ "name": "{{ sealed_secret('0') }}" }))
+            .unwrap(),
+        serde_json::json!({ "name": "curl" })
+    );
+    // Nothing is left next to the runner
+    let leftovers: Vec<_> = fs::read_dir(temp_dir.path().join("deployed"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.contains(".secrets-"))
+        .collect();
+    assert!(leftovers.is_empty(), "{leftovers:?}");
+}
+
+fn hook(command: String, run_on: HookLocation) -> HostHook {
+    HostHook {
+        command,
+        run_on,
+        groups: vec![],
+        timeout_secs: Some(10),
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_hooks_run_around_execution() {
+    let temp_dir = TempDir::new().unwrap();
+    let log = temp_dir.path().join("hooks.log");
+    let manager = DeploymentManager::new(test_config(&temp_dir, 1, 30))
+        .with_history(ExecutionHistory::new(temp_dir.path().join("history")))
+        .with_rollback_policy(RollbackPolicy::disabled());
+
+    let runner = runner_script(
+        &format!("echo run >> {}", log.display()),
+        vec![task_json("install", false, serde_json::Value::Null)],
+    );
+    let mut plan = local_plan(&manager, &temp_dir, &runner).await;
+    plan.binary_compilations[0].source_tasks = vec!["install".to_string()];
+    plan.schedule.hooks.pre_execution = vec![hook(
+        format!("echo \"drain $RUSTLE_HOST\" >> {}", log.display()),
+        HookLocation::Controller,
+    )];
+    plan.schedule.hooks.post_execution = vec![hook(
+        format!("echo undrain {{host}} >> {}", log.display()),
+        HookLocation::Host,
+    )];
+    manager.deploy_binaries(&plan).await.unwrap();
+
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    assert_eq!(report.successful_deployments, 1, "{report:?}");
+    assert_eq!(
+        fs::read_to_string(&log).unwrap(),
+        "drain host-0\nrun\nundrain host-0\n"
+    );
+
+    // A failing pre-execution hook keeps the binary from running
+    plan.schedule.hooks.pre_execution = vec![hook("exit 4".to_string(), HookLocation::Controller)];
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    assert!(status_error(&report).contains("Hook `exit 4` failed"));
+    assert_eq!(
+        fs::read_to_string(&log).unwrap(),
+        "drain host-0\nrun\nundrain host-0\n"
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_hosts_are_only_deployed_inside_maintenance_windows() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager = DeploymentManager::new(test_config(&temp_dir, 1, 30));
+    let mut plan = local_plan(&manager, &temp_dir, b"runner").await;
+    let target_path = plan.deployment_targets[0].target_path.clone();
+
+    let now = chrono::Utc::now();
+    let window = |from: chrono::Duration, to: chrono::Duration| MaintenanceWindow {
+        groups: vec!["all".to_string()],
+        start: (now + from).format("%H:%M").to_string(),
+        end: (now + to).format("%H:%M").to_string(),
+        days: vec![],
+    };
+    plan.schedule = HostSchedule {
+        maintenance_windows: vec![window(
+            chrono::Duration::hours(2),
+            chrono::Duration::hours(3),
+        )],
+        ..Default::default()
+    };
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+    assert!(status_error(&report).contains("outside its maintenance window"));
+    assert!(!std::path::Path::new(&target_path).exists());
+
+    plan.schedule.maintenance_windows = vec![window(
+        chrono::Duration::hours(-1),
+        chrono::Duration::hours(1),
+    )];
+    let report = manager.deploy_binaries(&plan).await.unwrap();
+    assert_eq!(report.successful_deployments, 1);
+    assert_eq!(fs::read(&target_path).unwrap(), b"runner");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_serial_batches_stop_after_too_many_failures() {
+    let temp_dir = TempDir::new().unwrap();
+    let log = temp_dir.path().join("runs.log");
+    let manager = DeploymentManager::new(test_config(&temp_dir, 4, 30))
+        .with_history(ExecutionHistory::new(temp_dir.path().join("history")))
+        .with_rollback_policy(RollbackPolicy::disabled());
+
+    let runner = runner_script(
+        &format!(
+            "echo \"$RUSTLE_HOST_ID\" >> {}\n[ \"$RUSTLE_HOST_ID\" = host-1 ] && exit 1",
+            log.display()
+        ),
+        vec![task_json("install", false, serde_json::Value::Null)],
+    );
+    let mut plan = local_plan(&manager, &temp_dir, &runner).await;
+    plan.binary_compilations[0].source_tasks = vec!["install".to_string()];
+    let first = plan.deployment_targets[0].clone();
+    plan.deployment_targets = (0..4)
+        .map(|index| DeploymentTarget {
+            host: format!("host-{index}"),
+            target_path: format!("{}-{index}", first.target_path),
+            ..first.clone()
+        })
+        .collect();
+    assert_eq!(
+        manager
+            .deploy_binaries(&plan)
+            .await
+            .unwrap()
+            .successful_deployments,
+        4
+    );
+
+    // One host at a time, in order, even with four forks
+    plan.deployment_strategy = DeploymentStrategy::Rolling {
+        batch_size: 1,
+        max_fail_percentage: None,
+    };
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    assert_eq!(report.failed_deployments, 1);
+    assert_eq!(
+        fs::read_to_string(&log).unwrap(),
+        "host-0\nhost-1\nhost-2\nhost-3\n"
+    );
+
+    fs::remove_file(&log).unwrap();
+    plan.deployment_strategy = DeploymentStrategy::Rolling {
+        batch_size: 2,
+        max_fail_percentage: Some(0),
+    };
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    assert_eq!(
+        (
+            report.successful_deployments,
+            report.failed_deployments,
+            report.skipped_deployments
+        ),
+        (1, 1, 2)
+    );
+    assert!(matches!(
+        &report.deployment_results[3].status,
+        DeploymentStatus::Skipped { reason } if reason.contains("batch 1")
+    ));
+    let mut ran: Vec<String> = fs::read_to_string(&log)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    ran.sort();
+    assert_eq!(ran, ["host-0", "host-1"]);
+}
+
+fn delegated_task(id: &str, args: serde_json::Value, delegate_to: Option<&str>) -> Task {
+    serde_json::from_value(serde_json::json!({
+        "id": id, "name": id, "task_type": "Command", "module": "command", "args": args,
+        "dependencies": [], "conditions": [], "target_hosts": "All",
+        "timeout": null, "retry_policy": null, "failure_policy": "Abort",
+        "run_once": delegate_to.is_none(), "delegate_to": delegate_to,
+    }))
+    .unwrap()
+}
+
+/// Shell lines that hand `task` to the controller and keep the delivered result in `copy_to`
+fn delegate_and_wait(task: &str, copy_to: &str) -> String {
+    let request: ProgressEvent = serde_json::from_value(serde_json::json!({
+        "type": "DelegationRequested", "execution_id": "run-1", "task_id": task,
+        "context": {"host": "web", "variables": {"port": 80}},
+    }))
+    .unwrap();
+    format!(
+        "echo '{}'\n\
+         for i in $(seq 100); do [ -f \"$RUSTLE_DELEGATION_DIR/{task}.json\" ] && break; sleep 0.1; done\n\
+         cp \"$RUSTLE_DELEGATION_DIR/{task}.json\" {copy_to}",
+        encode_frame(&request).unwrap().trim_end()
+    )
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_run_once_and_delegated_tasks_go_through_the_controller() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().display().to_string();
+    let manager = DeploymentManager::new(test_config(&temp_dir, 2, 30))
+        .with_history(ExecutionHistory::new(temp_dir.path().join("history")))
+        .with_rollback_policy(RollbackPolicy::disabled());
+
+    // Run for another host, the runner executes just the delegated task
+    let completed: ProgressEvent = serde_json::from_value(serde_json::json!({
+        "type": "TaskCompleted", "execution_id": "run-2",
+        "task_result": task_json("migrate", false, serde_json::json!({
+            "ansible_facts": {"leader": "host-0"},
+        })),
+    }))
+    .unwrap();
+    let prelude = format!(
+        "if [ -n \"$RUSTLE_DELEGATED_TASK\" ]; then\n\
+         echo \"$RUSTLE_HOST_ID $RUSTLE_DELEGATED_TASK\" >> {dir}/delegated.log\n\
+         echo '{}'\n\
+         exit 0\n\
+         fi\n\
+         {}\n\
+         {}",
+        encode_frame(&completed).unwrap().trim_end(),
+        delegate_and_wait("migrate", &format!("{dir}/\"$RUSTLE_HOST_ID\".migrate")),
+        delegate_and_wait("notify", &format!("{dir}/\"$RUSTLE_HOST_ID\".notify")),
+    );
+    let runner = runner_script(
+        &prelude,
+        vec![
+            task_json("migrate", false, serde_json::Value::Null),
+            task_json("notify", false, serde_json::Value::Null),
+        ],
+    );
+    let mut plan = local_plan(&manager, &temp_dir, &runner).await;
+    plan.binary_compilations[0].source_tasks = vec!["migrate".to_string(), "notify".to_string()];
+    let first = plan.deployment_targets[0].clone();
+    plan.deployment_targets = (0..2)
+        .map(|index| DeploymentTarget {
+            host: format!("host-{index}"),
+            target_path: format!("{}-{index}", first.target_path),
+            ..first.clone()
+        })
+        .collect();
+    plan.delegated_tasks = vec![
+        delegated_task("migrate", serde_json::json!({}), None),
+        delegated_task(
+            "notify",
+            serde_json::json!({"cmd": "echo delegated"}),
+            Some("localhost"),
+        ),
+    ];
+    manager.deploy_binaries(&plan).await.unwrap();
+
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    assert_eq!(report.successful_deployments, 2, "{report:?}");
+
+    // The run-once task ran on the first host only, and both got its result
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("delegated.log")).unwrap(),
+        "host-0 migrate\n"
+    );
+    let delivered = |file: &str| -> DelegatedResult {
+        serde_json::from_slice(&fs::read(temp_dir.path().join(file)).unwrap()).unwrap()
+    };
+    for host in ["host-0", "host-1"] {
+        let migrate = delivered(&format!("{host}.migrate"));
+        assert_eq!(migrate.task_result.task_id, "migrate");
+        assert_eq!(migrate.facts["leader"], "host-0");
+
+        // Delegated to localhost, the task ran on the controller
+        let notify = delivered(&format!("{host}.notify"));
+        assert!(!notify.task_result.failed, "{notify:?}");
+        assert_eq!(
+            notify.task_result.stdout.as_deref().map(str::trim),
+            Some("delegated")
+        );
+    }
+}
+
+/// Shell line that writes `event` as a frame of the event stream
+fn emit(event: serde_json::Value) -> String {
+    let event: ProgressEvent = serde_json::from_value(event).unwrap();
+    format!("echo '{}'", encode_frame(&event).unwrap().trim_end())
+}
+
+/// Shell lines that report reaching the task at `position`, which at most
+/// `throttle` hosts may run at once, and wait for the controller to release
+/// it or stop the runner there
+fn reach_and_wait(position: usize, throttle: Option<u32>) -> String {
+    format!(
+        "mkdir -p \"$RUSTLE_SYNC_DIR\"\n\
+         {}\n\
+         for i in $(seq 100); do [ -f \"$RUSTLE_SYNC_DIR/{position}.released\" ] || [ -f \"$RUSTLE_SYNC_DIR/{position}.aborted\" ] && break; sleep 0.1; done",
+        emit(serde_json::json!({
+            "type": "TaskReached", "execution_id": "run-1",
+            "task_id": format!("task-{position}"), "position": position, "throttle": throttle,
+        }))
+    )
+}
+
+/// Run the single target of `plan` as `hosts` hosts, host-0 to host-N
+fn spread_targets(plan: &mut DeploymentPlan, hosts: usize) {
+    let first = plan.deployment_targets[0].clone();
+    plan.deployment_targets = (0..hosts)
+        .map(|index| DeploymentTarget {
+            host: format!("host-{index}"),
+            target_path: format!("{}-{index}", first.target_path),
+            ..first.clone()
+        })
+        .collect();
+}
+
+fn log_lines(log: &std::path::Path) -> Vec<String> {
+    fs::read_to_string(log)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_linear_strategy_keeps_hosts_at_the_same_task() {
+    let temp_dir = TempDir::new().unwrap();
+    let log = temp_dir.path().join("linear.log");
+    let manager = DeploymentManager::new(test_config(&temp_dir, 2, 30))
+        .with_history(ExecutionHistory::new(temp_dir.path().join("history")))
+        .with_rollback_policy(RollbackPolicy::disabled());
+
+    // host-1 is slow to reach the first task, host-0 waits for it
+    let prelude = format!(
+        "if [ \"$RUSTLE_HOST_ID\" = host-1 ]; then sleep 0.5; echo \"host-1 reached\" >> {log}; fi\n\
+         {}\n\
+         echo \"$RUSTLE_HOST_ID released\" >> {log}",
+        reach_and_wait(0, None),
+        log = log.display(),
+    );
+    let runner = runner_script(
+        &prelude,
+        vec![task_json("task-0", false, serde_json::Value::Null)],
+    );
+    let mut plan = local_plan(&manager, &temp_dir, &runner).await;
+    plan.binary_compilations[0].source_tasks = vec!["task-0".to_string()];
+    spread_targets(&mut plan, 2);
+    manager.deploy_binaries(&plan).await.unwrap();
+
+    let start = Instant::now();
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    assert_eq!(report.successful_deployments, 2, "{report:?}");
+    assert!(start.elapsed() < Duration::from_secs(5));
+    let lines = log_lines(&log);
+    assert_eq!(lines[0], "host-1 reached");
+    let mut released = lines[1..].to_vec();
+    released.sort();
+    assert_eq!(released, ["host-0 released", "host-1 released"]);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_throttle_caps_the_hosts_running_a_task() {
+    let temp_dir = TempDir::new().unwrap();
+    let log = temp_dir.path().join("throttle.log");
+    let manager = DeploymentManager::new(test_config(&temp_dir, 3, 30))
+        .with_history(ExecutionHistory::new(temp_dir.path().join("history")))
+        .with_rollback_policy(RollbackPolicy::disabled());
+
+    let prelude = format!(
+        "{}\n\
+         echo \"start $RUSTLE_HOST_ID\" >> {log}\n\
+         sleep 0.3\n\
+         echo \"end $RUSTLE_HOST_ID\" >> {log}\n\
+         {}\n\
+         sleep 0.5",
+        reach_and_wait(0, Some(1)),
+        emit(serde_json::json!({
+            "type": "TaskCompleted", "execution_id": "run-1",
+            "task_result": task_json("task-0", false, serde_json::Value::Null),
+        })),
+        log = log.display(),
+    );
+    let runner = runner_script(
+        &prelude,
+        vec![task_json("task-0", false, serde_json::Value::Null)],
+    );
+    let mut plan = local_plan(&manager, &temp_dir, &runner).await;
+    plan.binary_compilations[0].source_tasks = vec!["task-0".to_string()];
+    plan.strategy = ExecutionStrategy::Free;
+    spread_targets(&mut plan, 3);
+    manager.deploy_binaries(&plan).await.unwrap();
+
+    let start = Instant::now();
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    assert_eq!(report.successful_deployments, 3, "{report:?}");
+    // Slots are given back on completion, not when the runners exit
+    assert!(
+        start.elapsed() < Duration::from_secs(2),
+        "{:?}",
+        start.elapsed()
+    );
+    let lines = log_lines(&log);
+    assert_eq!(lines.len(), 6);
+    for pair in lines.chunks(2) {
+        let host = pair[0].strip_prefix("start ").unwrap();
+        assert_eq!(pair[1], format!("end {host}"), "{lines:?}");
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_any_errors_fatal_stops_every_host() {
+    let temp_dir = TempDir::new().unwrap();
+    let log = temp_dir.path().join("fatal.log");
+    let manager = DeploymentManager::new(test_config(&temp_dir, 2, 30))
+        .with_history(ExecutionHistory::new(temp_dir.path().join("history")))
+        .with_rollback_policy(RollbackPolicy::disabled());
+
+    // host-0 fails while host-1 is busy, host-2 waits for a fork
+    let prelude = format!(
+        "if [ \"$RUSTLE_HOST_ID\" = host-0 ]; then\n\
+         {}\n\
+         exit 1\n\
+         fi\n\
+         sleep 0.5\n\
+         {}\n\
+         if [ -f \"$RUSTLE_SYNC_DIR/1.aborted\" ]; then\n\
+         echo \"$RUSTLE_HOST_ID stopped: $(cat \"$RUSTLE_SYNC_DIR/1.aborted\")\" >> {log}\n\
+         exit 1\n\
+         fi\n\
+         echo \"$RUSTLE_HOST_ID went on\" >> {log}",
+        emit(serde_json::json!({
+            "type": "FatalFailure", "execution_id": "run-1",
+            "task_id": "task-0", "play_id": "site",
+        })),
+        reach_and_wait(1, None),
+        log = log.display(),
+    );
+    let runner = runner_script(
+        &prelude,
+        vec![task_json("task-0", false, serde_json::Value::Null)],
+    );
+    let mut plan = local_plan(&manager, &temp_dir, &runner).await;
+    plan.binary_compilations[0].source_tasks = vec!["task-0".to_string()];
+    plan.strategy = ExecutionStrategy::Free;
+    spread_targets(&mut plan, 3);
+    manager.deploy_binaries(&plan).await.unwrap();
+
+    let report = manager.execute_deployments(&plan, &[]).await.unwrap();
+    let reason = "host-0 failed task task-0 of play site, which has any_errors_fatal";
+    assert_eq!(
+        log_lines(&log),
+        [format!("host-1 stopped: {reason}")],
+        "{report:?}"
+    );
+    let statuses: Vec<_> = report
+        .deployment_results
+        .iter()
+        .map(|result| &result.status)
+        .collect();
+    assert!(matches!(statuses[0], DeploymentStatus::Failed { .. }));
+    assert!(matches!(statuses[1], DeploymentStatus::Aborted { reason: r } if r == reason));
+    assert!(matches!(statuses[2], DeploymentStatus::Skipped { reason: r } if r == reason));
+    assert_eq!(
+        (
+            report.successful_deployments,
+            report.failed_deployments,
+            report.aborted_deployments,
+            report.skipped_deployments
+        ),
+        (0, 1, 1, 1)
+    );
+}
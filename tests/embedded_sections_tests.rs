@@ -0,0 +1,139 @@
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::template::{
+    BinaryTemplateGenerator, CompressionType, EmbeddedData, EmbeddedSections, EncryptedSecrets,
+    SectionEncoding, SectionKind, TargetInfo, TemplateConfig,
+};
+use rustle_deploy::types::deployment::RuntimeConfig;
+use rustle_deploy::types::platform::Platform;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+fn load_plan() -> RustlePlanOutput {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    serde_json::from_str(&content).expect("Failed to parse rustle plan")
+}
+
+fn target_info() -> TargetInfo {
+    TargetInfo {
+        target_triple: "x86_64-unknown-linux-gnu".to_string(),
+        platform: Platform::Linux,
+        architecture: "x86_64".to_string(),
+        os_family: "unix".to_string(),
+        libc: Some("gnu".to_string()),
+        features: vec![],
+    }
+}
+
+fn embedded_data(static_files: HashMap<String, Vec<u8>>) -> EmbeddedData {
+    EmbeddedData {
+        execution_plan: "{}".to_string(),
+        static_files,
+        module_binaries: HashMap::new(),
+        runtime_config: RuntimeConfig {
+            controller_endpoint: None,
+            execution_timeout: Duration::from_secs(300),
+            report_interval: Duration::from_secs(30),
+            cleanup_on_completion: true,
+            log_level: "info".to_string(),
+            verbose: false,
+        },
+        secrets: EncryptedSecrets {
+            vault_data: HashMap::new(),
+            encryption_key_id: "none".to_string(),
+            decryption_method: "none".to_string(),
+        },
+        facts_cache: None,
+    }
+}
+
+#[test]
+fn test_sections_round_trip_and_report_sizes() {
+    let plan = format!("{{\"tasks\": [{}]}}", ["\"noop\""; 2000].join(","));
+    let data = embedded_data(HashMap::from([
+        ("/etc/motd".to_string(), b"welcome\n".repeat(100)),
+        (
+            "/etc/app.conf.j2".to_string(),
+            b"port={{ port }}\n".to_vec(),
+        ),
+    ]));
+
+    let sections = EmbeddedSections::pack(&plan, &data, SectionEncoding::Zstd).unwrap();
+    let paths: Vec<_> = sections.sections.iter().map(|s| s.path.clone()).collect();
+    assert_eq!(
+        paths,
+        [
+            "src/sections/execution_plan.zst",
+            "src/sections/runtime_config.zst",
+            "src/sections/static_0000.zst",
+            "src/sections/static_0001.zst",
+        ]
+        .map(std::path::PathBuf::from)
+    );
+    assert_eq!(sections.sections[2].name, "/etc/app.conf.j2");
+    assert_eq!(sections.sections[2].kind, SectionKind::Template);
+    assert_eq!(sections.sections[3].kind, SectionKind::StaticFile);
+    assert_eq!(
+        zstd::decode_all(sections.sections[0].data.as_slice()).unwrap(),
+        plan.as_bytes()
+    );
+
+    let report = sections.report();
+    assert_eq!(report.assets[0].original_size, plan.len() as u64);
+    assert!(report.assets[0].embedded_size < report.assets[0].original_size);
+    assert_eq!(report.assets[3].original_size, 800);
+    assert!(report.embedded_size() < report.original_size());
+    assert!(report.to_string().contains("/etc/motd (static file)"));
+
+    let raw = EmbeddedSections::pack(&plan, &data, SectionEncoding::Raw).unwrap();
+    assert_eq!(raw.sections[0].data, plan.as_bytes());
+    assert!(raw.sections[0]
+        .path
+        .to_string_lossy()
+        .ends_with("execution_plan.bin"));
+}
+
+#[tokio::test]
+async fn test_runner_includes_sections_instead_of_literals() {
+    let plan = load_plan();
+    let generator = BinaryTemplateGenerator::new(TemplateConfig::default()).unwrap();
+    let template = generator
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &target_info())
+        .await
+        .expect("Failed to generate template");
+
+    let main_rs = &template.source_files[Path::new("src/main.rs")];
+    assert!(main_rs.contains(r#"include_bytes!("sections/execution_plan.zst")"#));
+    assert!(main_rs.contains("ruzstd::decoding::StreamingDecoder"));
+    assert!(main_rs.contains("embedded_data::execution_plan()"));
+    assert!(!main_rs.contains(&plan.metadata.playbook_hash));
+    assert!(template.cargo_toml.contains("ruzstd"));
+
+    let files: Vec<_> = template.project_files().map(|(path, _)| path).collect();
+    assert!(files.contains(&Path::new("src/sections/execution_plan.zst")));
+    assert!(files.contains(&Path::new("src/sections/runtime_config.zst")));
+    let report = template.section_report();
+    assert_eq!(report.assets[0].kind, SectionKind::ExecutionPlan);
+    assert_eq!(report.assets[1].kind, SectionKind::RuntimeConfig);
+}
+
+#[tokio::test]
+async fn test_uncompressed_sections() {
+    let plan = load_plan();
+    let generator = BinaryTemplateGenerator::new(TemplateConfig {
+        compression_algorithm: CompressionType::None,
+        ..Default::default()
+    })
+    .unwrap();
+    let template = generator
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &target_info())
+        .await
+        .expect("Failed to generate template");
+
+    let main_rs = &template.source_files[Path::new("src/main.rs")];
+    assert!(main_rs.contains(r#"include_bytes!("sections/execution_plan.bin")"#));
+    assert!(!main_rs.contains("ruzstd"));
+    assert!(!template.cargo_toml.contains("ruzstd"));
+}
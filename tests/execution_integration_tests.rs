@@ -1,6 +1,8 @@
 use rustle_deploy::deploy::DeploymentManager;
-use rustle_deploy::execution::{ExecutionPlanParser, PlanFormat};
-use rustle_deploy::types::DeploymentConfig;
+use rustle_deploy::execution::{ExecutionPlanParser, PlanFormat, SerialBatch};
+use rustle_deploy::types::{
+    DeploymentConfig, DeploymentMethod, DeploymentStatus, DeploymentStrategy, DeploymentTarget,
+};
 use std::fs;
 use tempfile::TempDir;
 
@@ -11,6 +13,7 @@ async fn test_end_to_end_deployment_plan_creation() {
         cache_dir: temp_dir.path().to_path_buf(),
         output_dir: temp_dir.path().to_path_buf(),
         parallel_jobs: 4,
+        forks: 10,
         default_timeout_secs: 300,
         verify_deployments: true,
         compression: true,
@@ -37,6 +40,127 @@ async fn test_end_to_end_deployment_plan_creation() {
     assert!(!plan.metadata.deployment_id.is_empty());
 }
 
+#[tokio::test]
+async fn test_heterogeneous_targets_get_one_binary_per_triple() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager = DeploymentManager::new(DeploymentConfig {
+        cache_dir: temp_dir.path().to_path_buf(),
+        output_dir: temp_dir.path().to_path_buf(),
+        parallel_jobs: 1,
+        forks: 1,
+        default_timeout_secs: 300,
+        verify_deployments: false,
+        compression: false,
+        strip_symbols: false,
+        binary_size_limit_mb: 0,
+    });
+    let content = fs::read_to_string("tests/fixtures/execution_plans/simple_plan.json")
+        .expect("Failed to read test fixture");
+    let execution_plan = ExecutionPlanParser::new()
+        .parse(&content, PlanFormat::Json)
+        .unwrap();
+
+    let target = |host: &str, triple: &str| DeploymentTarget {
+        host: host.to_string(),
+        target_path: "/usr/local/bin/rustle-runner".to_string(),
+        binary_compilation_id: format!("rustle-{triple}"),
+        deployment_method: DeploymentMethod::Ssh,
+        status: DeploymentStatus::Pending,
+        deployed_at: None,
+        version: String::new(),
+        connection: Default::default(),
+    };
+    let targets = [
+        target("web1", "x86_64-unknown-linux-gnu"),
+        target("pi", "aarch64-unknown-linux-gnu"),
+        target("web2", "x86_64-unknown-linux-gnu"),
+        target("win", "x86_64-pc-windows-msvc"),
+    ];
+
+    let plan = manager
+        .create_deployment_plan_from_execution(&execution_plan, &targets)
+        .await
+        .unwrap();
+
+    assert_eq!(plan.binary_compilations.len(), 3);
+    for target in &plan.deployment_targets {
+        let compilation = plan
+            .binary_compilations
+            .iter()
+            .find(|c| c.compilation_id == target.binary_compilation_id)
+            .unwrap_or_else(|| panic!("no binary for {}", target.host));
+        let expected = match target.host.as_str() {
+            "pi" => "aarch64-unknown-linux-gnu",
+            "win" => "x86_64-pc-windows-msvc",
+            _ => "x86_64-unknown-linux-gnu",
+        };
+        assert_eq!(compilation.target_triple, expected);
+    }
+    let windows = plan
+        .binary_compilations
+        .iter()
+        .find(|c| c.target_triple.contains("windows"))
+        .unwrap();
+    assert!(windows.binary_name.ends_with(".exe"));
+}
+
+#[tokio::test]
+async fn test_serial_percentage_becomes_rolling_batches() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager = DeploymentManager::new(DeploymentConfig {
+        cache_dir: temp_dir.path().to_path_buf(),
+        output_dir: temp_dir.path().to_path_buf(),
+        parallel_jobs: 1,
+        forks: 10,
+        default_timeout_secs: 300,
+        verify_deployments: false,
+        compression: false,
+        strip_symbols: false,
+        binary_size_limit_mb: 0,
+    });
+    let content = fs::read_to_string("tests/fixtures/execution_plans/simple_plan.json")
+        .expect("Failed to read test fixture");
+    let mut execution_plan = ExecutionPlanParser::new()
+        .parse(&content, PlanFormat::Json)
+        .unwrap();
+    execution_plan.deployment_config.serial = Some(SerialBatch::Percentage("25%".to_string()));
+    execution_plan.deployment_config.max_fail_percentage = Some(10);
+
+    let targets: Vec<DeploymentTarget> = (0..10)
+        .map(|index| DeploymentTarget {
+            host: format!("web{index}"),
+            target_path: "/usr/local/bin/rustle-runner".to_string(),
+            binary_compilation_id: "rustle-x86_64-unknown-linux-gnu".to_string(),
+            deployment_method: DeploymentMethod::Ssh,
+            status: DeploymentStatus::Pending,
+            deployed_at: None,
+            version: String::new(),
+            connection: Default::default(),
+        })
+        .collect();
+    let plan = manager
+        .create_deployment_plan_from_execution(&execution_plan, &targets)
+        .await
+        .unwrap();
+
+    // 25% of 10 hosts rounds down to batches of 2
+    assert!(matches!(
+        plan.deployment_strategy,
+        DeploymentStrategy::Rolling {
+            batch_size: 2,
+            max_fail_percentage: Some(10)
+        }
+    ));
+    assert_eq!(plan.deployment_strategy.batches(10).len(), 5);
+    assert_eq!(plan.deployment_strategy.batches(5), [0..2, 2..4, 4..5]);
+
+    execution_plan.deployment_config.serial = Some(SerialBatch::Percentage("lots".to_string()));
+    assert!(manager
+        .create_deployment_plan_from_execution(&execution_plan, &targets)
+        .await
+        .is_err());
+}
+
 #[test]
 fn test_execution_plan_serialization_roundtrip() {
     let parser = ExecutionPlanParser::new();
@@ -0,0 +1,80 @@
+use rustle_deploy::api::facts::{gather_facts_arg, is_local_host, GATHER_FACTS_ARG};
+use rustle_deploy::api::{facts_plan, local_facts, ApiError, FactSubset};
+use rustle_deploy::execution::rustle_plan::BinaryDeploymentPlan;
+use rustle_deploy::template::{BinaryTemplateGenerator, TargetInfo, TemplateConfig};
+use rustle_deploy::types::platform::Platform;
+use std::path::Path;
+
+fn linux_target() -> TargetInfo {
+    TargetInfo {
+        target_triple: "x86_64-unknown-linux-gnu".to_string(),
+        platform: Platform::Linux,
+        architecture: "x86_64".to_string(),
+        os_family: "unix".to_string(),
+        libc: Some("glibc".to_string()),
+        features: vec![],
+    }
+}
+
+#[test]
+fn test_subsets_parse_from_ansible_names() {
+    let subsets: Vec<FactSubset> = "network, Hardware,min"
+        .split(',')
+        .map(|subset| subset.parse().unwrap())
+        .collect();
+    assert_eq!(
+        subsets,
+        [FactSubset::Network, FactSubset::Hardware, FactSubset::Min]
+    );
+    assert!("facter".parse::<FactSubset>().is_err());
+
+    assert_eq!(gather_facts_arg(&[]), GATHER_FACTS_ARG);
+    assert_eq!(
+        gather_facts_arg(&subsets),
+        format!("{GATHER_FACTS_ARG}=network,hardware,min")
+    );
+}
+
+#[test]
+fn test_facts_plan_runs_nothing_on_its_hosts() {
+    let hosts = vec!["web1".to_string(), "db1".to_string()];
+    let plan = facts_plan(&hosts).unwrap();
+    assert_eq!(plan.hosts, hosts);
+    assert_eq!(plan.total_tasks, 0);
+    assert!(plan.binary_deployments.is_empty());
+
+    assert!(matches!(facts_plan(&[]), Err(ApiError::Facts { .. })));
+    assert!(is_local_host("localhost") && !is_local_host("web1"));
+}
+
+#[tokio::test]
+async fn test_local_facts_hold_the_subsets_asked_for() {
+    let minimal = local_facts(&[]).await.unwrap();
+    assert!(minimal.contains_key("ansible_system"));
+    assert!(minimal.contains_key("ansible_distribution"));
+    assert!(!minimal.contains_key("ansible_env"));
+
+    let env = local_facts(&[FactSubset::Env]).await.unwrap();
+    assert!(env["ansible_env"].is_object());
+}
+
+#[tokio::test]
+async fn test_runners_can_gather_facts_instead_of_running() {
+    let hosts = vec!["web1".to_string()];
+    let plan = facts_plan(&hosts).unwrap();
+    let deployment = BinaryDeploymentPlan {
+        target_hosts: hosts,
+        ..Default::default()
+    };
+    let template = BinaryTemplateGenerator::new(TemplateConfig::default())
+        .unwrap()
+        .generate_binary_template(&plan, &deployment, &linux_target())
+        .await
+        .unwrap();
+
+    let facts = &template.source_files[Path::new("src/facts.rs")];
+    assert!(facts.contains(&format!("\"{GATHER_FACTS_ARG}\"")));
+    let main_rs = &template.source_files[Path::new("src/main.rs")];
+    assert!(main_rs.contains("mod facts;"));
+    assert!(main_rs.contains("facts::requested()"));
+}
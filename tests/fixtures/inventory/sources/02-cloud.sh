@@ -0,0 +1,24 @@
+#!/bin/sh
+# Dynamic inventory answering with _meta, so --host is never run
+case "$1" in
+  --list)
+    cat <<'JSON'
+{
+  "db": ["db-01"],
+  "web": {"hosts": ["web01.example.com"], "vars": {"tier": "frontend"}},
+  "_meta": {
+    "hostvars": {
+      "db-01": {
+        "ansible_host": "10.0.0.5",
+        "ansible_port": 2222,
+        "ansible_architecture": "aarch64",
+        "ansible_os_family": "Debian"
+      },
+      "web01.example.com": {"ansible_host": "10.0.1.1"}
+    }
+  }
+}
+JSON
+    ;;
+  *) exit 1 ;;
+esac
@@ -0,0 +1,171 @@
+mod helpers;
+
+use helpers::TaskBuilder;
+use rustle_deploy::execution::ExecutionPlan;
+use rustle_deploy::runtime::{LocalExecutor, RuntimeConfig};
+
+fn task(
+    id: &str,
+    play: &str,
+    module: &str,
+    args: serde_json::Value,
+    notify: &[&str],
+) -> serde_json::Value {
+    TaskBuilder::new(id, module, args)
+        .play(play)
+        .notify(notify)
+        .build()
+}
+
+fn handler(id: &str, name: &str, play: &str, listen: &[&str]) -> serde_json::Value {
+    serde_json::json!({
+        "id": id, "name": name, "module": "command",
+        "args": { "cmd": format!("echo {id}") }, "listen": listen, "play_id": play,
+    })
+}
+
+fn plan(tasks: Vec<serde_json::Value>, handlers: Vec<serde_json::Value>) -> ExecutionPlan {
+    helpers::plan(
+        "handlers",
+        serde_json::json!({ "tasks": tasks, "handlers": handlers }),
+    )
+}
+
+fn echo(text: &str) -> serde_json::Value {
+    serde_json::json!({ "cmd": format!("echo {text}") })
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_notified_handlers_run_once_at_the_end_of_each_play() {
+    let plan = plan(
+        vec![
+            task(
+                "configure",
+                "setup",
+                "command",
+                echo("one"),
+                &["restart app"],
+            ),
+            task(
+                "deploy",
+                "setup",
+                "command",
+                echo("two"),
+                &["restart app", "web changed"],
+            ),
+            // Tasks that change nothing notify nobody
+            task(
+                "report",
+                "setup",
+                "debug",
+                serde_json::json!({}),
+                &["audit"],
+            ),
+            task(
+                "upgrade",
+                "upgrade",
+                "command",
+                echo("four"),
+                &["restart app"],
+            ),
+        ],
+        vec![
+            handler("restart-setup", "restart app", "setup", &[]),
+            handler("reload-web", "reload web", "setup", &["web changed"]),
+            handler("audit", "audit", "setup", &[]),
+            handler("restart-upgrade", "restart app", "upgrade", &[]),
+        ],
+    );
+
+    let mut executor = LocalExecutor::new(RuntimeConfig::default());
+    let result = executor.execute_plan(plan).await.unwrap();
+    assert!(result.success, "{:?}", result.errors);
+    assert_eq!(result.summary.total_tasks, 4);
+
+    let ran: Vec<&str> = result
+        .handler_results
+        .iter()
+        .map(|handler| handler.task_id.as_str())
+        .collect();
+    assert_eq!(ran, ["restart-setup", "reload-web", "restart-upgrade"]);
+    assert_eq!(
+        result.handler_results[0].stdout.as_deref().map(str::trim),
+        Some("restart-setup")
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_flush_handlers_runs_notified_handlers_immediately() {
+    let install = task(
+        "install",
+        "site",
+        "command",
+        echo("install"),
+        &["restart app"],
+    );
+    let mut flush = task(
+        "flush",
+        "site",
+        "meta",
+        serde_json::json!({ "_raw_params": "flush_handlers" }),
+        &[],
+    );
+    flush["dependencies"] = serde_json::json!(["install"]);
+    let mut migrate = task(
+        "migrate",
+        "site",
+        "command",
+        echo("migrate"),
+        &["restart app"],
+    );
+    migrate["dependencies"] = serde_json::json!(["flush"]);
+
+    let plan = plan(
+        vec![install, flush, migrate],
+        vec![handler("restart", "restart app", "site", &[])],
+    );
+    let mut executor = LocalExecutor::new(RuntimeConfig::default());
+    let result = executor.execute_plan(plan).await.unwrap();
+    assert!(result.success, "{:?}", result.errors);
+
+    // Once when flushed, and again for the notification after the flush
+    assert_eq!(result.handler_results.len(), 2);
+    assert_eq!(
+        result.task_results["flush"].output["flushed_handlers"],
+        serde_json::json!(["restart"])
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_handlers_notify_other_handlers() {
+    let mut restart = handler("restart", "restart app", "site", &[]);
+    restart["notify"] = serde_json::json!(["check health"]);
+    let mut check = handler("check", "check health", "site", &[]);
+    check["notify"] = serde_json::json!(["restart app"]);
+
+    // Defined before the handler that notifies it, yet still run in the
+    // same flush; the handler it notifies back has already run
+    let plan = plan(
+        vec![task(
+            "install",
+            "site",
+            "command",
+            echo("install"),
+            &["restart app"],
+        )],
+        vec![check, restart],
+    );
+    let mut executor = LocalExecutor::new(RuntimeConfig::default());
+    let result = executor.execute_plan(plan).await.unwrap();
+    assert!(result.success, "{:?}", result.errors);
+
+    let ran: Vec<&str> = result
+        .handler_results
+        .iter()
+        .map(|handler| handler.task_id.as_str())
+        .collect();
+    assert_eq!(ran, ["restart", "check"]);
+}
@@ -0,0 +1,58 @@
+//! Builders for the tasks of execution plans
+
+use serde_json::{json, Value};
+
+/// Builder for the JSON of a task, which aborts its play on failure and
+/// depends on nothing unless told otherwise
+pub struct TaskBuilder {
+    task: Value,
+}
+
+impl TaskBuilder {
+    pub fn new(id: &str, module: &str, args: Value) -> Self {
+        Self {
+            task: json!({
+                "id": id, "name": id, "task_type": "Command", "module": module,
+                "args": args, "dependencies": [], "conditions": [], "target_hosts": "All",
+                "timeout": null, "retry_policy": null, "failure_policy": "Abort",
+            }),
+        }
+    }
+
+    /// A `command` task running `cmd`
+    pub fn command(id: &str, cmd: &str) -> Self {
+        Self::new(id, "command", json!({ "cmd": cmd }))
+    }
+
+    /// A `command` task running `script` with `sh -c`
+    pub fn script(id: &str, script: &str) -> Self {
+        Self::command(id, &format!("sh -c '{script}'"))
+    }
+
+    pub fn play(self, play: &str) -> Self {
+        self.set("play_id", json!(play))
+    }
+
+    pub fn after(self, dependencies: &[&str]) -> Self {
+        self.set("dependencies", json!(dependencies))
+    }
+
+    pub fn notify(self, handlers: &[&str]) -> Self {
+        self.set("notify", json!(handlers))
+    }
+
+    /// Carry on with the play when the task fails
+    pub fn continue_on_failure(self) -> Self {
+        self.set("failure_policy", json!("Continue"))
+    }
+
+    /// Set any other field of the task
+    pub fn set(mut self, key: &str, value: Value) -> Self {
+        self.task[key] = value;
+        self
+    }
+
+    pub fn build(self) -> Value {
+        self.task
+    }
+}
@@ -0,0 +1,52 @@
+//! Execution plan fixtures
+
+use rustle_deploy::execution::ExecutionPlan;
+use serde_json::{json, Value};
+
+/// The JSON of an execution plan with id `plan_id` that runs nothing, but
+/// for `fields` such as `tasks`, `handlers`, `blocks`, `plays` or `strategy`,
+/// in the format [`ExecutionPlanParser`] accepts
+///
+/// [`ExecutionPlanParser`]: rustle_deploy::execution::ExecutionPlanParser
+pub fn plan_json(plan_id: &str, fields: Value) -> Value {
+    let mut plan = json!({
+        "metadata": {
+            "version": "1.0",
+            "created_at": "2024-01-01T00:00:00Z",
+            "rustle_plan_version": "1.0.0",
+            "plan_id": plan_id,
+            "description": null,
+            "author": null,
+            "tags": []
+        },
+        "tasks": [],
+        "inventory": {
+            "format": "Json",
+            "source": { "Inline": { "content": "{}" } },
+            "groups": {},
+            "hosts": {},
+            "variables": {}
+        },
+        "strategy": "Linear",
+        "facts_template": { "global_facts": [], "host_facts": [], "custom_facts": {} },
+        "deployment_config": {
+            "target_path": format!("/tmp/rustle-{plan_id}"),
+            "backup_previous": false,
+            "verify_deployment": false,
+            "cleanup_on_success": false,
+            "deployment_timeout": null
+        },
+        "modules": []
+    });
+    if let Value::Object(fields) = fields {
+        for (key, value) in fields {
+            plan[key] = value;
+        }
+    }
+    plan
+}
+
+/// The execution plan of [`plan_json`]
+pub fn plan(plan_id: &str, fields: Value) -> ExecutionPlan {
+    serde_json::from_value(plan_json(plan_id, fields)).unwrap()
+}
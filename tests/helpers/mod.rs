@@ -0,0 +1,10 @@
+//! Execution plans and tasks shared by the runner tests
+//!
+//! Each test crate uses some of the helpers only.
+#![allow(dead_code, unused_imports)]
+
+pub mod builders;
+pub mod fixtures;
+
+pub use builders::TaskBuilder;
+pub use fixtures::{plan, plan_json};
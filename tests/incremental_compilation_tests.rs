@@ -0,0 +1,64 @@
+use rustle_deploy::compilation::IncrementalWorkspace;
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::template::{BinaryTemplateGenerator, TargetInfo, TemplateConfig};
+use rustle_deploy::types::platform::Platform;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_only_changed_files_are_rewritten() {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    let plan: RustlePlanOutput = serde_json::from_str(&content).unwrap();
+    let generator = BinaryTemplateGenerator::new(TemplateConfig::default()).unwrap();
+    let target_info = TargetInfo {
+        target_triple: "x86_64-unknown-linux-gnu".to_string(),
+        platform: Platform::Linux,
+        architecture: "x86_64".to_string(),
+        os_family: "unix".to_string(),
+        libc: Some("glibc".to_string()),
+        features: vec![],
+    };
+    let mut template = generator
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &target_info)
+        .await
+        .unwrap();
+
+    let temp_dir = TempDir::new().unwrap();
+    let workspace = IncrementalWorkspace::new(temp_dir.path().to_path_buf());
+
+    let (project, first) = workspace.prepare(&template, "Release").await.unwrap();
+    // The sources, the embedded data sections and Cargo.toml
+    assert_eq!(first.written.len(), template.project_files().count() + 1);
+    assert!(project.cargo_toml_path.exists());
+
+    // Nothing changed, nothing is written
+    let (_, second) = workspace.prepare(&template, "Release").await.unwrap();
+    assert!(second.is_up_to_date());
+
+    // A changed file is the only one written, a dropped one is removed
+    let main_rs = PathBuf::from("src/main.rs");
+    template
+        .source_files
+        .get_mut(&main_rs)
+        .unwrap()
+        .push_str("\n// edited\n");
+    let dropped = template
+        .source_files
+        .keys()
+        .find(|path| **path != main_rs)
+        .cloned()
+        .unwrap();
+    template.source_files.remove(&dropped);
+    let (project, third) = workspace.prepare(&template, "Release").await.unwrap();
+    assert_eq!(third.written, vec![main_rs.clone()]);
+    assert_eq!(third.removed, vec![dropped.clone()]);
+    assert!(!project.project_dir.join(&dropped).exists());
+
+    // Other profiles build in their own directory
+    assert_ne!(
+        workspace.project_dir("x86_64-unknown-linux-gnu", "Release"),
+        workspace.project_dir("x86_64-unknown-linux-gnu", "Debug")
+    );
+}
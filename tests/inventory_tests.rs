@@ -1,11 +1,24 @@
+use async_trait::async_trait;
 use chrono::Utc;
-use rustle_deploy::inventory::{InventoryProcessor, JsonInventoryProcessor};
+use rustle_deploy::deploy::{SshConfig, SshConnectionConfig};
+use rustle_deploy::execution::{VarsFileLoader, VaultSecret, VaultSecrets};
+use rustle_deploy::inventory::{
+    dynamic_inventory, expand_host_range, ConnectionPreflight, DirectoryVars, HostPattern,
+    IniInventoryParser, InventoryError, InventoryExport, InventoryLoader, InventoryPlugin,
+    InventoryProcessor, JsonInventoryProcessor, PatternError, PrecedenceResolver, ValidationError,
+    VariableError, VariablePrecedence, MASKED_VALUE,
+};
 use rustle_deploy::types::inventory::{
     ConnectionConfig, ConnectionMethod, InventoryFormat, InventoryGroup, InventoryHost,
     InventoryMetadata, ParsedInventory,
 };
 use serde_json::json;
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
 
 #[tokio::test]
 async fn test_inventory_processor_basic() {
@@ -83,6 +96,1061 @@ async fn test_variable_resolution() {
     assert!(host.variables.contains_key("host_var"));
 }
 
+#[test]
+fn test_group_and_host_vars_directories() {
+    let processor = InventoryProcessor::new()
+        .with_inventory_path(Path::new("tests/fixtures/inventory/layout/hosts.yml"));
+    let mut inventory = create_test_inventory_with_groups();
+    inventory
+        .global_vars
+        .insert("ntp_server".to_string(), json!("inventory.ntp"));
+
+    processor.resolve_variables(&mut inventory).unwrap();
+
+    let vars = &inventory.hosts["web-server"].variables;
+    // group_vars/all/ overrides the inventory's own group variables, its
+    // files in the order of their names
+    assert_eq!(vars["ntp_server"], json!("pool.ntp.org"));
+    assert_eq!(vars["dns_server"], json!("10.0.0.53"));
+    // group_vars/<group> overrides group_vars/all and the inventory's group
+    assert_eq!(vars["http_port"], json!(80));
+    assert_eq!(vars["group_var"], json!("from_group_vars"));
+    assert_eq!(vars["host_var"], json!("from_host_vars"));
+    assert_eq!(vars.len(), 5);
+}
+
+#[test]
+fn test_vaulted_group_vars() {
+    let dir = TempDir::new().unwrap();
+    let secret = VaultSecret::new("default", "correct horse");
+    std::fs::create_dir_all(dir.path().join("group_vars/web")).unwrap();
+    std::fs::write(
+        dir.path().join("group_vars/web/vault.yml"),
+        secret.encrypt(b"db_password: s3cret\n"),
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("group_vars/web/main.yml"), "db_user: app\n").unwrap();
+
+    let mut inventory = create_test_inventory_with_groups();
+    let processor = InventoryProcessor::new().with_inventory_path(dir.path());
+    assert!(matches!(
+        processor.resolve_variables(&mut inventory),
+        Err(VariableError::VarsFile(_))
+    ));
+
+    let mut inventory = create_test_inventory_with_groups();
+    let processor = InventoryProcessor::new()
+        .with_inventory_path(dir.path())
+        .with_vars_loader(
+            VarsFileLoader::new().with_vault(VaultSecrets::new().with_secret(secret)),
+        );
+    processor.resolve_variables(&mut inventory).unwrap();
+    let vars = &inventory.hosts["web-server"].variables;
+    assert_eq!(vars["db_password"], json!("s3cret"));
+    assert_eq!(vars["db_user"], json!("app"));
+}
+
+#[test]
+fn test_inventory_export() {
+    let dir = TempDir::new().unwrap();
+    let secret = VaultSecret::new("default", "correct horse");
+    std::fs::create_dir_all(dir.path().join("group_vars")).unwrap();
+    std::fs::write(
+        dir.path().join("group_vars/db.yml"),
+        secret.encrypt(b"db_password: s3cret\n"),
+    )
+    .unwrap();
+    let loader = VarsFileLoader::new().with_vault(VaultSecrets::new().with_secret(secret));
+
+    let content = std::fs::read_to_string("tests/fixtures/inventory/production.ini").unwrap();
+    let mut inventory = IniInventoryParser::new()
+        .parse(&content, "production.ini")
+        .unwrap();
+    let processor = InventoryProcessor::new()
+        .with_inventory_path(dir.path())
+        .with_vars_loader(loader.clone());
+    processor.resolve_variables(&mut inventory).unwrap();
+    let directory_vars = DirectoryVars::load(dir.path(), &inventory, &loader).unwrap();
+
+    let export = InventoryExport::new(&inventory).with_sensitive(directory_vars.sensitive);
+    let list = export.to_json();
+    assert_eq!(list["all"]["children"], json!(["datacenter", "ungrouped"]));
+    assert_eq!(list["datacenter"]["children"], json!(["db", "web"]));
+    assert_eq!(list["ungrouped"]["hosts"], json!(["mail.example.com"]));
+    assert_eq!(list["web"]["hosts"].as_array().unwrap().len(), 4);
+    let hostvars = &list["_meta"]["hostvars"];
+    assert_eq!(
+        hostvars["db-a.example.com"]["db_password"],
+        json!(MASKED_VALUE)
+    );
+    assert_eq!(hostvars["db-a.example.com"]["region"], json!("eu-west"));
+    assert_eq!(hostvars["web01.example.com"]["http_port"], json!(80));
+    assert!(hostvars["web01.example.com"].get("db_password").is_none());
+
+    let graph = export.graph("all", false).unwrap();
+    assert!(graph.starts_with("@all:\n  |--@datacenter:\n  |  |--@db:\n  |  |  |--10.0.0.1\n"));
+    assert!(graph.ends_with("  |--@ungrouped:\n  |  |--mail.example.com\n"));
+    let graph = export.graph("db", true).unwrap();
+    assert!(graph.contains("  |--db-a.example.com\n"));
+    assert!(graph.contains("  |  |--{backup = true}\n"));
+    assert!(graph.contains(&format!("{{db_password = {MASKED_VALUE}}}")));
+    assert!(!graph.contains("s3cret"));
+    assert!(export.graph("nosuchgroup", false).is_none());
+}
+
+#[tokio::test]
+async fn test_connection_preflight() {
+    // A port nothing listens on
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let mut inventory = create_test_inventory_with_groups();
+    let host = inventory.hosts.get_mut("web-server").unwrap();
+    host.address = Some("127.0.0.1".to_string());
+    host.connection.host = Some("127.0.0.1".to_string());
+    host.connection.port = Some(port);
+    let mut local = host.clone();
+    local.name = "localhost".to_string();
+    local.connection.method = ConnectionMethod::Local;
+    inventory.hosts.insert("localhost".to_string(), local);
+
+    let processor = InventoryProcessor::new();
+    assert!(processor.preflight(&inventory).await.is_none());
+
+    let processor = InventoryProcessor::new().with_preflight(
+        ConnectionPreflight::new()
+            .with_ssh_config(SshConnectionConfig::default(), SshConfig::default())
+            .with_timeout(std::time::Duration::from_secs(5)),
+    );
+    let report = processor.preflight(&inventory).await.unwrap();
+    // Only SSH and WinRM hosts are checked
+    assert_eq!(report.hosts.len(), 1);
+    let reachability = report.get("web-server").unwrap();
+    assert!(!reachability.reachable);
+    assert!(!reachability.authenticated);
+    assert!(reachability.latency.is_none());
+    assert!(reachability.error.is_some());
+
+    assert!(!report.is_usable("web-server"));
+    assert!(report.is_usable("localhost"));
+    assert_eq!(report.unusable_hosts(), vec!["web-server"]);
+    match report.require_all() {
+        Err(ValidationError::UnreachableHost { host }) => assert!(host.starts_with("web-server (")),
+        other => panic!("expected an unreachable host, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_parse_ini_inventory() {
+    let content = std::fs::read_to_string("tests/fixtures/inventory/production.ini").unwrap();
+    let inventory = IniInventoryParser::new()
+        .parse(&content, "production.ini")
+        .unwrap();
+
+    let mut names: Vec<&str> = inventory.hosts.keys().map(String::as_str).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            "10.0.0.1",
+            "10.0.0.3",
+            "10.0.0.5",
+            "badwolf.example.com",
+            "db-a.example.com",
+            "db-b.example.com",
+            "mail.example.com",
+            "web01.example.com",
+            "web02.example.com",
+            "web03.example.com",
+        ]
+    );
+    assert!(matches!(inventory.metadata.format, InventoryFormat::Ini));
+
+    let badwolf = &inventory.hosts["badwolf.example.com"];
+    assert_eq!(badwolf.connection.port, Some(5309));
+    assert_eq!(badwolf.connection.username.as_deref(), Some("admin"));
+    assert_eq!(badwolf.groups, vec!["web"]);
+
+    // Inline variables are literals, those of sections strings
+    let db = &inventory.hosts["db-a.example.com"].variables;
+    assert_eq!(db["backup"], json!(true));
+    assert_eq!(db["weight"], json!(2.5));
+    assert_eq!(db["label"], json!("42"));
+    assert_eq!(
+        inventory.hosts["web02.example.com"].variables["http_port"],
+        json!(80)
+    );
+    assert_eq!(
+        inventory.groups["web"].variables["max_clients"],
+        json!("200")
+    );
+    assert_eq!(
+        inventory.global_vars["ansible_python_interpreter"],
+        json!("/usr/bin/python3")
+    );
+
+    assert_eq!(inventory.groups["datacenter"].children, vec!["web", "db"]);
+    assert_eq!(inventory.groups["db"].parent_groups, vec!["datacenter"]);
+    assert_eq!(
+        inventory.groups["ungrouped"].hosts,
+        vec!["mail.example.com"]
+    );
+    assert_eq!(
+        inventory.hosts["mail.example.com"]
+            .connection
+            .username
+            .as_deref(),
+        Some("postmaster")
+    );
+
+    // Group variables are inherited through the children of groups
+    let mut inventory = inventory;
+    InventoryProcessor::new()
+        .resolve_variables(&mut inventory)
+        .unwrap();
+    let vars = &inventory.hosts["web01.example.com"].variables;
+    assert_eq!(vars["region"], json!("eu-west"));
+    assert_eq!(vars["ntp_server"], json!("ntp.example.com"));
+    assert_eq!(
+        vars["ansible_python_interpreter"],
+        json!("/usr/bin/python3")
+    );
+}
+
+#[test]
+fn test_host_patterns() {
+    let content = std::fs::read_to_string("tests/fixtures/inventory/production.ini").unwrap();
+    let inventory = IniInventoryParser::new()
+        .parse(&content, "production.ini")
+        .unwrap();
+    let hosts = |pattern: &str| -> Vec<String> {
+        HostPattern::parse(pattern)
+            .unwrap()
+            .hosts(&inventory)
+            .into_iter()
+            .map(str::to_string)
+            .collect()
+    };
+    let web = vec![
+        "web01.example.com",
+        "web02.example.com",
+        "web03.example.com",
+        "badwolf.example.com",
+    ];
+    let db = vec![
+        "db-a.example.com",
+        "db-b.example.com",
+        "10.0.0.1",
+        "10.0.0.3",
+        "10.0.0.5",
+    ];
+
+    assert_eq!(hosts("all").len(), 10);
+    assert_eq!(hosts("web"), web);
+    assert_eq!(hosts("ungrouped"), vec!["mail.example.com"]);
+    assert_eq!(hosts("mail.example.com"), vec!["mail.example.com"]);
+    // Groups stand for the hosts of their children
+    assert_eq!(hosts("datacenter:!db"), web);
+    assert_eq!(hosts("datacenter:&db"), db);
+    assert_eq!(hosts("!datacenter"), vec!["mail.example.com"]);
+    assert_eq!(hosts("db,web[0]").len(), 6);
+
+    assert_eq!(hosts("web[0]"), vec!["web01.example.com"]);
+    assert_eq!(hosts("web[-1]"), vec!["badwolf.example.com"]);
+    assert_eq!(
+        hosts("web[1:2]"),
+        vec!["web02.example.com", "web03.example.com"]
+    );
+    assert_eq!(
+        hosts("web[2:]"),
+        vec!["web03.example.com", "badwolf.example.com"]
+    );
+    assert!(hosts("web[7]").is_empty());
+
+    assert_eq!(
+        hosts(r"db-*.example.com:~10\.0\.0\.[13]$"),
+        vec![
+            "db-a.example.com",
+            "db-b.example.com",
+            "10.0.0.1",
+            "10.0.0.3"
+        ]
+    );
+    assert_eq!(
+        hosts(r"~web0[12]\.:~.*wolf"),
+        vec![
+            "web01.example.com",
+            "web02.example.com",
+            "badwolf.example.com"
+        ]
+    );
+    assert_eq!(hosts("*.example.com:&~db").len(), 2);
+
+    let pattern: HostPattern = "web:!~web0[23]".parse().unwrap();
+    assert_eq!(pattern.to_string(), "web:!~web0[23]");
+    assert!(pattern.matches(&inventory, "badwolf.example.com"));
+    assert!(!pattern.matches(&inventory, "web02.example.com"));
+    let filtered = pattern.filter(&inventory);
+    assert_eq!(filtered.metadata.host_count, 2);
+    assert_eq!(
+        filtered.groups["web"].hosts,
+        vec!["web01.example.com", "badwolf.example.com"]
+    );
+    assert!(filtered.groups["db"].hosts.is_empty());
+
+    assert!(matches!(HostPattern::parse(""), Err(PatternError::Empty)));
+    assert!(matches!(
+        HostPattern::parse("~web(0"),
+        Err(PatternError::InvalidRegex { .. })
+    ));
+}
+
+#[test]
+fn test_ini_host_ranges() {
+    assert_eq!(
+        expand_host_range("node[08:10]-[a:b]").unwrap(),
+        vec!["node08-a", "node08-b", "node09-a", "node09-b", "node10-a", "node10-b"]
+    );
+    assert_eq!(
+        expand_host_range("web[:2]").unwrap(),
+        vec!["web0", "web1", "web2"]
+    );
+    assert!(expand_host_range("web[01:100]").is_err());
+    assert!(expand_host_range("web[5:1]").is_err());
+    assert!(expand_host_range("web[1:3").is_err());
+
+    for invalid in [
+        "[web:nope]\nhost\n",
+        "[web]\nhost port\n",
+        "[web]\nhost x='a\n",
+    ] {
+        assert!(matches!(
+            IniInventoryParser::new().parse(invalid, "test"),
+            Err(InventoryError::InvalidIni { .. })
+        ));
+    }
+}
+
+/// A plugin whose configuration lists the hosts it returns
+struct StaticPlugin;
+
+#[async_trait]
+impl InventoryPlugin for StaticPlugin {
+    fn name(&self) -> &'static str {
+        "static"
+    }
+
+    fn aliases(&self) -> &[&'static str] {
+        &["example.static"]
+    }
+
+    async fn parse(
+        &self,
+        config: &serde_json::Value,
+        _path: &Path,
+    ) -> Result<ParsedInventory, InventoryError> {
+        dynamic_inventory(&json!({ "plugin_hosts": config["hosts"] }))
+    }
+}
+
+#[tokio::test]
+async fn test_inventory_directory_sources() {
+    let processor = InventoryProcessor::new();
+    let inventory = processor
+        .process_from_source(Path::new("tests/fixtures/inventory/sources"))
+        .await
+        .unwrap();
+
+    let mut names: Vec<&str> = inventory.hosts.keys().map(String::as_str).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            "bastion.example.com",
+            "db-01",
+            "web01.example.com",
+            "web02.example.com"
+        ]
+    );
+
+    // The script's variables are merged over those of the YAML inventory
+    let web01 = &inventory.hosts["web01.example.com"];
+    assert_eq!(web01.address.as_deref(), Some("10.0.1.1"));
+    assert_eq!(web01.variables["http_port"], json!(80));
+    assert_eq!(web01.variables["tier"], json!("frontend"));
+    assert_eq!(web01.variables["ntp_server"], json!("ntp.example.com"));
+    assert_eq!(
+        inventory.groups["web"].hosts,
+        vec!["web01.example.com", "web02.example.com"]
+    );
+
+    let db = &inventory.hosts["db-01"];
+    assert_eq!(db.connection.port, Some(2222));
+    // group_vars/ of the directory apply
+    assert_eq!(db.variables["backup"], json!(true));
+
+    let bastion = &inventory.hosts["bastion.example.com"];
+    assert_eq!(bastion.groups, vec!["ungrouped"]);
+    assert_eq!(bastion.connection.username.as_deref(), Some("jump"));
+}
+
+#[tokio::test]
+async fn test_constructed_source() {
+    let inventory = InventoryLoader::new()
+        .load(Path::new("tests/fixtures/inventory/constructed"))
+        .await
+        .unwrap();
+
+    let sorted = |group: &str| {
+        let mut hosts = inventory.groups[group].hosts.clone();
+        hosts.sort();
+        hosts
+    };
+    assert_eq!(sorted("webservers"), vec!["web-1", "web-2"]);
+    assert_eq!(sorted("production"), vec!["db-1", "web-1"]);
+    assert_eq!(sorted("role_frontend"), vec!["web-1", "web-2"]);
+    assert_eq!(
+        inventory.groups["role_database"].parent_groups,
+        vec!["roles"]
+    );
+    assert!(inventory.groups["ungrouped"].hosts.is_empty());
+    // Nothing is known of the hosts before they are probed
+    assert!(!inventory.groups.contains_key("probed"));
+
+    let web = &inventory.hosts["web-2"];
+    assert_eq!(web.variables["service_name"], json!("frontend-staging"));
+    let mut groups = web.groups.clone();
+    groups.sort();
+    assert_eq!(groups, vec!["role_frontend", "webservers"]);
+}
+
+#[tokio::test]
+async fn test_constructed_regrouping() {
+    let by_architecture: rustle_deploy::inventory::Constructed = serde_json::from_value(json!({
+        "keyed_groups": [{ "key": "ansible_architecture", "prefix": "arch" }],
+        "groups": { "db_servers": "role == 'database'" },
+    }))
+    .unwrap();
+    let inventory = InventoryProcessor::new()
+        .with_constructed(by_architecture)
+        .process_from_source(Path::new("tests/fixtures/inventory/constructed"))
+        .await
+        .unwrap();
+
+    // The constructed source is applied again once the hosts are probed
+    let mut probed = inventory.groups["probed"].hosts.clone();
+    probed.sort();
+    assert_eq!(probed, vec!["db-1", "web-1", "web-2"]);
+    assert_eq!(inventory.groups["webservers"].hosts.len(), 2);
+
+    let arch = format!("arch_{}", std::env::consts::ARCH);
+    assert_eq!(inventory.groups[&arch].hosts.len(), 3);
+    assert_eq!(inventory.groups["db_servers"].hosts, vec!["db-1"]);
+    assert!(inventory.hosts["db-1"].groups.contains(&arch));
+    assert_eq!(inventory.metadata.group_count, inventory.groups.len());
+}
+
+#[tokio::test]
+async fn test_inventory_scripts() {
+    let loader = InventoryLoader::new();
+    let inventory = loader
+        .load(Path::new("tests/fixtures/inventory/scripts/no_meta.sh"))
+        .await
+        .unwrap();
+    assert!(matches!(
+        inventory.metadata.format,
+        InventoryFormat::Dynamic
+    ));
+    assert_eq!(inventory.global_vars["env"], json!("staging"));
+    assert_eq!(
+        inventory.hosts["app-2"].address.as_deref(),
+        Some("app-2.internal")
+    );
+    assert_eq!(inventory.groups["app"].hosts, vec!["app-1", "app-2"]);
+
+    match loader
+        .load(Path::new("tests/fixtures/inventory/scripts/failing.sh"))
+        .await
+    {
+        Err(InventoryError::DynamicScriptFailed { reason, .. }) => {
+            assert!(reason.contains("cannot reach the CMDB"), "{reason}")
+        }
+        other => panic!("expected the script to fail, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_inventory_plugins() {
+    let dir = TempDir::new().unwrap();
+    let config = dir.path().join("hosts.yml");
+    std::fs::write(&config, "plugin: example.static\nhosts: [a, b]\n").unwrap();
+
+    let loader = InventoryLoader::new();
+    assert!(matches!(
+        loader.load(&config).await,
+        Err(InventoryError::UnknownPlugin { plugin }) if plugin == "example.static"
+    ));
+
+    let inventory = loader
+        .with_plugin(Arc::new(StaticPlugin))
+        .load(&config)
+        .await
+        .unwrap();
+    assert_eq!(inventory.groups["plugin_hosts"].hosts, vec!["a", "b"]);
+    assert_eq!(inventory.metadata.host_count, 2);
+}
+
+#[derive(Debug, Clone)]
+struct HttpRequest {
+    path: String,
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+type Requests = Arc<Mutex<Vec<HttpRequest>>>;
+type Respond = Arc<dyn Fn(&HttpRequest) -> String + Send + Sync>;
+
+/// Answer the HTTP request on `stream` with `respond`, recording it
+async fn serve_http<S>(stream: S, respond: &Respond, requests: &Requests)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await.unwrap();
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or_default()
+        .to_string();
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    let content_length = headers
+        .get("content-length")
+        .map_or(0, |length| length.parse().unwrap());
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await.unwrap();
+
+    let request = HttpRequest {
+        path,
+        headers,
+        body: String::from_utf8(body).unwrap(),
+    };
+    let response = respond(&request);
+    requests.lock().unwrap().push(request);
+
+    let mut stream = reader.into_inner();
+    let head = format!(
+        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+        response.len()
+    );
+    stream.write_all(head.as_bytes()).await.unwrap();
+    stream.write_all(response.as_bytes()).await.unwrap();
+}
+
+/// Serve `respond`'s answers over TCP, recording the requests
+async fn start_http_server(respond: Respond) -> (String, Requests) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let requests: Requests = Arc::default();
+    let recorded = requests.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            serve_http(stream, &respond, &recorded).await;
+        }
+    });
+    (endpoint, requests)
+}
+
+/// Serve the pages of `DescribeInstances` in the fixtures
+async fn start_ec2_server() -> (String, Requests) {
+    start_http_server(Arc::new(|request: &HttpRequest| {
+        let page = if request.body.contains("NextToken=page-2") {
+            2
+        } else {
+            1
+        };
+        std::fs::read_to_string(format!(
+            "tests/fixtures/inventory/ec2/describe_instances_{page}.xml"
+        ))
+        .unwrap()
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn test_aws_ec2_inventory() {
+    let (endpoint, requests) = start_ec2_server().await;
+    let dir = TempDir::new().unwrap();
+    let config = dir.path().join("aws_ec2.yml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"plugin: amazon.aws.aws_ec2
+regions: [us-east-1]
+endpoint_url: {endpoint}
+access_key: AKIDEXAMPLE
+secret_key: secret
+filters:
+  instance-state-name: [running, terminated]
+  tag:Environment: production
+keyed_groups:
+  - key: tags.Role
+    prefix: role
+  - key: instance_type
+    prefix: type
+    parent_group: instance_types
+compose:
+  ansible_host: private_ip_address
+cache: true
+cache_connection: {cache}
+"#,
+            cache = dir.path().join("cache").display()
+        ),
+    )
+    .unwrap();
+
+    let inventory = InventoryLoader::builtin().load(&config).await.unwrap();
+
+    // The second page follows the first; the terminated instance has no
+    // name and is left out
+    let mut hosts: Vec<&str> = inventory.hosts.keys().map(String::as_str).collect();
+    hosts.sort();
+    assert_eq!(
+        hosts,
+        vec![
+            "ec2-54-1-2-3.compute-1.amazonaws.com",
+            "ip-10-0-2-20.ec2.internal"
+        ]
+    );
+    let bodies: Vec<String> = requests
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|request| request.body.clone())
+        .collect();
+    assert_eq!(bodies.len(), 2);
+    assert!(bodies[0].starts_with("Action=DescribeInstances&Version=2016-11-15&"));
+    assert!(bodies[0].contains(
+        "Filter.1.Name=instance-state-name&Filter.1.Value.1=running&Filter.1.Value.2=terminated"
+    ));
+    assert!(bodies[0].contains("Filter.2.Name=tag%3AEnvironment&Filter.2.Value.1=production"));
+
+    let web = &inventory.hosts["ec2-54-1-2-3.compute-1.amazonaws.com"];
+    assert_eq!(web.address.as_deref(), Some("10.0.1.10"));
+    assert_eq!(web.variables["instance_id"], json!("i-0a1b2c3d4e5f60001"));
+    assert_eq!(web.variables["public_ip_address"], json!("54.1.2.3"));
+    assert_eq!(web.variables["state"]["name"], json!("running"));
+    assert_eq!(web.variables["ebs_optimized"], json!(false));
+    assert_eq!(
+        web.variables["tags"],
+        json!({"Name": "web-1", "Role": "web"})
+    );
+    assert_eq!(
+        web.variables["security_groups"],
+        json!([{"group_id": "sg-0123", "group_name": "web & api"}])
+    );
+    assert_eq!(
+        web.variables["placement"],
+        json!({"availability_zone": "us-east-1a", "tenancy": "default", "region": "us-east-1"})
+    );
+
+    assert_eq!(inventory.groups["aws_ec2"].hosts.len(), 2);
+    assert_eq!(
+        inventory.groups["role_web"].hosts,
+        vec!["ec2-54-1-2-3.compute-1.amazonaws.com"]
+    );
+    assert_eq!(
+        inventory.groups["role_db"].hosts,
+        vec!["ip-10-0-2-20.ec2.internal"]
+    );
+    let mut types = inventory.groups["instance_types"].children.clone();
+    types.sort();
+    assert_eq!(types, vec!["type_r6g_large", "type_t3_micro"]);
+    assert_eq!(
+        inventory.groups["type_t3_micro"].parent_groups,
+        vec!["instance_types"]
+    );
+
+    // The instances are cached; grouping is not, and applies at once
+    let cached = std::fs::read_to_string(&config)
+        .unwrap()
+        .replace("prefix: role", "prefix: tier");
+    std::fs::write(&config, cached).unwrap();
+    let inventory = InventoryLoader::builtin().load(&config).await.unwrap();
+    assert_eq!(requests.lock().unwrap().len(), 2);
+    assert!(inventory.groups.contains_key("tier_web"));
+    assert!(!inventory.groups.contains_key("role_web"));
+}
+
+#[tokio::test]
+async fn test_kubernetes_inventory() {
+    let (server, requests) = start_http_server(Arc::new(|request: &HttpRequest| {
+        let fixture = if request.path.starts_with("/api/v1/nodes") {
+            "nodes"
+        } else if request.path.contains("continue=page-2") {
+            "pods_2"
+        } else {
+            "pods_1"
+        };
+        std::fs::read_to_string(format!("tests/fixtures/inventory/k8s/{fixture}.json")).unwrap()
+    }))
+    .await;
+    let dir = TempDir::new().unwrap();
+    let kubeconfig = dir.path().join("kubeconfig");
+    std::fs::write(
+        &kubeconfig,
+        format!(
+            r#"apiVersion: v1
+kind: Config
+current-context: dev
+clusters:
+  - name: prod
+    cluster:
+      server: {server}
+contexts:
+  - name: prod-admin
+    context:
+      cluster: prod
+      user: admin
+users:
+  - name: admin
+    user:
+      token: s3cr3t
+"#
+        ),
+    )
+    .unwrap();
+    let config = dir.path().join("k8s.yml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"plugin: kubernetes.core.k8s
+connections:
+  - name: prod
+    kubeconfig: {kubeconfig}
+    context: prod-admin
+    namespaces: [shop]
+    nodes: true
+keyed_groups:
+  - key: pod_labels.tier
+    prefix: tier
+"#,
+            kubeconfig = kubeconfig.display()
+        ),
+    )
+    .unwrap();
+
+    let inventory = InventoryLoader::builtin().load(&config).await.unwrap();
+
+    let requests = requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[0].path, "/api/v1/namespaces/shop/pods?limit=500");
+    assert_eq!(
+        requests[1].path,
+        "/api/v1/namespaces/shop/pods?limit=500&continue=page-2"
+    );
+    assert!(requests
+        .iter()
+        .all(|request| request.headers["authorization"] == "Bearer s3cr3t"));
+
+    let mut hosts: Vec<&str> = inventory.hosts.keys().map(String::as_str).collect();
+    hosts.sort();
+    assert_eq!(
+        hosts,
+        vec![
+            "db-0_postgres",
+            "node-a",
+            "web-7d4b9c-x2x9z_app",
+            "web-7d4b9c-x2x9z_proxy"
+        ]
+    );
+
+    let proxy = &inventory.hosts["web-7d4b9c-x2x9z_proxy"];
+    assert!(matches!(proxy.connection.method, ConnectionMethod::Kubectl));
+    assert_eq!(
+        proxy.variables["ansible_kubectl_pod"],
+        json!("web-7d4b9c-x2x9z")
+    );
+    assert_eq!(proxy.variables["ansible_kubectl_container"], json!("proxy"));
+    assert_eq!(proxy.variables["ansible_kubectl_namespace"], json!("shop"));
+    assert_eq!(
+        proxy.variables["ansible_kubectl_context"],
+        json!("prod-admin")
+    );
+    assert_eq!(proxy.variables["pod_ip"], json!("10.244.1.17"));
+    assert_eq!(
+        proxy.variables["container_image"],
+        json!("envoyproxy/envoy:v1.29")
+    );
+    assert_eq!(proxy.variables["container_ready"], json!(false));
+    assert_eq!(proxy.variables["container_state"], json!("Waiting"));
+
+    let node = &inventory.hosts["node-a"];
+    assert_eq!(node.address.as_deref(), Some("192.168.10.11"));
+    assert_eq!(node.variables["node_architecture"], json!("arm64"));
+    assert_eq!(inventory.groups["nodes"].hosts, vec!["node-a"]);
+
+    let mut pods = inventory.groups["namespace_shop_pods"].hosts.clone();
+    pods.sort();
+    assert_eq!(
+        pods,
+        vec![
+            "db-0_postgres",
+            "web-7d4b9c-x2x9z_app",
+            "web-7d4b9c-x2x9z_proxy"
+        ]
+    );
+    assert_eq!(inventory.groups["prod"].children, vec!["namespace_shop"]);
+    assert_eq!(
+        inventory.groups["namespace_shop"].children,
+        vec!["namespace_shop_pods"]
+    );
+    assert_eq!(
+        inventory.groups["label_app_db"].hosts,
+        vec!["db-0_postgres"]
+    );
+    assert_eq!(inventory.groups["tier_frontend"].hosts.len(), 2);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_docker_containers_inventory() {
+    let dir = TempDir::new().unwrap();
+    let socket = dir.path().join("docker.sock");
+    let listener = tokio::net::UnixListener::bind(&socket).unwrap();
+    let requests: Requests = Arc::default();
+    let recorded = requests.clone();
+    let respond: Respond = Arc::new(|_: &HttpRequest| {
+        std::fs::read_to_string("tests/fixtures/inventory/containers.json").unwrap()
+    });
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            serve_http(stream, &respond, &recorded).await;
+        }
+    });
+
+    let config = dir.path().join("docker.yml");
+    let write_config = |extra: &str| {
+        std::fs::write(
+            &config,
+            format!(
+                "plugin: community.docker.docker_containers\ndocker_host: unix://{}\nkeyed_groups:\n  - key: docker_state\n    prefix: state\n{extra}",
+                socket.display()
+            ),
+        )
+        .unwrap();
+    };
+
+    write_config("");
+    let inventory = InventoryLoader::builtin().load(&config).await.unwrap();
+    assert_eq!(requests.lock().unwrap()[0].path, "/containers/json?all=1");
+    let web = &inventory.hosts["shop-web-1"];
+    assert!(matches!(web.connection.method, ConnectionMethod::Docker));
+    assert_eq!(web.address.as_deref(), Some("shop-web-1"));
+    assert_eq!(web.variables["docker_short_id"], json!("8dfafdbc3a40e"));
+    assert_eq!(
+        web.variables["docker_networks"],
+        json!({"shop_default": "172.18.0.2"})
+    );
+    assert_eq!(
+        web.variables["docker_labels"]["com.docker.compose.service"],
+        json!("web")
+    );
+    assert_eq!(inventory.groups["state_running"].hosts, vec!["shop-web-1"]);
+    assert_eq!(inventory.groups["state_exited"].hosts, vec!["shop-db-1"]);
+
+    write_config("connection_type: ssh\n");
+    let inventory = InventoryLoader::builtin().load(&config).await.unwrap();
+    let web = &inventory.hosts["shop-web-1"];
+    assert!(matches!(web.connection.method, ConnectionMethod::Ssh));
+    assert_eq!(web.address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(web.connection.port, Some(2222));
+}
+
+#[tokio::test]
+async fn test_gcp_compute_inventory() {
+    let (server, requests) = start_http_server(Arc::new(|request: &HttpRequest| {
+        if request.path == "/token" {
+            return json!({ "access_token": "ya29.test", "expires_in": 3599 }).to_string();
+        }
+        let page = if request.path.contains("pageToken=page-2") {
+            2
+        } else {
+            1
+        };
+        std::fs::read_to_string(format!(
+            "tests/fixtures/inventory/gcp/instances_{page}.json"
+        ))
+        .unwrap()
+    }))
+    .await;
+    let key = openssl::rsa::Rsa::generate(2048).unwrap();
+    let key = openssl::pkey::PKey::from_rsa(key).unwrap();
+    let private_key = String::from_utf8(key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+    let dir = TempDir::new().unwrap();
+    let account = dir.path().join("account.json");
+    std::fs::write(
+        &account,
+        json!({
+            "type": "service_account",
+            "client_email": "inventory@shop-prod.iam.gserviceaccount.com",
+            "private_key": private_key,
+            "token_uri": format!("{server}/token"),
+        })
+        .to_string(),
+    )
+    .unwrap();
+    let config = dir.path().join("gcp.yml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"plugin: google.cloud.gcp_compute
+projects: [shop-prod]
+filters:
+  - status = RUNNING
+  - labels.env = prod
+hostnames: [name]
+auth_kind: serviceaccount
+service_account_file: {account}
+endpoint_url: {server}
+keyed_groups:
+  - key: labels.role
+    prefix: role
+  - key: zone
+    prefix: zone
+"#,
+            account = account.display()
+        ),
+    )
+    .unwrap();
+
+    let inventory = InventoryLoader::builtin().load(&config).await.unwrap();
+
+    let requests = requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 3);
+    assert!(requests[0]
+        .body
+        .contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer"));
+    assert_eq!(
+        requests[1].path,
+        "/compute/v1/projects/shop-prod/aggregated/instances\
+         ?filter=%28status+%3D+RUNNING%29+%28labels.env+%3D+prod%29"
+    );
+    assert!(requests[2].path.ends_with("&pageToken=page-2"));
+    assert!(requests[1..]
+        .iter()
+        .all(|request| request.headers["authorization"] == "Bearer ya29.test"));
+
+    let web = &inventory.hosts["web-1"];
+    assert_eq!(web.address.as_deref(), Some("34.76.10.1"));
+    assert_eq!(web.variables["zone"], json!("europe-west1-b"));
+    assert_eq!(web.variables["machineType"], json!("e2-medium"));
+    assert_eq!(web.variables["project"], json!("shop-prod"));
+    assert_eq!(
+        inventory.hosts["db-1"].address.as_deref(),
+        Some("10.132.0.3")
+    );
+
+    let mut hosts = inventory.groups["gcp_compute"].hosts.clone();
+    hosts.sort();
+    assert_eq!(hosts, vec!["db-1", "web-1"]);
+    assert_eq!(inventory.groups["role_db"].hosts, vec!["db-1"]);
+    assert_eq!(inventory.groups["zone_europe_west1_b"].hosts, vec!["web-1"]);
+}
+
+#[tokio::test]
+async fn test_azure_rm_inventory() {
+    let (server, requests) = start_http_server(Arc::new(|request: &HttpRequest| {
+        let fixture = if request.path.ends_with("/oauth2/v2.0/token") {
+            return json!({ "access_token": "az-token", "expires_in": 3599 }).to_string();
+        } else if request.path.contains("statusOnly=true") {
+            "instance_views"
+        } else if request.path.contains("skiptoken=page-2") {
+            "virtual_machines_2"
+        } else if request.path.contains("/virtualMachines") {
+            "virtual_machines"
+        } else if request.path.contains("/networkInterfaces") {
+            "network_interfaces"
+        } else {
+            "public_ip_addresses"
+        };
+        let next = format!(
+            "http://{}/subscriptions/0000-1111/providers/Microsoft.Compute/virtualMachines\
+             ?api-version=2024-03-01&skiptoken=page-2",
+            request.headers["host"]
+        );
+        std::fs::read_to_string(format!("tests/fixtures/inventory/azure/{fixture}.json"))
+            .unwrap()
+            .replace("NEXT_LINK", &next)
+    }))
+    .await;
+    let dir = TempDir::new().unwrap();
+    let config = dir.path().join("inventory.azure_rm.yml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"plugin: azure.azcollection.azure_rm
+subscription_id: 0000-1111
+client_id: rustle
+secret: s3cr3t
+tenant: tenant-x
+endpoint_url: {server}
+authority_url: {server}
+plain_host_names: true
+keyed_groups:
+  - key: tags
+    prefix: tag
+  - key: resource_group
+    prefix: rg
+"#
+        ),
+    )
+    .unwrap();
+
+    let inventory = InventoryLoader::builtin().load(&config).await.unwrap();
+
+    let requests = requests.lock().unwrap().clone();
+    assert_eq!(requests[0].path, "/tenant-x/oauth2/v2.0/token");
+    assert!(requests[0].body.contains("grant_type=client_credentials"));
+    assert!(requests[0].body.contains("client_secret=s3cr3t"));
+    assert!(requests[1..]
+        .iter()
+        .all(|request| request.headers["authorization"] == "Bearer az-token"));
+
+    // batch-1 is deallocated, and left out by the default host filters
+    let mut hosts = inventory.groups["azure_rm"].hosts.clone();
+    hosts.sort();
+    assert_eq!(hosts, vec!["db-1", "web-1"]);
+    assert!(!inventory.hosts.contains_key("batch-1"));
+
+    let web = &inventory.hosts["web-1"];
+    assert_eq!(web.address.as_deref(), Some("20.50.1.10"));
+    assert_eq!(web.variables["resource_group"], json!("shop-prod"));
+    assert_eq!(web.variables["powerstate"], json!("running"));
+    assert_eq!(web.variables["virtual_machine_size"], json!("Standard_B2s"));
+    assert_eq!(web.variables["os_profile"]["system"], json!("linux"));
+    assert_eq!(web.variables["security_group"], json!("web-nsg"));
+    assert_eq!(
+        web.variables["public_dns_hostnames"],
+        json!(["web-1.westeurope.cloudapp.azure.com"])
+    );
+    assert_eq!(inventory.hosts["db-1"].address.as_deref(), Some("10.0.1.4"));
+    assert_eq!(inventory.groups["tag_role_web"].hosts, vec!["web-1"]);
+    assert_eq!(inventory.groups["rg_shop_prod"].hosts.len(), 2);
+}
+
 #[tokio::test]
 async fn test_process_ansible_dynamic_inventory() {
     let processor = JsonInventoryProcessor::new();
@@ -167,6 +1235,87 @@ async fn test_process_simple_json_inventory() {
     assert_eq!(host.address, Some("db-01".to_string()));
 }
 
+#[test]
+fn test_variable_precedence() {
+    let vars = |value: serde_json::Value| -> HashMap<String, serde_json::Value> {
+        serde_json::from_value(value).unwrap()
+    };
+    // Layers added in any order take effect by precedence
+    let resolver = PrecedenceResolver::new()
+        .with_layer(VariablePrecedence::ExtraVars, vars(json!({"env": "prod"})))
+        .with_layer(
+            VariablePrecedence::RoleDefaults,
+            vars(json!({"env": "dev", "port": 8080, "user": "app", "greeting": "hi"})),
+        )
+        .with_layer(
+            VariablePrecedence::InventoryGroupVars,
+            vars(json!({"port": 80, "user": "web"})),
+        )
+        .with_layer(
+            VariablePrecedence::InventoryHostVars,
+            vars(json!({"port": 443})),
+        )
+        .with_layer(
+            VariablePrecedence::TaskVars,
+            vars(json!({
+                "user": "deploy",
+                "url": "https://{{ host }}:{{ port }}/{{ env }}",
+                "host": "{{ env }}.example.com",
+                "ports": ["{{ port }}", "{{ missing }}"],
+            })),
+        );
+
+    assert_eq!(
+        resolver.get_raw("port"),
+        Some((VariablePrecedence::InventoryHostVars, &json!(443)))
+    );
+    assert_eq!(
+        resolver.resolve("url").unwrap(),
+        Some(json!("https://prod.example.com:443/prod"))
+    );
+    assert_eq!(resolver.resolve("nothing").unwrap(), None);
+
+    let resolved = resolver.resolve_all().unwrap();
+    assert_eq!(resolved["env"], json!("prod"));
+    assert_eq!(resolved["user"], json!("deploy"));
+    assert_eq!(resolved["greeting"], json!("hi"));
+    assert_eq!(resolved["ports"], json!([443, "{{ missing }}"]));
+    assert_eq!(resolved.len(), 7);
+}
+
+#[test]
+fn test_variable_cycles() {
+    let resolver = PrecedenceResolver::new()
+        .with_layer(
+            VariablePrecedence::PlayVars,
+            [
+                ("a".to_string(), json!("{{ b }}")),
+                ("b".to_string(), json!("{{ c | default('') }}")),
+                ("c".to_string(), json!("{{ a }}x")),
+            ]
+            .into_iter()
+            .collect(),
+        )
+        .with_layer(
+            VariablePrecedence::TaskVars,
+            [("name".to_string(), json!("{{ name }}-1"))]
+                .into_iter()
+                .collect(),
+        );
+
+    match resolver.resolve("a") {
+        Err(VariableError::CircularDependency { cycle }) => {
+            assert_eq!(cycle, vec!["a", "b", "c", "a"]);
+        }
+        other => panic!("expected a cycle, got {other:?}"),
+    }
+    // A variable cannot refer to a lower precedence value of itself
+    assert!(matches!(
+        resolver.resolve("name"),
+        Err(VariableError::CircularDependency { .. })
+    ));
+}
+
 fn create_test_inventory() -> ParsedInventory {
     let mut hosts = HashMap::new();
     let connection = ConnectionConfig {
@@ -0,0 +1,158 @@
+use rustle_deploy::execution::render_plan_variables;
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::modules::files::template_engine::jinja_environment::referenced_templates;
+use rustle_deploy::modules::files::template_engine::{AdvancedTemplateProcessor, TemplateError};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+const CORPUS: &str = "tests/fixtures/templates/ansible";
+
+/// Every `<name>.yml` of the corpus holds the variables `<name>.j2` is
+/// rendered with into `<name>.expected`
+#[test]
+fn test_ansible_template_corpus() {
+    let processor = AdvancedTemplateProcessor::new().unwrap();
+    let mut cases: Vec<_> = std::fs::read_dir(CORPUS)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "yml"))
+        .collect();
+    cases.sort();
+    assert!(cases.len() >= 6, "the corpus is missing templates");
+
+    for vars_file in cases {
+        let name = vars_file.file_stem().unwrap().to_string_lossy().to_string();
+        let template = std::fs::read_to_string(Path::new(CORPUS).join(format!("{name}.j2")))
+            .unwrap_or_else(|e| panic!("{name}.j2: {e}"));
+        let expected =
+            std::fs::read_to_string(Path::new(CORPUS).join(format!("{name}.expected"))).unwrap();
+        let variables: Value =
+            serde_yaml::from_str(&std::fs::read_to_string(&vars_file).unwrap()).unwrap();
+
+        let rendered = processor
+            .render_template_in(&template, &variables, Path::new(CORPUS))
+            .unwrap_or_else(|e| panic!("{name}: {e}"));
+        assert_eq!(rendered, expected, "{name} rendered differently");
+    }
+}
+
+#[test]
+fn test_template_errors() {
+    let processor = AdvancedTemplateProcessor::new().unwrap();
+
+    // Undefined variables fail, as Ansible's do, unless they have a default
+    let error = processor
+        .render_template("port = {{ port }}", &json!({}))
+        .unwrap_err();
+    assert!(
+        matches!(error, TemplateError::RenderingFailed { .. }),
+        "{error}"
+    );
+    assert_eq!(
+        processor
+            .render_template("port = {{ port | default(80) }}", &json!({}))
+            .unwrap(),
+        "port = 80"
+    );
+
+    let error = processor
+        .render_template("{% for x in items %}{{ x }}", &json!({ "items": [1] }))
+        .unwrap_err();
+    assert!(
+        matches!(error, TemplateError::UnbalancedBlocks { .. }),
+        "{error}"
+    );
+    let error = processor
+        .render_template("{{ port + }}", &json!({ "port": 1 }))
+        .unwrap_err();
+    assert!(matches!(error, TemplateError::Syntax { .. }), "{error}");
+}
+
+#[test]
+fn test_template_tests_match_conditions() {
+    let processor = AdvancedTemplateProcessor::new().unwrap();
+    let variables = json!({
+        "ansible_distribution_version": "20.04",
+        "install": {"changed": true, "failed": false},
+        "probe": {"failed": true, "skipped": false},
+        "hostname": "web-01",
+        "groups": ["web", "db"],
+        "nothing": null,
+    });
+    let check = |condition: &str| {
+        processor
+            .render_template(
+                &format!("{{% if {condition} %}}yes{{% else %}}no{{% endif %}}"),
+                &variables,
+            )
+            .unwrap_or_else(|e| panic!("{condition}: {e}"))
+    };
+
+    assert_eq!(
+        check("ansible_distribution_version is version('20.04', '>=')"),
+        "yes"
+    );
+    assert_eq!(
+        check("ansible_distribution_version is version('22.04', operator='lt')"),
+        "yes"
+    );
+    assert_eq!(check("'1.0rc1' is version('1.0.1', '<')"), "yes");
+    assert_eq!(check("install is succeeded and install is changed"), "yes");
+    assert_eq!(check("probe is failed and probe is not skipped"), "yes");
+    assert_eq!(check("hostname is match('web-\\\\d+')"), "yes");
+    assert_eq!(check("hostname is match('WEB', ignorecase=true)"), "yes");
+    assert_eq!(check("hostname is match('\\\\d+')"), "no");
+    assert_eq!(check("hostname is search('\\\\d+')"), "yes");
+    assert_eq!(
+        check("['web'] is subset(groups) and groups is superset(['db'])"),
+        "yes"
+    );
+    assert_eq!(check("'' is falsy and groups is truthy"), "yes");
+    assert_eq!(check("missing is undefined and hostname is defined"), "yes");
+    assert_eq!(check("nothing is none"), "yes");
+
+    // A test of the wrong kind of value fails, as the condition would
+    let error = processor
+        .render_template("{% if hostname is succeeded %}{% endif %}", &variables)
+        .unwrap_err();
+    assert!(error.to_string().contains("task result"), "{error}");
+}
+
+#[test]
+fn test_referenced_templates() {
+    let template = r#"{% extends 'base.j2' %}
+{% from "macros.j2" import listen %}
+{%- include ['site/' ~ name ~ '.j2', 'default.j2'] ignore missing -%}
+{% include ["a.j2", "b.j2"] %}{% import 'macros.j2' as m %}{% include partial %}"#;
+    assert_eq!(
+        referenced_templates(template),
+        ["base.j2", "macros.j2", "a.j2", "b.j2"]
+    );
+}
+
+#[test]
+fn test_plan_variables_render_with_jinja() {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .unwrap();
+    let mut plan: RustlePlanOutput = serde_json::from_str(&content).unwrap();
+    let args = &mut plan.plays[0].batches[0].tasks[0].args;
+    args.insert(
+        "content".to_string(),
+        json!("{% for port in ports %}listen {{ port }};\n{% endfor %}"),
+    );
+    args.insert("mode".to_string(), json!("{{ modes.0 | default('0644') }}"));
+    args.insert("owner".to_string(), json!("{{ item.owner }}"));
+
+    let variables = HashMap::from([
+        ("ports".to_string(), json!([80, 443])),
+        ("modes".to_string(), json!(["0600"])),
+    ]);
+    render_plan_variables(&mut plan, &variables);
+    let args = &plan.plays[0].batches[0].tasks[0].args;
+    assert_eq!(args["content"], "listen 80;\nlisten 443;\n");
+    assert_eq!(args["mode"], "0600");
+    // Loop variables are only known to the runner
+    assert_eq!(args["owner"], "{{ item.owner }}");
+}
@@ -0,0 +1,45 @@
+use rustle_deploy::binary::{HostLibc, HostPlatform, Version};
+use rustle_deploy::compilation::linkage::musl_variant;
+use rustle_deploy::compilation::{choose_linkage, Linkage};
+
+fn linux_host(libc: HostLibc) -> HostPlatform {
+    HostPlatform {
+        os: "linux".to_string(),
+        arch: "x86_64".to_string(),
+        kernel: Some(Version(5, 15)),
+        libc,
+    }
+}
+
+#[test]
+fn test_musl_variant_of_gnu_targets() {
+    assert_eq!(
+        musl_variant("x86_64-unknown-linux-gnu").as_deref(),
+        Some("x86_64-unknown-linux-musl")
+    );
+    assert_eq!(
+        musl_variant("armv7-unknown-linux-gnueabihf").as_deref(),
+        Some("armv7-unknown-linux-musleabihf")
+    );
+    assert_eq!(musl_variant("x86_64-apple-darwin"), None);
+}
+
+#[test]
+fn test_static_linkage_chosen_for_hosts_without_glibc() {
+    let triple = "x86_64-unknown-linux-gnu";
+
+    let decision = choose_linkage(triple, &[linux_host(HostLibc::Musl)], None);
+    assert_eq!(decision.target_triple, "x86_64-unknown-linux-musl");
+    assert_eq!(decision.linkage, Linkage::Static);
+    assert!(decision.reason.is_some());
+
+    let decision = choose_linkage(triple, &[linux_host(HostLibc::Glibc(Version(2, 35)))], None);
+    assert_eq!(decision.target_triple, triple);
+    assert!(decision.reason.is_none());
+
+    // Forcing either way wins over the hosts
+    let decision = choose_linkage(triple, &[linux_host(HostLibc::Musl)], Some(false));
+    assert_eq!(decision.target_triple, triple);
+    let decision = choose_linkage(triple, &[], Some(true));
+    assert_eq!(decision.target_triple, "x86_64-unknown-linux-musl");
+}
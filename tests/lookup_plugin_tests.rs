@@ -0,0 +1,308 @@
+use rustle_deploy::execution::resolve_variable_lookups;
+use rustle_deploy::modules::files::template_engine::{
+    AdvancedTemplateProcessor, LookupContext, LookupRegistry, LookupSide,
+};
+use rustle_deploy::runtime::{LookupConfig, LookupError, SecretLookups, SensitiveValues};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const CONTROLLER: &[LookupSide] = &[LookupSide::Controller];
+
+fn registry() -> LookupRegistry {
+    LookupRegistry::builtin()
+        .with_secret_lookups(Arc::new(SecretLookups::new(LookupConfig::default())))
+}
+
+async fn evaluate(value: Value, context: &LookupContext) -> Result<Value, LookupError> {
+    registry()
+        .evaluate(&value, context, CONTROLLER, &mut SensitiveValues::new())
+        .await
+}
+
+#[tokio::test]
+async fn test_file_lookups_read_the_controllers_files() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("motd"), "  welcome\n\n").unwrap();
+    std::fs::write(dir.path().join("banner"), "hello\n").unwrap();
+    let context = LookupContext::new(dir.path())
+        .with_variables(HashMap::from([("name".to_string(), json!("banner"))]));
+
+    assert_eq!(
+        evaluate(json!("{{ lookup('file', 'motd') }}"), &context)
+            .await
+            .unwrap(),
+        "  welcome"
+    );
+    assert_eq!(
+        evaluate(
+            json!("{{ lookup('ansible.builtin.file', 'motd', lstrip=true) | upper }}!"),
+            &context
+        )
+        .await
+        .unwrap(),
+        "WELCOME!"
+    );
+    // Several terms are joined by lookup() and listed by query()
+    assert_eq!(
+        evaluate(json!("{{ lookup('file', name, 'motd') }}"), &context)
+            .await
+            .unwrap(),
+        "hello,  welcome"
+    );
+    assert_eq!(
+        evaluate(json!("{{ query('file', name) }}"), &context)
+            .await
+            .unwrap(),
+        json!(["hello"])
+    );
+
+    let error = evaluate(json!("{{ lookup('file', 'missing') }}"), &context)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, LookupError::Failed { .. }), "{error}");
+    assert_eq!(
+        evaluate(
+            json!("{{ lookup('file', 'missing', errors='ignore') }}"),
+            &context
+        )
+        .await
+        .unwrap(),
+        Value::Null
+    );
+}
+
+#[tokio::test]
+async fn test_env_and_pipe_lookups_run_on_the_controller() {
+    let dir = TempDir::new().unwrap();
+    std::env::set_var("RUSTLE_LOOKUP_TEST_REGION", "eu-west-1");
+    let context = LookupContext::new(dir.path());
+
+    let value = evaluate(
+        json!({
+            "region": "{{ lookup('env', 'RUSTLE_LOOKUP_TEST_REGION') }}",
+            "unset": "{{ lookup('env', 'RUSTLE_LOOKUP_TEST_UNSET', default='none') }}",
+            "cwd": "{{ lookup('pipe', 'pwd') }}",
+            "nested": "{{ lookup('pipe', 'echo ' ~ lookup('env', 'RUSTLE_LOOKUP_TEST_REGION')) }}",
+        }),
+        &context,
+    )
+    .await
+    .unwrap();
+    assert_eq!(value["region"], "eu-west-1");
+    assert_eq!(value["unset"], "none");
+    assert_eq!(
+        value["cwd"],
+        dir.path()
+            .canonicalize()
+            .unwrap()
+            .to_string_lossy()
+            .as_ref()
+    );
+    assert_eq!(value["nested"], "eu-west-1");
+
+    let error = evaluate(json!("{{ lookup('pipe', 'exit 3') }}"), &context)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("exit status: 3"), "{error}");
+}
+
+#[tokio::test]
+async fn test_password_lookups_generate_and_keep_passwords() {
+    let dir = TempDir::new().unwrap();
+    let context = LookupContext::new(dir.path());
+    let mut sensitive = SensitiveValues::new();
+    let lookup =
+        json!("{{ lookup('password', 'credentials/db length=32 chars=ascii_lowercase') }}");
+
+    let password = registry()
+        .evaluate(&lookup, &context, CONTROLLER, &mut sensitive)
+        .await
+        .unwrap();
+    let password = password.as_str().unwrap().to_string();
+    assert_eq!(password.len(), 32);
+    assert!(password.chars().all(|c| c.is_ascii_lowercase()));
+    assert_eq!(sensitive.len(), 1);
+
+    let stored = dir.path().join("credentials/db");
+    assert_eq!(std::fs::read_to_string(&stored).unwrap().trim(), password);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&stored).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // The stored password is returned from then on
+    assert_eq!(evaluate(lookup, &context).await.unwrap(), password.as_str());
+    let fresh = evaluate(json!("{{ lookup('password', '/dev/null') }}"), &context)
+        .await
+        .unwrap();
+    assert_eq!(fresh.as_str().unwrap().len(), 20);
+}
+
+#[tokio::test]
+async fn test_csvfile_and_ini_lookups() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(
+        dir.path().join("users.csv"),
+        "alice,1001,\"Alice, Admin\"\nbob,1002,Bob\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("app.ini"),
+        "[global]\nname = app\n\n[db]\n; primary\nhost = db1\nport: 5432\nreplica_1 = db2\nreplica_2 = db3\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("app.properties"), "user.name=deploy\n").unwrap();
+    let context = LookupContext::new(dir.path());
+
+    let value = evaluate(
+        json!({
+            "uid": "{{ lookup('csvfile', 'bob file=users.csv delimiter=,') }}",
+            "gecos": "{{ lookup('csvfile', 'alice', file='users.csv', delimiter=',', col=2) }}",
+            "missing": "{{ lookup('csvfile', 'carol file=users.csv delimiter=, default=-') }}",
+            "name": "{{ lookup('ini', 'name file=app.ini') }}",
+            "port": "{{ lookup('ini', 'port section=db file=app.ini') | int }}",
+            "replicas": "{{ query('ini', 'replica_.* section=db file=app.ini re=true') }}",
+            "user": "{{ lookup('ini', 'user.name type=properties file=app.properties') }}",
+            "absent": "{{ lookup('ini', 'absent section=db file=app.ini default=none') }}",
+        }),
+        &context,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "uid": "1002",
+            "gecos": "Alice, Admin",
+            "missing": "-",
+            "name": "app",
+            "port": 5432,
+            "replicas": ["db2", "db3"],
+            "user": "deploy",
+            "absent": "none",
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_url_lookups_fetch_over_http() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/keys", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request).await.unwrap();
+            let body = "ssh-ed25519 AAAA alice\nssh-ed25519 BBBB bob\n";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    let context = LookupContext::new(".");
+
+    assert_eq!(
+        evaluate(json!(format!("{{{{ query('url', '{url}') }}}}")), &context)
+            .await
+            .unwrap(),
+        json!(["ssh-ed25519 AAAA alice", "ssh-ed25519 BBBB bob"])
+    );
+    assert_eq!(
+        evaluate(
+            json!(format!(
+                "{{{{ lookup('url', '{url}', split_lines=false) }}}}"
+            )),
+            &context
+        )
+        .await
+        .unwrap(),
+        "ssh-ed25519 AAAA alice\nssh-ed25519 BBBB bob\n"
+    );
+}
+
+#[tokio::test]
+async fn test_templates_call_lookups() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("id.pub"), "ssh-ed25519 AAAA\n").unwrap();
+    std::fs::write(dir.path().join("motd.j2"), "Welcome to {{ host }}").unwrap();
+    let context = LookupContext::new(dir.path());
+    let template = "key: {{ lookup('file', 'id.pub') }}\n\
+                    motd: {{ lookup('template', 'motd.j2') }}\n\
+                    {% if query('file', 'id.pub') %}authorized{% else %}open{% endif %}";
+
+    let rendered = AdvancedTemplateProcessor::new()
+        .unwrap()
+        .render_template_with_lookups(template, &json!({ "host": "web1" }), &registry(), &context)
+        .await
+        .unwrap();
+    assert!(rendered.contains("key: ssh-ed25519 AAAA\n"), "{rendered}");
+    assert!(rendered.contains("motd: Welcome to web1\n"), "{rendered}");
+    assert!(rendered.ends_with("authorized"), "{rendered}");
+}
+
+#[tokio::test]
+async fn test_lookups_are_evaluated_on_their_side() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("user"), "app").unwrap();
+    let context = LookupContext::new(dir.path());
+
+    // Secret stores are left for the runners
+    let value = json!(
+        "{{ lookup('file', 'user') }}:{{ lookup('hashivault', 'secret/data/app:password') }}"
+    );
+    assert_eq!(
+        evaluate(value, &context).await.unwrap(),
+        "app:{{ lookup('hashivault', 'secret/data/app:password') }}"
+    );
+
+    // A runner refuses the lookups of the controller
+    let runner = SecretLookups::new(LookupConfig::default());
+    let error = runner
+        .resolve(&json!("{{ lookup('file', 'user') }}"))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error, LookupError::ControllerOnly { .. }),
+        "{error}"
+    );
+
+    let error = evaluate(json!("{{ lookup('nope', 'x') }}"), &context)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error, LookupError::UnknownPlugin { .. }),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn test_variable_definitions_call_lookups() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("db.txt"), "postgres").unwrap();
+    let mut variables = HashMap::from([
+        ("db_file".to_string(), json!("db.txt")),
+        (
+            "db_engine".to_string(),
+            json!("{{ lookup('file', db_file) }}"),
+        ),
+        (
+            "db_password".to_string(),
+            json!("{{ lookup('password', 'db.pass length=12') }}"),
+        ),
+    ]);
+
+    let sensitive =
+        resolve_variable_lookups(&mut variables, &registry(), &LookupContext::new(dir.path()))
+            .await
+            .unwrap();
+    assert_eq!(variables["db_engine"], "postgres");
+    assert_eq!(variables["db_password"].as_str().unwrap().len(), 12);
+    assert_eq!(sensitive.len(), 1);
+}
@@ -0,0 +1,325 @@
+mod helpers;
+
+use helpers::TaskBuilder;
+use rustle_deploy::execution::ExecutionPlan;
+use rustle_deploy::runtime::{
+    AwsCredentials, AwsLookupConfig, HashiVaultAuth, HashiVaultConfig, LocalExecutor, LookupConfig,
+    LookupError, RuntimeConfig, SecretLookups,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+#[derive(Debug, Clone)]
+struct Request {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: serde_json::Value,
+}
+
+type Requests = Arc<Mutex<Vec<Request>>>;
+
+/// Serve `respond`'s answers to JSON requests, recording the requests
+async fn start_server<F>(respond: F) -> (String, Requests)
+where
+    F: Fn(&Request) -> (u16, serde_json::Value) + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let requests: Requests = Arc::default();
+    let respond = Arc::new(respond);
+
+    let recorded = requests.clone();
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                return;
+            };
+            let (recorded, respond) = (recorded.clone(), respond.clone());
+            tokio::spawn(async move {
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).await.unwrap();
+                let mut parts = request_line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_string();
+                let path = parts.next().unwrap_or_default().to_string();
+
+                let mut headers = HashMap::new();
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).await.unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+                    }
+                }
+                let content_length = headers
+                    .get("content-length")
+                    .map_or(0, |length| length.parse().unwrap());
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).await.unwrap();
+
+                let request = Request {
+                    method,
+                    path,
+                    headers,
+                    body: serde_json::from_slice(&body).unwrap_or_default(),
+                };
+                let (status, response) = respond(&request);
+                recorded.lock().unwrap().push(request);
+
+                let response = response.to_string();
+                let mut stream = reader.into_inner();
+                let head = format!(
+                    "HTTP/1.1 {status} Status\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    response.len()
+                );
+                stream.write_all(head.as_bytes()).await.unwrap();
+                stream.write_all(response.as_bytes()).await.unwrap();
+            });
+        }
+    });
+
+    (base_url, requests)
+}
+
+/// A Vault server with one KV version 2 secret, for the token "root-token"
+/// and the tokens its AppRole login hands out
+async fn start_vault() -> (String, Requests) {
+    start_server(|request| match request.path.as_str() {
+        "/v1/auth/approle/login" if request.body["secret_id"] == "s3cret-id" => {
+            (200, json!({ "auth": { "client_token": "approle-token" } }))
+        }
+        "/v1/secret/data/app"
+            if ["root-token", "approle-token"]
+                .contains(&request.headers["x-vault-token"].as_str()) =>
+        {
+            (
+                200,
+                json!({ "data": {
+                    "data": { "username": "app", "password": "hunter2" },
+                    "metadata": { "version": 3 }
+                } }),
+            )
+        }
+        _ => (403, json!({ "errors": ["permission denied"] })),
+    })
+    .await
+}
+
+fn vault_config(url: &str, auth: HashiVaultAuth) -> LookupConfig {
+    LookupConfig {
+        hashivault: HashiVaultConfig {
+            url: Some(url.to_string()),
+            namespace: Some("team-a".to_string()),
+            auth,
+        },
+        ..LookupConfig::default()
+    }
+}
+
+fn root_token() -> HashiVaultAuth {
+    HashiVaultAuth::Token {
+        token: Some("root-token".to_string()),
+    }
+}
+
+fn aws_config(endpoint: &str) -> LookupConfig {
+    LookupConfig {
+        aws: AwsLookupConfig {
+            region: Some("eu-west-1".to_string()),
+            endpoint: Some(endpoint.to_string()),
+            credentials: Some(AwsCredentials {
+                access_key_id: "AKIDEXAMPLE".to_string(),
+                secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+                session_token: None,
+            }),
+        },
+        ..LookupConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn test_hashivault_lookups_read_kv_secrets() {
+    let (url, requests) = start_vault().await;
+    let lookups = SecretLookups::new(vault_config(&url, root_token()));
+
+    let secret = lookups
+        .resolve(&json!("{{ lookup('hashivault', 'secret/data/app') }}"))
+        .await
+        .unwrap();
+    assert_eq!(secret, json!({ "username": "app", "password": "hunter2" }));
+
+    let args = lookups
+        .resolve(&json!({
+            "url": "postgres://app:{{ lookup(\"hashi_vault\", \"secret/data/app:password\") }}@db",
+            "count": 3,
+        }))
+        .await
+        .unwrap();
+    assert_eq!(args["url"], "postgres://app:hunter2@db");
+    assert_eq!(args["count"], 3);
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests[0].method, "GET");
+    assert_eq!(requests[0].headers["x-vault-namespace"], "team-a");
+}
+
+#[tokio::test]
+async fn test_hashivault_lookups_log_in_with_approle() {
+    let (url, requests) = start_vault().await;
+    let lookups = SecretLookups::new(vault_config(
+        &url,
+        HashiVaultAuth::AppRole {
+            role_id: "deployer".to_string(),
+            secret_id: Some("s3cret-id".to_string()),
+            mount: "approle".to_string(),
+        },
+    ));
+
+    for term in ["secret/data/app:username", "secret/data/app:password"] {
+        lookups.lookup("hashivault", term).await.unwrap();
+    }
+    let paths: Vec<_> = requests
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|request| request.path.clone())
+        .collect();
+    // The token of the login is reused
+    assert_eq!(
+        paths,
+        [
+            "/v1/auth/approle/login",
+            "/v1/secret/data/app",
+            "/v1/secret/data/app"
+        ]
+    );
+
+    let error = lookups
+        .lookup("hashivault", "secret/data/app:missing")
+        .await
+        .unwrap_err();
+    assert!(matches!(error, LookupError::Failed { .. }), "{error}");
+}
+
+#[tokio::test]
+async fn test_aws_lookups_sign_their_requests() {
+    let (endpoint, requests) = start_server(|request| {
+        match request.headers["x-amz-target"].as_str() {
+            "secretsmanager.GetSecretValue" if request.body["SecretId"] == "prod/db" => (
+                200,
+                json!({ "Name": "prod/db", "SecretString": "{\"password\":\"hunter2\"}" }),
+            ),
+            "AmazonSSM.GetParameter" if request.body["Name"] == "/prod/db/port" => (
+                200,
+                json!({ "Parameter": { "Name": "/prod/db/port", "Value": "5432" } }),
+            ),
+            _ => (
+                400,
+                json!({ "__type": "com.amazonaws#ResourceNotFoundException", "message": "no such secret" }),
+            ),
+        }
+    })
+    .await;
+    let lookups = SecretLookups::new(aws_config(&endpoint));
+
+    assert_eq!(
+        lookups.lookup("aws_secret", "prod/db").await.unwrap(),
+        "{\"password\":\"hunter2\"}"
+    );
+    assert_eq!(
+        lookups
+            .lookup("amazon.aws.aws_ssm", "/prod/db/port")
+            .await
+            .unwrap(),
+        "5432"
+    );
+    let error = lookups.lookup("aws_secret", "prod/none").await.unwrap_err();
+    assert!(
+        error.to_string().contains("ResourceNotFoundException"),
+        "{error}"
+    );
+
+    let requests = requests.lock().unwrap();
+    let ssm = &requests[1];
+    assert_eq!(ssm.method, "POST");
+    assert_eq!(ssm.body["WithDecryption"], true);
+    let authorization = &ssm.headers["authorization"];
+    assert!(
+        authorization.contains("/eu-west-1/ssm/aws4_request"),
+        "{authorization}"
+    );
+    assert!(
+        authorization.contains(
+            "SignedHeaders=content-type;host;x-amz-content-sha256;x-amz-date;x-amz-target"
+        ),
+        "{authorization}"
+    );
+}
+
+#[tokio::test]
+async fn test_lookups_are_cached_for_their_ttl() {
+    let (url, requests) = start_vault().await;
+    let cached = SecretLookups::new(vault_config(&url, root_token()));
+    for _ in 0..3 {
+        cached
+            .lookup("hashivault", "secret/data/app:password")
+            .await
+            .unwrap();
+    }
+    assert_eq!(requests.lock().unwrap().len(), 1);
+
+    let uncached = SecretLookups::new(LookupConfig {
+        cache_ttl_secs: 0,
+        ..vault_config(&url, root_token())
+    });
+    for _ in 0..2 {
+        uncached
+            .lookup("hashivault", "secret/data/app:password")
+            .await
+            .unwrap();
+    }
+    assert_eq!(requests.lock().unwrap().len(), 3);
+
+    assert!(matches!(
+        cached.lookup("password_store", "app").await,
+        Err(LookupError::UnknownPlugin { .. })
+    ));
+}
+
+fn plan(tasks: Vec<serde_json::Value>) -> ExecutionPlan {
+    helpers::plan("lookups", serde_json::json!({ "tasks": tasks }))
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_runner_resolves_lookups_before_running_tasks() {
+    let (url, _) = start_vault().await;
+    let mut executor = LocalExecutor::new(RuntimeConfig {
+        lookups: Some(vault_config(&url, root_token())),
+        ..Default::default()
+    });
+    let result = executor
+        .execute_plan(plan(vec![TaskBuilder::command(
+            "connect",
+            "echo {{ lookup('hashivault', 'secret/data/app:password') }}",
+        )
+        .build()]))
+        .await
+        .unwrap();
+
+    let connect = &result.task_results["connect"];
+    assert!(!connect.failed, "{:?}", connect.error);
+    assert_eq!(connect.stdout.as_deref().map(str::trim), Some("hunter2"));
+    // The audit log keeps the lookup, not the secret
+    assert_eq!(
+        result.audit[0].params["cmd"],
+        "echo {{ lookup('hashivault', 'secret/data/app:password') }}"
+    );
+}
@@ -0,0 +1,142 @@
+mod helpers;
+
+use helpers::{plan, TaskBuilder};
+use rustle_deploy::runtime::{ExecutionResult, LocalExecutor, RuntimeConfig};
+use std::collections::HashMap;
+
+fn task(id: &str, cmd: &str, task_loop: serde_json::Value) -> serde_json::Value {
+    TaskBuilder::command(id, cmd)
+        .set("task_loop", task_loop)
+        .build()
+}
+
+async fn execute(
+    tasks: Vec<serde_json::Value>,
+    variables: HashMap<String, serde_json::Value>,
+) -> ExecutionResult {
+    let plan = plan("loops", serde_json::json!({ "tasks": tasks }));
+    let mut executor = LocalExecutor::new(RuntimeConfig::default()).with_variables(variables);
+    executor.execute_plan(plan).await.unwrap()
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_loop_executes_once_per_item_with_loop_control() {
+    let mut users = task(
+        "users",
+        "echo {{ user.name }} {{ i }}",
+        serde_json::json!({
+            "kind": "loop",
+            "items": "{{ users }}",
+            "control": { "loop_var": "user", "index_var": "i", "label": "{{ user.name }}" }
+        }),
+    );
+    users["conditions"] = serde_json::json!([{
+        "variable": "user.active", "operator": "Equals", "value": true
+    }]);
+    let variables = HashMap::from([(
+        "users".to_string(),
+        serde_json::json!([
+            { "name": "alice", "active": true },
+            { "name": "bob", "active": false },
+            { "name": "carol", "active": true },
+        ]),
+    )]);
+
+    let result = execute(vec![users], variables).await;
+    assert!(result.success, "{:?}", result.errors);
+    let users = &result.task_results["users"];
+    assert!(users.changed);
+    assert_eq!(users.output["msg"], "All items completed");
+
+    let results = users.output["results"].as_array().unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(
+        results[0]["stdout"].as_str().map(str::trim),
+        Some("alice 0")
+    );
+    assert_eq!(results[0]["ansible_loop_var"], "user");
+    assert_eq!(results[1]["skipped"], true);
+    assert_eq!(results[1]["_ansible_item_label"], "bob");
+    assert_eq!(
+        results[2]["stdout"].as_str().map(str::trim),
+        Some("carol 2")
+    );
+    assert_eq!(results[2]["i"], 2);
+    assert_eq!(results[2]["item"]["name"], "carol");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_with_dict_aggregates_item_failures() {
+    let result = execute(
+        vec![
+            task(
+                "check",
+                "test {{ item.value }} -gt 1",
+                serde_json::json!({
+                    "kind": "with_dict",
+                    "items": { "web": 2, "db": 1 },
+                }),
+            ),
+            task(
+                "nothing",
+                "echo {{ item }}",
+                serde_json::json!({ "kind": "with_items", "items": [] }),
+            ),
+        ],
+        HashMap::new(),
+    )
+    .await;
+
+    let check = &result.task_results["check"];
+    assert!(check.failed);
+    assert_eq!(check.output["msg"], "One or more items failed");
+    let failed: Vec<&serde_json::Value> = check.output["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|item| item["failed"] == true)
+        .map(|item| &item["item"]["key"])
+        .collect();
+    assert_eq!(failed, ["db"]);
+
+    let nothing = &result.task_results["nothing"];
+    assert!(nothing.skipped);
+    assert_eq!(nothing.output["msg"], "No items in the list");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_until_retries_until_the_result_satisfies_conditions() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let counter = dir.path().join("attempts");
+    let count = format!("sh -c 'echo x >> {0}; wc -l < {0}'", counter.display());
+    let until = |variable: &str, value: &str, retries: u32| {
+        serde_json::json!({
+            "conditions": [{ "variable": variable, "operator": "Contains", "value": value }],
+            "register": "count",
+            "retries": retries,
+            "delay": 0,
+        })
+    };
+
+    let mut eventually = task("eventually", &count, serde_json::Value::Null);
+    eventually["until"] = until("count.stdout", "3", 5);
+    let mut never = task("never", "echo nope", serde_json::Value::Null);
+    never["until"] = until("count.stdout", "yes", 2);
+
+    let result = execute(vec![eventually, never], HashMap::new()).await;
+
+    let eventually = &result.task_results["eventually"];
+    assert!(!eventually.failed, "{:?}", eventually.error);
+    assert_eq!(eventually.output["attempts"], 3);
+
+    let never = &result.task_results["never"];
+    assert!(never.failed);
+    assert_eq!(never.output["attempts"], 3);
+    assert_eq!(
+        never.error.as_deref(),
+        Some("Until conditions not met after 3 attempts")
+    );
+}
@@ -0,0 +1,138 @@
+use chrono::Utc;
+use rustle_deploy::deploy::{serve_metrics, CollectedResults, DeploymentMetrics};
+use rustle_deploy::runtime::{ExecutionResult, ExecutionSummary, TaskResult, TaskStatus};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+fn task(name: &str, millis: u64, status: TaskStatus) -> TaskResult {
+    TaskResult {
+        task_id: name.to_string(),
+        name: name.to_string(),
+        changed: false,
+        failed: status == TaskStatus::Failed,
+        skipped: status == TaskStatus::Skipped,
+        output: serde_json::Value::Null,
+        stdout: None,
+        stderr: None,
+        start_time: Utc::now(),
+        end_time: Utc::now(),
+        duration: Duration::from_millis(millis),
+        error: None,
+        status,
+    }
+}
+
+fn execution(tasks: Vec<TaskResult>) -> ExecutionResult {
+    let failed = tasks.iter().any(|task| task.failed);
+    ExecutionResult {
+        execution_id: "metrics".to_string(),
+        success: !failed,
+        failed,
+        summary: ExecutionSummary {
+            total_tasks: 0,
+            completed_tasks: 0,
+            failed_tasks: 0,
+            skipped_tasks: 0,
+            changed_tasks: 0,
+            ignored_tasks: 0,
+            rescued_tasks: 0,
+        },
+        task_results: tasks
+            .into_iter()
+            .map(|task| (task.task_id.clone(), task))
+            .collect(),
+        start_time: Utc::now(),
+        end_time: Utc::now(),
+        duration: Duration::from_secs(1),
+        errors: Vec::new(),
+        module_metrics: Default::default(),
+        handler_results: Vec::new(),
+        spans: Vec::new(),
+        audit: Vec::new(),
+    }
+}
+
+fn metrics() -> DeploymentMetrics {
+    let metrics = DeploymentMetrics::new();
+    metrics.record_collected(&CollectedResults {
+        deployment_id: "nightly".to_string(),
+        results: BTreeMap::from([
+            (
+                "web1".to_string(),
+                execution(vec![
+                    task("install nginx", 2_000, TaskStatus::Success),
+                    task("open firewall", 50, TaskStatus::Skipped),
+                ]),
+            ),
+            (
+                "web2".to_string(),
+                execution(vec![task("install nginx", 700, TaskStatus::Failed)]),
+            ),
+        ]),
+        missing_hosts: vec!["db1".to_string()],
+    });
+    metrics.record_transfer("web1", 4096);
+    metrics.record_transfer("web1", 1024);
+    metrics
+}
+
+#[test]
+fn test_collected_results_are_counted() {
+    let text = metrics().render();
+
+    for line in [
+        "rustle_hosts_total{phase=\"execute\",status=\"succeeded\"} 1",
+        "rustle_hosts_total{phase=\"execute\",status=\"failed\"} 1",
+        "rustle_hosts_total{phase=\"execute\",status=\"missing\"} 1",
+        "rustle_task_duration_seconds_bucket{task=\"install nginx\",le=\"1\"} 1",
+        "rustle_task_duration_seconds_bucket{task=\"install nginx\",le=\"5\"} 2",
+        "rustle_task_duration_seconds_count{task=\"install nginx\"} 2",
+        "rustle_task_duration_seconds_sum{task=\"install nginx\"} 2.7",
+        "rustle_transfer_bytes_total{host=\"web1\"} 5120",
+        "rustle_compile_cache_hit_ratio 0",
+        "# TYPE rustle_task_duration_seconds histogram",
+    ] {
+        assert!(text.contains(line), "{line} missing from\n{text}");
+    }
+    // Skipped tasks did not run
+    assert!(!text.contains("open firewall"));
+}
+
+#[test]
+fn test_textfile_is_written_for_the_collector() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("textfile").join("rustle.prom");
+    metrics().write_textfile(&path).unwrap();
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), metrics().render());
+    // Nothing but the finished file is left for the collector to read
+    let files: Vec<_> = std::fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(files, ["rustle.prom"]);
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint_serves_the_metrics() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let metrics = Arc::new(metrics());
+    let server = tokio::spawn(serve_metrics(listener, Arc::clone(&metrics)));
+
+    let response = reqwest::get(format!("http://{address}/metrics"))
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain; version=0.0.4"));
+    assert_eq!(response.text().await.unwrap(), metrics.render());
+
+    let missing = reqwest::get(format!("http://{address}/")).await.unwrap();
+    assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+    server.abort();
+}
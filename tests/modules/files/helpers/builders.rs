@@ -374,6 +374,7 @@ pub struct TemplateTestBuilder {
     variables: Option<HashMap<String, Value>>,
     trim_blocks: Option<bool>,
     lstrip_blocks: Option<bool>,
+    validate: Option<String>,
 }
 
 impl TemplateTestBuilder {
@@ -389,6 +390,7 @@ impl TemplateTestBuilder {
             variables: None,
             trim_blocks: None,
             lstrip_blocks: None,
+            validate: None,
         }
     }
 
@@ -453,6 +455,11 @@ impl TemplateTestBuilder {
         self
     }
 
+    pub fn validate<S: Into<String>>(mut self, validate: S) -> Self {
+        self.validate = Some(validate.into());
+        self
+    }
+
     pub fn build(self) -> ModuleArgs {
         let mut args = HashMap::new();
 
@@ -499,6 +506,10 @@ impl TemplateTestBuilder {
             args.insert("lstrip_blocks".to_string(), Value::Bool(lstrip_blocks));
         }
 
+        if let Some(validate) = self.validate {
+            args.insert("validate".to_string(), Value::String(validate));
+        }
+
         ModuleArgs {
             args,
             special: SpecialParameters::default(),
@@ -330,3 +330,26 @@ async fn test_copy_symlink_follow() {
     assert_file_content(&dest_path, "target content").unwrap();
     assert_is_file(&dest_path);
 }
+
+/// Test that a copy replaces the destination only once it passes validation
+#[tokio::test]
+async fn test_copy_validate_before_replacing() {
+    let env = TestEnvironment::new();
+    let dest_path = env.create_test_file("sudoers", "root ALL=(ALL) ALL\n");
+    let invalid = env.create_test_file("invalid", "root ALL=(ALL\n");
+    let valid = env.create_test_file("valid", "admin ALL=(ALL) ALL\n");
+    let copy = |src: &std::path::Path| {
+        CopyTestBuilder::new()
+            .src(src.to_string_lossy())
+            .dest(dest_path.to_string_lossy())
+            .validate("grep -q 'ALL)' %s")
+            .build()
+    };
+
+    assert!(env.execute_module("copy", copy(&invalid)).await.is_err());
+    assert_file_content(&dest_path, "root ALL=(ALL) ALL\n").unwrap();
+
+    let result = env.execute_module("copy", copy(&valid)).await.unwrap();
+    assert!(result.changed);
+    assert_file_content(&dest_path, "admin ALL=(ALL) ALL\n").unwrap();
+}
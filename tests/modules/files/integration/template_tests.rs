@@ -399,3 +399,90 @@ Item {{@index}}: {{this}}
     assert!(content.contains("value_0"));
     assert!(content.contains("value_999"));
 }
+
+/// Test templates extending, importing and including templates found
+/// along `ansible_search_path`
+#[tokio::test]
+async fn test_template_search_path() {
+    let env = TestEnvironment::new();
+    let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/templates/searchpath");
+    let output_path = env.temp_path("nginx.conf");
+
+    let args = TemplateTestBuilder::new()
+        .src(fixtures.join("site/nginx.conf.j2").to_string_lossy())
+        .dest(output_path.to_string_lossy())
+        .variable(
+            "ansible_search_path",
+            vec![
+                fixtures.join("role").to_string_lossy().to_string(),
+                fixtures.join("shared").to_string_lossy().to_string(),
+            ],
+        )
+        .variable("ports", vec![80, 443])
+        .variable("server_name", "example.com")
+        .variable(
+            "locations",
+            serde_json::json!([{"path": "/", "backend": "http://app"}]),
+        )
+        .build();
+
+    let result = env.execute_module("template", args).await.unwrap();
+
+    assert!(result.changed);
+    let expected = std::fs::read_to_string(fixtures.join("nginx.conf.expected")).unwrap();
+    assert_file_content(&output_path, &expected).unwrap();
+}
+
+/// Test that a rendered file replaces the destination only once it passes
+/// validation
+#[tokio::test]
+async fn test_template_validate_before_replacing() {
+    let env = TestEnvironment::new();
+    let template_path = env.create_test_file("app.conf.j2", "port = {{ port }}\n");
+    let output_path = env.create_test_file("app.conf", "port = 80\n");
+    let render = |port: &str| {
+        TemplateTestBuilder::new()
+            .src(template_path.to_string_lossy())
+            .dest(output_path.to_string_lossy())
+            .variable("port", port)
+            .backup(true)
+            .validate("grep -q '^port = [0-9]*$' %s")
+            .build()
+    };
+
+    let error = env
+        .execute_module("template", render("eighty"))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("Validation command"), "{error}");
+    assert_file_content(&output_path, "port = 80\n").unwrap();
+    let leftovers: Vec<_> = std::fs::read_dir(output_path.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| name.contains(".tmp."))
+        .collect();
+    assert!(leftovers.is_empty(), "{leftovers:?}");
+
+    let result = env
+        .execute_module("template", render("8080"))
+        .await
+        .unwrap();
+    assert!(result.changed);
+    assert_file_content(&output_path, "port = 8080\n").unwrap();
+    assert_file_content(
+        result.results["backup_file"].as_str().unwrap(),
+        "port = 80\n",
+    )
+    .unwrap();
+
+    let missing_placeholder = TemplateTestBuilder::new()
+        .src(template_path.to_string_lossy())
+        .dest(output_path.to_string_lossy())
+        .validate("true")
+        .build();
+    assert!(env
+        .execute_module("template", missing_placeholder)
+        .await
+        .is_err());
+}
@@ -0,0 +1,54 @@
+mod helpers;
+
+use helpers::{plan, TaskBuilder};
+use rustle_deploy::runtime::{ExecutionResult, LocalExecutor, RuntimeConfig, NO_LOG_MESSAGE};
+
+fn task(id: &str, after: Option<&str>, cmd: &str) -> serde_json::Value {
+    TaskBuilder::command(id, cmd)
+        .after(after.as_slice())
+        .play("site")
+        .continue_on_failure()
+        .build()
+}
+
+async fn execute(tasks: Vec<serde_json::Value>) -> ExecutionResult {
+    let plan = plan("no-log", serde_json::json!({ "tasks": tasks }));
+    let mut executor = LocalExecutor::new(RuntimeConfig::default());
+    executor.execute_plan(plan).await.unwrap()
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_no_log_results_are_censored_but_registered() {
+    let mut token = task("token", None, "echo s3cr3t-token");
+    token["no_log"] = true.into();
+    token["register"] = "token_out".into();
+    let mut rotate = task("rotate", Some("token"), "echo rotated");
+    rotate["when"] = serde_json::json!(["token_out.stdout is search('s3cr3t')"]);
+    let mut users = task("users", Some("rotate"), "echo {{ item }}");
+    users["no_log"] = true.into();
+    users["task_loop"] = serde_json::json!({ "kind": "loop", "items": ["hunter2", "swordfish"] });
+    let mut login = task("login", Some("users"), "false s3cr3t-password");
+    login["no_log"] = true.into();
+
+    let result = execute(vec![token, rotate, users, login]).await;
+    assert!(result.task_results["rotate"].stdout.is_some());
+
+    let token = &result.task_results["token"];
+    assert!(token.stdout.is_none());
+    assert_eq!(token.output["censored"], NO_LOG_MESSAGE);
+    let items = result.task_results["users"].output["results"]
+        .as_array()
+        .unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0]["censored"], NO_LOG_MESSAGE);
+    assert_eq!(items[0]["changed"], true);
+    let login = &result.task_results["login"];
+    assert!(login.failed);
+    assert_eq!(login.error.as_deref(), Some(NO_LOG_MESSAGE));
+
+    let serialized = serde_json::to_string(&result).unwrap();
+    for secret in ["s3cr3t", "hunter2", "swordfish"] {
+        assert!(!serialized.contains(secret), "{secret} leaked");
+    }
+}
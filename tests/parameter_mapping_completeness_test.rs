@@ -1,5 +1,6 @@
 // Include the parameter mapping modules for testing
 #[path = "../src/templates/modules/parameter_mapping/mod.rs"]
+#[allow(dead_code, unused_imports)]
 mod parameter_mapping;
 
 #[test]
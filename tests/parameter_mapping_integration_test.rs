@@ -3,6 +3,7 @@ use std::collections::HashMap;
 
 // Include the parameter mapping modules for testing
 #[path = "../src/templates/modules/parameter_mapping/mod.rs"]
+#[allow(dead_code)]
 mod parameter_mapping;
 
 #[test]
@@ -296,3 +297,37 @@ fn test_real_world_file_operations_scenario() {
     );
     assert_eq!(mapped.get("state").unwrap().as_str().unwrap(), "link");
 }
+
+#[test]
+fn test_module_defaults_and_environment_precedence() {
+    let mapper = parameter_mapping::ParameterMapper::new();
+    let defaults = |value: serde_json::Value| -> parameter_mapping::ModuleDefaults {
+        serde_json::from_value(value).unwrap()
+    };
+    let play = defaults(serde_json::json!({
+        "command": {"chdir": "/srv", "creates": "/srv/play"},
+        "package": {"state": "latest"},
+    }));
+    let block = defaults(serde_json::json!({
+        "ansible.builtin.command": {"creates": "/srv/fqcn", "removes": "/srv/block"},
+    }));
+    let task = defaults(serde_json::json!({"command": {"creates": "/srv/task"}}));
+    let mut params = HashMap::new();
+    params.insert("_raw_params".to_string(), Value::from("make install"));
+    params.insert("removes".to_string(), Value::from("/srv/own"));
+    let environment = HashMap::from([("PATH".to_string(), "/opt/bin".to_string())]);
+
+    let mapped = mapper
+        .map_for_task("command", params, &[&play, &block, &task], &environment)
+        .unwrap();
+
+    assert_eq!(mapped["cmd"], "make install");
+    assert_eq!(mapped["chdir"], "/srv");
+    assert_eq!(mapped["creates"], "/srv/task");
+    assert_eq!(mapped["removes"], "/srv/own");
+    assert!(!mapped.contains_key("state"));
+    assert_eq!(
+        mapped[parameter_mapping::ENVIRONMENT_PARAM],
+        serde_json::json!({"PATH": "/opt/bin"})
+    );
+}
@@ -0,0 +1,178 @@
+use rustle_deploy::api::parse_plan;
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::execution::{
+    plan_json_schema, HandlerBuilder, PlanBuildError, PlanBuilder, PlayBuilder, TaskBuilder,
+    VaultSecrets, PLAN_SCHEMA_VERSION,
+};
+use serde_json::Value;
+
+/// The fixtures written in the rustle-plan format
+const FIXTURES: &[&str] = &[
+    "file_operations_plan.json",
+    "file_operations_playbook_with_facts_plan.json",
+    "package_management_plan.json",
+    "service_management_plan.json",
+];
+
+fn fixture(name: &str) -> RustlePlanOutput {
+    let path = format!("tests/fixtures/execution_plans/{name}");
+    let content = std::fs::read_to_string(&path).expect("Failed to read test fixture");
+    parse_plan(&content, &VaultSecrets::default()).unwrap()
+}
+
+/// Why `plan` does not follow the published schema
+fn schema_errors(plan: &Value) -> Vec<String> {
+    let schema = plan_json_schema();
+    let validator = jsonschema::validator_for(&schema).expect("The schema compiles");
+    validator.iter_errors(plan).map(|e| e.to_string()).collect()
+}
+
+fn web_play() -> PlayBuilder {
+    PlayBuilder::new("web")
+        .with_hosts(["web1", "web2"])
+        .with_task(
+            TaskBuilder::new("Install nginx", "package")
+                .with_arg("name", "nginx")
+                .with_arg("state", "present"),
+        )
+        .with_task(
+            TaskBuilder::new("Configure nginx", "template")
+                .with_id("configure")
+                .with_arg("src", "nginx.conf.j2")
+                .with_arg("dest", "/etc/nginx/nginx.conf")
+                .with_dependency("task_0")
+                .with_notify("restart web server"),
+        )
+        .with_handler(
+            HandlerBuilder::new("Restart nginx", "service")
+                .with_arg("name", "nginx")
+                .with_arg("state", "restarted")
+                .with_listen("restart web server"),
+        )
+}
+
+#[test]
+fn test_published_schema_describes_the_current_version() {
+    let schema = plan_json_schema();
+    assert_eq!(schema["title"], "RustlePlanOutput");
+    assert!(schema["description"]
+        .as_str()
+        .unwrap()
+        .ends_with(&format!("schema version {PLAN_SCHEMA_VERSION}")));
+    let required = schema["required"].as_array().unwrap();
+    assert!(required.contains(&Value::from("plays")));
+    assert!(!required.contains(&Value::from("schema_version")));
+}
+
+#[test]
+fn test_fixture_plans_follow_the_schema_and_round_trip() {
+    for name in FIXTURES {
+        let plan = fixture(name);
+        let json = serde_json::to_value(&plan).unwrap();
+        let errors = schema_errors(&json);
+        assert!(errors.is_empty(), "{name}: {errors:?}");
+
+        let reparsed: RustlePlanOutput = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&reparsed).unwrap(), json, "{name}");
+    }
+}
+
+#[test]
+fn test_schema_refuses_malformed_plans() {
+    let mut plan = serde_json::to_value(fixture(FIXTURES[0])).unwrap();
+    plan.as_object_mut().unwrap().remove("plays");
+    assert!(!schema_errors(&plan).is_empty());
+
+    let mut plan = serde_json::to_value(fixture(FIXTURES[0])).unwrap();
+    plan["plays"][0]["batches"][0]["tasks"][0]["estimated_duration"]["nanos"] =
+        Value::from(1_000_000_000u64);
+    assert!(!schema_errors(&plan).is_empty());
+}
+
+#[test]
+fn test_builder_numbers_tasks_and_fills_in_the_plan() {
+    let plan = PlanBuilder::new()
+        .with_play(web_play())
+        .with_play(
+            PlayBuilder::new("db")
+                .with_hosts(["db1", "web1"])
+                .with_task(TaskBuilder::new("Ping", "ping").with_hosts(["db1"])),
+        )
+        .build()
+        .unwrap();
+
+    assert_eq!(plan.schema_version, PLAN_SCHEMA_VERSION);
+    assert_eq!(plan.total_tasks, 3);
+    assert_eq!(plan.hosts, ["web1", "web2", "db1"]);
+
+    let web = &plan.plays[0];
+    assert_eq!(web.play_id, "play-0");
+    let tasks = &web.batches[0].tasks;
+    assert_eq!(tasks[0].task_id, "task_0");
+    assert_eq!(tasks[0].hosts, ["web1", "web2"]);
+    assert_eq!(tasks[1].task_id, "configure");
+    assert_eq!(tasks[1].execution_order, 1);
+    assert_eq!(web.handlers[0].handler_id, "handler_0");
+
+    let db = &plan.plays[1].batches[0].tasks[0];
+    assert_eq!(db.task_id, "task_2");
+    assert_eq!(db.execution_order, 2);
+    assert_eq!(db.hosts, ["db1"]);
+}
+
+#[test]
+fn test_built_plans_follow_the_schema_and_round_trip() {
+    let plan = PlanBuilder::new().with_play(web_play()).build().unwrap();
+    let json = serde_json::to_value(&plan).unwrap();
+    let errors = schema_errors(&json);
+    assert!(errors.is_empty(), "{errors:?}");
+
+    let parsed = parse_plan(&json.to_string(), &VaultSecrets::default()).unwrap();
+    assert_eq!(serde_json::to_value(&parsed).unwrap(), json);
+
+    // The same plays hash the same
+    let again = PlanBuilder::new().with_play(web_play()).build().unwrap();
+    assert_eq!(again.metadata.playbook_hash, plan.metadata.playbook_hash);
+    assert_eq!(again.metadata.inventory_hash, plan.metadata.inventory_hash);
+}
+
+#[test]
+fn test_builder_refuses_inconsistent_plans() {
+    let error = PlanBuilder::new()
+        .with_play(PlayBuilder::new("nowhere").with_task(TaskBuilder::new("Ping", "ping")))
+        .build()
+        .unwrap_err();
+    assert!(matches!(error, PlanBuildError::NoHosts { play } if play == "nowhere"));
+
+    let error = PlanBuilder::new()
+        .with_play(web_play().with_task(TaskBuilder::new("Again", "ping").with_id("configure")))
+        .build()
+        .unwrap_err();
+    assert!(matches!(error, PlanBuildError::DuplicateId { id } if id == "configure"));
+
+    let error = PlanBuilder::new()
+        .with_play(
+            web_play().with_task(TaskBuilder::new("Later", "ping").with_dependency("missing")),
+        )
+        .build()
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        PlanBuildError::UnknownDependency { dependency, .. } if dependency == "missing"
+    ));
+
+    // Handlers of other plays cannot be notified
+    let error = PlanBuilder::new()
+        .with_play(web_play())
+        .with_play(
+            PlayBuilder::new("db")
+                .with_hosts(["db1"])
+                .with_task(TaskBuilder::new("Ping", "ping").with_notify("Restart nginx")),
+        )
+        .build()
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        PlanBuildError::UnknownHandler { handler, .. } if handler == "Restart nginx"
+    ));
+}
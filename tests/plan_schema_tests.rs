@@ -0,0 +1,147 @@
+use rustle_deploy::api::{parse_plan, parse_plan_with_mode};
+use rustle_deploy::execution::plan_schema::{
+    schema_version, LEGACY_SCHEMA_VERSION, SCHEMA_VERSION_FIELD,
+};
+use rustle_deploy::execution::{
+    MigrationError, MigrationStep, ParseMode, PlanMigrations, VaultSecrets, PLAN_SCHEMA_VERSION,
+};
+use serde_json::Value;
+
+fn fixture() -> Value {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    serde_json::from_str(&content).unwrap()
+}
+
+/// The fixture as written before the schema was versioned, with the legacy
+/// fields of its deployment
+fn legacy_plan() -> Value {
+    let mut plan = fixture();
+    plan.as_object_mut().unwrap().remove(SCHEMA_VERSION_FIELD);
+    let deployment = &mut plan["binary_deployments"][0];
+    let tasks = deployment["tasks"].take();
+    let deployment = deployment.as_object_mut().unwrap();
+    deployment.remove("tasks");
+    deployment.insert("task_ids".to_string(), tasks);
+    deployment.insert("binary_name".to_string(), "".into());
+    let requirements = deployment["compilation_requirements"]
+        .as_object_mut()
+        .unwrap();
+    requirements.insert("target_arch".to_string(), "".into());
+    requirements.insert("target_os".to_string(), "".into());
+    requirements.insert(
+        "target_triple".to_string(),
+        "aarch64-unknown-linux-gnu".into(),
+    );
+    plan
+}
+
+#[test]
+fn test_legacy_plan_is_migrated_to_the_current_schema() {
+    let mut plan = legacy_plan();
+    assert_eq!(schema_version(&plan).unwrap(), LEGACY_SCHEMA_VERSION);
+
+    let report = PlanMigrations::new()
+        .migrate(&mut plan, ParseMode::Strict)
+        .unwrap();
+    assert_eq!(report.from_version, LEGACY_SCHEMA_VERSION);
+    assert_eq!(report.to_version, PLAN_SCHEMA_VERSION);
+    assert_eq!(report.applied.len(), 1);
+    assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+    assert_eq!(plan[SCHEMA_VERSION_FIELD], PLAN_SCHEMA_VERSION);
+
+    let deployment = &plan["binary_deployments"][0];
+    assert_eq!(
+        deployment["tasks"],
+        fixture()["binary_deployments"][0]["tasks"]
+    );
+    let id = deployment["deployment_id"].as_str().unwrap();
+    assert_eq!(deployment["binary_name"], format!("rustle-runner-{id}"));
+    assert_eq!(
+        deployment["compilation_requirements"]["target_arch"],
+        "aarch64"
+    );
+    assert_eq!(deployment["compilation_requirements"]["target_os"], "linux");
+
+    let report = PlanMigrations::new()
+        .migrate(&mut plan, ParseMode::Strict)
+        .unwrap();
+    assert!(report.is_current());
+}
+
+#[test]
+fn test_parsed_plans_are_of_the_current_schema() {
+    let content = legacy_plan().to_string();
+    let plan = parse_plan(&content, &VaultSecrets::default()).unwrap();
+    assert_eq!(plan.schema_version, PLAN_SCHEMA_VERSION);
+    assert!(!plan.binary_deployments[0].tasks.is_empty());
+
+    let reparsed: Value = serde_json::to_value(&plan).unwrap();
+    assert_eq!(reparsed[SCHEMA_VERSION_FIELD], PLAN_SCHEMA_VERSION);
+}
+
+#[test]
+fn test_strict_mode_refuses_what_lenient_mode_warns_about() {
+    let mut plan = legacy_plan();
+    let deployment = plan["binary_deployments"][0].as_object_mut().unwrap();
+    deployment.remove("task_ids");
+
+    let err = PlanMigrations::new()
+        .migrate(&mut plan.clone(), ParseMode::Strict)
+        .unwrap_err();
+    assert!(matches!(err, MigrationError::MissingRequiredField { .. }));
+
+    let report = PlanMigrations::new()
+        .migrate(&mut plan, ParseMode::Lenient)
+        .unwrap();
+    assert_eq!(report.to_version, PLAN_SCHEMA_VERSION);
+    assert_eq!(report.warnings.len(), 1);
+    assert!(report.warnings[0].contains("tasks or task_ids"));
+}
+
+#[test]
+fn test_newer_plans_are_refused_in_strict_mode() {
+    let mut plan = fixture();
+    plan[SCHEMA_VERSION_FIELD] = (PLAN_SCHEMA_VERSION + 1).into();
+    let content = plan.to_string();
+
+    let err =
+        parse_plan_with_mode(&content, &VaultSecrets::default(), ParseMode::Strict).unwrap_err();
+    assert!(err.to_string().contains("newer"), "{err}");
+
+    let plan = parse_plan(&content, &VaultSecrets::default()).unwrap();
+    assert_eq!(plan.schema_version, PLAN_SCHEMA_VERSION + 1);
+}
+
+#[test]
+fn test_registered_steps_run_in_order() {
+    fn rename_hosts(plan: &mut Value, warnings: &mut Vec<String>) -> Result<(), MigrationError> {
+        let hosts = plan["hosts"].take();
+        plan["target_hosts"] = hosts;
+        warnings.push("hosts were renamed".to_string());
+        Ok(())
+    }
+
+    let migrations = PlanMigrations::new()
+        .with_step(MigrationStep::new(
+            PLAN_SCHEMA_VERSION,
+            "Rename hosts",
+            rename_hosts,
+        ))
+        .with_current_version(PLAN_SCHEMA_VERSION + 1);
+    let mut plan = legacy_plan();
+    let report = migrations.migrate(&mut plan, ParseMode::Strict).unwrap();
+    assert_eq!(report.applied.len(), 2);
+    assert_eq!(report.applied[1], "Rename hosts");
+    assert_eq!(report.warnings, ["hosts were renamed"]);
+    assert_eq!(plan[SCHEMA_VERSION_FIELD], PLAN_SCHEMA_VERSION + 1);
+    assert!(plan["target_hosts"].is_array());
+
+    let mut unmigratable = fixture();
+    unmigratable[SCHEMA_VERSION_FIELD] = 0.into();
+    let err = migrations
+        .migrate(&mut unmigratable, ParseMode::Lenient)
+        .unwrap_err();
+    assert!(matches!(err, MigrationError::UnsupportedVersion { .. }));
+}
@@ -0,0 +1,369 @@
+use rustle_deploy::execution::rustle_plan::{RustlePlanOutput, TaskCondition, TaskPlan};
+use rustle_deploy::execution::{is_playbook, LoopKind, PlaybookError, PlaybookLoader};
+use rustle_deploy::inventory::InventoryProcessor;
+use rustle_deploy::types::inventory::ParsedInventory;
+use serde_json::json;
+use std::path::Path;
+
+const PLAYBOOK: &str = "tests/fixtures/playbooks/site.yml";
+
+async fn inventory() -> ParsedInventory {
+    InventoryProcessor::new()
+        .with_architecture_from_facts()
+        .process_from_source(Path::new("tests/fixtures/playbooks/hosts"))
+        .await
+        .unwrap()
+}
+
+async fn plan() -> RustlePlanOutput {
+    PlaybookLoader::new()
+        .load(Path::new(PLAYBOOK), Some(&inventory().await))
+        .unwrap()
+}
+
+fn task<'a>(plan: &'a RustlePlanOutput, name: &str) -> &'a TaskPlan {
+    plan.plays
+        .iter()
+        .flat_map(|play| &play.batches)
+        .flat_map(|batch| &batch.tasks)
+        .find(|task| task.name == name)
+        .unwrap_or_else(|| panic!("No task {name}"))
+}
+
+#[tokio::test]
+async fn test_plays_are_planned_for_their_hosts() {
+    let plan = plan().await;
+
+    assert_eq!(plan.plays.len(), 2);
+    let web = &plan.plays[0];
+    assert_eq!(web.name, "Configure web servers");
+    assert_eq!(web.hosts, vec!["web1", "web2"]);
+    // The imported playbook runs on the controller
+    assert_eq!(plan.plays[1].hosts, vec!["localhost"]);
+    assert_eq!(plan.hosts, vec!["web1", "web2", "localhost"]);
+    assert!(plan.binary_deployments.is_empty());
+
+    let names: Vec<&str> = web.batches[0]
+        .tasks
+        .iter()
+        .map(|task| task.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            "Update the package cache",
+            "common : Install the message of the day",
+            "nginx : Install nginx",
+            "nginx : Write the site",
+            "nginx : Report the broken site",
+            "Copy the release",
+        ]
+    );
+    assert_eq!(plan.total_tasks, 7);
+}
+
+#[tokio::test]
+async fn test_modules_and_arguments() {
+    let plan = plan().await;
+
+    let update = task(&plan, "Update the package cache");
+    assert_eq!(update.module, "apt");
+    assert_eq!(update.args["update_cache"], json!("yes"));
+    assert_eq!(update.args["cache_valid_time"], json!("3600"));
+    assert_eq!(update.r#become.as_ref().unwrap().enabled, Some(true));
+
+    let report = task(&plan, "Report");
+    assert_eq!(report.module, "command");
+    assert_eq!(report.args["_raw_params"], json!("echo deployed"));
+    assert!(report.changed_when.is_some());
+}
+
+#[tokio::test]
+async fn test_role_variables_follow_their_precedence() {
+    let plan = plan().await;
+
+    // The role parameter refers to a play variable and overrides the role's
+    // default, which the other defaults do not
+    let report = task(&plan, "nginx : Report the broken site");
+    assert_eq!(
+        report.args["msg"],
+        json!("nginx on 8080 with 2 workers failed")
+    );
+}
+
+#[tokio::test]
+async fn test_role_dependencies_run_once() {
+    let plan = plan().await;
+
+    // The play applies common and nginx depends on it, which does not apply
+    // it again
+    let common = plan.plays[0].batches[0]
+        .tasks
+        .iter()
+        .filter(|task| task.name.starts_with("common : "))
+        .count();
+    assert_eq!(common, 1);
+}
+
+#[tokio::test]
+async fn test_role_files_and_templates_are_located() {
+    let plan = plan().await;
+
+    let motd = task(&plan, "common : Install the message of the day");
+    assert_eq!(
+        motd.args["src"],
+        json!("tests/fixtures/playbooks/roles/common/files/motd")
+    );
+    let site = task(&plan, "nginx : Write the site");
+    assert_eq!(
+        site.args["src"],
+        json!("tests/fixtures/playbooks/roles/nginx/templates/site.conf.j2")
+    );
+    // Sources found nowhere are read on the target
+    let copy = task(&plan, "Copy the release");
+    assert_eq!(copy.args["src"], json!("{{ item }}"));
+}
+
+#[tokio::test]
+async fn test_blocks_and_handlers() {
+    let plan = plan().await;
+    let web = &plan.plays[0];
+
+    assert_eq!(web.blocks.len(), 1);
+    let block = &web.blocks[0];
+    assert_eq!(block.name.as_deref(), Some("Configure nginx"));
+    assert_eq!(
+        block.block,
+        vec![task(&plan, "nginx : Write the site").task_id.clone()]
+    );
+    assert_eq!(
+        block.rescue,
+        vec![task(&plan, "nginx : Report the broken site")
+            .task_id
+            .clone()]
+    );
+
+    let handlers: Vec<&str> = web.handlers.iter().map(|h| h.name.as_str()).collect();
+    assert_eq!(handlers, vec!["restart nginx", "reload app"]);
+    assert_eq!(
+        task(&plan, "nginx : Write the site").notify,
+        vec!["restart nginx"]
+    );
+}
+
+#[tokio::test]
+async fn test_included_tasks_inherit_conditions_and_tags() {
+    let plan = plan().await;
+
+    let copy = task(&plan, "Copy the release");
+    assert_eq!(copy.tags, vec!["deploy"]);
+    assert!(matches!(
+        &copy.conditions[..],
+        [TaskCondition::When { expression }] if expression == "deploy_app | default(true)"
+    ));
+    // Variables of the play are rendered, the loop item is left to the runner
+    assert_eq!(copy.args["dest"], json!("/srv/app/current/"));
+    assert_eq!(copy.args["src"], json!("{{ item }}"));
+    let task_loop = copy.task_loop.as_ref().unwrap();
+    assert_eq!(task_loop.kind, LoopKind::Loop);
+    assert_eq!(task_loop.items, json!(["app.tar.gz", "app.conf"]));
+}
+
+#[test]
+fn test_plays_without_inventory() {
+    // Plays for the controller run without an inventory, others need one
+    let plan = PlaybookLoader::new()
+        .load(Path::new("tests/fixtures/playbooks/checks.yml"), None)
+        .unwrap();
+    assert_eq!(plan.hosts, vec!["localhost"]);
+
+    let error = PlaybookLoader::new()
+        .load(Path::new(PLAYBOOK), None)
+        .unwrap_err();
+    assert!(matches!(error, PlaybookError::Invalid { .. }));
+}
+
+#[test]
+fn test_unplannable_playbooks_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let playbook = dir.path().join("site.yml");
+
+    std::fs::write(&playbook, "- hosts: localhost\n  roles:\n    - missing\n").unwrap();
+    let error = PlaybookLoader::new().load(&playbook, None).unwrap_err();
+    assert!(matches!(error, PlaybookError::RoleNotFound { ref role, .. } if role == "missing"));
+
+    std::fs::write(
+        &playbook,
+        "- hosts: localhost\n  tasks:\n    - name: Wait\n      command: check\n      until: result.rc == 0\n",
+    )
+    .unwrap();
+    let error = PlaybookLoader::new().load(&playbook, None).unwrap_err();
+    assert!(matches!(error, PlaybookError::Unsupported { ref keyword, .. } if keyword == "until"));
+}
+
+#[test]
+fn test_is_playbook() {
+    assert!(is_playbook(Path::new("site.yml")));
+    assert!(is_playbook(Path::new("playbooks/site.yaml")));
+    assert!(!is_playbook(Path::new("plan.json")));
+    assert!(!is_playbook(Path::new("-")));
+}
+
+/// A playbook with the files `files` next to it, in a temporary directory
+fn playbook_dir(files: &[(&str, &str)]) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for (name, content) in files {
+        let path = dir.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn test_includes_are_planned_for_each_item() {
+    let dir = playbook_dir(&[
+        (
+            "site.yml",
+            "- hosts: localhost\n  tasks:\n    - include_tasks: user.yml\n      loop: [alice, bob]\n      loop_control:\n        loop_var: user\n",
+        ),
+        (
+            "user.yml",
+            "- name: Create user\n  user:\n    name: \"{{ user }}\"\n",
+        ),
+    ]);
+    let plan = PlaybookLoader::new()
+        .load(&dir.path().join("site.yml"), None)
+        .unwrap();
+
+    let users: Vec<&serde_json::Value> = plan.plays[0].batches[0]
+        .tasks
+        .iter()
+        .map(|task| &task.args["name"])
+        .collect();
+    assert_eq!(users, vec![&json!("alice"), &json!("bob")]);
+}
+
+#[test]
+fn test_included_variables_apply_to_later_tasks() {
+    let dir = playbook_dir(&[
+        (
+            "site.yml",
+            "- hosts: localhost\n  tasks:\n    - name: Before\n      debug:\n        msg: \"{{ port }}\"\n    - include_vars: app.yml\n    - include_vars:\n        file: db.yml\n        name: db\n    - name: After\n      debug:\n        msg: \"{{ port }} {{ db.host }}\"\n",
+        ),
+        ("vars/app.yml", "port: 8080\n"),
+        ("db.yml", "host: db1\n"),
+    ]);
+    let plan = PlaybookLoader::new()
+        .load(&dir.path().join("site.yml"), None)
+        .unwrap();
+
+    assert_eq!(plan.total_tasks, 2);
+    assert_eq!(task(&plan, "Before").args["msg"], json!("{{ port }}"));
+    assert_eq!(task(&plan, "After").args["msg"], json!("8080 db1"));
+}
+
+#[test]
+fn test_missing_files_are_reported_together() {
+    let dir = playbook_dir(&[(
+        "site.yml",
+        "- hosts: localhost\n  vars_files: [secrets.yml]\n  tasks:\n    - include_tasks: setup.yml\n    - name: Load the settings\n      include_vars: settings.yml\n",
+    )]);
+    let error = PlaybookLoader::new()
+        .load(&dir.path().join("site.yml"), None)
+        .unwrap_err();
+
+    let PlaybookError::MissingFiles(missing) = error else {
+        panic!("Expected missing files, got {error}");
+    };
+    let keywords: Vec<&str> = missing.iter().map(|file| file.keyword.as_str()).collect();
+    assert_eq!(
+        keywords,
+        vec!["vars_files", "include_tasks", "include_vars"]
+    );
+    assert_eq!(missing[2].included_by, "Load the settings");
+    assert!(missing[1].path.ends_with("setup.yml"));
+}
+
+#[test]
+fn test_role_dependency_cycles_are_refused() {
+    let dir = playbook_dir(&[
+        ("site.yml", "- hosts: localhost\n  roles:\n    - app\n"),
+        (
+            "roles/app/meta/main.yml",
+            "allow_duplicates: true\ndependencies:\n  - base\n",
+        ),
+        ("roles/base/meta/main.yml", "dependencies:\n  - app\n"),
+        (
+            "roles/app/tasks/main.yml",
+            "- name: App\n  command: echo app\n",
+        ),
+    ]);
+    let error = PlaybookLoader::new()
+        .load(&dir.path().join("site.yml"), None)
+        .unwrap_err();
+
+    assert!(
+        matches!(error, PlaybookError::RoleCycle { ref chain } if chain == "app -> base -> app")
+    );
+}
+
+#[test]
+fn test_plan_includes_are_inlined() {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .unwrap();
+    let mut plan: RustlePlanOutput = serde_json::from_str(&content).unwrap();
+    {
+        let tasks = &mut plan.plays[0].batches[0].tasks;
+        tasks[2].module = "ansible.builtin.include_tasks".to_string();
+        tasks[2].args = [("file".to_string(), json!("extra.yml"))].into();
+        tasks[2].tags = vec!["extra".to_string()];
+        tasks[3].dependencies = vec![tasks[2].task_id.clone()];
+    }
+    let dir = playbook_dir(&[(
+        "extra.yml",
+        "- name: First\n  command: echo one\n- name: Second\n  command: echo two\n",
+    )]);
+
+    PlaybookLoader::new()
+        .resolve_includes(&mut plan, dir.path())
+        .unwrap();
+
+    let ids: Vec<&str> = plan.plays[0].batches[0]
+        .tasks
+        .iter()
+        .map(|task| task.task_id.as_str())
+        .collect();
+    assert_eq!(
+        ids,
+        vec![
+            "task_0",
+            "task_1",
+            "task_2.task_0",
+            "task_2.task_1",
+            "task_3",
+            "task_4"
+        ]
+    );
+    assert_eq!(plan.total_tasks, 6);
+    assert_eq!(task(&plan, "Second").tags, vec!["extra"]);
+    let dependent = &plan.plays[0].batches[0].tasks[4];
+    assert_eq!(
+        dependent.dependencies,
+        vec!["task_2.task_0", "task_2.task_1"]
+    );
+    assert!(plan.binary_deployments[0]
+        .tasks
+        .contains(&"task_2.task_1".to_string()));
+
+    // Files missing are refused before anything is built
+    let mut plan: RustlePlanOutput = serde_json::from_str(&content).unwrap();
+    plan.plays[0].batches[0].tasks[2].module = "include_tasks".to_string();
+    plan.plays[0].batches[0].tasks[2].args =
+        [("_raw_params".to_string(), json!("gone.yml"))].into();
+    let error = PlaybookLoader::new()
+        .resolve_includes(&mut plan, dir.path())
+        .unwrap_err();
+    assert!(matches!(error, PlaybookError::MissingFiles(ref missing) if missing.len() == 1));
+}
@@ -0,0 +1,160 @@
+use rustle_deploy::compilation::backends::prebuilt::runner_file_name;
+use rustle_deploy::compilation::backends::{CompilationBackend, PrebuiltBackend};
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::runtime::{signature_path, BinarySigner};
+use rustle_deploy::template::{
+    append_archive, pack_archive, split_appended, unpack_archive, BinaryTemplateGenerator,
+    TargetInfo, TemplateConfig,
+};
+use rustle_deploy::types::compilation::TargetSpecification;
+use rustle_deploy::types::platform::Platform;
+use std::path::Path;
+use tempfile::TempDir;
+
+const TARGET: &str = "x86_64-unknown-linux-musl";
+/// Nothing listens there, so a download fails at once
+const RELEASE_URL: &str = "http://127.0.0.1:9/releases";
+
+fn fake_runner() -> Vec<u8> {
+    let mut runner = b"\x7fELF".to_vec();
+    runner.extend_from_slice(&[0x42; 512]);
+    runner
+}
+
+/// A backend whose cache holds `runner`, signed by `signer`
+fn cached_backend(cache: &Path, runner: &[u8], signer: &BinarySigner) -> PrebuiltBackend {
+    let path = cache.join("1.0.0").join(runner_file_name(TARGET));
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, runner).unwrap();
+    std::fs::write(
+        signature_path(&path),
+        signer.sign(runner, &runner_file_name(TARGET)).encode(),
+    )
+    .unwrap();
+    PrebuiltBackend::new(RELEASE_URL, BinarySigner::from_seed(&[7; 32]).public_key())
+        .with_version("1.0.0")
+        .with_cache_dir(Some(cache.to_path_buf()))
+}
+
+async fn generate_template() -> rustle_deploy::template::GeneratedTemplate {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    let plan: RustlePlanOutput = serde_json::from_str(&content).unwrap();
+    let target_info = TargetInfo {
+        target_triple: TARGET.to_string(),
+        platform: Platform::Linux,
+        architecture: "x86_64".to_string(),
+        os_family: "unix".to_string(),
+        libc: Some("musl".to_string()),
+        features: vec![],
+    };
+    BinaryTemplateGenerator::new(TemplateConfig::default())
+        .unwrap()
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &target_info)
+        .await
+        .expect("Failed to generate template")
+}
+
+#[tokio::test]
+async fn test_appended_archive_round_trip() {
+    let template = generate_template().await;
+    let archive = pack_archive(&template.sections).unwrap();
+    assert_eq!(archive, pack_archive(&template.sections).unwrap());
+
+    let runner = fake_runner();
+    let binary = append_archive(&runner, &archive, None);
+    let parts = split_appended(&binary).expect("data is appended");
+    assert_eq!(parts.runner, runner.as_slice());
+    assert_eq!(parts.archive, archive.as_slice());
+    assert_eq!(parts.signature, None);
+
+    let sections = unpack_archive(parts.archive).unwrap();
+    assert_eq!(sections.encoding, template.sections.encoding);
+    assert_eq!(sections.sections.len(), template.sections.sections.len());
+    for (unpacked, packed) in sections.sections.iter().zip(&template.sections.sections) {
+        assert_eq!(unpacked.name, packed.name);
+        assert_eq!(unpacked.path, packed.path);
+        assert_eq!(unpacked.data, packed.data);
+    }
+
+    assert!(split_appended(&runner).is_none());
+}
+
+#[test]
+fn test_runner_urls() {
+    let backend = PrebuiltBackend::new(
+        "https://example.com/runners/",
+        BinarySigner::from_seed(&[7; 32]).public_key(),
+    )
+    .with_version("1.2.3");
+    assert_eq!(
+        backend.runner_url(TARGET),
+        "https://example.com/runners/1.2.3/rustle-runner-x86_64-unknown-linux-musl"
+    );
+    assert_eq!(
+        runner_file_name("x86_64-pc-windows-msvc"),
+        "rustle-runner-x86_64-pc-windows-msvc.exe"
+    );
+    assert!(backend.supports_target(TARGET));
+    assert!(!backend.supports_target("riscv64gc-unknown-linux-gnu"));
+    assert!(!backend.get_capabilities().requires_toolchain);
+}
+
+#[test]
+fn test_runners_must_be_signed_executables() {
+    let signer = BinarySigner::from_seed(&[7; 32]);
+    let backend = PrebuiltBackend::new(RELEASE_URL, signer.public_key());
+    let runner = fake_runner();
+    let signature = signer.sign(&runner, "rustle-runner").encode();
+    backend.verify(&runner, &signature, TARGET).unwrap();
+
+    let forged = BinarySigner::from_seed(&[8; 32])
+        .sign(&runner, "rustle-runner")
+        .encode();
+    assert!(backend.verify(&runner, &forged, TARGET).is_err());
+
+    let mut tampered = runner.clone();
+    tampered[100] ^= 1;
+    assert!(backend.verify(&tampered, &signature, TARGET).is_err());
+
+    let script = b"#!/bin/sh\n".to_vec();
+    let signature = signer.sign(&script, "rustle-runner").encode();
+    assert!(backend.verify(&script, &signature, TARGET).is_err());
+}
+
+#[tokio::test]
+async fn test_cached_runner_is_reverified() {
+    let cache = TempDir::new().unwrap();
+    let runner = fake_runner();
+    let backend = cached_backend(cache.path(), &runner, &BinarySigner::from_seed(&[7; 32]));
+    assert_eq!(backend.fetch_runner(TARGET).await.unwrap(), runner);
+
+    // Signed by another key, so it is downloaded again, which fails here
+    let cache = TempDir::new().unwrap();
+    let backend = cached_backend(cache.path(), &runner, &BinarySigner::from_seed(&[8; 32]));
+    assert!(backend.fetch_runner(TARGET).await.is_err());
+}
+
+#[tokio::test]
+async fn test_execution_data_appended_to_runner() {
+    let cache = TempDir::new().unwrap();
+    let runner = fake_runner();
+    let backend = cached_backend(cache.path(), &runner, &BinarySigner::from_seed(&[7; 32]));
+    let template = generate_template().await;
+
+    let binary = backend
+        .compile_binary(
+            &template,
+            &TargetSpecification::new(TARGET),
+            &serde_json::Value::Null,
+        )
+        .await
+        .unwrap();
+
+    let parts = split_appended(&binary.binary_data).unwrap();
+    assert_eq!(parts.runner, runner.as_slice());
+    let sections = unpack_archive(parts.archive).unwrap();
+    assert_eq!(sections.report(), template.section_report());
+    assert_eq!(binary.size, binary.binary_data.len() as u64);
+}
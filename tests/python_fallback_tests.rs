@@ -0,0 +1,219 @@
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::modules::python::payload_path;
+use rustle_deploy::modules::PythonModules;
+use rustle_deploy::template::{BinaryTemplateGenerator, TargetInfo, TemplateConfig};
+use rustle_deploy::types::compilation::RunnerProfile;
+use rustle_deploy::types::platform::Platform;
+use std::io::Read;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// An `ansible` package with a module importing module_utils, which import
+/// others
+fn ansible_package() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    let files = [
+        ("__init__.py", ""),
+        ("modules/__init__.py", ""),
+        (
+            "modules/acme_probe.py",
+            "from ansible.module_utils.basic import AnsibleModule\nfrom ansible.module_utils.common.text import (\n    to_text,\n)\n\nAnsibleModule().exit_json(changed=False)\n",
+        ),
+        ("module_utils/__init__.py", ""),
+        (
+            "module_utils/basic.py",
+            "from .six import PY3\nimport json\n\nclass AnsibleModule:\n    pass\n",
+        ),
+        ("module_utils/six.py", "PY3 = True\n"),
+        ("module_utils/common/__init__.py", ""),
+        ("module_utils/common/text.py", "def to_text(x):\n    return x\n"),
+        ("module_utils/unused.py", ""),
+    ];
+    for (path, content) in files {
+        let path = dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+/// The file operations plan, its first task running `module`
+fn plan_running(module: &str) -> RustlePlanOutput {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    let mut plan: RustlePlanOutput =
+        serde_json::from_str(&content).expect("Failed to parse rustle plan");
+    plan.plays[0].batches[0].tasks[0].module = module.to_string();
+    plan
+}
+
+fn linux_target() -> TargetInfo {
+    TargetInfo {
+        target_triple: "x86_64-unknown-linux-gnu".to_string(),
+        platform: Platform::Linux,
+        architecture: "x86_64".to_string(),
+        os_family: "unix".to_string(),
+        libc: Some("glibc".to_string()),
+        features: vec![],
+    }
+}
+
+fn zip_entries(package: &[u8]) -> Vec<String> {
+    let archive = zip::ZipArchive::new(std::io::Cursor::new(package)).unwrap();
+    let mut names: Vec<String> = archive.file_names().map(str::to_string).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_finds_modules_by_their_names() {
+    let ansible = ansible_package();
+    let library = TempDir::new().unwrap();
+    std::fs::write(library.path().join("acme_probe.py"), "print('{}')\n").unwrap();
+    let modules = PythonModules::new().with_ansible_dir(ansible.path());
+
+    let installed = ansible.path().join("modules/acme_probe.py");
+    assert_eq!(modules.find("acme_probe"), Some(installed.clone()));
+    assert_eq!(
+        modules.find("ansible.builtin.acme_probe"),
+        Some(installed.clone())
+    );
+    assert_eq!(modules.find("ansible.legacy.acme_probe"), Some(installed));
+    assert_eq!(modules.find("community.general.acme_probe"), None);
+    assert_eq!(modules.find("../acme_probe"), None);
+    assert_eq!(modules.find("missing"), None);
+
+    // Library directories come first
+    let modules = modules.with_library(library.path());
+    assert_eq!(
+        modules.find("acme_probe"),
+        Some(library.path().join("acme_probe.py"))
+    );
+}
+
+#[test]
+fn test_packages_modules_with_the_module_utils_they_import() {
+    let ansible = ansible_package();
+    let modules = PythonModules::new().with_ansible_dir(ansible.path());
+
+    let package = modules.package("ansible.builtin.acme_probe").unwrap();
+    assert_eq!(
+        zip_entries(&package),
+        [
+            "__main__.py",
+            "ansible/__init__.py",
+            "ansible/module_utils/__init__.py",
+            "ansible/module_utils/basic.py",
+            "ansible/module_utils/common/__init__.py",
+            "ansible/module_utils/common/text.py",
+            "ansible/module_utils/six.py",
+            "ansible/modules/__init__.py",
+            "ansible/modules/acme_probe.py",
+        ]
+    );
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(&package)).unwrap();
+    let mut main = String::new();
+    archive
+        .by_name("__main__.py")
+        .unwrap()
+        .read_to_string(&mut main)
+        .unwrap();
+    assert!(main.contains("\"ansible.modules.acme_probe\""));
+
+    // The same module packages the same
+    assert_eq!(modules.package("acme_probe").unwrap(), package);
+}
+
+#[test]
+fn test_library_modules_importing_module_utils_need_an_installation() {
+    let ansible = ansible_package();
+    let modules = PythonModules::new().with_library(ansible.path().join("modules"));
+
+    let error = modules.package("acme_probe").unwrap_err();
+    assert!(error.to_string().contains("ansible.module_utils"));
+    assert!(modules.package("missing").is_err());
+}
+
+#[tokio::test]
+async fn test_runner_embeds_python_modules_it_is_not_built_with() {
+    let ansible = ansible_package();
+    let modules = PythonModules::new().with_ansible_dir(ansible.path());
+    let plan = plan_running("acme_probe");
+    let generator = BinaryTemplateGenerator::new(TemplateConfig::default())
+        .unwrap()
+        .with_python_modules(modules.clone());
+
+    let fallback = generator.python_fallback_tasks(&plan);
+    assert_eq!(fallback.len(), 1);
+    assert_eq!(
+        fallback[0].task_id,
+        plan.plays[0].batches[0].tasks[0].task_id
+    );
+    assert_eq!(fallback[0].module, "acme_probe");
+
+    let template = generator
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &linux_target())
+        .await
+        .unwrap();
+
+    let payload = payload_path("acme_probe");
+    assert_eq!(
+        template.embedded_data.static_files[&payload],
+        modules.package("acme_probe").unwrap()
+    );
+    let files = &template.source_files;
+    assert!(files.contains_key(Path::new("src/python_fallback.rs")));
+    assert!(!files.contains_key(Path::new("src/modules/acme_probe.rs")));
+    let main_rs = &files[Path::new("src/main.rs")];
+    assert!(main_rs.contains("mod python_fallback;"));
+    assert!(main_rs.contains(&format!(r#"&[("acme_probe", "{payload}"), ];"#)));
+    assert!(main_rs.contains("python_fallback::execute(&task.module, payload, mapped_args)"));
+    assert!(!main_rs.contains("pub mod acme_probe;"));
+}
+
+#[tokio::test]
+async fn test_builtin_modules_do_not_fall_back() {
+    let ansible = ansible_package();
+    std::fs::write(ansible.path().join("modules/debug.py"), "").unwrap();
+    let plan = plan_running("debug");
+    let generator = BinaryTemplateGenerator::new(TemplateConfig::default())
+        .unwrap()
+        .with_python_modules(PythonModules::new().with_ansible_dir(ansible.path()));
+
+    assert!(generator.python_fallback_tasks(&plan).is_empty());
+    let template = generator
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &linux_target())
+        .await
+        .unwrap();
+    assert!(!template
+        .source_files
+        .contains_key(Path::new("src/python_fallback.rs")));
+    assert!(!template.source_files[Path::new("src/main.rs")].contains("python_fallback"));
+}
+
+#[tokio::test]
+async fn test_wasi_runners_cannot_run_python_modules() {
+    let plan = plan_running("acme_probe");
+    let generator = BinaryTemplateGenerator::new(TemplateConfig {
+        runner_profile: RunnerProfile::Wasi,
+        ..Default::default()
+    })
+    .unwrap()
+    .with_python_modules(PythonModules::new());
+    let target_info = TargetInfo {
+        target_triple: "wasm32-wasi".to_string(),
+        platform: Platform::Unknown("wasi".to_string()),
+        architecture: "wasm32".to_string(),
+        os_family: "wasm".to_string(),
+        libc: None,
+        features: vec![],
+    };
+
+    let error = generator
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &target_info)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("cannot run Python modules"));
+}
@@ -0,0 +1,173 @@
+use chrono::{TimeZone, Utc};
+use rustle_deploy::deploy::{CollectedResults, ReportFormat, ReportTarget, RunReport};
+use rustle_deploy::runtime::{ExecutionResult, ExecutionSummary, TaskResult, TaskStatus};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+fn task(id: &str, second: u32, status: TaskStatus) -> TaskResult {
+    let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, second).unwrap();
+    TaskResult {
+        task_id: id.to_string(),
+        name: format!("Check {id}"),
+        changed: status == TaskStatus::Success && id.ends_with("drift"),
+        failed: status == TaskStatus::Failed,
+        skipped: status == TaskStatus::Skipped,
+        output: serde_json::Value::Null,
+        stdout: None,
+        stderr: None,
+        start_time: time,
+        end_time: time,
+        duration: Duration::from_millis(500),
+        error: (status == TaskStatus::Failed).then(|| "sshd <permits> root".to_string()),
+        status,
+    }
+}
+
+fn execution(tasks: Vec<TaskResult>) -> ExecutionResult {
+    let failed = tasks.iter().any(|task| task.failed);
+    let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    ExecutionResult {
+        execution_id: "scan".to_string(),
+        success: !failed,
+        failed,
+        summary: ExecutionSummary {
+            total_tasks: tasks.len(),
+            completed_tasks: tasks.len(),
+            failed_tasks: tasks.iter().filter(|task| task.failed).count(),
+            skipped_tasks: tasks.iter().filter(|task| task.skipped).count(),
+            changed_tasks: tasks.iter().filter(|task| task.changed).count(),
+            ignored_tasks: 0,
+            rescued_tasks: 0,
+        },
+        task_results: tasks
+            .into_iter()
+            .map(|task| (task.task_id.clone(), task))
+            .collect(),
+        start_time: time,
+        end_time: time,
+        duration: Duration::from_secs(2),
+        errors: Vec::new(),
+        module_metrics: Default::default(),
+        handler_results: Vec::new(),
+        spans: Vec::new(),
+        audit: Vec::new(),
+    }
+}
+
+fn collected() -> CollectedResults {
+    CollectedResults {
+        deployment_id: "compliance-42".to_string(),
+        results: BTreeMap::from([
+            (
+                "web1".to_string(),
+                execution(vec![
+                    task("ntp", 2, TaskStatus::Success),
+                    task("ssh", 1, TaskStatus::Failed),
+                    task("audit", 3, TaskStatus::Skipped),
+                ]),
+            ),
+            (
+                "web2".to_string(),
+                execution(vec![task("sysctl_drift", 1, TaskStatus::Success)]),
+            ),
+        ]),
+        missing_hosts: vec!["db1".to_string()],
+    }
+}
+
+#[test]
+fn test_report_target_parses_format_and_path() {
+    let target: ReportTarget = "junit=out/results.xml".parse().unwrap();
+    assert_eq!(target.format, ReportFormat::JUnit);
+    assert_eq!(target.path, std::path::Path::new("out/results.xml"));
+    assert_eq!(
+        "SARIF=scan.sarif".parse::<ReportTarget>().unwrap().format,
+        ReportFormat::Sarif
+    );
+    assert!("junit".parse::<ReportTarget>().is_err());
+    assert!("junit=".parse::<ReportTarget>().is_err());
+    assert!("html=report.html".parse::<ReportTarget>().is_err());
+}
+
+#[test]
+fn test_junit_report_has_a_testcase_per_task_and_host() {
+    let xml = RunReport::from(&collected()).junit();
+
+    assert!(xml.contains(
+        "<testsuites name=\"compliance-42\" tests=\"5\" failures=\"2\" skipped=\"1\" time=\"4.000\">"
+    ));
+    assert!(xml.contains(
+        "<testsuite name=\"web1\" hostname=\"web1\" tests=\"3\" failures=\"1\" skipped=\"1\" time=\"2.000\">"
+    ));
+    // Tasks are reported in the order they ran
+    let ssh = xml.find("name=\"Check ssh\"").unwrap();
+    let ntp = xml.find("name=\"Check ntp\"").unwrap();
+    assert!(ssh < ntp);
+    assert!(xml.contains("<failure message=\"sshd &lt;permits&gt; root\"/>"));
+    assert!(xml.contains(
+        "<testcase classname=\"web1\" name=\"Check audit\" time=\"0.500\">\n      <skipped/>"
+    ));
+    // A host that never reported fails as a whole
+    assert!(xml.contains(
+        "<testcase classname=\"db1\" name=\"deployment\">\n      <failure message=\"no result was uploaded\"/>"
+    ));
+}
+
+#[test]
+fn test_sarif_report_lists_failed_and_changed_tasks() {
+    let sarif = RunReport::from(&collected()).sarif();
+    let run = &sarif["runs"][0];
+
+    assert_eq!(sarif["version"], "2.1.0");
+    assert_eq!(run["tool"]["driver"]["name"], "rustle-deploy");
+    assert_eq!(run["automationDetails"]["id"], "compliance-42");
+    assert_eq!(run["invocations"][0]["executionSuccessful"], false);
+
+    let results: Vec<_> = run["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| {
+            (
+                result["ruleId"].as_str().unwrap(),
+                result["level"].as_str().unwrap(),
+                result["locations"][0]["logicalLocations"][0]["fullyQualifiedName"]
+                    .as_str()
+                    .unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        results,
+        [
+            ("ssh", "error", "web1/ssh"),
+            ("sysctl_drift", "warning", "web2/sysctl_drift"),
+            ("deployment", "error", "db1/deployment"),
+        ]
+    );
+    let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+    for result in run["results"].as_array().unwrap() {
+        let index = result["ruleIndex"].as_u64().unwrap() as usize;
+        assert_eq!(rules[index]["id"], result["ruleId"]);
+    }
+}
+
+#[test]
+fn test_reports_are_written_to_their_paths() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let report = RunReport::from(&collected());
+    for (format, name) in [
+        (ReportFormat::JUnit, "junit.xml"),
+        (ReportFormat::Sarif, "scan.sarif"),
+    ] {
+        let path = dir.path().join("ci").join(name);
+        report.write(&ReportTarget { format, path }).unwrap();
+    }
+
+    let xml = std::fs::read_to_string(dir.path().join("ci/junit.xml")).unwrap();
+    assert!(xml.starts_with("<?xml"));
+    let sarif: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.path().join("ci/scan.sarif")).unwrap())
+            .unwrap();
+    assert_eq!(sarif["runs"][0]["results"].as_array().unwrap().len(), 3);
+}
@@ -0,0 +1,101 @@
+use rustle_deploy::compilation::{
+    canonical_value, rustc_release, ReproducibleBuild, REMAPPED_PROJECT_DIR,
+};
+use rustle_deploy::deploy::{CompilerVersions, DeploymentManifest};
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::template::{BinaryTemplateGenerator, TargetInfo, TemplateConfig};
+use rustle_deploy::types::platform::Platform;
+use std::path::Path;
+
+fn load_plan() -> RustlePlanOutput {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    serde_json::from_str(&content).expect("Failed to parse rustle plan")
+}
+
+#[test]
+fn test_canonical_value_sorts_nested_keys() {
+    let mut inner = serde_json::Map::new();
+    inner.insert("zeta".to_string(), serde_json::json!(1));
+    inner.insert("alpha".to_string(), serde_json::json!(2));
+    let mut outer = serde_json::Map::new();
+    outer.insert(
+        "list".to_string(),
+        serde_json::json!([serde_json::Value::Object(inner.clone())]),
+    );
+    outer.insert("b".to_string(), serde_json::Value::Object(inner));
+    outer.insert("a".to_string(), serde_json::json!(null));
+
+    let canonical = serde_json::to_string(&canonical_value(&outer).unwrap()).unwrap();
+    assert_eq!(
+        canonical,
+        r#"{"a":null,"b":{"alpha":2,"zeta":1},"list":[{"alpha":2,"zeta":1}]}"#
+    );
+}
+
+#[test]
+fn test_reproducible_build_settings() {
+    let build = ReproducibleBuild::new("1.79.0", 1_700_000_000);
+    assert!(build.verify);
+    assert!(!build.clone().with_verify(false).verify);
+
+    let env = build.cargo_env();
+    assert!(env.contains(&("SOURCE_DATE_EPOCH".to_string(), "1700000000".to_string())));
+    assert!(env.contains(&("CARGO_INCREMENTAL".to_string(), "0".to_string())));
+
+    let flags = build.remap_flags(Path::new("/tmp/rustle-compilation/rustle-1234"));
+    assert!(flags.starts_with(&format!(
+        "--remap-path-prefix=/tmp/rustle-compilation/rustle-1234={REMAPPED_PROJECT_DIR}"
+    )));
+
+    assert_eq!(
+        rustc_release("rustc 1.79.0 (129f3b996 2024-06-10)").as_deref(),
+        Some("1.79.0")
+    );
+    assert_eq!(rustc_release("rustc"), None);
+}
+
+#[tokio::test]
+async fn test_generated_runner_is_deterministic() {
+    let plan = load_plan();
+    let target_info = TargetInfo {
+        target_triple: "x86_64-unknown-linux-gnu".to_string(),
+        platform: Platform::Linux,
+        architecture: "x86_64".to_string(),
+        os_family: "unix".to_string(),
+        libc: Some("gnu".to_string()),
+        features: vec![],
+    };
+
+    let mut main_rs = Vec::new();
+    for _ in 0..2 {
+        let generator = BinaryTemplateGenerator::new(TemplateConfig::default()).unwrap();
+        let template = generator
+            .generate_binary_template(&plan, &plan.binary_deployments[0], &target_info)
+            .await
+            .expect("Failed to generate template");
+        main_rs.push(template.source_files[Path::new("src/main.rs")].clone());
+    }
+    assert_eq!(main_rs[0], main_rs[1]);
+}
+
+#[test]
+fn test_manifest_records_reproducible_build() {
+    let mut manifest = DeploymentManifest::new("deploy-1", CompilerVersions::detect().clone());
+    let provenance = manifest.provenance("https://example.com/builder");
+    assert!(
+        provenance["predicate"]["buildDefinition"]["internalParameters"]["reproducible"].is_null()
+    );
+
+    manifest.reproducible = Some(ReproducibleBuild::new("1.79.0", 1_700_000_000));
+    let provenance = manifest.provenance("https://example.com/builder");
+    let reproducible =
+        &provenance["predicate"]["buildDefinition"]["internalParameters"]["reproducible"];
+    assert_eq!(reproducible["toolchain"], "1.79.0");
+    assert_eq!(reproducible["source_date_epoch"], 1_700_000_000);
+
+    let json = serde_json::to_string(&manifest).unwrap();
+    let loaded: DeploymentManifest = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.reproducible, manifest.reproducible);
+}
@@ -0,0 +1,112 @@
+use chrono::Utc;
+use rustle_deploy::deploy::ResultCollector;
+use rustle_deploy::runtime::{
+    generate_result_keypair, ObjectStoreConfig, ResultUploadConfig, ResultUploader, StateManager,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+type Objects = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+/// Serve PUT/GET of objects from memory, like a bare-bones object store
+async fn start_object_store() -> (String, Objects) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let objects: Objects = Arc::default();
+
+    let store = objects.clone();
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                return;
+            };
+            let store = store.clone();
+            tokio::spawn(async move {
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).await.unwrap();
+                let mut parts = request_line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_string();
+                let path = parts.next().unwrap_or_default().to_string();
+
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).await.unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).await.unwrap();
+
+                let (status, response_body) = match method.as_str() {
+                    "PUT" => {
+                        store.lock().unwrap().insert(path, body);
+                        ("200 OK", Vec::new())
+                    }
+                    _ => match store.lock().unwrap().get(&path) {
+                        Some(object) => ("200 OK", object.clone()),
+                        None => ("404 Not Found", Vec::new()),
+                    },
+                };
+
+                let mut stream = reader.into_inner();
+                let head = format!(
+                    "HTTP/1.1 {status}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    response_body.len()
+                );
+                stream.write_all(head.as_bytes()).await.unwrap();
+                stream.write_all(&response_body).await.unwrap();
+            });
+        }
+    });
+
+    (base_url, objects)
+}
+
+#[tokio::test]
+async fn test_uploaded_results_are_collected_and_decrypted() {
+    let (base_url, objects) = start_object_store().await;
+    let store = ObjectStoreConfig::Http {
+        base_url,
+        headers: HashMap::new(),
+    };
+    let (secret_key, public_key) = generate_result_keypair();
+
+    let uploader = ResultUploader::new(ResultUploadConfig {
+        store: store.clone(),
+        recipient_public_key: public_key,
+        deployment_id: "deploy-1".to_string(),
+        host_id: Some("web01".to_string()),
+    });
+    let result = StateManager::new("exec-web01".to_string(), 0).build_execution_result(Utc::now());
+    let key = uploader.upload(&result).await.unwrap();
+    assert_eq!(key, "deploy-1/web01.bundle.json");
+
+    let stored = objects.lock().unwrap()["/deploy-1/web01.bundle.json"].clone();
+    assert!(!String::from_utf8_lossy(&stored).contains("exec-web01"));
+
+    let collector =
+        ResultCollector::new(store, secret_key).with_poll_interval(Duration::from_millis(50));
+    let collected = collector
+        .collect(
+            "deploy-1",
+            &["web01".to_string(), "web02".to_string()],
+            Duration::from_millis(200),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(collected.results["web01"].execution_id, "exec-web01");
+    assert_eq!(collected.missing_hosts, ["web02"]);
+    assert!(!collected.is_complete());
+}
@@ -0,0 +1,83 @@
+mod helpers;
+
+use helpers::TaskBuilder;
+use rustle_deploy::execution::ExecutionPlan;
+use rustle_deploy::runtime::{ExecutionResult, LocalExecutor, PersistedState, RuntimeConfig};
+use std::path::Path;
+
+fn task(id: &str, after: Option<&str>, script: &str) -> serde_json::Value {
+    TaskBuilder::script(id, script)
+        .after(after.as_slice())
+        .play("site")
+        .build()
+}
+
+fn plan(dir: &Path) -> ExecutionPlan {
+    let dir = dir.display();
+    let mut migrate = task("migrate", None, &format!("echo ran >> {dir}/migrate.log"));
+    migrate["register"] = "migrate_out".into();
+    migrate["notify"] = serde_json::json!(["restart app"]);
+    let gate = task("gate", Some("migrate"), &format!("test -f {dir}/ready"));
+    let mut finish = task(
+        "finish",
+        Some("gate"),
+        &format!("echo ran >> {dir}/finish.log"),
+    );
+    finish["when"] = serde_json::json!(["migrate_out is changed"]);
+
+    helpers::plan(
+        "resume",
+        serde_json::json!({
+            "tasks": [migrate, gate, finish],
+            "handlers": [{
+                "id": "restart", "name": "restart app", "module": "command",
+                "args": { "cmd": format!("sh -c 'echo ran >> {dir}/restart.log'") },
+            }],
+        }),
+    )
+}
+
+async fn execute(dir: &Path, resume: bool) -> ExecutionResult {
+    let config = RuntimeConfig {
+        state_file: Some(dir.join("runner.state")),
+        ..RuntimeConfig::default()
+    };
+    let mut executor = LocalExecutor::new(config).with_resume(resume);
+    executor.execute_plan(plan(dir)).await.unwrap()
+}
+
+fn runs(dir: &Path, log: &str) -> usize {
+    std::fs::read_to_string(dir.join(log))
+        .map(|log| log.lines().count())
+        .unwrap_or(0)
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_resume_continues_from_the_first_incomplete_task() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let state_file = dir.path().join("runner.state");
+
+    let result = execute(dir.path(), false).await;
+    assert!(result.failed);
+    assert_eq!(runs(dir.path(), "migrate.log"), 1);
+    assert_eq!(runs(dir.path(), "restart.log"), 0);
+    let state = PersistedState::load(&state_file).unwrap().unwrap();
+    assert!(state.completed_tasks.contains_key("migrate"));
+    assert!(!state.completed_tasks.contains_key("gate"));
+    assert_eq!(state.notified_handlers, ["restart"]);
+
+    std::fs::write(dir.path().join("ready"), "").unwrap();
+    let result = execute(dir.path(), true).await;
+    assert!(result.success, "{:?}", result.errors);
+    assert_eq!(runs(dir.path(), "migrate.log"), 1);
+    assert_eq!(runs(dir.path(), "finish.log"), 1);
+    assert_eq!(runs(dir.path(), "restart.log"), 1);
+    assert!(result.task_results["migrate"].changed);
+    assert!(!state_file.exists());
+
+    // Without --resume, the whole plan runs again
+    let result = execute(dir.path(), false).await;
+    assert!(result.success);
+    assert_eq!(runs(dir.path(), "migrate.log"), 2);
+}
@@ -0,0 +1,126 @@
+use rustle_deploy::compilation::{check_profile_compatibility, CompilerConfig};
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::template::{
+    BinaryTemplateGenerator, GeneratedTemplate, TargetInfo, TemplateConfig,
+};
+use rustle_deploy::types::compilation::RunnerProfile;
+use rustle_deploy::types::platform::Platform;
+use std::path::Path;
+
+fn load_plan() -> RustlePlanOutput {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    serde_json::from_str(&content).expect("Failed to parse rustle plan")
+}
+
+async fn generate(plan: &RustlePlanOutput, runner_profile: RunnerProfile) -> GeneratedTemplate {
+    let generator = BinaryTemplateGenerator::new(TemplateConfig {
+        runner_profile,
+        ..Default::default()
+    })
+    .unwrap();
+
+    let target_info = TargetInfo {
+        target_triple: "aarch64-unknown-linux-musl".to_string(),
+        platform: Platform::Linux,
+        architecture: "aarch64".to_string(),
+        os_family: "unix".to_string(),
+        libc: Some("musl".to_string()),
+        features: vec![],
+    };
+
+    generator
+        .generate_binary_template(plan, &plan.binary_deployments[0], &target_info)
+        .await
+        .expect("Failed to generate template")
+}
+
+#[tokio::test]
+async fn test_minimal_profile_trims_runner() {
+    let plan = load_plan();
+    let template = generate(&plan, RunnerProfile::Minimal).await;
+
+    assert!(!template.cargo_toml.contains("reqwest"));
+    assert!(!template.cargo_toml.contains("\"full\""));
+    assert!(template.cargo_toml.contains("\"rt\""));
+    assert!(template.cargo_toml.contains("opt-level = \"z\""));
+    assert!(template.cargo_toml.contains("panic = \"abort\""));
+
+    let main_rs = &template.source_files[Path::new("src/main.rs")];
+    assert!(main_rs.contains("flavor = \"current_thread\""));
+    assert!(!main_rs.contains("reqwest::Client"));
+
+    let cargo_config = &template.source_files[Path::new(".cargo/config.toml")];
+    assert!(cargo_config.contains("target-feature=+crt-static"));
+}
+
+#[tokio::test]
+async fn test_standard_profile_is_unchanged() {
+    let plan = load_plan();
+    let template = generate(&plan, RunnerProfile::Standard).await;
+
+    assert!(template.cargo_toml.contains("reqwest"));
+    assert!(template.cargo_toml.contains("\"full\""));
+    assert!(!template
+        .source_files
+        .contains_key(Path::new(".cargo/config.toml")));
+
+    let main_rs = &template.source_files[Path::new("src/main.rs")];
+    assert!(!main_rs.contains("current_thread"));
+    assert!(main_rs.contains("reqwest::Client"));
+}
+
+#[test]
+fn test_runner_profile_selected_per_target() {
+    let mut config = CompilerConfig::default();
+    config.runner_profiles.insert(
+        "armv7-unknown-linux-musleabihf".to_string(),
+        RunnerProfile::Minimal,
+    );
+
+    assert_eq!(
+        config.runner_profile_for("armv7-unknown-linux-musleabihf"),
+        RunnerProfile::Minimal
+    );
+    assert_eq!(
+        config.runner_profile_for("x86_64-unknown-linux-gnu"),
+        RunnerProfile::Standard
+    );
+
+    let report = check_profile_compatibility(
+        &load_plan(),
+        "armv7-unknown-linux-musleabihf",
+        RunnerProfile::Minimal,
+    );
+    assert!(report.is_compatible());
+    assert!(report.supported_modules.contains(&"file".to_string()));
+}
+
+#[tokio::test]
+async fn test_runner_only_enables_used_modules() {
+    let plan = load_plan();
+    let template = generate(&plan, RunnerProfile::Standard).await;
+
+    // The plan copies and manages files, nothing else
+    assert!(template
+        .cargo_toml
+        .contains(r#"default = ["module-copy", "module-file"]"#));
+    assert!(template
+        .cargo_toml
+        .contains(r#"module-package = ["dep:regex"]"#));
+    assert!(template
+        .cargo_toml
+        .contains(r#"regex = { version = "1.10", optional = true }"#));
+
+    let mapper = &template.source_files[Path::new("src/modules/parameter_mapping/mapper.rs")];
+    assert!(mapper.contains(
+        "#[cfg(feature = \"module-package\")]\n        handlers.insert(\"apt\".to_string()"
+    ));
+    let handlers =
+        &template.source_files[Path::new("src/modules/parameter_mapping/handlers/mod.rs")];
+    assert!(handlers.contains("#[cfg(feature = \"module-file\")]\npub mod file;"));
+    assert!(!template
+        .source_files
+        .contains_key(Path::new("src/modules/package.rs")));
+}
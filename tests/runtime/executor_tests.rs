@@ -78,6 +78,23 @@ async fn test_multiple_tasks_execution() {
         timeout: None,
         retry_policy: None,
         failure_policy: FailurePolicy::Abort,
+        run_once: false,
+        delegate_to: None,
+        notify: vec![],
+        play_id: None,
+        task_loop: None,
+        until: None,
+        async_job: None,
+        failed_when: None,
+        changed_when: None,
+        ignore_errors: false,
+        register: None,
+        when: Vec::new(),
+        no_log: false,
+        r#become: None,
+        environment: Default::default(),
+        parallel: false,
+        throttle: None,
     });
     
     let config = RuntimeConfig::default();
@@ -195,6 +212,23 @@ fn create_test_execution_plan() -> ExecutionPlan {
                 timeout: None,
                 retry_policy: None,
                 failure_policy: FailurePolicy::Abort,
+                run_once: false,
+                delegate_to: None,
+                notify: vec![],
+                play_id: None,
+                task_loop: None,
+                until: None,
+                async_job: None,
+                failed_when: None,
+                changed_when: None,
+                ignore_errors: false,
+                register: None,
+                when: Vec::new(),
+                no_log: false,
+                r#become: None,
+                environment: Default::default(),
+                parallel: false,
+                throttle: None,
             }
         ],
         inventory: InventorySpec {
@@ -216,7 +250,15 @@ fn create_test_execution_plan() -> ExecutionPlan {
             verify_deployment: false,
             cleanup_on_success: true,
             deployment_timeout: Some(Duration::from_secs(300)),
+            maintenance_windows: vec![],
+            wait_for_window: false,
+            hooks: Default::default(),
+            serial: None,
+            max_fail_percentage: None,
         },
         modules: vec![],
+        handlers: vec![],
+        blocks: vec![],
+        plays: vec![],
     }
 }
\ No newline at end of file
@@ -104,6 +104,23 @@ async fn test_facts_integration() {
         timeout: None,
         retry_policy: None,
         failure_policy: FailurePolicy::Abort,
+        run_once: false,
+        delegate_to: None,
+        notify: vec![],
+        play_id: None,
+        task_loop: None,
+        until: None,
+        async_job: None,
+        failed_when: None,
+        changed_when: None,
+        ignore_errors: false,
+        register: None,
+        when: Vec::new(),
+        no_log: false,
+        r#become: None,
+        environment: Default::default(),
+        parallel: false,
+        throttle: None,
     });
     
     let config = RuntimeConfig::default();
@@ -167,6 +184,23 @@ async fn test_error_handling() {
         timeout: None,
         retry_policy: None,
         failure_policy: FailurePolicy::Abort,
+        run_once: false,
+        delegate_to: None,
+        notify: vec![],
+        play_id: None,
+        task_loop: None,
+        until: None,
+        async_job: None,
+        failed_when: None,
+        changed_when: None,
+        ignore_errors: false,
+        register: None,
+        when: Vec::new(),
+        no_log: false,
+        r#become: None,
+        environment: Default::default(),
+        parallel: false,
+        throttle: None,
     });
     
     let config = RuntimeConfig::default();
@@ -205,6 +239,23 @@ fn create_test_execution_plan() -> ExecutionPlan {
                 timeout: None,
                 retry_policy: None,
                 failure_policy: FailurePolicy::Abort,
+                run_once: false,
+                delegate_to: None,
+                notify: vec![],
+                play_id: None,
+                task_loop: None,
+                until: None,
+                async_job: None,
+                failed_when: None,
+                changed_when: None,
+                ignore_errors: false,
+                register: None,
+                when: Vec::new(),
+                no_log: false,
+                r#become: None,
+                environment: Default::default(),
+                parallel: false,
+                throttle: None,
             }
         ],
         inventory: InventorySpec {
@@ -226,8 +277,16 @@ fn create_test_execution_plan() -> ExecutionPlan {
             verify_deployment: false,
             cleanup_on_success: true,
             deployment_timeout: Some(Duration::from_secs(300)),
+            maintenance_windows: vec![],
+            wait_for_window: false,
+            hooks: Default::default(),
+            serial: None,
+            max_fail_percentage: None,
         },
         modules: vec![],
+        handlers: vec![],
+        blocks: vec![],
+        plays: vec![],
     }
 }
 
@@ -247,6 +306,23 @@ fn create_complex_execution_plan() -> ExecutionPlan {
             timeout: None,
             retry_policy: None,
             failure_policy: FailurePolicy::Abort,
+            run_once: false,
+            delegate_to: None,
+            notify: vec![],
+            play_id: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            when: Vec::new(),
+            no_log: false,
+            r#become: None,
+            environment: Default::default(),
+            parallel: false,
+            throttle: None,
         },
         Task {
             id: "main-task".to_string(),
@@ -260,6 +336,23 @@ fn create_complex_execution_plan() -> ExecutionPlan {
             timeout: None,
             retry_policy: None,
             failure_policy: FailurePolicy::Abort,
+            run_once: false,
+            delegate_to: None,
+            notify: vec![],
+            play_id: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            when: Vec::new(),
+            no_log: false,
+            r#become: None,
+            environment: Default::default(),
+            parallel: false,
+            throttle: None,
         },
         Task {
             id: "conditional-task".to_string(),
@@ -279,6 +372,23 @@ fn create_complex_execution_plan() -> ExecutionPlan {
             timeout: None,
             retry_policy: None,
             failure_policy: FailurePolicy::Abort,
+            run_once: false,
+            delegate_to: None,
+            notify: vec![],
+            play_id: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            when: Vec::new(),
+            no_log: false,
+            r#become: None,
+            environment: Default::default(),
+            parallel: false,
+            throttle: None,
         },
         Task {
             id: "cleanup-task".to_string(),
@@ -292,6 +402,23 @@ fn create_complex_execution_plan() -> ExecutionPlan {
             timeout: None,
             retry_policy: None,
             failure_policy: FailurePolicy::Abort,
+            run_once: false,
+            delegate_to: None,
+            notify: vec![],
+            play_id: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            when: Vec::new(),
+            no_log: false,
+            r#become: None,
+            environment: Default::default(),
+            parallel: false,
+            throttle: None,
         },
     ];
     
@@ -314,6 +441,23 @@ fn create_dependency_execution_plan() -> ExecutionPlan {
             timeout: None,
             retry_policy: None,
             failure_policy: FailurePolicy::Abort,
+            run_once: false,
+            delegate_to: None,
+            notify: vec![],
+            play_id: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            when: Vec::new(),
+            no_log: false,
+            r#become: None,
+            environment: Default::default(),
+            parallel: false,
+            throttle: None,
         },
         Task {
             id: "task-2".to_string(),
@@ -327,6 +471,23 @@ fn create_dependency_execution_plan() -> ExecutionPlan {
             timeout: None,
             retry_policy: None,
             failure_policy: FailurePolicy::Abort,
+            run_once: false,
+            delegate_to: None,
+            notify: vec![],
+            play_id: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            when: Vec::new(),
+            no_log: false,
+            r#become: None,
+            environment: Default::default(),
+            parallel: false,
+            throttle: None,
         },
         Task {
             id: "task-3".to_string(),
@@ -340,6 +501,23 @@ fn create_dependency_execution_plan() -> ExecutionPlan {
             timeout: None,
             retry_policy: None,
             failure_policy: FailurePolicy::Abort,
+            run_once: false,
+            delegate_to: None,
+            notify: vec![],
+            play_id: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            when: Vec::new(),
+            no_log: false,
+            r#become: None,
+            environment: Default::default(),
+            parallel: false,
+            throttle: None,
         },
     ];
     
@@ -362,6 +540,23 @@ fn create_parallel_execution_plan() -> ExecutionPlan {
             timeout: None,
             retry_policy: None,
             failure_policy: FailurePolicy::Abort,
+            run_once: false,
+            delegate_to: None,
+            notify: vec![],
+            play_id: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            when: Vec::new(),
+            no_log: false,
+            r#become: None,
+            environment: Default::default(),
+            parallel: false,
+            throttle: None,
         },
         Task {
             id: "parallel-task-2".to_string(),
@@ -375,6 +570,23 @@ fn create_parallel_execution_plan() -> ExecutionPlan {
             timeout: None,
             retry_policy: None,
             failure_policy: FailurePolicy::Abort,
+            run_once: false,
+            delegate_to: None,
+            notify: vec![],
+            play_id: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            when: Vec::new(),
+            no_log: false,
+            r#become: None,
+            environment: Default::default(),
+            parallel: false,
+            throttle: None,
         },
     ];
     
@@ -401,6 +613,23 @@ fn create_retry_execution_plan() -> ExecutionPlan {
                 backoff: BackoffStrategy::Fixed,
             }),
             failure_policy: FailurePolicy::Abort,
+            run_once: false,
+            delegate_to: None,
+            notify: vec![],
+            play_id: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            when: Vec::new(),
+            no_log: false,
+            r#become: None,
+            environment: Default::default(),
+            parallel: false,
+            throttle: None,
         },
     ];
     
@@ -0,0 +1,64 @@
+mod helpers;
+
+use helpers::TaskBuilder;
+use rustle_deploy::execution::ExecutionPlan;
+use rustle_deploy::runtime::{LocalExecutor, RuntimeConfig, SealedSecretError, SensitiveValues};
+use serde_json::json;
+
+fn plan(tasks: Vec<serde_json::Value>) -> ExecutionPlan {
+    helpers::plan("sealed", serde_json::json!({ "tasks": tasks }))
+}
+
+fn command(id: &str, cmd: &str) -> serde_json::Value {
+    TaskBuilder::command(id, cmd).continue_on_failure().build()
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_runner_substitutes_sealed_secrets_before_running_tasks() {
+    let mut sensitive = SensitiveValues::new();
+    sensitive.insert("hunter2");
+    let cmd = sensitive.replace(&json!("echo pass=hunter2"));
+    let cmd = cmd.as_str().unwrap();
+    assert_eq!(cmd, "echo pass={{ sealed_secret('0') }}");
+
+    let (sealed, key) = sensitive.seal().unwrap();
+    let mut executor = LocalExecutor::new(RuntimeConfig::default())
+        .with_sealed_secrets(sealed.open(&key).unwrap());
+    let result = executor
+        .execute_plan(plan(vec![command("connect", cmd)]))
+        .await
+        .unwrap();
+
+    let connect = &result.task_results["connect"];
+    assert!(!connect.failed, "{:?}", connect.error);
+    assert_eq!(
+        connect.stdout.as_deref().map(str::trim),
+        Some("pass=hunter2")
+    );
+    // The audit log keeps the reference, not the secret
+    assert_eq!(result.audit[0].params["cmd"], cmd);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_references_without_their_secrets_fail_the_task() {
+    let mut executor = LocalExecutor::new(RuntimeConfig::default());
+    let result = executor
+        .execute_plan(plan(vec![command(
+            "connect",
+            "echo {{ sealed_secret('0') }}",
+        )]))
+        .await
+        .unwrap();
+    assert!(result.failed);
+    assert_eq!(result.errors, ["Task execution failed: connect"]);
+
+    let (sealed, key) = SensitiveValues::new().seal().unwrap();
+    let error = sealed
+        .open(&key)
+        .unwrap()
+        .resolve(&json!("{{ sealed_secret('4') }}"))
+        .unwrap_err();
+    assert!(matches!(error, SealedSecretError::UnknownReference { id } if id == "4"));
+}
@@ -0,0 +1,328 @@
+#![cfg(feature = "server")]
+
+use chrono::{Duration as ChronoDuration, Utc};
+use rustle_deploy::api::ControlServer;
+use rustle_deploy::compilation::compiler::CompilerConfig;
+use rustle_deploy::deploy::history::RunRecord;
+use rustle_deploy::deploy::ExecutionHistory;
+use rustle_deploy::execution::{VaultSecret, VaultSecrets};
+use rustle_deploy::runtime::ModuleMetrics;
+use serde_json::Value;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+
+fn plan_json() -> String {
+    std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+        .expect("Failed to read test fixture")
+}
+
+/// Serve `server` on a free port, answering its base URL
+async fn serve(server: ControlServer) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(server.serve(listener));
+    format!("http://{address}")
+}
+
+fn server(temp_dir: &TempDir) -> ControlServer {
+    ControlServer::new()
+        .with_output_dir(temp_dir.path().join("runs"))
+        .with_compiler_config(CompilerConfig {
+            cache_dir: temp_dir.path().join("cache"),
+            ..Default::default()
+        })
+        .with_history(ExecutionHistory::new(temp_dir.path().join("history")))
+}
+
+#[tokio::test]
+async fn test_submitted_plans_are_listed_and_served() {
+    let temp_dir = TempDir::new().unwrap();
+    let base = serve(server(&temp_dir)).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{base}/plans"))
+        .body(plan_json())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    let submitted: Value = response.json().await.unwrap();
+    let plan_id = submitted["plan_id"].as_str().unwrap();
+    assert!(!submitted["hosts"].as_array().unwrap().is_empty());
+
+    let plans: Value = client
+        .get(format!("{base}/plans"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(plans[0]["plan_id"], plan_id);
+
+    let plan: Value = client
+        .get(format!("{base}/plans/{plan_id}"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(plan["hosts"], submitted["hosts"]);
+
+    let response = client
+        .post(format!("{base}/plans"))
+        .body("{ not a plan")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let response = client
+        .post(format!("{base}/plans/unknown/compile"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_cancelled_runs_finish_their_event_streams() {
+    let temp_dir = TempDir::new().unwrap();
+    let base = serve(server(&temp_dir)).await;
+    let client = reqwest::Client::new();
+    let submitted: Value = client
+        .post(format!("{base}/plans"))
+        .body(plan_json())
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let plan_id = submitted["plan_id"].as_str().unwrap();
+
+    let response = client
+        .post(format!("{base}/plans/{plan_id}/compile"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::ACCEPTED);
+    let run: Value = response.json().await.unwrap();
+    let run_id = run["run_id"].as_str().unwrap();
+    assert_eq!(run["kind"], "compile");
+    client
+        .post(format!("{base}/runs/{run_id}/cancel"))
+        .send()
+        .await
+        .unwrap();
+
+    // The stream ends once the run has finished, with the run
+    let events = tokio::time::timeout(Duration::from_secs(60), async {
+        client
+            .get(format!("{base}/runs/{run_id}/events"))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+    })
+    .await
+    .expect("The run finishes once cancelled");
+    assert!(events.contains("event: finished"));
+
+    let run: Value = client
+        .get(format!("{base}/runs/{run_id}"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_ne!(run["state"], "running");
+    assert!(run["finished_at"].is_string());
+
+    // Runners only come from compile runs that succeeded
+    let response = client
+        .post(format!("{base}/plans/{plan_id}/deploy?runners={run_id}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_history_lists_recorded_runs_newest_first() {
+    let temp_dir = TempDir::new().unwrap();
+    let history = ExecutionHistory::new(temp_dir.path().join("history"));
+    for (index, host) in ["web1", "db1", "web1"].iter().enumerate() {
+        history
+            .append(&RunRecord {
+                execution_id: format!("run-{index}"),
+                host: Some(host.to_string()),
+                recorded_at: Utc::now() + ChronoDuration::seconds(index as i64),
+                success: true,
+                duration: Duration::from_secs(1),
+                module_metrics: ModuleMetrics::new(),
+            })
+            .unwrap();
+    }
+    let base = serve(server(&temp_dir)).await;
+    let client = reqwest::Client::new();
+
+    let runs: Value = client
+        .get(format!("{base}/history?host=web1"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let ids: Vec<&str> = runs
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|run| run["execution_id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, ["run-2", "run-0"]);
+
+    let runs: Value = client
+        .get(format!("{base}/history?limit=1"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(runs.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_requests_must_carry_the_token() {
+    let temp_dir = TempDir::new().unwrap();
+    let base = serve(server(&temp_dir).with_token("s3cret")).await;
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{base}/runs")).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let response = client
+        .get(format!("{base}/runs"))
+        .bearer_auth("s3cret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    let response = client
+        .get(format!("{base}/runs"))
+        .bearer_auth("s3cre")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    // Only event streams, which browsers open without headers, take it in
+    // the query, where it would end up in access logs
+    let response = client
+        .get(format!("{base}/runs?token=s3cret"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let response = client
+        .get(format!("{base}/runs/unknown/events"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let response = client
+        .get(format!("{base}/runs/unknown/events?token=s3cret"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_plans_are_served_with_their_vaulted_values_encrypted() {
+    let temp_dir = TempDir::new().unwrap();
+    let secret = VaultSecret::new("default", "correct horse");
+    let vaulted = secret.encrypt(b"hunter2");
+    let base = serve(server(&temp_dir).with_vault(VaultSecrets::new().with_secret(secret))).await;
+    let client = reqwest::Client::new();
+
+    let mut plan: Value = serde_json::from_str(&plan_json()).unwrap();
+    plan["plays"][0]["batches"][0]["tasks"][0]["args"]["password"] =
+        serde_json::json!({ "__ansible_vault": vaulted });
+    let submitted: Value = client
+        .post(format!("{base}/plans"))
+        .body(plan.to_string())
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let plan_id = submitted["plan_id"].as_str().unwrap();
+
+    let served = client
+        .get(format!("{base}/plans/{plan_id}"))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(!served.contains("hunter2"));
+    let served: Value = serde_json::from_str(&served).unwrap();
+    assert_eq!(
+        served["plays"][0]["batches"][0]["tasks"][0]["args"]["password"]["__ansible_vault"],
+        vaulted
+    );
+}
+
+#[tokio::test]
+async fn test_only_the_most_recent_plans_are_kept() {
+    let temp_dir = TempDir::new().unwrap();
+    let base = serve(server(&temp_dir).with_retention(2)).await;
+    let client = reqwest::Client::new();
+
+    let mut plan_ids = Vec::new();
+    for _ in 0..3 {
+        let submitted: Value = client
+            .post(format!("{base}/plans"))
+            .body(plan_json())
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        plan_ids.push(submitted["plan_id"].as_str().unwrap().to_string());
+    }
+
+    let plans: Value = client
+        .get(format!("{base}/plans"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let kept: Vec<&str> = plans
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|plan| plan["plan_id"].as_str().unwrap())
+        .collect();
+    assert_eq!(kept, [plan_ids[1].as_str(), plan_ids[2].as_str()]);
+    let response = client
+        .get(format!("{base}/plans/{}", plan_ids[0]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
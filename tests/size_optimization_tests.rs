@@ -0,0 +1,53 @@
+use rustle_deploy::compilation::{LtoMode, SizeOptimizer, SizeProfile, StripMode};
+use rustle_deploy::types::compilation::OptimizationLevel;
+use tempfile::TempDir;
+
+#[test]
+fn test_profiles_mapped_from_optimization_level() {
+    let min_size = SizeProfile::for_level(&OptimizationLevel::MinSize);
+    assert_eq!(min_size.opt_level, "z");
+    assert_eq!(min_size.lto, LtoMode::Fat);
+    assert!(min_size.panic_abort);
+    assert_eq!(min_size.strip, StripMode::Symbols);
+
+    let release = SizeProfile::for_level(&OptimizationLevel::Release);
+    assert_eq!(release.lto, LtoMode::Thin);
+    let env = release.cargo_env(SizeProfile::cargo_profile(&OptimizationLevel::Release));
+    assert!(env.contains(&("CARGO_PROFILE_RELEASE_LTO".to_string(), "thin".to_string())));
+    assert!(env.contains(&(
+        "CARGO_PROFILE_RELEASE_PANIC".to_string(),
+        "abort".to_string()
+    )));
+
+    let debug = SizeProfile::for_level(&OptimizationLevel::Debug);
+    assert_eq!(SizeProfile::cargo_profile(&OptimizationLevel::Debug), "dev");
+    assert_eq!(debug.lto, LtoMode::Off);
+    assert!(!debug.panic_abort);
+}
+
+#[tokio::test]
+async fn test_strip_override_and_report() {
+    let optimizer = SizeOptimizer::default().with_strip(StripMode::None);
+    assert_eq!(
+        optimizer.profile(&OptimizationLevel::MinSize).strip,
+        StripMode::None
+    );
+    assert_ne!(optimizer.describe(), SizeOptimizer::default().describe());
+
+    // Without UPX the binary is left as built
+    let temp_dir = TempDir::new().unwrap();
+    let binary = temp_dir.path().join("rustle-runner");
+    std::fs::write(&binary, vec![0u8; 4096]).unwrap();
+    let report = optimizer
+        .optimize(
+            &binary,
+            "x86_64-unknown-linux-gnu",
+            &OptimizationLevel::Release,
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.built_size, 4096);
+    assert_eq!(report.final_size(), 4096);
+    assert!(report.packed_size.is_none());
+    assert_eq!(report.profile.strip, StripMode::None);
+}
@@ -0,0 +1,142 @@
+use rustle_deploy::compilation::{
+    check_self_test_output, SmokeTest, SmokeTestStatus, SELF_TEST_ARG,
+};
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::runtime::event_stream::encode_frame;
+use rustle_deploy::runtime::ProgressEvent;
+use rustle_deploy::template::{BinaryTemplateGenerator, TargetInfo, TemplateConfig};
+use rustle_deploy::types::platform::Platform;
+use std::collections::HashMap;
+use std::path::Path;
+
+const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+fn self_test_output(total_tasks: usize, facts: &[(&str, &str)]) -> String {
+    let started = ProgressEvent::ExecutionStarted {
+        execution_id: "self-test".to_string(),
+        total_tasks,
+    };
+    let facts = ProgressEvent::FactsCollected {
+        execution_id: "self-test".to_string(),
+        facts: facts
+            .iter()
+            .map(|(name, value)| (name.to_string(), serde_json::json!(value)))
+            .collect::<HashMap<_, _>>(),
+    };
+    format!(
+        "{}2024-01-01T00:00:00Z  INFO log line\n{}",
+        encode_frame(&started).unwrap(),
+        encode_frame(&facts).unwrap()
+    )
+}
+
+#[test]
+fn test_valid_self_test_stream() {
+    let output = self_test_output(
+        3,
+        &[
+            ("ansible_system", "Linux"),
+            ("ansible_architecture", "x86_64"),
+        ],
+    );
+    let (events, failures) = check_self_test_output(&output, TARGET, 3);
+    assert_eq!(events.len(), 2);
+    assert!(failures.is_empty(), "{failures:?}");
+
+    let (_, failures) = check_self_test_output(&output, "i686-unknown-linux-gnu", 3);
+    assert_eq!(
+        failures,
+        ["runs as x86_64 but was built for i686-unknown-linux-gnu"]
+    );
+}
+
+#[test]
+fn test_invalid_self_test_streams() {
+    let output = self_test_output(2, &[("ansible_system", "Linux")]);
+    let (_, failures) = check_self_test_output(&output, TARGET, 3);
+    assert_eq!(
+        failures,
+        [
+            "reported 2 tasks but the plan has 3",
+            "did not gather the ansible_architecture fact",
+        ]
+    );
+
+    let failed = ProgressEvent::ExecutionFailed {
+        execution_id: "self-test".to_string(),
+        error: "Task t1 uses module copy, which is not compiled in".to_string(),
+    };
+    let output = format!("@rustle:99:{{}}\n{}", encode_frame(&failed).unwrap());
+    let (_, failures) = check_self_test_output(&output, TARGET, 3);
+    assert!(failures[0].starts_with("malformed event frame"));
+    assert_eq!(
+        failures[1..],
+        [
+            "did not start with an ExecutionStarted event",
+            "did not report its facts",
+            "Task t1 uses module copy, which is not compiled in",
+        ]
+    );
+}
+
+#[test]
+fn test_smoke_test_status() {
+    assert!(SmokeTestStatus::Passed.is_deployable());
+    let skipped = SmokeTestStatus::Skipped {
+        reason: "wasmtime is not installed".to_string(),
+    };
+    assert!(skipped.is_deployable());
+    assert!(!SmokeTestStatus::Failed { failures: vec![] }.is_deployable());
+
+    let json = serde_json::to_value(&skipped).unwrap();
+    assert_eq!(json["status"], "skipped");
+    assert_eq!(
+        serde_json::from_value::<SmokeTestStatus>(json).unwrap(),
+        skipped
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_foreign_targets_are_skipped() {
+    let smoke_test = SmokeTest::new();
+    assert!(smoke_test
+        .unrunnable_reason("x86_64-pc-windows-msvc")
+        .is_some());
+
+    let report = smoke_test
+        .run(
+            Path::new("/nonexistent/rustle-runner.exe"),
+            "x86_64-pc-windows-msvc",
+            1,
+        )
+        .await
+        .unwrap();
+    assert!(matches!(report.status, SmokeTestStatus::Skipped { .. }));
+}
+
+#[tokio::test]
+async fn test_runner_supports_self_test() {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    let plan: RustlePlanOutput = serde_json::from_str(&content).unwrap();
+    let generator = BinaryTemplateGenerator::new(TemplateConfig::default()).unwrap();
+    let target_info = TargetInfo {
+        target_triple: TARGET.to_string(),
+        platform: Platform::Linux,
+        architecture: "x86_64".to_string(),
+        os_family: "unix".to_string(),
+        libc: Some("gnu".to_string()),
+        features: vec![],
+    };
+    let template = generator
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &target_info)
+        .await
+        .expect("Failed to generate template");
+
+    let main_rs = &template.source_files[Path::new("src/main.rs")];
+    assert!(main_rs.contains(&format!("arg == \"{SELF_TEST_ARG}\"")));
+    assert!(main_rs.contains(r#"const COMPILED_MODULES: &[&str] = &["#));
+    assert!(main_rs.contains(r#""copy", "#));
+}
@@ -0,0 +1,133 @@
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::execution::{
+    render_plan_variables, SopsError, SopsKeys, VarsFileError, VarsFileLoader,
+};
+use std::path::Path;
+use tempfile::TempDir;
+
+// The fixtures were encrypted for the identity in age_keys.txt following
+// the SOPS file format, independently of this crate
+const YAML_FIXTURE: &str = "tests/fixtures/secrets/vars.sops.yaml";
+const JSON_FIXTURE: &str = "tests/fixtures/secrets/vars.sops.json";
+const AGE_KEYS: &str = "tests/fixtures/secrets/age_keys.txt";
+
+fn loader() -> VarsFileLoader {
+    let keys = SopsKeys::new()
+        .with_age_key_file(Path::new(AGE_KEYS))
+        .unwrap();
+    VarsFileLoader::new().with_sops(keys)
+}
+
+fn sops_error(error: VarsFileError) -> SopsError {
+    match error {
+        VarsFileError::Sops { source, .. } => source,
+        other => panic!("expected a SOPS error, got {other}"),
+    }
+}
+
+#[test]
+fn test_sops_vars_files_are_decrypted() {
+    for fixture in [YAML_FIXTURE, JSON_FIXTURE] {
+        let vars = loader().load(Path::new(fixture)).unwrap();
+        assert_eq!(vars["db"]["user"], "deploy", "{fixture}");
+        assert_eq!(vars["db"]["password"], "s3cret");
+        assert_eq!(vars["db"]["port"], 5432);
+        assert_eq!(vars["api_keys"], serde_json::json!(["abc123", "def456"]));
+        assert_eq!(vars["enabled"], false);
+        assert_eq!(vars["ratio"], 0.5);
+        assert_eq!(vars["debug_unencrypted"], true);
+        assert!(!vars.contains_key("sops"));
+    }
+}
+
+#[test]
+fn test_modified_sops_files_are_refused() {
+    let dir = TempDir::new().unwrap();
+    let original = std::fs::read_to_string(YAML_FIXTURE).unwrap();
+    let path = dir.path().join("vars.yaml");
+
+    // Values left in the clear are covered by the MAC
+    std::fs::write(
+        &path,
+        original.replace("debug_unencrypted: true", "debug_unencrypted: false"),
+    )
+    .unwrap();
+    let error = sops_error(loader().load(&path).unwrap_err());
+    assert!(matches!(error, SopsError::MacMismatch), "{error}");
+
+    // Encrypted values are bound to their place in the document
+    let user = original
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("user: "))
+        .unwrap();
+    let password = original
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("password: "))
+        .unwrap();
+    std::fs::write(
+        &path,
+        original
+            .replace(user, "SWAPPED")
+            .replace(password, user)
+            .replace("SWAPPED", password),
+    )
+    .unwrap();
+    let error = sops_error(loader().load(&path).unwrap_err());
+    assert!(
+        matches!(&error, SopsError::DecryptionFailed { path } if path == "db:user"),
+        "{error}"
+    );
+}
+
+#[test]
+fn test_sops_files_need_one_of_their_keys() {
+    let error = VarsFileLoader::new()
+        .with_sops(SopsKeys::new())
+        .load(Path::new(YAML_FIXTURE))
+        .unwrap_err();
+    match sops_error(error) {
+        SopsError::NoKey { tried } => assert!(tried.contains("no age identity"), "{tried}"),
+        other => panic!("expected a missing key, got {other}"),
+    }
+
+    let stranger = "AGE-SECRET-KEY-1ZLS5N60423WQ8WCF6HYA8XN86R7QY3WSE4MCAYGVHQCHW3HS0N7SRSXK65";
+    let error = VarsFileLoader::new()
+        .with_sops(SopsKeys::new().with_age_keys(stranger).unwrap())
+        .load(Path::new(YAML_FIXTURE))
+        .unwrap_err();
+    assert!(matches!(sops_error(error), SopsError::NoKey { .. }));
+    assert!(matches!(
+        SopsKeys::new().with_age_keys("AGE-SECRET-KEY-1NOTAKEY"),
+        Err(SopsError::InvalidIdentity { .. })
+    ));
+}
+
+#[test]
+fn test_later_vars_files_take_precedence_and_render_into_the_plan() {
+    let dir = TempDir::new().unwrap();
+    let overrides = dir.path().join("overrides.yml");
+    std::fs::write(&overrides, "db:\n  user: admin\n  password: hunter2\n").unwrap();
+    let vars = loader()
+        .load_all(&[YAML_FIXTURE.into(), overrides])
+        .unwrap();
+    assert_eq!(vars["db"]["user"], "admin");
+    assert_eq!(vars["ratio"], 0.5);
+
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .unwrap();
+    let mut plan: RustlePlanOutput = serde_json::from_str(&content).unwrap();
+    let args = &mut plan.plays[0].batches[0].tasks[0].args;
+    args.insert("owner".to_string(), "{{ db.user }}".into());
+    args.insert(
+        "content".to_string(),
+        "key={{ api_keys.1 }} {{ unknown }}".into(),
+    );
+
+    render_plan_variables(&mut plan, &vars);
+    let args = &plan.plays[0].batches[0].tasks[0].args;
+    assert_eq!(args["owner"], "admin");
+    // Expressions naming other variables are left for the runner
+    assert_eq!(args["content"], "key=def456 {{ unknown }}");
+    assert_eq!(args["path"], "/tmp/rustle_file_test");
+}
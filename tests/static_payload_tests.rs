@@ -0,0 +1,146 @@
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::template::{
+    BinaryTemplateGenerator, DataEmbedder, EmbedError, PayloadPolicy, SectionKind, TargetInfo,
+    TemplateConfig, EMBEDDED_SRC_PREFIX,
+};
+use rustle_deploy::types::platform::Platform;
+use tempfile::TempDir;
+
+const SAMPLE_CONF: &str = "tests/fixtures/files/test_files/sample.conf";
+
+fn load_plan() -> RustlePlanOutput {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    serde_json::from_str(&content).expect("Failed to parse rustle plan")
+}
+
+fn copy_srcs(plan: &RustlePlanOutput) -> Vec<String> {
+    plan.plays
+        .iter()
+        .flat_map(|play| &play.batches)
+        .flat_map(|batch| &batch.tasks)
+        .filter(|task| task.module == "copy")
+        .map(|task| task.args["src"].as_str().unwrap().to_string())
+        .collect()
+}
+
+/// The fixture with copy tasks of `extra_srcs` added, `remote_src` ones
+/// where flagged
+fn plan_with_copies(extra_srcs: &[(&str, bool)]) -> RustlePlanOutput {
+    let mut plan = load_plan();
+    let tasks = &mut plan.plays[0].batches[0].tasks;
+    let copy = tasks
+        .iter()
+        .find(|task| task.module == "copy")
+        .unwrap()
+        .clone();
+    for (index, (src, remote_src)) in extra_srcs.iter().enumerate() {
+        let mut task = copy.clone();
+        task.task_id = format!("extra_copy_{index}");
+        task.args
+            .insert("src".to_string(), serde_json::json!(src.to_string()));
+        if *remote_src {
+            task.args
+                .insert("remote_src".to_string(), serde_json::json!(true));
+        }
+        tasks.push(task);
+    }
+    plan
+}
+
+#[tokio::test]
+async fn test_local_sources_embedded_and_rewritten() {
+    let plan = plan_with_copies(&[
+        (SAMPLE_CONF, false),
+        (SAMPLE_CONF, true),
+        ("/etc/does-not-exist.conf", false),
+        ("{{ config_dir }}/app.conf", false),
+    ]);
+    let embedder = DataEmbedder::new(&TemplateConfig::default()).unwrap();
+
+    let (rewritten, bundle) = embedder.bundle_local_sources(&plan).await.unwrap();
+
+    assert_eq!(bundle.files.len(), 1);
+    assert_eq!(bundle.payloads.len(), 2);
+    let key = &bundle.payloads[0].key;
+    assert!(key.starts_with("payload/") && key.ends_with("/sample.conf"));
+    assert_eq!(
+        bundle.files[key],
+        std::fs::read(SAMPLE_CONF).unwrap(),
+        "the file is embedded as is"
+    );
+
+    let embedded = format!("{EMBEDDED_SRC_PREFIX}{key}");
+    assert_eq!(
+        copy_srcs(&rewritten),
+        [
+            embedded.as_str(),
+            embedded.as_str(),
+            SAMPLE_CONF,
+            "/etc/does-not-exist.conf",
+            "{{ config_dir }}/app.conf",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_payload_exclusions_and_limits() {
+    let dir = TempDir::new().unwrap();
+    let secret = dir.path().join("id_rsa.key");
+    std::fs::write(&secret, "secret").unwrap();
+    let plan = plan_with_copies(&[(secret.to_str().unwrap(), false)]);
+
+    let embedder = DataEmbedder::new(&TemplateConfig::default())
+        .unwrap()
+        .with_payload_policy(PayloadPolicy {
+            exclude: vec!["*.key".to_string()],
+            ..Default::default()
+        });
+    let (rewritten, bundle) = embedder.bundle_local_sources(&plan).await.unwrap();
+    assert_eq!(bundle.excluded, std::slice::from_ref(&secret));
+    assert_eq!(copy_srcs(&rewritten)[1], secret.to_str().unwrap());
+
+    let embedder = DataEmbedder::new(&TemplateConfig::default())
+        .unwrap()
+        .with_payload_policy(PayloadPolicy {
+            max_file_size: 4,
+            ..Default::default()
+        });
+    match embedder.bundle_local_sources(&plan).await {
+        Err(EmbedError::PayloadTooLarge { limit, .. }) => assert_eq!(limit, 4),
+        other => panic!("expected the payload to be too large, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_runner_carries_payloads() {
+    let plan = load_plan();
+    let generator = BinaryTemplateGenerator::new(TemplateConfig::default()).unwrap();
+    let target_info = TargetInfo {
+        target_triple: "x86_64-unknown-linux-gnu".to_string(),
+        platform: Platform::Linux,
+        architecture: "x86_64".to_string(),
+        os_family: "unix".to_string(),
+        libc: Some("gnu".to_string()),
+        features: vec![],
+    };
+
+    let template = generator
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &target_info)
+        .await
+        .expect("Failed to generate template");
+
+    let report = template.section_report();
+    let payload = report
+        .assets
+        .iter()
+        .find(|asset| asset.kind == SectionKind::Payload)
+        .expect("sample.conf is embedded");
+    assert_eq!(
+        payload.original_size,
+        std::fs::metadata(SAMPLE_CONF).unwrap().len()
+    );
+    let copy_module = &template.source_files[std::path::Path::new("src/modules/copy.rs")];
+    assert!(copy_module.contains("embedded_data::static_file"));
+}
@@ -93,6 +93,7 @@ fn create_minimal_test_plan() -> RustlePlanOutput {
     use std::time::Duration;
 
     RustlePlanOutput {
+        schema_version: rustle_deploy::execution::PLAN_SCHEMA_VERSION,
         metadata: RustlePlanMetadata {
             created_at: Utc::now(),
             rustle_plan_version: "0.1.0".to_string(),
@@ -153,6 +153,56 @@ async fn test_data_embedder_mixed_static_files() {
     }
 }
 
+#[tokio::test]
+async fn test_data_embedder_bundles_referenced_templates() {
+    let fixtures = std::path::Path::new("tests/fixtures/templates/searchpath");
+    let config = TemplateConfig::default();
+    let embedder = DataEmbedder::new(&config)
+        .unwrap()
+        .with_template_search_path(vec![
+            fixtures.join("role/templates"),
+            fixtures.join("shared"),
+        ]);
+
+    let rustle_plan = create_test_rustle_plan();
+    let mut binary_deployment = create_test_binary_deployment();
+    binary_deployment.static_files = vec![StaticFileRef {
+        source_path: fixtures
+            .join("site/nginx.conf.j2")
+            .to_string_lossy()
+            .to_string(),
+        target_path: "/opt/rustle/templates/nginx.conf.j2".to_string(),
+        permissions: Some(0o644),
+        compress: false,
+    }];
+
+    let embedded_data = embedder
+        .embed_execution_data(&rustle_plan, &binary_deployment, &create_test_target_info())
+        .await
+        .unwrap();
+
+    // The templates it extends, imports and includes are placed next to it
+    let mut paths: Vec<&str> = embedded_data
+        .static_files
+        .keys()
+        .map(String::as_str)
+        .collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        [
+            "/opt/rustle/templates/base.conf.j2",
+            "/opt/rustle/templates/macros.j2",
+            "/opt/rustle/templates/nginx.conf.j2",
+            "/opt/rustle/templates/partials/locations.j2",
+        ]
+    );
+    assert_eq!(
+        embedded_data.static_files["/opt/rustle/templates/macros.j2"],
+        std::fs::read(fixtures.join("shared/macros.j2")).unwrap()
+    );
+}
+
 #[test]
 fn test_execution_plan_summary_creation() {
     // Test the create_execution_plan_summary logic
@@ -246,6 +296,7 @@ fn create_test_rustle_plan() -> RustlePlanOutput {
     use std::time::Duration;
 
     RustlePlanOutput {
+        schema_version: rustle_deploy::execution::PLAN_SCHEMA_VERSION,
         metadata: RustlePlanMetadata {
             created_at: Utc::now(),
             rustle_plan_version: "0.1.0".to_string(),
@@ -0,0 +1,168 @@
+mod helpers;
+
+use helpers::TaskBuilder;
+use rustle_deploy::execution::ExecutionPlan;
+use rustle_deploy::runtime::{LocalExecutor, RuntimeConfig, TaskStatus, SYNC_DIR_ENV};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Held by the tests that set [`SYNC_DIR_ENV`], which every runner of the
+/// process reads
+static SYNC_DIR: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+fn task(id: &str, after: &[&str], script: &str) -> serde_json::Value {
+    TaskBuilder::script(id, script)
+        .after(after)
+        .play("site")
+        .build()
+}
+
+fn plan(strategy: &str, tasks: Vec<serde_json::Value>) -> ExecutionPlan {
+    fatal_plan(strategy, tasks, false)
+}
+
+fn fatal_plan(
+    strategy: &str,
+    tasks: Vec<serde_json::Value>,
+    any_errors_fatal: bool,
+) -> ExecutionPlan {
+    helpers::plan(
+        "strategies",
+        serde_json::json!({
+            "tasks": tasks,
+            "plays": [{ "id": "site", "any_errors_fatal": any_errors_fatal }],
+            "strategy": strategy,
+        }),
+    )
+}
+
+async fn wait_for(path: &Path) {
+    for _ in 0..100 {
+        if path.exists() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("{} never appeared", path.display());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_independent_parallel_tasks_execute_side_by_side() {
+    let _env = SYNC_DIR.lock().await;
+    let mut tasks: Vec<_> = ["a", "b", "c"]
+        .into_iter()
+        .map(|id| {
+            let mut task = task(id, &[], &format!("sleep 1; echo {id}"));
+            task["parallel"] = true.into();
+            task
+        })
+        .collect();
+    tasks[0]["register"] = "a_out".into();
+    let mut joined = task("joined", &["a", "b", "c"], "echo joined");
+    joined["when"] = serde_json::json!(["a_out.stdout is search('a')"]);
+    tasks.push(joined);
+
+    let config = RuntimeConfig {
+        parallel_tasks: Some(3),
+        ..RuntimeConfig::default()
+    };
+    let mut executor = LocalExecutor::new(config);
+    let start = Instant::now();
+    let result = executor.execute_plan(plan("Free", tasks)).await.unwrap();
+    assert!(
+        start.elapsed() < Duration::from_millis(2500),
+        "{:?}",
+        start.elapsed()
+    );
+    assert!(result.success, "{:?}", result.errors);
+    assert_eq!(result.task_results["joined"].status, TaskStatus::Success);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_linear_runner_waits_for_the_controller() {
+    let _env = SYNC_DIR.lock().await;
+    let dir = tempfile::TempDir::new().unwrap();
+    let sync_dir = dir.path().join("sync");
+    std::env::set_var(SYNC_DIR_ENV, &sync_dir);
+    let (first, second) = (dir.path().join("first"), dir.path().join("second"));
+    let tasks = vec![
+        task("first", &[], &format!("touch {}", first.display())),
+        task("second", &["first"], &format!("touch {}", second.display())),
+    ];
+
+    let mut executor = LocalExecutor::new(RuntimeConfig::default());
+    let controller = async {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!first.exists());
+        std::fs::write(sync_dir.join("0.released"), "").unwrap();
+        wait_for(&first).await;
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!second.exists());
+        std::fs::write(sync_dir.join("1.released"), "").unwrap();
+    };
+    let (result, ()) = tokio::join!(executor.execute_plan(plan("Linear", tasks)), controller);
+    std::env::remove_var(SYNC_DIR_ENV);
+    let result = result.unwrap();
+    assert!(result.success, "{:?}", result.errors);
+    assert!(second.exists());
+    assert!(!sync_dir.exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_free_runner_waits_for_throttled_tasks_only() {
+    let _env = SYNC_DIR.lock().await;
+    let dir = tempfile::TempDir::new().unwrap();
+    let sync_dir = dir.path().join("sync");
+    std::env::set_var(SYNC_DIR_ENV, &sync_dir);
+    let (first, second) = (dir.path().join("first"), dir.path().join("second"));
+    let mut throttled = task("second", &["first"], &format!("touch {}", second.display()));
+    throttled["throttle"] = 1.into();
+    let tasks = vec![
+        task("first", &[], &format!("touch {}", first.display())),
+        throttled,
+    ];
+
+    let mut executor = LocalExecutor::new(RuntimeConfig::default());
+    let controller = async {
+        wait_for(&first).await;
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!second.exists());
+        std::fs::write(sync_dir.join("1.aborted"), "host-2 failed").unwrap();
+    };
+    let (result, ()) = tokio::join!(executor.execute_plan(plan("Free", tasks)), controller);
+    std::env::remove_var(SYNC_DIR_ENV);
+    let result = result.unwrap();
+    assert!(result.failed);
+    assert_eq!(result.errors, ["Play aborted: host-2 failed"]);
+    assert!(!result.task_results.contains_key("second"));
+    assert!(!second.exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_failure_in_a_fatal_play_ends_the_run() {
+    let _env = SYNC_DIR.lock().await;
+    let mut failing = task("failing", &[], "exit 1");
+    failing["failure_policy"] = "Continue".into();
+    let mut ignored = task("ignored", &[], "exit 1");
+    ignored["ignore_errors"] = true.into();
+    let tasks = vec![ignored, failing, task("after", &[], "echo after")];
+
+    let mut executor = LocalExecutor::new(RuntimeConfig::default());
+    let result = executor
+        .execute_plan(fatal_plan("Free", tasks, true))
+        .await
+        .unwrap();
+    assert!(result.failed);
+    assert_eq!(result.task_results["ignored"].status, TaskStatus::Ignored);
+    assert!(result.task_results["failing"].failed);
+    assert!(!result.task_results.contains_key("after"));
+    assert!(result
+        .errors
+        .iter()
+        .any(|error| error == "Task failing failed in play site, which has any_errors_fatal"));
+}
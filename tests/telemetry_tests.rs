@@ -0,0 +1,195 @@
+mod helpers;
+
+use helpers::TaskBuilder;
+use rustle_deploy::deploy::{OtlpExporter, Tracer};
+use rustle_deploy::execution::ExecutionPlan;
+use rustle_deploy::runtime::{LocalExecutor, RuntimeConfig, Span, TraceContext};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+fn task(id: &str, cmd: &str) -> serde_json::Value {
+    TaskBuilder::command(id, cmd)
+        .play("site")
+        .continue_on_failure()
+        .build()
+}
+
+fn plan(tasks: Vec<serde_json::Value>) -> ExecutionPlan {
+    helpers::plan(
+        "telemetry",
+        serde_json::json!({ "tasks": tasks, "plays": [{ "id": "site", "name": "Site" }] }),
+    )
+}
+
+fn named<'a>(spans: &'a [Span], name: &str) -> &'a Span {
+    spans
+        .iter()
+        .find(|span| span.name == name)
+        .unwrap_or_else(|| panic!("no span {name} in {spans:?}"))
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_runner_records_spans_under_the_given_context() {
+    let parent = TraceContext::new_root();
+    let mut executor =
+        LocalExecutor::new(RuntimeConfig::default()).with_trace_context(parent.clone());
+    let result = executor
+        .execute_plan(plan(vec![
+            task("install", "echo ok"),
+            task("restart", "false"),
+        ]))
+        .await
+        .unwrap();
+
+    let spans = &result.spans;
+    assert_eq!(spans.len(), 4, "{spans:?}");
+    assert!(spans.iter().all(|span| span.trace_id == parent.trace_id));
+
+    let runner = named(spans, "runner localhost");
+    assert_eq!(runner.parent_span_id.as_ref(), Some(&parent.span_id));
+    assert_eq!(runner.attributes["rustle.tasks"], 2);
+    assert!(runner.error.is_some());
+
+    let play = named(spans, "play Site");
+    assert_eq!(play.parent_span_id.as_ref(), Some(&runner.span_id));
+
+    let install = named(spans, "install");
+    assert_eq!(install.parent_span_id.as_ref(), Some(&play.span_id));
+    assert_eq!(install.attributes["host.name"], "localhost");
+    assert_eq!(install.attributes["rustle.module"], "command");
+    assert_eq!(install.attributes["rustle.failed"], false);
+    assert_eq!(
+        install.start_time,
+        result.task_results["install"].start_time
+    );
+    assert!(install.error.is_none());
+
+    let restart = named(spans, "restart");
+    assert_eq!(restart.attributes["rustle.failed"], true);
+    assert!(restart.error.is_some());
+
+    // Spans travel back to the controller with the result
+    let shipped: rustle_deploy::runtime::ExecutionResult =
+        serde_json::from_str(&serde_json::to_string(&result).unwrap()).unwrap();
+    assert_eq!(shipped.spans, result.spans);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_runner_without_a_context_records_no_spans() {
+    let mut executor = LocalExecutor::new(RuntimeConfig::default());
+    let result = executor
+        .execute_plan(plan(vec![task("install", "echo ok")]))
+        .await
+        .unwrap();
+    assert!(result.spans.is_empty());
+}
+
+type Requests = Arc<Mutex<Vec<(String, String, serde_json::Value)>>>;
+
+/// Accept OTLP/HTTP requests, recording their path, authorization header
+/// and body
+async fn start_collector() -> (String, Requests) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let requests: Requests = Arc::default();
+
+    let received = requests.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).await.unwrap();
+            let path = request_line
+                .split_whitespace()
+                .nth(1)
+                .unwrap_or_default()
+                .to_string();
+
+            let (mut content_length, mut authorization) = (0, String::new());
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).await.unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    } else if name.eq_ignore_ascii_case("authorization") {
+                        authorization = value.trim().to_string();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).await.unwrap();
+            received.lock().unwrap().push((
+                path,
+                authorization,
+                serde_json::from_slice(&body).unwrap(),
+            ));
+
+            let mut stream = reader.into_inner();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n{}")
+                .await
+                .unwrap();
+        }
+    });
+    (endpoint, requests)
+}
+
+#[tokio::test]
+async fn test_exporter_sends_the_unified_trace() {
+    let tracer = Tracer::new("deploy-7");
+    let mut execute = tracer.start("execute web1");
+    execute.set_attribute("host.name", "web1");
+    // What the runner on web1 shipped back
+    let mut task = Span::start("install nginx", &execute.context())
+        .with_attribute("rustle.changed", true)
+        .with_attribute("rustle.duration_ms", 1200);
+    task.fail("exit status 1");
+    tracer.record([execute.end(), task.end()]);
+    let spans = tracer.finish();
+    assert_eq!(spans[0].parent_span_id, None);
+
+    let (endpoint, requests) = start_collector().await;
+    let exporter = OtlpExporter::new(&endpoint)
+        .with_header("authorization", "Bearer token")
+        .with_service_name("ci-deploys");
+    exporter.export(&spans).await.unwrap();
+
+    let requests = requests.lock().unwrap();
+    let (path, authorization, body) = &requests[0];
+    assert_eq!(path, "/v1/traces");
+    assert_eq!(authorization, "Bearer token");
+
+    let resource = &body["resourceSpans"][0];
+    assert_eq!(
+        resource["resource"]["attributes"][0],
+        serde_json::json!({ "key": "service.name", "value": { "stringValue": "ci-deploys" } })
+    );
+    let exported = resource["scopeSpans"][0]["spans"].as_array().unwrap();
+    assert_eq!(exported.len(), 3);
+    let (root, execute, task) = (&exported[0], &exported[1], &exported[2]);
+    assert_eq!(root["name"], "deployment deploy-7");
+    assert!(root.get("parentSpanId").is_none());
+    assert_eq!(execute["parentSpanId"], root["spanId"]);
+    assert_eq!(task["parentSpanId"], execute["spanId"]);
+    assert!(exported
+        .iter()
+        .all(|span| span["traceId"] == root["traceId"]));
+    assert_eq!(
+        task["status"],
+        serde_json::json!({ "code": 2, "message": "exit status 1" })
+    );
+    let attributes = task["attributes"].as_array().unwrap();
+    assert!(attributes.contains(&serde_json::json!({
+        "key": "rustle.changed", "value": { "boolValue": true }
+    })));
+    assert!(attributes.contains(&serde_json::json!({
+        "key": "rustle.duration_ms", "value": { "intValue": "1200" }
+    })));
+}
@@ -61,13 +61,33 @@ fn create_test_execution_plan() -> RustlePlanOutput {
                     can_run_parallel: true,
                     estimated_duration: Duration::from_secs(5),
                     risk_level: RiskLevel::Low,
+                    run_once: false,
+                    delegate_to: None,
+                    task_loop: None,
+                    until: None,
+                    async_job: None,
+                    failed_when: None,
+                    changed_when: None,
+                    ignore_errors: false,
+                    register: None,
+                    no_log: false,
+                    r#become: None,
+                    environment: HashMap::new(),
+                    module_defaults: HashMap::new(),
+                    timeout: None,
+                    throttle: None,
                 }],
                 parallel_groups: vec![],
                 dependencies: vec![],
                 estimated_duration: Some(Duration::from_secs(10)),
             }],
             handlers: vec![],
+            blocks: vec![],
             estimated_duration: Some(Duration::from_secs(20)),
+            environment: HashMap::new(),
+            module_defaults: HashMap::new(),
+            timeout: None,
+            any_errors_fatal: false,
         }],
         binary_deployments: vec![],
         total_tasks: 1,
@@ -222,6 +242,7 @@ fn test_cargo_toml_generation() {
             name: "test-dep".to_string(),
             version: "1.0".to_string(),
             features: vec!["feature1".to_string()],
+            default_features: true,
         },
     ];
     
@@ -267,8 +288,8 @@ fn test_main_rs_generation() {
         .expect("Failed to generate main.rs");
     
     assert!(main_rs.contains("async fn main()"));
-    assert!(main_rs.contains("embedded_data::EXECUTION_PLAN"));
-    assert!(main_rs.contains("embedded_data::RUNTIME_CONFIG"));
+    assert!(main_rs.contains("embedded_data::execution_plan()"));
+    assert!(main_rs.contains("embedded_data::runtime_config()"));
     assert!(main_rs.contains("LocalExecutor"));
 }
 
@@ -228,6 +228,21 @@ async fn test_template_with_multiple_modules() {
             can_run_parallel: true,
             estimated_duration: std::time::Duration::from_secs(5),
             risk_level: rustle_deploy::execution::rustle_plan::RiskLevel::Low,
+            run_once: false,
+            delegate_to: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            no_log: false,
+            r#become: None,
+            environment: HashMap::new(),
+            module_defaults: HashMap::new(),
+            timeout: None,
+            throttle: None,
         },
         rustle_deploy::execution::rustle_plan::TaskPlan {
             task_id: "task-3".to_string(),
@@ -248,6 +263,21 @@ async fn test_template_with_multiple_modules() {
             can_run_parallel: false,
             estimated_duration: std::time::Duration::from_secs(10),
             risk_level: rustle_deploy::execution::rustle_plan::RiskLevel::Medium,
+            run_once: false,
+            delegate_to: None,
+            task_loop: None,
+            until: None,
+            async_job: None,
+            failed_when: None,
+            changed_when: None,
+            ignore_errors: false,
+            register: None,
+            no_log: false,
+            r#become: None,
+            environment: HashMap::new(),
+            module_defaults: HashMap::new(),
+            timeout: None,
+            throttle: None,
         },
     ]);
     
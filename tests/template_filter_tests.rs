@@ -0,0 +1,238 @@
+use rustle_deploy::modules::files::template_engine::AdvancedTemplateProcessor;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+fn render(template: &str, variables: Value) -> String {
+    AdvancedTemplateProcessor::new()
+        .unwrap()
+        .render_template(template, &variables)
+        .unwrap_or_else(|e| panic!("{template}: {e}"))
+}
+
+/// The value of a single expression, as task arguments are templated
+fn evaluate(expression: &str, variables: Value) -> Value {
+    let variables: HashMap<String, Value> = serde_json::from_value(variables).unwrap();
+    AdvancedTemplateProcessor::new()
+        .unwrap()
+        .render_value(&json!(format!("{{{{ {expression} }}}}")), &variables)
+}
+
+#[test]
+fn test_data_filters() {
+    let variables = json!({
+        "defaults": {"port": 80, "tls": {"enabled": false, "ciphers": ["a"]}},
+        "overrides": {"tls": {"enabled": true}},
+        "users": [
+            {"name": "alice", "uid": 1001, "admin": true},
+            {"name": "bob", "uid": 1002, "admin": false},
+        ],
+        "json": "{\"a\": [1, 2]}",
+        "yaml": "a:\n  - 1\n  - 2\n",
+    });
+
+    assert_eq!(
+        evaluate(
+            "defaults | combine(overrides, recursive=true)",
+            variables.clone()
+        ),
+        json!({"port": 80, "tls": {"enabled": true, "ciphers": ["a"]}})
+    );
+    assert_eq!(
+        evaluate("defaults | combine(overrides)", variables.clone()),
+        json!({"port": 80, "tls": {"enabled": true}})
+    );
+    assert_eq!(
+        evaluate("{'a': 1} | dict2items", variables.clone()),
+        json!([{"key": "a", "value": 1}])
+    );
+    assert_eq!(
+        evaluate(
+            "users | items2dict(key_name='name', value_name='uid')",
+            variables.clone()
+        ),
+        json!({"alice": 1001, "bob": 1002})
+    );
+    assert_eq!(
+        evaluate(
+            "users | selectattr('admin') | map(attribute='name') | list",
+            variables.clone()
+        ),
+        json!(["alice"])
+    );
+    assert_eq!(
+        evaluate(
+            "users | rejectattr('name', 'match', 'a.*') | map(attribute='uid') | list",
+            variables.clone()
+        ),
+        json!([1002])
+    );
+    assert_eq!(
+        evaluate(
+            "['alice'] | map('extract', {'alice': {'uid': 1}}, 'uid') | list",
+            json!({})
+        ),
+        json!([1])
+    );
+    assert_eq!(
+        evaluate("json | from_json", variables.clone()),
+        json!({"a": [1, 2]})
+    );
+    assert_eq!(
+        evaluate("yaml | from_yaml", variables.clone()),
+        json!({"a": [1, 2]})
+    );
+    assert_eq!(
+        render("{{ {'a': [1, 2]} | to_json }}", json!({})),
+        "{\"a\":[1,2]}"
+    );
+    assert_eq!(
+        render("{{ {'a': 1} | to_nice_json(indent=2) }}", json!({})),
+        "{\n  \"a\": 1\n}"
+    );
+    assert_eq!(
+        render("{{ {'a': [1]} | to_nice_yaml }}", json!({})),
+        "a:\n- 1\n"
+    );
+    assert_eq!(
+        evaluate(
+            "[1, 2, 2, 3] | unique | union([4]) | difference([1])",
+            json!({})
+        ),
+        json!([2, 3, 4])
+    );
+    assert_eq!(
+        evaluate("[1, [2, [3]]] | flatten(levels=1)", json!({})),
+        json!([1, 2, [3]])
+    );
+}
+
+#[test]
+fn test_text_filters() {
+    let variables = json!({"name": "web-01.example.com", "path": "/etc/nginx/nginx.conf"});
+
+    assert_eq!(
+        render(
+            "{{ name | regex_replace('^([a-z]+)-(\\\\d+).*$', '\\\\2:\\\\1') }}",
+            variables.clone()
+        ),
+        "01:web"
+    );
+    assert_eq!(
+        evaluate("name | regex_search('[0-9]+')", variables.clone()),
+        json!("01")
+    );
+    assert_eq!(
+        evaluate(
+            "name | regex_search('(?P<host>[a-z]+)-(\\\\d+)', '\\\\g<host>', '\\\\2')",
+            variables.clone()
+        ),
+        json!(["web", "01"])
+    );
+    assert_eq!(
+        evaluate("name | regex_search('^db')", variables.clone()),
+        Value::Null
+    );
+    assert_eq!(
+        render(
+            "{{ 'hello' | b64encode }} {{ 'aGVsbG8=' | b64decode }}",
+            json!({})
+        ),
+        "aGVsbG8= hello"
+    );
+    assert_eq!(
+        render(
+            "{{ path | basename }} {{ path | dirname }}",
+            variables.clone()
+        ),
+        "nginx.conf /etc/nginx"
+    );
+    assert_eq!(
+        render(
+            "{{ 'yes' | bool | ternary('on', 'off') }} {{ missing | default('none') }}",
+            json!({})
+        ),
+        "on none"
+    );
+    assert_eq!(
+        render("{{ '' | default('empty', true) }}", json!({})),
+        "empty"
+    );
+    assert_eq!(
+        render("rm {{ file | quote }}", json!({"file": "it's"})),
+        "rm 'it'\\''s'"
+    );
+}
+
+#[test]
+fn test_hash_filters() {
+    assert_eq!(
+        render("{{ 'abc' | hash('sha256') }}", json!({})),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        render("{{ 'abc' | hash('md5') }}", json!({})),
+        "900150983cd24fb0d6963f7d28e17f72"
+    );
+    assert_eq!(
+        render("{{ 'abc' | hash }} {{ 'abc' | checksum }}", json!({})),
+        "a9993e364706816aba3e25717850c26c9cd0d89d a9993e364706816aba3e25717850c26c9cd0d89d"
+    );
+    assert_eq!(
+        render(
+            "{{ 'Hello world!' | password_hash('sha512', 'saltstring') }}",
+            json!({})
+        ),
+        "$6$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1"
+    );
+    let random = render("{{ 's3cret' | password_hash }}", json!({}));
+    assert!(random.starts_with("$6$"), "{random}");
+    assert_ne!(random, render("{{ 's3cret' | password_hash }}", json!({})));
+}
+
+#[test]
+fn test_ipaddr_filters() {
+    let variables = json!({
+        "addresses": ["192.168.1.10/24", "8.8.8.8", "not an address", "fd00::1", "10.0.0.0/8"],
+    });
+
+    assert_eq!(
+        evaluate("addresses | ipaddr", variables.clone()),
+        json!(["192.168.1.10/24", "8.8.8.8", "fd00::1", "10.0.0.0/8"])
+    );
+    assert_eq!(
+        evaluate("addresses | ipv4('private')", variables.clone()),
+        json!(["192.168.1.10/24", "10.0.0.0/8"])
+    );
+    assert_eq!(
+        evaluate("addresses | ipaddr('public')", variables.clone()),
+        json!(["8.8.8.8"])
+    );
+    assert_eq!(
+        evaluate("addresses | ipv6", variables.clone()),
+        json!(["fd00::1"])
+    );
+    assert_eq!(
+        evaluate("addresses | ipaddr('net')", variables.clone()),
+        json!(["10.0.0.0/8"])
+    );
+    assert_eq!(
+        render(
+            "{{ a | ipaddr('address') }} {{ a | ipaddr('network') }} {{ a | ipaddr('netmask') }} \
+             {{ a | ipaddr('broadcast') }} {{ a | ipaddr('prefix') }} {{ a | ipaddr('size') }}",
+            json!({"a": "192.168.1.10/24"})
+        ),
+        "192.168.1.10 192.168.1.0 255.255.255.0 192.168.1.255 24 256"
+    );
+    assert_eq!(
+        render(
+            "{{ '10.0.0.0/24' | ipaddr(1) }} {{ '10.0.0.0/24' | ipaddr(-2) }} {{ '10.0.0.0/30' | ipaddr(8) }}",
+            json!({})
+        ),
+        "10.0.0.1/24 10.0.0.254/24 false"
+    );
+    assert_eq!(
+        render("{{ '192.0.2.5' | ipaddr('revdns') }}", json!({})),
+        "5.2.0.192.in-addr.arpa."
+    );
+    assert_eq!(evaluate("'nope' | ipaddr", json!({})), json!(false));
+}
@@ -0,0 +1,99 @@
+mod helpers;
+
+use helpers::{plan, TaskBuilder};
+use rustle_deploy::runtime::{ExecutionResult, LocalExecutor, RuntimeConfig, TaskStatus};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+fn task(id: &str, after: Option<&str>, script: &str) -> serde_json::Value {
+    TaskBuilder::script(id, script)
+        .after(after.as_slice())
+        .play("site")
+        .continue_on_failure()
+        .build()
+}
+
+async fn execute(
+    tasks: Vec<serde_json::Value>,
+    plays: Vec<serde_json::Value>,
+) -> (ExecutionResult, Duration) {
+    let plan = plan(
+        "timeouts",
+        serde_json::json!({ "tasks": tasks, "plays": plays }),
+    );
+    let mut executor = LocalExecutor::new(RuntimeConfig::default());
+    let start = Instant::now();
+    let result = executor.execute_plan(plan).await.unwrap();
+    (result, start.elapsed())
+}
+
+/// Whether the process is running, zombies that nobody reaped yet aside
+fn running(pid: &str) -> bool {
+    std::fs::read_to_string(format!("/proc/{pid}/stat"))
+        .is_ok_and(|stat| !stat.rsplit(')').next().unwrap_or("").starts_with(" Z"))
+}
+
+async fn assert_killed(pid_file: &Path) {
+    let pid = std::fs::read_to_string(pid_file).unwrap();
+    for _ in 0..50 {
+        if !running(pid.trim()) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("process {} survived the timeout", pid.trim());
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_task_timeout_kills_the_process_tree() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let pid_file = dir.path().join("child.pid");
+    let mut hang = task(
+        "hang",
+        None,
+        &format!("sleep 30 & echo $! > {}; wait", pid_file.display()),
+    );
+    hang["timeout"] = 1.into();
+    let after = task("after", None, "echo after");
+
+    let (result, elapsed) = execute(vec![hang, after], vec![]).await;
+    assert!(elapsed < Duration::from_secs(10), "took {elapsed:?}");
+    let hang = &result.task_results["hang"];
+    assert_eq!(hang.status, TaskStatus::Timeout);
+    assert!(hang.failed);
+    assert_eq!(hang.error.as_deref(), Some("Task timed out after 1s"));
+    assert_eq!(result.task_results["after"].status, TaskStatus::Success);
+    assert!(result.failed);
+    assert_killed(&pid_file).await;
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_play_deadline_stops_the_play() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let pid_file = dir.path().join("child.pid");
+    let first = task("first", None, "sleep 1");
+    let second = task(
+        "second",
+        Some("first"),
+        &format!("sleep 30 & echo $! > {}; wait", pid_file.display()),
+    );
+    let third = task("third", Some("second"), "echo third");
+
+    let (result, elapsed) = execute(
+        vec![first, second, third],
+        vec![serde_json::json!({ "id": "site", "timeout": 2 })],
+    )
+    .await;
+    assert!(elapsed < Duration::from_secs(10), "took {elapsed:?}");
+    assert_eq!(result.task_results["first"].status, TaskStatus::Success);
+    assert_eq!(result.task_results["second"].status, TaskStatus::Timeout);
+    assert!(!result.task_results.contains_key("third"));
+    assert!(result.failed);
+    assert!(result
+        .errors
+        .iter()
+        .any(|error| error == "Play timeout: site (2s)"));
+    assert_killed(&pid_file).await;
+}
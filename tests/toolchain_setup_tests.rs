@@ -0,0 +1,31 @@
+use rustle_deploy::compilation::toolchain::{
+    parse_version, rust_toolchain_toml, RustupTargets, TargetInstallPolicy, MIN_ZIGBUILD_VERSION,
+};
+
+#[test]
+fn test_tool_versions_parsed() {
+    assert_eq!(parse_version("0.11.0"), Some((0, 11)));
+    assert_eq!(parse_version("0.12.0-dev.1+abc"), Some((0, 12)));
+    assert_eq!(parse_version("cargo-zigbuild 0.19.1\n"), Some((0, 19)));
+    assert_eq!(parse_version("unknown"), None);
+    assert!(parse_version("cargo-zigbuild 0.16.0").unwrap() < MIN_ZIGBUILD_VERSION);
+}
+
+#[test]
+fn test_pinned_toolchain() {
+    let rustup =
+        RustupTargets::new(TargetInstallPolicy::Suggest).with_toolchain(Some("1.79.0".to_string()));
+    assert_eq!(
+        rustup.install_command("aarch64-unknown-linux-musl"),
+        "rustup target add --toolchain 1.79.0 aarch64-unknown-linux-musl"
+    );
+    assert_eq!(
+        RustupTargets::default().install_command("aarch64-unknown-linux-musl"),
+        "rustup target add aarch64-unknown-linux-musl"
+    );
+
+    let toml = rust_toolchain_toml("1.79.0", &["x86_64-unknown-linux-musl"]);
+    assert!(toml.starts_with("[toolchain]\n"));
+    assert!(toml.contains("channel = \"1.79.0\"\n"));
+    assert!(toml.contains("targets = [\"x86_64-unknown-linux-musl\"]\n"));
+}
@@ -0,0 +1,150 @@
+mod helpers;
+
+use helpers::TaskBuilder;
+use rustle_deploy::execution::{
+    ExecutionPlanParser, ParseError, PlanFormat, VarsFileLoader, VaultError, VaultIdentity,
+    VaultSecret, VaultSecrets,
+};
+use tempfile::TempDir;
+
+/// `---\ndb_password: s3cret\ndb_port: 5432\n` encrypted by `ansible-vault`
+/// with the password "correct horse"
+const VAULTED_VARS: &str = "$ANSIBLE_VAULT;1.1;AES256
+30303031303230333034303530363037303830393061306230633064306530663130313131323133
+3134313531363137313831393161316231633164316531660a333963343863646261366432393263
+61363636333333636464353930616562636533393830623631643637373330306661313366383764
+3162303839323933320a653662653338303262613665393632653231383735303336623664623739
+36363633343564306562633365646235346362353266636437356466613730396338373638346530
+6234363264326664343638343630386562356232343366363236
+";
+
+/// "hunter2" encrypted by `ansible-vault encrypt_string` with the password
+/// "prod-pass" of the vault id "prod"
+const VAULTED_STRING: &str = "$ANSIBLE_VAULT;1.2;AES256;prod
+32303231323232333234323532363237323832393261326232633264326532663330333133323333
+3334333533363337333833393361336233633364336533660a343962633132366538373938653666
+33383833363765653133373261303436326536376332333865303433313364316635333465393963
+3539396365356434300a363364303764316134623736396630313437363963313163366337346162
+3238
+";
+
+fn secrets() -> VaultSecrets {
+    VaultSecrets::new()
+        .with_secret(VaultSecret::new("default", "correct horse"))
+        .with_secret(VaultSecret::new("prod", "prod-pass"))
+}
+
+fn plan_json(args: serde_json::Value) -> String {
+    let task = TaskBuilder::new("set password", "command", args)
+        .continue_on_failure()
+        .build();
+    helpers::plan_json("vault", serde_json::json!({ "tasks": [task] })).to_string()
+}
+
+#[test]
+fn test_ansible_vault_data_is_decrypted() {
+    let vars = secrets().decrypt_str(VAULTED_VARS).unwrap();
+    assert_eq!(vars, "---\ndb_password: s3cret\ndb_port: 5432\n");
+    // Labelled data is decrypted with the password of its vault id
+    assert_eq!(secrets().decrypt_str(VAULTED_STRING).unwrap(), "hunter2");
+
+    let wrong = VaultSecrets::new().with_secret(VaultSecret::new("default", "tr0ub4dor"));
+    assert!(matches!(
+        wrong.decrypt_str(VAULTED_STRING),
+        Err(VaultError::DecryptionFailed { .. })
+    ));
+}
+
+#[test]
+fn test_encrypted_data_round_trips() {
+    let secret = VaultSecret::new("staging", "s3cret");
+    let vaulted = secret.encrypt(b"api_key: abc123\n");
+    assert!(vaulted.starts_with("$ANSIBLE_VAULT;1.2;AES256;staging\n"));
+    assert!(vaulted.lines().all(|line| line.len() <= 80));
+
+    let secrets = VaultSecrets::new().with_secret(secret);
+    assert_eq!(secrets.decrypt_str(&vaulted).unwrap(), "api_key: abc123\n");
+}
+
+#[test]
+fn test_parser_decrypts_vaulted_plan_values() {
+    let content = plan_json(serde_json::json!({
+        "cmd": "set-password",
+        "password": { "__ansible_vault": VAULTED_STRING },
+    }));
+
+    let plan = ExecutionPlanParser::new()
+        .with_vault(secrets())
+        .parse(&content, PlanFormat::Json)
+        .unwrap();
+    assert_eq!(plan.tasks[0].args["password"], "hunter2");
+
+    // Without a password the plan is refused rather than run with ciphertext
+    let error = ExecutionPlanParser::new()
+        .parse(&content, PlanFormat::Json)
+        .unwrap_err();
+    assert!(matches!(error, ParseError::Vault(VaultError::NoSecrets)));
+}
+
+#[test]
+fn test_vars_files_are_decrypted() {
+    let dir = TempDir::new().unwrap();
+    let vaulted = dir.path().join("secrets.yml");
+    std::fs::write(&vaulted, VAULTED_VARS).unwrap();
+    let loader = VarsFileLoader::new().with_vault(secrets());
+    let vars = loader.load(&vaulted).unwrap();
+    assert_eq!(vars["db_password"], "s3cret");
+    assert_eq!(vars["db_port"], 5432);
+
+    let inline = dir.path().join("vars.yml");
+    let indented: String = VAULTED_STRING
+        .lines()
+        .map(|line| format!("  {line}\n"))
+        .collect();
+    std::fs::write(
+        &inline,
+        format!("user: deploy\npassword: !vault |\n{indented}"),
+    )
+    .unwrap();
+    let vars = loader.load(&inline).unwrap();
+    assert_eq!(vars["user"], "deploy");
+    assert_eq!(vars["password"], "hunter2");
+}
+
+#[test]
+fn test_vault_ids_load_their_passwords() {
+    let dir = TempDir::new().unwrap();
+    let file = dir.path().join("vault-pass");
+    std::fs::write(&file, "correct horse\n").unwrap();
+
+    let identity: VaultIdentity = format!("{}", file.display()).parse().unwrap();
+    assert_eq!(identity.id, "default");
+    let secret = identity.load().unwrap();
+    assert_eq!(secret.id, "default");
+
+    let secrets = VaultSecrets::load(&[identity]).unwrap();
+    assert!(secrets.decrypt_str(VAULTED_VARS).is_ok());
+    assert!("prod@".parse::<VaultIdentity>().is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_password_scripts_are_run() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new().unwrap();
+    let script = dir.path().join("vault-client");
+    std::fs::write(
+        &script,
+        "#!/bin/sh\n[ \"$1\" = --vault-id ] && [ \"$2\" = prod ] && echo prod-pass\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let identity: VaultIdentity = format!("prod@{}", script.display()).parse().unwrap();
+    let secrets = VaultSecrets::load(&[identity]).unwrap();
+    assert_eq!(secrets.decrypt_str(VAULTED_STRING).unwrap(), "hunter2");
+
+    let failing: VaultIdentity = format!("staging@{}", script.display()).parse().unwrap();
+    assert!(matches!(failing.load(), Err(VaultError::Password { .. })));
+}
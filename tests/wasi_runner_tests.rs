@@ -0,0 +1,113 @@
+use rustle_deploy::compilation::{
+    check_module_compatibility, check_profile_compatibility, CompilerConfig,
+};
+use rustle_deploy::deploy::WasmtimeExecutor;
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::template::{BinaryTemplateGenerator, TargetInfo, TemplateConfig};
+use rustle_deploy::types::compilation::RunnerProfile;
+use rustle_deploy::types::platform::Platform;
+use std::path::Path;
+
+fn load_plan() -> RustlePlanOutput {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    serde_json::from_str(&content).expect("Failed to parse rustle plan")
+}
+
+#[test]
+fn test_wasi_targets_get_the_wasi_profile() {
+    let mut config = CompilerConfig {
+        default_runner_profile: RunnerProfile::Minimal,
+        ..Default::default()
+    };
+    assert_eq!(
+        config.runner_profile_for("wasm32-wasi"),
+        RunnerProfile::Wasi
+    );
+    assert_eq!(
+        config.runner_profile_for("wasm32-wasip1"),
+        RunnerProfile::Wasi
+    );
+
+    // The WASI runner builds for nothing else
+    config
+        .runner_profiles
+        .insert("x86_64-unknown-linux-gnu".to_string(), RunnerProfile::Wasi);
+    assert_eq!(
+        config.runner_profile_for("x86_64-unknown-linux-gnu"),
+        RunnerProfile::Standard
+    );
+}
+
+#[test]
+fn test_wasi_plan_validation() {
+    let report = check_profile_compatibility(&load_plan(), "wasm32-wasi", RunnerProfile::Wasi);
+    assert!(report.is_compatible());
+
+    let report =
+        check_module_compatibility(["file", "command"], "wasm32-wasi", RunnerProfile::Wasi);
+    assert!(!report.is_compatible());
+    assert_eq!(report.unsupported[0].module, "command");
+}
+
+#[tokio::test]
+async fn test_wasi_runner_template() {
+    let plan = load_plan();
+    let generator = BinaryTemplateGenerator::new(TemplateConfig {
+        runner_profile: RunnerProfile::Wasi,
+        ..Default::default()
+    })
+    .unwrap();
+    let target_info = TargetInfo {
+        target_triple: "wasm32-wasi".to_string(),
+        platform: Platform::Unknown("wasi".to_string()),
+        architecture: "wasm32".to_string(),
+        os_family: "wasm".to_string(),
+        libc: None,
+        features: vec![],
+    };
+
+    let template = generator
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &target_info)
+        .await
+        .expect("Failed to generate template");
+
+    assert!(!template.cargo_toml.contains("reqwest"));
+    assert!(!template.cargo_toml.contains("\"process\""));
+    assert!(!template.cargo_toml.contains("\"fs\""));
+    assert!(!template
+        .source_files
+        .contains_key(Path::new(".cargo/config.toml")));
+
+    let main_rs = &template.source_files[Path::new("src/main.rs")];
+    assert!(main_rs.contains("flavor = \"current_thread\""));
+    assert!(!main_rs.contains("current_exe"));
+    assert!(main_rs.contains("in the wasi runner profile"));
+}
+
+#[test]
+fn test_wasmtime_arguments() {
+    let executor = WasmtimeExecutor::new().with_dir("/srv/site", "/site");
+    let arguments = executor.arguments(
+        "/tmp/rustle-runner.wasm",
+        &["--version".to_string()],
+        &[("RUSTLE_HOST_ID", "web1")],
+    );
+
+    assert_eq!(executor.program(), "wasmtime");
+    assert_eq!(
+        arguments,
+        [
+            "run",
+            "--dir",
+            "/tmp::/tmp",
+            "--dir",
+            "/srv/site::/site",
+            "--env",
+            "RUSTLE_HOST_ID=web1",
+            "/tmp/rustle-runner.wasm",
+            "--version",
+        ]
+    );
+}
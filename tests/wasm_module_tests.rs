@@ -0,0 +1,102 @@
+use rustle_deploy::execution::rustle_plan::RustlePlanOutput;
+use rustle_deploy::template::{
+    BinaryTemplateGenerator, GeneratedTemplate, TargetInfo, TemplateConfig,
+};
+use rustle_deploy::types::compilation::RunnerProfile;
+use rustle_deploy::types::platform::Platform;
+use std::path::Path;
+
+/// The file operations plan, its first task running a module no runner
+/// is built with
+fn plan_with_unknown_module() -> RustlePlanOutput {
+    let content =
+        std::fs::read_to_string("tests/fixtures/execution_plans/file_operations_plan.json")
+            .expect("Failed to read test fixture");
+    let mut plan: RustlePlanOutput =
+        serde_json::from_str(&content).expect("Failed to parse rustle plan");
+    plan.plays[0].batches[0].tasks[0].module = "acme_probe".to_string();
+    plan
+}
+
+fn linux_target() -> TargetInfo {
+    TargetInfo {
+        target_triple: "x86_64-unknown-linux-gnu".to_string(),
+        platform: Platform::Linux,
+        architecture: "x86_64".to_string(),
+        os_family: "unix".to_string(),
+        libc: Some("glibc".to_string()),
+        features: vec![],
+    }
+}
+
+async fn generate(config: TemplateConfig) -> GeneratedTemplate {
+    let plan = plan_with_unknown_module();
+    BinaryTemplateGenerator::new(config)
+        .unwrap()
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &linux_target())
+        .await
+        .expect("Failed to generate template")
+}
+
+#[tokio::test]
+async fn test_runner_loads_unknown_modules_from_components() {
+    let template = generate(TemplateConfig {
+        wasm_modules: true,
+        ..Default::default()
+    })
+    .await;
+
+    let files = &template.source_files;
+    assert!(files[Path::new("src/wasm_host.rs")].contains("bindgen!"));
+    let wit = &files[Path::new("wit/module.wit")];
+    assert!(wit.contains("world rustle-module"));
+    assert!(wit.contains("export execute: func(args: string) -> result<string, string>;"));
+    assert!(template.cargo_toml.contains("wasmtime = \"29\""));
+    assert!(template.cargo_toml.contains("wasmtime-wasi = \"29\""));
+
+    // The module is left to its component rather than compiled in as a
+    // placeholder
+    assert!(!files.contains_key(Path::new("src/modules/acme_probe.rs")));
+    let main_rs = &files[Path::new("src/main.rs")];
+    assert!(main_rs.contains("mod wasm_host;"));
+    assert!(main_rs.contains("wasm_host::execute(&task.module, component, mapped_args)"));
+    assert!(!main_rs.contains("\"acme_probe\""));
+    assert!(!main_rs.contains("pub mod acme_probe;"));
+}
+
+#[tokio::test]
+async fn test_runner_without_wasm_modules_is_unchanged() {
+    let template = generate(TemplateConfig::default()).await;
+
+    let files = &template.source_files;
+    assert!(!files.contains_key(Path::new("src/wasm_host.rs")));
+    assert!(!files.contains_key(Path::new("wit/module.wit")));
+    assert!(!template.cargo_toml.contains("wasmtime"));
+    assert!(files.contains_key(Path::new("src/modules/acme_probe.rs")));
+    assert!(!files[Path::new("src/main.rs")].contains("wasm_host"));
+}
+
+#[tokio::test]
+async fn test_wasi_runners_cannot_load_wasm_modules() {
+    let plan = plan_with_unknown_module();
+    let generator = BinaryTemplateGenerator::new(TemplateConfig {
+        runner_profile: RunnerProfile::Wasi,
+        wasm_modules: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let target_info = TargetInfo {
+        target_triple: "wasm32-wasi".to_string(),
+        platform: Platform::Unknown("wasi".to_string()),
+        architecture: "wasm32".to_string(),
+        os_family: "wasm".to_string(),
+        libc: None,
+        features: vec![],
+    };
+
+    let error = generator
+        .generate_binary_template(&plan, &plan.binary_deployments[0], &target_info)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("cannot load WebAssembly modules"));
+}